@@ -5,6 +5,7 @@ use rodio::{OutputStream, OutputStreamHandle};
 use util::ResultExt;
 
 mod assets;
+pub mod relay;
 
 pub fn init(source: impl AssetSource, cx: &mut AppContext) {
     SoundRegistry::set_global(source, cx);
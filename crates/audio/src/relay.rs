@@ -0,0 +1,296 @@
+//! Capture and playback for the voice of calls that is relayed through the collab
+//! server, for servers without LiveKit.
+//!
+//! Frames carry [`FRAME_DURATION`] of mono audio at [`SAMPLE_RATE`], encoded as
+//! G.711 μ-law, one byte per sample.
+
+use anyhow::{anyhow, bail, Result};
+use collections::{hash_map::Entry, HashMap};
+use parking_lot::Mutex;
+use rodio::{
+    buffer::SamplesBuffer,
+    cpal::{
+        self,
+        traits::{DeviceTrait, HostTrait, StreamTrait},
+    },
+    OutputStream, Sink,
+};
+use std::{hash::Hash, sync::mpsc, thread, time::Duration};
+use util::ResultExt;
+
+pub const SAMPLE_RATE: u32 = 16_000;
+pub const FRAME_DURATION: Duration = Duration::from_millis(20);
+const FRAME_LEN: usize = (SAMPLE_RATE as u128 * FRAME_DURATION.as_millis() / 1000) as usize;
+
+const MU_LAW_BIAS: i32 = 0x84;
+const MU_LAW_CLIP: i32 = 32635;
+
+/// Captures the default microphone until dropped.
+pub struct RelayedAudioInput {
+    _stop: mpsc::Sender<()>,
+}
+
+impl RelayedAudioInput {
+    /// Starts capturing, calling `on_frame` with every encoded frame from the thread
+    /// that captures them.
+    pub fn start(on_frame: Box<dyn Fn(Vec<u8>) + Send + Sync>) -> Result<Self> {
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        let (started_tx, started_rx) = mpsc::channel();
+        // Streams can't be sent between threads on every platform, so each one is
+        // kept on a thread of its own until the input is dropped.
+        thread::Builder::new()
+            .name("relayed audio input".into())
+            .spawn(move || match build_input_stream(on_frame) {
+                Ok(stream) => {
+                    started_tx.send(Ok(())).ok();
+                    stop_rx.recv().ok();
+                    drop(stream);
+                }
+                Err(error) => {
+                    started_tx.send(Err(error)).ok();
+                }
+            })?;
+        started_rx
+            .recv()
+            .map_err(|_| anyhow!("audio input thread exited"))??;
+        Ok(Self { _stop: stop_tx })
+    }
+}
+
+fn build_input_stream(on_frame: Box<dyn Fn(Vec<u8>) + Send + Sync>) -> Result<cpal::Stream> {
+    let device = cpal::default_host()
+        .default_input_device()
+        .ok_or_else(|| anyhow!("no microphone available"))?;
+    let config = device.default_input_config()?;
+    let sample_format = config.sample_format();
+    let config = cpal::StreamConfig::from(config);
+    let mut encoder = FrameEncoder::new(config.sample_rate.0, config.channels, on_frame);
+    let stream = match sample_format {
+        cpal::SampleFormat::F32 => device.build_input_stream(
+            &config,
+            move |data: &[f32], _: &_| encoder.push(data),
+            log_input_error,
+            None,
+        )?,
+        cpal::SampleFormat::I16 => device.build_input_stream(
+            &config,
+            move |data: &[i16], _: &_| {
+                let data = data
+                    .iter()
+                    .map(|sample| *sample as f32 / 32768.)
+                    .collect::<Vec<_>>();
+                encoder.push(&data);
+            },
+            log_input_error,
+            None,
+        )?,
+        cpal::SampleFormat::U16 => device.build_input_stream(
+            &config,
+            move |data: &[u16], _: &_| {
+                let data = data
+                    .iter()
+                    .map(|sample| (*sample as f32 - 32768.) / 32768.)
+                    .collect::<Vec<_>>();
+                encoder.push(&data);
+            },
+            log_input_error,
+            None,
+        )?,
+        sample_format => bail!("unsupported microphone sample format {sample_format:?}"),
+    };
+    stream.play()?;
+    Ok(stream)
+}
+
+fn log_input_error(error: cpal::StreamError) {
+    log::error!("error capturing relayed audio: {error}");
+}
+
+/// Mixes captured audio down to mono, resamples it to [`SAMPLE_RATE`], and encodes
+/// it into frames.
+struct FrameEncoder {
+    channels: usize,
+    /// How many captured samples there are to each resampled one.
+    step: f64,
+    /// Where the next resampled sample falls, from the previous captured sample at 0
+    /// to the next one at 1.
+    position: f64,
+    previous: f32,
+    frame: Vec<u8>,
+    on_frame: Box<dyn Fn(Vec<u8>) + Send + Sync>,
+}
+
+impl FrameEncoder {
+    fn new(sample_rate: u32, channels: u16, on_frame: Box<dyn Fn(Vec<u8>) + Send + Sync>) -> Self {
+        Self {
+            channels: channels.max(1) as usize,
+            step: sample_rate as f64 / SAMPLE_RATE as f64,
+            position: 0.,
+            previous: 0.,
+            frame: Vec::with_capacity(FRAME_LEN),
+            on_frame,
+        }
+    }
+
+    fn push(&mut self, interleaved: &[f32]) {
+        for samples in interleaved.chunks(self.channels) {
+            let sample = samples.iter().sum::<f32>() / samples.len() as f32;
+            while self.position <= 1. {
+                let resampled = self.previous + (sample - self.previous) * self.position as f32;
+                self.frame.push(encode_sample(resampled));
+                if self.frame.len() == FRAME_LEN {
+                    (self.on_frame)(std::mem::replace(
+                        &mut self.frame,
+                        Vec::with_capacity(FRAME_LEN),
+                    ));
+                }
+                self.position += self.step;
+            }
+            self.position -= 1.;
+            self.previous = sample;
+        }
+    }
+}
+
+/// Plays the frames of each speaker through the default output, on a sink of their
+/// own so that speakers are mixed together.
+pub struct RelayedAudioOutput<K> {
+    commands: Mutex<Option<mpsc::Sender<OutputCommand<K>>>>,
+}
+
+enum OutputCommand<K> {
+    Play(K, Option<Vec<u8>>),
+    RemoveSpeaker(K),
+}
+
+impl<K: 'static + Send + Hash + Eq> Default for RelayedAudioOutput<K> {
+    fn default() -> Self {
+        Self {
+            commands: Mutex::new(None),
+        }
+    }
+}
+
+impl<K: 'static + Send + Hash + Eq> RelayedAudioOutput<K> {
+    /// Queues the given speaker's next frame. A frame of `None` was lost, and is
+    /// played as silence.
+    pub fn play(&self, speaker: K, frame: Option<&[u8]>) {
+        self.send(OutputCommand::Play(
+            speaker,
+            frame.map(|frame| frame.to_vec()),
+        ));
+    }
+
+    pub fn remove_speaker(&self, speaker: K) {
+        self.send(OutputCommand::RemoveSpeaker(speaker));
+    }
+
+    fn send(&self, command: OutputCommand<K>) {
+        let mut commands = self.commands.lock();
+        // The output is opened on first use, and reopened if it failed.
+        let commands_tx = commands.get_or_insert_with(|| {
+            let (commands_tx, commands_rx) = mpsc::channel();
+            thread::Builder::new()
+                .name("relayed audio output".into())
+                .spawn(move || play_output(commands_rx))
+                .log_err();
+            commands_tx
+        });
+        if commands_tx.send(command).is_err() {
+            commands.take();
+        }
+    }
+}
+
+fn play_output<K: Hash + Eq>(commands: mpsc::Receiver<OutputCommand<K>>) {
+    let Some((_stream, handle)) = OutputStream::try_default().log_err() else {
+        return;
+    };
+    let mut sinks = HashMap::<K, Sink>::default();
+    for command in commands {
+        match command {
+            OutputCommand::Play(speaker, frame) => {
+                let sink = match sinks.entry(speaker) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let Some(sink) = Sink::try_new(&handle).log_err() else {
+                            continue;
+                        };
+                        entry.insert(sink)
+                    }
+                };
+                let samples = match frame {
+                    Some(frame) => frame.iter().copied().map(decode_sample).collect(),
+                    None => vec![0.; FRAME_LEN],
+                };
+                sink.append(SamplesBuffer::new(1, SAMPLE_RATE, samples));
+            }
+            OutputCommand::RemoveSpeaker(speaker) => {
+                sinks.remove(&speaker);
+            }
+        }
+    }
+}
+
+fn encode_sample(sample: f32) -> u8 {
+    let mut magnitude = (sample.clamp(-1., 1.) * 32767.) as i32;
+    let sign = if magnitude < 0 {
+        magnitude = -magnitude;
+        0x80
+    } else {
+        0
+    };
+    let magnitude = magnitude.min(MU_LAW_CLIP) + MU_LAW_BIAS;
+    let exponent = (31 - magnitude.leading_zeros() as i32 - 7).clamp(0, 7);
+    let mantissa = (magnitude >> (exponent + 3)) & 0x0f;
+    !(sign | (exponent << 4) as u8 | mantissa as u8)
+}
+
+fn decode_sample(byte: u8) -> f32 {
+    let byte = !byte;
+    let exponent = ((byte >> 4) & 0x07) as i32;
+    let mantissa = (byte & 0x0f) as i32;
+    let magnitude = (((mantissa << 3) + MU_LAW_BIAS) << exponent) - MU_LAW_BIAS;
+    let sample = if byte & 0x80 == 0 {
+        magnitude
+    } else {
+        -magnitude
+    };
+    sample as f32 / 32768.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_mu_law_round_trip() {
+        for sample in [0., 0.001, -0.001, 0.25, -0.5, 0.9, -1., 1.] {
+            let decoded = decode_sample(encode_sample(sample));
+            assert!(
+                (decoded - sample).abs() <= sample.abs() / 16. + 0.001,
+                "{sample} decoded as {decoded}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_frame_encoder() {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let mut encoder = FrameEncoder::new(48_000, 2, {
+            let frames = frames.clone();
+            Box::new(move |frame| frames.lock().push(frame))
+        });
+
+        // 50ms of stereo audio at 48kHz makes two whole frames at 16kHz.
+        encoder.push(&vec![0.5; 48 * 50 * 2]);
+        let frames = frames.lock();
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|frame| frame.len() == FRAME_LEN));
+        assert_eq!(
+            decode_sample(frames[1][0]),
+            decode_sample(encode_sample(0.5))
+        );
+    }
+}
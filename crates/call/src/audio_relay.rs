@@ -0,0 +1,336 @@
+use audio::relay::{RelayedAudioInput, RelayedAudioOutput};
+use client::{
+    proto::{self, PeerId},
+    Client,
+};
+use collections::{BTreeMap, HashMap};
+use gpui::{AppContext, Global, Task};
+use std::{
+    any::Any,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering::SeqCst},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// How much audio each relayed frame carries.
+pub const RELAYED_AUDIO_FRAME_DURATION: Duration = audio::relay::FRAME_DURATION;
+
+/// How many frames are buffered for a speaker before their audio starts playing,
+/// to smooth over the jitter of going through the server.
+const JITTER_BUFFER_DEPTH: usize = 3;
+
+/// Frames beyond this many are dropped, oldest first, so that a speaker whose
+/// frames arrive in a burst doesn't fall further and further behind.
+const MAX_JITTER_BUFFER_LEN: usize = 25;
+
+/// Captures, encodes, decodes and plays the audio of calls whose voice is relayed
+/// through the collab server, because the server has no LiveKit.
+pub trait AudioRelayDevice: Send + Sync {
+    /// Starts capturing the microphone, calling `on_frame` with an encoded frame for
+    /// every [`RELAYED_AUDIO_FRAME_DURATION`] of audio. Capturing stops when the
+    /// returned value is dropped.
+    fn start_capture(
+        &self,
+        on_frame: Box<dyn Fn(Vec<u8>) + Send + Sync>,
+    ) -> anyhow::Result<Box<dyn Any + Send>>;
+
+    /// Decodes and plays the next frame from the given speaker. `None` means the
+    /// frame was lost, and the decoder should conceal the gap.
+    fn play(&self, speaker: PeerId, frame: Option<&[u8]>);
+
+    /// Stops playing audio from the given speaker and releases their decoder.
+    fn remove_speaker(&self, speaker: PeerId);
+}
+
+/// The device relayed calls use outside of tests, which captures the default
+/// microphone and plays through the default output, in the format of
+/// [`audio::relay`].
+#[derive(Default)]
+pub struct SystemAudioRelayDevice {
+    output: RelayedAudioOutput<PeerId>,
+}
+
+impl AudioRelayDevice for SystemAudioRelayDevice {
+    fn start_capture(
+        &self,
+        on_frame: Box<dyn Fn(Vec<u8>) + Send + Sync>,
+    ) -> anyhow::Result<Box<dyn Any + Send>> {
+        Ok(Box::new(RelayedAudioInput::start(on_frame)?))
+    }
+
+    fn play(&self, speaker: PeerId, frame: Option<&[u8]>) {
+        self.output.play(speaker, frame);
+    }
+
+    fn remove_speaker(&self, speaker: PeerId) {
+        self.output.remove_speaker(speaker);
+    }
+}
+
+struct GlobalAudioRelayDevice(Arc<dyn AudioRelayDevice>);
+
+impl Global for GlobalAudioRelayDevice {}
+
+/// Registers the device that relayed calls use for audio. Without one, rooms on
+/// servers that lack LiveKit have no voice.
+pub fn set_audio_relay_device(device: Arc<dyn AudioRelayDevice>, cx: &mut AppContext) {
+    cx.set_global(GlobalAudioRelayDevice(device));
+}
+
+pub(crate) fn audio_relay_device(cx: &AppContext) -> Option<Arc<dyn AudioRelayDevice>> {
+    cx.try_global::<GlobalAudioRelayDevice>()
+        .map(|device| device.0.clone())
+}
+
+/// The voice of a room that is relayed through the collab server.
+pub(crate) struct AudioRelay {
+    room_id: u64,
+    client: Arc<Client>,
+    device: Arc<dyn AudioRelayDevice>,
+    next_sequence: Arc<AtomicU32>,
+    capture: Option<Box<dyn Any + Send>>,
+    pub muted_by_user: bool,
+    pub deafened: bool,
+    speakers: HashMap<PeerId, JitterBuffer>,
+    _play_frames: Task<()>,
+}
+
+impl AudioRelay {
+    pub fn new(
+        room_id: u64,
+        client: Arc<Client>,
+        device: Arc<dyn AudioRelayDevice>,
+        muted_by_user: bool,
+        play_frames: Task<()>,
+    ) -> Self {
+        Self {
+            room_id,
+            client,
+            device,
+            next_sequence: Default::default(),
+            capture: None,
+            muted_by_user,
+            deafened: false,
+            speakers: HashMap::default(),
+            _play_frames: play_frames,
+        }
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    pub fn start_capture(&mut self) -> anyhow::Result<()> {
+        if self.capture.is_some() {
+            return Ok(());
+        }
+
+        let room_id = self.room_id;
+        let client = self.client.clone();
+        let next_sequence = self.next_sequence.clone();
+        // Sending from the capture thread keeps the main thread out of the audio path.
+        let logged_send_error = AtomicBool::new(false);
+        self.capture = Some(self.device.start_capture(Box::new(move |frame| {
+            let result = client.send(proto::RelayedAudioFrame {
+                room_id,
+                sequence: next_sequence.fetch_add(1, SeqCst),
+                audio_data: frame,
+            });
+            if let Err(error) = result {
+                if !logged_send_error.swap(true, SeqCst) {
+                    log::error!("failed to relay audio frame: {error:?}");
+                }
+            }
+        }))?);
+        Ok(())
+    }
+
+    pub fn stop_capture(&mut self) {
+        self.capture.take();
+    }
+
+    pub fn receive_frame(&mut self, speaker: PeerId, sequence: u32, frame: Vec<u8>) {
+        if self.deafened {
+            return;
+        }
+        self.speakers
+            .entry(speaker)
+            .or_default()
+            .push(sequence, frame);
+    }
+
+    /// Plays the next frame of every speaker. `play_frames` calls this once per
+    /// [`RELAYED_AUDIO_FRAME_DURATION`].
+    pub fn play_frames(&mut self) {
+        for (speaker, buffer) in &mut self.speakers {
+            if let Some(frame) = buffer.pop() {
+                self.device.play(*speaker, frame.as_deref());
+            }
+        }
+    }
+
+    pub fn remove_speaker(&mut self, speaker: PeerId) {
+        if self.speakers.remove(&speaker).is_some() {
+            self.device.remove_speaker(speaker);
+        }
+    }
+
+    pub fn set_deafened(&mut self, deafened: bool) {
+        self.deafened = deafened;
+        if deafened {
+            for speaker in self.speakers.drain().map(|(speaker, _)| speaker) {
+                self.device.remove_speaker(speaker);
+            }
+        }
+    }
+}
+
+/// A device that tests can feed captured frames into, and that records what it plays.
+#[cfg(any(test, feature = "test-support"))]
+#[derive(Default)]
+pub struct FakeAudioRelayDevice {
+    state: Arc<std::sync::Mutex<FakeAudioRelayDeviceState>>,
+}
+
+#[cfg(any(test, feature = "test-support"))]
+#[derive(Default)]
+struct FakeAudioRelayDeviceState {
+    on_frame: Option<Arc<dyn Fn(Vec<u8>) + Send + Sync>>,
+    played_frames: Vec<(PeerId, Option<Vec<u8>>)>,
+}
+
+#[cfg(any(test, feature = "test-support"))]
+impl FakeAudioRelayDevice {
+    pub fn is_capturing(&self) -> bool {
+        self.state.lock().unwrap().on_frame.is_some()
+    }
+
+    /// Simulates the microphone picking up a frame, which is only sent while capturing.
+    pub fn capture_frame(&self, frame: Vec<u8>) {
+        let on_frame = self.state.lock().unwrap().on_frame.clone();
+        if let Some(on_frame) = on_frame {
+            on_frame(frame);
+        }
+    }
+
+    pub fn take_played_frames(&self) -> Vec<(PeerId, Option<Vec<u8>>)> {
+        std::mem::take(&mut self.state.lock().unwrap().played_frames)
+    }
+}
+
+#[cfg(any(test, feature = "test-support"))]
+impl AudioRelayDevice for FakeAudioRelayDevice {
+    fn start_capture(
+        &self,
+        on_frame: Box<dyn Fn(Vec<u8>) + Send + Sync>,
+    ) -> anyhow::Result<Box<dyn Any + Send>> {
+        struct StopCapture(Arc<std::sync::Mutex<FakeAudioRelayDeviceState>>);
+
+        impl Drop for StopCapture {
+            fn drop(&mut self) {
+                self.0.lock().unwrap().on_frame.take();
+            }
+        }
+
+        self.state.lock().unwrap().on_frame = Some(on_frame.into());
+        Ok(Box::new(StopCapture(self.state.clone())))
+    }
+
+    fn play(&self, speaker: PeerId, frame: Option<&[u8]>) {
+        self.state
+            .lock()
+            .unwrap()
+            .played_frames
+            .push((speaker, frame.map(|frame| frame.to_vec())));
+    }
+
+    fn remove_speaker(&self, _: PeerId) {}
+}
+
+/// Reorders the frames received from one speaker, and paces them out once enough
+/// have arrived to play without gaps.
+#[derive(Default)]
+pub(crate) struct JitterBuffer {
+    frames: BTreeMap<u32, Vec<u8>>,
+    next_sequence: Option<u32>,
+    playing: bool,
+}
+
+impl JitterBuffer {
+    pub fn push(&mut self, sequence: u32, frame: Vec<u8>) {
+        if self
+            .next_sequence
+            .map_or(false, |next_sequence| sequence < next_sequence)
+        {
+            // The frame arrived after its turn to be played.
+            return;
+        }
+
+        self.frames.insert(sequence, frame);
+        while self.frames.len() > MAX_JITTER_BUFFER_LEN {
+            self.frames.pop_first();
+            self.next_sequence = self.frames.keys().next().copied();
+        }
+    }
+
+    /// Returns the frame to play next, or `None` if nothing should be played. A
+    /// frame of `None` stands for one that was lost.
+    pub fn pop(&mut self) -> Option<Option<Vec<u8>>> {
+        if !self.playing {
+            if self.frames.len() < JITTER_BUFFER_DEPTH {
+                return None;
+            }
+            // Skip over any silence since the speaker last went quiet.
+            self.next_sequence = self.frames.keys().next().copied();
+            self.playing = true;
+        }
+
+        let Some(&first_sequence) = self.frames.keys().next() else {
+            // The speaker went quiet. Buffer up again before playing their next words.
+            self.playing = false;
+            return None;
+        };
+        let sequence = self.next_sequence.unwrap_or(first_sequence);
+        self.next_sequence = Some(sequence.wrapping_add(1));
+        Some(self.frames.remove(&sequence))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jitter_buffer() {
+        let mut buffer = JitterBuffer::default();
+
+        // Nothing plays until enough frames are buffered, then they play in order.
+        buffer.push(1, vec![1]);
+        buffer.push(0, vec![0]);
+        assert_eq!(buffer.pop(), None);
+        buffer.push(3, vec![3]);
+        assert_eq!(buffer.pop(), Some(Some(vec![0])));
+        assert_eq!(buffer.pop(), Some(Some(vec![1])));
+
+        // Missing frames are reported as lost, and show up too late to be played.
+        assert_eq!(buffer.pop(), Some(None));
+        buffer.push(2, vec![2]);
+        assert_eq!(buffer.pop(), Some(Some(vec![3])));
+
+        // Once the buffer runs dry, it buffers up again.
+        assert_eq!(buffer.pop(), None);
+        buffer.push(4, vec![4]);
+        buffer.push(5, vec![5]);
+        assert_eq!(buffer.pop(), None);
+        buffer.push(6, vec![6]);
+        assert_eq!(buffer.pop(), Some(Some(vec![4])));
+
+        // A burst of frames only keeps the most recent ones.
+        for sequence in 7..7 + MAX_JITTER_BUFFER_LEN as u32 + 2 {
+            buffer.push(sequence, vec![sequence as u8]);
+        }
+        assert_eq!(buffer.pop(), Some(Some(vec![9])));
+    }
+}
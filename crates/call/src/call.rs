@@ -1,10 +1,18 @@
+pub mod audio_relay;
+pub mod call_history;
 pub mod call_settings;
+pub mod call_summary;
 pub mod participant;
+pub mod preflight;
 pub mod room;
+pub mod screen_annotation;
+pub mod session_statistics;
 
 use anyhow::{anyhow, Result};
 use audio::Audio;
+use call_history::CallHistoryEntry;
 use call_settings::CallSettings;
+use call_summary::CallSummary;
 use client::{proto, Client, TypedEnvelope, User, UserStore, ZED_ALWAYS_ACTIVE};
 use collections::HashSet;
 use futures::{channel::oneshot, future::Shared, Future, FutureExt};
@@ -13,13 +21,13 @@ use gpui::{
     Task, WeakModel,
 };
 use postage::watch;
-use project::Project;
+use project::{Project, ProjectPath, WorktreeId};
 use room::Event;
 use settings::Settings;
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 pub use participant::ParticipantLocation;
-pub use room::Room;
+pub use room::{GuestInvitation, Room};
 
 struct GlobalActiveCall(Model<ActiveCall>);
 
@@ -69,6 +77,52 @@ pub struct IncomingCall {
     pub calling_user: Arc<User>,
     pub participants: Vec<Arc<User>>,
     pub initial_project: Option<proto::ParticipantProject>,
+    pub context: Option<InviteContext>,
+    /// Whether the call came in while the user was already in another call, which
+    /// they need to leave to accept this one.
+    pub waiting: bool,
+    /// Whether the user is invited to observe the call. Observers don't appear to
+    /// the call's other participants, other than its admins and other observers.
+    pub observer: bool,
+}
+
+/// A note the caller attached to an invite, such as "can you look at foo.rs:120?",
+/// optionally pointing at a location in the project they shared with it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InviteContext {
+    pub message: String,
+    pub path: Option<ProjectPath>,
+    /// The zero-based row to open `path` at.
+    pub row: Option<u32>,
+}
+
+impl InviteContext {
+    pub fn from_proto(context: proto::InviteContext) -> Self {
+        let path = context
+            .worktree_id
+            .zip(context.path)
+            .map(|(worktree_id, path)| ProjectPath {
+                worktree_id: WorktreeId::from_usize(worktree_id as usize),
+                path: proto::path_from_proto(path).into(),
+            });
+        Self {
+            message: context.message,
+            row: context.row.filter(|_| path.is_some()),
+            path,
+        }
+    }
+
+    pub fn to_proto(&self) -> proto::InviteContext {
+        proto::InviteContext {
+            message: self.message.clone(),
+            worktree_id: self.path.as_ref().map(|path| path.worktree_id.to_proto()),
+            path: self
+                .path
+                .as_ref()
+                .map(|path| proto::path_to_proto(&path.path)),
+            row: self.row,
+        }
+    }
 }
 
 /// Singleton global maintaining the user's participation in a room across workspaces.
@@ -82,6 +136,7 @@ pub struct ActiveCall {
         watch::Sender<Option<IncomingCall>>,
         watch::Receiver<Option<IncomingCall>>,
     ),
+    waiting_calls: Vec<IncomingCall>,
     client: Arc<Client>,
     user_store: Model<UserStore>,
     _subscriptions: Vec<client::Subscription>,
@@ -97,6 +152,7 @@ impl ActiveCall {
             location: None,
             pending_invites: Default::default(),
             incoming_call: watch::channel(),
+            waiting_calls: Vec::new(),
             _join_debouncer: OneAtATime { cancel: None },
             _subscriptions: vec![
                 client.add_request_handler(cx.weak_model(), Self::handle_incoming_call),
@@ -131,9 +187,21 @@ impl ActiveCall {
                 })?
                 .await?,
             initial_project: envelope.payload.initial_project,
+            context: envelope.payload.context.map(InviteContext::from_proto),
+            waiting: envelope.payload.waiting,
+            observer: envelope.payload.observer,
         };
-        this.update(&mut cx, |this, _| {
-            *this.incoming_call.0.borrow_mut() = Some(call);
+        this.update(&mut cx, |this, cx| {
+            if call.waiting {
+                this.waiting_calls
+                    .retain(|waiting_call| waiting_call.room_id != call.room_id);
+                this.waiting_calls.push(call);
+                this.ring_next_waiting_call();
+                cx.notify();
+            } else {
+                this.take_waiting_call(call.room_id, cx);
+                *this.incoming_call.0.borrow_mut() = Some(call);
+            }
         })?;
 
         Ok(proto::Ack {})
@@ -145,14 +213,17 @@ impl ActiveCall {
         _: Arc<Client>,
         mut cx: AsyncAppContext,
     ) -> Result<()> {
-        this.update(&mut cx, |this, _| {
+        this.update(&mut cx, |this, cx| {
+            let room_id = envelope.payload.room_id;
             let mut incoming_call = this.incoming_call.0.borrow_mut();
             if incoming_call
                 .as_ref()
-                .map_or(false, |call| call.room_id == envelope.payload.room_id)
+                .map_or(false, |call| call.room_id == room_id && !call.waiting)
             {
                 incoming_call.take();
             }
+            drop(incoming_call);
+            this.take_waiting_call(room_id, cx);
         })?;
         Ok(())
     }
@@ -172,6 +243,74 @@ impl ActiveCall {
         initial_project: Option<Model<Project>>,
         cx: &mut ModelContext<Self>,
     ) -> Task<Result<()>> {
+        self.invite_with_context(called_user_id, initial_project, None, cx)
+    }
+
+    /// Invites a user into the call, showing them the given message and location
+    /// alongside the invite. The location must be in `initial_project`.
+    pub fn invite_with_context(
+        &mut self,
+        called_user_id: u64,
+        initial_project: Option<Model<Project>>,
+        context: Option<InviteContext>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        self.invite_internal(called_user_id, initial_project, context, None, cx)
+    }
+
+    /// Creates a room that ends after `duration` unless it's extended, and invites a
+    /// user into it. Fails if there's already a call.
+    pub fn invite_to_time_boxed_room(
+        &mut self,
+        called_user_id: u64,
+        initial_project: Option<Model<Project>>,
+        duration: Duration,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        if self.room.is_some() || self.pending_room_creation.is_some() {
+            return Task::ready(Err(anyhow!("already in a call")));
+        }
+        self.invite_internal(called_user_id, initial_project, None, Some(duration), cx)
+    }
+
+    /// Invites a user to observe the current call. Observers can follow the call's
+    /// participants and view its shared projects, but are only visible to the
+    /// room's admins and other observers. Only the room's admins can invite them.
+    pub fn invite_observer(
+        &mut self,
+        called_user_id: u64,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        let Some(room) = self.room().cloned() else {
+            return Task::ready(Err(anyhow!("no active call")));
+        };
+        if !self.pending_invites.insert(called_user_id) {
+            return Task::ready(Err(anyhow!("user was already invited")));
+        }
+        cx.notify();
+
+        let call = room.update(cx, |room, cx| {
+            room.call(called_user_id, None, None, true, cx)
+        });
+        cx.spawn(move |this, mut cx| async move {
+            let result = call.await;
+            this.update(&mut cx, |this, cx| {
+                this.pending_invites.remove(&called_user_id);
+                cx.notify();
+            })?;
+            result
+        })
+    }
+
+    fn invite_internal(
+        &mut self,
+        called_user_id: u64,
+        initial_project: Option<Model<Project>>,
+        context: Option<InviteContext>,
+        duration: Option<Duration>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        let context = context.map(|context| context.to_proto());
         if !self.pending_invites.insert(called_user_id) {
             return Task::ready(Err(anyhow!("user was already invited")));
         }
@@ -201,7 +340,7 @@ impl ActiveCall {
                 };
 
                 room.update(&mut cx, move |room, cx| {
-                    room.call(called_user_id, initial_project_id, cx)
+                    room.call(called_user_id, initial_project_id, context, false, cx)
                 })?
                 .await?;
 
@@ -218,6 +357,8 @@ impl ActiveCall {
                                 Room::create(
                                     called_user_id,
                                     initial_project,
+                                    context,
+                                    duration,
                                     client,
                                     user_store,
                                     cx,
@@ -283,11 +424,65 @@ impl ActiveCall {
         })
     }
 
+    /// Fetches the calls the current user has recently placed or received, oldest first.
+    ///
+    /// Pass the id of the oldest entry seen so far to page further back in time.
+    pub fn call_history(
+        &self,
+        before_id: Option<u64>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<CallHistoryEntry>>> {
+        let client = self.client.clone();
+        let user_store = self.user_store.clone();
+        cx.spawn(move |_, mut cx| async move {
+            let response = client.request(proto::GetCallHistory { before_id }).await?;
+            let mut entries = Vec::with_capacity(response.calls.len());
+            for record in response.calls {
+                entries.push(CallHistoryEntry::from_proto(record, &user_store, &mut cx).await?);
+            }
+            Ok(entries)
+        })
+    }
+
+    /// Fetches the summary of a call that the current user took part in.
+    pub fn call_summary(
+        &self,
+        summary_id: u64,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<CallSummary>> {
+        let client = self.client.clone();
+        let user_store = self.user_store.clone();
+        cx.spawn(move |_, mut cx| async move {
+            let response = client.request(proto::GetCallSummary { summary_id }).await?;
+            let summary = response
+                .summary
+                .ok_or_else(|| anyhow!("invalid call summary"))?;
+            CallSummary::from_proto(summary, &user_store, &mut cx).await
+        })
+    }
+
+    /// Calls back the user on the other end of a previous call.
+    pub fn redial(
+        &mut self,
+        entry: &CallHistoryEntry,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        let Some(user_id) = self.client.user_id() else {
+            return Task::ready(Err(anyhow!("not signed in")));
+        };
+        let other_user_id = entry.other_user(user_id).id;
+        self.invite(other_user_id, None, cx)
+    }
+
     pub fn incoming(&self) -> watch::Receiver<Option<IncomingCall>> {
         self.incoming_call.1.clone()
     }
 
     pub fn accept_incoming(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        if let Some(room_id) = self.ringing_waiting_call() {
+            return self.hang_up_and_accept(room_id, cx);
+        }
+
         if self.room.is_some() {
             return Task::ready(Err(anyhow!("cannot join while on another call")));
         }
@@ -320,7 +515,11 @@ impl ActiveCall {
         })
     }
 
-    pub fn decline_incoming(&mut self, _: &mut ModelContext<Self>) -> Result<()> {
+    pub fn decline_incoming(&mut self, cx: &mut ModelContext<Self>) -> Result<()> {
+        if let Some(room_id) = self.ringing_waiting_call() {
+            return self.decline_waiting(room_id, cx);
+        }
+
         let call = self
             .incoming_call
             .0
@@ -334,6 +533,95 @@ impl ActiveCall {
         Ok(())
     }
 
+    /// Calls that came in while the user was already in a call, oldest first.
+    pub fn waiting_calls(&self) -> &[IncomingCall] {
+        &self.waiting_calls
+    }
+
+    pub fn decline_waiting(&mut self, room_id: u64, cx: &mut ModelContext<Self>) -> Result<()> {
+        self.take_waiting_call(room_id, cx)
+            .ok_or_else(|| anyhow!("no waiting call"))?;
+        report_call_event_for_room("decline waiting", room_id, None, &self.client);
+        self.client.send(proto::DeclineCall { room_id })?;
+        Ok(())
+    }
+
+    /// Leaves the current call, if any, and joins the room of a waiting call. The
+    /// server makes the switch in one step, so the room being left learns about it
+    /// at the same time as the one being joined.
+    pub fn hang_up_and_accept(
+        &mut self,
+        room_id: u64,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        if self.pending_room_creation.is_some() {
+            return Task::ready(Err(anyhow!("cannot switch calls while creating a room")));
+        }
+        if self.take_waiting_call(room_id, cx).is_none() {
+            return Task::ready(Err(anyhow!("no waiting call")));
+        }
+
+        if let Some(room) = self.room().cloned() {
+            room.update(cx, |room, cx| room.clear_state(cx));
+        }
+
+        let client = self.client.clone();
+        let user_store = self.user_store.clone();
+        let join = self._join_debouncer.spawn(cx, move |cx| {
+            Room::accept_waiting(room_id, client, user_store, cx)
+        });
+
+        cx.spawn(|this, mut cx| async move {
+            let room = join.await?;
+            this.update(&mut cx, |this, cx| this.set_room(room.clone(), cx))?
+                .await?;
+            this.update(&mut cx, |this, cx| {
+                this.report_call_event("hang up and accept", cx)
+            })?;
+            Ok(())
+        })
+    }
+
+    fn ringing_waiting_call(&self) -> Option<u64> {
+        self.incoming_call
+            .1
+            .borrow()
+            .as_ref()
+            .filter(|call| call.waiting)
+            .map(|call| call.room_id)
+    }
+
+    fn take_waiting_call(
+        &mut self,
+        room_id: u64,
+        cx: &mut ModelContext<Self>,
+    ) -> Option<IncomingCall> {
+        let ix = self
+            .waiting_calls
+            .iter()
+            .position(|call| call.room_id == room_id)?;
+        let call = self.waiting_calls.remove(ix);
+        self.ring_next_waiting_call();
+        cx.notify();
+        Some(call)
+    }
+
+    /// Rings the oldest waiting call through [`Self::incoming`], unless something
+    /// else is already ringing there.
+    fn ring_next_waiting_call(&mut self) {
+        let mut incoming_call = self.incoming_call.0.borrow_mut();
+        let ringing_call_is_stale = incoming_call.as_ref().map_or(true, |call| {
+            call.waiting
+                && !self
+                    .waiting_calls
+                    .iter()
+                    .any(|waiting_call| waiting_call.room_id == call.room_id)
+        });
+        if ringing_call_is_stale {
+            *incoming_call = self.waiting_calls.first().cloned();
+        }
+    }
+
     pub fn join_channel(
         &mut self,
         channel_id: u64,
@@ -393,6 +681,42 @@ impl ActiveCall {
         }
     }
 
+    /// Creates a worktree in the given local project from one of the user's
+    /// project templates, then shares the project in the current room.
+    pub fn share_project_from_template(
+        &mut self,
+        project: Model<Project>,
+        template: &str,
+        abs_path: impl Into<PathBuf>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<u64>> {
+        if self.room.is_none() {
+            return Task::ready(Err(anyhow!("no active call")));
+        }
+
+        let create_worktree = project.update(cx, |project, cx| {
+            project.create_worktree_from_template(template, abs_path, cx)
+        });
+        cx.spawn(move |this, mut cx| async move {
+            create_worktree.await?;
+            this.update(&mut cx, |this, cx| this.share_project(project, cx))?
+                .await
+        })
+    }
+
+    pub fn share_scratch_project(
+        &mut self,
+        project: Model<Project>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<u64>> {
+        if let Some((room, _)) = self.room.as_ref() {
+            self.report_call_event("share scratch project", cx);
+            room.update(cx, |room, cx| room.share_scratch_project(project, cx))
+        } else {
+            Task::ready(Err(anyhow!("no active call")))
+        }
+    }
+
     pub fn unshare_project(
         &mut self,
         project: Model<Project>,
@@ -0,0 +1,59 @@
+use anyhow::{anyhow, Result};
+use client::{proto, User, UserStore};
+use gpui::{AsyncAppContext, Model};
+use std::sync::Arc;
+use time::OffsetDateTime;
+
+pub use proto::CallStatus;
+
+/// A call that the current user placed or received.
+#[derive(Clone, Debug)]
+pub struct CallHistoryEntry {
+    pub id: u64,
+    pub caller: Arc<User>,
+    pub callee: Arc<User>,
+    pub timestamp: OffsetDateTime,
+    pub status: CallStatus,
+}
+
+impl CallHistoryEntry {
+    pub(crate) async fn from_proto(
+        record: proto::CallRecord,
+        user_store: &Model<UserStore>,
+        cx: &mut AsyncAppContext,
+    ) -> Result<Self> {
+        let status = CallStatus::from_i32(record.status)
+            .ok_or_else(|| anyhow!("invalid call status {}", record.status))?;
+        let mut users = user_store
+            .update(cx, |user_store, cx| {
+                user_store.get_users(vec![record.caller_user_id, record.callee_user_id], cx)
+            })?
+            .await?
+            .into_iter();
+        let (Some(caller), Some(callee)) = (users.next(), users.next()) else {
+            return Err(anyhow!("failed to load call participants"));
+        };
+
+        Ok(Self {
+            id: record.id,
+            caller,
+            callee,
+            timestamp: OffsetDateTime::from_unix_timestamp(record.timestamp as i64)?,
+            status,
+        })
+    }
+
+    /// Returns the user on the other end of the call from the given user.
+    pub fn other_user(&self, user_id: u64) -> &Arc<User> {
+        if self.caller.id == user_id {
+            &self.callee
+        } else {
+            &self.caller
+        }
+    }
+
+    /// Returns whether the given user was called and never picked up.
+    pub fn is_missed_by(&self, user_id: u64) -> bool {
+        self.callee.id == user_id && self.status == CallStatus::Missed
+    }
+}
@@ -8,6 +8,9 @@ use settings::Settings;
 pub struct CallSettings {
     pub mute_on_join: bool,
     pub share_on_join: bool,
+    pub summarize_calls: bool,
+    pub share_session_statistics: bool,
+    pub session_export_webhook: Option<String>,
 }
 
 /// Configuration of voice calls in Zed.
@@ -22,6 +25,26 @@ pub struct CallSettingsContent {
     ///
     /// Default: true
     pub share_on_join: Option<bool>,
+
+    /// Whether to be sent a summary of each call when it ends, covering who took
+    /// part and which files were edited.
+    ///
+    /// Default: false
+    pub summarize_calls: Option<bool>,
+
+    /// Whether to contribute statistics about your part in each call to its
+    /// summary: how long you talked, how much you wrote and how long you spent
+    /// in each file. They're only included once at least two participants have
+    /// shared theirs, and aren't attributed to anyone.
+    ///
+    /// Default: false
+    pub share_session_statistics: Option<bool>,
+
+    /// A URL to post each exported session to, as a JSON object whose `text`
+    /// field holds the session's markdown, in addition to saving it locally.
+    ///
+    /// Default: null
+    pub session_export_webhook: Option<String>,
 }
 
 impl Settings for CallSettings {
@@ -0,0 +1,239 @@
+use anyhow::Result;
+use client::{proto, User, UserStore};
+use gpui::{AsyncAppContext, Model};
+use std::{fmt::Write as _, sync::Arc};
+use time::{Duration, OffsetDateTime};
+
+pub use proto::{CallStatistics, EditedFile};
+
+/// A summary of a call that the current user took part in, assembled by the
+/// server from the room's activity once its last participant left.
+#[derive(Clone, Debug)]
+pub struct CallSummary {
+    pub id: u64,
+    pub channel_id: Option<u64>,
+    pub started_at: OffsetDateTime,
+    pub ended_at: OffsetDateTime,
+    pub participants: Vec<Arc<User>>,
+    pub edited_files: Vec<EditedFile>,
+    /// Anonymized statistics, when enough participants opted into sharing them.
+    pub statistics: Option<CallStatistics>,
+}
+
+impl CallSummary {
+    pub(crate) async fn from_proto(
+        summary: proto::CallSummary,
+        user_store: &Model<UserStore>,
+        cx: &mut AsyncAppContext,
+    ) -> Result<Self> {
+        let participants = user_store
+            .update(cx, |user_store, cx| {
+                user_store.get_users(summary.participant_user_ids, cx)
+            })?
+            .await?;
+
+        Ok(Self {
+            id: summary.id,
+            channel_id: summary.channel_id,
+            started_at: OffsetDateTime::from_unix_timestamp(summary.started_at as i64)?,
+            ended_at: OffsetDateTime::from_unix_timestamp(summary.ended_at as i64)?,
+            participants,
+            edited_files: summary.edited_files,
+            statistics: summary.statistics,
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.ended_at - self.started_at
+    }
+
+    /// Renders the summary as a markdown document, for exporting it.
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = String::new();
+        let started_at = self.started_at.to_offset(time::UtcOffset::UTC);
+        writeln!(markdown, "# Call summary").unwrap();
+        writeln!(markdown).unwrap();
+        writeln!(
+            markdown,
+            "- **Started:** {} {:02}:{:02} UTC",
+            started_at.date(),
+            started_at.hour(),
+            started_at.minute()
+        )
+        .unwrap();
+        writeln!(
+            markdown,
+            "- **Duration:** {}",
+            format_duration(self.duration())
+        )
+        .unwrap();
+        let participants = self
+            .participants
+            .iter()
+            .map(|user| format!("@{}", user.github_login))
+            .collect::<Vec<_>>();
+        writeln!(markdown, "- **Participants:** {}", participants.join(", ")).unwrap();
+
+        writeln!(markdown).unwrap();
+        writeln!(markdown, "## Files edited").unwrap();
+        writeln!(markdown).unwrap();
+        if self.edited_files.is_empty() {
+            writeln!(markdown, "No files were edited.").unwrap();
+        } else {
+            writeln!(markdown, "| File | Lines added | Lines removed |").unwrap();
+            writeln!(markdown, "| --- | ---: | ---: |").unwrap();
+            for file in &self.edited_files {
+                writeln!(
+                    markdown,
+                    "| `{}` | {} | {} |",
+                    file.path, file.lines_added, file.lines_removed
+                )
+                .unwrap();
+            }
+        }
+
+        if let Some(statistics) = &self.statistics {
+            writeln!(markdown).unwrap();
+            writeln!(markdown, "## Statistics").unwrap();
+            writeln!(markdown).unwrap();
+            writeln!(
+                markdown,
+                "Shared by {} participants.",
+                statistics.contributor_count
+            )
+            .unwrap();
+            writeln!(markdown).unwrap();
+            if let Some(talk_time_ms) = statistics.total_talk_time_ms {
+                writeln!(
+                    markdown,
+                    "- **Talk time:** {}",
+                    format_duration(Duration::milliseconds(talk_time_ms as i64))
+                )
+                .unwrap();
+            }
+            writeln!(
+                markdown,
+                "- **Characters written:** {}",
+                statistics.total_characters_written
+            )
+            .unwrap();
+            if statistics.file_count > 0 {
+                writeln!(
+                    markdown,
+                    "- **Time in files:** {} across {} files",
+                    format_duration(Duration::milliseconds(statistics.total_file_time_ms as i64)),
+                    statistics.file_count
+                )
+                .unwrap();
+            }
+        }
+        markdown
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let minutes = duration.whole_minutes();
+    if minutes >= 60 {
+        format!("{}h {}m", minutes / 60, minutes % 60)
+    } else if minutes > 0 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}s", duration.whole_seconds().max(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_summary_to_markdown() {
+        let started_at = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let summary = CallSummary {
+            id: 1,
+            channel_id: None,
+            started_at,
+            ended_at: started_at + Duration::minutes(65),
+            participants: vec![
+                Arc::new(User {
+                    id: 1,
+                    github_login: "user_a".into(),
+                    avatar_uri: "".into(),
+                    display_name: None,
+                }),
+                Arc::new(User {
+                    id: 2,
+                    github_login: "user_b".into(),
+                    avatar_uri: "".into(),
+                    display_name: None,
+                }),
+            ],
+            edited_files: vec![EditedFile {
+                path: "zed/src/main.rs".into(),
+                lines_added: 10,
+                lines_removed: 2,
+            }],
+            statistics: None,
+        };
+
+        assert_eq!(
+            summary.to_markdown(),
+            concat!(
+                "# Call summary\n",
+                "\n",
+                "- **Started:** 2023-11-14 22:13 UTC\n",
+                "- **Duration:** 1h 5m\n",
+                "- **Participants:** @user_a, @user_b\n",
+                "\n",
+                "## Files edited\n",
+                "\n",
+                "| File | Lines added | Lines removed |\n",
+                "| --- | ---: | ---: |\n",
+                "| `zed/src/main.rs` | 10 | 2 |\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_call_summary_statistics_to_markdown() {
+        let started_at = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let summary = CallSummary {
+            id: 1,
+            channel_id: None,
+            started_at,
+            ended_at: started_at + Duration::minutes(30),
+            participants: Vec::new(),
+            edited_files: Vec::new(),
+            statistics: Some(CallStatistics {
+                contributor_count: 3,
+                total_talk_time_ms: Some(840_000),
+                total_characters_written: 1235,
+                total_file_time_ms: 1_500_000,
+                file_count: 2,
+            }),
+        };
+
+        assert_eq!(
+            summary.to_markdown(),
+            concat!(
+                "# Call summary\n",
+                "\n",
+                "- **Started:** 2023-11-14 22:13 UTC\n",
+                "- **Duration:** 30m\n",
+                "- **Participants:** \n",
+                "\n",
+                "## Files edited\n",
+                "\n",
+                "No files were edited.\n",
+                "\n",
+                "## Statistics\n",
+                "\n",
+                "Shared by 3 participants.\n",
+                "\n",
+                "- **Talk time:** 14m\n",
+                "- **Characters written:** 1235\n",
+                "- **Time in files:** 25m across 2 files\n",
+            )
+        );
+    }
+}
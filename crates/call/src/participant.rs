@@ -6,7 +6,15 @@ use gpui::WeakModel;
 pub use live_kit_client::Frame;
 pub use live_kit_client::{RemoteAudioTrack, RemoteVideoTrack};
 use project::Project;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
+
+/// How far a participant's clock can be from the server's before others are
+/// told that it's off, which throws off the times they see.
+pub const MAX_CLOCK_SKEW: Duration = Duration::from_secs(60);
+
+/// Participants with less bandwidth than this are told by the server to send
+/// fewer updates.
+const SLOW_CONNECTION_KBPS: u64 = 256;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ParticipantLocation {
@@ -32,11 +40,66 @@ impl ParticipantLocation {
     }
 }
 
+/// What a participant's client found out about itself in the checks it ran
+/// before joining the room.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParticipantCapabilities {
+    pub protocol_version: u32,
+    pub media_available: bool,
+    /// How far ahead of the server's clock the participant's clock is.
+    pub clock_skew_ms: i64,
+    pub bandwidth_kbps: Option<u64>,
+}
+
+impl ParticipantCapabilities {
+    pub fn from_proto(capabilities: &proto::ParticipantCapabilities) -> Self {
+        Self {
+            protocol_version: capabilities.protocol_version,
+            media_available: capabilities.media_available,
+            clock_skew_ms: capabilities.clock_skew_ms,
+            bandwidth_kbps: Some(capabilities.bandwidth_kbps).filter(|kbps| *kbps != 0),
+        }
+    }
+
+    pub fn to_proto(&self) -> proto::ParticipantCapabilities {
+        proto::ParticipantCapabilities {
+            protocol_version: self.protocol_version,
+            media_available: self.media_available,
+            clock_skew_ms: self.clock_skew_ms,
+            bandwidth_kbps: self.bandwidth_kbps.unwrap_or(0),
+        }
+    }
+
+    /// Describes what's limiting the participant, for showing to the others in the call.
+    pub fn limitations(&self) -> Vec<&'static str> {
+        let mut limitations = Vec::new();
+        if !self.media_available {
+            limitations.push("no audio or video");
+        }
+        if self
+            .bandwidth_kbps
+            .map_or(false, |kbps| kbps < SLOW_CONNECTION_KBPS)
+        {
+            limitations.push("slow connection");
+        }
+        if self.clock_skew_ms.unsigned_abs() > MAX_CLOCK_SKEW.as_millis() as u64 {
+            limitations.push("clock is off");
+        }
+        if self.protocol_version != client::PROTOCOL_VERSION {
+            limitations.push("different version of Zed");
+        }
+        limitations
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct LocalParticipant {
     pub projects: Vec<proto::ParticipantProject>,
     pub active_project: Option<WeakModel<Project>>,
     pub role: proto::ChannelRole,
+    /// Whether the local user is observing the room, hidden from participants
+    /// other than its admins and other observers.
+    pub observer: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -47,8 +110,17 @@ pub struct RemoteParticipant {
     pub projects: Vec<proto::ParticipantProject>,
     pub location: ParticipantLocation,
     pub participant_index: ParticipantIndex,
+    /// What the participant reported about itself when joining, unless its
+    /// client predates pre-flight checks.
+    pub capabilities: Option<ParticipantCapabilities>,
+    /// Whether the participant is observing the room. Only the room's admins and
+    /// other observers are told about observers.
+    pub observer: bool,
     pub muted: bool,
     pub speaking: bool,
+    /// Orders participants by when they last joined, moved to another project,
+    /// started speaking or shared their screen, the highest being the latest.
+    pub(crate) last_activity: u64,
     pub video_tracks: HashMap<live_kit_client::Sid, Arc<RemoteVideoTrack>>,
     pub audio_tracks: HashMap<live_kit_client::Sid, Arc<RemoteAudioTrack>>,
 }
@@ -0,0 +1,80 @@
+use crate::{
+    audio_relay,
+    participant::{ParticipantCapabilities, MAX_CLOCK_SKEW},
+};
+use client::{proto, Client};
+use gpui::{AppContext, Global};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+struct GlobalMediaAvailable(bool);
+
+impl Global for GlobalMediaAvailable {}
+
+/// Overrides whether this client can take part in the audio and video of calls.
+/// Otherwise, it can wherever LiveKit runs, or an audio relay device is registered.
+pub fn set_media_available(available: bool, cx: &mut AppContext) {
+    cx.set_global(GlobalMediaAvailable(available));
+}
+
+pub(crate) fn media_available(cx: &AppContext) -> bool {
+    if let Some(available) = cx.try_global::<GlobalMediaAvailable>() {
+        return available.0;
+    }
+    cfg!(any(target_os = "macos", test, feature = "test-support"))
+        || audio_relay::audio_relay_device(cx).is_some()
+}
+
+/// Checks what this client can do in a call before it joins a room, so that the
+/// server and the other participants can account for its limitations. Returns
+/// `None` when the server predates pre-flight checks.
+pub(crate) async fn run_preflight_checks(
+    client: Arc<Client>,
+    media_available: bool,
+) -> Option<ParticipantCapabilities> {
+    let sent_at = SystemTime::now();
+    let started_at = Instant::now();
+    let response = match client.request(proto::RoomPreflight {}).await {
+        Ok(response) => response,
+        Err(error) => {
+            log::warn!("failed to run pre-flight checks: {error:?}");
+            return None;
+        }
+    };
+    let round_trip = started_at.elapsed();
+
+    if response.protocol_version != client::PROTOCOL_VERSION {
+        log::warn!(
+            "server speaks protocol version {}, but this client speaks {}",
+            response.protocol_version,
+            client::PROTOCOL_VERSION
+        );
+    }
+
+    // Assume the server's clock was read halfway through the round trip.
+    let local_time_ms = (sent_at + round_trip / 2)
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+    let clock_skew_ms = local_time_ms - response.server_time_ms as i64;
+    if Duration::from_millis(clock_skew_ms.unsigned_abs()) > MAX_CLOCK_SKEW {
+        log::warn!("clock is {clock_skew_ms}ms ahead of the server's");
+    }
+
+    // Bits per millisecond are kilobits per second.
+    let round_trip_ms = round_trip.as_millis() as u64;
+    let bandwidth_kbps = if round_trip_ms == 0 {
+        None
+    } else {
+        Some(response.probe.len() as u64 * 8 / round_trip_ms)
+    };
+
+    Some(ParticipantCapabilities {
+        protocol_version: client::PROTOCOL_VERSION,
+        media_available,
+        clock_skew_ms,
+        bandwidth_kbps,
+    })
+}
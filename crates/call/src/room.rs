@@ -1,29 +1,50 @@
 use crate::{
+    audio_relay::{self, AudioRelay, RELAYED_AUDIO_FRAME_DURATION},
     call_settings::CallSettings,
-    participant::{LocalParticipant, ParticipantLocation, RemoteParticipant},
+    participant::{
+        LocalParticipant, ParticipantCapabilities, ParticipantLocation, RemoteParticipant,
+    },
+    preflight,
+    screen_annotation::{ScreenAnnotation, ScreenAnnotationShape, SCREEN_ANNOTATION_TTL},
+    session_statistics::SessionStatistics,
 };
 use anyhow::{anyhow, Result};
 use audio::{Audio, Sound};
 use client::{
     proto::{self, PeerId},
-    Client, ParticipantIndex, TypedEnvelope, User, UserStore,
+    ChatStore, Client, Credentials, ParticipantIndex, TypedEnvelope, User, UserStore,
 };
 use collections::{BTreeMap, HashMap, HashSet};
-use fs::Fs;
-use futures::{FutureExt, StreamExt};
+use fs::{Fs, RemoveOptions};
+use futures::{
+    future::{try_join_all, Shared},
+    FutureExt, StreamExt,
+};
 use gpui::{
-    AppContext, AsyncAppContext, Context, EventEmitter, Model, ModelContext, Task, WeakModel,
+    AppContext, AsyncAppContext, Context, EntityId, EventEmitter, Model, ModelContext, Point, Task,
+    WeakModel,
 };
 use language::LanguageRegistry;
 use live_kit_client::{LocalAudioTrack, LocalTrackPublication, LocalVideoTrack, RoomUpdate};
 use postage::{sink::Sink, stream::Stream, watch};
 use project::Project;
 use settings::Settings as _;
-use std::{future::Future, mem, sync::Arc, time::Duration};
-use util::{post_inc, ResultExt, TryFutureExt};
+use std::{
+    future::Future,
+    mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+use util::{paths::SCRATCH_PROJECTS_DIR, post_inc, ResultExt, TryFutureExt};
 
 pub const RECONNECT_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// How long location updates are held back when the server asked for fewer
+/// updates, so that only the latest location is sent.
+const REDUCED_SYNC_LOCATION_DELAY: Duration = Duration::from_secs(2);
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Event {
     RoomJoined {
@@ -38,6 +59,9 @@ pub enum Event {
     RemoteAudioTracksChanged {
         participant_id: proto::PeerId,
     },
+    ScreenAnnotationsChanged {
+        sharer_user_id: u64,
+    },
     RemoteProjectShared {
         owner: Arc<User>,
         project_id: u64,
@@ -55,15 +79,52 @@ pub enum Event {
     Left {
         channel_id: Option<u64>,
     },
+    /// The time-boxed room ends in `remaining`.
+    CountdownWarning {
+        remaining: Duration,
+    },
+    /// Everyone was removed from the room, because its time ran out or an admin
+    /// terminated it.
+    Ended,
+    /// A different remote participant became [`Room::active_speaker`], or nobody is
+    /// speaking anymore.
+    ActiveSpeakerChanged {
+        participant_id: Option<proto::PeerId>,
+    },
+}
+
+/// An invitation for someone without an account to join a room as a guest, which
+/// they accept by opening [`Credentials::guest_link`].
+#[derive(Clone, Debug)]
+pub struct GuestInvitation {
+    pub credentials: Credentials,
+    pub expires_at: SystemTime,
 }
 
 pub struct Room {
     id: u64,
     channel_id: Option<u64>,
+    /// When the room ends, if it was created with a fixed duration.
+    ends_at: Option<SystemTime>,
+    /// When the room's call started, if the server reported it.
+    started_at: Option<SystemTime>,
     live_kit: Option<LiveKitRoom>,
+    /// Carries the room's voice through the collab server when it has no LiveKit.
+    audio_relay: Option<AudioRelay>,
     status: RoomStatus,
+    /// Whether the server asked this client to send fewer updates, because
+    /// its connection is slow.
+    reduced_sync: bool,
     shared_projects: HashSet<WeakModel<Project>>,
+    /// The version of each buffer in a shared project whose edits have already
+    /// been reported for the call's summary.
+    reported_buffer_versions: HashMap<EntityId, clock::Global>,
     joined_projects: HashSet<WeakModel<Project>>,
+    /// Measures the local user's part in the call, if they share statistics.
+    statistics: Option<SessionStatistics>,
+    _active_project_subscription: Option<gpui::Subscription>,
+    scratch_project: Option<ScratchProject>,
+    chat: Option<WeakModel<ChatStore>>,
     local_participant: LocalParticipant,
     remote_participants: BTreeMap<u64, RemoteParticipant>,
     pending_participants: Vec<Arc<User>>,
@@ -73,11 +134,18 @@ pub struct Room {
     client: Arc<Client>,
     user_store: Model<UserStore>,
     follows_by_leader_id_project_id: HashMap<(PeerId, u64), Vec<PeerId>>,
+    screen_annotations: HashMap<u64, Vec<ScreenAnnotation>>,
+    next_screen_annotation_id: u64,
     client_subscriptions: Vec<client::Subscription>,
     _subscriptions: Vec<gpui::Subscription>,
     room_update_completed_tx: watch::Sender<Option<()>>,
     room_update_completed_rx: watch::Receiver<Option<()>>,
     pending_room_update: Option<Task<()>>,
+    pending_location_update: Option<Shared<Task<Result<(), Arc<anyhow::Error>>>>>,
+    location_update_count: usize,
+    /// The [`RemoteParticipant::last_activity`] to give the next participant who
+    /// does something.
+    next_activity: u64,
     maintain_connection: Option<Task<Option<()>>>,
 }
 
@@ -88,6 +156,18 @@ impl Room {
         self.channel_id
     }
 
+    pub fn reduced_sync(&self) -> bool {
+        self.reduced_sync
+    }
+
+    pub fn ends_at(&self) -> Option<SystemTime> {
+        self.ends_at
+    }
+
+    pub fn started_at(&self) -> Option<SystemTime> {
+        self.started_at
+    }
+
     pub fn is_sharing_project(&self) -> bool {
         !self.shared_projects.is_empty()
     }
@@ -108,6 +188,7 @@ impl Room {
         id: u64,
         channel_id: Option<u64>,
         live_kit_connection_info: Option<proto::LiveKitConnectionInfo>,
+        reduced_sync: bool,
         client: Arc<Client>,
         user_store: Model<UserStore>,
         cx: &mut ModelContext<Self>,
@@ -152,7 +233,25 @@ impl Room {
                 }
             });
 
-            let connect = room.connect(&connection_info.server_url, &connection_info.token);
+            let ice_configuration = connection_info.ice_configuration.as_ref().map(|config| {
+                live_kit_client::IceConfiguration {
+                    servers: config
+                        .servers
+                        .iter()
+                        .map(|server| live_kit_client::IceServer {
+                            urls: server.urls.clone(),
+                            username: server.username.clone(),
+                            credential: server.credential.clone(),
+                        })
+                        .collect(),
+                    force_relay: config.force_relay,
+                }
+            });
+            let connect = room.connect(
+                &connection_info.server_url,
+                &connection_info.token,
+                ice_configuration,
+            );
             cx.spawn(|this, mut cx| async move {
                 connect.await?;
                 this.update(&mut cx, |this, cx| {
@@ -184,6 +283,57 @@ impl Room {
             None
         };
 
+        let audio_relay = if live_kit_room.is_none() && client.server_capabilities().relayed_audio {
+            audio_relay::audio_relay_device(cx).map(|device| {
+                let play_frames = cx.spawn(|this, mut cx| async move {
+                    loop {
+                        cx.background_executor()
+                            .timer(RELAYED_AUDIO_FRAME_DURATION)
+                            .await;
+                        let Some(this) = this.upgrade() else {
+                            break;
+                        };
+                        let played = this.update(&mut cx, |this, _| {
+                            if let Some(audio_relay) = this.audio_relay.as_mut() {
+                                audio_relay.play_frames();
+                            }
+                        });
+                        if played.is_err() {
+                            break;
+                        }
+                    }
+                });
+
+                // Wait for the role of the local participant to be known before
+                // deciding whether to start talking.
+                cx.spawn(|this, mut cx| async move {
+                    this.update(&mut cx, |this, cx| {
+                        let muted_by_user = this
+                            .audio_relay
+                            .as_ref()
+                            .map_or(true, |audio_relay| audio_relay.muted_by_user);
+                        if this.read_only() || muted_by_user {
+                            Task::ready(Ok(()))
+                        } else {
+                            this.share_microphone(cx)
+                        }
+                    })?
+                    .await
+                })
+                .detach_and_log_err(cx);
+
+                AudioRelay::new(
+                    id,
+                    client.clone(),
+                    device,
+                    Self::mute_on_join(cx),
+                    play_frames,
+                )
+            })
+        } else {
+            None
+        };
+
         let maintain_connection = cx.spawn({
             let client = client.clone();
             move |this, cx| Self::maintain_connection(this, client.clone(), cx).log_err()
@@ -191,32 +341,64 @@ impl Room {
 
         Audio::play_sound(Sound::Joined, cx);
 
+        if CallSettings::get_global(cx).summarize_calls {
+            let report = client.request(proto::ReportRoomActivity {
+                room_id: id,
+                wants_summary: true,
+                ..Default::default()
+            });
+            cx.background_executor()
+                .spawn(report)
+                .detach_and_log_err(cx);
+        }
+
         let (room_update_completed_tx, room_update_completed_rx) = watch::channel();
 
         Self {
             id,
             channel_id,
+            ends_at: None,
+            started_at: None,
             live_kit: live_kit_room,
+            audio_relay,
             status: RoomStatus::Online,
+            reduced_sync,
             shared_projects: Default::default(),
+            reported_buffer_versions: Default::default(),
+            scratch_project: None,
+            chat: None,
             joined_projects: Default::default(),
+            statistics: CallSettings::get_global(cx)
+                .share_session_statistics
+                .then(SessionStatistics::default),
+            _active_project_subscription: None,
             participant_user_ids: Default::default(),
             local_participant: Default::default(),
             remote_participants: Default::default(),
             pending_participants: Default::default(),
             pending_call_count: 0,
             client_subscriptions: vec![
-                client.add_message_handler(cx.weak_model(), Self::handle_room_updated)
+                client.add_message_handler(cx.weak_model(), Self::handle_room_updated),
+                client.add_message_handler(cx.weak_model(), Self::handle_update_screen_annotations),
+                client.add_message_handler(cx.weak_model(), Self::handle_relayed_audio_frame),
+                client.add_message_handler(cx.weak_model(), Self::handle_room_countdown),
+                client.add_message_handler(cx.weak_model(), Self::handle_room_ended),
             ],
             _subscriptions: vec![
                 cx.on_release(Self::released),
                 cx.on_app_quit(Self::app_will_quit),
+                cx.subscribe(&user_store, Self::handle_user_store_event),
             ],
             leave_when_empty: false,
             pending_room_update: None,
+            pending_location_update: None,
+            location_update_count: 0,
+            next_activity: 0,
             client,
             user_store,
             follows_by_leader_id_project_id: Default::default(),
+            screen_annotations: Default::default(),
+            next_screen_annotation_id: 0,
             maintain_connection: Some(maintain_connection),
             room_update_completed_tx,
             room_update_completed_rx,
@@ -226,18 +408,29 @@ impl Room {
     pub(crate) fn create(
         called_user_id: u64,
         initial_project: Option<Model<Project>>,
+        context: Option<proto::InviteContext>,
+        duration: Option<Duration>,
         client: Arc<Client>,
         user_store: Model<UserStore>,
         cx: &mut AppContext,
     ) -> Task<Result<Model<Self>>> {
+        let media_available = preflight::media_available(cx);
         cx.spawn(move |mut cx| async move {
-            let response = client.request(proto::CreateRoom {}).await?;
+            let capabilities =
+                preflight::run_preflight_checks(client.clone(), media_available).await;
+            let response = client
+                .request(proto::CreateRoom {
+                    capabilities: capabilities.as_ref().map(ParticipantCapabilities::to_proto),
+                    duration_secs: duration.map(|duration| duration.as_secs()),
+                })
+                .await?;
             let room_proto = response.room.ok_or_else(|| anyhow!("invalid room"))?;
             let room = cx.new_model(|cx| {
                 let mut room = Self::new(
                     room_proto.id,
                     None,
                     response.live_kit_connection_info,
+                    response.reduced_sync,
                     client,
                     user_store,
                     cx,
@@ -245,6 +438,8 @@ impl Room {
                 if let Some(participant) = room_proto.participants.first() {
                     room.local_participant.role = participant.role()
                 }
+                room.ends_at = room_proto.ends_at.map(Into::into);
+                room.started_at = room_proto.started_at.map(Into::into);
                 room
             })?;
 
@@ -262,7 +457,7 @@ impl Room {
             match room
                 .update(&mut cx, |room, cx| {
                     room.leave_when_empty = true;
-                    room.call(called_user_id, initial_project_id, cx)
+                    room.call(called_user_id, initial_project_id, context, false, cx)
                 })?
                 .await
             {
@@ -278,8 +473,14 @@ impl Room {
         user_store: Model<UserStore>,
         cx: AsyncAppContext,
     ) -> Result<Model<Self>> {
+        let capabilities = Self::run_preflight_checks(&client, &cx).await?;
         Self::from_join_response(
-            client.request(proto::JoinChannel { channel_id }).await?,
+            client
+                .request(proto::JoinChannel {
+                    channel_id,
+                    capabilities,
+                })
+                .await?,
             client,
             user_store,
             cx,
@@ -292,14 +493,77 @@ impl Room {
         user_store: Model<UserStore>,
         cx: AsyncAppContext,
     ) -> Result<Model<Self>> {
+        let capabilities = Self::run_preflight_checks(&client, &cx).await?;
+        Self::from_join_response(
+            client
+                .request(proto::JoinRoom {
+                    id: room_id,
+                    capabilities,
+                })
+                .await?,
+            client,
+            user_store,
+            cx,
+        )
+    }
+
+    pub(crate) async fn accept_waiting(
+        room_id: u64,
+        client: Arc<Client>,
+        user_store: Model<UserStore>,
+        cx: AsyncAppContext,
+    ) -> Result<Model<Self>> {
+        let capabilities = Self::run_preflight_checks(&client, &cx).await?;
         Self::from_join_response(
-            client.request(proto::JoinRoom { id: room_id }).await?,
+            client
+                .request(proto::AcceptWaitingCall {
+                    room_id,
+                    capabilities,
+                })
+                .await?,
             client,
             user_store,
             cx,
         )
     }
 
+    async fn run_preflight_checks(
+        client: &Arc<Client>,
+        cx: &AsyncAppContext,
+    ) -> Result<Option<proto::ParticipantCapabilities>> {
+        let media_available = cx.update(preflight::media_available)?;
+        Ok(
+            preflight::run_preflight_checks(client.clone(), media_available)
+                .await
+                .as_ref()
+                .map(ParticipantCapabilities::to_proto),
+        )
+    }
+
+    fn handle_user_store_event(
+        &mut self,
+        _: Model<UserStore>,
+        event: &client::user::Event,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if let client::user::Event::UserUpdated(user) = event {
+            let mut updated = false;
+            if let Some(participant) = self.remote_participants.get_mut(&user.id) {
+                participant.user = user.clone();
+                updated = true;
+            }
+            for pending_user in &mut self.pending_participants {
+                if pending_user.id == user.id {
+                    *pending_user = user.clone();
+                    updated = true;
+                }
+            }
+            if updated {
+                cx.notify();
+            }
+        }
+    }
+
     fn released(&mut self, cx: &mut AppContext) {
         if self.status.is_online() {
             self.leave_internal(cx).detach_and_log_err(cx);
@@ -339,6 +603,7 @@ impl Room {
                 room_proto.id,
                 response.channel_id,
                 response.live_kit_connection_info,
+                response.reduced_sync,
                 client,
                 user_store,
                 cx,
@@ -376,16 +641,38 @@ impl Room {
         log::info!("leaving room");
         Audio::play_sound(Sound::Leave, cx);
 
+        let edited_files = self
+            .shared_projects
+            .clone()
+            .into_iter()
+            .filter_map(|project| project.upgrade())
+            .flat_map(|project| self.take_edited_files(project.read(cx), cx))
+            .collect::<Vec<_>>();
+        let statistics = self.take_statistics(cx);
         self.clear_state(cx);
 
-        let leave_room = self.client.request(proto::LeaveRoom {});
+        // Report the edits before leaving, so that they make it into the call's
+        // summary if this was the last participant.
+        let report = (!edited_files.is_empty() || statistics.is_some()).then(|| {
+            self.client.request(proto::ReportRoomActivity {
+                room_id: self.id,
+                edited_files,
+                statistics,
+                ..Default::default()
+            })
+        });
+        let client = self.client.clone();
         cx.background_executor().spawn(async move {
-            leave_room.await?;
+            if let Some(report) = report {
+                report.await.log_err();
+            }
+            client.request(proto::LeaveRoom {}).await?;
             anyhow::Ok(())
         })
     }
 
     pub(crate) fn clear_state(&mut self, cx: &mut AppContext) {
+        self.remove_scratch_project(cx);
         for project in self.shared_projects.drain() {
             if let Some(project) = project.upgrade() {
                 project.update(cx, |project, cx| {
@@ -393,6 +680,7 @@ impl Room {
                 });
             }
         }
+        self.reported_buffer_versions.clear();
         for project in self.joined_projects.drain() {
             if let Some(project) = project.upgrade() {
                 project.update(cx, |project, cx| {
@@ -408,6 +696,7 @@ impl Room {
         self.participant_user_ids.clear();
         self.client_subscriptions.clear();
         self.live_kit.take();
+        self.audio_relay.take();
         self.pending_room_update.take();
         self.maintain_connection.take();
     }
@@ -531,6 +820,10 @@ impl Room {
                                 proto::RejoinWorktree {
                                     id: worktree.id().to_proto(),
                                     scan_id: worktree.completed_scan_id() as u64,
+                                    loaded_directories: worktree
+                                        .loaded_directories()
+                                        .map(|path| proto::path_to_proto(path))
+                                        .collect(),
                                 }
                             })
                             .collect(),
@@ -545,6 +838,7 @@ impl Room {
             id: self.id,
             reshared_projects,
             rejoined_projects,
+            resume_token: self.client.take_resume_token(),
         });
 
         cx.spawn(|this, mut cx| async move {
@@ -556,6 +850,13 @@ impl Room {
                 this.status = RoomStatus::Online;
                 this.apply_room_update(room_proto, cx)?;
 
+                // Annotations and their clears may have been lost while we were
+                // away, so drop the strokes rather than risk keeping stale ones.
+                let sharer_user_ids = this.screen_annotations.keys().copied().collect::<Vec<_>>();
+                for sharer_user_id in sharer_user_ids {
+                    this.remove_screen_annotations(sharer_user_id, cx);
+                }
+
                 for reshared_project in response.reshared_projects {
                     if let Some(project) = projects.get(&reshared_project.id) {
                         project.update(cx, |project, cx| {
@@ -572,6 +873,11 @@ impl Room {
                     }
                 }
 
+                if let Some(chat) = this.chat.as_ref().and_then(|chat| chat.upgrade()) {
+                    chat.update(cx, |chat, cx| chat.rejoin(cx))
+                        .detach_and_log_err(cx);
+                }
+
                 anyhow::Ok(())
             })?
         })
@@ -581,6 +887,34 @@ impl Room {
         self.id
     }
 
+    pub fn client(&self) -> Arc<Client> {
+        self.client.clone()
+    }
+
+    pub fn user_store(&self) -> &Model<UserStore> {
+        &self.user_store
+    }
+
+    /// Opens the room's chat, which catches up on the messages it missed whenever
+    /// the room is rejoined.
+    pub fn open_chat(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<Model<ChatStore>>> {
+        if let Some(chat) = self.chat.as_ref().and_then(|chat| chat.upgrade()) {
+            return Task::ready(Ok(chat));
+        }
+
+        let open_chat = ChatStore::new(
+            self.id,
+            self.client.clone(),
+            self.user_store.clone(),
+            cx.to_async(),
+        );
+        cx.spawn(|this, mut cx| async move {
+            let chat = open_chat.await?;
+            this.update(&mut cx, |this, _| this.chat = Some(chat.downgrade()))?;
+            Ok(chat)
+        })
+    }
+
     pub fn status(&self) -> RoomStatus {
         self.status
     }
@@ -593,6 +927,19 @@ impl Room {
         &self.remote_participants
     }
 
+    /// The id of the project that the given participant is sharing under the id it
+    /// gave the project. Unlike the project's id, the host's id for it stays the same
+    /// when the project is shared again, and tells apart projects with the same root
+    /// names.
+    pub fn remote_project_id(&self, host_user_id: u64, host_project_id: u64) -> Option<u64> {
+        self.remote_participants
+            .get(&host_user_id)?
+            .projects
+            .iter()
+            .find(|project| project.host_project_id == host_project_id)
+            .map(|project| project.id)
+    }
+
     pub fn remote_participant_for_peer_id(&self, peer_id: PeerId) -> Option<&RemoteParticipant> {
         self.remote_participants
             .values()
@@ -642,6 +989,51 @@ impl Room {
         })
     }
 
+    /// Invites someone without an account into the room as a guest, who can take
+    /// part in it as a viewer or an editor until the invitation expires. Only the
+    /// room's editors can.
+    pub fn invite_guest(
+        &mut self,
+        role: proto::ChannelRole,
+        duration: Duration,
+        cx: &ModelContext<Self>,
+    ) -> Task<Result<GuestInvitation>> {
+        let client = self.client.clone();
+        let room_id = self.id;
+        cx.spawn(|_, _| async move {
+            let response = client
+                .request(proto::CreateGuest {
+                    room_id,
+                    role: role.into(),
+                    duration_secs: duration.as_secs(),
+                })
+                .await?;
+            let user = response.user.ok_or_else(|| anyhow!("invalid guest"))?;
+            Ok(GuestInvitation {
+                credentials: Credentials {
+                    user_id: user.id,
+                    access_token: response.access_token,
+                },
+                expires_at: SystemTime::UNIX_EPOCH + Duration::from_secs(response.expires_at),
+            })
+        })
+    }
+
+    /// Pushes back the end of a time-boxed room. Only the room's admins can.
+    pub fn extend(&mut self, duration: Duration, cx: &ModelContext<Self>) -> Task<Result<()>> {
+        let client = self.client.clone();
+        let room_id = self.id;
+        cx.spawn(|_, _| async move {
+            client
+                .request(proto::ExtendRoom {
+                    room_id,
+                    duration_secs: duration.as_secs(),
+                })
+                .await
+                .map(|_| ())
+        })
+    }
+
     pub fn pending_participants(&self) -> &[Arc<User>] {
         &self.pending_participants
     }
@@ -656,6 +1048,66 @@ impl Room {
             .map_or(&[], |v| v.as_slice())
     }
 
+    /// The remote participants that can be followed, which are those in shared
+    /// projects, in the order they're shown in.
+    fn followable_participants(&self) -> Vec<&RemoteParticipant> {
+        let mut participants = self
+            .remote_participants
+            .values()
+            .filter(|participant| {
+                matches!(
+                    participant.location,
+                    ParticipantLocation::SharedProject { .. }
+                )
+            })
+            .collect::<Vec<_>>();
+        participants.sort_by_key(|participant| participant.participant_index.0);
+        participants
+    }
+
+    /// The followable participant that comes after the given one, or before it if
+    /// `reverse` is true, wrapping around at the ends. Starts from the first, or the
+    /// last, when the given participant isn't followable.
+    pub fn adjacent_participant(&self, peer_id: Option<PeerId>, reverse: bool) -> Option<PeerId> {
+        let mut participants = self.followable_participants();
+        if reverse {
+            participants.reverse();
+        }
+        let next_ix = peer_id
+            .and_then(|peer_id| {
+                participants
+                    .iter()
+                    .position(|participant| participant.peer_id == peer_id)
+            })
+            .map_or(0, |ix| (ix + 1) % participants.len());
+        Some(participants.get(next_ix)?.peer_id)
+    }
+
+    /// The followable participant who most recently joined, moved to another
+    /// project, started speaking or shared their screen.
+    pub fn most_recently_active_participant(&self) -> Option<PeerId> {
+        self.followable_participants()
+            .into_iter()
+            .max_by_key(|participant| participant.last_activity)
+            .map(|participant| participant.peer_id)
+    }
+
+    /// Whether the room knows who is speaking, which it does when it's connected
+    /// to LiveKit.
+    pub fn knows_voice_activity(&self) -> bool {
+        self.live_kit.is_some()
+    }
+
+    /// The remote participant who most recently started speaking, of those who are
+    /// speaking.
+    pub fn active_speaker(&self) -> Option<PeerId> {
+        self.remote_participants
+            .values()
+            .filter(|participant| participant.speaking)
+            .max_by_key(|participant| participant.last_activity)
+            .map(|participant| participant.peer_id)
+    }
+
     /// Returns the most 'active' projects, defined as most people in the project
     pub fn most_active_project(&self, cx: &AppContext) -> Option<(u64, u64)> {
         let mut project_hosts_and_guest_counts = HashMap::<u64, (Option<u64>, u32)>::default();
@@ -706,6 +1158,100 @@ impl Room {
         this.update(&mut cx, |this, cx| this.apply_room_update(room, cx))?
     }
 
+    async fn handle_update_screen_annotations(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::UpdateScreenAnnotations>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        let author = envelope.original_sender_id()?;
+        this.update(&mut cx, |this, cx| {
+            let sharer_user_id = envelope.payload.sharer_user_id;
+            let author_user_id = this
+                .remote_participant_for_peer_id(author)
+                .map(|participant| participant.user.id)
+                .ok_or_else(|| anyhow!("annotation from unknown participant"))?;
+            match envelope
+                .payload
+                .variant
+                .ok_or_else(|| anyhow!("invalid screen annotation update"))?
+            {
+                proto::update_screen_annotations::Variant::Add(annotation) => {
+                    let annotation = ScreenAnnotation::from_proto(author, annotation);
+                    this.insert_screen_annotation(sharer_user_id, annotation, cx);
+                }
+                proto::update_screen_annotations::Variant::Clear(clear) => match clear.author {
+                    Some(annotations_author)
+                        if annotations_author == author || author_user_id == sharer_user_id =>
+                    {
+                        this.remove_screen_annotations_by(sharer_user_id, annotations_author, cx);
+                    }
+                    None if author_user_id == sharer_user_id => {
+                        this.remove_screen_annotations(sharer_user_id, cx);
+                    }
+                    _ => {
+                        return Err(anyhow!(
+                            "only the sharer can clear others' screen annotations"
+                        ));
+                    }
+                },
+            }
+            Ok(())
+        })?
+    }
+
+    async fn handle_relayed_audio_frame(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::RelayedAudioFrame>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        let speaker = envelope.original_sender_id()?;
+        this.update(&mut cx, |this, _| {
+            if let Some(audio_relay) = this.audio_relay.as_mut() {
+                audio_relay.receive_frame(
+                    speaker,
+                    envelope.payload.sequence,
+                    envelope.payload.audio_data,
+                );
+            }
+        })
+    }
+
+    async fn handle_room_countdown(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::RoomCountdown>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        this.update(&mut cx, |this, cx| {
+            if envelope.payload.room_id == this.id {
+                cx.emit(Event::CountdownWarning {
+                    remaining: Duration::from_secs(envelope.payload.remaining_secs),
+                });
+            }
+        })
+    }
+
+    async fn handle_room_ended(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::RoomEnded>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        this.update(&mut cx, |this, cx| {
+            if envelope.payload.room_id == this.id {
+                log::info!("room ended");
+                this.clear_state(cx);
+                cx.emit(Event::Ended);
+                cx.emit(Event::Left {
+                    channel_id: this.channel_id(),
+                });
+                cx.notify();
+            }
+        })
+    }
+
     fn apply_room_update(
         &mut self,
         mut room: proto::Room,
@@ -717,6 +1263,8 @@ impl Room {
             .iter()
             .position(|participant| Some(participant.user_id) == self.client.user_id());
         let local_participant = local_participant_ix.map(|ix| room.participants.swap_remove(ix));
+        self.ends_at = room.ends_at.take().map(Into::into);
+        self.started_at = room.started_at.take().map(Into::into);
 
         let pending_participant_user_ids = room
             .pending_participants
@@ -748,6 +1296,7 @@ impl Room {
                 if let Some(participant) = local_participant {
                     let role = participant.role();
                     this.local_participant.projects = participant.projects;
+                    this.local_participant.observer = participant.observer;
                     if this.local_participant.role != role {
                         this.local_participant.role = role;
 
@@ -761,6 +1310,9 @@ impl Room {
                             if let Some(live_kit_room) = &mut this.live_kit {
                                 live_kit_room.stop_publishing(cx);
                             }
+                            if let Some(audio_relay) = &mut this.audio_relay {
+                                audio_relay.stop_capture();
+                            }
                         }
 
                         this.joined_projects.retain(|project| {
@@ -830,15 +1382,26 @@ impl Room {
                         let role = participant.role();
                         let location = ParticipantLocation::from_proto(participant.location)
                             .unwrap_or(ParticipantLocation::External);
+                        let capabilities = participant
+                            .capabilities
+                            .as_ref()
+                            .map(ParticipantCapabilities::from_proto);
                         if let Some(remote_participant) =
                             this.remote_participants.get_mut(&participant.user_id)
                         {
+                            remote_participant.user = user.clone();
                             remote_participant.peer_id = peer_id;
                             remote_participant.projects = participant.projects;
                             remote_participant.participant_index = participant_index;
+                            remote_participant.capabilities = capabilities;
+                            remote_participant.observer = participant.observer;
                             if location != remote_participant.location
                                 || role != remote_participant.role
                             {
+                                if location != remote_participant.location {
+                                    remote_participant.last_activity =
+                                        post_inc(&mut this.next_activity);
+                                }
                                 remote_participant.location = location;
                                 remote_participant.role = role;
                                 cx.emit(Event::ParticipantLocationChanged {
@@ -855,8 +1418,11 @@ impl Room {
                                     projects: participant.projects,
                                     location,
                                     role,
+                                    capabilities,
+                                    observer: participant.observer,
                                     muted: true,
                                     speaking: false,
+                                    last_activity: post_inc(&mut this.next_activity),
                                     video_tracks: Default::default(),
                                     audio_tracks: Default::default(),
                                 },
@@ -906,6 +1472,9 @@ impl Room {
                                     project_id: project.id,
                                 });
                             }
+                            if let Some(audio_relay) = this.audio_relay.as_mut() {
+                                audio_relay.remove_speaker(participant.peer_id);
+                            }
                             false
                         }
                     });
@@ -990,6 +1559,7 @@ impl Room {
                     .get_mut(&user_id)
                     .ok_or_else(|| anyhow!("subscribed to track by unknown participant"))?;
                 participant.video_tracks.insert(track_id.clone(), track);
+                participant.last_activity = post_inc(&mut self.next_activity);
                 cx.emit(Event::RemoteVideoTracksChanged {
                     participant_id: participant.peer_id,
                 });
@@ -1005,9 +1575,11 @@ impl Room {
                     .get_mut(&user_id)
                     .ok_or_else(|| anyhow!("unsubscribed from track by unknown participant"))?;
                 participant.video_tracks.remove(&track_id);
-                cx.emit(Event::RemoteVideoTracksChanged {
-                    participant_id: participant.peer_id,
-                });
+                let participant_id = participant.peer_id;
+                if participant.video_tracks.is_empty() {
+                    self.remove_screen_annotations(user_id, cx);
+                }
+                cx.emit(Event::RemoteVideoTracksChanged { participant_id });
             }
 
             RoomUpdate::ActiveSpeakersChanged { speakers } => {
@@ -1016,13 +1588,23 @@ impl Room {
                     .filter_map(|speaker_sid| speaker_sid.parse().ok())
                     .collect::<Vec<u64>>();
                 speaker_ids.sort_unstable();
+                let active_speaker = self.active_speaker();
                 for (sid, participant) in &mut self.remote_participants {
                     if let Ok(_) = speaker_ids.binary_search(sid) {
+                        if !participant.speaking {
+                            participant.last_activity = post_inc(&mut self.next_activity);
+                        }
                         participant.speaking = true;
                     } else {
                         participant.speaking = false;
                     }
                 }
+                let new_active_speaker = self.active_speaker();
+                if new_active_speaker != active_speaker {
+                    cx.emit(Event::ActiveSpeakerChanged {
+                        participant_id: new_active_speaker,
+                    });
+                }
                 if let Some(id) = self.client.user_id() {
                     if let Some(room) = &mut self.live_kit {
                         if let Ok(_) = speaker_ids.binary_search(&id) {
@@ -1030,6 +1612,9 @@ impl Room {
                         } else {
                             room.speaking = false;
                         }
+                        if let Some(statistics) = &mut self.statistics {
+                            statistics.set_speaking(room.speaking, Instant::now());
+                        }
                     }
                 }
             }
@@ -1140,6 +1725,8 @@ impl Room {
         &mut self,
         called_user_id: u64,
         initial_project_id: Option<u64>,
+        context: Option<proto::InviteContext>,
+        observer: bool,
         cx: &mut ModelContext<Self>,
     ) -> Task<Result<()>> {
         if self.status.is_offline() {
@@ -1156,6 +1743,8 @@ impl Room {
                     room_id,
                     called_user_id,
                     initial_project_id,
+                    context,
+                    observer,
                 })
                 .await;
             this.update(&mut cx, |this, cx| {
@@ -1206,6 +1795,21 @@ impl Room {
         })
     }
 
+    /// Fetches details about every project shared in this room, such as each project's
+    /// root names, size, and which kinds of files it contains.
+    pub fn shared_project_metadata(
+        &self,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<proto::SharedProjectMetadata>>> {
+        let request = self
+            .client
+            .request(proto::GetRoomProjects { room_id: self.id });
+        cx.background_executor().spawn(async move {
+            let response = request.await?;
+            Ok(response.projects)
+        })
+    }
+
     pub fn share_project(
         &mut self,
         project: Model<Project>,
@@ -1215,12 +1819,23 @@ impl Room {
             return Task::ready(Ok(project_id));
         }
 
-        let request = self.client.request(proto::ShareProject {
-            room_id: self.id(),
-            worktrees: project.read(cx).worktree_metadata_protos(cx),
-        });
+        let room_id = self.id();
+        let client = self.client.clone();
+        let load_policy = project.update(cx, |project, cx| project.load_collab_policy(cx));
         cx.spawn(|this, mut cx| async move {
-            let response = request.await?;
+            // Nothing is shared until the policy is known, as it may deny some of
+            // the project's files to guests.
+            let policy = load_policy.await?;
+            let worktrees =
+                project.update(&mut cx, |project, cx| project.worktree_metadata_protos(cx))?;
+            let response = client
+                .request(proto::ShareProject {
+                    room_id,
+                    worktrees,
+                    policy: Some(policy.to_proto()),
+                    host_project_id: project.entity_id().as_u64(),
+                })
+                .await?;
 
             project.update(&mut cx, |project, cx| {
                 project.shared(response.project_id, cx)
@@ -1229,6 +1844,22 @@ impl Room {
             // If the user's location is in this project, it changes from UnsharedProject to SharedProject.
             this.update(&mut cx, |this, cx| {
                 this.shared_projects.insert(project.downgrade());
+                for buffer in project.read(cx).opened_buffers() {
+                    this.reported_buffer_versions
+                        .insert(buffer.entity_id(), buffer.read(cx).version());
+                }
+                // Closing the project's window closes its buffers, so their edits are
+                // reported before they're gone.
+                let weak_project = project.downgrade();
+                cx.observe_release(&project, move |this, project, cx| {
+                    if this.shared_projects.remove(&weak_project) {
+                        this.report_edited_files(project, cx);
+                        for buffer in project.opened_buffers() {
+                            this.reported_buffer_versions.remove(&buffer.entity_id());
+                        }
+                    }
+                })
+                .detach();
                 let active_project = this.local_participant.active_project.as_ref();
                 if active_project.map_or(false, |location| *location == project) {
                     this.set_location(Some(&project), cx)
@@ -1242,6 +1873,107 @@ impl Room {
         })
     }
 
+    /// Shares a project rooted in a new, empty directory that only lives as
+    /// long as the room, so that participants can write code together without
+    /// exposing any of the host's files.
+    pub fn share_scratch_project(
+        &mut self,
+        project: Model<Project>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<u64>> {
+        if let Some(scratch_project) = self.scratch_project.as_ref() {
+            if scratch_project.project != project {
+                return Task::ready(Err(anyhow!("the room already has a scratch project")));
+            }
+            if let Some(project_id) = project.read(cx).remote_id() {
+                return Task::ready(Ok(project_id));
+            }
+        }
+        if !project.read(cx).is_local() || project.read(cx).worktrees().next().is_some() {
+            return Task::ready(Err(anyhow!("scratch projects must start out empty")));
+        }
+
+        let dir = SCRATCH_PROJECTS_DIR.join(self.id.to_string());
+        let fs = project.read(cx).fs().clone();
+        self.scratch_project = Some(ScratchProject {
+            project: project.downgrade(),
+            dir: dir.clone(),
+            fs: fs.clone(),
+        });
+        let weak_project = project.downgrade();
+        cx.observe_release(&project, move |this, _, cx| {
+            if this
+                .scratch_project
+                .as_ref()
+                .map_or(false, |scratch_project| {
+                    scratch_project.project == weak_project
+                })
+            {
+                this.remove_scratch_project(cx);
+            }
+        })
+        .detach();
+        cx.spawn(|this, mut cx| async move {
+            let share = async {
+                // Files left behind by an earlier scratch project in this room, such
+                // as one whose host crashed, aren't shared again.
+                if fs.metadata(&dir).await?.is_some() {
+                    fs.remove_dir(
+                        &dir,
+                        RemoveOptions {
+                            recursive: true,
+                            ignore_if_not_exists: true,
+                        },
+                    )
+                    .await?;
+                }
+                let path = dir.join("scratch");
+                fs.create_dir(&path).await?;
+                project
+                    .update(&mut cx, |project, cx| {
+                        project.find_or_create_local_worktree(&path, true, cx)
+                    })?
+                    .await?;
+                this.update(&mut cx, |this, cx| this.share_project(project.clone(), cx))?
+                    .await
+            };
+            let result = share.await;
+            if result.is_err() {
+                this.update(&mut cx, |this, cx| {
+                    if this.is_scratch_project(&project) {
+                        this.remove_scratch_project(cx);
+                    }
+                })
+                .ok();
+            }
+            result
+        })
+    }
+
+    pub fn is_scratch_project(&self, project: &Model<Project>) -> bool {
+        self.scratch_project
+            .as_ref()
+            .map_or(false, |scratch_project| scratch_project.project == *project)
+    }
+
+    /// Forgets the scratch project, if there is one, and deletes its files.
+    fn remove_scratch_project(&mut self, cx: &mut AppContext) {
+        if let Some(ScratchProject { dir, fs, .. }) = self.scratch_project.take() {
+            cx.background_executor()
+                .spawn(async move {
+                    fs.remove_dir(
+                        &dir,
+                        RemoveOptions {
+                            recursive: true,
+                            ignore_if_not_exists: true,
+                        },
+                    )
+                    .await
+                })
+                .detach_and_log_err(cx);
+        }
+    }
+
     pub(crate) fn unshare_project(
         &mut self,
         project: Model<Project>,
@@ -1252,8 +1984,12 @@ impl Room {
             None => return Ok(()),
         };
 
+        self.report_edited_files(project.read(cx), cx);
         self.client.send(proto::UnshareProject { project_id })?;
         project.update(cx, |this, cx| this.unshare(cx))?;
+        if self.is_scratch_project(&project) {
+            self.remove_scratch_project(cx);
+        }
 
         if self.local_participant.active_project == Some(project.downgrade()) {
             self.set_location(Some(&project), cx).detach_and_log_err(cx);
@@ -1261,6 +1997,89 @@ impl Room {
         Ok(())
     }
 
+    /// Adds the lines edited in the given shared project since they were last
+    /// reported to the summary of the call.
+    fn report_edited_files(&mut self, project: &Project, cx: &mut ModelContext<Self>) {
+        let edited_files = self.take_edited_files(project, cx);
+        if !edited_files.is_empty() {
+            let report = self.client.request(proto::ReportRoomActivity {
+                room_id: self.id,
+                edited_files,
+                ..Default::default()
+            });
+            cx.background_executor()
+                .spawn(report)
+                .detach_and_log_err(cx);
+        }
+    }
+
+    /// Returns the lines edited in the given shared project's open buffers since
+    /// they were last reported. Guests' edits are included, because they are made
+    /// to the host's buffers too.
+    fn take_edited_files(&mut self, project: &Project, cx: &AppContext) -> Vec<proto::EditedFile> {
+        let mut edited_files = Vec::new();
+        for buffer in project.opened_buffers() {
+            let reported_version = self
+                .reported_buffer_versions
+                .insert(buffer.entity_id(), buffer.read(cx).version())
+                .unwrap_or_else(loaded_buffer_version);
+            let buffer = buffer.read(cx);
+            let Some(file) = buffer.file() else {
+                continue;
+            };
+
+            let mut lines_added = 0;
+            let mut lines_removed = 0;
+            for edit in buffer.edits_since::<language::Point>(&reported_version) {
+                lines_removed += line_count(&edit.old);
+                lines_added += line_count(&edit.new);
+            }
+            if lines_added > 0 || lines_removed > 0 {
+                edited_files.push(proto::EditedFile {
+                    path: proto::path_to_proto(&file.full_path(cx)),
+                    lines_added,
+                    lines_removed,
+                });
+            }
+        }
+        edited_files
+    }
+
+    /// Returns the statistics measured since the local user joined, if they
+    /// share them, counting what they wrote in the room's projects.
+    fn take_statistics(&mut self, cx: &AppContext) -> Option<proto::SessionStatistics> {
+        let statistics = self.statistics.as_mut()?;
+        for project in self.shared_projects.iter().chain(&self.joined_projects) {
+            if let Some(project) = project.upgrade() {
+                for buffer in project.read(cx).opened_buffers() {
+                    statistics.count_characters_written(&buffer, cx);
+                }
+            }
+        }
+        Some(statistics.take(self.live_kit.is_some(), Instant::now()))
+    }
+
+    fn track_active_file(&mut self, project: Option<&Model<Project>>, cx: &mut ModelContext<Self>) {
+        if self.statistics.is_none() {
+            return;
+        }
+
+        let active_file = project.and_then(|project| active_file_path(project, cx));
+        if let Some(statistics) = &mut self.statistics {
+            statistics.set_active_file(active_file, Instant::now());
+        }
+        self._active_project_subscription = project.map(|project| {
+            cx.subscribe(project, |this, project, event, cx| {
+                if let project::Event::ActiveEntryChanged(_) = event {
+                    let active_file = active_file_path(&project, cx);
+                    if let Some(statistics) = &mut this.statistics {
+                        statistics.set_active_file(active_file, Instant::now());
+                    }
+                }
+            })
+        });
+    }
+
     pub(crate) fn set_location(
         &mut self,
         project: Option<&Model<Project>>,
@@ -1287,17 +2106,62 @@ impl Room {
             self.local_participant.active_project = None;
             proto::participant_location::Variant::External(proto::participant_location::External {})
         };
+        self.track_active_file(project, cx);
 
         cx.notify();
+        let reduced_sync = self.reduced_sync;
+        self.location_update_count += 1;
+        let location_update_count = self.location_update_count;
+        let update = cx
+            .spawn(move |this, mut cx| async move {
+                if reduced_sync {
+                    cx.background_executor()
+                        .timer(REDUCED_SYNC_LOCATION_DELAY)
+                        .await;
+                    let superseded = this
+                        .update(&mut cx, |this, _| {
+                            this.location_update_count != location_update_count
+                        })
+                        .map_err(Arc::new)?;
+                    if superseded {
+                        return Ok(());
+                    }
+                }
+
+                client
+                    .request(proto::UpdateParticipantLocation {
+                        room_id,
+                        location: Some(proto::ParticipantLocation {
+                            variant: Some(location),
+                        }),
+                    })
+                    .await
+                    .map_err(Arc::new)?;
+                Ok(())
+            })
+            .shared();
+        self.pending_location_update = Some(update.clone());
+        cx.background_executor()
+            .spawn(async move { update.await.map_err(|err| anyhow!("{:?}", err)) })
+    }
+
+    /// Resolves once every operation generated locally in this room so far has been
+    /// acknowledged by the server, which by then has forwarded it to the other
+    /// participants. This covers location updates as well as edits in every project
+    /// shared in or joined through the room.
+    pub fn flush(&self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        let project_flushes = self
+            .shared_projects
+            .iter()
+            .chain(&self.joined_projects)
+            .filter_map(|project| Some(project.upgrade()?.read(cx).flush()))
+            .collect::<Vec<_>>();
+        let location_update = self.pending_location_update.clone();
         cx.background_executor().spawn(async move {
-            client
-                .request(proto::UpdateParticipantLocation {
-                    room_id,
-                    location: Some(proto::ParticipantLocation {
-                        variant: Some(location),
-                    }),
-                })
-                .await?;
+            if let Some(location_update) = location_update {
+                location_update.await.map_err(|err| anyhow!("{:?}", err))?;
+            }
+            try_join_all(project_flushes).await?;
             Ok(())
         })
     }
@@ -1309,12 +2173,20 @@ impl Room {
     }
 
     pub fn is_sharing_mic(&self) -> bool {
+        if let Some(audio_relay) = self.audio_relay.as_ref() {
+            return audio_relay.is_capturing();
+        }
         self.live_kit.as_ref().map_or(false, |live_kit| {
             !matches!(live_kit.microphone_track, LocalTrack::None)
         })
     }
 
     pub fn is_muted(&self) -> bool {
+        if let Some(audio_relay) = self.audio_relay.as_ref() {
+            return !audio_relay.is_capturing()
+                || audio_relay.muted_by_user
+                || audio_relay.deafened;
+        }
         self.live_kit.as_ref().map_or(false, |live_kit| {
             matches!(live_kit.microphone_track, LocalTrack::None)
                 || live_kit.muted_by_user
@@ -1334,15 +2206,33 @@ impl Room {
     }
 
     pub fn is_deafened(&self) -> Option<bool> {
+        if let Some(audio_relay) = self.audio_relay.as_ref() {
+            return Some(audio_relay.deafened);
+        }
         self.live_kit.as_ref().map(|live_kit| live_kit.deafened)
     }
 
+    /// Whether the participants of this room can talk to each other.
+    pub fn has_audio(&self) -> bool {
+        self.live_kit.is_some() || self.audio_relay.is_some()
+    }
+
     #[track_caller]
     pub fn share_microphone(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
         if self.status.is_offline() {
             return Task::ready(Err(anyhow!("room is offline")));
         }
 
+        let read_only = self.read_only();
+        if let Some(audio_relay) = self.audio_relay.as_mut() {
+            if read_only {
+                return Task::ready(Err(anyhow!("guests can't speak in calls")));
+            }
+            let result = audio_relay.start_capture();
+            cx.notify();
+            return Task::ready(result);
+        }
+
         let publish_id = if let Some(live_kit) = self.live_kit.as_mut() {
             let publish_id = post_inc(&mut live_kit.next_publish_id);
             live_kit.microphone_track = LocalTrack::Pending { publish_id };
@@ -1520,6 +2410,18 @@ impl Room {
                     task.detach_and_log_err(cx);
                 }
             }
+        } else if let Some(audio_relay) = self.audio_relay.as_mut() {
+            if audio_relay.muted_by_user || audio_relay.deafened || !audio_relay.is_capturing() {
+                audio_relay.muted_by_user = false;
+                audio_relay.set_deafened(false);
+            } else {
+                audio_relay.muted_by_user = true;
+            }
+            let muted = audio_relay.muted_by_user;
+
+            if let Some(task) = self.set_mute(muted, cx) {
+                task.detach_and_log_err(cx);
+            }
         }
     }
 
@@ -1535,6 +2437,17 @@ impl Room {
                 task.detach_and_log_err(cx);
             }
 
+            if should_change_mute {
+                if let Some(task) = self.set_mute(deafened, cx) {
+                    task.detach_and_log_err(cx);
+                }
+            }
+        } else if let Some(audio_relay) = self.audio_relay.as_mut() {
+            let deafened = !audio_relay.deafened;
+            audio_relay.set_deafened(deafened);
+            let should_change_mute = !audio_relay.muted_by_user;
+            cx.notify();
+
             if should_change_mute {
                 if let Some(task) = self.set_mute(deafened, cx) {
                     task.detach_and_log_err(cx);
@@ -1562,6 +2475,9 @@ impl Room {
                 track_publication, ..
             } => {
                 live_kit.room.unpublish_track(track_publication);
+                if let Some(user_id) = self.client.user_id() {
+                    self.remove_screen_annotations(user_id, cx);
+                }
                 cx.notify();
 
                 Audio::play_sound(Sound::StopScreenshare, cx);
@@ -1570,6 +2486,173 @@ impl Room {
         }
     }
 
+    pub fn screen_annotations(&self, sharer_user_id: u64) -> &[ScreenAnnotation] {
+        self.screen_annotations
+            .get(&sharer_user_id)
+            .map_or(&[], |annotations| annotations.as_slice())
+    }
+
+    /// Draws an annotation on top of the screen shared by the given user, which
+    /// every participant in the room sees until it expires.
+    pub fn annotate_screen(
+        &mut self,
+        sharer_user_id: u64,
+        shape: ScreenAnnotationShape,
+        points: Vec<Point<f32>>,
+        cx: &mut ModelContext<Self>,
+    ) -> Result<()> {
+        if self.status.is_offline() {
+            return Err(anyhow!("room is offline"));
+        }
+
+        let is_sharing = if Some(sharer_user_id) == self.client.user_id() {
+            self.is_screen_sharing()
+        } else {
+            self.remote_participants
+                .get(&sharer_user_id)
+                .map_or(false, |participant| !participant.video_tracks.is_empty())
+        };
+        if !is_sharing {
+            return Err(anyhow!(
+                "user {} is not sharing their screen",
+                sharer_user_id
+            ));
+        }
+
+        let author = self
+            .client
+            .peer_id()
+            .ok_or_else(|| anyhow!("not connected"))?;
+        let annotation = ScreenAnnotation {
+            id: post_inc(&mut self.next_screen_annotation_id),
+            author,
+            shape,
+            points,
+        };
+        self.client.send(proto::UpdateScreenAnnotations {
+            room_id: self.id,
+            sharer_user_id,
+            variant: Some(proto::update_screen_annotations::Variant::Add(
+                annotation.to_proto(),
+            )),
+        })?;
+        self.insert_screen_annotation(sharer_user_id, annotation, cx);
+        Ok(())
+    }
+
+    /// Removes every annotation drawn on the screen the local participant is sharing.
+    pub fn clear_screen_annotations(&mut self, cx: &mut ModelContext<Self>) -> Result<()> {
+        if self.status.is_offline() {
+            return Err(anyhow!("room is offline"));
+        }
+        if !self.is_screen_sharing() {
+            return Err(anyhow!("screen was not shared"));
+        }
+
+        let user_id = self
+            .client
+            .user_id()
+            .ok_or_else(|| anyhow!("not signed in"))?;
+        self.client.send(proto::UpdateScreenAnnotations {
+            room_id: self.id,
+            sharer_user_id: user_id,
+            variant: Some(proto::update_screen_annotations::Variant::Clear(
+                proto::update_screen_annotations::Clear { author: None },
+            )),
+        })?;
+        self.remove_screen_annotations(user_id, cx);
+        Ok(())
+    }
+
+    /// Removes the annotations the local participant drew on the screen shared by
+    /// the given user, before they expire.
+    pub fn clear_own_screen_annotations(
+        &mut self,
+        sharer_user_id: u64,
+        cx: &mut ModelContext<Self>,
+    ) -> Result<()> {
+        if self.status.is_offline() {
+            return Err(anyhow!("room is offline"));
+        }
+
+        let author = self
+            .client
+            .peer_id()
+            .ok_or_else(|| anyhow!("not connected"))?;
+        self.client.send(proto::UpdateScreenAnnotations {
+            room_id: self.id,
+            sharer_user_id,
+            variant: Some(proto::update_screen_annotations::Variant::Clear(
+                proto::update_screen_annotations::Clear {
+                    author: Some(author),
+                },
+            )),
+        })?;
+        self.remove_screen_annotations_by(sharer_user_id, author, cx);
+        Ok(())
+    }
+
+    fn insert_screen_annotation(
+        &mut self,
+        sharer_user_id: u64,
+        annotation: ScreenAnnotation,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let key = (annotation.author, annotation.id);
+        self.screen_annotations
+            .entry(sharer_user_id)
+            .or_default()
+            .push(annotation);
+        cx.emit(Event::ScreenAnnotationsChanged { sharer_user_id });
+        cx.notify();
+
+        cx.spawn(move |this, mut cx| async move {
+            cx.background_executor().timer(SCREEN_ANNOTATION_TTL).await;
+            this.update(&mut cx, |this, cx| {
+                let Some(annotations) = this.screen_annotations.get_mut(&sharer_user_id) else {
+                    return;
+                };
+                let len = annotations.len();
+                annotations.retain(|annotation| (annotation.author, annotation.id) != key);
+                if annotations.len() != len {
+                    if annotations.is_empty() {
+                        this.screen_annotations.remove(&sharer_user_id);
+                    }
+                    cx.emit(Event::ScreenAnnotationsChanged { sharer_user_id });
+                    cx.notify();
+                }
+            })
+        })
+        .detach();
+    }
+
+    fn remove_screen_annotations_by(
+        &mut self,
+        sharer_user_id: u64,
+        author: PeerId,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let Some(annotations) = self.screen_annotations.get_mut(&sharer_user_id) else {
+            return;
+        };
+        let len = annotations.len();
+        annotations.retain(|annotation| annotation.author != author);
+        if annotations.len() != len {
+            if annotations.is_empty() {
+                self.screen_annotations.remove(&sharer_user_id);
+            }
+            cx.emit(Event::ScreenAnnotationsChanged { sharer_user_id });
+            cx.notify();
+        }
+    }
+
+    fn remove_screen_annotations(&mut self, sharer_user_id: u64, cx: &mut ModelContext<Self>) {
+        if self.screen_annotations.remove(&sharer_user_id).is_some() {
+            cx.emit(Event::ScreenAnnotationsChanged { sharer_user_id });
+            cx.notify();
+        }
+    }
+
     fn set_deafened(
         &mut self,
         deafened: bool,
@@ -1609,6 +2692,18 @@ impl Room {
         should_mute: bool,
         cx: &mut ModelContext<Room>,
     ) -> Option<Task<Result<()>>> {
+        if let Some(audio_relay) = self.audio_relay.as_mut() {
+            cx.notify();
+            if should_mute {
+                Audio::play_sound(Sound::Mute, cx);
+                audio_relay.stop_capture();
+                return None;
+            } else {
+                Audio::play_sound(Sound::Unmute, cx);
+                return Some(self.share_microphone(cx));
+            }
+        }
+
         let live_kit = self.live_kit.as_mut()?;
         cx.notify();
 
@@ -1642,6 +2737,52 @@ impl Room {
             .room
             .set_display_sources(sources);
     }
+
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn ice_configuration(&self) -> Option<live_kit_client::IceConfiguration> {
+        self.live_kit.as_ref()?.room.ice_configuration()
+    }
+}
+
+/// The version of a buffer that was opened after its project was shared, as of
+/// when its text was loaded. Buffers start out with their text inserted by the
+/// first operation of replica 0.
+pub(crate) fn loaded_buffer_version() -> clock::Global {
+    let mut version = clock::Global::new();
+    version.observe(clock::Lamport {
+        replica_id: 0,
+        value: 1,
+    });
+    version
+}
+
+/// The path of the project's active file, including its worktree's name.
+fn active_file_path(project: &Model<Project>, cx: &AppContext) -> Option<String> {
+    let project = project.read(cx);
+    let project_path = project.path_for_entry(project.active_entry()?, cx)?;
+    let worktree = project.worktree_for_id(project_path.worktree_id, cx)?;
+    let root_name = worktree.read(cx).root_name();
+    Some(proto::path_to_proto(
+        &Path::new(root_name).join(&project_path.path),
+    ))
+}
+
+/// Returns how many lines the given range touches, not counting the line it
+/// ends at the start of.
+fn line_count(range: &Range<language::Point>) -> u32 {
+    if range.is_empty() {
+        0
+    } else {
+        range.end.row - range.start.row + (range.end.column > 0) as u32
+    }
+}
+
+/// A project shared from a directory of its own, which is deleted once the
+/// project is unshared or the room is left.
+struct ScratchProject {
+    project: WeakModel<Project>,
+    dir: PathBuf,
+    fs: Arc<dyn Fs>,
 }
 
 struct LiveKitRoom {
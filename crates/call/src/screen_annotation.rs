@@ -0,0 +1,48 @@
+use client::proto::{self, PeerId};
+use gpui::{point, Point};
+use std::time::Duration;
+
+pub use proto::ScreenAnnotationShape;
+
+/// How long an annotation stays on top of a shared screen before it fades away.
+pub const SCREEN_ANNOTATION_TTL: Duration = Duration::from_secs(8);
+
+/// A temporary drawing made by a participant on top of someone's shared screen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScreenAnnotation {
+    /// Unique among the annotations drawn by the same author.
+    pub id: u64,
+    pub author: PeerId,
+    pub shape: ScreenAnnotationShape,
+    /// Points relative to the shared screen, where (0, 0) is the top-left
+    /// corner and (1, 1) the bottom-right one. Rectangles and arrows are
+    /// described by their two end points, freehand strokes by every point.
+    pub points: Vec<Point<f32>>,
+}
+
+impl ScreenAnnotation {
+    pub(crate) fn from_proto(author: PeerId, annotation: proto::ScreenAnnotation) -> Self {
+        Self {
+            id: annotation.id,
+            author,
+            shape: annotation.shape(),
+            points: annotation
+                .points
+                .into_iter()
+                .map(|p| point(p.x.clamp(0., 1.), p.y.clamp(0., 1.)))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn to_proto(&self) -> proto::ScreenAnnotation {
+        proto::ScreenAnnotation {
+            id: self.id,
+            shape: self.shape.into(),
+            points: self
+                .points
+                .iter()
+                .map(|p| proto::ScreenAnnotationPoint { x: p.x, y: p.y })
+                .collect(),
+        }
+    }
+}
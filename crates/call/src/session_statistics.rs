@@ -0,0 +1,136 @@
+use crate::room::loaded_buffer_version;
+use client::proto;
+use collections::HashMap;
+use gpui::{AppContext, EntityId, Model};
+use language::Buffer;
+use std::{
+    mem,
+    time::{Duration, Instant},
+};
+
+/// Measures the local user's part in a call, for the statistics in its summary,
+/// when they opted into sharing them. Nothing leaves the client until the user
+/// leaves the call.
+#[derive(Default)]
+pub struct SessionStatistics {
+    talk_time: Duration,
+    speaking_since: Option<Instant>,
+    characters_written: u64,
+    counted_buffer_versions: HashMap<EntityId, clock::Global>,
+    active_file: Option<(String, Instant)>,
+    file_times: HashMap<String, Duration>,
+}
+
+impl SessionStatistics {
+    /// Records whether the user is speaking, according to the call's audio.
+    pub fn set_speaking(&mut self, speaking: bool, now: Instant) {
+        match (speaking, self.speaking_since) {
+            (true, None) => self.speaking_since = Some(now),
+            (false, Some(since)) => {
+                self.talk_time += now.saturating_duration_since(since);
+                self.speaking_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Records which file the user is looking at, if any.
+    pub fn set_active_file(&mut self, path: Option<String>, now: Instant) {
+        if self.active_file.as_ref().map(|(path, _)| path) == path.as_ref() {
+            return;
+        }
+        if let Some((path, since)) = self.active_file.take() {
+            *self.file_times.entry(path).or_default() += now.saturating_duration_since(since);
+        }
+        self.active_file = path.map(|path| (path, now));
+    }
+
+    /// Counts the characters the user has written in the given buffer since it
+    /// was last counted, which are still there.
+    pub fn count_characters_written(&mut self, buffer: &Model<Buffer>, cx: &AppContext) {
+        let counted_version = self
+            .counted_buffer_versions
+            .insert(buffer.entity_id(), buffer.read(cx).version())
+            .unwrap_or_else(loaded_buffer_version);
+        let buffer = buffer.read(cx);
+        self.characters_written += buffer
+            .ranges_inserted_by(buffer.replica_id(), &counted_version)
+            .into_iter()
+            .map(|range| buffer.text_for_range(range).flat_map(str::chars).count() as u64)
+            .sum::<u64>();
+    }
+
+    /// Returns what was measured since the statistics were last taken. Talk time
+    /// is only included if the user was connected to the call's audio.
+    pub fn take(&mut self, connected_to_audio: bool, now: Instant) -> proto::SessionStatistics {
+        if let Some(since) = &mut self.speaking_since {
+            self.talk_time += now.saturating_duration_since(*since);
+            *since = now;
+        }
+        if let Some((path, since)) = &mut self.active_file {
+            *self.file_times.entry(path.clone()).or_default() +=
+                now.saturating_duration_since(*since);
+            *since = now;
+        }
+
+        let talk_time = mem::take(&mut self.talk_time);
+        let mut file_times = self
+            .file_times
+            .drain()
+            .map(|(path, duration)| proto::FileTime {
+                path,
+                duration_ms: duration.as_millis() as u64,
+            })
+            .collect::<Vec<_>>();
+        file_times.sort_by(|a, b| a.path.cmp(&b.path));
+        proto::SessionStatistics {
+            talk_time_ms: connected_to_audio.then(|| talk_time.as_millis() as u64),
+            characters_written: mem::take(&mut self.characters_written),
+            file_times,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_statistics_timings() {
+        let start = Instant::now();
+        let mut statistics = SessionStatistics::default();
+        statistics.set_speaking(true, start);
+        statistics.set_active_file(Some("a/main.rs".into()), start);
+        statistics.set_speaking(false, start + Duration::from_secs(3));
+        statistics.set_active_file(Some("a/lib.rs".into()), start + Duration::from_secs(5));
+        statistics.set_speaking(true, start + Duration::from_secs(6));
+
+        let taken = statistics.take(true, start + Duration::from_secs(8));
+        assert_eq!(taken.talk_time_ms, Some(5_000));
+        assert_eq!(
+            taken.file_times,
+            [
+                proto::FileTime {
+                    path: "a/lib.rs".into(),
+                    duration_ms: 3_000,
+                },
+                proto::FileTime {
+                    path: "a/main.rs".into(),
+                    duration_ms: 5_000,
+                },
+            ]
+        );
+
+        // Whatever is still going on is counted from when the statistics were
+        // last taken, and talk time is left out without audio.
+        let taken = statistics.take(false, start + Duration::from_secs(9));
+        assert_eq!(taken.talk_time_ms, None);
+        assert_eq!(
+            taken.file_times,
+            [proto::FileTime {
+                path: "a/lib.rs".into(),
+                duration_ms: 1_000,
+            }]
+        );
+    }
+}
@@ -244,6 +244,21 @@ impl ChannelBuffer {
             .cloned()
     }
 
+    /// Renders the notes as a standalone markdown document, headed by the
+    /// channel's name.
+    pub fn export_markdown(&self, cx: &AppContext) -> String {
+        let text = self.buffer.read(cx).text();
+        let Some(channel) = self.channel(cx) else {
+            return text;
+        };
+        let mut markdown = format!("# {}\n\n", channel.name);
+        markdown.push_str(text.trim_start());
+        if !markdown.ends_with('\n') {
+            markdown.push('\n');
+        }
+        markdown
+    }
+
     pub(crate) fn disconnect(&mut self, cx: &mut ModelContext<Self>) {
         log::info!("channel buffer {} disconnected", self.channel_id);
         if self.connected {
@@ -16,8 +16,8 @@ use rpc::{
     proto::{self, ChannelRole, ChannelVisibility},
     TypedEnvelope,
 };
-use std::{mem, sync::Arc, time::Duration};
-use util::{async_maybe, maybe, ResultExt};
+use std::{cmp::Ordering, mem, sync::Arc, time::Duration};
+use util::{async_maybe, collation::Collation, maybe, ResultExt};
 
 pub fn init(client: &Arc<Client>, user_store: Model<UserStore>, cx: &mut AppContext) {
     let channel_store =
@@ -114,7 +114,9 @@ pub struct ChannelMembership {
     pub role: proto::ChannelRole,
 }
 impl ChannelMembership {
-    pub fn sort_key(&self) -> MembershipSortKey {
+    /// How the membership is ordered among the channel's members, which are listed
+    /// by role, then by kind, then by name in the given collation.
+    pub fn sort_key(&self, collation: Collation) -> MembershipSortKey {
         MembershipSortKey {
             role_order: match self.role {
                 proto::ChannelRole::Admin => 0,
@@ -127,15 +129,35 @@ impl ChannelMembership {
                 proto::channel_member::Kind::Invitee => 1,
             },
             username_order: self.user.github_login.as_str(),
+            collation,
         }
     }
 }
 
-#[derive(PartialOrd, Ord, PartialEq, Eq)]
+#[derive(PartialEq, Eq)]
 pub struct MembershipSortKey<'a> {
     role_order: u8,
     kind_order: u8,
     username_order: &'a str,
+    collation: Collation,
+}
+
+impl PartialOrd for MembershipSortKey<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MembershipSortKey<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.role_order
+            .cmp(&other.role_order)
+            .then_with(|| self.kind_order.cmp(&other.kind_order))
+            .then_with(|| {
+                self.collation
+                    .compare(self.username_order, other.username_order)
+            })
+    }
 }
 
 pub enum ChannelEvent {
@@ -163,6 +163,7 @@ async fn test_channel_messages(cx: &mut TestAppContext) {
                 id: 5,
                 github_login: "nathansobo".into(),
                 avatar_url: "http://avatar.com/nathansobo".into(),
+                display_name: None,
             }],
         },
     );
@@ -213,6 +214,7 @@ async fn test_channel_messages(cx: &mut TestAppContext) {
                 id: 6,
                 github_login: "maxbrunsfeld".into(),
                 avatar_url: "http://avatar.com/maxbrunsfeld".into(),
+                display_name: None,
             }],
         },
     );
@@ -255,6 +257,7 @@ async fn test_channel_messages(cx: &mut TestAppContext) {
                 id: 7,
                 github_login: "as-cii".into(),
                 avatar_url: "http://avatar.com/as-cii".into(),
+                display_name: None,
             }],
         },
     );
@@ -0,0 +1,359 @@
+use crate::{user, Client, Subscription, User, UserStore};
+use anyhow::{anyhow, Result};
+use collections::HashSet;
+use gpui::{AsyncAppContext, EventEmitter, Model, ModelContext, Task, WeakModel};
+use rand::prelude::*;
+use rpc::{proto, TypedEnvelope};
+use std::sync::Arc;
+use time::OffsetDateTime;
+use util::post_inc;
+
+/// The text chat of a call, which every participant can read and write.
+///
+/// Messages are persisted by the server for as long as the call's room exists,
+/// or for as long as its channel exists for calls in a channel. Only the most
+/// recent page is loaded when the chat is opened, and earlier pages are loaded on
+/// demand. Messages that are being sent are shown straight away, after all the
+/// messages the server has acknowledged.
+pub struct ChatStore {
+    room_id: u64,
+    client: Arc<Client>,
+    user_store: Model<UserStore>,
+    messages: Vec<RoomChatMessage>,
+    loaded_all_messages: bool,
+    next_pending_message_id: usize,
+    rng: StdRng,
+    _subscription: Subscription,
+    _user_store_subscription: gpui::Subscription,
+}
+
+#[derive(Clone, Debug)]
+pub struct RoomChatMessage {
+    pub id: RoomChatMessageId,
+    pub sender: Arc<User>,
+    pub body: String,
+    pub timestamp: OffsetDateTime,
+    pub nonce: u128,
+}
+
+/// Saved messages are ordered by their id, and come before pending messages.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RoomChatMessageId {
+    Saved(u64),
+    Pending(usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChatStoreEvent {
+    MessagesChanged,
+    NewMessage { message_id: u64 },
+}
+
+impl EventEmitter<ChatStoreEvent> for ChatStore {}
+
+pub fn init(client: &Arc<Client>) {
+    client.add_model_message_handler(ChatStore::handle_room_message_received);
+}
+
+impl ChatStore {
+    /// Opens the chat of the given room, loading its most recent messages.
+    pub async fn new(
+        room_id: u64,
+        client: Arc<Client>,
+        user_store: Model<UserStore>,
+        mut cx: AsyncAppContext,
+    ) -> Result<Model<Self>> {
+        let subscription = client.subscribe_to_entity(room_id)?;
+        let response = client
+            .request(proto::GetRoomMessages {
+                room_id,
+                before_message_id: None,
+                after_message_id: None,
+            })
+            .await?;
+
+        let this = cx.new_model(|cx| Self {
+            room_id,
+            client: client.clone(),
+            user_store: user_store.clone(),
+            messages: Vec::new(),
+            loaded_all_messages: false,
+            next_pending_message_id: 0,
+            rng: StdRng::from_entropy(),
+            _subscription: subscription.set_model(&cx.handle(), &mut cx.to_async()),
+            _user_store_subscription: cx.subscribe(&user_store, Self::handle_user_store_event),
+        })?;
+        Self::handle_loaded_messages(this.downgrade(), user_store, response, &mut cx).await?;
+        Ok(this)
+    }
+
+    pub fn room_id(&self) -> u64 {
+        self.room_id
+    }
+
+    pub fn messages(&self) -> &[RoomChatMessage] {
+        &self.messages
+    }
+
+    pub fn loaded_all_messages(&self) -> bool {
+        self.loaded_all_messages
+    }
+
+    pub fn pending_messages(&self) -> impl Iterator<Item = &RoomChatMessage> {
+        self.messages.iter().filter(|message| message.is_pending())
+    }
+
+    /// Shows the message straight away, and sends it to the room's other
+    /// participants. Resolves to the message's id once the server saved it.
+    pub fn send_message(
+        &mut self,
+        body: String,
+        cx: &mut ModelContext<Self>,
+    ) -> Result<Task<Result<u64>>> {
+        if body.trim().is_empty() {
+            return Err(anyhow!("message body can't be empty"));
+        }
+        let current_user = self
+            .user_store
+            .read(cx)
+            .current_user()
+            .ok_or_else(|| anyhow!("current_user is not present"))?;
+
+        let nonce = self.rng.gen();
+        self.insert_messages(
+            vec![RoomChatMessage {
+                id: RoomChatMessageId::Pending(post_inc(&mut self.next_pending_message_id)),
+                sender: current_user,
+                body: body.clone(),
+                timestamp: OffsetDateTime::now_utc(),
+                nonce,
+            }],
+            cx,
+        );
+
+        let request = self.client.request(proto::SendRoomMessage {
+            room_id: self.room_id,
+            body,
+            nonce: Some(nonce.into()),
+        });
+        let user_store = self.user_store.clone();
+        Ok(cx.spawn(move |this, mut cx| async move {
+            let response = match request.await {
+                Ok(response) => response,
+                Err(error) => {
+                    this.update(&mut cx, |this, cx| {
+                        this.remove_message_with_nonce(nonce, cx)
+                    })?;
+                    return Err(error);
+                }
+            };
+            let message = response.message.ok_or_else(|| anyhow!("invalid message"))?;
+            let id = message.id;
+            let message = RoomChatMessage::from_proto(message, &user_store, &mut cx).await?;
+            this.update(&mut cx, |this, cx| this.insert_messages(vec![message], cx))?;
+            Ok(id)
+        }))
+    }
+
+    /// Loads the page of messages sent before the earliest loaded message.
+    /// Returns `None` once every message has been loaded.
+    pub fn load_more_messages(&mut self, cx: &mut ModelContext<Self>) -> Option<Task<Result<()>>> {
+        if self.loaded_all_messages {
+            return None;
+        }
+
+        let before_message_id = self.messages.iter().find_map(|message| match message.id {
+            RoomChatMessageId::Saved(id) => Some(id),
+            RoomChatMessageId::Pending(_) => None,
+        })?;
+        let request = self.client.request(proto::GetRoomMessages {
+            room_id: self.room_id,
+            before_message_id: Some(before_message_id),
+            after_message_id: None,
+        });
+        let user_store = self.user_store.clone();
+        Some(cx.spawn(move |this, mut cx| async move {
+            let response = request.await?;
+            Self::handle_loaded_messages(this, user_store, response, &mut cx).await
+        }))
+    }
+
+    /// Loads the messages that were sent while the client was reconnecting to the
+    /// room, which the server couldn't send it.
+    pub fn rejoin(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        let latest_message_id = self
+            .messages
+            .iter()
+            .rev()
+            .find_map(|message| match message.id {
+                RoomChatMessageId::Saved(id) => Some(id),
+                RoomChatMessageId::Pending(_) => None,
+            });
+        let room_id = self.room_id;
+        let client = self.client.clone();
+        let user_store = self.user_store.clone();
+        cx.spawn(move |this, mut cx| async move {
+            let Some(mut after_message_id) = latest_message_id else {
+                let response = client
+                    .request(proto::GetRoomMessages {
+                        room_id,
+                        before_message_id: None,
+                        after_message_id: None,
+                    })
+                    .await?;
+                return Self::handle_loaded_messages(this, user_store, response, &mut cx).await;
+            };
+
+            loop {
+                let response = client
+                    .request(proto::GetRoomMessages {
+                        room_id,
+                        before_message_id: None,
+                        after_message_id: Some(after_message_id),
+                    })
+                    .await?;
+                if let Some(message) = response.messages.last() {
+                    after_message_id = message.id;
+                }
+                let messages =
+                    RoomChatMessage::from_proto_vec(response.messages, &user_store, &mut cx)
+                        .await?;
+                this.update(&mut cx, |this, cx| this.insert_messages(messages, cx))?;
+                if response.done {
+                    return Ok(());
+                }
+            }
+        })
+    }
+
+    async fn handle_loaded_messages(
+        this: WeakModel<Self>,
+        user_store: Model<UserStore>,
+        response: proto::GetRoomMessagesResponse,
+        cx: &mut AsyncAppContext,
+    ) -> Result<()> {
+        let messages = RoomChatMessage::from_proto_vec(response.messages, &user_store, cx).await?;
+        this.update(cx, |this, cx| {
+            this.loaded_all_messages = response.done;
+            this.insert_messages(messages, cx);
+        })
+    }
+
+    async fn handle_room_message_received(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::RoomMessageReceived>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        let user_store = this.update(&mut cx, |this, _| this.user_store.clone())?;
+        let message = envelope
+            .payload
+            .message
+            .ok_or_else(|| anyhow!("empty message"))?;
+        let message_id = message.id;
+        let message = RoomChatMessage::from_proto(message, &user_store, &mut cx).await?;
+        this.update(&mut cx, |this, cx| {
+            this.insert_messages(vec![message], cx);
+            cx.emit(ChatStoreEvent::NewMessage { message_id });
+        })
+    }
+
+    /// Shows the new name of senders who changed it while the chat was open.
+    fn handle_user_store_event(
+        &mut self,
+        _: Model<UserStore>,
+        event: &user::Event,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if let user::Event::UserUpdated(user) = event {
+            let mut updated = false;
+            for message in &mut self.messages {
+                if message.sender.id == user.id {
+                    message.sender = user.clone();
+                    updated = true;
+                }
+            }
+            if updated {
+                cx.emit(ChatStoreEvent::MessagesChanged);
+                cx.notify();
+            }
+        }
+    }
+
+    /// Inserts messages in order, replacing any message with the same nonce,
+    /// such as the pending copy of a message that has since been saved.
+    fn insert_messages(&mut self, messages: Vec<RoomChatMessage>, cx: &mut ModelContext<Self>) {
+        if messages.is_empty() {
+            return;
+        }
+        for message in messages {
+            self.messages
+                .retain(|existing| existing.nonce != message.nonce);
+            let ix = self
+                .messages
+                .partition_point(|existing| existing.id < message.id);
+            self.messages.insert(ix, message);
+        }
+        cx.emit(ChatStoreEvent::MessagesChanged);
+        cx.notify();
+    }
+
+    fn remove_message_with_nonce(&mut self, nonce: u128, cx: &mut ModelContext<Self>) {
+        let len = self.messages.len();
+        self.messages.retain(|message| message.nonce != nonce);
+        if self.messages.len() != len {
+            cx.emit(ChatStoreEvent::MessagesChanged);
+            cx.notify();
+        }
+    }
+}
+
+impl RoomChatMessage {
+    pub fn is_pending(&self) -> bool {
+        matches!(self.id, RoomChatMessageId::Pending(_))
+    }
+
+    async fn from_proto(
+        message: proto::RoomMessage,
+        user_store: &Model<UserStore>,
+        cx: &mut AsyncAppContext,
+    ) -> Result<Self> {
+        let sender = user_store
+            .update(cx, |user_store, cx| {
+                user_store.get_user(message.sender_id, cx)
+            })?
+            .await?;
+        Ok(Self {
+            id: RoomChatMessageId::Saved(message.id),
+            sender,
+            body: message.body,
+            timestamp: OffsetDateTime::from_unix_timestamp(message.timestamp as i64)?,
+            nonce: message
+                .nonce
+                .ok_or_else(|| anyhow!("nonce is required"))?
+                .into(),
+        })
+    }
+
+    async fn from_proto_vec(
+        messages: Vec<proto::RoomMessage>,
+        user_store: &Model<UserStore>,
+        cx: &mut AsyncAppContext,
+    ) -> Result<Vec<Self>> {
+        let sender_ids = messages
+            .iter()
+            .map(|message| message.sender_id)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        user_store
+            .update(cx, |user_store, cx| user_store.get_users(sender_ids, cx))?
+            .await?;
+
+        let mut result = Vec::with_capacity(messages.len());
+        for message in messages {
+            result.push(Self::from_proto(message, user_store, cx).await?);
+        }
+        Ok(result)
+    }
+}
@@ -1,6 +1,11 @@
 #[cfg(any(test, feature = "test-support"))]
 pub mod test;
 
+pub mod chat_store;
+#[cfg(unix)]
+mod connection_broker;
+pub mod connectivity;
+pub mod dual_stack;
 pub mod telemetry;
 pub mod user;
 
@@ -22,7 +27,7 @@ use lazy_static::lazy_static;
 use parking_lot::RwLock;
 use postage::watch;
 use rand::prelude::*;
-use release_channel::{AppVersion, ReleaseChannel};
+use release_channel::{AppVersion, ReleaseChannel, RELEASE_CHANNEL};
 use rpc::proto::{AnyTypedEnvelope, EntityMessage, EnvelopedMessage, PeerId, RequestMessage};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -36,14 +41,17 @@ use std::{
     marker::PhantomData,
     path::PathBuf,
     sync::{atomic::AtomicU64, Arc, Weak},
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 use telemetry::Telemetry;
 use thiserror::Error;
 use url::Url;
 use util::http::{HttpClient, ZedHttpClient};
-use util::{ResultExt, TryFutureExt};
+use util::{collation::Collation, ResultExt, TryFutureExt};
 
+pub use chat_store::{ChatStore, ChatStoreEvent, RoomChatMessage, RoomChatMessageId};
+pub use connectivity::ConnectionDiagnosis;
+pub use dual_stack::AddressFamily;
 pub use rpc::*;
 pub use telemetry::Event;
 pub use user::*;
@@ -57,6 +65,16 @@ lazy_static! {
     pub static ref ADMIN_API_TOKEN: Option<String> = std::env::var("ZED_ADMIN_API_TOKEN")
         .ok()
         .and_then(|s| if s.is_empty() { None } else { Some(s) });
+    /// Credentials handed out by a self-hosted server, which are used instead of
+    /// signing in through the browser.
+    static ref ZED_ACCESS_TOKEN: Option<Credentials> = std::env::var("ZED_USER_ID")
+        .ok()
+        .and_then(|user_id| user_id.parse().ok())
+        .zip(std::env::var("ZED_ACCESS_TOKEN").ok())
+        .map(|(user_id, access_token)| Credentials {
+            user_id,
+            access_token,
+        });
     pub static ref ZED_APP_PATH: Option<PathBuf> =
         std::env::var("ZED_APP_PATH").ok().map(PathBuf::from);
     pub static ref ZED_ALWAYS_ACTIVE: bool =
@@ -65,6 +83,15 @@ lazy_static! {
 
 pub const INITIAL_RECONNECTION_DELAY: Duration = Duration::from_millis(100);
 pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the clients of a restarting server spread their reconnections over, so
+/// that they don't all arrive at the remaining servers at once.
+pub const RESTART_RECONNECT_SPREAD: Duration = Duration::from_secs(5);
+/// How often the client compares the wall clock against its own timers to
+/// detect that the machine was asleep.
+pub const SYSTEM_RESUME_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How far the wall clock must run ahead of the client's timers before the
+/// gap is treated as the machine having been asleep.
+pub const SYSTEM_RESUME_THRESHOLD: Duration = Duration::from_secs(5);
 
 actions!(client, [SignIn, SignOut, Reconnect]);
 
@@ -107,6 +134,7 @@ pub fn init_settings(cx: &mut AppContext) {
 }
 
 pub fn init(client: &Arc<Client>, cx: &mut AppContext) {
+    chat_store::init(client);
     let client = Arc::downgrade(client);
     cx.on_action({
         let client = client.clone();
@@ -149,6 +177,11 @@ struct GlobalClient(Arc<Client>);
 
 impl Global for GlobalClient {}
 
+/// The connection to the collaboration server. A single client is stored as a global
+/// and shared by every window, so each running instance of Zed holds one connection
+/// per signed-in user, no matter how many windows it has open. On Unix, the instances
+/// signed in as the same user share one connection too, which is brokered by the first
+/// of them to connect.
 pub struct Client {
     id: AtomicU64,
     peer: Arc<Peer>,
@@ -177,14 +210,20 @@ pub struct Client {
             >,
         >,
     >,
+
+    #[allow(clippy::type_complexity)]
+    #[cfg(any(test, feature = "test-support"))]
+    system_clock: RwLock<Option<Box<dyn 'static + Send + Sync + Fn() -> SystemTime>>>,
 }
 
 #[derive(Error, Debug)]
 pub enum EstablishConnectionError {
     #[error("upgrade required")]
-    UpgradeRequired,
+    UpgradeRequired(UpgradeRequired),
     #[error("unauthorized")]
     Unauthorized,
+    #[error("too many connections, the limit is {limit}")]
+    TooManyConnections { limit: usize },
     #[error("{0}")]
     Other(#[from] anyhow::Error),
     #[error("{0}")]
@@ -200,7 +239,22 @@ impl From<WebsocketError> for EstablishConnectionError {
         if let WebsocketError::Http(response) = &error {
             match response.status() {
                 StatusCode::UNAUTHORIZED => return EstablishConnectionError::Unauthorized,
-                StatusCode::UPGRADE_REQUIRED => return EstablishConnectionError::UpgradeRequired,
+                StatusCode::UPGRADE_REQUIRED => {
+                    return EstablishConnectionError::UpgradeRequired(
+                        UpgradeRequired::from_headers(|name| {
+                            response.headers().get(name)?.to_str().ok()
+                        }),
+                    )
+                }
+                StatusCode::TOO_MANY_REQUESTS => {
+                    let limit = response
+                        .headers()
+                        .get(rpc::CONNECTION_LIMIT_HEADER)
+                        .and_then(|limit| limit.to_str().ok()?.parse().ok());
+                    if let Some(limit) = limit {
+                        return EstablishConnectionError::TooManyConnections { limit };
+                    }
+                }
                 _ => {}
             }
         }
@@ -245,10 +299,32 @@ impl Status {
 
 struct ClientState {
     credentials: Option<Credentials>,
+    /// Whether the credentials are a guest's, which are only used for this session
+    /// and never stored in the keychain.
+    guest: bool,
     status: (watch::Sender<Status>, watch::Receiver<Status>),
     entity_id_extractors: HashMap<TypeId, fn(&dyn AnyTypedEnvelope) -> u64>,
     _reconnect_task: Option<Task<()>>,
+    _restart_reconnect_task: Option<Task<()>>,
+    _resume_detector: Option<Task<()>>,
+    address_family: Option<AddressFamily>,
+    server_capabilities: Option<proto::ServerCapabilities>,
+    /// How the server this client most recently connected to orders names.
+    collation: Collation,
+    connection_diagnosis: Option<ConnectionDiagnosis>,
+    upgrade_required: Option<UpgradeRequired>,
     reconnect_interval: Duration,
+    /// The URL of the server this client last connected to.
+    rpc_url: Option<Url>,
+    /// Where to connect next, if the server that's restarting named another one.
+    reconnect_url: Option<Url>,
+    /// The token a restarting server handed out for rejoining the room this client
+    /// was in.
+    resume_token: Option<String>,
+    /// Carries the connections of the other instances of Zed signed in as the same
+    /// user, if this instance connected to the server itself.
+    #[cfg(unix)]
+    connection_broker: Option<connection_broker::ConnectionBroker>,
     entities_by_type_and_remote_id: HashMap<(TypeId, u64), WeakSubscriber>,
     models_by_message_type: HashMap<TypeId, AnyWeakModel>,
     entity_types_by_message_type: HashMap<TypeId, TypeId>,
@@ -279,14 +355,39 @@ pub struct Credentials {
     pub access_token: String,
 }
 
+impl Credentials {
+    /// The link that someone invited into a room as a guest signs in with.
+    pub fn guest_link(&self) -> String {
+        format!(
+            "{}guest/{}/{}",
+            RELEASE_CHANNEL.link_prefix(),
+            self.user_id,
+            url::form_urlencoded::byte_serialize(self.access_token.as_bytes()).collect::<String>()
+        )
+    }
+}
+
 impl Default for ClientState {
     fn default() -> Self {
         Self {
             credentials: None,
+            guest: false,
             status: watch::channel_with(Status::SignedOut),
             entity_id_extractors: Default::default(),
             _reconnect_task: None,
+            _restart_reconnect_task: None,
+            _resume_detector: None,
+            address_family: None,
+            rpc_url: None,
+            server_capabilities: None,
+            collation: Collation::default(),
+            connection_diagnosis: None,
+            upgrade_required: None,
             reconnect_interval: Duration::from_secs(5),
+            reconnect_url: None,
+            resume_token: None,
+            #[cfg(unix)]
+            connection_broker: None,
             models_by_message_type: Default::default(),
             entities_by_type_and_remote_id: Default::default(),
             entity_types_by_message_type: Default::default(),
@@ -433,6 +534,8 @@ impl Client {
             authenticate: Default::default(),
             #[cfg(any(test, feature = "test-support"))]
             establish_connection: Default::default(),
+            #[cfg(any(test, feature = "test-support"))]
+            system_clock: Default::default(),
         });
 
         client
@@ -455,6 +558,7 @@ impl Client {
     pub fn teardown(&self) {
         let mut state = self.state.write();
         state._reconnect_task.take();
+        state._resume_detector.take();
         state.message_handlers.clear();
         state.models_by_message_type.clear();
         state.entities_by_type_and_remote_id.clear();
@@ -483,6 +587,24 @@ impl Client {
         self
     }
 
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn override_system_clock<F>(&self, now: F) -> &Self
+    where
+        F: 'static + Send + Sync + Fn() -> SystemTime,
+    {
+        *self.system_clock.write() = Some(Box::new(now));
+        self
+    }
+
+    fn system_time(&self) -> SystemTime {
+        #[cfg(any(test, feature = "test-support"))]
+        if let Some(now) = self.system_clock.read().as_ref() {
+            return now();
+        }
+
+        SystemTime::now()
+    }
+
     pub fn global(cx: &AppContext) -> Arc<Self> {
         cx.global::<GlobalClient>().0.clone()
     }
@@ -518,30 +640,33 @@ impl Client {
         match status {
             Status::Connected { .. } => {
                 state._reconnect_task = None;
+                state.connection_diagnosis = None;
+                state.upgrade_required = None;
+                if state._resume_detector.is_none() {
+                    state._resume_detector = Some(self.detect_system_resume(cx));
+                }
             }
             Status::ConnectionLost => {
                 let this = self.clone();
                 let reconnect_interval = state.reconnect_interval;
                 state._reconnect_task = Some(cx.spawn(move |cx| async move {
-                    #[cfg(any(test, feature = "test-support"))]
-                    let mut rng = StdRng::seed_from_u64(0);
-                    #[cfg(not(any(test, feature = "test-support")))]
-                    let mut rng = StdRng::from_entropy();
-
+                    let mut rng = reconnection_rng();
                     let mut delay = INITIAL_RECONNECTION_DELAY;
                     while let Err(error) = this.authenticate_and_connect(true, &cx).await {
                         log::error!("failed to connect {}", error);
                         if matches!(*this.status().borrow(), Status::ConnectionError) {
+                            // Wait for a random part of the delay, even once it stops
+                            // growing, so that clients that lost their connections
+                            // together don't keep retrying together.
+                            let jittered_delay = delay.mul_f32(rng.gen_range(0.5..=1.0));
                             this.set_status(
                                 Status::ReconnectionError {
-                                    next_reconnection: Instant::now() + delay,
+                                    next_reconnection: Instant::now() + jittered_delay,
                                 },
                                 &cx,
                             );
-                            cx.background_executor().timer(delay).await;
-                            delay = delay
-                                .mul_f32(rng.gen_range(1.0..=2.0))
-                                .min(reconnect_interval);
+                            cx.background_executor().timer(jittered_delay).await;
+                            delay = (delay * 2).min(reconnect_interval);
                         } else {
                             break;
                         }
@@ -550,12 +675,61 @@ impl Client {
             }
             Status::SignedOut | Status::UpgradeRequired => {
                 self.telemetry.set_authenticated_user_info(None, false);
+                state.connection_diagnosis = None;
                 state._reconnect_task.take();
+                state._restart_reconnect_task.take();
+                state._resume_detector.take();
             }
             _ => {}
         }
     }
 
+    /// While the machine is asleep the client's timers don't fire, so the
+    /// wall clock appears to jump forward when it wakes up. The connection is
+    /// almost certainly dead by then, but nothing will notice until the
+    /// receive timeout elapses, so reconnect as soon as the jump is observed.
+    fn detect_system_resume(self: &Arc<Self>, cx: &AsyncAppContext) -> Task<()> {
+        let this = Arc::downgrade(self);
+        cx.spawn(|cx| async move {
+            let mut last_checked_at = match this.upgrade() {
+                Some(this) => this.system_time(),
+                None => return,
+            };
+            loop {
+                cx.background_executor()
+                    .timer(SYSTEM_RESUME_CHECK_INTERVAL)
+                    .await;
+                let Some(this) = this.upgrade() else {
+                    return;
+                };
+                let now = this.system_time();
+                let elapsed = now
+                    .duration_since(last_checked_at)
+                    .unwrap_or(Duration::ZERO);
+                last_checked_at = now;
+                if elapsed > SYSTEM_RESUME_CHECK_INTERVAL + SYSTEM_RESUME_THRESHOLD {
+                    log::info!(
+                        "client {} detected system resume after {:?}",
+                        this.id(),
+                        elapsed
+                    );
+                    this.handle_system_resume(&cx);
+                }
+            }
+        })
+    }
+
+    /// Forces an immediate reconnect after the machine wakes from sleep,
+    /// rather than waiting for the dead connection to time out or for the
+    /// next scheduled reconnection attempt.
+    pub fn handle_system_resume(self: &Arc<Self>, cx: &AsyncAppContext) {
+        match *self.status().borrow() {
+            Status::Connected { .. } | Status::ReconnectionError { .. } => {}
+            _ => return,
+        }
+        self.reconnect(cx);
+    }
+
     pub fn subscribe_to_entity<T>(
         self: &Arc<Self>,
         remote_id: u64,
@@ -755,7 +929,10 @@ impl Client {
             Status::Connected { .. } | Status::Connecting { .. } | Status::Reconnecting { .. } => {
                 return Ok(())
             }
-            Status::UpgradeRequired => return Err(EstablishConnectionError::UpgradeRequired)?,
+            Status::UpgradeRequired => {
+                let upgrade_required = self.upgrade_required().unwrap_or_default();
+                return Err(EstablishConnectionError::UpgradeRequired(upgrade_required))?;
+            }
         };
 
         if was_disconnected {
@@ -778,7 +955,7 @@ impl Client {
                     match authenticate {
                         Ok(creds) => credentials = Some(creds),
                         Err(err) => {
-                            self.set_status(Status::ConnectionError, cx);
+                            self.set_connection_error(ConnectionDiagnosis::diagnose(&err), cx);
                             return Err(err);
                         }
                     }
@@ -804,46 +981,63 @@ impl Client {
                 match connection {
                     Ok(conn) => {
                         self.state.write().credentials = Some(credentials.clone());
-                        if !read_from_keychain && IMPERSONATE_LOGIN.is_none() {
+                        let guest = self.state.read().guest;
+                        if !read_from_keychain && !guest && IMPERSONATE_LOGIN.is_none() {
                             write_credentials_to_keychain(credentials, cx).await.log_err();
                         }
 
                         futures::select_biased! {
                             result = self.set_connection(conn, cx).fuse() => result,
                             _ = timeout => {
-                                self.set_status(Status::ConnectionError, cx);
+                                self.set_connection_error(ConnectionDiagnosis::ServerUnreachable, cx);
                                 Err(anyhow!("timed out waiting on hello message from server"))
                             }
                         }
                     }
                     Err(EstablishConnectionError::Unauthorized) => {
-                        self.state.write().credentials.take();
+                        {
+                            let mut state = self.state.write();
+                            state.credentials.take();
+                            state.guest = false;
+                        }
                         if read_from_keychain {
                             delete_credentials_from_keychain(cx).await.log_err();
                             self.set_status(Status::SignedOut, cx);
                             self.authenticate_and_connect(false, cx).await
                         } else {
-                            self.set_status(Status::ConnectionError, cx);
+                            self.set_connection_error(ConnectionDiagnosis::AuthenticationFailed, cx);
                             Err(EstablishConnectionError::Unauthorized)?
                         }
                     }
-                    Err(EstablishConnectionError::UpgradeRequired) => {
+                    Err(EstablishConnectionError::UpgradeRequired(upgrade_required)) => {
+                        self.state.write().upgrade_required = Some(upgrade_required.clone());
                         self.set_status(Status::UpgradeRequired, cx);
-                        Err(EstablishConnectionError::UpgradeRequired)?
+                        Err(EstablishConnectionError::UpgradeRequired(upgrade_required))?
                     }
                     Err(error) => {
-                        self.set_status(Status::ConnectionError, cx);
-                        Err(error)?
+                        let error = anyhow::Error::from(error);
+                        self.set_connection_error(ConnectionDiagnosis::diagnose(&error), cx);
+                        Err(error)
                     }
                 }
             }
             _ = &mut timeout => {
-                self.set_status(Status::ConnectionError, cx);
+                self.set_connection_error(ConnectionDiagnosis::ServerUnreachable, cx);
                 Err(anyhow!("timed out trying to establish connection"))
             }
         }
     }
 
+    fn set_connection_error(
+        self: &Arc<Self>,
+        diagnosis: ConnectionDiagnosis,
+        cx: &AsyncAppContext,
+    ) {
+        log::info!("connection error: {}", diagnosis);
+        self.state.write().connection_diagnosis = Some(diagnosis);
+        self.set_status(Status::ConnectionError, cx);
+    }
+
     async fn set_connection(
         self: &Arc<Self>,
         conn: Connection,
@@ -878,6 +1072,30 @@ impl Client {
                 .payload
                 .peer_id
                 .ok_or_else(|| anyhow!("invalid peer id"))?;
+
+            // Messages are converted to the form understood by the server if it's older,
+            // but it may have stopped accepting this client's version since connecting.
+            let protocol_version = match rpc::negotiate_protocol_version(
+                hello.payload.protocol_version,
+                hello.payload.min_protocol_version,
+            ) {
+                Ok(protocol_version) => protocol_version,
+                Err(upgrade_required) => {
+                    self.state.write().upgrade_required = Some(upgrade_required.clone());
+                    self.set_status(Status::UpgradeRequired, cx);
+                    Err(EstablishConnectionError::UpgradeRequired(upgrade_required))?
+                }
+            };
+            self.peer
+                .set_protocol_version(connection_id, protocol_version)?;
+            let mut state = self.state.write();
+            state.server_capabilities = hello.payload.capabilities;
+            state.collation = hello
+                .payload
+                .collation_locale
+                .as_deref()
+                .map_or(Collation::default(), Collation::for_locale);
+            drop(state);
             Ok(peer_id)
         };
 
@@ -907,10 +1125,24 @@ impl Client {
             |cx| {
                 async move {
                     while let Some(message) = incoming.next().await {
+                        // The server hangs up once it has too many messages queued for
+                        // this client, after dropping them. Reconnecting rejoins the room
+                        // and its projects, which resyncs everything that was dropped.
+                        if is_send_backlog_exceeded(message.as_ref()) {
+                            log::warn!("server dropped messages for this client, reconnecting");
+                            this.reconnect(&cx);
+                            break;
+                        }
+                        #[cfg(unix)]
+                        let Some(message) = this.relay_brokered_message(message) else {
+                            continue;
+                        };
                         this.handle_message(message, &cx);
                         // Don't starve the main thread when receiving lots of messages at once.
                         smol::future::yield_now().await;
                     }
+                    #[cfg(unix)]
+                    this.disconnect_brokered_connections();
                 }
             }
         })
@@ -961,9 +1193,67 @@ impl Client {
             return callback(credentials, cx);
         }
 
+        #[cfg(unix)]
+        return self.establish_brokered_connection(credentials, cx);
+        #[cfg(not(unix))]
         self.establish_websocket_connection(credentials, cx)
     }
 
+    /// Connects through another instance of Zed signed in as the same user, so that
+    /// they share its connection to the server. If there isn't one, connects directly
+    /// and brokers the connections of the instances that start later.
+    #[cfg(unix)]
+    fn establish_brokered_connection(
+        self: &Arc<Self>,
+        credentials: &Credentials,
+        cx: &AsyncAppContext,
+    ) -> Task<Result<Connection, EstablishConnectionError>> {
+        let socket_path = connection_broker::socket_path(credentials.user_id);
+        let hello = connection_broker::Hello {
+            access_token: credentials.access_token.clone(),
+            protocol_version: rpc::PROTOCOL_VERSION,
+            app_version: cx.update(|cx| AppVersion::global(cx).to_string()).ok(),
+        };
+        let credentials = credentials.clone();
+        let this = self.clone();
+        cx.spawn(|cx| async move {
+            let executor = cx.background_executor().clone();
+            // Guests only sign in for the one session, so they don't share connections.
+            let (brokering, guest) = {
+                let state = this.state.read();
+                (state.connection_broker.is_some(), state.guest)
+            };
+            if guest {
+                return this.establish_websocket_connection(&credentials, &cx).await;
+            }
+
+            if !brokering {
+                match connection_broker::connect(&socket_path, hello, &executor).await {
+                    Ok(connection) => {
+                        log::info!("connected through the connection broker");
+                        return Ok(connection);
+                    }
+                    Err(error) => log::info!("not connecting through a broker: {error:#}"),
+                }
+            }
+
+            let connection = this
+                .establish_websocket_connection(&credentials, &cx)
+                .await?;
+            if !brokering {
+                match connection_broker::ConnectionBroker::listen(
+                    &socket_path,
+                    Arc::downgrade(&this),
+                    &executor,
+                ) {
+                    Ok(broker) => this.state.write().connection_broker = Some(broker),
+                    Err(error) => log::info!("not brokering connections: {error:#}"),
+                }
+            }
+            Ok(connection)
+        })
+    }
+
     async fn get_rpc_url(
         http: Arc<ZedHttpClient>,
         release_channel: Option<ReleaseChannel>,
@@ -979,7 +1269,16 @@ impl Client {
             url += "?";
             url += preview_param;
         }
-        let response = http.get(&url, Default::default(), false).await?;
+        let response = http
+            .get(&url, Default::default(), false)
+            .await
+            .map_err(|source| connectivity::RpcRequestFailed {
+                host: Url::parse(&url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(str::to_string))
+                    .unwrap_or_else(|| url.clone()),
+                source,
+            })?;
         let collab_url = if response.status().is_redirection() {
             response
                 .headers()
@@ -989,10 +1288,9 @@ impl Client {
                 .map_err(EstablishConnectionError::other)?
                 .to_string()
         } else {
-            Err(anyhow!(
-                "unexpected /rpc response status {}",
-                response.status()
-            ))?
+            Err(connectivity::UnexpectedRpcResponse {
+                status: response.status(),
+            })?
         };
 
         Url::parse(&collab_url).context("invalid rpc url")
@@ -1025,15 +1323,33 @@ impl Client {
             );
 
         let http = self.http.clone();
+        let this = self.clone();
+        let executor = cx.background_executor().clone();
+        let reconnect_url = self.state.write().reconnect_url.take();
         cx.background_executor().spawn(async move {
-            let mut rpc_url = Self::get_rpc_url(http, release_channel).await?;
-            let rpc_host = rpc_url
+            let mut rpc_url = match reconnect_url {
+                Some(url) => url,
+                None => Self::get_rpc_url(http, release_channel).await?,
+            };
+            let (rpc_host, rpc_port) = rpc_url
                 .host_str()
                 .zip(rpc_url.port_or_known_default())
                 .ok_or_else(|| anyhow!("missing host in rpc url"))?;
-            let stream = smol::net::TcpStream::connect(rpc_host).await?;
+            let (stream, addr) = dual_stack::connect_to_host(
+                &dual_stack::SystemResolver,
+                rpc_host,
+                rpc_port,
+                &executor,
+                smol::net::TcpStream::connect,
+            )
+            .await?;
+            {
+                let mut state = this.state.write();
+                state.address_family = Some(AddressFamily::from(&addr));
+                state.rpc_url = Some(rpc_url.clone());
+            }
 
-            log::info!("connected to rpc endpoint {}", rpc_url);
+            log::info!("connected to rpc endpoint {} at {}", rpc_url, addr);
 
             match rpc_url.scheme() {
                 "https" => {
@@ -1047,7 +1363,7 @@ impl Client {
                             .sink_map_err(|error| anyhow!(error)),
                     ))
                 }
-                "http" => {
+                "http" if is_secure_url(&rpc_url) => {
                     rpc_url.set_scheme("ws").unwrap();
                     let request = request.uri(rpc_url.as_str()).body(())?;
                     let (stream, _) = async_tungstenite::client_async(request, stream).await?;
@@ -1057,6 +1373,7 @@ impl Client {
                             .sink_map_err(|error| anyhow!(error)),
                     ))
                 }
+                "http" => Err(anyhow!("refusing to sign in to {} without https", rpc_url))?,
                 _ => Err(anyhow!("invalid rpc url: {}", rpc_url))?,
             }
         })
@@ -1091,6 +1408,10 @@ impl Client {
                     let public_key_string = String::try_from(public_key)
                         .expect("failed to serialize public key for auth");
 
+                    if let Some(credentials) = ZED_ACCESS_TOKEN.clone() {
+                        return Ok(credentials);
+                    }
+
                     if let Some((login, token)) =
                         IMPERSONATE_LOGIN.as_ref().zip(ADMIN_API_TOKEN.as_ref())
                     {
@@ -1152,11 +1473,12 @@ impl Client {
                                     )
                                     .context("failed to respond to login http request")?;
                                     return Ok((
-                                        user_id
-                                            .ok_or_else(|| anyhow!("missing user_id parameter"))?,
-                                        access_token.ok_or_else(|| {
-                                            anyhow!("missing access_token parameter")
-                                        })?,
+                                        user_id.ok_or(connectivity::InvalidCredentials(
+                                            "missing user_id parameter",
+                                        ))?,
+                                        access_token.ok_or(connectivity::InvalidCredentials(
+                                            "missing access_token parameter",
+                                        ))?,
                                     ));
                                 }
                             }
@@ -1165,12 +1487,15 @@ impl Client {
                         })
                         .await?;
 
-                    let access_token = private_key
-                        .decrypt_string(&access_token)
-                        .context("failed to decrypt access token")?;
+                    let access_token = private_key.decrypt_string(&access_token).map_err(|_| {
+                        connectivity::InvalidCredentials("failed to decrypt access token")
+                    })?;
+                    let user_id = user_id.parse().map_err(|_| {
+                        connectivity::InvalidCredentials("invalid user_id parameter")
+                    })?;
 
                     Ok(Credentials {
-                        user_id: user_id.parse()?,
+                        user_id,
                         access_token,
                     })
                 })
@@ -1199,6 +1524,11 @@ impl Client {
         // Use the collab server's admin API to retrieve the id
         // of the impersonated user.
         let mut url = Self::get_rpc_url(http.clone(), None).await?;
+        if !is_secure_url(&url) {
+            Err(anyhow!(
+                "refusing to send the admin API token over http to {url}"
+            ))?;
+        }
         url.set_path("/user");
         url.set_query(Some(&format!("github_login={login}")));
         let request = Request::get(url.as_str())
@@ -1208,6 +1538,11 @@ impl Client {
         let mut response = http.send(request).await?;
         let mut body = String::new();
         response.body_mut().read_to_string(&mut body).await?;
+        if matches!(response.status().as_u16(), 401 | 403) {
+            Err(connectivity::InvalidCredentials(
+                "admin API token was rejected",
+            ))?;
+        }
         if !response.status().is_success() {
             Err(anyhow!(
                 "admin user request failed {} - {}",
@@ -1225,7 +1560,26 @@ impl Client {
         })
     }
 
+    /// Signs in with the credentials from a guest invitation, in place of whoever was
+    /// signed in. The server calls the guest into the room they were invited to as
+    /// soon as they connect.
+    pub async fn sign_in_as_guest(
+        self: &Arc<Self>,
+        credentials: Credentials,
+        cx: &AsyncAppContext,
+    ) -> Result<()> {
+        self.disconnect(cx);
+        {
+            let mut state = self.state.write();
+            state.credentials = Some(credentials);
+            state.guest = true;
+        }
+        self.authenticate_and_connect(false, cx).await
+    }
+
     pub fn disconnect(self: &Arc<Self>, cx: &AsyncAppContext) {
+        #[cfg(unix)]
+        self.state.write().connection_broker.take();
         self.peer.teardown();
         self.set_status(Status::SignedOut, cx);
     }
@@ -1235,6 +1589,94 @@ impl Client {
         self.set_status(Status::ConnectionLost, cx);
     }
 
+    /// Reconnects because the server is restarting, to the server it named if it did,
+    /// after a random delay of up to [`RESTART_RECONNECT_SPREAD`]. The resume token it
+    /// handed out is kept until the room this client was in is rejoined.
+    pub fn reconnect_after_restart(
+        self: &Arc<Self>,
+        reconnect_url: Option<String>,
+        resume_token: Option<String>,
+        cx: &AsyncAppContext,
+    ) {
+        {
+            let mut state = self.state.write();
+            state.reconnect_url = reconnect_url
+                .and_then(|url| Url::parse(&url).log_err())
+                .filter(|url| {
+                    let allowed = state
+                        .rpc_url
+                        .as_ref()
+                        .map_or(false, |rpc_url| is_allowed_reconnect_url(url, rpc_url));
+                    if !allowed {
+                        log::warn!("ignoring reconnect url {url}");
+                    }
+                    allowed
+                });
+            state.resume_token = resume_token;
+            let delay = RESTART_RECONNECT_SPREAD.mul_f32(reconnection_rng().gen());
+            let this = Arc::downgrade(self);
+            state._restart_reconnect_task = Some(cx.spawn(move |cx| async move {
+                cx.background_executor().timer(delay).await;
+                let Some(this) = this.upgrade() else {
+                    return;
+                };
+                // The server may have dropped the connection already, in which case
+                // the client is reconnecting anyway.
+                if matches!(*this.status().borrow(), Status::Connected { .. }) {
+                    this.reconnect(&cx);
+                }
+            }));
+        }
+    }
+
+    /// Takes the token for rejoining a room that the last server handed out before
+    /// restarting, if there is one.
+    pub fn take_resume_token(&self) -> Option<String> {
+        self.state.write().resume_token.take()
+    }
+
+    /// The versions the server still accepts, if it refused to talk to this client
+    /// because it is too old.
+    pub fn upgrade_required(&self) -> Option<UpgradeRequired> {
+        self.state.read().upgrade_required.clone()
+    }
+
+    /// Why the most recent attempt to connect to the server failed, if it did.
+    pub fn connection_diagnosis(&self) -> Option<ConnectionDiagnosis> {
+        self.state.read().connection_diagnosis.clone()
+    }
+
+    /// The features supported by the server this client most recently connected to.
+    /// Servers that don't advertise capabilities support everything that predates them.
+    pub fn server_capabilities(&self) -> proto::ServerCapabilities {
+        self.state
+            .read()
+            .server_capabilities
+            .clone()
+            .unwrap_or(proto::ServerCapabilities {
+                calls_audio: true,
+                channel_links: true,
+                relayed_audio: false,
+            })
+    }
+
+    /// How the server this client most recently connected to orders the names in the
+    /// lists it sends, such as contacts and channel members, which the client keeps
+    /// in the same order.
+    pub fn collation(&self) -> Collation {
+        self.state.read().collation
+    }
+
+    /// The address family used by the most recent websocket connection to the server.
+    pub fn address_family(&self) -> Option<AddressFamily> {
+        self.state.read().address_family
+    }
+
+    /// The bandwidth used by the current connection to the server.
+    pub fn bandwidth_usage(&self) -> Result<BandwidthUsage> {
+        self.peer.bandwidth_usage(self.connection_id()?)
+    }
+
     fn connection_id(&self) -> Result<ConnectionId> {
         if let Status::Connected { connection_id, .. } = *self.status().borrow() {
             Ok(connection_id)
@@ -1392,11 +1834,83 @@ impl Client {
         }
     }
 
+    /// Relays a message that the server sent on a connection this client brokers,
+    /// returning any other message to be handled.
+    #[cfg(unix)]
+    fn relay_brokered_message(
+        &self,
+        message: Box<dyn AnyTypedEnvelope>,
+    ) -> Option<Box<dyn AnyTypedEnvelope>> {
+        match &self.state.read().connection_broker {
+            Some(broker) => broker.relay(message),
+            None => Some(message),
+        }
+    }
+
+    /// Hangs up on the instances this client brokers connections for, once its own
+    /// connection is lost.
+    #[cfg(unix)]
+    fn disconnect_brokered_connections(&self) {
+        if let Some(broker) = &self.state.read().connection_broker {
+            broker.disconnect_followers();
+        }
+    }
+
     pub fn telemetry(&self) -> &Arc<Telemetry> {
         &self.telemetry
     }
 }
 
+/// Whether the message is the notice the server sends before hanging up on a client
+/// that fell too far behind on receiving messages.
+fn is_send_backlog_exceeded(message: &dyn AnyTypedEnvelope) -> bool {
+    message
+        .as_any()
+        .downcast_ref::<TypedEnvelope<proto::Error>>()
+        .map_or(false, |envelope| {
+            envelope.payload.code() == ErrorCode::SendBacklogExceeded
+        })
+}
+
+/// Whether credentials can be sent to the URL without being readable on the way,
+/// which for http is only the case when it's on this machine.
+/// Randomizes when the client reconnects. Tests use a fixed seed, so that they're
+/// deterministic.
+fn reconnection_rng() -> StdRng {
+    #[cfg(any(test, feature = "test-support"))]
+    return StdRng::seed_from_u64(0);
+    #[cfg(not(any(test, feature = "test-support")))]
+    return StdRng::from_entropy();
+}
+
+/// Whether a restarting server can send this client to the given URL, which it hands
+/// its credentials to. It has to use the same scheme as the server the client is
+/// connected to, and be on that server's host or one in the same domain.
+fn is_allowed_reconnect_url(url: &Url, rpc_url: &Url) -> bool {
+    if url.scheme() != rpc_url.scheme() {
+        return false;
+    }
+    match (url.host(), rpc_url.host()) {
+        (Some(url::Host::Domain(host)), Some(url::Host::Domain(rpc_host))) => {
+            let domain = rpc_host
+                .split_once('.')
+                .map_or(rpc_host, |(_, domain)| domain);
+            host == rpc_host || (domain.contains('.') && host.ends_with(&format!(".{domain}")))
+        }
+        (host, rpc_host) => host.is_some() && host == rpc_host,
+    }
+}
+
+fn is_secure_url(url: &Url) -> bool {
+    match url.host() {
+        _ if url.scheme() == "https" => true,
+        Some(url::Host::Domain(domain)) => domain == "localhost",
+        Some(url::Host::Ipv4(address)) => address.is_loopback(),
+        Some(url::Host::Ipv6(address)) => address.is_loopback(),
+        None => false,
+    }
+}
+
 async fn read_credentials_from_keychain(cx: &AsyncAppContext) -> Option<Credentials> {
     if IMPERSONATE_LOGIN.is_some() {
         return None;
@@ -1617,6 +2131,50 @@ mod tests {
         assert_eq!(decode_worktree_url("not://the-right-format"), None);
     }
 
+    #[test]
+    fn test_allowed_reconnect_urls() {
+        let rpc_url = Url::parse("https://collab.zed.dev/rpc").unwrap();
+        let is_allowed = |url: &str| is_allowed_reconnect_url(&Url::parse(url).unwrap(), &rpc_url);
+        assert!(is_allowed("https://collab.zed.dev/rpc"));
+        assert!(is_allowed("https://collab-2.zed.dev/rpc"));
+        assert!(!is_allowed("http://collab-2.zed.dev/rpc"));
+        assert!(!is_allowed("https://zed.dev.example.com/rpc"));
+        assert!(!is_allowed("https://example.com/rpc"));
+        assert!(!is_allowed("https://127.0.0.1/rpc"));
+
+        let rpc_url = Url::parse("http://localhost:8080/rpc").unwrap();
+        assert!(is_allowed_reconnect_url(
+            &Url::parse("http://localhost:8081/rpc").unwrap(),
+            &rpc_url
+        ));
+        assert!(!is_allowed_reconnect_url(
+            &Url::parse("http://127.0.0.1:8081/rpc").unwrap(),
+            &rpc_url
+        ));
+    }
+
+    #[test]
+    fn test_upgrade_required_response() {
+        let response = async_tungstenite::tungstenite::http::Response::builder()
+            .status(StatusCode::UPGRADE_REQUIRED)
+            .header(MIN_APP_VERSION_HEADER, "0.130.0")
+            .header(RELEASE_NOTES_URL_HEADER, "https://zed.dev/releases")
+            .body(None)
+            .unwrap();
+        let error = EstablishConnectionError::from(WebsocketError::Http(response));
+        let EstablishConnectionError::UpgradeRequired(upgrade_required) = error else {
+            panic!("expected an upgrade required error, got {error:?}");
+        };
+        assert_eq!(
+            upgrade_required,
+            UpgradeRequired {
+                min_protocol_version: None,
+                min_app_version: Some("0.130.0".parse().unwrap()),
+                release_notes_url: Some("https://zed.dev/releases".into()),
+            }
+        );
+    }
+
     #[gpui::test]
     async fn test_subscribing_to_entity(cx: &mut TestAppContext) {
         init_test(cx);
@@ -0,0 +1,390 @@
+use crate::Client;
+use anyhow::{anyhow, Context as _, Result};
+use async_tungstenite::tungstenite::Message as WebSocketMessage;
+use collections::HashMap;
+use futures::{
+    channel::mpsc, future, AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _,
+    StreamExt as _,
+};
+use gpui::{BackgroundExecutor, Task};
+use parking_lot::Mutex;
+use rpc::{
+    proto::{self, AnyTypedEnvelope},
+    Connection, TypedEnvelope,
+};
+use serde::{Deserialize, Serialize};
+use smol::net::unix::{UnixListener, UnixStream};
+use std::{
+    any::TypeId,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Weak},
+};
+use util::ResultExt as _;
+
+/// The largest frame that's relayed between instances, which no message comes close to.
+const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// Where the instance of Zed that's connected to the server as the given user listens
+/// for other instances signed in as that user.
+pub fn socket_path(user_id: u64) -> PathBuf {
+    util::paths::SUPPORT_DIR.join(format!("connection-broker-{user_id}.sock"))
+}
+
+/// What an instance sends the broker when it connects, which the server checks like it
+/// would have if the instance had connected itself.
+#[derive(Serialize, Deserialize)]
+pub struct Hello {
+    pub access_token: String,
+    pub protocol_version: u32,
+    pub app_version: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HelloResponse {
+    error: Option<String>,
+}
+
+/// Connects to the server through another instance of Zed signed in as the same user,
+/// which carries the connection over its own. Fails if no instance is listening on the
+/// socket, or if the server refuses the connection.
+pub async fn connect(
+    path: &Path,
+    hello: Hello,
+    executor: &BackgroundExecutor,
+) -> Result<Connection> {
+    let stream = UnixStream::connect(path).await?;
+    let (mut reader, mut writer) = stream.split();
+    write_frame(
+        &mut writer,
+        &WebSocketMessage::Binary(serde_json::to_vec(&hello)?),
+    )
+    .await?;
+    let response = match read_frame(&mut reader).await? {
+        Some(WebSocketMessage::Binary(response)) => response,
+        _ => Err(anyhow!("connection broker hung up"))?,
+    };
+    let response: HelloResponse = serde_json::from_slice(&response)?;
+    if let Some(error) = response.error {
+        Err(anyhow!("connection broker failed to connect: {error}"))?;
+    }
+
+    let (connection, incoming_tx, outgoing_rx) = Connection::tunneled();
+    executor
+        .spawn(async move {
+            relay(reader, writer, incoming_tx, outgoing_rx)
+                .await
+                .log_err();
+        })
+        .detach();
+    Ok(connection)
+}
+
+/// Lets the other instances of Zed signed in as the same user share this instance's
+/// connection to the server, by listening for them on a local socket and carrying
+/// their connections over this one. Stops listening when dropped.
+pub struct ConnectionBroker {
+    followers: Arc<Mutex<HashMap<u64, mpsc::UnboundedSender<WebSocketMessage>>>>,
+    _listener: Task<()>,
+}
+
+impl ConnectionBroker {
+    /// Starts listening on the given socket. Fails if another instance is already
+    /// listening on it.
+    pub fn listen(
+        path: &Path,
+        client: Weak<Client>,
+        executor: &BackgroundExecutor,
+    ) -> Result<Self> {
+        let listener = bind(path)?;
+        let followers = Arc::new(Mutex::new(HashMap::default()));
+        let _listener = executor.spawn({
+            let followers = followers.clone();
+            let executor = executor.clone();
+            async move {
+                let mut next_brokered_connection_id = 0;
+                loop {
+                    let stream = match listener.accept().await {
+                        Ok((stream, _)) => stream,
+                        Err(error) => {
+                            log::error!("connection broker stopped listening: {error}");
+                            return;
+                        }
+                    };
+                    next_brokered_connection_id += 1;
+                    let serve = serve(
+                        stream,
+                        next_brokered_connection_id,
+                        client.clone(),
+                        followers.clone(),
+                    );
+                    executor
+                        .spawn(async move {
+                            serve.await.log_err();
+                        })
+                        .detach();
+                }
+            }
+        });
+        Ok(Self {
+            followers,
+            _listener,
+        })
+    }
+
+    /// Relays a message from the server to the instance it's for. Returns the message
+    /// if it isn't for one of those instances.
+    pub fn relay(&self, message: Box<dyn AnyTypedEnvelope>) -> Option<Box<dyn AnyTypedEnvelope>> {
+        let payload_type_id = message.payload_type_id();
+        if payload_type_id == TypeId::of::<proto::BrokeredConnectionMessage>() {
+            let message = message
+                .into_any()
+                .downcast::<TypedEnvelope<proto::BrokeredConnectionMessage>>()
+                .unwrap()
+                .payload;
+            if let Some(follower) = self.followers.lock().get(&message.brokered_connection_id) {
+                follower
+                    .unbounded_send(message.into_websocket_message())
+                    .ok();
+            }
+            None
+        } else if payload_type_id == TypeId::of::<proto::CloseBrokeredConnection>() {
+            let message = message
+                .into_any()
+                .downcast::<TypedEnvelope<proto::CloseBrokeredConnection>>()
+                .unwrap()
+                .payload;
+            self.followers
+                .lock()
+                .remove(&message.brokered_connection_id);
+            None
+        } else {
+            Some(message)
+        }
+    }
+
+    /// Hangs up on every instance this one carries a connection for, once its own
+    /// connection to the server is lost. They connect again like this one does.
+    pub fn disconnect_followers(&self) {
+        self.followers.lock().clear();
+    }
+}
+
+/// Binds the socket, replacing the one left behind by an instance that exited without
+/// removing it.
+fn bind(path: &Path) -> Result<UnixListener> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    match UnixListener::bind(path) {
+        Ok(listener) => Ok(listener),
+        Err(error) if error.kind() == io::ErrorKind::AddrInUse => {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                Err(error).context("another instance is brokering connections")?;
+            }
+            std::fs::remove_file(path)?;
+            Ok(UnixListener::bind(path)?)
+        }
+        Err(error) => Err(error)?,
+    }
+}
+
+/// Opens a connection for an instance that connected to the broker, and carries it
+/// until either the instance or the server hangs up.
+async fn serve(
+    stream: UnixStream,
+    brokered_connection_id: u64,
+    client: Weak<Client>,
+    followers: Arc<Mutex<HashMap<u64, mpsc::UnboundedSender<WebSocketMessage>>>>,
+) -> Result<()> {
+    let (mut reader, mut writer) = stream.split();
+    let hello = match read_frame(&mut reader).await? {
+        Some(WebSocketMessage::Binary(hello)) => hello,
+        _ => Err(anyhow!("instance hung up before saying hello"))?,
+    };
+    let hello: Hello = serde_json::from_slice(&hello)?;
+
+    // The follower is registered before the connection is opened, as the server may
+    // start sending messages on it before the response to opening it is handled.
+    let (from_server_tx, from_server_rx) = mpsc::unbounded();
+    followers
+        .lock()
+        .insert(brokered_connection_id, from_server_tx);
+    let opened = async {
+        let client = client
+            .upgrade()
+            .ok_or_else(|| anyhow!("client was dropped"))?;
+        client
+            .request(proto::OpenBrokeredConnection {
+                brokered_connection_id,
+                access_token: hello.access_token,
+                protocol_version: hello.protocol_version,
+                app_version: hello.app_version,
+            })
+            .await
+    }
+    .await;
+    let response = HelloResponse {
+        error: opened.as_ref().err().map(|error| error.to_string()),
+    };
+    let result = async {
+        write_frame(
+            &mut writer,
+            &WebSocketMessage::Binary(serde_json::to_vec(&response)?),
+        )
+        .await?;
+        opened?;
+
+        let (to_server_tx, mut to_server_rx) = mpsc::unbounded();
+        let forward = async {
+            while let Some(message) = to_server_rx.next().await {
+                let client = client
+                    .upgrade()
+                    .ok_or_else(|| anyhow!("client was dropped"))?;
+                if let Some(message) = proto::BrokeredConnectionMessage::from_websocket_message(
+                    brokered_connection_id,
+                    message,
+                ) {
+                    client.send(message)?;
+                }
+            }
+            anyhow::Ok(())
+        };
+        let relay = relay(reader, writer, to_server_tx, from_server_rx);
+        futures::pin_mut!(forward, relay);
+        future::select(forward, relay).await.factor_first().0
+    }
+    .await;
+
+    followers.lock().remove(&brokered_connection_id);
+    if let Some(client) = client.upgrade() {
+        client
+            .send(proto::CloseBrokeredConnection {
+                brokered_connection_id,
+            })
+            .ok();
+    }
+    result
+}
+
+/// Relays messages between a local socket and the channels of a connection carried
+/// over it, until either side hangs up.
+async fn relay(
+    mut reader: impl AsyncRead + Unpin,
+    mut writer: impl AsyncWrite + Unpin,
+    incoming_tx: mpsc::UnboundedSender<WebSocketMessage>,
+    mut outgoing_rx: mpsc::UnboundedReceiver<WebSocketMessage>,
+) -> Result<()> {
+    let read = async {
+        while let Some(message) = read_frame(&mut reader).await? {
+            incoming_tx.unbounded_send(message)?;
+        }
+        anyhow::Ok(())
+    };
+    let write = async {
+        while let Some(message) = outgoing_rx.next().await {
+            write_frame(&mut writer, &message).await?;
+        }
+        anyhow::Ok(())
+    };
+    futures::pin_mut!(read, write);
+    future::select(read, write).await.factor_first().0
+}
+
+/// Writes a message to a local socket, as its kind, the length of its payload and the
+/// payload. Only the kinds of messages that peers exchange are written.
+async fn write_frame(
+    writer: &mut (impl AsyncWrite + Unpin),
+    message: &WebSocketMessage,
+) -> Result<()> {
+    let (kind, payload) = match message {
+        WebSocketMessage::Binary(payload) => (0, payload),
+        WebSocketMessage::Ping(payload) => (1, payload),
+        WebSocketMessage::Pong(payload) => (2, payload),
+        _ => return Ok(()),
+    };
+    writer.write_all(&[kind]).await?;
+    writer
+        .write_all(&(payload.len() as u32).to_be_bytes())
+        .await?;
+    writer.write_all(payload).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Reads a message written by [`write_frame`], or `None` if the other side hung up.
+async fn read_frame(reader: &mut (impl AsyncRead + Unpin)) -> Result<Option<WebSocketMessage>> {
+    let mut header = [0; 5];
+    match reader.read_exact(&mut header).await {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => Err(error)?,
+    }
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if len > MAX_FRAME_LEN {
+        Err(anyhow!("frame of {len} bytes is too large"))?;
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload).await?;
+    match header[0] {
+        0 => Ok(Some(WebSocketMessage::Binary(payload))),
+        1 => Ok(Some(WebSocketMessage::Ping(payload))),
+        2 => Ok(Some(WebSocketMessage::Pong(payload))),
+        kind => Err(anyhow!("invalid frame kind {kind}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames() {
+        futures::executor::block_on(async {
+            let messages = [
+                WebSocketMessage::Binary(vec![1, 2, 3]),
+                WebSocketMessage::Ping(Vec::new()),
+                WebSocketMessage::Close(None),
+                WebSocketMessage::Pong(vec![4]),
+            ];
+            let mut bytes = Vec::new();
+            for message in &messages {
+                write_frame(&mut bytes, message).await.unwrap();
+            }
+
+            // Close frames aren't relayed, since hanging up closes the connection.
+            let mut reader = bytes.as_slice();
+            assert_eq!(
+                read_frame(&mut reader).await.unwrap(),
+                Some(WebSocketMessage::Binary(vec![1, 2, 3]))
+            );
+            assert_eq!(
+                read_frame(&mut reader).await.unwrap(),
+                Some(WebSocketMessage::Ping(Vec::new()))
+            );
+            assert_eq!(
+                read_frame(&mut reader).await.unwrap(),
+                Some(WebSocketMessage::Pong(vec![4]))
+            );
+            assert_eq!(read_frame(&mut reader).await.unwrap(), None);
+
+            // A frame that's cut off is an error rather than the other side hanging up.
+            let mut reader = &bytes[..7];
+            assert!(read_frame(&mut reader).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_bind_replaces_stale_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("connection-broker-1.sock");
+
+        let listener = bind(&path).unwrap();
+        assert!(bind(&path).is_err());
+
+        // The socket is left behind when its instance exits.
+        drop(listener);
+        assert!(path.exists());
+        bind(&path).unwrap();
+    }
+}
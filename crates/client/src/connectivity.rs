@@ -0,0 +1,271 @@
+use crate::{dual_stack::ResolveError, EstablishConnectionError};
+use async_tungstenite::tungstenite::error::Error as WebsocketError;
+use std::{fmt, io};
+use thiserror::Error;
+use util::http::{self, StatusCode};
+
+/// Why the client most recently failed to connect to the server, in terms the user can
+/// act on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionDiagnosis {
+    /// The server's host name could not be resolved.
+    DnsFailure {
+        host: String,
+    },
+    /// Something between the client and the server answered in its place, usually a
+    /// captive portal that wants the user to sign in to the network first.
+    TlsInterception,
+    /// The server rejected the user's credentials.
+    AuthenticationFailed,
+    /// The server's address was found, but it could not be reached.
+    ServerUnreachable,
+    /// The user is already connected to the server from as many places as it allows.
+    TooManyConnections {
+        limit: usize,
+    },
+    Unknown {
+        message: String,
+    },
+}
+
+/// Returned when the `/rpc` endpoint answers with something other than a redirect to
+/// the collaboration server, as happens when a captive portal intercepts the request.
+#[derive(Error, Debug)]
+#[error("unexpected /rpc response status {status}")]
+pub struct UnexpectedRpcResponse {
+    pub status: StatusCode,
+}
+
+/// Returned when the request to the `/rpc` endpoint fails before any response arrives.
+#[derive(Error, Debug)]
+#[error("failed to request /rpc from {host}")]
+pub struct RpcRequestFailed {
+    pub host: String,
+    #[source]
+    pub source: http::Error,
+}
+
+/// Returned when signing in finishes without credentials the client can use, such as
+/// when the access token it receives can't be decrypted.
+#[derive(Error, Debug)]
+#[error("invalid credentials: {0}")]
+pub struct InvalidCredentials(pub &'static str);
+
+impl ConnectionDiagnosis {
+    pub fn diagnose(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            match cause.downcast_ref() {
+                Some(EstablishConnectionError::Unauthorized) => {
+                    return Self::AuthenticationFailed;
+                }
+                Some(EstablishConnectionError::TooManyConnections { limit }) => {
+                    return Self::TooManyConnections { limit: *limit };
+                }
+                _ => {}
+            }
+            if cause.is::<InvalidCredentials>() {
+                return Self::AuthenticationFailed;
+            }
+            if let Some(error) = cause.downcast_ref::<ResolveError>() {
+                return Self::DnsFailure {
+                    host: error.host.clone(),
+                };
+            }
+            if let Some(error) = cause.downcast_ref::<UnexpectedRpcResponse>() {
+                if let Some(diagnosis) = Self::for_rpc_response(error.status) {
+                    return diagnosis;
+                }
+                break;
+            }
+            if let Some(error) = cause.downcast_ref::<RpcRequestFailed>() {
+                match error.source.kind() {
+                    http::ErrorKind::NameResolution => {
+                        return Self::DnsFailure {
+                            host: error.host.clone(),
+                        };
+                    }
+                    http::ErrorKind::ConnectionFailed | http::ErrorKind::Timeout => {
+                        return Self::ServerUnreachable;
+                    }
+                    http::ErrorKind::BadServerCertificate => return Self::TlsInterception,
+                    // I/O errors are diagnosed by their own kind, further down the chain.
+                    http::ErrorKind::Io => {}
+                    _ => break,
+                }
+            }
+            if let Some(WebsocketError::Tls(_)) = cause.downcast_ref() {
+                return Self::TlsInterception;
+            }
+            if let Some(error) = cause.downcast_ref::<io::Error>() {
+                if is_unreachable(error.kind()) {
+                    return Self::ServerUnreachable;
+                }
+            }
+        }
+
+        Self::Unknown {
+            message: error.to_string(),
+        }
+    }
+
+    /// What a response from the `/rpc` endpoint that isn't a redirect says about the
+    /// network, if anything.
+    fn for_rpc_response(status: StatusCode) -> Option<Self> {
+        if status.is_success()
+            || status == StatusCode::PROXY_AUTHENTICATION_REQUIRED
+            || status == StatusCode::NETWORK_AUTHENTICATION_REQUIRED
+        {
+            // A captive portal answered, or the network wants the user to sign in.
+            Some(Self::TlsInterception)
+        } else if status.is_server_error() {
+            Some(Self::ServerUnreachable)
+        } else {
+            None
+        }
+    }
+
+    /// A short explanation of the failure with a suggestion of what to do about it.
+    pub fn guidance(&self) -> String {
+        match self {
+            Self::DnsFailure { host } => {
+                format!("Couldn't look up {host}. Check your internet connection and DNS settings.")
+            }
+            Self::TlsInterception => {
+                "The connection was intercepted before reaching Zed's servers. \
+                If you're on a public network, open a browser to sign in to it, then try again."
+                    .to_string()
+            }
+            Self::AuthenticationFailed => {
+                "Zed couldn't verify your account. Sign out and sign in again.".to_string()
+            }
+            Self::ServerUnreachable => {
+                "Zed's servers can't be reached right now. Check your firewall or proxy settings, \
+                or try again later."
+                    .to_string()
+            }
+            Self::TooManyConnections { limit } => format!(
+                "You're already connected to Zed's servers from {limit} places, which is as many \
+                as your account can be. Quit Zed on another device, then try again."
+            ),
+            Self::Unknown { message } => format!("Couldn't connect to Zed's servers: {message}"),
+        }
+    }
+}
+
+impl fmt::Display for ConnectionDiagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.guidance())
+    }
+}
+
+fn is_unreachable(kind: io::ErrorKind) -> bool {
+    matches!(
+        kind,
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::AddrNotAvailable
+            | io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_diagnose() {
+        let error = anyhow!(EstablishConnectionError::Unauthorized);
+        assert_eq!(
+            ConnectionDiagnosis::diagnose(&error),
+            ConnectionDiagnosis::AuthenticationFailed
+        );
+
+        let error = anyhow!(EstablishConnectionError::TooManyConnections { limit: 10 });
+        assert_eq!(
+            ConnectionDiagnosis::diagnose(&error),
+            ConnectionDiagnosis::TooManyConnections { limit: 10 }
+        );
+
+        let error = anyhow!(EstablishConnectionError::other(ResolveError {
+            host: "collab.zed.dev".into(),
+            source: io::Error::new(io::ErrorKind::Other, "no such host"),
+        }));
+        assert_eq!(
+            ConnectionDiagnosis::diagnose(&error),
+            ConnectionDiagnosis::DnsFailure {
+                host: "collab.zed.dev".into()
+            }
+        );
+
+        let error = anyhow!(InvalidCredentials("failed to decrypt access token"));
+        assert_eq!(
+            ConnectionDiagnosis::diagnose(&error),
+            ConnectionDiagnosis::AuthenticationFailed
+        );
+
+        // The `/rpc` endpoint's status says whether the network or the server is at fault.
+        let error = anyhow!(EstablishConnectionError::other(UnexpectedRpcResponse {
+            status: StatusCode::OK
+        }));
+        assert_eq!(
+            ConnectionDiagnosis::diagnose(&error),
+            ConnectionDiagnosis::TlsInterception
+        );
+        let error = anyhow!(EstablishConnectionError::other(UnexpectedRpcResponse {
+            status: StatusCode::SERVICE_UNAVAILABLE
+        }));
+        assert_eq!(
+            ConnectionDiagnosis::diagnose(&error),
+            ConnectionDiagnosis::ServerUnreachable
+        );
+        let error = anyhow!(EstablishConnectionError::other(UnexpectedRpcResponse {
+            status: StatusCode::NOT_FOUND
+        }));
+        assert_eq!(
+            ConnectionDiagnosis::diagnose(&error),
+            ConnectionDiagnosis::Unknown {
+                message: "unexpected /rpc response status 404 Not Found".into()
+            }
+        );
+
+        // So does the reason the request to the `/rpc` endpoint failed.
+        let error = anyhow!(EstablishConnectionError::other(RpcRequestFailed {
+            host: "zed.dev".into(),
+            source: http::ErrorKind::NameResolution.into(),
+        }));
+        assert_eq!(
+            ConnectionDiagnosis::diagnose(&error),
+            ConnectionDiagnosis::DnsFailure {
+                host: "zed.dev".into()
+            }
+        );
+        let error = anyhow!(EstablishConnectionError::other(RpcRequestFailed {
+            host: "zed.dev".into(),
+            source: http::ErrorKind::ConnectionFailed.into(),
+        }));
+        assert_eq!(
+            ConnectionDiagnosis::diagnose(&error),
+            ConnectionDiagnosis::ServerUnreachable
+        );
+
+        let error = anyhow!(EstablishConnectionError::from(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "connection refused"
+        )));
+        assert_eq!(
+            ConnectionDiagnosis::diagnose(&error),
+            ConnectionDiagnosis::ServerUnreachable
+        );
+
+        let error = anyhow!("something else went wrong");
+        assert_eq!(
+            ConnectionDiagnosis::diagnose(&error),
+            ConnectionDiagnosis::Unknown {
+                message: "something else went wrong".into()
+            }
+        );
+    }
+}
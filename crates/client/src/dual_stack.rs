@@ -0,0 +1,359 @@
+use anyhow::{anyhow, Result};
+use collections::HashSet;
+use futures::{future::BoxFuture, stream::FuturesUnordered, FutureExt as _, StreamExt as _};
+use gpui::BackgroundExecutor;
+use std::{
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+use thiserror::Error;
+
+/// How long to wait for a connection attempt to succeed before racing it against an
+/// attempt to the next address, as recommended by RFC 8305 ("Happy Eyeballs").
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl From<&SocketAddr> for AddressFamily {
+    fn from(addr: &SocketAddr) -> Self {
+        match addr.ip() {
+            IpAddr::V4(_) => Self::Ipv4,
+            IpAddr::V6(_) => Self::Ipv6,
+        }
+    }
+}
+
+/// Returned when a host name can't be resolved to any address.
+#[derive(Error, Debug)]
+#[error("failed to resolve {host}: {source}")]
+pub struct ResolveError {
+    pub host: String,
+    #[source]
+    pub source: io::Error,
+}
+
+pub trait Resolver: Send + Sync {
+    /// Returns both the A and AAAA records for `host`, in the order the system prefers them.
+    fn resolve(&self, host: &str, port: u16) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>>;
+}
+
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>> {
+        let host = host.to_string();
+        async move { smol::net::resolve((host.as_str(), port)).await }.boxed()
+    }
+}
+
+/// Resolves `host` and connects to one of its addresses, returning the connection along
+/// with the address it was made to.
+///
+/// Addresses are attempted alternating between address families. Each attempt is given
+/// [`CONNECTION_ATTEMPT_DELAY`] before the next one is started alongside it, and the first
+/// attempt to succeed wins, so an address family that is unreachable on the current
+/// network (such as IPv4 on an IPv6-only network) only delays the connection briefly.
+pub async fn connect_to_host<S, F, Fut>(
+    resolver: &dyn Resolver,
+    host: &str,
+    port: u16,
+    executor: &BackgroundExecutor,
+    connect: F,
+) -> Result<(S, SocketAddr)>
+where
+    F: FnMut(SocketAddr) -> Fut,
+    Fut: Future<Output = io::Result<S>>,
+{
+    let addrs = resolver
+        .resolve(host, port)
+        .await
+        .map_err(|source| ResolveError {
+            host: host.to_string(),
+            source,
+        })?;
+    if addrs.is_empty() {
+        Err(ResolveError {
+            host: host.to_string(),
+            source: io::Error::new(io::ErrorKind::NotFound, "no addresses found"),
+        })?;
+    }
+    connect_to_addrs(addrs, executor, connect).await
+}
+
+async fn connect_to_addrs<S, F, Fut>(
+    addrs: Vec<SocketAddr>,
+    executor: &BackgroundExecutor,
+    mut connect: F,
+) -> Result<(S, SocketAddr)>
+where
+    F: FnMut(SocketAddr) -> Fut,
+    Fut: Future<Output = io::Result<S>>,
+{
+    let mut pending_addrs = interleave_address_families(addrs).into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+    loop {
+        if attempts.is_empty() {
+            let Some(addr) = pending_addrs.next() else {
+                break;
+            };
+            attempts.push(connect(addr).map(move |result| (addr, result)));
+        }
+
+        let mut attempt_delay = executor.timer(CONNECTION_ATTEMPT_DELAY).fuse();
+        futures::select_biased! {
+            (addr, result) = attempts.select_next_some() => match result {
+                Ok(stream) => return Ok((stream, addr)),
+                Err(error) => {
+                    log::warn!("failed to connect to {}: {}", addr, error);
+                    last_error = Some(error);
+                    if let Some(addr) = pending_addrs.next() {
+                        attempts.push(connect(addr).map(move |result| (addr, result)));
+                    }
+                }
+            },
+            _ = attempt_delay => {
+                if let Some(addr) = pending_addrs.next() {
+                    attempts.push(connect(addr).map(move |result| (addr, result)));
+                }
+            }
+        }
+    }
+
+    Err(last_error.map_or_else(
+        || anyhow!("no addresses to connect to"),
+        |error| error.into(),
+    ))
+}
+
+/// Reorders `addrs` so that consecutive addresses alternate between address families,
+/// starting with the family of the first address and otherwise preserving the order
+/// the resolver returned them in.
+fn interleave_address_families(mut addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let mut seen = HashSet::default();
+    addrs.retain(|addr| seen.insert(*addr));
+    let Some(first_family) = addrs.first().map(AddressFamily::from) else {
+        return addrs;
+    };
+    let (preferred, fallback): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| AddressFamily::from(addr) == first_family);
+
+    let mut preferred = preferred.into_iter();
+    let mut fallback = fallback.into_iter();
+    let mut interleaved = Vec::new();
+    loop {
+        match (preferred.next(), fallback.next()) {
+            (None, None) => break,
+            (a, b) => interleaved.extend(a.into_iter().chain(b)),
+        }
+    }
+    interleaved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::HashMap;
+    use gpui::TestAppContext;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct FakeResolver {
+        records: HashMap<String, Vec<SocketAddr>>,
+    }
+
+    impl Resolver for FakeResolver {
+        fn resolve(
+            &self,
+            host: &str,
+            _port: u16,
+        ) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>> {
+            let result = self.records.get(host).cloned().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("unknown host {host}"))
+            });
+            async move { result }.boxed()
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    enum FakeAddr {
+        /// Accepts connections after the given delay.
+        Reachable(Duration),
+        /// Refuses connections immediately.
+        Refused,
+        /// Never answers, as happens when there is no route to the address.
+        Unreachable,
+    }
+
+    fn fake_connector(
+        executor: &BackgroundExecutor,
+        addrs: HashMap<SocketAddr, FakeAddr>,
+        attempted: Arc<Mutex<Vec<SocketAddr>>>,
+    ) -> impl FnMut(SocketAddr) -> BoxFuture<'static, io::Result<SocketAddr>> {
+        let executor = executor.clone();
+        move |addr| {
+            attempted.lock().push(addr);
+            let behavior = addrs.get(&addr).copied().unwrap_or(FakeAddr::Refused);
+            let executor = executor.clone();
+            async move {
+                match behavior {
+                    FakeAddr::Reachable(delay) => {
+                        if !delay.is_zero() {
+                            executor.timer(delay).await;
+                        }
+                        Ok(addr)
+                    }
+                    FakeAddr::Refused => Err(io::Error::new(
+                        io::ErrorKind::ConnectionRefused,
+                        "connection refused",
+                    )),
+                    FakeAddr::Unreachable => futures::future::pending().await,
+                }
+            }
+            .boxed()
+        }
+    }
+
+    fn v4(last: u8) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, last], 443))
+    }
+
+    fn v6(last: u16) -> SocketAddr {
+        SocketAddr::from(([0xfd00, 0, 0, 0, 0, 0, 0, last], 443))
+    }
+
+    fn resolver(addrs: Vec<SocketAddr>) -> FakeResolver {
+        FakeResolver {
+            records: HashMap::from_iter([("collab.test".to_string(), addrs)]),
+        }
+    }
+
+    #[test]
+    fn test_interleave_address_families() {
+        assert_eq!(
+            interleave_address_families(vec![v6(1), v6(2), v6(3), v4(1), v4(2)]),
+            vec![v6(1), v4(1), v6(2), v4(2), v6(3)]
+        );
+        assert_eq!(
+            interleave_address_families(vec![v4(1), v4(2), v6(1)]),
+            vec![v4(1), v6(1), v4(2)]
+        );
+        assert_eq!(
+            interleave_address_families(vec![v4(1), v6(1), v4(1), v4(2)]),
+            vec![v4(1), v6(1), v4(2)]
+        );
+        assert_eq!(interleave_address_families(vec![]), vec![]);
+    }
+
+    #[gpui::test]
+    async fn test_ipv6_only_network(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+        let attempted = Arc::new(Mutex::new(Vec::new()));
+        let connect = fake_connector(
+            &executor,
+            HashMap::from_iter([
+                (v4(1), FakeAddr::Unreachable),
+                (v6(1), FakeAddr::Reachable(Duration::from_millis(10))),
+            ]),
+            attempted.clone(),
+        );
+
+        // The resolver prefers the unreachable IPv4 address, but the IPv6 attempt starts
+        // after a short delay and wins.
+        let task = executor.spawn({
+            let executor = executor.clone();
+            async move {
+                let resolver = resolver(vec![v4(1), v6(1)]);
+                connect_to_host(&resolver, "collab.test", 443, &executor, connect).await
+            }
+        });
+        executor.advance_clock(CONNECTION_ATTEMPT_DELAY + Duration::from_millis(10));
+        let (stream, addr) = task.await.unwrap();
+        assert_eq!(stream, v6(1));
+        assert_eq!(AddressFamily::from(&addr), AddressFamily::Ipv6);
+        assert_eq!(*attempted.lock(), vec![v4(1), v6(1)]);
+    }
+
+    #[gpui::test]
+    async fn test_refused_address_falls_back_immediately(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+        let attempted = Arc::new(Mutex::new(Vec::new()));
+        let connect = fake_connector(
+            &executor,
+            HashMap::from_iter([
+                (v6(1), FakeAddr::Refused),
+                (v4(1), FakeAddr::Reachable(Duration::ZERO)),
+                (v6(2), FakeAddr::Reachable(Duration::ZERO)),
+            ]),
+            attempted.clone(),
+        );
+
+        let resolver = resolver(vec![v6(1), v6(2), v4(1)]);
+        let (stream, addr) = connect_to_host(&resolver, "collab.test", 443, &executor, connect)
+            .await
+            .unwrap();
+        assert_eq!(stream, v4(1));
+        assert_eq!(AddressFamily::from(&addr), AddressFamily::Ipv4);
+        assert_eq!(*attempted.lock(), vec![v6(1), v4(1)]);
+    }
+
+    #[gpui::test]
+    async fn test_preferred_address_wins_when_reachable(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+        let attempted = Arc::new(Mutex::new(Vec::new()));
+        let connect = fake_connector(
+            &executor,
+            HashMap::from_iter([
+                (v6(1), FakeAddr::Reachable(Duration::from_millis(100))),
+                (v4(1), FakeAddr::Reachable(Duration::ZERO)),
+            ]),
+            attempted.clone(),
+        );
+
+        let task = executor.spawn({
+            let executor = executor.clone();
+            async move {
+                let resolver = resolver(vec![v6(1), v4(1)]);
+                connect_to_host(&resolver, "collab.test", 443, &executor, connect).await
+            }
+        });
+        executor.advance_clock(Duration::from_millis(100));
+        let (stream, _) = task.await.unwrap();
+        assert_eq!(stream, v6(1));
+        assert_eq!(*attempted.lock(), vec![v6(1)]);
+    }
+
+    #[gpui::test]
+    async fn test_all_addresses_failing(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+        let connect = fake_connector(
+            &executor,
+            HashMap::from_iter([(v6(1), FakeAddr::Refused), (v4(1), FakeAddr::Refused)]),
+            Default::default(),
+        );
+        let resolver = resolver(vec![v6(1), v4(1)]);
+        let error = connect_to_host(&resolver, "collab.test", 443, &executor, connect)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("connection refused"));
+
+        let error = connect_to_host(
+            &resolver,
+            "unknown.test",
+            443,
+            &executor,
+            fake_connector(&executor, HashMap::default(), Default::default()),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("unknown host"));
+    }
+}
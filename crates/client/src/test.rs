@@ -91,6 +91,10 @@ impl FakeServer {
                             connection_id,
                             proto::Hello {
                                 peer_id: Some(connection_id.into()),
+                                capabilities: None,
+                                protocol_version: rpc::PROTOCOL_VERSION,
+                                min_protocol_version: rpc::MIN_PROTOCOL_VERSION,
+                                collation_locale: None,
                             },
                         )
                         .unwrap();
@@ -11,7 +11,10 @@ use postage::{sink::Sink, watch};
 use rpc::proto::{RequestMessage, UsersResponse};
 use std::sync::{Arc, Weak};
 use text::ReplicaId;
-use util::TryFutureExt as _;
+use util::{
+    collation::{collate, Collation},
+    TryFutureExt as _,
+};
 
 pub type UserId = u64;
 
@@ -23,6 +26,8 @@ pub struct User {
     pub id: UserId,
     pub github_login: String,
     pub avatar_uri: SharedUri,
+    /// The name the user chose to be shown instead of their GitHub login.
+    pub display_name: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -30,6 +35,8 @@ pub struct Collaborator {
     pub peer_id: proto::PeerId,
     pub replica_id: ReplicaId,
     pub user_id: UserId,
+    /// Whether the host has made the project read-only for this collaborator.
+    pub read_only: bool,
 }
 
 impl PartialOrd for User {
@@ -40,13 +47,15 @@ impl PartialOrd for User {
 
 impl Ord for User {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.github_login.cmp(&other.github_login)
+        collate(&self.github_login, &other.github_login)
     }
 }
 
 impl PartialEq for User {
     fn eq(&self, other: &Self) -> bool {
-        self.id == other.id && self.github_login == other.github_login
+        self.id == other.id
+            && self.github_login == other.github_login
+            && self.display_name == other.display_name
     }
 }
 
@@ -57,6 +66,25 @@ pub struct Contact {
     pub user: Arc<User>,
     pub online: bool,
     pub busy: bool,
+    pub devices: Vec<ContactDevice>,
+    /// The name of the project the contact is working in, if they share it with the
+    /// current user.
+    pub working_in: Option<SharedString>,
+}
+
+/// One of a contact's connected devices.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContactDevice {
+    pub peer_id: proto::PeerId,
+    /// Whether the contact is in a call on this device.
+    pub busy: bool,
+}
+
+/// A private nickname and note that the current user has attached to a contact.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContactNote {
+    pub nickname: Option<SharedString>,
+    pub note: Option<SharedString>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -73,9 +101,15 @@ pub struct UserStore {
     update_contacts_tx: mpsc::UnboundedSender<UpdateContacts>,
     current_user: watch::Receiver<Option<Arc<User>>>,
     contacts: Vec<Arc<Contact>>,
+    contact_notes: HashMap<u64, ContactNote>,
+    location_sharing: HashSet<u64>,
+    blocked_users: HashSet<u64>,
     incoming_contact_requests: Vec<Arc<User>>,
     outgoing_contact_requests: Vec<Arc<User>>,
     pending_contact_requests: HashMap<u64, usize>,
+    /// How the contacts and contact requests are ordered, which is the way the
+    /// server orders them.
+    collation: Collation,
     invite_info: Option<InviteInfo>,
     client: Weak<Client>,
     _maintain_contacts: Task<()>,
@@ -96,6 +130,11 @@ pub enum Event {
     },
     ShowContacts,
     ParticipantIndicesChanged,
+    /// A user changed how they are shown to others, such as their display name.
+    UserUpdated(Arc<User>),
+    /// The server has fallen behind on sending messages to this client, and will
+    /// disconnect it if the backlog keeps growing.
+    ConnectionLagging,
 }
 
 #[derive(Clone, Copy)]
@@ -121,14 +160,21 @@ impl UserStore {
             client.add_message_handler(cx.weak_model(), Self::handle_update_contacts),
             client.add_message_handler(cx.weak_model(), Self::handle_update_invite_info),
             client.add_message_handler(cx.weak_model(), Self::handle_show_contacts),
+            client.add_message_handler(cx.weak_model(), Self::handle_connection_lagging),
+            client.add_message_handler(cx.weak_model(), Self::handle_server_restarting),
+            client.add_message_handler(cx.weak_model(), Self::handle_update_user),
         ];
         Self {
             users: Default::default(),
             current_user: current_user_rx,
             contacts: Default::default(),
+            contact_notes: Default::default(),
+            location_sharing: Default::default(),
+            blocked_users: Default::default(),
             incoming_contact_requests: Default::default(),
             participant_indices: Default::default(),
             outgoing_contact_requests: Default::default(),
+            collation: client.collation(),
             invite_info: None,
             client: Arc::downgrade(&client),
             update_contacts_tx,
@@ -239,6 +285,113 @@ impl UserStore {
         Ok(())
     }
 
+    async fn handle_connection_lagging(
+        this: Model<Self>,
+        message: TypedEnvelope<proto::ConnectionLagging>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        log::warn!(
+            "server has {} messages queued for this client",
+            message.payload.queued_message_count
+        );
+        this.update(&mut cx, |_, cx| cx.emit(Event::ConnectionLagging))?;
+        Ok(())
+    }
+
+    async fn handle_server_restarting(
+        _: Model<Self>,
+        message: TypedEnvelope<proto::ServerRestarting>,
+        client: Arc<Client>,
+        cx: AsyncAppContext,
+    ) -> Result<()> {
+        log::info!("server is restarting, reconnecting");
+        client.reconnect_after_restart(
+            message.payload.reconnect_url,
+            message.payload.resume_token,
+            &cx,
+        );
+        Ok(())
+    }
+
+    async fn handle_update_user(
+        this: Model<Self>,
+        message: TypedEnvelope<proto::UpdateUser>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        let user = message
+            .payload
+            .user
+            .ok_or_else(|| anyhow!("invalid user"))?;
+        this.update(&mut cx, |this, cx| {
+            this.insert_updated_user(User::new(user), cx)
+        })?;
+        Ok(())
+    }
+
+    /// Replaces the cached copies of a user that changed, including the ones in
+    /// the contact list and contact requests.
+    fn insert_updated_user(&mut self, user: Arc<User>, cx: &mut ModelContext<Self>) {
+        if let Some(old_user) = self.users.insert(user.id, user.clone()) {
+            if old_user.avatar_uri != user.avatar_uri {
+                cx.forget_image(old_user.avatar_uri.clone());
+            }
+        }
+        for contact in &mut self.contacts {
+            if contact.user.id == user.id {
+                *contact = Arc::new(Contact {
+                    user: user.clone(),
+                    online: contact.online,
+                    busy: contact.busy,
+                    devices: contact.devices.clone(),
+                    working_in: contact.working_in.clone(),
+                });
+            }
+        }
+        for request in self
+            .incoming_contact_requests
+            .iter_mut()
+            .chain(&mut self.outgoing_contact_requests)
+        {
+            if request.id == user.id {
+                *request = user.clone();
+            }
+        }
+        cx.emit(Event::UserUpdated(user));
+        cx.notify();
+    }
+
+    /// Sets the name shown to other users instead of the current user's GitHub
+    /// login, or goes back to showing the login when `display_name` is `None`.
+    pub fn set_display_name(
+        &self,
+        display_name: Option<String>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        let client = self.client.upgrade();
+        cx.spawn(move |_, _| async move {
+            client
+                .ok_or_else(|| anyhow!("can't upgrade client reference"))?
+                .request(proto::SetDisplayName { display_name })
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// Uploads an image to use as the current user's avatar instead of the one
+    /// from their GitHub account.
+    pub fn upload_avatar(&self, data: Vec<u8>, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        let client = self.client.upgrade();
+        cx.spawn(move |_, _| async move {
+            client
+                .ok_or_else(|| anyhow!("can't upgrade client reference"))?
+                .request(proto::UploadAvatar { data })
+                .await?;
+            Ok(())
+        })
+    }
+
     pub fn invite_info(&self) -> Option<&InviteInfo> {
         self.invite_info.as_ref()
     }
@@ -269,6 +422,9 @@ impl UserStore {
             }
             UpdateContacts::Clear(barrier) => {
                 self.contacts.clear();
+                self.contact_notes.clear();
+                self.location_sharing.clear();
+                self.blocked_users.clear();
                 self.incoming_contact_requests.clear();
                 self.outgoing_contact_requests.clear();
                 drop(barrier);
@@ -316,6 +472,9 @@ impl UserStore {
                         );
                     }
 
+                    let contact_notes = message.contact_notes;
+                    let location_sharing = message.location_sharing;
+                    let blocked_users = message.blocked_users;
                     let removed_contacts =
                         HashSet::<u64>::from_iter(message.remove_contacts.iter().copied());
                     let removed_incoming_requests =
@@ -324,20 +483,62 @@ impl UserStore {
                         HashSet::<u64>::from_iter(message.remove_outgoing_requests.iter().copied());
 
                     this.update(&mut cx, |this, cx| {
+                        if let Some(client) = this.client.upgrade() {
+                            this.set_collation(client.collation());
+                        }
+                        let collation = this.collation;
+
                         // Remove contacts
                         this.contacts
                             .retain(|contact| !removed_contacts.contains(&contact.user.id));
+                        this.contact_notes
+                            .retain(|user_id, _| !removed_contacts.contains(user_id));
+                        this.location_sharing
+                            .retain(|user_id| !removed_contacts.contains(user_id));
                         // Update existing contacts and insert new ones
                         for updated_contact in updated_contacts {
-                            match this.contacts.binary_search_by_key(
-                                &&updated_contact.user.github_login,
-                                |contact| &contact.user.github_login,
-                            ) {
+                            match this.contacts.binary_search_by(|contact| {
+                                collation.compare(
+                                    &contact.user.github_login,
+                                    &updated_contact.user.github_login,
+                                )
+                            }) {
                                 Ok(ix) => this.contacts[ix] = updated_contact,
                                 Err(ix) => this.contacts.insert(ix, updated_contact),
                             }
                         }
 
+                        // Update contact nicknames and notes
+                        for contact_note in contact_notes {
+                            let note = ContactNote {
+                                nickname: contact_note.nickname.map(SharedString::from),
+                                note: contact_note.note.map(SharedString::from),
+                            };
+                            if note == ContactNote::default() {
+                                this.contact_notes.remove(&contact_note.user_id);
+                            } else {
+                                this.contact_notes.insert(contact_note.user_id, note);
+                            }
+                        }
+
+                        // Update which contacts can see the project we're working in
+                        for sharing in location_sharing {
+                            if sharing.enabled {
+                                this.location_sharing.insert(sharing.user_id);
+                            } else {
+                                this.location_sharing.remove(&sharing.user_id);
+                            }
+                        }
+
+                        // Update which users can't send us contact requests or call us
+                        for blocked_user in blocked_users {
+                            if blocked_user.blocked {
+                                this.blocked_users.insert(blocked_user.user_id);
+                            } else {
+                                this.blocked_users.remove(&blocked_user.user_id);
+                            }
+                        }
+
                         // Remove incoming contact requests
                         this.incoming_contact_requests.retain(|user| {
                             if removed_incoming_requests.contains(&user.id) {
@@ -352,11 +553,9 @@ impl UserStore {
                         });
                         // Update existing incoming requests and insert new ones
                         for user in incoming_requests {
-                            match this
-                                .incoming_contact_requests
-                                .binary_search_by_key(&&user.github_login, |contact| {
-                                    &contact.github_login
-                                }) {
+                            match this.incoming_contact_requests.binary_search_by(|contact| {
+                                collation.compare(&contact.github_login, &user.github_login)
+                            }) {
                                 Ok(ix) => this.incoming_contact_requests[ix] = user,
                                 Err(ix) => this.incoming_contact_requests.insert(ix, user),
                             }
@@ -367,11 +566,9 @@ impl UserStore {
                             .retain(|user| !removed_outgoing_requests.contains(&user.id));
                         // Update existing incoming requests and insert new ones
                         for request in outgoing_requests {
-                            match this
-                                .outgoing_contact_requests
-                                .binary_search_by_key(&&request.github_login, |contact| {
-                                    &contact.github_login
-                                }) {
+                            match this.outgoing_contact_requests.binary_search_by(|contact| {
+                                collation.compare(&contact.github_login, &request.github_login)
+                            }) {
                                 Ok(ix) => this.outgoing_contact_requests[ix] = request,
                                 Err(ix) => this.outgoing_contact_requests.insert(ix, request),
                             }
@@ -390,9 +587,131 @@ impl UserStore {
         &self.contacts
     }
 
+    pub fn contact_note(&self, user_id: u64) -> Option<&ContactNote> {
+        self.contact_notes.get(&user_id)
+    }
+
+    /// Returns the nickname the current user has given to this contact, falling back
+    /// to their display name.
+    pub fn contact_display_name(&self, user: &User) -> SharedString {
+        self.contact_notes
+            .get(&user.id)
+            .and_then(|note| note.nickname.clone())
+            .unwrap_or_else(|| user.display_name().to_string().into())
+    }
+
+    pub fn set_contact_note(
+        &mut self,
+        user_id: u64,
+        nickname: Option<String>,
+        note: Option<String>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        self.perform_contact_request(
+            user_id,
+            proto::UpdateContactNote {
+                user_id,
+                nickname,
+                note,
+            },
+            cx,
+        )
+    }
+
+    /// Whether the current user shares the name of the project they're working in
+    /// with this contact.
+    pub fn shares_location_with(&self, user_id: u64) -> bool {
+        self.location_sharing.contains(&user_id)
+    }
+
+    pub fn set_location_sharing(
+        &mut self,
+        user_id: u64,
+        enabled: bool,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        self.perform_contact_request(user_id, proto::SetLocationSharing { user_id, enabled }, cx)
+    }
+
+    /// Whether the current user has blocked this user from sending them contact
+    /// requests and calling them.
+    pub fn is_user_blocked(&self, user_id: u64) -> bool {
+        self.blocked_users.contains(&user_id)
+    }
+
+    pub fn blocked_user_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.blocked_users.iter().copied()
+    }
+
+    pub fn set_user_blocked(
+        &mut self,
+        user_id: u64,
+        blocked: bool,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        self.perform_contact_request(user_id, proto::SetUserBlocked { user_id, blocked }, cx)
+    }
+
+    /// Reports a user for admins to review, pointing to the room the report is made
+    /// from and to messages the user sent in its chat or in channels.
+    pub fn report_user(
+        &self,
+        user_id: u64,
+        room_id: Option<u64>,
+        channel_message_ids: Vec<u64>,
+        room_message_ids: Vec<u64>,
+        reason: String,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        let client = self.client.upgrade();
+        cx.spawn(move |_, _| async move {
+            client
+                .ok_or_else(|| anyhow!("can't upgrade client reference"))?
+                .request(proto::ReportUser {
+                    user_id,
+                    room_id,
+                    channel_message_ids,
+                    room_message_ids,
+                    reason,
+                })
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// Tells the server which project the current user is working in, so that
+    /// contacts they share it with can see it.
+    pub fn update_working_location(&self, name: Option<String>) -> Result<()> {
+        let client = self
+            .client
+            .upgrade()
+            .ok_or_else(|| anyhow!("can't upgrade client reference"))?;
+        client.send(proto::UpdateWorkingLocation { name })
+    }
+
+    /// Orders the contacts and contact requests the way a server that was connected
+    /// to orders them, if that's different from how they're ordered now.
+    fn set_collation(&mut self, collation: Collation) {
+        if collation == self.collation {
+            return;
+        }
+        self.collation = collation;
+        self.contacts
+            .sort_by(|a, b| collation.compare(&a.user.github_login, &b.user.github_login));
+        for requests in [
+            &mut self.incoming_contact_requests,
+            &mut self.outgoing_contact_requests,
+        ] {
+            requests.sort_by(|a, b| collation.compare(&a.github_login, &b.github_login));
+        }
+    }
+
     pub fn has_contact(&self, user: &Arc<User>) -> bool {
         self.contacts
-            .binary_search_by_key(&&user.github_login, |contact| &contact.user.github_login)
+            .binary_search_by(|contact| {
+                self.collation
+                    .compare(&contact.user.github_login, &user.github_login)
+            })
             .is_ok()
     }
 
@@ -411,19 +730,28 @@ impl UserStore {
     pub fn contact_request_status(&self, user: &User) -> ContactRequestStatus {
         if self
             .contacts
-            .binary_search_by_key(&&user.github_login, |contact| &contact.user.github_login)
+            .binary_search_by(|contact| {
+                self.collation
+                    .compare(&contact.user.github_login, &user.github_login)
+            })
             .is_ok()
         {
             ContactRequestStatus::RequestAccepted
         } else if self
             .outgoing_contact_requests
-            .binary_search_by_key(&&user.github_login, |user| &user.github_login)
+            .binary_search_by(|request| {
+                self.collation
+                    .compare(&request.github_login, &user.github_login)
+            })
             .is_ok()
         {
             ContactRequestStatus::RequestSent
         } else if self
             .incoming_contact_requests
-            .binary_search_by_key(&&user.github_login, |user| &user.github_login)
+            .binary_search_by(|request| {
+                self.collation
+                    .compare(&request.github_login, &user.github_login)
+            })
             .is_ok()
         {
             ContactRequestStatus::RequestReceived
@@ -625,7 +953,8 @@ impl UserStore {
     }
 
     pub fn current_user(&self) -> Option<Arc<User>> {
-        self.current_user.borrow().clone()
+        let current_user = self.current_user.borrow().clone()?;
+        self.get_cached_user(current_user.id).or(Some(current_user))
     }
 
     pub fn watch_current_user(&self) -> watch::Receiver<Option<Arc<User>>> {
@@ -684,8 +1013,8 @@ impl UserStore {
         let mut ret = HashMap::default();
         let mut missing_user_ids = Vec::new();
         for id in user_ids {
-            if let Some(github_login) = self.get_cached_user(id).map(|u| u.github_login.clone()) {
-                ret.insert(id, github_login.into());
+            if let Some(user) = self.get_cached_user(id) {
+                ret.insert(id, user.display_name().to_string().into());
             } else {
                 missing_user_ids.push(id)
             }
@@ -708,8 +1037,15 @@ impl User {
             id: message.id,
             github_login: message.github_login,
             avatar_uri: message.avatar_url.into(),
+            display_name: message.display_name,
         })
     }
+
+    /// The name to show for this user: the display name they chose, or their
+    /// GitHub login if they haven't chosen one.
+    pub fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.github_login)
+    }
 }
 
 impl Contact {
@@ -727,6 +1063,17 @@ impl Contact {
             user,
             online: contact.online,
             busy: contact.busy,
+            devices: contact
+                .devices
+                .into_iter()
+                .filter_map(|device| {
+                    Some(ContactDevice {
+                        peer_id: device.peer_id?,
+                        busy: device.busy,
+                    })
+                })
+                .collect(),
+            working_in: contact.working_in.map(SharedString::from),
         })
     }
 }
@@ -737,6 +1084,7 @@ impl Collaborator {
             peer_id: message.peer_id.ok_or_else(|| anyhow!("invalid peer id"))?,
             replica_id: message.replica_id as ReplicaId,
             user_id: message.user_id as UserId,
+            read_only: message.read_only,
         })
     }
 }
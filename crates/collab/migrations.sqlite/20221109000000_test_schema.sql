@@ -35,6 +35,14 @@ CREATE TABLE "contacts" (
 CREATE UNIQUE INDEX "index_contacts_user_ids" ON "contacts" ("user_id_a", "user_id_b");
 CREATE INDEX "index_contacts_user_id_b" ON "contacts" ("user_id_b");
 
+CREATE TABLE "contact_notes" (
+    "user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "contact_user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "nickname" VARCHAR,
+    "note" TEXT,
+    PRIMARY KEY (user_id, contact_user_id)
+);
+
 CREATE TABLE "rooms" (
     "id" INTEGER PRIMARY KEY AUTOINCREMENT,
     "live_kit_room" VARCHAR NOT NULL,
@@ -353,3 +361,17 @@ CREATE TABLE contributors (
     signed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
     PRIMARY KEY (user_id)
 );
+
+CREATE TABLE "call_records" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "room_id" INTEGER NOT NULL,
+    "caller_user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "callee_user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "created_at" TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "ended_at" TIMESTAMP,
+    "status" VARCHAR NOT NULL
+);
+
+CREATE INDEX "index_call_records_on_caller_user_id" ON "call_records" ("caller_user_id");
+CREATE INDEX "index_call_records_on_callee_user_id" ON "call_records" ("callee_user_id");
+CREATE INDEX "index_call_records_on_room_id_and_callee_user_id" ON "call_records" ("room_id", "callee_user_id");
@@ -0,0 +1,21 @@
+CREATE TABLE "room_activities" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "room_id" INTEGER NOT NULL REFERENCES rooms (id) ON DELETE CASCADE,
+    "user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "kind" VARCHAR NOT NULL,
+    "detail" VARCHAR,
+    "lines_added" INTEGER NOT NULL DEFAULT 0,
+    "lines_removed" INTEGER NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX "index_room_activities_on_room_id" ON "room_activities" ("room_id");
+
+CREATE TABLE "call_summaries" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "room_id" INTEGER NOT NULL,
+    "channel_id" INTEGER,
+    "started_at" TIMESTAMP NOT NULL,
+    "ended_at" TIMESTAMP NOT NULL,
+    "content" TEXT NOT NULL
+);
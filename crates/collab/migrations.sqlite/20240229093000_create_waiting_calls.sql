@@ -0,0 +1,13 @@
+CREATE TABLE "waiting_calls" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "room_id" INTEGER NOT NULL REFERENCES rooms (id) ON DELETE CASCADE,
+    "called_user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "calling_user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "calling_connection_id" INTEGER NOT NULL,
+    "calling_connection_server_id" INTEGER REFERENCES servers (id) ON DELETE SET NULL,
+    "initial_project_id" INTEGER,
+    "role" TEXT
+);
+
+CREATE UNIQUE INDEX "index_waiting_calls_on_room_id_and_called_user_id" ON "waiting_calls" ("room_id", "called_user_id");
+CREATE INDEX "index_waiting_calls_on_called_user_id" ON "waiting_calls" ("called_user_id");
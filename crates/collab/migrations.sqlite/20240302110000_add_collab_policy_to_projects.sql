@@ -0,0 +1,2 @@
+ALTER TABLE "projects" ADD COLUMN "guests_read_only" BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE "projects" ADD COLUMN "allowed_roles" VARCHAR;
@@ -0,0 +1,11 @@
+CREATE TABLE "room_messages" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "room_id" INTEGER NOT NULL REFERENCES rooms (id) ON DELETE CASCADE,
+    "sender_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "body" TEXT NOT NULL,
+    "sent_at" TIMESTAMP NOT NULL,
+    "nonce" BLOB NOT NULL
+);
+
+CREATE INDEX "index_room_messages_on_room_id" ON "room_messages" ("room_id");
+CREATE UNIQUE INDEX "index_room_messages_on_sender_id_nonce" ON "room_messages" ("sender_id", "nonce");
@@ -0,0 +1 @@
+ALTER TABLE "project_collaborators" ADD COLUMN "read_only" BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+ALTER TABLE "users" ADD COLUMN "display_name" VARCHAR;
+CREATE UNIQUE INDEX "index_users_on_lower_display_name" ON "users" (LOWER("display_name"));
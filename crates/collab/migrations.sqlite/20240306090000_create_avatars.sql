@@ -0,0 +1,8 @@
+CREATE TABLE "avatars" (
+    "digest" VARCHAR PRIMARY KEY,
+    "content_type" VARCHAR NOT NULL,
+    "data" BLOB NOT NULL,
+    "created_at" TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE "users" ADD COLUMN "avatar_digest" VARCHAR REFERENCES avatars (digest);
@@ -0,0 +1,8 @@
+CREATE TABLE "routed_messages" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "server_id" INTEGER NOT NULL REFERENCES servers (id) ON DELETE CASCADE,
+    "connection_id" INTEGER NOT NULL,
+    "envelope" BLOB NOT NULL
+);
+
+CREATE INDEX "index_routed_messages_on_server_id" ON "routed_messages" ("server_id");
@@ -0,0 +1,9 @@
+CREATE TABLE "guests" (
+    "user_id" INTEGER PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    "room_id" INTEGER REFERENCES rooms (id) ON DELETE SET NULL,
+    "role" VARCHAR NOT NULL,
+    "invited_by_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "expires_at" TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "index_guests_on_room_id" ON "guests" ("room_id");
@@ -0,0 +1,15 @@
+CREATE TABLE "user_reports" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "reporter_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "reported_user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "room_id" INTEGER REFERENCES rooms (id) ON DELETE SET NULL,
+    "channel_message_ids" TEXT NOT NULL,
+    "room_message_ids" TEXT NOT NULL,
+    "reason" TEXT NOT NULL,
+    "status" VARCHAR NOT NULL,
+    "created_at" TIMESTAMP NOT NULL,
+    "reviewed_at" TIMESTAMP
+);
+
+CREATE INDEX "index_user_reports_on_reported_user_id_and_created_at" ON "user_reports" ("reported_user_id", "created_at");
+CREATE INDEX "index_user_reports_on_status" ON "user_reports" ("status");
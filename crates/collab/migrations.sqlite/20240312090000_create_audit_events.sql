@@ -0,0 +1,15 @@
+CREATE TABLE "audit_events" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "kind" VARCHAR NOT NULL,
+    "user_id" INTEGER NOT NULL,
+    "subject_user_id" INTEGER,
+    "room_id" INTEGER,
+    "channel_id" INTEGER,
+    "project_id" INTEGER,
+    "detail" VARCHAR,
+    "created_at" TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX "index_audit_events_on_user_id" ON "audit_events" ("user_id");
+CREATE INDEX "index_audit_events_on_subject_user_id" ON "audit_events" ("subject_user_id");
+CREATE INDEX "index_audit_events_on_room_id" ON "audit_events" ("room_id");
@@ -0,0 +1 @@
+ALTER TABLE "servers" ADD COLUMN "rejoin_deadline" TIMESTAMP WITHOUT TIME ZONE;
@@ -0,0 +1 @@
+ALTER TABLE "rooms" ADD COLUMN "ends_at" TIMESTAMP WITHOUT TIME ZONE;
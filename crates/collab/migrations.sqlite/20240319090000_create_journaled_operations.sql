@@ -0,0 +1,8 @@
+CREATE TABLE "journaled_operations" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "project_id" INTEGER NOT NULL REFERENCES projects (id) ON DELETE CASCADE,
+    "buffer_id" INTEGER NOT NULL,
+    "operation" BLOB NOT NULL
+);
+
+CREATE INDEX "index_journaled_operations_on_project_id" ON "journaled_operations" ("project_id");
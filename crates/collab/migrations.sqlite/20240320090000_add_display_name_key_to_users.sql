@@ -0,0 +1,3 @@
+ALTER TABLE "users" ADD COLUMN "display_name_key" VARCHAR;
+DROP INDEX "index_users_on_lower_display_name";
+CREATE UNIQUE INDEX "index_users_on_display_name_key" ON "users" ("display_name_key");
@@ -0,0 +1 @@
+ALTER TABLE "user_reports" ADD COLUMN "counts_toward_throttle" BOOLEAN NOT NULL DEFAULT FALSE;
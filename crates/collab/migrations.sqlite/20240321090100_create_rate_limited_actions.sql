@@ -0,0 +1,9 @@
+CREATE TABLE "rate_limited_actions" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "kind" VARCHAR NOT NULL,
+    "target_user_id" INTEGER REFERENCES users (id) ON DELETE CASCADE,
+    "created_at" TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "index_rate_limited_actions_on_user_id_and_kind" ON "rate_limited_actions" ("user_id", "kind");
@@ -0,0 +1,9 @@
+CREATE TABLE "user_connections" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "connection_server_id" INTEGER NOT NULL REFERENCES servers (id) ON DELETE CASCADE,
+    "connection_id" INTEGER NOT NULL
+);
+
+CREATE INDEX "index_user_connections_on_user_id" ON "user_connections" ("user_id");
+CREATE UNIQUE INDEX "index_user_connections_on_connection_server_id_and_connection_id" ON "user_connections" ("connection_server_id", "connection_id");
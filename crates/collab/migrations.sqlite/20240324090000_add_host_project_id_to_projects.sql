@@ -0,0 +1 @@
+ALTER TABLE "projects" ADD COLUMN "host_project_id" INTEGER NOT NULL DEFAULT 0;
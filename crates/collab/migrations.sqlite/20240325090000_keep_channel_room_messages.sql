@@ -0,0 +1,28 @@
+CREATE TABLE "room_messages_new" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "room_id" INTEGER REFERENCES rooms (id) ON DELETE CASCADE,
+    "channel_id" INTEGER REFERENCES channels (id) ON DELETE CASCADE,
+    "sender_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "body" TEXT NOT NULL,
+    "sent_at" TIMESTAMP NOT NULL,
+    "nonce" BLOB NOT NULL
+);
+
+INSERT INTO "room_messages_new" ("id", "room_id", "channel_id", "sender_id", "body", "sent_at", "nonce")
+SELECT
+    "room_messages"."id",
+    CASE WHEN "rooms"."channel_id" IS NULL THEN "room_messages"."room_id" END,
+    "rooms"."channel_id",
+    "room_messages"."sender_id",
+    "room_messages"."body",
+    "room_messages"."sent_at",
+    "room_messages"."nonce"
+FROM "room_messages"
+JOIN "rooms" ON "rooms"."id" = "room_messages"."room_id";
+
+DROP TABLE "room_messages";
+ALTER TABLE "room_messages_new" RENAME TO "room_messages";
+
+CREATE INDEX "index_room_messages_on_room_id" ON "room_messages" ("room_id");
+CREATE INDEX "index_room_messages_on_channel_id" ON "room_messages" ("channel_id");
+CREATE UNIQUE INDEX "index_room_messages_on_sender_id_nonce" ON "room_messages" ("sender_id", "nonce");
@@ -0,0 +1,2 @@
+-- Rooms' ICE servers are now stored encrypted, so drop the ones stored in plaintext.
+UPDATE "rooms" SET "ice_servers" = NULL;
@@ -0,0 +1,7 @@
+CREATE TABLE contact_notes (
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    contact_user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    nickname VARCHAR,
+    note TEXT,
+    PRIMARY KEY (user_id, contact_user_id)
+);
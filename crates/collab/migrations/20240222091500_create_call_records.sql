@@ -0,0 +1,13 @@
+CREATE TABLE "call_records" (
+    "id" SERIAL PRIMARY KEY,
+    "room_id" INTEGER NOT NULL,
+    "caller_user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "callee_user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "created_at" TIMESTAMP NOT NULL DEFAULT now(),
+    "ended_at" TIMESTAMP,
+    "status" VARCHAR NOT NULL
+);
+
+CREATE INDEX "index_call_records_on_caller_user_id" ON "call_records" ("caller_user_id");
+CREATE INDEX "index_call_records_on_callee_user_id" ON "call_records" ("callee_user_id");
+CREATE INDEX "index_call_records_on_room_id_and_callee_user_id" ON "call_records" ("room_id", "callee_user_id");
@@ -0,0 +1 @@
+ALTER TABLE "room_participants" ADD COLUMN "capabilities" VARCHAR;
@@ -0,0 +1,7 @@
+CREATE TABLE location_shares (
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    contact_user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    PRIMARY KEY (user_id, contact_user_id)
+);
+
+CREATE INDEX "index_location_shares_on_contact_user_id" ON "location_shares" ("contact_user_id");
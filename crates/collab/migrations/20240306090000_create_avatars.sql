@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS "avatars" (
+    "digest" VARCHAR PRIMARY KEY,
+    "content_type" VARCHAR NOT NULL,
+    "data" BYTEA NOT NULL,
+    "created_at" TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now()
+);
+
+ALTER TABLE users ADD COLUMN avatar_digest VARCHAR REFERENCES avatars (digest);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS "routed_messages" (
+    "id" SERIAL PRIMARY KEY,
+    "server_id" INTEGER NOT NULL REFERENCES servers (id) ON DELETE CASCADE,
+    "connection_id" INTEGER NOT NULL,
+    "envelope" BYTEA NOT NULL
+);
+
+CREATE INDEX "index_routed_messages_on_server_id" ON "routed_messages" ("server_id");
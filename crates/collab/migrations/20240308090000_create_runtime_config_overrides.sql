@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS "runtime_config_overrides" (
+    "environment" VARCHAR NOT NULL,
+    "name" VARCHAR NOT NULL,
+    "value" VARCHAR NOT NULL,
+    PRIMARY KEY ("environment", "name")
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS "resume_tokens" (
+    "id" SERIAL PRIMARY KEY,
+    "user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "connection_server_id" INTEGER NOT NULL REFERENCES servers (id) ON DELETE CASCADE,
+    "connection_id" INTEGER NOT NULL,
+    "token_hash" VARCHAR NOT NULL
+);
+
+CREATE UNIQUE INDEX "index_resume_tokens_on_token_hash" ON "resume_tokens" ("token_hash");
+CREATE UNIQUE INDEX "index_resume_tokens_on_connection_server_id_and_connection_id" ON "resume_tokens" ("connection_server_id", "connection_id");
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS "audit_events" (
+    "id" SERIAL PRIMARY KEY,
+    "kind" VARCHAR NOT NULL,
+    "user_id" INTEGER NOT NULL,
+    "subject_user_id" INTEGER,
+    "room_id" INTEGER,
+    "channel_id" INTEGER,
+    "project_id" INTEGER,
+    "detail" VARCHAR,
+    "created_at" TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX "index_audit_events_on_user_id" ON "audit_events" ("user_id");
+CREATE INDEX "index_audit_events_on_subject_user_id" ON "audit_events" ("subject_user_id");
+CREATE INDEX "index_audit_events_on_room_id" ON "audit_events" ("room_id");
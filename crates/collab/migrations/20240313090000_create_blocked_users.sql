@@ -0,0 +1,7 @@
+CREATE TABLE "blocked_users" (
+    "user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "blocked_user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    PRIMARY KEY ("user_id", "blocked_user_id")
+);
+
+CREATE INDEX "index_blocked_users_on_blocked_user_id" ON "blocked_users" ("blocked_user_id");
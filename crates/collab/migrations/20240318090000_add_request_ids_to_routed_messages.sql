@@ -0,0 +1,2 @@
+ALTER TABLE "routed_messages" ADD COLUMN "request_id" INTEGER;
+ALTER TABLE "routed_messages" ADD COLUMN "requester_server_id" INTEGER;
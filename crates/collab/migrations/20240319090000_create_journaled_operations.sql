@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS "journaled_operations" (
+    "id" SERIAL PRIMARY KEY,
+    "project_id" INTEGER NOT NULL REFERENCES projects (id) ON DELETE CASCADE,
+    "buffer_id" BIGINT NOT NULL,
+    "operation" BYTEA NOT NULL
+);
+
+CREATE INDEX "index_journaled_operations_on_project_id" ON "journaled_operations" ("project_id");
@@ -0,0 +1,4 @@
+ALTER TABLE "users" ADD COLUMN "display_name_key" VARCHAR;
+UPDATE "users" SET "display_name_key" = LOWER(NORMALIZE("display_name", NFC)) WHERE "display_name" IS NOT NULL;
+DROP INDEX "index_users_on_lower_display_name";
+CREATE UNIQUE INDEX "index_users_on_display_name_key" ON "users" ("display_name_key");
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS "rate_limited_actions" (
+    "id" SERIAL PRIMARY KEY,
+    "user_id" INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "kind" VARCHAR NOT NULL,
+    "target_user_id" INTEGER REFERENCES users (id) ON DELETE CASCADE,
+    "created_at" TIMESTAMP WITHOUT TIME ZONE NOT NULL
+);
+
+CREATE INDEX "index_rate_limited_actions_on_user_id_and_kind" ON "rate_limited_actions" ("user_id", "kind");
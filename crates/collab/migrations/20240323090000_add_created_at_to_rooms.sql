@@ -0,0 +1 @@
+ALTER TABLE "rooms" ADD COLUMN "created_at" TIMESTAMP WITHOUT TIME ZONE;
@@ -0,0 +1 @@
+ALTER TABLE "projects" ADD COLUMN "host_project_id" INT8 NOT NULL DEFAULT 0;
@@ -0,0 +1,9 @@
+ALTER TABLE "room_messages" ALTER COLUMN "room_id" DROP NOT NULL;
+ALTER TABLE "room_messages" ADD COLUMN "channel_id" INTEGER REFERENCES channels (id) ON DELETE CASCADE;
+
+UPDATE "room_messages"
+SET "channel_id" = "rooms"."channel_id", "room_id" = NULL
+FROM "rooms"
+WHERE "rooms"."id" = "room_messages"."room_id" AND "rooms"."channel_id" IS NOT NULL;
+
+CREATE INDEX "index_room_messages_on_channel_id" ON "room_messages" ("channel_id");
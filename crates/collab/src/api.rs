@@ -1,8 +1,16 @@
 use crate::{
     auth,
-    db::{ContributorSelector, User, UserId},
-    rpc, AppState, Error, Result,
+    db::{
+        audit_event, AuditEventFilter, AuditEventId, AuditEventKind, ChannelId,
+        ContributorSelector, ProjectId, ReportStatus, RoomId, RoomListing, User, UserId,
+        UserReport, UserReportId,
+    },
+    ice::IceConfig,
+    rpc,
+    runtime_config::RuntimeConfig,
+    AppState, Error, Result,
 };
+use ::rpc::ConnectionId;
 use anyhow::anyhow;
 use axum::{
     body::Body,
@@ -10,11 +18,12 @@ use axum::{
     http::{self, Request, StatusCode},
     middleware::{self, Next},
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post, put},
     Extension, Json, Router,
 };
 use axum_extra::response::ErasedJson;
 use chrono::SecondsFormat;
+use collections::BTreeMap;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tower::ServiceBuilder;
@@ -26,8 +35,26 @@ pub fn routes(rpc_server: Arc<rpc::Server>, state: Arc<AppState>) -> Router<Body
         .route("/users/:id/access_tokens", post(create_access_token))
         .route("/panic", post(trace_panic))
         .route("/rpc_server_snapshot", get(get_rpc_server_snapshot))
+        .route("/rpc_server_connections", get(get_rpc_server_connections))
         .route("/contributors", get(get_contributors).post(add_contributor))
         .route("/contributor", get(check_is_contributor))
+        .route("/rooms/:id/ice_servers", put(set_room_ice_servers))
+        .route("/reports", get(get_user_reports))
+        .route("/reports/:id", put(review_user_report))
+        .route("/audit_events", get(get_audit_events))
+        .route(
+            "/runtime_config",
+            get(get_runtime_config).patch(update_runtime_config),
+        )
+        .route("/admin/drain", post(drain_rpc_server))
+        .route("/admin/rooms", get(list_rooms))
+        .route("/admin/rooms/:id", delete(terminate_room))
+        .route(
+            "/admin/connections/:owner_id/:id",
+            delete(disconnect_connection),
+        )
+        .route("/admin/users/:id/ban", post(ban_user).delete(unban_user))
+        .route("/admin/users/:id/invite_count", put(set_invite_count))
         .layer(
             ServiceBuilder::new()
                 .layer(Extension(state))
@@ -84,18 +111,32 @@ async fn get_authenticated_user(
     Query(params): Query<AuthenticatedUserParams>,
     Extension(app): Extension<Arc<AppState>>,
 ) -> Result<Json<AuthenticatedUserResponse>> {
-    let user = app
-        .db
-        .get_or_create_user_by_github_account(
-            &params.github_login,
-            params.github_user_id,
-            params.github_email.as_deref(),
-        )
-        .await?;
+    let user = authenticated_user(&app, &params).await?;
     let metrics_id = app.db.get_user_metrics_id(user.id).await?;
     return Ok(Json(AuthenticatedUserResponse { user, metrics_id }));
 }
 
+/// The user who signed in with the given GitHub account, who is created if they're
+/// new. Self-hosted servers only look users up, as their users are added with
+/// `collab self-host add-user`, and the endpoint mustn't create accounts for
+/// arbitrary logins.
+async fn authenticated_user(app: &AppState, params: &AuthenticatedUserParams) -> Result<User> {
+    if app.config.is_self_hosted() {
+        app.db
+            .get_user_by_github_login(&params.github_login)
+            .await?
+            .ok_or_else(|| Error::Http(StatusCode::NOT_FOUND, "user not found".to_string()))
+    } else {
+        app.db
+            .get_or_create_user_by_github_account(
+                &params.github_login,
+                params.github_user_id,
+                params.github_email.as_deref(),
+            )
+            .await
+    }
+}
+
 #[derive(Deserialize, Debug)]
 struct CreateUserParams {
     github_user_id: i32,
@@ -135,6 +176,22 @@ async fn get_rpc_server_snapshot(
     Ok(ErasedJson::pretty(rpc_server.snapshot().await))
 }
 
+#[derive(Debug, Deserialize)]
+struct ConnectionsParams {
+    user_id: Option<UserId>,
+}
+
+async fn get_rpc_server_connections(
+    Query(params): Query<ConnectionsParams>,
+    Extension(rpc_server): Extension<Arc<rpc::Server>>,
+) -> Result<Json<Vec<rpc::ConnectionStats>>> {
+    let mut connections = rpc_server.connection_stats().await?;
+    if let Some(user_id) = params.user_id {
+        connections.retain(|connection| connection.user_id == user_id);
+    }
+    Ok(Json(connections))
+}
+
 async fn get_contributors(Extension(app): Extension<Arc<AppState>>) -> Result<Json<Vec<String>>> {
     Ok(Json(app.db.get_contributors().await?))
 }
@@ -194,6 +251,224 @@ async fn add_contributor(
         .await?)
 }
 
+/// Overrides the ICE servers of a room for the participants who join it from
+/// now on. A body of `null` reverts the room to the deployment's servers.
+async fn set_room_ice_servers(
+    Path(room_id): Path<RoomId>,
+    Json(ice_config): Json<Option<IceConfig>>,
+    Extension(app): Extension<Arc<AppState>>,
+) -> Result<()> {
+    let ice_servers = ice_config
+        .map(|ice_config| {
+            let key = app.ice_servers_key.as_ref().ok_or_else(|| {
+                Error::Http(
+                    StatusCode::BAD_REQUEST,
+                    "rooms' ICE servers can't be overridden without ICE_SERVERS_KEY".to_string(),
+                )
+            })?;
+            key.seal(&ice_config)
+        })
+        .transpose()?;
+    Ok(app
+        .db
+        .set_room_ice_servers(room_id, ice_servers.as_deref())
+        .await?)
+}
+
+#[derive(Deserialize)]
+struct GetUserReportsParams {
+    status: Option<ReportStatus>,
+}
+
+/// Lists the reports users have made about each other, defaulting to the ones that
+/// haven't been reviewed yet.
+async fn get_user_reports(
+    Query(params): Query<GetUserReportsParams>,
+    Extension(app): Extension<Arc<AppState>>,
+) -> Result<Json<Vec<UserReport>>> {
+    let status = params.status.unwrap_or(ReportStatus::Open);
+    Ok(Json(app.db.get_user_reports(status).await?))
+}
+
+#[derive(Deserialize)]
+struct ReviewUserReportParams {
+    status: ReportStatus,
+}
+
+/// Upholds or dismisses a report. Dismissed reports no longer count towards
+/// throttling the user they're about.
+async fn review_user_report(
+    Path(report_id): Path<UserReportId>,
+    Json(params): Json<ReviewUserReportParams>,
+    Extension(app): Extension<Arc<AppState>>,
+) -> Result<Json<UserReport>> {
+    if params.status == ReportStatus::Open {
+        return Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "reports can only be upheld or dismissed".to_string(),
+        ));
+    }
+    Ok(Json(
+        app.db.review_user_report(report_id, params.status).await?,
+    ))
+}
+
+const AUDIT_EVENT_COUNT_PER_PAGE: usize = 100;
+
+#[derive(Deserialize)]
+struct GetAuditEventsParams {
+    user_id: Option<UserId>,
+    room_id: Option<RoomId>,
+    channel_id: Option<ChannelId>,
+    project_id: Option<ProjectId>,
+    kind: Option<AuditEventKind>,
+    before_id: Option<AuditEventId>,
+}
+
+/// Lists the audit log's events that match the given parameters, most recent last.
+/// Earlier pages are fetched by passing the first event's ID as `before_id`.
+async fn get_audit_events(
+    Query(params): Query<GetAuditEventsParams>,
+    Extension(app): Extension<Arc<AppState>>,
+) -> Result<Json<Vec<audit_event::Model>>> {
+    let filter = AuditEventFilter {
+        user_id: params.user_id,
+        room_id: params.room_id,
+        channel_id: params.channel_id,
+        project_id: params.project_id,
+        kind: params.kind,
+    };
+    Ok(Json(
+        app.db
+            .get_audit_events(&filter, AUDIT_EVENT_COUNT_PER_PAGE, params.before_id)
+            .await?,
+    ))
+}
+
+async fn get_runtime_config(
+    Extension(app): Extension<Arc<AppState>>,
+) -> Json<BTreeMap<&'static str, String>> {
+    Json(app.runtime_config.get().values())
+}
+
+/// Overrides runtime config values for every server in the environment, keyed by the
+/// environment variables that set them. Values of `null` remove the override.
+async fn update_runtime_config(
+    Json(changes): Json<BTreeMap<String, Option<String>>>,
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(rpc_server): Extension<Arc<rpc::Server>>,
+) -> Result<Json<BTreeMap<&'static str, String>>> {
+    let environment = &app.config.zed_environment;
+    let changes = changes
+        .into_iter()
+        .map(|(name, value)| (name.to_uppercase(), value))
+        .collect::<Vec<_>>();
+
+    // The overrides are only stored if they're all valid.
+    app.db
+        .update_runtime_config_overrides(environment, &changes, |overrides| {
+            RuntimeConfig::new(&app.config, overrides)
+                .map_err(|error| Error::Http(StatusCode::BAD_REQUEST, format!("{error:?}")))?;
+            Ok(())
+        })
+        .await?;
+    rpc_server.reload_runtime_config().await?;
+    Ok(Json(app.runtime_config.get().values()))
+}
+
+#[derive(Deserialize)]
+struct DrainParams {
+    reconnect_url: Option<String>,
+}
+
+/// Starts draining this server ahead of a restart. The server exits once its clients
+/// have reconnected elsewhere, and refuses new connections in the meantime. Clients
+/// are sent to `reconnect_url` if one is given, as long as its host is one of the
+/// configured `RECONNECT_HOSTS`.
+async fn drain_rpc_server(
+    Query(params): Query<DrainParams>,
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(rpc_server): Extension<Arc<rpc::Server>>,
+) -> Result<StatusCode> {
+    if let Some(reconnect_url) = params.reconnect_url.as_deref() {
+        if !app.config.is_allowed_reconnect_url(reconnect_url) {
+            return Err(Error::Http(
+                StatusCode::BAD_REQUEST,
+                format!("clients can't be sent to {reconnect_url}"),
+            ));
+        }
+    }
+    tokio::spawn(async move { rpc_server.drain(params.reconnect_url).await });
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn list_rooms(Extension(app): Extension<Arc<AppState>>) -> Result<Json<Vec<RoomListing>>> {
+    Ok(Json(app.db.list_rooms().await?))
+}
+
+/// Ends the room for everyone in it, and cancels the calls into it.
+async fn terminate_room(
+    Path(room_id): Path<RoomId>,
+    Extension(rpc_server): Extension<Arc<rpc::Server>>,
+) -> Result<()> {
+    if rpc_server.terminate_room(room_id).await? {
+        Ok(())
+    } else {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            format!("room {room_id} does not exist"),
+        ))
+    }
+}
+
+/// Closes a connection to this server, which the client will try to reconnect. The
+/// connection's ID is as listed by `/rpc_server_connections`.
+async fn disconnect_connection(
+    Path((owner_id, id)): Path<(u32, u32)>,
+    Extension(rpc_server): Extension<Arc<rpc::Server>>,
+) -> Result<()> {
+    rpc_server
+        .disconnect(ConnectionId { owner_id, id })
+        .map_err(|error| Error::Http(StatusCode::NOT_FOUND, error.to_string()))
+}
+
+/// Stops the user from signing in, and closes their connections to this server.
+async fn ban_user(
+    Path(user_id): Path<UserId>,
+    Extension(rpc_server): Extension<Arc<rpc::Server>>,
+) -> Result<()> {
+    rpc_server.ban_user(user_id).await
+}
+
+async fn unban_user(
+    Path(user_id): Path<UserId>,
+    Extension(app): Extension<Arc<AppState>>,
+) -> Result<()> {
+    app.db.set_user_banned(user_id, false).await
+}
+
+#[derive(Deserialize)]
+struct SetInviteCountParams {
+    count: i32,
+}
+
+/// Sets how many more people the user can invite, and tells their clients.
+async fn set_invite_count(
+    Path(user_id): Path<UserId>,
+    Json(params): Json<SetInviteCountParams>,
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(rpc_server): Extension<Arc<rpc::Server>>,
+) -> Result<()> {
+    if params.count < 0 {
+        return Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "invite count can't be negative".to_string(),
+        ));
+    }
+    app.db.set_invite_count(user_id, params.count).await?;
+    rpc_server.invite_count_updated(user_id).await
+}
+
 #[derive(Deserialize)]
 struct CreateAccessTokenQueryParams {
     public_key: String,
@@ -216,6 +491,12 @@ async fn create_access_token(
         .get_user_by_id(user_id)
         .await?
         .ok_or_else(|| anyhow!("user not found"))?;
+    if user.banned_at.is_some() {
+        return Err(Error::Http(
+            StatusCode::FORBIDDEN,
+            "user is banned".to_string(),
+        ));
+    }
 
     let mut impersonated_user_id = None;
     if let Some(impersonate) = params.impersonate {
@@ -246,3 +527,56 @@ async fn create_access_token(
         encrypted_access_token,
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::TestServer, SELF_HOSTED_ENVIRONMENT};
+    use gpui::BackgroundExecutor;
+
+    fn params(github_login: &str, github_user_id: i32) -> AuthenticatedUserParams {
+        AuthenticatedUserParams {
+            github_user_id: Some(github_user_id),
+            github_login: github_login.into(),
+            github_email: Some(format!("{github_login}@example.com")),
+        }
+    }
+
+    #[gpui::test]
+    async fn test_authenticated_user(executor: BackgroundExecutor) {
+        // Users signing in with GitHub are created the first time, and keep their
+        // account when they rename their GitHub login.
+        let server = TestServer::start(executor).await;
+        let user = authenticated_user(&server.app_state, &params("user_a", 100))
+            .await
+            .unwrap();
+        assert_eq!(user.github_login, "user_a");
+        let renamed_user = authenticated_user(&server.app_state, &params("user_a2", 100))
+            .await
+            .unwrap();
+        assert_eq!(renamed_user.id, user.id);
+        assert_eq!(renamed_user.github_login, "user_a2");
+    }
+
+    #[gpui::test]
+    async fn test_authenticated_user_when_self_hosted(executor: BackgroundExecutor) {
+        // Self-hosted servers only know the users that were added to them.
+        let server = TestServer::start_with_config(executor, |config| {
+            config.zed_environment = SELF_HOSTED_ENVIRONMENT.into();
+        })
+        .await;
+        let result = authenticated_user(&server.app_state, &params("user_b", 200)).await;
+        assert!(matches!(result, Err(Error::Http(StatusCode::NOT_FOUND, _))));
+
+        let user = server
+            .app_state
+            .db
+            .get_or_create_user_by_github_account("user_b", Some(200), None)
+            .await
+            .unwrap();
+        let authenticated = authenticated_user(&server.app_state, &params("user_b", 200))
+            .await
+            .unwrap();
+        assert_eq!(authenticated.id, user.id);
+    }
+}
@@ -1,9 +1,10 @@
 use crate::{
     db::{self, AccessTokenId, Database, UserId},
-    AppState, Error, Result,
+    AppState, Config, Error, Result,
 };
 use anyhow::{anyhow, Context};
 use axum::{
+    extract::ConnectInfo,
     http::{self, Request, StatusCode},
     middleware::Next,
     response::IntoResponse,
@@ -16,7 +17,8 @@ use scrypt::{
     Scrypt,
 };
 use serde::{Deserialize, Serialize};
-use std::{sync::Arc, time::Instant};
+use std::{net::SocketAddr, sync::Arc, time::Instant};
+use time::OffsetDateTime;
 
 lazy_static! {
     static ref METRIC_ACCESS_TOKEN_HASHING_TIME: Histogram = register_histogram!(
@@ -61,6 +63,15 @@ pub async fn validate_header<B>(mut req: Request<B>, next: Next<B>) -> impl Into
 
     let state = req.extensions().get::<Arc<AppState>>().unwrap();
 
+    // Self-hosted servers are usually reached directly rather than through our
+    // load balancer, so make sure access tokens aren't sent over plain http.
+    if state.config.is_self_hosted() && !is_secure_request(&req, &state.config) {
+        return Err(Error::Http(
+            StatusCode::FORBIDDEN,
+            "signing in requires https".to_string(),
+        ));
+    }
+
     // In development, allow impersonation using the admin API token.
     // Don't allow this in production because we can't tell who is doing
     // the impersonating.
@@ -83,6 +94,22 @@ pub async fn validate_header<B>(mut req: Request<B>, next: Next<B>) -> impl Into
                 .get_user_by_id(user_id)
                 .await?
                 .ok_or_else(|| anyhow!("user {} not found", user_id))?;
+            if user.banned_at.is_some() {
+                return Err(Error::Http(
+                    StatusCode::FORBIDDEN,
+                    "user is banned".to_string(),
+                ));
+            }
+
+            // Guests' access tokens stop working once their invitation expires.
+            if let Some(guest) = state.db.get_guest(user_id).await? {
+                if guest.is_expired(OffsetDateTime::now_utc()) {
+                    return Err(Error::Http(
+                        StatusCode::UNAUTHORIZED,
+                        "guest invitation has expired".to_string(),
+                    ));
+                }
+            }
 
             let impersonator = if let Some(impersonator_id) = validate_result.impersonator_id {
                 let impersonator = state
@@ -106,6 +133,25 @@ pub async fn validate_header<B>(mut req: Request<B>, next: Next<B>) -> impl Into
     ))
 }
 
+/// Whether the request reached the proxy in front of this server over TLS, or came
+/// from this machine. Only trusted proxies can vouch for how a request reached them,
+/// as anyone else can set `X-Forwarded-Proto` too.
+fn is_secure_request<B>(req: &Request<B>, config: &Config) -> bool {
+    let Some(ConnectInfo(peer_address)) = req.extensions().get::<ConnectInfo<SocketAddr>>() else {
+        return false;
+    };
+    let forwarded_proto = req
+        .headers()
+        .get("x-forwarded-proto")
+        .and_then(|header| header.to_str().ok());
+    match forwarded_proto {
+        Some(forwarded_proto) if config.is_trusted_proxy(peer_address.ip()) => {
+            forwarded_proto.eq_ignore_ascii_case("https")
+        }
+        _ => peer_address.ip().is_loopback(),
+    }
+}
+
 const MAX_ACCESS_TOKENS_TO_STORE: usize = 8;
 
 #[derive(Serialize, Deserialize)]
@@ -207,3 +253,66 @@ pub async fn verify_access_token(
         },
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TestServer;
+
+    #[test]
+    fn test_is_secure_request() {
+        let config = Config {
+            trusted_proxies: Some("10.0.0.2, 10.0.0.3".into()),
+            ..TestServer::test_config()
+        };
+        let request = |peer_address: &str, forwarded_proto: Option<&str>| {
+            let mut request = Request::builder();
+            if let Some(forwarded_proto) = forwarded_proto {
+                request = request.header("X-Forwarded-Proto", forwarded_proto);
+            }
+            request
+                .extension(ConnectInfo(peer_address.parse::<SocketAddr>().unwrap()))
+                .body(())
+                .unwrap()
+        };
+
+        // Trusted proxies, including those on this machine, report how requests
+        // reached them.
+        assert!(is_secure_request(
+            &request("10.0.0.2:4000", Some("https")),
+            &config
+        ));
+        assert!(!is_secure_request(
+            &request("10.0.0.3:4000", Some("http")),
+            &config
+        ));
+        assert!(is_secure_request(
+            &request("127.0.0.1:4000", Some("https")),
+            &config
+        ));
+        assert!(!is_secure_request(
+            &request("127.0.0.1:4000", Some("http")),
+            &config
+        ));
+
+        // Requests from this machine are secure without a proxy.
+        assert!(is_secure_request(&request("[::1]:4000", None), &config));
+
+        // Anyone else reaching the server over plain http can't claim otherwise.
+        assert!(!is_secure_request(
+            &request("192.168.1.20:4000", Some("https")),
+            &config
+        ));
+        assert!(!is_secure_request(
+            &request("192.168.1.20:4000", None),
+            &config
+        ));
+
+        // Requests whose peer is unknown are never secure.
+        let request = Request::builder()
+            .header("X-Forwarded-Proto", "https")
+            .body(())
+            .unwrap();
+        assert!(!is_secure_request(&request, &config));
+    }
+}
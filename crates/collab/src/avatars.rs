@@ -0,0 +1,114 @@
+use crate::{AppState, Error, Result};
+use axum::{
+    body::Body,
+    extract::Path,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Extension, Router,
+};
+use sha2::{Digest, Sha256};
+use std::{sync::Arc, time::Duration};
+
+/// The largest avatar image that users can upload.
+pub const MAX_AVATAR_SIZE: usize = 1024 * 1024;
+
+/// How many avatars each user can upload within [`AVATAR_UPLOAD_WINDOW`].
+pub const AVATAR_UPLOAD_LIMIT: usize = 10;
+
+/// The window that [`AVATAR_UPLOAD_LIMIT`] applies to.
+pub const AVATAR_UPLOAD_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Avatars are served under their digest, so a given URL always refers to the
+/// same image and can be cached indefinitely.
+const AVATAR_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+pub fn routes(state: Arc<AppState>) -> Router<Body> {
+    Router::new()
+        .route("/avatars/:digest", get(get_avatar))
+        .layer(Extension(state))
+}
+
+/// Returns the content type of an uploaded avatar, based on the signature at
+/// the start of the image rather than anything the client claims.
+pub fn avatar_content_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+/// The content address that an avatar is stored and served under.
+pub fn avatar_digest(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+pub fn avatar_url(url_prefix: &str, digest: &str) -> String {
+    format!("{}/{}", url_prefix.trim_end_matches('/'), digest)
+}
+
+async fn get_avatar(
+    Path(digest): Path<String>,
+    Extension(app): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse> {
+    let avatar = app
+        .db
+        .get_avatar(&digest)
+        .await?
+        .ok_or_else(|| Error::Http(StatusCode::NOT_FOUND, "avatar not found".to_string()))?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, avatar.content_type),
+            (header::CACHE_CONTROL, AVATAR_CACHE_CONTROL.to_string()),
+            // Browsers must not guess that an image is something they'd execute.
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        avatar.data,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_avatar_content_type() {
+        assert_eq!(
+            avatar_content_type(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Some("image/png")
+        );
+        assert_eq!(
+            avatar_content_type(b"\xff\xd8\xff\xe0\0\x10JFIF"),
+            Some("image/jpeg")
+        );
+        assert_eq!(avatar_content_type(b"GIF89a\x01\0"), Some("image/gif"));
+        assert_eq!(
+            avatar_content_type(b"RIFF\x24\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(avatar_content_type(b"RIFF\x24\0\0\0WAVE"), None);
+        assert_eq!(avatar_content_type(b"<svg></svg>"), None);
+        assert_eq!(avatar_content_type(b""), None);
+    }
+
+    #[test]
+    fn test_avatar_url() {
+        let digest = avatar_digest(b"avatar");
+        assert_eq!(digest.len(), 64);
+        assert_eq!(
+            avatar_url("https://collab.example.com/avatars/", &digest),
+            format!("https://collab.example.com/avatars/{digest}")
+        );
+        assert_eq!(
+            avatar_url("https://collab.example.com/avatars", &digest),
+            format!("https://collab.example.com/avatars/{digest}")
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Prints the operation trace that a randomized collaboration test writes when
+//! `TRACE_OPERATIONS` is set, grouping the operations by the client that applied
+//! them.
+//!
+//! Each operation is listed with the latest operation of every other client
+//! that finished before it started and touched the same project or buffer, along
+//! with the latest server operation that finished before it started. Those are
+//! the operations whose effects it may have observed.
+//!
+//! Usage: collab_trace <trace.jsonl> [--full]
+
+use anyhow::{anyhow, Context as _};
+use serde::Deserialize;
+use std::{collections::BTreeMap, env, fs};
+
+const OPERATION_WIDTH: usize = 120;
+
+#[derive(Deserialize)]
+struct TraceEvent {
+    started_seq: usize,
+    finished_seq: usize,
+    started_at_ms: u64,
+    finished_at_ms: u64,
+    peer: String,
+    client_ix: Option<usize>,
+    batch_id: Option<usize>,
+    #[serde(default)]
+    project_ids: Vec<u64>,
+    #[serde(default)]
+    buffer_ids: Vec<u64>,
+    operation: serde_json::Value,
+    outcome: TraceOutcome,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TraceOutcome {
+    Applied,
+    Skipped,
+    Failed(String),
+}
+
+impl TraceEvent {
+    fn client_name(&self) -> String {
+        match self.client_ix {
+            Some(client_ix) if client_ix > 0 => format!("{}#{}", self.peer, client_ix),
+            _ => self.peer.clone(),
+        }
+    }
+
+    fn is_server(&self) -> bool {
+        self.client_ix.is_none()
+    }
+
+    fn touches_same_entities(&self, other: &Self) -> bool {
+        self.project_ids
+            .iter()
+            .any(|id| other.project_ids.contains(id))
+            || self
+                .buffer_ids
+                .iter()
+                .any(|id| other.buffer_ids.contains(id))
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut path = None;
+    let mut full = false;
+    for arg in env::args().skip(1) {
+        if arg == "--full" {
+            full = true;
+        } else if path.is_none() {
+            path = Some(arg);
+        } else {
+            return Err(anyhow!("usage: collab_trace <trace.jsonl> [--full]"));
+        }
+    }
+    let path = path.ok_or_else(|| anyhow!("usage: collab_trace <trace.jsonl> [--full]"))?;
+
+    let mut events = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read trace {:?}", path))?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(ix, line)| {
+            serde_json::from_str::<TraceEvent>(line)
+                .with_context(|| format!("invalid trace event on line {}", ix + 1))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    events.sort_by_key(|event| event.started_seq);
+
+    let mut events_by_client = BTreeMap::<(bool, String), Vec<usize>>::new();
+    for (ix, event) in events.iter().enumerate() {
+        events_by_client
+            .entry((!event.is_server(), event.client_name()))
+            .or_default()
+            .push(ix);
+    }
+
+    for ((_, client_name), event_ixs) in &events_by_client {
+        println!("{} ({} operations)", client_name, event_ixs.len());
+        for &ix in event_ixs {
+            let event = &events[ix];
+            let outcome = match &event.outcome {
+                TraceOutcome::Applied => "applied".to_string(),
+                TraceOutcome::Skipped => "skipped".to_string(),
+                TraceOutcome::Failed(error) => format!("failed: {}", error),
+            };
+            let batch = event
+                .batch_id
+                .map(|batch_id| format!(" batch {}", batch_id))
+                .unwrap_or_default();
+            println!(
+                "  [{}..{}] {:>6}ms..{:>6}ms{} {}",
+                event.started_seq,
+                event.finished_seq,
+                event.started_at_ms,
+                event.finished_at_ms,
+                batch,
+                outcome
+            );
+
+            let mut operation = event.operation.to_string();
+            if !full && operation.len() > OPERATION_WIDTH {
+                let mut end = OPERATION_WIDTH;
+                while !operation.is_char_boundary(end) {
+                    end -= 1;
+                }
+                operation.truncate(end);
+                operation.push('…');
+            }
+            println!("    {}", operation);
+
+            if !event.project_ids.is_empty() || !event.buffer_ids.is_empty() {
+                println!(
+                    "    projects {:?}, buffers {:?}",
+                    event.project_ids, event.buffer_ids
+                );
+            }
+
+            let predecessors = predecessors(&events, ix);
+            if !predecessors.is_empty() {
+                println!("    after {}", predecessors.join(", "));
+            }
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+/// Returns the latest operation of each other client that finished before the
+/// given operation started and touched the same entities, along with the latest
+/// such server operation.
+fn predecessors(events: &[TraceEvent], ix: usize) -> Vec<String> {
+    let event = &events[ix];
+    let mut latest_by_client = BTreeMap::<String, &TraceEvent>::new();
+    for other in events {
+        if other.finished_seq >= event.started_seq
+            || other.client_name() == event.client_name()
+            || !(other.is_server() || other.touches_same_entities(event))
+        {
+            continue;
+        }
+        latest_by_client
+            .entry(other.client_name())
+            .and_modify(|latest| {
+                if other.finished_seq > latest.finished_seq {
+                    *latest = other;
+                }
+            })
+            .or_insert(other);
+    }
+    latest_by_client
+        .into_iter()
+        .map(|(client_name, other)| format!("{} [{}]", client_name, other.started_seq))
+        .collect()
+}
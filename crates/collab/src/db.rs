@@ -2,7 +2,7 @@
 pub mod tests;
 
 #[cfg(test)]
-pub use tests::TestDb;
+pub use tests::{DatabaseFaults, TestDb};
 
 mod ids;
 mod queries;
@@ -12,7 +12,7 @@ use crate::{executor::Executor, Error, Result};
 use anyhow::anyhow;
 use collections::{BTreeMap, HashMap, HashSet};
 use dashmap::DashMap;
-use futures::StreamExt;
+use futures::{FutureExt, StreamExt};
 use rand::{prelude::StdRng, Rng, SeedableRng};
 use rpc::{
     proto::{self},
@@ -20,7 +20,7 @@ use rpc::{
 };
 use sea_orm::{
     entity::prelude::*,
-    sea_query::{Alias, Expr, OnConflict},
+    sea_query::{Alias, Expr, Func, OnConflict},
     ActiveValue, Condition, ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbErr,
     FromQueryResult, IntoActiveModel, IsolationLevel, JoinType, QueryOrder, QuerySelect, Statement,
     TransactionTrait,
@@ -41,10 +41,12 @@ use std::{
     time::Duration,
 };
 pub use tables::*;
+use time::PrimitiveDateTime;
 use tokio::sync::{Mutex, OwnedMutexGuard};
 
 pub use ids::*;
 pub use queries::contributors::ContributorSelector;
+pub use queries::servers::{routed_messages_channel, NewRoutedMessage};
 pub use sea_orm::ConnectOptions;
 pub use tables::user::Model as User;
 
@@ -60,6 +62,8 @@ pub struct Database {
     notification_kinds_by_name: HashMap<String, NotificationKindId>,
     #[cfg(test)]
     runtime: Option<tokio::runtime::Runtime>,
+    #[cfg(test)]
+    faults: parking_lot::Mutex<(DatabaseFaults, StdRng)>,
 }
 
 // The `Database` type has so many methods that its impl blocks are split into
@@ -78,6 +82,8 @@ impl Database {
             executor,
             #[cfg(test)]
             runtime: None,
+            #[cfg(test)]
+            faults: parking_lot::Mutex::new((DatabaseFaults::default(), StdRng::seed_from_u64(0))),
         })
     }
 
@@ -95,7 +101,24 @@ impl Database {
         let migrations = MigrationSource::resolve(migrations_path)
             .await
             .map_err(|err| anyhow!("failed to load migrations: {err:?}"))?;
+        self.apply_migrations(migrations, ignore_checksum_mismatch)
+            .await
+    }
+
+    /// Runs the SQLite migrations that are compiled into the binary, so that servers
+    /// using SQLite don't need the migrations directory at runtime.
+    #[cfg(feature = "sqlite")]
+    pub async fn migrate_embedded_sqlite(&self) -> anyhow::Result<Vec<(Migration, Duration)>> {
+        let migrator = sqlx::migrate!("./migrations.sqlite");
+        self.apply_migrations(migrator.iter().cloned().collect(), false)
+            .await
+    }
 
+    async fn apply_migrations(
+        &self,
+        migrations: Vec<Migration>,
+        ignore_checksum_mismatch: bool,
+    ) -> anyhow::Result<Vec<(Migration, Duration)>> {
         let mut connection = sqlx::AnyConnection::connect(self.options.get_url()).await?;
 
         connection.ensure_migrations_table().await?;
@@ -211,7 +234,7 @@ impl Database {
                             Ok(()) => {
                                 return Ok(Some(RoomGuard {
                                     data,
-                                    _guard,
+                                    _guards: vec![_guard],
                                     _not_send: PhantomData,
                                 }));
                             }
@@ -244,6 +267,18 @@ impl Database {
         self.run(body).await
     }
 
+    /// The same as room_transaction, but for when the room is only known once the transaction
+    /// has started, e.g. because it's created on demand.
+    async fn deferred_room_transaction<F, Fut, T>(&self, f: F) -> Result<RoomGuard<T>>
+    where
+        F: Send + Fn(TransactionHandle) -> Fut,
+        Fut: Send + Future<Output = Result<(RoomId, T)>>,
+    {
+        self.optional_room_transaction(|tx| f(tx).map(|result| result.map(Some)))
+            .await?
+            .ok_or_else(|| anyhow!("transaction did not identify a room").into())
+    }
+
     /// room_transaction runs the block in a transaction. It returns a RoomGuard, that keeps
     /// the database locked until it is dropped. This ensures that updates sent to clients are
     /// properly serialized with respect to database changes.
@@ -263,7 +298,55 @@ impl Database {
                         Ok(()) => {
                             return Ok(RoomGuard {
                                 data,
-                                _guard,
+                                _guards: vec![_guard],
+                                _not_send: PhantomData,
+                            });
+                        }
+                        Err(error) => {
+                            if !self.retry_on_serialization_error(&error, i).await {
+                                return Err(error);
+                            }
+                        }
+                    },
+                    Err(error) => {
+                        tx.rollback().await?;
+                        if !self.retry_on_serialization_error(&error, i).await {
+                            return Err(error);
+                        }
+                    }
+                }
+                i += 1;
+            }
+        };
+
+        self.run(body).await
+    }
+
+    /// The same as room_transaction, but for changes to several rooms at once. The rooms are
+    /// locked in order of their ids, so that transactions that share rooms can't deadlock.
+    async fn rooms_transaction<F, Fut, T>(&self, room_ids: &[RoomId], f: F) -> Result<RoomGuard<T>>
+    where
+        F: Send + Fn(TransactionHandle) -> Fut,
+        Fut: Send + Future<Output = Result<T>>,
+    {
+        let mut room_ids = room_ids.to_vec();
+        room_ids.sort_unstable();
+        room_ids.dedup();
+        let body = async {
+            let mut i = 0;
+            loop {
+                let mut guards = Vec::with_capacity(room_ids.len());
+                for room_id in &room_ids {
+                    let lock = self.rooms.entry(*room_id).or_default().clone();
+                    guards.push(lock.lock_owned().await);
+                }
+                let (tx, result) = self.with_transaction(&f).await?;
+                match result {
+                    Ok(data) => match tx.commit().await.map_err(Into::into) {
+                        Ok(()) => {
+                            return Ok(RoomGuard {
+                                data,
+                                _guards: guards,
                                 _not_send: PhantomData,
                             });
                         }
@@ -340,6 +423,7 @@ impl Database {
         {
             if let Executor::Deterministic(executor) = &self.executor {
                 executor.simulate_random_delay().await;
+                self.inject_fault(executor).await?;
             }
 
             self.runtime.as_ref().unwrap().block_on(future)
@@ -351,6 +435,12 @@ impl Database {
         }
     }
 
+    /// Checks out a connection and pings the database on it, which takes longer the
+    /// fewer connections the pool has to spare.
+    pub async fn ping(&self) -> Result<()> {
+        self.run(async { Ok(self.pool.ping().await?) }).await
+    }
+
     async fn retry_on_serialization_error(&self, error: &Error, prev_attempt_count: usize) -> bool {
         // If the error is due to a failure to serialize concurrent transactions, then retry
         // this transaction after a delay. With each subsequent retry, double the delay duration.
@@ -407,7 +497,7 @@ impl Deref for TransactionHandle {
 /// so that updates to rooms are serialized.
 pub struct RoomGuard<T> {
     data: T,
-    _guard: OwnedMutexGuard<()>,
+    _guards: Vec<OwnedMutexGuard<()>>,
     _not_send: PhantomData<Rc<()>>,
 }
 
@@ -430,6 +520,15 @@ impl<T> RoomGuard<T> {
     pub fn into_inner(self) -> T {
         self.data
     }
+
+    /// Transforms the inner value, keeping the rooms locked.
+    fn map<U>(self, f: impl FnOnce(T) -> U) -> RoomGuard<U> {
+        RoomGuard {
+            data: f(self.data),
+            _guards: self._guards,
+            _not_send: PhantomData,
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -458,6 +557,76 @@ pub struct CreatedChannelMessage {
     pub notifications: NotificationBatch,
 }
 
+/// The longest display name a user can choose, in characters.
+pub const MAX_DISPLAY_NAME_LENGTH: usize = 32;
+
+pub struct UpdatedUser {
+    pub user: User,
+    pub room_connection_ids: Vec<ConnectionId>,
+}
+
+pub struct CreatedRoomMessage {
+    /// The saved message, which is the original one when it was resent.
+    pub message: proto::RoomMessage,
+    /// The connections of the room's other participants, which is empty when the
+    /// message was resent, as they've already been sent it.
+    pub participant_connection_ids: Vec<ConnectionId>,
+}
+
+/// An event to add to the audit log. Fields that don't apply to its kind are left
+/// as `None`.
+#[derive(Clone, Debug)]
+pub struct NewAuditEvent {
+    pub kind: AuditEventKind,
+    pub user_id: UserId,
+    pub subject_user_id: Option<UserId>,
+    pub room_id: Option<RoomId>,
+    pub channel_id: Option<ChannelId>,
+    pub project_id: Option<ProjectId>,
+    pub detail: Option<String>,
+}
+
+impl NewAuditEvent {
+    pub fn new(kind: AuditEventKind, user_id: UserId) -> Self {
+        Self {
+            kind,
+            user_id,
+            subject_user_id: None,
+            room_id: None,
+            channel_id: None,
+            project_id: None,
+            detail: None,
+        }
+    }
+}
+
+/// Which events to return from the audit log. Events have to match every field
+/// that is set.
+#[derive(Clone, Debug, Default)]
+pub struct AuditEventFilter {
+    /// Matches events the user did, or that were done to them.
+    pub user_id: Option<UserId>,
+    pub room_id: Option<RoomId>,
+    pub channel_id: Option<ChannelId>,
+    pub project_id: Option<ProjectId>,
+    pub kind: Option<AuditEventKind>,
+}
+
+/// A report of a user's conduct, as admins review it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct UserReport {
+    pub id: UserReportId,
+    pub reporter_id: UserId,
+    pub reported_user_id: UserId,
+    pub room_id: Option<RoomId>,
+    pub channel_message_ids: Vec<MessageId>,
+    pub room_message_ids: Vec<RoomMessageId>,
+    pub reason: String,
+    pub status: ReportStatus,
+    pub created_at: PrimitiveDateTime,
+    pub reviewed_at: Option<PrimitiveDateTime>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, FromQueryResult, Serialize, Deserialize)]
 pub struct Invite {
     pub email_address: String,
@@ -605,6 +774,12 @@ pub struct JoinRoom {
     pub room: proto::Room,
     pub channel_id: Option<ChannelId>,
     pub channel_members: Vec<UserId>,
+    /// The room's override of the deployment's ICE servers, encrypted with
+    /// [`IceServersKey`](crate::ice::IceServersKey).
+    pub ice_servers: Option<String>,
+    /// Whether joining created the room, which channel rooms are the first time
+    /// someone joins them.
+    pub room_created: bool,
 }
 
 pub struct RejoinedRoom {
@@ -613,6 +788,7 @@ pub struct RejoinedRoom {
     pub reshared_projects: Vec<ResharedProject>,
     pub channel_id: Option<ChannelId>,
     pub channel_members: Vec<UserId>,
+    pub observer_visibility: ObserverVisibility,
 }
 
 pub struct ResharedProject {
@@ -653,6 +829,9 @@ pub struct LeftRoom {
     pub left_projects: HashMap<ProjectId, LeftProject>,
     pub canceled_calls_to_user_ids: Vec<UserId>,
     pub deleted: bool,
+    /// Notifies the participants who asked for it of the call's summary, when
+    /// the room was left empty.
+    pub notifications: NotificationBatch,
 }
 
 pub struct RefreshedRoom {
@@ -661,6 +840,49 @@ pub struct RefreshedRoom {
     pub channel_members: Vec<UserId>,
     pub stale_participant_user_ids: Vec<UserId>,
     pub canceled_calls_to_user_ids: Vec<UserId>,
+    pub notifications: NotificationBatch,
+}
+
+/// The longest a time-boxed room can last from when it was created, however many
+/// times it's extended.
+pub const MAX_TIME_BOXED_ROOM_LIFETIME: time::Duration = time::Duration::hours(48);
+
+/// A room that ends at a fixed time.
+pub struct TimeBoxedRoom {
+    pub room: proto::Room,
+    pub ends_at: time::OffsetDateTime,
+}
+
+/// A room that everyone was removed from, because its time was up or an admin
+/// terminated it.
+#[derive(Default)]
+pub struct EndedRoom {
+    /// The connections of the participants who were still in the room.
+    pub connection_ids: Vec<ConnectionId>,
+    pub participant_user_ids: Vec<UserId>,
+    pub canceled_calls_to_user_ids: Vec<UserId>,
+    pub channel_id: Option<ChannelId>,
+    pub channel_members: Vec<UserId>,
+    pub live_kit_room: String,
+    pub notifications: NotificationBatch,
+}
+
+/// A room as listed for administrators.
+#[derive(Debug, Serialize)]
+pub struct RoomListing {
+    pub id: RoomId,
+    pub channel_id: Option<ChannelId>,
+    pub participant_user_ids: Vec<UserId>,
+    /// The users who are being called into the room.
+    pub pending_user_ids: Vec<UserId>,
+    pub ends_at: Option<PrimitiveDateTime>,
+}
+
+/// The number of records removed by [`Database::delete_orphaned_rooms_and_projects`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SweptOrphans {
+    pub projects: usize,
+    pub rooms: usize,
 }
 
 pub struct RefreshedChannelBuffer {
@@ -672,6 +894,25 @@ pub struct Project {
     pub collaborators: Vec<ProjectCollaborator>,
     pub worktrees: BTreeMap<u64, Worktree>,
     pub language_servers: Vec<proto::LanguageServer>,
+    /// Whether the host has made the project read-only for the collaborator it
+    /// was loaded for. Their role in the room can also prevent them from editing.
+    pub read_only: bool,
+    pub observer_visibility: ObserverVisibility,
+}
+
+/// Which of a room's connections belong to observers, and which can see them.
+#[derive(Debug, Default)]
+pub struct ObserverVisibility {
+    pub observers: HashSet<ConnectionId>,
+    pub viewers: HashSet<ConnectionId>,
+}
+
+impl ObserverVisibility {
+    /// Whether the participant on `viewer` is allowed to know about the one on
+    /// `connection_id`.
+    pub fn can_see(&self, viewer: ConnectionId, connection_id: ConnectionId) -> bool {
+        !self.observers.contains(&connection_id) || self.viewers.contains(&viewer)
+    }
 }
 
 pub struct ProjectCollaborator {
@@ -679,6 +920,7 @@ pub struct ProjectCollaborator {
     pub user_id: UserId,
     pub replica_id: ReplicaId,
     pub is_host: bool,
+    pub read_only: bool,
 }
 
 impl ProjectCollaborator {
@@ -687,6 +929,7 @@ impl ProjectCollaborator {
             peer_id: Some(self.connection_id.into()),
             replica_id: self.replica_id.0 as u32,
             user_id: self.user_id.to_proto(),
+            read_only: self.read_only,
         }
     }
 }
@@ -69,6 +69,9 @@ macro_rules! id_type {
 
 id_type!(BufferId);
 id_type!(AccessTokenId);
+id_type!(AuditEventId);
+id_type!(CallRecordId);
+id_type!(CallSummaryId);
 id_type!(ChannelChatParticipantId);
 id_type!(ChannelId);
 id_type!(ChannelMemberId);
@@ -76,6 +79,8 @@ id_type!(MessageId);
 id_type!(ContactId);
 id_type!(FollowerId);
 id_type!(RoomId);
+id_type!(RoomActivityId);
+id_type!(RoomMessageId);
 id_type!(RoomParticipantId);
 id_type!(ProjectId);
 id_type!(ProjectCollaboratorId);
@@ -83,6 +88,8 @@ id_type!(ReplicaId);
 id_type!(ServerId);
 id_type!(SignupId);
 id_type!(UserId);
+id_type!(UserReportId);
+id_type!(WaitingCallId);
 id_type!(ChannelBufferCollaboratorId);
 id_type!(FlagId);
 id_type!(NotificationId);
@@ -258,3 +265,154 @@ impl Into<i32> for ChannelVisibility {
         proto.into()
     }
 }
+
+/// CallStatus records how a call between two users was resolved.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Default, Hash)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+pub enum CallStatus {
+    /// The callee is still being rung.
+    #[sea_orm(string_value = "ringing")]
+    #[default]
+    Ringing,
+    /// The callee joined the room.
+    #[sea_orm(string_value = "answered")]
+    Answered,
+    /// The callee declined the call.
+    #[sea_orm(string_value = "declined")]
+    Declined,
+    /// The call was canceled, or could not reach the callee, before they answered.
+    #[sea_orm(string_value = "missed")]
+    Missed,
+}
+
+impl From<proto::CallStatus> for CallStatus {
+    fn from(value: proto::CallStatus) -> Self {
+        match value {
+            proto::CallStatus::Ringing => CallStatus::Ringing,
+            proto::CallStatus::Answered => CallStatus::Answered,
+            proto::CallStatus::Declined => CallStatus::Declined,
+            proto::CallStatus::Missed => CallStatus::Missed,
+        }
+    }
+}
+
+impl Into<proto::CallStatus> for CallStatus {
+    fn into(self) -> proto::CallStatus {
+        match self {
+            CallStatus::Ringing => proto::CallStatus::Ringing,
+            CallStatus::Answered => proto::CallStatus::Answered,
+            CallStatus::Declined => proto::CallStatus::Declined,
+            CallStatus::Missed => proto::CallStatus::Missed,
+        }
+    }
+}
+
+impl Into<i32> for CallStatus {
+    fn into(self) -> i32 {
+        let proto: proto::CallStatus = self.into();
+        proto.into()
+    }
+}
+
+/// ReportStatus records where a report of a user stands in admin review.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    /// No admin has reviewed the report yet.
+    #[sea_orm(string_value = "open")]
+    Open,
+    /// An admin agreed with the report.
+    #[sea_orm(string_value = "upheld")]
+    Upheld,
+    /// An admin found nothing wrong, so the report no longer counts against the
+    /// reported user.
+    #[sea_orm(string_value = "dismissed")]
+    Dismissed,
+}
+
+/// RateLimitedActionKind distinguishes the actions that users can only take so often,
+/// which are counted across servers.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+pub enum RateLimitedActionKind {
+    /// A user whose invites are throttled invited someone to a channel.
+    #[sea_orm(string_value = "throttled_invite")]
+    ThrottledInvite,
+    /// A user sent a contact request to the target user.
+    #[sea_orm(string_value = "contact_request")]
+    ContactRequest,
+    /// A user was flagged for admins to review, for sending too many contact requests.
+    #[sea_orm(string_value = "contact_requests_flagged")]
+    ContactRequestsFlagged,
+    /// A user uploaded an avatar.
+    #[sea_orm(string_value = "avatar_upload")]
+    AvatarUpload,
+}
+
+/// RoomActivityKind distinguishes the entries of a room's activity feed, which
+/// its call summary is assembled from.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Hash)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+pub enum RoomActivityKind {
+    /// The user joined the call.
+    #[sea_orm(string_value = "joined")]
+    Joined,
+    /// The user asked to be sent a summary when the call ends.
+    #[sea_orm(string_value = "requested_summary")]
+    RequestedSummary,
+    /// The user edited the file at `detail`.
+    #[sea_orm(string_value = "edited_file")]
+    EditedFile,
+    /// The user shared the statistics in `detail`, as JSON.
+    #[sea_orm(string_value = "reported_statistics")]
+    ReportedStatistics,
+}
+
+/// AuditEventKind distinguishes the events in the audit log, which admins look
+/// through when investigating reports of abuse.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventKind {
+    /// The user created the room.
+    #[sea_orm(string_value = "room_created")]
+    RoomCreated,
+    /// The user joined the room, or the channel's room.
+    #[sea_orm(string_value = "room_joined")]
+    RoomJoined,
+    /// The user shared the project into the room.
+    #[sea_orm(string_value = "project_shared")]
+    ProjectShared,
+    /// The user set the subject's role in the room to `detail`.
+    #[sea_orm(string_value = "room_role_changed")]
+    RoomRoleChanged,
+    /// The user set the subject's role in the channel to `detail`.
+    #[sea_orm(string_value = "channel_role_changed")]
+    ChannelRoleChanged,
+    /// The user made the project read-only for the subject, or read-write, as
+    /// `detail` says.
+    #[sea_orm(string_value = "project_access_changed")]
+    ProjectAccessChanged,
+    /// The user sent more contact requests than they can in an hour, and was flagged
+    /// for review.
+    #[sea_orm(string_value = "contact_requests_limited")]
+    ContactRequestsLimited,
+    /// The user invited the subject, a guest created for the invitation, into the
+    /// room, with the role in `detail`.
+    #[sea_orm(string_value = "guest_created")]
+    GuestCreated,
+    /// The user invited the subject to the channel, with the role in `detail`.
+    #[sea_orm(string_value = "channel_member_invited")]
+    ChannelMemberInvited,
+    /// The user removed the subject from the channel, or left it if they're the
+    /// same user.
+    #[sea_orm(string_value = "channel_member_removed")]
+    ChannelMemberRemoved,
+    /// The user set the channel's visibility to `detail`.
+    #[sea_orm(string_value = "channel_visibility_changed")]
+    ChannelVisibilityChanged,
+    /// The user stopped sharing the project into the room.
+    #[sea_orm(string_value = "project_unshared")]
+    ProjectUnshared,
+}
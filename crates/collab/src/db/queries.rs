@@ -1,13 +1,23 @@
 use super::*;
 
 pub mod access_tokens;
+pub mod audit_events;
 pub mod buffers;
+pub mod call_records;
+pub mod call_summaries;
 pub mod channels;
 pub mod contacts;
 pub mod contributors;
+pub mod guests;
 pub mod messages;
 pub mod notifications;
 pub mod projects;
+pub mod rate_limits;
+pub mod resume_tokens;
+pub mod room_messages;
 pub mod rooms;
+pub mod runtime_config;
 pub mod servers;
+pub mod user_connections;
+pub mod user_reports;
 pub mod users;
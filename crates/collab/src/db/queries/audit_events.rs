@@ -0,0 +1,76 @@
+use super::*;
+
+impl Database {
+    /// Returns the events in the audit log that match the filter, most recent last.
+    pub async fn get_audit_events(
+        &self,
+        filter: &AuditEventFilter,
+        limit: usize,
+        before_id: Option<AuditEventId>,
+    ) -> Result<Vec<audit_event::Model>> {
+        self.transaction(|tx| async move {
+            let mut condition = Condition::all();
+            if let Some(user_id) = filter.user_id {
+                condition = condition.add(
+                    Condition::any()
+                        .add(audit_event::Column::UserId.eq(user_id))
+                        .add(audit_event::Column::SubjectUserId.eq(user_id)),
+                );
+            }
+            if let Some(room_id) = filter.room_id {
+                condition = condition.add(audit_event::Column::RoomId.eq(room_id));
+            }
+            if let Some(channel_id) = filter.channel_id {
+                condition = condition.add(audit_event::Column::ChannelId.eq(channel_id));
+            }
+            if let Some(project_id) = filter.project_id {
+                condition = condition.add(audit_event::Column::ProjectId.eq(project_id));
+            }
+            if let Some(kind) = filter.kind {
+                condition = condition.add(audit_event::Column::Kind.eq(kind));
+            }
+            if let Some(before_id) = before_id {
+                condition = condition.add(audit_event::Column::Id.lt(before_id));
+            }
+
+            let mut events = audit_event::Entity::find()
+                .filter(condition)
+                .order_by_desc(audit_event::Column::Id)
+                .limit(limit as u64)
+                .all(&*tx)
+                .await?;
+            events.reverse();
+            Ok(events)
+        })
+        .await
+    }
+
+    /// Adds an event to the audit log, for events that don't change the database
+    /// otherwise.
+    pub async fn create_audit_event(&self, event: NewAuditEvent) -> Result<()> {
+        self.transaction(|tx| async move { self.record_audit_event(event, &tx).await })
+            .await
+    }
+
+    /// Adds an event to the audit log, as part of the transaction that made it happen.
+    pub(crate) async fn record_audit_event(
+        &self,
+        event: NewAuditEvent,
+        tx: &DatabaseTransaction,
+    ) -> Result<()> {
+        audit_event::ActiveModel {
+            id: ActiveValue::NotSet,
+            kind: ActiveValue::Set(event.kind),
+            user_id: ActiveValue::Set(event.user_id),
+            subject_user_id: ActiveValue::Set(event.subject_user_id),
+            room_id: ActiveValue::Set(event.room_id),
+            channel_id: ActiveValue::Set(event.channel_id),
+            project_id: ActiveValue::Set(event.project_id),
+            detail: ActiveValue::Set(event.detail),
+            created_at: ActiveValue::NotSet,
+        }
+        .insert(tx)
+        .await?;
+        Ok(())
+    }
+}
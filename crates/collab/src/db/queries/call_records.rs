@@ -0,0 +1,81 @@
+use super::*;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+impl Database {
+    /// Returns the calls that the given user has placed or received, most recent last.
+    pub async fn get_call_history(
+        &self,
+        user_id: UserId,
+        limit: usize,
+        before_id: Option<CallRecordId>,
+    ) -> Result<Vec<call_record::Model>> {
+        self.transaction(|tx| async move {
+            let mut condition = Condition::all().add(
+                Condition::any()
+                    .add(call_record::Column::CallerUserId.eq(user_id))
+                    .add(call_record::Column::CalleeUserId.eq(user_id)),
+            );
+            if let Some(before_id) = before_id {
+                condition = condition.add(call_record::Column::Id.lt(before_id));
+            }
+
+            let mut records = call_record::Entity::find()
+                .filter(condition)
+                .order_by_desc(call_record::Column::Id)
+                .limit(limit as u64)
+                .all(&*tx)
+                .await?;
+            records.reverse();
+            Ok(records)
+        })
+        .await
+    }
+
+    /// Records that `caller_user_id` started ringing `callee_user_id`.
+    pub(crate) async fn create_call_record(
+        &self,
+        room_id: RoomId,
+        caller_user_id: UserId,
+        callee_user_id: UserId,
+        tx: &DatabaseTransaction,
+    ) -> Result<()> {
+        call_record::ActiveModel {
+            room_id: ActiveValue::Set(room_id),
+            caller_user_id: ActiveValue::Set(caller_user_id),
+            callee_user_id: ActiveValue::Set(callee_user_id),
+            status: ActiveValue::Set(CallStatus::Ringing),
+            id: ActiveValue::NotSet,
+            created_at: ActiveValue::NotSet,
+            ended_at: ActiveValue::NotSet,
+        }
+        .insert(tx)
+        .await?;
+        Ok(())
+    }
+
+    /// Resolves the calls that are still ringing the given users in the given room.
+    pub(crate) async fn resolve_call_records(
+        &self,
+        room_id: RoomId,
+        callee_user_ids: impl IntoIterator<Item = UserId>,
+        status: CallStatus,
+        tx: &DatabaseTransaction,
+    ) -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+        call_record::Entity::update_many()
+            .filter(
+                Condition::all()
+                    .add(call_record::Column::RoomId.eq(room_id))
+                    .add(call_record::Column::CalleeUserId.is_in(callee_user_ids))
+                    .add(call_record::Column::Status.eq(CallStatus::Ringing)),
+            )
+            .set(call_record::ActiveModel {
+                status: ActiveValue::Set(status),
+                ended_at: ActiveValue::Set(Some(PrimitiveDateTime::new(now.date(), now.time()))),
+                ..Default::default()
+            })
+            .exec(tx)
+            .await?;
+        Ok(())
+    }
+}
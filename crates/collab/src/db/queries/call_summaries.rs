@@ -0,0 +1,246 @@
+use super::*;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+impl Database {
+    /// Adds what a participant did during a call to the room's activity feed. Observers
+    /// are hidden from most of the room, so nothing they do is added to it.
+    pub async fn report_room_activity(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        connection: ConnectionId,
+        report: &proto::ReportRoomActivity,
+    ) -> Result<RoomGuard<()>> {
+        self.room_transaction(room_id, |tx| async move {
+            let participant = room_participant::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(room_participant::Column::RoomId.eq(room_id))
+                        .add(
+                            room_participant::Column::AnsweringConnectionId
+                                .eq(connection.id as i32),
+                        )
+                        .add(
+                            room_participant::Column::AnsweringConnectionServerId
+                                .eq(connection.owner_id as i32),
+                        ),
+                )
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("not a participant in the room"))?;
+            if participant.observer {
+                return Ok(());
+            }
+
+            let mut activities = Vec::new();
+            if report.wants_summary {
+                activities.push(new_room_activity(
+                    room_id,
+                    user_id,
+                    RoomActivityKind::RequestedSummary,
+                    None,
+                ));
+            }
+            for file in &report.edited_files {
+                let mut activity = new_room_activity(
+                    room_id,
+                    user_id,
+                    RoomActivityKind::EditedFile,
+                    Some(file.path.clone()),
+                );
+                activity.lines_added =
+                    ActiveValue::Set(file.lines_added.min(i32::MAX as u32) as i32);
+                activity.lines_removed =
+                    ActiveValue::Set(file.lines_removed.min(i32::MAX as u32) as i32);
+                activities.push(activity);
+            }
+            if let Some(statistics) = &report.statistics {
+                let statistics = call_summary::ParticipantStatistics::from_proto(statistics);
+                activities.push(new_room_activity(
+                    room_id,
+                    user_id,
+                    RoomActivityKind::ReportedStatistics,
+                    Some(serde_json::to_string(&statistics)?),
+                ));
+            }
+            if !activities.is_empty() {
+                room_activity::Entity::insert_many(activities)
+                    .exec(&*tx)
+                    .await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns a call summary, if the given user took part in the call.
+    pub async fn get_call_summary(
+        &self,
+        summary_id: CallSummaryId,
+        user_id: UserId,
+    ) -> Result<proto::CallSummary> {
+        self.transaction(|tx| async move {
+            let summary = call_summary::Entity::find_by_id(summary_id)
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("no such call summary"))?;
+            let content: call_summary::Content = serde_json::from_str(&summary.content)?;
+            if !content.participant_user_ids.contains(&user_id) {
+                Err(anyhow!("no such call summary"))?;
+            }
+            Ok(summary.to_proto(content))
+        })
+        .await
+    }
+
+    /// Records that the given user joined the call in the given room, unless they're
+    /// observing it, so that the call's summary doesn't list observers.
+    pub(crate) async fn record_room_join(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        tx: &DatabaseTransaction,
+    ) -> Result<()> {
+        let observer = room_participant::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(room_participant::Column::RoomId.eq(room_id))
+                    .add(room_participant::Column::UserId.eq(user_id)),
+            )
+            .one(tx)
+            .await?
+            .map_or(false, |participant| participant.observer);
+        if observer {
+            return Ok(());
+        }
+
+        new_room_activity(room_id, user_id, RoomActivityKind::Joined, None)
+            .insert(tx)
+            .await?;
+        Ok(())
+    }
+
+    /// Ends the call in a room that its last participant just left, clearing the
+    /// room's activity feed for the next call. If any participant asked for one,
+    /// the feed is first assembled into a summary, and they are notified of it.
+    pub(crate) async fn summarize_call(
+        &self,
+        room_id: RoomId,
+        channel_id: Option<ChannelId>,
+        tx: &DatabaseTransaction,
+    ) -> Result<NotificationBatch> {
+        let activities = room_activity::Entity::find()
+            .filter(room_activity::Column::RoomId.eq(room_id))
+            .order_by_asc(room_activity::Column::Id)
+            .all(tx)
+            .await?;
+        room_activity::Entity::delete_many()
+            .filter(room_activity::Column::RoomId.eq(room_id))
+            .exec(tx)
+            .await?;
+
+        let mut recipient_ids = Vec::new();
+        for activity in &activities {
+            if activity.kind == RoomActivityKind::RequestedSummary
+                && !recipient_ids.contains(&activity.user_id)
+            {
+                recipient_ids.push(activity.user_id);
+            }
+        }
+        let Some(started_at) = activities.iter().map(|activity| activity.created_at).min() else {
+            return Ok(Vec::new());
+        };
+        if recipient_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut content = call_summary::Content::default();
+        let mut edited_files = BTreeMap::<String, call_summary::EditedFile>::default();
+        let mut statistics = BTreeMap::<UserId, call_summary::ParticipantStatistics>::default();
+        for activity in activities {
+            match activity.kind {
+                RoomActivityKind::Joined => {
+                    if !content.participant_user_ids.contains(&activity.user_id) {
+                        content.participant_user_ids.push(activity.user_id);
+                    }
+                }
+                RoomActivityKind::RequestedSummary => {}
+                RoomActivityKind::EditedFile => {
+                    if let Some(path) = activity.detail {
+                        let file = edited_files.entry(path.clone()).or_insert_with(|| {
+                            call_summary::EditedFile {
+                                path,
+                                ..Default::default()
+                            }
+                        });
+                        file.lines_added =
+                            file.lines_added.saturating_add(activity.lines_added as u32);
+                        file.lines_removed = file
+                            .lines_removed
+                            .saturating_add(activity.lines_removed as u32);
+                    }
+                }
+                RoomActivityKind::ReportedStatistics => {
+                    if let Some(reported) = activity
+                        .detail
+                        .and_then(|detail| serde_json::from_str(&detail).ok())
+                    {
+                        statistics
+                            .entry(activity.user_id)
+                            .or_default()
+                            .merge(reported);
+                    }
+                }
+            }
+        }
+        content.edited_files = edited_files.into_values().collect();
+        content.statistics =
+            call_summary::Statistics::aggregate(statistics.into_values().collect());
+
+        let now = OffsetDateTime::now_utc();
+        let summary = call_summary::ActiveModel {
+            id: ActiveValue::NotSet,
+            room_id: ActiveValue::Set(room_id),
+            channel_id: ActiveValue::Set(channel_id),
+            started_at: ActiveValue::Set(started_at),
+            ended_at: ActiveValue::Set(PrimitiveDateTime::new(now.date(), now.time())),
+            content: ActiveValue::Set(serde_json::to_string(&content)?),
+        }
+        .insert(tx)
+        .await?;
+
+        let mut notifications = Vec::new();
+        for recipient_id in recipient_ids {
+            notifications.extend(
+                self.create_notification(
+                    recipient_id,
+                    rpc::Notification::CallSummaryReady {
+                        summary_id: summary.id.to_proto(),
+                    },
+                    false,
+                    tx,
+                )
+                .await?,
+            );
+        }
+        Ok(notifications)
+    }
+}
+
+fn new_room_activity(
+    room_id: RoomId,
+    user_id: UserId,
+    kind: RoomActivityKind,
+    detail: Option<String>,
+) -> room_activity::ActiveModel {
+    room_activity::ActiveModel {
+        id: ActiveValue::NotSet,
+        room_id: ActiveValue::Set(room_id),
+        user_id: ActiveValue::Set(user_id),
+        kind: ActiveValue::Set(kind),
+        detail: ActiveValue::Set(detail),
+        lines_added: ActiveValue::Set(0),
+        lines_removed: ActiveValue::Set(0),
+        created_at: ActiveValue::NotSet,
+    }
+}
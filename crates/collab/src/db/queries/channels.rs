@@ -1,6 +1,7 @@
 use super::*;
 use rpc::{proto::channel_member::Kind, ErrorCode, ErrorCodeExt};
 use sea_orm::TryGetableMany;
+use time::OffsetDateTime;
 
 impl Database {
     #[cfg(test)]
@@ -103,8 +104,11 @@ impl Database {
         channel_id: ChannelId,
         user_id: UserId,
         connection: ConnectionId,
-    ) -> Result<(JoinRoom, Option<MembershipUpdated>, ChannelRole)> {
-        self.transaction(move |tx| async move {
+        capabilities: Option<&proto::ParticipantCapabilities>,
+        max_participants: usize,
+    ) -> Result<RoomGuard<(JoinRoom, Option<MembershipUpdated>, ChannelRole)>> {
+        let capabilities = room_participant::Capabilities::json_from_proto(capabilities)?;
+        self.deferred_room_transaction(move |tx| async move {
             let channel = self.get_channel_internal(channel_id, &*tx).await?;
             let mut role = self.channel_role_for_user(&channel, user_id, &*tx).await?;
 
@@ -161,13 +165,39 @@ impl Database {
             let role = role.unwrap();
 
             let live_kit_room = format!("channel-{}", nanoid::nanoid!(30));
-            let room_id = self
+            let (room_id, room_created) = self
                 .get_or_create_channel_room(channel_id, &live_kit_room, &*tx)
                 .await?;
+            self.check_room_participant_quota(room_id, user_id, max_participants, &tx)
+                .await?;
+
+            let mut joined_room = self
+                .join_channel_room_internal(room_id, user_id, connection, role, capabilities, &*tx)
+                .await?;
+            joined_room.room_created = room_created;
+            Ok((room_id, (joined_room, accept_invite_result, role)))
+        })
+        .await
+    }
 
-            self.join_channel_room_internal(room_id, user_id, connection, role, &*tx)
-                .await
-                .map(|jr| (jr, accept_invite_result, role))
+    /// Whether joining the given channel would only let the user in because it's
+    /// public, rather than because they're a member or were invited.
+    pub async fn is_public_channel_join(
+        &self,
+        channel_id: ChannelId,
+        user_id: UserId,
+    ) -> Result<bool> {
+        self.transaction(|tx| async move {
+            let channel = self.get_channel_internal(channel_id, &*tx).await?;
+            Ok(channel.visibility == ChannelVisibility::Public
+                && self
+                    .channel_role_for_user(&channel, user_id, &*tx)
+                    .await?
+                    .is_none()
+                && self
+                    .pending_invite_for_channel(&channel, user_id, &*tx)
+                    .await?
+                    .is_none())
         })
         .await
     }
@@ -210,6 +240,15 @@ impl Database {
             let mut model = channel.into_active_model();
             model.visibility = ActiveValue::Set(visibility);
             let channel = model.update(&*tx).await?;
+            self.record_audit_event(
+                NewAuditEvent {
+                    channel_id: Some(channel_id),
+                    detail: Some(visibility.to_value()),
+                    ..NewAuditEvent::new(AuditEventKind::ChannelVisibilityChanged, admin_id)
+                },
+                &tx,
+            )
+            .await?;
 
             let channel_members = channel_member::Entity::find()
                 .filter(channel_member::Column::ChannelId.eq(channel.root_id()))
@@ -300,6 +339,16 @@ impl Database {
             }
             .insert(&*tx)
             .await?;
+            self.record_audit_event(
+                NewAuditEvent {
+                    subject_user_id: Some(invitee_id),
+                    channel_id: Some(channel_id),
+                    detail: Some(role.to_value()),
+                    ..NewAuditEvent::new(AuditEventKind::ChannelMemberInvited, inviter_id)
+                },
+                &tx,
+            )
+            .await?;
 
             let channel = Channel::from_model(channel);
 
@@ -485,6 +534,15 @@ impl Database {
             if result.rows_affected == 0 {
                 Err(anyhow!("no such member"))?;
             }
+            self.record_audit_event(
+                NewAuditEvent {
+                    subject_user_id: Some(member_id),
+                    channel_id: Some(channel_id),
+                    ..NewAuditEvent::new(AuditEventKind::ChannelMemberRemoved, admin_id)
+                },
+                &tx,
+            )
+            .await?;
 
             Ok(RemoveChannelMemberResult {
                 membership_update: self
@@ -692,6 +750,16 @@ impl Database {
             let mut update = membership.into_active_model();
             update.role = ActiveValue::Set(role);
             let updated = channel_member::Entity::update(update).exec(&*tx).await?;
+            self.record_audit_event(
+                NewAuditEvent {
+                    subject_user_id: Some(for_user),
+                    channel_id: Some(channel_id),
+                    detail: Some(role.to_value()),
+                    ..NewAuditEvent::new(AuditEventKind::ChannelRoleChanged, admin_id)
+                },
+                &tx,
+            )
+            .await?;
 
             if updated.accepted {
                 Ok(SetMemberRoleResult::MembershipUpdated(
@@ -765,6 +833,7 @@ impl Database {
     ) -> Result<Vec<channel_member::Model>> {
         Ok(channel_member::Entity::find()
             .filter(channel_member::Column::ChannelId.eq(channel.root_id()))
+            .order_by_asc(channel_member::Column::UserId)
             .all(tx)
             .await?)
     }
@@ -933,27 +1002,27 @@ impl Database {
         channel_id: ChannelId,
         live_kit_room: &str,
         tx: &DatabaseTransaction,
-    ) -> Result<RoomId> {
+    ) -> Result<(RoomId, bool)> {
         let room = room::Entity::find()
             .filter(room::Column::ChannelId.eq(channel_id))
             .one(&*tx)
             .await?;
 
-        let room_id = if let Some(room) = room {
-            room.id
+        if let Some(room) = room {
+            Ok((room.id, false))
         } else {
+            let now = OffsetDateTime::now_utc();
             let result = room::Entity::insert(room::ActiveModel {
                 channel_id: ActiveValue::Set(Some(channel_id)),
                 live_kit_room: ActiveValue::Set(live_kit_room.to_string()),
+                created_at: ActiveValue::Set(Some(PrimitiveDateTime::new(now.date(), now.time()))),
                 ..Default::default()
             })
             .exec(&*tx)
             .await?;
 
-            result.last_insert_id
-        };
-
-        Ok(room_id)
+            Ok((result.last_insert_id, true))
+        }
     }
 
     /// Move a channel from one parent to another
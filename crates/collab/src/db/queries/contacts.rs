@@ -1,7 +1,8 @@
 use super::*;
 
 impl Database {
-    /// Retrieves the contacts for the user with the given ID.
+    /// Retrieves the contacts for the user with the given ID. Contacts who are only
+    /// observing a call aren't busy, since observers are hidden from most of the room.
     pub async fn get_contacts(&self, user_id: UserId) -> Result<Vec<Contact>> {
         #[derive(Debug, FromQueryResult)]
         struct ContactWithUserBusyStatuses {
@@ -19,12 +20,26 @@ impl Database {
             let mut db_contacts = contact::Entity::find()
                 .column_as(
                     Expr::col((user_a_participant.clone(), room_participant::Column::Id))
-                        .is_not_null(),
+                        .is_not_null()
+                        .and(
+                            Expr::col((
+                                user_a_participant.clone(),
+                                room_participant::Column::Observer,
+                            ))
+                            .eq(false),
+                        ),
                     "user_a_busy",
                 )
                 .column_as(
                     Expr::col((user_b_participant.clone(), room_participant::Column::Id))
-                        .is_not_null(),
+                        .is_not_null()
+                        .and(
+                            Expr::col((
+                                user_b_participant.clone(),
+                                room_participant::Column::Observer,
+                            ))
+                            .eq(false),
+                        ),
                     "user_b_busy",
                 )
                 .filter(
@@ -87,14 +102,53 @@ impl Database {
         .await
     }
 
-    /// Returns whether the given user is a busy (on a call).
+    /// Returns whether the given user is a busy (on a call). Observing a call doesn't
+    /// count.
     pub async fn is_user_busy(&self, user_id: UserId) -> Result<bool> {
         self.transaction(|tx| async move {
             let participant = room_participant::Entity::find()
                 .filter(room_participant::Column::UserId.eq(user_id))
                 .one(&*tx)
                 .await?;
-            Ok(participant.is_some())
+            Ok(participant.map_or(false, |participant| !participant.observer))
+        })
+        .await
+    }
+
+    /// Returns whether the given user is observing a call.
+    pub async fn is_user_observing(&self, user_id: UserId) -> Result<bool> {
+        self.transaction(|tx| async move {
+            let participant = room_participant::Entity::find()
+                .filter(room_participant::Column::UserId.eq(user_id))
+                .one(&*tx)
+                .await?;
+            Ok(participant.map_or(false, |participant| participant.observer))
+        })
+        .await
+    }
+
+    /// Returns the connections on which the given users have answered a call, for
+    /// those of them that are in one and aren't observing it.
+    pub async fn call_connections(
+        &self,
+        user_ids: &[UserId],
+    ) -> Result<HashMap<UserId, ConnectionId>> {
+        self.transaction(|tx| async move {
+            let participants = room_participant::Entity::find()
+                .filter(
+                    room_participant::Column::UserId
+                        .is_in(user_ids.iter().copied())
+                        .and(room_participant::Column::AnsweringConnectionLost.eq(false))
+                        .and(room_participant::Column::Observer.eq(false)),
+                )
+                .all(&*tx)
+                .await?;
+            Ok(participants
+                .into_iter()
+                .filter_map(|participant| {
+                    Some((participant.user_id, participant.answering_connection()?))
+                })
+                .collect())
         })
         .await
     }
@@ -124,6 +178,86 @@ impl Database {
         .await
     }
 
+    /// Whether the users have met, by being on a call together, being members of the
+    /// same organization's channels, or one of them having asked the other to be a
+    /// contact. Joining a public channel as a guest doesn't count.
+    pub async fn have_users_interacted(
+        &self,
+        user_id_1: UserId,
+        user_id_2: UserId,
+    ) -> Result<bool> {
+        self.transaction(|tx| async move {
+            let (id_a, id_b) = if user_id_1 < user_id_2 {
+                (user_id_1, user_id_2)
+            } else {
+                (user_id_2, user_id_1)
+            };
+            let contacts = contact::Entity::find()
+                .filter(
+                    contact::Column::UserIdA
+                        .eq(id_a)
+                        .and(contact::Column::UserIdB.eq(id_b)),
+                )
+                .count(&*tx)
+                .await?;
+            if contacts > 0 {
+                return Ok(true);
+            }
+
+            let calls = call_record::Entity::find()
+                .filter(
+                    Condition::any()
+                        .add(
+                            call_record::Column::CallerUserId
+                                .eq(user_id_1)
+                                .and(call_record::Column::CalleeUserId.eq(user_id_2)),
+                        )
+                        .add(
+                            call_record::Column::CallerUserId
+                                .eq(user_id_2)
+                                .and(call_record::Column::CalleeUserId.eq(user_id_1)),
+                        ),
+                )
+                .count(&*tx)
+                .await?;
+            if calls > 0 {
+                return Ok(true);
+            }
+
+            let memberships = channel_member::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(channel_member::Column::UserId.is_in([user_id_1, user_id_2]))
+                        .add(channel_member::Column::Accepted.eq(true))
+                        .add(
+                            channel_member::Column::Role
+                                .is_in([ChannelRole::Admin, ChannelRole::Member]),
+                        ),
+                )
+                .all(&*tx)
+                .await?;
+            let root_ids = channel::Entity::find()
+                .filter(
+                    channel::Column::Id
+                        .is_in(memberships.iter().map(|membership| membership.channel_id)),
+                )
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .map(|channel| (channel.id, channel.root_id()))
+                .collect::<HashMap<_, _>>();
+            let organizations = |user_id| {
+                memberships
+                    .iter()
+                    .filter(|membership| membership.user_id == user_id)
+                    .filter_map(|membership| root_ids.get(&membership.channel_id).copied())
+                    .collect::<HashSet<_>>()
+            };
+            Ok(!organizations(user_id_1).is_disjoint(&organizations(user_id_2)))
+        })
+        .await
+    }
+
     /// Invite the user with `receiver_id` to be a contact of the user with `sender_id`.
     pub async fn send_contact_request(
         &self,
@@ -131,6 +265,21 @@ impl Database {
         receiver_id: UserId,
     ) -> Result<NotificationBatch> {
         self.transaction(|tx| async move {
+            let guests = guest::Entity::find()
+                .filter(guest::Column::UserId.is_in([sender_id, receiver_id]))
+                .count(&*tx)
+                .await?;
+            if guests > 0 {
+                Err(anyhow!("guests cannot have contacts"))?;
+            }
+            if blocked_user::Entity::find_by_id((receiver_id, sender_id))
+                .one(&*tx)
+                .await?
+                .is_some()
+            {
+                Err(anyhow!("cannot send a contact request to this user"))?;
+            }
+
             let (id_a, id_b, a_to_b) = if sender_id < receiver_id {
                 (sender_id, receiver_id, true)
             } else {
@@ -169,6 +318,13 @@ impl Database {
             if rows_affected == 0 {
                 Err(anyhow!("contact already requested"))?;
             }
+            self.record_rate_limited_action(
+                sender_id,
+                RateLimitedActionKind::ContactRequest,
+                Some(receiver_id),
+                &*tx,
+            )
+            .await?;
 
             Ok(self
                 .create_notification(
@@ -218,6 +374,28 @@ impl Database {
                 .ok_or_else(|| anyhow!("no such contact"))?;
 
             contact::Entity::delete_by_id(contact.id).exec(&*tx).await?;
+            contact_note::Entity::delete_many()
+                .filter(
+                    contact_note::Column::UserId
+                        .eq(id_a)
+                        .and(contact_note::Column::ContactUserId.eq(id_b))
+                        .or(contact_note::Column::UserId
+                            .eq(id_b)
+                            .and(contact_note::Column::ContactUserId.eq(id_a))),
+                )
+                .exec(&*tx)
+                .await?;
+            location_share::Entity::delete_many()
+                .filter(
+                    location_share::Column::UserId
+                        .eq(id_a)
+                        .and(location_share::Column::ContactUserId.eq(id_b))
+                        .or(location_share::Column::UserId
+                            .eq(id_b)
+                            .and(location_share::Column::ContactUserId.eq(id_a))),
+                )
+                .exec(&*tx)
+                .await?;
 
             let mut deleted_notification_id = None;
             if !contact.accepted {
@@ -358,4 +536,223 @@ impl Database {
         })
         .await
     }
+
+    /// Retrieves the nicknames and notes the given user has attached to their contacts.
+    pub async fn get_contact_notes(&self, user_id: UserId) -> Result<Vec<contact_note::Model>> {
+        self.transaction(|tx| async move {
+            Ok(contact_note::Entity::find()
+                .filter(contact_note::Column::UserId.eq(user_id))
+                .order_by_asc(contact_note::Column::ContactUserId)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Sets the private nickname and note that `user_id` has attached to `contact_user_id`.
+    ///
+    /// The row is removed once both the nickname and the note are cleared.
+    pub async fn set_contact_note(
+        &self,
+        user_id: UserId,
+        contact_user_id: UserId,
+        nickname: Option<String>,
+        note: Option<String>,
+    ) -> Result<contact_note::Model> {
+        self.transaction(|tx| async move {
+            let (id_a, id_b) = if user_id < contact_user_id {
+                (user_id, contact_user_id)
+            } else {
+                (contact_user_id, user_id)
+            };
+            let is_contact = contact::Entity::find()
+                .filter(
+                    contact::Column::UserIdA
+                        .eq(id_a)
+                        .and(contact::Column::UserIdB.eq(id_b))
+                        .and(contact::Column::Accepted.eq(true)),
+                )
+                .one(&*tx)
+                .await?
+                .is_some();
+            if !is_contact {
+                Err(anyhow!("no such contact"))?;
+            }
+
+            let contact_note = contact_note::Model {
+                user_id,
+                contact_user_id,
+                nickname,
+                note,
+            };
+
+            if contact_note.nickname.is_none() && contact_note.note.is_none() {
+                contact_note::Entity::delete_by_id((user_id, contact_user_id))
+                    .exec(&*tx)
+                    .await?;
+            } else {
+                contact_note::Entity::insert(contact_note::ActiveModel {
+                    user_id: ActiveValue::Set(user_id),
+                    contact_user_id: ActiveValue::Set(contact_user_id),
+                    nickname: ActiveValue::Set(contact_note.nickname.clone()),
+                    note: ActiveValue::Set(contact_note.note.clone()),
+                })
+                .on_conflict(
+                    OnConflict::columns([
+                        contact_note::Column::UserId,
+                        contact_note::Column::ContactUserId,
+                    ])
+                    .update_columns([contact_note::Column::Nickname, contact_note::Column::Note])
+                    .to_owned(),
+                )
+                .exec_without_returning(&*tx)
+                .await?;
+            }
+
+            Ok(contact_note)
+        })
+        .await
+    }
+
+    /// Returns the contacts that the given user shows the project they're working in to.
+    pub async fn location_shared_with(&self, user_id: UserId) -> Result<HashSet<UserId>> {
+        self.transaction(|tx| async move {
+            Ok(location_share::Entity::find()
+                .filter(location_share::Column::UserId.eq(user_id))
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .map(|share| share.contact_user_id)
+                .collect())
+        })
+        .await
+    }
+
+    /// Returns the contacts who show the given user the project they're working in.
+    pub async fn location_shared_by(&self, user_id: UserId) -> Result<HashSet<UserId>> {
+        self.transaction(|tx| async move {
+            Ok(location_share::Entity::find()
+                .filter(location_share::Column::ContactUserId.eq(user_id))
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .map(|share| share.user_id)
+                .collect())
+        })
+        .await
+    }
+
+    /// Sets whether `user_id` shows `contact_user_id` the project they're working in.
+    pub async fn set_location_sharing(
+        &self,
+        user_id: UserId,
+        contact_user_id: UserId,
+        enabled: bool,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            if enabled {
+                let (id_a, id_b) = if user_id < contact_user_id {
+                    (user_id, contact_user_id)
+                } else {
+                    (contact_user_id, user_id)
+                };
+                let is_contact = contact::Entity::find()
+                    .filter(
+                        contact::Column::UserIdA
+                            .eq(id_a)
+                            .and(contact::Column::UserIdB.eq(id_b))
+                            .and(contact::Column::Accepted.eq(true)),
+                    )
+                    .one(&*tx)
+                    .await?
+                    .is_some();
+                if !is_contact {
+                    Err(anyhow!("no such contact"))?;
+                }
+
+                location_share::Entity::insert(location_share::ActiveModel {
+                    user_id: ActiveValue::Set(user_id),
+                    contact_user_id: ActiveValue::Set(contact_user_id),
+                })
+                .on_conflict(
+                    OnConflict::columns([
+                        location_share::Column::UserId,
+                        location_share::Column::ContactUserId,
+                    ])
+                    .do_nothing()
+                    .to_owned(),
+                )
+                .exec_without_returning(&*tx)
+                .await?;
+            } else {
+                location_share::Entity::delete_by_id((user_id, contact_user_id))
+                    .exec(&*tx)
+                    .await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the users that the given user has blocked.
+    pub async fn get_blocked_users(&self, user_id: UserId) -> Result<HashSet<UserId>> {
+        self.transaction(|tx| async move {
+            Ok(blocked_user::Entity::find()
+                .filter(blocked_user::Column::UserId.eq(user_id))
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .map(|blocked_user| blocked_user.blocked_user_id)
+                .collect())
+        })
+        .await
+    }
+
+    /// Whether `user_id` has blocked `blocked_user_id`.
+    pub async fn has_blocked_user(&self, user_id: UserId, blocked_user_id: UserId) -> Result<bool> {
+        self.transaction(|tx| async move {
+            Ok(blocked_user::Entity::find_by_id((user_id, blocked_user_id))
+                .one(&*tx)
+                .await?
+                .is_some())
+        })
+        .await
+    }
+
+    /// Sets whether `user_id` has blocked `blocked_user_id`, which stops them from
+    /// sending `user_id` contact requests or calling them.
+    pub async fn set_user_blocked(
+        &self,
+        user_id: UserId,
+        blocked_user_id: UserId,
+        blocked: bool,
+    ) -> Result<()> {
+        if user_id == blocked_user_id {
+            Err(anyhow!("cannot block yourself"))?;
+        }
+        self.transaction(|tx| async move {
+            if blocked {
+                blocked_user::Entity::insert(blocked_user::ActiveModel {
+                    user_id: ActiveValue::Set(user_id),
+                    blocked_user_id: ActiveValue::Set(blocked_user_id),
+                })
+                .on_conflict(
+                    OnConflict::columns([
+                        blocked_user::Column::UserId,
+                        blocked_user::Column::BlockedUserId,
+                    ])
+                    .do_nothing()
+                    .to_owned(),
+                )
+                .exec_without_returning(&*tx)
+                .await?;
+            } else {
+                blocked_user::Entity::delete_by_id((user_id, blocked_user_id))
+                    .exec(&*tx)
+                    .await?;
+            }
+            Ok(())
+        })
+        .await
+    }
 }
@@ -0,0 +1,142 @@
+use super::*;
+use rpc::{ErrorCode, ErrorCodeExt};
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+impl Database {
+    /// Creates a guest who can only take part in the given room, as a viewer or an
+    /// editor, until `expires_at`. Guests are invited by editors already in the room,
+    /// and can't invite guests of their own.
+    pub async fn create_guest(
+        &self,
+        room_id: RoomId,
+        inviter_id: UserId,
+        role: ChannelRole,
+        expires_at: OffsetDateTime,
+    ) -> Result<User> {
+        if !matches!(role, ChannelRole::Guest | ChannelRole::Member) {
+            Err(anyhow!("guests can only be viewers or editors"))?;
+        }
+
+        self.transaction(|tx| async move {
+            if guest::Entity::find_by_id(inviter_id)
+                .one(&*tx)
+                .await?
+                .is_some()
+            {
+                Err(ErrorCode::Forbidden
+                    .message("guests cannot invite guests".into())
+                    .anyhow())?;
+            }
+
+            let inviter = room_participant::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(room_participant::Column::RoomId.eq(room_id))
+                        .add(room_participant::Column::UserId.eq(inviter_id))
+                        .add(room_participant::Column::AnsweringConnectionId.is_not_null()),
+                )
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("user is not in the room"))?;
+            if !matches!(
+                inviter.role.unwrap_or(ChannelRole::Member),
+                ChannelRole::Admin | ChannelRole::Member
+            ) {
+                Err(ErrorCode::Forbidden
+                    .message("only editors can invite guests".into())
+                    .anyhow())?;
+            }
+
+            let user = user::Entity::insert(user::ActiveModel {
+                github_login: ActiveValue::set(format!("guest-{}", Uuid::new_v4().simple())),
+                admin: ActiveValue::set(false),
+                metrics_id: ActiveValue::set(Uuid::new_v4()),
+                ..Default::default()
+            })
+            .exec_with_returning(&*tx)
+            .await?;
+            guest::ActiveModel {
+                user_id: ActiveValue::set(user.id),
+                room_id: ActiveValue::set(Some(room_id)),
+                role: ActiveValue::set(role),
+                invited_by_id: ActiveValue::set(inviter_id),
+                expires_at: ActiveValue::set(PrimitiveDateTime::new(
+                    expires_at.date(),
+                    expires_at.time(),
+                )),
+            }
+            .insert(&*tx)
+            .await?;
+            self.record_audit_event(
+                NewAuditEvent {
+                    subject_user_id: Some(user.id),
+                    room_id: Some(room_id),
+                    detail: Some(role.to_value()),
+                    ..NewAuditEvent::new(AuditEventKind::GuestCreated, inviter_id)
+                },
+                &tx,
+            )
+            .await?;
+            Ok(user)
+        })
+        .await
+    }
+
+    /// Returns the guest with the given user ID, or `None` if the user has an account.
+    pub async fn get_guest(&self, user_id: UserId) -> Result<Option<guest::Model>> {
+        self.transaction(|tx| async move {
+            let guest = guest::Entity::find_by_id(user_id).one(&*tx).await?;
+            Ok(guest)
+        })
+        .await
+    }
+
+    /// Deletes the guests whose invitations expired before `now`, along with their
+    /// access tokens, and returns how many were deleted. Guests who are still in a room
+    /// are kept until they've been removed from it.
+    pub async fn delete_expired_guests(&self, now: OffsetDateTime) -> Result<usize> {
+        self.transaction(|tx| async move {
+            let now = PrimitiveDateTime::new(now.date(), now.time());
+            let expired_guest_ids = guest::Entity::find()
+                .filter(guest::Column::ExpiresAt.lte(now))
+                .select_only()
+                .column(guest::Column::UserId)
+                .into_values::<UserId, GuestUserIds>()
+                .all(&*tx)
+                .await?;
+            let participant_ids = room_participant::Entity::find()
+                .filter(room_participant::Column::UserId.is_in(expired_guest_ids.iter().copied()))
+                .select_only()
+                .column(room_participant::Column::UserId)
+                .into_values::<UserId, GuestUserIds>()
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .collect::<HashSet<_>>();
+            let expired_guest_ids = expired_guest_ids
+                .into_iter()
+                .filter(|user_id| !participant_ids.contains(user_id))
+                .collect::<Vec<_>>();
+            if expired_guest_ids.is_empty() {
+                return Ok(0);
+            }
+
+            access_token::Entity::delete_many()
+                .filter(access_token::Column::UserId.is_in(expired_guest_ids.iter().copied()))
+                .exec(&*tx)
+                .await?;
+            // Deleting the users deletes their guest rows too.
+            user::Entity::delete_many()
+                .filter(user::Column::Id.is_in(expired_guest_ids.iter().copied()))
+                .exec(&*tx)
+                .await?;
+            Ok(expired_guest_ids.len())
+        })
+        .await
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+enum GuestUserIds {
+    UserId,
+}
@@ -1,4 +1,8 @@
 use super::*;
+use crate::rpc::quotas;
+use prost::Message as _;
+use rpc::ErrorCode;
+use sea_orm::sea_query::LikeExpr;
 
 impl Database {
     /// Returns the count of all projects, excluding ones marked as admin.
@@ -27,7 +31,10 @@ impl Database {
         &self,
         room_id: RoomId,
         connection: ConnectionId,
+        host_project_id: u64,
         worktrees: &[proto::WorktreeMetadata],
+        policy: Option<&proto::CollabPolicy>,
+        max_projects: usize,
     ) -> Result<RoomGuard<(ProjectId, proto::Room)>> {
         self.room_transaction(room_id, |tx| async move {
             let participant = room_participant::Entity::find()
@@ -56,6 +63,16 @@ impl Database {
                 return Err(anyhow!("guests cannot share projects"))?;
             }
 
+            let project_count = project::Entity::find()
+                .filter(project::Column::RoomId.eq(room_id))
+                .count(&*tx)
+                .await?;
+            quotas::check(
+                ErrorCode::TooManySharedProjects,
+                max_projects,
+                project_count as usize,
+            )?;
+
             let project = project::ActiveModel {
                 room_id: ActiveValue::set(participant.room_id),
                 host_user_id: ActiveValue::set(participant.user_id),
@@ -63,6 +80,17 @@ impl Database {
                 host_connection_server_id: ActiveValue::set(Some(ServerId(
                     connection.owner_id as i32,
                 ))),
+                host_project_id: ActiveValue::set(host_project_id as i64),
+                guests_read_only: ActiveValue::set(policy.map_or(false, |policy| policy.read_only)),
+                allowed_roles: ActiveValue::set(policy.filter(|policy| policy.restrict_roles).map(
+                    |policy| {
+                        policy
+                            .allowed_roles()
+                            .map(|role| ChannelRole::from(role).to_value())
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    },
+                )),
                 ..Default::default()
             }
             .insert(&*tx)
@@ -95,6 +123,15 @@ impl Database {
             }
             .insert(&*tx)
             .await?;
+            self.record_audit_event(
+                NewAuditEvent {
+                    room_id: Some(room_id),
+                    project_id: Some(project.id),
+                    ..NewAuditEvent::new(AuditEventKind::ProjectShared, participant.user_id)
+                },
+                &tx,
+            )
+            .await?;
 
             let room = self.get_room(room_id, &tx).await?;
             Ok((project.id, room))
@@ -117,9 +154,19 @@ impl Database {
                 .await?
                 .ok_or_else(|| anyhow!("project not found"))?;
             if project.host_connection()? == connection {
+                let host_user_id = project.host_user_id;
                 project::Entity::delete(project.into_active_model())
                     .exec(&*tx)
                     .await?;
+                self.record_audit_event(
+                    NewAuditEvent {
+                        room_id: Some(room_id),
+                        project_id: Some(project_id),
+                        ..NewAuditEvent::new(AuditEventKind::ProjectUnshared, host_user_id)
+                    },
+                    &tx,
+                )
+                .await?;
                 let room = self.get_room(room_id, &tx).await?;
                 Ok((room, guest_connection_ids))
             } else {
@@ -537,6 +584,12 @@ impl Database {
             if project.room_id != participant.room_id {
                 return Err(anyhow!("no such project"))?;
             }
+            if !project.can_be_joined_by(participant.role) {
+                return Err(anyhow!(
+                    "the host's policy doesn't allow you to join this project"
+                ))?;
+            }
+            let read_only = project.is_read_only_by_policy_for(participant.role);
 
             let mut collaborators = project
                 .find_related(project_collaborator::Entity)
@@ -557,6 +610,7 @@ impl Database {
                 user_id: ActiveValue::set(participant.user_id),
                 replica_id: ActiveValue::set(replica_id),
                 is_host: ActiveValue::set(false),
+                read_only: ActiveValue::set(read_only),
                 ..Default::default()
             }
             .insert(&*tx)
@@ -696,6 +750,7 @@ impl Database {
                         user_id: collaborator.user_id,
                         replica_id: collaborator.replica_id,
                         is_host: collaborator.is_host,
+                        read_only: collaborator.read_only,
                     })
                     .collect(),
                 worktrees,
@@ -706,6 +761,8 @@ impl Database {
                         name: language_server.name,
                     })
                     .collect(),
+                read_only,
+                observer_visibility: self.observer_visibility(participant.room_id, &tx).await?,
             };
             Ok((project, replica_id as ReplicaId))
         })
@@ -744,9 +801,11 @@ impl Database {
                 .find_related(project_collaborator::Entity)
                 .all(&*tx)
                 .await?;
+            let observer_visibility = self.observer_visibility(project.room_id, &tx).await?;
             let connection_ids = collaborators
                 .into_iter()
                 .map(|collaborator| collaborator.connection())
+                .filter(|collaborator| observer_visibility.can_see(*collaborator, connection))
                 .collect();
 
             follower::Entity::delete_many()
@@ -852,6 +911,134 @@ impl Database {
         .map(|guard| guard.into_inner())
     }
 
+    /// Returns the children of the given directories of a worktree, or all of its
+    /// entries when no directories are given, along with the repositories among
+    /// them, for a guest that loads the worktree's entries lazily.
+    pub async fn load_worktree_directories(
+        &self,
+        project_id: ProjectId,
+        worktree_id: u64,
+        directories: Option<Vec<String>>,
+        connection_id: ConnectionId,
+    ) -> Result<RoomGuard<proto::UpdateWorktree>> {
+        let room_id = self.room_id_for_project(project_id).await?;
+        self.room_transaction(room_id, |tx| async move {
+            project_collaborator::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(project_collaborator::Column::ProjectId.eq(project_id))
+                        .add(project_collaborator::Column::ConnectionId.eq(connection_id.id as i32))
+                        .add(
+                            project_collaborator::Column::ConnectionServerId
+                                .eq(connection_id.owner_id as i32),
+                        ),
+                )
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("no such project collaborator"))?;
+
+            let worktree = worktree::Entity::find_by_id((worktree_id as i64, project_id))
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("no such worktree"))?;
+
+            let mut condition = Condition::all()
+                .add(worktree_entry::Column::ProjectId.eq(project_id))
+                .add(worktree_entry::Column::WorktreeId.eq(worktree_id as i64))
+                .add(worktree_entry::Column::IsDeleted.eq(false));
+            if let Some(directories) = &directories {
+                // Only read the directories' children, not everything beneath them.
+                let mut children = Condition::any();
+                for directory in directories {
+                    if directory.is_empty() {
+                        children = children.add(
+                            Condition::all()
+                                .add(worktree_entry::Column::Path.ne(""))
+                                .add(worktree_entry::Column::Path.not_like("%/%")),
+                        );
+                    } else {
+                        let directory = escape_like_pattern(directory);
+                        children = children.add(
+                            Condition::all()
+                                .add(
+                                    worktree_entry::Column::Path
+                                        .like(LikeExpr::new(format!("{directory}/%")).escape('\\')),
+                                )
+                                .add(worktree_entry::Column::Path.not_like(
+                                    LikeExpr::new(format!("{directory}/%/%")).escape('\\'),
+                                )),
+                        );
+                    }
+                }
+                condition = condition.add(children);
+            }
+
+            let mut updated_entries = Vec::new();
+            let mut db_entries = worktree_entry::Entity::find()
+                .filter(condition)
+                .stream(&*tx)
+                .await?;
+            while let Some(db_entry) = db_entries.next().await {
+                let db_entry = db_entry?;
+                updated_entries.push(proto::Entry {
+                    id: db_entry.id as u64,
+                    is_dir: db_entry.is_dir,
+                    path: db_entry.path,
+                    inode: db_entry.inode as u64,
+                    mtime: Some(proto::Timestamp {
+                        seconds: db_entry.mtime_seconds as u64,
+                        nanos: db_entry.mtime_nanos as u32,
+                    }),
+                    is_symlink: db_entry.is_symlink,
+                    is_ignored: db_entry.is_ignored,
+                    is_external: db_entry.is_external,
+                    git_status: db_entry.git_status.map(|status| status as i32),
+                });
+            }
+            drop(db_entries);
+
+            let updated_repositories = worktree_repository::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(worktree_repository::Column::ProjectId.eq(project_id))
+                        .add(worktree_repository::Column::WorktreeId.eq(worktree_id as i64))
+                        .add(worktree_repository::Column::IsDeleted.eq(false))
+                        .add(
+                            worktree_repository::Column::WorkDirectoryId.is_in(
+                                updated_entries
+                                    .iter()
+                                    .filter(|entry| entry.is_dir)
+                                    .map(|entry| entry.id as i64),
+                            ),
+                        ),
+                )
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .map(|db_repository_entry| proto::RepositoryEntry {
+                    work_directory_id: db_repository_entry.work_directory_id as u64,
+                    branch: db_repository_entry.branch,
+                })
+                .collect();
+
+            Ok(proto::UpdateWorktree {
+                project_id: project_id.to_proto(),
+                worktree_id,
+                abs_path: worktree.abs_path,
+                root_name: worktree.root_name,
+                updated_entries,
+                removed_entries: Vec::new(),
+                scan_id: worktree.scan_id as u64,
+                is_last_update: worktree.scan_id == worktree.completed_scan_id,
+                updated_repositories,
+                removed_repositories: Vec::new(),
+                all_directories_loaded: directories.is_none(),
+                loaded_directories: directories.unwrap_or_default(),
+            })
+        })
+        .await
+    }
+
     /// Returns the host connection for a request to join a shared project.
     pub async fn host_for_mutating_project_request(
         &self,
@@ -867,10 +1054,17 @@ impl Database {
                 .await?
                 .ok_or_else(|| anyhow!("no such room"))?;
 
-            if !current_participant
-                .role
-                .map_or(false, |role| role.can_edit_projects())
-            {
+            let collaborator = project_collaborator::Entity::find()
+                .filter(project_collaborator::Column::ProjectId.eq(project_id))
+                .filter(project_collaborator::Column::ConnectionId.eq(connection_id.id as i32))
+                .filter(
+                    project_collaborator::Column::ConnectionServerId
+                        .eq(connection_id.owner_id as i32),
+                )
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("no such project"))?;
+            if !collaborator.can_edit(current_participant.role) {
                 Err(anyhow!("not authorized to edit projects"))?;
             }
 
@@ -890,6 +1084,132 @@ impl Database {
         .map(|guard| guard.into_inner())
     }
 
+    /// Describes the projects shared in the given room, as seen by the participant
+    /// with the given connection. Only visible worktrees are described.
+    pub async fn shared_projects_in_room(
+        &self,
+        room_id: RoomId,
+        connection_id: ConnectionId,
+    ) -> Result<Vec<proto::SharedProjectMetadata>> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryExtensions {
+            Extension,
+            FileCount,
+        }
+
+        // Trimming every character other than `.` from the end of a path leaves the
+        // path up to and including its last `.`, so extensions can be counted without
+        // loading every entry.
+        const PREFIX: &str = "rtrim(path, replace(path, '.', ''))";
+        let extension = format!("lower(substr(path, length({PREFIX}) + 1))");
+
+        self.transaction(|tx| {
+            let extension = extension.clone();
+            async move {
+                let current_participant = room_participant::Entity::find()
+                    .filter(room_participant::Column::RoomId.eq(room_id))
+                    .filter(
+                        room_participant::Column::AnsweringConnectionId.eq(connection_id.id as i32),
+                    )
+                    .filter(
+                        room_participant::Column::AnsweringConnectionServerId
+                            .eq(connection_id.owner_id as i32),
+                    )
+                    .one(&*tx)
+                    .await?
+                    .ok_or_else(|| anyhow!("no such room"))?;
+
+                if !current_participant
+                    .role
+                    .map_or(false, |role| role.can_read_projects())
+                {
+                    Err(anyhow!("not authorized to read projects"))?;
+                }
+                let db_projects = project::Entity::find()
+                    .filter(project::Column::RoomId.eq(room_id))
+                    .filter(project::Column::HostConnectionId.is_not_null())
+                    .order_by_asc(project::Column::Id)
+                    .all(&*tx)
+                    .await?;
+
+                let mut projects = Vec::new();
+                for db_project in db_projects {
+                    if !db_project.can_be_joined_by(current_participant.role) {
+                        continue;
+                    }
+                    let read_only =
+                        !db_project.can_be_edited_by(current_participant.role, connection_id);
+                    let db_worktrees = worktree::Entity::find()
+                        .filter(worktree::Column::ProjectId.eq(db_project.id))
+                        .filter(worktree::Column::Visible.eq(true))
+                        .order_by_asc(worktree::Column::Id)
+                        .all(&*tx)
+                        .await?;
+                    let worktree_ids = db_worktrees
+                        .iter()
+                        .map(|db_worktree| db_worktree.id)
+                        .collect::<Vec<_>>();
+                    let worktree_root_names = db_worktrees
+                        .into_iter()
+                        .map(|db_worktree| db_worktree.root_name)
+                        .collect();
+
+                    let entries = worktree_entry::Entity::find()
+                        .filter(worktree_entry::Column::ProjectId.eq(db_project.id))
+                        .filter(worktree_entry::Column::WorktreeId.is_in(worktree_ids))
+                        .filter(worktree_entry::Column::IsDeleted.eq(false));
+                    let entry_count = entries.clone().count(&*tx).await?;
+                    let file_counts = entries
+                        .filter(worktree_entry::Column::IsDir.eq(false))
+                        // Skip paths without an extension, including hidden files like
+                        // `.gitignore` and files in directories with a `.` in their name.
+                        .filter(Expr::cust(format!(
+                            "{PREFIX} NOT IN ('', '.') AND {PREFIX} NOT LIKE '%/.'"
+                        )))
+                        .filter(Expr::cust(format!(
+                            "{extension} <> '' AND {extension} NOT LIKE '%/%'"
+                        )))
+                        .select_only()
+                        .column_as(Expr::cust(extension.clone()), QueryExtensions::Extension)
+                        .column_as(
+                            worktree_entry::Column::Id.count(),
+                            QueryExtensions::FileCount,
+                        )
+                        .group_by(Expr::cust(extension.clone()))
+                        .into_values::<(String, i64), QueryExtensions>()
+                        .all(&*tx)
+                        .await?;
+
+                    let mut file_extensions = file_counts
+                        .into_iter()
+                        .map(|(extension, file_count)| proto::FileExtensionCount {
+                            extension,
+                            file_count: file_count as u64,
+                        })
+                        .collect::<Vec<_>>();
+                    file_extensions.sort_by(|a, b| {
+                        b.file_count
+                            .cmp(&a.file_count)
+                            .then_with(|| a.extension.cmp(&b.extension))
+                    });
+
+                    projects.push(proto::SharedProjectMetadata {
+                        id: db_project.id.to_proto(),
+                        host_user_id: db_project.host_user_id.to_proto(),
+                        host_project_id: db_project.host_project_id as u64,
+                        worktree_root_names,
+                        file_extensions,
+                        entry_count,
+                        read_only,
+                    });
+                }
+
+                Ok(projects)
+            }
+        })
+        .await
+    }
+
     pub async fn project_collaborators_for_buffer_update(
         &self,
         project_id: ProjectId,
@@ -905,35 +1225,98 @@ impl Database {
                 .await?
                 .ok_or_else(|| anyhow!("no such room"))?;
 
-            if requires_write
-                && !current_participant
-                    .role
-                    .map_or(false, |role| role.can_edit_projects())
-            {
-                Err(anyhow!("not authorized to edit projects"))?;
-            }
-
             let collaborators = project_collaborator::Entity::find()
                 .filter(project_collaborator::Column::ProjectId.eq(project_id))
                 .all(&*tx)
-                .await?
+                .await?;
+            let current_collaborator = collaborators
+                .iter()
+                .find(|collaborator| collaborator.connection() == connection_id)
+                .ok_or_else(|| anyhow!("no such project"))?;
+            if requires_write && !current_collaborator.can_edit(current_participant.role) {
+                Err(anyhow!("not authorized to edit projects"))?;
+            }
+
+            // Updates from observers, such as their selections, only go to guests
+            // who can see them. The host still gets them, but doesn't know the
+            // observer as a collaborator, so it doesn't show their selections.
+            let observer_visibility = self.observer_visibility(room_id, &tx).await?;
+            let collaborators = collaborators
                 .into_iter()
+                .filter(|collaborator| {
+                    collaborator.is_host
+                        || observer_visibility.can_see(collaborator.connection(), connection_id)
+                })
                 .map(|collaborator| ProjectCollaborator {
                     connection_id: collaborator.connection(),
                     user_id: collaborator.user_id,
                     replica_id: collaborator.replica_id,
                     is_host: collaborator.is_host,
+                    read_only: collaborator.read_only,
                 })
                 .collect::<Vec<_>>();
+            Ok(collaborators)
+        })
+        .await
+    }
 
-            if collaborators
-                .iter()
-                .any(|collaborator| collaborator.connection_id == connection_id)
-            {
-                Ok(collaborators)
-            } else {
-                Err(anyhow!("no such project"))?
+    /// Makes the project read-only or read-write for one of its guests, returning
+    /// the connections of the project's guests so they can be told about it.
+    ///
+    /// Only the project's host can change a guest's access.
+    pub async fn set_project_collaborator_read_only(
+        &self,
+        project_id: ProjectId,
+        host_connection_id: ConnectionId,
+        guest_connection_id: ConnectionId,
+        read_only: bool,
+    ) -> Result<RoomGuard<Vec<ConnectionId>>> {
+        let room_id = self.room_id_for_project(project_id).await?;
+        self.room_transaction(room_id, |tx| async move {
+            let project = project::Entity::find_by_id(project_id)
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("no such project"))?;
+            if project.host_connection()? != host_connection_id {
+                Err(anyhow!("only the host can change a guest's access"))?;
             }
+
+            let guest = project_collaborator::Entity::find()
+                .filter(project_collaborator::Column::ProjectId.eq(project_id))
+                .filter(
+                    project_collaborator::Column::ConnectionId.eq(guest_connection_id.id as i32),
+                )
+                .filter(
+                    project_collaborator::Column::ConnectionServerId
+                        .eq(guest_connection_id.owner_id as i32),
+                )
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("no such collaborator"))?;
+            if guest.is_host {
+                Err(anyhow!("the host can't make their own project read-only"))?;
+            }
+
+            let guest_user_id = guest.user_id;
+            project_collaborator::Entity::update(project_collaborator::ActiveModel {
+                read_only: ActiveValue::set(read_only),
+                ..guest.into_active_model()
+            })
+            .exec(&*tx)
+            .await?;
+            self.record_audit_event(
+                NewAuditEvent {
+                    subject_user_id: Some(guest_user_id),
+                    room_id: Some(room_id),
+                    project_id: Some(project_id),
+                    detail: Some(if read_only { "read_only" } else { "read_write" }.into()),
+                    ..NewAuditEvent::new(AuditEventKind::ProjectAccessChanged, project.host_user_id)
+                },
+                &tx,
+            )
+            .await?;
+
+            self.project_guest_connection_ids(project_id, &tx).await
         })
         .await
     }
@@ -1100,4 +1483,103 @@ impl Database {
         })
         .await
     }
+
+    /// Journals operations on one of a project's buffers that couldn't be delivered
+    /// to its host on the given connection, so that they can be replayed to the host
+    /// when it rejoins. Fails if the host has already rejoined on another connection,
+    /// or if too many operations are waiting for it, in which case the guest has to
+    /// resynchronize the buffer instead.
+    pub async fn journal_buffer_operations(
+        &self,
+        project_id: ProjectId,
+        host_connection_id: ConnectionId,
+        buffer_id: u64,
+        operations: &[proto::Operation],
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            let project = project::Entity::find_by_id(project_id)
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("no such project"))?;
+            if project.host_connection()? != host_connection_id {
+                Err(anyhow!("project host changed while recording operations"))?;
+            }
+
+            let journaled_count = journaled_operation::Entity::find()
+                .filter(journaled_operation::Column::ProjectId.eq(project_id))
+                .count(&*tx)
+                .await? as usize;
+            if journaled_count + operations.len() > MAX_JOURNALED_OPERATIONS {
+                Err(anyhow!("too many operations are waiting for the host"))?;
+            }
+
+            if !operations.is_empty() {
+                journaled_operation::Entity::insert_many(operations.iter().map(|operation| {
+                    journaled_operation::ActiveModel {
+                        project_id: ActiveValue::set(project_id),
+                        buffer_id: ActiveValue::set(buffer_id as i64),
+                        operation: ActiveValue::set(operation.encode_to_vec()),
+                        ..Default::default()
+                    }
+                }))
+                .exec(&*tx)
+                .await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Removes the operations waiting for a project's host, ordered by buffer id,
+    /// so that they can be replayed to it.
+    pub async fn take_journaled_operations(
+        &self,
+        project_id: ProjectId,
+    ) -> Result<Vec<(u64, Vec<proto::Operation>)>> {
+        self.transaction(|tx| async move {
+            let rows = journaled_operation::Entity::find()
+                .filter(journaled_operation::Column::ProjectId.eq(project_id))
+                .order_by_asc(journaled_operation::Column::BufferId)
+                .order_by_asc(journaled_operation::Column::Id)
+                .all(&*tx)
+                .await?;
+            journaled_operation::Entity::delete_many()
+                .filter(journaled_operation::Column::ProjectId.eq(project_id))
+                .exec(&*tx)
+                .await?;
+
+            let mut operations_by_buffer_id = Vec::<(u64, Vec<proto::Operation>)>::new();
+            for row in rows {
+                let operation = proto::Operation::decode(row.operation.as_slice())
+                    .map_err(|error| anyhow!("{}", error))?;
+                let buffer_id = row.buffer_id as u64;
+                match operations_by_buffer_id.last_mut() {
+                    Some((last_buffer_id, operations)) if *last_buffer_id == buffer_id => {
+                        operations.push(operation)
+                    }
+                    _ => operations_by_buffer_id.push((buffer_id, vec![operation])),
+                }
+            }
+            Ok(operations_by_buffer_id)
+        })
+        .await
+    }
 }
+
+/// Escapes the wildcards in a string that's matched literally by a `LIKE` pattern
+/// whose escape character is a backslash.
+fn escape_like_pattern(string: &str) -> String {
+    let mut result = String::with_capacity(string.len());
+    for c in string.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// The most buffer operations that are kept for a project while its host is away.
+/// Guests whose operations don't fit resynchronize their buffers with the host once
+/// it's back instead.
+pub const MAX_JOURNALED_OPERATIONS: usize = 4096;
@@ -0,0 +1,94 @@
+use super::*;
+use time::OffsetDateTime;
+
+impl Database {
+    /// Records an action of the given kind that the user took, unless they've already
+    /// taken `limit` of them since `since`, in which case nothing is recorded and
+    /// `false` is returned. The user's actions from before `since` are forgotten.
+    pub async fn try_rate_limited_action(
+        &self,
+        user_id: UserId,
+        kind: RateLimitedActionKind,
+        target_user_id: Option<UserId>,
+        limit: usize,
+        since: OffsetDateTime,
+    ) -> Result<bool> {
+        self.transaction(|tx| async move {
+            let recent_count = self
+                .recent_rate_limited_actions(user_id, kind, since, &*tx)
+                .await?
+                .len();
+            if recent_count >= limit {
+                return Ok(false);
+            }
+            self.record_rate_limited_action(user_id, kind, target_user_id, &*tx)
+                .await?;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Returns the user's actions of the given kind since `since`, oldest first.
+    pub async fn get_rate_limited_actions(
+        &self,
+        user_id: UserId,
+        kind: RateLimitedActionKind,
+        since: OffsetDateTime,
+    ) -> Result<Vec<rate_limited_action::Model>> {
+        self.transaction(|tx| async move {
+            self.recent_rate_limited_actions(user_id, kind, since, &*tx)
+                .await
+        })
+        .await
+    }
+
+    /// Returns the user's actions of the given kind since `since`, oldest first,
+    /// deleting the ones from before then.
+    pub(crate) async fn recent_rate_limited_actions(
+        &self,
+        user_id: UserId,
+        kind: RateLimitedActionKind,
+        since: OffsetDateTime,
+        tx: &DatabaseTransaction,
+    ) -> Result<Vec<rate_limited_action::Model>> {
+        let since = PrimitiveDateTime::new(since.date(), since.time());
+        rate_limited_action::Entity::delete_many()
+            .filter(
+                Condition::all()
+                    .add(rate_limited_action::Column::UserId.eq(user_id))
+                    .add(rate_limited_action::Column::Kind.eq(kind))
+                    .add(rate_limited_action::Column::CreatedAt.lt(since)),
+            )
+            .exec(tx)
+            .await?;
+        Ok(rate_limited_action::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(rate_limited_action::Column::UserId.eq(user_id))
+                    .add(rate_limited_action::Column::Kind.eq(kind)),
+            )
+            .order_by_asc(rate_limited_action::Column::Id)
+            .all(tx)
+            .await?)
+    }
+
+    pub(crate) async fn record_rate_limited_action(
+        &self,
+        user_id: UserId,
+        kind: RateLimitedActionKind,
+        target_user_id: Option<UserId>,
+        tx: &DatabaseTransaction,
+    ) -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+        rate_limited_action::ActiveModel {
+            user_id: ActiveValue::set(user_id),
+            kind: ActiveValue::set(kind),
+            target_user_id: ActiveValue::set(target_user_id),
+            created_at: ActiveValue::set(PrimitiveDateTime::new(now.date(), now.time())),
+            ..Default::default()
+        }
+        .insert(tx)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+use super::*;
+use sha2::{Digest, Sha256};
+
+impl Database {
+    /// Issues a resume token for every connection to the given server that's in a
+    /// room, replacing any the server issued before. Only hashes of the tokens are
+    /// stored, so the tokens themselves are returned for sending to their connections.
+    pub async fn create_resume_tokens(
+        &self,
+        server_id: ServerId,
+    ) -> Result<HashMap<ConnectionId, String>> {
+        self.transaction(|tx| async move {
+            resume_token::Entity::delete_many()
+                .filter(resume_token::Column::ConnectionServerId.eq(server_id))
+                .exec(&*tx)
+                .await?;
+
+            let participants = room_participant::Entity::find()
+                .filter(room_participant::Column::AnsweringConnectionServerId.eq(server_id))
+                .all(&*tx)
+                .await?;
+            let mut tokens = HashMap::default();
+            for participant in participants {
+                let Some(connection) = participant.answering_connection() else {
+                    continue;
+                };
+                if tokens.contains_key(&connection) {
+                    continue;
+                }
+
+                let token = rpc::auth::random_token();
+                resume_token::ActiveModel {
+                    user_id: ActiveValue::set(participant.user_id),
+                    connection_server_id: ActiveValue::set(server_id),
+                    connection_id: ActiveValue::set(connection.id as i32),
+                    token_hash: ActiveValue::set(hash_resume_token(&token)),
+                    ..Default::default()
+                }
+                .insert(&*tx)
+                .await?;
+                tokens.insert(connection, token);
+            }
+            Ok(tokens)
+        })
+        .await
+    }
+
+    /// Redeems a resume token that was issued to the given user for a connection in
+    /// the given room, returning that connection. Each token can only be redeemed once,
+    /// and tokens that weren't issued to the user for that room are refused.
+    pub async fn take_resume_token(
+        &self,
+        token: &str,
+        user_id: UserId,
+        room_id: RoomId,
+    ) -> Result<ConnectionId> {
+        self.transaction(|tx| async move {
+            let resume_token = resume_token::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(resume_token::Column::TokenHash.eq(hash_resume_token(token)))
+                        .add(resume_token::Column::UserId.eq(user_id)),
+                )
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("invalid resume token"))?;
+            let connection = ConnectionId {
+                owner_id: resume_token.connection_server_id.0 as u32,
+                id: resume_token.connection_id as u32,
+            };
+
+            let participant = room_participant::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(room_participant::Column::RoomId.eq(room_id))
+                        .add(room_participant::Column::UserId.eq(user_id))
+                        .add(
+                            room_participant::Column::AnsweringConnectionId
+                                .eq(connection.id as i32),
+                        )
+                        .add(
+                            room_participant::Column::AnsweringConnectionServerId
+                                .eq(connection.owner_id as i32),
+                        ),
+                )
+                .one(&*tx)
+                .await?;
+            if participant.is_none() {
+                Err(anyhow!("invalid resume token"))?;
+            }
+
+            resume_token::Entity::delete_by_id(resume_token.id)
+                .exec(&*tx)
+                .await?;
+            Ok(connection)
+        })
+        .await
+    }
+
+    /// Whether any connection to a stale server in the given environment holds a
+    /// resume token it hasn't redeemed yet.
+    pub async fn stale_servers_have_resume_tokens(
+        &self,
+        environment: &str,
+        new_server_id: ServerId,
+    ) -> Result<bool> {
+        self.transaction(|tx| async move {
+            let stale_server_ids = self
+                .stale_server_ids_internal(environment, new_server_id, &tx)
+                .await?;
+            let token = resume_token::Entity::find()
+                .filter(resume_token::Column::ConnectionServerId.is_in(stale_server_ids))
+                .one(&*tx)
+                .await?;
+            Ok(token.is_some())
+        })
+        .await
+    }
+
+    /// Deletes the resume tokens issued by stale servers in the given environment, so
+    /// that the connections they were issued for can be cleaned up.
+    pub async fn delete_stale_resume_tokens(
+        &self,
+        environment: &str,
+        new_server_id: ServerId,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            let stale_server_ids = self
+                .stale_server_ids_internal(environment, new_server_id, &tx)
+                .await?;
+            resume_token::Entity::delete_many()
+                .filter(resume_token::Column::ConnectionServerId.is_in(stale_server_ids))
+                .exec(&*tx)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn hash_resume_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
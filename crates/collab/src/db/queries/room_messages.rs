@@ -0,0 +1,195 @@
+use super::*;
+use sea_orm::TryInsertResult;
+use time::OffsetDateTime;
+
+impl Database {
+    /// Saves a chat message sent by a participant of a call, returning the
+    /// connections of the room's other participants so it can be sent to them.
+    ///
+    /// Resending a message with the same nonce returns the original message, and
+    /// no connections to send it to.
+    pub async fn create_room_message(
+        &self,
+        room_id: RoomId,
+        connection: ConnectionId,
+        user_id: UserId,
+        body: &str,
+        timestamp: OffsetDateTime,
+        nonce: u128,
+    ) -> Result<RoomGuard<CreatedRoomMessage>> {
+        self.room_transaction(room_id, |tx| async move {
+            let mut participants = room_participant::Entity::find()
+                .filter(room_participant::Column::RoomId.eq(room_id))
+                .stream(&*tx)
+                .await?;
+
+            let mut is_participant = false;
+            let mut participant_connection_ids = Vec::new();
+            while let Some(participant) = participants.next().await {
+                let participant = participant?;
+                if let Some(answering_connection) = participant.answering_connection() {
+                    if answering_connection == connection {
+                        // Observers are hidden from most of the room, which their
+                        // messages would give away.
+                        if participant.observer {
+                            Err(anyhow!("observers can't send messages"))?;
+                        }
+                        is_participant = true;
+                    } else {
+                        participant_connection_ids.push(answering_connection);
+                    }
+                }
+            }
+            drop(participants);
+
+            if !is_participant {
+                Err(anyhow!("not a room participant"))?;
+            }
+
+            let room = room::Entity::find_by_id(room_id)
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("no such room"))?;
+            let timestamp = timestamp.to_offset(time::UtcOffset::UTC);
+            let timestamp = time::PrimitiveDateTime::new(timestamp.date(), timestamp.time());
+            let result = room_message::Entity::insert(room_message::ActiveModel {
+                id: ActiveValue::NotSet,
+                room_id: ActiveValue::Set(room.channel_id.is_none().then_some(room_id)),
+                channel_id: ActiveValue::Set(room.channel_id),
+                sender_id: ActiveValue::Set(user_id),
+                body: ActiveValue::Set(body.to_string()),
+                sent_at: ActiveValue::Set(timestamp),
+                nonce: ActiveValue::Set(Uuid::from_u128(nonce)),
+            })
+            .on_conflict(
+                OnConflict::columns([room_message::Column::SenderId, room_message::Column::Nonce])
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .do_nothing()
+            .exec(&*tx)
+            .await?;
+
+            let (message_id, participant_connection_ids) = match result {
+                TryInsertResult::Inserted(result) => {
+                    (result.last_insert_id, participant_connection_ids)
+                }
+                _ => {
+                    let message = room_message::Entity::find()
+                        .filter(
+                            Condition::all()
+                                .add(room_message::Column::SenderId.eq(user_id))
+                                .add(room_message::Column::Nonce.eq(Uuid::from_u128(nonce))),
+                        )
+                        .one(&*tx)
+                        .await?
+                        .ok_or_else(|| anyhow!("failed to insert room message"))?;
+                    (message.id, Vec::new())
+                }
+            };
+            let message = room_message::Entity::find_by_id(message_id)
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("failed to insert room message"))?;
+
+            Ok(CreatedRoomMessage {
+                message: room_message_to_proto(message),
+                participant_connection_ids,
+            })
+        })
+        .await
+    }
+
+    /// Retrieves the chat messages of a call, newest last. Calls in a channel share
+    /// the chat of the channel's earlier calls.
+    ///
+    /// Use `before_message_id` to paginate back through the room's messages, and
+    /// `after_message_id` to load the messages sent after a given one, oldest first.
+    pub async fn get_room_messages(
+        &self,
+        room_id: RoomId,
+        connection: ConnectionId,
+        count: usize,
+        before_message_id: Option<RoomMessageId>,
+        after_message_id: Option<RoomMessageId>,
+    ) -> Result<Vec<proto::RoomMessage>> {
+        self.transaction(|tx| async move {
+            room_participant::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(room_participant::Column::RoomId.eq(room_id))
+                        .add(
+                            room_participant::Column::AnsweringConnectionId
+                                .eq(connection.id as i32),
+                        )
+                        .add(
+                            room_participant::Column::AnsweringConnectionServerId
+                                .eq(connection.owner_id as i32),
+                        ),
+                )
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("not a room participant"))?;
+
+            let mut condition = Condition::all().add(self.room_chat(room_id, &*tx).await?);
+            if let Some(before_message_id) = before_message_id {
+                condition = condition.add(room_message::Column::Id.lt(before_message_id));
+            }
+            if let Some(after_message_id) = after_message_id {
+                condition = condition.add(room_message::Column::Id.gt(after_message_id));
+            }
+
+            let query = room_message::Entity::find()
+                .filter(condition)
+                .limit(count as u64);
+            let messages = if after_message_id.is_some() {
+                query
+                    .order_by_asc(room_message::Column::Id)
+                    .all(&*tx)
+                    .await?
+            } else {
+                let mut messages = query
+                    .order_by_desc(room_message::Column::Id)
+                    .all(&*tx)
+                    .await?;
+                messages.reverse();
+                messages
+            };
+            Ok(messages.into_iter().map(room_message_to_proto).collect())
+        })
+        .await
+    }
+
+    /// The condition that selects the messages in the chat of the given room, which
+    /// are its channel's when it has one.
+    pub(crate) async fn room_chat(
+        &self,
+        room_id: RoomId,
+        tx: &DatabaseTransaction,
+    ) -> Result<Condition> {
+        let room = room::Entity::find_by_id(room_id)
+            .one(tx)
+            .await?
+            .ok_or_else(|| anyhow!("no such room"))?;
+        Ok(match room.channel_id {
+            Some(channel_id) => {
+                Condition::all().add(room_message::Column::ChannelId.eq(channel_id))
+            }
+            None => Condition::all().add(room_message::Column::RoomId.eq(room_id)),
+        })
+    }
+}
+
+fn room_message_to_proto(message: room_message::Model) -> proto::RoomMessage {
+    let nonce = message.nonce.as_u64_pair();
+    proto::RoomMessage {
+        id: message.id.to_proto(),
+        sender_id: message.sender_id.to_proto(),
+        body: message.body,
+        timestamp: message.sent_at.assume_utc().unix_timestamp() as u64,
+        nonce: Some(proto::Nonce {
+            upper_half: nonce.0,
+            lower_half: nonce.1,
+        }),
+    }
+}
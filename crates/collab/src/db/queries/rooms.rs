@@ -1,4 +1,7 @@
 use super::*;
+use crate::rpc::quotas;
+use rpc::ErrorCode;
+use time::OffsetDateTime;
 
 impl Database {
     /// Clears all room participants in rooms attached to a stale server.
@@ -13,18 +16,43 @@ impl Database {
                 .add(room_participant::Column::AnsweringConnectionId.is_not_null())
                 .add(room_participant::Column::AnsweringConnectionServerId.ne(new_server_id));
 
-            let stale_participant_user_ids = room_participant::Entity::find()
-                .filter(stale_participant_filter.clone())
+            // Participants whose connections were issued resume tokens can still come
+            // back, so they're kept until the tokens are redeemed or expire.
+            let resumable_connections = resume_token::Entity::find()
+                .filter(resume_token::Column::ConnectionServerId.ne(new_server_id))
                 .all(&*tx)
                 .await?
                 .into_iter()
+                .map(|token| ConnectionId {
+                    owner_id: token.connection_server_id.0 as u32,
+                    id: token.connection_id as u32,
+                })
+                .collect::<HashSet<_>>();
+            let stale_participants = room_participant::Entity::find()
+                .filter(stale_participant_filter)
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .filter(|participant| {
+                    participant
+                        .answering_connection()
+                        .map_or(true, |connection| {
+                            !resumable_connections.contains(&connection)
+                        })
+                })
+                .collect::<Vec<_>>();
+            let stale_participant_user_ids = stale_participants
+                .iter()
                 .map(|participant| participant.user_id)
                 .collect::<Vec<_>>();
 
             // Delete participants who failed to reconnect and cancel their calls.
             let mut canceled_calls_to_user_ids = Vec::new();
             room_participant::Entity::delete_many()
-                .filter(stale_participant_filter)
+                .filter(
+                    room_participant::Column::Id
+                        .is_in(stale_participants.iter().map(|participant| participant.id)),
+                )
                 .exec(&*tx)
                 .await?;
             let called_participants = room_participant::Entity::find()
@@ -50,8 +78,44 @@ impl Database {
                     .into_iter()
                     .map(|participant| participant.user_id),
             );
+            let waiting_calls = waiting_call::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(waiting_call::Column::RoomId.eq(room_id))
+                        .add(
+                            waiting_call::Column::CallingUserId
+                                .is_in(stale_participant_user_ids.iter().copied()),
+                        ),
+                )
+                .all(&*tx)
+                .await?;
+            waiting_call::Entity::delete_many()
+                .filter(
+                    waiting_call::Column::Id
+                        .is_in(waiting_calls.iter().map(|waiting_call| waiting_call.id)),
+                )
+                .exec(&*tx)
+                .await?;
+            canceled_calls_to_user_ids.extend(
+                waiting_calls
+                    .into_iter()
+                    .map(|waiting_call| waiting_call.called_user_id),
+            );
+            self.resolve_call_records(
+                room_id,
+                canceled_calls_to_user_ids.iter().copied(),
+                CallStatus::Missed,
+                &tx,
+            )
+            .await?;
 
             let (channel, room) = self.get_channel_room(room_id, &tx).await?;
+            let notifications = if room.participants.is_empty() {
+                self.summarize_call(room_id, channel.as_ref().map(|channel| channel.id), &tx)
+                    .await?
+            } else {
+                Vec::new()
+            };
             let channel_members;
             if let Some(channel) = &channel {
                 channel_members = self.get_channel_participants(channel, &tx).await?;
@@ -74,6 +138,7 @@ impl Database {
                 channel_members,
                 stale_participant_user_ids,
                 canceled_calls_to_user_ids,
+                notifications,
             })
         })
         .await
@@ -96,7 +161,21 @@ impl Database {
 
             if let Some(pending_participant) = pending_participant {
                 let room = self.get_room(pending_participant.room_id, &tx).await?;
-                Ok(Self::build_incoming_call(&room, user_id))
+                return Ok(Self::build_incoming_call(&room, user_id));
+            }
+
+            let waiting_call = waiting_call::Entity::find()
+                .filter(waiting_call::Column::CalledUserId.eq(user_id))
+                .one(&*tx)
+                .await?;
+            if let Some(waiting_call) = waiting_call {
+                let room = self.get_room(waiting_call.room_id, &tx).await?;
+                Ok(
+                    Self::build_incoming_call(&room, user_id).map(|mut incoming_call| {
+                        incoming_call.waiting = true;
+                        incoming_call
+                    }),
+                )
             } else {
                 Ok(None)
             }
@@ -110,10 +189,14 @@ impl Database {
         user_id: UserId,
         connection: ConnectionId,
         live_kit_room: &str,
+        capabilities: Option<&proto::ParticipantCapabilities>,
     ) -> Result<proto::Room> {
+        let capabilities = room_participant::Capabilities::json_from_proto(capabilities)?;
         self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
             let room = room::ActiveModel {
                 live_kit_room: ActiveValue::set(live_kit_room.into()),
+                created_at: ActiveValue::set(Some(PrimitiveDateTime::new(now.date(), now.time()))),
                 ..Default::default()
             }
             .insert(&*tx)
@@ -133,6 +216,8 @@ impl Database {
                 ))),
                 participant_index: ActiveValue::set(Some(0)),
                 role: ActiveValue::set(Some(ChannelRole::Admin)),
+                capabilities: ActiveValue::set(capabilities.clone()),
+                observer: ActiveValue::set(false),
 
                 id: ActiveValue::NotSet,
                 location_kind: ActiveValue::NotSet,
@@ -141,6 +226,15 @@ impl Database {
             }
             .insert(&*tx)
             .await?;
+            self.record_room_join(room.id, user_id, &tx).await?;
+            self.record_audit_event(
+                NewAuditEvent {
+                    room_id: Some(room.id),
+                    ..NewAuditEvent::new(AuditEventKind::RoomCreated, user_id)
+                },
+                &tx,
+            )
+            .await?;
 
             let room = self.get_room(room.id, &tx).await?;
             Ok(room)
@@ -155,6 +249,8 @@ impl Database {
         calling_connection: ConnectionId,
         called_user_id: UserId,
         initial_project_id: Option<ProjectId>,
+        observer: bool,
+        max_participants: usize,
     ) -> Result<RoomGuard<(proto::Room, proto::IncomingCall)>> {
         self.room_transaction(room_id, |tx| async move {
             let caller = room_participant::Entity::find()
@@ -167,37 +263,131 @@ impl Database {
                 .await?
                 .ok_or_else(|| anyhow!("user is not in the room"))?;
 
-            let called_user_role = match caller.role.unwrap_or(ChannelRole::Member) {
+            let mut called_user_role = match caller.role.unwrap_or(ChannelRole::Member) {
                 ChannelRole::Admin | ChannelRole::Member => ChannelRole::Member,
                 ChannelRole::Guest => ChannelRole::Guest,
                 ChannelRole::Banned => return Err(anyhow!("banned users cannot invite").into()),
             };
 
-            room_participant::ActiveModel {
-                room_id: ActiveValue::set(room_id),
-                user_id: ActiveValue::set(called_user_id),
-                answering_connection_lost: ActiveValue::set(false),
-                participant_index: ActiveValue::NotSet,
-                calling_user_id: ActiveValue::set(calling_user_id),
-                calling_connection_id: ActiveValue::set(calling_connection.id as i32),
-                calling_connection_server_id: ActiveValue::set(Some(ServerId(
-                    calling_connection.owner_id as i32,
-                ))),
-                initial_project_id: ActiveValue::set(initial_project_id),
-                role: ActiveValue::set(Some(called_user_role)),
+            // Guests can only be called into the room they were invited to, and keep
+            // the role they were given there.
+            if let Some(guest) = guest::Entity::find_by_id(called_user_id).one(&*tx).await? {
+                if guest.room_id != Some(room_id) {
+                    return Err(anyhow!("guests can only be called into their own room").into());
+                }
+                called_user_role = guest.role;
+            }
 
-                id: ActiveValue::NotSet,
-                answering_connection_id: ActiveValue::NotSet,
-                answering_connection_server_id: ActiveValue::NotSet,
-                location_kind: ActiveValue::NotSet,
-                location_project_id: ActiveValue::NotSet,
+            // Observers watch the room without being able to edit in it, and are
+            // hidden from everyone but the room's admins and its other observers.
+            if observer {
+                if caller.role != Some(ChannelRole::Admin) {
+                    return Err(anyhow!("only the room's admins can invite observers").into());
+                }
+                let db_room = room::Entity::find_by_id(room_id)
+                    .one(&*tx)
+                    .await?
+                    .ok_or_else(|| anyhow!("no such room"))?;
+                if db_room.channel_id.is_some() {
+                    return Err(anyhow!("channel rooms can't have observers").into());
+                }
+                called_user_role = ChannelRole::Guest;
             }
-            .insert(&*tx)
-            .await?;
+
+            // Calling someone again replaces the call that was waiting for them, since
+            // they may have left their other call in the meantime.
+            let result = waiting_call::Entity::delete_many()
+                .filter(
+                    Condition::all()
+                        .add(waiting_call::Column::RoomId.eq(room_id))
+                        .add(waiting_call::Column::CalledUserId.eq(called_user_id)),
+                )
+                .exec(&*tx)
+                .await?;
+            if result.rows_affected > 0 {
+                self.resolve_call_records(room_id, [called_user_id], CallStatus::Missed, &tx)
+                    .await?;
+            }
+
+            // Users who are already in a call elsewhere get a waiting call, which rings
+            // alongside their current call until they decline it or switch to it.
+            let called_participant = room_participant::Entity::find()
+                .filter(room_participant::Column::UserId.eq(called_user_id))
+                .one(&*tx)
+                .await?;
+            let waiting = match called_participant {
+                None => false,
+                Some(participant) if participant.room_id == room_id => {
+                    return Err(anyhow!("user is already in the room").into());
+                }
+                Some(participant) if participant.answering_connection_id.is_none() => {
+                    return Err(anyhow!("user is already being called").into());
+                }
+                Some(_) if observer => {
+                    return Err(anyhow!("user is busy").into());
+                }
+                Some(_) => {
+                    let db_room = room::Entity::find_by_id(room_id)
+                        .one(&*tx)
+                        .await?
+                        .ok_or_else(|| anyhow!("no such room"))?;
+                    if db_room.channel_id.is_some() {
+                        return Err(anyhow!("user is busy").into());
+                    }
+                    true
+                }
+            };
+
+            self.check_room_participant_quota(room_id, called_user_id, max_participants, &tx)
+                .await?;
+
+            if waiting {
+                waiting_call::ActiveModel {
+                    room_id: ActiveValue::set(room_id),
+                    called_user_id: ActiveValue::set(called_user_id),
+                    calling_user_id: ActiveValue::set(calling_user_id),
+                    calling_connection_id: ActiveValue::set(calling_connection.id as i32),
+                    calling_connection_server_id: ActiveValue::set(Some(ServerId(
+                        calling_connection.owner_id as i32,
+                    ))),
+                    initial_project_id: ActiveValue::set(initial_project_id),
+                    role: ActiveValue::set(Some(called_user_role)),
+                    id: ActiveValue::NotSet,
+                }
+                .insert(&*tx)
+                .await?;
+            } else {
+                room_participant::ActiveModel {
+                    room_id: ActiveValue::set(room_id),
+                    user_id: ActiveValue::set(called_user_id),
+                    answering_connection_lost: ActiveValue::set(false),
+                    participant_index: ActiveValue::NotSet,
+                    calling_user_id: ActiveValue::set(calling_user_id),
+                    calling_connection_id: ActiveValue::set(calling_connection.id as i32),
+                    calling_connection_server_id: ActiveValue::set(Some(ServerId(
+                        calling_connection.owner_id as i32,
+                    ))),
+                    initial_project_id: ActiveValue::set(initial_project_id),
+                    role: ActiveValue::set(Some(called_user_role)),
+                    observer: ActiveValue::set(observer),
+
+                    id: ActiveValue::NotSet,
+                    answering_connection_id: ActiveValue::NotSet,
+                    answering_connection_server_id: ActiveValue::NotSet,
+                    location_kind: ActiveValue::NotSet,
+                    location_project_id: ActiveValue::NotSet,
+                    capabilities: ActiveValue::NotSet,
+                }
+                .insert(&*tx)
+                .await?;
+            }
+            self.create_call_record(room_id, calling_user_id, called_user_id, &tx)
+                .await?;
 
             let room = self.get_room(room_id, &tx).await?;
-            let incoming_call = Self::build_incoming_call(&room, called_user_id)
+            let mut incoming_call = Self::build_incoming_call(&room, called_user_id)
                 .ok_or_else(|| anyhow!("failed to build incoming call"))?;
+            incoming_call.waiting = waiting;
             Ok((room, incoming_call))
         })
         .await
@@ -217,6 +407,16 @@ impl Database {
                 )
                 .exec(&*tx)
                 .await?;
+            waiting_call::Entity::delete_many()
+                .filter(
+                    waiting_call::Column::RoomId
+                        .eq(room_id)
+                        .and(waiting_call::Column::CalledUserId.eq(called_user_id)),
+                )
+                .exec(&*tx)
+                .await?;
+            self.resolve_call_records(room_id, [called_user_id], CallStatus::Missed, &tx)
+                .await?;
             let room = self.get_room(room_id, &tx).await?;
             Ok(room)
         })
@@ -240,17 +440,43 @@ impl Database {
                 .one(&*tx)
                 .await?;
 
-            let participant = if let Some(participant) = participant {
-                participant
-            } else if expected_room_id.is_some() {
-                return Err(anyhow!("could not find call to decline"))?;
+            let room_id = if let Some(participant) = participant {
+                let room_id = participant.room_id;
+                room_participant::Entity::delete(participant.into_active_model())
+                    .exec(&*tx)
+                    .await?;
+                room_id
             } else {
-                return Ok(None);
+                let mut filter =
+                    Condition::all().add(waiting_call::Column::CalledUserId.eq(user_id));
+                if let Some(room_id) = expected_room_id {
+                    filter = filter.add(waiting_call::Column::RoomId.eq(room_id));
+                }
+                let waiting_call = waiting_call::Entity::find()
+                    .filter(filter)
+                    .one(&*tx)
+                    .await?;
+
+                if let Some(waiting_call) = waiting_call {
+                    let room_id = waiting_call.room_id;
+                    waiting_call::Entity::delete_by_id(waiting_call.id)
+                        .exec(&*tx)
+                        .await?;
+                    room_id
+                } else if expected_room_id.is_some() {
+                    return Err(anyhow!("could not find call to decline"))?;
+                } else {
+                    return Ok(None);
+                }
             };
 
-            let room_id = participant.room_id;
-            room_participant::Entity::delete(participant.into_active_model())
-                .exec(&*tx)
+            // Calls are only declined without a room id when the callee went offline.
+            let status = if expected_room_id.is_some() {
+                CallStatus::Declined
+            } else {
+                CallStatus::Missed
+            };
+            self.resolve_call_records(room_id, [user_id], status, &tx)
                 .await?;
 
             let room = self.get_room(room_id, &tx).await?;
@@ -282,11 +508,34 @@ impl Database {
                         .add(room_participant::Column::AnsweringConnectionId.is_null()),
                 )
                 .one(&*tx)
-                .await?
-                .ok_or_else(|| anyhow!("no call to cancel"))?;
+                .await?;
 
-            room_participant::Entity::delete(participant.into_active_model())
-                .exec(&*tx)
+            if let Some(participant) = participant {
+                room_participant::Entity::delete(participant.into_active_model())
+                    .exec(&*tx)
+                    .await?;
+            } else {
+                let result = waiting_call::Entity::delete_many()
+                    .filter(
+                        Condition::all()
+                            .add(waiting_call::Column::CalledUserId.eq(called_user_id))
+                            .add(waiting_call::Column::RoomId.eq(room_id))
+                            .add(
+                                waiting_call::Column::CallingConnectionId
+                                    .eq(calling_connection.id as i32),
+                            )
+                            .add(
+                                waiting_call::Column::CallingConnectionServerId
+                                    .eq(calling_connection.owner_id as i32),
+                            ),
+                    )
+                    .exec(&*tx)
+                    .await?;
+                if result.rows_affected == 0 {
+                    Err(anyhow!("no call to cancel"))?;
+                }
+            }
+            self.resolve_call_records(room_id, [called_user_id], CallStatus::Missed, &tx)
                 .await?;
 
             let room = self.get_room(room_id, &tx).await?;
@@ -300,23 +549,16 @@ impl Database {
         room_id: RoomId,
         user_id: UserId,
         connection: ConnectionId,
+        capabilities: Option<&proto::ParticipantCapabilities>,
     ) -> Result<RoomGuard<JoinRoom>> {
+        let capabilities = room_participant::Capabilities::json_from_proto(capabilities)?;
         self.room_transaction(room_id, |tx| async move {
-            #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
-            enum QueryChannelId {
-                ChannelId,
-            }
-
-            let channel_id: Option<ChannelId> = room::Entity::find()
-                .select_only()
-                .column(room::Column::ChannelId)
-                .filter(room::Column::Id.eq(room_id))
-                .into_values::<_, QueryChannelId>()
+            let db_room = room::Entity::find_by_id(room_id)
                 .one(&*tx)
                 .await?
                 .ok_or_else(|| anyhow!("no such room"))?;
 
-            if channel_id.is_some() {
+            if db_room.channel_id.is_some() {
                 Err(anyhow!("tried to join channel call directly"))?
             }
 
@@ -338,6 +580,7 @@ impl Database {
                         connection.owner_id as i32,
                     ))),
                     answering_connection_lost: ActiveValue::set(false),
+                    capabilities: ActiveValue::set(capabilities.clone()),
                     ..Default::default()
                 })
                 .exec(&*tx)
@@ -345,17 +588,197 @@ impl Database {
             if result.rows_affected == 0 {
                 Err(anyhow!("room does not exist or was already joined"))?;
             }
+            self.resolve_call_records(room_id, [user_id], CallStatus::Answered, &tx)
+                .await?;
+            self.record_room_join(room_id, user_id, &tx).await?;
+            self.record_audit_event(
+                NewAuditEvent {
+                    room_id: Some(room_id),
+                    ..NewAuditEvent::new(AuditEventKind::RoomJoined, user_id)
+                },
+                &tx,
+            )
+            .await?;
 
             let room = self.get_room(room_id, &tx).await?;
             Ok(JoinRoom {
                 room,
                 channel_id: None,
                 channel_members: vec![],
+                ice_servers: db_room.ice_servers,
+                room_created: false,
             })
         })
         .await
     }
 
+    /// Hangs up the user's current call on the given connection, if any, and joins
+    /// the room of a call that was waiting for them, in a single transaction that
+    /// holds the locks of both rooms.
+    pub async fn accept_waiting_call(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        connection: ConnectionId,
+        capabilities: Option<&proto::ParticipantCapabilities>,
+    ) -> Result<RoomGuard<(JoinRoom, Option<LeftRoom>)>> {
+        let capabilities = room_participant::Capabilities::json_from_proto(capabilities)?;
+        loop {
+            // The room being left is only known from the database, so it's looked up
+            // before taking the locks, and the transaction starts over if the
+            // connection moved to another room in the meantime.
+            let left_room_id = self
+                .transaction(|tx| async move {
+                    self.room_id_for_connection_internal(connection, &tx).await
+                })
+                .await?;
+            let room_ids = [Some(room_id), left_room_id]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>();
+            let accepted = self
+                .rooms_transaction(&room_ids, |tx| {
+                    let capabilities = capabilities.clone();
+                    async move {
+                        let current_room_id = self
+                            .room_id_for_connection_internal(connection, &tx)
+                            .await?;
+                        if current_room_id != left_room_id {
+                            return Ok(None);
+                        }
+                        self.accept_waiting_call_internal(
+                            room_id,
+                            user_id,
+                            connection,
+                            capabilities,
+                            &tx,
+                        )
+                        .await
+                        .map(Some)
+                    }
+                })
+                .await?;
+            if accepted.is_some() {
+                return Ok(accepted.map(Option::unwrap));
+            }
+        }
+    }
+
+    async fn accept_waiting_call_internal(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        connection: ConnectionId,
+        capabilities: Option<String>,
+        tx: &DatabaseTransaction,
+    ) -> Result<(JoinRoom, Option<LeftRoom>)> {
+        let waiting_call = waiting_call::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(waiting_call::Column::RoomId.eq(room_id))
+                    .add(waiting_call::Column::CalledUserId.eq(user_id)),
+            )
+            .one(&*tx)
+            .await?
+            .ok_or_else(|| anyhow!("no waiting call to accept"))?;
+        let db_room = room::Entity::find_by_id(room_id)
+            .one(&*tx)
+            .await?
+            .ok_or_else(|| anyhow!("no such room"))?;
+        if db_room.channel_id.is_some() {
+            Err(anyhow!("tried to join channel call directly"))?
+        }
+
+        waiting_call::Entity::delete_by_id(waiting_call.id)
+            .exec(&*tx)
+            .await?;
+        let left_room = self
+            .leave_room_internal(connection, &tx)
+            .await?
+            .map(|(_, left_room)| left_room);
+
+        let busy = room_participant::Entity::find()
+            .filter(room_participant::Column::UserId.eq(user_id))
+            .one(&*tx)
+            .await?
+            .is_some();
+        if busy {
+            Err(anyhow!("user is already in another call"))?
+        }
+
+        let participant_index = self
+            .get_next_participant_index_internal(room_id, &*tx)
+            .await?;
+        room_participant::ActiveModel {
+            room_id: ActiveValue::set(room_id),
+            user_id: ActiveValue::set(user_id),
+            answering_connection_id: ActiveValue::set(Some(connection.id as i32)),
+            answering_connection_server_id: ActiveValue::set(Some(ServerId(
+                connection.owner_id as i32,
+            ))),
+            answering_connection_lost: ActiveValue::set(false),
+            calling_user_id: ActiveValue::set(waiting_call.calling_user_id),
+            calling_connection_id: ActiveValue::set(waiting_call.calling_connection_id),
+            calling_connection_server_id: ActiveValue::set(
+                waiting_call.calling_connection_server_id,
+            ),
+            initial_project_id: ActiveValue::set(waiting_call.initial_project_id),
+            participant_index: ActiveValue::set(Some(participant_index)),
+            role: ActiveValue::set(waiting_call.role),
+            capabilities: ActiveValue::set(capabilities),
+            observer: ActiveValue::set(false),
+
+            id: ActiveValue::NotSet,
+            location_kind: ActiveValue::NotSet,
+            location_project_id: ActiveValue::NotSet,
+        }
+        .insert(&*tx)
+        .await?;
+        self.resolve_call_records(room_id, [user_id], CallStatus::Answered, &tx)
+            .await?;
+        self.record_room_join(room_id, user_id, &tx).await?;
+        self.record_audit_event(
+            NewAuditEvent {
+                room_id: Some(room_id),
+                ..NewAuditEvent::new(AuditEventKind::RoomJoined, user_id)
+            },
+            &tx,
+        )
+        .await?;
+
+        let room = self.get_room(room_id, &tx).await?;
+        Ok((
+            JoinRoom {
+                room,
+                channel_id: None,
+                channel_members: vec![],
+                ice_servers: db_room.ice_servers,
+                room_created: false,
+            },
+            left_room,
+        ))
+    }
+
+    /// The room that the given connection is a participant in, if any.
+    async fn room_id_for_connection_internal(
+        &self,
+        connection: ConnectionId,
+        tx: &DatabaseTransaction,
+    ) -> Result<Option<RoomId>> {
+        let participant = room_participant::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(room_participant::Column::AnsweringConnectionId.eq(connection.id as i32))
+                    .add(
+                        room_participant::Column::AnsweringConnectionServerId
+                            .eq(connection.owner_id as i32),
+                    ),
+            )
+            .one(&*tx)
+            .await?;
+        Ok(participant.map(|participant| participant.room_id))
+    }
+
     async fn get_next_participant_index_internal(
         &self,
         room_id: RoomId,
@@ -385,6 +808,262 @@ impl Database {
         Ok(participant_index)
     }
 
+    /// Overrides the ICE servers that participants who join the given room are
+    /// told to use, which must already be encrypted. `None` reverts the room to
+    /// the deployment's servers.
+    pub async fn set_room_ice_servers(
+        &self,
+        room_id: RoomId,
+        ice_servers: Option<&str>,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            let result = room::Entity::update_many()
+                .filter(room::Column::Id.eq(room_id))
+                .set(room::ActiveModel {
+                    ice_servers: ActiveValue::set(ice_servers.map(str::to_string)),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?;
+            if result.rows_affected == 0 {
+                Err(anyhow!("no such room"))?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Makes the room end `duration` from now. Only the room's admins can time-box
+    /// it, and only if it isn't a channel's room.
+    pub async fn time_box_room(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        duration: Duration,
+    ) -> Result<RoomGuard<TimeBoxedRoom>> {
+        let now = OffsetDateTime::now_utc();
+        let now = now - time::Duration::nanoseconds(now.nanosecond() as i64);
+        let ends_at = PrimitiveDateTime::new(now.date(), now.time()) + duration;
+        self.room_transaction(room_id, |tx| async move {
+            self.set_room_ends_at(room_id, user_id, |_| Ok(ends_at), &tx)
+                .await
+        })
+        .await
+    }
+
+    /// Pushes back the end of a time-boxed room by `duration`.
+    pub async fn extend_room(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        duration: Duration,
+    ) -> Result<RoomGuard<TimeBoxedRoom>> {
+        self.room_transaction(room_id, |tx| async move {
+            self.set_room_ends_at(
+                room_id,
+                user_id,
+                |ends_at| Ok(ends_at.ok_or_else(|| anyhow!("room is not time-boxed"))? + duration),
+                &tx,
+            )
+            .await
+        })
+        .await
+    }
+
+    async fn set_room_ends_at(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        ends_at: impl FnOnce(Option<PrimitiveDateTime>) -> Result<PrimitiveDateTime>,
+        tx: &DatabaseTransaction,
+    ) -> Result<TimeBoxedRoom> {
+        room_participant::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(room_participant::Column::RoomId.eq(room_id))
+                    .add(room_participant::Column::UserId.eq(user_id))
+                    .add(room_participant::Column::Role.eq(ChannelRole::Admin)),
+            )
+            .one(tx)
+            .await?
+            .ok_or_else(|| anyhow!("only admins can change when the room ends"))?;
+        let room = room::Entity::find_by_id(room_id)
+            .one(tx)
+            .await?
+            .ok_or_else(|| anyhow!("could not find room"))?;
+        if room.channel_id.is_some() {
+            Err(anyhow!("channel rooms can't be time-boxed"))?;
+        }
+
+        let ends_at = ends_at(room.ends_at)?;
+        if let Some(created_at) = room.created_at {
+            if ends_at - created_at > MAX_TIME_BOXED_ROOM_LIFETIME {
+                Err(anyhow!(
+                    "rooms can't last longer than {} hours",
+                    MAX_TIME_BOXED_ROOM_LIFETIME.whole_hours()
+                ))?;
+            }
+        }
+        room::Entity::update(room::ActiveModel {
+            ends_at: ActiveValue::set(Some(ends_at)),
+            ..room.into_active_model()
+        })
+        .exec(tx)
+        .await?;
+        Ok(TimeBoxedRoom {
+            room: self.get_room(room_id, tx).await?,
+            ends_at: ends_at.assume_utc(),
+        })
+    }
+
+    /// Returns the rooms that end at a fixed time, and when they do.
+    pub async fn time_boxed_rooms(&self) -> Result<Vec<(RoomId, OffsetDateTime)>> {
+        self.transaction(|tx| async move {
+            let rooms = room::Entity::find()
+                .filter(room::Column::EndsAt.is_not_null())
+                .all(&*tx)
+                .await?;
+            Ok(rooms
+                .into_iter()
+                .filter_map(|room| Some((room.id, room.ends_at?.assume_utc())))
+                .collect())
+        })
+        .await
+    }
+
+    /// Returns the connections of a time-boxed room's participants, or none if the
+    /// room no longer ends at `ends_at`.
+    pub async fn time_boxed_room_connection_ids(
+        &self,
+        room_id: RoomId,
+        ends_at: OffsetDateTime,
+    ) -> Result<Vec<ConnectionId>> {
+        let ends_at = PrimitiveDateTime::new(ends_at.date(), ends_at.time());
+        self.transaction(|tx| async move {
+            let Some(room) = room::Entity::find_by_id(room_id).one(&*tx).await? else {
+                return Ok(Vec::new());
+            };
+            if room.ends_at != Some(ends_at) {
+                return Ok(Vec::new());
+            }
+
+            let participants = room_participant::Entity::find()
+                .filter(room_participant::Column::RoomId.eq(room_id))
+                .all(&*tx)
+                .await?;
+            Ok(participants
+                .iter()
+                .filter_map(|participant| participant.answering_connection())
+                .collect())
+        })
+        .await
+    }
+
+    /// Removes everyone from a time-boxed room whose time is up, which deletes it.
+    /// Nothing happens if the room was extended, so that it no longer ends at `ends_at`.
+    pub async fn end_room(
+        &self,
+        room_id: RoomId,
+        ends_at: OffsetDateTime,
+    ) -> Result<Option<RoomGuard<EndedRoom>>> {
+        let ends_at = PrimitiveDateTime::new(ends_at.date(), ends_at.time());
+        self.optional_room_transaction(|tx| async move {
+            let Some(room) = room::Entity::find_by_id(room_id).one(&*tx).await? else {
+                return Ok(None);
+            };
+            if room.ends_at != Some(ends_at) {
+                return Ok(None);
+            }
+
+            Ok(Some((room_id, self.end_room_internal(room, &tx).await?)))
+        })
+        .await
+    }
+
+    /// Removes everyone from the room, which deletes it. Returns `None` if there's no
+    /// such room.
+    pub async fn terminate_room(&self, room_id: RoomId) -> Result<Option<RoomGuard<EndedRoom>>> {
+        self.optional_room_transaction(|tx| async move {
+            let Some(room) = room::Entity::find_by_id(room_id).one(&*tx).await? else {
+                return Ok(None);
+            };
+            Ok(Some((room_id, self.end_room_internal(room, &tx).await?)))
+        })
+        .await
+    }
+
+    async fn end_room_internal(
+        &self,
+        room: room::Model,
+        tx: &DatabaseTransaction,
+    ) -> Result<EndedRoom> {
+        let participants = room_participant::Entity::find()
+            .filter(room_participant::Column::RoomId.eq(room.id))
+            .all(tx)
+            .await?;
+        let mut ended_room = EndedRoom {
+            channel_id: room.channel_id,
+            live_kit_room: room.live_kit_room,
+            ..Default::default()
+        };
+        for participant in participants {
+            let Some(connection) = participant.answering_connection() else {
+                continue;
+            };
+            if let Some((_, left_room)) = self.leave_room_internal(connection, tx).await? {
+                ended_room.connection_ids.push(connection);
+                ended_room.participant_user_ids.push(participant.user_id);
+                ended_room
+                    .canceled_calls_to_user_ids
+                    .extend(left_room.canceled_calls_to_user_ids);
+                ended_room.channel_members = left_room.channel_members;
+                ended_room.notifications.extend(left_room.notifications);
+            }
+        }
+        Ok(ended_room)
+    }
+
+    /// Lists every room, along with who's in it and who's being called into it.
+    pub async fn list_rooms(&self) -> Result<Vec<RoomListing>> {
+        self.transaction(|tx| async move {
+            let rooms = room::Entity::find()
+                .order_by_asc(room::Column::Id)
+                .all(&*tx)
+                .await?;
+            let mut participants = room_participant::Entity::find()
+                .order_by_asc(room_participant::Column::Id)
+                .stream(&*tx)
+                .await?;
+            let mut listings = rooms
+                .into_iter()
+                .map(|room| {
+                    (
+                        room.id,
+                        RoomListing {
+                            id: room.id,
+                            channel_id: room.channel_id,
+                            participant_user_ids: Vec::new(),
+                            pending_user_ids: Vec::new(),
+                            ends_at: room.ends_at,
+                        },
+                    )
+                })
+                .collect::<BTreeMap<_, _>>();
+            while let Some(participant) = participants.next().await {
+                let participant = participant?;
+                if let Some(listing) = listings.get_mut(&participant.room_id) {
+                    if participant.answering_connection_id.is_some() {
+                        listing.participant_user_ids.push(participant.user_id);
+                    } else {
+                        listing.pending_user_ids.push(participant.user_id);
+                    }
+                }
+            }
+            Ok(listings.into_values().collect())
+        })
+        .await
+    }
+
     /// Returns the channel ID for the given room, if it has one.
     pub async fn channel_id_for_room(&self, room_id: RoomId) -> Result<Option<ChannelId>> {
         self.transaction(|tx| async move {
@@ -398,12 +1077,36 @@ impl Database {
         .await
     }
 
+    /// Fails if the room can't take another participant under the given quota, counting
+    /// those being called into it but not the given user. This is checked in the same
+    /// transaction as the participant is added in, and as transactions are serializable,
+    /// concurrent calls and joins can't all pass it.
+    pub(crate) async fn check_room_participant_quota(
+        &self,
+        room_id: RoomId,
+        except_user_id: UserId,
+        quota: usize,
+        tx: &DatabaseTransaction,
+    ) -> Result<()> {
+        let count = room_participant::Entity::find()
+            .filter(
+                room_participant::Column::RoomId
+                    .eq(room_id)
+                    .and(room_participant::Column::UserId.ne(except_user_id)),
+            )
+            .count(tx)
+            .await?;
+        quotas::check(ErrorCode::RoomFull, quota, count as usize)?;
+        Ok(())
+    }
+
     pub(crate) async fn join_channel_room_internal(
         &self,
         room_id: RoomId,
         user_id: UserId,
         connection: ConnectionId,
         role: ChannelRole,
+        capabilities: Option<String>,
         tx: &DatabaseTransaction,
     ) -> Result<JoinRoom> {
         let participant_index = self
@@ -425,6 +1128,8 @@ impl Database {
             ))),
             participant_index: ActiveValue::Set(Some(participant_index)),
             role: ActiveValue::set(Some(role)),
+            capabilities: ActiveValue::set(capabilities),
+            observer: ActiveValue::set(false),
             id: ActiveValue::NotSet,
             location_kind: ActiveValue::NotSet,
             location_project_id: ActiveValue::NotSet,
@@ -438,19 +1143,36 @@ impl Database {
                     room_participant::Column::AnsweringConnectionLost,
                     room_participant::Column::ParticipantIndex,
                     room_participant::Column::Role,
+                    room_participant::Column::Capabilities,
                 ])
                 .to_owned(),
         )
         .exec(&*tx)
         .await?;
+        self.record_room_join(room_id, user_id, &tx).await?;
 
         let (channel, room) = self.get_channel_room(room_id, &tx).await?;
         let channel = channel.ok_or_else(|| anyhow!("no channel for room"))?;
+        self.record_audit_event(
+            NewAuditEvent {
+                room_id: Some(room_id),
+                channel_id: Some(channel.id),
+                ..NewAuditEvent::new(AuditEventKind::RoomJoined, user_id)
+            },
+            &tx,
+        )
+        .await?;
         let channel_members = self.get_channel_participants(&channel, &*tx).await?;
+        let ice_servers = room::Entity::find_by_id(room_id)
+            .one(&*tx)
+            .await?
+            .and_then(|room| room.ice_servers);
         Ok(JoinRoom {
             room,
             channel_id: Some(channel.id),
             channel_members,
+            ice_servers,
+            room_created: false,
         })
     }
 
@@ -492,6 +1214,40 @@ impl Database {
                 return Err(anyhow!("room does not exist or was already joined"))?;
             }
 
+            // Calls the user placed before losing their connection keep ringing, and
+            // can be canceled from the connection they rejoined on.
+            room_participant::Entity::update_many()
+                .filter(
+                    Condition::all()
+                        .add(room_participant::Column::RoomId.eq(room_id))
+                        .add(room_participant::Column::CallingUserId.eq(user_id))
+                        .add(room_participant::Column::AnsweringConnectionId.is_null()),
+                )
+                .set(room_participant::ActiveModel {
+                    calling_connection_id: ActiveValue::set(connection.id as i32),
+                    calling_connection_server_id: ActiveValue::set(Some(ServerId(
+                        connection.owner_id as i32,
+                    ))),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?;
+            waiting_call::Entity::update_many()
+                .filter(
+                    Condition::all()
+                        .add(waiting_call::Column::RoomId.eq(room_id))
+                        .add(waiting_call::Column::CallingUserId.eq(user_id)),
+                )
+                .set(waiting_call::ActiveModel {
+                    calling_connection_id: ActiveValue::set(connection.id as i32),
+                    calling_connection_server_id: ActiveValue::set(Some(ServerId(
+                        connection.owner_id as i32,
+                    ))),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?;
+
             let mut reshared_projects = Vec::new();
             for reshared_project in &rejoin_room.reshared_projects {
                 let project_id = ProjectId::from_proto(reshared_project.project_id);
@@ -546,6 +1302,7 @@ impl Database {
                             user_id: collaborator.user_id,
                             replica_id: collaborator.replica_id,
                             is_host: collaborator.is_host,
+                            read_only: collaborator.read_only,
                         })
                         .collect(),
                     worktrees: reshared_project.worktrees.clone(),
@@ -731,6 +1488,7 @@ impl Database {
                         user_id: collaborator.user_id,
                         replica_id: collaborator.replica_id,
                         is_host: collaborator.is_host,
+                        read_only: collaborator.read_only,
                     })
                     .collect::<Vec<_>>();
 
@@ -756,6 +1514,7 @@ impl Database {
                 channel_members,
                 rejoined_projects,
                 reshared_projects,
+                observer_visibility: self.observer_visibility(room_id, &tx).await?,
             })
         })
         .await
@@ -766,174 +1525,212 @@ impl Database {
         connection: ConnectionId,
     ) -> Result<Option<RoomGuard<LeftRoom>>> {
         self.optional_room_transaction(|tx| async move {
-            let leaving_participant = room_participant::Entity::find()
+            self.leave_room_internal(connection, &tx).await
+        })
+        .await
+    }
+
+    /// Removes the participant with the given connection from their room, returning
+    /// what changed so callers can notify the room's other participants.
+    async fn leave_room_internal(
+        &self,
+        connection: ConnectionId,
+        tx: &DatabaseTransaction,
+    ) -> Result<Option<(RoomId, LeftRoom)>> {
+        let leaving_participant = room_participant::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(room_participant::Column::AnsweringConnectionId.eq(connection.id as i32))
+                    .add(
+                        room_participant::Column::AnsweringConnectionServerId
+                            .eq(connection.owner_id as i32),
+                    ),
+            )
+            .one(&*tx)
+            .await?;
+
+        if let Some(leaving_participant) = leaving_participant {
+            // Leave room.
+            let room_id = leaving_participant.room_id;
+            let observer_visibility = self.observer_visibility(room_id, tx).await?;
+            room_participant::Entity::delete_by_id(leaving_participant.id)
+                .exec(&*tx)
+                .await?;
+
+            // Cancel pending calls initiated by the leaving user.
+            let called_participants = room_participant::Entity::find()
                 .filter(
                     Condition::all()
                         .add(
-                            room_participant::Column::AnsweringConnectionId
-                                .eq(connection.id as i32),
+                            room_participant::Column::CallingUserId.eq(leaving_participant.user_id),
                         )
-                        .add(
-                            room_participant::Column::AnsweringConnectionServerId
-                                .eq(connection.owner_id as i32),
-                        ),
+                        .add(room_participant::Column::AnsweringConnectionId.is_null()),
                 )
-                .one(&*tx)
+                .all(&*tx)
                 .await?;
+            room_participant::Entity::delete_many()
+                .filter(
+                    room_participant::Column::Id
+                        .is_in(called_participants.iter().map(|participant| participant.id)),
+                )
+                .exec(&*tx)
+                .await?;
+            let mut canceled_calls_to_user_ids = called_participants
+                .into_iter()
+                .map(|participant| participant.user_id)
+                .collect::<Vec<_>>();
+            let waiting_calls = waiting_call::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(waiting_call::Column::RoomId.eq(room_id))
+                        .add(waiting_call::Column::CallingUserId.eq(leaving_participant.user_id)),
+                )
+                .all(&*tx)
+                .await?;
+            waiting_call::Entity::delete_many()
+                .filter(
+                    waiting_call::Column::Id
+                        .is_in(waiting_calls.iter().map(|waiting_call| waiting_call.id)),
+                )
+                .exec(&*tx)
+                .await?;
+            canceled_calls_to_user_ids.extend(
+                waiting_calls
+                    .into_iter()
+                    .map(|waiting_call| waiting_call.called_user_id),
+            );
+            self.resolve_call_records(
+                room_id,
+                canceled_calls_to_user_ids.iter().copied(),
+                CallStatus::Missed,
+                &tx,
+            )
+            .await?;
 
-            if let Some(leaving_participant) = leaving_participant {
-                // Leave room.
-                let room_id = leaving_participant.room_id;
-                room_participant::Entity::delete_by_id(leaving_participant.id)
-                    .exec(&*tx)
-                    .await?;
-
-                // Cancel pending calls initiated by the leaving user.
-                let called_participants = room_participant::Entity::find()
-                    .filter(
-                        Condition::all()
-                            .add(
-                                room_participant::Column::CallingUserId
-                                    .eq(leaving_participant.user_id),
-                            )
-                            .add(room_participant::Column::AnsweringConnectionId.is_null()),
-                    )
-                    .all(&*tx)
-                    .await?;
-                room_participant::Entity::delete_many()
-                    .filter(
-                        room_participant::Column::Id
-                            .is_in(called_participants.iter().map(|participant| participant.id)),
-                    )
-                    .exec(&*tx)
-                    .await?;
-                let canceled_calls_to_user_ids = called_participants
-                    .into_iter()
-                    .map(|participant| participant.user_id)
-                    .collect();
-
-                // Detect left projects.
-                #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
-                enum QueryProjectIds {
-                    ProjectId,
-                }
-                let project_ids: Vec<ProjectId> = project_collaborator::Entity::find()
-                    .select_only()
-                    .column_as(
-                        project_collaborator::Column::ProjectId,
-                        QueryProjectIds::ProjectId,
-                    )
-                    .filter(
-                        Condition::all()
-                            .add(
-                                project_collaborator::Column::ConnectionId.eq(connection.id as i32),
-                            )
-                            .add(
-                                project_collaborator::Column::ConnectionServerId
-                                    .eq(connection.owner_id as i32),
-                            ),
-                    )
-                    .into_values::<_, QueryProjectIds>()
-                    .all(&*tx)
-                    .await?;
-                let mut left_projects = HashMap::default();
-                let mut collaborators = project_collaborator::Entity::find()
-                    .filter(project_collaborator::Column::ProjectId.is_in(project_ids))
-                    .stream(&*tx)
-                    .await?;
-                while let Some(collaborator) = collaborators.next().await {
-                    let collaborator = collaborator?;
-                    let left_project =
-                        left_projects
-                            .entry(collaborator.project_id)
-                            .or_insert(LeftProject {
-                                id: collaborator.project_id,
-                                host_user_id: Default::default(),
-                                connection_ids: Default::default(),
-                                host_connection_id: None,
-                            });
-
-                    let collaborator_connection_id = collaborator.connection();
-                    if collaborator_connection_id != connection {
-                        left_project.connection_ids.push(collaborator_connection_id);
-                    }
-
-                    if collaborator.is_host {
-                        left_project.host_user_id = collaborator.user_id;
-                        left_project.host_connection_id = Some(collaborator_connection_id);
-                    }
+            // Detect left projects.
+            #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+            enum QueryProjectIds {
+                ProjectId,
+            }
+            let project_ids: Vec<ProjectId> = project_collaborator::Entity::find()
+                .select_only()
+                .column_as(
+                    project_collaborator::Column::ProjectId,
+                    QueryProjectIds::ProjectId,
+                )
+                .filter(
+                    Condition::all()
+                        .add(project_collaborator::Column::ConnectionId.eq(connection.id as i32))
+                        .add(
+                            project_collaborator::Column::ConnectionServerId
+                                .eq(connection.owner_id as i32),
+                        ),
+                )
+                .into_values::<_, QueryProjectIds>()
+                .all(&*tx)
+                .await?;
+            let mut left_projects = HashMap::default();
+            let mut collaborators = project_collaborator::Entity::find()
+                .filter(project_collaborator::Column::ProjectId.is_in(project_ids))
+                .stream(&*tx)
+                .await?;
+            while let Some(collaborator) = collaborators.next().await {
+                let collaborator = collaborator?;
+                let left_project =
+                    left_projects
+                        .entry(collaborator.project_id)
+                        .or_insert(LeftProject {
+                            id: collaborator.project_id,
+                            host_user_id: Default::default(),
+                            connection_ids: Default::default(),
+                            host_connection_id: None,
+                        });
+
+                let collaborator_connection_id = collaborator.connection();
+                if collaborator_connection_id != connection
+                    && observer_visibility.can_see(collaborator_connection_id, connection)
+                {
+                    left_project.connection_ids.push(collaborator_connection_id);
                 }
-                drop(collaborators);
-
-                // Leave projects.
-                project_collaborator::Entity::delete_many()
-                    .filter(
-                        Condition::all()
-                            .add(
-                                project_collaborator::Column::ConnectionId.eq(connection.id as i32),
-                            )
-                            .add(
-                                project_collaborator::Column::ConnectionServerId
-                                    .eq(connection.owner_id as i32),
-                            ),
-                    )
-                    .exec(&*tx)
-                    .await?;
 
-                follower::Entity::delete_many()
-                    .filter(
-                        Condition::all()
-                            .add(follower::Column::FollowerConnectionId.eq(connection.id as i32)),
-                    )
-                    .exec(&*tx)
-                    .await?;
+                if collaborator.is_host {
+                    left_project.host_user_id = collaborator.user_id;
+                    left_project.host_connection_id = Some(collaborator_connection_id);
+                }
+            }
+            drop(collaborators);
 
-                // Unshare projects.
-                project::Entity::delete_many()
-                    .filter(
-                        Condition::all()
-                            .add(project::Column::RoomId.eq(room_id))
-                            .add(project::Column::HostConnectionId.eq(connection.id as i32))
-                            .add(
-                                project::Column::HostConnectionServerId
-                                    .eq(connection.owner_id as i32),
-                            ),
-                    )
-                    .exec(&*tx)
-                    .await?;
+            // Leave projects.
+            project_collaborator::Entity::delete_many()
+                .filter(
+                    Condition::all()
+                        .add(project_collaborator::Column::ConnectionId.eq(connection.id as i32))
+                        .add(
+                            project_collaborator::Column::ConnectionServerId
+                                .eq(connection.owner_id as i32),
+                        ),
+                )
+                .exec(&*tx)
+                .await?;
 
-                let (channel, room) = self.get_channel_room(room_id, &tx).await?;
-                let deleted = if room.participants.is_empty() {
-                    let result = room::Entity::delete_by_id(room_id).exec(&*tx).await?;
-                    result.rows_affected > 0
-                } else {
-                    false
-                };
+            follower::Entity::delete_many()
+                .filter(
+                    Condition::all()
+                        .add(follower::Column::FollowerConnectionId.eq(connection.id as i32)),
+                )
+                .exec(&*tx)
+                .await?;
 
-                let channel_members = if let Some(channel) = &channel {
-                    self.get_channel_participants(channel, &tx).await?
-                } else {
-                    Vec::new()
-                };
-                let left_room = LeftRoom {
-                    room,
-                    channel_id: channel.map(|channel| channel.id),
-                    channel_members,
-                    left_projects,
-                    canceled_calls_to_user_ids,
-                    deleted,
-                };
+            // Unshare projects.
+            project::Entity::delete_many()
+                .filter(
+                    Condition::all()
+                        .add(project::Column::RoomId.eq(room_id))
+                        .add(project::Column::HostConnectionId.eq(connection.id as i32))
+                        .add(
+                            project::Column::HostConnectionServerId.eq(connection.owner_id as i32),
+                        ),
+                )
+                .exec(&*tx)
+                .await?;
 
-                if left_room.room.participants.is_empty() {
-                    self.rooms.remove(&room_id);
-                }
+            let (channel, room) = self.get_channel_room(room_id, &tx).await?;
+            let notifications = if room.participants.is_empty() {
+                self.summarize_call(room_id, channel.as_ref().map(|channel| channel.id), &tx)
+                    .await?
+            } else {
+                Vec::new()
+            };
+            let deleted = if room.participants.is_empty() {
+                let result = room::Entity::delete_by_id(room_id).exec(&*tx).await?;
+                result.rows_affected > 0
+            } else {
+                false
+            };
 
-                Ok(Some((room_id, left_room)))
+            let channel_members = if let Some(channel) = &channel {
+                self.get_channel_participants(channel, &tx).await?
             } else {
-                Ok(None)
+                Vec::new()
+            };
+            let left_room = LeftRoom {
+                room,
+                channel_id: channel.map(|channel| channel.id),
+                channel_members,
+                left_projects,
+                canceled_calls_to_user_ids,
+                deleted,
+                notifications,
+            };
+
+            if left_room.room.participants.is_empty() {
+                self.rooms.remove(&room_id);
             }
-        })
-        .await
+
+            Ok(Some((room_id, left_room)))
+        } else {
+            Ok(None)
+        }
     }
 
     /// Updates the location of a participant in the given room.
@@ -1038,6 +1835,16 @@ impl Database {
             if result.rows_affected != 1 {
                 Err(anyhow!("could not update room participant role"))?;
             }
+            self.record_audit_event(
+                NewAuditEvent {
+                    subject_user_id: Some(user_id),
+                    room_id: Some(room_id),
+                    detail: Some(role.to_value()),
+                    ..NewAuditEvent::new(AuditEventKind::RoomRoleChanged, admin_id)
+                },
+                &tx,
+            )
+            .await?;
             Ok(self.get_room(room_id, &tx).await?)
         })
         .await
@@ -1082,12 +1889,95 @@ impl Database {
         Ok(())
     }
 
+    /// Returns the rooms that each of the given server's connections is participating in.
+    pub async fn room_ids_by_connection(
+        &self,
+        server_id: ServerId,
+    ) -> Result<HashMap<ConnectionId, Vec<RoomId>>> {
+        self.transaction(|tx| async move {
+            let mut participants = room_participant::Entity::find()
+                .filter(room_participant::Column::AnsweringConnectionServerId.eq(server_id))
+                .stream(&*tx)
+                .await?;
+
+            let mut room_ids = HashMap::<ConnectionId, Vec<RoomId>>::default();
+            while let Some(participant) = participants.next().await {
+                let participant = participant?;
+                if let Some(connection_id) = participant.answering_connection() {
+                    room_ids
+                        .entry(connection_id)
+                        .or_default()
+                        .push(participant.room_id);
+                }
+            }
+            Ok(room_ids)
+        })
+        .await
+    }
+
+    /// Deletes projects whose host is no longer a participant in their room, along with
+    /// rooms outside of channels that nobody is participating in or being called to.
+    pub async fn delete_orphaned_rooms_and_projects(&self) -> Result<SweptOrphans> {
+        self.transaction(|tx| async move {
+            let mut participants_by_room = HashMap::<RoomId, HashSet<UserId>>::default();
+            let mut participants = room_participant::Entity::find().stream(&*tx).await?;
+            while let Some(participant) = participants.next().await {
+                let participant = participant?;
+                participants_by_room
+                    .entry(participant.room_id)
+                    .or_default()
+                    .insert(participant.user_id);
+            }
+            drop(participants);
+
+            let orphaned_project_ids = project::Entity::find()
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .filter(|project| {
+                    !participants_by_room
+                        .get(&project.room_id)
+                        .map_or(false, |user_ids| user_ids.contains(&project.host_user_id))
+                })
+                .map(|project| project.id)
+                .collect::<Vec<_>>();
+            if !orphaned_project_ids.is_empty() {
+                project::Entity::delete_many()
+                    .filter(project::Column::Id.is_in(orphaned_project_ids.iter().copied()))
+                    .exec(&*tx)
+                    .await?;
+            }
+
+            let orphaned_room_ids = room::Entity::find()
+                .filter(room::Column::ChannelId.is_null())
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .filter(|room| !participants_by_room.contains_key(&room.id))
+                .map(|room| room.id)
+                .collect::<Vec<_>>();
+            if !orphaned_room_ids.is_empty() {
+                room::Entity::delete_many()
+                    .filter(room::Column::Id.is_in(orphaned_room_ids.iter().copied()))
+                    .exec(&*tx)
+                    .await?;
+            }
+
+            Ok(SweptOrphans {
+                projects: orphaned_project_ids.len(),
+                rooms: orphaned_room_ids.len(),
+            })
+        })
+        .await
+    }
+
     pub async fn connection_lost(&self, connection: ConnectionId) -> Result<()> {
         self.transaction(|tx| async move {
             self.room_connection_lost(connection, &*tx).await?;
             self.channel_buffer_connection_lost(connection, &*tx)
                 .await?;
             self.channel_chat_connection_lost(connection, &*tx).await?;
+            self.user_connection_lost(connection, &*tx).await?;
             Ok(())
         })
         .await
@@ -1121,6 +2011,121 @@ impl Database {
         Ok(())
     }
 
+    /// Describes every room, project, follower and channel buffer record that still
+    /// refers to the given connection.
+    #[cfg(test)]
+    pub async fn resources_for_connection(&self, connection: ConnectionId) -> Result<Vec<String>> {
+        self.transaction(|tx| async move {
+            let mut resources = Vec::new();
+
+            let participants = room_participant::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(
+                            room_participant::Column::AnsweringConnectionId
+                                .eq(connection.id as i32),
+                        )
+                        .add(
+                            room_participant::Column::AnsweringConnectionServerId
+                                .eq(connection.owner_id as i32),
+                        ),
+                )
+                .all(&*tx)
+                .await?;
+            resources.extend(
+                participants
+                    .into_iter()
+                    .map(|participant| format!("participant in room {}", participant.room_id)),
+            );
+
+            let projects = project::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(project::Column::HostConnectionId.eq(connection.id as i32))
+                        .add(
+                            project::Column::HostConnectionServerId.eq(connection.owner_id as i32),
+                        ),
+                )
+                .all(&*tx)
+                .await?;
+            resources.extend(
+                projects
+                    .into_iter()
+                    .map(|project| format!("host of project {}", project.id)),
+            );
+
+            let collaborators = project_collaborator::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(project_collaborator::Column::ConnectionId.eq(connection.id as i32))
+                        .add(
+                            project_collaborator::Column::ConnectionServerId
+                                .eq(connection.owner_id as i32),
+                        ),
+                )
+                .all(&*tx)
+                .await?;
+            resources.extend(collaborators.into_iter().map(|collaborator| {
+                format!("collaborator in project {}", collaborator.project_id)
+            }));
+
+            let followers = follower::Entity::find()
+                .filter(
+                    Condition::any()
+                        .add(
+                            Condition::all()
+                                .add(follower::Column::LeaderConnectionId.eq(connection.id as i32))
+                                .add(
+                                    follower::Column::LeaderConnectionServerId
+                                        .eq(connection.owner_id as i32),
+                                ),
+                        )
+                        .add(
+                            Condition::all()
+                                .add(
+                                    follower::Column::FollowerConnectionId.eq(connection.id as i32),
+                                )
+                                .add(
+                                    follower::Column::FollowerConnectionServerId
+                                        .eq(connection.owner_id as i32),
+                                ),
+                        ),
+                )
+                .all(&*tx)
+                .await?;
+            resources.extend(
+                followers
+                    .into_iter()
+                    .map(|follower| format!("follower in project {}", follower.project_id)),
+            );
+
+            let channel_buffer_collaborators = channel_buffer_collaborator::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(
+                            channel_buffer_collaborator::Column::ConnectionId
+                                .eq(connection.id as i32),
+                        )
+                        .add(
+                            channel_buffer_collaborator::Column::ConnectionServerId
+                                .eq(connection.owner_id as i32),
+                        ),
+                )
+                .all(&*tx)
+                .await?;
+            resources.extend(
+                channel_buffer_collaborators
+                    .into_iter()
+                    .map(|collaborator| {
+                        format!("collaborator in channel buffer {}", collaborator.channel_id)
+                    }),
+            );
+
+            Ok(resources)
+        })
+        .await
+    }
+
     fn build_incoming_call(
         room: &proto::Room,
         called_user_id: UserId,
@@ -1136,6 +2141,7 @@ impl Database {
             participant_user_ids: room
                 .participants
                 .iter()
+                .filter(|participant| !participant.observer || pending_participant.observer)
                 .map(|participant| participant.user_id)
                 .collect(),
             initial_project: room.participants.iter().find_map(|participant| {
@@ -1146,6 +2152,9 @@ impl Database {
                     .find(|project| project.id == initial_project_id)
                     .cloned()
             }),
+            waiting: false,
+            context: None,
+            observer: pending_participant.observer,
         })
     }
 
@@ -1187,6 +2196,77 @@ impl Database {
         .await
     }
 
+    /// Returns the other connections in the room, which hear the audio that the
+    /// given connection relays through the server. Fails unless the connection
+    /// is allowed to speak in the room.
+    pub async fn relayed_audio_connection_ids(
+        &self,
+        room_id: RoomId,
+        connection_id: ConnectionId,
+    ) -> Result<HashSet<ConnectionId>> {
+        self.transaction(|tx| async move {
+            let mut participants = room_participant::Entity::find()
+                .filter(room_participant::Column::RoomId.eq(room_id))
+                .stream(&*tx)
+                .await?;
+
+            let mut can_speak = false;
+            let mut connection_ids = HashSet::default();
+            while let Some(participant) = participants.next().await {
+                let participant = participant?;
+                if let Some(answering_connection) = participant.answering_connection() {
+                    if answering_connection == connection_id {
+                        can_speak = participant
+                            .role
+                            .map_or(true, |role| role.can_publish_to_rooms());
+                    } else if participant
+                        .capabilities()
+                        .map_or(true, |capabilities| capabilities.media_available)
+                    {
+                        connection_ids.insert(answering_connection);
+                    }
+                }
+            }
+
+            if !can_speak {
+                Err(anyhow!("not allowed to speak in this room"))?;
+            }
+
+            Ok(connection_ids)
+        })
+        .await
+    }
+
+    /// Determines which of the room's participants are observers, and which are
+    /// allowed to see them.
+    pub(crate) async fn observer_visibility(
+        &self,
+        room_id: RoomId,
+        tx: &DatabaseTransaction,
+    ) -> Result<ObserverVisibility> {
+        let participants = room_participant::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(room_participant::Column::RoomId.eq(room_id))
+                    .add(room_participant::Column::AnsweringConnectionId.is_not_null()),
+            )
+            .all(tx)
+            .await?;
+        let mut visibility = ObserverVisibility::default();
+        for participant in participants {
+            let Some(connection) = participant.answering_connection() else {
+                continue;
+            };
+            if participant.observer {
+                visibility.observers.insert(connection);
+            }
+            if participant.can_see_observers() {
+                visibility.viewers.insert(connection);
+            }
+        }
+        Ok(visibility)
+    }
+
     async fn get_channel_room(
         &self,
         room_id: RoomId,
@@ -1246,6 +2326,10 @@ impl Database {
                         location: Some(proto::ParticipantLocation { variant: location }),
                         participant_index: participant_index as u32,
                         role: db_participant.role.unwrap_or(ChannelRole::Member).into(),
+                        capabilities: db_participant
+                            .capabilities()
+                            .map(|capabilities| capabilities.to_proto()),
+                        observer: db_participant.observer,
                     },
                 );
             } else {
@@ -1253,11 +2337,27 @@ impl Database {
                     user_id: db_participant.user_id.to_proto(),
                     calling_user_id: db_participant.calling_user_id.to_proto(),
                     initial_project_id: db_participant.initial_project_id.map(|id| id.to_proto()),
+                    observer: db_participant.observer,
                 });
             }
         }
         drop(db_participants);
 
+        let mut db_waiting_calls = db_room
+            .find_related(waiting_call::Entity)
+            .stream(tx)
+            .await?;
+        while let Some(db_waiting_call) = db_waiting_calls.next().await {
+            let db_waiting_call = db_waiting_call?;
+            pending_participants.push(proto::PendingParticipant {
+                user_id: db_waiting_call.called_user_id.to_proto(),
+                calling_user_id: db_waiting_call.calling_user_id.to_proto(),
+                initial_project_id: db_waiting_call.initial_project_id.map(|id| id.to_proto()),
+                observer: false,
+            });
+        }
+        drop(db_waiting_calls);
+
         let mut db_projects = db_room
             .find_related(project::Entity)
             .find_with_related(worktree::Entity)
@@ -1277,6 +2377,7 @@ impl Database {
                 } else {
                     participant.projects.push(proto::ParticipantProject {
                         id: db_project.id.to_proto(),
+                        host_project_id: db_project.host_project_id as u64,
                         worktree_root_names: Default::default(),
                     });
                     participant.projects.last_mut().unwrap()
@@ -1317,6 +2418,14 @@ impl Database {
                 participants: participants.into_values().collect(),
                 pending_participants,
                 followers,
+                ends_at: db_room.ends_at.map(|ends_at| proto::Timestamp {
+                    seconds: ends_at.assume_utc().unix_timestamp() as u64,
+                    nanos: 0,
+                }),
+                started_at: db_room.created_at.map(|created_at| proto::Timestamp {
+                    seconds: created_at.assume_utc().unix_timestamp() as u64,
+                    nanos: 0,
+                }),
             },
         ))
     }
@@ -0,0 +1,117 @@
+use super::*;
+
+impl Database {
+    /// Returns the runtime config overrides for the given environment, as pairs of
+    /// environment variable names and values.
+    pub async fn get_runtime_config_overrides(
+        &self,
+        environment: &str,
+    ) -> Result<Vec<(String, String)>> {
+        self.transaction(|tx| async move {
+            Ok(runtime_config_override::Entity::find()
+                .filter(runtime_config_override::Column::Environment.eq(environment))
+                .order_by_asc(runtime_config_override::Column::Name)
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .map(|row| (row.name, row.value))
+                .collect())
+        })
+        .await
+    }
+
+    /// Overrides the value of the given environment variable for every server in the
+    /// environment, or goes back to the value they were started with when `value` is
+    /// `None`.
+    pub async fn set_runtime_config_override(
+        &self,
+        environment: &str,
+        name: &str,
+        value: Option<&str>,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            self.set_runtime_config_override_internal(environment, name, value, &tx)
+                .await
+        })
+        .await
+    }
+
+    /// Applies several changes to the overrides at once, as pairs of environment
+    /// variable names and values, where a value of `None` removes the override. The
+    /// changes are only stored if `check` accepts the overrides they result in, and
+    /// either all of them are or none are.
+    pub async fn update_runtime_config_overrides(
+        &self,
+        environment: &str,
+        changes: &[(String, Option<String>)],
+        check: impl Send + Sync + Fn(&[(String, String)]) -> Result<()>,
+    ) -> Result<()> {
+        self.transaction(|tx| {
+            let check = &check;
+            async move {
+                let mut overrides = runtime_config_override::Entity::find()
+                    .filter(runtime_config_override::Column::Environment.eq(environment))
+                    .all(&*tx)
+                    .await?
+                    .into_iter()
+                    .map(|row| (row.name, row.value))
+                    .collect::<BTreeMap<_, _>>();
+                for (name, value) in changes {
+                    match value {
+                        Some(value) => overrides.insert(name.clone(), value.clone()),
+                        None => overrides.remove(name),
+                    };
+                }
+                check(&overrides.into_iter().collect::<Vec<_>>())?;
+
+                for (name, value) in changes {
+                    self.set_runtime_config_override_internal(
+                        environment,
+                        name,
+                        value.as_deref(),
+                        &tx,
+                    )
+                    .await?;
+                }
+                Ok(())
+            }
+        })
+        .await
+    }
+
+    async fn set_runtime_config_override_internal(
+        &self,
+        environment: &str,
+        name: &str,
+        value: Option<&str>,
+        tx: &DatabaseTransaction,
+    ) -> Result<()> {
+        if let Some(value) = value {
+            runtime_config_override::Entity::insert(runtime_config_override::ActiveModel {
+                environment: ActiveValue::set(environment.to_string()),
+                name: ActiveValue::set(name.to_string()),
+                value: ActiveValue::set(value.to_string()),
+            })
+            .on_conflict(
+                OnConflict::columns([
+                    runtime_config_override::Column::Environment,
+                    runtime_config_override::Column::Name,
+                ])
+                .update_column(runtime_config_override::Column::Value)
+                .to_owned(),
+            )
+            .exec_without_returning(tx)
+            .await?;
+        } else {
+            runtime_config_override::Entity::delete_many()
+                .filter(
+                    Condition::all()
+                        .add(runtime_config_override::Column::Environment.eq(environment))
+                        .add(runtime_config_override::Column::Name.eq(name)),
+                )
+                .exec(tx)
+                .await?;
+        }
+        Ok(())
+    }
+}
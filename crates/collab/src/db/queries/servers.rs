@@ -1,4 +1,7 @@
 use super::*;
+use collections::BTreeSet;
+use sea_orm::sea_query::Query;
+use time::{OffsetDateTime, PrimitiveDateTime};
 
 impl Database {
     /// Creates a new server in the given environment.
@@ -36,7 +39,7 @@ impl Database {
             }
 
             let stale_server_epochs = self
-                .stale_server_ids(environment, new_server_id, &tx)
+                .stale_server_ids_internal(environment, new_server_id, &tx)
                 .await?;
             let room_ids = room_participant::Entity::find()
                 .select_only()
@@ -67,6 +70,8 @@ impl Database {
     }
 
     /// Deletes any stale servers in the environment that don't match the `new_server_id`.
+    /// Servers that issued resume tokens which haven't been redeemed are kept, along
+    /// with the rooms and projects of the connections they were issued for.
     pub async fn delete_stale_servers(
         &self,
         environment: &str,
@@ -77,7 +82,15 @@ impl Database {
                 .filter(
                     Condition::all()
                         .add(server::Column::Environment.eq(environment))
-                        .add(server::Column::Id.ne(new_server_id)),
+                        .add(server::Column::Id.ne(new_server_id))
+                        .add(
+                            server::Column::Id.not_in_subquery(
+                                Query::select()
+                                    .column(resume_token::Column::ConnectionServerId)
+                                    .from(resume_token::Entity)
+                                    .to_owned(),
+                            ),
+                        ),
                 )
                 .exec(&*tx)
                 .await?;
@@ -86,7 +99,127 @@ impl Database {
         .await
     }
 
-    async fn stale_server_ids(
+    /// Returns the IDs of the servers in the given `environment` other than `new_server_id`.
+    pub async fn stale_server_ids(
+        &self,
+        environment: &str,
+        new_server_id: ServerId,
+    ) -> Result<Vec<ServerId>> {
+        self.transaction(|tx| async move {
+            self.stale_server_ids_internal(environment, new_server_id, &tx)
+                .await
+        })
+        .await
+    }
+
+    /// Gives the clients of the stale servers in the given `environment` until
+    /// `deadline` to rejoin their rooms, unless an earlier server already gave them
+    /// a deadline. Returns the last of the stale servers' deadlines, if there are any.
+    pub async fn start_rejoin_window(
+        &self,
+        environment: &str,
+        new_server_id: ServerId,
+        deadline: OffsetDateTime,
+    ) -> Result<Option<OffsetDateTime>> {
+        self.transaction(|tx| async move {
+            let stale_server_ids = self
+                .stale_server_ids_internal(environment, new_server_id, &tx)
+                .await?;
+            server::Entity::update_many()
+                .filter(
+                    Condition::all()
+                        .add(server::Column::Id.is_in(stale_server_ids.iter().copied()))
+                        .add(server::Column::RejoinDeadline.is_null()),
+                )
+                .set(server::ActiveModel {
+                    rejoin_deadline: ActiveValue::set(Some(PrimitiveDateTime::new(
+                        deadline.date(),
+                        deadline.time(),
+                    ))),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?;
+
+            let deadline = server::Entity::find()
+                .filter(server::Column::Id.is_in(stale_server_ids))
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .filter_map(|server| server.rejoin_deadline)
+                .max();
+            Ok(deadline.map(PrimitiveDateTime::assume_utc))
+        })
+        .await
+    }
+
+    /// Stores messages for connections on other servers in one go, notifying each
+    /// of those servers once if the database supports it.
+    pub async fn publish_routed_messages(&self, messages: Vec<NewRoutedMessage>) -> Result<()> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        self.transaction(|tx| {
+            let messages = messages.clone();
+            async move {
+                let server_ids = messages
+                    .iter()
+                    .map(|message| message.server_id)
+                    .collect::<BTreeSet<_>>();
+                routed_message::Entity::insert_many(messages.into_iter().map(|message| {
+                    routed_message::ActiveModel {
+                        server_id: ActiveValue::set(message.server_id),
+                        connection_id: ActiveValue::set(message.connection_id as i32),
+                        request_id: ActiveValue::set(message.request_id.map(|id| id as i32)),
+                        requester_server_id: ActiveValue::set(message.requester_server_id),
+                        envelope: ActiveValue::set(message.envelope),
+                        ..Default::default()
+                    }
+                }))
+                .exec(&*tx)
+                .await?;
+
+                // Notifications are only sent once the transaction commits, by which
+                // point the messages can be taken by the receiving servers.
+                if let sea_orm::DatabaseBackend::Postgres = self.pool.get_database_backend() {
+                    for server_id in server_ids {
+                        tx.execute(Statement::from_sql_and_values(
+                            sea_orm::DatabaseBackend::Postgres,
+                            "SELECT pg_notify($1, '')",
+                            [routed_messages_channel(server_id).into()],
+                        ))
+                        .await?;
+                    }
+                }
+                Ok(())
+            }
+        })
+        .await
+    }
+
+    /// Removes and returns the messages that were published for the given server,
+    /// in the order they were published.
+    pub async fn take_routed_messages(
+        &self,
+        server_id: ServerId,
+    ) -> Result<Vec<routed_message::Model>> {
+        self.transaction(|tx| async move {
+            let messages = routed_message::Entity::find()
+                .filter(routed_message::Column::ServerId.eq(server_id))
+                .order_by_asc(routed_message::Column::Id)
+                .all(&*tx)
+                .await?;
+            routed_message::Entity::delete_many()
+                .filter(routed_message::Column::Id.is_in(messages.iter().map(|message| message.id)))
+                .exec(&*tx)
+                .await?;
+            Ok(messages)
+        })
+        .await
+    }
+
+    pub(crate) async fn stale_server_ids_internal(
         &self,
         environment: &str,
         new_server_id: ServerId,
@@ -103,3 +236,19 @@ impl Database {
         Ok(stale_servers.into_iter().map(|server| server.id).collect())
     }
 }
+
+/// A message for [`Database::publish_routed_messages`] to store.
+#[derive(Clone, Debug)]
+pub struct NewRoutedMessage {
+    pub server_id: ServerId,
+    pub connection_id: u32,
+    pub request_id: Option<u32>,
+    pub requester_server_id: Option<ServerId>,
+    pub envelope: Vec<u8>,
+}
+
+/// The channel that a server listens on to be notified of the messages published
+/// for it.
+pub fn routed_messages_channel(server_id: ServerId) -> String {
+    format!("routed_messages_{}", server_id)
+}
@@ -0,0 +1,88 @@
+use super::*;
+use crate::rpc::quotas;
+
+impl Database {
+    /// How many connections the given user has open to the given servers.
+    pub async fn user_connection_count(
+        &self,
+        user_id: UserId,
+        server_ids: &[ServerId],
+    ) -> Result<usize> {
+        self.transaction(|tx| async move {
+            self.user_connection_count_internal(user_id, server_ids, &tx)
+                .await
+        })
+        .await
+    }
+
+    /// Records a connection the given user opened, failing if they already have as
+    /// many connections to the given servers as the quota allows. The connections are
+    /// counted in the same transaction as the new one is recorded in, so concurrent
+    /// connections to different servers can't all pass the check.
+    pub async fn add_user_connection(
+        &self,
+        user_id: UserId,
+        connection: ConnectionId,
+        server_ids: &[ServerId],
+        quota: usize,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            let count = self
+                .user_connection_count_internal(user_id, server_ids, &tx)
+                .await?;
+            if quotas::reached(quota, count) {
+                Err(anyhow!("too many connections: the limit is {quota}"))?;
+            }
+
+            user_connection::ActiveModel {
+                user_id: ActiveValue::set(user_id),
+                connection_server_id: ActiveValue::set(ServerId(connection.owner_id as i32)),
+                connection_id: ActiveValue::set(connection.id as i32),
+                ..Default::default()
+            }
+            .insert(&*tx)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub(crate) async fn user_connection_lost(
+        &self,
+        connection: ConnectionId,
+        tx: &DatabaseTransaction,
+    ) -> Result<()> {
+        user_connection::Entity::delete_many()
+            .filter(
+                Condition::all()
+                    .add(
+                        user_connection::Column::ConnectionServerId
+                            .eq(ServerId(connection.owner_id as i32)),
+                    )
+                    .add(user_connection::Column::ConnectionId.eq(connection.id as i32)),
+            )
+            .exec(tx)
+            .await?;
+        Ok(())
+    }
+
+    async fn user_connection_count_internal(
+        &self,
+        user_id: UserId,
+        server_ids: &[ServerId],
+        tx: &DatabaseTransaction,
+    ) -> Result<usize> {
+        let count = user_connection::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(user_connection::Column::UserId.eq(user_id))
+                    .add(
+                        user_connection::Column::ConnectionServerId
+                            .is_in(server_ids.iter().copied()),
+                    ),
+            )
+            .count(tx)
+            .await?;
+        Ok(count as usize)
+    }
+}
@@ -0,0 +1,224 @@
+use super::*;
+use time::OffsetDateTime;
+
+impl Database {
+    /// Records a report that one user made about another, pointing to the room it was
+    /// made from and to messages the reported user sent.
+    pub async fn create_user_report(
+        &self,
+        reporter_id: UserId,
+        reported_user_id: UserId,
+        room_id: Option<RoomId>,
+        channel_message_ids: &[MessageId],
+        room_message_ids: &[RoomMessageId],
+        reason: &str,
+    ) -> Result<UserReportId> {
+        if reporter_id == reported_user_id {
+            Err(anyhow!("users cannot report themselves"))?;
+        }
+
+        self.transaction(|tx| async move {
+            user::Entity::find_by_id(reported_user_id)
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("no such user"))?;
+
+            // Reports only count toward throttling users that the reporter was in a
+            // room with, and guests' reports never do.
+            let mut counts_toward_throttle = false;
+            if let Some(room_id) = room_id {
+                if !self.was_in_room(room_id, reporter_id, &*tx).await? {
+                    Err(anyhow!("no such room"))?;
+                }
+                counts_toward_throttle = self.was_in_room(room_id, reported_user_id, &*tx).await?
+                    && guest::Entity::find_by_id(reporter_id)
+                        .one(&*tx)
+                        .await?
+                        .is_none();
+            }
+
+            // Messages that the reporter can't see are treated as though they don't
+            // exist, so that reports can't be used to probe for them.
+            let channel_messages = channel_message::Entity::find()
+                .filter(channel_message::Column::Id.is_in(channel_message_ids.iter().copied()))
+                .all(&*tx)
+                .await?;
+            if channel_messages.len() != channel_message_ids.len() {
+                Err(anyhow!("no such message"))?;
+            }
+            for message in &channel_messages {
+                let channel = self.get_channel_internal(message.channel_id, &*tx).await?;
+                let role = self
+                    .channel_role_for_user(&channel, reporter_id, &*tx)
+                    .await?;
+                if matches!(role, None | Some(ChannelRole::Banned)) {
+                    Err(anyhow!("no such message"))?;
+                }
+            }
+            let room_messages = match room_id {
+                Some(room_id) if !room_message_ids.is_empty() => {
+                    room_message::Entity::find()
+                        .filter(
+                            Condition::all()
+                                .add(
+                                    room_message::Column::Id
+                                        .is_in(room_message_ids.iter().copied()),
+                                )
+                                .add(self.room_chat(room_id, &*tx).await?),
+                        )
+                        .all(&*tx)
+                        .await?
+                }
+                _ => Vec::new(),
+            };
+            if room_messages.len() != room_message_ids.len() {
+                Err(anyhow!("no such message"))?;
+            }
+
+            if channel_messages
+                .iter()
+                .map(|message| message.sender_id)
+                .chain(room_messages.iter().map(|message| message.sender_id))
+                .any(|sender_id| sender_id != reported_user_id)
+            {
+                Err(anyhow!(
+                    "reported messages must be sent by the reported user"
+                ))?;
+            }
+
+            let now = OffsetDateTime::now_utc();
+            let report = user_report::ActiveModel {
+                reporter_id: ActiveValue::set(reporter_id),
+                reported_user_id: ActiveValue::set(reported_user_id),
+                room_id: ActiveValue::set(room_id),
+                channel_message_ids: ActiveValue::set(serde_json::to_string(channel_message_ids)?),
+                room_message_ids: ActiveValue::set(serde_json::to_string(room_message_ids)?),
+                reason: ActiveValue::set(reason.to_string()),
+                counts_toward_throttle: ActiveValue::set(counts_toward_throttle),
+                status: ActiveValue::set(ReportStatus::Open),
+                created_at: ActiveValue::set(PrimitiveDateTime::new(now.date(), now.time())),
+                ..Default::default()
+            }
+            .insert(&*tx)
+            .await?;
+            Ok(report.id)
+        })
+        .await
+    }
+
+    /// How many different users have reported the given one since `since`, not
+    /// counting reports that admins dismissed or that don't count toward throttling.
+    pub async fn user_report_count(&self, user_id: UserId, since: OffsetDateTime) -> Result<usize> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryReporterIds {
+            ReporterId,
+        }
+
+        self.transaction(|tx| async move {
+            let reporter_ids = user_report::Entity::find()
+                .select_only()
+                .column(user_report::Column::ReporterId)
+                .distinct()
+                .filter(
+                    Condition::all()
+                        .add(user_report::Column::ReportedUserId.eq(user_id))
+                        .add(user_report::Column::Status.ne(ReportStatus::Dismissed))
+                        .add(user_report::Column::CountsTowardThrottle.eq(true))
+                        .add(
+                            user_report::Column::CreatedAt
+                                .gte(PrimitiveDateTime::new(since.date(), since.time())),
+                        ),
+                )
+                .into_values::<UserId, QueryReporterIds>()
+                .all(&*tx)
+                .await?;
+            Ok(reporter_ids.len())
+        })
+        .await
+    }
+
+    /// Returns the reports with the given status, oldest first.
+    pub async fn get_user_reports(&self, status: ReportStatus) -> Result<Vec<UserReport>> {
+        self.transaction(|tx| async move {
+            let reports = user_report::Entity::find()
+                .filter(user_report::Column::Status.eq(status))
+                .order_by_asc(user_report::Column::Id)
+                .all(&*tx)
+                .await?;
+            reports.into_iter().map(user_report_from_model).collect()
+        })
+        .await
+    }
+
+    /// Records an admin's review of a report.
+    pub async fn review_user_report(
+        &self,
+        report_id: UserReportId,
+        status: ReportStatus,
+    ) -> Result<UserReport> {
+        self.transaction(|tx| async move {
+            let report = user_report::Entity::find_by_id(report_id)
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("no such report"))?;
+            let now = OffsetDateTime::now_utc();
+            let report = user_report::ActiveModel {
+                status: ActiveValue::set(status),
+                reviewed_at: ActiveValue::set(Some(PrimitiveDateTime::new(now.date(), now.time()))),
+                ..report.into_active_model()
+            }
+            .update(&*tx)
+            .await?;
+            user_report_from_model(report)
+        })
+        .await
+    }
+}
+
+impl Database {
+    /// Whether the user is in the room, or was earlier in its call.
+    async fn was_in_room(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        tx: &DatabaseTransaction,
+    ) -> Result<bool> {
+        let participant = room_participant::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(room_participant::Column::RoomId.eq(room_id))
+                    .add(room_participant::Column::UserId.eq(user_id))
+                    .add(room_participant::Column::AnsweringConnectionId.is_not_null()),
+            )
+            .one(tx)
+            .await?;
+        if participant.is_some() {
+            return Ok(true);
+        }
+        let joined = room_activity::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(room_activity::Column::RoomId.eq(room_id))
+                    .add(room_activity::Column::UserId.eq(user_id))
+                    .add(room_activity::Column::Kind.eq(RoomActivityKind::Joined)),
+            )
+            .one(tx)
+            .await?;
+        Ok(joined.is_some())
+    }
+}
+
+fn user_report_from_model(report: user_report::Model) -> Result<UserReport> {
+    Ok(UserReport {
+        id: report.id,
+        reporter_id: report.reporter_id,
+        reported_user_id: report.reported_user_id,
+        room_id: report.room_id,
+        channel_message_ids: serde_json::from_str(&report.channel_message_ids)?,
+        room_message_ids: serde_json::from_str(&report.room_message_ids)?,
+        reason: report.reason,
+        status: report.status,
+        created_at: report.created_at,
+        reviewed_at: report.reviewed_at,
+    })
+}
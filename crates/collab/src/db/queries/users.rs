@@ -1,4 +1,6 @@
 use super::*;
+use time::OffsetDateTime;
+use unicode_normalization::UnicodeNormalization;
 
 impl Database {
     /// Creates a new user.
@@ -186,6 +188,237 @@ impl Database {
         .await
     }
 
+    /// Bans the user, which stops them from signing in, or lifts their ban.
+    pub async fn set_user_banned(&self, id: UserId, banned: bool) -> Result<()> {
+        self.transaction(|tx| async move {
+            let banned_at = banned.then(|| {
+                let now = OffsetDateTime::now_utc();
+                PrimitiveDateTime::new(now.date(), now.time())
+            });
+            let result = user::Entity::update_many()
+                .filter(user::Column::Id.eq(id))
+                .set(user::ActiveModel {
+                    banned_at: ActiveValue::set(banned_at),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?;
+            if result.rows_affected == 0 {
+                Err(anyhow!("no such user"))?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Grants or revokes the user's admin access.
+    pub async fn set_user_admin(&self, id: UserId, admin: bool) -> Result<()> {
+        self.transaction(|tx| async move {
+            let result = user::Entity::update_many()
+                .filter(user::Column::Id.eq(id))
+                .set(user::ActiveModel {
+                    admin: ActiveValue::set(admin),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?;
+            if result.rows_affected == 0 {
+                Err(anyhow!("no such user"))?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Sets how many more people the user can invite.
+    pub async fn set_invite_count(&self, id: UserId, invite_count: i32) -> Result<()> {
+        self.transaction(|tx| async move {
+            let result = user::Entity::update_many()
+                .filter(user::Column::Id.eq(id))
+                .set(user::ActiveModel {
+                    invite_count: ActiveValue::set(invite_count),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?;
+            if result.rows_affected == 0 {
+                Err(anyhow!("no such user"))?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Sets the name shown to other users instead of the user's GitHub login, or
+    /// clears it when `display_name` is `None`. Returns the updated user, along
+    /// with the connections of everyone in a call with them.
+    ///
+    /// Display names are unique regardless of case, and can't be another user's
+    /// GitHub login.
+    pub async fn set_user_display_name(
+        &self,
+        id: UserId,
+        display_name: Option<&str>,
+    ) -> Result<UpdatedUser> {
+        self.transaction(|tx| async move {
+            let display_name = display_name.map(Self::sanitize_display_name).transpose()?;
+            let display_name_key = display_name.as_deref().map(display_name_key);
+
+            if let Some(display_name_key) = &display_name_key {
+                let conflicting_user = user::Entity::find()
+                    .filter(
+                        Condition::all().add(user::Column::Id.ne(id)).add(
+                            Condition::any()
+                                .add(user::Column::DisplayNameKey.eq(display_name_key.clone()))
+                                // GitHub logins are ASCII, which `LOWER` handles everywhere.
+                                .add(
+                                    Expr::expr(Func::lower(Expr::col(user::Column::GithubLogin)))
+                                        .eq(display_name_key.clone()),
+                                ),
+                        ),
+                    )
+                    .one(&*tx)
+                    .await?;
+                if conflicting_user.is_some() {
+                    Err(anyhow!("display name is already taken"))?;
+                }
+            }
+
+            let user = user::Entity::find_by_id(id)
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("could not find user"))?;
+            let user = user::Entity::update(user::ActiveModel {
+                display_name: ActiveValue::set(display_name),
+                display_name_key: ActiveValue::set(display_name_key),
+                ..user.into_active_model()
+            })
+            .exec(&*tx)
+            .await?;
+
+            self.updated_user(user, &tx).await
+        })
+        .await
+    }
+
+    /// Sets the avatar of the user with the given ID, storing the image under
+    /// its digest unless an identical image was already uploaded, and deleting
+    /// their previous avatar if nobody else uses it. Returns the updated user,
+    /// along with the connections of everyone in a call with them.
+    pub async fn set_user_avatar(
+        &self,
+        id: UserId,
+        digest: &str,
+        content_type: &str,
+        data: &[u8],
+    ) -> Result<UpdatedUser> {
+        self.transaction(|tx| async move {
+            avatar::Entity::insert(avatar::ActiveModel {
+                digest: ActiveValue::set(digest.to_string()),
+                content_type: ActiveValue::set(content_type.to_string()),
+                data: ActiveValue::set(data.to_vec()),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::column(avatar::Column::Digest)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(&*tx)
+            .await?;
+
+            let user = user::Entity::find_by_id(id)
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("could not find user"))?;
+            let previous_digest = user.avatar_digest.clone();
+            let user = user::Entity::update(user::ActiveModel {
+                avatar_digest: ActiveValue::set(Some(digest.to_string())),
+                ..user.into_active_model()
+            })
+            .exec(&*tx)
+            .await?;
+            if let Some(previous_digest) = previous_digest {
+                self.delete_avatar_if_unused(&previous_digest, &tx).await?;
+            }
+
+            self.updated_user(user, &tx).await
+        })
+        .await
+    }
+
+    /// Returns the avatar stored under the given digest.
+    pub async fn get_avatar(&self, digest: &str) -> Result<Option<avatar::Model>> {
+        self.transaction(|tx| async move {
+            Ok(avatar::Entity::find_by_id(digest.to_string())
+                .one(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Deletes the avatar stored under the given digest, unless a user still has it.
+    async fn delete_avatar_if_unused(&self, digest: &str, tx: &DatabaseTransaction) -> Result<()> {
+        let is_used = user::Entity::find()
+            .filter(user::Column::AvatarDigest.eq(digest))
+            .count(tx)
+            .await?
+            > 0;
+        if !is_used {
+            avatar::Entity::delete_by_id(digest.to_string())
+                .exec(tx)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn updated_user(&self, user: User, tx: &DatabaseTransaction) -> Result<UpdatedUser> {
+        let room_ids = room_participant::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(room_participant::Column::UserId.eq(user.id))
+                    .add(room_participant::Column::AnsweringConnectionId.is_not_null()),
+            )
+            .all(tx)
+            .await?
+            .into_iter()
+            .map(|participant| participant.room_id)
+            .collect::<Vec<_>>();
+        let room_connection_ids = room_participant::Entity::find()
+            .filter(room_participant::Column::RoomId.is_in(room_ids))
+            .all(tx)
+            .await?
+            .iter()
+            .filter_map(|participant| participant.answering_connection())
+            .collect();
+
+        Ok(UpdatedUser {
+            user,
+            room_connection_ids,
+        })
+    }
+
+    fn sanitize_display_name(display_name: &str) -> Result<String> {
+        let display_name = display_name.trim().nfc().collect::<String>();
+        if display_name.is_empty() {
+            Err(anyhow!("display name can't be blank"))?;
+        }
+        if display_name.chars().count() > MAX_DISPLAY_NAME_LENGTH {
+            Err(anyhow!(
+                "display name can't be longer than {MAX_DISPLAY_NAME_LENGTH} characters"
+            ))?;
+        }
+        if display_name
+            .chars()
+            .any(|c| c.is_control() || is_format_character(c))
+        {
+            Err(anyhow!(
+                "display name can't contain control or formatting characters"
+            ))?;
+        }
+        Ok(display_name)
+    }
+
     /// hard delete the user.
     pub async fn destroy_user(&self, id: UserId) -> Result<()> {
         self.transaction(|tx| async move {
@@ -193,31 +426,58 @@ impl Database {
                 .filter(access_token::Column::UserId.eq(id))
                 .exec(&*tx)
                 .await?;
+            let avatar_digest = user::Entity::find_by_id(id)
+                .one(&*tx)
+                .await?
+                .and_then(|user| user.avatar_digest);
             user::Entity::delete_by_id(id).exec(&*tx).await?;
+            if let Some(avatar_digest) = avatar_digest {
+                self.delete_avatar_if_unused(&avatar_digest, &tx).await?;
+            }
             Ok(())
         })
         .await
     }
 
     /// Find users where github_login ILIKE name_query.
+    ///
+    /// On Postgres, the closest matches by trigram distance come first. SQLite has no
+    /// trigrams, so the shortest logins, which the query covers the most of, come
+    /// first instead.
     pub async fn fuzzy_search_users(&self, name_query: &str, limit: u32) -> Result<Vec<User>> {
         self.transaction(|tx| async {
             let tx = tx;
             let like_string = Self::fuzzy_like_string(name_query);
-            let query = "
-                SELECT users.*
-                FROM users
-                WHERE github_login ILIKE $1
-                ORDER BY github_login <-> $2
-                LIMIT $3
-            ";
+            let backend = self.pool.get_database_backend();
+            let statement = if let sea_orm::DatabaseBackend::Postgres = backend {
+                Statement::from_sql_and_values(
+                    backend,
+                    "
+                    SELECT users.*
+                    FROM users
+                    WHERE github_login ILIKE $1
+                    ORDER BY github_login <-> $2, id
+                    LIMIT $3
+                    ",
+                    vec![like_string.into(), name_query.into(), limit.into()],
+                )
+            } else {
+                // SQLite's LIKE ignores case already.
+                Statement::from_sql_and_values(
+                    backend,
+                    "
+                    SELECT users.*
+                    FROM users
+                    WHERE github_login LIKE $1
+                    ORDER BY LENGTH(github_login), id
+                    LIMIT $2
+                    ",
+                    vec![like_string.into(), limit.into()],
+                )
+            };
 
             Ok(user::Entity::find()
-                .from_raw_sql(Statement::from_sql_and_values(
-                    self.pool.get_database_backend(),
-                    query,
-                    vec![like_string.into(), name_query.into(), limit.into()],
-                ))
+                .from_raw_sql(statement)
                 .all(&*tx)
                 .await?)
         })
@@ -293,3 +553,40 @@ impl Database {
         .await
     }
 }
+
+/// The key that display names are unique by, which is the same for names that only
+/// differ in case or in how their characters are composed.
+fn display_name_key(display_name: &str) -> String {
+    // Upper-casing first folds characters like `ß`, whose upper case is spelled with
+    // several characters.
+    display_name.to_uppercase().to_lowercase().nfc().collect()
+}
+
+/// Whether the character is in Unicode's `Cf` category, such as zero-width spaces and
+/// bidirectional overrides, which would let a name look like another one.
+fn is_format_character(c: char) -> bool {
+    matches!(
+        c,
+        '\u{ad}'
+            | '\u{600}'..='\u{605}'
+            | '\u{61c}'
+            | '\u{6dd}'
+            | '\u{70f}'
+            | '\u{890}'..='\u{891}'
+            | '\u{8e2}'
+            | '\u{180e}'
+            | '\u{200b}'..='\u{200f}'
+            | '\u{202a}'..='\u{202e}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{206f}'
+            | '\u{feff}'
+            | '\u{fff9}'..='\u{fffb}'
+            | '\u{110bd}'
+            | '\u{110cd}'
+            | '\u{13430}'..='\u{1343f}'
+            | '\u{1bca0}'..='\u{1bca3}'
+            | '\u{1d173}'..='\u{1d17a}'
+            | '\u{e0001}'
+            | '\u{e0020}'..='\u{e007f}'
+    )
+}
@@ -1,7 +1,12 @@
 pub mod access_token;
+pub mod audit_event;
+pub mod avatar;
+pub mod blocked_user;
 pub mod buffer;
 pub mod buffer_operation;
 pub mod buffer_snapshot;
+pub mod call_record;
+pub mod call_summary;
 pub mod channel;
 pub mod channel_buffer_collaborator;
 pub mod channel_chat_participant;
@@ -9,22 +14,35 @@ pub mod channel_member;
 pub mod channel_message;
 pub mod channel_message_mention;
 pub mod contact;
+pub mod contact_note;
 pub mod contributor;
 pub mod feature_flag;
 pub mod follower;
+pub mod guest;
+pub mod journaled_operation;
 pub mod language_server;
+pub mod location_share;
 pub mod notification;
 pub mod notification_kind;
 pub mod observed_buffer_edits;
 pub mod observed_channel_messages;
 pub mod project;
 pub mod project_collaborator;
+pub mod rate_limited_action;
+pub mod resume_token;
 pub mod room;
+pub mod room_activity;
+pub mod room_message;
 pub mod room_participant;
+pub mod routed_message;
+pub mod runtime_config_override;
 pub mod server;
 pub mod signup;
 pub mod user;
+pub mod user_connection;
 pub mod user_feature;
+pub mod user_report;
+pub mod waiting_call;
 pub mod worktree;
 pub mod worktree_diagnostic_summary;
 pub mod worktree_entry;
@@ -0,0 +1,28 @@
+use crate::db::{AuditEventId, AuditEventKind, ChannelId, ProjectId, RoomId, UserId};
+use sea_orm::entity::prelude::*;
+use serde::Serialize;
+use time::PrimitiveDateTime;
+
+/// Something a user did that admins may need to look back on. Events outlive the
+/// rooms, channels and projects they mention, so their IDs aren't foreign keys.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "audit_events")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: AuditEventId,
+    pub kind: AuditEventKind,
+    /// The user who did it.
+    pub user_id: UserId,
+    /// The user it was done to, for changes to someone's permissions.
+    pub subject_user_id: Option<UserId>,
+    pub room_id: Option<RoomId>,
+    pub channel_id: Option<ChannelId>,
+    pub project_id: Option<ProjectId>,
+    pub detail: Option<String>,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
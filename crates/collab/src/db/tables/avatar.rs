@@ -0,0 +1,18 @@
+use sea_orm::entity::prelude::*;
+
+/// An image uploaded by a user to be shown as their avatar, addressed by the
+/// SHA-256 digest of its contents.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "avatars")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub digest: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,48 @@
+use crate::db::{CallRecordId, CallStatus, RoomId, UserId};
+use rpc::proto;
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// An attempt by one user to call another into a room.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "call_records")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: CallRecordId,
+    pub room_id: RoomId,
+    pub caller_user_id: UserId,
+    pub callee_user_id: UserId,
+    pub created_at: PrimitiveDateTime,
+    pub ended_at: Option<PrimitiveDateTime>,
+    pub status: CallStatus,
+}
+
+impl Model {
+    pub fn to_proto(&self) -> proto::CallRecord {
+        proto::CallRecord {
+            id: self.id.to_proto(),
+            caller_user_id: self.caller_user_id.to_proto(),
+            callee_user_id: self.callee_user_id.to_proto(),
+            timestamp: self.created_at.assume_utc().unix_timestamp() as u64,
+            status: self.status.into(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::CallerUserId",
+        to = "super::user::Column::Id"
+    )]
+    Caller,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::CalleeUserId",
+        to = "super::user::Column::Id"
+    )]
+    Callee,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,174 @@
+use crate::db::{CallSummaryId, ChannelId, RoomId, UserId};
+use collections::HashSet;
+use rpc::proto;
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use time::PrimitiveDateTime;
+
+/// A summary of a call, assembled from the room's activity feed when the last
+/// participant left.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "call_summaries")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: CallSummaryId,
+    pub room_id: RoomId,
+    pub channel_id: Option<ChannelId>,
+    pub started_at: PrimitiveDateTime,
+    pub ended_at: PrimitiveDateTime,
+    /// The [`Content`] of the summary, as JSON.
+    pub content: String,
+}
+
+/// Statistics are only included in a summary once this many participants have
+/// shared theirs. With fewer, a contributor could subtract their own share from a
+/// total to learn the other's.
+pub const MIN_STATISTICS_CONTRIBUTORS: usize = 3;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Content {
+    pub participant_user_ids: Vec<UserId>,
+    pub edited_files: Vec<EditedFile>,
+    #[serde(default)]
+    pub statistics: Option<Statistics>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EditedFile {
+    pub path: String,
+    pub lines_added: u32,
+    pub lines_removed: u32,
+}
+
+/// What a participant who opted into sharing statistics reported about their
+/// part in the call, stored as the detail of their activity.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParticipantStatistics {
+    pub talk_time_ms: Option<u64>,
+    pub characters_written: u64,
+    pub file_times: Vec<FileTime>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileTime {
+    pub path: String,
+    pub duration_ms: u64,
+}
+
+/// Statistics summed across every participant who shared theirs. Only totals are
+/// kept, since per-participant figures could be attributed to their participants,
+/// and the files that were viewed are only counted, not named.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Statistics {
+    pub contributor_count: u32,
+    pub total_talk_time_ms: Option<u64>,
+    pub total_characters_written: u64,
+    pub total_file_time_ms: u64,
+    pub file_count: u32,
+}
+
+impl ParticipantStatistics {
+    pub fn from_proto(statistics: &proto::SessionStatistics) -> Self {
+        Self {
+            talk_time_ms: statistics.talk_time_ms,
+            characters_written: statistics.characters_written,
+            file_times: statistics
+                .file_times
+                .iter()
+                .map(|file_time| FileTime {
+                    path: file_time.path.clone(),
+                    duration_ms: file_time.duration_ms,
+                })
+                .collect(),
+        }
+    }
+
+    /// Adds what a participant reported later on in the call.
+    pub fn merge(&mut self, other: Self) {
+        if let Some(talk_time_ms) = other.talk_time_ms {
+            *self.talk_time_ms.get_or_insert(0) += talk_time_ms;
+        }
+        self.characters_written += other.characters_written;
+        self.file_times.extend(other.file_times);
+    }
+}
+
+impl Statistics {
+    /// Aggregates the statistics each participant shared, unless too few did. Talk
+    /// time is left out unless enough of them were connected to audio.
+    pub fn aggregate(contributors: Vec<ParticipantStatistics>) -> Option<Self> {
+        if contributors.len() < MIN_STATISTICS_CONTRIBUTORS {
+            return None;
+        }
+
+        let mut statistics = Self {
+            contributor_count: contributors.len() as u32,
+            ..Default::default()
+        };
+        let mut talk_time_contributor_count = 0;
+        let mut talk_time_ms = 0u64;
+        let mut paths = HashSet::default();
+        for contributor in contributors {
+            if let Some(contributor_talk_time_ms) = contributor.talk_time_ms {
+                talk_time_contributor_count += 1;
+                talk_time_ms = talk_time_ms.saturating_add(contributor_talk_time_ms);
+            }
+            statistics.total_characters_written = statistics
+                .total_characters_written
+                .saturating_add(contributor.characters_written);
+            for file_time in contributor.file_times {
+                statistics.total_file_time_ms = statistics
+                    .total_file_time_ms
+                    .saturating_add(file_time.duration_ms);
+                paths.insert(file_time.path);
+            }
+        }
+        if talk_time_contributor_count >= MIN_STATISTICS_CONTRIBUTORS {
+            statistics.total_talk_time_ms = Some(talk_time_ms);
+        }
+        statistics.file_count = paths.len() as u32;
+        Some(statistics)
+    }
+
+    pub fn to_proto(&self) -> proto::CallStatistics {
+        proto::CallStatistics {
+            contributor_count: self.contributor_count,
+            total_talk_time_ms: self.total_talk_time_ms,
+            total_characters_written: self.total_characters_written,
+            total_file_time_ms: self.total_file_time_ms,
+            file_count: self.file_count,
+        }
+    }
+}
+
+impl Model {
+    pub fn to_proto(&self, content: Content) -> proto::CallSummary {
+        proto::CallSummary {
+            id: self.id.to_proto(),
+            channel_id: self.channel_id.map(|id| id.to_proto()),
+            started_at: self.started_at.assume_utc().unix_timestamp() as u64,
+            ended_at: self.ended_at.assume_utc().unix_timestamp() as u64,
+            participant_user_ids: content
+                .participant_user_ids
+                .iter()
+                .map(|id| id.to_proto())
+                .collect(),
+            edited_files: content
+                .edited_files
+                .into_iter()
+                .map(|file| proto::EditedFile {
+                    path: file.path,
+                    lines_added: file.lines_added,
+                    lines_removed: file.lines_removed,
+                })
+                .collect(),
+            statistics: content.statistics.map(|statistics| statistics.to_proto()),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
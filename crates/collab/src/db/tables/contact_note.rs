@@ -0,0 +1,42 @@
+use crate::db::UserId;
+use sea_orm::entity::prelude::*;
+
+/// A private nickname and note that a user has attached to one of their contacts.
+#[derive(Clone, Debug, Default, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "contact_notes")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub user_id: UserId,
+    #[sea_orm(primary_key)]
+    pub contact_user_id: UserId,
+    pub nickname: Option<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    pub fn to_proto(&self) -> rpc::proto::ContactNote {
+        rpc::proto::ContactNote {
+            user_id: self.contact_user_id.to_proto(),
+            nickname: self.nickname.clone(),
+            note: self.note.clone(),
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use crate::db::{ChannelRole, RoomId, UserId};
+use sea_orm::entity::prelude::*;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+/// A user without an account, who was invited to take part in a single room for a
+/// limited time.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "guests")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: UserId,
+    /// The only room the guest can take part in, until it ends.
+    pub room_id: Option<RoomId>,
+    /// [`ChannelRole::Guest`] for viewers, or [`ChannelRole::Member`] for editors.
+    pub role: ChannelRole,
+    pub invited_by_id: UserId,
+    pub expires_at: PrimitiveDateTime,
+}
+
+impl Model {
+    pub fn is_expired(&self, now: OffsetDateTime) -> bool {
+        self.expires_at.assume_utc() <= now
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,19 @@
+use crate::db::ProjectId;
+use sea_orm::entity::prelude::*;
+
+/// A buffer operation that a guest sent while the project's host was disconnected,
+/// which is replayed to the host when it rejoins.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "journaled_operations")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub project_id: ProjectId,
+    pub buffer_id: i64,
+    pub operation: Vec<u8>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,4 +1,4 @@
-use crate::db::{ProjectId, Result, RoomId, ServerId, UserId};
+use crate::db::{ChannelRole, ProjectId, Result, RoomId, ServerId, UserId};
 use anyhow::anyhow;
 use rpc::ConnectionId;
 use sea_orm::entity::prelude::*;
@@ -12,6 +12,15 @@ pub struct Model {
     pub host_user_id: UserId,
     pub host_connection_id: Option<i32>,
     pub host_connection_server_id: Option<ServerId>,
+    /// The id the host gave the project, which unlike the project's own id stays the
+    /// same when the host shares it again.
+    pub host_project_id: i64,
+    /// Whether the host's collaboration policy makes the project read-only for
+    /// everyone but channel admins.
+    pub guests_read_only: bool,
+    /// A comma-separated list of the roles that may join the project, or `None`
+    /// if every role may join.
+    pub allowed_roles: Option<String>,
 }
 
 impl Model {
@@ -27,6 +36,35 @@ impl Model {
             id: host_connection_id as u32,
         })
     }
+
+    /// Whether a room participant with the given role may join this project.
+    pub fn can_be_joined_by(&self, role: Option<ChannelRole>) -> bool {
+        let Some(allowed_roles) = &self.allowed_roles else {
+            return true;
+        };
+        role.map_or(false, |role| {
+            let role = role.to_value();
+            allowed_roles
+                .split(',')
+                .any(|allowed_role| allowed_role == role)
+        })
+    }
+
+    /// Whether the room participant with the given role and connection may edit
+    /// this project when they join it. The host can always edit their own project.
+    pub fn can_be_edited_by(&self, role: Option<ChannelRole>, connection: ConnectionId) -> bool {
+        if self.host_connection().ok() == Some(connection) {
+            return true;
+        }
+        role.map_or(false, |role| role.can_edit_projects())
+            && !self.is_read_only_by_policy_for(role)
+    }
+
+    /// Whether the host's policy makes the project read-only for guests with the
+    /// given role when they join. The host can change this for each guest later.
+    pub fn is_read_only_by_policy_for(&self, role: Option<ChannelRole>) -> bool {
+        self.guests_read_only && role != Some(ChannelRole::Admin)
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
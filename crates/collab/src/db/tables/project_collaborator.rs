@@ -1,4 +1,4 @@
-use crate::db::{ProjectCollaboratorId, ProjectId, ReplicaId, ServerId, UserId};
+use crate::db::{ChannelRole, ProjectCollaboratorId, ProjectId, ReplicaId, ServerId, UserId};
 use rpc::ConnectionId;
 use sea_orm::entity::prelude::*;
 
@@ -13,6 +13,8 @@ pub struct Model {
     pub user_id: UserId,
     pub replica_id: ReplicaId,
     pub is_host: bool,
+    /// Whether the host has made the project read-only for this guest.
+    pub read_only: bool,
 }
 
 impl Model {
@@ -22,6 +24,12 @@ impl Model {
             id: self.connection_id as u32,
         }
     }
+
+    /// Whether this collaborator, whose room participant has the given role,
+    /// may edit the project.
+    pub fn can_edit(&self, role: Option<ChannelRole>) -> bool {
+        self.is_host || (!self.read_only && role.map_or(false, |role| role.can_edit_projects()))
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
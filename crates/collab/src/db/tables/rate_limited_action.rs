@@ -0,0 +1,21 @@
+use crate::db::{RateLimitedActionKind, UserId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// An action that a user took recently, which counts toward their limit for actions
+/// of its kind.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "rate_limited_actions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: UserId,
+    pub kind: RateLimitedActionKind,
+    pub target_user_id: Option<UserId>,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
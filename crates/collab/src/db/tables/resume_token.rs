@@ -0,0 +1,20 @@
+use crate::db::{ServerId, UserId};
+use sea_orm::entity::prelude::*;
+
+/// A token that lets a client, once its server restarts, resume the connection it
+/// had to that server from another one.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "resume_tokens")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: UserId,
+    pub connection_server_id: ServerId,
+    pub connection_id: i32,
+    pub token_hash: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,5 +1,6 @@
 use crate::db::{ChannelId, RoomId};
 use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
 
 #[derive(Clone, Default, Debug, PartialEq, Eq, DeriveEntityModel)]
 #[sea_orm(table_name = "rooms")]
@@ -8,6 +9,14 @@ pub struct Model {
     pub id: RoomId,
     pub live_kit_room: String,
     pub channel_id: Option<ChannelId>,
+    /// Overrides the deployment's ICE servers for this room, as an
+    /// [`IceConfig`](crate::ice::IceConfig) encrypted with
+    /// [`IceServersKey`](crate::ice::IceServersKey).
+    pub ice_servers: Option<String>,
+    /// When the room ends, if it was created with a fixed duration.
+    pub ends_at: Option<PrimitiveDateTime>,
+    /// When the room was created, which is when its call started.
+    pub created_at: Option<PrimitiveDateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -18,6 +27,8 @@ pub enum Relation {
     Project,
     #[sea_orm(has_many = "super::follower::Entity")]
     Follower,
+    #[sea_orm(has_many = "super::waiting_call::Entity")]
+    WaitingCall,
     #[sea_orm(
         belongs_to = "super::channel::Entity",
         from = "Column::ChannelId",
@@ -44,6 +55,12 @@ impl Related<super::follower::Entity> for Entity {
     }
 }
 
+impl Related<super::waiting_call::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::WaitingCall.def()
+    }
+}
+
 impl Related<super::channel::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Channel.def()
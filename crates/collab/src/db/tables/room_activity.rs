@@ -0,0 +1,42 @@
+use crate::db::{RoomActivityId, RoomActivityKind, RoomId, UserId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// An entry in the activity feed of a call that is in progress.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "room_activities")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: RoomActivityId,
+    pub room_id: RoomId,
+    pub user_id: UserId,
+    pub kind: RoomActivityKind,
+    pub detail: Option<String>,
+    pub lines_added: i32,
+    pub lines_removed: i32,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::room::Entity",
+        from = "Column::RoomId",
+        to = "super::room::Column::Id"
+    )]
+    Room,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl Related<super::room::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Room.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
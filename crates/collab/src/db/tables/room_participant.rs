@@ -1,6 +1,10 @@
 use crate::db::{ChannelRole, ProjectId, RoomId, RoomParticipantId, ServerId, UserId};
-use rpc::ConnectionId;
+use rpc::{proto, ConnectionId};
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Participants whose connection is slower than this are asked to send fewer updates.
+const REDUCED_SYNC_BANDWIDTH_KBPS: u64 = 256;
 
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
 #[sea_orm(table_name = "room_participants")]
@@ -20,6 +24,55 @@ pub struct Model {
     pub calling_connection_server_id: Option<ServerId>,
     pub participant_index: Option<i32>,
     pub role: Option<ChannelRole>,
+    /// The participant's [`Capabilities`], as JSON, if their client reported any.
+    pub capabilities: Option<String>,
+    /// Whether the participant is observing the room, hidden from participants
+    /// who can't [see observers](Model::can_see_observers).
+    pub observer: bool,
+}
+
+/// What the participant's client found out about itself in the checks it ran
+/// before joining the room.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub protocol_version: u32,
+    pub media_available: bool,
+    pub clock_skew_ms: i64,
+    pub bandwidth_kbps: u64,
+}
+
+impl Capabilities {
+    pub fn from_proto(capabilities: &proto::ParticipantCapabilities) -> Self {
+        Self {
+            protocol_version: capabilities.protocol_version,
+            media_available: capabilities.media_available,
+            clock_skew_ms: capabilities.clock_skew_ms,
+            bandwidth_kbps: capabilities.bandwidth_kbps,
+        }
+    }
+
+    pub fn to_proto(&self) -> proto::ParticipantCapabilities {
+        proto::ParticipantCapabilities {
+            protocol_version: self.protocol_version,
+            media_available: self.media_available,
+            clock_skew_ms: self.clock_skew_ms,
+            bandwidth_kbps: self.bandwidth_kbps,
+        }
+    }
+
+    /// Converts the capabilities a client reported into the form they're stored in.
+    pub fn json_from_proto(
+        capabilities: Option<&proto::ParticipantCapabilities>,
+    ) -> serde_json::Result<Option<String>> {
+        capabilities
+            .map(|capabilities| serde_json::to_string(&Self::from_proto(capabilities)))
+            .transpose()
+    }
+
+    /// Whether the participant should send updates less often than usual.
+    pub fn needs_reduced_sync(&self) -> bool {
+        self.bandwidth_kbps != 0 && self.bandwidth_kbps < REDUCED_SYNC_BANDWIDTH_KBPS
+    }
 }
 
 impl Model {
@@ -29,6 +82,15 @@ impl Model {
             id: self.answering_connection_id? as u32,
         })
     }
+
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        serde_json::from_str(self.capabilities.as_deref()?).ok()
+    }
+
+    /// Observers are only visible to the room's admins and to other observers.
+    pub fn can_see_observers(&self) -> bool {
+        self.observer || self.role == Some(ChannelRole::Admin)
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -0,0 +1,24 @@
+use crate::db::ServerId;
+use sea_orm::entity::prelude::*;
+
+/// A message that one server published for a connection on another server, which
+/// hasn't been delivered yet.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "routed_messages")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub server_id: ServerId,
+    pub connection_id: i32,
+    /// Set for requests and their responses, which are matched up by it.
+    pub request_id: Option<i32>,
+    /// The server that a request's response is routed back to, which is only set
+    /// for requests.
+    pub requester_server_id: Option<ServerId>,
+    pub envelope: Vec<u8>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
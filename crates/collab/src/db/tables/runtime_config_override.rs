@@ -0,0 +1,18 @@
+use sea_orm::entity::prelude::*;
+
+/// A value that replaces the one a server in the environment was started with for
+/// one of its environment variables, such as `RATE_LIMITS`.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "runtime_config_overrides")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub environment: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
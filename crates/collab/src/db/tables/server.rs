@@ -1,5 +1,6 @@
 use crate::db::ServerId;
 use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
 
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
 #[sea_orm(table_name = "servers")]
@@ -7,6 +8,9 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: ServerId,
     pub environment: String,
+    /// Once the server has gone stale, when its clients stop being able to rejoin
+    /// the rooms they were in.
+    pub rejoin_deadline: Option<PrimitiveDateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -1,6 +1,7 @@
 use crate::db::UserId;
 use sea_orm::entity::prelude::*;
 use serde::Serialize;
+use time::PrimitiveDateTime;
 
 /// A user model.
 #[derive(Clone, Debug, Default, PartialEq, Eq, DeriveEntityModel, Serialize)]
@@ -18,6 +19,16 @@ pub struct Model {
     pub connected_once: bool,
     pub metrics_id: Uuid,
     pub created_at: DateTime,
+    /// The name shown to other users instead of the GitHub login, if the user
+    /// has chosen one.
+    pub display_name: Option<String>,
+    /// The display name, case-folded and normalized, which is unique among users.
+    #[serde(skip)]
+    pub display_name_key: Option<String>,
+    /// The digest of the avatar the user uploaded, if any.
+    pub avatar_digest: Option<String>,
+    /// When the user was banned, which stops them from signing in.
+    pub banned_at: Option<PrimitiveDateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -0,0 +1,19 @@
+use crate::db::{ServerId, UserId};
+use sea_orm::entity::prelude::*;
+
+/// A connection a user has open to one of the servers, which lets the number of
+/// connections each user has be limited across all of them.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "user_connections")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: UserId,
+    pub connection_server_id: ServerId,
+    pub connection_id: i32,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
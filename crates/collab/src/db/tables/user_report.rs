@@ -0,0 +1,31 @@
+use crate::db::{ReportStatus, RoomId, UserId, UserReportId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// A report that one user made about another's conduct, kept for admins to review.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "user_reports")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: UserReportId,
+    pub reporter_id: UserId,
+    pub reported_user_id: UserId,
+    /// The room the report was made from, if it's still around.
+    pub room_id: Option<RoomId>,
+    /// The reported channel messages' IDs, as a JSON array.
+    pub channel_message_ids: String,
+    /// The reported room messages' IDs, as a JSON array.
+    pub room_message_ids: String,
+    pub reason: String,
+    /// Whether the report counts toward throttling the reported user, which it only
+    /// does if the reporter isn't a guest and was in the room with them.
+    pub counts_toward_throttle: bool,
+    pub status: ReportStatus,
+    pub created_at: PrimitiveDateTime,
+    pub reviewed_at: Option<PrimitiveDateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
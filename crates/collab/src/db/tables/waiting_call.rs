@@ -0,0 +1,37 @@
+use crate::db::{ChannelRole, ProjectId, RoomId, ServerId, UserId, WaitingCallId};
+use sea_orm::entity::prelude::*;
+
+/// A call to a user who was already in another room when it was placed. It
+/// rings alongside their current call until they decline it, or hang up and
+/// accept it.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "waiting_calls")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: WaitingCallId,
+    pub room_id: RoomId,
+    pub called_user_id: UserId,
+    pub calling_user_id: UserId,
+    pub calling_connection_id: i32,
+    pub calling_connection_server_id: Option<ServerId>,
+    pub initial_project_id: Option<ProjectId>,
+    pub role: Option<ChannelRole>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::room::Entity",
+        from = "Column::RoomId",
+        to = "super::room::Column::Id"
+    )]
+    Room,
+}
+
+impl Related<super::room::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Room.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
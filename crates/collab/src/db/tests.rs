@@ -35,17 +35,15 @@ impl TestDb {
             let mut db = Database::new(options, Executor::Deterministic(background))
                 .await
                 .unwrap();
-            let sql = include_str!(concat!(
-                env!("CARGO_MANIFEST_DIR"),
-                "/migrations.sqlite/20221109000000_test_schema.sql"
-            ));
-            db.pool
-                .execute(sea_orm::Statement::from_string(
-                    db.pool.get_database_backend(),
-                    sql,
-                ))
-                .await
-                .unwrap();
+            for migration in sqlx::migrate!("./migrations.sqlite").iter() {
+                db.pool
+                    .execute(sea_orm::Statement::from_string(
+                        db.pool.get_database_backend(),
+                        migration.sql.to_string(),
+                    ))
+                    .await
+                    .unwrap();
+            }
             db.initialize_notification_kinds().await.unwrap();
             db
         });
@@ -103,6 +101,78 @@ impl TestDb {
     }
 }
 
+/// How often the transactions run by a test database fail. A failing transaction
+/// is never run, so the database is left as it was, and the fault is chosen by
+/// the database's own RNG, so that a failing seed reproduces the same errors.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DatabaseFaults {
+    /// The probability that a transaction fails straight away, as happens when
+    /// the connection to the database is lost.
+    pub error_probability: f64,
+    /// The probability that a transaction fails after keeping its caller waiting,
+    /// as happens when no connection can be acquired from the pool.
+    pub timeout_probability: f64,
+    /// The most times that a transaction which times out yields to other tasks
+    /// before it fails.
+    pub max_timeout: usize,
+}
+
+enum DatabaseFault {
+    Error,
+    Timeout(usize),
+}
+
+impl DatabaseFaults {
+    fn choose(&self, rng: &mut StdRng) -> Option<DatabaseFault> {
+        if rng.gen_bool(self.error_probability) {
+            Some(DatabaseFault::Error)
+        } else if rng.gen_bool(self.timeout_probability) {
+            Some(DatabaseFault::Timeout(
+                rng.gen_range(1..=self.max_timeout.max(1)),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl Database {
+    /// Makes transactions fail with the given probabilities, drawing the faults
+    /// from `rng`.
+    pub fn inject_faults(&self, faults: DatabaseFaults, rng: StdRng) {
+        *self.faults.lock() = (faults, rng);
+    }
+
+    /// Changes how often transactions fail, while still drawing the faults from
+    /// the RNG given to [`Database::inject_faults`].
+    pub fn set_faults(&self, faults: DatabaseFaults) {
+        self.faults.lock().0 = faults;
+    }
+
+    pub(super) async fn inject_fault(&self, executor: &BackgroundExecutor) -> Result<()> {
+        let fault = {
+            let (faults, rng) = &mut *self.faults.lock();
+            faults.choose(rng)
+        };
+        match fault {
+            None => Ok(()),
+            Some(DatabaseFault::Error) => Err(injected_error("injected database error")),
+            Some(DatabaseFault::Timeout(yields)) => {
+                for _ in 0..yields {
+                    executor.simulate_random_delay().await;
+                }
+                Err(injected_error("injected database timeout"))
+            }
+        }
+    }
+}
+
+/// Injected faults fail the same way as losing the connection to the database does,
+/// so that callers can't tell them apart from real failures.
+fn injected_error(message: &str) -> Error {
+    DbErr::Conn(sea_orm::RuntimeErr::Internal(message.to_string())).into()
+}
+
 #[macro_export]
 macro_rules! test_both_dbs {
     ($test_name:ident, $postgres_test_name:ident, $sqlite_test_name:ident) => {
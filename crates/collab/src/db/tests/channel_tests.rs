@@ -135,16 +135,17 @@ async fn test_joining_channels(db: &Arc<Database>) {
 
     // can join a room with membership to its channel
     let (joined_room, _, _) = db
-        .join_channel(channel_1, user_1, ConnectionId { owner_id, id: 1 })
+        .join_channel(channel_1, user_1, ConnectionId { owner_id, id: 1 }, None, 0)
         .await
-        .unwrap();
+        .unwrap()
+        .into_inner();
     assert_eq!(joined_room.room.participants.len(), 1);
 
     let room_id = RoomId::from_proto(joined_room.room.id);
     drop(joined_room);
     // cannot join a room without membership to its channel
     assert!(db
-        .join_room(room_id, user_2, ConnectionId { owner_id, id: 1 },)
+        .join_room(room_id, user_2, ConnectionId { owner_id, id: 1 }, None)
         .await
         .is_err());
 }
@@ -722,7 +723,7 @@ async fn test_guest_access(db: &Arc<Database>) {
         .await
         .is_err());
 
-    db.join_channel(zed_channel, guest, guest_connection)
+    db.join_channel(zed_channel, guest, guest_connection, None, 0)
         .await
         .unwrap();
 
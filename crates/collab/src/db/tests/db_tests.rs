@@ -1,9 +1,7 @@
 use super::*;
 use crate::test_both_dbs;
-use gpui::TestAppContext;
 use pretty_assertions::{assert_eq, assert_ne};
 use std::sync::Arc;
-use tests::TestDb;
 
 test_both_dbs!(
     test_get_users,
@@ -439,49 +437,1298 @@ async fn test_add_contacts(db: &Arc<Database>) {
     );
 }
 
+test_both_dbs!(
+    test_contact_notes,
+    test_contact_notes_postgres,
+    test_contact_notes_sqlite
+);
+
+async fn test_contact_notes(db: &Arc<Database>) {
+    let mut user_ids = Vec::new();
+    for i in 0..3 {
+        user_ids.push(
+            db.create_user(
+                &format!("user{i}@example.com"),
+                false,
+                NewUserParams {
+                    github_login: format!("user{i}"),
+                    github_user_id: i,
+                },
+            )
+            .await
+            .unwrap()
+            .user_id,
+        );
+    }
+
+    let user_1 = user_ids[0];
+    let user_2 = user_ids[1];
+    let user_3 = user_ids[2];
+
+    db.send_contact_request(user_1, user_2).await.unwrap();
+
+    // Notes can't be attached to pending contacts.
+    db.set_contact_note(user_1, user_2, Some("Two".into()), None)
+        .await
+        .unwrap_err();
+
+    db.respond_to_contact_request(user_2, user_1, true)
+        .await
+        .unwrap();
+
+    // Notes can't be attached to users who aren't contacts.
+    db.set_contact_note(user_1, user_3, Some("Three".into()), None)
+        .await
+        .unwrap_err();
+
+    db.set_contact_note(
+        user_1,
+        user_2,
+        Some("Two".into()),
+        Some("Met at the offsite".into()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        db.get_contact_notes(user_1).await.unwrap(),
+        &[contact_note::Model {
+            user_id: user_1,
+            contact_user_id: user_2,
+            nickname: Some("Two".into()),
+            note: Some("Met at the offsite".into()),
+        }]
+    );
+
+    // Notes are private to the user who wrote them.
+    assert_eq!(db.get_contact_notes(user_2).await.unwrap(), &[]);
+
+    // Notes can be updated.
+    db.set_contact_note(user_1, user_2, Some("Deux".into()), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        db.get_contact_notes(user_1).await.unwrap(),
+        &[contact_note::Model {
+            user_id: user_1,
+            contact_user_id: user_2,
+            nickname: Some("Deux".into()),
+            note: None,
+        }]
+    );
+
+    // Clearing both the nickname and the note removes the row.
+    db.set_contact_note(user_1, user_2, None, None)
+        .await
+        .unwrap();
+    assert_eq!(db.get_contact_notes(user_1).await.unwrap(), &[]);
+
+    // Notes are deleted along with the contact.
+    db.set_contact_note(user_1, user_2, Some("Two".into()), None)
+        .await
+        .unwrap();
+    db.set_contact_note(user_2, user_1, Some("One".into()), None)
+        .await
+        .unwrap();
+    db.remove_contact(user_1, user_2).await.unwrap();
+    assert_eq!(db.get_contact_notes(user_1).await.unwrap(), &[]);
+    assert_eq!(db.get_contact_notes(user_2).await.unwrap(), &[]);
+}
+
+test_both_dbs!(
+    test_location_sharing,
+    test_location_sharing_postgres,
+    test_location_sharing_sqlite
+);
+
+async fn test_location_sharing(db: &Arc<Database>) {
+    let mut user_ids = Vec::new();
+    for i in 0..3 {
+        user_ids.push(
+            db.create_user(
+                &format!("user{i}@example.com"),
+                false,
+                NewUserParams {
+                    github_login: format!("user{i}"),
+                    github_user_id: i,
+                },
+            )
+            .await
+            .unwrap()
+            .user_id,
+        );
+    }
+
+    let user_1 = user_ids[0];
+    let user_2 = user_ids[1];
+    let user_3 = user_ids[2];
+
+    db.send_contact_request(user_1, user_2).await.unwrap();
+
+    // Locations can't be shared with pending contacts.
+    db.set_location_sharing(user_1, user_2, true)
+        .await
+        .unwrap_err();
+
+    db.respond_to_contact_request(user_2, user_1, true)
+        .await
+        .unwrap();
+
+    // Locations can't be shared with users who aren't contacts.
+    db.set_location_sharing(user_1, user_3, true)
+        .await
+        .unwrap_err();
+
+    // Sharing is one-directional.
+    db.set_location_sharing(user_1, user_2, true).await.unwrap();
+    db.set_location_sharing(user_1, user_2, true).await.unwrap();
+    assert_eq!(
+        db.location_shared_with(user_1).await.unwrap(),
+        HashSet::from_iter([user_2])
+    );
+    assert_eq!(
+        db.location_shared_by(user_2).await.unwrap(),
+        HashSet::from_iter([user_1])
+    );
+    assert!(db.location_shared_with(user_2).await.unwrap().is_empty());
+    assert!(db.location_shared_by(user_1).await.unwrap().is_empty());
+
+    db.set_location_sharing(user_1, user_2, false)
+        .await
+        .unwrap();
+    assert!(db.location_shared_with(user_1).await.unwrap().is_empty());
+
+    // Consent is withdrawn along with the contact.
+    db.set_location_sharing(user_1, user_2, true).await.unwrap();
+    db.set_location_sharing(user_2, user_1, true).await.unwrap();
+    db.remove_contact(user_1, user_2).await.unwrap();
+    assert!(db.location_shared_with(user_1).await.unwrap().is_empty());
+    assert!(db.location_shared_with(user_2).await.unwrap().is_empty());
+}
+
 test_both_dbs!(
     test_metrics_id,
     test_metrics_id_postgres,
     test_metrics_id_sqlite
 );
 
-async fn test_metrics_id(db: &Arc<Database>) {
-    let NewUserResult {
-        user_id: user1,
-        metrics_id: metrics_id1,
-        ..
-    } = db
-        .create_user(
-            "person1@example.com",
-            false,
-            NewUserParams {
-                github_login: "person1".into(),
-                github_user_id: 101,
+async fn test_metrics_id(db: &Arc<Database>) {
+    let NewUserResult {
+        user_id: user1,
+        metrics_id: metrics_id1,
+        ..
+    } = db
+        .create_user(
+            "person1@example.com",
+            false,
+            NewUserParams {
+                github_login: "person1".into(),
+                github_user_id: 101,
+            },
+        )
+        .await
+        .unwrap();
+    let NewUserResult {
+        user_id: user2,
+        metrics_id: metrics_id2,
+        ..
+    } = db
+        .create_user(
+            "person2@example.com",
+            false,
+            NewUserParams {
+                github_login: "person2".into(),
+                github_user_id: 102,
+            },
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(db.get_user_metrics_id(user1).await.unwrap(), metrics_id1);
+    assert_eq!(db.get_user_metrics_id(user2).await.unwrap(), metrics_id2);
+    assert_eq!(metrics_id1.len(), 36);
+    assert_eq!(metrics_id2.len(), 36);
+    assert_ne!(metrics_id1, metrics_id2);
+}
+
+test_both_dbs!(
+    test_user_display_names,
+    test_user_display_names_postgres,
+    test_user_display_names_sqlite
+);
+
+async fn test_user_display_names(db: &Arc<Database>) {
+    let mut user_ids = Vec::new();
+    for i in 1..=2 {
+        let user = db
+            .create_user(
+                &format!("user{i}@example.com"),
+                false,
+                NewUserParams {
+                    github_login: format!("user{i}"),
+                    github_user_id: i,
+                },
+            )
+            .await
+            .unwrap();
+        user_ids.push(user.user_id);
+    }
+    let (user_1, user_2) = (user_ids[0], user_ids[1]);
+
+    let updated = db
+        .set_user_display_name(user_1, Some("  Ada Lovelace "))
+        .await
+        .unwrap();
+    assert_eq!(updated.user.display_name.as_deref(), Some("Ada Lovelace"));
+    assert!(updated.room_connection_ids.is_empty());
+    assert_eq!(
+        db.get_user_by_id(user_1)
+            .await
+            .unwrap()
+            .unwrap()
+            .display_name
+            .as_deref(),
+        Some("Ada Lovelace")
+    );
+
+    // Names are unique regardless of case, and can't be another user's login.
+    db.set_user_display_name(user_2, Some("ADA LOVELACE"))
+        .await
+        .unwrap_err();
+    db.set_user_display_name(user_2, Some("User1"))
+        .await
+        .unwrap_err();
+    db.set_user_display_name(user_1, Some("ada lovelace"))
+        .await
+        .unwrap();
+    db.set_user_display_name(user_2, Some("user2"))
+        .await
+        .unwrap();
+
+    // Names can't be blank, too long, or contain control or formatting characters.
+    db.set_user_display_name(user_2, Some("   "))
+        .await
+        .unwrap_err();
+    db.set_user_display_name(user_2, Some(&"a".repeat(MAX_DISPLAY_NAME_LENGTH + 1)))
+        .await
+        .unwrap_err();
+    db.set_user_display_name(user_2, Some("Grace\nHopper"))
+        .await
+        .unwrap_err();
+    db.set_user_display_name(user_2, Some("Grace\u{200b}Hopper"))
+        .await
+        .unwrap_err();
+    db.set_user_display_name(user_2, Some("Grace\u{202e}Hopper"))
+        .await
+        .unwrap_err();
+    db.set_user_display_name(user_2, Some(&"é".repeat(MAX_DISPLAY_NAME_LENGTH)))
+        .await
+        .unwrap();
+
+    // Names are normalized, so they're unique regardless of how their characters are
+    // composed, and case is folded beyond ASCII.
+    let updated = db
+        .set_user_display_name(user_1, Some("Rene\u{301} Straße"))
+        .await
+        .unwrap();
+    assert_eq!(updated.user.display_name.as_deref(), Some("René Straße"));
+    db.set_user_display_name(user_2, Some("RENÉ STRASSE"))
+        .await
+        .unwrap_err();
+    db.set_user_display_name(user_2, Some("rené strasse"))
+        .await
+        .unwrap_err();
+    db.set_user_display_name(user_1, Some("ada lovelace"))
+        .await
+        .unwrap();
+
+    // Clearing a name frees it up for other users.
+    db.set_user_display_name(user_1, None).await.unwrap();
+    db.set_user_display_name(user_2, Some("Ada Lovelace"))
+        .await
+        .unwrap();
+    assert_eq!(
+        db.get_user_by_id(user_1)
+            .await
+            .unwrap()
+            .unwrap()
+            .display_name,
+        None
+    );
+}
+
+test_both_dbs!(
+    test_user_avatars,
+    test_user_avatars_postgres,
+    test_user_avatars_sqlite
+);
+
+async fn test_user_avatars(db: &Arc<Database>) {
+    let mut user_ids = Vec::new();
+    for i in 1..=2 {
+        let user = db
+            .create_user(
+                &format!("user{i}@example.com"),
+                false,
+                NewUserParams {
+                    github_login: format!("user{i}"),
+                    github_user_id: i,
+                },
+            )
+            .await
+            .unwrap();
+        user_ids.push(user.user_id);
+    }
+    let (user_1, user_2) = (user_ids[0], user_ids[1]);
+
+    let updated = db
+        .set_user_avatar(user_1, "abc", "image/png", &[1, 2, 3])
+        .await
+        .unwrap();
+    assert_eq!(updated.user.avatar_digest.as_deref(), Some("abc"));
+    assert!(updated.room_connection_ids.is_empty());
+
+    // Uploading an identical image reuses the stored avatar.
+    let updated = db
+        .set_user_avatar(user_2, "abc", "image/png", &[1, 2, 3])
+        .await
+        .unwrap();
+    assert_eq!(updated.user.avatar_digest.as_deref(), Some("abc"));
+
+    let avatar = db.get_avatar("abc").await.unwrap().unwrap();
+    assert_eq!(avatar.content_type, "image/png");
+    assert_eq!(avatar.data, vec![1, 2, 3]);
+    assert!(db.get_avatar("def").await.unwrap().is_none());
+
+    // A replaced avatar is kept while someone else still has it...
+    db.set_user_avatar(user_1, "def", "image/png", &[4, 5, 6])
+        .await
+        .unwrap();
+    assert!(db.get_avatar("abc").await.unwrap().is_some());
+
+    // ...and deleted once nobody has it.
+    db.set_user_avatar(user_2, "def", "image/png", &[4, 5, 6])
+        .await
+        .unwrap();
+    assert!(db.get_avatar("abc").await.unwrap().is_none());
+
+    // Deleting users deletes the avatars that only they had.
+    db.destroy_user(user_1).await.unwrap();
+    assert!(db.get_avatar("def").await.unwrap().is_some());
+    db.destroy_user(user_2).await.unwrap();
+    assert!(db.get_avatar("def").await.unwrap().is_none());
+}
+
+test_both_dbs!(
+    test_routed_messages,
+    test_routed_messages_postgres,
+    test_routed_messages_sqlite
+);
+
+async fn test_routed_messages(db: &Arc<Database>) {
+    let server_1 = db.create_server("test").await.unwrap();
+    let server_2 = db.create_server("test").await.unwrap();
+
+    let message = |server_id, connection_id: u32, request_id| NewRoutedMessage {
+        server_id,
+        connection_id,
+        request_id,
+        requester_server_id: request_id.map(|_| server_2),
+        envelope: vec![connection_id as u8],
+    };
+    db.publish_routed_messages(vec![
+        message(server_1, 1, None),
+        message(server_2, 2, None),
+        message(server_1, 3, Some(7)),
+    ])
+    .await
+    .unwrap();
+
+    let messages = db.take_routed_messages(server_1).await.unwrap();
+    assert_eq!(
+        messages
+            .iter()
+            .map(|message| (
+                message.connection_id,
+                message.request_id,
+                message.requester_server_id,
+                message.envelope.clone()
+            ))
+            .collect::<Vec<_>>(),
+        &[
+            (1, None, None, vec![1]),
+            (3, Some(7), Some(server_2), vec![3])
+        ]
+    );
+
+    // Messages are only taken once.
+    assert!(db.take_routed_messages(server_1).await.unwrap().is_empty());
+
+    // Messages for servers that are deleted are discarded along with them.
+    db.delete_stale_servers("test", server_1).await.unwrap();
+    assert!(db.take_routed_messages(server_2).await.unwrap().is_empty());
+}
+
+test_both_dbs!(
+    test_runtime_config_overrides,
+    test_runtime_config_overrides_postgres,
+    test_runtime_config_overrides_sqlite
+);
+
+async fn test_runtime_config_overrides(db: &Arc<Database>) {
+    db.set_runtime_config_override("test", "RATE_LIMITS", Some("{}"))
+        .await
+        .unwrap();
+    db.set_runtime_config_override("test", "LAZY_WORKTREE_ENTRY_THRESHOLD", Some("10"))
+        .await
+        .unwrap();
+    db.set_runtime_config_override("staging", "LAZY_WORKTREE_ENTRY_THRESHOLD", Some("20"))
+        .await
+        .unwrap();
+    db.set_runtime_config_override("test", "LAZY_WORKTREE_ENTRY_THRESHOLD", Some("30"))
+        .await
+        .unwrap();
+    assert_eq!(
+        db.get_runtime_config_overrides("test").await.unwrap(),
+        &[
+            (
+                "LAZY_WORKTREE_ENTRY_THRESHOLD".to_string(),
+                "30".to_string()
+            ),
+            ("RATE_LIMITS".to_string(), "{}".to_string()),
+        ]
+    );
+
+    db.set_runtime_config_override("test", "RATE_LIMITS", None)
+        .await
+        .unwrap();
+    assert_eq!(
+        db.get_runtime_config_overrides("test").await.unwrap(),
+        &[(
+            "LAZY_WORKTREE_ENTRY_THRESHOLD".to_string(),
+            "30".to_string()
+        )]
+    );
+
+    // Changes made together are stored together, or not at all when the overrides
+    // they result in are rejected.
+    let changes = [
+        ("LAZY_WORKTREE_ENTRY_THRESHOLD".to_string(), None),
+        ("FEATURE_FLAGS".to_string(), Some("notes".to_string())),
+    ];
+    db.update_runtime_config_overrides("test", &changes, |overrides| {
+        assert_eq!(
+            overrides,
+            &[("FEATURE_FLAGS".to_string(), "notes".to_string())]
+        );
+        Err(anyhow!("rejected"))?
+    })
+    .await
+    .unwrap_err();
+    assert_eq!(
+        db.get_runtime_config_overrides("test").await.unwrap(),
+        &[(
+            "LAZY_WORKTREE_ENTRY_THRESHOLD".to_string(),
+            "30".to_string()
+        )]
+    );
+    db.update_runtime_config_overrides("test", &changes, |_| Ok(()))
+        .await
+        .unwrap();
+    assert_eq!(
+        db.get_runtime_config_overrides("test").await.unwrap(),
+        &[("FEATURE_FLAGS".to_string(), "notes".to_string())]
+    );
+}
+
+test_both_dbs!(
+    test_resume_tokens,
+    test_resume_tokens_postgres,
+    test_resume_tokens_sqlite
+);
+
+async fn test_resume_tokens(db: &Arc<Database>) {
+    let old_server = db.create_server("test").await.unwrap();
+    let user1 = new_test_user(db, "user1@example.com").await;
+    let user2 = new_test_user(db, "user2@example.com").await;
+    let connection1 = ConnectionId {
+        owner_id: old_server.0 as u32,
+        id: 1,
+    };
+    let connection2 = ConnectionId {
+        owner_id: old_server.0 as u32,
+        id: 2,
+    };
+    let room_id = RoomId::from_proto(
+        db.create_room(user1, connection1, "", None)
+            .await
+            .unwrap()
+            .id,
+    );
+    db.call(room_id, user1, connection1, user2, None, false, 0)
+        .await
+        .unwrap();
+    db.join_room(room_id, user2, connection2, None)
+        .await
+        .unwrap();
+
+    // Every connection in a room gets a token before the server restarts.
+    let tokens = db.create_resume_tokens(old_server).await.unwrap();
+    assert_eq!(tokens.len(), 2);
+    let new_server = db.create_server("test").await.unwrap();
+
+    // The participants that hold tokens survive the cleanup, as does their server.
+    let refreshed_room = db
+        .clear_stale_room_participants(room_id, new_server)
+        .await
+        .unwrap();
+    assert_eq!(refreshed_room.room.participants.len(), 2);
+    assert!(refreshed_room.stale_participant_user_ids.is_empty());
+    drop(refreshed_room);
+    assert!(db
+        .stale_servers_have_resume_tokens("test", new_server)
+        .await
+        .unwrap());
+    db.delete_stale_servers("test", new_server).await.unwrap();
+    assert_eq!(
+        db.stale_server_ids("test", new_server).await.unwrap(),
+        &[old_server]
+    );
+
+    // Tokens can only be redeemed once, by the user they were issued to, for the room
+    // they were in.
+    let token1 = &tokens[&connection1];
+    let other_room_id = RoomId::from_proto(room_id.to_proto() + 1);
+    db.take_resume_token(token1, user2, room_id)
+        .await
+        .unwrap_err();
+    db.take_resume_token(token1, user1, other_room_id)
+        .await
+        .unwrap_err();
+    db.take_resume_token("not-a-token", user1, room_id)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        db.take_resume_token(token1, user1, room_id).await.unwrap(),
+        connection1
+    );
+    db.take_resume_token(token1, user1, room_id)
+        .await
+        .unwrap_err();
+
+    // Once the remaining tokens expire, the stale participants are cleaned up.
+    db.delete_stale_resume_tokens("test", new_server)
+        .await
+        .unwrap();
+    assert!(!db
+        .stale_servers_have_resume_tokens("test", new_server)
+        .await
+        .unwrap());
+    let refreshed_room = db
+        .clear_stale_room_participants(room_id, new_server)
+        .await
+        .unwrap();
+    assert_eq!(refreshed_room.stale_participant_user_ids, &[user1, user2]);
+    drop(refreshed_room);
+    db.delete_stale_servers("test", new_server).await.unwrap();
+    assert!(db
+        .stale_server_ids("test", new_server)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+test_both_dbs!(
+    test_time_boxed_room_extensions,
+    test_time_boxed_room_extensions_postgres,
+    test_time_boxed_room_extensions_sqlite
+);
+
+async fn test_time_boxed_room_extensions(db: &Arc<Database>) {
+    let server = db.create_server("test").await.unwrap();
+    let user = new_test_user(db, "user1@example.com").await;
+    let connection = ConnectionId {
+        owner_id: server.0 as u32,
+        id: 1,
+    };
+    let room_id = RoomId::from_proto(db.create_room(user, connection, "", None).await.unwrap().id);
+    let day = std::time::Duration::from_secs(24 * 60 * 60);
+
+    // Rooms can be extended until they'd last longer than their lifetime allows.
+    let time_boxed_room = db.time_box_room(room_id, user, day).await.unwrap();
+    let ends_at = time_boxed_room.ends_at;
+    drop(time_boxed_room);
+    let time_boxed_room = db
+        .extend_room(room_id, user, day - std::time::Duration::from_secs(60))
+        .await
+        .unwrap();
+    assert_eq!(
+        time_boxed_room.ends_at,
+        ends_at + day - std::time::Duration::from_secs(60)
+    );
+    drop(time_boxed_room);
+    assert!(db
+        .extend_room(room_id, user, std::time::Duration::from_secs(120))
+        .await
+        .is_err());
+}
+
+test_both_dbs!(
+    test_rejoin_window,
+    test_rejoin_window_postgres,
+    test_rejoin_window_sqlite
+);
+
+async fn test_rejoin_window(db: &Arc<Database>) {
+    let server1 = db.create_server("test").await.unwrap();
+    let user1 = new_test_user(db, "user1@example.com").await;
+    let user2 = new_test_user(db, "user2@example.com").await;
+    let old_connection = ConnectionId {
+        owner_id: server1.0 as u32,
+        id: 1,
+    };
+    let room_id = RoomId::from_proto(
+        db.create_room(user1, old_connection, "", None)
+            .await
+            .unwrap()
+            .id,
+    );
+    db.call(room_id, user1, old_connection, user2, None, false, 0)
+        .await
+        .unwrap();
+
+    // The first server to find a stale server sets its deadline, which restarting
+    // again doesn't extend.
+    let deadline = |seconds| time::OffsetDateTime::from_unix_timestamp(seconds).unwrap();
+    let server2 = db.create_server("test").await.unwrap();
+    assert_eq!(
+        db.start_rejoin_window("test", server2, deadline(100))
+            .await
+            .unwrap(),
+        Some(deadline(100))
+    );
+    assert_eq!(
+        db.start_rejoin_window("test", server2, deadline(200))
+            .await
+            .unwrap(),
+        Some(deadline(100))
+    );
+    let server3 = db.create_server("test").await.unwrap();
+    assert_eq!(
+        db.start_rejoin_window("test", server3, deadline(300))
+            .await
+            .unwrap(),
+        Some(deadline(300))
+    );
+
+    // A caller who rejoins from another server can still cancel the call they placed.
+    let new_connection = ConnectionId {
+        owner_id: server3.0 as u32,
+        id: 1,
+    };
+    db.rejoin_room(
+        proto::RejoinRoom {
+            id: room_id.to_proto(),
+            ..Default::default()
+        },
+        user1,
+        new_connection,
+    )
+    .await
+    .unwrap();
+    let room = db
+        .cancel_call(room_id, new_connection, user2)
+        .await
+        .unwrap();
+    assert_eq!(room.pending_participants.len(), 0);
+    drop(room);
+
+    db.delete_stale_servers("test", server3).await.unwrap();
+    assert_eq!(
+        db.start_rejoin_window("test", server3, deadline(400))
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+test_both_dbs!(test_quotas, test_quotas_postgres, test_quotas_sqlite);
+
+async fn test_quotas(db: &Arc<Database>) {
+    let server1 = db.create_server("test").await.unwrap();
+    let server2 = db.create_server("test").await.unwrap();
+    let user1 = new_test_user(db, "user1@example.com").await;
+    let user2 = new_test_user(db, "user2@example.com").await;
+    let user3 = new_test_user(db, "user3@example.com").await;
+    let connection = |server: ServerId, id| ConnectionId {
+        owner_id: server.0 as u32,
+        id,
+    };
+
+    // Connections are counted across all the live servers.
+    let live_servers = [server1, server2];
+    db.add_user_connection(user1, connection(server1, 1), &live_servers, 2)
+        .await
+        .unwrap();
+    db.add_user_connection(user1, connection(server2, 1), &live_servers, 2)
+        .await
+        .unwrap();
+    db.add_user_connection(user1, connection(server1, 2), &live_servers, 2)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        db.user_connection_count(user1, &live_servers)
+            .await
+            .unwrap(),
+        2
+    );
+
+    // Connections to servers that aren't live anymore aren't counted, and lost
+    // connections are forgotten.
+    db.add_user_connection(user1, connection(server1, 2), &[server1], 2)
+        .await
+        .unwrap();
+    db.connection_lost(connection(server1, 2)).await.unwrap();
+    assert_eq!(
+        db.user_connection_count(user1, &live_servers)
+            .await
+            .unwrap(),
+        2
+    );
+
+    // Rooms can only have so many participants, counting those being called.
+    let room_id = RoomId::from_proto(
+        db.create_room(user1, connection(server1, 1), "", None)
+            .await
+            .unwrap()
+            .id,
+    );
+    db.call(
+        room_id,
+        user1,
+        connection(server1, 1),
+        user2,
+        None,
+        false,
+        2,
+    )
+    .await
+    .unwrap();
+    let error = db
+        .call(
+            room_id,
+            user1,
+            connection(server1, 1),
+            user3,
+            None,
+            false,
+            2,
+        )
+        .await
+        .unwrap_err();
+    let Error::Internal(error) = error else {
+        panic!("unexpected error {error:?}");
+    };
+    assert_eq!(rpc::exceeded_quota(&error), Some(2));
+
+    // And so many shared projects.
+    db.share_project(room_id, connection(server1, 1), 1, &[], None, 1)
+        .await
+        .unwrap();
+    let error = db
+        .share_project(room_id, connection(server1, 1), 2, &[], None, 1)
+        .await
+        .unwrap_err();
+    let Error::Internal(error) = error else {
+        panic!("unexpected error {error:?}");
+    };
+    assert_eq!(rpc::exceeded_quota(&error), Some(1));
+}
+
+test_both_dbs!(test_guests, test_guests_postgres, test_guests_sqlite);
+
+async fn test_guests(db: &Arc<Database>) {
+    let server = db.create_server("test").await.unwrap();
+    let user1 = new_test_user(db, "user1@example.com").await;
+    let user2 = new_test_user(db, "user2@example.com").await;
+    let user3 = new_test_user(db, "user3@example.com").await;
+    let connection = |id| ConnectionId {
+        owner_id: server.0 as u32,
+        id,
+    };
+    let room_id = RoomId::from_proto(
+        db.create_room(user1, connection(1), "", None)
+            .await
+            .unwrap()
+            .id,
+    );
+    let other_room_id = RoomId::from_proto(
+        db.create_room(user3, connection(3), "", None)
+            .await
+            .unwrap()
+            .id,
+    );
+    let expires_at = time::OffsetDateTime::now_utc() + time::Duration::hours(1);
+
+    // Only editors who are in the room can invite guests, who can't be admins.
+    db.create_guest(room_id, user2, ChannelRole::Guest, expires_at)
+        .await
+        .unwrap_err();
+    db.create_guest(room_id, user1, ChannelRole::Admin, expires_at)
+        .await
+        .unwrap_err();
+    let guest = db
+        .create_guest(room_id, user1, ChannelRole::Guest, expires_at)
+        .await
+        .unwrap();
+    let guest_model = db.get_guest(guest.id).await.unwrap().unwrap();
+    assert_eq!(guest_model.room_id, Some(room_id));
+    assert_eq!(guest_model.invited_by_id, user1);
+    assert!(!guest_model.is_expired(time::OffsetDateTime::now_utc()));
+    assert!(guest_model.is_expired(expires_at));
+    assert_eq!(db.get_guest(user1).await.unwrap(), None);
+
+    // Guests can only be called into their own room, where they keep their role.
+    db.call(
+        other_room_id,
+        user3,
+        connection(3),
+        guest.id,
+        None,
+        false,
+        0,
+    )
+    .await
+    .unwrap_err();
+    db.call(room_id, user1, connection(1), guest.id, None, false, 0)
+        .await
+        .unwrap();
+    let join = db
+        .join_room(room_id, guest.id, connection(4), None)
+        .await
+        .unwrap();
+    let participant = join
+        .room
+        .participants
+        .iter()
+        .find(|participant| participant.user_id == guest.id.to_proto())
+        .unwrap();
+    assert_eq!(participant.role(), proto::ChannelRole::Guest);
+    drop(join);
+
+    // Guests can't invite guests of their own, or have contacts.
+    db.create_guest(room_id, guest.id, ChannelRole::Guest, expires_at)
+        .await
+        .unwrap_err();
+    db.send_contact_request(guest.id, user1).await.unwrap_err();
+    db.send_contact_request(user1, guest.id).await.unwrap_err();
+
+    // Expired guests are deleted, but not while they're still in the room.
+    let now = time::OffsetDateTime::now_utc();
+    let expired_guest = db
+        .create_guest(room_id, user1, ChannelRole::Member, now)
+        .await
+        .unwrap();
+    assert_eq!(db.delete_expired_guests(now).await.unwrap(), 1);
+    assert_eq!(db.get_guest(expired_guest.id).await.unwrap(), None);
+    assert_eq!(db.get_user_by_id(expired_guest.id).await.unwrap(), None);
+    assert_eq!(db.delete_expired_guests(expires_at).await.unwrap(), 0);
+    drop(db.leave_room(connection(4)).await.unwrap());
+    assert_eq!(db.delete_expired_guests(expires_at).await.unwrap(), 1);
+    assert_eq!(db.get_user_by_id(guest.id).await.unwrap(), None);
+}
+
+test_both_dbs!(
+    test_have_users_interacted,
+    test_have_users_interacted_postgres,
+    test_have_users_interacted_sqlite
+);
+
+async fn test_have_users_interacted(db: &Arc<Database>) {
+    let user_1 = new_test_user(db, "user1@example.com").await;
+    let user_2 = new_test_user(db, "user2@example.com").await;
+    let user_3 = new_test_user(db, "user3@example.com").await;
+    let user_4 = new_test_user(db, "user4@example.com").await;
+    assert!(!db.have_users_interacted(user_1, user_2).await.unwrap());
+
+    // Users who have asked to be contacts have interacted, whether or not the
+    // request was accepted.
+    db.send_contact_request(user_1, user_2).await.unwrap();
+    assert!(db.have_users_interacted(user_1, user_2).await.unwrap());
+    assert!(db.have_users_interacted(user_2, user_1).await.unwrap());
+
+    // Members of a channel have interacted, but only once they accept its invite.
+    let channel_id = db.create_root_channel("zed", user_3).await.unwrap();
+    db.invite_channel_member(channel_id, user_4, user_3, ChannelRole::Member)
+        .await
+        .unwrap();
+    assert!(!db.have_users_interacted(user_3, user_4).await.unwrap());
+    db.respond_to_channel_invite(channel_id, user_4, true)
+        .await
+        .unwrap();
+    assert!(db.have_users_interacted(user_3, user_4).await.unwrap());
+    assert!(!db.have_users_interacted(user_1, user_4).await.unwrap());
+}
+
+test_both_dbs!(
+    test_blocked_users,
+    test_blocked_users_postgres,
+    test_blocked_users_sqlite
+);
+
+async fn test_blocked_users(db: &Arc<Database>) {
+    let user_1 = new_test_user(db, "user1@example.com").await;
+    let user_2 = new_test_user(db, "user2@example.com").await;
+    let user_3 = new_test_user(db, "user3@example.com").await;
+    db.set_user_blocked(user_1, user_1, true).await.unwrap_err();
+
+    // Blocked users can't send contact requests to the user who blocked them, but
+    // can still be asked to be a contact.
+    db.set_user_blocked(user_1, user_2, true).await.unwrap();
+    db.set_user_blocked(user_1, user_2, true).await.unwrap();
+    db.set_user_blocked(user_1, user_3, true).await.unwrap();
+    assert!(db.has_blocked_user(user_1, user_2).await.unwrap());
+    assert!(!db.has_blocked_user(user_2, user_1).await.unwrap());
+    assert_eq!(
+        db.get_blocked_users(user_1).await.unwrap(),
+        HashSet::from_iter([user_2, user_3])
+    );
+    db.send_contact_request(user_2, user_1).await.unwrap_err();
+    db.send_contact_request(user_1, user_3).await.unwrap();
+
+    db.set_user_blocked(user_1, user_2, false).await.unwrap();
+    assert_eq!(
+        db.get_blocked_users(user_1).await.unwrap(),
+        HashSet::from_iter([user_3])
+    );
+    db.send_contact_request(user_2, user_1).await.unwrap();
+}
+
+test_both_dbs!(
+    test_user_reports,
+    test_user_reports_postgres,
+    test_user_reports_sqlite
+);
+
+async fn test_user_reports(db: &Arc<Database>) {
+    let server = db.create_server("test").await.unwrap();
+    let user1 = new_test_user(db, "user1@example.com").await;
+    let user2 = new_test_user(db, "user2@example.com").await;
+    let user3 = new_test_user(db, "user3@example.com").await;
+    let connection = |id| ConnectionId {
+        owner_id: server.0 as u32,
+        id,
+    };
+    let room_id = RoomId::from_proto(
+        db.create_room(user1, connection(1), "", None)
+            .await
+            .unwrap()
+            .id,
+    );
+    db.call(room_id, user1, connection(1), user2, None, false, 0)
+        .await
+        .unwrap();
+    db.join_room(room_id, user2, connection(2), None)
+        .await
+        .unwrap();
+    let message_id = db
+        .create_room_message(
+            room_id,
+            connection(1),
+            user1,
+            "hello",
+            time::OffsetDateTime::now_utc(),
+            1,
+        )
+        .await
+        .unwrap()
+        .message
+        .id;
+    let message_id = RoomMessageId::from_proto(message_id);
+    let since = time::OffsetDateTime::now_utc() - time::Duration::days(1);
+
+    // Users can't report themselves, or messages the reported user didn't send.
+    db.create_user_report(user1, user1, Some(room_id), &[], &[message_id], "spam")
+        .await
+        .unwrap_err();
+    db.create_user_report(user1, user2, Some(room_id), &[], &[message_id], "spam")
+        .await
+        .unwrap_err();
+    db.create_user_report(user2, user1, None, &[], &[message_id], "spam")
+        .await
+        .unwrap_err();
+
+    // Reports can only be made from rooms the reporter was in.
+    db.create_user_report(user3, user1, Some(room_id), &[], &[], "rude")
+        .await
+        .unwrap_err();
+
+    // Reports count once per reporter, and only if they were in a room with the
+    // reported user.
+    let report1 = db
+        .create_user_report(user2, user1, Some(room_id), &[], &[message_id], "spam")
+        .await
+        .unwrap();
+    db.create_user_report(user2, user1, None, &[], &[], "more spam")
+        .await
+        .unwrap();
+    db.create_user_report(user3, user1, None, &[], &[], "rude")
+        .await
+        .unwrap();
+    assert_eq!(db.user_report_count(user1, since).await.unwrap(), 1);
+
+    db.call(room_id, user1, connection(1), user3, None, false, 0)
+        .await
+        .unwrap();
+    db.join_room(room_id, user3, connection(3), None)
+        .await
+        .unwrap();
+    let report3 = db
+        .create_user_report(user3, user1, Some(room_id), &[], &[], "rude")
+        .await
+        .unwrap();
+    assert_eq!(db.user_report_count(user1, since).await.unwrap(), 2);
+    assert_eq!(db.user_report_count(user2, since).await.unwrap(), 0);
+
+    let reports = db.get_user_reports(ReportStatus::Open).await.unwrap();
+    assert_eq!(reports.len(), 4);
+    assert_eq!(reports[0].id, report1);
+    assert_eq!(reports[0].room_id, Some(room_id));
+    assert_eq!(reports[0].room_message_ids, [message_id]);
+    assert_eq!(reports[0].reason, "spam");
+
+    // Dismissed reports no longer count.
+    let report = db
+        .review_user_report(report3, ReportStatus::Dismissed)
+        .await
+        .unwrap();
+    assert_eq!(report.status, ReportStatus::Dismissed);
+    assert!(report.reviewed_at.is_some());
+    assert_eq!(db.user_report_count(user1, since).await.unwrap(), 1);
+    assert_eq!(
+        db.get_user_reports(ReportStatus::Open).await.unwrap().len(),
+        3
+    );
+}
+
+test_both_dbs!(
+    test_rate_limited_actions,
+    test_rate_limited_actions_postgres,
+    test_rate_limited_actions_sqlite
+);
+
+async fn test_rate_limited_actions(db: &Arc<Database>) {
+    let user1 = new_test_user(db, "user1@example.com").await;
+    let user2 = new_test_user(db, "user2@example.com").await;
+    let kind = RateLimitedActionKind::ThrottledInvite;
+    let an_hour_ago = time::OffsetDateTime::now_utc() - time::Duration::hours(1);
+
+    for _ in 0..2 {
+        assert!(db
+            .try_rate_limited_action(user1, kind, None, 2, an_hour_ago)
+            .await
+            .unwrap());
+    }
+    assert!(!db
+        .try_rate_limited_action(user1, kind, None, 2, an_hour_ago)
+        .await
+        .unwrap());
+
+    // Limits are per user and per kind.
+    assert!(db
+        .try_rate_limited_action(user2, kind, None, 2, an_hour_ago)
+        .await
+        .unwrap());
+    assert!(db
+        .try_rate_limited_action(
+            user1,
+            RateLimitedActionKind::ContactRequest,
+            None,
+            2,
+            an_hour_ago
+        )
+        .await
+        .unwrap());
+
+    // Actions from before the window are forgotten.
+    let later = time::OffsetDateTime::now_utc() + time::Duration::minutes(1);
+    assert!(db
+        .try_rate_limited_action(user1, kind, None, 2, later)
+        .await
+        .unwrap());
+
+    // Contact requests are counted once they've been sent.
+    let kind = RateLimitedActionKind::ContactRequest;
+    db.send_contact_request(user2, user1).await.unwrap();
+    db.send_contact_request(user2, user1).await.unwrap_err();
+    let actions = db
+        .get_rate_limited_actions(user2, kind, an_hour_ago)
+        .await
+        .unwrap();
+    assert_eq!(
+        actions
+            .iter()
+            .map(|action| action.target_user_id)
+            .collect::<Vec<_>>(),
+        &[Some(user1)]
+    );
+}
+
+test_both_dbs!(
+    test_audit_events,
+    test_audit_events_postgres,
+    test_audit_events_sqlite
+);
+
+async fn test_audit_events(db: &Arc<Database>) {
+    let server = db.create_server("test").await.unwrap();
+    let user1 = new_test_user(db, "user1@example.com").await;
+    let user2 = new_test_user(db, "user2@example.com").await;
+    let connection = |id| ConnectionId {
+        owner_id: server.0 as u32,
+        id,
+    };
+    let room_id = RoomId::from_proto(
+        db.create_room(user1, connection(1), "", None)
+            .await
+            .unwrap()
+            .id,
+    );
+    db.call(room_id, user1, connection(1), user2, None, false, 0)
+        .await
+        .unwrap();
+    db.join_room(room_id, user2, connection(2), None)
+        .await
+        .unwrap();
+    let project_id = db
+        .share_project(room_id, connection(1), 1, &[], None, 0)
+        .await
+        .unwrap()
+        .0;
+    db.set_room_participant_role(user1, room_id, user2, ChannelRole::Guest)
+        .await
+        .unwrap();
+
+    // Failed changes aren't recorded.
+    db.set_room_participant_role(user2, room_id, user1, ChannelRole::Guest)
+        .await
+        .unwrap_err();
+
+    let summarize = |events: Vec<audit_event::Model>| {
+        events
+            .into_iter()
+            .map(|event| {
+                (
+                    event.kind,
+                    event.user_id,
+                    event.subject_user_id,
+                    event.detail,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let events = db
+        .get_audit_events(&AuditEventFilter::default(), 10, None)
+        .await
+        .unwrap();
+    assert!(events.iter().all(|event| event.room_id == Some(room_id)));
+    assert_eq!(events[2].project_id, Some(project_id));
+    assert_eq!(
+        summarize(events.clone()),
+        [
+            (AuditEventKind::RoomCreated, user1, None, None),
+            (AuditEventKind::RoomJoined, user2, None, None),
+            (AuditEventKind::ProjectShared, user1, None, None),
+            (
+                AuditEventKind::RoomRoleChanged,
+                user1,
+                Some(user2),
+                Some("guest".into())
+            ),
+        ]
+    );
+
+    // Events can be filtered by the user they were done by or to, and paged through.
+    let user2_events = db
+        .get_audit_events(
+            &AuditEventFilter {
+                user_id: Some(user2),
+                ..Default::default()
             },
+            10,
+            None,
         )
         .await
         .unwrap();
-    let NewUserResult {
-        user_id: user2,
-        metrics_id: metrics_id2,
-        ..
-    } = db
-        .create_user(
-            "person2@example.com",
-            false,
-            NewUserParams {
-                github_login: "person2".into(),
-                github_user_id: 102,
+    assert_eq!(user2_events, [events[1].clone(), events[3].clone()]);
+    let page = db
+        .get_audit_events(&AuditEventFilter::default(), 2, Some(events[3].id))
+        .await
+        .unwrap();
+    assert_eq!(page, events[1..3]);
+    let shares = db
+        .get_audit_events(
+            &AuditEventFilter {
+                kind: Some(AuditEventKind::ProjectShared),
+                ..Default::default()
             },
+            10,
+            None,
         )
         .await
         .unwrap();
+    assert_eq!(shares, [events[2].clone()]);
 
-    assert_eq!(db.get_user_metrics_id(user1).await.unwrap(), metrics_id1);
-    assert_eq!(db.get_user_metrics_id(user2).await.unwrap(), metrics_id2);
-    assert_eq!(metrics_id1.len(), 36);
-    assert_eq!(metrics_id2.len(), 36);
-    assert_ne!(metrics_id1, metrics_id2);
+    // Guests, channel membership and visibility, and unsharing are recorded too.
+    let expires_at = time::OffsetDateTime::now_utc() + time::Duration::hours(1);
+    let guest = db
+        .create_guest(room_id, user1, ChannelRole::Guest, expires_at)
+        .await
+        .unwrap();
+    db.unshare_project(project_id, connection(1)).await.unwrap();
+    let channel_id = db.create_root_channel("channel", user1).await.unwrap();
+    db.invite_channel_member(channel_id, user2, user1, ChannelRole::Member)
+        .await
+        .unwrap();
+    db.set_channel_visibility(channel_id, ChannelVisibility::Public, user1)
+        .await
+        .unwrap();
+    db.remove_channel_member(channel_id, user2, user1)
+        .await
+        .unwrap();
+    let events = db
+        .get_audit_events(&AuditEventFilter::default(), 20, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        summarize(events[4..].to_vec()),
+        [
+            (
+                AuditEventKind::GuestCreated,
+                user1,
+                Some(guest.id),
+                Some("guest".into())
+            ),
+            (AuditEventKind::ProjectUnshared, user1, None, None),
+            (
+                AuditEventKind::ChannelMemberInvited,
+                user1,
+                Some(user2),
+                Some("member".into())
+            ),
+            (
+                AuditEventKind::ChannelVisibilityChanged,
+                user1,
+                None,
+                Some("public".into())
+            ),
+            (
+                AuditEventKind::ChannelMemberRemoved,
+                user1,
+                Some(user2),
+                None
+            ),
+        ]
+    );
 }
 
 test_both_dbs!(
@@ -517,7 +1764,7 @@ async fn test_project_count(db: &Arc<Database>) {
         .unwrap();
 
     let room_id = RoomId::from_proto(
-        db.create_room(user1.user_id, ConnectionId { owner_id, id: 0 }, "")
+        db.create_room(user1.user_id, ConnectionId { owner_id, id: 0 }, "", None)
             .await
             .unwrap()
             .id,
@@ -528,26 +1775,33 @@ async fn test_project_count(db: &Arc<Database>) {
         ConnectionId { owner_id, id: 0 },
         user2.user_id,
         None,
+        false,
+        0,
+    )
+    .await
+    .unwrap();
+    db.join_room(
+        room_id,
+        user2.user_id,
+        ConnectionId { owner_id, id: 1 },
+        None,
     )
     .await
     .unwrap();
-    db.join_room(room_id, user2.user_id, ConnectionId { owner_id, id: 1 })
-        .await
-        .unwrap();
     assert_eq!(db.project_count_excluding_admins().await.unwrap(), 0);
 
-    db.share_project(room_id, ConnectionId { owner_id, id: 1 }, &[])
+    db.share_project(room_id, ConnectionId { owner_id, id: 1 }, 1, &[], None, 0)
         .await
         .unwrap();
     assert_eq!(db.project_count_excluding_admins().await.unwrap(), 1);
 
-    db.share_project(room_id, ConnectionId { owner_id, id: 1 }, &[])
+    db.share_project(room_id, ConnectionId { owner_id, id: 1 }, 2, &[], None, 0)
         .await
         .unwrap();
     assert_eq!(db.project_count_excluding_admins().await.unwrap(), 2);
 
     // Projects shared by admins aren't counted.
-    db.share_project(room_id, ConnectionId { owner_id, id: 0 }, &[])
+    db.share_project(room_id, ConnectionId { owner_id, id: 0 }, 1, &[], None, 0)
         .await
         .unwrap();
     assert_eq!(db.project_count_excluding_admins().await.unwrap(), 2);
@@ -558,6 +1812,96 @@ async fn test_project_count(db: &Arc<Database>) {
     assert_eq!(db.project_count_excluding_admins().await.unwrap(), 0);
 }
 
+test_both_dbs!(
+    test_delete_orphaned_rooms_and_projects,
+    test_delete_orphaned_rooms_and_projects_postgres,
+    test_delete_orphaned_rooms_and_projects_sqlite
+);
+
+async fn test_delete_orphaned_rooms_and_projects(db: &Arc<Database>) {
+    let owner_id = db.create_server("test").await.unwrap().0 as u32;
+    let user1 = new_test_user(db, "user1@example.com").await;
+    let user2 = new_test_user(db, "user2@example.com").await;
+    let user3 = new_test_user(db, "user3@example.com").await;
+    let connection1 = ConnectionId { owner_id, id: 1 };
+    let connection2 = ConnectionId { owner_id, id: 2 };
+    let connection3 = ConnectionId { owner_id, id: 3 };
+
+    // Users 1 and 2 share projects in a room.
+    let room_id = RoomId::from_proto(
+        db.create_room(user1, connection1, "", None)
+            .await
+            .unwrap()
+            .id,
+    );
+    db.call(room_id, user1, connection1, user2, None, false, 0)
+        .await
+        .unwrap();
+    db.join_room(room_id, user2, connection2, None)
+        .await
+        .unwrap();
+    let (project1_id, _) = db
+        .share_project(room_id, connection1, 2, &[], None, 0)
+        .await
+        .unwrap()
+        .into_inner();
+    let (project2_id, _) = db
+        .share_project(room_id, connection2, 1, &[], None, 0)
+        .await
+        .unwrap()
+        .into_inner();
+
+    // User 3 shares a project in a room of their own.
+    let orphaned_room_id = RoomId::from_proto(
+        db.create_room(user3, connection3, "", None)
+            .await
+            .unwrap()
+            .id,
+    );
+    let (project3_id, _) = db
+        .share_project(orphaned_room_id, connection3, 2, &[], None, 0)
+        .await
+        .unwrap()
+        .into_inner();
+
+    // Nothing is swept while everyone is still participating.
+    assert_eq!(
+        db.delete_orphaned_rooms_and_projects().await.unwrap(),
+        SweptOrphans::default()
+    );
+
+    // User 2's participation and all of user 3's room vanish without their projects
+    // being cleaned up.
+    db.transaction(|tx| async move {
+        room_participant::Entity::delete_many()
+            .filter(
+                Condition::any()
+                    .add(room_participant::Column::UserId.eq(user2))
+                    .add(room_participant::Column::RoomId.eq(orphaned_room_id)),
+            )
+            .exec(&*tx)
+            .await?;
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(
+        db.delete_orphaned_rooms_and_projects().await.unwrap(),
+        SweptOrphans {
+            projects: 2,
+            rooms: 1,
+        }
+    );
+    assert_eq!(db.room_id_for_project(project1_id).await.unwrap(), room_id);
+    assert!(db.room_id_for_project(project2_id).await.is_err());
+    assert!(db.room_id_for_project(project3_id).await.is_err());
+    assert_eq!(
+        db.delete_orphaned_rooms_and_projects().await.unwrap(),
+        SweptOrphans::default()
+    );
+}
+
 #[test]
 fn test_fuzzy_like_string() {
     assert_eq!(Database::fuzzy_like_string("abcd"), "%a%b%c%d%");
@@ -565,10 +1909,13 @@ fn test_fuzzy_like_string() {
     assert_eq!(Database::fuzzy_like_string(" z  "), "%z%");
 }
 
-#[gpui::test]
-async fn test_fuzzy_search_users(cx: &mut TestAppContext) {
-    let test_db = TestDb::postgres(cx.executor());
-    let db = test_db.db();
+test_both_dbs!(
+    test_fuzzy_search_users,
+    test_fuzzy_search_users_postgres,
+    test_fuzzy_search_users_sqlite
+);
+
+async fn test_fuzzy_search_users(db: &Arc<Database>) {
     for (i, github_login) in [
         "California",
         "colorado",
@@ -597,10 +1944,19 @@ async fn test_fuzzy_search_users(cx: &mut TestAppContext) {
         fuzzy_search_user_names(db, "clr").await,
         &["colorado", "California"]
     );
-    assert_eq!(
-        fuzzy_search_user_names(db, "ro").await,
-        &["rhode-island", "colorado", "oregon"],
-    );
+
+    // Postgres ranks matches by trigram distance, and SQLite puts shorter logins first.
+    if let sea_orm::DatabaseBackend::Postgres = db.pool.get_database_backend() {
+        assert_eq!(
+            fuzzy_search_user_names(db, "ro").await,
+            &["rhode-island", "colorado", "oregon"],
+        );
+    } else {
+        assert_eq!(
+            fuzzy_search_user_names(db, "ro").await,
+            &["oregon", "colorado", "rhode-island"],
+        );
+    }
 
     async fn fuzzy_search_user_names(db: &Database, query: &str) -> Vec<String> {
         db.fuzzy_search_users(query, 10)
@@ -1,6 +1,6 @@
 use super::new_test_user;
 use crate::{
-    db::{ChannelRole, Database, MessageId},
+    db::{ChannelRole, Database, MessageId, RoomId, RoomMessageId},
     test_both_dbs,
 };
 use channel::mentions_to_proto;
@@ -419,3 +419,175 @@ async fn test_channel_message_mentions(db: &Arc<Database>) {
         ]
     );
 }
+
+test_both_dbs!(
+    test_room_messages,
+    test_room_messages_postgres,
+    test_room_messages_sqlite
+);
+
+async fn test_room_messages(db: &Arc<Database>) {
+    let owner_id = db.create_server("test").await.unwrap().0 as u32;
+    let user_a = new_test_user(db, "user_a@example.com").await;
+    let user_b = new_test_user(db, "user_b@example.com").await;
+    let user_c = new_test_user(db, "user_c@example.com").await;
+    let connection_a = rpc::ConnectionId { owner_id, id: 1 };
+    let connection_b = rpc::ConnectionId { owner_id, id: 2 };
+    let connection_c = rpc::ConnectionId { owner_id, id: 3 };
+
+    let room_id = RoomId::from_proto(
+        db.create_room(user_a, connection_a, "", None)
+            .await
+            .unwrap()
+            .id,
+    );
+    db.call(room_id, user_a, connection_a, user_b, None, false, 0)
+        .await
+        .unwrap();
+    db.join_room(room_id, user_b, connection_b, None)
+        .await
+        .unwrap();
+
+    let mut all_messages = Vec::new();
+    for i in 0..10 {
+        let created = db
+            .create_room_message(
+                room_id,
+                connection_a,
+                user_a,
+                &i.to_string(),
+                OffsetDateTime::now_utc(),
+                i,
+            )
+            .await
+            .unwrap();
+        assert_eq!(created.participant_connection_ids, [connection_b]);
+        all_messages.push(created.message.id);
+    }
+
+    // Resending a message returns the original message, without sending it again.
+    let created = db
+        .create_room_message(
+            room_id,
+            connection_a,
+            user_a,
+            "resent",
+            OffsetDateTime::now_utc(),
+            9,
+        )
+        .await
+        .unwrap();
+    assert_eq!(created.message.id, all_messages[9]);
+    assert_eq!(created.message.body, "9");
+    assert!(created.participant_connection_ids.is_empty());
+
+    let messages = db
+        .get_room_messages(room_id, connection_b, 3, None, None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|message| message.id)
+        .collect::<Vec<_>>();
+    assert_eq!(messages, &all_messages[7..10]);
+
+    let messages = db
+        .get_room_messages(
+            room_id,
+            connection_b,
+            4,
+            Some(RoomMessageId::from_proto(all_messages[6])),
+            None,
+        )
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|message| message.id)
+        .collect::<Vec<_>>();
+    assert_eq!(messages, &all_messages[2..6]);
+
+    // Messages sent after a given one are loaded oldest first.
+    let messages = db
+        .get_room_messages(
+            room_id,
+            connection_b,
+            4,
+            None,
+            Some(RoomMessageId::from_proto(all_messages[2])),
+        )
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|message| message.id)
+        .collect::<Vec<_>>();
+    assert_eq!(messages, &all_messages[3..7]);
+
+    // Only the room's participants can read and write its chat.
+    db.create_room_message(
+        room_id,
+        connection_c,
+        user_c,
+        "hello",
+        OffsetDateTime::now_utc(),
+        100,
+    )
+    .await
+    .unwrap_err();
+    db.get_room_messages(room_id, connection_c, 3, None, None)
+        .await
+        .unwrap_err();
+}
+
+test_both_dbs!(
+    test_channel_room_messages,
+    test_channel_room_messages_postgres,
+    test_channel_room_messages_sqlite
+);
+
+async fn test_channel_room_messages(db: &Arc<Database>) {
+    let owner_id = db.create_server("test").await.unwrap().0 as u32;
+    let user = new_test_user(db, "user@example.com").await;
+    let channel = db.create_root_channel("channel", user).await.unwrap();
+    let connection = rpc::ConnectionId { owner_id, id: 1 };
+
+    let room_id = db
+        .join_channel(channel, user, connection, None, 0)
+        .await
+        .unwrap()
+        .into_inner()
+        .0
+        .room
+        .id;
+    let room_id = RoomId::from_proto(room_id);
+    let message_id = db
+        .create_room_message(
+            room_id,
+            connection,
+            user,
+            "hello",
+            OffsetDateTime::now_utc(),
+            1,
+        )
+        .await
+        .unwrap()
+        .message
+        .id;
+    drop(db.leave_room(connection).await.unwrap());
+
+    // The chat of a channel's calls is kept from one call to the next.
+    let room_id = db
+        .join_channel(channel, user, connection, None, 0)
+        .await
+        .unwrap()
+        .into_inner()
+        .0
+        .room
+        .id;
+    let messages = db
+        .get_room_messages(RoomId::from_proto(room_id), connection, 3, None, None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|message| message.id)
+        .collect::<Vec<_>>();
+    assert_eq!(messages, [message_id]);
+}
@@ -1,4 +1,8 @@
-use std::{future::Future, time::Duration};
+use std::{
+    future::Future,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
 
 #[cfg(test)]
 use gpui::BackgroundExecutor;
@@ -36,4 +40,29 @@ impl Executor {
             }
         }
     }
+
+    /// The time elapsed since an arbitrary point, which never goes backwards.
+    /// Deterministic executors report their simulated time.
+    pub fn monotonic_time(&self) -> Duration {
+        match self {
+            Executor::Production => {
+                static ORIGIN: OnceLock<Instant> = OnceLock::new();
+                ORIGIN.get_or_init(Instant::now).elapsed()
+            }
+            #[cfg(test)]
+            Executor::Deterministic(background) => background.simulated_time(),
+        }
+    }
+
+    /// Runs the future, returning how long it took. Deterministic executors report that
+    /// no time passed, as their clock only moves when tests advance it.
+    pub async fn measure<F: Future>(&self, future: F) -> (F::Output, Duration) {
+        let started_at = Instant::now();
+        let output = future.await;
+        match self {
+            Executor::Production => (output, started_at.elapsed()),
+            #[cfg(test)]
+            Executor::Deterministic(_) => (output, Duration::ZERO),
+        }
+    }
 }
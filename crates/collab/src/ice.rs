@@ -0,0 +1,226 @@
+use crate::{db::UserId, Config, Result};
+use ::rpc::proto;
+use anyhow::{anyhow, Context as _};
+use hmac::{Hmac, Mac};
+use rand::RngCore as _;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long the TURN credentials minted for a participant stay valid when
+/// `TURN_CREDENTIAL_TTL_SECS` is unset.
+pub const DEFAULT_TURN_CREDENTIAL_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The STUN and TURN servers that clients should use for the media connections
+/// of calls, for deployments whose participants sit behind NATs that LiveKit's
+/// own servers can't get through.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IceConfig {
+    pub servers: Vec<IceServer>,
+    /// Only allow media to flow through the TURN servers, never directly.
+    #[serde(default)]
+    pub force_relay: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IceServer {
+    pub urls: Vec<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub credential: Option<String>,
+    /// The secret shared with a TURN server that supports the TURN REST API, such
+    /// as coturn's `static-auth-secret`. When set, each participant is given their
+    /// own short-lived credential instead of `username` and `credential`, so
+    /// rotating the secret only takes changing it here and on the TURN server.
+    #[serde(default)]
+    pub shared_secret: Option<String>,
+}
+
+impl IceConfig {
+    /// Reads the deployment's ICE servers from the `ICE_SERVERS` JSON array.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let Some(servers) = config.ice_servers.as_deref() else {
+            return Ok(None);
+        };
+        let servers = serde_json::from_str(servers).context("invalid ICE_SERVERS")?;
+        Ok(Some(Self {
+            servers,
+            force_relay: config.ice_force_relay.unwrap_or(false),
+        }))
+    }
+
+    /// Returns the configuration to send to the given participant, minting TURN
+    /// credentials that expire `credential_ttl` after `now`.
+    pub fn to_proto(
+        &self,
+        user_id: UserId,
+        now: SystemTime,
+        credential_ttl: Duration,
+    ) -> proto::IceConfiguration {
+        proto::IceConfiguration {
+            servers: self
+                .servers
+                .iter()
+                .map(|server| {
+                    let (username, credential) = if let Some(secret) = &server.shared_secret {
+                        turn_credential(secret, user_id, now + credential_ttl)
+                    } else {
+                        (
+                            server.username.clone().unwrap_or_default(),
+                            server.credential.clone().unwrap_or_default(),
+                        )
+                    };
+                    proto::IceServer {
+                        urls: server.urls.clone(),
+                        username,
+                        credential,
+                    }
+                })
+                .collect(),
+            force_relay: self.force_relay,
+        }
+    }
+}
+
+/// The key that rooms' ICE servers are encrypted with in the database, as they
+/// carry TURN credentials and shared secrets.
+pub struct IceServersKey(LessSafeKey);
+
+impl IceServersKey {
+    /// Reads the key from `ICE_SERVERS_KEY`, 32 base64-encoded bytes.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let Some(key) = config.ice_servers_key.as_deref() else {
+            return Ok(None);
+        };
+        let key = base64::decode(key).context("invalid ICE_SERVERS_KEY")?;
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+            .map_err(|_| anyhow!("ICE_SERVERS_KEY must be 32 bytes long"))?;
+        Ok(Some(Self(LessSafeKey::new(key))))
+    }
+
+    /// Encrypts the given servers under a random nonce, which is stored in front of
+    /// them.
+    pub fn seal(&self, ice_config: &IceConfig) -> Result<String> {
+        let mut nonce = [0; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let mut ciphertext = serde_json::to_vec(ice_config)?;
+        self.0
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut ciphertext,
+            )
+            .map_err(|_| anyhow!("failed to encrypt ICE servers"))?;
+        let mut sealed = nonce.to_vec();
+        sealed.append(&mut ciphertext);
+        Ok(base64::encode(sealed))
+    }
+
+    pub fn open(&self, sealed: &str) -> Result<IceConfig> {
+        let mut sealed = base64::decode(sealed).context("invalid ICE servers")?;
+        if sealed.len() < NONCE_LEN {
+            Err(anyhow!("invalid ICE servers"))?;
+        }
+        let (nonce, ciphertext) = sealed.split_at_mut(NONCE_LEN);
+        let nonce =
+            Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow!("invalid ICE servers"))?;
+        let ice_config = self
+            .0
+            .open_in_place(nonce, Aad::empty(), ciphertext)
+            .map_err(|_| anyhow!("failed to decrypt ICE servers"))?;
+        Ok(serde_json::from_slice(ice_config)?)
+    }
+}
+
+/// Mints a TURN REST API credential, which TURN servers verify using only the
+/// shared secret: the username carries the expiry, and the credential is the
+/// username signed with the secret.
+fn turn_credential(secret: &str, user_id: UserId, expires_at: SystemTime) -> (String, String) {
+    let expires_at = expires_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let username = format!("{expires_at}:{user_id}");
+    let mut mac =
+        Hmac::<Sha1>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(username.as_bytes());
+    let credential = base64::encode(mac.finalize().into_bytes());
+    (username, credential)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TestServer;
+
+    #[test]
+    fn test_ice_config_to_proto() {
+        let config: IceConfig = serde_json::from_str(
+            r#"{
+                "servers": [
+                    {"urls": ["stun:stun.example.com:3478"]},
+                    {"urls": ["turn:turn.example.com:3478"], "username": "zed", "credential": "hunter2"},
+                    {"urls": ["turns:turn.example.com:5349"], "shared_secret": "north"}
+                ],
+                "force_relay": true
+            }"#,
+        )
+        .unwrap();
+
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let proto = config.to_proto(UserId(5), now, Duration::from_secs(60));
+        assert!(proto.force_relay);
+        assert_eq!(
+            proto.servers,
+            [
+                proto::IceServer {
+                    urls: vec!["stun:stun.example.com:3478".into()],
+                    username: String::new(),
+                    credential: String::new(),
+                },
+                proto::IceServer {
+                    urls: vec!["turn:turn.example.com:3478".into()],
+                    username: "zed".into(),
+                    credential: "hunter2".into(),
+                },
+                proto::IceServer {
+                    urls: vec!["turns:turn.example.com:5349".into()],
+                    username: "1700000060:5".into(),
+                    credential: "ZjfboAlK9He8OsNvAl4VueBi/J8=".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ice_servers_key() {
+        let key = |key: &str| {
+            let mut config = TestServer::test_config();
+            config.ice_servers_key = Some(key.into());
+            IceServersKey::from_config(&config)
+        };
+        assert!(key("c2hvcnQ=").is_err());
+        let other_key = key(&base64::encode([8; 32])).unwrap().unwrap();
+        let key = key(&base64::encode([7; 32])).unwrap().unwrap();
+
+        let ice_config = IceConfig {
+            servers: vec![IceServer {
+                urls: vec!["turns:turn.example.com:5349".into()],
+                shared_secret: Some("north".into()),
+                ..Default::default()
+            }],
+            force_relay: true,
+        };
+        let sealed = key.seal(&ice_config).unwrap();
+        assert!(!base64::decode(&sealed)
+            .unwrap()
+            .windows(5)
+            .any(|window| window == b"north"));
+        assert_ne!(key.seal(&ice_config).unwrap(), sealed);
+        assert_eq!(key.open(&sealed).unwrap(), ice_config);
+        assert!(other_key.open(&sealed).is_err());
+        assert!(key.open("").is_err());
+    }
+}
@@ -1,18 +1,30 @@
 pub mod api;
 pub mod auth;
+pub mod avatars;
 pub mod db;
 pub mod env;
 pub mod executor;
+pub mod ice;
+pub mod message_bus;
 pub mod rpc;
+pub mod runtime_config;
+pub mod webhooks;
 
 #[cfg(test)]
 mod tests;
 
+use crate::rpc::{ContactRequestPolicy, OverloadPolicy, QuotaPolicy, ReportPolicy};
+use ::rpc::{BacklogPolicy, RateLimitPolicy};
+use anyhow::Context as _;
 use axum::{http::StatusCode, response::IntoResponse};
 use db::Database;
 use executor::Executor;
+use message_bus::{InMemoryMessageBus, MessageBus, PostgresMessageBus};
+use runtime_config::{RuntimeConfig, SharedRuntimeConfig};
 use serde::Deserialize;
-use std::{path::PathBuf, sync::Arc};
+use std::{net::IpAddr, path::PathBuf, sync::Arc, time::Duration};
+use util::{collation::Collation, SemanticVersion};
+use webhooks::{HttpWebhookClient, Webhooks};
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
@@ -101,12 +113,251 @@ pub struct Config {
     pub rust_log: Option<String>,
     pub log_json: Option<bool>,
     pub zed_environment: Arc<str>,
+    pub min_client_version: Option<String>,
+    pub release_notes_url: Option<String>,
+    pub send_backlog_shed_threshold: Option<usize>,
+    pub send_backlog_lagging_threshold: Option<usize>,
+    pub send_backlog_disconnect_threshold: Option<usize>,
+    /// A JSON array of [`ice::IceServer`]s for the media connections of calls.
+    pub ice_servers: Option<String>,
+    pub ice_force_relay: Option<bool>,
+    pub turn_credential_ttl_secs: Option<u64>,
+    /// The base64-encoded 32-byte key that rooms' own ICE servers are encrypted with
+    /// in the database. Rooms' ICE servers can't be overridden when it's unset.
+    pub ice_servers_key: Option<String>,
+    /// Guests that support it load the entries of worktrees with more entries
+    /// than this one directory at a time, rather than all at once when joining.
+    pub lazy_worktree_entry_threshold: Option<usize>,
+    /// The public URL that avatars uploaded by users are served under, such as
+    /// `https://collab.example.com/avatars`. Uploads are rejected when unset.
+    pub avatar_url_prefix: Option<String>,
+    /// A JSON [`RateLimitPolicy`] for the messages that each connection sends.
+    pub rate_limits: Option<String>,
+    /// How often servers look for rooms and projects that nothing live refers to.
+    pub orphan_sweep_interval_secs: Option<u64>,
+    /// How long a client that lost its connection has to reconnect before it's removed
+    /// from its room.
+    pub reconnect_timeout_secs: Option<u64>,
+    /// How long a server waits after starting before cleaning up after the servers it
+    /// replaced.
+    pub cleanup_timeout_secs: Option<u64>,
+    /// A comma-separated list of the feature flags that are enabled for every user, on
+    /// top of the ones enabled for them in the database.
+    pub feature_flags: Option<String>,
+    /// How many milliseconds late the server's timers can fire before it starts
+    /// turning work away. Zero ignores how far behind the event loop is.
+    pub overload_event_loop_lag_ms: Option<u64>,
+    /// How many milliseconds it can take to ping the database before the server starts
+    /// turning work away. Zero ignores how busy the database is.
+    pub overload_db_latency_ms: Option<u64>,
+    /// How many users have to report someone before they can only send a few invites
+    /// an hour. Zero never throttles invites.
+    pub reports_to_throttle_invites: Option<usize>,
+    /// How many users have to report someone before they can't join public channels
+    /// they aren't a member of. Zero never blocks joins.
+    pub reports_to_block_public_joins: Option<usize>,
+    /// How many contact requests each user can send an hour before they're rejected
+    /// and the user is flagged for review. Zero never limits them.
+    pub contact_requests_per_hour: Option<usize>,
+    /// How many contact requests each user can send an hour before the rest can only
+    /// go to people they've met. Zero never limits them.
+    pub contact_requests_to_strangers_per_hour: Option<usize>,
+    /// How many connections each user can have at once, across all servers. Zero
+    /// never limits them.
+    pub max_connections_per_user: Option<usize>,
+    /// How many projects can be shared in each room at once. Zero never limits them.
+    pub max_shared_projects_per_room: Option<usize>,
+    /// How many users can be in each room at once, counting those being called. Zero
+    /// never limits them.
+    pub max_room_participants: Option<usize>,
+    /// A JSON array of [`webhooks::WebhookEndpoint`]s that are sent an event whenever
+    /// a room is created or ends, and whenever a user joins or leaves one.
+    pub webhooks: Option<String>,
+    /// A comma-separated list of the hosts that a draining server can send its
+    /// clients to. Draining servers can't name another server when it's unset.
+    pub reconnect_hosts: Option<String>,
+    /// The locale, such as `sv-SE`, whose alphabet contacts and channel members are
+    /// ordered by. Clients are told to order them the same way.
+    pub collation_locale: Option<String>,
+    /// A comma-separated list of the IP addresses of the proxies in front of a
+    /// self-hosted server, whose `X-Forwarded-Proto` header is trusted along with
+    /// that of proxies on the same machine.
+    pub trusted_proxies: Option<String>,
 }
 
 impl Config {
     pub fn is_development(&self) -> bool {
         self.zed_environment == "development".into()
     }
+
+    pub fn is_self_hosted(&self) -> bool {
+        self.zed_environment == SELF_HOSTED_ENVIRONMENT.into()
+    }
+
+    /// How names are ordered in the lists the server sends to clients.
+    pub fn collation(&self) -> Collation {
+        self.collation_locale
+            .as_deref()
+            .map_or(Collation::default(), Collation::for_locale)
+    }
+
+    /// Whether requests from the given address come from a proxy whose
+    /// `X-Forwarded-Proto` header can be trusted.
+    pub fn is_trusted_proxy(&self, address: IpAddr) -> bool {
+        address.is_loopback()
+            || self
+                .trusted_proxies
+                .as_deref()
+                .map_or(false, |trusted_proxies| {
+                    trusted_proxies
+                        .split(',')
+                        .filter_map(|proxy| proxy.trim().parse::<IpAddr>().ok())
+                        .any(|proxy| proxy == address)
+                })
+    }
+
+    /// Whether a draining server can send its clients to the given URL, which they
+    /// hand their credentials to when they reconnect.
+    pub fn is_allowed_reconnect_url(&self, url: &str) -> bool {
+        let Some(reconnect_hosts) = self.reconnect_hosts.as_deref() else {
+            return false;
+        };
+        let Ok(url) = reqwest::Url::parse(url) else {
+            return false;
+        };
+        url.scheme() == "https"
+            && url.host_str().map_or(false, |host| {
+                reconnect_hosts
+                    .split(',')
+                    .any(|allowed_host| allowed_host.trim() == host)
+            })
+    }
+
+    pub fn min_client_version(&self) -> Result<Option<SemanticVersion>> {
+        let Some(min_client_version) = self.min_client_version.as_deref() else {
+            return Ok(None);
+        };
+        Ok(Some(
+            min_client_version
+                .parse()
+                .context("invalid MIN_CLIENT_VERSION")?,
+        ))
+    }
+
+    pub fn turn_credential_ttl(&self) -> Duration {
+        self.turn_credential_ttl_secs
+            .map_or(ice::DEFAULT_TURN_CREDENTIAL_TTL, Duration::from_secs)
+    }
+
+    pub fn reconnect_timeout(&self) -> Duration {
+        self.reconnect_timeout_secs
+            .map_or(crate::rpc::RECONNECT_TIMEOUT, Duration::from_secs)
+    }
+
+    pub fn cleanup_timeout(&self) -> Duration {
+        self.cleanup_timeout_secs
+            .map_or(crate::rpc::CLEANUP_TIMEOUT, Duration::from_secs)
+    }
+
+    pub fn feature_flags(&self) -> Vec<String> {
+        parse_feature_flags(self.feature_flags.as_deref().unwrap_or_default())
+    }
+
+    pub fn lazy_worktree_entry_threshold(&self) -> usize {
+        self.lazy_worktree_entry_threshold
+            .unwrap_or(DEFAULT_LAZY_WORKTREE_ENTRY_THRESHOLD)
+    }
+
+    pub fn rate_limit_policy(&self) -> Result<RateLimitPolicy> {
+        let Some(rate_limits) = self.rate_limits.as_deref() else {
+            return Ok(RateLimitPolicy::default());
+        };
+        parse_rate_limits(rate_limits)
+    }
+
+    pub fn orphan_sweep_interval(&self) -> Duration {
+        self.orphan_sweep_interval_secs
+            .map_or(crate::rpc::ORPHAN_SWEEP_INTERVAL, Duration::from_secs)
+    }
+
+    pub fn overload_policy(&self) -> OverloadPolicy {
+        let default = OverloadPolicy::default();
+        OverloadPolicy {
+            max_event_loop_lag: self
+                .overload_event_loop_lag_ms
+                .map_or(default.max_event_loop_lag, Duration::from_millis),
+            max_db_latency: self
+                .overload_db_latency_ms
+                .map_or(default.max_db_latency, Duration::from_millis),
+        }
+    }
+
+    pub fn report_policy(&self) -> ReportPolicy {
+        let default = ReportPolicy::default();
+        ReportPolicy {
+            throttle_invites_at: self
+                .reports_to_throttle_invites
+                .unwrap_or(default.throttle_invites_at),
+            block_public_joins_at: self
+                .reports_to_block_public_joins
+                .unwrap_or(default.block_public_joins_at),
+        }
+    }
+
+    pub fn contact_request_policy(&self) -> ContactRequestPolicy {
+        let default = ContactRequestPolicy::default();
+        ContactRequestPolicy {
+            max_per_hour: self
+                .contact_requests_per_hour
+                .unwrap_or(default.max_per_hour),
+            max_to_strangers_per_hour: self
+                .contact_requests_to_strangers_per_hour
+                .unwrap_or(default.max_to_strangers_per_hour),
+        }
+    }
+
+    pub fn quota_policy(&self) -> QuotaPolicy {
+        let default = QuotaPolicy::default();
+        QuotaPolicy {
+            max_connections_per_user: self
+                .max_connections_per_user
+                .unwrap_or(default.max_connections_per_user),
+            max_shared_projects_per_room: self
+                .max_shared_projects_per_room
+                .unwrap_or(default.max_shared_projects_per_room),
+            max_room_participants: self
+                .max_room_participants
+                .unwrap_or(default.max_room_participants),
+        }
+    }
+
+    pub fn backlog_policy(&self) -> BacklogPolicy {
+        let default = BacklogPolicy::default();
+        BacklogPolicy {
+            shed_lossy_messages: self
+                .send_backlog_shed_threshold
+                .unwrap_or(default.shed_lossy_messages),
+            notify_lagging: self
+                .send_backlog_lagging_threshold
+                .unwrap_or(default.notify_lagging),
+            disconnect: self
+                .send_backlog_disconnect_threshold
+                .unwrap_or(default.disconnect),
+        }
+    }
+}
+
+fn parse_feature_flags(feature_flags: &str) -> Vec<String> {
+    feature_flags
+        .split(',')
+        .map(str::trim)
+        .filter(|flag| !flag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn parse_rate_limits(rate_limits: &str) -> Result<RateLimitPolicy> {
+    Ok(serde_json::from_str(rate_limits).context("invalid RATE_LIMITS")?)
 }
 
 #[derive(Default, Deserialize)]
@@ -115,9 +366,100 @@ pub struct MigrateConfig {
     pub migrations_path: Option<PathBuf>,
 }
 
+pub const DEFAULT_LAZY_WORKTREE_ENTRY_THRESHOLD: usize = 20_000;
+
+/// The `ZED_ENVIRONMENT` of servers started with `collab self-host`.
+pub const SELF_HOSTED_ENVIRONMENT: &str = "self-hosted";
+
+/// Configuration for `collab self-host`, which runs the whole collaboration
+/// service out of a single SQLite file so that a team can host it on their own
+/// network. Everything has a default, so no environment variables are required.
+#[derive(Default, Deserialize)]
+pub struct SelfHostConfig {
+    pub http_port: Option<u16>,
+    pub database_path: Option<PathBuf>,
+    /// The token for the admin API. The API is unusable when this is left unset,
+    /// as a random token is generated and never shown.
+    pub api_token: Option<String>,
+    pub live_kit_server: Option<String>,
+    pub live_kit_key: Option<String>,
+    pub live_kit_secret: Option<String>,
+    pub ice_servers: Option<String>,
+    pub ice_force_relay: Option<bool>,
+    pub turn_credential_ttl_secs: Option<u64>,
+    pub ice_servers_key: Option<String>,
+    pub avatar_url_prefix: Option<String>,
+    pub rate_limits: Option<String>,
+    pub webhooks: Option<String>,
+    pub collation_locale: Option<String>,
+    pub trusted_proxies: Option<String>,
+    pub rust_log: Option<String>,
+}
+
+impl SelfHostConfig {
+    pub const DEFAULT_HTTP_PORT: u16 = 8080;
+    pub const DEFAULT_DATABASE_PATH: &'static str = "collab.db";
+
+    pub fn into_config(self) -> Config {
+        let database_path = self
+            .database_path
+            .unwrap_or_else(|| PathBuf::from(Self::DEFAULT_DATABASE_PATH));
+        Config {
+            http_port: self.http_port.unwrap_or(Self::DEFAULT_HTTP_PORT),
+            database_url: format!("sqlite://{}?mode=rwc", database_path.display()),
+            database_max_connections: 5,
+            api_token: self.api_token.unwrap_or_else(::rpc::auth::random_token),
+            invite_link_prefix: String::new(),
+            live_kit_server: self.live_kit_server,
+            live_kit_key: self.live_kit_key,
+            live_kit_secret: self.live_kit_secret,
+            rust_log: self.rust_log,
+            log_json: None,
+            zed_environment: SELF_HOSTED_ENVIRONMENT.into(),
+            min_client_version: None,
+            release_notes_url: None,
+            send_backlog_shed_threshold: None,
+            send_backlog_lagging_threshold: None,
+            send_backlog_disconnect_threshold: None,
+            ice_servers: self.ice_servers,
+            ice_force_relay: self.ice_force_relay,
+            turn_credential_ttl_secs: self.turn_credential_ttl_secs,
+            ice_servers_key: self.ice_servers_key,
+            lazy_worktree_entry_threshold: None,
+            avatar_url_prefix: self.avatar_url_prefix,
+            rate_limits: self.rate_limits,
+            orphan_sweep_interval_secs: None,
+            reconnect_timeout_secs: None,
+            cleanup_timeout_secs: None,
+            feature_flags: None,
+            overload_event_loop_lag_ms: None,
+            overload_db_latency_ms: None,
+            reports_to_throttle_invites: None,
+            reports_to_block_public_joins: None,
+            contact_requests_per_hour: None,
+            contact_requests_to_strangers_per_hour: None,
+            max_connections_per_user: None,
+            max_shared_projects_per_room: None,
+            max_room_participants: None,
+            webhooks: self.webhooks,
+            reconnect_hosts: None,
+            collation_locale: self.collation_locale,
+            trusted_proxies: self.trusted_proxies,
+        }
+    }
+}
+
 pub struct AppState {
     pub db: Arc<Database>,
     pub live_kit_client: Option<Arc<dyn live_kit_server::api::Client>>,
+    pub ice_config: Option<Arc<ice::IceConfig>>,
+    pub ice_servers_key: Option<Arc<ice::IceServersKey>>,
+    /// The oldest version of Zed that can connect, parsed from the config once it's
+    /// loaded.
+    pub min_client_version: Option<SemanticVersion>,
+    pub runtime_config: SharedRuntimeConfig,
+    pub message_bus: Arc<dyn MessageBus>,
+    pub webhooks: Arc<Webhooks>,
     pub config: Config,
 }
 
@@ -127,6 +469,22 @@ impl AppState {
         db_options.max_connections(config.database_max_connections);
         let mut db = Database::new(db_options, Executor::Production).await?;
         db.initialize_notification_kinds().await?;
+        let db = Arc::new(db);
+        let overrides = db
+            .get_runtime_config_overrides(&config.zed_environment)
+            .await?;
+        let runtime_config = RuntimeConfig::new(&config, &overrides)?;
+
+        // Servers sharing a Postgres database can be deployed side by side, while other
+        // databases only support a single server.
+        let message_bus: Arc<dyn MessageBus> = if config.database_url.starts_with("postgres") {
+            Arc::new(PostgresMessageBus::new(
+                db.clone(),
+                config.database_url.clone(),
+            )?)
+        } else {
+            Arc::new(InMemoryMessageBus::default())
+        };
 
         let live_kit_client = if let Some(((server, key), secret)) = config
             .live_kit_server
@@ -143,9 +501,21 @@ impl AppState {
             None
         };
 
+        let webhooks = Webhooks::new(
+            &config,
+            Arc::new(HttpWebhookClient::new()?),
+            Executor::Production,
+        )?;
+
         let this = Self {
-            db: Arc::new(db),
+            db,
             live_kit_client,
+            ice_config: ice::IceConfig::from_config(&config)?.map(Arc::new),
+            ice_servers_key: ice::IceServersKey::from_config(&config)?.map(Arc::new),
+            min_client_version: config.min_client_version()?,
+            runtime_config: SharedRuntimeConfig::new(runtime_config),
+            message_bus,
+            webhooks: Arc::new(webhooks),
             config,
         };
         Ok(Arc::new(this))
@@ -1,12 +1,16 @@
 use anyhow::anyhow;
 use axum::{routing::get, Extension, Router};
-use collab::{db, env, executor::Executor, AppState, Config, MigrateConfig, Result};
+#[cfg(feature = "self-hosted")]
+use collab::SelfHostConfig;
+use collab::{auth, db, env, executor::Executor, AppState, Config, MigrateConfig, Result};
 use db::Database;
+use sqlx::migrate::Migration;
 use std::{
     env::args,
     net::{SocketAddr, TcpListener},
     path::Path,
     sync::Arc,
+    time::Duration,
 };
 use tokio::signal::unix::SignalKind;
 use tracing_log::LogTracer;
@@ -39,60 +43,161 @@ async fn main() -> Result<()> {
             run_migrations().await?;
 
             let state = AppState::new(config).await?;
+            serve(state).await?;
+        }
+        #[cfg(feature = "self-hosted")]
+        Some("self-host") => {
+            let config = envy::from_env::<SelfHostConfig>()
+                .expect("error loading config")
+                .into_config();
+            let db_options = db::ConnectOptions::new(config.database_url.clone());
+            let db = Database::new(db_options, Executor::Production).await?;
+
+            match args().nth(2).as_deref() {
+                None => {
+                    init_tracing(&config);
+                    for (migration, duration) in db.migrate_embedded_sqlite().await? {
+                        log::info!(
+                            "Migrated {} {} {:?}",
+                            migration.version,
+                            migration.description,
+                            duration
+                        );
+                    }
+                    drop(db);
+
+                    println!(
+                        "collab is serving on port {}. Put it behind a proxy that terminates TLS, \
+                         as clients only sign in over https, and add team members with:\n\n    \
+                         collab self-host add-user <login>\n",
+                        config.http_port
+                    );
 
-            let listener = TcpListener::bind(&format!("0.0.0.0:{}", state.config.http_port))
-                .expect("failed to bind TCP listener");
-
-            let epoch = state
-                .db
-                .create_server(&state.config.zed_environment)
-                .await?;
-            let rpc_server = collab::rpc::Server::new(epoch, state.clone(), Executor::Production);
-            rpc_server.start().await?;
-
-            let app = collab::api::routes(rpc_server.clone(), state.clone())
-                .merge(collab::rpc::routes(rpc_server.clone()))
-                .merge(
-                    Router::new()
-                        .route("/", get(handle_root))
-                        .route("/healthz", get(handle_liveness_probe))
-                        .layer(Extension(state.clone())),
-                );
-
-            axum::Server::from_tcp(listener)?
-                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
-                .with_graceful_shutdown(async move {
-                    let mut sigterm = tokio::signal::unix::signal(SignalKind::terminate())
-                        .expect("failed to listen for interrupt signal");
-                    let mut sigint = tokio::signal::unix::signal(SignalKind::interrupt())
-                        .expect("failed to listen for interrupt signal");
-                    let sigterm = sigterm.recv();
-                    let sigint = sigint.recv();
-                    futures::pin_mut!(sigterm, sigint);
-                    futures::future::select(sigterm, sigint).await;
-                    tracing::info!("Received interrupt signal");
-                    rpc_server.teardown();
-                })
-                .await?;
+                    let state = AppState::new(config).await?;
+                    serve(state).await?;
+                }
+                Some("add-user") => {
+                    let login = args().nth(3).ok_or_else(|| {
+                        anyhow!("usage: collab self-host add-user <login> [--admin]")
+                    })?;
+                    let admin = args().nth(4).as_deref() == Some("--admin");
+                    db.migrate_embedded_sqlite().await?;
+                    let user = db
+                        .get_or_create_user_by_github_account(&login, None, None)
+                        .await?;
+                    db.set_user_admin(user.id, admin).await?;
+                    let access_token = auth::create_access_token(&db, user.id, None).await?;
+                    print_credentials(user.id, &access_token);
+                }
+                Some("impersonate") => {
+                    let (Some(admin_login), Some(login)) = (args().nth(3), args().nth(4)) else {
+                        return Err(anyhow!(
+                            "usage: collab self-host impersonate <admin-login> <login>"
+                        )
+                        .into());
+                    };
+                    // Impersonation tokens belong to the admin who asked for them, so
+                    // connections made with them are attributed to that admin.
+                    let admin = db
+                        .get_user_by_github_login(&admin_login)
+                        .await?
+                        .filter(|admin| admin.admin && admin.banned_at.is_none())
+                        .ok_or_else(|| anyhow!("{admin_login} is not an admin"))?;
+                    let user = db
+                        .get_user_by_github_login(&login)
+                        .await?
+                        .ok_or_else(|| anyhow!("user {login} does not exist"))?;
+                    let access_token =
+                        auth::create_access_token(&db, admin.id, Some(user.id)).await?;
+                    print_credentials(user.id, &access_token);
+                }
+                _ => {
+                    Err(anyhow!(
+                        "usage: collab self-host [add-user <login> [--admin] | impersonate <admin-login> <login>]"
+                    ))?;
+                }
+            }
         }
         _ => {
-            Err(anyhow!("usage: collab <version | migrate | serve>"))?;
+            Err(anyhow!(
+                "usage: collab <version | migrate | serve | self-host>"
+            ))?;
         }
     }
     Ok(())
 }
 
+/// Prints the credentials a team member launches Zed with, which are shown only
+/// once, as only their hash is stored.
+#[cfg(feature = "self-hosted")]
+fn print_credentials(user_id: db::UserId, access_token: &str) {
+    println!(
+        "To connect, launch Zed with:\n\n    \
+         ZED_RPC_URL=https://<this-host>/rpc ZED_USER_ID={} ZED_ACCESS_TOKEN='{}' zed\n",
+        user_id, access_token
+    );
+}
+
+async fn serve(state: Arc<AppState>) -> Result<()> {
+    let listener = TcpListener::bind(&format!("0.0.0.0:{}", state.config.http_port))
+        .expect("failed to bind TCP listener");
+
+    let epoch = state
+        .db
+        .create_server(&state.config.zed_environment)
+        .await?;
+    let rpc_server = collab::rpc::Server::new(epoch, state.clone(), Executor::Production);
+    rpc_server.start().await?;
+
+    let app = collab::api::routes(rpc_server.clone(), state.clone())
+        .merge(collab::rpc::routes(rpc_server.clone()))
+        .merge(collab::avatars::routes(state.clone()))
+        .merge(
+            Router::new()
+                .route("/", get(handle_root))
+                .route("/healthz", get(handle_liveness_probe))
+                .layer(Extension(state.clone())),
+        );
+
+    axum::Server::from_tcp(listener)?
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move {
+            let mut sigterm = tokio::signal::unix::signal(SignalKind::terminate())
+                .expect("failed to listen for interrupt signal");
+            let mut sigint = tokio::signal::unix::signal(SignalKind::interrupt())
+                .expect("failed to listen for interrupt signal");
+            let sigterm = sigterm.recv();
+            let sigint = sigint.recv();
+            let drained = rpc_server.wait_until_drained();
+            futures::pin_mut!(sigterm, sigint, drained);
+            let interrupted = futures::future::select(sigterm, sigint);
+            if let futures::future::Either::Left(_) =
+                futures::future::select(interrupted, drained).await
+            {
+                // Give clients the chance to move to another server, rather than
+                // dropping their connections.
+                tracing::info!("Received interrupt signal");
+                rpc_server.drain(None).await;
+            }
+        })
+        .await?;
+    Ok(())
+}
+
 async fn run_migrations() -> Result<()> {
     let config = envy::from_env::<MigrateConfig>().expect("error loading config");
     let db_options = db::ConnectOptions::new(config.database_url.clone());
     let db = Database::new(db_options, Executor::Production).await?;
 
-    let migrations_path = config
-        .migrations_path
-        .as_deref()
-        .unwrap_or_else(|| Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/migrations")));
-
-    let migrations = db.migrate(&migrations_path, false).await?;
+    let migrations = if config.database_url.starts_with("sqlite:") {
+        migrate_sqlite(&db).await?
+    } else {
+        let migrations_path = config
+            .migrations_path
+            .as_deref()
+            .unwrap_or_else(|| Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/migrations")));
+        db.migrate(&migrations_path, false).await?
+    };
     for (migration, duration) in migrations {
         log::info!(
             "Migrated {} {} {:?}",
@@ -105,6 +210,18 @@ async fn run_migrations() -> Result<()> {
     return Ok(());
 }
 
+#[cfg(feature = "sqlite")]
+async fn migrate_sqlite(db: &Database) -> anyhow::Result<Vec<(Migration, Duration)>> {
+    db.migrate_embedded_sqlite().await
+}
+
+#[cfg(not(feature = "sqlite"))]
+async fn migrate_sqlite(_: &Database) -> anyhow::Result<Vec<(Migration, Duration)>> {
+    Err(anyhow!(
+        "collab was built without SQLite support, which the `sqlite` feature adds"
+    ))
+}
+
 async fn handle_root() -> String {
     format!("collab v{} ({})", VERSION, REVISION.unwrap_or("unknown"))
 }
@@ -0,0 +1,428 @@
+use crate::{
+    db::{routed_message, routed_messages_channel, Database, NewRoutedMessage, ServerId, UserId},
+    rpc::ResultExt as _,
+    Result,
+};
+use async_trait::async_trait;
+use collections::{HashMap, VecDeque};
+use futures::{channel::mpsc, stream::BoxStream, StreamExt};
+use parking_lot::Mutex;
+use prost::Message as _;
+use rpc::{proto, ConnectionId, RoutedEnvelope};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgConnectOptions, PgListener, PgPool, PgPoolOptions};
+use std::{str::FromStr, sync::Arc};
+
+/// The application name that a server's listener connection reports to Postgres, which
+/// is how the other servers can tell that it's still running.
+const LISTENER_APPLICATION_NAME_PREFIX: &str = "collab-server-";
+
+/// The channel that [`ServerEvent`]s are broadcast on, as the payload of a `NOTIFY`.
+const SERVER_EVENTS_CHANNEL: &str = "collab_server_events";
+
+/// The most routed messages that are stored in one transaction.
+const MAX_ROUTED_MESSAGE_BATCH_SIZE: usize = 1024;
+
+/// A message addressed to a connection on another server.
+#[derive(Clone, Debug)]
+pub struct RoutedMessage {
+    pub receiver_id: ConnectionId,
+    pub envelope: RoutedEnvelope,
+}
+
+impl RoutedMessage {
+    /// The server holding the connection that the message is addressed to.
+    pub fn server_id(&self) -> ServerId {
+        ServerId(self.receiver_id.owner_id as i32)
+    }
+}
+
+/// Something that happened on one server which the others need to know about, such as
+/// which users are connected to it, so that their contacts' statuses stay up to date.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ServerEvent {
+    /// A server has subscribed to the bus, and wants to know about the connections of
+    /// the others.
+    ServerStarted {
+        server_id: ServerId,
+    },
+    ConnectionOpened {
+        connection_id: ConnectionId,
+        user_id: UserId,
+    },
+    ConnectionClosed {
+        connection_id: ConnectionId,
+    },
+    /// A user was banned, so every server closes their connections.
+    UserBanned {
+        server_id: ServerId,
+        user_id: UserId,
+    },
+}
+
+impl ServerEvent {
+    /// The server that broadcast the event.
+    pub fn sender_id(&self) -> ServerId {
+        match self {
+            ServerEvent::ServerStarted { server_id }
+            | ServerEvent::UserBanned { server_id, .. } => *server_id,
+            ServerEvent::ConnectionOpened { connection_id, .. }
+            | ServerEvent::ConnectionClosed { connection_id } => {
+                ServerId(connection_id.owner_id as i32)
+            }
+        }
+    }
+}
+
+/// What a server receives from the bus.
+#[derive(Clone, Debug)]
+pub enum BusMessage {
+    Routed(RoutedMessage),
+    Event(ServerEvent),
+}
+
+/// Routes messages between the servers of a deployment, so that the participants of a
+/// room can be connected to different servers, such as while one deployment replaces
+/// another.
+#[async_trait]
+pub trait MessageBus: Send + Sync {
+    /// Publishes a message to the server holding its receiver. Messages published to
+    /// the same server are delivered in the order they were published.
+    fn publish(&self, message: RoutedMessage);
+
+    /// Sends an event to every subscribed server, including the one broadcasting it.
+    fn broadcast(&self, event: ServerEvent);
+
+    /// Returns the messages published to the given server, along with the events
+    /// broadcast by any server. The server is live for as long as the stream is held.
+    async fn subscribe(&self, server_id: ServerId) -> Result<BoxStream<'static, BusMessage>>;
+
+    /// Returns the servers that are currently subscribed.
+    async fn live_server_ids(&self) -> Result<Vec<ServerId>>;
+}
+
+/// A bus for servers running in the same process, which is how tests run several of
+/// them at once.
+#[derive(Default)]
+pub struct InMemoryMessageBus {
+    subscribers: Mutex<HashMap<ServerId, mpsc::UnboundedSender<BusMessage>>>,
+}
+
+#[async_trait]
+impl MessageBus for InMemoryMessageBus {
+    fn publish(&self, message: RoutedMessage) {
+        let mut subscribers = self.subscribers.lock();
+        let server_id = message.server_id();
+        if let Some(subscriber) = subscribers.get(&server_id) {
+            if subscriber
+                .unbounded_send(BusMessage::Routed(message))
+                .is_err()
+            {
+                subscribers.remove(&server_id);
+            }
+        }
+    }
+
+    fn broadcast(&self, event: ServerEvent) {
+        self.subscribers.lock().retain(|_, subscriber| {
+            subscriber
+                .unbounded_send(BusMessage::Event(event.clone()))
+                .is_ok()
+        });
+    }
+
+    async fn subscribe(&self, server_id: ServerId) -> Result<BoxStream<'static, BusMessage>> {
+        let (tx, rx) = mpsc::unbounded();
+        self.subscribers.lock().insert(server_id, tx);
+        Ok(rx.boxed())
+    }
+
+    async fn live_server_ids(&self) -> Result<Vec<ServerId>> {
+        let mut subscribers = self.subscribers.lock();
+        subscribers.retain(|_, subscriber| !subscriber.is_closed());
+        Ok(subscribers.keys().copied().collect())
+    }
+}
+
+enum Outgoing {
+    Message(RoutedMessage),
+    Event(ServerEvent),
+}
+
+/// A bus for servers that share a Postgres database. Messages are stored in the
+/// database, and their receivers are woken with `NOTIFY`. Events have nothing to be
+/// taken later, so they're sent as the payload of a `NOTIFY` instead.
+pub struct PostgresMessageBus {
+    db: Arc<Database>,
+    database_url: String,
+    pool: PgPool,
+    outbox: mpsc::UnboundedSender<Outgoing>,
+}
+
+impl PostgresMessageBus {
+    /// Creates the bus, which must happen inside a Tokio runtime.
+    pub fn new(db: Arc<Database>, database_url: String) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .connect_lazy(&database_url)
+            .map_err(anyhow::Error::from)?;
+
+        // Messages are sent by a single task, so that they're delivered in order. Those
+        // that are ready together are stored in one transaction.
+        let (outbox, outgoing) = mpsc::unbounded::<Outgoing>();
+        tokio::spawn({
+            let db = db.clone();
+            let pool = pool.clone();
+            let mut outgoing = outgoing.ready_chunks(MAX_ROUTED_MESSAGE_BATCH_SIZE);
+            async move {
+                while let Some(chunk) = outgoing.next().await {
+                    let mut messages = Vec::new();
+                    for outgoing in chunk {
+                        match outgoing {
+                            Outgoing::Message(message) => {
+                                messages.push(encode_routed_message(message))
+                            }
+                            Outgoing::Event(event) => {
+                                db.publish_routed_messages(std::mem::take(&mut messages))
+                                    .await
+                                    .trace_err();
+                                notify_server_event(&pool, &event).await.trace_err();
+                            }
+                        }
+                    }
+                    db.publish_routed_messages(messages).await.trace_err();
+                }
+            }
+        });
+
+        Ok(Self {
+            db,
+            database_url,
+            pool,
+            outbox,
+        })
+    }
+}
+
+#[async_trait]
+impl MessageBus for PostgresMessageBus {
+    fn publish(&self, message: RoutedMessage) {
+        self.outbox.unbounded_send(Outgoing::Message(message)).ok();
+    }
+
+    fn broadcast(&self, event: ServerEvent) {
+        self.outbox.unbounded_send(Outgoing::Event(event)).ok();
+    }
+
+    async fn subscribe(&self, server_id: ServerId) -> Result<BoxStream<'static, BusMessage>> {
+        // The listener gets a connection of its own, which lasts as long as the stream.
+        let options = PgConnectOptions::from_str(&self.database_url)
+            .map_err(anyhow::Error::from)?
+            .application_name(&format!("{LISTENER_APPLICATION_NAME_PREFIX}{server_id}"));
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .map_err(anyhow::Error::from)?;
+        let mut listener = PgListener::connect_with(&pool)
+            .await
+            .map_err(anyhow::Error::from)?;
+        listener
+            .listen_all([
+                routed_messages_channel(server_id).as_str(),
+                SERVER_EVENTS_CHANNEL,
+            ])
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        let db = self.db.clone();
+        let messages = futures::stream::unfold(
+            (listener, VecDeque::new(), true),
+            move |(mut listener, mut pending, mut take)| {
+                let db = db.clone();
+                async move {
+                    loop {
+                        if let Some(message) = pending.pop_front() {
+                            return Some((message, (listener, pending, take)));
+                        }
+
+                        // Messages published before the server subscribed, or while the
+                        // listener was reconnecting, are taken along with the new ones.
+                        if take {
+                            if let Some(messages) =
+                                db.take_routed_messages(server_id).await.trace_err()
+                            {
+                                pending.extend(
+                                    messages
+                                        .into_iter()
+                                        .filter_map(decode_routed_message)
+                                        .map(BusMessage::Routed),
+                                );
+                            }
+                        }
+
+                        if pending.is_empty() {
+                            match listener.try_recv().await {
+                                Ok(Some(notification))
+                                    if notification.channel() == SERVER_EVENTS_CHANNEL =>
+                                {
+                                    take = false;
+                                    if let Some(event) =
+                                        serde_json::from_str(notification.payload()).trace_err()
+                                    {
+                                        pending.push_back(BusMessage::Event(event));
+                                    }
+                                }
+                                Ok(_) => take = true,
+                                Err(error) => {
+                                    tracing::error!(%error, "routed message listener failed");
+                                    return None;
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+        );
+        Ok(messages.boxed())
+    }
+
+    async fn live_server_ids(&self) -> Result<Vec<ServerId>> {
+        let application_names: Vec<(String,)> = sqlx::query_as(
+            "SELECT application_name FROM pg_stat_activity WHERE application_name LIKE $1",
+        )
+        .bind(format!("{LISTENER_APPLICATION_NAME_PREFIX}%"))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(anyhow::Error::from)?;
+        Ok(application_names
+            .into_iter()
+            .filter_map(|(name,)| {
+                let server_id = name.strip_prefix(LISTENER_APPLICATION_NAME_PREFIX)?;
+                Some(ServerId(server_id.parse().ok()?))
+            })
+            .collect())
+    }
+}
+
+async fn notify_server_event(pool: &PgPool, event: &ServerEvent) -> Result<()> {
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(SERVER_EVENTS_CHANNEL)
+        .bind(serde_json::to_string(event).map_err(anyhow::Error::from)?)
+        .execute(pool)
+        .await
+        .map_err(anyhow::Error::from)?;
+    Ok(())
+}
+
+fn encode_routed_message(message: RoutedMessage) -> NewRoutedMessage {
+    let server_id = message.server_id();
+    let connection_id = message.receiver_id.id;
+    let (request_id, requester_server_id, envelope) = match message.envelope {
+        RoutedEnvelope::Message(envelope) => (None, None, envelope),
+        RoutedEnvelope::Request {
+            requester_epoch,
+            request_id,
+            envelope,
+        } => (
+            Some(request_id),
+            Some(ServerId(requester_epoch as i32)),
+            envelope,
+        ),
+        RoutedEnvelope::Response {
+            request_id,
+            envelope,
+        } => (Some(request_id), None, envelope),
+    };
+    NewRoutedMessage {
+        server_id,
+        connection_id,
+        request_id,
+        requester_server_id,
+        envelope: envelope.encode_to_vec(),
+    }
+}
+
+fn decode_routed_message(message: routed_message::Model) -> Option<RoutedMessage> {
+    let envelope = proto::Envelope::decode(message.envelope.as_slice()).trace_err()?;
+    let envelope = match (message.request_id, message.requester_server_id) {
+        (None, _) => RoutedEnvelope::Message(envelope),
+        (Some(request_id), Some(requester_server_id)) => RoutedEnvelope::Request {
+            requester_epoch: requester_server_id.0 as u32,
+            request_id: request_id as u32,
+            envelope,
+        },
+        (Some(request_id), None) => RoutedEnvelope::Response {
+            request_id: request_id as u32,
+            envelope,
+        },
+    };
+    Some(RoutedMessage {
+        receiver_id: ConnectionId {
+            owner_id: message.server_id.0 as u32,
+            id: message.connection_id as u32,
+        },
+        envelope,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rpc::proto::EnvelopedMessage as _;
+
+    #[gpui::test]
+    async fn test_in_memory_message_bus() {
+        let bus = InMemoryMessageBus::default();
+        let mut messages_1 = bus.subscribe(ServerId(1)).await.unwrap();
+        let mut messages_2 = bus.subscribe(ServerId(2)).await.unwrap();
+        assert_eq!(
+            sorted(bus.live_server_ids().await.unwrap()),
+            &[ServerId(1), ServerId(2)]
+        );
+
+        for (owner_id, id) in [(1, 5), (2, 6), (3, 7), (1, 8)] {
+            bus.publish(RoutedMessage {
+                receiver_id: ConnectionId { owner_id, id },
+                envelope: RoutedEnvelope::Message(
+                    proto::Test { id: id as u64 }.into_envelope(0, None, None),
+                ),
+            });
+        }
+        for expected_id in [5, 8] {
+            let Some(BusMessage::Routed(message)) = messages_1.next().await else {
+                panic!("expected a routed message");
+            };
+            assert_eq!(
+                message.receiver_id,
+                ConnectionId {
+                    owner_id: 1,
+                    id: expected_id
+                }
+            );
+        }
+
+        // Events are broadcast to every server, including the sender.
+        let event = ServerEvent::ConnectionOpened {
+            connection_id: ConnectionId { owner_id: 2, id: 9 },
+            user_id: UserId(1),
+        };
+        bus.broadcast(event.clone());
+        for messages in [&mut messages_1, &mut messages_2] {
+            let Some(BusMessage::Event(received)) = messages.next().await else {
+                panic!("expected an event");
+            };
+            assert_eq!(received, event);
+            assert_eq!(received.sender_id(), ServerId(2));
+        }
+
+        // Servers stop being live once they drop their subscription.
+        drop(messages_2);
+        assert_eq!(bus.live_server_ids().await.unwrap(), &[ServerId(1)]);
+    }
+
+    fn sorted(mut server_ids: Vec<ServerId>) -> Vec<ServerId> {
+        server_ids.sort();
+        server_ids
+    }
+}
@@ -1,32 +1,48 @@
+mod abuse;
+mod audio_routes;
+mod brokered_connections;
 mod connection_pool;
+mod guest;
+mod overload;
+pub(crate) mod quotas;
+mod room_timers;
 
 use crate::{
     auth::{self, Impersonator},
+    avatars::{self, MAX_AVATAR_SIZE},
     db::{
-        self, BufferId, ChannelId, ChannelRole, ChannelsForUser, CreatedChannelMessage, Database,
-        InviteMemberResult, MembershipUpdated, MessageId, NotificationId, ProjectId,
-        RemoveChannelMemberResult, RespondToChannelInvite, RoomId, ServerId, User, UserId,
+        self, AuditEventKind, BufferId, ChannelId, ChannelRole, ChannelsForUser,
+        CreatedChannelMessage, Database, InviteMemberResult, MembershipUpdated, MessageId,
+        NewAuditEvent, NotificationId, ProjectId, RateLimitedActionKind, RemoveChannelMemberResult,
+        RespondToChannelInvite, RoomId, RoomMessageId, ServerId, User, UserId,
     },
     executor::Executor,
+    ice::{IceConfig, IceServersKey},
+    message_bus::{BusMessage, MessageBus, RoutedMessage, ServerEvent},
+    runtime_config::{RuntimeConfig, SharedRuntimeConfig},
+    webhooks::{WebhookEvent, Webhooks},
     AppState, Error, Result,
 };
+pub use abuse::{ContactRequestPolicy, ReportPolicy, THROTTLED_INVITE_LIMIT};
 use anyhow::anyhow;
 use async_tungstenite::tungstenite::{
     protocol::CloseFrame as TungsteniteCloseFrame, Message as TungsteniteMessage,
 };
+use audio_routes::AudioRoutes;
 use axum::{
     body::Body,
     extract::{
         ws::{CloseFrame as AxumCloseFrame, Message as AxumMessage},
         ConnectInfo, WebSocketUpgrade,
     },
-    headers::{Header, HeaderName},
+    headers::{Header, HeaderName, HeaderValue},
     http::StatusCode,
     middleware,
     response::IntoResponse,
     routing::get,
     Extension, Router, TypedHeader,
 };
+use brokered_connections::BrokeredConnections;
 use collections::{HashMap, HashSet};
 pub use connection_pool::ConnectionPool;
 use futures::{
@@ -36,13 +52,21 @@ use futures::{
     FutureExt, SinkExt, StreamExt, TryStreamExt,
 };
 use lazy_static::lazy_static;
-use prometheus::{register_int_gauge, IntGauge};
+use overload::OverloadState;
+pub use overload::{OverloadLevel, OverloadPolicy};
+use prometheus::{
+    register_int_counter_vec, register_int_gauge, register_int_gauge_vec, IntCounterVec, IntGauge,
+    IntGaugeVec,
+};
+pub use quotas::QuotaPolicy;
+use room_timers::{RoomTimerEvent, RoomTimers, MAX_ROOM_DURATION, ROOM_TIMER_TICK};
 use rpc::{
     proto::{
         self, Ack, AnyTypedEnvelope, EntityMessage, EnvelopedMessage, LiveKitConnectionInfo,
         RequestMessage, ShareProject, UpdateChannelBufferCollaborators,
     },
-    Connection, ConnectionId, ErrorCode, ErrorCodeExt, ErrorExt, Peer, Receipt, TypedEnvelope,
+    Connection, ConnectionId, ErrorCode, ErrorCodeExt, ErrorExt, MessageCategory, Peer, Receipt,
+    RpcError, TypedEnvelope,
 };
 use serde::{Serialize, Serializer};
 use std::{
@@ -53,25 +77,66 @@ use std::{
     mem,
     net::SocketAddr,
     ops::{Deref, DerefMut},
+    path::Path,
     rc::Rc,
     sync::{
         atomic::{AtomicBool, Ordering::SeqCst},
-        Arc,
+        Arc, Weak,
     },
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 use time::OffsetDateTime;
 use tokio::sync::{watch, Semaphore};
 use tower::ServiceBuilder;
 use tracing::{field, info_span, instrument, Instrument};
-use util::SemanticVersion;
+use util::{collation::Collation, SemanticVersion};
 
+/// How long clients that lose their connection have to reconnect, unless
+/// `RECONNECT_TIMEOUT_SECS` says otherwise.
 pub const RECONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long servers wait after starting before cleaning up after the ones they
+/// replaced, unless `CLEANUP_TIMEOUT_SECS` says otherwise.
 pub const CLEANUP_TIMEOUT: Duration = Duration::from_secs(10);
-
+pub const ORPHAN_SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+pub const RUNTIME_CONFIG_RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a draining server waits for its clients to move to another server before
+/// tearing down anyway. It's longer than [`RECONNECT_TIMEOUT`], after which the rooms
+/// of clients that don't come back are released, but shorter than the grace period
+/// that Kubernetes gives the server after `SIGTERM` before killing it.
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(45);
+/// How long clients that were handed resume tokens by a restarting server have to
+/// rejoin their rooms, after the stale server's other connections are cleaned up.
+pub const RESUME_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long the clients of a server that stopped, whether or not it restarted cleanly,
+/// have to rejoin their rooms on another server, counting from when the new server
+/// starts. It's longer than [`RECONNECT_TIMEOUT`] and [`CLEANUP_TIMEOUT`], so that
+/// rooms and calls outlive the restart.
+pub const REJOIN_WINDOW: Duration = Duration::from_secs(60);
+pub const DRAIN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+pub const OVERLOAD_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many times a lost connection's resources are released before giving up.
+const CONNECTION_CLEANUP_ATTEMPTS: usize = 5;
+/// How long to wait before releasing a lost connection's resources again after the
+/// first failure, which doubles after each one after that.
+const CONNECTION_CLEANUP_BACKOFF: Duration = Duration::from_millis(100);
 const MESSAGE_COUNT_PER_PAGE: usize = 100;
 const MAX_MESSAGE_LEN: usize = 1024;
+const MAX_CONTACT_NICKNAME_LEN: usize = 64;
+const MAX_CONTACT_NOTE_LEN: usize = 4096;
+const MAX_INVITE_MESSAGE_LEN: usize = 280;
+const MAX_WORKING_LOCATION_LEN: usize = 256;
+const MAX_SCREEN_ANNOTATION_POINTS: usize = 1024;
+/// Relayed audio frames hold 20ms of 16kHz mu-law audio, one byte per sample.
+const MAX_RELAYED_AUDIO_FRAME_LEN: usize = 320;
+const MAX_ROOM_ACTIVITY_REPORT_LEN: usize = 256;
+const MAX_ROOM_ACTIVITY_DETAIL_LEN: usize = 1024;
+const MAX_REPORT_REASON_LEN: usize = 1024;
+const MAX_REPORTED_MESSAGES: usize = 50;
 const NOTIFICATION_COUNT_PER_PAGE: usize = 50;
+const CALL_HISTORY_COUNT_PER_PAGE: usize = 50;
+/// How much filler clients download to estimate their bandwidth before joining a room.
+const ROOM_PREFLIGHT_PROBE_LEN: usize = 64 * 1024;
 
 lazy_static! {
     static ref METRIC_CONNECTIONS: IntGauge =
@@ -81,6 +146,41 @@ lazy_static! {
         "number of open projects with one or more guests"
     )
     .unwrap();
+    static ref METRIC_SWEPT_ORPHANS: IntCounterVec = register_int_counter_vec!(
+        "swept_orphans",
+        "orphaned records removed by the periodic sweep, by kind",
+        &["kind"]
+    )
+    .unwrap();
+    static ref METRIC_OVERLOAD_LEVEL: IntGauge = register_int_gauge!(
+        "overload_level",
+        "how much work the server is turning away, from 0 for none to 3 for the most"
+    )
+    .unwrap();
+    static ref METRIC_SHED_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "shed_messages",
+        "messages turned away because the server was overloaded, by message type",
+        &["message"]
+    )
+    .unwrap();
+    static ref METRIC_RUNTIME_CONFIG: IntGaugeVec = register_int_gauge_vec!(
+        "runtime_config",
+        "active values of the numeric runtime config, by environment variable",
+        &["name"]
+    )
+    .unwrap();
+    static ref METRIC_BYTES_SENT: IntGaugeVec = register_int_gauge_vec!(
+        "rpc_bytes_sent",
+        "bytes sent to clients since the server started, by message category",
+        &["category"]
+    )
+    .unwrap();
+    static ref METRIC_BYTES_RECEIVED: IntGaugeVec = register_int_gauge_vec!(
+        "rpc_bytes_received",
+        "bytes received from clients since the server started, by message category",
+        &["category"]
+    )
+    .unwrap();
 }
 
 type MessageHandler =
@@ -108,6 +208,21 @@ struct Session {
     peer: Arc<Peer>,
     connection_pool: Arc<parking_lot::Mutex<ConnectionPool>>,
     live_kit_client: Option<Arc<dyn live_kit_server::api::Client>>,
+    ice_config: Option<Arc<IceConfig>>,
+    ice_servers_key: Option<Arc<IceServersKey>>,
+    runtime_config: SharedRuntimeConfig,
+    overload: Arc<OverloadState>,
+    room_timers: Arc<RoomTimers>,
+    audio_routes: Arc<AudioRoutes>,
+    webhooks: Arc<Webhooks>,
+    message_bus: Arc<dyn MessageBus>,
+    /// The server, for opening the connections that this connection brokers.
+    server: Weak<Server>,
+    /// Whether the user is a guest, who can only send the messages guests are allowed.
+    guest: bool,
+    avatar_url_prefix: Option<Arc<str>>,
+    /// How names are ordered in the lists sent to the client.
+    collation: Collation,
     _executor: Executor,
 }
 
@@ -130,6 +245,95 @@ impl Session {
             _not_send: PhantomData,
         }
     }
+
+    /// The ICE servers this user's media connections should use in a room, with
+    /// freshly minted TURN credentials. A room's own servers, which are stored
+    /// encrypted, take precedence over the deployment's.
+    fn ice_configuration(&self, room_ice_servers: Option<&str>) -> Option<proto::IceConfiguration> {
+        let room_ice_config = room_ice_servers.and_then(|ice_servers| {
+            let key = self.ice_servers_key.as_ref()?;
+            key.open(ice_servers).trace_err()
+        });
+        let ice_config = room_ice_config.as_ref().or(self.ice_config.as_deref())?;
+        Some(ice_config.to_proto(
+            self.user_id,
+            SystemTime::now(),
+            self.runtime_config.get().turn_credential_ttl,
+        ))
+    }
+
+    /// The server's overload level, if it's high enough that messages shed at the
+    /// given level are turned away.
+    fn shedding(
+        &self,
+        shed_at: Option<OverloadLevel>,
+        message_name: &'static str,
+    ) -> Option<OverloadLevel> {
+        let shed_at = shed_at?;
+        let level = self.overload.level();
+        if level < shed_at {
+            return None;
+        }
+        METRIC_SHED_MESSAGES
+            .with_label_values(&[message_name])
+            .inc();
+        tracing::warn!(message = message_name, ?level, "shedding message");
+        Some(level)
+    }
+
+    /// What the user is kept from doing because of the reports made about them.
+    async fn report_throttle(&self) -> Result<abuse::ReportThrottle> {
+        let since = OffsetDateTime::now_utc() - abuse::REPORT_WINDOW;
+        let report_count = self
+            .db()
+            .await
+            .user_report_count(self.user_id, since)
+            .await?;
+        let report_policy = self.runtime_config.get().report_policy;
+        Ok(report_policy.throttle(report_count))
+    }
+
+    /// Fails if the user's invites are throttled and they've already sent as many as
+    /// they can for now.
+    async fn check_invite_throttle(&self, message_name: &str) -> Result<()> {
+        let throttle = self.report_throttle().await?;
+        if throttle >= abuse::ReportThrottle::ThrottleInvites
+            && !self
+                .db()
+                .await
+                .try_rate_limited_action(
+                    self.user_id,
+                    RateLimitedActionKind::ThrottledInvite,
+                    None,
+                    abuse::THROTTLED_INVITE_LIMIT,
+                    OffsetDateTime::now_utc() - abuse::INVITE_WINDOW,
+                )
+                .await?
+        {
+            Err(abuse::throttled_error(throttle, message_name))?;
+        }
+        Ok(())
+    }
+
+    /// How many users each room can have, counting those being called into it.
+    fn room_participant_quota(&self) -> usize {
+        self.runtime_config.get().quota_policy.max_room_participants
+    }
+
+    /// Users who uploaded an avatar are shown with it, and everyone else with
+    /// their GitHub avatar.
+    fn user_to_proto(&self, user: User) -> proto::User {
+        let avatar_url = match (&user.avatar_digest, &self.avatar_url_prefix) {
+            (Some(digest), Some(url_prefix)) => avatars::avatar_url(url_prefix, digest),
+            _ => format!("https://github.com/{}.png?size=128", user.github_login),
+        };
+        proto::User {
+            id: user.id.to_proto(),
+            avatar_url,
+            github_login: user.github_login,
+            display_name: user.display_name,
+        }
+    }
 }
 
 impl fmt::Debug for Session {
@@ -159,6 +363,12 @@ pub struct Server {
     executor: Executor,
     handlers: HashMap<TypeId, MessageHandler>,
     teardown: watch::Sender<()>,
+    draining: AtomicBool,
+    drained: watch::Sender<bool>,
+    overload: Arc<OverloadState>,
+    room_timers: Arc<RoomTimers>,
+    audio_routes: Arc<AudioRoutes>,
+    brokered_connections: BrokeredConnections,
 }
 
 pub(crate) struct ConnectionPoolGuard<'a> {
@@ -169,10 +379,22 @@ pub(crate) struct ConnectionPoolGuard<'a> {
 #[derive(Serialize)]
 pub struct ServerSnapshot<'a> {
     peer: &'a Peer,
+    overload_level: OverloadLevel,
     #[serde(serialize_with = "serialize_deref")]
     connection_pool: ConnectionPoolGuard<'a>,
 }
 
+#[derive(Clone, Debug, Serialize)]
+pub struct ConnectionStats {
+    pub connection_id: ConnectionId,
+    pub user_id: UserId,
+    pub admin: bool,
+    pub room_ids: Vec<RoomId>,
+    pub queued_message_count: usize,
+    pub rejected_request_count: u64,
+    pub delayed_message_count: u64,
+}
+
 pub fn serialize_deref<S, T, U>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -184,32 +406,70 @@ where
 
 impl Server {
     pub fn new(id: ServerId, app_state: Arc<AppState>, executor: Executor) -> Arc<Self> {
+        let peer = Peer::new(id.0 as u32);
+        let runtime_config = app_state.runtime_config.get();
+        peer.set_backlog_policy(runtime_config.backlog_policy);
+        peer.set_rate_limit_policy(runtime_config.rate_limit_policy.clone());
+        record_runtime_config(&runtime_config);
+        peer.set_remote_router(Arc::new({
+            let message_bus = app_state.message_bus.clone();
+            move |receiver_id, envelope| {
+                message_bus.publish(RoutedMessage {
+                    receiver_id,
+                    envelope,
+                });
+                Ok(())
+            }
+        }));
+        let room_timers = RoomTimers::new(OffsetDateTime::now_utc(), executor.monotonic_time());
         let mut server = Self {
             id: parking_lot::Mutex::new(id),
-            peer: Peer::new(id.0 as u32),
+            peer,
             app_state,
             executor,
             connection_pool: Default::default(),
             handlers: Default::default(),
             teardown: watch::channel(()).0,
+            draining: Default::default(),
+            drained: watch::channel(false).0,
+            overload: Default::default(),
+            room_timers: Arc::new(room_timers),
+            audio_routes: Default::default(),
+            brokered_connections: Default::default(),
         };
 
         server
             .add_request_handler(ping)
+            .add_request_handler(open_brokered_connection)
+            .add_message_handler(brokered_connection_message)
+            .add_message_handler(close_brokered_connection)
+            .add_request_handler(room_preflight)
             .add_request_handler(create_room)
             .add_request_handler(join_room)
+            .add_request_handler(accept_waiting_call)
             .add_request_handler(rejoin_room)
             .add_request_handler(leave_room)
+            .add_request_handler(extend_room)
             .add_request_handler(set_room_participant_role)
             .add_request_handler(call)
+            .add_request_handler(create_guest)
             .add_request_handler(cancel_call)
             .add_message_handler(decline_call)
+            .add_request_handler(get_call_history)
+            .add_request_handler(report_room_activity)
+            .add_request_handler(get_call_summary)
             .add_request_handler(update_participant_location)
+            .add_message_handler(update_screen_annotations)
+            .add_request_handler(send_room_message)
+            .add_request_handler(get_room_messages)
+            .add_message_handler(relay_audio_frame)
             .add_request_handler(share_project)
             .add_message_handler(unshare_project)
+            .add_request_handler(get_room_projects)
             .add_request_handler(join_project)
             .add_message_handler(leave_project)
             .add_request_handler(update_project)
+            .add_request_handler(set_collaborator_read_only)
             .add_request_handler(update_worktree)
             .add_message_handler(start_language_server)
             .add_message_handler(update_language_server)
@@ -221,9 +481,14 @@ impl Server {
             .add_request_handler(forward_read_only_project_request::<proto::GetReferences>)
             .add_request_handler(forward_read_only_project_request::<proto::SearchProject>)
             .add_request_handler(forward_read_only_project_request::<proto::GetDocumentHighlights>)
+            .add_request_handler(forward_read_only_project_request::<proto::GetLinkedEditingRanges>)
+            .add_request_handler(
+                forward_read_only_project_request::<proto::GetLanguageServerConfigurations>,
+            )
             .add_request_handler(forward_read_only_project_request::<proto::GetProjectSymbols>)
             .add_request_handler(forward_read_only_project_request::<proto::OpenBufferForSymbol>)
             .add_request_handler(forward_read_only_project_request::<proto::OpenBufferById>)
+            .add_request_handler(forward_read_only_project_request::<proto::CloseBuffer>)
             .add_request_handler(forward_read_only_project_request::<proto::SynchronizeBuffers>)
             .add_request_handler(forward_read_only_project_request::<proto::InlayHints>)
             .add_request_handler(forward_read_only_project_request::<proto::OpenBufferByPath>)
@@ -245,20 +510,47 @@ impl Server {
             .add_request_handler(forward_mutating_project_request::<proto::CopyProjectEntry>)
             .add_request_handler(forward_mutating_project_request::<proto::DeleteProjectEntry>)
             .add_request_handler(forward_mutating_project_request::<proto::ExpandProjectEntry>)
+            .add_request_handler(load_worktree_directory)
+            .add_request_handler(load_worktree_entries)
             .add_request_handler(forward_mutating_project_request::<proto::OnTypeFormatting>)
             .add_request_handler(forward_mutating_project_request::<proto::SaveBuffer>)
             .add_message_handler(create_buffer_for_peer)
+            .add_message_handler(unshare_buffer_for_peer)
             .add_request_handler(update_buffer)
             .add_message_handler(broadcast_project_message_from_host::<proto::RefreshInlayHints>)
             .add_message_handler(broadcast_project_message_from_host::<proto::UpdateBufferFile>)
             .add_message_handler(broadcast_project_message_from_host::<proto::BufferReloaded>)
             .add_message_handler(broadcast_project_message_from_host::<proto::BufferSaved>)
             .add_message_handler(broadcast_project_message_from_host::<proto::UpdateDiffBase>)
+            .add_request_handler(forward_mutating_project_request::<proto::AddBookmark>)
+            .add_request_handler(forward_mutating_project_request::<proto::RemoveBookmark>)
+            .add_request_handler(forward_read_only_project_request::<proto::GetBookmarks>)
+            .add_message_handler(broadcast_project_message_from_host::<proto::UpdateBookmarks>)
+            .add_request_handler(forward_read_only_project_request::<proto::GetEntryDecorations>)
+            .add_message_handler(
+                broadcast_project_message_from_host::<proto::UpdateEntryDecorations>,
+            )
+            .add_request_handler(forward_read_only_project_request::<proto::AddReviewComment>)
+            .add_request_handler(forward_mutating_project_request::<proto::ResolveReviewComment>)
+            .add_request_handler(forward_read_only_project_request::<proto::GetReview>)
+            .add_message_handler(broadcast_project_message_from_host::<proto::UpdateReview>)
+            .add_request_handler(forward_read_only_project_request::<proto::SuggestEdit>)
+            .add_request_handler(forward_read_only_project_request::<proto::GetEditSuggestions>)
+            .add_message_handler(
+                broadcast_project_message_from_host::<proto::UpdateEditSuggestions>,
+            )
             .add_request_handler(get_users)
             .add_request_handler(fuzzy_search_users)
+            .add_request_handler(set_display_name)
+            .add_request_handler(upload_avatar)
             .add_request_handler(request_contact)
             .add_request_handler(remove_contact)
+            .add_request_handler(report_user)
             .add_request_handler(respond_to_contact_request)
+            .add_request_handler(update_contact_note)
+            .add_request_handler(set_location_sharing)
+            .add_request_handler(set_user_blocked)
+            .add_message_handler(update_working_location)
             .add_request_handler(create_channel)
             .add_request_handler(delete_channel)
             .add_request_handler(invite_channel_member)
@@ -296,163 +588,445 @@ impl Server {
         let server_id = *self.id.lock();
         let app_state = self.app_state.clone();
         let peer = self.peer.clone();
-        let timeout = self.executor.sleep(CLEANUP_TIMEOUT);
+        let started_at = self.executor.monotonic_time();
+        let timeout = self
+            .executor
+            .sleep(self.app_state.runtime_config.get().cleanup_timeout);
         let pool = self.connection_pool.clone();
         let live_kit_client = self.app_state.live_kit_client.clone();
+        let executor = self.executor.clone();
 
         let span = info_span!("start server");
         self.executor.spawn_detached(
             async move {
                 tracing::info!("waiting for cleanup timeout");
                 timeout.await;
-                tracing::info!("cleanup timeout expired, retrieving stale rooms");
-                if let Some((room_ids, channel_ids)) = app_state
+                if stale_servers_are_live(server_id, &app_state).await {
+                    tracing::info!("stale servers are still live, leaving them to be swept");
+                    return;
+                }
+
+                // The rooms of stale servers' clients are kept until the rejoin window
+                // closes, so that calls survive the restart.
+                let elapsed = executor.monotonic_time().saturating_sub(started_at);
+                let rejoin_window = remaining_rejoin_window(server_id, elapsed, &app_state).await;
+                tracing::info!(?rejoin_window, "waiting for clients to rejoin their rooms");
+                executor.sleep(rejoin_window).await;
+                tracing::info!("rejoin window closed, retrieving stale rooms");
+                clean_up_stale_servers(
+                    server_id,
+                    &app_state,
+                    &peer,
+                    &pool,
+                    live_kit_client.as_deref(),
+                )
+                .await;
+
+                // Clients that were handed resume tokens before the restart keep their
+                // rooms for a while longer, in case they're slow to come back.
+                let environment = &app_state.config.zed_environment;
+                if app_state
                     .db
-                    .stale_server_resource_ids(&app_state.config.zed_environment, server_id)
+                    .stale_servers_have_resume_tokens(environment, server_id)
                     .await
                     .trace_err()
+                    .unwrap_or(false)
                 {
-                    tracing::info!(stale_room_count = room_ids.len(), "retrieved stale rooms");
-                    tracing::info!(
-                        stale_channel_buffer_count = channel_ids.len(),
-                        "retrieved stale channel buffers"
-                    );
+                    tracing::info!("waiting for resume tokens to be redeemed");
+                    executor.sleep(RESUME_TIMEOUT).await;
+                    app_state
+                        .db
+                        .delete_stale_resume_tokens(environment, server_id)
+                        .await
+                        .trace_err();
+                    clean_up_stale_servers(
+                        server_id,
+                        &app_state,
+                        &peer,
+                        &pool,
+                        live_kit_client.as_deref(),
+                    )
+                    .await;
+                }
+            }
+            .instrument(span),
+        );
 
-                    for channel_id in channel_ids {
-                        if let Some(refreshed_channel_buffer) = app_state
-                            .db
-                            .clear_stale_channel_buffer_collaborators(channel_id, server_id)
-                            .await
-                            .trace_err()
-                        {
-                            for connection_id in refreshed_channel_buffer.connection_ids {
-                                peer.send(
-                                    connection_id,
-                                    proto::UpdateChannelBufferCollaborators {
-                                        channel_id: channel_id.to_proto(),
-                                        collaborators: refreshed_channel_buffer
-                                            .collaborators
-                                            .clone(),
-                                    },
-                                )
-                                .trace_err();
+        let mut bus_messages = self.app_state.message_bus.subscribe(server_id).await?;
+        self.app_state
+            .message_bus
+            .broadcast(ServerEvent::ServerStarted { server_id });
+        let peer = self.peer.clone();
+        let pool = self.connection_pool.clone();
+        let message_bus = self.app_state.message_bus.clone();
+        let executor = self.executor.clone();
+        let mut teardown = self.teardown.subscribe();
+        self.executor.spawn_detached(
+            async move {
+                loop {
+                    futures::select_biased! {
+                        _ = teardown.changed().fuse() => return,
+                        message = bus_messages.next().fuse() => {
+                            match message {
+                                Some(BusMessage::Routed(message)) => {
+                                    // Routed requests wait for their response, which mustn't
+                                    // hold up the messages behind them.
+                                    let mut delivery =
+                                        peer.deliver_routed(message.receiver_id, message.envelope);
+                                    if let Some(result) = (&mut delivery).now_or_never() {
+                                        result.trace_err();
+                                    } else {
+                                        executor.spawn_detached(async move {
+                                            delivery.await.trace_err();
+                                        });
+                                    }
+                                }
+                                Some(BusMessage::Event(event)) => {
+                                    if event.sender_id() != server_id {
+                                        handle_server_event(
+                                            event,
+                                            &peer,
+                                            &pool,
+                                            message_bus.as_ref(),
+                                        );
+                                    }
+                                }
+                                None => return,
                             }
                         }
                     }
+                }
+            }
+            .instrument(info_span!("deliver routed messages")),
+        );
 
-                    for room_id in room_ids {
-                        let mut contacts_to_update = HashSet::default();
-                        let mut canceled_calls_to_user_ids = Vec::new();
-                        let mut live_kit_room = String::new();
-                        let mut delete_live_kit_room = false;
-
-                        if let Some(mut refreshed_room) = app_state
-                            .db
-                            .clear_stale_room_participants(room_id, server_id)
-                            .await
-                            .trace_err()
-                        {
-                            tracing::info!(
-                                room_id = room_id.0,
-                                new_participant_count = refreshed_room.room.participants.len(),
-                                "refreshed room"
-                            );
-                            room_updated(&refreshed_room.room, &peer);
-                            if let Some(channel_id) = refreshed_room.channel_id {
-                                channel_updated(
-                                    channel_id,
-                                    &refreshed_room.room,
-                                    &refreshed_room.channel_members,
-                                    &peer,
-                                    &*pool.lock(),
-                                );
-                            }
-                            contacts_to_update
-                                .extend(refreshed_room.stale_participant_user_ids.iter().copied());
-                            contacts_to_update
-                                .extend(refreshed_room.canceled_calls_to_user_ids.iter().copied());
-                            canceled_calls_to_user_ids =
-                                mem::take(&mut refreshed_room.canceled_calls_to_user_ids);
-                            live_kit_room = mem::take(&mut refreshed_room.room.live_kit_room);
-                            delete_live_kit_room = refreshed_room.room.participants.is_empty();
-                        }
+        let app_state = self.app_state.clone();
+        let peer = self.peer.clone();
+        let pool = self.connection_pool.clone();
+        let live_kit_client = self.app_state.live_kit_client.clone();
+        let executor = self.executor.clone();
+        let mut teardown = self.teardown.subscribe();
+        self.executor.spawn_detached(
+            async move {
+                let mut previously_stale_server_ids = HashSet::default();
+                loop {
+                    let sweep_interval = app_state.runtime_config.get().orphan_sweep_interval;
+                    futures::select_biased! {
+                        _ = teardown.changed().fuse() => return,
+                        _ = executor.sleep(sweep_interval).fuse() => {}
+                    }
+                    sweep_orphans(
+                        server_id,
+                        &app_state,
+                        &peer,
+                        &pool,
+                        live_kit_client.as_deref(),
+                        &mut previously_stale_server_ids,
+                    )
+                    .await;
+                }
+            }
+            .instrument(info_span!("sweep orphans")),
+        );
 
-                        {
-                            let pool = pool.lock();
-                            for canceled_user_id in canceled_calls_to_user_ids {
-                                for connection_id in pool.user_connection_ids(canceled_user_id) {
-                                    peer.send(
-                                        connection_id,
-                                        proto::CallCanceled {
-                                            room_id: room_id.to_proto(),
-                                        },
-                                    )
-                                    .trace_err();
-                                }
-                            }
-                        }
+        // Time-boxed rooms outlive the server that created them, so their timers are
+        // picked back up from the database. Ending a room more than once is harmless.
+        for (room_id, ends_at) in self.app_state.db.time_boxed_rooms().await? {
+            self.room_timers.schedule(room_id, ends_at);
+        }
 
-                        for user_id in contacts_to_update {
-                            let busy = app_state.db.is_user_busy(user_id).await.trace_err();
-                            let contacts = app_state.db.get_contacts(user_id).await.trace_err();
-                            if let Some((busy, contacts)) = busy.zip(contacts) {
-                                let pool = pool.lock();
-                                let updated_contact = contact_for_user(user_id, busy, &pool);
-                                for contact in contacts {
-                                    if let db::Contact::Accepted {
-                                        user_id: contact_user_id,
-                                        ..
-                                    } = contact
-                                    {
-                                        for contact_conn_id in
-                                            pool.user_connection_ids(contact_user_id)
-                                        {
-                                            peer.send(
-                                                contact_conn_id,
-                                                proto::UpdateContacts {
-                                                    contacts: vec![updated_contact.clone()],
-                                                    remove_contacts: Default::default(),
-                                                    incoming_requests: Default::default(),
-                                                    remove_incoming_requests: Default::default(),
-                                                    outgoing_requests: Default::default(),
-                                                    remove_outgoing_requests: Default::default(),
-                                                },
-                                            )
-                                            .trace_err();
-                                        }
-                                    }
-                                }
-                            }
-                        }
+        let app_state = self.app_state.clone();
+        let peer = self.peer.clone();
+        let pool = self.connection_pool.clone();
+        let live_kit_client = self.app_state.live_kit_client.clone();
+        let room_timers = self.room_timers.clone();
+        let executor = self.executor.clone();
+        let mut teardown = self.teardown.subscribe();
+        self.executor.spawn_detached(
+            async move {
+                loop {
+                    futures::select_biased! {
+                        _ = teardown.changed().fuse() => return,
+                        _ = executor.sleep(ROOM_TIMER_TICK).fuse() => {}
+                    }
+                    for event in room_timers.tick(executor.monotonic_time()) {
+                        room_timer_fired(
+                            event,
+                            &app_state,
+                            &peer,
+                            &pool,
+                            live_kit_client.as_deref(),
+                        )
+                        .await;
+                    }
+                }
+            }
+            .instrument(info_span!("room timers")),
+        );
 
-                        if let Some(live_kit) = live_kit_client.as_ref() {
-                            if delete_live_kit_room {
-                                live_kit.delete_room(live_kit_room).await.trace_err();
-                            }
-                        }
+        let app_state = self.app_state.clone();
+        let peer = self.peer.clone();
+        let executor = self.executor.clone();
+        let mut teardown = self.teardown.subscribe();
+        self.executor.spawn_detached(
+            async move {
+                loop {
+                    futures::select_biased! {
+                        _ = teardown.changed().fuse() => return,
+                        _ = executor.sleep(RUNTIME_CONFIG_RELOAD_INTERVAL).fuse() => {}
                     }
+                    reload_runtime_config(&app_state, &peer).await.trace_err();
                 }
+            }
+            .instrument(info_span!("reload runtime config")),
+        );
 
-                app_state
-                    .db
-                    .delete_stale_servers(&app_state.config.zed_environment, server_id)
-                    .await
-                    .trace_err();
+        let app_state = self.app_state.clone();
+        let overload = self.overload.clone();
+        let executor = self.executor.clone();
+        let mut teardown = self.teardown.subscribe();
+        self.executor.spawn_detached(
+            async move {
+                loop {
+                    let sleep = executor.measure(executor.sleep(OVERLOAD_CHECK_INTERVAL));
+                    let slept = futures::select_biased! {
+                        _ = teardown.changed().fuse() => return,
+                        (_, slept) = sleep.fuse() => slept,
+                    };
+                    let event_loop_lag = slept.saturating_sub(OVERLOAD_CHECK_INTERVAL);
+                    let (_, db_latency) = executor
+                        .measure(async { app_state.db.ping().await.trace_err() })
+                        .await;
+                    let level = app_state.runtime_config.get().overload_policy.level(
+                        overload.measured(),
+                        event_loop_lag,
+                        db_latency,
+                    );
+                    let previous_level = overload.set_measured(level);
+                    METRIC_OVERLOAD_LEVEL.set(level as i64);
+                    if level != previous_level {
+                        tracing::warn!(
+                            ?level,
+                            ?previous_level,
+                            ?event_loop_lag,
+                            ?db_latency,
+                            "overload level changed"
+                        );
+                    }
+                }
             }
-            .instrument(span),
+            .instrument(info_span!("monitor overload")),
         );
         Ok(())
     }
 
+    /// Applies the runtime config overrides currently stored in the database, rather
+    /// than waiting for them to be reloaded periodically.
+    pub async fn reload_runtime_config(&self) -> Result<()> {
+        reload_runtime_config(&self.app_state, &self.peer).await
+    }
+
+    /// Removes everyone from the room, which deletes it. Returns whether there was
+    /// such a room.
+    pub async fn terminate_room(&self, room_id: RoomId) -> Result<bool> {
+        let Some(ended_room) = self.app_state.db.terminate_room(room_id).await? else {
+            return Ok(false);
+        };
+        tracing::info!(room_id = room_id.0, "room terminated");
+        room_ended(
+            room_id,
+            ended_room.into_inner(),
+            &self.app_state,
+            &self.peer,
+            &self.connection_pool,
+            self.app_state.live_kit_client.as_deref(),
+        )
+        .await;
+        Ok(true)
+    }
+
+    /// Closes one of this server's connections, which signs it out as if the client
+    /// had gone away.
+    pub fn disconnect(&self, connection_id: ConnectionId) -> Result<()> {
+        if self
+            .connection_pool
+            .lock()
+            .connection(connection_id)
+            .is_none()
+        {
+            Err(anyhow!("no such connection"))?;
+        }
+        tracing::info!(%connection_id, "disconnecting connection");
+        self.peer.disconnect(connection_id);
+        Ok(())
+    }
+
+    /// Bans the user and closes their connections to every server in the deployment.
+    /// Banned users can't sign in, so their clients can't reconnect.
+    pub async fn ban_user(&self, user_id: UserId) -> Result<()> {
+        self.app_state.db.set_user_banned(user_id, true).await?;
+        tracing::info!(%user_id, "banned user");
+        self.app_state
+            .message_bus
+            .broadcast(ServerEvent::UserBanned {
+                server_id: *self.id.lock(),
+                user_id,
+            });
+        disconnect_user(user_id, &self.peer, &self.connection_pool.lock());
+        Ok(())
+    }
+
     pub fn teardown(&self) {
         self.peer.teardown();
         self.connection_pool.lock().reset();
         let _ = self.teardown.send(());
     }
 
+    /// Stops accepting connections and asks every connected client to reconnect to
+    /// another server, then tears down once they've all left and their rooms have moved
+    /// with them, or after [`DRAIN_TIMEOUT`]. If the server is already draining, this
+    /// waits for that drain to finish.
+    ///
+    /// Clients are sent to `reconnect_url` if one is given, and those in rooms are
+    /// handed resume tokens so their rooms survive until they rejoin.
+    pub async fn drain(&self, reconnect_url: Option<String>) {
+        if self.draining.swap(true, SeqCst) {
+            self.wait_until_drained().await;
+            return;
+        }
+
+        let server_id = *self.id.lock();
+        tracing::info!(%server_id, "draining server");
+        self.send_restarting(reconnect_url).await;
+
+        let deadline = self.executor.monotonic_time() + DRAIN_TIMEOUT;
+        loop {
+            let connection_count = self.connection_pool.lock().connection_ids().count();
+            let room_count = self
+                .app_state
+                .db
+                .room_ids_by_connection(server_id)
+                .await
+                .trace_err()
+                .map_or(0, |room_ids| room_ids.len());
+            if connection_count == 0 && room_count == 0 {
+                tracing::info!(%server_id, "server drained");
+                break;
+            }
+            if self.executor.monotonic_time() >= deadline {
+                tracing::warn!(
+                    %server_id,
+                    connection_count,
+                    room_count,
+                    "timed out draining server"
+                );
+                break;
+            }
+            self.executor.sleep(DRAIN_POLL_INTERVAL).await;
+        }
+
+        self.teardown();
+        self.drained.send_replace(true);
+    }
+
+    /// Tells every connected client that this server is restarting, handing those in
+    /// rooms a resume token.
+    pub(crate) async fn send_restarting(&self, reconnect_url: Option<String>) {
+        let server_id = *self.id.lock();
+        let mut resume_tokens = self
+            .app_state
+            .db
+            .create_resume_tokens(server_id)
+            .await
+            .trace_err()
+            .unwrap_or_default();
+        let connection_ids = self
+            .connection_pool
+            .lock()
+            .connection_ids()
+            .collect::<Vec<_>>();
+        tracing::info!(
+            %server_id,
+            connection_count = connection_ids.len(),
+            resume_token_count = resume_tokens.len(),
+            "sending restart to clients"
+        );
+        for connection_id in connection_ids {
+            self.peer
+                .send(
+                    connection_id,
+                    proto::ServerRestarting {
+                        reconnect_url: reconnect_url.clone(),
+                        resume_token: resume_tokens.remove(&connection_id),
+                    },
+                )
+                .trace_err();
+        }
+    }
+
+    /// The quota on the user's connections, if they already have as many as it allows
+    /// across all the live servers.
+    pub async fn reached_connection_quota(&self, user_id: UserId) -> Result<Option<usize>> {
+        let quota = self.connection_quota();
+        let server_ids = self.app_state.message_bus.live_server_ids().await?;
+        let connection_count = self
+            .app_state
+            .db
+            .user_connection_count(user_id, &server_ids)
+            .await?;
+        Ok(quotas::reached(quota, connection_count).then_some(quota))
+    }
+
+    /// Records a connection the user opened, failing if they already have as many as
+    /// the quota allows. The quota is checked before upgrading to a connection too, but
+    /// other connections may have been opened in the meantime.
+    async fn record_user_connection(
+        &self,
+        user_id: UserId,
+        connection_id: ConnectionId,
+    ) -> Result<()> {
+        let server_ids = self.app_state.message_bus.live_server_ids().await?;
+        self.app_state
+            .db
+            .add_user_connection(user_id, connection_id, &server_ids, self.connection_quota())
+            .await
+    }
+
+    fn connection_quota(&self) -> usize {
+        self.app_state
+            .runtime_config
+            .get()
+            .quota_policy
+            .max_connections_per_user
+    }
+
+    /// Whether the server has stopped accepting connections because it's draining.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(SeqCst)
+    }
+
+    /// Waits until a drain, however it was started, has finished.
+    pub async fn wait_until_drained(&self) {
+        let mut drained = self.drained.subscribe();
+        while !*drained.borrow_and_update() {
+            if drained.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Holds the server at the given overload level, regardless of how busy it is,
+    /// until this is called again with `None`.
+    #[cfg(test)]
+    pub fn simulate_overload(&self, level: Option<OverloadLevel>) {
+        self.overload.simulate(level);
+    }
+
     #[cfg(test)]
     pub fn reset(&self, id: ServerId) {
         self.teardown();
+        self.draining.store(false, SeqCst);
+        self.drained.send_replace(false);
         *self.id.lock() = id;
         self.peer.reset(id.0 as u32);
     }
@@ -510,7 +1084,28 @@ impl Server {
         Fut: 'static + Send + Future<Output = Result<()>>,
         M: EnvelopedMessage,
     {
-        self.add_handler(move |envelope, session| handler(envelope.payload, session));
+        let shed_at = OverloadLevel::shedding::<M>();
+        let allowed_for_guests = guest::is_allowed(M::NAME);
+        self.add_handler(move |envelope, session| {
+            let shed = session.shedding(shed_at, M::NAME).is_some();
+            let forbidden = session.guest && !allowed_for_guests;
+            let peer = session.peer.clone();
+            let connection_id = session.connection_id;
+            let future = (!shed && !forbidden).then(|| handler(envelope.payload, session));
+            async move {
+                let Some(future) = future else {
+                    return Ok(());
+                };
+                let result = future.await;
+                // Messages have no response to report an error in, so when the database
+                // fails to take one, the sender is disconnected rather than left to think
+                // it went through. Clients resend their state when they reconnect.
+                if let Err(Error::Database(_)) = &result {
+                    peer.disconnect(connection_id);
+                }
+                result
+            }
+        });
         self
     }
 
@@ -521,11 +1116,21 @@ impl Server {
         M: RequestMessage,
     {
         let handler = Arc::new(handler);
+        let shed_at = OverloadLevel::shedding::<M>();
+        let allowed_for_guests = guest::is_allowed(M::NAME);
         self.add_handler(move |envelope, session| {
             let receipt = envelope.receipt();
             let handler = handler.clone();
             async move {
                 let peer = session.peer.clone();
+                if session.guest && !allowed_for_guests {
+                    peer.respond_with_error(receipt, guest::forbidden_error(M::NAME))?;
+                    return Ok(());
+                }
+                if let Some(level) = session.shedding(shed_at, M::NAME) {
+                    peer.respond_with_error(receipt, overload::overloaded_error(level, M::NAME))?;
+                    return Ok(());
+                }
                 let responded = Arc::new(AtomicBool::default());
                 let response = Response {
                     peer: peer.clone(),
@@ -579,28 +1184,68 @@ impl Server {
                 });
 
             tracing::info!(%user_id, %login, %connection_id, %address, "connection opened");
-            this.peer.send(connection_id, proto::Hello { peer_id: Some(connection_id.into()) })?;
+
+            if let Err(error) = this.record_user_connection(user_id, connection_id).await {
+                this.peer.disconnect(connection_id);
+                return Err(error);
+            }
+
+            this.peer.send(connection_id, proto::Hello {
+                peer_id: Some(connection_id.into()),
+                capabilities: Some(this.capabilities()),
+                protocol_version: rpc::PROTOCOL_VERSION,
+                min_protocol_version: rpc::MIN_PROTOCOL_VERSION,
+                collation_locale: this.app_state.config.collation_locale.clone(),
+            })?;
             tracing::info!(%user_id, %login, %connection_id, %address, "sent hello message");
 
             if let Some(send_connection_id) = send_connection_id.take() {
                 let _ = send_connection_id.send(connection_id);
             }
 
-            if !user.connected_once {
+            let guest = this.app_state.db.get_guest(user_id).await?;
+            if !user.connected_once && guest.is_none() {
                 this.peer.send(connection_id, proto::ShowContacts {})?;
                 this.app_state.db.set_user_connected_once(user_id, true).await?;
             }
 
-            let (contacts, channels_for_user, channel_invites) = future::try_join3(
+            let (contacts, contact_notes, channels_for_user, channel_invites) = future::try_join4(
                 this.app_state.db.get_contacts(user_id),
+                this.app_state.db.get_contact_notes(user_id),
                 this.app_state.db.get_channels_for_user(user_id),
                 this.app_state.db.get_channel_invites_for_user(user_id),
             ).await?;
+            let busy_contact_ids = contacts
+                .iter()
+                .filter_map(|contact| match contact {
+                    db::Contact::Accepted { user_id, busy: true } => Some(*user_id),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let call_connections = this.app_state.db.call_connections(&busy_contact_ids).await?;
+            let (location_shared_with, location_shared_by, blocked_user_ids) = future::try_join3(
+                this.app_state.db.location_shared_with(user_id),
+                this.app_state.db.location_shared_by(user_id),
+                this.app_state.db.get_blocked_users(user_id),
+            ).await?;
+            let contacts = this.sort_contacts(contacts).await?;
 
             {
                 let mut pool = this.connection_pool.lock();
                 pool.add_connection(connection_id, user_id, user.admin);
-                this.peer.send(connection_id, build_initial_contacts_update(contacts, &pool))?;
+                this.app_state.message_bus.broadcast(ServerEvent::ConnectionOpened {
+                    connection_id,
+                    user_id,
+                });
+                this.peer.send(connection_id, build_initial_contacts_update(
+                    contacts,
+                    contact_notes,
+                    &call_connections,
+                    &location_shared_with,
+                    &location_shared_by,
+                    &blocked_user_ids,
+                    &pool,
+                ))?;
                 this.peer.send(connection_id, build_update_user_channels(&channels_for_user))?;
                 this.peer.send(connection_id, build_channels_update(
                     channels_for_user,
@@ -608,6 +1253,18 @@ impl Server {
                 ))?;
             }
 
+            // The server may have started draining while the connection was being set up,
+            // after the clients it knew of were told to move.
+            if this.is_draining() {
+                this.peer.send(
+                    connection_id,
+                    proto::ServerRestarting {
+                        reconnect_url: None,
+                        resume_token: None,
+                    },
+                )?;
+            }
+
             if let Some(incoming_call) = this.app_state.db.incoming_call_for_user(user_id).await? {
                 this.peer.send(connection_id, incoming_call)?;
             }
@@ -619,6 +1276,18 @@ impl Server {
                 peer: this.peer.clone(),
                 connection_pool: this.connection_pool.clone(),
                 live_kit_client: this.app_state.live_kit_client.clone(),
+                ice_config: this.app_state.ice_config.clone(),
+                ice_servers_key: this.app_state.ice_servers_key.clone(),
+                runtime_config: this.app_state.runtime_config.clone(),
+                overload: this.overload.clone(),
+                room_timers: this.room_timers.clone(),
+                audio_routes: this.audio_routes.clone(),
+                webhooks: this.app_state.webhooks.clone(),
+                message_bus: this.app_state.message_bus.clone(),
+                server: Arc::downgrade(&this),
+                guest: guest.is_some(),
+                avatar_url_prefix: this.app_state.config.avatar_url_prefix.as_deref().map(Arc::from),
+                collation: this.app_state.config.collation(),
                 _executor: executor.clone()
             };
             update_user_contacts(user_id, &session).await?;
@@ -626,6 +1295,16 @@ impl Server {
             let handle_io = handle_io.fuse();
             futures::pin_mut!(handle_io);
 
+            // Guests are signed out once their invitation expires.
+            let guest_expired = match &guest {
+                Some(guest) => {
+                    let remaining = guest.expires_at.assume_utc() - OffsetDateTime::now_utc();
+                    executor.sleep(remaining.try_into().unwrap_or_default()).boxed()
+                }
+                None => future::pending().boxed(),
+            }.fuse();
+            futures::pin_mut!(guest_expired);
+
             // Handlers for foreground messages are pushed into the following `FuturesUnordered`.
             // This prevents deadlocks when e.g., client A performs a request to client B and
             // client B performs a request to client A. If both clients stop processing further
@@ -651,6 +1330,10 @@ impl Server {
                         }
                         break;
                     }
+                    _ = guest_expired => {
+                        tracing::info!(%user_id, %login, %connection_id, %address, "guest invitation expired");
+                        break;
+                    }
                     _ = foreground_message_handlers.next() => {}
                     next_message = next_message => {
                         let (permit, message) = next_message;
@@ -701,7 +1384,7 @@ impl Server {
         if let Some(user) = self.app_state.db.get_user_by_id(inviter_id).await? {
             if let Some(code) = &user.invite_code {
                 let pool = self.connection_pool.lock();
-                let invitee_contact = contact_for_user(invitee_id, false, &pool);
+                let invitee_contact = contact_for_user(invitee_id, false, None, &pool);
                 for connection_id in pool.user_connection_ids(inviter_id) {
                     self.peer.send(
                         connection_id,
@@ -744,6 +1427,75 @@ impl Server {
         Ok(())
     }
 
+    /// The features that clients can use on this server, which they are told
+    /// about when they connect.
+    pub fn capabilities(&self) -> proto::ServerCapabilities {
+        proto::ServerCapabilities {
+            calls_audio: self.app_state.live_kit_client.is_some(),
+            channel_links: !self.app_state.config.is_self_hosted(),
+            relayed_audio: true,
+        }
+    }
+
+    /// Orders contacts and contact requests by their users' logins, the way the server
+    /// tells clients to order them.
+    async fn sort_contacts(&self, mut contacts: Vec<db::Contact>) -> Result<Vec<db::Contact>> {
+        let logins = self
+            .app_state
+            .db
+            .get_users_by_ids(contacts.iter().map(|contact| contact.user_id()).collect())
+            .await?
+            .into_iter()
+            .map(|user| (user.id, user.github_login))
+            .collect::<HashMap<_, _>>();
+        let collation = self.app_state.config.collation();
+        let login = |contact: &db::Contact| {
+            logins
+                .get(&contact.user_id())
+                .map_or("", |login| login.as_str())
+        };
+        contacts.sort_by(|a, b| collation.compare(login(a), login(b)));
+        Ok(contacts)
+    }
+
+    /// Describes every connection to this server, along with the rooms it is
+    /// participating in, the number of messages still waiting to be sent to it,
+    /// and how many of its messages were rate limited.
+    pub async fn connection_stats(&self) -> Result<Vec<ConnectionStats>> {
+        let server_id = *self.id.lock();
+        let connections = {
+            let pool = self.connection_pool.lock();
+            pool.connection_ids()
+                .filter_map(|connection_id| {
+                    let connection = pool.connection(connection_id)?;
+                    Some((connection_id, connection.user_id, connection.admin))
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut room_ids = self.app_state.db.room_ids_by_connection(server_id).await?;
+        Ok(connections
+            .into_iter()
+            .map(|(connection_id, user_id, admin)| {
+                let rate_limit_stats = self
+                    .peer
+                    .rate_limit_stats(connection_id)
+                    .unwrap_or_default();
+                ConnectionStats {
+                    connection_id,
+                    user_id,
+                    admin,
+                    room_ids: room_ids.remove(&connection_id).unwrap_or_default(),
+                    queued_message_count: self
+                        .peer
+                        .queued_message_count(connection_id)
+                        .unwrap_or(0),
+                    rejected_request_count: rate_limit_stats.rejected_requests,
+                    delayed_message_count: rate_limit_stats.delayed_messages,
+                }
+            })
+            .collect())
+    }
+
     pub async fn snapshot<'a>(self: &'a Arc<Self>) -> ServerSnapshot<'a> {
         ServerSnapshot {
             connection_pool: ConnectionPoolGuard {
@@ -751,30 +1503,556 @@ impl Server {
                 _not_send: PhantomData,
             },
             peer: &self.peer,
+            overload_level: self.overload.level(),
         }
     }
 }
 
-impl<'a> Deref for ConnectionPoolGuard<'a> {
-    type Target = ConnectionPool;
-
-    fn deref(&self) -> &Self::Target {
-        &*self.guard
+/// Keeps track of the connections of the other servers in the deployment, so that
+/// messages for their users are routed to them, and closes the connections of users
+/// that they ban.
+fn handle_server_event(
+    event: ServerEvent,
+    peer: &Peer,
+    pool: &parking_lot::Mutex<ConnectionPool>,
+    message_bus: &dyn MessageBus,
+) {
+    let mut pool = pool.lock();
+    match event {
+        ServerEvent::ServerStarted { .. } => {
+            // The new server doesn't know about the connections that were opened before
+            // it started, so they're announced again.
+            for connection_id in pool.connection_ids().collect::<Vec<_>>() {
+                if let Some(connection) = pool.connection(connection_id) {
+                    message_bus.broadcast(ServerEvent::ConnectionOpened {
+                        connection_id,
+                        user_id: connection.user_id,
+                    });
+                }
+            }
+        }
+        ServerEvent::ConnectionOpened {
+            connection_id,
+            user_id,
+        } => pool.add_remote_connection(connection_id, user_id),
+        ServerEvent::ConnectionClosed { connection_id } => {
+            pool.remove_remote_connection(connection_id)
+        }
+        ServerEvent::UserBanned { user_id, .. } => disconnect_user(user_id, peer, &pool),
     }
 }
 
-impl<'a> DerefMut for ConnectionPoolGuard<'a> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut *self.guard
+/// Closes the user's connections to this server.
+fn disconnect_user(user_id: UserId, peer: &Peer, pool: &ConnectionPool) {
+    let connection_ids = pool
+        .user_connection_ids(user_id)
+        .filter(|connection_id| pool.connection(*connection_id).is_some())
+        .collect::<Vec<_>>();
+    for connection_id in connection_ids {
+        peer.disconnect(connection_id);
     }
 }
 
-impl<'a> Drop for ConnectionPoolGuard<'a> {
-    fn drop(&mut self) {
-        #[cfg(test)]
-        self.check_invariants();
+/// Refreshes the rooms and channel buffers that still refer to connections on servers
+/// other than the given one, then deletes those servers.
+async fn clean_up_stale_servers(
+    server_id: ServerId,
+    app_state: &AppState,
+    peer: &Peer,
+    pool: &parking_lot::Mutex<ConnectionPool>,
+    live_kit_client: Option<&dyn live_kit_server::api::Client>,
+) {
+    // The stale servers' connections are gone, along with the responses to any requests
+    // that were routed to them.
+    if let Some(stale_server_ids) = app_state
+        .db
+        .stale_server_ids(&app_state.config.zed_environment, server_id)
+        .await
+        .trace_err()
+    {
+        let mut pool = pool.lock();
+        for stale_server_id in stale_server_ids {
+            pool.remove_remote_server(stale_server_id.0 as u32);
+            peer.fail_remote_requests(stale_server_id.0 as u32);
+        }
     }
-}
+
+    if let Some((room_ids, channel_ids)) = app_state
+        .db
+        .stale_server_resource_ids(&app_state.config.zed_environment, server_id)
+        .await
+        .trace_err()
+    {
+        tracing::info!(stale_room_count = room_ids.len(), "retrieved stale rooms");
+        tracing::info!(
+            stale_channel_buffer_count = channel_ids.len(),
+            "retrieved stale channel buffers"
+        );
+
+        for channel_id in channel_ids {
+            if let Some(refreshed_channel_buffer) = app_state
+                .db
+                .clear_stale_channel_buffer_collaborators(channel_id, server_id)
+                .await
+                .trace_err()
+            {
+                for connection_id in refreshed_channel_buffer.connection_ids {
+                    peer.send(
+                        connection_id,
+                        proto::UpdateChannelBufferCollaborators {
+                            channel_id: channel_id.to_proto(),
+                            collaborators: refreshed_channel_buffer.collaborators.clone(),
+                        },
+                    )
+                    .trace_err();
+                }
+            }
+        }
+
+        for room_id in room_ids {
+            let mut contacts_to_update = HashSet::default();
+            let mut canceled_calls_to_user_ids = Vec::new();
+            let mut live_kit_room = String::new();
+            let mut delete_live_kit_room = false;
+            let mut notifications = Vec::new();
+
+            if let Some(mut refreshed_room) = app_state
+                .db
+                .clear_stale_room_participants(room_id, server_id)
+                .await
+                .trace_err()
+            {
+                tracing::info!(
+                    room_id = room_id.0,
+                    new_participant_count = refreshed_room.room.participants.len(),
+                    "refreshed room"
+                );
+                room_updated(&refreshed_room.room, &peer);
+                for user_id in &refreshed_room.stale_participant_user_ids {
+                    app_state.webhooks.send(WebhookEvent::UserLeft {
+                        room_id,
+                        channel_id: refreshed_room.channel_id,
+                        user_id: *user_id,
+                    });
+                }
+                if refreshed_room.room.participants.is_empty() {
+                    app_state.webhooks.send(WebhookEvent::RoomEnded {
+                        room_id,
+                        channel_id: refreshed_room.channel_id,
+                    });
+                }
+                if let Some(channel_id) = refreshed_room.channel_id {
+                    channel_updated(
+                        channel_id,
+                        &refreshed_room.room,
+                        &refreshed_room.channel_members,
+                        &peer,
+                        &*pool.lock(),
+                    );
+                }
+                contacts_to_update
+                    .extend(refreshed_room.stale_participant_user_ids.iter().copied());
+                contacts_to_update
+                    .extend(refreshed_room.canceled_calls_to_user_ids.iter().copied());
+                canceled_calls_to_user_ids =
+                    mem::take(&mut refreshed_room.canceled_calls_to_user_ids);
+                live_kit_room = mem::take(&mut refreshed_room.room.live_kit_room);
+                delete_live_kit_room = refreshed_room.room.participants.is_empty();
+                notifications = mem::take(&mut refreshed_room.notifications);
+            }
+
+            {
+                let pool = pool.lock();
+                for canceled_user_id in canceled_calls_to_user_ids {
+                    for connection_id in pool.user_connection_ids(canceled_user_id) {
+                        peer.send(
+                            connection_id,
+                            proto::CallCanceled {
+                                room_id: room_id.to_proto(),
+                            },
+                        )
+                        .trace_err();
+                    }
+                }
+                send_notifications(&pool, &peer, notifications);
+            }
+
+            for user_id in contacts_to_update {
+                refresh_contacts(user_id, app_state, peer, pool).await;
+            }
+
+            if let Some(live_kit) = live_kit_client.as_ref() {
+                if delete_live_kit_room {
+                    live_kit.delete_room(live_kit_room).await.trace_err();
+                }
+            }
+        }
+    }
+
+    app_state
+        .db
+        .delete_stale_servers(&app_state.config.zed_environment, server_id)
+        .await
+        .trace_err();
+}
+
+/// Sends the user's contacts their current status, for when it changed without the
+/// user's own session being involved.
+async fn refresh_contacts(
+    user_id: UserId,
+    app_state: &AppState,
+    peer: &Peer,
+    pool: &parking_lot::Mutex<ConnectionPool>,
+) {
+    let busy = app_state.db.is_user_busy(user_id).await.trace_err();
+    let call_connections = app_state.db.call_connections(&[user_id]).await.trace_err();
+    let contacts = app_state.db.get_contacts(user_id).await.trace_err();
+    let location_shared_with = app_state.db.location_shared_with(user_id).await.trace_err();
+    if let Some((((busy, mut call_connections), contacts), location_shared_with)) = busy
+        .zip(call_connections)
+        .zip(contacts)
+        .zip(location_shared_with)
+    {
+        let pool = pool.lock();
+        let updated_contact =
+            contact_for_user(user_id, busy, call_connections.remove(&user_id), &pool);
+        for contact in contacts {
+            if let db::Contact::Accepted {
+                user_id: contact_user_id,
+                ..
+            } = contact
+            {
+                let updated_contact = contact_for_recipient(
+                    &updated_contact,
+                    contact_user_id,
+                    &location_shared_with,
+                    pool.user_working_location(user_id),
+                );
+                for contact_conn_id in pool.user_connection_ids(contact_user_id) {
+                    peer.send(
+                        contact_conn_id,
+                        proto::UpdateContacts {
+                            contacts: vec![updated_contact.clone()],
+                            remove_contacts: Default::default(),
+                            incoming_requests: Default::default(),
+                            remove_incoming_requests: Default::default(),
+                            outgoing_requests: Default::default(),
+                            remove_outgoing_requests: Default::default(),
+                            contact_notes: Default::default(),
+                            location_sharing: Default::default(),
+                            blocked_users: Default::default(),
+                        },
+                    )
+                    .trace_err();
+                }
+            }
+        }
+    }
+}
+
+/// Warns a time-boxed room's participants that it's about to end, or ends it, unless
+/// it was extended in the meantime.
+async fn room_timer_fired(
+    event: RoomTimerEvent,
+    app_state: &AppState,
+    peer: &Peer,
+    pool: &parking_lot::Mutex<ConnectionPool>,
+    live_kit_client: Option<&dyn live_kit_server::api::Client>,
+) {
+    match event {
+        RoomTimerEvent::Countdown {
+            room_id,
+            ends_at,
+            remaining,
+        } => {
+            let Some(connection_ids) = app_state
+                .db
+                .time_boxed_room_connection_ids(room_id, ends_at)
+                .await
+                .trace_err()
+            else {
+                return;
+            };
+            for connection_id in connection_ids {
+                peer.send(
+                    connection_id,
+                    proto::RoomCountdown {
+                        room_id: room_id.to_proto(),
+                        remaining_secs: remaining.as_secs(),
+                    },
+                )
+                .trace_err();
+            }
+        }
+        RoomTimerEvent::End { room_id, ends_at } => {
+            let Some(ended_room) = app_state.db.end_room(room_id, ends_at).await.trace_err() else {
+                return;
+            };
+            let Some(ended_room) = ended_room.map(|ended_room| ended_room.into_inner()) else {
+                return;
+            };
+            tracing::info!(room_id = room_id.0, "time-boxed room ended");
+            room_ended(room_id, ended_room, app_state, peer, pool, live_kit_client).await;
+        }
+    }
+}
+
+/// Tells the participants of a room that everyone was removed from that it ended, and
+/// everyone else affected that they left it.
+async fn room_ended(
+    room_id: RoomId,
+    ended_room: db::EndedRoom,
+    app_state: &AppState,
+    peer: &Peer,
+    pool: &parking_lot::Mutex<ConnectionPool>,
+    live_kit_client: Option<&dyn live_kit_server::api::Client>,
+) {
+    for user_id in &ended_room.participant_user_ids {
+        app_state.webhooks.send(WebhookEvent::UserLeft {
+            room_id,
+            channel_id: ended_room.channel_id,
+            user_id: *user_id,
+        });
+    }
+    app_state.webhooks.send(WebhookEvent::RoomEnded {
+        room_id,
+        channel_id: ended_room.channel_id,
+    });
+
+    {
+        let pool = pool.lock();
+        for connection_id in &ended_room.connection_ids {
+            peer.send(
+                *connection_id,
+                proto::RoomEnded {
+                    room_id: room_id.to_proto(),
+                },
+            )
+            .trace_err();
+        }
+        for canceled_user_id in &ended_room.canceled_calls_to_user_ids {
+            for connection_id in pool.user_connection_ids(*canceled_user_id) {
+                peer.send(
+                    connection_id,
+                    proto::CallCanceled {
+                        room_id: room_id.to_proto(),
+                    },
+                )
+                .trace_err();
+            }
+        }
+        if let Some(channel_id) = ended_room.channel_id {
+            channel_updated(
+                channel_id,
+                &proto::Room::default(),
+                &ended_room.channel_members,
+                peer,
+                &pool,
+            );
+        }
+        send_notifications(&pool, peer, ended_room.notifications);
+    }
+
+    for user_id in ended_room
+        .participant_user_ids
+        .into_iter()
+        .chain(ended_room.canceled_calls_to_user_ids)
+    {
+        refresh_contacts(user_id, app_state, peer, pool).await;
+    }
+
+    if let Some(live_kit) = live_kit_client {
+        live_kit
+            .delete_room(ended_room.live_kit_room)
+            .await
+            .trace_err();
+    }
+}
+
+/// Reads the runtime config overrides from the database, and applies the resulting
+/// config to this server if it changed. Only new values are used from then on, so
+/// rooms and connections carry on undisturbed.
+async fn reload_runtime_config(app_state: &AppState, peer: &Peer) -> Result<()> {
+    let overrides = app_state
+        .db
+        .get_runtime_config_overrides(&app_state.config.zed_environment)
+        .await?;
+    let runtime_config = RuntimeConfig::new(&app_state.config, &overrides)?;
+    if *app_state.runtime_config.get() == runtime_config {
+        return Ok(());
+    }
+
+    peer.set_backlog_policy(runtime_config.backlog_policy);
+    peer.set_rate_limit_policy(runtime_config.rate_limit_policy.clone());
+    record_runtime_config(&runtime_config);
+    let new_values = runtime_config.values();
+    let old_values = app_state.runtime_config.replace(runtime_config).values();
+    for (name, new_value) in &new_values {
+        let old_value = &old_values[name];
+        if old_value != new_value {
+            tracing::info!(%name, %old_value, %new_value, "runtime config changed");
+        }
+    }
+    Ok(())
+}
+
+fn record_runtime_config(runtime_config: &RuntimeConfig) {
+    for (name, value) in runtime_config.values() {
+        if let Ok(value) = value.parse() {
+            METRIC_RUNTIME_CONFIG.with_label_values(&[name]).set(value);
+        }
+    }
+}
+
+/// How much longer the clients of stale servers have to rejoin their rooms, given how
+/// long ago this server started. The window is stored with the stale servers, so
+/// restarting again during it doesn't extend it.
+async fn remaining_rejoin_window(
+    server_id: ServerId,
+    elapsed: Duration,
+    app_state: &AppState,
+) -> Duration {
+    let remaining = REJOIN_WINDOW.saturating_sub(elapsed);
+    let now = OffsetDateTime::now_utc();
+    let deadline = app_state
+        .db
+        .start_rejoin_window(
+            &app_state.config.zed_environment,
+            server_id,
+            now + remaining,
+        )
+        .await
+        .trace_err()
+        .flatten();
+    deadline.map_or(Duration::ZERO, |deadline| {
+        Duration::try_from(deadline - now)
+            .unwrap_or(Duration::ZERO)
+            .min(remaining)
+    })
+}
+
+/// Whether any other server in this environment is still subscribed to the message bus,
+/// such as the previous deployment while its connections drain. The rooms and channel
+/// buffers of stale servers are only cleaned up once they've all stopped.
+async fn stale_servers_are_live(server_id: ServerId, app_state: &AppState) -> bool {
+    let Some(live_server_ids) = app_state.message_bus.live_server_ids().await.trace_err() else {
+        return false;
+    };
+    live_server_ids.iter().any(|live_id| *live_id != server_id)
+        && app_state
+            .db
+            .stale_server_ids(&app_state.config.zed_environment, server_id)
+            .await
+            .trace_err()
+            .map_or(false, |stale_server_ids| {
+                stale_server_ids
+                    .iter()
+                    .any(|stale_id| live_server_ids.contains(stale_id))
+            })
+}
+
+/// Removes records that no longer belong to anything live: servers that were already
+/// stale during the previous sweep, and the rooms and projects left without participants.
+async fn sweep_orphans(
+    server_id: ServerId,
+    app_state: &AppState,
+    peer: &Peer,
+    pool: &parking_lot::Mutex<ConnectionPool>,
+    live_kit_client: Option<&dyn live_kit_server::api::Client>,
+    previously_stale_server_ids: &mut HashSet<ServerId>,
+) {
+    if let Some(stale_server_ids) = app_state
+        .db
+        .stale_server_ids(&app_state.config.zed_environment, server_id)
+        .await
+        .trace_err()
+    {
+        // A newer server has started, and will clean up after this one instead.
+        if stale_server_ids
+            .iter()
+            .any(|stale_id| *stale_id > server_id)
+        {
+            previously_stale_server_ids.clear();
+        } else if stale_servers_are_live(server_id, app_state).await {
+            previously_stale_server_ids.clear();
+        } else if !stale_server_ids.is_empty()
+            && stale_server_ids
+                .iter()
+                .all(|stale_id| previously_stale_server_ids.contains(stale_id))
+        {
+            tracing::info!(
+                stale_server_count = stale_server_ids.len(),
+                "sweeping stale servers"
+            );
+            app_state
+                .db
+                .delete_stale_resume_tokens(&app_state.config.zed_environment, server_id)
+                .await
+                .trace_err();
+            clean_up_stale_servers(server_id, app_state, peer, pool, live_kit_client).await;
+            METRIC_SWEPT_ORPHANS
+                .with_label_values(&["servers"])
+                .inc_by(stale_server_ids.len() as u64);
+            previously_stale_server_ids.clear();
+        } else {
+            *previously_stale_server_ids = stale_server_ids.into_iter().collect();
+        }
+    }
+
+    if let Some(orphans) = app_state
+        .db
+        .delete_orphaned_rooms_and_projects()
+        .await
+        .trace_err()
+    {
+        tracing::info!(
+            orphaned_project_count = orphans.projects,
+            orphaned_room_count = orphans.rooms,
+            "swept orphaned rooms and projects"
+        );
+        METRIC_SWEPT_ORPHANS
+            .with_label_values(&["projects"])
+            .inc_by(orphans.projects as u64);
+        METRIC_SWEPT_ORPHANS
+            .with_label_values(&["rooms"])
+            .inc_by(orphans.rooms as u64);
+    }
+
+    if let Some(guest_count) = app_state
+        .db
+        .delete_expired_guests(OffsetDateTime::now_utc())
+        .await
+        .trace_err()
+    {
+        if guest_count > 0 {
+            tracing::info!(guest_count, "deleted expired guests");
+        }
+        METRIC_SWEPT_ORPHANS
+            .with_label_values(&["guests"])
+            .inc_by(guest_count as u64);
+    }
+}
+
+impl<'a> Deref for ConnectionPoolGuard<'a> {
+    type Target = ConnectionPool;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.guard
+    }
+}
+
+impl<'a> DerefMut for ConnectionPoolGuard<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut *self.guard
+    }
+}
+
+impl<'a> Drop for ConnectionPoolGuard<'a> {
+    fn drop(&mut self) {
+        #[cfg(test)]
+        self.check_invariants();
+    }
+}
 
 fn broadcast<F>(
     sender_id: Option<ConnectionId>,
@@ -871,25 +2149,30 @@ pub async fn handle_websocket_request(
     Extension(impersonator): Extension<Impersonator>,
     ws: WebSocketUpgrade,
 ) -> axum::response::Response {
-    if protocol_version != rpc::PROTOCOL_VERSION {
+    if server.is_draining() {
         return (
-            StatusCode::UPGRADE_REQUIRED,
-            "client must be upgraded".to_string(),
+            StatusCode::SERVICE_UNAVAILABLE,
+            "server is restarting".to_string(),
         )
             .into_response();
     }
 
-    // the first version of zed that sent this header was 0.121.x
-    if let Some(version) = app_version_header.map(|header| header.0 .0) {
-        // 0.123.0 was a nightly version with incompatible collab changes
-        // that were reverted.
-        if version == "0.123.0".parse().unwrap() {
-            return (
-                StatusCode::UPGRADE_REQUIRED,
-                "client must be upgraded".to_string(),
-            )
-                .into_response();
-        }
+    let app_version = app_version_header.map(|header| header.0 .0);
+    if !is_supported_client(&server.app_state, protocol_version, app_version) {
+        return upgrade_required_response(&server.app_state);
+    }
+
+    let reached_quota = match server.reached_connection_quota(user.id).await {
+        Ok(reached_quota) => reached_quota,
+        Err(error) => return error.into_response(),
+    };
+    if let Some(quota) = reached_quota {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(rpc::CONNECTION_LIMIT_HEADER, quota.to_string())],
+            "too many connections".to_string(),
+        )
+            .into_response();
     }
 
     let socket_address = socket_address.to_string();
@@ -899,7 +2182,7 @@ pub async fn handle_websocket_request(
             .map_ok(to_tungstenite_message)
             .err_into()
             .with(|message| async move { Ok(to_axum_message(message)) });
-        let connection = Connection::new(Box::pin(socket));
+        let connection = Connection::new(Box::pin(socket)).with_protocol_version(protocol_version);
         async move {
             server
                 .handle_connection(
@@ -916,6 +2199,53 @@ pub async fn handle_websocket_request(
     })
 }
 
+fn is_supported_client(
+    app_state: &AppState,
+    protocol_version: u32,
+    app_version: Option<SemanticVersion>,
+) -> bool {
+    if !(rpc::MIN_PROTOCOL_VERSION..=rpc::PROTOCOL_VERSION).contains(&protocol_version) {
+        return false;
+    }
+
+    // the first version of zed that sent this header was 0.121.x, so clients that
+    // don't send it are older than any minimum version.
+    let Some(version) = app_version else {
+        return app_state.min_client_version.is_none();
+    };
+
+    // 0.123.0 was a nightly version with incompatible collab changes
+    // that were reverted.
+    if version == "0.123.0".parse().unwrap() {
+        return false;
+    }
+
+    app_state
+        .min_client_version
+        .map_or(true, |min_version| version >= min_version)
+}
+
+fn upgrade_required_response(app_state: &AppState) -> axum::response::Response {
+    let upgrade_required = rpc::UpgradeRequired {
+        min_protocol_version: Some(rpc::MIN_PROTOCOL_VERSION),
+        min_app_version: app_state.min_client_version,
+        release_notes_url: app_state.config.release_notes_url.clone(),
+    };
+    let mut response = (
+        StatusCode::UPGRADE_REQUIRED,
+        "client must be upgraded".to_string(),
+    )
+        .into_response();
+    for (name, value) in upgrade_required.to_headers() {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response
+                .headers_mut()
+                .insert(HeaderName::from_static(name), value);
+        }
+    }
+    response
+}
+
 pub async fn handle_metrics(Extension(server): Extension<Arc<Server>>) -> Result<String> {
     let connections = server
         .connection_pool
@@ -929,6 +2259,16 @@ pub async fn handle_metrics(Extension(server): Extension<Arc<Server>>) -> Result
     let shared_projects = server.app_state.db.project_count_excluding_admins().await?;
     METRIC_SHARED_PROJECTS.set(shared_projects as _);
 
+    let bandwidth_usage = server.peer.total_bandwidth_usage();
+    for category in MessageCategory::ALL {
+        METRIC_BYTES_SENT
+            .with_label_values(&[category.as_str()])
+            .set(bandwidth_usage.bytes_sent(category) as _);
+        METRIC_BYTES_RECEIVED
+            .with_label_values(&[category.as_str()])
+            .set(bandwidth_usage.bytes_received(category) as _);
+    }
+
     let encoder = prometheus::TextEncoder::new();
     let metric_families = prometheus::gather();
     let encoded_metrics = encoder
@@ -944,23 +2284,38 @@ async fn connection_lost(
     executor: Executor,
 ) -> Result<()> {
     session.peer.disconnect(session.connection_id);
+    if let Some(server) = session.server.upgrade() {
+        server.brokered_connections.close_all(session.connection_id);
+    }
+    session.audio_routes.forget_speaker(session.connection_id);
     session
         .connection_pool()
         .await
         .remove_connection(session.connection_id)?;
-
     session
-        .db()
-        .await
-        .connection_lost(session.connection_id)
-        .await
-        .trace_err();
+        .message_bus
+        .broadcast(ServerEvent::ConnectionClosed {
+            connection_id: session.connection_id,
+        });
+
+    retry_connection_cleanup(&executor, || async {
+        session
+            .db()
+            .await
+            .connection_lost(session.connection_id)
+            .await
+    })
+    .await
+    .trace_err();
 
+    let reconnect_timeout = session.runtime_config.get().reconnect_timeout;
     futures::select_biased! {
-        _ = executor.sleep(RECONNECT_TIMEOUT).fuse() => {
+        _ = executor.sleep(reconnect_timeout).fuse() => {
             log::info!("connection lost, removing all resources for user:{}, connection:{:?}", session.user_id, session.connection_id);
-            leave_room_for_session(&session).await.trace_err();
-            leave_channel_buffers_for_session(&session)
+            retry_connection_cleanup(&executor, || leave_room_for_session(&session))
+                .await
+                .trace_err();
+            retry_connection_cleanup(&executor, || leave_channel_buffers_for_session(&session))
                 .await
                 .trace_err();
 
@@ -970,7 +2325,7 @@ async fn connection_lost(
                 .is_user_online(session.user_id)
             {
                 let db = session.db().await;
-                if let Some(room) = db.decline_call(None, session.user_id).await.trace_err().flatten() {
+                while let Some(room) = db.decline_call(None, session.user_id).await.trace_err().flatten() {
                     room_updated(&room, &session.peer);
                 }
             }
@@ -983,23 +2338,206 @@ async fn connection_lost(
     Ok(())
 }
 
+/// Runs a step of releasing a lost connection's resources until it succeeds. Nothing
+/// else releases them until the server restarts, so a transient database error would
+/// otherwise leave the user in their room and block them from joining another one.
+async fn retry_connection_cleanup<F, Fut>(executor: &Executor, mut cleanup: F) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut attempt = 1;
+    let mut backoff = CONNECTION_CLEANUP_BACKOFF;
+    loop {
+        match cleanup().await {
+            Err(error) if attempt < CONNECTION_CLEANUP_ATTEMPTS => {
+                log::warn!(
+                    "failed to clean up lost connection, retrying in {backoff:?}: {error:?}"
+                );
+                executor.sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
 /// Acknowledges a ping from a client, used to keep the connection alive.
 async fn ping(_: proto::Ping, response: Response<proto::Ping>, _session: Session) -> Result<()> {
     response.send(proto::Ack {})?;
     Ok(())
 }
 
+/// Opens a connection for another instance of Zed signed in as the same user, which the
+/// sender carries over its own connection. The other instance's access token and
+/// versions are checked like they would have been if it had connected itself.
+async fn open_brokered_connection(
+    request: proto::OpenBrokeredConnection,
+    response: Response<proto::OpenBrokeredConnection>,
+    session: Session,
+) -> Result<()> {
+    let server = session
+        .server
+        .upgrade()
+        .ok_or_else(|| anyhow!("server is shutting down"))?;
+    if server.is_draining() {
+        Err(anyhow!("server is restarting"))?;
+    }
+
+    let app_version = request
+        .app_version
+        .as_deref()
+        .map(str::parse::<SemanticVersion>)
+        .transpose()?;
+    if !is_supported_client(&server.app_state, request.protocol_version, app_version) {
+        Err(anyhow!("client must be upgraded"))?;
+    }
+
+    let db = &server.app_state.db;
+    let verified = auth::verify_access_token(&request.access_token, session.user_id, db)
+        .await
+        .map_or(false, |result| {
+            result.is_valid && result.impersonator_id.is_none()
+        });
+    if !verified {
+        Err(anyhow!("invalid access token"))?;
+    }
+    if let Some(quota) = server.reached_connection_quota(session.user_id).await? {
+        Err(anyhow!("too many connections, the limit is {quota}"))?;
+    }
+    let user = db
+        .get_user_by_id(session.user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+
+    let broker_id = session.connection_id;
+    let brokered_connection_id = request.brokered_connection_id;
+    let (connection, mut outgoing_rx) = server
+        .brokered_connections
+        .open(broker_id, brokered_connection_id)?;
+    response.send(proto::Ack {})?;
+
+    let peer = session.peer.clone();
+    server.executor.spawn_detached(async move {
+        while let Some(message) = outgoing_rx.next().await {
+            let Some(message) = proto::BrokeredConnectionMessage::from_websocket_message(
+                brokered_connection_id,
+                message,
+            ) else {
+                continue;
+            };
+            if peer.send(broker_id, message).is_err() {
+                return;
+            }
+        }
+        peer.send(
+            broker_id,
+            proto::CloseBrokeredConnection {
+                brokered_connection_id,
+            },
+        )
+        .ok();
+    });
+
+    let connection = connection.with_protocol_version(request.protocol_version);
+    let address = format!("brokered by {broker_id}");
+    let handle_connection = server.handle_connection(
+        connection,
+        address,
+        user,
+        None,
+        None,
+        server.executor.clone(),
+    );
+    server.executor.spawn_detached(async move {
+        use util::ResultExt;
+        handle_connection.await.log_err();
+    });
+    Ok(())
+}
+
+/// Delivers a message that the sender received on a connection it brokers. It's
+/// delivered before the handler's future is returned rather than when it's polled, so
+/// that the order of the messages is kept even though foreground handlers run
+/// concurrently.
+fn brokered_connection_message(
+    message: proto::BrokeredConnectionMessage,
+    session: Session,
+) -> impl Future<Output = Result<()>> {
+    let delivered = match session.server.upgrade() {
+        Some(server) => server
+            .brokered_connections
+            .deliver(
+                session.connection_id,
+                message.brokered_connection_id,
+                message.into_websocket_message(),
+            )
+            .map_err(Error::from),
+        None => Ok(()),
+    };
+    future::ready(delivered)
+}
+
+/// Closes a connection that the sender brokers, once its instance of Zed has hung up.
+async fn close_brokered_connection(
+    message: proto::CloseBrokeredConnection,
+    session: Session,
+) -> Result<()> {
+    if let Some(server) = session.server.upgrade() {
+        server
+            .brokered_connections
+            .close(session.connection_id, message.brokered_connection_id);
+    }
+    Ok(())
+}
+
+/// Answers the checks that clients run before joining a room.
+async fn room_preflight(
+    _request: proto::RoomPreflight,
+    response: Response<proto::RoomPreflight>,
+    _session: Session,
+) -> Result<()> {
+    let server_time_ms = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    response.send(proto::RoomPreflightResponse {
+        protocol_version: rpc::PROTOCOL_VERSION,
+        server_time_ms,
+        probe: vec![0; ROOM_PREFLIGHT_PROBE_LEN],
+    })?;
+    Ok(())
+}
+
+/// Whether a participant's client reported that it can't take part in the
+/// call's audio and video, in which case it isn't given access to them.
+fn lacks_media(capabilities: Option<&proto::ParticipantCapabilities>) -> bool {
+    capabilities.map_or(false, |capabilities| !capabilities.media_available)
+}
+
+fn needs_reduced_sync(capabilities: Option<&proto::ParticipantCapabilities>) -> bool {
+    capabilities.map_or(false, |capabilities| {
+        db::room_participant::Capabilities::from_proto(capabilities).needs_reduced_sync()
+    })
+}
+
 /// Creates a new room for calling (outside of channels)
 async fn create_room(
-    _request: proto::CreateRoom,
+    request: proto::CreateRoom,
     response: Response<proto::CreateRoom>,
     session: Session,
 ) -> Result<()> {
     let live_kit_room = nanoid::nanoid!(30);
+    let capabilities = request.capabilities.as_ref();
+    let duration = request.duration_secs.map(room_duration).transpose()?;
 
     let live_kit_connection_info = {
         let live_kit_room = live_kit_room.clone();
-        let live_kit = session.live_kit_client.as_ref();
+        let live_kit = session
+            .live_kit_client
+            .as_ref()
+            .filter(|_| !lacks_media(capabilities));
 
         util::async_maybe!({
             let live_kit = live_kit?;
@@ -1012,6 +2550,7 @@ async fn create_room(
                 server_url: live_kit.url().into(),
                 token,
                 can_publish: true,
+                ice_configuration: session.ice_configuration(None),
             })
         })
     }
@@ -1020,18 +2559,76 @@ async fn create_room(
     let room = session
         .db()
         .await
-        .create_room(session.user_id, session.connection_id, &live_kit_room)
+        .create_room(
+            session.user_id,
+            session.connection_id,
+            &live_kit_room,
+            capabilities,
+        )
         .await?;
+    let room_id = RoomId::from_proto(room.id);
+    session.webhooks.send(WebhookEvent::RoomCreated {
+        room_id,
+        channel_id: None,
+    });
+    session.webhooks.send(WebhookEvent::UserJoined {
+        room_id,
+        channel_id: None,
+        user_id: session.user_id,
+    });
+    let room = if let Some(duration) = duration {
+        let time_boxed_room = session
+            .db()
+            .await
+            .time_box_room(room_id, session.user_id, duration)
+            .await?;
+        session
+            .room_timers
+            .schedule(room_id, time_boxed_room.ends_at);
+        time_boxed_room.into_inner().room
+    } else {
+        room
+    };
 
     response.send(proto::CreateRoomResponse {
         room: Some(room.clone()),
         live_kit_connection_info,
+        reduced_sync: needs_reduced_sync(capabilities),
     })?;
 
     update_user_contacts(session.user_id, &session).await?;
     Ok(())
 }
 
+/// Pushes back the end of a time-boxed room.
+async fn extend_room(
+    request: proto::ExtendRoom,
+    response: Response<proto::ExtendRoom>,
+    session: Session,
+) -> Result<()> {
+    let room_id = RoomId::from_proto(request.room_id);
+    let duration = room_duration(request.duration_secs)?;
+    let time_boxed_room = session
+        .db()
+        .await
+        .extend_room(room_id, session.user_id, duration)
+        .await?;
+    room_updated(&time_boxed_room.room, &session.peer);
+    session
+        .room_timers
+        .schedule(room_id, time_boxed_room.ends_at);
+    response.send(proto::Ack {})?;
+    Ok(())
+}
+
+fn room_duration(duration_secs: u64) -> Result<Duration> {
+    let duration = Duration::from_secs(duration_secs);
+    if duration.is_zero() || duration > MAX_ROOM_DURATION {
+        Err(anyhow!("invalid room duration"))?;
+    }
+    Ok(duration)
+}
+
 /// Join a room from an invitation. Equivalent to joining a channel if there is one.
 async fn join_room(
     request: proto::JoinRoom,
@@ -1043,18 +2640,45 @@ async fn join_room(
     let channel_id = session.db().await.channel_id_for_room(room_id).await?;
 
     if let Some(channel_id) = channel_id {
-        return join_channel_internal(channel_id, Box::new(response), session).await;
+        return join_channel_internal(
+            channel_id,
+            request.capabilities,
+            Box::new(response),
+            session,
+        )
+        .await;
     }
 
+    let capabilities = request.capabilities.as_ref();
     let joined_room = {
         let room = session
             .db()
             .await
-            .join_room(room_id, session.user_id, session.connection_id)
+            .join_room(
+                room_id,
+                session.user_id,
+                session.connection_id,
+                capabilities,
+            )
             .await?;
         room_updated(&room.room, &session.peer);
+        session.audio_routes.forget_room(room_id);
         room.into_inner()
     };
+    // Observers are hidden from most of the room, so they aren't announced, and the
+    // project they're working in isn't revealed to their contacts while they observe.
+    if is_observer(&joined_room.room, session.connection_id) {
+        session
+            .connection_pool()
+            .await
+            .set_working_location(session.connection_id, None);
+    } else {
+        session.webhooks.send(WebhookEvent::UserJoined {
+            room_id,
+            channel_id: None,
+            user_id: session.user_id,
+        });
+    }
 
     for connection_id in session
         .connection_pool()
@@ -1072,46 +2696,157 @@ async fn join_room(
             .trace_err();
     }
 
-    let live_kit_connection_info = if let Some(live_kit) = session.live_kit_client.as_ref() {
-        if let Some(token) = live_kit
-            .room_token(
-                &joined_room.room.live_kit_room,
-                &session.user_id.to_string(),
+    response.send(proto::JoinRoomResponse {
+        live_kit_connection_info: joined_room_live_kit_info(&joined_room, capabilities, &session),
+        room: Some(room_seen_by(&joined_room.room, session.connection_id)),
+        channel_id: None,
+        reduced_sync: needs_reduced_sync(capabilities),
+    })?;
+
+    update_user_contacts(session.user_id, &session).await?;
+    Ok(())
+}
+
+/// Hang up the current call, if any, and join the room of a call that was
+/// waiting for the user while they were in it.
+async fn accept_waiting_call(
+    request: proto::AcceptWaitingCall,
+    response: Response<proto::AcceptWaitingCall>,
+    session: Session,
+) -> Result<()> {
+    let room_id = RoomId::from_proto(request.room_id);
+    let capabilities = request.capabilities.as_ref();
+    let (joined_room, left_room) = {
+        let accepted = session
+            .db()
+            .await
+            .accept_waiting_call(
+                room_id,
+                session.user_id,
+                session.connection_id,
+                capabilities,
             )
-            .trace_err()
-        {
-            Some(proto::LiveKitConnectionInfo {
-                server_url: live_kit.url().into(),
-                token,
-                can_publish: true,
-            })
-        } else {
-            None
+            .await?;
+        if let Some(left_room) = &accepted.1 {
+            room_updated(&left_room.room, &session.peer);
         }
-    } else {
-        None
+        room_updated(&accepted.0.room, &session.peer);
+        accepted.into_inner()
     };
+    session.webhooks.send(WebhookEvent::UserJoined {
+        room_id,
+        channel_id: joined_room.channel_id,
+        user_id: session.user_id,
+    });
+
+    for connection_id in session
+        .connection_pool()
+        .await
+        .user_connection_ids(session.user_id)
+    {
+        session
+            .peer
+            .send(
+                connection_id,
+                proto::CallCanceled {
+                    room_id: room_id.to_proto(),
+                },
+            )
+            .trace_err();
+    }
 
     response.send(proto::JoinRoomResponse {
-        room: Some(joined_room.room),
+        live_kit_connection_info: joined_room_live_kit_info(&joined_room, capabilities, &session),
+        room: Some(room_seen_by(&joined_room.room, session.connection_id)),
         channel_id: None,
-        live_kit_connection_info,
+        reduced_sync: needs_reduced_sync(capabilities),
     })?;
 
-    update_user_contacts(session.user_id, &session).await?;
+    if let Some(left_room) = left_room {
+        room_left(left_room, &session).await?;
+    } else {
+        update_user_contacts(session.user_id, &session).await?;
+    }
     Ok(())
 }
 
+/// Whether the participant on the given connection is observing the room.
+fn is_observer(room: &proto::Room, connection_id: ConnectionId) -> bool {
+    room.participants.iter().any(|participant| {
+        participant.observer && participant.peer_id == Some(connection_id.into())
+    })
+}
+
+fn joined_room_live_kit_info(
+    joined_room: &db::JoinRoom,
+    capabilities: Option<&proto::ParticipantCapabilities>,
+    session: &Session,
+) -> Option<proto::LiveKitConnectionInfo> {
+    let live_kit = session
+        .live_kit_client
+        .as_ref()
+        .filter(|_| !lacks_media(capabilities))?;
+    let observer = is_observer(&joined_room.room, session.connection_id);
+    let live_kit_room = &joined_room.room.live_kit_room;
+    let identity = session.user_id.to_string();
+    let token = if observer {
+        live_kit.observer_token(live_kit_room, &identity)
+    } else {
+        live_kit.room_token(live_kit_room, &identity)
+    }
+    .trace_err()?;
+    Some(proto::LiveKitConnectionInfo {
+        server_url: live_kit.url().into(),
+        token,
+        can_publish: !observer,
+        ice_configuration: session.ice_configuration(joined_room.ice_servers.as_deref()),
+    })
+}
+
 /// Rejoin room is used to reconnect to a room after connection errors.
 async fn rejoin_room(
     request: proto::RejoinRoom,
     response: Response<proto::RejoinRoom>,
     session: Session,
 ) -> Result<()> {
+    // A client that was handed a resume token by a restarting server redeems it here,
+    // which lets the connection it had to that server be cleaned up. Tokens that don't
+    // match the room being rejoined are refused, along with the rejoin.
+    if let Some(resume_token) = request.resume_token.as_deref() {
+        let resumed_connection = session
+            .db()
+            .await
+            .take_resume_token(
+                resume_token,
+                session.user_id,
+                RoomId::from_proto(request.id),
+            )
+            .await?;
+        tracing::info!(%resumed_connection, "resumed connection");
+    }
+
     let room;
     let channel_id;
     let channel_members;
+    let mut journaled_operations = Vec::new();
     {
+        let loaded_directories = request
+            .rejoined_projects
+            .iter()
+            .flat_map(|project| {
+                project.worktrees.iter().filter_map(move |worktree| {
+                    if worktree.loaded_directories.is_empty() {
+                        None
+                    } else {
+                        Some((
+                            (ProjectId::from_proto(project.id), worktree.id),
+                            worktree.loaded_directories.clone(),
+                        ))
+                    }
+                })
+            })
+            .collect::<HashMap<_, _>>();
+
         let mut rejoined_room = session
             .db()
             .await
@@ -1119,7 +2854,7 @@ async fn rejoin_room(
             .await?;
 
         response.send(proto::RejoinRoomResponse {
-            room: Some(rejoined_room.room.clone()),
+            room: Some(room_seen_by(&rejoined_room.room, session.connection_id)),
             reshared_projects: rejoined_room
                 .reshared_projects
                 .iter()
@@ -1150,6 +2885,11 @@ async fn rejoin_room(
                     collaborators: rejoined_project
                         .collaborators
                         .iter()
+                        .filter(|collaborator| {
+                            rejoined_room
+                                .observer_visibility
+                                .can_see(session.connection_id, collaborator.connection_id)
+                        })
                         .map(|collaborator| collaborator.to_proto())
                         .collect(),
                     language_servers: rejoined_project.language_servers.clone(),
@@ -1194,6 +2934,12 @@ async fn rejoin_room(
 
         for project in &rejoined_room.rejoined_projects {
             for collaborator in &project.collaborators {
+                if !rejoined_room
+                    .observer_visibility
+                    .can_see(collaborator.connection_id, session.connection_id)
+                {
+                    continue;
+                }
                 session
                     .peer
                     .send(
@@ -1216,7 +2962,7 @@ async fn rejoin_room(
                 const MAX_CHUNK_SIZE: usize = 256;
 
                 // Stream this worktree's entries.
-                let message = proto::UpdateWorktree {
+                let mut message = proto::UpdateWorktree {
                     project_id: project.id.to_proto(),
                     worktree_id: worktree.id,
                     abs_path: worktree.abs_path.clone(),
@@ -1227,7 +2973,26 @@ async fn rejoin_room(
                     is_last_update: worktree.completed_scan_id == worktree.scan_id,
                     updated_repositories: worktree.updated_repositories,
                     removed_repositories: worktree.removed_repositories,
+                    loaded_directories: Vec::new(),
+                    all_directories_loaded: false,
                 };
+
+                // A guest that was loading this worktree lazily keeps doing so,
+                // so only send it changes within the directories it has loaded.
+                if let Some(directories) = loaded_directories.get(&(project.id, worktree.id)) {
+                    let mut pool = session.connection_pool().await;
+                    pool.load_directories(
+                        session.connection_id,
+                        project.id,
+                        worktree.id,
+                        directories.iter().cloned(),
+                    );
+                    if let Some(directories) =
+                        pool.loaded_directories(session.connection_id, project.id, worktree.id)
+                    {
+                        remove_entries_outside_loaded_directories(&mut message, directories);
+                    }
+                }
                 for update in proto::split_worktree_update(message, MAX_CHUNK_SIZE) {
                     session.peer.send(session.connection_id, update.clone())?;
                 }
@@ -1273,6 +3038,21 @@ async fn rejoin_room(
             }
         }
 
+        for project in &rejoined_room.reshared_projects {
+            let operations_by_buffer_id = session
+                .db()
+                .await
+                .take_journaled_operations(project.id)
+                .await?;
+            for (buffer_id, operations) in operations_by_buffer_id {
+                journaled_operations.push(proto::UpdateBuffer {
+                    project_id: project.id.to_proto(),
+                    buffer_id,
+                    operations,
+                });
+            }
+        }
+
         let rejoined_room = rejoined_room.into_inner();
 
         room = rejoined_room.room;
@@ -1280,6 +3060,15 @@ async fn rejoin_room(
         channel_members = rejoined_room.channel_members;
     }
 
+    // Replay the buffer operations that guests sent while the host was away.
+    for update in journaled_operations {
+        session
+            .peer
+            .request(session.connection_id, update)
+            .await
+            .trace_err();
+    }
+
     if let Some(channel_id) = channel_id {
         channel_updated(
             channel_id,
@@ -1326,6 +3115,9 @@ async fn set_room_participant_role(
         let live_kit_room = room.live_kit_room.clone();
         let can_publish = ChannelRole::from(request.role()).can_publish_to_rooms();
         room_updated(&room, &session.peer);
+        session
+            .audio_routes
+            .forget_room(RoomId::from_proto(request.room_id));
         (live_kit_room, can_publish)
     };
 
@@ -1369,8 +3161,31 @@ async fn call(
     {
         return Err(anyhow!("cannot call a user who isn't a contact"))?;
     }
+    if session
+        .db()
+        .await
+        .has_blocked_user(called_user_id, calling_user_id)
+        .await?
+    {
+        return Err(anyhow!("cannot call this user"))?;
+    }
+    session.check_invite_throttle(proto::Call::NAME).await?;
 
-    let incoming_call = {
+    let context = request.context.map(|mut context| {
+        context.message = context.message.trim().to_string();
+        context
+    });
+    if let Some(context) = &context {
+        if context.message.len() > MAX_INVITE_MESSAGE_LEN {
+            return Err(anyhow!("invite message is too long"))?;
+        }
+        if context.path.is_some() && (initial_project_id.is_none() || context.worktree_id.is_none())
+        {
+            return Err(anyhow!("invite location must be in the shared project"))?;
+        }
+    }
+
+    let mut incoming_call = {
         let (room, incoming_call) = &mut *session
             .db()
             .await
@@ -1380,11 +3195,14 @@ async fn call(
                 calling_connection_id,
                 called_user_id,
                 initial_project_id,
+                request.observer,
+                session.room_participant_quota(),
             )
             .await?;
         room_updated(&room, &session.peer);
         mem::take(incoming_call)
     };
+    incoming_call.context = context;
     update_user_contacts(called_user_id, &session).await?;
 
     let mut calls = session
@@ -1419,6 +3237,57 @@ async fn call(
     Err(anyhow!("failed to ring user"))?
 }
 
+/// Invite someone without an account into the current room, by creating a guest
+/// who is called into it as soon as they connect.
+async fn create_guest(
+    request: proto::CreateGuest,
+    response: Response<proto::CreateGuest>,
+    session: Session,
+) -> Result<()> {
+    let room_id = RoomId::from_proto(request.room_id);
+    let duration = Duration::from_secs(request.duration_secs);
+    if duration.is_zero() || duration > guest::MAX_GUEST_DURATION {
+        return Err(anyhow!("invalid guest duration"))?;
+    }
+    session
+        .check_invite_throttle(proto::CreateGuest::NAME)
+        .await?;
+    let expires_at = OffsetDateTime::now_utc() + duration;
+
+    let db = session.db().await;
+    let user = db
+        .create_guest(
+            room_id,
+            session.user_id,
+            ChannelRole::from(request.role()),
+            expires_at,
+        )
+        .await?;
+    let access_token = auth::create_access_token(&db, user.id, None).await?;
+    {
+        let (room, _) = &*db
+            .call(
+                room_id,
+                session.user_id,
+                session.connection_id,
+                user.id,
+                None,
+                false,
+                session.room_participant_quota(),
+            )
+            .await?;
+        room_updated(room, &session.peer);
+    }
+    drop(db);
+
+    response.send(proto::CreateGuestResponse {
+        user: Some(session.user_to_proto(user)),
+        access_token,
+        expires_at: expires_at.unix_timestamp() as u64,
+    })?;
+    Ok(())
+}
+
 /// Cancel an outgoing call.
 async fn cancel_call(
     request: proto::CancelCall,
@@ -1489,6 +3358,85 @@ async fn decline_call(message: proto::DeclineCall, session: Session) -> Result<(
     Ok(())
 }
 
+/// Get the calls the current user has recently placed or received.
+async fn get_call_history(
+    request: proto::GetCallHistory,
+    response: Response<proto::GetCallHistory>,
+    session: Session,
+) -> Result<()> {
+    let calls = session
+        .db()
+        .await
+        .get_call_history(
+            session.user_id,
+            CALL_HISTORY_COUNT_PER_PAGE,
+            request.before_id.map(db::CallRecordId::from_proto),
+        )
+        .await?;
+    response.send(proto::GetCallHistoryResponse {
+        done: calls.len() < CALL_HISTORY_COUNT_PER_PAGE,
+        calls: calls.iter().map(|call| call.to_proto()).collect(),
+    })?;
+    Ok(())
+}
+
+/// Adds to the activity feed that the summary of the current call is assembled from.
+async fn report_room_activity(
+    request: proto::ReportRoomActivity,
+    response: Response<proto::ReportRoomActivity>,
+    session: Session,
+) -> Result<()> {
+    let file_times = request
+        .statistics
+        .as_ref()
+        .map_or(&[][..], |statistics| &statistics.file_times);
+    if request.edited_files.len() + file_times.len() > MAX_ROOM_ACTIVITY_REPORT_LEN {
+        return Err(anyhow!("too much activity reported at once"))?;
+    }
+    if request
+        .edited_files
+        .iter()
+        .map(|file| &file.path)
+        .chain(file_times.iter().map(|file_time| &file_time.path))
+        .any(|detail| detail.len() > MAX_ROOM_ACTIVITY_DETAIL_LEN)
+    {
+        return Err(anyhow!("activity detail is too long"))?;
+    }
+
+    session
+        .db()
+        .await
+        .report_room_activity(
+            RoomId::from_proto(request.room_id),
+            session.user_id,
+            session.connection_id,
+            &request,
+        )
+        .await?;
+    response.send(proto::Ack {})?;
+    Ok(())
+}
+
+/// Get the summary of a call that the current user took part in.
+async fn get_call_summary(
+    request: proto::GetCallSummary,
+    response: Response<proto::GetCallSummary>,
+    session: Session,
+) -> Result<()> {
+    let summary = session
+        .db()
+        .await
+        .get_call_summary(
+            db::CallSummaryId::from_proto(request.summary_id),
+            session.user_id,
+        )
+        .await?;
+    response.send(proto::GetCallSummaryResponse {
+        summary: Some(summary),
+    })?;
+    Ok(())
+}
+
 /// Updates other participants in the room with your current location.
 async fn update_participant_location(
     request: proto::UpdateParticipantLocation,
@@ -1516,13 +3464,22 @@ async fn share_project(
     response: Response<proto::ShareProject>,
     session: Session,
 ) -> Result<()> {
+    let room_id = RoomId::from_proto(request.room_id);
+    let quota = session
+        .runtime_config
+        .get()
+        .quota_policy
+        .max_shared_projects_per_room;
     let (project_id, room) = &*session
         .db()
         .await
         .share_project(
-            RoomId::from_proto(request.room_id),
+            room_id,
             session.connection_id,
+            request.host_project_id,
             &request.worktrees,
+            request.policy.as_ref(),
+            quota,
         )
         .await?;
     response.send(proto::ShareProjectResponse {
@@ -1553,6 +3510,21 @@ async fn unshare_project(message: proto::UnshareProject, session: Session) -> Re
     Ok(())
 }
 
+/// Describes the projects shared in the caller's room, so they can pick one to join.
+async fn get_room_projects(
+    request: proto::GetRoomProjects,
+    response: Response<proto::GetRoomProjects>,
+    session: Session,
+) -> Result<()> {
+    let projects = session
+        .db()
+        .await
+        .shared_projects_in_room(RoomId::from_proto(request.room_id), session.connection_id)
+        .await?;
+    response.send(proto::GetRoomProjectsResponse { projects })?;
+    Ok(())
+}
+
 /// Join someone elses shared project.
 async fn join_project(
     request: proto::JoinProject,
@@ -1574,6 +3546,11 @@ async fn join_project(
         .collaborators
         .iter()
         .filter(|collaborator| collaborator.connection_id != session.connection_id)
+        .filter(|collaborator| {
+            project
+                .observer_visibility
+                .can_see(session.connection_id, collaborator.connection_id)
+        })
         .map(|collaborator| collaborator.to_proto())
         .collect::<Vec<_>>();
 
@@ -1589,16 +3566,24 @@ async fn join_project(
         .collect::<Vec<_>>();
 
     for collaborator in &collaborators {
+        let connection_id = collaborator.peer_id.unwrap().into();
+        if !project
+            .observer_visibility
+            .can_see(connection_id, session.connection_id)
+        {
+            continue;
+        }
         session
             .peer
             .send(
-                collaborator.peer_id.unwrap().into(),
+                connection_id,
                 proto::AddProjectCollaborator {
                     project_id: project_id.to_proto(),
                     collaborator: Some(proto::Collaborator {
                         peer_id: Some(session.connection_id.into()),
                         replica_id: replica_id.0 as u32,
                         user_id: guest_user_id.to_proto(),
+                        read_only: project.read_only,
                     }),
                 },
             )
@@ -1611,6 +3596,7 @@ async fn join_project(
         replica_id: replica_id.0 as u32,
         collaborators: collaborators.clone(),
         language_servers: project.language_servers.clone(),
+        read_only: project.read_only,
     })?;
 
     for (worktree_id, worktree) in mem::take(&mut project.worktrees) {
@@ -1619,8 +3605,10 @@ async fn join_project(
         #[cfg(not(any(test, feature = "test-support")))]
         const MAX_CHUNK_SIZE: usize = 256;
 
-        // Stream this worktree's entries.
-        let message = proto::UpdateWorktree {
+        // Stream this worktree's entries. Large worktrees are sent to guests
+        // that support it one directory at a time, starting with the root's
+        // children, and the rest are loaded as the guest needs them.
+        let mut message = proto::UpdateWorktree {
             project_id: project_id.to_proto(),
             worktree_id,
             abs_path: worktree.abs_path.clone(),
@@ -1631,7 +3619,26 @@ async fn join_project(
             is_last_update: worktree.scan_id == worktree.completed_scan_id,
             updated_repositories: worktree.repository_entries.into_values().collect(),
             removed_repositories: Default::default(),
+            loaded_directories: Default::default(),
+            all_directories_loaded: false,
         };
+        if request.load_entries_lazily
+            && message.updated_entries.len()
+                > session.runtime_config.get().lazy_worktree_entry_threshold
+        {
+            let root_directory = String::new();
+            let mut pool = session.connection_pool().await;
+            pool.load_directories(
+                session.connection_id,
+                project_id,
+                worktree_id,
+                [root_directory.clone()],
+            );
+            let loaded_directories = HashSet::from_iter([root_directory.clone()]);
+            remove_entries_outside_loaded_directories(&mut message, &loaded_directories);
+            message.removed_entries.clear();
+            message.loaded_directories = vec![root_directory];
+        }
         for update in proto::split_worktree_update(message, MAX_CHUNK_SIZE) {
             session.peer.send(session.connection_id, update.clone())?;
         }
@@ -1683,6 +3690,10 @@ async fn join_project(
 async fn leave_project(request: proto::LeaveProject, session: Session) -> Result<()> {
     let sender_id = session.connection_id;
     let project_id = ProjectId::from_proto(request.project_id);
+    session
+        .connection_pool()
+        .await
+        .forget_loaded_directories(sender_id, project_id);
 
     let (room, project) = &*session
         .db()
@@ -1702,28 +3713,68 @@ async fn leave_project(request: proto::LeaveProject, session: Session) -> Result
     Ok(())
 }
 
-/// Updates other participants with changes to the project
-async fn update_project(
-    request: proto::UpdateProject,
-    response: Response<proto::UpdateProject>,
+/// Updates other participants with changes to the project
+async fn update_project(
+    request: proto::UpdateProject,
+    response: Response<proto::UpdateProject>,
+    session: Session,
+) -> Result<()> {
+    let project_id = ProjectId::from_proto(request.project_id);
+    let (room, guest_connection_ids) = &*session
+        .db()
+        .await
+        .update_project(project_id, session.connection_id, &request.worktrees)
+        .await?;
+    broadcast(
+        Some(session.connection_id),
+        guest_connection_ids.iter().copied(),
+        |connection_id| {
+            session
+                .peer
+                .forward_send(session.connection_id, connection_id, request.clone())
+        },
+    );
+    room_updated(&room, &session.peer);
+    response.send(proto::Ack {})?;
+
+    Ok(())
+}
+
+/// Makes the host's project read-only or read-write for one of its guests.
+async fn set_collaborator_read_only(
+    request: proto::SetCollaboratorReadOnly,
+    response: Response<proto::SetCollaboratorReadOnly>,
     session: Session,
 ) -> Result<()> {
     let project_id = ProjectId::from_proto(request.project_id);
-    let (room, guest_connection_ids) = &*session
+    let guest_connection_id = request
+        .peer_id
+        .ok_or_else(|| anyhow!("invalid peer id"))?
+        .into();
+    let guest_connection_ids = session
         .db()
         .await
-        .update_project(project_id, session.connection_id, &request.worktrees)
+        .set_project_collaborator_read_only(
+            project_id,
+            session.connection_id,
+            guest_connection_id,
+            request.read_only,
+        )
         .await?;
     broadcast(
         Some(session.connection_id),
         guest_connection_ids.iter().copied(),
         |connection_id| {
-            session
-                .peer
-                .forward_send(session.connection_id, connection_id, request.clone())
+            session.peer.send(
+                connection_id,
+                proto::UpdateCollaboratorReadOnly {
+                    project_id: request.project_id,
+                    peer_id: request.peer_id,
+                    read_only: request.read_only,
+                },
+            )
         },
     );
-    room_updated(&room, &session.peer);
     response.send(proto::Ack {})?;
 
     Ok(())
@@ -1741,19 +3792,146 @@ async fn update_worktree(
         .update_worktree(&request, session.connection_id)
         .await?;
 
+    let project_id = ProjectId::from_proto(request.project_id);
+    let pool = session.connection_pool().await;
     broadcast(
         Some(session.connection_id),
         guest_connection_ids.iter().copied(),
         |connection_id| {
+            let mut update = request.clone();
+            if let Some(loaded_directories) =
+                pool.loaded_directories(connection_id, project_id, request.worktree_id)
+            {
+                remove_entries_outside_loaded_directories(&mut update, loaded_directories);
+            }
             session
                 .peer
-                .forward_send(session.connection_id, connection_id, request.clone())
+                .forward_send(session.connection_id, connection_id, update)
         },
     );
+    drop(pool);
+    response.send(proto::Ack {})?;
+    Ok(())
+}
+
+/// Sends a guest that loads a worktree lazily the entries of a directory,
+/// along with those of its ancestors that it hasn't loaded yet.
+async fn load_worktree_directory(
+    request: proto::LoadWorktreeDirectory,
+    response: Response<proto::LoadWorktreeDirectory>,
+    session: Session,
+) -> Result<()> {
+    let project_id = ProjectId::from_proto(request.project_id);
+    let worktree_id = request.worktree_id;
+    let directories = {
+        let pool = session.connection_pool().await;
+        let loaded_directories =
+            pool.loaded_directories(session.connection_id, project_id, worktree_id);
+        let mut directories = Path::new(&request.path)
+            .ancestors()
+            .filter_map(|ancestor| ancestor.to_str())
+            .filter(|ancestor| {
+                !ancestor.is_empty()
+                    && loaded_directories.map_or(false, |loaded| !loaded.contains(*ancestor))
+            })
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        directories.reverse();
+        directories
+    };
+    if directories.is_empty() {
+        response.send(proto::Ack {})?;
+        return Ok(());
+    }
+
+    let update = session
+        .db()
+        .await
+        .load_worktree_directories(
+            project_id,
+            worktree_id,
+            Some(directories.clone()),
+            session.connection_id,
+        )
+        .await?;
+
+    session.connection_pool().await.load_directories(
+        session.connection_id,
+        project_id,
+        worktree_id,
+        directories,
+    );
+
+    #[cfg(any(test, feature = "test-support"))]
+    const MAX_CHUNK_SIZE: usize = 2;
+    #[cfg(not(any(test, feature = "test-support")))]
+    const MAX_CHUNK_SIZE: usize = 256;
+
+    for update in proto::split_worktree_update(update.clone(), MAX_CHUNK_SIZE) {
+        session.peer.send(session.connection_id, update)?;
+    }
+    response.send(proto::Ack {})?;
+    Ok(())
+}
+
+/// Sends a guest that loads a worktree lazily all of the worktree's entries,
+/// so that it can search them, after which it receives every update to it.
+async fn load_worktree_entries(
+    request: proto::LoadWorktreeEntries,
+    response: Response<proto::LoadWorktreeEntries>,
+    session: Session,
+) -> Result<()> {
+    let project_id = ProjectId::from_proto(request.project_id);
+    let worktree_id = request.worktree_id;
+    let update = session
+        .db()
+        .await
+        .load_worktree_directories(project_id, worktree_id, None, session.connection_id)
+        .await?;
+
+    session.connection_pool().await.load_all_directories(
+        session.connection_id,
+        project_id,
+        worktree_id,
+    );
+
+    #[cfg(any(test, feature = "test-support"))]
+    const MAX_CHUNK_SIZE: usize = 2;
+    #[cfg(not(any(test, feature = "test-support")))]
+    const MAX_CHUNK_SIZE: usize = 256;
+
+    for update in proto::split_worktree_update(update.clone(), MAX_CHUNK_SIZE) {
+        session.peer.send(session.connection_id, update)?;
+    }
     response.send(proto::Ack {})?;
     Ok(())
 }
 
+/// Turns the entries of an update that lie in directories a guest hasn't
+/// loaded into removals, so that the guest doesn't keep stale copies of
+/// entries that were moved out of its view.
+fn remove_entries_outside_loaded_directories(
+    update: &mut proto::UpdateWorktree,
+    loaded_directories: &HashSet<String>,
+) {
+    let mut unloaded_entry_ids = HashSet::default();
+    update.updated_entries.retain(|entry| {
+        let is_loaded = Path::new(&entry.path).parent().map_or(true, |parent| {
+            parent
+                .to_str()
+                .map_or(false, |parent| loaded_directories.contains(parent))
+        });
+        if !is_loaded {
+            unloaded_entry_ids.insert(entry.id);
+        }
+        is_loaded
+    });
+    update
+        .updated_repositories
+        .retain(|repository| !unloaded_entry_ids.contains(&repository.work_directory_id));
+    update.removed_entries.extend(unloaded_entry_ids);
+}
+
 /// Updates other participants with changes to the diagnostics
 async fn update_diagnostic_summary(
     message: proto::UpdateDiagnosticSummary,
@@ -1765,13 +3943,21 @@ async fn update_diagnostic_summary(
         .update_diagnostic_summary(&message, session.connection_id)
         .await?;
 
+    // Summaries replace each other, so guests with a poor connection only need the latest one.
+    let summary_key = message
+        .summary
+        .as_ref()
+        .map(|summary| (summary.path.clone(), summary.language_server_id));
     broadcast(
         Some(session.connection_id),
         guest_connection_ids.iter().copied(),
         |connection_id| {
-            session
-                .peer
-                .forward_send(session.connection_id, connection_id, message.clone())
+            session.peer.forward_send_coalesced(
+                session.connection_id,
+                connection_id,
+                (message.project_id, message.worktree_id, &summary_key),
+                message.clone(),
+            )
         },
     );
 
@@ -1836,13 +4022,34 @@ async fn update_language_server(
         .await
         .project_connection_ids(project_id, session.connection_id)
         .await?;
+
+    // Progress reports and configuration changes are only informational, so
+    // guests with a poor connection only receive the latest state of each one.
+    let progress_key = match &request.variant {
+        Some(proto::update_language_server::Variant::WorkStart(work)) => Some(&work.token),
+        Some(proto::update_language_server::Variant::WorkProgress(work)) => Some(&work.token),
+        Some(proto::update_language_server::Variant::WorkEnd(work)) => Some(&work.token),
+        _ => None,
+    };
+    let is_configuration = matches!(
+        request.variant,
+        Some(proto::update_language_server::Variant::ConfigurationUpdated(_))
+    );
     broadcast(
         Some(session.connection_id),
         project_connection_ids.iter().copied(),
         |connection_id| {
-            session
-                .peer
-                .forward_send(session.connection_id, connection_id, request.clone())
+            session.peer.forward_send_coalesced(
+                session.connection_id,
+                connection_id,
+                (
+                    request.project_id,
+                    request.language_server_id,
+                    progress_key,
+                    is_configuration,
+                ),
+                request.clone(),
+            )
         },
     );
     Ok(())
@@ -1916,6 +4123,26 @@ async fn create_buffer_for_peer(
     Ok(())
 }
 
+/// Notify a guest that they may no longer see a buffer the host sent them
+async fn unshare_buffer_for_peer(
+    request: proto::UnshareBufferForPeer,
+    session: Session,
+) -> Result<()> {
+    session
+        .db()
+        .await
+        .check_user_is_project_host(
+            ProjectId::from_proto(request.project_id),
+            session.connection_id,
+        )
+        .await?;
+    let peer_id = request.peer_id.ok_or_else(|| anyhow!("invalid peer id"))?;
+    session
+        .peer
+        .forward_send(session.connection_id, peer_id.into(), request)?;
+    Ok(())
+}
+
 /// Notify other participants that a buffer has been updated. This is
 /// allowed for guests as long as the update is limited to selections.
 async fn update_buffer(
@@ -1967,10 +4194,31 @@ async fn update_buffer(
         },
     );
     if host_connection_id != session.connection_id {
-        session
+        let forwarded = session
             .peer
             .forward_request(session.connection_id, host_connection_id, request.clone())
-            .await?;
+            .await;
+        if let Err(error) = forwarded {
+            // If the host lost its connection, it may still rejoin the project, in
+            // which case the operations are replayed to it.
+            let host_is_connected = session
+                .connection_pool()
+                .await
+                .is_connected(host_connection_id);
+            if host_is_connected {
+                Err(error)?;
+            }
+            session
+                .db()
+                .await
+                .journal_buffer_operations(
+                    project_id,
+                    host_connection_id,
+                    request.buffer_id,
+                    &request.operations,
+                )
+                .await?;
+        }
     }
 
     response.send(proto::Ack {})?;
@@ -2001,6 +4249,170 @@ async fn broadcast_project_message_from_host<T: EntityMessage<Entity = ShareProj
     Ok(())
 }
 
+/// Relay annotations drawn on a shared screen to the other participants in the room.
+async fn update_screen_annotations(
+    request: proto::UpdateScreenAnnotations,
+    session: Session,
+) -> Result<()> {
+    if let Some(proto::update_screen_annotations::Variant::Add(annotation)) = &request.variant {
+        if annotation.points.len() > MAX_SCREEN_ANNOTATION_POINTS {
+            return Err(anyhow!("screen annotation has too many points"))?;
+        }
+    }
+
+    // Dropping a stroke only loses ink, but dropping a clear leaves stale strokes
+    // on screen, so clears are never shed.
+    let mut is_clear = false;
+    if let Some(proto::update_screen_annotations::Variant::Clear(clear)) = &request.variant {
+        // Participants can clear their own annotations, but only the sharer can clear
+        // everyone's.
+        let is_sharer = session.user_id.to_proto() == request.sharer_user_id;
+        let is_author = clear
+            .author
+            .map_or(false, |author| session.connection_id == author.into());
+        if !is_sharer && !is_author {
+            Err(ErrorCode::Forbidden
+                .message("only the sharer can clear others' screen annotations".into())
+                .anyhow())?;
+        }
+        is_clear = true;
+    }
+    let room_id = RoomId::from_proto(request.room_id);
+    let connection_ids = session
+        .db()
+        .await
+        .room_connection_ids(room_id, session.connection_id)
+        .await?;
+    broadcast(
+        Some(session.connection_id),
+        connection_ids.iter().copied(),
+        |connection_id| {
+            if is_clear {
+                session
+                    .peer
+                    .forward_send(session.connection_id, connection_id, request.clone())
+            } else {
+                session.peer.forward_send_lossy(
+                    session.connection_id,
+                    connection_id,
+                    request.clone(),
+                )
+            }
+        },
+    );
+    Ok(())
+}
+
+/// Save a chat message sent in a call, and send it to the call's other participants.
+async fn send_room_message(
+    request: proto::SendRoomMessage,
+    response: Response<proto::SendRoomMessage>,
+    session: Session,
+) -> Result<()> {
+    let body = request.body.trim().to_string();
+    if body.len() > MAX_MESSAGE_LEN {
+        return Err(anyhow!("message is too long"))?;
+    }
+    if body.is_empty() {
+        return Err(anyhow!("message can't be blank"))?;
+    }
+    let nonce = request
+        .nonce
+        .ok_or_else(|| anyhow!("nonce can't be blank"))?;
+
+    let room_id = RoomId::from_proto(request.room_id);
+    let created = session
+        .db()
+        .await
+        .create_room_message(
+            room_id,
+            session.connection_id,
+            session.user_id,
+            &body,
+            OffsetDateTime::now_utc(),
+            nonce.into(),
+        )
+        .await?;
+    let message = created.message;
+    broadcast(
+        Some(session.connection_id),
+        created.participant_connection_ids.iter().copied(),
+        |connection_id| {
+            session.peer.send(
+                connection_id,
+                proto::RoomMessageReceived {
+                    room_id: room_id.to_proto(),
+                    message: Some(message.clone()),
+                },
+            )
+        },
+    );
+    response.send(proto::SendRoomMessageResponse {
+        message: Some(message),
+    })?;
+    Ok(())
+}
+
+/// Retrieve a page of the chat messages sent in a call.
+async fn get_room_messages(
+    request: proto::GetRoomMessages,
+    response: Response<proto::GetRoomMessages>,
+    session: Session,
+) -> Result<()> {
+    let messages = session
+        .db()
+        .await
+        .get_room_messages(
+            RoomId::from_proto(request.room_id),
+            session.connection_id,
+            MESSAGE_COUNT_PER_PAGE,
+            request.before_message_id.map(RoomMessageId::from_proto),
+            request.after_message_id.map(RoomMessageId::from_proto),
+        )
+        .await?;
+    response.send(proto::GetRoomMessagesResponse {
+        done: messages.len() < MESSAGE_COUNT_PER_PAGE,
+        messages,
+    })?;
+    Ok(())
+}
+
+/// Forward a frame of a participant's voice to everyone else in their call,
+/// for calls that don't go through LiveKit.
+async fn relay_audio_frame(request: proto::RelayedAudioFrame, session: Session) -> Result<()> {
+    if request.audio_data.len() > MAX_RELAYED_AUDIO_FRAME_LEN {
+        return Err(anyhow!("relayed audio frame is too large"))?;
+    }
+
+    let room_id = RoomId::from_proto(request.room_id);
+    let connection_ids = match session
+        .audio_routes
+        .listeners(session.connection_id, room_id)
+    {
+        Some(connection_ids) => connection_ids,
+        None => {
+            let connection_ids = session
+                .db()
+                .await
+                .relayed_audio_connection_ids(room_id, session.connection_id)
+                .await?;
+            session
+                .audio_routes
+                .insert(session.connection_id, room_id, connection_ids)
+        }
+    };
+    broadcast(
+        Some(session.connection_id),
+        connection_ids.iter().copied(),
+        |connection_id| {
+            session
+                .peer
+                .forward_send_lossy(session.connection_id, connection_id, request.clone())
+        },
+    );
+    Ok(())
+}
+
 /// Start following another user in a call.
 async fn follow(
     request: proto::Follow,
@@ -2125,11 +4537,7 @@ async fn get_users(
         .get_users_by_ids(user_ids)
         .await?
         .into_iter()
-        .map(|user| proto::User {
-            id: user.id.to_proto(),
-            avatar_url: format!("https://github.com/{}.png?size=128", user.github_login),
-            github_login: user.github_login,
-        })
+        .map(|user| session.user_to_proto(user))
         .collect();
     response.send(proto::UsersResponse { users })?;
     Ok(())
@@ -2156,16 +4564,104 @@ async fn fuzzy_search_users(
     let users = users
         .into_iter()
         .filter(|user| user.id != session.user_id)
-        .map(|user| proto::User {
-            id: user.id.to_proto(),
-            avatar_url: format!("https://github.com/{}.png?size=128", user.github_login),
-            github_login: user.github_login,
-        })
+        .map(|user| session.user_to_proto(user))
         .collect();
     response.send(proto::UsersResponse { users })?;
     Ok(())
 }
 
+/// Sets the name shown to other users instead of the current user's GitHub
+/// login, and updates everyone who can see the user.
+async fn set_display_name(
+    request: proto::SetDisplayName,
+    response: Response<proto::SetDisplayName>,
+    session: Session,
+) -> Result<()> {
+    let updated_user = session
+        .db()
+        .await
+        .set_user_display_name(session.user_id, request.display_name.as_deref())
+        .await?;
+    broadcast_user_update(updated_user, &session).await?;
+    response.send(proto::Ack {})?;
+    Ok(())
+}
+
+/// Stores an image as the current user's avatar, and updates everyone who can
+/// see the user.
+async fn upload_avatar(
+    request: proto::UploadAvatar,
+    response: Response<proto::UploadAvatar>,
+    session: Session,
+) -> Result<()> {
+    let url_prefix = session
+        .avatar_url_prefix
+        .clone()
+        .ok_or_else(|| anyhow!("avatar uploads are not enabled on this server"))?;
+    if request.data.len() > MAX_AVATAR_SIZE {
+        Err(anyhow!(
+            "avatar can't be larger than {} KiB",
+            MAX_AVATAR_SIZE / 1024
+        ))?;
+    }
+    let content_type = avatars::avatar_content_type(&request.data)
+        .ok_or_else(|| anyhow!("avatar must be a PNG, JPEG, GIF or WebP image"))?;
+    let digest = avatars::avatar_digest(&request.data);
+
+    let updated_user = {
+        let db = session.db().await;
+        if !db
+            .try_rate_limited_action(
+                session.user_id,
+                RateLimitedActionKind::AvatarUpload,
+                None,
+                avatars::AVATAR_UPLOAD_LIMIT,
+                OffsetDateTime::now_utc() - avatars::AVATAR_UPLOAD_WINDOW,
+            )
+            .await?
+        {
+            Err(ErrorCode::RateLimited
+                .message("too many avatars uploaded, try again later".to_string())
+                .anyhow())?;
+        }
+        db.set_user_avatar(session.user_id, &digest, content_type, &request.data)
+            .await?
+    };
+    broadcast_user_update(updated_user, &session).await?;
+    response.send(proto::UploadAvatarResponse {
+        avatar_url: avatars::avatar_url(&url_prefix, &digest),
+    })?;
+    Ok(())
+}
+
+/// Sends a user's new details to their own connections, their contacts, and
+/// everyone in a call with them.
+async fn broadcast_user_update(updated_user: db::UpdatedUser, session: &Session) -> Result<()> {
+    let contacts = session.db().await.get_contacts(session.user_id).await?;
+
+    let pool = session.connection_pool().await;
+    let mut connection_ids = pool
+        .user_connection_ids(session.user_id)
+        .collect::<HashSet<_>>();
+    for contact in contacts {
+        if let db::Contact::Accepted { user_id, .. } = contact {
+            connection_ids.extend(pool.user_connection_ids(user_id));
+        }
+    }
+    connection_ids.extend(updated_user.room_connection_ids);
+
+    let user = session.user_to_proto(updated_user.user);
+    broadcast(None, connection_ids, |connection_id| {
+        session.peer.send(
+            connection_id,
+            proto::UpdateUser {
+                user: Some(user.clone()),
+            },
+        )
+    });
+    Ok(())
+}
+
 /// Send a contact request to another user.
 async fn request_contact(
     request: proto::RequestContact,
@@ -2177,6 +4673,7 @@ async fn request_contact(
     if requester_id == responder_id {
         return Err(anyhow!("cannot add yourself as a contact"))?;
     }
+    check_contact_request_limits(requester_id, responder_id, &session).await?;
 
     let notifications = session
         .db()
@@ -2233,14 +4730,18 @@ async fn respond_to_contact_request(
             .await?;
         let requester_busy = db.is_user_busy(requester_id).await?;
         let responder_busy = db.is_user_busy(responder_id).await?;
+        let mut call_connections = db.call_connections(&[requester_id, responder_id]).await?;
 
         let pool = session.connection_pool().await;
         // Update responder with new contact
         let mut update = proto::UpdateContacts::default();
         if accept {
-            update
-                .contacts
-                .push(contact_for_user(requester_id, requester_busy, &pool));
+            update.contacts.push(contact_for_user(
+                requester_id,
+                requester_busy,
+                call_connections.remove(&requester_id),
+                &pool,
+            ));
         }
         update
             .remove_incoming_requests
@@ -2252,9 +4753,12 @@ async fn respond_to_contact_request(
         // Update requester with new contact
         let mut update = proto::UpdateContacts::default();
         if accept {
-            update
-                .contacts
-                .push(contact_for_user(responder_id, responder_busy, &pool));
+            update.contacts.push(contact_for_user(
+                responder_id,
+                responder_busy,
+                call_connections.remove(&responder_id),
+                &pool,
+            ));
         }
         update
             .remove_outgoing_requests
@@ -2265,12 +4769,249 @@ async fn respond_to_contact_request(
         }
 
         send_notifications(&*pool, &session.peer, notifications);
+        drop(pool);
+
+        if request.response == proto::ContactRequestResponse::Block as i32 {
+            db.set_user_blocked(responder_id, requester_id, true)
+                .await?;
+            send_blocked_user_update(requester_id.to_proto(), true, &session).await?;
+        }
+    }
+
+    response.send(proto::Ack {})?;
+    Ok(())
+}
+
+/// Choose whether a contact can see the project the current user is working in.
+async fn set_location_sharing(
+    request: proto::SetLocationSharing,
+    response: Response<proto::SetLocationSharing>,
+    session: Session,
+) -> Result<()> {
+    let contact_user_id = UserId::from_proto(request.user_id);
+    session
+        .db()
+        .await
+        .set_location_sharing(session.user_id, contact_user_id, request.enabled)
+        .await?;
+
+    let update = proto::UpdateContacts {
+        location_sharing: vec![proto::LocationSharing {
+            user_id: request.user_id,
+            enabled: request.enabled,
+        }],
+        ..Default::default()
+    };
+    for connection_id in session
+        .connection_pool()
+        .await
+        .user_connection_ids(session.user_id)
+    {
+        session.peer.send(connection_id, update.clone())?;
+    }
+    response.send(proto::Ack {})?;
+
+    update_user_contacts(session.user_id, &session).await?;
+    Ok(())
+}
+
+/// Block or unblock a user from sending the current user contact requests and calls.
+async fn set_user_blocked(
+    request: proto::SetUserBlocked,
+    response: Response<proto::SetUserBlocked>,
+    session: Session,
+) -> Result<()> {
+    session
+        .db()
+        .await
+        .set_user_blocked(
+            session.user_id,
+            UserId::from_proto(request.user_id),
+            request.blocked,
+        )
+        .await?;
+    send_blocked_user_update(request.user_id, request.blocked, &session).await?;
+    response.send(proto::Ack {})?;
+    Ok(())
+}
+
+/// Tells the current user's connections whether they've blocked the given user.
+async fn send_blocked_user_update(user_id: u64, blocked: bool, session: &Session) -> Result<()> {
+    let update = proto::UpdateContacts {
+        blocked_users: vec![proto::BlockedUser { user_id, blocked }],
+        ..Default::default()
+    };
+    for connection_id in session
+        .connection_pool()
+        .await
+        .user_connection_ids(session.user_id)
+    {
+        session.peer.send(connection_id, update.clone())?;
+    }
+    Ok(())
+}
+
+/// Record the project the current user is working in, for the contacts they
+/// share it with.
+async fn update_working_location(
+    message: proto::UpdateWorkingLocation,
+    session: Session,
+) -> Result<()> {
+    // Only the project's name is shared, never where it lives on disk.
+    let name = message
+        .name
+        .as_deref()
+        .and_then(|name| Path::new(name.trim()).file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .filter(|name| !name.is_empty());
+    if let Some(name) = &name {
+        if name.len() > MAX_WORKING_LOCATION_LEN {
+            return Err(anyhow!("project name is too long"))?;
+        }
+    }
+    // Observers are hidden from most of the room, which the project they're
+    // working in could give away.
+    let observing = session
+        .db()
+        .await
+        .is_user_observing(session.user_id)
+        .await?;
+    let name = name.filter(|_| !observing);
+
+    session
+        .connection_pool()
+        .await
+        .set_working_location(session.connection_id, name);
+    update_user_contacts(session.user_id, &session).await?;
+    Ok(())
+}
+
+/// Set the private nickname and note for a contact.
+async fn update_contact_note(
+    request: proto::UpdateContactNote,
+    response: Response<proto::UpdateContactNote>,
+    session: Session,
+) -> Result<()> {
+    let contact_user_id = UserId::from_proto(request.user_id);
+
+    let nickname = request
+        .nickname
+        .map(|nickname| nickname.trim().to_string())
+        .filter(|nickname| !nickname.is_empty());
+    if let Some(nickname) = &nickname {
+        if nickname.chars().count() > MAX_CONTACT_NICKNAME_LEN {
+            return Err(anyhow!("nickname is too long"))?;
+        }
+    }
+    let note = request.note.filter(|note| !note.trim().is_empty());
+    if let Some(note) = &note {
+        if note.len() > MAX_CONTACT_NOTE_LEN {
+            return Err(anyhow!("note is too long"))?;
+        }
+    }
+
+    let contact_note = session
+        .db()
+        .await
+        .set_contact_note(session.user_id, contact_user_id, nickname, note)
+        .await?;
+
+    // Nicknames and notes are private, so only the author's connections are updated.
+    let update = proto::UpdateContacts {
+        contact_notes: vec![contact_note.to_proto()],
+        ..Default::default()
+    };
+    for connection_id in session
+        .connection_pool()
+        .await
+        .user_connection_ids(session.user_id)
+    {
+        session.peer.send(connection_id, update.clone())?;
     }
 
     response.send(proto::Ack {})?;
     Ok(())
 }
 
+/// Rejects contact requests from users who are sending too many of them. Only a couple
+/// can be sent to the same person each hour. Past the first few each hour, requests
+/// can only go to people the sender has met, and past the hourly limit, they're
+/// rejected and the sender is flagged for admins to review.
+async fn check_contact_request_limits(
+    requester_id: UserId,
+    responder_id: UserId,
+    session: &Session,
+) -> Result<()> {
+    let policy = session.runtime_config.get().contact_request_policy;
+    let now = OffsetDateTime::now_utc();
+    let window_start = now - abuse::CONTACT_REQUEST_WINDOW;
+    let sent = session
+        .db()
+        .await
+        .get_rate_limited_actions(
+            requester_id,
+            RateLimitedActionKind::ContactRequest,
+            window_start,
+        )
+        .await?;
+    let expires_in = |first_sent: Option<&db::rate_limited_action::Model>| {
+        first_sent.map_or(Duration::ZERO, |action| {
+            abuse::contact_request_expires_in(action.created_at.assume_utc(), now)
+        })
+    };
+
+    let mut sent_to_responder = sent
+        .iter()
+        .filter(|action| action.target_user_id == Some(responder_id))
+        .peekable();
+    let first_sent_to_responder = sent_to_responder.peek().copied();
+    if sent_to_responder.count() >= abuse::REPEATED_CONTACT_REQUEST_LIMIT {
+        Err(RpcError::from_proto(
+            &rpc::rate_limited_error(expires_in(first_sent_to_responder)),
+            proto::RequestContact::NAME,
+        ))?;
+    }
+
+    if policy.max_per_hour > 0 && sent.len() >= policy.max_per_hour {
+        // Users are only flagged once a window, so that admins aren't flooded.
+        let db = session.db().await;
+        if db
+            .try_rate_limited_action(
+                requester_id,
+                RateLimitedActionKind::ContactRequestsFlagged,
+                None,
+                1,
+                window_start,
+            )
+            .await?
+        {
+            db.create_audit_event(NewAuditEvent {
+                detail: Some(sent.len().to_string()),
+                ..NewAuditEvent::new(AuditEventKind::ContactRequestsLimited, requester_id)
+            })
+            .await?;
+        }
+        Err(RpcError::from_proto(
+            &rpc::rate_limited_error(expires_in(sent.first())),
+            proto::RequestContact::NAME,
+        ))?;
+    }
+
+    if policy.max_to_strangers_per_hour > 0
+        && sent.len() >= policy.max_to_strangers_per_hour
+        && !session
+            .db()
+            .await
+            .have_users_interacted(requester_id, responder_id)
+            .await?
+    {
+        Err(ErrorCode::Forbidden
+            .message("too many contact requests to people you haven't met".into())
+            .anyhow())?;
+    }
+    Ok(())
+}
+
 /// Remove a contact.
 async fn remove_contact(
     request: proto::RemoveContact,
@@ -2322,6 +5063,50 @@ async fn remove_contact(
     Ok(())
 }
 
+/// Reports a user for an admin to review. Users who are reported by enough others
+/// are throttled until the reports are dismissed or expire.
+async fn report_user(
+    request: proto::ReportUser,
+    response: Response<proto::ReportUser>,
+    session: Session,
+) -> Result<()> {
+    let reason = request.reason.trim();
+    if reason.is_empty() {
+        return Err(anyhow!("a reason is required"))?;
+    }
+    if reason.len() > MAX_REPORT_REASON_LEN {
+        return Err(anyhow!("reason is too long"))?;
+    }
+    if request.channel_message_ids.len() + request.room_message_ids.len() > MAX_REPORTED_MESSAGES {
+        return Err(anyhow!("too many messages reported at once"))?;
+    }
+    let channel_message_ids = request
+        .channel_message_ids
+        .iter()
+        .map(|id| MessageId::from_proto(*id))
+        .collect::<Vec<_>>();
+    let room_message_ids = request
+        .room_message_ids
+        .iter()
+        .map(|id| RoomMessageId::from_proto(*id))
+        .collect::<Vec<_>>();
+
+    session
+        .db()
+        .await
+        .create_user_report(
+            session.user_id,
+            UserId::from_proto(request.user_id),
+            request.room_id.map(RoomId::from_proto),
+            &channel_message_ids,
+            &room_message_ids,
+            reason,
+        )
+        .await?;
+    response.send(proto::Ack {})?;
+    Ok(())
+}
+
 /// Creates a new channel.
 async fn create_channel(
     request: proto::CreateChannel,
@@ -2407,6 +5192,9 @@ async fn invite_channel_member(
     response: Response<proto::InviteChannelMember>,
     session: Session,
 ) -> Result<()> {
+    session
+        .check_invite_throttle(proto::InviteChannelMember::NAME)
+        .await?;
     let db = session.db().await;
     let channel_id = ChannelId::from_proto(request.channel_id);
     let invitee_id = UserId::from_proto(request.user_id);
@@ -2653,9 +5441,39 @@ async fn get_channel_members(
 ) -> Result<()> {
     let db = session.db().await;
     let channel_id = ChannelId::from_proto(request.channel_id);
-    let members = db
+    let mut members = db
         .get_channel_participant_details(channel_id, session.user_id)
         .await?;
+    let logins = db
+        .get_users_by_ids(
+            members
+                .iter()
+                .map(|member| UserId::from_proto(member.user_id))
+                .collect(),
+        )
+        .await?
+        .into_iter()
+        .map(|user| (user.id.to_proto(), user.github_login))
+        .collect::<HashMap<_, _>>();
+    let login = |member: &proto::ChannelMember| {
+        logins
+            .get(&member.user_id)
+            .map_or("", |login| login.as_str())
+    };
+    // List the members the way clients show them: by role, then with the invitees
+    // after those who've accepted, then by login.
+    let role_order = |member: &proto::ChannelMember| match member.role() {
+        proto::ChannelRole::Admin => 0,
+        proto::ChannelRole::Member => 1,
+        proto::ChannelRole::Banned => 2,
+        proto::ChannelRole::Guest => 3,
+    };
+    members.sort_by(|a, b| {
+        role_order(a)
+            .cmp(&role_order(b))
+            .then_with(|| a.kind().cmp(&b.kind()))
+            .then_with(|| session.collation.compare(login(a), login(b)))
+    });
     response.send(proto::GetChannelMembersResponse { members })?;
     Ok(())
 }
@@ -2708,7 +5526,13 @@ async fn join_channel(
     session: Session,
 ) -> Result<()> {
     let channel_id = ChannelId::from_proto(request.channel_id);
-    join_channel_internal(channel_id, Box::new(response), session).await
+    join_channel_internal(
+        channel_id,
+        request.capabilities,
+        Box::new(response),
+        session,
+    )
+    .await
 }
 
 trait JoinChannelInternalResponse {
@@ -2727,68 +5551,108 @@ impl JoinChannelInternalResponse for Response<proto::JoinRoom> {
 
 async fn join_channel_internal(
     channel_id: ChannelId,
+    capabilities: Option<proto::ParticipantCapabilities>,
     response: Box<impl JoinChannelInternalResponse>,
     session: Session,
 ) -> Result<()> {
-    let joined_room = {
-        leave_room_for_session(&session).await?;
-        let db = session.db().await;
-
-        let (joined_room, membership_updated, role) = db
-            .join_channel(channel_id, session.user_id, session.connection_id)
+    let throttle = session.report_throttle().await?;
+    if throttle >= abuse::ReportThrottle::BlockPublicJoins
+        && session
+            .db()
+            .await
+            .is_public_channel_join(channel_id, session.user_id)
+            .await?
+    {
+        Err(abuse::throttled_error(throttle, proto::JoinChannel::NAME))?;
+    }
+    leave_room_for_session(&session).await?;
+    let capabilities = capabilities.as_ref();
+    let (joined_room, membership_updated) = {
+        let join_result = session
+            .db()
+            .await
+            .join_channel(
+                channel_id,
+                session.user_id,
+                session.connection_id,
+                capabilities,
+                session.room_participant_quota(),
+            )
             .await?;
+        let (joined_room, _, role) = &*join_result;
+
+        let live_kit_connection_info = session
+            .live_kit_client
+            .as_ref()
+            .filter(|_| !lacks_media(capabilities))
+            .and_then(|live_kit| {
+                let (can_publish, token) = if *role == ChannelRole::Guest {
+                    (
+                        false,
+                        live_kit
+                            .guest_token(
+                                &joined_room.room.live_kit_room,
+                                &session.user_id.to_string(),
+                            )
+                            .trace_err()?,
+                    )
+                } else {
+                    (
+                        true,
+                        live_kit
+                            .room_token(
+                                &joined_room.room.live_kit_room,
+                                &session.user_id.to_string(),
+                            )
+                            .trace_err()?,
+                    )
+                };
 
-        let live_kit_connection_info = session.live_kit_client.as_ref().and_then(|live_kit| {
-            let (can_publish, token) = if role == ChannelRole::Guest {
-                (
-                    false,
-                    live_kit
-                        .guest_token(
-                            &joined_room.room.live_kit_room,
-                            &session.user_id.to_string(),
-                        )
-                        .trace_err()?,
-                )
-            } else {
-                (
-                    true,
-                    live_kit
-                        .room_token(
-                            &joined_room.room.live_kit_room,
-                            &session.user_id.to_string(),
-                        )
-                        .trace_err()?,
-                )
-            };
-
-            Some(LiveKitConnectionInfo {
-                server_url: live_kit.url().into(),
-                token,
-                can_publish,
-            })
-        });
+                Some(LiveKitConnectionInfo {
+                    server_url: live_kit.url().into(),
+                    token,
+                    can_publish,
+                    ice_configuration: session
+                        .ice_configuration(joined_room.ice_servers.as_deref()),
+                })
+            });
 
         response.send(proto::JoinRoomResponse {
             room: Some(joined_room.room.clone()),
             channel_id: joined_room.channel_id.map(|id| id.to_proto()),
             live_kit_connection_info,
+            reduced_sync: needs_reduced_sync(capabilities),
         })?;
-
-        let connection_pool = session.connection_pool().await;
-        if let Some(membership_updated) = membership_updated {
-            notify_membership_updated(
-                &connection_pool,
-                membership_updated,
-                session.user_id,
-                &session.peer,
-            );
-        }
-
         room_updated(&joined_room.room, &session.peer);
 
-        joined_room
+        let (joined_room, membership_updated, _) = join_result.into_inner();
+        (joined_room, membership_updated)
     };
 
+    // Channel rooms are created by the first participant to join them, and deleted
+    // once the last one leaves.
+    let room_id = RoomId::from_proto(joined_room.room.id);
+    if joined_room.room_created {
+        session.webhooks.send(WebhookEvent::RoomCreated {
+            room_id,
+            channel_id: Some(channel_id),
+        });
+    }
+    session.webhooks.send(WebhookEvent::UserJoined {
+        room_id,
+        channel_id: Some(channel_id),
+        user_id: session.user_id,
+    });
+
+    if let Some(membership_updated) = membership_updated {
+        notify_membership_updated(
+            &*session.connection_pool().await,
+            membership_updated,
+            session.user_id,
+            &session.peer,
+        );
+    }
+
     channel_updated(
         channel_id,
         &joined_room.room,
@@ -3268,7 +6132,12 @@ async fn get_private_user_info(
         .get_user_by_id(session.user_id)
         .await?
         .ok_or_else(|| anyhow!("user not found"))?;
-    let flags = db.get_user_flags(session.user_id).await?;
+    let mut flags = db.get_user_flags(session.user_id).await?;
+    for flag in &session.runtime_config.get().feature_flags {
+        if !flags.contains(flag) {
+            flags.push(flag.clone());
+        }
+    }
 
     response.send(proto::GetPrivateUserInfoResponse {
         metrics_id,
@@ -3386,14 +6255,46 @@ fn build_channels_update(
 
 fn build_initial_contacts_update(
     contacts: Vec<db::Contact>,
+    contact_notes: Vec<db::contact_note::Model>,
+    call_connections: &HashMap<UserId, ConnectionId>,
+    location_shared_with: &HashSet<UserId>,
+    location_shared_by: &HashSet<UserId>,
+    blocked_user_ids: &HashSet<UserId>,
     pool: &ConnectionPool,
 ) -> proto::UpdateContacts {
     let mut update = proto::UpdateContacts::default();
+    update.contact_notes = contact_notes
+        .iter()
+        .map(|contact_note| contact_note.to_proto())
+        .collect();
+    update.location_sharing = location_shared_with
+        .iter()
+        .map(|user_id| proto::LocationSharing {
+            user_id: user_id.to_proto(),
+            enabled: true,
+        })
+        .collect();
+    update.blocked_users = blocked_user_ids
+        .iter()
+        .map(|user_id| proto::BlockedUser {
+            user_id: user_id.to_proto(),
+            blocked: true,
+        })
+        .collect();
 
     for contact in contacts {
         match contact {
             db::Contact::Accepted { user_id, busy } => {
-                update.contacts.push(contact_for_user(user_id, busy, &pool));
+                let mut contact = contact_for_user(
+                    user_id,
+                    busy,
+                    call_connections.get(&user_id).copied(),
+                    &pool,
+                );
+                if location_shared_by.contains(&user_id) {
+                    contact.working_in = pool.user_working_location(user_id).map(str::to_string);
+                }
+                update.contacts.push(contact);
             }
             db::Contact::Outgoing { user_id } => update.outgoing_requests.push(user_id.to_proto()),
             db::Contact::Incoming { user_id } => {
@@ -3409,14 +6310,49 @@ fn build_initial_contacts_update(
     update
 }
 
-fn contact_for_user(user_id: UserId, busy: bool, pool: &ConnectionPool) -> proto::Contact {
+/// Describes a contact's presence across all of their connected devices. The contact is
+/// online if any of their devices is connected, and busy if they are in a call on any of
+/// them or are being called. `call_connection` is the connection on which they answered
+/// the call they're in, if any.
+fn contact_for_user(
+    user_id: UserId,
+    busy: bool,
+    call_connection: Option<ConnectionId>,
+    pool: &ConnectionPool,
+) -> proto::Contact {
+    let devices = pool
+        .user_connection_ids(user_id)
+        .map(|connection_id| proto::ContactDevice {
+            peer_id: Some(connection_id.into()),
+            busy: Some(connection_id) == call_connection,
+        })
+        .collect::<Vec<_>>();
     proto::Contact {
         user_id: user_id.to_proto(),
-        online: pool.is_user_online(user_id),
-        busy,
+        online: !devices.is_empty(),
+        busy: busy || devices.iter().any(|device| device.busy),
+        devices,
+        // The project a contact is working in is only revealed to the recipients
+        // they've shared it with, by `contact_for_recipient`.
+        working_in: None,
     }
 }
 
+/// Reveals the project a contact is working in to a recipient the contact has chosen
+/// to share it with.
+fn contact_for_recipient(
+    contact: &proto::Contact,
+    recipient_id: UserId,
+    location_shared_with: &HashSet<UserId>,
+    working_location: Option<&str>,
+) -> proto::Contact {
+    let mut contact = contact.clone();
+    if location_shared_with.contains(&recipient_id) {
+        contact.working_in = working_location.map(str::to_string);
+    }
+    contact
+}
+
 fn room_updated(room: &proto::Room, peer: &Peer) {
     broadcast(
         None,
@@ -3427,13 +6363,44 @@ fn room_updated(room: &proto::Room, peer: &Peer) {
             peer.send(
                 peer_id.into(),
                 proto::RoomUpdated {
-                    room: Some(room.clone()),
+                    room: Some(room_seen_by(room, peer_id)),
                 },
             )
         },
     );
 }
 
+/// The room as the participant on the given connection is allowed to see it.
+/// Observers are left out, along with whoever they follow or are followed by,
+/// unless that participant is one of the room's admins or an observer too.
+fn room_seen_by(room: &proto::Room, connection_id: ConnectionId) -> proto::Room {
+    let can_see_observers = room.participants.iter().any(|participant| {
+        participant.peer_id.map(ConnectionId::from) == Some(connection_id)
+            && (participant.observer || participant.role() == proto::ChannelRole::Admin)
+    });
+    if can_see_observers {
+        return room.clone();
+    }
+
+    let mut room = room.clone();
+    let observer_peer_ids = room
+        .participants
+        .iter()
+        .filter(|participant| participant.observer)
+        .filter_map(|participant| participant.peer_id)
+        .collect::<Vec<_>>();
+    room.participants
+        .retain(|participant| !participant.observer);
+    room.pending_participants
+        .retain(|participant| !participant.observer);
+    room.followers.retain(|follower| {
+        !observer_peer_ids.iter().any(|peer_id| {
+            follower.leader_id == Some(*peer_id) || follower.follower_id == Some(*peer_id)
+        })
+    });
+    room
+}
+
 fn channel_updated(
     channel_id: ChannelId,
     room: &proto::Room,
@@ -3472,15 +6439,23 @@ async fn update_user_contacts(user_id: UserId, session: &Session) -> Result<()>
 
     let contacts = db.get_contacts(user_id).await?;
     let busy = db.is_user_busy(user_id).await?;
+    let mut call_connections = db.call_connections(&[user_id]).await?;
+    let location_shared_with = db.location_shared_with(user_id).await?;
 
     let pool = session.connection_pool().await;
-    let updated_contact = contact_for_user(user_id, busy, &pool);
+    let updated_contact = contact_for_user(user_id, busy, call_connections.remove(&user_id), &pool);
     for contact in contacts {
         if let db::Contact::Accepted {
             user_id: contact_user_id,
             ..
         } = contact
         {
+            let updated_contact = contact_for_recipient(
+                &updated_contact,
+                contact_user_id,
+                &location_shared_with,
+                pool.user_working_location(user_id),
+            );
             for contact_conn_id in pool.user_connection_ids(contact_user_id) {
                 session
                     .peer
@@ -3493,6 +6468,9 @@ async fn update_user_contacts(user_id: UserId, session: &Session) -> Result<()>
                             remove_incoming_requests: Default::default(),
                             outgoing_requests: Default::default(),
                             remove_outgoing_requests: Default::default(),
+                            contact_notes: Default::default(),
+                            location_sharing: Default::default(),
+                            blocked_users: Default::default(),
                         },
                     )
                     .trace_err();
@@ -3503,35 +6481,46 @@ async fn update_user_contacts(user_id: UserId, session: &Session) -> Result<()>
 }
 
 async fn leave_room_for_session(session: &Session) -> Result<()> {
-    let mut contacts_to_update = HashSet::default();
-
-    let room_id;
-    let canceled_calls_to_user_ids;
-    let live_kit_room;
-    let delete_live_kit_room;
-    let room;
-    let channel_members;
-    let channel_id;
-
-    if let Some(mut left_room) = session.db().await.leave_room(session.connection_id).await? {
-        contacts_to_update.insert(session.user_id);
+    let left_room =
+        if let Some(left_room) = session.db().await.leave_room(session.connection_id).await? {
+            room_updated(&left_room.room, &session.peer);
+            left_room.into_inner()
+        } else {
+            return Ok(());
+        };
+    room_left(left_room, session).await
+}
 
-        for project in left_room.left_projects.values() {
-            project_left(project, session);
-        }
+/// Notifies everyone affected by the session's user leaving a room, once the room's
+/// remaining participants have been sent its new state.
+async fn room_left(mut left_room: db::LeftRoom, session: &Session) -> Result<()> {
+    let mut contacts_to_update = HashSet::default();
+    contacts_to_update.insert(session.user_id);
 
-        room_id = RoomId::from_proto(left_room.room.id);
-        canceled_calls_to_user_ids = mem::take(&mut left_room.canceled_calls_to_user_ids);
-        live_kit_room = mem::take(&mut left_room.room.live_kit_room);
-        delete_live_kit_room = left_room.deleted;
-        room = mem::take(&mut left_room.room);
-        channel_members = mem::take(&mut left_room.channel_members);
-        channel_id = left_room.channel_id;
+    for project in left_room.left_projects.values() {
+        project_left(project, session);
+    }
 
-        room_updated(&room, &session.peer);
-    } else {
-        return Ok(());
+    let room_id = RoomId::from_proto(left_room.room.id);
+    session.audio_routes.forget_room(room_id);
+    session.webhooks.send(WebhookEvent::UserLeft {
+        room_id,
+        channel_id: left_room.channel_id,
+        user_id: session.user_id,
+    });
+    if left_room.deleted {
+        session.webhooks.send(WebhookEvent::RoomEnded {
+            room_id,
+            channel_id: left_room.channel_id,
+        });
     }
+    let canceled_calls_to_user_ids = mem::take(&mut left_room.canceled_calls_to_user_ids);
+    let live_kit_room = mem::take(&mut left_room.room.live_kit_room);
+    let delete_live_kit_room = left_room.deleted;
+    let room = mem::take(&mut left_room.room);
+    let channel_members = mem::take(&mut left_room.channel_members);
+    let channel_id = left_room.channel_id;
+    let notifications = mem::take(&mut left_room.notifications);
 
     if let Some(channel_id) = channel_id {
         channel_updated(
@@ -3559,6 +6548,7 @@ async fn leave_room_for_session(session: &Session) -> Result<()> {
             }
             contacts_to_update.insert(canceled_user_id);
         }
+        send_notifications(&*pool, &session.peer, notifications);
     }
 
     for contact_user_id in contacts_to_update {
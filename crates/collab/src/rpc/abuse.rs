@@ -0,0 +1,140 @@
+use rpc::{ErrorCode, ErrorCodeExt as _};
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// How far back reports count against the user they're about.
+pub const REPORT_WINDOW: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How many invites a throttled user can send in [`INVITE_WINDOW`].
+pub const THROTTLED_INVITE_LIMIT: usize = 5;
+
+/// The window that [`THROTTLED_INVITE_LIMIT`] applies to.
+pub const INVITE_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// What a user who has been reported is kept from doing. Each level restricts the
+/// user more than the one before.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReportThrottle {
+    #[default]
+    None,
+    /// The user can only send a few invites an hour.
+    ThrottleInvites,
+    /// The user can't join public channels they aren't a member of, either.
+    BlockPublicJoins,
+}
+
+/// How many users have to report someone before they're throttled. A threshold of
+/// zero is never reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReportPolicy {
+    pub throttle_invites_at: usize,
+    pub block_public_joins_at: usize,
+}
+
+impl Default for ReportPolicy {
+    fn default() -> Self {
+        Self {
+            throttle_invites_at: 3,
+            block_public_joins_at: 5,
+        }
+    }
+}
+
+impl ReportPolicy {
+    /// The throttle for a user who was reported by the given number of users.
+    pub fn throttle(&self, report_count: usize) -> ReportThrottle {
+        let reached = |threshold: usize| threshold > 0 && report_count >= threshold;
+        if reached(self.block_public_joins_at) {
+            ReportThrottle::BlockPublicJoins
+        } else if reached(self.throttle_invites_at) {
+            ReportThrottle::ThrottleInvites
+        } else {
+            ReportThrottle::None
+        }
+    }
+}
+
+/// The window that [`ContactRequestPolicy`]'s limits apply to.
+pub const CONTACT_REQUEST_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// How many contact requests a user can send the same person in
+/// [`CONTACT_REQUEST_WINDOW`], such as after they decline or the request is cancelled.
+pub const REPEATED_CONTACT_REQUEST_LIMIT: usize = 2;
+
+/// How many contact requests each user can send in [`CONTACT_REQUEST_WINDOW`]. A limit
+/// of zero is never reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContactRequestPolicy {
+    /// Requests past this many are rejected, and the sender is flagged for admins to
+    /// review.
+    pub max_per_hour: usize,
+    /// Requests past this many can only be sent to users the sender has interacted
+    /// with.
+    pub max_to_strangers_per_hour: usize,
+}
+
+impl Default for ContactRequestPolicy {
+    fn default() -> Self {
+        Self {
+            max_per_hour: 30,
+            max_to_strangers_per_hour: 10,
+        }
+    }
+}
+
+/// How long until a contact request sent at `sent_at` no longer counts towards the
+/// sender's limits.
+pub fn contact_request_expires_in(sent_at: OffsetDateTime, now: OffsetDateTime) -> Duration {
+    let age = (now - sent_at).try_into().unwrap_or_default();
+    CONTACT_REQUEST_WINDOW.saturating_sub(age)
+}
+
+/// The error that throttled users' requests are rejected with.
+pub fn throttled_error(throttle: ReportThrottle, message_name: &str) -> anyhow::Error {
+    ErrorCode::Forbidden
+        .message(format!(
+            "your account has been reported, so {message_name} is restricted"
+        ))
+        .with_tag("throttle", &format!("{throttle:?}"))
+        .anyhow()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_throttle() {
+        let policy = ReportPolicy {
+            throttle_invites_at: 2,
+            block_public_joins_at: 4,
+        };
+        assert_eq!(policy.throttle(1), ReportThrottle::None);
+        assert_eq!(policy.throttle(3), ReportThrottle::ThrottleInvites);
+        assert_eq!(policy.throttle(4), ReportThrottle::BlockPublicJoins);
+
+        // Thresholds of zero are ignored.
+        let policy = ReportPolicy {
+            throttle_invites_at: 0,
+            ..policy
+        };
+        assert_eq!(policy.throttle(3), ReportThrottle::None);
+    }
+
+    #[test]
+    fn test_contact_request_expires_in() {
+        let now = OffsetDateTime::now_utc();
+        assert_eq!(
+            contact_request_expires_in(now - Duration::from_secs(60), now),
+            CONTACT_REQUEST_WINDOW - Duration::from_secs(60)
+        );
+        assert_eq!(
+            contact_request_expires_in(now - CONTACT_REQUEST_WINDOW, now),
+            Duration::ZERO
+        );
+        assert_eq!(
+            contact_request_expires_in(now + Duration::from_secs(60), now),
+            CONTACT_REQUEST_WINDOW
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use crate::db::RoomId;
+use collections::{HashMap, HashSet};
+use parking_lot::Mutex;
+use rpc::ConnectionId;
+use std::sync::Arc;
+
+/// How many frames a speaker relays before the connections that hear them are looked
+/// up in the database again, which is about once a second. Looking them up for every
+/// frame would query the database fifty times a second for each speaker.
+pub const FRAMES_PER_ROUTE_LOOKUP: u32 = 50;
+
+/// The connections that hear each speaker's relayed audio, as of the last time they
+/// were looked up. Routes are forgotten when a room's participants leave or change
+/// roles, and otherwise looked up again every [`FRAMES_PER_ROUTE_LOOKUP`] frames, which
+/// is how long someone who joins takes to start hearing the speaker.
+#[derive(Default)]
+pub struct AudioRoutes(Mutex<HashMap<ConnectionId, Route>>);
+
+struct Route {
+    room_id: RoomId,
+    listeners: Arc<HashSet<ConnectionId>>,
+    frames_until_lookup: u32,
+}
+
+impl AudioRoutes {
+    /// Returns who hears the speaker on the given connection in the given room, or
+    /// `None` if they need to be looked up.
+    pub fn listeners(
+        &self,
+        speaker: ConnectionId,
+        room_id: RoomId,
+    ) -> Option<Arc<HashSet<ConnectionId>>> {
+        let mut routes = self.0.lock();
+        let route = routes.get_mut(&speaker)?;
+        if route.room_id != room_id || route.frames_until_lookup == 0 {
+            return None;
+        }
+        route.frames_until_lookup -= 1;
+        Some(route.listeners.clone())
+    }
+
+    /// Remembers who was just looked up as hearing the given speaker.
+    pub fn insert(
+        &self,
+        speaker: ConnectionId,
+        room_id: RoomId,
+        listeners: HashSet<ConnectionId>,
+    ) -> Arc<HashSet<ConnectionId>> {
+        let listeners = Arc::new(listeners);
+        self.0.lock().insert(
+            speaker,
+            Route {
+                room_id,
+                listeners: listeners.clone(),
+                frames_until_lookup: FRAMES_PER_ROUTE_LOOKUP,
+            },
+        );
+        listeners
+    }
+
+    /// Forgets the routes of every speaker in the given room, so that they're looked
+    /// up again on their next frame.
+    pub fn forget_room(&self, room_id: RoomId) {
+        self.0.lock().retain(|_, route| route.room_id != room_id);
+    }
+
+    /// Forgets the route of a speaker who disconnected.
+    pub fn forget_speaker(&self, speaker: ConnectionId) {
+        self.0.lock().remove(&speaker);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_routes() {
+        let routes = AudioRoutes::default();
+        let room_1 = RoomId(1);
+        let room_2 = RoomId(2);
+        let speaker = ConnectionId { owner_id: 0, id: 1 };
+        let listener = ConnectionId { owner_id: 0, id: 2 };
+        assert_eq!(routes.listeners(speaker, room_1), None);
+
+        // Routes are reused until it's time to look them up again.
+        routes.insert(speaker, room_1, [listener].into_iter().collect());
+        for _ in 0..FRAMES_PER_ROUTE_LOOKUP {
+            assert_eq!(
+                routes.listeners(speaker, room_1).unwrap().as_ref(),
+                &[listener].into_iter().collect::<HashSet<_>>()
+            );
+        }
+        assert_eq!(routes.listeners(speaker, room_1), None);
+
+        // They're only reused for the room they were looked up in, and are forgotten
+        // when that room changes.
+        routes.insert(speaker, room_1, [listener].into_iter().collect());
+        assert_eq!(routes.listeners(speaker, room_2), None);
+        routes.forget_room(room_2);
+        assert!(routes.listeners(speaker, room_1).is_some());
+        routes.forget_room(room_1);
+        assert_eq!(routes.listeners(speaker, room_1), None);
+
+        routes.insert(speaker, room_1, HashSet::default());
+        routes.forget_speaker(speaker);
+        assert_eq!(routes.listeners(speaker, room_1), None);
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::{anyhow, Result};
+use async_tungstenite::tungstenite::Message as WebSocketMessage;
+use collections::HashMap;
+use futures::channel::mpsc;
+use parking_lot::Mutex;
+use rpc::{Connection, ConnectionId};
+
+/// The connections that clients broker for other instances of Zed signed in as the
+/// same user, so that all of a user's instances on a machine share one connection to
+/// the server. Each is keyed by the connection that carries it, and the id that the
+/// broker chose for it.
+#[derive(Default)]
+pub struct BrokeredConnections(
+    Mutex<HashMap<(ConnectionId, u64), mpsc::UnboundedSender<WebSocketMessage>>>,
+);
+
+impl BrokeredConnections {
+    /// Opens a connection carried by the given broker. Returns the connection, and the
+    /// receiver of the messages sent on it, which are relayed to the broker.
+    pub fn open(
+        &self,
+        broker_id: ConnectionId,
+        brokered_connection_id: u64,
+    ) -> Result<(Connection, mpsc::UnboundedReceiver<WebSocketMessage>)> {
+        let mut connections = self.0.lock();
+        let key = (broker_id, brokered_connection_id);
+        if connections.contains_key(&key) {
+            return Err(anyhow!(
+                "brokered connection {brokered_connection_id} is already open"
+            ));
+        }
+        let (connection, incoming_tx, outgoing_rx) = Connection::tunneled();
+        connections.insert(key, incoming_tx);
+        Ok((connection, outgoing_rx))
+    }
+
+    /// Delivers a message that the broker received on one of the connections it carries.
+    pub fn deliver(
+        &self,
+        broker_id: ConnectionId,
+        brokered_connection_id: u64,
+        message: WebSocketMessage,
+    ) -> Result<()> {
+        let connections = self.0.lock();
+        let incoming_tx = connections
+            .get(&(broker_id, brokered_connection_id))
+            .ok_or_else(|| anyhow!("no such brokered connection"))?;
+        incoming_tx.unbounded_send(message)?;
+        Ok(())
+    }
+
+    /// Closes a connection, once its instance of Zed has hung up on the broker.
+    pub fn close(&self, broker_id: ConnectionId, brokered_connection_id: u64) {
+        self.0.lock().remove(&(broker_id, brokered_connection_id));
+    }
+
+    /// Closes every connection a broker carries, once its own connection is lost.
+    pub fn close_all(&self, broker_id: ConnectionId) {
+        self.0.lock().retain(|(id, _), _| *id != broker_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt as _;
+
+    #[test]
+    fn test_brokered_connections() {
+        let connections = BrokeredConnections::default();
+        let broker_1 = ConnectionId { owner_id: 0, id: 1 };
+        let broker_2 = ConnectionId { owner_id: 0, id: 2 };
+
+        let (connection_1, mut outgoing_rx_1) = connections.open(broker_1, 1).unwrap();
+        let (connection_2, mut outgoing_rx_2) = connections.open(broker_2, 1).unwrap();
+        assert!(connections.open(broker_1, 1).is_err());
+
+        // Messages are only delivered on connections the broker carries.
+        connections
+            .deliver(broker_1, 1, WebSocketMessage::Binary(vec![1]))
+            .unwrap();
+        assert!(connections
+            .deliver(broker_1, 2, WebSocketMessage::Binary(vec![2]))
+            .is_err());
+
+        // Closing a connection ends the stream of messages relayed from it, once the
+        // connection itself has been dropped.
+        connections.close(broker_1, 1);
+        assert!(connections
+            .deliver(broker_1, 1, WebSocketMessage::Binary(vec![3]))
+            .is_err());
+        drop(connection_1);
+        assert_eq!(futures::executor::block_on(outgoing_rx_1.next()), None);
+
+        connections.close_all(broker_2);
+        assert!(connections
+            .deliver(broker_2, 1, WebSocketMessage::Binary(vec![4]))
+            .is_err());
+        drop(connection_2);
+        assert_eq!(futures::executor::block_on(outgoing_rx_2.next()), None);
+    }
+}
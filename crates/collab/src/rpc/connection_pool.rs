@@ -1,6 +1,6 @@
-use crate::db::UserId;
+use crate::db::{ProjectId, UserId};
 use anyhow::{anyhow, Result};
-use collections::{BTreeMap, HashSet};
+use collections::{BTreeMap, HashMap, HashSet};
 use rpc::ConnectionId;
 use serde::Serialize;
 use tracing::instrument;
@@ -9,6 +9,12 @@ use tracing::instrument;
 pub struct ConnectionPool {
     connections: BTreeMap<ConnectionId, Connection>,
     connected_users: BTreeMap<UserId, ConnectedUser>,
+    /// The connections of the other servers in the deployment, which are announced over
+    /// the message bus. Messages sent to them are routed to their servers.
+    #[serde(skip)]
+    remote_connections: HashMap<ConnectionId, UserId>,
+    #[serde(skip)]
+    remote_connected_users: HashMap<UserId, HashSet<ConnectionId>>,
 }
 
 #[derive(Default, Serialize)]
@@ -20,18 +26,34 @@ struct ConnectedUser {
 pub struct Connection {
     pub user_id: UserId,
     pub admin: bool,
+    /// The name of the project the user is working in on this connection, as
+    /// reported by their client.
+    pub working_location: Option<String>,
+    /// The directories that have been sent on this connection, for the worktrees
+    /// whose entries it loads lazily, keyed by project and worktree id.
+    #[serde(skip)]
+    loaded_directories: HashMap<(ProjectId, u64), HashSet<String>>,
 }
 
 impl ConnectionPool {
     pub fn reset(&mut self) {
         self.connections.clear();
         self.connected_users.clear();
+        self.remote_connections.clear();
+        self.remote_connected_users.clear();
     }
 
     #[instrument(skip(self))]
     pub fn add_connection(&mut self, connection_id: ConnectionId, user_id: UserId, admin: bool) {
-        self.connections
-            .insert(connection_id, Connection { user_id, admin });
+        self.connections.insert(
+            connection_id,
+            Connection {
+                user_id,
+                admin,
+                working_location: None,
+                loaded_directories: Default::default(),
+            },
+        );
         let connected_user = self.connected_users.entry(user_id).or_default();
         connected_user.connection_ids.insert(connection_id);
     }
@@ -53,26 +75,155 @@ impl ConnectionPool {
         Ok(())
     }
 
+    pub fn add_remote_connection(&mut self, connection_id: ConnectionId, user_id: UserId) {
+        self.remote_connections.insert(connection_id, user_id);
+        self.remote_connected_users
+            .entry(user_id)
+            .or_default()
+            .insert(connection_id);
+    }
+
+    pub fn remove_remote_connection(&mut self, connection_id: ConnectionId) {
+        if let Some(user_id) = self.remote_connections.remove(&connection_id) {
+            if let Some(connection_ids) = self.remote_connected_users.get_mut(&user_id) {
+                connection_ids.remove(&connection_id);
+                if connection_ids.is_empty() {
+                    self.remote_connected_users.remove(&user_id);
+                }
+            }
+        }
+    }
+
+    /// Forgets the connections of a server that has stopped.
+    pub fn remove_remote_server(&mut self, server_id: u32) {
+        let connection_ids = self
+            .remote_connections
+            .keys()
+            .filter(|connection_id| connection_id.owner_id == server_id)
+            .copied()
+            .collect::<Vec<_>>();
+        for connection_id in connection_ids {
+            self.remove_remote_connection(connection_id);
+        }
+    }
+
+    pub fn set_working_location(&mut self, connection_id: ConnectionId, name: Option<String>) {
+        if let Some(connection) = self.connections.get_mut(&connection_id) {
+            connection.working_location = name;
+        }
+    }
+
+    /// Records that the children of the given directories were sent on the given
+    /// connection, which loads the worktree's entries lazily from now on.
+    pub fn load_directories(
+        &mut self,
+        connection_id: ConnectionId,
+        project_id: ProjectId,
+        worktree_id: u64,
+        paths: impl IntoIterator<Item = String>,
+    ) {
+        if let Some(connection) = self.connections.get_mut(&connection_id) {
+            connection
+                .loaded_directories
+                .entry((project_id, worktree_id))
+                .or_default()
+                .extend(paths);
+        }
+    }
+
+    /// The directories that have been sent on the given connection, or `None` if
+    /// all of the worktree's entries are sent to it.
+    pub fn loaded_directories(
+        &self,
+        connection_id: ConnectionId,
+        project_id: ProjectId,
+        worktree_id: u64,
+    ) -> Option<&HashSet<String>> {
+        self.connections
+            .get(&connection_id)?
+            .loaded_directories
+            .get(&(project_id, worktree_id))
+    }
+
+    /// Records that all of the worktree's entries were sent on the given connection,
+    /// which receives every update to the worktree from now on.
+    pub fn load_all_directories(
+        &mut self,
+        connection_id: ConnectionId,
+        project_id: ProjectId,
+        worktree_id: u64,
+    ) {
+        if let Some(connection) = self.connections.get_mut(&connection_id) {
+            connection
+                .loaded_directories
+                .remove(&(project_id, worktree_id));
+        }
+    }
+
+    pub fn forget_loaded_directories(
+        &mut self,
+        connection_id: ConnectionId,
+        project_id: ProjectId,
+    ) {
+        if let Some(connection) = self.connections.get_mut(&connection_id) {
+            connection
+                .loaded_directories
+                .retain(|(loaded_project_id, _), _| *loaded_project_id != project_id);
+        }
+    }
+
+    /// Returns the project the user is working in on one of their connections, if any.
+    pub fn user_working_location(&self, user_id: UserId) -> Option<&str> {
+        self.user_connection_ids(user_id).find_map(|connection_id| {
+            self.connections
+                .get(&connection_id)?
+                .working_location
+                .as_deref()
+        })
+    }
+
     pub fn connections(&self) -> impl Iterator<Item = &Connection> {
         self.connections.values()
     }
 
+    pub fn connection(&self, connection_id: ConnectionId) -> Option<&Connection> {
+        self.connections.get(&connection_id)
+    }
+
+    /// Whether the connection is open on any server in the deployment.
+    pub fn is_connected(&self, connection_id: ConnectionId) -> bool {
+        self.connections.contains_key(&connection_id)
+            || self.remote_connections.contains_key(&connection_id)
+    }
+
+    pub fn connection_ids(&self) -> impl Iterator<Item = ConnectionId> + '_ {
+        self.connections.keys().copied()
+    }
+
+    pub fn connected_user_ids(&self) -> impl Iterator<Item = UserId> + '_ {
+        self.connected_users.keys().copied()
+    }
+
+    pub fn user_connection_count(&self, user_id: UserId) -> usize {
+        self.connected_users
+            .get(&user_id)
+            .map_or(0, |state| state.connection_ids.len())
+    }
+
+    /// The user's connections to any server in the deployment.
     pub fn user_connection_ids(&self, user_id: UserId) -> impl Iterator<Item = ConnectionId> + '_ {
         self.connected_users
             .get(&user_id)
             .into_iter()
             .map(|state| &state.connection_ids)
+            .chain(self.remote_connected_users.get(&user_id))
             .flatten()
             .copied()
     }
 
+    /// Whether the user is connected to any server in the deployment.
     pub fn is_user_online(&self, user_id: UserId) -> bool {
-        !self
-            .connected_users
-            .get(&user_id)
-            .unwrap_or(&Default::default())
-            .connection_ids
-            .is_empty()
+        self.user_connection_ids(user_id).next().is_some()
     }
 
     #[cfg(test)]
@@ -94,5 +245,9 @@ impl ConnectionPool {
                 );
             }
         }
+
+        for (connection_id, user_id) in &self.remote_connections {
+            assert!(self.remote_connected_users[user_id].contains(connection_id));
+        }
     }
 }
@@ -0,0 +1,113 @@
+use rpc::{proto, ErrorCode, ErrorCodeExt as _};
+use std::time::Duration;
+
+/// The longest a guest can be invited for.
+pub const MAX_GUEST_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Whether guests can send messages of the given type. Guests can take part in the
+/// room they were invited to and in the projects shared into it, but can't start
+/// calls of their own, host projects, manage contacts or channels, or invite other
+/// guests. Messages that aren't listed here are denied, so new messages have to be
+/// allowed explicitly.
+pub fn is_allowed(message_name: &str) -> bool {
+    matches!(
+        message_name,
+        // Taking part in the room.
+        "Ping"
+            | "RoomPreflight"
+            | "JoinRoom"
+            | "RejoinRoom"
+            | "LeaveRoom"
+            | "DeclineCall"
+            | "ReportRoomActivity"
+            | "GetCallSummary"
+            | "UpdateParticipantLocation"
+            | "UpdateScreenAnnotations"
+            | "SendRoomMessage"
+            | "GetRoomMessages"
+            | "RelayAudioFrame"
+            | "Follow"
+            | "Unfollow"
+            | "UpdateFollowers"
+            // Joining the room's projects. Whether a guest can edit them is checked
+            // against their role when the requests are forwarded to the host.
+            | "GetRoomProjects"
+            | "JoinProject"
+            | "LeaveProject"
+            | "UpdateBuffer"
+            | "LoadWorktreeDirectory"
+            | "LoadWorktreeEntries"
+            | "GetHover"
+            | "GetDefinition"
+            | "GetTypeDefinition"
+            | "GetReferences"
+            | "SearchProject"
+            | "GetDocumentHighlights"
+            | "GetLinkedEditingRanges"
+            | "GetLanguageServerConfigurations"
+            | "GetProjectSymbols"
+            | "OpenBufferForSymbol"
+            | "OpenBufferById"
+            | "CloseBuffer"
+            | "SynchronizeBuffers"
+            | "InlayHints"
+            | "OpenBufferByPath"
+            | "GetCompletions"
+            | "ApplyCompletionAdditionalEdits"
+            | "ResolveCompletionDocumentation"
+            | "GetCodeActions"
+            | "ApplyCodeAction"
+            | "PrepareRename"
+            | "PerformRename"
+            | "ReloadBuffers"
+            | "FormatBuffers"
+            | "CreateProjectEntry"
+            | "RenameProjectEntry"
+            | "CopyProjectEntry"
+            | "DeleteProjectEntry"
+            | "ExpandProjectEntry"
+            | "OnTypeFormatting"
+            | "SaveBuffer"
+            | "AcknowledgeBufferVersion"
+            | "AddBookmark"
+            | "RemoveBookmark"
+            | "GetBookmarks"
+            | "GetEntryDecorations"
+            | "AddReviewComment"
+            | "ResolveReviewComment"
+            | "GetReview"
+            | "SuggestEdit"
+            | "GetEditSuggestions"
+            // Signing in.
+            | "GetUsers"
+            | "GetPrivateUserInfo"
+            | "GetNotifications"
+            | "MarkNotificationAsRead"
+    )
+}
+
+/// The error that guests' requests are rejected with when they aren't allowed.
+pub fn forbidden_error(message_name: &str) -> proto::Error {
+    ErrorCode::Forbidden
+        .message(format!("guests cannot send {message_name}"))
+        .to_proto()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guest_messages() {
+        assert!(is_allowed("JoinRoom"));
+        assert!(is_allowed("JoinProject"));
+        assert!(is_allowed("UpdateBuffer"));
+        assert!(!is_allowed("ShareProject"));
+        assert!(!is_allowed("UpdateWorktree"));
+        assert!(!is_allowed("CreateRoom"));
+        assert!(!is_allowed("RequestContact"));
+        assert!(!is_allowed("CreateGuest"));
+        assert!(!is_allowed("ReportUser"));
+        assert!(!is_allowed("SomeFutureMessage"));
+    }
+}
@@ -0,0 +1,234 @@
+use parking_lot::Mutex;
+use rpc::{proto, ErrorCode, ErrorCodeExt as _, ErrorExt as _};
+use serde::Serialize;
+use std::{any::TypeId, time::Duration};
+
+/// How overloaded a server is. Each level turns away more work than the one before,
+/// so that the rooms already in progress keep working for as long as possible.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum OverloadLevel {
+    #[default]
+    Normal,
+    /// No new rooms are created.
+    RejectRoomCreation,
+    /// Nobody joins a room, channel or project they aren't already in.
+    RejectJoins,
+    /// Messages that collaboration can carry on without are turned away too.
+    ShedNonEssential,
+}
+
+impl OverloadLevel {
+    /// The level at which the server stops handling messages of the given type, if it
+    /// ever does.
+    pub fn shedding<M: 'static>() -> Option<Self> {
+        let is_one_of = |message_types: &[TypeId]| message_types.contains(&TypeId::of::<M>());
+        if is_one_of(&[TypeId::of::<proto::CreateRoom>()]) {
+            Some(Self::RejectRoomCreation)
+        } else if is_one_of(&[
+            TypeId::of::<proto::JoinRoom>(),
+            TypeId::of::<proto::AcceptWaitingCall>(),
+            TypeId::of::<proto::Call>(),
+            TypeId::of::<proto::JoinChannel>(),
+            TypeId::of::<proto::JoinProject>(),
+            TypeId::of::<proto::JoinChannelBuffer>(),
+            TypeId::of::<proto::JoinChannelChat>(),
+        ]) {
+            Some(Self::RejectJoins)
+        } else if is_one_of(&[
+            TypeId::of::<proto::RoomPreflight>(),
+            TypeId::of::<proto::ReportRoomActivity>(),
+            TypeId::of::<proto::GetCallHistory>(),
+            TypeId::of::<proto::GetCallSummary>(),
+            TypeId::of::<proto::GetRoomMessages>(),
+            TypeId::of::<proto::FuzzySearchUsers>(),
+            TypeId::of::<proto::UpdateScreenAnnotations>(),
+            TypeId::of::<proto::UpdateWorkingLocation>(),
+        ]) {
+            Some(Self::ShedNonEssential)
+        } else {
+            None
+        }
+    }
+
+    /// How many times over its limits the server has to be to reach this level.
+    fn load_threshold(self) -> f64 {
+        match self {
+            Self::Normal => 0.,
+            Self::RejectRoomCreation => 1.,
+            Self::RejectJoins => 2.,
+            Self::ShedNonEssential => 4.,
+        }
+    }
+}
+
+/// The fraction of a level's load that the load has to fall below for the server to
+/// leave that level.
+const RECOVERY_RATIO: f64 = 0.75;
+
+/// When a server counts as overloaded. A limit of zero is never exceeded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OverloadPolicy {
+    /// How late the server's timers can fire, which measures how far its event loop
+    /// has fallen behind.
+    pub max_event_loop_lag: Duration,
+    /// How long it can take to check out a database connection and ping the database
+    /// on it, which grows as the connection pool runs out.
+    pub max_db_latency: Duration,
+}
+
+impl Default for OverloadPolicy {
+    fn default() -> Self {
+        Self {
+            max_event_loop_lag: Duration::from_millis(250),
+            max_db_latency: Duration::from_secs(1),
+        }
+    }
+}
+
+impl OverloadPolicy {
+    /// The level the server is at given its latest measurements and the level it was
+    /// at before. The first level is reached once either limit is exceeded, and each
+    /// level after that once it's exceeded twice over again. A level is only left once
+    /// the load falls below [`RECOVERY_RATIO`] of the load it's reached at, so that
+    /// servers whose load hovers around a threshold don't keep changing levels.
+    pub fn level(
+        &self,
+        previous_level: OverloadLevel,
+        event_loop_lag: Duration,
+        db_latency: Duration,
+    ) -> OverloadLevel {
+        let load = f64::max(
+            load(event_loop_lag, self.max_event_loop_lag),
+            load(db_latency, self.max_db_latency),
+        );
+        [
+            OverloadLevel::ShedNonEssential,
+            OverloadLevel::RejectJoins,
+            OverloadLevel::RejectRoomCreation,
+        ]
+        .into_iter()
+        .find(|level| {
+            let threshold = if *level <= previous_level {
+                level.load_threshold() * RECOVERY_RATIO
+            } else {
+                level.load_threshold()
+            };
+            load >= threshold
+        })
+        .unwrap_or(OverloadLevel::Normal)
+    }
+}
+
+fn load(measured: Duration, limit: Duration) -> f64 {
+    if limit.is_zero() {
+        0.
+    } else {
+        measured.as_secs_f64() / limit.as_secs_f64()
+    }
+}
+
+/// The level a server is at, which can be simulated in tests.
+#[derive(Default)]
+pub struct OverloadState {
+    measured: Mutex<OverloadLevel>,
+    simulated: Mutex<Option<OverloadLevel>>,
+}
+
+impl OverloadState {
+    pub fn level(&self) -> OverloadLevel {
+        self.simulated.lock().unwrap_or(*self.measured.lock())
+    }
+
+    /// The level last measured, ignoring any simulated level.
+    pub fn measured(&self) -> OverloadLevel {
+        *self.measured.lock()
+    }
+
+    /// Records a new measurement, returning the level it replaced.
+    pub fn set_measured(&self, level: OverloadLevel) -> OverloadLevel {
+        std::mem::replace(&mut *self.measured.lock(), level)
+    }
+
+    /// Holds the server at the given level regardless of what's measured, until this
+    /// is called again with `None`.
+    pub fn simulate(&self, level: Option<OverloadLevel>) {
+        *self.simulated.lock() = level;
+    }
+}
+
+/// The error that requests are rejected with while the server is shedding them.
+pub fn overloaded_error(level: OverloadLevel, message_name: &str) -> proto::Error {
+    ErrorCode::Overloaded
+        .message(format!(
+            "server is overloaded, so {message_name} is unavailable"
+        ))
+        .with_tag("level", &format!("{level:?}"))
+        .to_proto()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overload_level() {
+        let policy = OverloadPolicy {
+            max_event_loop_lag: Duration::from_millis(100),
+            max_db_latency: Duration::from_millis(500),
+        };
+        let ms = Duration::from_millis;
+        let normal = OverloadLevel::Normal;
+        assert_eq!(policy.level(normal, ms(50), ms(100)), OverloadLevel::Normal);
+        assert_eq!(
+            policy.level(normal, ms(100), ms(0)),
+            OverloadLevel::RejectRoomCreation
+        );
+        assert_eq!(
+            policy.level(normal, ms(0), ms(1000)),
+            OverloadLevel::RejectJoins
+        );
+        assert_eq!(
+            policy.level(normal, ms(150), ms(1500)),
+            OverloadLevel::RejectJoins
+        );
+        assert_eq!(
+            policy.level(normal, ms(400), ms(0)),
+            OverloadLevel::ShedNonEssential
+        );
+
+        // Levels are left only once the load falls well below where they start.
+        let shedding = OverloadLevel::ShedNonEssential;
+        assert_eq!(
+            policy.level(shedding, ms(350), ms(0)),
+            OverloadLevel::ShedNonEssential
+        );
+        assert_eq!(
+            policy.level(shedding, ms(160), ms(0)),
+            OverloadLevel::RejectJoins
+        );
+        assert_eq!(
+            policy.level(shedding, ms(80), ms(0)),
+            OverloadLevel::RejectRoomCreation
+        );
+        assert_eq!(policy.level(shedding, ms(70), ms(0)), OverloadLevel::Normal);
+        assert_eq!(
+            policy.level(OverloadLevel::RejectJoins, ms(350), ms(0)),
+            OverloadLevel::RejectJoins
+        );
+
+        // Limits of zero are ignored.
+        let policy = OverloadPolicy {
+            max_event_loop_lag: Duration::ZERO,
+            ..policy
+        };
+        assert_eq!(policy.level(normal, ms(1000), ms(0)), OverloadLevel::Normal);
+
+        // Simulated levels take precedence over measured ones.
+        let state = OverloadState::default();
+        state.set_measured(OverloadLevel::RejectJoins);
+        state.simulate(Some(OverloadLevel::Normal));
+        assert_eq!(state.level(), OverloadLevel::Normal);
+        state.simulate(None);
+        assert_eq!(state.level(), OverloadLevel::RejectJoins);
+    }
+}
@@ -0,0 +1,52 @@
+use rpc::{quota_exceeded_error, ErrorCode};
+
+/// How much each user and room can take up. A quota of zero is never reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuotaPolicy {
+    /// How many connections each user can have at once, across all servers.
+    pub max_connections_per_user: usize,
+    /// How many projects can be shared in each room at once.
+    pub max_shared_projects_per_room: usize,
+    /// How many users can be in each room at once, counting those being called.
+    pub max_room_participants: usize,
+}
+
+impl Default for QuotaPolicy {
+    fn default() -> Self {
+        Self {
+            max_connections_per_user: 10,
+            max_shared_projects_per_room: 20,
+            max_room_participants: 50,
+        }
+    }
+}
+
+/// Whether there's no room for one more of something under the given quota.
+pub fn reached(quota: usize, count: usize) -> bool {
+    quota > 0 && count >= quota
+}
+
+/// Fails with the given error code if there's no room for one more of something
+/// under the given quota.
+pub fn check(code: ErrorCode, quota: usize, count: usize) -> anyhow::Result<()> {
+    if reached(quota, count) {
+        Err(quota_exceeded_error(code, quota))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quotas() {
+        assert!(!reached(2, 1));
+        assert!(reached(2, 2));
+        assert!(!reached(0, 100));
+
+        check(ErrorCode::RoomFull, 2, 1).unwrap();
+        let error = check(ErrorCode::RoomFull, 2, 2).unwrap_err();
+        assert_eq!(rpc::exceeded_quota(&error), Some(2));
+    }
+}
@@ -0,0 +1,254 @@
+use crate::db::RoomId;
+use collections::HashMap;
+use parking_lot::Mutex;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// How often the timers of time-boxed rooms are checked, which is how precisely they
+/// fire.
+pub const ROOM_TIMER_TICK: Duration = Duration::from_secs(1);
+
+/// The longest a time-boxed room can be created or extended for at once.
+pub const MAX_ROOM_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long before a time-boxed room ends its participants are warned that it will.
+pub const COUNTDOWN_WARNINGS: [Duration; 3] = [
+    Duration::from_secs(5 * 60),
+    Duration::from_secs(60),
+    Duration::from_secs(10),
+];
+
+/// The number of ticks the wheel holds before it wraps around. Timers further out
+/// than that stay in their slot for more than one turn.
+const SLOT_COUNT: u64 = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RoomTimerEvent {
+    /// The room, which is scheduled to end at `ends_at`, ends in `remaining`.
+    Countdown {
+        room_id: RoomId,
+        ends_at: OffsetDateTime,
+        remaining: Duration,
+    },
+    /// The room has reached `ends_at`, which is when it was scheduled to end.
+    End {
+        room_id: RoomId,
+        ends_at: OffsetDateTime,
+    },
+}
+
+impl RoomTimerEvent {
+    fn room_id(&self) -> RoomId {
+        match self {
+            Self::Countdown { room_id, .. } | Self::End { room_id, .. } => *room_id,
+        }
+    }
+}
+
+/// A timer wheel for the countdowns of the time-boxed rooms this server is keeping
+/// track of.
+///
+/// Timers are due at readings of the server's monotonic clock, worked out from when
+/// their rooms end. Each tick checks every slot up to the clock's current reading,
+/// so that time spent between ticks delays timers by at most a tick, rather than
+/// adding up.
+pub struct RoomTimers(Mutex<Wheel>);
+
+struct Wheel {
+    /// A wall-clock time, and the reading of the server's clock at that time.
+    origin: (OffsetDateTime, Duration),
+    /// The clock's reading as of the latest tick.
+    clock: Duration,
+    slots: Vec<Vec<Timer>>,
+    /// The latest tick whose slot has been checked.
+    current_tick: u64,
+    /// Each room's latest schedule. Timers from earlier ones are dropped when reached.
+    generations: HashMap<RoomId, usize>,
+    next_generation: usize,
+}
+
+struct Timer {
+    due: Duration,
+    generation: usize,
+    event: RoomTimerEvent,
+}
+
+impl RoomTimers {
+    /// Creates the timers of a server whose clock reads `clock` at the wall-clock
+    /// time `now`.
+    pub fn new(now: OffsetDateTime, clock: Duration) -> Self {
+        Self(Mutex::new(Wheel {
+            origin: (now, clock),
+            clock,
+            slots: (0..SLOT_COUNT).map(|_| Vec::new()).collect(),
+            current_tick: ticks_passed(clock),
+            generations: HashMap::default(),
+            next_generation: 0,
+        }))
+    }
+
+    /// Schedules the room to end at `ends_at`, along with the warnings that lead up
+    /// to it. Any earlier schedule for the room is replaced. Rooms whose time is
+    /// already up end on the next tick.
+    pub fn schedule(&self, room_id: RoomId, ends_at: OffsetDateTime) {
+        let mut wheel = self.0.lock();
+        let generation = util::post_inc(&mut wheel.next_generation);
+        wheel.generations.insert(room_id, generation);
+        let (origin_time, origin_clock) = wheel.origin;
+        let ends_in = Duration::try_from(ends_at - origin_time).unwrap_or_default();
+        let due = origin_clock + ends_in;
+        for warning in COUNTDOWN_WARNINGS {
+            if let Some(warning_due) = due.checked_sub(warning) {
+                if warning_due > wheel.clock {
+                    wheel.insert(
+                        warning_due,
+                        generation,
+                        RoomTimerEvent::Countdown {
+                            room_id,
+                            ends_at,
+                            remaining: warning,
+                        },
+                    );
+                }
+            }
+        }
+        wheel.insert(due, generation, RoomTimerEvent::End { room_id, ends_at });
+    }
+
+    /// Turns the wheel to the clock's current reading, returning the events that
+    /// are due, in the order they were due.
+    pub fn tick(&self, clock: Duration) -> Vec<RoomTimerEvent> {
+        let mut wheel = self.0.lock();
+        let wheel = &mut *wheel;
+        wheel.clock = wheel.clock.max(clock);
+        let target_tick = ticks_passed(wheel.clock);
+        let mut due = Vec::new();
+        for tick in (wheel.current_tick + 1..=target_tick).take(SLOT_COUNT as usize) {
+            let slot = &mut wheel.slots[(tick % SLOT_COUNT) as usize];
+            slot.retain(|timer| {
+                let room_id = timer.event.room_id();
+                if wheel.generations.get(&room_id) != Some(&timer.generation) {
+                    false
+                } else if timer.due <= wheel.clock {
+                    due.push((timer.due, timer.event.clone()));
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+        wheel.current_tick = wheel.current_tick.max(target_tick);
+
+        due.sort_by_key(|(due, _)| *due);
+        for (_, event) in &due {
+            if let RoomTimerEvent::End { room_id, .. } = event {
+                wheel.generations.remove(room_id);
+            }
+        }
+        due.into_iter().map(|(_, event)| event).collect()
+    }
+}
+
+impl Wheel {
+    fn insert(&mut self, due: Duration, generation: usize, event: RoomTimerEvent) {
+        let tick = tick_for(due).max(self.current_tick + 1);
+        self.slots[(tick % SLOT_COUNT) as usize].push(Timer {
+            due,
+            generation,
+            event,
+        });
+    }
+}
+
+/// The tick in which a timer that's due at the given reading of the clock fires.
+fn tick_for(clock: Duration) -> u64 {
+    let tick_millis = ROOM_TIMER_TICK.as_millis();
+    ((clock.as_millis() + tick_millis - 1) / tick_millis) as u64
+}
+
+/// The last tick that has fully passed at the given reading of the clock, whose
+/// timers are all due.
+fn ticks_passed(clock: Duration) -> u64 {
+    (clock.as_millis() / ROOM_TIMER_TICK.as_millis()) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_room_timers() {
+        let now = OffsetDateTime::UNIX_EPOCH;
+        let timers = RoomTimers::new(now, Duration::ZERO);
+        let room_1 = RoomId(1);
+        let room_2 = RoomId(2);
+        let clock = |secs: u64| Duration::from_secs(secs);
+
+        // Rooms are warned as they approach their end, even when that's further out
+        // than a turn of the wheel.
+        let ends_at_1 = now + Duration::from_secs(90);
+        let ends_at_2 = now + Duration::from_secs(5);
+        timers.schedule(room_1, ends_at_1);
+        timers.schedule(room_2, ends_at_2);
+        assert_eq!(
+            timers.tick(clock(5)),
+            [RoomTimerEvent::End {
+                room_id: room_2,
+                ends_at: ends_at_2
+            }]
+        );
+        assert_eq!(
+            timers.tick(clock(30)),
+            [RoomTimerEvent::Countdown {
+                room_id: room_1,
+                ends_at: ends_at_1,
+                remaining: Duration::from_secs(60)
+            }]
+        );
+
+        // Time spent between ticks doesn't delay the timers that were due during it.
+        assert_eq!(
+            timers.tick(clock(80) + Duration::from_millis(500)),
+            [RoomTimerEvent::Countdown {
+                room_id: room_1,
+                ends_at: ends_at_1,
+                remaining: Duration::from_secs(10)
+            }]
+        );
+
+        // Rescheduling a room replaces the timers it had.
+        let extended_ends_at = ends_at_1 + Duration::from_secs(60);
+        timers.schedule(room_1, extended_ends_at);
+        assert!(timers.tick(clock(89)).is_empty());
+        assert_eq!(
+            timers.tick(clock(200)),
+            [
+                RoomTimerEvent::Countdown {
+                    room_id: room_1,
+                    ends_at: extended_ends_at,
+                    remaining: Duration::from_secs(60)
+                },
+                RoomTimerEvent::Countdown {
+                    room_id: room_1,
+                    ends_at: extended_ends_at,
+                    remaining: Duration::from_secs(10)
+                },
+                RoomTimerEvent::End {
+                    room_id: room_1,
+                    ends_at: extended_ends_at
+                }
+            ]
+        );
+        assert!(timers.tick(clock(300)).is_empty());
+
+        // Rooms whose time is already up end on the next tick.
+        timers.schedule(room_2, ends_at_2);
+        assert_eq!(
+            timers.tick(clock(301)),
+            [RoomTimerEvent::End {
+                room_id: room_2,
+                ends_at: ends_at_2
+            }]
+        );
+    }
+}
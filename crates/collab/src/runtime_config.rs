@@ -0,0 +1,321 @@
+use crate::{
+    rpc::{ContactRequestPolicy, OverloadPolicy, QuotaPolicy, ReportPolicy, REJOIN_WINDOW},
+    Config, Result,
+};
+use anyhow::{anyhow, Context as _};
+use collections::BTreeMap;
+use parking_lot::RwLock;
+use rpc::{BacklogPolicy, RateLimitPolicy};
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+
+/// The operational parameters that can be tuned while servers are running, without
+/// disrupting the rooms they're serving.
+///
+/// Each one starts out with the value of the environment variable that configures
+/// it, which can be overridden for every server in the environment by storing a new
+/// value under the variable's name with [`crate::db::Database::set_runtime_config_override`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuntimeConfig {
+    pub backlog_policy: BacklogPolicy,
+    pub rate_limit_policy: RateLimitPolicy,
+    pub turn_credential_ttl: Duration,
+    pub lazy_worktree_entry_threshold: usize,
+    pub orphan_sweep_interval: Duration,
+    pub reconnect_timeout: Duration,
+    pub cleanup_timeout: Duration,
+    pub feature_flags: Vec<String>,
+    pub overload_policy: OverloadPolicy,
+    pub report_policy: ReportPolicy,
+    pub contact_request_policy: ContactRequestPolicy,
+    pub quota_policy: QuotaPolicy,
+}
+
+/// The environment variables that can be overridden at runtime.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuntimeConfigOverrides {
+    send_backlog_shed_threshold: Option<usize>,
+    send_backlog_lagging_threshold: Option<usize>,
+    send_backlog_disconnect_threshold: Option<usize>,
+    rate_limits: Option<String>,
+    turn_credential_ttl_secs: Option<u64>,
+    lazy_worktree_entry_threshold: Option<usize>,
+    orphan_sweep_interval_secs: Option<u64>,
+    reconnect_timeout_secs: Option<u64>,
+    cleanup_timeout_secs: Option<u64>,
+    feature_flags: Option<String>,
+    overload_event_loop_lag_ms: Option<u64>,
+    overload_db_latency_ms: Option<u64>,
+    reports_to_throttle_invites: Option<usize>,
+    reports_to_block_public_joins: Option<usize>,
+    contact_requests_per_hour: Option<usize>,
+    contact_requests_to_strangers_per_hour: Option<usize>,
+    max_connections_per_user: Option<usize>,
+    max_shared_projects_per_room: Option<usize>,
+    max_room_participants: Option<usize>,
+}
+
+impl RuntimeConfig {
+    /// Applies overrides, given as pairs of environment variable names and values, to
+    /// the config that the server was started with.
+    pub fn new(config: &Config, overrides: &[(String, String)]) -> Result<Self> {
+        let overrides = envy::from_iter::<_, RuntimeConfigOverrides>(overrides.iter().cloned())
+            .context("invalid runtime config override")?;
+        let backlog_policy = config.backlog_policy();
+        let overload_policy = config.overload_policy();
+        let report_policy = config.report_policy();
+        let contact_request_policy = config.contact_request_policy();
+        let quota_policy = config.quota_policy();
+        let reconnect_timeout = overrides
+            .reconnect_timeout_secs
+            .map_or_else(|| config.reconnect_timeout(), Duration::from_secs);
+        let cleanup_timeout = overrides
+            .cleanup_timeout_secs
+            .map_or_else(|| config.cleanup_timeout(), Duration::from_secs);
+        // Rooms are only kept for the rejoin window after a restart, so clients have to
+        // be cleaned up and given the chance to reconnect within it.
+        if reconnect_timeout >= REJOIN_WINDOW || cleanup_timeout >= REJOIN_WINDOW {
+            Err(anyhow!(
+                "RECONNECT_TIMEOUT_SECS and CLEANUP_TIMEOUT_SECS must be less than {} seconds",
+                REJOIN_WINDOW.as_secs()
+            ))?;
+        }
+        Ok(Self {
+            backlog_policy: BacklogPolicy {
+                shed_lossy_messages: overrides
+                    .send_backlog_shed_threshold
+                    .unwrap_or(backlog_policy.shed_lossy_messages),
+                notify_lagging: overrides
+                    .send_backlog_lagging_threshold
+                    .unwrap_or(backlog_policy.notify_lagging),
+                disconnect: overrides
+                    .send_backlog_disconnect_threshold
+                    .unwrap_or(backlog_policy.disconnect),
+            },
+            rate_limit_policy: match overrides.rate_limits.as_deref() {
+                Some(rate_limits) => crate::parse_rate_limits(rate_limits)?,
+                None => config.rate_limit_policy()?,
+            },
+            turn_credential_ttl: overrides
+                .turn_credential_ttl_secs
+                .map_or_else(|| config.turn_credential_ttl(), Duration::from_secs),
+            lazy_worktree_entry_threshold: overrides
+                .lazy_worktree_entry_threshold
+                .unwrap_or_else(|| config.lazy_worktree_entry_threshold()),
+            orphan_sweep_interval: overrides
+                .orphan_sweep_interval_secs
+                .map_or_else(|| config.orphan_sweep_interval(), Duration::from_secs),
+            reconnect_timeout,
+            cleanup_timeout,
+            feature_flags: overrides
+                .feature_flags
+                .as_deref()
+                .map_or_else(|| config.feature_flags(), crate::parse_feature_flags),
+            overload_policy: OverloadPolicy {
+                max_event_loop_lag: overrides
+                    .overload_event_loop_lag_ms
+                    .map_or(overload_policy.max_event_loop_lag, Duration::from_millis),
+                max_db_latency: overrides
+                    .overload_db_latency_ms
+                    .map_or(overload_policy.max_db_latency, Duration::from_millis),
+            },
+            report_policy: ReportPolicy {
+                throttle_invites_at: overrides
+                    .reports_to_throttle_invites
+                    .unwrap_or(report_policy.throttle_invites_at),
+                block_public_joins_at: overrides
+                    .reports_to_block_public_joins
+                    .unwrap_or(report_policy.block_public_joins_at),
+            },
+            contact_request_policy: ContactRequestPolicy {
+                max_per_hour: overrides
+                    .contact_requests_per_hour
+                    .unwrap_or(contact_request_policy.max_per_hour),
+                max_to_strangers_per_hour: overrides
+                    .contact_requests_to_strangers_per_hour
+                    .unwrap_or(contact_request_policy.max_to_strangers_per_hour),
+            },
+            quota_policy: QuotaPolicy {
+                max_connections_per_user: overrides
+                    .max_connections_per_user
+                    .unwrap_or(quota_policy.max_connections_per_user),
+                max_shared_projects_per_room: overrides
+                    .max_shared_projects_per_room
+                    .unwrap_or(quota_policy.max_shared_projects_per_room),
+                max_room_participants: overrides
+                    .max_room_participants
+                    .unwrap_or(quota_policy.max_room_participants),
+            },
+        })
+    }
+
+    /// The active values, keyed by the environment variables that configure them, in
+    /// the form those variables take.
+    pub fn values(&self) -> BTreeMap<&'static str, String> {
+        BTreeMap::from_iter([
+            (
+                "SEND_BACKLOG_SHED_THRESHOLD",
+                self.backlog_policy.shed_lossy_messages.to_string(),
+            ),
+            (
+                "SEND_BACKLOG_LAGGING_THRESHOLD",
+                self.backlog_policy.notify_lagging.to_string(),
+            ),
+            (
+                "SEND_BACKLOG_DISCONNECT_THRESHOLD",
+                self.backlog_policy.disconnect.to_string(),
+            ),
+            (
+                "RATE_LIMITS",
+                serde_json::to_string(&self.rate_limit_policy).unwrap_or_default(),
+            ),
+            (
+                "TURN_CREDENTIAL_TTL_SECS",
+                self.turn_credential_ttl.as_secs().to_string(),
+            ),
+            (
+                "LAZY_WORKTREE_ENTRY_THRESHOLD",
+                self.lazy_worktree_entry_threshold.to_string(),
+            ),
+            (
+                "ORPHAN_SWEEP_INTERVAL_SECS",
+                self.orphan_sweep_interval.as_secs().to_string(),
+            ),
+            (
+                "RECONNECT_TIMEOUT_SECS",
+                self.reconnect_timeout.as_secs().to_string(),
+            ),
+            (
+                "CLEANUP_TIMEOUT_SECS",
+                self.cleanup_timeout.as_secs().to_string(),
+            ),
+            ("FEATURE_FLAGS", self.feature_flags.join(",")),
+            (
+                "OVERLOAD_EVENT_LOOP_LAG_MS",
+                self.overload_policy
+                    .max_event_loop_lag
+                    .as_millis()
+                    .to_string(),
+            ),
+            (
+                "OVERLOAD_DB_LATENCY_MS",
+                self.overload_policy.max_db_latency.as_millis().to_string(),
+            ),
+            (
+                "REPORTS_TO_THROTTLE_INVITES",
+                self.report_policy.throttle_invites_at.to_string(),
+            ),
+            (
+                "REPORTS_TO_BLOCK_PUBLIC_JOINS",
+                self.report_policy.block_public_joins_at.to_string(),
+            ),
+            (
+                "CONTACT_REQUESTS_PER_HOUR",
+                self.contact_request_policy.max_per_hour.to_string(),
+            ),
+            (
+                "CONTACT_REQUESTS_TO_STRANGERS_PER_HOUR",
+                self.contact_request_policy
+                    .max_to_strangers_per_hour
+                    .to_string(),
+            ),
+            (
+                "MAX_CONNECTIONS_PER_USER",
+                self.quota_policy.max_connections_per_user.to_string(),
+            ),
+            (
+                "MAX_SHARED_PROJECTS_PER_ROOM",
+                self.quota_policy.max_shared_projects_per_room.to_string(),
+            ),
+            (
+                "MAX_ROOM_PARTICIPANTS",
+                self.quota_policy.max_room_participants.to_string(),
+            ),
+        ])
+    }
+}
+
+/// The active [`RuntimeConfig`], which is shared by everything that reads it so that
+/// reloading it takes effect immediately.
+#[derive(Clone)]
+pub struct SharedRuntimeConfig(Arc<RwLock<Arc<RuntimeConfig>>>);
+
+impl SharedRuntimeConfig {
+    pub fn new(runtime_config: RuntimeConfig) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(runtime_config))))
+    }
+
+    pub fn get(&self) -> Arc<RuntimeConfig> {
+        self.0.read().clone()
+    }
+
+    /// Replaces the active config, returning the one it replaced.
+    pub fn replace(&self, runtime_config: RuntimeConfig) -> Arc<RuntimeConfig> {
+        std::mem::replace(&mut *self.0.write(), Arc::new(runtime_config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TestServer;
+
+    #[test]
+    fn test_runtime_config_overrides() {
+        let mut config = TestServer::test_config();
+        config.send_backlog_shed_threshold = Some(10);
+        config.lazy_worktree_entry_threshold = Some(100);
+
+        let runtime_config = RuntimeConfig::new(&config, &[]).unwrap();
+        assert_eq!(runtime_config.backlog_policy.shed_lossy_messages, 10);
+        assert_eq!(runtime_config.lazy_worktree_entry_threshold, 100);
+        assert_eq!(runtime_config.rate_limit_policy, RateLimitPolicy::default());
+
+        // Overrides take precedence over the environment, and leave everything else as
+        // it was.
+        let runtime_config = RuntimeConfig::new(
+            &config,
+            &[
+                ("LAZY_WORKTREE_ENTRY_THRESHOLD".into(), "200".into()),
+                (
+                    "RATE_LIMITS".into(),
+                    r#"{"default": {"per_second": 1, "burst": 2}}"#.into(),
+                ),
+            ],
+        )
+        .unwrap();
+        assert_eq!(runtime_config.backlog_policy.shed_lossy_messages, 10);
+        assert_eq!(runtime_config.lazy_worktree_entry_threshold, 200);
+        assert_eq!(
+            runtime_config.values()["RATE_LIMITS"],
+            r#"{"default":{"per_second":1,"burst":2},"messages":{}}"#
+        );
+
+        let runtime_config = RuntimeConfig::new(
+            &config,
+            &[
+                ("RECONNECT_TIMEOUT_SECS".into(), "20".into()),
+                ("FEATURE_FLAGS".into(), "language-models, notes".into()),
+            ],
+        )
+        .unwrap();
+        assert_eq!(runtime_config.reconnect_timeout, Duration::from_secs(20));
+        assert_eq!(runtime_config.feature_flags, ["language-models", "notes"]);
+        assert_eq!(
+            runtime_config.values()["FEATURE_FLAGS"],
+            "language-models,notes"
+        );
+
+        // Invalid overrides, and overrides of variables that can't change at runtime,
+        // are rejected.
+        for (name, value) in [
+            ("LAZY_WORKTREE_ENTRY_THRESHOLD", "lots"),
+            ("RATE_LIMITS", "{"),
+            ("RECONNECT_TIMEOUT_SECS", "600"),
+            ("DATABASE_URL", "postgres://elsewhere"),
+        ] {
+            assert!(RuntimeConfig::new(&config, &[(name.into(), value.into())]).is_err());
+        }
+    }
+}
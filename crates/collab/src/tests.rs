@@ -5,19 +5,27 @@ mod channel_buffer_tests;
 mod channel_guest_tests;
 mod channel_message_tests;
 mod channel_tests;
+mod compression_tests;
 mod editor_tests;
 mod following_tests;
 mod integration_tests;
+mod network_faults;
 mod notification_tests;
+mod operation_trace;
 mod random_channel_buffer_tests;
 mod random_project_collaboration_tests;
 mod randomized_test_helpers;
+mod scenario;
 mod test_server;
 
+pub use network_faults::NetworkFaults;
+pub use operation_trace::OperationEntities;
 pub use randomized_test_helpers::{
-    run_randomized_test, save_randomized_test_plan, RandomizedTest, TestError, UserTestPlan,
+    replay_randomized_test, run_randomized_test, save_randomized_test_plan, RandomizedTest,
+    TestError, UserTestPlan,
 };
-pub use test_server::{TestClient, TestServer};
+pub use scenario::Scenario;
+pub use test_server::{ClosedRemoteBuffer, DeletedWorktreeEntry, TestClient, TestServer};
 
 #[derive(Debug, Eq, PartialEq)]
 struct RoomParticipants {
@@ -1,5 +1,5 @@
 use crate::{
-    rpc::{CLEANUP_TIMEOUT, RECONNECT_TIMEOUT},
+    rpc::{RECONNECT_TIMEOUT, REJOIN_WINDOW},
     tests::{test_server::open_channel_notes, TestServer},
 };
 use call::ActiveCall;
@@ -79,6 +79,12 @@ async fn test_core_channel_buffers(
     assert_eq!(buffer_text(&buffer_a, cx_a), "hello, beautiful world");
     assert_eq!(buffer_text(&buffer_b, cx_b), "hello, beautiful world");
 
+    // Either client can export the notes as markdown
+    assert_eq!(
+        channel_buffer_b.read_with(cx_b, |buffer, cx| buffer.export_markdown(cx)),
+        "# zed\n\nhello, beautiful world\n"
+    );
+
     // Client A closes the channel buffer.
     cx_a.update(|_| drop(channel_buffer_a));
     executor.run_until_parked();
@@ -577,7 +583,7 @@ async fn test_channel_buffers_and_server_restarts(
 
     // Server restarts.
     server.start().await.unwrap();
-    deterministic.advance_clock(CLEANUP_TIMEOUT);
+    deterministic.advance_clock(REJOIN_WINDOW);
 
     // Clients reconnects. Clients A and B see each other's edits, and see
     // that client C has disconnected.
@@ -1,4 +1,7 @@
-use crate::{db::ChannelId, tests::TestServer};
+use crate::{
+    db::ChannelId,
+    tests::{room_participants, RoomParticipants, TestServer},
+};
 use call::ActiveCall;
 use editor::Editor;
 use gpui::{BackgroundExecutor, TestAppContext};
@@ -266,3 +269,77 @@ async fn test_channel_requires_zed_cla(cx_a: &mut TestAppContext, cx_b: &mut Tes
     cx_a.run_until_parked();
     assert!(room_b.read_with(cx_b, |room, _| !room.read_only()));
 }
+
+#[gpui::test(iterations = 10)]
+async fn test_guests_joining_channel_concurrently(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
+    let active_call_c = cx_c.read(ActiveCall::global);
+
+    let channel_id = server
+        .make_public_channel("the-channel", &client_a, cx_a)
+        .await;
+    active_call_a
+        .update(cx_a, |call, cx| call.join_channel(channel_id, cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+
+    // Both guests join at the same time.
+    let join_b = active_call_b.update(cx_b, |call, cx| call.join_channel(channel_id, cx));
+    let join_c = active_call_c.update(cx_c, |call, cx| call.join_channel(channel_id, cx));
+    let (room_b, room_c) = futures::future::try_join(join_b, join_c).await.unwrap();
+    let (room_b, room_c) = (room_b.unwrap(), room_c.unwrap());
+    executor.run_until_parked();
+
+    // Each of them ends up in the same room, and every participant sees the others.
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+    let room_id = room_a.read_with(cx_a, |room, _| room.id());
+    assert_eq!(room_b.read_with(cx_b, |room, _| room.id()), room_id);
+    assert_eq!(room_c.read_with(cx_c, |room, _| room.id()), room_id);
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string(), "user_c".to_string()],
+            pending: vec![],
+        }
+    );
+    assert_eq!(
+        room_participants(&room_b, cx_b),
+        RoomParticipants {
+            remote: vec!["user_a".to_string(), "user_c".to_string()],
+            pending: vec![],
+        }
+    );
+    assert_eq!(
+        room_participants(&room_c, cx_c),
+        RoomParticipants {
+            remote: vec!["user_a".to_string(), "user_b".to_string()],
+            pending: vec![],
+        }
+    );
+    for (room, cx) in [(&room_b, &mut *cx_b), (&room_c, &mut *cx_c)] {
+        assert!(room.read_with(cx, |room, _| room.read_only()));
+    }
+    assert_eq!(
+        client_a.channel_store().read_with(cx_a, |store, _| {
+            let mut participants = store
+                .channel_participants(channel_id)
+                .iter()
+                .map(|user| user.github_login.clone())
+                .collect::<Vec<_>>();
+            participants.sort();
+            participants
+        }),
+        ["user_a", "user_b", "user_c"]
+    );
+}
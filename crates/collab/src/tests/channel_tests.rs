@@ -13,6 +13,7 @@ use rpc::{
     RECEIVE_TIMEOUT,
 };
 use std::sync::Arc;
+use util::collation::{Collation, Locale};
 
 #[gpui::test]
 async fn test_core_channels(
@@ -323,6 +324,71 @@ fn assert_members_eq(
     );
 }
 
+#[gpui::test]
+async fn test_members_and_contacts_ordered_by_server_collation(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+    cx_d: &mut TestAppContext,
+) {
+    let mut server = TestServer::start_with_config(executor.clone(), |config| {
+        config.collation_locale = Some("sv-SE".into());
+    })
+    .await;
+    let client_a = server.create_client(cx_a, "admin").await;
+    let client_b = server.create_client(cx_b, "åsa").await;
+    let client_c = server.create_client(cx_c, "zoe").await;
+    let client_d = server.create_client(cx_d, "anna").await;
+    assert_eq!(
+        client_a.client().collation(),
+        Collation::Locale(Locale::Swedish)
+    );
+
+    // Swedish puts å after z, where other locales would put it with the a's.
+    let channel_id = server
+        .make_channel(
+            "the-channel",
+            None,
+            (&client_a, cx_a),
+            &mut [(&client_b, cx_b), (&client_c, cx_c), (&client_d, cx_d)],
+        )
+        .await;
+    let members = client_a
+        .channel_store()
+        .update(cx_a, |store, cx| {
+            store.get_channel_member_details(channel_id, cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        members
+            .iter()
+            .map(|member| member.user.github_login.as_str())
+            .collect::<Vec<_>>(),
+        ["admin", "anna", "zoe", "åsa"]
+    );
+
+    server
+        .make_contacts(&mut [
+            (&client_a, cx_a),
+            (&client_b, cx_b),
+            (&client_c, cx_c),
+            (&client_d, cx_d),
+        ])
+        .await;
+    client_a.user_store().read_with(cx_a, |user_store, _| {
+        assert_eq!(
+            user_store
+                .contacts()
+                .iter()
+                .map(|contact| contact.user.github_login.as_str())
+                .collect::<Vec<_>>(),
+            ["anna", "zoe", "åsa"]
+        );
+    });
+}
+
 #[gpui::test]
 async fn test_joining_channel_ancestor_member(
     executor: BackgroundExecutor,
@@ -0,0 +1,159 @@
+use anyhow::anyhow;
+use async_tungstenite::tungstenite::Message as WebSocketMessage;
+use futures::{SinkExt as _, StreamExt as _};
+use prost::Message as _;
+use rpc::proto::{
+    self, Compression, EnvelopedMessage as _, Message, MessageStream, COMPRESSION_THRESHOLD,
+};
+use std::time::Instant;
+
+const ITERATIONS: usize = 20;
+
+/// Sends the largest and the most frequent envelopes of a big project between a client
+/// and the server, checking that large envelopes shrink on the wire once compression is
+/// negotiated, and that small ones are sent as they are.
+#[gpui::test]
+async fn test_compressed_envelope_sizes() {
+    for (name, envelope) in envelopes() {
+        let encoded_len = envelope.encoded_len();
+        let (_, frame) = write_envelopes(
+            &envelope,
+            Compression::for_protocol_version(rpc::PROTOCOL_VERSION),
+            1,
+        )
+        .await;
+        if encoded_len >= COMPRESSION_THRESHOLD {
+            assert!(frame.len() < encoded_len / 2, "{name} wasn't compressed");
+        } else {
+            assert_eq!(frame.len(), encoded_len, "{name} was compressed");
+        }
+    }
+}
+
+/// Measures how many bytes each envelope takes on the wire and how long each end spends
+/// on it, with every envelope compressed as older peers require and with the
+/// compression that's negotiated between current ones. It only runs when asked to, with
+/// `cargo test -p collab compression_benchmarks -- --ignored`, and prints its results
+/// with `RUST_LOG=info`.
+#[gpui::test]
+#[ignore]
+async fn test_compression_benchmarks() {
+    for (name, envelope) in envelopes() {
+        let encoded_len = envelope.encoded_len();
+        for compression in [
+            Compression::Always,
+            Compression::for_protocol_version(rpc::PROTOCOL_VERSION),
+        ] {
+            let start = Instant::now();
+            let (frames, frame) = write_envelopes(&envelope, compression, ITERATIONS).await;
+            let write_time = start.elapsed() / ITERATIONS as u32;
+
+            let mut reader = MessageStream::new(futures::stream::iter(frames).map(anyhow::Ok));
+            let start = Instant::now();
+            for _ in 0..ITERATIONS {
+                reader.read().await.unwrap();
+            }
+            let read_time = start.elapsed() / ITERATIONS as u32;
+
+            log::info!(
+                "{name} with {compression:?}: {encoded_len} bytes encoded, {} on the wire, written in {write_time:?} and read in {read_time:?}",
+                frame.len()
+            );
+        }
+    }
+}
+
+fn envelopes() -> [(&'static str, proto::Envelope); 3] {
+    [
+        ("worktree snapshot", worktree_snapshot(5_000)),
+        ("buffer state", buffer_state(20_000)),
+        ("buffer edit", buffer_edit()),
+    ]
+}
+
+/// Writes the envelope the given number of times, returning the frames that were sent
+/// along with the first one's payload.
+async fn write_envelopes(
+    envelope: &proto::Envelope,
+    compression: Compression,
+    count: usize,
+) -> (Vec<WebSocketMessage>, Vec<u8>) {
+    let (tx, rx) = futures::channel::mpsc::unbounded();
+    let mut writer = MessageStream::new(tx.sink_map_err(|_| anyhow!("connection closed")));
+    writer.set_compression(compression);
+    for envelope in vec![envelope.clone(); count] {
+        writer.write(Message::Envelope(envelope)).await.unwrap();
+    }
+    drop(writer);
+
+    let frames = rx.collect::<Vec<_>>().await;
+    let WebSocketMessage::Binary(frame) = &frames[0] else {
+        panic!("unexpected frame {:?}", frames[0]);
+    };
+    let frame = frame.clone();
+    (frames, frame)
+}
+
+fn worktree_snapshot(entry_count: u64) -> proto::Envelope {
+    proto::UpdateWorktree {
+        project_id: 1,
+        worktree_id: 1,
+        root_name: "zed".into(),
+        abs_path: "/home/user/zed".into(),
+        updated_entries: (0..entry_count)
+            .map(|id| proto::Entry {
+                id,
+                is_dir: false,
+                path: format!("crates/crate_{}/src/module_{id}.rs", id / 100),
+                inode: 1_000_000 + id,
+                mtime: Some(proto::Timestamp {
+                    seconds: 1_700_000_000 + id,
+                    nanos: 0,
+                }),
+                ..Default::default()
+            })
+            .collect(),
+        scan_id: 1,
+        is_last_update: true,
+        ..Default::default()
+    }
+    .into_envelope(1, None, None)
+}
+
+fn buffer_state(line_count: usize) -> proto::Envelope {
+    let base_text = (1..=line_count)
+        .map(|row| format!("    let value_{row} = compute(value_{}, {row});\n", row - 1))
+        .collect::<String>();
+    proto::CreateBufferForPeer {
+        project_id: 1,
+        peer_id: None,
+        variant: Some(proto::create_buffer_for_peer::Variant::State(
+            proto::BufferState {
+                id: 1,
+                base_text,
+                ..Default::default()
+            },
+        )),
+    }
+    .into_envelope(1, None, None)
+}
+
+fn buffer_edit() -> proto::Envelope {
+    proto::UpdateBuffer {
+        project_id: 1,
+        buffer_id: 1,
+        operations: vec![proto::Operation {
+            variant: Some(proto::operation::Variant::Edit(proto::operation::Edit {
+                replica_id: 1,
+                lamport_timestamp: 42,
+                version: vec![proto::VectorClockEntry {
+                    replica_id: 1,
+                    timestamp: 41,
+                }],
+                ranges: vec![proto::Range { start: 10, end: 10 }],
+                new_text: vec!["x".into()],
+            })),
+        }],
+    }
+    .into_envelope(1, None, None)
+}
@@ -9,7 +9,8 @@ use std::{
 use call::ActiveCall;
 use editor::{
     actions::{
-        ConfirmCodeAction, ConfirmCompletion, ConfirmRename, Redo, Rename, ToggleCodeActions, Undo,
+        ConfirmCodeAction, ConfirmCompletion, ConfirmRename, Newline, Redo, Rename,
+        ToggleCodeActions, Undo,
     },
     test::editor_test_context::{AssertionContextManager, EditorTestContext},
     Editor,
@@ -639,6 +640,12 @@ async fn test_collaborating_with_code_actions(
     // After the action is confirmed, an editor containing both modified files is opened.
     confirm_action.await.unwrap();
 
+    // The host applied the edits, opening every buffer they touched.
+    project_a.read_with(cx_a, |project, cx| {
+        assert!(project.has_open_buffer((worktree_id, "main.rs"), cx));
+        assert!(project.has_open_buffer((worktree_id, "other.rs"), cx));
+    });
+
     let code_action_editor = workspace_b.update(cx_b, |workspace, cx| {
         workspace
             .active_item(cx)
@@ -656,6 +663,30 @@ async fn test_collaborating_with_code_actions(
         editor.redo(&Redo, cx);
         assert_eq!(editor.text(cx), "mod other;\nfn main() { let foo = 4; }\n");
     });
+
+    // Undoing the action as a whole reverts both files for the host as well.
+    let host_text = |path: &str, cx: &mut TestAppContext| {
+        project_a.update(cx, |project, cx| {
+            project
+                .get_open_buffer(&(worktree_id, path).into(), cx)
+                .unwrap()
+                .read(cx)
+                .text()
+        })
+    };
+    cx_a.background_executor.run_until_parked();
+    assert_eq!(
+        host_text("main.rs", cx_a),
+        "mod other;\nfn main() { let foo = 4; }"
+    );
+    assert_eq!(host_text("other.rs", cx_a), "");
+    code_action_editor.update(cx_b, |editor, cx| editor.undo(&Undo, cx));
+    cx_a.background_executor.run_until_parked();
+    assert_eq!(
+        host_text("main.rs", cx_a),
+        "mod other;\nfn main() { let foo = other::foo(); }"
+    );
+    assert_eq!(host_text("other.rs", cx_a), "pub fn foo() -> usize { 4 }");
 }
 
 #[gpui::test(iterations = 10)]
@@ -873,6 +904,12 @@ async fn test_language_server_statuses(cx_a: &mut TestAppContext, cx_b: &mut Tes
     let mut fake_language_servers = language
         .set_fake_lsp_adapter(Arc::new(FakeLspAdapter {
             name: "the-language-server",
+            workspace_configuration: Some(json!({
+                "the-language-server": {
+                    "features": ["a", "b"],
+                    "extraEnv": { "THE_API_KEY": "hunter2" },
+                }
+            })),
             ..Default::default()
         }))
         .await;
@@ -923,10 +960,21 @@ async fn test_language_server_statuses(cx_a: &mut TestAppContext, cx_b: &mut Tes
         .unwrap();
     executor.run_until_parked();
     let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    executor.run_until_parked();
 
+    // Guests see the host's server configuration, without its secrets.
     project_b.read_with(cx_b, |project, _| {
         let status = project.language_server_statuses().next().unwrap();
         assert_eq!(status.name, "the-language-server");
+        assert_eq!(
+            status.configuration,
+            Some(json!({
+                "the-language-server": {
+                    "features": ["a", "b"],
+                    "extraEnv": { "THE_API_KEY": "<redacted>" },
+                }
+            }))
+        );
     });
 
     fake_language_server.notify::<lsp::notification::Progress>(lsp::ProgressParams {
@@ -1300,7 +1348,7 @@ async fn test_on_input_format_from_guest_to_host(
             capabilities: lsp::ServerCapabilities {
                 document_on_type_formatting_provider: Some(lsp::DocumentOnTypeFormattingOptions {
                     first_trigger_character: ":".to_string(),
-                    more_trigger_character: Some(vec![">".to_string()]),
+                    more_trigger_character: Some(vec![">".to_string(), "\n".to_string()]),
                 }),
                 ..Default::default()
             },
@@ -1401,6 +1449,152 @@ async fn test_on_input_format_from_guest_to_host(
     buffer_a.read_with(cx_a, |buffer, _| {
         assert_eq!(buffer.text(), "fn main() { a }")
     });
+
+    // Inserting a newline as the guest is also formatted by the host's language server.
+    editor_b.update(cx_b, |editor, cx| {
+        editor.change_selections(None, cx, |s| s.select_ranges([0..0]));
+        editor.newline(&Newline, cx);
+    });
+    fake_language_server
+        .handle_request::<lsp::request::OnTypeFormatting, _, _>(|params, _| async move {
+            assert_eq!(params.ch, "\n");
+            assert_eq!(
+                params.text_document_position.position,
+                lsp::Position::new(1, 0),
+            );
+
+            Ok(Some(vec![lsp::TextEdit {
+                new_text: "// ".to_string(),
+                range: lsp::Range::new(lsp::Position::new(0, 0), lsp::Position::new(0, 0)),
+            }]))
+        })
+        .next()
+        .await
+        .unwrap();
+    executor.run_until_parked();
+
+    buffer_a.read_with(cx_a, |buffer, _| {
+        assert_eq!(buffer.text(), "// \nfn main() { a }")
+    });
+    editor_b.update(cx_b, |editor, cx| {
+        assert_eq!(editor.text(cx), "// \nfn main() { a }");
+        editor.undo(&Undo, cx);
+        assert_eq!(editor.text(cx), "\nfn main() { a }");
+    });
+    executor.run_until_parked();
+
+    buffer_a.read_with(cx_a, |buffer, _| {
+        assert_eq!(buffer.text(), "\nfn main() { a }")
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_linked_editing_ranges_from_guest(
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(cx_a.executor()).await;
+    let executor = cx_a.executor();
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    // Set up a fake language server.
+    let mut language = Language::new(
+        LanguageConfig {
+            name: "HTML".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["html".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        None,
+    );
+    let mut fake_language_servers = language
+        .set_fake_lsp_adapter(Arc::new(FakeLspAdapter {
+            capabilities: lsp::ServerCapabilities {
+                linked_editing_range_provider: Some(
+                    lsp::LinkedEditingRangeServerCapabilities::Simple(true),
+                ),
+                ..Default::default()
+            },
+            ..Default::default()
+        }))
+        .await;
+    client_a.language_registry().add(Arc::new(language));
+
+    client_a
+        .fs()
+        .insert_tree("/a", json!({ "index.html": "<div></div>" }))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    // Open the file in an editor as the guest.
+    let buffer_b = project_b
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "index.html"), cx))
+        .await
+        .unwrap();
+    let cx_b = cx_b.add_empty_window();
+    let editor_b = cx_b.new_view(|cx| Editor::for_buffer(buffer_b, Some(project_b.clone()), cx));
+
+    let fake_language_server = fake_language_servers.next().await.unwrap();
+    executor.run_until_parked();
+
+    // Move the cursor into the opening tag's name, querying the host's language
+    // server for the ranges linked to it.
+    let mut requests = fake_language_server
+        .handle_request::<lsp::request::LinkedEditingRange, _, _>(|params, _| async move {
+            assert_eq!(
+                params.text_document_position_params.position,
+                lsp::Position::new(0, 4),
+            );
+            Ok(Some(lsp::LinkedEditingRanges {
+                ranges: vec![
+                    lsp::Range::new(lsp::Position::new(0, 1), lsp::Position::new(0, 4)),
+                    lsp::Range::new(lsp::Position::new(0, 7), lsp::Position::new(0, 10)),
+                ],
+                word_pattern: None,
+            }))
+        });
+    cx_b.focus_view(&editor_b);
+    editor_b.update(cx_b, |editor, cx| {
+        editor.change_selections(None, cx, |s| s.select_ranges([4..4]));
+    });
+    executor.advance_clock(editor::LINKED_EDITING_RANGES_DEBOUNCE_TIMEOUT * 2);
+    requests.next().await.unwrap();
+    executor.run_until_parked();
+
+    // Typing in the opening tag renames the closing one too, for all participants.
+    editor_b.update(cx_b, |editor, cx| {
+        editor.handle_input("x", cx);
+        editor.handle_input("y", cx);
+        assert_eq!(editor.text(cx), "<divxy></divxy>");
+    });
+    executor.run_until_parked();
+    let buffer_a = project_a
+        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "index.html"), cx))
+        .await
+        .unwrap();
+    buffer_a.read_with(cx_a, |buffer, _| {
+        assert_eq!(buffer.text(), "<divxy></divxy>")
+    });
+
+    // Each mirrored edit is undone together with the edit that caused it.
+    editor_b.update(cx_b, |editor, cx| {
+        editor.undo(&Undo, cx);
+        assert_eq!(editor.text(cx), "<div></div>");
+    });
+    executor.run_until_parked();
+    buffer_a.read_with(cx_a, |buffer, _| assert_eq!(buffer.text(), "<div></div>"));
 }
 
 #[gpui::test(iterations = 10)]
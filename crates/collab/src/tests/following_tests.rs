@@ -2077,3 +2077,168 @@ async fn test_following_to_channel_notes_other_workspace(
         assert_eq!(editor.tab_description(0, cx).unwrap(), "1.txt");
     });
 }
+
+#[gpui::test(iterations = 10)]
+async fn test_cycling_through_collaborators(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
+    let active_call_c = cx_c.read(ActiveCall::global);
+
+    cx_a.update(editor::init);
+    cx_b.update(editor::init);
+    cx_c.update(editor::init);
+
+    // Client A shares a project, and clients B and C join it.
+    client_a
+        .fs()
+        .insert_tree("/a", json!({ "1.txt": "one" }))
+        .await;
+    let (project_a, _) = client_a.build_local_project("/a", cx_a).await;
+    active_call_a
+        .update(cx_a, |call, cx| call.set_location(Some(&project_a), cx))
+        .await
+        .unwrap();
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    active_call_b
+        .update(cx_b, |call, cx| call.set_location(Some(&project_b), cx))
+        .await
+        .unwrap();
+    let project_c = client_c.build_remote_project(project_id, cx_c).await;
+    active_call_c
+        .update(cx_c, |call, cx| call.set_location(Some(&project_c), cx))
+        .await
+        .unwrap();
+
+    let (_workspace_a, cx_a) = client_a.build_workspace(&project_a, cx_a);
+    let (workspace_b, cx_b) = client_b.build_workspace(&project_b, cx_b);
+    let (_workspace_c, cx_c) = client_c.build_workspace(&project_c, cx_c);
+    executor.run_until_parked();
+
+    let peer_id_a = client_a.peer_id().unwrap();
+    let peer_id_c = client_c.peer_id().unwrap();
+    let leader_b = |cx: &mut VisualTestContext| {
+        workspace_b.update(cx, |workspace, _| {
+            workspace.leader_for_pane(workspace.active_pane())
+        })
+    };
+
+    // Client B cycles through the other participants in the order they joined.
+    workspace_b.update(cx_b, |workspace, cx| {
+        workspace.follow_adjacent_collaborator(false, cx)
+    });
+    assert_eq!(leader_b(cx_b), Some(peer_id_a));
+    workspace_b.update(cx_b, |workspace, cx| {
+        workspace.follow_adjacent_collaborator(false, cx)
+    });
+    assert_eq!(leader_b(cx_b), Some(peer_id_c));
+    workspace_b.update(cx_b, |workspace, cx| {
+        workspace.follow_adjacent_collaborator(false, cx)
+    });
+    assert_eq!(leader_b(cx_b), Some(peer_id_a));
+
+    // Cycling backwards wraps around the other way.
+    workspace_b.update(cx_b, |workspace, cx| {
+        workspace.follow_adjacent_collaborator(true, cx)
+    });
+    assert_eq!(leader_b(cx_b), Some(peer_id_c));
+
+    // Client C was the last to move, so they're the most recently active. Once
+    // client A moves, they are instead.
+    cx_b.update(|cx| {
+        let room = ActiveCall::global(cx).read(cx).room().unwrap().read(cx);
+        assert_eq!(room.most_recently_active_participant(), Some(peer_id_c));
+    });
+    active_call_a
+        .update(cx_a, |call, cx| call.set_location(None, cx))
+        .await
+        .unwrap();
+    active_call_a
+        .update(cx_a, |call, cx| call.set_location(Some(&project_a), cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    workspace_b.update(cx_b, |workspace, cx| {
+        workspace.follow_most_recently_active_collaborator(
+            &workspace::FollowMostRecentlyActiveCollaborator,
+            cx,
+        )
+    });
+    assert_eq!(leader_b(cx_b), Some(peer_id_a));
+
+    // Nobody is speaking, so following the speaker leaves the pane as it is
+    // until someone starts to.
+    workspace_b.update(cx_b, |workspace, cx| {
+        workspace.toggle_follow_speaker(&workspace::ToggleFollowSpeaker, cx);
+        assert!(workspace.is_following_speaker());
+    });
+    assert_eq!(leader_b(cx_b), Some(peer_id_a));
+    workspace_b.update(cx_b, |workspace, cx| {
+        workspace.toggle_follow_speaker(&workspace::ToggleFollowSpeaker, cx);
+        assert!(!workspace.is_following_speaker());
+    });
+    assert_eq!(leader_b(cx_b), None);
+
+    // Once following the speaker, the pane switches to whoever keeps speaking for
+    // a moment, but not to someone who only interjects briefly.
+    let set_speakers = |speakers: &[&TestClient]| {
+        server.test_live_kit_server.set_active_speakers(
+            &speakers
+                .iter()
+                .map(|client| client.user_id().unwrap().to_string())
+                .collect::<Vec<_>>(),
+        );
+        executor.run_until_parked();
+    };
+    workspace_b.update(cx_b, |workspace, cx| {
+        workspace.toggle_follow_speaker(&workspace::ToggleFollowSpeaker, cx);
+    });
+    set_speakers(&[&client_c]);
+    assert_eq!(leader_b(cx_b), None);
+    executor.advance_clock(workspace::SPEAKER_FOLLOW_DELAY);
+    assert_eq!(leader_b(cx_b), Some(peer_id_c));
+    set_speakers(&[&client_c, &client_a]);
+    set_speakers(&[&client_c]);
+    executor.advance_clock(workspace::SPEAKER_FOLLOW_DELAY);
+    assert_eq!(leader_b(cx_b), Some(peer_id_c));
+
+    // Speakers outside of the project aren't followed, as that would mean
+    // joining the project they're in.
+    active_call_a
+        .update(cx_a, |call, cx| call.set_location(None, cx))
+        .await
+        .unwrap();
+    set_speakers(&[]);
+    set_speakers(&[&client_a]);
+    executor.advance_clock(workspace::SPEAKER_FOLLOW_DELAY);
+    assert_eq!(leader_b(cx_b), Some(peer_id_c));
+    active_call_a
+        .update(cx_a, |call, cx| call.set_location(Some(&project_a), cx))
+        .await
+        .unwrap();
+
+    // Following someone in particular stops following the speaker.
+    workspace_b.update(cx_b, |workspace, cx| {
+        workspace.follow(peer_id_a, cx);
+        assert!(!workspace.is_following_speaker());
+    });
+    set_speakers(&[]);
+    set_speakers(&[&client_c]);
+    executor.advance_clock(workspace::SPEAKER_FOLLOW_DELAY);
+    assert_eq!(leader_b(cx_b), Some(peer_id_a));
+}
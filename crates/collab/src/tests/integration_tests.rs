@@ -1,14 +1,36 @@
 use crate::{
-    rpc::{CLEANUP_TIMEOUT, RECONNECT_TIMEOUT},
-    tests::{channel_id, room_participants, RoomParticipants, TestClient, TestServer},
+    auth,
+    db::{
+        AuditEventFilter, AuditEventKind, DatabaseFaults, ProjectId, ReportStatus, RoomId, UserId,
+    },
+    ice::{IceConfig, IceServer},
+    rpc::{
+        OverloadLevel, CLEANUP_TIMEOUT, DRAIN_POLL_INTERVAL, RECONNECT_TIMEOUT, REJOIN_WINDOW,
+        RESUME_TIMEOUT, RUNTIME_CONFIG_RELOAD_INTERVAL, THROTTLED_INVITE_LIMIT,
+    },
+    tests::{
+        channel_id, room_participants, NetworkFaults, RoomParticipants, Scenario, TestClient,
+        TestServer,
+    },
+    webhooks,
+};
+use call::{
+    audio_relay::{set_audio_relay_device, FakeAudioRelayDevice, RELAYED_AUDIO_FRAME_DURATION},
+    call_history::CallStatus,
+    call_settings::CallSettings,
+    preflight, room,
+    screen_annotation::{ScreenAnnotationShape, SCREEN_ANNOTATION_TTL},
+    ActiveCall, InviteContext, ParticipantLocation, Room,
+};
+use client::{
+    connectivity::ConnectionDiagnosis, ChatStore, ContactNote, MessageCategory, User,
+    RECEIVE_TIMEOUT, RESTART_RECONNECT_SPREAD,
 };
-use call::{room, ActiveCall, ParticipantLocation, Room};
-use client::{User, RECEIVE_TIMEOUT};
 use collections::{HashMap, HashSet};
 use fs::{repository::GitFileStatus, FakeFs, Fs as _, RemoveOptions};
 use futures::StreamExt as _;
 use gpui::{
-    px, size, AppContext, BackgroundExecutor, Model, Modifiers, MouseButton, MouseDownEvent,
+    point, px, size, AppContext, BackgroundExecutor, Model, Modifiers, MouseButton, MouseDownEvent,
     TestAppContext,
 };
 use language::{
@@ -19,10 +41,14 @@ use language::{
 use live_kit_client::MacOSDisplay;
 use lsp::LanguageServerId;
 use project::{
-    search::SearchQuery, DiagnosticSummary, FormatTrigger, HoverBlockKind, Project, ProjectPath,
+    project_settings::ProjectSettings, search::SearchQuery, CollabPolicy, DiagnosticSummary,
+    EntryDecoration, FormatTrigger, HoverBlockKind, Project, ProjectPath, MAX_SUGGESTED_TEXT_LEN,
 };
 use rand::prelude::*;
-use rpc::proto::ChannelRole;
+use rpc::{
+    proto::{self, ChannelRole, PeerId},
+    ErrorCode, ErrorExt as _, Notification,
+};
 use serde_json::json;
 use settings::SettingsStore;
 use std::{
@@ -37,6 +63,7 @@ use std::{
     time::Duration,
 };
 use unindent::Unindent as _;
+use util::paths::{PROJECT_TEMPLATES_DIR, SCRATCH_PROJECTS_DIR};
 
 #[ctor::ctor]
 fn init_logger() {
@@ -348,6 +375,452 @@ async fn test_basic_calls(
     );
 }
 
+#[gpui::test(iterations = 10)]
+async fn test_room_preflight_capabilities(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+
+    // Client B can't take part in the call's audio and video.
+    cx_b.update(|cx| preflight::set_media_available(false, cx));
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    executor.run_until_parked();
+
+    let room_a = cx_a.read(|cx| ActiveCall::global(cx).read(cx).room().unwrap().clone());
+    let room_b = cx_b.read(|cx| ActiveCall::global(cx).read(cx).room().unwrap().clone());
+
+    // The server only gave client A access to the call's media.
+    cx_a.read(|cx| room_a.read_with(cx, |room, _| assert!(room.is_connected())));
+    cx_b.read(|cx| room_b.read_with(cx, |room, _| assert!(!room.is_connected())));
+
+    // Both participants see what the other reported before joining.
+    room_a.read_with(cx_a, |room, _| {
+        let user_b_id = client_b.user_id().unwrap();
+        let capabilities = room.remote_participants()[&user_b_id]
+            .capabilities
+            .clone()
+            .unwrap();
+        assert!(!capabilities.media_available);
+        assert_eq!(capabilities.protocol_version, rpc::PROTOCOL_VERSION);
+        assert_eq!(capabilities.limitations(), ["no audio or video"]);
+        assert!(!room.reduced_sync());
+    });
+    room_b.read_with(cx_b, |room, _| {
+        let user_a_id = client_a.user_id().unwrap();
+        let capabilities = room.remote_participants()[&user_a_id]
+            .capabilities
+            .clone()
+            .unwrap();
+        assert!(capabilities.media_available);
+        assert!(capabilities.limitations().is_empty());
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_screen_annotations(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+    let active_call_b = cx_b.read(ActiveCall::global);
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
+    let active_call_c = cx_c.read(ActiveCall::global);
+    let room_c = active_call_c.read_with(cx_c, |call, _| call.room().unwrap().clone());
+    let user_a = client_a.user_id().unwrap();
+
+    // Nothing can be annotated before a screen is shared.
+    room_b
+        .update(cx_b, |room, cx| {
+            room.annotate_screen(user_a, ScreenAnnotationShape::Arrow, Vec::new(), cx)
+        })
+        .unwrap_err();
+
+    let display = MacOSDisplay::new();
+    room_a
+        .update(cx_a, |room, cx| {
+            room.set_display_sources(vec![display.clone()]);
+            room.share_screen(cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+
+    // User B draws a rectangle over user A's screen, which everyone sees.
+    room_b
+        .update(cx_b, |room, cx| {
+            room.annotate_screen(
+                user_a,
+                ScreenAnnotationShape::Rectangle,
+                vec![point(0.25, 0.25), point(0.5, 0.75)],
+                cx,
+            )
+        })
+        .unwrap();
+    executor.run_until_parked();
+    for (room, cx) in [(&room_a, &*cx_a), (&room_b, &*cx_b), (&room_c, &*cx_c)] {
+        room.read_with(cx, |room, _| {
+            let annotations = room.screen_annotations(user_a);
+            assert_eq!(annotations.len(), 1);
+            assert_eq!(annotations[0].author, client_b.peer_id().unwrap());
+            assert_eq!(annotations[0].shape, ScreenAnnotationShape::Rectangle);
+            assert_eq!(
+                annotations[0].points,
+                vec![point(0.25, 0.25), point(0.5, 0.75)]
+            );
+        });
+    }
+
+    // Other participants can clear the annotations they drew, but only those.
+    room_c
+        .update(cx_c, |room, cx| {
+            room.annotate_screen(
+                user_a,
+                ScreenAnnotationShape::Arrow,
+                vec![point(0.5, 0.5), point(0.75, 0.5)],
+                cx,
+            )
+        })
+        .unwrap();
+    executor.run_until_parked();
+    room_c
+        .update(cx_c, |room, cx| {
+            room.clear_own_screen_annotations(user_a, cx)
+        })
+        .unwrap();
+    executor.run_until_parked();
+    for (room, cx) in [(&room_a, &*cx_a), (&room_b, &*cx_b), (&room_c, &*cx_c)] {
+        room.read_with(cx, |room, _| {
+            let annotations = room.screen_annotations(user_a);
+            assert_eq!(annotations.len(), 1);
+            assert_eq!(annotations[0].author, client_b.peer_id().unwrap());
+        });
+    }
+
+    // Only the sharer can clear everyone's annotations, even when the request
+    // doesn't come from the room.
+    room_c
+        .update(cx_c, |room, cx| room.clear_screen_annotations(cx))
+        .unwrap_err();
+    let room_id = room_c.read_with(cx_c, |room, _| room.id());
+    client_c
+        .send(proto::UpdateScreenAnnotations {
+            room_id,
+            sharer_user_id: user_a,
+            variant: Some(proto::update_screen_annotations::Variant::Clear(
+                proto::update_screen_annotations::Clear { author: None },
+            )),
+        })
+        .unwrap();
+    client_c
+        .send(proto::UpdateScreenAnnotations {
+            room_id,
+            sharer_user_id: user_a,
+            variant: Some(proto::update_screen_annotations::Variant::Clear(
+                proto::update_screen_annotations::Clear {
+                    author: client_b.peer_id(),
+                },
+            )),
+        })
+        .unwrap();
+    executor.run_until_parked();
+    for (room, cx) in [(&room_a, &*cx_a), (&room_b, &*cx_b)] {
+        room.read_with(cx, |room, _| {
+            assert_eq!(room.screen_annotations(user_a).len(), 1)
+        });
+    }
+    room_a
+        .update(cx_a, |room, cx| room.clear_screen_annotations(cx))
+        .unwrap();
+    executor.run_until_parked();
+    for (room, cx) in [(&room_a, &*cx_a), (&room_b, &*cx_b), (&room_c, &*cx_c)] {
+        room.read_with(cx, |room, _| {
+            assert!(room.screen_annotations(user_a).is_empty())
+        });
+    }
+
+    // Annotations expire on their own.
+    room_c
+        .update(cx_c, |room, cx| {
+            room.annotate_screen(
+                user_a,
+                ScreenAnnotationShape::Freehand,
+                vec![point(0.1, 0.1), point(0.2, 0.15), point(0.3, 0.1)],
+                cx,
+            )
+        })
+        .unwrap();
+    executor.run_until_parked();
+    room_a.read_with(cx_a, |room, _| {
+        assert_eq!(room.screen_annotations(user_a).len(), 1)
+    });
+    executor.advance_clock(SCREEN_ANNOTATION_TTL);
+    executor.run_until_parked();
+    for (room, cx) in [(&room_a, &*cx_a), (&room_b, &*cx_b), (&room_c, &*cx_c)] {
+        room.read_with(cx, |room, _| {
+            assert!(room.screen_annotations(user_a).is_empty())
+        });
+    }
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_room_chat(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let room_id = cx_a.read(|cx| {
+        ActiveCall::global(cx)
+            .read(cx)
+            .room()
+            .unwrap()
+            .read(cx)
+            .id()
+    });
+
+    let chat_a = ChatStore::new(
+        room_id,
+        client_a.client().clone(),
+        client_a.user_store().clone(),
+        cx_a.to_async(),
+    )
+    .await
+    .unwrap();
+
+    // The message is shown as pending until the server saves it.
+    let send = chat_a
+        .update(cx_a, |chat, cx| chat.send_message("one".into(), cx))
+        .unwrap();
+    chat_a.read_with(cx_a, |chat, _| {
+        assert_eq!(chat.pending_messages().count(), 1)
+    });
+    send.await.unwrap();
+    chat_a.read_with(cx_a, |chat, _| {
+        assert_eq!(chat.pending_messages().count(), 0);
+        assert_eq!(room_chat_messages(chat), ["one"]);
+    });
+
+    // Participants who open the chat later see its history.
+    let room_b = cx_b.read(|cx| ActiveCall::global(cx).read(cx).room().unwrap().clone());
+    let chat_b = room_b
+        .update(cx_b, |room, cx| room.open_chat(cx))
+        .await
+        .unwrap();
+    chat_b.read_with(cx_b, |chat, _| {
+        assert_eq!(room_chat_messages(chat), ["one"]);
+        assert_eq!(chat.messages()[0].sender.github_login, "user_a");
+        assert!(chat.loaded_all_messages());
+    });
+    assert!(chat_b
+        .update(cx_b, |chat, cx| chat.load_more_messages(cx))
+        .is_none());
+
+    // New messages are received as they're sent.
+    chat_b
+        .update(cx_b, |chat, cx| chat.send_message("two".into(), cx))
+        .unwrap()
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    chat_a.read_with(cx_a, |chat, _| {
+        assert_eq!(room_chat_messages(chat), ["one", "two"]);
+        assert_eq!(chat.messages()[1].sender.github_login, "user_b");
+    });
+
+    // Blank messages aren't sent.
+    chat_a
+        .update(cx_a, |chat, cx| chat.send_message("  ".into(), cx))
+        .unwrap_err();
+
+    // Resending a message doesn't show it twice.
+    let nonce = chat_a.read_with(cx_a, |chat, _| chat.messages()[0].nonce);
+    let response = client_a
+        .client()
+        .request(proto::SendRoomMessage {
+            room_id,
+            body: "one".into(),
+            nonce: Some(nonce.into()),
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert_eq!(response.message.unwrap().body, "one");
+    chat_b.read_with(cx_b, |chat, _| {
+        assert_eq!(room_chat_messages(chat), ["one", "two"])
+    });
+
+    // Messages sent while a participant is reconnecting are loaded once they rejoin.
+    server.forbid_connections();
+    server.disconnect_client(client_b.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT);
+    executor.run_until_parked();
+    chat_a
+        .update(cx_a, |chat, cx| chat.send_message("three".into(), cx))
+        .unwrap()
+        .await
+        .unwrap();
+    server.allow_connections();
+    executor.advance_clock(RECEIVE_TIMEOUT);
+    executor.run_until_parked();
+    chat_b.read_with(cx_b, |chat, _| {
+        assert_eq!(room_chat_messages(chat), ["one", "two", "three"])
+    });
+
+    fn room_chat_messages(chat: &ChatStore) -> Vec<&str> {
+        chat.messages()
+            .iter()
+            .map(|message| message.body.as_str())
+            .collect()
+    }
+}
+
+#[gpui::test]
+async fn test_display_names(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let user_a_id = client_a.user_id().unwrap();
+    let room_b = cx_b.read(|cx| ActiveCall::global(cx).read(cx).room().unwrap().clone());
+
+    client_a
+        .user_store()
+        .update(cx_a, |store, cx| {
+            store.set_display_name(Some(" Ada ".into()), cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+
+    // The new name is shown to the user, their contacts, and the room's participants.
+    client_a.user_store().read_with(cx_a, |store, _| {
+        assert_eq!(store.current_user().unwrap().display_name(), "Ada");
+    });
+    client_b.user_store().read_with(cx_b, |store, cx| {
+        assert_eq!(store.contacts()[0].user.display_name(), "Ada");
+        assert_eq!(
+            store.participant_names([user_a_id].into_iter(), cx)[&user_a_id].as_ref(),
+            "Ada"
+        );
+    });
+    room_b.read_with(cx_b, |room, _| {
+        assert_eq!(
+            room.remote_participants()[&user_a_id].user.display_name(),
+            "Ada"
+        );
+    });
+
+    // Names are unique regardless of case.
+    client_b
+        .user_store()
+        .update(cx_b, |store, cx| {
+            store.set_display_name(Some("ADA".into()), cx)
+        })
+        .await
+        .unwrap_err();
+
+    // Clearing the name goes back to showing the GitHub login.
+    client_a
+        .user_store()
+        .update(cx_a, |store, cx| store.set_display_name(None, cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    room_b.read_with(cx_b, |room, _| {
+        assert_eq!(
+            room.remote_participants()[&user_a_id].user.display_name(),
+            "user_a"
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_avatar_uploads(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let user_a_id = client_a.user_id().unwrap();
+    let room_b = cx_b.read(|cx| ActiveCall::global(cx).read(cx).room().unwrap().clone());
+
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+    client_a
+        .user_store()
+        .update(cx_a, |store, cx| store.upload_avatar(png.clone(), cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+
+    // The uploaded avatar is served under its digest, and shown to the user's
+    // contacts and the room's participants instead of their GitHub avatar.
+    let avatar_uri = client_a.user_store().read_with(cx_a, |store, _| {
+        store.current_user().unwrap().avatar_uri.clone()
+    });
+    assert!(avatar_uri.starts_with("http://collab.test/avatars/"));
+    client_b.user_store().read_with(cx_b, |store, _| {
+        assert_eq!(store.contacts()[0].user.avatar_uri, avatar_uri);
+    });
+    room_b.read_with(cx_b, |room, _| {
+        assert_eq!(
+            room.remote_participants()[&user_a_id].user.avatar_uri,
+            avatar_uri
+        );
+    });
+
+    // Uploading the same image again keeps the same URL.
+    client_b
+        .user_store()
+        .update(cx_b, |store, cx| store.upload_avatar(png, cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    client_a.user_store().read_with(cx_a, |store, _| {
+        assert_eq!(store.contacts()[0].user.avatar_uri, avatar_uri);
+    });
+
+    // Anything other than an image is rejected.
+    client_a
+        .user_store()
+        .update(cx_a, |store, cx| {
+            store.upload_avatar(b"<svg></svg>".to_vec(), cx)
+        })
+        .await
+        .unwrap_err();
+}
+
 #[gpui::test(iterations = 10)]
 async fn test_calling_multiple_users_simultaneously(
     executor: BackgroundExecutor,
@@ -638,13 +1111,21 @@ async fn test_room_uniqueness(
     let call_b1 = incoming_call_b.next().await.unwrap().unwrap();
     assert_eq!(call_b1.calling_user.github_login, "user_a");
 
-    // Ensure calling users A and B from client C fails.
+    // Calling user A from client C rings them with a waiting call, since they're
+    // already in a call, but calling user B fails while they're being called.
     active_call_c
         .update(cx_c, |call, cx| {
             call.invite(client_a.user_id().unwrap(), None, cx)
         })
         .await
-        .unwrap_err();
+        .unwrap();
+    executor.run_until_parked();
+    active_call_a.read_with(cx_a, |call, _| {
+        let waiting_calls = call.waiting_calls();
+        assert_eq!(waiting_calls.len(), 1);
+        assert_eq!(waiting_calls[0].calling_user.github_login, "user_c");
+        assert!(waiting_calls[0].waiting);
+    });
     active_call_c
         .update(cx_c, |call, cx| {
             call.invite(client_b.user_id().unwrap(), None, cx)
@@ -662,7 +1143,7 @@ async fn test_room_uniqueness(
 
     active_call_b2.read_with(cx_b2, |call, _| assert!(call.room().is_none()));
 
-    // User B joins the room and calling them after they've joined still fails.
+    // User B joins the room, after which calling them rings them with a waiting call.
     active_call_b
         .update(cx_b, |call, cx| call.accept_incoming(cx))
         .await
@@ -672,7 +1153,9 @@ async fn test_room_uniqueness(
             call.invite(client_b.user_id().unwrap(), None, cx)
         })
         .await
-        .unwrap_err();
+        .unwrap();
+    executor.run_until_parked();
+    active_call_b.read_with(cx_b, |call, _| assert_eq!(call.waiting_calls().len(), 1));
 
     // Ensure User B can't create a room while they belong to another room.
     active_call_b2
@@ -699,127 +1182,158 @@ async fn test_room_uniqueness(
     executor.run_until_parked();
     let call_b2 = incoming_call_b.next().await.unwrap().unwrap();
     assert_eq!(call_b2.calling_user.github_login, "user_c");
+    assert!(!call_b2.waiting);
+    active_call_b.read_with(cx_b, |call, _| assert!(call.waiting_calls().is_empty()));
 }
 
 #[gpui::test(iterations = 10)]
-async fn test_client_disconnecting_from_room(
+async fn test_call_waiting(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
     server
-        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
         .await;
 
     let active_call_a = cx_a.read(ActiveCall::global);
     let active_call_b = cx_b.read(ActiveCall::global);
+    let active_call_c = cx_c.read(ActiveCall::global);
 
-    // Call user B from client A.
+    // User A calls user B, who accepts.
     active_call_a
         .update(cx_a, |call, cx| {
             call.invite(client_b.user_id().unwrap(), None, cx)
         })
         .await
         .unwrap();
-
-    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
-
-    // User B receives the call and joins the room.
-
     let mut incoming_call_b = active_call_b.read_with(cx_b, |call, _| call.incoming());
     incoming_call_b.next().await.unwrap().unwrap();
     active_call_b
         .update(cx_b, |call, cx| call.accept_incoming(cx))
         .await
         .unwrap();
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
 
-    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
+    // User C calls user A while they're in the call, and user A declines the waiting call.
+    let mut incoming_call_a = active_call_a.read_with(cx_a, |call, _| call.incoming());
+    active_call_c
+        .update(cx_c, |call, cx| {
+            call.invite(client_a.user_id().unwrap(), None, cx)
+        })
+        .await
+        .unwrap();
+    let room_c = active_call_c.read_with(cx_c, |call, _| call.room().unwrap().clone());
     executor.run_until_parked();
     assert_eq!(
-        room_participants(&room_a, cx_a),
-        RoomParticipants {
-            remote: vec!["user_b".to_string()],
-            pending: Default::default()
-        }
-    );
-    assert_eq!(
-        room_participants(&room_b, cx_b),
+        room_participants(&room_c, cx_c),
         RoomParticipants {
-            remote: vec!["user_a".to_string()],
-            pending: Default::default()
+            remote: Default::default(),
+            pending: vec!["user_a".to_string()]
         }
     );
+    let call_a = incoming_call_a.next().await.unwrap().unwrap();
+    assert_eq!(call_a.calling_user.github_login, "user_c");
+    assert!(call_a.waiting);
+    active_call_a.update(cx_a, |call, cx| call.decline_incoming(cx).unwrap());
+    assert!(incoming_call_a.next().await.unwrap().is_none());
+    executor.run_until_parked();
+    active_call_a.read_with(cx_a, |call, _| {
+        assert!(call.waiting_calls().is_empty());
+        assert_eq!(call.room(), Some(&room_a));
+    });
 
-    // User A automatically reconnects to the room upon disconnection.
-    server.disconnect_client(client_a.peer_id().unwrap());
-    executor.advance_clock(RECEIVE_TIMEOUT);
+    // User C calls again, and this time user A hangs up and accepts.
+    active_call_c
+        .update(cx_c, |call, cx| {
+            call.invite(client_a.user_id().unwrap(), None, cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    let waiting_room_id = active_call_a.read_with(cx_a, |call, _| call.waiting_calls()[0].room_id);
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.hang_up_and_accept(waiting_room_id, cx)
+        })
+        .await
+        .unwrap();
     executor.run_until_parked();
+
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+    assert_eq!(room_a.read_with(cx_a, |room, _| room.id()), waiting_room_id);
     assert_eq!(
         room_participants(&room_a, cx_a),
         RoomParticipants {
-            remote: vec!["user_b".to_string()],
+            remote: vec!["user_c".to_string()],
             pending: Default::default()
         }
     );
     assert_eq!(
-        room_participants(&room_b, cx_b),
+        room_participants(&room_c, cx_c),
         RoomParticipants {
             remote: vec!["user_a".to_string()],
             pending: Default::default()
         }
     );
+    active_call_a.read_with(cx_a, |call, _| assert!(call.waiting_calls().is_empty()));
 
-    // When user A disconnects, both client A and B clear their room on the active call.
-    server.forbid_connections();
-    server.disconnect_client(client_a.peer_id().unwrap());
-    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
-
-    active_call_a.read_with(cx_a, |call, _| assert!(call.room().is_none()));
-
+    // User B was left alone in the old call, which ended for them.
     active_call_b.read_with(cx_b, |call, _| assert!(call.room().is_none()));
-    assert_eq!(
-        room_participants(&room_a, cx_a),
-        RoomParticipants {
-            remote: Default::default(),
-            pending: Default::default()
-        }
-    );
-    assert_eq!(
-        room_participants(&room_b, cx_b),
-        RoomParticipants {
-            remote: Default::default(),
-            pending: Default::default()
-        }
-    );
+}
 
-    // Allow user A to reconnect to the server.
-    server.allow_connections();
-    executor.advance_clock(RECEIVE_TIMEOUT);
+#[gpui::test(iterations = 10)]
+async fn test_calls_while_database_is_failing(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    server
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
 
-    // Call user B again from client A.
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
+
+    // User A calls user B, who accepts.
     active_call_a
         .update(cx_a, |call, cx| {
             call.invite(client_b.user_id().unwrap(), None, cx)
         })
         .await
         .unwrap();
-
-    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
-
-    // User B receives the call and joins the room.
-
     let mut incoming_call_b = active_call_b.read_with(cx_b, |call, _| call.incoming());
     incoming_call_b.next().await.unwrap().unwrap();
     active_call_b
         .update(cx_b, |call, cx| call.accept_incoming(cx))
         .await
         .unwrap();
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
 
-    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
+    // While every transaction fails, user A's invitation to user C is rejected
+    // instead of leaving user A waiting for a response.
+    server.inject_database_faults(
+        DatabaseFaults {
+            error_probability: 1.,
+            ..Default::default()
+        },
+        StdRng::seed_from_u64(0),
+    );
+    let invite = active_call_a.update(cx_a, |call, cx| {
+        call.invite(client_c.user_id().unwrap(), None, cx)
+    });
     executor.run_until_parked();
+    invite.await.unwrap_err();
     assert_eq!(
         room_participants(&room_a, cx_a),
         RoomParticipants {
@@ -827,1080 +1341,1916 @@ async fn test_client_disconnecting_from_room(
             pending: Default::default()
         }
     );
-    assert_eq!(
-        room_participants(&room_b, cx_b),
-        RoomParticipants {
-            remote: vec!["user_a".to_string()],
-            pending: Default::default()
-        }
-    );
 
-    // User B gets disconnected from the LiveKit server, which causes it
-    // to automatically leave the room.
-    server
-        .test_live_kit_server
-        .disconnect_client(client_b.user_id().unwrap().to_string())
-        .await;
+    // Once the database recovers, the same invitation goes through.
+    server.set_database_faults(DatabaseFaults::default());
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_c.user_id().unwrap(), None, cx)
+        })
+        .await
+        .unwrap();
     executor.run_until_parked();
-    active_call_a.update(cx_a, |call, _| assert!(call.room().is_none()));
-    active_call_b.update(cx_b, |call, _| assert!(call.room().is_none()));
     assert_eq!(
         room_participants(&room_a, cx_a),
         RoomParticipants {
-            remote: Default::default(),
-            pending: Default::default()
-        }
-    );
-    assert_eq!(
-        room_participants(&room_b, cx_b),
-        RoomParticipants {
-            remote: Default::default(),
-            pending: Default::default()
+            remote: vec!["user_b".to_string()],
+            pending: vec!["user_c".to_string()]
         }
     );
 }
 
 #[gpui::test(iterations = 10)]
-async fn test_server_restarts(
+async fn test_host_messages_while_database_is_failing(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
-    cx_c: &mut TestAppContext,
-    cx_d: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    let mut language = Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["rs".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    );
+    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
+    client_a.language_registry().add(Arc::new(language));
     client_a
         .fs()
-        .insert_tree("/a", json!({ "a.txt": "a-contents" }))
+        .insert_tree("/a", json!({ "a.rs": "let one = two" }))
         .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+    let _buffer = project_a
+        .update(cx_a, |project, cx| {
+            project.open_buffer((worktree_id, "a.rs"), cx)
+        })
+        .await
+        .unwrap();
+    let fake_language_server = fake_language_servers.next().await.unwrap();
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    executor.run_until_parked();
 
-    // Invite client B to collaborate on a project
-    let (project_a, _) = client_a.build_local_project("/a", cx_a).await;
+    // The server can't store the diagnostics the host sends while every transaction
+    // fails, and as messages have no response to fail, it disconnects the host.
+    server.inject_database_faults(
+        DatabaseFaults {
+            error_probability: 1.,
+            ..Default::default()
+        },
+        StdRng::seed_from_u64(0),
+    );
+    fake_language_server.notify::<lsp::notification::PublishDiagnostics>(
+        lsp::PublishDiagnosticsParams {
+            uri: lsp::Url::from_file_path("/a/a.rs").unwrap(),
+            version: None,
+            diagnostics: vec![lsp::Diagnostic {
+                severity: Some(lsp::DiagnosticSeverity::ERROR),
+                range: lsp::Range::new(lsp::Position::new(0, 4), lsp::Position::new(0, 7)),
+                message: "message 1".to_string(),
+                ..Default::default()
+            }],
+        },
+    );
+    executor.run_until_parked();
+    assert!(!client_a.status().borrow().is_connected());
+    project_b.read_with(cx_b, |project, cx| {
+        assert_eq!(project.diagnostic_summaries(false, cx).count(), 0);
+    });
+
+    // Once the database recovers, the host reconnects and sends the diagnostics again.
+    server.set_database_faults(DatabaseFaults::default());
+    executor.advance_clock(RECEIVE_TIMEOUT);
+    executor.run_until_parked();
+    assert!(client_a.status().borrow().is_connected());
+    project_b.read_with(cx_b, |project, cx| {
+        assert_eq!(
+            project.diagnostic_summaries(false, cx).collect::<Vec<_>>(),
+            &[(
+                ProjectPath {
+                    worktree_id,
+                    path: Arc::from(Path::new("a.rs")),
+                },
+                LanguageServerId(0),
+                DiagnosticSummary {
+                    error_count: 1,
+                    warning_count: 0,
+                    ..Default::default()
+                },
+            )]
+        )
+    });
+}
 
+#[gpui::test(iterations = 10)]
+async fn test_invite_context(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
     let client_c = server.create_client(cx_c, "user_c").await;
-    let client_d = server.create_client(cx_d, "user_d").await;
     server
-        .make_contacts(&mut [
-            (&client_a, cx_a),
-            (&client_b, cx_b),
-            (&client_c, cx_c),
-            (&client_d, cx_d),
-        ])
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+    client_a
+        .fs()
+        .insert_tree("/a", json!({ "foo.rs": "one\ntwo\nthree" }))
         .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
 
     let active_call_a = cx_a.read(ActiveCall::global);
     let active_call_b = cx_b.read(ActiveCall::global);
-    let active_call_c = cx_c.read(ActiveCall::global);
-    let active_call_d = cx_d.read(ActiveCall::global);
 
-    // User A calls users B, C, and D.
+    // User A invites user B with a message pointing at a line in the shared project.
+    let context = InviteContext {
+        message: "  can you look at this?  ".into(),
+        path: Some(ProjectPath {
+            worktree_id,
+            path: Path::new("foo.rs").into(),
+        }),
+        row: Some(1),
+    };
     active_call_a
         .update(cx_a, |call, cx| {
-            call.invite(client_b.user_id().unwrap(), Some(project_a.clone()), cx)
+            call.invite_with_context(
+                client_b.user_id().unwrap(),
+                Some(project_a.clone()),
+                Some(context.clone()),
+                cx,
+            )
         })
         .await
         .unwrap();
+
+    let mut incoming_call_b = active_call_b.read_with(cx_b, |call, _| call.incoming());
+    let call_b = incoming_call_b.next().await.unwrap().unwrap();
+    assert_eq!(
+        call_b.context,
+        Some(InviteContext {
+            message: "can you look at this?".into(),
+            ..context
+        })
+    );
+
+    // Messages that are too long are rejected.
     active_call_a
         .update(cx_a, |call, cx| {
-            call.invite(client_c.user_id().unwrap(), Some(project_a.clone()), cx)
+            call.invite_with_context(
+                client_c.user_id().unwrap(),
+                None,
+                Some(InviteContext {
+                    message: "a".repeat(281),
+                    ..Default::default()
+                }),
+                cx,
+            )
         })
         .await
-        .unwrap();
+        .unwrap_err();
+}
+
+#[gpui::test]
+async fn test_time_boxed_rooms(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
+    let events_a = active_call_events(cx_a);
+    let events_b = active_call_events(cx_b);
+    let countdown_events = |events: &Rc<RefCell<Vec<room::Event>>>| {
+        mem::take(&mut *events.borrow_mut())
+            .into_iter()
+            .filter(|event| {
+                matches!(
+                    event,
+                    room::Event::CountdownWarning { .. } | room::Event::Ended
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // User A calls user B into a room that lasts for 90 seconds.
     active_call_a
         .update(cx_a, |call, cx| {
-            call.invite(client_d.user_id().unwrap(), Some(project_a.clone()), cx)
+            call.invite_to_time_boxed_room(
+                client_b.user_id().unwrap(),
+                None,
+                Duration::from_secs(90),
+                cx,
+            )
         })
         .await
         .unwrap();
-
+    let mut incoming_call_b = active_call_b.read_with(cx_b, |call, _| call.incoming());
+    incoming_call_b.next().await.unwrap().unwrap();
+    active_call_b
+        .update(cx_b, |call, cx| call.accept_incoming(cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
     let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
+    let ends_at = room_a.read_with(cx_a, |room, _| room.ends_at()).unwrap();
+    assert_eq!(
+        room_b.read_with(cx_b, |room, _| room.ends_at()),
+        Some(ends_at)
+    );
+    let started_at = room_a.read_with(cx_a, |room, _| room.started_at()).unwrap();
+    assert!(started_at < ends_at);
+    assert_eq!(
+        room_b.read_with(cx_b, |room, _| room.started_at()),
+        Some(started_at)
+    );
 
-    // User B receives the call and joins the room.
+    // Both participants are warned when a minute is left.
+    executor.advance_clock(Duration::from_secs(31));
+    executor.run_until_parked();
+    let warning = room::Event::CountdownWarning {
+        remaining: Duration::from_secs(60),
+    };
+    assert_eq!(countdown_events(&events_a), [warning.clone()]);
+    assert_eq!(countdown_events(&events_b), [warning]);
 
-    let mut incoming_call_b = active_call_b.read_with(cx_b, |call, _| call.incoming());
-    assert!(incoming_call_b.next().await.unwrap().is_some());
+    // Only the host can extend the room.
+    room_b
+        .update(cx_b, |room, cx| room.extend(Duration::from_secs(60), cx))
+        .await
+        .unwrap_err();
+    room_a
+        .update(cx_a, |room, cx| room.extend(Duration::from_secs(60), cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert_eq!(
+        room_b.read_with(cx_b, |room, _| room.ends_at()),
+        Some(ends_at + Duration::from_secs(60))
+    );
+
+    // The room's original end passes without it ending.
+    executor.advance_clock(Duration::from_secs(60));
+    executor.run_until_parked();
+    assert!(!countdown_events(&events_a).contains(&room::Event::Ended));
+    assert!(active_call_a.read_with(cx_a, |call, _| call.room().is_some()));
+
+    // Once the extension runs out, everyone is removed from the room.
+    executor.advance_clock(Duration::from_secs(120));
+    executor.run_until_parked();
+    assert_eq!(
+        countdown_events(&events_a).last(),
+        Some(&room::Event::Ended)
+    );
+    assert_eq!(
+        countdown_events(&events_b).last(),
+        Some(&room::Event::Ended)
+    );
+    assert!(active_call_a.read_with(cx_a, |call, _| call.room().is_none()));
+    assert!(active_call_b.read_with(cx_b, |call, _| call.room().is_none()));
+}
+
+#[gpui::test]
+async fn test_admin_room_and_user_management(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    let user_a = UserId::from_proto(client_a.user_id().unwrap());
+    let user_b = UserId::from_proto(client_b.user_id().unwrap());
+    let user_c = UserId::from_proto(client_c.user_id().unwrap());
+    server
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
+    let active_call_c = cx_c.read(ActiveCall::global);
+
+    // User A calls users B and C, and only user B answers.
+    active_call_a
+        .update(cx_a, |call, cx| call.invite(user_b.to_proto(), None, cx))
+        .await
+        .unwrap();
+    active_call_a
+        .update(cx_a, |call, cx| call.invite(user_c.to_proto(), None, cx))
+        .await
+        .unwrap();
     active_call_b
         .update(cx_b, |call, cx| call.accept_incoming(cx))
         .await
         .unwrap();
+    executor.run_until_parked();
 
-    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
-
-    // User C receives the call and joins the room.
+    // Admins can list the rooms and who's in them.
+    let rooms = server.app_state.db.list_rooms().await.unwrap();
+    assert_eq!(rooms.len(), 1);
+    assert_eq!(rooms[0].participant_user_ids, [user_a, user_b]);
+    assert_eq!(rooms[0].pending_user_ids, [user_c]);
 
+    // Terminating the room removes its participants and cancels its calls.
+    let room_id = rooms[0].id;
     let mut incoming_call_c = active_call_c.read_with(cx_c, |call, _| call.incoming());
-    assert!(incoming_call_c.next().await.unwrap().is_some());
-    active_call_c
-        .update(cx_c, |call, cx| call.accept_incoming(cx))
+    assert!(server.terminate_room(room_id).await.unwrap());
+    executor.run_until_parked();
+    assert!(active_call_a.read_with(cx_a, |call, _| call.room().is_none()));
+    assert!(active_call_b.read_with(cx_b, |call, _| call.room().is_none()));
+    assert!(incoming_call_c.next().await.unwrap().is_none());
+    assert!(server.app_state.db.list_rooms().await.unwrap().is_empty());
+    assert!(!server.terminate_room(room_id).await.unwrap());
+
+    // Connections can be closed, after which the client reconnects.
+    let connection_id = server.user_connection_stats(user_b).await[0].connection_id;
+    server.disconnect(connection_id).unwrap();
+    executor.advance_clock(RECEIVE_TIMEOUT);
+    executor.run_until_parked();
+    let connections = server.user_connection_stats(user_b).await;
+    assert_eq!(connections.len(), 1);
+    assert_ne!(connections[0].connection_id, connection_id);
+    server.disconnect(connection_id).unwrap_err();
+
+    // Invite counts can be adjusted.
+    server
+        .app_state
+        .db
+        .set_invite_count(user_a, 5)
         .await
         .unwrap();
+    let user = server.app_state.db.get_user_by_id(user_a).await.unwrap();
+    assert_eq!(user.unwrap().invite_count, 5);
+    server
+        .app_state
+        .db
+        .set_invite_count(UserId(1000), 5)
+        .await
+        .unwrap_err();
 
-    let room_c = active_call_c.read_with(cx_c, |call, _| call.room().unwrap().clone());
+    // Banned users are signed out of every server, including those other than the one
+    // that banned them. The test server doesn't check access tokens, which stop working
+    // for banned users, so connections are forbidden instead.
+    server.forbid_connections();
+    let new_server = server.deploy(&executor).await;
+    new_server.ban_user(user_c).await.unwrap();
+    executor.advance_clock(RECEIVE_TIMEOUT);
+    executor.run_until_parked();
+    assert_eq!(server.user_connection_count(user_c), 0);
+    let user = server.app_state.db.get_user_by_id(user_c).await.unwrap();
+    assert!(user.unwrap().banned_at.is_some());
 
-    // User D receives the call but doesn't join the room yet.
+    server
+        .app_state
+        .db
+        .set_user_banned(user_c, false)
+        .await
+        .unwrap();
+    let user = server.app_state.db.get_user_by_id(user_c).await.unwrap();
+    assert!(user.unwrap().banned_at.is_none());
+}
 
-    let mut incoming_call_d = active_call_d.read_with(cx_d, |call, _| call.incoming());
-    assert!(incoming_call_d.next().await.unwrap().is_some());
+#[gpui::test(iterations = 10)]
+async fn test_room_observers(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    server
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
 
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
+    let active_call_c = cx_c.read(ActiveCall::global);
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
+
+    client_a
+        .fs()
+        .insert_tree("/a", json!({ "a.txt": "a-contents" }))
+        .await;
+    let (project_a, _) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    // Only the room's admins can invite observers.
+    active_call_b
+        .update(cx_b, |call, cx| {
+            call.invite_observer(client_c.user_id().unwrap(), cx)
+        })
+        .await
+        .unwrap_err();
+
+    // User C is invited to observe, and is told about everyone in the room.
+    let mut incoming_call_c = active_call_c.read_with(cx_c, |call, _| call.incoming());
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite_observer(client_c.user_id().unwrap(), cx)
+        })
+        .await
+        .unwrap();
+    let call_c = incoming_call_c.next().await.unwrap().unwrap();
+    assert!(call_c.observer);
+    assert_eq!(call_c.participants.len(), 2);
     executor.run_until_parked();
     assert_eq!(
         room_participants(&room_a, cx_a),
         RoomParticipants {
-            remote: vec!["user_b".to_string(), "user_c".to_string()],
-            pending: vec!["user_d".to_string()]
+            remote: vec!["user_b".to_string()],
+            pending: vec!["user_c".to_string()]
         }
     );
     assert_eq!(
         room_participants(&room_b, cx_b),
         RoomParticipants {
-            remote: vec!["user_a".to_string(), "user_c".to_string()],
-            pending: vec!["user_d".to_string()]
-        }
-    );
-    assert_eq!(
-        room_participants(&room_c, cx_c),
-        RoomParticipants {
-            remote: vec!["user_a".to_string(), "user_b".to_string()],
-            pending: vec!["user_d".to_string()]
+            remote: vec!["user_a".to_string()],
+            pending: Default::default()
         }
     );
 
-    // The server is torn down.
-    server.reset().await;
-
-    // Users A and B reconnect to the call. User C has troubles reconnecting, so it leaves the room.
-    client_c.override_establish_connection(|_, cx| cx.spawn(|_| future::pending()));
-    executor.advance_clock(RECONNECT_TIMEOUT);
+    // Once user C joins, user A sees them, but user B doesn't.
+    active_call_c
+        .update(cx_c, |call, cx| call.accept_incoming(cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    let room_c = active_call_c.read_with(cx_c, |call, _| call.room().unwrap().clone());
     assert_eq!(
         room_participants(&room_a, cx_a),
         RoomParticipants {
             remote: vec!["user_b".to_string(), "user_c".to_string()],
-            pending: vec!["user_d".to_string()]
+            pending: Default::default()
         }
     );
     assert_eq!(
         room_participants(&room_b, cx_b),
         RoomParticipants {
-            remote: vec!["user_a".to_string(), "user_c".to_string()],
-            pending: vec!["user_d".to_string()]
+            remote: vec!["user_a".to_string()],
+            pending: Default::default()
         }
     );
     assert_eq!(
         room_participants(&room_c, cx_c),
         RoomParticipants {
-            remote: vec![],
-            pending: vec![]
+            remote: vec!["user_a".to_string(), "user_b".to_string()],
+            pending: Default::default()
         }
     );
+    room_a.read_with(cx_a, |room, _| {
+        let participant_c = room
+            .remote_participants()
+            .get(&client_c.user_id().unwrap())
+            .unwrap();
+        assert!(participant_c.observer);
+        assert_eq!(participant_c.role, proto::ChannelRole::Guest);
+    });
+    room_c.read_with(cx_c, |room, _| assert!(room.local_participant().observer));
+
+    // User B doesn't see user C as busy, and user C can't give themselves away by
+    // chatting in the room.
+    client_b.user_store().read_with(cx_b, |store, _| {
+        let contact_c = store
+            .contacts()
+            .iter()
+            .find(|contact| contact.user.id == client_c.user_id().unwrap())
+            .unwrap();
+        assert!(!contact_c.busy);
+    });
+    client_c
+        .request(proto::SendRoomMessage {
+            room_id: room_c.read_with(cx_c, |room, _| room.id()),
+            body: "hello".into(),
+            nonce: Some(1.into()),
+        })
+        .await
+        .unwrap_err();
 
-    // User D is notified again of the incoming call and accepts it.
-    assert!(incoming_call_d.next().await.unwrap().is_some());
-    active_call_d
-        .update(cx_d, |call, cx| call.accept_incoming(cx))
+    // Observers can view shared projects, without appearing in them to user B.
+    let project_c = client_c.build_remote_project(project_id, cx_c).await;
+    executor.run_until_parked();
+    project_a.read_with(cx_a, |project, _| {
+        assert_eq!(project.collaborators().len(), 2);
+    });
+    project_b.read_with(cx_b, |project, _| {
+        assert_eq!(project.collaborators().len(), 1);
+    });
+    project_c.read_with(cx_c, |project, _| {
+        assert_eq!(project.collaborators().len(), 2);
+        assert!(project.is_read_only());
+    });
+
+    // When user C leaves, user B isn't told about it either.
+    active_call_c
+        .update(cx_c, |call, cx| call.hang_up(cx))
         .await
         .unwrap();
     executor.run_until_parked();
-
-    let room_d = active_call_d.read_with(cx_d, |call, _| call.room().unwrap().clone());
     assert_eq!(
         room_participants(&room_a, cx_a),
         RoomParticipants {
-            remote: vec![
-                "user_b".to_string(),
-                "user_c".to_string(),
-                "user_d".to_string(),
-            ],
-            pending: vec![]
-        }
-    );
-    assert_eq!(
-        room_participants(&room_b, cx_b),
-        RoomParticipants {
-            remote: vec![
-                "user_a".to_string(),
-                "user_c".to_string(),
-                "user_d".to_string(),
-            ],
-            pending: vec![]
-        }
-    );
-    assert_eq!(
-        room_participants(&room_c, cx_c),
-        RoomParticipants {
-            remote: vec![],
-            pending: vec![]
-        }
-    );
-    assert_eq!(
-        room_participants(&room_d, cx_d),
-        RoomParticipants {
-            remote: vec![
-                "user_a".to_string(),
-                "user_b".to_string(),
-                "user_c".to_string(),
-            ],
-            pending: vec![]
+            remote: vec!["user_b".to_string()],
+            pending: Default::default()
         }
     );
+    project_a.read_with(cx_a, |project, _| {
+        assert_eq!(project.collaborators().len(), 1);
+    });
+    project_b.read_with(cx_b, |project, _| {
+        assert_eq!(project.collaborators().len(), 1);
+    });
+}
 
-    // The server finishes restarting, cleaning up stale connections.
-    server.start().await.unwrap();
-    executor.advance_clock(CLEANUP_TIMEOUT);
+#[gpui::test(iterations = 10)]
+async fn test_client_disconnecting_from_room(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
+
+    // Call user B from client A.
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b.user_id().unwrap(), None, cx)
+        })
+        .await
+        .unwrap();
+
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+
+    // User B receives the call and joins the room.
+
+    let mut incoming_call_b = active_call_b.read_with(cx_b, |call, _| call.incoming());
+    incoming_call_b.next().await.unwrap().unwrap();
+    active_call_b
+        .update(cx_b, |call, cx| call.accept_incoming(cx))
+        .await
+        .unwrap();
+
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
+    executor.run_until_parked();
     assert_eq!(
         room_participants(&room_a, cx_a),
         RoomParticipants {
-            remote: vec!["user_b".to_string(), "user_d".to_string()],
-            pending: vec![]
+            remote: vec!["user_b".to_string()],
+            pending: Default::default()
         }
     );
     assert_eq!(
         room_participants(&room_b, cx_b),
         RoomParticipants {
-            remote: vec!["user_a".to_string(), "user_d".to_string()],
-            pending: vec![]
-        }
-    );
-    assert_eq!(
-        room_participants(&room_c, cx_c),
-        RoomParticipants {
-            remote: vec![],
-            pending: vec![]
-        }
-    );
-    assert_eq!(
-        room_participants(&room_d, cx_d),
-        RoomParticipants {
-            remote: vec!["user_a".to_string(), "user_b".to_string()],
-            pending: vec![]
+            remote: vec!["user_a".to_string()],
+            pending: Default::default()
         }
     );
 
-    // User D hangs up.
-    active_call_d
-        .update(cx_d, |call, cx| call.hang_up(cx))
-        .await
-        .unwrap();
+    // User A automatically reconnects to the room upon disconnection.
+    server.disconnect_client(client_a.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT);
     executor.run_until_parked();
     assert_eq!(
         room_participants(&room_a, cx_a),
         RoomParticipants {
             remote: vec!["user_b".to_string()],
-            pending: vec![]
+            pending: Default::default()
         }
     );
     assert_eq!(
         room_participants(&room_b, cx_b),
         RoomParticipants {
             remote: vec!["user_a".to_string()],
-            pending: vec![]
+            pending: Default::default()
         }
     );
+
+    // When user A disconnects, both client A and B clear their room on the active call.
+    server.forbid_connections();
+    server.disconnect_client(client_a.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
+
+    active_call_a.read_with(cx_a, |call, _| assert!(call.room().is_none()));
+
+    active_call_b.read_with(cx_b, |call, _| assert!(call.room().is_none()));
     assert_eq!(
-        room_participants(&room_c, cx_c),
+        room_participants(&room_a, cx_a),
         RoomParticipants {
-            remote: vec![],
-            pending: vec![]
+            remote: Default::default(),
+            pending: Default::default()
         }
     );
     assert_eq!(
-        room_participants(&room_d, cx_d),
+        room_participants(&room_b, cx_b),
         RoomParticipants {
-            remote: vec![],
-            pending: vec![]
+            remote: Default::default(),
+            pending: Default::default()
         }
     );
 
-    // User B calls user D again.
-    active_call_b
-        .update(cx_b, |call, cx| {
-            call.invite(client_d.user_id().unwrap(), None, cx)
+    // Allow user A to reconnect to the server.
+    server.allow_connections();
+    executor.advance_clock(RECEIVE_TIMEOUT);
+
+    // Call user B again from client A.
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b.user_id().unwrap(), None, cx)
         })
         .await
         .unwrap();
 
-    // User D receives the call but doesn't join the room yet.
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
 
-    let mut incoming_call_d = active_call_d.read_with(cx_d, |call, _| call.incoming());
-    assert!(incoming_call_d.next().await.unwrap().is_some());
+    // User B receives the call and joins the room.
+
+    let mut incoming_call_b = active_call_b.read_with(cx_b, |call, _| call.incoming());
+    incoming_call_b.next().await.unwrap().unwrap();
+    active_call_b
+        .update(cx_b, |call, cx| call.accept_incoming(cx))
+        .await
+        .unwrap();
+
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
     executor.run_until_parked();
     assert_eq!(
         room_participants(&room_a, cx_a),
         RoomParticipants {
             remote: vec!["user_b".to_string()],
-            pending: vec!["user_d".to_string()]
+            pending: Default::default()
         }
     );
     assert_eq!(
         room_participants(&room_b, cx_b),
         RoomParticipants {
             remote: vec!["user_a".to_string()],
-            pending: vec!["user_d".to_string()]
+            pending: Default::default()
         }
     );
 
-    // The server is torn down.
-    server.reset().await;
-
-    // Users A and B have troubles reconnecting, so they leave the room.
-    client_a.override_establish_connection(|_, cx| cx.spawn(|_| future::pending()));
-    client_b.override_establish_connection(|_, cx| cx.spawn(|_| future::pending()));
-    client_c.override_establish_connection(|_, cx| cx.spawn(|_| future::pending()));
-    executor.advance_clock(RECONNECT_TIMEOUT);
+    // User B gets disconnected from the LiveKit server, which causes it
+    // to automatically leave the room.
+    server
+        .test_live_kit_server
+        .disconnect_client(client_b.user_id().unwrap().to_string())
+        .await;
+    executor.run_until_parked();
+    active_call_a.update(cx_a, |call, _| assert!(call.room().is_none()));
+    active_call_b.update(cx_b, |call, _| assert!(call.room().is_none()));
     assert_eq!(
         room_participants(&room_a, cx_a),
         RoomParticipants {
-            remote: vec![],
-            pending: vec![]
+            remote: Default::default(),
+            pending: Default::default()
         }
     );
     assert_eq!(
         room_participants(&room_b, cx_b),
         RoomParticipants {
-            remote: vec![],
-            pending: vec![]
+            remote: Default::default(),
+            pending: Default::default()
         }
     );
-
-    // User D is notified again of the incoming call but doesn't accept it.
-    assert!(incoming_call_d.next().await.unwrap().is_some());
-
-    // The server finishes restarting, cleaning up stale connections and canceling the
-    // call to user D because the room has become empty.
-    server.start().await.unwrap();
-    executor.advance_clock(CLEANUP_TIMEOUT);
-    assert!(incoming_call_d.next().await.unwrap().is_none());
 }
 
 #[gpui::test(iterations = 10)]
-async fn test_calls_on_multiple_connections(
+async fn test_client_reconnects_after_system_sleep(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
-    cx_b1: &mut TestAppContext,
-    cx_b2: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
-    let client_b1 = server.create_client(cx_b1, "user_b").await;
-    let client_b2 = server.create_client(cx_b2, "user_b").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
     server
-        .make_contacts(&mut [(&client_a, cx_a), (&client_b1, cx_b1)])
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
 
     let active_call_a = cx_a.read(ActiveCall::global);
-    let active_call_b1 = cx_b1.read(ActiveCall::global);
-    let active_call_b2 = cx_b2.read(ActiveCall::global);
-
-    let mut incoming_call_b1 = active_call_b1.read_with(cx_b1, |call, _| call.incoming());
-
-    let mut incoming_call_b2 = active_call_b2.read_with(cx_b2, |call, _| call.incoming());
-    assert!(incoming_call_b1.next().await.unwrap().is_none());
-    assert!(incoming_call_b2.next().await.unwrap().is_none());
+    let active_call_b = cx_b.read(ActiveCall::global);
 
-    // Call user B from client A, ensuring both clients for user B ring.
     active_call_a
         .update(cx_a, |call, cx| {
-            call.invite(client_b1.user_id().unwrap(), None, cx)
+            call.invite(client_b.user_id().unwrap(), None, cx)
         })
         .await
         .unwrap();
     executor.run_until_parked();
-    assert!(incoming_call_b1.next().await.unwrap().is_some());
-    assert!(incoming_call_b2.next().await.unwrap().is_some());
+    active_call_b
+        .update(cx_b, |call, cx| call.accept_incoming(cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
 
-    // User B declines the call on one of the two connections, causing both connections
-    // to stop ringing.
-    active_call_b2.update(cx_b2, |call, cx| call.decline_incoming(cx).unwrap());
+    // User A's machine sleeps for several minutes. On wake-up, the client notices
+    // the jump in wall-clock time and reconnects without waiting for a timeout.
+    let peer_id_a = client_a.peer_id().unwrap();
+    server.simulate_system_sleep(peer_id_a, Duration::from_secs(5 * 60), executor.clone());
+    assert!(matches!(
+        *client_a.status().borrow(),
+        client::Status::Connected { .. }
+    ));
+    assert_ne!(client_a.peer_id(), Some(peer_id_a));
+
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string()],
+            pending: Default::default()
+        }
+    );
+    assert_eq!(
+        room_participants(&room_b, cx_b),
+        RoomParticipants {
+            remote: vec!["user_a".to_string()],
+            pending: Default::default()
+        }
+    );
+}
+
+#[gpui::test]
+async fn test_windows_share_one_connection(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+
+    // Every window in the app talks to the server through the same client.
+    let project_1 = client_a.build_empty_local_project(cx_a);
+    let project_2 = client_a.build_empty_local_project(cx_a);
+    let window_1 = client_a.workspace_for_project(&project_1, cx_a);
+    let window_2 = client_a.workspace_for_project(&project_2, cx_a);
+    for window in [window_1, window_2] {
+        window
+            .update(cx_a, |workspace, _| {
+                assert!(std::ptr::eq(
+                    workspace.client(),
+                    client_a.app_state.client.as_ref()
+                ));
+            })
+            .unwrap();
+    }
     executor.run_until_parked();
-    assert!(incoming_call_b1.next().await.unwrap().is_none());
-    assert!(incoming_call_b2.next().await.unwrap().is_none());
+    let user_id_a = client_a.current_user_id(cx_a);
+    assert_eq!(
+        server
+            .connection_pool
+            .lock()
+            .user_connection_ids(user_id_a)
+            .count(),
+        1
+    );
 
-    // Call user B again from client A.
-    active_call_a
-        .update(cx_a, |call, cx| {
-            call.invite(client_b1.user_id().unwrap(), None, cx)
-        })
+    // Closing one of the windows doesn't make the user appear offline.
+    window_1.update(cx_a, |_, cx| cx.remove_window()).unwrap();
+    executor.run_until_parked();
+    assert!(server.connection_pool.lock().is_user_online(user_id_a));
+    client_b.user_store().read_with(cx_b, |store, _| {
+        let contact = &store.contacts()[0];
+        assert_eq!(contact.user.github_login, "user_a");
+        assert!(contact.online);
+    });
+}
+
+#[gpui::test]
+async fn test_brokered_connections(executor: BackgroundExecutor, cx_a: &mut TestAppContext) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let user_id_a = client_a.current_user_id(cx_a);
+    let connection_count = || {
+        server
+            .connection_pool
+            .lock()
+            .user_connection_ids(user_id_a)
+            .count()
+    };
+    let open = |brokered_connection_id, access_token: &str| proto::OpenBrokeredConnection {
+        brokered_connection_id,
+        access_token: access_token.to_string(),
+        protocol_version: rpc::PROTOCOL_VERSION,
+        app_version: None,
+    };
+    let access_token = auth::create_access_token(&server.app_state.db, user_id_a, None)
+        .await
+        .unwrap();
+
+    // Connections are only brokered for instances with one of the user's access tokens.
+    assert!(client_a
+        .client()
+        .request(open(1, "not-a-token"))
+        .await
+        .is_err());
+
+    // Another instance's connection is carried over this one's until it hangs up.
+    client_a
+        .client()
+        .request(open(1, &access_token))
         .await
         .unwrap();
     executor.run_until_parked();
-    assert!(incoming_call_b1.next().await.unwrap().is_some());
-    assert!(incoming_call_b2.next().await.unwrap().is_some());
-
-    // User B accepts the call on one of the two connections, causing both connections
-    // to stop ringing.
-    active_call_b2
-        .update(cx_b2, |call, cx| call.accept_incoming(cx))
+    assert_eq!(connection_count(), 2);
+    assert!(client_a
+        .client()
+        .request(open(1, &access_token))
         .await
+        .is_err());
+    client_a
+        .client()
+        .send(proto::CloseBrokeredConnection {
+            brokered_connection_id: 1,
+        })
         .unwrap();
     executor.run_until_parked();
-    assert!(incoming_call_b1.next().await.unwrap().is_none());
-    assert!(incoming_call_b2.next().await.unwrap().is_none());
-
-    // User B disconnects the client that is not on the call. Everything should be fine.
-    client_b1.disconnect(&cx_b1.to_async());
-    executor.advance_clock(RECEIVE_TIMEOUT);
-    client_b1
-        .authenticate_and_connect(false, &cx_b1.to_async())
-        .await
-        .unwrap();
-
-    // User B hangs up, and user A calls them again.
-    active_call_b2
-        .update(cx_b2, |call, cx| call.hang_up(cx))
-        .await
-        .unwrap();
-    executor.run_until_parked();
-    active_call_a
-        .update(cx_a, |call, cx| {
-            call.invite(client_b1.user_id().unwrap(), None, cx)
-        })
-        .await
-        .unwrap();
-    executor.run_until_parked();
-    assert!(incoming_call_b1.next().await.unwrap().is_some());
-    assert!(incoming_call_b2.next().await.unwrap().is_some());
-
-    // User A cancels the call, causing both connections to stop ringing.
-    active_call_a
-        .update(cx_a, |call, cx| {
-            call.cancel_invite(client_b1.user_id().unwrap(), cx)
-        })
-        .await
-        .unwrap();
-    executor.run_until_parked();
-    assert!(incoming_call_b1.next().await.unwrap().is_none());
-    assert!(incoming_call_b2.next().await.unwrap().is_none());
-
-    // User A calls user B again.
-    active_call_a
-        .update(cx_a, |call, cx| {
-            call.invite(client_b1.user_id().unwrap(), None, cx)
-        })
-        .await
-        .unwrap();
-    executor.run_until_parked();
-    assert!(incoming_call_b1.next().await.unwrap().is_some());
-    assert!(incoming_call_b2.next().await.unwrap().is_some());
-
-    // User A hangs up, causing both connections to stop ringing.
-    active_call_a
-        .update(cx_a, |call, cx| call.hang_up(cx))
-        .await
-        .unwrap();
-    executor.run_until_parked();
-    assert!(incoming_call_b1.next().await.unwrap().is_none());
-    assert!(incoming_call_b2.next().await.unwrap().is_none());
+    assert_eq!(connection_count(), 1);
 
-    // User A calls user B again.
-    active_call_a
-        .update(cx_a, |call, cx| {
-            call.invite(client_b1.user_id().unwrap(), None, cx)
-        })
+    // The connections it carries are closed when the broker's connection is lost.
+    client_a
+        .client()
+        .request(open(2, &access_token))
         .await
         .unwrap();
     executor.run_until_parked();
-    assert!(incoming_call_b1.next().await.unwrap().is_some());
-    assert!(incoming_call_b2.next().await.unwrap().is_some());
-
-    // User A disconnects, causing both connections to stop ringing.
+    assert_eq!(connection_count(), 2);
     server.forbid_connections();
     server.disconnect_client(client_a.peer_id().unwrap());
-    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
-    assert!(incoming_call_b1.next().await.unwrap().is_none());
-    assert!(incoming_call_b2.next().await.unwrap().is_none());
-
-    // User A reconnects automatically, then calls user B again.
-    server.allow_connections();
     executor.advance_clock(RECEIVE_TIMEOUT);
-    active_call_a
-        .update(cx_a, |call, cx| {
-            call.invite(client_b1.user_id().unwrap(), None, cx)
-        })
-        .await
-        .unwrap();
     executor.run_until_parked();
-    assert!(incoming_call_b1.next().await.unwrap().is_some());
-    assert!(incoming_call_b2.next().await.unwrap().is_some());
+    assert_eq!(connection_count(), 0);
+}
 
-    // User B disconnects all clients, causing user A to no longer see a pending call for them.
-    server.forbid_connections();
-    server.disconnect_client(client_b1.peer_id().unwrap());
-    server.disconnect_client(client_b2.peer_id().unwrap());
-    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
+#[gpui::test]
+async fn test_min_client_version(executor: BackgroundExecutor, cx_a: &mut TestAppContext) {
+    let mut server = TestServer::start_with_config(executor.clone(), |config| {
+        config.min_client_version = Some("0.130.0".into());
+    })
+    .await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let user_id_a = client_a.current_user_id(cx_a);
+    let access_token = auth::create_access_token(&server.app_state.db, user_id_a, None)
+        .await
+        .unwrap();
+    let open = |brokered_connection_id, app_version: Option<&str>| proto::OpenBrokeredConnection {
+        brokered_connection_id,
+        access_token: access_token.clone(),
+        protocol_version: rpc::PROTOCOL_VERSION,
+        app_version: app_version.map(str::to_string),
+    };
+
+    // Clients older than the minimum version are turned away, including those too
+    // old to say which version they are.
+    for app_version in [None, Some("0.129.9")] {
+        assert!(client_a
+            .client()
+            .request(open(1, app_version))
+            .await
+            .is_err());
+    }
 
-    active_call_a.read_with(cx_a, |call, _| assert!(call.room().is_none()));
+    client_a
+        .client()
+        .request(open(1, Some("0.130.0")))
+        .await
+        .unwrap();
 }
 
 #[gpui::test(iterations = 10)]
-async fn test_unshare_project(
+async fn test_server_restarts(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
     cx_c: &mut TestAppContext,
+    cx_d: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
+    client_a
+        .fs()
+        .insert_tree("/a", json!({ "a.txt": "a-contents" }))
+        .await;
+
+    // Invite client B to collaborate on a project
+    let (project_a, _) = client_a.build_local_project("/a", cx_a).await;
+
     let client_b = server.create_client(cx_b, "user_b").await;
     let client_c = server.create_client(cx_c, "user_c").await;
+    let client_d = server.create_client(cx_d, "user_d").await;
     server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .make_contacts(&mut [
+            (&client_a, cx_a),
+            (&client_b, cx_b),
+            (&client_c, cx_c),
+            (&client_d, cx_d),
+        ])
         .await;
 
     let active_call_a = cx_a.read(ActiveCall::global);
     let active_call_b = cx_b.read(ActiveCall::global);
+    let active_call_c = cx_c.read(ActiveCall::global);
+    let active_call_d = cx_d.read(ActiveCall::global);
 
-    client_a
-        .fs()
-        .insert_tree(
-            "/a",
-            json!({
-                "a.txt": "a-contents",
-                "b.txt": "b-contents",
-            }),
-        )
-        .await;
-
-    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+    // User A calls users B, C, and D.
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b.user_id().unwrap(), Some(project_a.clone()), cx)
+        })
         .await
         .unwrap();
-
-    let worktree_a = project_a.read_with(cx_a, |project, _| project.worktrees().next().unwrap());
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
-    executor.run_until_parked();
-
-    assert!(worktree_a.read_with(cx_a, |tree, _| tree.as_local().unwrap().is_shared()));
-
-    project_b
-        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_c.user_id().unwrap(), Some(project_a.clone()), cx)
+        })
         .await
         .unwrap();
-
-    // When client B leaves the room, the project becomes read-only.
-    active_call_b
-        .update(cx_b, |call, cx| call.hang_up(cx))
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_d.user_id().unwrap(), Some(project_a.clone()), cx)
+        })
         .await
         .unwrap();
-    executor.run_until_parked();
 
-    assert!(project_b.read_with(cx_b, |project, _| project.is_disconnected()));
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
 
-    // Client C opens the project.
-    let project_c = client_c.build_remote_project(project_id, cx_c).await;
+    // User B receives the call and joins the room.
 
-    // When client A unshares the project, client C's project becomes read-only.
-    project_a
-        .update(cx_a, |project, cx| project.unshare(cx))
+    let mut incoming_call_b = active_call_b.read_with(cx_b, |call, _| call.incoming());
+    assert!(incoming_call_b.next().await.unwrap().is_some());
+    active_call_b
+        .update(cx_b, |call, cx| call.accept_incoming(cx))
+        .await
         .unwrap();
-    executor.run_until_parked();
 
-    assert!(worktree_a.read_with(cx_a, |tree, _| !tree.as_local().unwrap().is_shared()));
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
 
-    assert!(project_c.read_with(cx_c, |project, _| project.is_disconnected()));
+    // User C receives the call and joins the room.
 
-    // Client C can open the project again after client A re-shares.
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+    let mut incoming_call_c = active_call_c.read_with(cx_c, |call, _| call.incoming());
+    assert!(incoming_call_c.next().await.unwrap().is_some());
+    active_call_c
+        .update(cx_c, |call, cx| call.accept_incoming(cx))
         .await
         .unwrap();
-    let project_c2 = client_c.build_remote_project(project_id, cx_c).await;
+
+    let room_c = active_call_c.read_with(cx_c, |call, _| call.room().unwrap().clone());
+
+    // User D receives the call but doesn't join the room yet.
+
+    let mut incoming_call_d = active_call_d.read_with(cx_d, |call, _| call.incoming());
+    assert!(incoming_call_d.next().await.unwrap().is_some());
+
     executor.run_until_parked();
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string(), "user_c".to_string()],
+            pending: vec!["user_d".to_string()]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_b, cx_b),
+        RoomParticipants {
+            remote: vec!["user_a".to_string(), "user_c".to_string()],
+            pending: vec!["user_d".to_string()]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_c, cx_c),
+        RoomParticipants {
+            remote: vec!["user_a".to_string(), "user_b".to_string()],
+            pending: vec!["user_d".to_string()]
+        }
+    );
 
-    assert!(worktree_a.read_with(cx_a, |tree, _| tree.as_local().unwrap().is_shared()));
-    project_c2
-        .update(cx_c, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
-        .await
-        .unwrap();
+    // The server is torn down.
+    server.reset().await;
 
-    // When client A (the host) leaves the room, the project gets unshared and guests are notified.
-    active_call_a
-        .update(cx_a, |call, cx| call.hang_up(cx))
+    // Users A and B reconnect to the call. User C has troubles reconnecting, so it leaves the room.
+    client_c.override_establish_connection(|_, cx| cx.spawn(|_| future::pending()));
+    executor.advance_clock(RECONNECT_TIMEOUT);
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string(), "user_c".to_string()],
+            pending: vec!["user_d".to_string()]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_b, cx_b),
+        RoomParticipants {
+            remote: vec!["user_a".to_string(), "user_c".to_string()],
+            pending: vec!["user_d".to_string()]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_c, cx_c),
+        RoomParticipants {
+            remote: vec![],
+            pending: vec![]
+        }
+    );
+
+    // User D is notified again of the incoming call and accepts it.
+    assert!(incoming_call_d.next().await.unwrap().is_some());
+    active_call_d
+        .update(cx_d, |call, cx| call.accept_incoming(cx))
         .await
         .unwrap();
     executor.run_until_parked();
 
-    project_a.read_with(cx_a, |project, _| assert!(!project.is_shared()));
-
-    project_c2.read_with(cx_c, |project, _| {
-        assert!(project.is_disconnected());
-        assert!(project.collaborators().is_empty());
-    });
+    let room_d = active_call_d.read_with(cx_d, |call, _| call.room().unwrap().clone());
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec![
+                "user_b".to_string(),
+                "user_c".to_string(),
+                "user_d".to_string(),
+            ],
+            pending: vec![]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_b, cx_b),
+        RoomParticipants {
+            remote: vec![
+                "user_a".to_string(),
+                "user_c".to_string(),
+                "user_d".to_string(),
+            ],
+            pending: vec![]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_c, cx_c),
+        RoomParticipants {
+            remote: vec![],
+            pending: vec![]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_d, cx_d),
+        RoomParticipants {
+            remote: vec![
+                "user_a".to_string(),
+                "user_b".to_string(),
+                "user_c".to_string(),
+            ],
+            pending: vec![]
+        }
+    );
+
+    // The server finishes restarting. User C keeps its place in the room until the
+    // rejoin window closes, at which point stale connections are cleaned up.
+    server.start().await.unwrap();
+    executor.advance_clock(CLEANUP_TIMEOUT);
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec![
+                "user_b".to_string(),
+                "user_c".to_string(),
+                "user_d".to_string(),
+            ],
+            pending: vec![]
+        }
+    );
+    executor.advance_clock(REJOIN_WINDOW - CLEANUP_TIMEOUT);
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string(), "user_d".to_string()],
+            pending: vec![]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_b, cx_b),
+        RoomParticipants {
+            remote: vec!["user_a".to_string(), "user_d".to_string()],
+            pending: vec![]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_c, cx_c),
+        RoomParticipants {
+            remote: vec![],
+            pending: vec![]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_d, cx_d),
+        RoomParticipants {
+            remote: vec!["user_a".to_string(), "user_b".to_string()],
+            pending: vec![]
+        }
+    );
+
+    // User D hangs up.
+    active_call_d
+        .update(cx_d, |call, cx| call.hang_up(cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string()],
+            pending: vec![]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_b, cx_b),
+        RoomParticipants {
+            remote: vec!["user_a".to_string()],
+            pending: vec![]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_c, cx_c),
+        RoomParticipants {
+            remote: vec![],
+            pending: vec![]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_d, cx_d),
+        RoomParticipants {
+            remote: vec![],
+            pending: vec![]
+        }
+    );
+
+    // User B calls user D again.
+    active_call_b
+        .update(cx_b, |call, cx| {
+            call.invite(client_d.user_id().unwrap(), None, cx)
+        })
+        .await
+        .unwrap();
+
+    // User D receives the call but doesn't join the room yet.
+
+    let mut incoming_call_d = active_call_d.read_with(cx_d, |call, _| call.incoming());
+    assert!(incoming_call_d.next().await.unwrap().is_some());
+    executor.run_until_parked();
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string()],
+            pending: vec!["user_d".to_string()]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_b, cx_b),
+        RoomParticipants {
+            remote: vec!["user_a".to_string()],
+            pending: vec!["user_d".to_string()]
+        }
+    );
+
+    // The server is torn down.
+    server.reset().await;
+
+    // Users A and B have troubles reconnecting, so they leave the room.
+    client_a.override_establish_connection(|_, cx| cx.spawn(|_| future::pending()));
+    client_b.override_establish_connection(|_, cx| cx.spawn(|_| future::pending()));
+    client_c.override_establish_connection(|_, cx| cx.spawn(|_| future::pending()));
+    executor.advance_clock(RECONNECT_TIMEOUT);
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec![],
+            pending: vec![]
+        }
+    );
+    assert_eq!(
+        room_participants(&room_b, cx_b),
+        RoomParticipants {
+            remote: vec![],
+            pending: vec![]
+        }
+    );
+
+    // User D is notified again of the incoming call but doesn't accept it.
+    assert!(incoming_call_d.next().await.unwrap().is_some());
+
+    // The server finishes restarting. Once the rejoin window closes, it cleans up
+    // stale connections and cancels the call to user D because the room has become
+    // empty.
+    server.start().await.unwrap();
+    executor.advance_clock(REJOIN_WINDOW);
+    assert!(incoming_call_d.next().await.unwrap().is_none());
 }
 
-#[gpui::test(iterations = 10)]
-async fn test_project_reconnect(
+#[gpui::test]
+async fn test_rooms_spanning_deployments(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
     server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
-        .await;
-
-    cx_b.update(editor::init);
-
-    client_a
-        .fs()
-        .insert_tree(
-            "/root-1",
-            json!({
-                "dir1": {
-                    "a.txt": "a",
-                    "b.txt": "b",
-                    "subdir1": {
-                        "c.txt": "c",
-                        "d.txt": "d",
-                        "e.txt": "e",
-                    }
-                },
-                "dir2": {
-                    "v.txt": "v",
-                },
-                "dir3": {
-                    "w.txt": "w",
-                    "x.txt": "x",
-                    "y.txt": "y",
-                },
-                "dir4": {
-                    "z.txt": "z",
-                },
-            }),
-        )
-        .await;
-    client_a
-        .fs()
-        .insert_tree(
-            "/root-2",
-            json!({
-                "2.txt": "2",
-            }),
-        )
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
         .await;
-    client_a
-        .fs()
-        .insert_tree(
-            "/root-3",
-            json!({
-                "3.txt": "3",
-            }),
-        )
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
-
     let active_call_a = cx_a.read(ActiveCall::global);
-    let (project_a1, _) = client_a.build_local_project("/root-1/dir1", cx_a).await;
-    let (project_a2, _) = client_a.build_local_project("/root-2", cx_a).await;
-    let (project_a3, _) = client_a.build_local_project("/root-3", cx_a).await;
-    let worktree_a1 = project_a1.read_with(cx_a, |project, _| project.worktrees().next().unwrap());
-    let project1_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a1.clone(), cx))
-        .await
-        .unwrap();
-    let project2_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a2.clone(), cx))
-        .await
-        .unwrap();
-    let project3_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a3.clone(), cx))
-        .await
-        .unwrap();
+    let active_call_b = cx_b.read(ActiveCall::global);
+    let active_call_c = cx_c.read(ActiveCall::global);
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
 
-    let project_b1 = client_b.build_remote_project(project1_id, cx_b).await;
-    let project_b2 = client_b.build_remote_project(project2_id, cx_b).await;
-    let project_b3 = client_b.build_remote_project(project3_id, cx_b).await;
+    // A new deployment starts alongside the old one, which keeps its connections.
+    // The new server doesn't clean up after the old one while it's still running.
+    let old_server_id = server.id();
+    let new_server = server.deploy(&executor).await;
+    executor.advance_clock(CLEANUP_TIMEOUT);
+
+    // Users B and C reconnect to the new server, while user A stays on the old one.
+    server.disconnect_client(client_b.peer_id().unwrap());
+    server.disconnect_client(client_c.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT);
     executor.run_until_parked();
+    assert_eq!(client_a.peer_id().unwrap().owner_id, old_server_id.0 as u32);
+    assert_eq!(
+        client_b.peer_id().unwrap().owner_id,
+        new_server.id().0 as u32
+    );
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string()],
+            pending: Default::default()
+        }
+    );
+    assert_eq!(
+        room_participants(&room_b, cx_b),
+        RoomParticipants {
+            remote: vec!["user_a".to_string()],
+            pending: Default::default()
+        }
+    );
 
-    let worktree1_id = worktree_a1.read_with(cx_a, |worktree, _| {
-        assert!(worktree.as_local().unwrap().is_shared());
-        worktree.id()
-    });
-    let (worktree_a2, _) = project_a1
-        .update(cx_a, |p, cx| {
-            p.find_or_create_local_worktree("/root-1/dir2", true, cx)
+    // Updates to the room made on the new server reach user A on the old one.
+    active_call_b
+        .update(cx_b, |call, cx| {
+            call.invite(client_c.user_id().unwrap(), None, cx)
         })
         .await
         .unwrap();
     executor.run_until_parked();
-
-    let worktree2_id = worktree_a2.read_with(cx_a, |tree, _| {
-        assert!(tree.as_local().unwrap().is_shared());
-        tree.id()
-    });
-    executor.run_until_parked();
-
-    project_b1.read_with(cx_b, |project, cx| {
-        assert!(project.worktree_for_id(worktree2_id, cx).is_some())
-    });
-
-    let buffer_a1 = project_a1
-        .update(cx_a, |p, cx| p.open_buffer((worktree1_id, "a.txt"), cx))
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string()],
+            pending: vec!["user_c".to_string()]
+        }
+    );
+    let mut incoming_call_c = active_call_c.read_with(cx_c, |call, _| call.incoming());
+    assert!(incoming_call_c.next().await.unwrap().is_some());
+    active_call_c
+        .update(cx_c, |call, cx| call.accept_incoming(cx))
         .await
         .unwrap();
-    let buffer_b1 = project_b1
-        .update(cx_b, |p, cx| p.open_buffer((worktree1_id, "a.txt"), cx))
+    let room_c = active_call_c.read_with(cx_c, |call, _| call.room().unwrap().clone());
+    executor.run_until_parked();
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string(), "user_c".to_string()],
+            pending: Default::default()
+        }
+    );
+
+    // And updates made on the old server reach users B and C on the new one.
+    active_call_a
+        .update(cx_a, |call, cx| call.hang_up(cx))
         .await
         .unwrap();
+    executor.run_until_parked();
+    assert_eq!(
+        room_participants(&room_b, cx_b),
+        RoomParticipants {
+            remote: vec!["user_c".to_string()],
+            pending: Default::default()
+        }
+    );
+    assert_eq!(
+        room_participants(&room_c, cx_c),
+        RoomParticipants {
+            remote: vec!["user_b".to_string()],
+            pending: Default::default()
+        }
+    );
+}
 
-    // Drop client A's connection.
-    server.forbid_connections();
-    server.disconnect_client(client_a.peer_id().unwrap());
-    executor.advance_clock(RECEIVE_TIMEOUT);
-
-    project_a1.read_with(cx_a, |project, _| {
-        assert!(project.is_shared());
-        assert_eq!(project.collaborators().len(), 1);
-    });
+#[gpui::test]
+async fn test_draining_server(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let old_server = server.deploy(&executor).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
 
-    project_b1.read_with(cx_b, |project, _| {
-        assert!(!project.is_disconnected());
-        assert_eq!(project.collaborators().len(), 1);
+    // Draining the old server sends its clients to the new one, taking their room
+    // with them.
+    let new_server = server.deploy(&executor).await;
+    let drain = executor.spawn({
+        let old_server = old_server.clone();
+        async move { old_server.drain(None).await }
     });
+    executor.run_until_parked();
+    assert!(old_server.is_draining());
+    executor.advance_clock(RESTART_RECONNECT_SPREAD);
+    for client in [&client_a, &client_b] {
+        assert_eq!(client.peer_id().unwrap().owner_id, new_server.id().0 as u32);
+    }
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string()],
+            pending: Default::default()
+        }
+    );
 
-    worktree_a1.read_with(cx_a, |tree, _| {
-        assert!(tree.as_local().unwrap().is_shared())
-    });
+    // The old server finishes draining once nothing refers to it anymore, and its
+    // departure doesn't disturb the room.
+    executor.advance_clock(DRAIN_POLL_INTERVAL);
+    drain.await;
+    executor.advance_clock(RECONNECT_TIMEOUT);
+    executor.run_until_parked();
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string()],
+            pending: Default::default()
+        }
+    );
+    assert_eq!(
+        room_participants(&room_b, cx_b),
+        RoomParticipants {
+            remote: vec!["user_a".to_string()],
+            pending: Default::default()
+        }
+    );
+}
 
-    // While client A is disconnected, add and remove files from client A's project.
-    client_a
-        .fs()
-        .insert_tree(
-            "/root-1/dir1/subdir2",
-            json!({
-                "f.txt": "f-contents",
-                "g.txt": "g-contents",
-                "h.txt": "h-contents",
-                "i.txt": "i-contents",
-            }),
-        )
+#[gpui::test]
+async fn test_server_restart_with_resume_tokens(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
     client_a
         .fs()
-        .remove_dir(
-            "/root-1/dir1/subdir1".as_ref(),
-            RemoveOptions {
-                recursive: true,
-                ..Default::default()
-            },
-        )
+        .insert_tree("/a", json!({ "a.txt": "a-contents" }))
+        .await;
+    let (project_a, _) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
         .await
         .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
 
-    // While client A is disconnected, add and remove worktrees from client A's project.
-    project_a1.update(cx_a, |project, cx| {
-        project.remove_worktree(worktree2_id, cx)
-    });
-    let (worktree_a3, _) = project_a1
-        .update(cx_a, |p, cx| {
-            p.find_or_create_local_worktree("/root-1/dir3", true, cx)
-        })
+    // The clients can't come back until well after the restarted server has cleaned
+    // up after its previous incarnation, but their resume tokens keep the room.
+    server.restart(&executor).await;
+    server.forbid_connections();
+    server.start().await.unwrap();
+    executor.advance_clock(REJOIN_WINDOW);
+    let environment = &server.app_state.config.zed_environment;
+    let (stale_room_ids, _) = server
+        .app_state
+        .db
+        .stale_server_resource_ids(environment, server.id())
         .await
         .unwrap();
-    worktree_a3
-        .read_with(cx_a, |tree, _| tree.as_local().unwrap().scan_complete())
-        .await;
+    assert_eq!(stale_room_ids.len(), 1);
 
-    let worktree3_id = worktree_a3.read_with(cx_a, |tree, _| {
-        assert!(!tree.as_local().unwrap().is_shared());
-        tree.id()
-    });
+    // Once they reconnect, they rejoin the room and project as if nothing happened.
+    server.allow_connections();
+    executor.advance_clock(RECEIVE_TIMEOUT);
     executor.run_until_parked();
+    for client in [&client_a, &client_b] {
+        assert_eq!(client.peer_id().unwrap().owner_id, server.id().0 as u32);
+    }
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string()],
+            pending: Default::default()
+        }
+    );
+    assert_eq!(
+        room_participants(&room_b, cx_b),
+        RoomParticipants {
+            remote: vec!["user_a".to_string()],
+            pending: Default::default()
+        }
+    );
+    assert!(!project_b.read_with(cx_b, |project, _| project.is_disconnected()));
 
-    // While client A is disconnected, close project 2
-    cx_a.update(|_| drop(project_a2));
-
-    // While client A is disconnected, mutate a buffer on both the host and the guest.
-    buffer_a1.update(cx_a, |buf, cx| buf.edit([(0..0, "W")], None, cx));
-    buffer_b1.update(cx_b, |buf, cx| buf.edit([(1..1, "Z")], None, cx));
+    // The previous incarnation is forgotten once no tokens are left to redeem.
+    executor.advance_clock(RESUME_TIMEOUT);
     executor.run_until_parked();
+    assert_eq!(
+        server
+            .app_state
+            .db
+            .stale_server_ids(environment, server.id())
+            .await
+            .unwrap(),
+        vec![]
+    );
+    assert!(!project_b.read_with(cx_b, |project, _| project.is_disconnected()));
+}
 
-    // Client A reconnects. Their project is re-shared, and client B re-joins it.
-    server.allow_connections();
+#[gpui::test]
+async fn test_overload_shedding(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    server
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let room_id = cx_a
+        .read(ActiveCall::global)
+        .read_with(cx_a, |call, cx| call.room().unwrap().read(cx).id());
+    let call_c = proto::Call {
+        room_id,
+        called_user_id: client_c.user_id().unwrap(),
+        initial_project_id: None,
+        context: None,
+    };
+    let search = proto::FuzzySearchUsers {
+        query: "user".into(),
+    };
+
+    // New rooms are the first thing to be turned away, with an error saying why.
+    server.simulate_overload(Some(OverloadLevel::RejectRoomCreation));
+    let error = client_c
+        .request(proto::CreateRoom::default())
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code(), ErrorCode::Overloaded);
+    assert_eq!(error.error_tag("level"), Some("RejectRoomCreation"));
+    client_a.request(search.clone()).await.unwrap();
+
+    // Then people joining rooms they aren't in.
+    server.simulate_overload(Some(OverloadLevel::RejectJoins));
+    let error = client_a.request(call_c.clone()).await.unwrap_err();
+    assert_eq!(error.error_code(), ErrorCode::Overloaded);
+    client_a.request(search.clone()).await.unwrap();
+
+    // Then anything collaboration can do without, while everything else still works.
+    server.simulate_overload(Some(OverloadLevel::ShedNonEssential));
+    let error = client_a.request(search.clone()).await.unwrap_err();
+    assert_eq!(error.error_code(), ErrorCode::Overloaded);
     client_a
-        .authenticate_and_connect(false, &cx_a.to_async())
+        .request(proto::GetUsers {
+            user_ids: vec![client_b.user_id().unwrap()],
+        })
         .await
         .unwrap();
-    executor.run_until_parked();
 
-    project_a1.read_with(cx_a, |project, cx| {
-        assert!(project.is_shared());
-        assert!(worktree_a1.read(cx).as_local().unwrap().is_shared());
-        assert_eq!(
-            worktree_a1
-                .read(cx)
-                .snapshot()
-                .paths()
-                .map(|p| p.to_str().unwrap())
-                .collect::<Vec<_>>(),
-            vec![
-                "a.txt",
-                "b.txt",
-                "subdir2",
-                "subdir2/f.txt",
-                "subdir2/g.txt",
-                "subdir2/h.txt",
-                "subdir2/i.txt"
-            ]
-        );
-        assert!(worktree_a3.read(cx).as_local().unwrap().is_shared());
-        assert_eq!(
-            worktree_a3
-                .read(cx)
-                .snapshot()
-                .paths()
-                .map(|p| p.to_str().unwrap())
-                .collect::<Vec<_>>(),
-            vec!["w.txt", "x.txt", "y.txt"]
-        );
-    });
-
-    project_b1.read_with(cx_b, |project, cx| {
-        assert!(!project.is_disconnected());
-        assert_eq!(
-            project
-                .worktree_for_id(worktree1_id, cx)
-                .unwrap()
-                .read(cx)
-                .snapshot()
-                .paths()
-                .map(|p| p.to_str().unwrap())
-                .collect::<Vec<_>>(),
-            vec![
-                "a.txt",
-                "b.txt",
-                "subdir2",
-                "subdir2/f.txt",
-                "subdir2/g.txt",
-                "subdir2/h.txt",
-                "subdir2/i.txt"
-            ]
-        );
-        assert!(project.worktree_for_id(worktree2_id, cx).is_none());
-        assert_eq!(
-            project
-                .worktree_for_id(worktree3_id, cx)
-                .unwrap()
-                .read(cx)
-                .snapshot()
-                .paths()
-                .map(|p| p.to_str().unwrap())
-                .collect::<Vec<_>>(),
-            vec!["w.txt", "x.txt", "y.txt"]
-        );
-    });
-
-    project_b2.read_with(cx_b, |project, _| assert!(project.is_disconnected()));
-
-    project_b3.read_with(cx_b, |project, _| assert!(!project.is_disconnected()));
-
-    buffer_a1.read_with(cx_a, |buffer, _| assert_eq!(buffer.text(), "WaZ"));
-
-    buffer_b1.read_with(cx_b, |buffer, _| assert_eq!(buffer.text(), "WaZ"));
-
-    // Drop client B's connection.
-    server.forbid_connections();
-    server.disconnect_client(client_b.peer_id().unwrap());
-    executor.advance_clock(RECEIVE_TIMEOUT);
+    // Once the server recovers, it accepts everything again.
+    server.simulate_overload(None);
+    client_a.request(search).await.unwrap();
+    client_a.request(call_c).await.unwrap();
+}
 
-    // While client B is disconnected, add and remove files from client A's project
-    client_a
-        .fs()
-        .insert_file("/root-1/dir1/subdir2/j.txt", "j-contents".into())
+#[gpui::test]
+async fn test_guests(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
     client_a
         .fs()
-        .remove_file("/root-1/dir1/subdir2/i.txt".as_ref(), Default::default())
+        .insert_tree("/a", json!({ "a.txt": "a-contents" }))
+        .await;
+    let (project_a, _) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
         .await
         .unwrap();
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+    let room_id = room_a.read_with(cx_a, |room, _| room.id());
 
-    // While client B is disconnected, add and remove worktrees from client A's project.
-    let (worktree_a4, _) = project_a1
-        .update(cx_a, |p, cx| {
-            p.find_or_create_local_worktree("/root-1/dir4", true, cx)
+    // Guests can't be invited for more than a day.
+    client_a
+        .request(proto::CreateGuest {
+            room_id,
+            role: ChannelRole::Guest.into(),
+            duration_secs: 2 * 24 * 60 * 60,
+        })
+        .await
+        .unwrap_err();
+    let invitation = room_a
+        .update(cx_a, |room, cx| {
+            room.invite_guest(ChannelRole::Guest, Duration::from_secs(60 * 60), cx)
         })
         .await
         .unwrap();
-    executor.run_until_parked();
-
-    let worktree4_id = worktree_a4.read_with(cx_a, |tree, _| {
-        assert!(tree.as_local().unwrap().is_shared());
-        tree.id()
-    });
-    project_a1.update(cx_a, |project, cx| {
-        project.remove_worktree(worktree3_id, cx)
-    });
-    executor.run_until_parked();
+    assert!(!invitation.credentials.access_token.is_empty());
+    assert!(invitation
+        .credentials
+        .guest_link()
+        .contains(&format!("guest/{}/", invitation.credentials.user_id)));
+    let guest = server
+        .app_state
+        .db
+        .get_user_by_id(UserId::from_proto(invitation.credentials.user_id))
+        .await
+        .unwrap()
+        .unwrap();
 
-    // While client B is disconnected, mutate a buffer on both the host and the guest.
-    buffer_a1.update(cx_a, |buf, cx| buf.edit([(1..1, "X")], None, cx));
-    buffer_b1.update(cx_b, |buf, cx| buf.edit([(2..2, "Y")], None, cx));
+    // The guest is called into the room when they connect, and joins it as a viewer.
+    let client_c = server.create_client(cx_c, &guest.github_login).await;
     executor.run_until_parked();
-
-    // While disconnected, close project 3
-    cx_a.update(|_| drop(project_a3));
-
-    // Client B reconnects. They re-join the room and the remaining shared project.
-    server.allow_connections();
-    client_b
-        .authenticate_and_connect(false, &cx_b.to_async())
+    let active_call_c = cx_c.read(ActiveCall::global);
+    active_call_c
+        .update(cx_c, |call, cx| call.accept_incoming(cx))
         .await
         .unwrap();
+    let project_c = client_c.build_remote_project(project_id, cx_c).await;
+    assert!(project_c.read_with(cx_c, |project, _| project.is_read_only()));
+
+    // They can't do anything outside of that room.
+    let error = client_c
+        .request(proto::CreateRoom::default())
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code(), ErrorCode::Forbidden);
+    let error = client_c
+        .request(proto::RequestContact {
+            responder_id: client_a.user_id().unwrap(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code(), ErrorCode::Forbidden);
+    let error = client_c
+        .request(proto::ShareProject {
+            room_id,
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code(), ErrorCode::Forbidden);
+
+    // Once the invitation expires, the guest is signed out and leaves the room. The
+    // test server doesn't check access tokens, which stop working at the same time, so
+    // connections are forbidden instead.
+    server.forbid_connections();
+    executor.advance_clock(Duration::from_secs(60 * 60));
+    executor.advance_clock(RECONNECT_TIMEOUT);
     executor.run_until_parked();
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string()],
+            pending: Default::default()
+        }
+    );
+}
 
-    project_b1.read_with(cx_b, |project, cx| {
-        assert!(!project.is_disconnected());
-        assert_eq!(
-            project
-                .worktree_for_id(worktree1_id, cx)
-                .unwrap()
-                .read(cx)
-                .snapshot()
-                .paths()
-                .map(|p| p.to_str().unwrap())
-                .collect::<Vec<_>>(),
-            vec![
-                "a.txt",
-                "b.txt",
-                "subdir2",
-                "subdir2/f.txt",
-                "subdir2/g.txt",
-                "subdir2/h.txt",
-                "subdir2/j.txt"
-            ]
-        );
-        assert!(project.worktree_for_id(worktree2_id, cx).is_none());
-        assert_eq!(
-            project
-                .worktree_for_id(worktree4_id, cx)
-                .unwrap()
-                .read(cx)
-                .snapshot()
-                .paths()
-                .map(|p| p.to_str().unwrap())
-                .collect::<Vec<_>>(),
-            vec!["z.txt"]
+#[gpui::test]
+async fn test_user_reports(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+    cx_d: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    let client_d = server.create_client(cx_d, "user_d").await;
+    let public_channel_id = server.make_public_channel("public", &client_d, cx_d).await;
+    let mut channel_ids = Vec::new();
+    for i in 0..=THROTTLED_INVITE_LIMIT {
+        channel_ids.push(
+            server
+                .make_channel(&format!("channel-{i}"), None, (&client_a, cx_a), &mut [])
+                .await,
         );
-    });
-
-    project_b3.read_with(cx_b, |project, _| assert!(project.is_disconnected()));
+    }
+    for (name, value) in [
+        ("REPORTS_TO_THROTTLE_INVITES", "1"),
+        ("REPORTS_TO_BLOCK_PUBLIC_JOINS", "2"),
+    ] {
+        server
+            .app_state
+            .db
+            .set_runtime_config_override(
+                &server.app_state.config.zed_environment,
+                name,
+                Some(value),
+            )
+            .await
+            .unwrap();
+    }
+    server.reload_runtime_config().await.unwrap();
 
-    buffer_a1.read_with(cx_a, |buffer, _| assert_eq!(buffer.text(), "WXaYZ"));
+    // Reports only count when they're made from a room with the reported user.
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+    let room_id = cx_a
+        .read(ActiveCall::global)
+        .read_with(cx_a, |call, cx| call.room().unwrap().read(cx).id());
+    let report = |reason: &str| proto::ReportUser {
+        user_id: client_a.user_id().unwrap(),
+        room_id: Some(room_id),
+        reason: reason.into(),
+        ..Default::default()
+    };
+
+    // Reports need a reason, and can't be made from rooms the reporter isn't in.
+    client_b.request(report(" ")).await.unwrap_err();
+    client_d.request(report("spam invites")).await.unwrap_err();
+    client_b.request(report("spam invites")).await.unwrap();
+
+    // Once reported, a user can only send a few invites at a time.
+    let invite_c = |channel_id| proto::InviteChannelMember {
+        channel_id,
+        user_id: client_c.user_id().unwrap(),
+        role: ChannelRole::Member.into(),
+    };
+    for channel_id in &channel_ids[..THROTTLED_INVITE_LIMIT] {
+        client_a.request(invite_c(*channel_id)).await.unwrap();
+    }
+    let error = client_a
+        .request(invite_c(channel_ids[THROTTLED_INVITE_LIMIT]))
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code(), ErrorCode::Forbidden);
+    assert_eq!(error.error_tag("throttle"), Some("ThrottleInvites"));
+
+    // When more users report them, they can't join public channels either.
+    client_c.request(report("rude in chat")).await.unwrap();
+    let join_public_channel = proto::JoinChannel {
+        channel_id: public_channel_id,
+        capabilities: None,
+    };
+    let error = client_a
+        .request(join_public_channel.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code(), ErrorCode::Forbidden);
+    assert_eq!(error.error_tag("throttle"), Some("BlockPublicJoins"));
 
-    buffer_b1.read_with(cx_b, |buffer, _| assert_eq!(buffer.text(), "WXaYZ"));
+    // Until an admin dismisses one of the reports.
+    let reports = server
+        .app_state
+        .db
+        .get_user_reports(ReportStatus::Open)
+        .await
+        .unwrap();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[1].reason, "rude in chat");
+    server
+        .app_state
+        .db
+        .review_user_report(reports[1].id, ReportStatus::Dismissed)
+        .await
+        .unwrap();
+    client_a.request(join_public_channel).await.unwrap();
 }
 
-#[gpui::test(iterations = 10)]
-async fn test_active_call_events(
+#[gpui::test]
+async fn test_quotas(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
+    cx_a2: &mut TestAppContext,
     cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
+    let client_a2 = server.create_client(cx_a2, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
-    client_a.fs().insert_tree("/a", json!({})).await;
-    client_b.fs().insert_tree("/b", json!({})).await;
-
-    let (project_a, _) = client_a.build_local_project("/a", cx_a).await;
-    let (project_b, _) = client_b.build_local_project("/b", cx_b).await;
-
+    let client_c = server.create_client(cx_c, "user_c").await;
+    server
+        .make_contacts(&mut [(&client_a, cx_a), (&client_c, cx_c)])
+        .await;
     server
         .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
+    for (name, value) in [
+        ("MAX_CONNECTIONS_PER_USER", "1"),
+        ("MAX_SHARED_PROJECTS_PER_ROOM", "1"),
+        ("MAX_ROOM_PARTICIPANTS", "2"),
+    ] {
+        server
+            .app_state
+            .db
+            .set_runtime_config_override(
+                &server.app_state.config.zed_environment,
+                name,
+                Some(value),
+            )
+            .await
+            .unwrap();
+    }
+    server.reload_runtime_config().await.unwrap();
     let active_call_a = cx_a.read(ActiveCall::global);
-    let active_call_b = cx_b.read(ActiveCall::global);
-
-    let events_a = active_call_events(cx_a);
-    let events_b = active_call_events(cx_b);
 
-    let project_a_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+    // Rooms can only have so many participants.
+    let error = active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_c.user_id().unwrap(), None, cx)
+        })
         .await
-        .unwrap();
-    executor.run_until_parked();
-    assert_eq!(mem::take(&mut *events_a.borrow_mut()), vec![]);
-    assert_eq!(
-        mem::take(&mut *events_b.borrow_mut()),
-        vec![room::Event::RemoteProjectShared {
-            owner: Arc::new(User {
-                id: client_a.user_id().unwrap(),
-                github_login: "user_a".to_string(),
-                avatar_uri: "avatar_a".into(),
-            }),
-            project_id: project_a_id,
-            worktree_root_names: vec!["a".to_string()],
-        }]
-    );
+        .unwrap_err();
+    assert_eq!(error.error_code(), ErrorCode::RoomFull);
+    assert_eq!(rpc::exceeded_quota(&error), Some(2));
 
-    let project_b_id = active_call_b
-        .update(cx_b, |call, cx| call.share_project(project_b.clone(), cx))
+    // And so many shared projects.
+    client_a
+        .fs()
+        .insert_tree("/a", json!({ "a.txt": "a" }))
+        .await;
+    client_a
+        .fs()
+        .insert_tree("/b", json!({ "b.txt": "b" }))
+        .await;
+    let (project_a, _) = client_a.build_local_project("/a", cx_a).await;
+    let (project_b, _) = client_a.build_local_project("/b", cx_a).await;
+    active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
         .await
         .unwrap();
+    let error = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_b.clone(), cx))
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code(), ErrorCode::TooManySharedProjects);
+    assert_eq!(rpc::exceeded_quota(&error), Some(1));
+
+    // Users can only connect from so many places. Connections made before the quota
+    // was lowered stay open.
+    client_a2.disconnect(&cx_a2.to_async());
     executor.run_until_parked();
+    client_a2
+        .authenticate_and_connect(false, &cx_a2.to_async())
+        .await
+        .unwrap_err();
     assert_eq!(
-        mem::take(&mut *events_a.borrow_mut()),
-        vec![room::Event::RemoteProjectShared {
-            owner: Arc::new(User {
-                id: client_b.user_id().unwrap(),
-                github_login: "user_b".to_string(),
-                avatar_uri: "avatar_b".into(),
-            }),
-            project_id: project_b_id,
-            worktree_root_names: vec!["b".to_string()]
-        }]
+        client_a2.connection_diagnosis(),
+        Some(ConnectionDiagnosis::TooManyConnections { limit: 1 })
     );
-    assert_eq!(mem::take(&mut *events_b.borrow_mut()), vec![]);
-
-    // Sharing a project twice is idempotent.
-    let project_b_id_2 = active_call_b
-        .update(cx_b, |call, cx| call.share_project(project_b.clone(), cx))
-        .await
-        .unwrap();
-    assert_eq!(project_b_id_2, project_b_id);
-    executor.run_until_parked();
-    assert_eq!(mem::take(&mut *events_a.borrow_mut()), vec![]);
-    assert_eq!(mem::take(&mut *events_b.borrow_mut()), vec![]);
-}
-
-fn active_call_events(cx: &mut TestAppContext) -> Rc<RefCell<Vec<room::Event>>> {
-    let events = Rc::new(RefCell::new(Vec::new()));
-    let active_call = cx.read(ActiveCall::global);
-    cx.update({
-        let events = events.clone();
-        |cx| {
-            cx.subscribe(&active_call, move |_, event, _| {
-                events.borrow_mut().push(event.clone())
-            })
-            .detach()
-        }
-    });
-    events
+    assert!(client_a.status().borrow().is_connected());
 }
 
 #[gpui::test]
-async fn test_mute_deafen(
+async fn test_webhooks(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
-    cx_c: &mut TestAppContext,
 ) {
-    let mut server = TestServer::start(executor.clone()).await;
+    let mut server = TestServer::start_with_config(executor.clone(), |config| {
+        config.webhooks = Some(
+            r#"[
+                {"url": "https://hooks.example.com/a", "secret": "a"},
+                {"url": "https://hooks.example.com/b", "secret": "b"}
+            ]"#
+            .into(),
+        );
+    })
+    .await;
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
-    let client_c = server.create_client(cx_c, "user_c").await;
-
     server
-        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
-
+    let user_a = client_a.user_id().unwrap().to_proto();
+    let user_b = client_b.user_id().unwrap().to_proto();
     let active_call_a = cx_a.read(ActiveCall::global);
     let active_call_b = cx_b.read(ActiveCall::global);
-    let active_call_c = cx_c.read(ActiveCall::global);
-
-    // User A calls user B, B answers.
+    let webhook_client = server.test_webhook_client.clone();
+    let take_events = || {
+        // Each event is delivered to every endpoint, in no particular order.
+        let (bodies_a, bodies_b): (Vec<_>, Vec<_>) = webhook_client
+            .take_deliveries()
+            .into_iter()
+            .partition(|(url, _)| url == "https://hooks.example.com/a");
+        let events = |bodies: Vec<(String, serde_json::Value)>| {
+            let mut events = bodies
+                .into_iter()
+                .map(|(_, body)| {
+                    (
+                        body["event"].as_str().unwrap().to_string(),
+                        body["user_id"].as_u64(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            events.sort();
+            events
+        };
+        let events_a = events(bodies_a);
+        assert_eq!(events_a, events(bodies_b));
+        events_a
+    };
+
+    // Creating a room by calling someone reports the room and its creator.
     active_call_a
         .update(cx_a, |call, cx| {
             call.invite(client_b.user_id().unwrap(), None, cx)
@@ -1908,1035 +3258,733 @@ async fn test_mute_deafen(
         .await
         .unwrap();
     executor.run_until_parked();
+    assert_eq!(
+        take_events(),
+        [
+            ("room_created".to_string(), None),
+            ("user_joined".to_string(), Some(user_a)),
+        ]
+    );
+
     active_call_b
         .update(cx_b, |call, cx| call.accept_incoming(cx))
         .await
         .unwrap();
     executor.run_until_parked();
+    assert_eq!(take_events(), [("user_joined".to_string(), Some(user_b))]);
 
-    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
-    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
-
-    room_a.read_with(cx_a, |room, _| assert!(!room.is_muted()));
-    room_b.read_with(cx_b, |room, _| assert!(!room.is_muted()));
-
-    // Users A and B are both muted.
-    assert_eq!(
-        participant_audio_state(&room_a, cx_a),
-        &[ParticipantAudioState {
-            user_id: client_b.user_id().unwrap(),
-            is_muted: false,
-            audio_tracks_playing: vec![true],
-        }]
-    );
-    assert_eq!(
-        participant_audio_state(&room_b, cx_b),
-        &[ParticipantAudioState {
-            user_id: client_a.user_id().unwrap(),
-            is_muted: false,
-            audio_tracks_playing: vec![true],
-        }]
-    );
-
-    // User A mutes
-    room_a.update(cx_a, |room, cx| room.toggle_mute(cx));
-    executor.run_until_parked();
-
-    // User A hears user B, but B doesn't hear A.
-    room_a.read_with(cx_a, |room, _| assert!(room.is_muted()));
-    room_b.read_with(cx_b, |room, _| assert!(!room.is_muted()));
-    assert_eq!(
-        participant_audio_state(&room_a, cx_a),
-        &[ParticipantAudioState {
-            user_id: client_b.user_id().unwrap(),
-            is_muted: false,
-            audio_tracks_playing: vec![true],
-        }]
-    );
-    assert_eq!(
-        participant_audio_state(&room_b, cx_b),
-        &[ParticipantAudioState {
-            user_id: client_a.user_id().unwrap(),
-            is_muted: true,
-            audio_tracks_playing: vec![true],
-        }]
-    );
-
-    // User A deafens
-    room_a.update(cx_a, |room, cx| room.toggle_deafen(cx));
-    executor.run_until_parked();
-
-    // User A does not hear user B.
-    room_a.read_with(cx_a, |room, _| assert!(room.is_muted()));
-    room_b.read_with(cx_b, |room, _| assert!(!room.is_muted()));
-    assert_eq!(
-        participant_audio_state(&room_a, cx_a),
-        &[ParticipantAudioState {
-            user_id: client_b.user_id().unwrap(),
-            is_muted: false,
-            audio_tracks_playing: vec![false],
-        }]
-    );
-    assert_eq!(
-        participant_audio_state(&room_b, cx_b),
-        &[ParticipantAudioState {
-            user_id: client_a.user_id().unwrap(),
-            is_muted: true,
-            audio_tracks_playing: vec![true],
-        }]
-    );
-
-    // User B calls user C, C joins.
+    // Endpoints that fail are retried with backoff.
+    webhook_client.fail_next_attempts(2);
     active_call_b
-        .update(cx_b, |call, cx| {
-            call.invite(client_c.user_id().unwrap(), None, cx)
-        })
+        .update(cx_b, |call, cx| call.hang_up(cx))
         .await
         .unwrap();
     executor.run_until_parked();
-    active_call_c
-        .update(cx_c, |call, cx| call.accept_incoming(cx))
+    assert!(take_events().is_empty());
+    executor.advance_clock(webhooks::INITIAL_RETRY_DELAY);
+    assert_eq!(take_events(), [("user_left".to_string(), Some(user_b))]);
+
+    // The room ends once its last participant leaves.
+    active_call_a
+        .update(cx_a, |call, cx| call.hang_up(cx))
         .await
         .unwrap();
     executor.run_until_parked();
-
-    // User A does not hear users B or C.
-    assert_eq!(
-        participant_audio_state(&room_a, cx_a),
-        &[
-            ParticipantAudioState {
-                user_id: client_b.user_id().unwrap(),
-                is_muted: false,
-                audio_tracks_playing: vec![false],
-            },
-            ParticipantAudioState {
-                user_id: client_c.user_id().unwrap(),
-                is_muted: false,
-                audio_tracks_playing: vec![false],
-            }
-        ]
-    );
     assert_eq!(
-        participant_audio_state(&room_b, cx_b),
-        &[
-            ParticipantAudioState {
-                user_id: client_a.user_id().unwrap(),
-                is_muted: true,
-                audio_tracks_playing: vec![true],
-            },
-            ParticipantAudioState {
-                user_id: client_c.user_id().unwrap(),
-                is_muted: false,
-                audio_tracks_playing: vec![true],
-            }
+        take_events(),
+        [
+            ("room_ended".to_string(), None),
+            ("user_left".to_string(), Some(user_a)),
         ]
     );
-
-    #[derive(PartialEq, Eq, Debug)]
-    struct ParticipantAudioState {
-        user_id: u64,
-        is_muted: bool,
-        audio_tracks_playing: Vec<bool>,
-    }
-
-    fn participant_audio_state(
-        room: &Model<Room>,
-        cx: &TestAppContext,
-    ) -> Vec<ParticipantAudioState> {
-        room.read_with(cx, |room, _| {
-            room.remote_participants()
-                .iter()
-                .map(|(user_id, participant)| ParticipantAudioState {
-                    user_id: *user_id,
-                    is_muted: participant.muted,
-                    audio_tracks_playing: participant
-                        .audio_tracks
-                        .values()
-                        .map(|track| track.is_playing())
-                        .collect(),
-                })
-                .collect::<Vec<_>>()
-        })
-    }
 }
 
-#[gpui::test(iterations = 10)]
-async fn test_room_location(
+#[gpui::test]
+async fn test_contact_request_limits(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+    cx_d: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
-    client_a.fs().insert_tree("/a", json!({})).await;
-    client_b.fs().insert_tree("/b", json!({})).await;
-
-    let active_call_a = cx_a.read(ActiveCall::global);
-    let active_call_b = cx_b.read(ActiveCall::global);
+    let client_c = server.create_client(cx_c, "user_c").await;
+    let client_d = server.create_client(cx_d, "user_d").await;
+    server
+        .make_channel("zed", None, (&client_a, cx_a), &mut [(&client_d, cx_d)])
+        .await;
+    for (name, value) in [
+        ("CONTACT_REQUESTS_PER_HOUR", "2"),
+        ("CONTACT_REQUESTS_TO_STRANGERS_PER_HOUR", "1"),
+    ] {
+        server
+            .app_state
+            .db
+            .set_runtime_config_override(
+                &server.app_state.config.zed_environment,
+                name,
+                Some(value),
+            )
+            .await
+            .unwrap();
+    }
+    server.reload_runtime_config().await.unwrap();
+    let request_contact = |client: &TestClient| proto::RequestContact {
+        responder_id: client.user_id().unwrap(),
+    };
 
-    let a_notified = Rc::new(Cell::new(false));
-    cx_a.update({
-        let notified = a_notified.clone();
-        |cx| {
-            cx.observe(&active_call_a, move |_, _| notified.set(true))
-                .detach()
-        }
-    });
+    // After the first few requests, users can only ask people they've met to be
+    // contacts.
+    client_a.request(request_contact(&client_b)).await.unwrap();
+    let error = client_a
+        .request(request_contact(&client_c))
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code(), ErrorCode::Forbidden);
+    client_a.request(request_contact(&client_d)).await.unwrap();
 
-    let b_notified = Rc::new(Cell::new(false));
-    cx_b.update({
-        let b_notified = b_notified.clone();
-        |cx| {
-            cx.observe(&active_call_b, move |_, _| b_notified.set(true))
-                .detach()
-        }
-    });
-
-    let (project_a, _) = client_a.build_local_project("/a", cx_a).await;
-    active_call_a
-        .update(cx_a, |call, cx| call.set_location(Some(&project_a), cx))
+    // Past the hourly limit, they can't send any, and admins are told about them.
+    let error = client_a
+        .request(request_contact(&client_c))
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code(), ErrorCode::RateLimited);
+    assert!(rpc::retry_after(&error).unwrap() <= Duration::from_secs(60 * 60));
+    client_a
+        .request(request_contact(&client_c))
+        .await
+        .unwrap_err();
+    let events = server
+        .app_state
+        .db
+        .get_audit_events(
+            &AuditEventFilter {
+                kind: Some(AuditEventKind::ContactRequestsLimited),
+                ..Default::default()
+            },
+            10,
+            None,
+        )
         .await
         .unwrap();
-    let (project_b, _) = client_b.build_local_project("/b", cx_b).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        events[0].user_id,
+        UserId::from_proto(client_a.user_id().unwrap())
+    );
+}
 
+#[gpui::test]
+async fn test_blocking_users(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
     server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .make_contacts(&mut [(&client_a, cx_a), (&client_c, cx_c)])
         .await;
+    let user_a = client_a.user_id().unwrap();
+    let user_b = client_b.user_id().unwrap();
+    let user_c = client_c.user_id().unwrap();
+    let active_call_c = cx_c.read(ActiveCall::global);
 
-    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
-
-    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
+    // Users who are blocked while their contact request is declined can't send another.
+    client_b
+        .user_store()
+        .update(cx_b, |store, cx| store.request_contact(user_a, cx))
+        .await
+        .unwrap();
+    client_a
+        .request(proto::RespondToContactRequest {
+            requester_id: user_b,
+            response: proto::ContactRequestResponse::Block as i32,
+        })
+        .await
+        .unwrap();
     executor.run_until_parked();
-    assert!(a_notified.take());
-    assert_eq!(
-        participant_locations(&room_a, cx_a),
-        vec![("user_b".to_string(), ParticipantLocation::External)]
-    );
-    assert!(b_notified.take());
-    assert_eq!(
-        participant_locations(&room_b, cx_b),
-        vec![("user_a".to_string(), ParticipantLocation::UnsharedProject)]
-    );
+    assert!(client_a
+        .user_store()
+        .read_with(cx_a, |store, _| store.is_user_blocked(user_b)));
+    assert!(client_b
+        .summarize_contacts(cx_b)
+        .outgoing_requests
+        .is_empty());
+    client_b
+        .user_store()
+        .update(cx_b, |store, cx| store.request_contact(user_a, cx))
+        .await
+        .unwrap_err();
 
-    let project_a_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+    // Once they're unblocked they can, but only a couple of times an hour.
+    client_a
+        .user_store()
+        .update(cx_a, |store, cx| store.set_user_blocked(user_b, false, cx))
         .await
         .unwrap();
     executor.run_until_parked();
-    assert!(a_notified.take());
-    assert_eq!(
-        participant_locations(&room_a, cx_a),
-        vec![("user_b".to_string(), ParticipantLocation::External)]
-    );
-    assert!(b_notified.take());
-    assert_eq!(
-        participant_locations(&room_b, cx_b),
-        vec![(
-            "user_a".to_string(),
-            ParticipantLocation::SharedProject {
-                project_id: project_a_id
-            }
-        )]
-    );
+    assert!(!client_a
+        .user_store()
+        .read_with(cx_a, |store, _| store.is_user_blocked(user_b)));
+    client_b
+        .user_store()
+        .update(cx_b, |store, cx| store.request_contact(user_a, cx))
+        .await
+        .unwrap();
+    client_a
+        .user_store()
+        .update(cx_a, |store, cx| {
+            store.respond_to_contact_request(user_b, false, cx)
+        })
+        .await
+        .unwrap();
+    let error = client_b
+        .user_store()
+        .update(cx_b, |store, cx| store.request_contact(user_a, cx))
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code(), ErrorCode::RateLimited);
 
-    let project_b_id = active_call_b
-        .update(cx_b, |call, cx| call.share_project(project_b.clone(), cx))
+    // Blocked contacts can't call, and stay blocked after reconnecting.
+    client_a
+        .user_store()
+        .update(cx_a, |store, cx| store.set_user_blocked(user_c, true, cx))
+        .await
+        .unwrap();
+    active_call_c
+        .update(cx_c, |call, cx| call.invite(user_a, None, cx))
+        .await
+        .unwrap_err();
+    client_a.disconnect(&cx_a.to_async());
+    client_a.clear_contacts(cx_a).await;
+    assert!(!client_a
+        .user_store()
+        .read_with(cx_a, |store, _| store.is_user_blocked(user_c)));
+    client_a
+        .authenticate_and_connect(false, &cx_a.to_async())
         .await
         .unwrap();
     executor.run_until_parked();
-    assert!(a_notified.take());
-    assert_eq!(
-        participant_locations(&room_a, cx_a),
-        vec![("user_b".to_string(), ParticipantLocation::External)]
-    );
-    assert!(b_notified.take());
-    assert_eq!(
-        participant_locations(&room_b, cx_b),
-        vec![(
-            "user_a".to_string(),
-            ParticipantLocation::SharedProject {
-                project_id: project_a_id
-            }
-        )]
-    );
+    assert!(client_a
+        .user_store()
+        .read_with(cx_a, |store, _| store.is_user_blocked(user_c)));
+}
 
-    active_call_b
-        .update(cx_b, |call, cx| call.set_location(Some(&project_b), cx))
+#[gpui::test]
+async fn test_runtime_config_reload(executor: BackgroundExecutor, cx_a: &mut TestAppContext) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let user_ids = vec![client_a.user_id().unwrap()];
+    for _ in 0..3 {
+        client_a
+            .request(proto::GetUsers {
+                user_ids: user_ids.clone(),
+            })
+            .await
+            .unwrap();
+    }
+
+    // Overrides stored in the database are picked up without restarting the server,
+    // and apply to existing connections.
+    server
+        .app_state
+        .db
+        .set_runtime_config_override(
+            &server.app_state.config.zed_environment,
+            "RATE_LIMITS",
+            Some(r#"{"messages": {"GetUsers": {"per_second": 1, "burst": 1}}}"#),
+        )
         .await
         .unwrap();
+    executor.advance_clock(RUNTIME_CONFIG_RELOAD_INTERVAL);
     executor.run_until_parked();
-    assert!(a_notified.take());
-    assert_eq!(
-        participant_locations(&room_a, cx_a),
-        vec![(
-            "user_b".to_string(),
-            ParticipantLocation::SharedProject {
-                project_id: project_b_id
-            }
-        )]
-    );
-    assert!(b_notified.take());
-    assert_eq!(
-        participant_locations(&room_b, cx_b),
-        vec![(
-            "user_a".to_string(),
-            ParticipantLocation::SharedProject {
-                project_id: project_a_id
-            }
-        )]
-    );
+    client_a
+        .request(proto::GetUsers {
+            user_ids: user_ids.clone(),
+        })
+        .await
+        .unwrap();
+    let error = client_a
+        .request(proto::GetUsers {
+            user_ids: user_ids.clone(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code(), ErrorCode::RateLimited);
 
-    active_call_b
-        .update(cx_b, |call, cx| call.set_location(None, cx))
+    // Removing the override goes back to the value the server was started with.
+    server
+        .app_state
+        .db
+        .set_runtime_config_override(
+            &server.app_state.config.zed_environment,
+            "RATE_LIMITS",
+            None,
+        )
         .await
         .unwrap();
-    executor.run_until_parked();
-    assert!(a_notified.take());
-    assert_eq!(
-        participant_locations(&room_a, cx_a),
-        vec![("user_b".to_string(), ParticipantLocation::External)]
-    );
-    assert!(b_notified.take());
+    server.reload_runtime_config().await.unwrap();
     assert_eq!(
-        participant_locations(&room_b, cx_b),
-        vec![(
-            "user_a".to_string(),
-            ParticipantLocation::SharedProject {
-                project_id: project_a_id
-            }
-        )]
+        server.app_state.runtime_config.get().rate_limit_policy,
+        Default::default()
     );
+    client_a
+        .request(proto::GetUsers { user_ids })
+        .await
+        .unwrap();
 
-    fn participant_locations(
-        room: &Model<Room>,
-        cx: &TestAppContext,
-    ) -> Vec<(String, ParticipantLocation)> {
-        room.read_with(cx, |room, _| {
-            room.remote_participants()
-                .values()
-                .map(|participant| {
-                    (
-                        participant.user.github_login.to_string(),
-                        participant.location,
-                    )
-                })
-                .collect()
-        })
-    }
+    // Feature flags that are turned on for everyone are added to each user's own.
+    server
+        .app_state
+        .db
+        .set_runtime_config_override(
+            &server.app_state.config.zed_environment,
+            "FEATURE_FLAGS",
+            Some("notes"),
+        )
+        .await
+        .unwrap();
+    server.reload_runtime_config().await.unwrap();
+    let info = client_a
+        .request(proto::GetPrivateUserInfo {})
+        .await
+        .unwrap();
+    assert_eq!(info.flags, ["notes"]);
 }
 
 #[gpui::test(iterations = 10)]
-async fn test_propagate_saves_and_fs_changes(
+async fn test_calls_on_multiple_connections(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
-    cx_b: &mut TestAppContext,
-    cx_c: &mut TestAppContext,
+    cx_b1: &mut TestAppContext,
+    cx_b2: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
-    let client_b = server.create_client(cx_b, "user_b").await;
-    let client_c = server.create_client(cx_c, "user_c").await;
-
+    let client_b1 = server.create_client(cx_b1, "user_b").await;
+    let client_b2 = server.create_client(cx_b2, "user_b").await;
     server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b1, cx_b1)])
         .await;
+
     let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b1 = cx_b1.read(ActiveCall::global);
+    let active_call_b2 = cx_b2.read(ActiveCall::global);
 
-    let rust = Arc::new(Language::new(
-        LanguageConfig {
-            name: "Rust".into(),
-            matcher: LanguageMatcher {
-                path_suffixes: vec!["rs".to_string()],
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        Some(tree_sitter_rust::language()),
-    ));
-    let javascript = Arc::new(Language::new(
-        LanguageConfig {
-            name: "JavaScript".into(),
-            matcher: LanguageMatcher {
-                path_suffixes: vec!["js".to_string()],
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        Some(tree_sitter_rust::language()),
-    ));
-    for client in [&client_a, &client_b, &client_c] {
-        client.language_registry().add(rust.clone());
-        client.language_registry().add(javascript.clone());
-    }
+    let mut incoming_call_b1 = active_call_b1.read_with(cx_b1, |call, _| call.incoming());
 
-    client_a
-        .fs()
-        .insert_tree(
-            "/a",
-            json!({
-                "file1.rs": "",
-                "file2": ""
-            }),
-        )
-        .await;
-    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+    let mut incoming_call_b2 = active_call_b2.read_with(cx_b2, |call, _| call.incoming());
+    assert!(incoming_call_b1.next().await.unwrap().is_none());
+    assert!(incoming_call_b2.next().await.unwrap().is_none());
 
-    let worktree_a = project_a.read_with(cx_a, |p, _| p.worktrees().next().unwrap());
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+    // Call user B from client A, ensuring both clients for user B ring.
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b1.user_id().unwrap(), None, cx)
+        })
         .await
         .unwrap();
+    executor.run_until_parked();
+    assert!(incoming_call_b1.next().await.unwrap().is_some());
+    assert!(incoming_call_b2.next().await.unwrap().is_some());
 
-    // Join that worktree as clients B and C.
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
-    let project_c = client_c.build_remote_project(project_id, cx_c).await;
-
-    let worktree_b = project_b.read_with(cx_b, |p, _| p.worktrees().next().unwrap());
-
-    let worktree_c = project_c.read_with(cx_c, |p, _| p.worktrees().next().unwrap());
+    // User B declines the call on one of the two connections, causing both connections
+    // to stop ringing.
+    active_call_b2.update(cx_b2, |call, cx| call.decline_incoming(cx).unwrap());
+    executor.run_until_parked();
+    assert!(incoming_call_b1.next().await.unwrap().is_none());
+    assert!(incoming_call_b2.next().await.unwrap().is_none());
 
-    // Open and edit a buffer as both guests B and C.
-    let buffer_b = project_b
-        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "file1.rs"), cx))
-        .await
-        .unwrap();
-    let buffer_c = project_c
-        .update(cx_c, |p, cx| p.open_buffer((worktree_id, "file1.rs"), cx))
+    // Call user B again from client A.
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b1.user_id().unwrap(), None, cx)
+        })
         .await
         .unwrap();
+    executor.run_until_parked();
+    assert!(incoming_call_b1.next().await.unwrap().is_some());
+    assert!(incoming_call_b2.next().await.unwrap().is_some());
 
-    buffer_b.read_with(cx_b, |buffer, _| {
-        assert_eq!(&*buffer.language().unwrap().name(), "Rust");
-    });
-
-    buffer_c.read_with(cx_c, |buffer, _| {
-        assert_eq!(&*buffer.language().unwrap().name(), "Rust");
-    });
-    buffer_b.update(cx_b, |buf, cx| buf.edit([(0..0, "i-am-b, ")], None, cx));
-    buffer_c.update(cx_c, |buf, cx| buf.edit([(0..0, "i-am-c, ")], None, cx));
-
-    // Open and edit that buffer as the host.
-    let buffer_a = project_a
-        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "file1.rs"), cx))
+    // User B accepts the call on one of the two connections, causing both connections
+    // to stop ringing.
+    active_call_b2
+        .update(cx_b2, |call, cx| call.accept_incoming(cx))
         .await
         .unwrap();
-
     executor.run_until_parked();
+    assert!(incoming_call_b1.next().await.unwrap().is_none());
+    assert!(incoming_call_b2.next().await.unwrap().is_none());
 
-    buffer_a.read_with(cx_a, |buf, _| assert_eq!(buf.text(), "i-am-c, i-am-b, "));
-    buffer_a.update(cx_a, |buf, cx| {
-        buf.edit([(buf.len()..buf.len(), "i-am-a")], None, cx)
-    });
+    // User B disconnects the client that is not on the call. Everything should be fine.
+    client_b1.disconnect(&cx_b1.to_async());
+    executor.advance_clock(RECEIVE_TIMEOUT);
+    client_b1
+        .authenticate_and_connect(false, &cx_b1.to_async())
+        .await
+        .unwrap();
 
+    // User B hangs up, and user A calls them again.
+    active_call_b2
+        .update(cx_b2, |call, cx| call.hang_up(cx))
+        .await
+        .unwrap();
     executor.run_until_parked();
-
-    buffer_a.read_with(cx_a, |buf, _| {
-        assert_eq!(buf.text(), "i-am-c, i-am-b, i-am-a");
-    });
-
-    buffer_b.read_with(cx_b, |buf, _| {
-        assert_eq!(buf.text(), "i-am-c, i-am-b, i-am-a");
-    });
-
-    buffer_c.read_with(cx_c, |buf, _| {
-        assert_eq!(buf.text(), "i-am-c, i-am-b, i-am-a");
-    });
-
-    // Edit the buffer as the host and concurrently save as guest B.
-    let save_b = project_b.update(cx_b, |project, cx| {
-        project.save_buffer(buffer_b.clone(), cx)
-    });
-    buffer_a.update(cx_a, |buf, cx| buf.edit([(0..0, "hi-a, ")], None, cx));
-    save_b.await.unwrap();
-    assert_eq!(
-        client_a.fs().load("/a/file1.rs".as_ref()).await.unwrap(),
-        "hi-a, i-am-c, i-am-b, i-am-a"
-    );
-
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b1.user_id().unwrap(), None, cx)
+        })
+        .await
+        .unwrap();
     executor.run_until_parked();
+    assert!(incoming_call_b1.next().await.unwrap().is_some());
+    assert!(incoming_call_b2.next().await.unwrap().is_some());
 
-    buffer_a.read_with(cx_a, |buf, _| assert!(!buf.is_dirty()));
-
-    buffer_b.read_with(cx_b, |buf, _| assert!(!buf.is_dirty()));
-
-    buffer_c.read_with(cx_c, |buf, _| assert!(!buf.is_dirty()));
-
-    // Make changes on host's file system, see those changes on guest worktrees.
-    client_a
-        .fs()
-        .rename(
-            "/a/file1.rs".as_ref(),
-            "/a/file1.js".as_ref(),
-            Default::default(),
-        )
+    // User A cancels the call, causing both connections to stop ringing.
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.cancel_invite(client_b1.user_id().unwrap(), cx)
+        })
         .await
         .unwrap();
-    client_a
-        .fs()
-        .rename("/a/file2".as_ref(), "/a/file3".as_ref(), Default::default())
+    executor.run_until_parked();
+    assert!(incoming_call_b1.next().await.unwrap().is_none());
+    assert!(incoming_call_b2.next().await.unwrap().is_none());
+
+    // User A calls user B again.
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b1.user_id().unwrap(), None, cx)
+        })
         .await
         .unwrap();
-    client_a.fs().insert_file("/a/file4", "4".into()).await;
     executor.run_until_parked();
+    assert!(incoming_call_b1.next().await.unwrap().is_some());
+    assert!(incoming_call_b2.next().await.unwrap().is_some());
 
-    worktree_a.read_with(cx_a, |tree, _| {
-        assert_eq!(
-            tree.paths()
-                .map(|p| p.to_string_lossy())
-                .collect::<Vec<_>>(),
-            ["file1.js", "file3", "file4"]
-        )
-    });
-
-    worktree_b.read_with(cx_b, |tree, _| {
-        assert_eq!(
-            tree.paths()
-                .map(|p| p.to_string_lossy())
-                .collect::<Vec<_>>(),
-            ["file1.js", "file3", "file4"]
-        )
-    });
-
-    worktree_c.read_with(cx_c, |tree, _| {
-        assert_eq!(
-            tree.paths()
-                .map(|p| p.to_string_lossy())
-                .collect::<Vec<_>>(),
-            ["file1.js", "file3", "file4"]
-        )
-    });
-
-    // Ensure buffer files are updated as well.
-
-    buffer_a.read_with(cx_a, |buffer, _| {
-        assert_eq!(buffer.file().unwrap().path().to_str(), Some("file1.js"));
-        assert_eq!(&*buffer.language().unwrap().name(), "JavaScript");
-    });
-
-    buffer_b.read_with(cx_b, |buffer, _| {
-        assert_eq!(buffer.file().unwrap().path().to_str(), Some("file1.js"));
-        assert_eq!(&*buffer.language().unwrap().name(), "JavaScript");
-    });
-
-    buffer_c.read_with(cx_c, |buffer, _| {
-        assert_eq!(buffer.file().unwrap().path().to_str(), Some("file1.js"));
-        assert_eq!(&*buffer.language().unwrap().name(), "JavaScript");
-    });
-
-    let new_buffer_a = project_a
-        .update(cx_a, |p, cx| p.create_buffer("", None, cx))
+    // User A hangs up, causing both connections to stop ringing.
+    active_call_a
+        .update(cx_a, |call, cx| call.hang_up(cx))
+        .await
         .unwrap();
+    executor.run_until_parked();
+    assert!(incoming_call_b1.next().await.unwrap().is_none());
+    assert!(incoming_call_b2.next().await.unwrap().is_none());
 
-    let new_buffer_id = new_buffer_a.read_with(cx_a, |buffer, _| buffer.remote_id());
-    let new_buffer_b = project_b
-        .update(cx_b, |p, cx| p.open_buffer_by_id(new_buffer_id, cx))
+    // User A calls user B again.
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b1.user_id().unwrap(), None, cx)
+        })
         .await
         .unwrap();
+    executor.run_until_parked();
+    assert!(incoming_call_b1.next().await.unwrap().is_some());
+    assert!(incoming_call_b2.next().await.unwrap().is_some());
 
-    new_buffer_b.read_with(cx_b, |buffer, _| {
-        assert!(buffer.file().is_none());
-    });
+    // User A disconnects, causing both connections to stop ringing.
+    server.forbid_connections();
+    server.disconnect_client(client_a.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
+    assert!(incoming_call_b1.next().await.unwrap().is_none());
+    assert!(incoming_call_b2.next().await.unwrap().is_none());
 
-    new_buffer_a.update(cx_a, |buffer, cx| {
-        buffer.edit([(0..0, "ok")], None, cx);
-    });
-    project_a
-        .update(cx_a, |project, cx| {
-            project.save_buffer_as(new_buffer_a.clone(), "/a/file3.rs".into(), cx)
+    // User A reconnects automatically, then calls user B again.
+    server.allow_connections();
+    executor.advance_clock(RECEIVE_TIMEOUT);
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b1.user_id().unwrap(), None, cx)
         })
         .await
         .unwrap();
-
     executor.run_until_parked();
+    assert!(incoming_call_b1.next().await.unwrap().is_some());
+    assert!(incoming_call_b2.next().await.unwrap().is_some());
 
-    new_buffer_b.read_with(cx_b, |buffer_b, _| {
-        assert_eq!(
-            buffer_b.file().unwrap().path().as_ref(),
-            Path::new("file3.rs")
-        );
+    // User B disconnects all clients, causing user A to no longer see a pending call for them.
+    server.forbid_connections();
+    server.disconnect_client(client_b1.peer_id().unwrap());
+    server.disconnect_client(client_b2.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
 
-        new_buffer_a.read_with(cx_a, |buffer_a, _| {
-            assert_eq!(buffer_b.saved_mtime(), buffer_a.saved_mtime());
-            assert_eq!(buffer_b.saved_version(), buffer_a.saved_version());
-        });
-    });
+    active_call_a.read_with(cx_a, |call, _| assert!(call.room().is_none()));
 }
 
 #[gpui::test(iterations = 10)]
-async fn test_git_diff_base_change(
+async fn test_unshare_project(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
     server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
         .await;
+
     let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
 
     client_a
         .fs()
         .insert_tree(
-            "/dir",
+            "/a",
             json!({
-            ".git": {},
-            "sub": {
-                ".git": {},
-                "b.txt": "
-                    one
-                    two
-                    three
-                ".unindent(),
-            },
-            "a.txt": "
-                    one
-                    two
-                    three
-                ".unindent(),
+                "a.txt": "a-contents",
+                "b.txt": "b-contents",
             }),
         )
         .await;
 
-    let (project_local, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
     let project_id = active_call_a
-        .update(cx_a, |call, cx| {
-            call.share_project(project_local.clone(), cx)
-        })
-        .await
-        .unwrap();
-
-    let project_remote = client_b.build_remote_project(project_id, cx_b).await;
-
-    let diff_base = "
-        one
-        three
-    "
-    .unindent();
-
-    let new_diff_base = "
-        one
-        two
-    "
-    .unindent();
-
-    client_a.fs().set_index_for_repo(
-        Path::new("/dir/.git"),
-        &[(Path::new("a.txt"), diff_base.clone())],
-    );
-
-    // Create the buffer
-    let buffer_local_a = project_local
-        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
         .await
         .unwrap();
 
-    // Wait for it to catch up to the new diff
+    let worktree_a = project_a.read_with(cx_a, |project, _| project.worktrees().next().unwrap());
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
     executor.run_until_parked();
 
-    // Smoke test diffing
-
-    buffer_local_a.read_with(cx_a, |buffer, _| {
-        assert_eq!(buffer.diff_base(), Some(diff_base.as_ref()));
-        git::diff::assert_hunks(
-            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
-            &buffer,
-            &diff_base,
-            &[(1..2, "", "two\n")],
-        );
-    });
+    assert!(worktree_a.read_with(cx_a, |tree, _| tree.as_local().unwrap().is_shared()));
 
-    // Create remote buffer
-    let buffer_remote_a = project_remote
+    project_b
         .update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
         .await
         .unwrap();
 
-    // Wait remote buffer to catch up to the new diff
+    // When client B leaves the room, the project becomes read-only.
+    active_call_b
+        .update(cx_b, |call, cx| call.hang_up(cx))
+        .await
+        .unwrap();
     executor.run_until_parked();
 
-    // Smoke test diffing
-
-    buffer_remote_a.read_with(cx_b, |buffer, _| {
-        assert_eq!(buffer.diff_base(), Some(diff_base.as_ref()));
-        git::diff::assert_hunks(
-            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
-            &buffer,
-            &diff_base,
-            &[(1..2, "", "two\n")],
-        );
-    });
+    assert!(project_b.read_with(cx_b, |project, _| project.is_disconnected()));
 
-    client_a.fs().set_index_for_repo(
-        Path::new("/dir/.git"),
-        &[(Path::new("a.txt"), new_diff_base.clone())],
-    );
+    // Client C opens the project.
+    let project_c = client_c.build_remote_project(project_id, cx_c).await;
 
-    // Wait for buffer_local_a to receive it
+    // When client A unshares the project, client C's project becomes read-only.
+    project_a
+        .update(cx_a, |project, cx| project.unshare(cx))
+        .unwrap();
     executor.run_until_parked();
 
-    // Smoke test new diffing
-
-    buffer_local_a.read_with(cx_a, |buffer, _| {
-        assert_eq!(buffer.diff_base(), Some(new_diff_base.as_ref()));
-
-        git::diff::assert_hunks(
-            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
-            &buffer,
-            &diff_base,
-            &[(2..3, "", "three\n")],
-        );
-    });
-
-    // Smoke test B
-
-    buffer_remote_a.read_with(cx_b, |buffer, _| {
-        assert_eq!(buffer.diff_base(), Some(new_diff_base.as_ref()));
-        git::diff::assert_hunks(
-            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
-            &buffer,
-            &diff_base,
-            &[(2..3, "", "three\n")],
-        );
-    });
-
-    //Nested git dir
-
-    let diff_base = "
-        one
-        three
-    "
-    .unindent();
-
-    let new_diff_base = "
-        one
-        two
-    "
-    .unindent();
+    assert!(worktree_a.read_with(cx_a, |tree, _| !tree.as_local().unwrap().is_shared()));
 
-    client_a.fs().set_index_for_repo(
-        Path::new("/dir/sub/.git"),
-        &[(Path::new("b.txt"), diff_base.clone())],
-    );
+    assert!(project_c.read_with(cx_c, |project, _| project.is_disconnected()));
 
-    // Create the buffer
-    let buffer_local_b = project_local
-        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "sub/b.txt"), cx))
+    // Client C can open the project again after client A re-shares.
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
         .await
         .unwrap();
-
-    // Wait for it to catch up to the new diff
+    let project_c2 = client_c.build_remote_project(project_id, cx_c).await;
     executor.run_until_parked();
 
-    // Smoke test diffing
-
-    buffer_local_b.read_with(cx_a, |buffer, _| {
-        assert_eq!(buffer.diff_base(), Some(diff_base.as_ref()));
-        git::diff::assert_hunks(
-            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
-            &buffer,
-            &diff_base,
-            &[(1..2, "", "two\n")],
-        );
-    });
-
-    // Create remote buffer
-    let buffer_remote_b = project_remote
-        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "sub/b.txt"), cx))
+    assert!(worktree_a.read_with(cx_a, |tree, _| tree.as_local().unwrap().is_shared()));
+    project_c2
+        .update(cx_c, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
         .await
         .unwrap();
 
-    // Wait remote buffer to catch up to the new diff
-    executor.run_until_parked();
-
-    // Smoke test diffing
-
-    buffer_remote_b.read_with(cx_b, |buffer, _| {
-        assert_eq!(buffer.diff_base(), Some(diff_base.as_ref()));
-        git::diff::assert_hunks(
-            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
-            &buffer,
-            &diff_base,
-            &[(1..2, "", "two\n")],
-        );
-    });
-
-    client_a.fs().set_index_for_repo(
-        Path::new("/dir/sub/.git"),
-        &[(Path::new("b.txt"), new_diff_base.clone())],
-    );
-
-    // Wait for buffer_local_b to receive it
+    // When client A (the host) leaves the room, the project gets unshared and guests are notified.
+    active_call_a
+        .update(cx_a, |call, cx| call.hang_up(cx))
+        .await
+        .unwrap();
     executor.run_until_parked();
 
-    // Smoke test new diffing
-
-    buffer_local_b.read_with(cx_a, |buffer, _| {
-        assert_eq!(buffer.diff_base(), Some(new_diff_base.as_ref()));
-        println!("{:?}", buffer.as_rope().to_string());
-        println!("{:?}", buffer.diff_base());
-        println!(
-            "{:?}",
-            buffer
-                .snapshot()
-                .git_diff_hunks_in_row_range(0..4)
-                .collect::<Vec<_>>()
-        );
-
-        git::diff::assert_hunks(
-            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
-            &buffer,
-            &diff_base,
-            &[(2..3, "", "three\n")],
-        );
-    });
-
-    // Smoke test B
+    project_a.read_with(cx_a, |project, _| assert!(!project.is_shared()));
 
-    buffer_remote_b.read_with(cx_b, |buffer, _| {
-        assert_eq!(buffer.diff_base(), Some(new_diff_base.as_ref()));
-        git::diff::assert_hunks(
-            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
-            &buffer,
-            &diff_base,
-            &[(2..3, "", "three\n")],
-        );
+    project_c2.read_with(cx_c, |project, _| {
+        assert!(project.is_disconnected());
+        assert!(project.collaborators().is_empty());
     });
 }
 
-#[gpui::test]
-async fn test_git_branch_name(
+#[gpui::test(iterations = 10)]
+async fn test_listing_shared_projects_in_room(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
-    cx_c: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
-    let client_c = server.create_client(cx_c, "user_c").await;
     server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
     let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
 
     client_a
         .fs()
         .insert_tree(
-            "/dir",
+            "/code",
             json!({
-            ".git": {},
+                "main.rs": "",
+                "lib.rs": "",
+                "README.md": "",
+                "src": {
+                    "util.RS": "",
+                    "Makefile": "",
+                },
             }),
         )
         .await;
-
-    let (project_local, _worktree_id) = client_a.build_local_project("/dir", cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| {
-            call.share_project(project_local.clone(), cx)
-        })
-        .await
-        .unwrap();
-
-    let project_remote = client_b.build_remote_project(project_id, cx_b).await;
     client_a
         .fs()
-        .set_branch_name(Path::new("/dir/.git"), Some("branch-1"));
-
-    // Wait for it to catch up to the new branch
-    executor.run_until_parked();
-
-    #[track_caller]
-    fn assert_branch(branch_name: Option<impl Into<String>>, project: &Project, cx: &AppContext) {
-        let branch_name = branch_name.map(Into::into);
-        let worktrees = project.visible_worktrees(cx).collect::<Vec<_>>();
-        assert_eq!(worktrees.len(), 1);
-        let worktree = worktrees[0].clone();
-        let root_entry = worktree.read(cx).snapshot().root_git_entry().unwrap();
-        assert_eq!(root_entry.branch(), branch_name.map(Into::into));
-    }
-
-    // Smoke test branch reading
-
-    project_local.read_with(cx_a, |project, cx| {
-        assert_branch(Some("branch-1"), project, cx)
-    });
-
-    project_remote.read_with(cx_b, |project, cx| {
-        assert_branch(Some("branch-1"), project, cx)
-    });
-
+        .insert_tree("/docs", json!({ "guide.md": "" }))
+        .await;
     client_a
         .fs()
-        .set_branch_name(Path::new("/dir/.git"), Some("branch-2"));
-
-    // Wait for buffer_local_a to receive it
+        .insert_tree("/deps", json!({ "dep.rs": "" }))
+        .await;
+    let (project_a, _) = client_a.build_local_project("/code", cx_a).await;
+    project_a
+        .update(cx_a, |project, cx| {
+            project.find_or_create_local_worktree("/docs", true, cx)
+        })
+        .await
+        .unwrap();
+    // Invisible worktrees, such as those opened for go-to-definition, aren't described.
+    project_a
+        .update(cx_a, |project, cx| {
+            project.find_or_create_local_worktree("/deps/dep.rs", false, cx)
+        })
+        .await
+        .unwrap();
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
     executor.run_until_parked();
 
-    // Smoke test branch reading
-
-    project_local.read_with(cx_a, |project, cx| {
-        assert_branch(Some("branch-2"), project, cx)
-    });
-
-    project_remote.read_with(cx_b, |project, cx| {
-        assert_branch(Some("branch-2"), project, cx)
-    });
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
+    let projects = room_b
+        .update(cx_b, |room, cx| room.shared_project_metadata(cx))
+        .await
+        .unwrap();
+    assert_eq!(
+        projects,
+        [proto::SharedProjectMetadata {
+            id: project_id,
+            host_user_id: client_a.user_id().unwrap(),
+            worktree_root_names: vec!["code".to_string(), "docs".to_string()],
+            file_extensions: vec![
+                proto::FileExtensionCount {
+                    extension: "rs".to_string(),
+                    file_count: 3,
+                },
+                proto::FileExtensionCount {
+                    extension: "md".to_string(),
+                    file_count: 2,
+                },
+            ],
+            // Both worktree roots, the "src" directory, and the six files.
+            entry_count: 9,
+            read_only: false,
+            host_project_id: project_a.entity_id().as_u64(),
+        }]
+    );
 
-    let project_remote_c = client_c.build_remote_project(project_id, cx_c).await;
+    // Unshared projects are no longer listed.
+    project_a.update(cx_a, |project, cx| project.unshare(cx).unwrap());
     executor.run_until_parked();
-
-    project_remote_c.read_with(cx_c, |project, cx| {
-        assert_branch(Some("branch-2"), project, cx)
-    });
+    let projects = room_b
+        .update(cx_b, |room, cx| room.shared_project_metadata(cx))
+        .await
+        .unwrap();
+    assert_eq!(projects, []);
 }
 
 #[gpui::test]
-async fn test_git_status_sync(
+async fn test_remote_projects_with_same_root_names(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
-    cx_c: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
-    let client_c = server.create_client(cx_c, "user_c").await;
     server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
     let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
 
     client_a
         .fs()
-        .insert_tree(
-            "/dir",
-            json!({
-            ".git": {},
-            "a.txt": "a",
-            "b.txt": "b",
-            }),
-        )
+        .insert_tree("/one/code", json!({ "main.rs": "" }))
         .await;
-
-    const A_TXT: &'static str = "a.txt";
-    const B_TXT: &'static str = "b.txt";
-
-    client_a.fs().set_status_for_repo_via_git_operation(
-        Path::new("/dir/.git"),
-        &[
-            (&Path::new(A_TXT), GitFileStatus::Added),
-            (&Path::new(B_TXT), GitFileStatus::Added),
-        ],
-    );
-
-    let (project_local, _worktree_id) = client_a.build_local_project("/dir", cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| {
-            call.share_project(project_local.clone(), cx)
-        })
+    client_a
+        .fs()
+        .insert_tree("/two/code", json!({ "lib.rs": "" }))
+        .await;
+    let (project_a1, _) = client_a.build_local_project("/one/code", cx_a).await;
+    let (project_a2, _) = client_a.build_local_project("/two/code", cx_a).await;
+    let project_id1 = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a1.clone(), cx))
+        .await
+        .unwrap();
+    let project_id2 = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a2.clone(), cx))
         .await
         .unwrap();
-
-    let project_remote = client_b.build_remote_project(project_id, cx_b).await;
-
-    // Wait for it to catch up to the new status
-    executor.run_until_parked();
-
-    #[track_caller]
-    fn assert_status(
-        file: &impl AsRef<Path>,
-        status: Option<GitFileStatus>,
-        project: &Project,
-        cx: &AppContext,
-    ) {
-        let file = file.as_ref();
-        let worktrees = project.visible_worktrees(cx).collect::<Vec<_>>();
-        assert_eq!(worktrees.len(), 1);
-        let worktree = worktrees[0].clone();
-        let snapshot = worktree.read(cx).snapshot();
-        assert_eq!(snapshot.status_for_file(file), status);
-    }
-
-    // Smoke test status reading
-
-    project_local.read_with(cx_a, |project, cx| {
-        assert_status(&Path::new(A_TXT), Some(GitFileStatus::Added), project, cx);
-        assert_status(&Path::new(B_TXT), Some(GitFileStatus::Added), project, cx);
-    });
-
-    project_remote.read_with(cx_b, |project, cx| {
-        assert_status(&Path::new(A_TXT), Some(GitFileStatus::Added), project, cx);
-        assert_status(&Path::new(B_TXT), Some(GitFileStatus::Added), project, cx);
-    });
-
-    client_a.fs().set_status_for_repo_via_working_copy_change(
-        Path::new("/dir/.git"),
-        &[
-            (&Path::new(A_TXT), GitFileStatus::Modified),
-            (&Path::new(B_TXT), GitFileStatus::Modified),
-        ],
-    );
-
-    // Wait for buffer_local_a to receive it
     executor.run_until_parked();
 
-    // Smoke test status reading
-
-    project_local.read_with(cx_a, |project, cx| {
-        assert_status(
-            &Path::new(A_TXT),
-            Some(GitFileStatus::Modified),
-            project,
-            cx,
+    // Both projects are rooted at "code", but the guest can tell them apart by the
+    // ids the host gave them.
+    let user_id_a = client_a.user_id().unwrap();
+    let host_project_id1 = project_a1.entity_id().as_u64();
+    let host_project_id2 = project_a2.entity_id().as_u64();
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
+    room_b.read_with(cx_b, |room, _| {
+        assert_eq!(
+            room.remote_project_id(user_id_a, host_project_id1),
+            Some(project_id1)
         );
-        assert_status(
-            &Path::new(B_TXT),
-            Some(GitFileStatus::Modified),
-            project,
-            cx,
+        assert_eq!(
+            room.remote_project_id(user_id_a, host_project_id2),
+            Some(project_id2)
         );
     });
 
-    project_remote.read_with(cx_b, |project, cx| {
-        assert_status(
-            &Path::new(A_TXT),
-            Some(GitFileStatus::Modified),
-            project,
-            cx,
-        );
-        assert_status(
-            &Path::new(B_TXT),
-            Some(GitFileStatus::Modified),
-            project,
-            cx,
-        );
+    // When the host shares a project again, it gets a new id, but keeps the one the
+    // host gave it.
+    project_a1.update(cx_a, |project, cx| project.unshare(cx).unwrap());
+    executor.run_until_parked();
+    room_b.read_with(cx_b, |room, _| {
+        assert_eq!(room.remote_project_id(user_id_a, host_project_id1), None);
     });
-
-    // And synchronization while joining
-    let project_remote_c = client_c.build_remote_project(project_id, cx_c).await;
+    let new_project_id1 = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a1.clone(), cx))
+        .await
+        .unwrap();
     executor.run_until_parked();
-
-    project_remote_c.read_with(cx_c, |project, cx| {
-        assert_status(
-            &Path::new(A_TXT),
-            Some(GitFileStatus::Modified),
-            project,
-            cx,
-        );
-        assert_status(
-            &Path::new(B_TXT),
-            Some(GitFileStatus::Modified),
-            project,
-            cx,
+    assert_ne!(new_project_id1, project_id1);
+    room_b.read_with(cx_b, |room, _| {
+        assert_eq!(
+            room.remote_project_id(user_id_a, host_project_id1),
+            Some(new_project_id1)
         );
     });
 }
 
 #[gpui::test(iterations = 10)]
-async fn test_fs_operations(
+async fn test_project_reconnect(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
@@ -2947,275 +3995,359 @@ async fn test_fs_operations(
     server
         .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
-    let active_call_a = cx_a.read(ActiveCall::global);
+
+    cx_b.update(editor::init);
 
     client_a
         .fs()
         .insert_tree(
-            "/dir",
+            "/root-1",
             json!({
-                "a.txt": "a-contents",
-                "b.txt": "b-contents",
+                "dir1": {
+                    "a.txt": "a",
+                    "b.txt": "b",
+                    "subdir1": {
+                        "c.txt": "c",
+                        "d.txt": "d",
+                        "e.txt": "e",
+                    }
+                },
+                "dir2": {
+                    "v.txt": "v",
+                },
+                "dir3": {
+                    "w.txt": "w",
+                    "x.txt": "x",
+                    "y.txt": "y",
+                },
+                "dir4": {
+                    "z.txt": "z",
+                },
+            }),
+        )
+        .await;
+    client_a
+        .fs()
+        .insert_tree(
+            "/root-2",
+            json!({
+                "2.txt": "2",
+            }),
+        )
+        .await;
+    client_a
+        .fs()
+        .insert_tree(
+            "/root-3",
+            json!({
+                "3.txt": "3",
             }),
         )
         .await;
-    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
-        .await
-        .unwrap();
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
-
-    let worktree_a = project_a.read_with(cx_a, |project, _| project.worktrees().next().unwrap());
 
-    let worktree_b = project_b.read_with(cx_b, |project, _| project.worktrees().next().unwrap());
-
-    let entry = project_b
-        .update(cx_b, |project, cx| {
-            project.create_entry((worktree_id, "c.txt"), false, cx)
-        })
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let (project_a1, _) = client_a.build_local_project("/root-1/dir1", cx_a).await;
+    let (project_a2, _) = client_a.build_local_project("/root-2", cx_a).await;
+    let (project_a3, _) = client_a.build_local_project("/root-3", cx_a).await;
+    let worktree_a1 = project_a1.read_with(cx_a, |project, _| project.worktrees().next().unwrap());
+    let project1_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a1.clone(), cx))
+        .await
+        .unwrap();
+    let project2_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a2.clone(), cx))
+        .await
+        .unwrap();
+    let project3_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a3.clone(), cx))
         .await
-        .unwrap()
         .unwrap();
 
-    worktree_a.read_with(cx_a, |worktree, _| {
-        assert_eq!(
-            worktree
-                .paths()
-                .map(|p| p.to_string_lossy())
-                .collect::<Vec<_>>(),
-            ["a.txt", "b.txt", "c.txt"]
-        );
-    });
+    let project_b1 = client_b.build_remote_project(project1_id, cx_b).await;
+    let project_b2 = client_b.build_remote_project(project2_id, cx_b).await;
+    let project_b3 = client_b.build_remote_project(project3_id, cx_b).await;
+    executor.run_until_parked();
 
-    worktree_b.read_with(cx_b, |worktree, _| {
-        assert_eq!(
-            worktree
-                .paths()
-                .map(|p| p.to_string_lossy())
-                .collect::<Vec<_>>(),
-            ["a.txt", "b.txt", "c.txt"]
-        );
+    let worktree1_id = worktree_a1.read_with(cx_a, |worktree, _| {
+        assert!(worktree.as_local().unwrap().is_shared());
+        worktree.id()
     });
-
-    project_b
-        .update(cx_b, |project, cx| {
-            project.rename_entry(entry.id, Path::new("d.txt"), cx)
+    let (worktree_a2, _) = project_a1
+        .update(cx_a, |p, cx| {
+            p.find_or_create_local_worktree("/root-1/dir2", true, cx)
         })
         .await
-        .unwrap()
         .unwrap();
+    executor.run_until_parked();
 
-    worktree_a.read_with(cx_a, |worktree, _| {
-        assert_eq!(
-            worktree
-                .paths()
-                .map(|p| p.to_string_lossy())
-                .collect::<Vec<_>>(),
-            ["a.txt", "b.txt", "d.txt"]
-        );
+    let worktree2_id = worktree_a2.read_with(cx_a, |tree, _| {
+        assert!(tree.as_local().unwrap().is_shared());
+        tree.id()
     });
+    executor.run_until_parked();
 
-    worktree_b.read_with(cx_b, |worktree, _| {
-        assert_eq!(
-            worktree
-                .paths()
-                .map(|p| p.to_string_lossy())
-                .collect::<Vec<_>>(),
-            ["a.txt", "b.txt", "d.txt"]
-        );
+    project_b1.read_with(cx_b, |project, cx| {
+        assert!(project.worktree_for_id(worktree2_id, cx).is_some())
     });
 
-    let dir_entry = project_b
-        .update(cx_b, |project, cx| {
-            project.create_entry((worktree_id, "DIR"), true, cx)
-        })
+    let buffer_a1 = project_a1
+        .update(cx_a, |p, cx| p.open_buffer((worktree1_id, "a.txt"), cx))
+        .await
+        .unwrap();
+    let buffer_b1 = project_b1
+        .update(cx_b, |p, cx| p.open_buffer((worktree1_id, "a.txt"), cx))
         .await
-        .unwrap()
         .unwrap();
 
-    worktree_a.read_with(cx_a, |worktree, _| {
-        assert_eq!(
-            worktree
-                .paths()
-                .map(|p| p.to_string_lossy())
-                .collect::<Vec<_>>(),
-            ["DIR", "a.txt", "b.txt", "d.txt"]
-        );
+    // Drop client A's connection.
+    server.forbid_connections();
+    server.disconnect_client(client_a.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT);
+
+    project_a1.read_with(cx_a, |project, _| {
+        assert!(project.is_shared());
+        assert_eq!(project.collaborators().len(), 1);
     });
 
-    worktree_b.read_with(cx_b, |worktree, _| {
-        assert_eq!(
-            worktree
-                .paths()
-                .map(|p| p.to_string_lossy())
-                .collect::<Vec<_>>(),
-            ["DIR", "a.txt", "b.txt", "d.txt"]
-        );
+    project_b1.read_with(cx_b, |project, _| {
+        assert!(!project.is_disconnected());
+        assert_eq!(project.collaborators().len(), 1);
     });
 
-    project_b
-        .update(cx_b, |project, cx| {
-            project.create_entry((worktree_id, "DIR/e.txt"), false, cx)
-        })
+    worktree_a1.read_with(cx_a, |tree, _| {
+        assert!(tree.as_local().unwrap().is_shared())
+    });
+
+    // While client A is disconnected, add and remove files from client A's project.
+    client_a
+        .fs()
+        .insert_tree(
+            "/root-1/dir1/subdir2",
+            json!({
+                "f.txt": "f-contents",
+                "g.txt": "g-contents",
+                "h.txt": "h-contents",
+                "i.txt": "i-contents",
+            }),
+        )
+        .await;
+    client_a
+        .fs()
+        .remove_dir(
+            "/root-1/dir1/subdir1".as_ref(),
+            RemoveOptions {
+                recursive: true,
+                ..Default::default()
+            },
+        )
         .await
-        .unwrap()
         .unwrap();
-    project_b
-        .update(cx_b, |project, cx| {
-            project.create_entry((worktree_id, "DIR/SUBDIR"), true, cx)
+
+    // While client A is disconnected, add and remove worktrees from client A's project.
+    project_a1.update(cx_a, |project, cx| {
+        project.remove_worktree(worktree2_id, cx)
+    });
+    let (worktree_a3, _) = project_a1
+        .update(cx_a, |p, cx| {
+            p.find_or_create_local_worktree("/root-1/dir3", true, cx)
         })
         .await
-        .unwrap()
         .unwrap();
-    project_b
-        .update(cx_b, |project, cx| {
-            project.create_entry((worktree_id, "DIR/SUBDIR/f.txt"), false, cx)
-        })
+    worktree_a3
+        .read_with(cx_a, |tree, _| tree.as_local().unwrap().scan_complete())
+        .await;
+
+    let worktree3_id = worktree_a3.read_with(cx_a, |tree, _| {
+        assert!(!tree.as_local().unwrap().is_shared());
+        tree.id()
+    });
+    executor.run_until_parked();
+
+    // While client A is disconnected, close project 2
+    cx_a.update(|_| drop(project_a2));
+
+    // While client A is disconnected, mutate a buffer on both the host and the guest.
+    buffer_a1.update(cx_a, |buf, cx| buf.edit([(0..0, "W")], None, cx));
+    buffer_b1.update(cx_b, |buf, cx| buf.edit([(1..1, "Z")], None, cx));
+    executor.run_until_parked();
+
+    // Client A reconnects. Their project is re-shared, and client B re-joins it.
+    server.allow_connections();
+    client_a
+        .authenticate_and_connect(false, &cx_a.to_async())
         .await
-        .unwrap()
         .unwrap();
+    executor.run_until_parked();
 
-    worktree_a.read_with(cx_a, |worktree, _| {
+    project_a1.read_with(cx_a, |project, cx| {
+        assert!(project.is_shared());
+        assert!(worktree_a1.read(cx).as_local().unwrap().is_shared());
         assert_eq!(
-            worktree
+            worktree_a1
+                .read(cx)
+                .snapshot()
                 .paths()
-                .map(|p| p.to_string_lossy())
+                .map(|p| p.to_str().unwrap())
                 .collect::<Vec<_>>(),
-            [
-                "DIR",
-                "DIR/SUBDIR",
-                "DIR/SUBDIR/f.txt",
-                "DIR/e.txt",
+            vec![
                 "a.txt",
                 "b.txt",
-                "d.txt"
+                "subdir2",
+                "subdir2/f.txt",
+                "subdir2/g.txt",
+                "subdir2/h.txt",
+                "subdir2/i.txt"
             ]
         );
-    });
-
-    worktree_b.read_with(cx_b, |worktree, _| {
+        assert!(worktree_a3.read(cx).as_local().unwrap().is_shared());
         assert_eq!(
-            worktree
+            worktree_a3
+                .read(cx)
+                .snapshot()
                 .paths()
-                .map(|p| p.to_string_lossy())
+                .map(|p| p.to_str().unwrap())
                 .collect::<Vec<_>>(),
-            [
-                "DIR",
-                "DIR/SUBDIR",
-                "DIR/SUBDIR/f.txt",
-                "DIR/e.txt",
-                "a.txt",
-                "b.txt",
-                "d.txt"
-            ]
+            vec!["w.txt", "x.txt", "y.txt"]
         );
     });
 
-    project_b
-        .update(cx_b, |project, cx| {
-            project.copy_entry(entry.id, Path::new("f.txt"), cx)
-        })
-        .await
-        .unwrap()
-        .unwrap();
-
-    worktree_a.read_with(cx_a, |worktree, _| {
+    project_b1.read_with(cx_b, |project, cx| {
+        assert!(!project.is_disconnected());
         assert_eq!(
-            worktree
+            project
+                .worktree_for_id(worktree1_id, cx)
+                .unwrap()
+                .read(cx)
+                .snapshot()
                 .paths()
-                .map(|p| p.to_string_lossy())
+                .map(|p| p.to_str().unwrap())
                 .collect::<Vec<_>>(),
-            [
-                "DIR",
-                "DIR/SUBDIR",
-                "DIR/SUBDIR/f.txt",
-                "DIR/e.txt",
+            vec![
                 "a.txt",
                 "b.txt",
-                "d.txt",
-                "f.txt"
-            ]
+                "subdir2",
+                "subdir2/f.txt",
+                "subdir2/g.txt",
+                "subdir2/h.txt",
+                "subdir2/i.txt"
+            ]
         );
-    });
-
-    worktree_b.read_with(cx_b, |worktree, _| {
+        assert!(project.worktree_for_id(worktree2_id, cx).is_none());
         assert_eq!(
-            worktree
+            project
+                .worktree_for_id(worktree3_id, cx)
+                .unwrap()
+                .read(cx)
+                .snapshot()
                 .paths()
-                .map(|p| p.to_string_lossy())
+                .map(|p| p.to_str().unwrap())
                 .collect::<Vec<_>>(),
-            [
-                "DIR",
-                "DIR/SUBDIR",
-                "DIR/SUBDIR/f.txt",
-                "DIR/e.txt",
-                "a.txt",
-                "b.txt",
-                "d.txt",
-                "f.txt"
-            ]
+            vec!["w.txt", "x.txt", "y.txt"]
         );
     });
 
-    project_b
-        .update(cx_b, |project, cx| {
-            project.delete_entry(dir_entry.id, cx).unwrap()
+    project_b2.read_with(cx_b, |project, _| assert!(project.is_disconnected()));
+
+    project_b3.read_with(cx_b, |project, _| assert!(!project.is_disconnected()));
+
+    buffer_a1.read_with(cx_a, |buffer, _| assert_eq!(buffer.text(), "WaZ"));
+
+    buffer_b1.read_with(cx_b, |buffer, _| assert_eq!(buffer.text(), "WaZ"));
+
+    // Drop client B's connection.
+    server.forbid_connections();
+    server.disconnect_client(client_b.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT);
+
+    // While client B is disconnected, add and remove files from client A's project
+    client_a
+        .fs()
+        .insert_file("/root-1/dir1/subdir2/j.txt", "j-contents".into())
+        .await;
+    client_a
+        .fs()
+        .remove_file("/root-1/dir1/subdir2/i.txt".as_ref(), Default::default())
+        .await
+        .unwrap();
+
+    // While client B is disconnected, add and remove worktrees from client A's project.
+    let (worktree_a4, _) = project_a1
+        .update(cx_a, |p, cx| {
+            p.find_or_create_local_worktree("/root-1/dir4", true, cx)
         })
         .await
         .unwrap();
     executor.run_until_parked();
 
-    worktree_a.read_with(cx_a, |worktree, _| {
-        assert_eq!(
-            worktree
-                .paths()
-                .map(|p| p.to_string_lossy())
-                .collect::<Vec<_>>(),
-            ["a.txt", "b.txt", "d.txt", "f.txt"]
-        );
+    let worktree4_id = worktree_a4.read_with(cx_a, |tree, _| {
+        assert!(tree.as_local().unwrap().is_shared());
+        tree.id()
     });
-
-    worktree_b.read_with(cx_b, |worktree, _| {
-        assert_eq!(
-            worktree
-                .paths()
-                .map(|p| p.to_string_lossy())
-                .collect::<Vec<_>>(),
-            ["a.txt", "b.txt", "d.txt", "f.txt"]
-        );
+    project_a1.update(cx_a, |project, cx| {
+        project.remove_worktree(worktree3_id, cx)
     });
+    executor.run_until_parked();
 
-    project_b
-        .update(cx_b, |project, cx| {
-            project.delete_entry(entry.id, cx).unwrap()
-        })
+    // While client B is disconnected, mutate a buffer on both the host and the guest.
+    buffer_a1.update(cx_a, |buf, cx| buf.edit([(1..1, "X")], None, cx));
+    buffer_b1.update(cx_b, |buf, cx| buf.edit([(2..2, "Y")], None, cx));
+    executor.run_until_parked();
+
+    // While disconnected, close project 3
+    cx_a.update(|_| drop(project_a3));
+
+    // Client B reconnects. They re-join the room and the remaining shared project.
+    server.allow_connections();
+    client_b
+        .authenticate_and_connect(false, &cx_b.to_async())
         .await
         .unwrap();
+    executor.run_until_parked();
 
-    worktree_a.read_with(cx_a, |worktree, _| {
+    project_b1.read_with(cx_b, |project, cx| {
+        assert!(!project.is_disconnected());
         assert_eq!(
-            worktree
+            project
+                .worktree_for_id(worktree1_id, cx)
+                .unwrap()
+                .read(cx)
+                .snapshot()
                 .paths()
-                .map(|p| p.to_string_lossy())
+                .map(|p| p.to_str().unwrap())
                 .collect::<Vec<_>>(),
-            ["a.txt", "b.txt", "f.txt"]
+            vec![
+                "a.txt",
+                "b.txt",
+                "subdir2",
+                "subdir2/f.txt",
+                "subdir2/g.txt",
+                "subdir2/h.txt",
+                "subdir2/j.txt"
+            ]
         );
-    });
-
-    worktree_b.read_with(cx_b, |worktree, _| {
+        assert!(project.worktree_for_id(worktree2_id, cx).is_none());
         assert_eq!(
-            worktree
+            project
+                .worktree_for_id(worktree4_id, cx)
+                .unwrap()
+                .read(cx)
+                .snapshot()
                 .paths()
-                .map(|p| p.to_string_lossy())
+                .map(|p| p.to_str().unwrap())
                 .collect::<Vec<_>>(),
-            ["a.txt", "b.txt", "f.txt"]
+            vec!["z.txt"]
         );
     });
+
+    project_b3.read_with(cx_b, |project, _| assert!(project.is_disconnected()));
+
+    buffer_a1.read_with(cx_a, |buffer, _| assert_eq!(buffer.text(), "WXaYZ"));
+
+    buffer_b1.read_with(cx_b, |buffer, _| assert_eq!(buffer.text(), "WXaYZ"));
 }
 
 #[gpui::test(iterations = 10)]
-async fn test_local_settings(
+async fn test_guest_edits_replayed_after_host_reconnects(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
@@ -3228,133 +4360,54 @@ async fn test_local_settings(
         .await;
     let active_call_a = cx_a.read(ActiveCall::global);
 
-    // As client A, open a project that contains some local settings files
     client_a
         .fs()
-        .insert_tree(
-            "/dir",
-            json!({
-                ".zed": {
-                    "settings.json": r#"{ "tab_size": 2 }"#
-                },
-                "a": {
-                    ".zed": {
-                        "settings.json": r#"{ "tab_size": 8 }"#
-                    },
-                    "a.txt": "a-contents",
-                },
-                "b": {
-                    "b.txt": "b-contents",
-                }
-            }),
-        )
+        .insert_tree("/dir", json!({ "a.txt": "one two" }))
         .await;
-    let (project_a, _) = client_a.build_local_project("/dir", cx_a).await;
-    executor.run_until_parked();
+    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
     let project_id = active_call_a
         .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
         .await
         .unwrap();
-    executor.run_until_parked();
-
-    // As client B, join that project and observe the local settings.
     let project_b = client_b.build_remote_project(project_id, cx_b).await;
-
-    let worktree_b = project_b.read_with(cx_b, |project, _| project.worktrees().next().unwrap());
-    executor.run_until_parked();
-    cx_b.read(|cx| {
-        let store = cx.global::<SettingsStore>();
-        assert_eq!(
-            store
-                .local_settings(worktree_b.read(cx).id().to_usize())
-                .collect::<Vec<_>>(),
-            &[
-                (Path::new("").into(), r#"{"tab_size":2}"#.to_string()),
-                (Path::new("a").into(), r#"{"tab_size":8}"#.to_string()),
-            ]
-        )
-    });
-
-    // As client A, update a settings file. As Client B, see the changed settings.
-    client_a
-        .fs()
-        .insert_file("/dir/.zed/settings.json", r#"{}"#.into())
-        .await;
-    executor.run_until_parked();
-    cx_b.read(|cx| {
-        let store = cx.global::<SettingsStore>();
-        assert_eq!(
-            store
-                .local_settings(worktree_b.read(cx).id().to_usize())
-                .collect::<Vec<_>>(),
-            &[
-                (Path::new("").into(), r#"{}"#.to_string()),
-                (Path::new("a").into(), r#"{"tab_size":8}"#.to_string()),
-            ]
-        )
-    });
-
-    // As client A, create and remove some settings files. As client B, see the changed settings.
-    client_a
-        .fs()
-        .remove_file("/dir/.zed/settings.json".as_ref(), Default::default())
+    let buffer_a = project_a
+        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
         .await
         .unwrap();
-    client_a
-        .fs()
-        .create_dir("/dir/b/.zed".as_ref())
+    let buffer_b = project_b
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
         .await
         .unwrap();
-    client_a
-        .fs()
-        .insert_file("/dir/b/.zed/settings.json", r#"{"tab_size": 4}"#.into())
-        .await;
-    executor.run_until_parked();
-    cx_b.read(|cx| {
-        let store = cx.global::<SettingsStore>();
-        assert_eq!(
-            store
-                .local_settings(worktree_b.read(cx).id().to_usize())
-                .collect::<Vec<_>>(),
-            &[
-                (Path::new("a").into(), r#"{"tab_size":8}"#.to_string()),
-                (Path::new("b").into(), r#"{"tab_size":4}"#.to_string()),
-            ]
-        )
-    });
 
-    // As client B, disconnect.
+    // While the host is disconnected, the server acknowledges the guest's edits.
     server.forbid_connections();
-    server.disconnect_client(client_b.peer_id().unwrap());
+    server.disconnect_client(client_a.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT);
+    buffer_b.update(cx_b, |buffer, cx| buffer.edit([(3..3, " and")], None, cx));
+    project_b
+        .read_with(cx_b, |project, _| project.flush())
+        .await
+        .unwrap();
 
-    // As client A, change and remove settings files while client B is disconnected.
-    client_a
-        .fs()
-        .insert_file("/dir/a/.zed/settings.json", r#"{"hard_tabs":true}"#.into())
-        .await;
+    // Once the host rejoins, the edits are replayed to it.
+    server.allow_connections();
     client_a
-        .fs()
-        .remove_file("/dir/b/.zed/settings.json".as_ref(), Default::default())
+        .authenticate_and_connect(false, &cx_a.to_async())
         .await
         .unwrap();
     executor.run_until_parked();
-
-    // As client B, reconnect and see the changed settings.
-    server.allow_connections();
-    executor.advance_clock(RECEIVE_TIMEOUT);
-    cx_b.read(|cx| {
-        let store = cx.global::<SettingsStore>();
-        assert_eq!(
-            store
-                .local_settings(worktree_b.read(cx).id().to_usize())
-                .collect::<Vec<_>>(),
-            &[(Path::new("a").into(), r#"{"hard_tabs":true}"#.to_string()),]
-        )
-    });
-}
+    buffer_a.read_with(cx_a, |buffer, _| {
+        assert_eq!(buffer.text(), "one and two");
+        assert_eq!(buffer.deferred_ops_len(), 0);
+    });
+    buffer_b.read_with(cx_b, |buffer, _| {
+        assert_eq!(buffer.text(), "one and two");
+        assert_eq!(buffer.deferred_ops_len(), 0);
+    });
+}
 
 #[gpui::test(iterations = 10)]
-async fn test_buffer_conflict_after_save(
+async fn test_active_call_events(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
@@ -3362,415 +4415,435 @@ async fn test_buffer_conflict_after_save(
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
-    server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
-        .await;
-    let active_call_a = cx_a.read(ActiveCall::global);
-
-    client_a
-        .fs()
-        .insert_tree(
-            "/dir",
-            json!({
-                "a.txt": "a-contents",
-            }),
-        )
-        .await;
-    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
-        .await
-        .unwrap();
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
-
-    // Open a buffer as client B
-    let buffer_b = project_b
-        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
-        .await
-        .unwrap();
-
-    buffer_b.update(cx_b, |buf, cx| buf.edit([(0..0, "world ")], None, cx));
-
-    buffer_b.read_with(cx_b, |buf, _| {
-        assert!(buf.is_dirty());
-        assert!(!buf.has_conflict());
-    });
-
-    project_b
-        .update(cx_b, |project, cx| {
-            project.save_buffer(buffer_b.clone(), cx)
-        })
-        .await
-        .unwrap();
-
-    buffer_b.read_with(cx_b, |buffer_b, _| assert!(!buffer_b.is_dirty()));
-
-    buffer_b.read_with(cx_b, |buf, _| {
-        assert!(!buf.has_conflict());
-    });
-
-    buffer_b.update(cx_b, |buf, cx| buf.edit([(0..0, "hello ")], None, cx));
+    client_a.fs().insert_tree("/a", json!({})).await;
+    client_b.fs().insert_tree("/b", json!({})).await;
 
-    buffer_b.read_with(cx_b, |buf, _| {
-        assert!(buf.is_dirty());
-        assert!(!buf.has_conflict());
-    });
-}
+    let (project_a, _) = client_a.build_local_project("/a", cx_a).await;
+    let (project_b, _) = client_b.build_local_project("/b", cx_b).await;
 
-#[gpui::test(iterations = 10)]
-async fn test_buffer_reloading(
-    executor: BackgroundExecutor,
-    cx_a: &mut TestAppContext,
-    cx_b: &mut TestAppContext,
-) {
-    let mut server = TestServer::start(executor.clone()).await;
-    let client_a = server.create_client(cx_a, "user_a").await;
-    let client_b = server.create_client(cx_b, "user_b").await;
     server
         .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
     let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
 
-    client_a
-        .fs()
-        .insert_tree(
-            "/dir",
-            json!({
-                "a.txt": "a\nb\nc",
-            }),
-        )
-        .await;
-    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
-    let project_id = active_call_a
+    let events_a = active_call_events(cx_a);
+    let events_b = active_call_events(cx_b);
+
+    let project_a_id = active_call_a
         .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
         .await
         .unwrap();
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    executor.run_until_parked();
+    assert_eq!(mem::take(&mut *events_a.borrow_mut()), vec![]);
+    assert_eq!(
+        mem::take(&mut *events_b.borrow_mut()),
+        vec![room::Event::RemoteProjectShared {
+            owner: Arc::new(User {
+                id: client_a.user_id().unwrap(),
+                github_login: "user_a".to_string(),
+                avatar_uri: "avatar_a".into(),
+                display_name: None,
+            }),
+            project_id: project_a_id,
+            worktree_root_names: vec!["a".to_string()],
+        }]
+    );
 
-    // Open a buffer as client B
-    let buffer_b = project_b
-        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+    let project_b_id = active_call_b
+        .update(cx_b, |call, cx| call.share_project(project_b.clone(), cx))
         .await
         .unwrap();
+    executor.run_until_parked();
+    assert_eq!(
+        mem::take(&mut *events_a.borrow_mut()),
+        vec![room::Event::RemoteProjectShared {
+            owner: Arc::new(User {
+                id: client_b.user_id().unwrap(),
+                github_login: "user_b".to_string(),
+                avatar_uri: "avatar_b".into(),
+                display_name: None,
+            }),
+            project_id: project_b_id,
+            worktree_root_names: vec!["b".to_string()]
+        }]
+    );
+    assert_eq!(mem::take(&mut *events_b.borrow_mut()), vec![]);
 
-    buffer_b.read_with(cx_b, |buf, _| {
-        assert!(!buf.is_dirty());
-        assert!(!buf.has_conflict());
-        assert_eq!(buf.line_ending(), LineEnding::Unix);
-    });
-
-    let new_contents = Rope::from("d\ne\nf");
-    client_a
-        .fs()
-        .save("/dir/a.txt".as_ref(), &new_contents, LineEnding::Windows)
+    // Sharing a project twice is idempotent.
+    let project_b_id_2 = active_call_b
+        .update(cx_b, |call, cx| call.share_project(project_b.clone(), cx))
         .await
         .unwrap();
-
+    assert_eq!(project_b_id_2, project_b_id);
     executor.run_until_parked();
+    assert_eq!(mem::take(&mut *events_a.borrow_mut()), vec![]);
+    assert_eq!(mem::take(&mut *events_b.borrow_mut()), vec![]);
+}
 
-    buffer_b.read_with(cx_b, |buf, _| {
-        assert_eq!(buf.text(), new_contents.to_string());
-        assert!(!buf.is_dirty());
-        assert!(!buf.has_conflict());
-        assert_eq!(buf.line_ending(), LineEnding::Windows);
+fn active_call_events(cx: &mut TestAppContext) -> Rc<RefCell<Vec<room::Event>>> {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let active_call = cx.read(ActiveCall::global);
+    cx.update({
+        let events = events.clone();
+        |cx| {
+            cx.subscribe(&active_call, move |_, event, _| {
+                events.borrow_mut().push(event.clone())
+            })
+            .detach()
+        }
     });
+    events
 }
 
-#[gpui::test(iterations = 10)]
-async fn test_editing_while_guest_opens_buffer(
+#[gpui::test]
+async fn test_mute_deafen(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+
     server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
         .await;
+
     let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
+    let active_call_c = cx_c.read(ActiveCall::global);
 
-    client_a
-        .fs()
-        .insert_tree("/dir", json!({ "a.txt": "a-contents" }))
-        .await;
-    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+    // User A calls user B, B answers.
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b.user_id().unwrap(), None, cx)
+        })
         .await
         .unwrap();
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
-
-    // Open a buffer as client A
-    let buffer_a = project_a
-        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+    executor.run_until_parked();
+    active_call_b
+        .update(cx_b, |call, cx| call.accept_incoming(cx))
         .await
         .unwrap();
-
-    // Start opening the same buffer as client B
-    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx));
-    let buffer_b = cx_b.executor().spawn(open_buffer);
-
-    // Edit the buffer as client A while client B is still opening it.
-    cx_b.executor().simulate_random_delay().await;
-    buffer_a.update(cx_a, |buf, cx| buf.edit([(0..0, "X")], None, cx));
-    cx_b.executor().simulate_random_delay().await;
-    buffer_a.update(cx_a, |buf, cx| buf.edit([(1..1, "Y")], None, cx));
-
-    let text = buffer_a.read_with(cx_a, |buf, _| buf.text());
-    let buffer_b = buffer_b.await.unwrap();
     executor.run_until_parked();
 
-    buffer_b.read_with(cx_b, |buf, _| assert_eq!(buf.text(), text));
-}
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
 
-#[gpui::test(iterations = 10)]
-async fn test_leaving_worktree_while_opening_buffer(
-    executor: BackgroundExecutor,
-    cx_a: &mut TestAppContext,
-    cx_b: &mut TestAppContext,
-) {
-    let mut server = TestServer::start(executor.clone()).await;
-    let client_a = server.create_client(cx_a, "user_a").await;
-    let client_b = server.create_client(cx_b, "user_b").await;
-    server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
-        .await;
-    let active_call_a = cx_a.read(ActiveCall::global);
+    room_a.read_with(cx_a, |room, _| assert!(!room.is_muted()));
+    room_b.read_with(cx_b, |room, _| assert!(!room.is_muted()));
 
-    client_a
-        .fs()
-        .insert_tree("/dir", json!({ "a.txt": "a-contents" }))
-        .await;
-    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
-        .await
-        .unwrap();
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    // Users A and B are both muted.
+    assert_eq!(
+        participant_audio_state(&room_a, cx_a),
+        &[ParticipantAudioState {
+            user_id: client_b.user_id().unwrap(),
+            is_muted: false,
+            audio_tracks_playing: vec![true],
+        }]
+    );
+    assert_eq!(
+        participant_audio_state(&room_b, cx_b),
+        &[ParticipantAudioState {
+            user_id: client_a.user_id().unwrap(),
+            is_muted: false,
+            audio_tracks_playing: vec![true],
+        }]
+    );
 
-    // See that a guest has joined as client A.
+    // User A mutes
+    room_a.update(cx_a, |room, cx| room.toggle_mute(cx));
     executor.run_until_parked();
 
-    project_a.read_with(cx_a, |p, _| assert_eq!(p.collaborators().len(), 1));
-
-    // Begin opening a buffer as client B, but leave the project before the open completes.
-    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx));
-    let buffer_b = cx_b.executor().spawn(open_buffer);
-    cx_b.update(|_| drop(project_b));
-    drop(buffer_b);
+    // User A hears user B, but B doesn't hear A.
+    room_a.read_with(cx_a, |room, _| assert!(room.is_muted()));
+    room_b.read_with(cx_b, |room, _| assert!(!room.is_muted()));
+    assert_eq!(
+        participant_audio_state(&room_a, cx_a),
+        &[ParticipantAudioState {
+            user_id: client_b.user_id().unwrap(),
+            is_muted: false,
+            audio_tracks_playing: vec![true],
+        }]
+    );
+    assert_eq!(
+        participant_audio_state(&room_b, cx_b),
+        &[ParticipantAudioState {
+            user_id: client_a.user_id().unwrap(),
+            is_muted: true,
+            audio_tracks_playing: vec![true],
+        }]
+    );
 
-    // See that the guest has left.
+    // User A deafens
+    room_a.update(cx_a, |room, cx| room.toggle_deafen(cx));
     executor.run_until_parked();
 
-    project_a.read_with(cx_a, |p, _| assert!(p.collaborators().is_empty()));
-}
-
-#[gpui::test(iterations = 10)]
-async fn test_canceling_buffer_opening(
-    executor: BackgroundExecutor,
-    cx_a: &mut TestAppContext,
-    cx_b: &mut TestAppContext,
-) {
-    let mut server = TestServer::start(executor.clone()).await;
-    let client_a = server.create_client(cx_a, "user_a").await;
-    let client_b = server.create_client(cx_b, "user_b").await;
-    server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
-        .await;
-    let active_call_a = cx_a.read(ActiveCall::global);
+    // User A does not hear user B.
+    room_a.read_with(cx_a, |room, _| assert!(room.is_muted()));
+    room_b.read_with(cx_b, |room, _| assert!(!room.is_muted()));
+    assert_eq!(
+        participant_audio_state(&room_a, cx_a),
+        &[ParticipantAudioState {
+            user_id: client_b.user_id().unwrap(),
+            is_muted: false,
+            audio_tracks_playing: vec![false],
+        }]
+    );
+    assert_eq!(
+        participant_audio_state(&room_b, cx_b),
+        &[ParticipantAudioState {
+            user_id: client_a.user_id().unwrap(),
+            is_muted: true,
+            audio_tracks_playing: vec![true],
+        }]
+    );
 
-    client_a
-        .fs()
-        .insert_tree(
-            "/dir",
-            json!({
-                "a.txt": "abc",
-            }),
-        )
-        .await;
-    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+    // User B calls user C, C joins.
+    active_call_b
+        .update(cx_b, |call, cx| {
+            call.invite(client_c.user_id().unwrap(), None, cx)
+        })
         .await
         .unwrap();
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
-
-    let buffer_a = project_a
-        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+    executor.run_until_parked();
+    active_call_c
+        .update(cx_c, |call, cx| call.accept_incoming(cx))
         .await
         .unwrap();
+    executor.run_until_parked();
 
-    // Open a buffer as client B but cancel after a random amount of time.
-    let buffer_b = project_b.update(cx_b, |p, cx| {
-        p.open_buffer_by_id(buffer_a.read_with(cx_a, |a, _| a.remote_id()), cx)
-    });
-    executor.simulate_random_delay().await;
-    drop(buffer_b);
+    // User A does not hear users B or C.
+    assert_eq!(
+        participant_audio_state(&room_a, cx_a),
+        &[
+            ParticipantAudioState {
+                user_id: client_b.user_id().unwrap(),
+                is_muted: false,
+                audio_tracks_playing: vec![false],
+            },
+            ParticipantAudioState {
+                user_id: client_c.user_id().unwrap(),
+                is_muted: false,
+                audio_tracks_playing: vec![false],
+            }
+        ]
+    );
+    assert_eq!(
+        participant_audio_state(&room_b, cx_b),
+        &[
+            ParticipantAudioState {
+                user_id: client_a.user_id().unwrap(),
+                is_muted: true,
+                audio_tracks_playing: vec![true],
+            },
+            ParticipantAudioState {
+                user_id: client_c.user_id().unwrap(),
+                is_muted: false,
+                audio_tracks_playing: vec![true],
+            }
+        ]
+    );
 
-    // Try opening the same buffer again as client B, and ensure we can
-    // still do it despite the cancellation above.
-    let buffer_b = project_b
-        .update(cx_b, |p, cx| {
-            p.open_buffer_by_id(buffer_a.read_with(cx_a, |a, _| a.remote_id()), cx)
-        })
-        .await
-        .unwrap();
+    #[derive(PartialEq, Eq, Debug)]
+    struct ParticipantAudioState {
+        user_id: u64,
+        is_muted: bool,
+        audio_tracks_playing: Vec<bool>,
+    }
 
-    buffer_b.read_with(cx_b, |buf, _| assert_eq!(buf.text(), "abc"));
+    fn participant_audio_state(
+        room: &Model<Room>,
+        cx: &TestAppContext,
+    ) -> Vec<ParticipantAudioState> {
+        room.read_with(cx, |room, _| {
+            room.remote_participants()
+                .iter()
+                .map(|(user_id, participant)| ParticipantAudioState {
+                    user_id: *user_id,
+                    is_muted: participant.muted,
+                    audio_tracks_playing: participant
+                        .audio_tracks
+                        .values()
+                        .map(|track| track.is_playing())
+                        .collect(),
+                })
+                .collect::<Vec<_>>()
+        })
+    }
 }
 
 #[gpui::test(iterations = 10)]
-async fn test_leaving_project(
+async fn test_room_location(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
-    cx_c: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
-    let client_c = server.create_client(cx_c, "user_c").await;
-    server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
-        .await;
-    let active_call_a = cx_a.read(ActiveCall::global);
-
-    client_a
-        .fs()
-        .insert_tree(
-            "/a",
-            json!({
-                "a.txt": "a-contents",
-                "b.txt": "b-contents",
-            }),
-        )
-        .await;
-    let (project_a, _) = client_a.build_local_project("/a", cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
-        .await
-        .unwrap();
-    let project_b1 = client_b.build_remote_project(project_id, cx_b).await;
-    let project_c = client_c.build_remote_project(project_id, cx_c).await;
-
-    // Client A sees that a guest has joined.
-    executor.run_until_parked();
+    client_a.fs().insert_tree("/a", json!({})).await;
+    client_b.fs().insert_tree("/b", json!({})).await;
 
-    project_a.read_with(cx_a, |project, _| {
-        assert_eq!(project.collaborators().len(), 2);
-    });
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
 
-    project_b1.read_with(cx_b, |project, _| {
-        assert_eq!(project.collaborators().len(), 2);
+    let a_notified = Rc::new(Cell::new(false));
+    cx_a.update({
+        let notified = a_notified.clone();
+        |cx| {
+            cx.observe(&active_call_a, move |_, _| notified.set(true))
+                .detach()
+        }
     });
 
-    project_c.read_with(cx_c, |project, _| {
-        assert_eq!(project.collaborators().len(), 2);
+    let b_notified = Rc::new(Cell::new(false));
+    cx_b.update({
+        let b_notified = b_notified.clone();
+        |cx| {
+            cx.observe(&active_call_b, move |_, _| b_notified.set(true))
+                .detach()
+        }
     });
 
-    // Client B opens a buffer.
-    let buffer_b1 = project_b1
-        .update(cx_b, |project, cx| {
-            let worktree_id = project.worktrees().next().unwrap().read(cx).id();
-            project.open_buffer((worktree_id, "a.txt"), cx)
-        })
+    let (project_a, _) = client_a.build_local_project("/a", cx_a).await;
+    active_call_a
+        .update(cx_a, |call, cx| call.set_location(Some(&project_a), cx))
         .await
         .unwrap();
+    let (project_b, _) = client_b.build_local_project("/b", cx_b).await;
 
-    buffer_b1.read_with(cx_b, |buffer, _| assert_eq!(buffer.text(), "a-contents"));
-
-    // Drop client B's project and ensure client A and client C observe client B leaving.
-    cx_b.update(|_| drop(project_b1));
-    executor.run_until_parked();
-
-    project_a.read_with(cx_a, |project, _| {
-        assert_eq!(project.collaborators().len(), 1);
-    });
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
 
-    project_c.read_with(cx_c, |project, _| {
-        assert_eq!(project.collaborators().len(), 1);
-    });
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
 
-    // Client B re-joins the project and can open buffers as before.
-    let project_b2 = client_b.build_remote_project(project_id, cx_b).await;
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
     executor.run_until_parked();
+    assert!(a_notified.take());
+    assert_eq!(
+        participant_locations(&room_a, cx_a),
+        vec![("user_b".to_string(), ParticipantLocation::External)]
+    );
+    assert!(b_notified.take());
+    assert_eq!(
+        participant_locations(&room_b, cx_b),
+        vec![("user_a".to_string(), ParticipantLocation::UnsharedProject)]
+    );
 
-    project_a.read_with(cx_a, |project, _| {
-        assert_eq!(project.collaborators().len(), 2);
-    });
-
-    project_b2.read_with(cx_b, |project, _| {
-        assert_eq!(project.collaborators().len(), 2);
-    });
-
-    project_c.read_with(cx_c, |project, _| {
-        assert_eq!(project.collaborators().len(), 2);
-    });
-
-    let buffer_b2 = project_b2
-        .update(cx_b, |project, cx| {
-            let worktree_id = project.worktrees().next().unwrap().read(cx).id();
-            project.open_buffer((worktree_id, "a.txt"), cx)
-        })
+    let project_a_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
         .await
         .unwrap();
+    executor.run_until_parked();
+    assert!(a_notified.take());
+    assert_eq!(
+        participant_locations(&room_a, cx_a),
+        vec![("user_b".to_string(), ParticipantLocation::External)]
+    );
+    assert!(b_notified.take());
+    assert_eq!(
+        participant_locations(&room_b, cx_b),
+        vec![(
+            "user_a".to_string(),
+            ParticipantLocation::SharedProject {
+                project_id: project_a_id
+            }
+        )]
+    );
 
-    buffer_b2.read_with(cx_b, |buffer, _| assert_eq!(buffer.text(), "a-contents"));
-
-    // Drop client B's connection and ensure client A and client C observe client B leaving.
-    client_b.disconnect(&cx_b.to_async());
-    executor.advance_clock(RECONNECT_TIMEOUT);
-
-    project_a.read_with(cx_a, |project, _| {
-        assert_eq!(project.collaborators().len(), 1);
-    });
-
-    project_b2.read_with(cx_b, |project, _| {
-        assert!(project.is_disconnected());
-    });
-
-    project_c.read_with(cx_c, |project, _| {
-        assert_eq!(project.collaborators().len(), 1);
-    });
-
-    // Client B can't join the project, unless they re-join the room.
-    cx_b.spawn(|cx| {
-        Project::remote(
-            project_id,
-            client_b.app_state.client.clone(),
-            client_b.user_store().clone(),
-            client_b.language_registry().clone(),
-            FakeFs::new(cx.background_executor().clone()),
-            ChannelRole::Member,
-            cx,
-        )
-    })
-    .await
-    .unwrap_err();
-
-    // Simulate connection loss for client C and ensure client A observes client C leaving the project.
-    client_c.wait_for_current_user(cx_c).await;
-    server.forbid_connections();
-    server.disconnect_client(client_c.peer_id().unwrap());
-    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
+    let project_b_id = active_call_b
+        .update(cx_b, |call, cx| call.share_project(project_b.clone(), cx))
+        .await
+        .unwrap();
     executor.run_until_parked();
+    assert!(a_notified.take());
+    assert_eq!(
+        participant_locations(&room_a, cx_a),
+        vec![("user_b".to_string(), ParticipantLocation::External)]
+    );
+    assert!(b_notified.take());
+    assert_eq!(
+        participant_locations(&room_b, cx_b),
+        vec![(
+            "user_a".to_string(),
+            ParticipantLocation::SharedProject {
+                project_id: project_a_id
+            }
+        )]
+    );
 
-    project_a.read_with(cx_a, |project, _| {
-        assert_eq!(project.collaborators().len(), 0);
-    });
+    active_call_b
+        .update(cx_b, |call, cx| call.set_location(Some(&project_b), cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert!(a_notified.take());
+    assert_eq!(
+        participant_locations(&room_a, cx_a),
+        vec![(
+            "user_b".to_string(),
+            ParticipantLocation::SharedProject {
+                project_id: project_b_id
+            }
+        )]
+    );
+    assert!(b_notified.take());
+    assert_eq!(
+        participant_locations(&room_b, cx_b),
+        vec![(
+            "user_a".to_string(),
+            ParticipantLocation::SharedProject {
+                project_id: project_a_id
+            }
+        )]
+    );
 
-    project_b2.read_with(cx_b, |project, _| {
-        assert!(project.is_disconnected());
-    });
+    active_call_b
+        .update(cx_b, |call, cx| call.set_location(None, cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert!(a_notified.take());
+    assert_eq!(
+        participant_locations(&room_a, cx_a),
+        vec![("user_b".to_string(), ParticipantLocation::External)]
+    );
+    assert!(b_notified.take());
+    assert_eq!(
+        participant_locations(&room_b, cx_b),
+        vec![(
+            "user_a".to_string(),
+            ParticipantLocation::SharedProject {
+                project_id: project_a_id
+            }
+        )]
+    );
 
-    project_c.read_with(cx_c, |project, _| {
-        assert!(project.is_disconnected());
-    });
+    fn participant_locations(
+        room: &Model<Room>,
+        cx: &TestAppContext,
+    ) -> Vec<(String, ParticipantLocation)> {
+        room.read_with(cx, |room, _| {
+            room.remote_participants()
+                .values()
+                .map(|participant| {
+                    (
+                        participant.user.github_login.to_string(),
+                        participant.location,
+                    )
+                })
+                .collect()
+        })
+    }
 }
 
 #[gpui::test(iterations = 10)]
-async fn test_collaborating_with_diagnostics(
+async fn test_propagate_saves_and_fs_changes(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
@@ -3780,13 +4853,13 @@ async fn test_collaborating_with_diagnostics(
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
     let client_c = server.create_client(cx_c, "user_c").await;
+
     server
         .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
         .await;
     let active_call_a = cx_a.read(ActiveCall::global);
 
-    // Set up a fake language server.
-    let mut language = Language::new(
+    let rust = Arc::new(Language::new(
         LanguageConfig {
             name: "Rust".into(),
             matcher: LanguageMatcher {
@@ -3796,1275 +4869,5248 @@ async fn test_collaborating_with_diagnostics(
             ..Default::default()
         },
         Some(tree_sitter_rust::language()),
-    );
-    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
-    client_a.language_registry().add(Arc::new(language));
+    ));
+    let javascript = Arc::new(Language::new(
+        LanguageConfig {
+            name: "JavaScript".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["js".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    ));
+    for client in [&client_a, &client_b, &client_c] {
+        client.language_registry().add(rust.clone());
+        client.language_registry().add(javascript.clone());
+    }
 
-    // Share a project as client A
     client_a
         .fs()
         .insert_tree(
             "/a",
             json!({
-                "a.rs": "let one = two",
-                "other.rs": "",
+                "file1.rs": "",
+                "file2": ""
             }),
         )
         .await;
     let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
 
-    // Cause the language server to start.
-    let _buffer = project_a
-        .update(cx_a, |project, cx| {
-            project.open_buffer(
-                ProjectPath {
-                    worktree_id,
-                    path: Path::new("other.rs").into(),
-                },
-                cx,
-            )
-        })
-        .await
-        .unwrap();
-
-    // Simulate a language server reporting errors for a file.
-    let mut fake_language_server = fake_language_servers.next().await.unwrap();
-    fake_language_server
-        .receive_notification::<lsp::notification::DidOpenTextDocument>()
-        .await;
-    fake_language_server.notify::<lsp::notification::PublishDiagnostics>(
-        lsp::PublishDiagnosticsParams {
-            uri: lsp::Url::from_file_path("/a/a.rs").unwrap(),
-            version: None,
-            diagnostics: vec![lsp::Diagnostic {
-                severity: Some(lsp::DiagnosticSeverity::WARNING),
-                range: lsp::Range::new(lsp::Position::new(0, 4), lsp::Position::new(0, 7)),
-                message: "message 0".to_string(),
-                ..Default::default()
-            }],
-        },
-    );
-
-    // Client A shares the project and, simultaneously, the language server
-    // publishes a diagnostic. This is done to ensure that the server always
-    // observes the latest diagnostics for a worktree.
+    let worktree_a = project_a.read_with(cx_a, |p, _| p.worktrees().next().unwrap());
     let project_id = active_call_a
         .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
         .await
         .unwrap();
-    fake_language_server.notify::<lsp::notification::PublishDiagnostics>(
-        lsp::PublishDiagnosticsParams {
-            uri: lsp::Url::from_file_path("/a/a.rs").unwrap(),
-            version: None,
-            diagnostics: vec![lsp::Diagnostic {
-                severity: Some(lsp::DiagnosticSeverity::ERROR),
-                range: lsp::Range::new(lsp::Position::new(0, 4), lsp::Position::new(0, 7)),
-                message: "message 1".to_string(),
-                ..Default::default()
-            }],
-        },
-    );
 
-    // Join the worktree as client B.
+    // Join that worktree as clients B and C.
     let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    let project_c = client_c.build_remote_project(project_id, cx_c).await;
 
-    // Wait for server to see the diagnostics update.
-    executor.run_until_parked();
+    let worktree_b = project_b.read_with(cx_b, |p, _| p.worktrees().next().unwrap());
 
-    // Ensure client B observes the new diagnostics.
+    let worktree_c = project_c.read_with(cx_c, |p, _| p.worktrees().next().unwrap());
 
-    project_b.read_with(cx_b, |project, cx| {
-        assert_eq!(
-            project.diagnostic_summaries(false, cx).collect::<Vec<_>>(),
-            &[(
-                ProjectPath {
-                    worktree_id,
-                    path: Arc::from(Path::new("a.rs")),
-                },
-                LanguageServerId(0),
-                DiagnosticSummary {
-                    error_count: 1,
-                    warning_count: 0,
-                    ..Default::default()
-                },
-            )]
-        )
+    // Open and edit a buffer as both guests B and C.
+    let buffer_b = project_b
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "file1.rs"), cx))
+        .await
+        .unwrap();
+    let buffer_c = project_c
+        .update(cx_c, |p, cx| p.open_buffer((worktree_id, "file1.rs"), cx))
+        .await
+        .unwrap();
+
+    buffer_b.read_with(cx_b, |buffer, _| {
+        assert_eq!(&*buffer.language().unwrap().name(), "Rust");
     });
 
-    // Join project as client C and observe the diagnostics.
-    let project_c = client_c.build_remote_project(project_id, cx_c).await;
-    executor.run_until_parked();
-    let project_c_diagnostic_summaries =
-        Rc::new(RefCell::new(project_c.read_with(cx_c, |project, cx| {
-            project.diagnostic_summaries(false, cx).collect::<Vec<_>>()
-        })));
-    project_c.update(cx_c, |_, cx| {
-        let summaries = project_c_diagnostic_summaries.clone();
-        cx.subscribe(&project_c, {
-            move |p, _, event, cx| {
-                if let project::Event::DiskBasedDiagnosticsFinished { .. } = event {
-                    *summaries.borrow_mut() = p.diagnostic_summaries(false, cx).collect();
-                }
-            }
-        })
-        .detach();
+    buffer_c.read_with(cx_c, |buffer, _| {
+        assert_eq!(&*buffer.language().unwrap().name(), "Rust");
     });
+    buffer_b.update(cx_b, |buf, cx| buf.edit([(0..0, "i-am-b, ")], None, cx));
+    buffer_c.update(cx_c, |buf, cx| buf.edit([(0..0, "i-am-c, ")], None, cx));
+
+    // Open and edit that buffer as the host.
+    let buffer_a = project_a
+        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "file1.rs"), cx))
+        .await
+        .unwrap();
 
     executor.run_until_parked();
-    assert_eq!(
-        project_c_diagnostic_summaries.borrow().as_slice(),
-        &[(
-            ProjectPath {
-                worktree_id,
-                path: Arc::from(Path::new("a.rs")),
-            },
-            LanguageServerId(0),
-            DiagnosticSummary {
-                error_count: 1,
-                warning_count: 0,
-                ..Default::default()
-            },
-        )]
-    );
 
-    // Simulate a language server reporting more errors for a file.
-    fake_language_server.notify::<lsp::notification::PublishDiagnostics>(
-        lsp::PublishDiagnosticsParams {
-            uri: lsp::Url::from_file_path("/a/a.rs").unwrap(),
-            version: None,
-            diagnostics: vec![
-                lsp::Diagnostic {
-                    severity: Some(lsp::DiagnosticSeverity::ERROR),
-                    range: lsp::Range::new(lsp::Position::new(0, 4), lsp::Position::new(0, 7)),
-                    message: "message 1".to_string(),
-                    ..Default::default()
-                },
-                lsp::Diagnostic {
-                    severity: Some(lsp::DiagnosticSeverity::WARNING),
-                    range: lsp::Range::new(lsp::Position::new(0, 10), lsp::Position::new(0, 13)),
-                    message: "message 2".to_string(),
-                    ..Default::default()
-                },
-            ],
-        },
-    );
+    buffer_a.read_with(cx_a, |buf, _| assert_eq!(buf.text(), "i-am-c, i-am-b, "));
+    buffer_a.update(cx_a, |buf, cx| {
+        buf.edit([(buf.len()..buf.len(), "i-am-a")], None, cx)
+    });
 
-    // Clients B and C get the updated summaries
     executor.run_until_parked();
 
-    project_b.read_with(cx_b, |project, cx| {
-        assert_eq!(
-            project.diagnostic_summaries(false, cx).collect::<Vec<_>>(),
-            [(
-                ProjectPath {
-                    worktree_id,
-                    path: Arc::from(Path::new("a.rs")),
-                },
-                LanguageServerId(0),
-                DiagnosticSummary {
-                    error_count: 1,
-                    warning_count: 1,
-                },
-            )]
-        );
+    buffer_a.read_with(cx_a, |buf, _| {
+        assert_eq!(buf.text(), "i-am-c, i-am-b, i-am-a");
     });
 
-    project_c.read_with(cx_c, |project, cx| {
-        assert_eq!(
-            project.diagnostic_summaries(false, cx).collect::<Vec<_>>(),
-            [(
-                ProjectPath {
-                    worktree_id,
-                    path: Arc::from(Path::new("a.rs")),
-                },
-                LanguageServerId(0),
-                DiagnosticSummary {
-                    error_count: 1,
-                    warning_count: 1,
-                },
-            )]
-        );
+    buffer_b.read_with(cx_b, |buf, _| {
+        assert_eq!(buf.text(), "i-am-c, i-am-b, i-am-a");
     });
 
-    // Open the file with the errors on client B. They should be present.
-    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx));
-    let buffer_b = cx_b.executor().spawn(open_buffer).await.unwrap();
-
-    buffer_b.read_with(cx_b, |buffer, _| {
-        assert_eq!(
-            buffer
-                .snapshot()
-                .diagnostics_in_range::<_, Point>(0..buffer.len(), false)
-                .collect::<Vec<_>>(),
-            &[
-                DiagnosticEntry {
-                    range: Point::new(0, 4)..Point::new(0, 7),
-                    diagnostic: Diagnostic {
-                        group_id: 2,
-                        message: "message 1".to_string(),
-                        severity: lsp::DiagnosticSeverity::ERROR,
-                        is_primary: true,
-                        ..Default::default()
-                    }
-                },
-                DiagnosticEntry {
-                    range: Point::new(0, 10)..Point::new(0, 13),
-                    diagnostic: Diagnostic {
-                        group_id: 3,
-                        severity: lsp::DiagnosticSeverity::WARNING,
-                        message: "message 2".to_string(),
-                        is_primary: true,
-                        ..Default::default()
-                    }
-                }
-            ]
-        );
+    buffer_c.read_with(cx_c, |buf, _| {
+        assert_eq!(buf.text(), "i-am-c, i-am-b, i-am-a");
     });
 
-    // Simulate a language server reporting no errors for a file.
-    fake_language_server.notify::<lsp::notification::PublishDiagnostics>(
-        lsp::PublishDiagnosticsParams {
-            uri: lsp::Url::from_file_path("/a/a.rs").unwrap(),
-            version: None,
-            diagnostics: vec![],
-        },
+    // Edit the buffer as the host and concurrently save as guest B.
+    let save_b = project_b.update(cx_b, |project, cx| {
+        project.save_buffer(buffer_b.clone(), cx)
+    });
+    buffer_a.update(cx_a, |buf, cx| buf.edit([(0..0, "hi-a, ")], None, cx));
+    save_b.await.unwrap();
+    assert_eq!(
+        client_a.fs().load("/a/file1.rs".as_ref()).await.unwrap(),
+        "hi-a, i-am-c, i-am-b, i-am-a"
     );
+
     executor.run_until_parked();
 
-    project_a.read_with(cx_a, |project, cx| {
+    buffer_a.read_with(cx_a, |buf, _| assert!(!buf.is_dirty()));
+
+    buffer_b.read_with(cx_b, |buf, _| assert!(!buf.is_dirty()));
+
+    buffer_c.read_with(cx_c, |buf, _| assert!(!buf.is_dirty()));
+
+    // Make changes on host's file system, see those changes on guest worktrees.
+    client_a
+        .fs()
+        .rename(
+            "/a/file1.rs".as_ref(),
+            "/a/file1.js".as_ref(),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+    client_a
+        .fs()
+        .rename("/a/file2".as_ref(), "/a/file3".as_ref(), Default::default())
+        .await
+        .unwrap();
+    client_a.fs().insert_file("/a/file4", "4".into()).await;
+    executor.run_until_parked();
+
+    worktree_a.read_with(cx_a, |tree, _| {
         assert_eq!(
-            project.diagnostic_summaries(false, cx).collect::<Vec<_>>(),
-            []
+            tree.paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            ["file1.js", "file3", "file4"]
         )
     });
 
-    project_b.read_with(cx_b, |project, cx| {
+    worktree_b.read_with(cx_b, |tree, _| {
         assert_eq!(
-            project.diagnostic_summaries(false, cx).collect::<Vec<_>>(),
-            []
+            tree.paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            ["file1.js", "file3", "file4"]
         )
     });
 
-    project_c.read_with(cx_c, |project, cx| {
+    worktree_c.read_with(cx_c, |tree, _| {
         assert_eq!(
-            project.diagnostic_summaries(false, cx).collect::<Vec<_>>(),
-            []
+            tree.paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            ["file1.js", "file3", "file4"]
         )
     });
-}
 
-#[gpui::test(iterations = 10)]
-async fn test_collaborating_with_lsp_progress_updates_and_diagnostics_ordering(
-    executor: BackgroundExecutor,
-    cx_a: &mut TestAppContext,
-    cx_b: &mut TestAppContext,
-) {
-    let mut server = TestServer::start(executor.clone()).await;
-    let client_a = server.create_client(cx_a, "user_a").await;
-    let client_b = server.create_client(cx_b, "user_b").await;
-    server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
-        .await;
+    // Ensure buffer files are updated as well.
 
-    // Set up a fake language server.
-    let mut language = Language::new(
-        LanguageConfig {
-            name: "Rust".into(),
-            matcher: LanguageMatcher {
-                path_suffixes: vec!["rs".to_string()],
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        Some(tree_sitter_rust::language()),
-    );
-    let mut fake_language_servers = language
-        .set_fake_lsp_adapter(Arc::new(FakeLspAdapter {
-            disk_based_diagnostics_progress_token: Some("the-disk-based-token".into()),
-            disk_based_diagnostics_sources: vec!["the-disk-based-diagnostics-source".into()],
-            ..Default::default()
-        }))
-        .await;
-    client_a.language_registry().add(Arc::new(language));
+    buffer_a.read_with(cx_a, |buffer, _| {
+        assert_eq!(buffer.file().unwrap().path().to_str(), Some("file1.js"));
+        assert_eq!(&*buffer.language().unwrap().name(), "JavaScript");
+    });
+
+    buffer_b.read_with(cx_b, |buffer, _| {
+        assert_eq!(buffer.file().unwrap().path().to_str(), Some("file1.js"));
+        assert_eq!(&*buffer.language().unwrap().name(), "JavaScript");
+    });
+
+    buffer_c.read_with(cx_c, |buffer, _| {
+        assert_eq!(buffer.file().unwrap().path().to_str(), Some("file1.js"));
+        assert_eq!(&*buffer.language().unwrap().name(), "JavaScript");
+    });
+
+    let new_buffer_a = project_a
+        .update(cx_a, |p, cx| p.create_buffer("", None, cx))
+        .unwrap();
+
+    let new_buffer_id = new_buffer_a.read_with(cx_a, |buffer, _| buffer.remote_id());
+    let new_buffer_b = project_b
+        .update(cx_b, |p, cx| p.open_buffer_by_id(new_buffer_id, cx))
+        .await
+        .unwrap();
+
+    new_buffer_b.read_with(cx_b, |buffer, _| {
+        assert!(buffer.file().is_none());
+    });
+
+    new_buffer_a.update(cx_a, |buffer, cx| {
+        buffer.edit([(0..0, "ok")], None, cx);
+    });
+    project_a
+        .update(cx_a, |project, cx| {
+            project.save_buffer_as(new_buffer_a.clone(), "/a/file3.rs".into(), cx)
+        })
+        .await
+        .unwrap();
+
+    executor.run_until_parked();
+
+    new_buffer_b.read_with(cx_b, |buffer_b, _| {
+        assert_eq!(
+            buffer_b.file().unwrap().path().as_ref(),
+            Path::new("file3.rs")
+        );
+
+        new_buffer_a.read_with(cx_a, |buffer_a, _| {
+            assert_eq!(buffer_b.saved_mtime(), buffer_a.saved_mtime());
+            assert_eq!(buffer_b.saved_version(), buffer_a.saved_version());
+        });
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_git_diff_base_change(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
 
-    let file_names = &["one.rs", "two.rs", "three.rs", "four.rs", "five.rs"];
     client_a
         .fs()
         .insert_tree(
-            "/test",
+            "/dir",
             json!({
-                "one.rs": "const ONE: usize = 1;",
-                "two.rs": "const TWO: usize = 2;",
-                "three.rs": "const THREE: usize = 3;",
-                "four.rs": "const FOUR: usize = 3;",
-                "five.rs": "const FIVE: usize = 3;",
+            ".git": {},
+            "sub": {
+                ".git": {},
+                "b.txt": "
+                    one
+                    two
+                    three
+                ".unindent(),
+            },
+            "a.txt": "
+                    one
+                    two
+                    three
+                ".unindent(),
             }),
         )
         .await;
 
-    let (project_a, worktree_id) = client_a.build_local_project("/test", cx_a).await;
-
-    // Share a project as client A
-    let active_call_a = cx_a.read(ActiveCall::global);
+    let (project_local, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
     let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .update(cx_a, |call, cx| {
+            call.share_project(project_local.clone(), cx)
+        })
         .await
         .unwrap();
 
-    // Join the project as client B and open all three files.
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
-    let guest_buffers = futures::future::try_join_all(file_names.iter().map(|file_name| {
-        project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, file_name), cx))
-    }))
-    .await
-    .unwrap();
+    let project_remote = client_b.build_remote_project(project_id, cx_b).await;
 
-    // Simulate a language server reporting errors for a file.
-    let fake_language_server = fake_language_servers.next().await.unwrap();
-    fake_language_server
-        .request::<lsp::request::WorkDoneProgressCreate>(lsp::WorkDoneProgressCreateParams {
-            token: lsp::NumberOrString::String("the-disk-based-token".to_string()),
-        })
+    let diff_base = "
+        one
+        three
+    "
+    .unindent();
+
+    let new_diff_base = "
+        one
+        two
+    "
+    .unindent();
+
+    client_a.fs().set_index_for_repo(
+        Path::new("/dir/.git"),
+        &[(Path::new("a.txt"), diff_base.clone())],
+    );
+
+    // Create the buffer
+    let buffer_local_a = project_local
+        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
         .await
         .unwrap();
-    fake_language_server.notify::<lsp::notification::Progress>(lsp::ProgressParams {
-        token: lsp::NumberOrString::String("the-disk-based-token".to_string()),
-        value: lsp::ProgressParamsValue::WorkDone(lsp::WorkDoneProgress::Begin(
-            lsp::WorkDoneProgressBegin {
-                title: "Progress Began".into(),
-                ..Default::default()
-            },
-        )),
+
+    // Wait for it to catch up to the new diff
+    executor.run_until_parked();
+
+    // Smoke test diffing
+
+    buffer_local_a.read_with(cx_a, |buffer, _| {
+        assert_eq!(buffer.diff_base(), Some(diff_base.as_ref()));
+        git::diff::assert_hunks(
+            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
+            &buffer,
+            &diff_base,
+            &[(1..2, "", "two\n")],
+        );
     });
-    for file_name in file_names {
-        fake_language_server.notify::<lsp::notification::PublishDiagnostics>(
-            lsp::PublishDiagnosticsParams {
-                uri: lsp::Url::from_file_path(Path::new("/test").join(file_name)).unwrap(),
-                version: None,
-                diagnostics: vec![lsp::Diagnostic {
-                    severity: Some(lsp::DiagnosticSeverity::WARNING),
-                    source: Some("the-disk-based-diagnostics-source".into()),
-                    range: lsp::Range::new(lsp::Position::new(0, 0), lsp::Position::new(0, 0)),
-                    message: "message one".to_string(),
-                    ..Default::default()
-                }],
-            },
+
+    // Create remote buffer
+    let buffer_remote_a = project_remote
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+        .await
+        .unwrap();
+
+    // Wait remote buffer to catch up to the new diff
+    executor.run_until_parked();
+
+    // Smoke test diffing
+
+    buffer_remote_a.read_with(cx_b, |buffer, _| {
+        assert_eq!(buffer.diff_base(), Some(diff_base.as_ref()));
+        git::diff::assert_hunks(
+            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
+            &buffer,
+            &diff_base,
+            &[(1..2, "", "two\n")],
         );
-    }
-    fake_language_server.notify::<lsp::notification::Progress>(lsp::ProgressParams {
-        token: lsp::NumberOrString::String("the-disk-based-token".to_string()),
-        value: lsp::ProgressParamsValue::WorkDone(lsp::WorkDoneProgress::End(
-            lsp::WorkDoneProgressEnd { message: None },
-        )),
     });
 
-    // When the "disk base diagnostics finished" message is received, the buffers'
-    // diagnostics are expected to be present.
-    let disk_based_diagnostics_finished = Arc::new(AtomicBool::new(false));
-    project_b.update(cx_b, {
-        let project_b = project_b.clone();
-        let disk_based_diagnostics_finished = disk_based_diagnostics_finished.clone();
-        move |_, cx| {
-            cx.subscribe(&project_b, move |_, _, event, cx| {
-                if let project::Event::DiskBasedDiagnosticsFinished { .. } = event {
-                    disk_based_diagnostics_finished.store(true, SeqCst);
-                    for buffer in &guest_buffers {
-                        assert_eq!(
-                            buffer
-                                .read(cx)
-                                .snapshot()
-                                .diagnostics_in_range::<_, usize>(0..5, false)
-                                .count(),
-                            1,
-                            "expected a diagnostic for buffer {:?}",
-                            buffer.read(cx).file().unwrap().path(),
-                        );
-                    }
-                }
-            })
-            .detach();
-        }
+    client_a.fs().set_index_for_repo(
+        Path::new("/dir/.git"),
+        &[(Path::new("a.txt"), new_diff_base.clone())],
+    );
+
+    // Wait for buffer_local_a to receive it
+    executor.run_until_parked();
+
+    // Smoke test new diffing
+
+    buffer_local_a.read_with(cx_a, |buffer, _| {
+        assert_eq!(buffer.diff_base(), Some(new_diff_base.as_ref()));
+
+        git::diff::assert_hunks(
+            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
+            &buffer,
+            &diff_base,
+            &[(2..3, "", "three\n")],
+        );
+    });
+
+    // Smoke test B
+
+    buffer_remote_a.read_with(cx_b, |buffer, _| {
+        assert_eq!(buffer.diff_base(), Some(new_diff_base.as_ref()));
+        git::diff::assert_hunks(
+            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
+            &buffer,
+            &diff_base,
+            &[(2..3, "", "three\n")],
+        );
     });
 
+    //Nested git dir
+
+    let diff_base = "
+        one
+        three
+    "
+    .unindent();
+
+    let new_diff_base = "
+        one
+        two
+    "
+    .unindent();
+
+    client_a.fs().set_index_for_repo(
+        Path::new("/dir/sub/.git"),
+        &[(Path::new("b.txt"), diff_base.clone())],
+    );
+
+    // Create the buffer
+    let buffer_local_b = project_local
+        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "sub/b.txt"), cx))
+        .await
+        .unwrap();
+
+    // Wait for it to catch up to the new diff
+    executor.run_until_parked();
+
+    // Smoke test diffing
+
+    buffer_local_b.read_with(cx_a, |buffer, _| {
+        assert_eq!(buffer.diff_base(), Some(diff_base.as_ref()));
+        git::diff::assert_hunks(
+            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
+            &buffer,
+            &diff_base,
+            &[(1..2, "", "two\n")],
+        );
+    });
+
+    // Create remote buffer
+    let buffer_remote_b = project_remote
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "sub/b.txt"), cx))
+        .await
+        .unwrap();
+
+    // Wait remote buffer to catch up to the new diff
+    executor.run_until_parked();
+
+    // Smoke test diffing
+
+    buffer_remote_b.read_with(cx_b, |buffer, _| {
+        assert_eq!(buffer.diff_base(), Some(diff_base.as_ref()));
+        git::diff::assert_hunks(
+            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
+            &buffer,
+            &diff_base,
+            &[(1..2, "", "two\n")],
+        );
+    });
+
+    client_a.fs().set_index_for_repo(
+        Path::new("/dir/sub/.git"),
+        &[(Path::new("b.txt"), new_diff_base.clone())],
+    );
+
+    // Wait for buffer_local_b to receive it
+    executor.run_until_parked();
+
+    // Smoke test new diffing
+
+    buffer_local_b.read_with(cx_a, |buffer, _| {
+        assert_eq!(buffer.diff_base(), Some(new_diff_base.as_ref()));
+        println!("{:?}", buffer.as_rope().to_string());
+        println!("{:?}", buffer.diff_base());
+        println!(
+            "{:?}",
+            buffer
+                .snapshot()
+                .git_diff_hunks_in_row_range(0..4)
+                .collect::<Vec<_>>()
+        );
+
+        git::diff::assert_hunks(
+            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
+            &buffer,
+            &diff_base,
+            &[(2..3, "", "three\n")],
+        );
+    });
+
+    // Smoke test B
+
+    buffer_remote_b.read_with(cx_b, |buffer, _| {
+        assert_eq!(buffer.diff_base(), Some(new_diff_base.as_ref()));
+        git::diff::assert_hunks(
+            buffer.snapshot().git_diff_hunks_in_row_range(0..4),
+            &buffer,
+            &diff_base,
+            &[(2..3, "", "three\n")],
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_git_branch_name(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/dir",
+            json!({
+            ".git": {},
+            }),
+        )
+        .await;
+
+    let (project_local, _worktree_id) = client_a.build_local_project("/dir", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| {
+            call.share_project(project_local.clone(), cx)
+        })
+        .await
+        .unwrap();
+
+    let project_remote = client_b.build_remote_project(project_id, cx_b).await;
+    client_a
+        .fs()
+        .set_branch_name(Path::new("/dir/.git"), Some("branch-1"));
+
+    // Wait for it to catch up to the new branch
+    executor.run_until_parked();
+
+    #[track_caller]
+    fn assert_branch(branch_name: Option<impl Into<String>>, project: &Project, cx: &AppContext) {
+        let branch_name = branch_name.map(Into::into);
+        let worktrees = project.visible_worktrees(cx).collect::<Vec<_>>();
+        assert_eq!(worktrees.len(), 1);
+        let worktree = worktrees[0].clone();
+        let root_entry = worktree.read(cx).snapshot().root_git_entry().unwrap();
+        assert_eq!(root_entry.branch(), branch_name.map(Into::into));
+    }
+
+    // Smoke test branch reading
+
+    project_local.read_with(cx_a, |project, cx| {
+        assert_branch(Some("branch-1"), project, cx)
+    });
+
+    project_remote.read_with(cx_b, |project, cx| {
+        assert_branch(Some("branch-1"), project, cx)
+    });
+
+    client_a
+        .fs()
+        .set_branch_name(Path::new("/dir/.git"), Some("branch-2"));
+
+    // Wait for buffer_local_a to receive it
+    executor.run_until_parked();
+
+    // Smoke test branch reading
+
+    project_local.read_with(cx_a, |project, cx| {
+        assert_branch(Some("branch-2"), project, cx)
+    });
+
+    project_remote.read_with(cx_b, |project, cx| {
+        assert_branch(Some("branch-2"), project, cx)
+    });
+
+    let project_remote_c = client_c.build_remote_project(project_id, cx_c).await;
+    executor.run_until_parked();
+
+    project_remote_c.read_with(cx_c, |project, cx| {
+        assert_branch(Some("branch-2"), project, cx)
+    });
+}
+
+#[gpui::test]
+async fn test_git_status_sync(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/dir",
+            json!({
+            ".git": {},
+            "a.txt": "a",
+            "b.txt": "b",
+            }),
+        )
+        .await;
+
+    const A_TXT: &'static str = "a.txt";
+    const B_TXT: &'static str = "b.txt";
+
+    client_a.fs().set_status_for_repo_via_git_operation(
+        Path::new("/dir/.git"),
+        &[
+            (&Path::new(A_TXT), GitFileStatus::Added),
+            (&Path::new(B_TXT), GitFileStatus::Added),
+        ],
+    );
+
+    let (project_local, _worktree_id) = client_a.build_local_project("/dir", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| {
+            call.share_project(project_local.clone(), cx)
+        })
+        .await
+        .unwrap();
+
+    let project_remote = client_b.build_remote_project(project_id, cx_b).await;
+
+    // Wait for it to catch up to the new status
+    executor.run_until_parked();
+
+    #[track_caller]
+    fn assert_status(
+        file: &impl AsRef<Path>,
+        status: Option<GitFileStatus>,
+        project: &Project,
+        cx: &AppContext,
+    ) {
+        let file = file.as_ref();
+        let worktrees = project.visible_worktrees(cx).collect::<Vec<_>>();
+        assert_eq!(worktrees.len(), 1);
+        let worktree = worktrees[0].clone();
+        let snapshot = worktree.read(cx).snapshot();
+        assert_eq!(snapshot.status_for_file(file), status);
+    }
+
+    // Smoke test status reading
+
+    project_local.read_with(cx_a, |project, cx| {
+        assert_status(&Path::new(A_TXT), Some(GitFileStatus::Added), project, cx);
+        assert_status(&Path::new(B_TXT), Some(GitFileStatus::Added), project, cx);
+    });
+
+    project_remote.read_with(cx_b, |project, cx| {
+        assert_status(&Path::new(A_TXT), Some(GitFileStatus::Added), project, cx);
+        assert_status(&Path::new(B_TXT), Some(GitFileStatus::Added), project, cx);
+    });
+
+    client_a.fs().set_status_for_repo_via_working_copy_change(
+        Path::new("/dir/.git"),
+        &[
+            (&Path::new(A_TXT), GitFileStatus::Modified),
+            (&Path::new(B_TXT), GitFileStatus::Modified),
+        ],
+    );
+
+    // Wait for buffer_local_a to receive it
+    executor.run_until_parked();
+
+    // Smoke test status reading
+
+    project_local.read_with(cx_a, |project, cx| {
+        assert_status(
+            &Path::new(A_TXT),
+            Some(GitFileStatus::Modified),
+            project,
+            cx,
+        );
+        assert_status(
+            &Path::new(B_TXT),
+            Some(GitFileStatus::Modified),
+            project,
+            cx,
+        );
+    });
+
+    project_remote.read_with(cx_b, |project, cx| {
+        assert_status(
+            &Path::new(A_TXT),
+            Some(GitFileStatus::Modified),
+            project,
+            cx,
+        );
+        assert_status(
+            &Path::new(B_TXT),
+            Some(GitFileStatus::Modified),
+            project,
+            cx,
+        );
+    });
+
+    // And synchronization while joining
+    let project_remote_c = client_c.build_remote_project(project_id, cx_c).await;
+    executor.run_until_parked();
+
+    project_remote_c.read_with(cx_c, |project, cx| {
+        assert_status(
+            &Path::new(A_TXT),
+            Some(GitFileStatus::Modified),
+            project,
+            cx,
+        );
+        assert_status(
+            &Path::new(B_TXT),
+            Some(GitFileStatus::Modified),
+            project,
+            cx,
+        );
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_fs_operations(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/dir",
+            json!({
+                "a.txt": "a-contents",
+                "b.txt": "b-contents",
+            }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    let worktree_a = project_a.read_with(cx_a, |project, _| project.worktrees().next().unwrap());
+
+    let worktree_b = project_b.read_with(cx_b, |project, _| project.worktrees().next().unwrap());
+
+    let entry = project_b
+        .update(cx_b, |project, cx| {
+            project.create_entry((worktree_id, "c.txt"), false, cx)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    worktree_a.read_with(cx_a, |worktree, _| {
+        assert_eq!(
+            worktree
+                .paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            ["a.txt", "b.txt", "c.txt"]
+        );
+    });
+
+    worktree_b.read_with(cx_b, |worktree, _| {
+        assert_eq!(
+            worktree
+                .paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            ["a.txt", "b.txt", "c.txt"]
+        );
+    });
+
+    project_b
+        .update(cx_b, |project, cx| {
+            project.rename_entry(entry.id, Path::new("d.txt"), cx)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    worktree_a.read_with(cx_a, |worktree, _| {
+        assert_eq!(
+            worktree
+                .paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            ["a.txt", "b.txt", "d.txt"]
+        );
+    });
+
+    worktree_b.read_with(cx_b, |worktree, _| {
+        assert_eq!(
+            worktree
+                .paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            ["a.txt", "b.txt", "d.txt"]
+        );
+    });
+
+    let dir_entry = project_b
+        .update(cx_b, |project, cx| {
+            project.create_entry((worktree_id, "DIR"), true, cx)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    worktree_a.read_with(cx_a, |worktree, _| {
+        assert_eq!(
+            worktree
+                .paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            ["DIR", "a.txt", "b.txt", "d.txt"]
+        );
+    });
+
+    worktree_b.read_with(cx_b, |worktree, _| {
+        assert_eq!(
+            worktree
+                .paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            ["DIR", "a.txt", "b.txt", "d.txt"]
+        );
+    });
+
+    project_b
+        .update(cx_b, |project, cx| {
+            project.create_entry((worktree_id, "DIR/e.txt"), false, cx)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    project_b
+        .update(cx_b, |project, cx| {
+            project.create_entry((worktree_id, "DIR/SUBDIR"), true, cx)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    project_b
+        .update(cx_b, |project, cx| {
+            project.create_entry((worktree_id, "DIR/SUBDIR/f.txt"), false, cx)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    worktree_a.read_with(cx_a, |worktree, _| {
+        assert_eq!(
+            worktree
+                .paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            [
+                "DIR",
+                "DIR/SUBDIR",
+                "DIR/SUBDIR/f.txt",
+                "DIR/e.txt",
+                "a.txt",
+                "b.txt",
+                "d.txt"
+            ]
+        );
+    });
+
+    worktree_b.read_with(cx_b, |worktree, _| {
+        assert_eq!(
+            worktree
+                .paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            [
+                "DIR",
+                "DIR/SUBDIR",
+                "DIR/SUBDIR/f.txt",
+                "DIR/e.txt",
+                "a.txt",
+                "b.txt",
+                "d.txt"
+            ]
+        );
+    });
+
+    project_b
+        .update(cx_b, |project, cx| {
+            project.copy_entry(entry.id, Path::new("f.txt"), cx)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    worktree_a.read_with(cx_a, |worktree, _| {
+        assert_eq!(
+            worktree
+                .paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            [
+                "DIR",
+                "DIR/SUBDIR",
+                "DIR/SUBDIR/f.txt",
+                "DIR/e.txt",
+                "a.txt",
+                "b.txt",
+                "d.txt",
+                "f.txt"
+            ]
+        );
+    });
+
+    worktree_b.read_with(cx_b, |worktree, _| {
+        assert_eq!(
+            worktree
+                .paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            [
+                "DIR",
+                "DIR/SUBDIR",
+                "DIR/SUBDIR/f.txt",
+                "DIR/e.txt",
+                "a.txt",
+                "b.txt",
+                "d.txt",
+                "f.txt"
+            ]
+        );
+    });
+
+    project_b
+        .update(cx_b, |project, cx| {
+            project.delete_entry(dir_entry.id, cx).unwrap()
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+
+    worktree_a.read_with(cx_a, |worktree, _| {
+        assert_eq!(
+            worktree
+                .paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            ["a.txt", "b.txt", "d.txt", "f.txt"]
+        );
+    });
+
+    worktree_b.read_with(cx_b, |worktree, _| {
+        assert_eq!(
+            worktree
+                .paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            ["a.txt", "b.txt", "d.txt", "f.txt"]
+        );
+    });
+
+    project_b
+        .update(cx_b, |project, cx| {
+            project.delete_entry(entry.id, cx).unwrap()
+        })
+        .await
+        .unwrap();
+
+    worktree_a.read_with(cx_a, |worktree, _| {
+        assert_eq!(
+            worktree
+                .paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            ["a.txt", "b.txt", "f.txt"]
+        );
+    });
+
+    worktree_b.read_with(cx_b, |worktree, _| {
+        assert_eq!(
+            worktree
+                .paths()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            ["a.txt", "b.txt", "f.txt"]
+        );
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_local_settings(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    // As client A, open a project that contains some local settings files
+    client_a
+        .fs()
+        .insert_tree(
+            "/dir",
+            json!({
+                ".zed": {
+                    "settings.json": r#"{ "tab_size": 2 }"#
+                },
+                "a": {
+                    ".zed": {
+                        "settings.json": r#"{ "tab_size": 8 }"#
+                    },
+                    "a.txt": "a-contents",
+                },
+                "b": {
+                    "b.txt": "b-contents",
+                }
+            }),
+        )
+        .await;
+    let (project_a, _) = client_a.build_local_project("/dir", cx_a).await;
+    executor.run_until_parked();
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+
+    // As client B, join that project and observe the local settings.
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    let worktree_b = project_b.read_with(cx_b, |project, _| project.worktrees().next().unwrap());
+    executor.run_until_parked();
+    cx_b.read(|cx| {
+        let store = cx.global::<SettingsStore>();
+        assert_eq!(
+            store
+                .local_settings(worktree_b.read(cx).id().to_usize())
+                .collect::<Vec<_>>(),
+            &[
+                (Path::new("").into(), r#"{"tab_size":2}"#.to_string()),
+                (Path::new("a").into(), r#"{"tab_size":8}"#.to_string()),
+            ]
+        )
+    });
+
+    // As client A, update a settings file. As Client B, see the changed settings.
+    client_a
+        .fs()
+        .insert_file("/dir/.zed/settings.json", r#"{}"#.into())
+        .await;
+    executor.run_until_parked();
+    cx_b.read(|cx| {
+        let store = cx.global::<SettingsStore>();
+        assert_eq!(
+            store
+                .local_settings(worktree_b.read(cx).id().to_usize())
+                .collect::<Vec<_>>(),
+            &[
+                (Path::new("").into(), r#"{}"#.to_string()),
+                (Path::new("a").into(), r#"{"tab_size":8}"#.to_string()),
+            ]
+        )
+    });
+
+    // As client A, create and remove some settings files. As client B, see the changed settings.
+    client_a
+        .fs()
+        .remove_file("/dir/.zed/settings.json".as_ref(), Default::default())
+        .await
+        .unwrap();
+    client_a
+        .fs()
+        .create_dir("/dir/b/.zed".as_ref())
+        .await
+        .unwrap();
+    client_a
+        .fs()
+        .insert_file("/dir/b/.zed/settings.json", r#"{"tab_size": 4}"#.into())
+        .await;
+    executor.run_until_parked();
+    cx_b.read(|cx| {
+        let store = cx.global::<SettingsStore>();
+        assert_eq!(
+            store
+                .local_settings(worktree_b.read(cx).id().to_usize())
+                .collect::<Vec<_>>(),
+            &[
+                (Path::new("a").into(), r#"{"tab_size":8}"#.to_string()),
+                (Path::new("b").into(), r#"{"tab_size":4}"#.to_string()),
+            ]
+        )
+    });
+
+    // As client B, disconnect.
+    server.forbid_connections();
+    server.disconnect_client(client_b.peer_id().unwrap());
+
+    // As client A, change and remove settings files while client B is disconnected.
+    client_a
+        .fs()
+        .insert_file("/dir/a/.zed/settings.json", r#"{"hard_tabs":true}"#.into())
+        .await;
+    client_a
+        .fs()
+        .remove_file("/dir/b/.zed/settings.json".as_ref(), Default::default())
+        .await
+        .unwrap();
+    executor.run_until_parked();
+
+    // As client B, reconnect and see the changed settings.
+    server.allow_connections();
+    executor.advance_clock(RECEIVE_TIMEOUT);
+    cx_b.read(|cx| {
+        let store = cx.global::<SettingsStore>();
+        assert_eq!(
+            store
+                .local_settings(worktree_b.read(cx).id().to_usize())
+                .collect::<Vec<_>>(),
+            &[(Path::new("a").into(), r#"{"hard_tabs":true}"#.to_string()),]
+        )
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_buffer_conflict_after_save(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/dir",
+            json!({
+                "a.txt": "a-contents",
+            }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    // Open a buffer as client B
+    let buffer_b = project_b
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+        .await
+        .unwrap();
+
+    buffer_b.update(cx_b, |buf, cx| buf.edit([(0..0, "world ")], None, cx));
+
+    buffer_b.read_with(cx_b, |buf, _| {
+        assert!(buf.is_dirty());
+        assert!(!buf.has_conflict());
+    });
+
+    project_b
+        .update(cx_b, |project, cx| {
+            project.save_buffer(buffer_b.clone(), cx)
+        })
+        .await
+        .unwrap();
+
+    buffer_b.read_with(cx_b, |buffer_b, _| assert!(!buffer_b.is_dirty()));
+
+    buffer_b.read_with(cx_b, |buf, _| {
+        assert!(!buf.has_conflict());
+    });
+
+    buffer_b.update(cx_b, |buf, cx| buf.edit([(0..0, "hello ")], None, cx));
+
+    buffer_b.read_with(cx_b, |buf, _| {
+        assert!(buf.is_dirty());
+        assert!(!buf.has_conflict());
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_buffer_reloading(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/dir",
+            json!({
+                "a.txt": "a\nb\nc",
+            }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    // Open a buffer as client B
+    let buffer_b = project_b
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+        .await
+        .unwrap();
+
+    buffer_b.read_with(cx_b, |buf, _| {
+        assert!(!buf.is_dirty());
+        assert!(!buf.has_conflict());
+        assert_eq!(buf.line_ending(), LineEnding::Unix);
+    });
+
+    let new_contents = Rope::from("d\ne\nf");
+    client_a
+        .fs()
+        .save("/dir/a.txt".as_ref(), &new_contents, LineEnding::Windows)
+        .await
+        .unwrap();
+
+    executor.run_until_parked();
+
+    buffer_b.read_with(cx_b, |buf, _| {
+        assert_eq!(buf.text(), new_contents.to_string());
+        assert!(!buf.is_dirty());
+        assert!(!buf.has_conflict());
+        assert_eq!(buf.line_ending(), LineEnding::Windows);
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_shared_bookmarks(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree("/dir", json!({ "a.txt": "one\ntwo\nthree" }))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    executor.run_until_parked();
+
+    // Client B bookmarks text it has just typed, before the host has seen it.
+    let buffer_b = project_b
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+        .await
+        .unwrap();
+    let anchor_b = buffer_b.update(cx_b, |buffer, cx| {
+        buffer.edit([(0..0, "zero\n")], None, cx);
+        buffer.anchor_before(Point::new(0, 2))
+    });
+    let bookmark = project_b
+        .update(cx_b, |project, cx| {
+            project.add_bookmark("  ".into(), &buffer_b, anchor_b, cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(bookmark.id, 1);
+    assert_eq!(bookmark.name, "1");
+    assert_eq!(bookmark.author_user_id, client_b.user_id());
+
+    // The host adds a named bookmark of its own.
+    let buffer_a = project_a.read_with(cx_a, |project, _| {
+        project.buffer_for_id(bookmark.buffer_id).unwrap()
+    });
+    let anchor_a = buffer_a.read_with(cx_a, |buffer, _| buffer.anchor_after(Point::new(2, 0)));
+    project_a
+        .update(cx_a, |project, cx| {
+            project.add_bookmark("agenda".into(), &buffer_a, anchor_a, cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+
+    let bookmark_names = |project: &Model<Project>, cx: &TestAppContext| {
+        project.read_with(cx, |project, _| {
+            project
+                .bookmarks()
+                .iter()
+                .map(|bookmark| (bookmark.id, bookmark.name.clone()))
+                .collect::<Vec<_>>()
+        })
+    };
+    let expected = vec![(1, "1".to_string()), (2, "agenda".to_string())];
+    assert_eq!(bookmark_names(&project_a, cx_a), expected);
+    assert_eq!(bookmark_names(&project_b, cx_b), expected);
+
+    // Client C joins later, receives the existing bookmarks, and jumps to one
+    // without having opened the buffer first.
+    let project_c = client_c.build_remote_project(project_id, cx_c).await;
+    executor.run_until_parked();
+    assert_eq!(bookmark_names(&project_c, cx_c), expected);
+    let location = project_c
+        .update(cx_c, |project, cx| project.open_bookmark(1, cx))
+        .await
+        .unwrap();
+    location.buffer.read_with(cx_c, |buffer, _| {
+        assert_eq!(buffer.text(), "zero\none\ntwo\nthree");
+        assert_eq!(
+            buffer.summary_for_anchor::<Point>(&location.range.start),
+            Point::new(0, 2)
+        );
+    });
+
+    // Client B loses connection, and a bookmark is removed in the meantime.
+    server.forbid_connections();
+    server.disconnect_client(client_b.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT);
+    project_c
+        .update(cx_c, |project, cx| project.remove_bookmark(2, cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    let expected = vec![(1, "1".to_string())];
+    assert_eq!(bookmark_names(&project_a, cx_a), expected);
+    assert_eq!(bookmark_names(&project_c, cx_c), expected);
+
+    // After reconnecting, client B catches up with the host's bookmarks.
+    server.allow_connections();
+    executor.advance_clock(RECEIVE_TIMEOUT);
+    executor.run_until_parked();
+    assert_eq!(bookmark_names(&project_b, cx_b), expected);
+
+    // The host keeps the bookmarked buffer open once nothing else uses it, even
+    // after unsharing the project.
+    project_a.update(cx_a, |project, cx| project.unshare(cx).unwrap());
+    drop(buffer_a);
+    executor.run_until_parked();
+    let location = project_a
+        .update(cx_a, |project, cx| project.open_bookmark(1, cx))
+        .await
+        .unwrap();
+    location.buffer.read_with(cx_a, |buffer, _| {
+        assert_eq!(
+            buffer.summary_for_anchor::<Point>(&location.range.start),
+            Point::new(0, 2)
+        );
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_entry_decorations(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    // Only the host knows about Rust.
+    client_a.language_registry().add(Arc::new(Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["rs".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        None,
+    )));
+    client_a
+        .fs()
+        .insert_tree(
+            "/dir",
+            json!({
+                ".env": "SECRET=1",
+                "main.rs": "fn main() {}",
+                "main.gleam": "pub fn main() {}",
+                "src": {},
+                "tests": {
+                    "a.rs": "",
+                    "b.rs": "",
+                    "c.rs": "",
+                },
+            }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    executor.run_until_parked();
+
+    let decoration = |project: &Model<Project>, path: &str, cx: &TestAppContext| {
+        project.read_with(cx, |project, cx| {
+            let entry_id = project.entry_for_path(&(worktree_id, path).into(), cx)?.id;
+            project.entry_decoration(worktree_id, entry_id, cx)
+        })
+    };
+    let rust = Some(EntryDecoration {
+        language: Some("Rust".into()),
+        is_private: false,
+    });
+    let private = Some(EntryDecoration {
+        language: None,
+        is_private: true,
+    });
+    // Guests fetch the decorations a chunk at a time.
+    assert_eq!(decoration(&project_b, "main.rs", cx_b), rust);
+    assert_eq!(decoration(&project_b, ".env", cx_b), private);
+    assert_eq!(decoration(&project_b, "tests/a.rs", cx_b), rust);
+    assert_eq!(decoration(&project_b, "tests/c.rs", cx_b), rust);
+    assert_eq!(decoration(&project_b, "main.gleam", cx_b), None);
+    assert_eq!(decoration(&project_b, "src", cx_b), None);
+
+    // Guests are sent the decorations of new entries.
+    client_a
+        .fs()
+        .insert_file("/dir/src/lib.rs", "".into())
+        .await;
+    executor.run_until_parked();
+    assert_eq!(decoration(&project_b, "src/lib.rs", cx_b), rust);
+
+    // When the host learns a new language, the decorations are detected again
+    // once the languages stop changing.
+    client_a.language_registry().add(Arc::new(Language::new(
+        LanguageConfig {
+            name: "Gleam".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["gleam".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        None,
+    )));
+    executor.run_until_parked();
+    assert_eq!(decoration(&project_b, "main.gleam", cx_b), None);
+    executor.advance_clock(Duration::from_secs(1));
+    let gleam = Some(EntryDecoration {
+        language: Some("Gleam".into()),
+        is_private: false,
+    });
+    assert_eq!(decoration(&project_a, "main.gleam", cx_a), gleam);
+    assert_eq!(decoration(&project_b, "main.gleam", cx_b), gleam);
+
+    // Renaming a file detects its decoration again.
+    project_a
+        .update(cx_a, |project, cx| {
+            project.rename_entry(
+                project
+                    .entry_for_path(&(worktree_id, "main.rs").into(), cx)
+                    .unwrap()
+                    .id,
+                Path::new("main.txt"),
+                cx,
+            )
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert_eq!(decoration(&project_b, "main.txt", cx_b), None);
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_guest_annotations(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree("/notes", json!({ "a.txt": "one\ntwo\nthree" }))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/notes", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    let project_c = client_c.build_remote_project(project_id, cx_c).await;
+    executor.run_until_parked();
+
+    // Client B annotates a line, which no one else sees.
+    let buffer_b = project_b
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+        .await
+        .unwrap();
+    let range = buffer_b.read_with(cx_b, |buffer, _| {
+        buffer.anchor_after(Point::new(1, 0))..buffer.anchor_before(Point::new(1, 3))
+    });
+    let annotation = project_b.update(cx_b, |project, cx| {
+        project.add_annotation(&buffer_b, range, "check this".into(), cx)
+    });
+    executor.run_until_parked();
+    assert_eq!(
+        project_b.read_with(cx_b, |project, _| project.annotations().to_vec()),
+        [annotation.clone()]
+    );
+    project_a.read_with(cx_a, |project, _| assert!(project.annotations().is_empty()));
+    project_c.read_with(cx_c, |project, _| assert!(project.annotations().is_empty()));
+
+    // While client B is disconnected, the host edits before the annotated line.
+    server.forbid_connections();
+    server.disconnect_client(client_b.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT);
+    let buffer_a = project_a.read_with(cx_a, |project, _| {
+        project.buffer_for_id(annotation.buffer_id).unwrap()
+    });
+    buffer_a.update(cx_a, |buffer, cx| buffer.edit([(0..0, "zero\n")], None, cx));
+    executor.run_until_parked();
+
+    // After the buffer is resynchronized, the annotation still covers the same text.
+    server.allow_connections();
+    executor.advance_clock(RECEIVE_TIMEOUT);
+    executor.run_until_parked();
+    let location = project_b
+        .update(cx_b, |project, cx| {
+            project.open_annotation(annotation.id, cx)
+        })
+        .await
+        .unwrap();
+    location.buffer.read_with(cx_b, |buffer, _| {
+        assert_eq!(buffer.text(), "zero\none\ntwo\nthree");
+        let range = location.range.to_point(buffer);
+        assert_eq!(range, Point::new(2, 0)..Point::new(2, 3));
+    });
+
+    project_b.update(cx_b, |project, cx| {
+        project.remove_annotation(annotation.id, cx)
+    });
+    project_b.read_with(cx_b, |project, _| assert!(project.annotations().is_empty()));
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_shared_review(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    let text = "one\nTWO\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\nELEVEN\ntwelve\n";
+    let diff_base = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\neleven\ntwelve\n";
+    client_a
+        .fs()
+        .insert_tree(
+            "/dir",
+            json!({ ".git": {}, "a.txt": text, "b.txt": "unchanged\n" }),
+        )
+        .await;
+    client_a.fs().set_index_for_repo(
+        Path::new("/dir/.git"),
+        &[
+            (Path::new("a.txt"), diff_base.to_string()),
+            (Path::new("b.txt"), "unchanged\n".to_string()),
+        ],
+    );
+    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+
+    // The host starts reviewing the changed hunks of a couple of buffers.
+    let mut buffers_a = Vec::new();
+    for path in ["a.txt", "b.txt"] {
+        buffers_a.push(
+            project_a
+                .update(cx_a, |p, cx| p.open_buffer((worktree_id, path), cx))
+                .await
+                .unwrap(),
+        );
+    }
+    executor.run_until_parked();
+    project_a
+        .update(cx_a, |project, cx| {
+            project.start_review(buffers_a.clone(), cx)
+        })
+        .await
+        .unwrap();
+
+    // A guest who joins afterwards sees the same excerpts.
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    executor.run_until_parked();
+    let locations = project_b
+        .update(cx_b, |project, cx| project.open_review_excerpts(cx))
+        .await
+        .unwrap();
+    let excerpt_texts = locations
+        .iter()
+        .map(|location| {
+            location.buffer.read_with(cx_b, |buffer, _| {
+                buffer
+                    .text_for_range(location.range.clone())
+                    .collect::<String>()
+            })
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        excerpt_texts,
+        [
+            "one\nTWO\nthree\nfour\nfive",
+            "eight\nnine\nten\nELEVEN\ntwelve\n"
+        ]
+    );
+
+    // The guest suggests an edit, which the host sees along with its author.
+    let buffer_b = locations[0].buffer.clone();
+    let range = buffer_b.read_with(cx_b, |buffer, _| {
+        buffer.anchor_before(Point::new(1, 0))..buffer.anchor_after(Point::new(1, 3))
+    });
+    let comment = project_b
+        .update(cx_b, |project, cx| {
+            project.add_review_comment(
+                &buffer_b,
+                range,
+                "should be lowercase".into(),
+                Some("two".into()),
+                cx,
+            )
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert_eq!(comment.author_user_id, client_b.user_id());
+    project_a.read_with(cx_a, |project, _| {
+        assert_eq!(project.review().unwrap().comments, [comment.clone()]);
+    });
+
+    // Applying the suggestion edits the buffer for everyone and resolves the comment.
+    project_b
+        .update(cx_b, |project, cx| {
+            project.apply_review_suggestion(comment.id, cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    buffers_a[0].read_with(cx_a, |buffer, _| {
+        assert_eq!(
+            buffer.text(),
+            "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\nELEVEN\ntwelve\n"
+        );
+    });
+    project_a.read_with(cx_a, |project, _| {
+        assert!(project.review().unwrap().comments.is_empty());
+    });
+    project_b.read_with(cx_b, |project, _| {
+        assert!(project.review().unwrap().comments.is_empty());
+    });
+
+    // Once the host ends the review, the guest no longer sees it.
+    project_a.update(cx_a, |project, cx| project.end_review(cx));
+    executor.run_until_parked();
+    project_b.read_with(cx_b, |project, _| assert!(project.review().is_none()));
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_share_project_from_template(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            PROJECT_TEMPLATES_DIR.join("interview"),
+            json!({
+                "README.md": "# Reverse a linked list",
+                "src": { "main.rs": "fn main() {}" },
+            }),
+        )
+        .await;
+    let project_a = client_a.build_empty_local_project(cx_a);
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| {
+            call.share_project_from_template(project_a.clone(), "interview", "/interview", cx)
+        })
+        .await
+        .unwrap();
+
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    executor.run_until_parked();
+    project_b.read_with(cx_b, |project, cx| {
+        let worktree = project.worktrees().next().unwrap();
+        assert_eq!(worktree.read(cx).root_name(), "interview");
+        assert_eq!(
+            worktree
+                .read(cx)
+                .paths()
+                .map(|path| path.to_str().unwrap())
+                .collect::<Vec<_>>(),
+            ["README.md", "src", "src/main.rs"]
+        );
+    });
+
+    let buffer_b = project_b
+        .update(cx_b, |project, cx| {
+            let worktree_id = project.worktrees().next().unwrap().read(cx).id();
+            project.open_buffer((worktree_id, "src/main.rs"), cx)
+        })
+        .await
+        .unwrap();
+    buffer_b.read_with(cx_b, |buffer, _| assert_eq!(buffer.text(), "fn main() {}"));
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_room_scratch_project(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
+
+    let room_id = active_call_a.read_with(cx_a, |call, cx| call.room().unwrap().read(cx).id());
+    let scratch_dir = SCRATCH_PROJECTS_DIR.join(room_id.to_string());
+    let scratch_path = scratch_dir.join("scratch");
+
+    // The room forgets about scratch projects that fail to be shared.
+    client_a
+        .fs()
+        .create_dir(&SCRATCH_PROJECTS_DIR)
+        .await
+        .unwrap();
+    client_a.fs().insert_file(&scratch_dir, String::new()).await;
+    let project_a = client_a.build_empty_local_project(cx_a);
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.share_scratch_project(project_a.clone(), cx)
+        })
+        .await
+        .unwrap_err();
+    active_call_a.read_with(cx_a, |call, cx| {
+        assert!(!call.room().unwrap().read(cx).is_scratch_project(&project_a))
+    });
+    executor.run_until_parked();
+
+    // Files left behind by an earlier scratch project aren't shared again.
+    client_a
+        .fs()
+        .remove_file(&scratch_dir, Default::default())
+        .await
+        .unwrap();
+    client_a
+        .fs()
+        .insert_tree(&scratch_path, json!({ "stale.rs": "" }))
+        .await;
+    let project_a = client_a.build_empty_local_project(cx_a);
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| {
+            call.share_scratch_project(project_a.clone(), cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert!(client_a
+        .fs()
+        .metadata(&scratch_path.join("stale.rs"))
+        .await
+        .unwrap()
+        .is_none());
+
+    // A room has one scratch project at a time.
+    let other_project_a = client_a.build_empty_local_project(cx_a);
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.share_scratch_project(other_project_a.clone(), cx)
+        })
+        .await
+        .unwrap_err();
+
+    // The scratch project is listed among the host's projects, like any other.
+    active_call_b.read_with(cx_b, |call, cx| {
+        let room = call.room().unwrap().read(cx);
+        let host = room.remote_participants().values().next().unwrap();
+        assert_eq!(host.projects.len(), 1);
+        assert_eq!(host.projects[0].id, project_id);
+        assert_eq!(host.projects[0].worktree_root_names, ["scratch"]);
+    });
+    active_call_a.read_with(cx_a, |call, cx| {
+        assert!(call.room().unwrap().read(cx).is_scratch_project(&project_a))
+    });
+
+    // Guests can write to it.
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    let worktree_id = project_b.read_with(cx_b, |project, cx| {
+        project.worktrees().next().unwrap().read(cx).id()
+    });
+    project_b
+        .update(cx_b, |project, cx| {
+            project.create_entry((worktree_id, "main.rs"), false, cx)
+        })
+        .await
+        .unwrap();
+    let buffer_b = project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
+        })
+        .await
+        .unwrap();
+    buffer_b.update(cx_b, |buffer, cx| {
+        buffer.edit([(0..0, "fn main() {}")], None, cx)
+    });
+    project_b
+        .update(cx_b, |project, cx| {
+            project.save_buffer(buffer_b.clone(), cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        client_a
+            .fs()
+            .load(&scratch_path.join("main.rs"))
+            .await
+            .unwrap(),
+        "fn main() {}"
+    );
+
+    // The scratch project's files are deleted once it's unshared, so a new one
+    // starts out empty.
+    active_call_a
+        .update(cx_a, |call, cx| call.unshare_project(project_a.clone(), cx))
+        .unwrap();
+    executor.run_until_parked();
+    assert!(client_a
+        .fs()
+        .metadata(&scratch_path)
+        .await
+        .unwrap()
+        .is_none());
+    let project_a = client_a.build_empty_local_project(cx_a);
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.share_scratch_project(project_a.clone(), cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert!(client_a
+        .fs()
+        .metadata(&scratch_path.join("main.rs"))
+        .await
+        .unwrap()
+        .is_none());
+
+    // The scratch project's files are deleted once the host leaves the room.
+    active_call_a
+        .update(cx_a, |call, cx| call.hang_up(cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert!(client_a
+        .fs()
+        .metadata(&scratch_path)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_bandwidth_budget_for_initial_share(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    const FILE_COUNT: usize = 200;
+    const FILE_LEN: usize = 4096;
+    // Generous upper bounds on the encoded size of a worktree entry, and on the
+    // overhead of replicating a buffer beyond its contents.
+    const ENTRY_BUDGET: u64 = 128;
+    const BUFFER_OVERHEAD_BUDGET: u64 = 1024;
+
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    let files = (0..FILE_COUNT)
+        .map(|ix| (format!("file-{ix}.txt"), json!("x".repeat(FILE_LEN))))
+        .collect::<serde_json::Map<_, _>>();
+    client_a
+        .fs()
+        .insert_tree("/dir", serde_json::Value::Object(files))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
+
+    let host_usage = client_a.bandwidth_usage().unwrap();
+    let guest_usage = client_b.bandwidth_usage().unwrap();
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    executor.run_until_parked();
+
+    // Sharing a project sends its entries, but not the contents of its files.
+    let host_sent = client_a
+        .bandwidth_usage()
+        .unwrap()
+        .bytes_sent(MessageCategory::WorktreeSync)
+        - host_usage.bytes_sent(MessageCategory::WorktreeSync);
+    let guest_received = client_b
+        .bandwidth_usage()
+        .unwrap()
+        .bytes_received(MessageCategory::WorktreeSync)
+        - guest_usage.bytes_received(MessageCategory::WorktreeSync);
+    let budget = FILE_COUNT as u64 * ENTRY_BUDGET;
+    assert!(
+        host_sent > 0 && host_sent < budget,
+        "host sent {host_sent} bytes"
+    );
+    assert!(
+        guest_received > 0 && guest_received < budget,
+        "guest received {guest_received} bytes"
+    );
+
+    // Opening a buffer costs about as much as the file's contents.
+    let guest_usage = client_b.bandwidth_usage().unwrap();
+    project_b
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "file-0.txt"), cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    let guest_received = client_b
+        .bandwidth_usage()
+        .unwrap()
+        .bytes_received(MessageCategory::BufferOperations)
+        - guest_usage.bytes_received(MessageCategory::BufferOperations);
+    assert!(
+        guest_received >= FILE_LEN as u64
+            && guest_received < FILE_LEN as u64 + BUFFER_OVERHEAD_BUDGET,
+        "guest received {guest_received} bytes"
+    );
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_editing_while_guest_opens_buffer(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree("/dir", json!({ "a.txt": "a-contents" }))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    // Open a buffer as client A
+    let buffer_a = project_a
+        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+        .await
+        .unwrap();
+
+    // Start opening the same buffer as client B
+    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx));
+    let buffer_b = cx_b.executor().spawn(open_buffer);
+
+    // Edit the buffer as client A while client B is still opening it.
+    cx_b.executor().simulate_random_delay().await;
+    buffer_a.update(cx_a, |buf, cx| buf.edit([(0..0, "X")], None, cx));
+    cx_b.executor().simulate_random_delay().await;
+    buffer_a.update(cx_a, |buf, cx| buf.edit([(1..1, "Y")], None, cx));
+
+    let text = buffer_a.read_with(cx_a, |buf, _| buf.text());
+    let buffer_b = buffer_b.await.unwrap();
+    executor.run_until_parked();
+
+    buffer_b.read_with(cx_b, |buf, _| assert_eq!(buf.text(), text));
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_leaving_worktree_while_opening_buffer(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree("/dir", json!({ "a.txt": "a-contents" }))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    // See that a guest has joined as client A.
+    executor.run_until_parked();
+
+    project_a.read_with(cx_a, |p, _| assert_eq!(p.collaborators().len(), 1));
+
+    // Begin opening a buffer as client B, but leave the project before the open completes.
+    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx));
+    let buffer_b = cx_b.executor().spawn(open_buffer);
+    cx_b.update(|_| drop(project_b));
+    drop(buffer_b);
+
+    // See that the guest has left.
+    executor.run_until_parked();
+
+    project_a.read_with(cx_a, |p, _| assert!(p.collaborators().is_empty()));
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_host_tracks_guest_open_buffers(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/a",
+            json!({
+                "a.txt": "a-contents",
+                "dir": {
+                    "b.txt": "b-contents",
+                },
+            }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    let guest_peer_id = client_b.peer_id().unwrap();
+
+    let buffer_b = project_b
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "dir/b.txt"), cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+
+    let buffer_id = buffer_b.read_with(cx_b, |buffer, _| buffer.remote_id());
+    project_a.read_with(cx_a, |project, _| {
+        assert_eq!(
+            project.guest_open_buffers().get(&guest_peer_id),
+            Some(&HashSet::from_iter([buffer_id]))
+        );
+
+        let guests_with_open_buffers_in = |path: &str| {
+            project
+                .guests_with_open_buffers_in(&ProjectPath {
+                    worktree_id,
+                    path: Path::new(path).into(),
+                })
+                .into_iter()
+                .map(|guest| guest.peer_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(guests_with_open_buffers_in("dir/b.txt"), [guest_peer_id]);
+        assert_eq!(guests_with_open_buffers_in("dir"), [guest_peer_id]);
+        assert_eq!(guests_with_open_buffers_in(""), [guest_peer_id]);
+        assert_eq!(guests_with_open_buffers_in("a.txt"), []);
+    });
+
+    // Closing the buffer tells the host, which sends it again if it's reopened.
+    cx_b.update(|_| drop(buffer_b));
+    executor.run_until_parked();
+    project_a.read_with(cx_a, |project, _| {
+        assert_eq!(
+            project.guest_open_buffers().get(&guest_peer_id),
+            Some(&HashSet::default())
+        );
+    });
+    let buffer_b = project_b
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "dir/b.txt"), cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert_eq!(
+        buffer_b.read_with(cx_b, |buffer, _| buffer.text()),
+        "b-contents"
+    );
+    project_a.read_with(cx_a, |project, _| {
+        assert_eq!(
+            project.guest_open_buffers().get(&guest_peer_id),
+            Some(&HashSet::from_iter([buffer_id]))
+        );
+    });
+
+    // Once the guest leaves the project, the host forgets which buffers they had open.
+    cx_b.update(|_| {
+        drop(buffer_b);
+        drop(project_b);
+    });
+    executor.run_until_parked();
+    project_a.read_with(cx_a, |project, _| {
+        assert!(project.guest_open_buffers().is_empty());
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_guest_edits_reach_host_after_closing_project(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/a",
+            json!({ "a.txt": "a-contents", "b.txt": "b-contents" }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let buffer_a = project_a
+        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+        .await
+        .unwrap();
+    let other_buffer_a = project_a
+        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "b.txt"), cx))
+        .await
+        .unwrap();
+
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    let buffer_b = project_b
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+        .await
+        .unwrap();
+    let other_buffer_b = project_b
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "b.txt"), cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+
+    // The guest waits for their edits to be acknowledged before closing the project.
+    buffer_b.update(cx_b, |buffer, cx| {
+        buffer.edit([(0..0, "flushed ")], None, cx)
+    });
+    project_b
+        .read_with(cx_b, |project, _| project.flush())
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    buffer_a.read_with(cx_a, |buffer, _| {
+        assert_eq!(buffer.text(), "flushed a-contents")
+    });
+
+    // Edits made right before the project is dropped still reach the host.
+    other_buffer_b.update(cx_b, |buffer, cx| {
+        buffer.edit([(0..0, "dropped ")], None, cx)
+    });
+    cx_b.update(|_| {
+        drop(buffer_b);
+        drop(other_buffer_b);
+        drop(project_b);
+    });
+    executor.run_until_parked();
+    other_buffer_a.read_with(cx_a, |buffer, _| {
+        assert_eq!(buffer.text(), "dropped b-contents")
+    });
+    project_a.read_with(cx_a, |project, _| {
+        assert!(project.collaborators().is_empty())
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_room_flush(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree("/a", json!({ "a.txt": "a-contents" }))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let buffer_a = project_a
+        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+        .await
+        .unwrap();
+
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    let buffer_b = project_b
+        .update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+        .await
+        .unwrap();
+
+    // Once the guest's room is flushed, the host has applied every edit the guest made,
+    // without having to wait for the executor to park.
+    for (ix, text) in ["one ", "two ", "three "].into_iter().enumerate() {
+        buffer_b.update(cx_b, |buffer, cx| buffer.edit([(0..0, text)], None, cx));
+        let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
+        room_b
+            .update(cx_b, |room, cx| room.flush(cx))
+            .await
+            .unwrap();
+        buffer_a.read_with(cx_a, |buffer, _| {
+            assert!(
+                buffer.text().starts_with(text),
+                "edit {ix} wasn't applied by the host"
+            )
+        });
+    }
+    buffer_a.read_with(cx_a, |buffer, _| {
+        assert_eq!(buffer.text(), "three two one a-contents")
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_canceling_buffer_opening(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/dir",
+            json!({
+                "a.txt": "abc",
+            }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/dir", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    let buffer_a = project_a
+        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "a.txt"), cx))
+        .await
+        .unwrap();
+
+    // Open a buffer as client B but cancel after a random amount of time.
+    let buffer_b = project_b.update(cx_b, |p, cx| {
+        p.open_buffer_by_id(buffer_a.read_with(cx_a, |a, _| a.remote_id()), cx)
+    });
+    executor.simulate_random_delay().await;
+    drop(buffer_b);
+
+    // Try opening the same buffer again as client B, and ensure we can
+    // still do it despite the cancellation above.
+    let buffer_b = project_b
+        .update(cx_b, |p, cx| {
+            p.open_buffer_by_id(buffer_a.read_with(cx_a, |a, _| a.remote_id()), cx)
+        })
+        .await
+        .unwrap();
+
+    buffer_b.read_with(cx_b, |buf, _| assert_eq!(buf.text(), "abc"));
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_leaving_project(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/a",
+            json!({
+                "a.txt": "a-contents",
+                "b.txt": "b-contents",
+            }),
+        )
+        .await;
+    let (project_a, _) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b1 = client_b.build_remote_project(project_id, cx_b).await;
+    let project_c = client_c.build_remote_project(project_id, cx_c).await;
+
+    // Client A sees that a guest has joined.
+    executor.run_until_parked();
+
+    project_a.read_with(cx_a, |project, _| {
+        assert_eq!(project.collaborators().len(), 2);
+    });
+
+    project_b1.read_with(cx_b, |project, _| {
+        assert_eq!(project.collaborators().len(), 2);
+    });
+
+    project_c.read_with(cx_c, |project, _| {
+        assert_eq!(project.collaborators().len(), 2);
+    });
+
+    // Client B opens a buffer.
+    let buffer_b1 = project_b1
+        .update(cx_b, |project, cx| {
+            let worktree_id = project.worktrees().next().unwrap().read(cx).id();
+            project.open_buffer((worktree_id, "a.txt"), cx)
+        })
+        .await
+        .unwrap();
+
+    buffer_b1.read_with(cx_b, |buffer, _| assert_eq!(buffer.text(), "a-contents"));
+
+    // Drop client B's project and ensure client A and client C observe client B leaving.
+    cx_b.update(|_| drop(project_b1));
+    executor.run_until_parked();
+
+    project_a.read_with(cx_a, |project, _| {
+        assert_eq!(project.collaborators().len(), 1);
+    });
+
+    project_c.read_with(cx_c, |project, _| {
+        assert_eq!(project.collaborators().len(), 1);
+    });
+
+    // Client B re-joins the project and can open buffers as before.
+    let project_b2 = client_b.build_remote_project(project_id, cx_b).await;
+    executor.run_until_parked();
+
+    project_a.read_with(cx_a, |project, _| {
+        assert_eq!(project.collaborators().len(), 2);
+    });
+
+    project_b2.read_with(cx_b, |project, _| {
+        assert_eq!(project.collaborators().len(), 2);
+    });
+
+    project_c.read_with(cx_c, |project, _| {
+        assert_eq!(project.collaborators().len(), 2);
+    });
+
+    let buffer_b2 = project_b2
+        .update(cx_b, |project, cx| {
+            let worktree_id = project.worktrees().next().unwrap().read(cx).id();
+            project.open_buffer((worktree_id, "a.txt"), cx)
+        })
+        .await
+        .unwrap();
+
+    buffer_b2.read_with(cx_b, |buffer, _| assert_eq!(buffer.text(), "a-contents"));
+
+    // Drop client B's connection and ensure client A and client C observe client B leaving.
+    client_b.disconnect(&cx_b.to_async());
+    executor.advance_clock(RECONNECT_TIMEOUT);
+
+    project_a.read_with(cx_a, |project, _| {
+        assert_eq!(project.collaborators().len(), 1);
+    });
+
+    project_b2.read_with(cx_b, |project, _| {
+        assert!(project.is_disconnected());
+    });
+
+    project_c.read_with(cx_c, |project, _| {
+        assert_eq!(project.collaborators().len(), 1);
+    });
+
+    // Client B can't join the project, unless they re-join the room.
+    cx_b.spawn(|cx| {
+        Project::remote(
+            project_id,
+            client_b.app_state.client.clone(),
+            client_b.user_store().clone(),
+            client_b.language_registry().clone(),
+            FakeFs::new(cx.background_executor().clone()),
+            ChannelRole::Member,
+            cx,
+        )
+    })
+    .await
+    .unwrap_err();
+
+    // Simulate connection loss for client C and ensure client A observes client C leaving the project.
+    client_c.wait_for_current_user(cx_c).await;
+    server.forbid_connections();
+    server.disconnect_client(client_c.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
+    executor.run_until_parked();
+
+    project_a.read_with(cx_a, |project, _| {
+        assert_eq!(project.collaborators().len(), 0);
+    });
+
+    project_b2.read_with(cx_b, |project, _| {
+        assert!(project.is_disconnected());
+    });
+
+    project_c.read_with(cx_c, |project, _| {
+        assert!(project.is_disconnected());
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_collaborating_with_diagnostics(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    // Set up a fake language server.
+    let mut language = Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["rs".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    );
+    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
+    client_a.language_registry().add(Arc::new(language));
+
+    // Share a project as client A
+    client_a
+        .fs()
+        .insert_tree(
+            "/a",
+            json!({
+                "a.rs": "let one = two",
+                "other.rs": "",
+            }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+
+    // Cause the language server to start.
+    let _buffer = project_a
+        .update(cx_a, |project, cx| {
+            project.open_buffer(
+                ProjectPath {
+                    worktree_id,
+                    path: Path::new("other.rs").into(),
+                },
+                cx,
+            )
+        })
+        .await
+        .unwrap();
+
+    // Simulate a language server reporting errors for a file.
+    let mut fake_language_server = fake_language_servers.next().await.unwrap();
+    fake_language_server
+        .receive_notification::<lsp::notification::DidOpenTextDocument>()
+        .await;
+    fake_language_server.notify::<lsp::notification::PublishDiagnostics>(
+        lsp::PublishDiagnosticsParams {
+            uri: lsp::Url::from_file_path("/a/a.rs").unwrap(),
+            version: None,
+            diagnostics: vec![lsp::Diagnostic {
+                severity: Some(lsp::DiagnosticSeverity::WARNING),
+                range: lsp::Range::new(lsp::Position::new(0, 4), lsp::Position::new(0, 7)),
+                message: "message 0".to_string(),
+                ..Default::default()
+            }],
+        },
+    );
+
+    // Client A shares the project and, simultaneously, the language server
+    // publishes a diagnostic. This is done to ensure that the server always
+    // observes the latest diagnostics for a worktree.
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    fake_language_server.notify::<lsp::notification::PublishDiagnostics>(
+        lsp::PublishDiagnosticsParams {
+            uri: lsp::Url::from_file_path("/a/a.rs").unwrap(),
+            version: None,
+            diagnostics: vec![lsp::Diagnostic {
+                severity: Some(lsp::DiagnosticSeverity::ERROR),
+                range: lsp::Range::new(lsp::Position::new(0, 4), lsp::Position::new(0, 7)),
+                message: "message 1".to_string(),
+                ..Default::default()
+            }],
+        },
+    );
+
+    // Join the worktree as client B.
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    // Wait for server to see the diagnostics update.
+    executor.run_until_parked();
+
+    // Ensure client B observes the new diagnostics.
+
+    project_b.read_with(cx_b, |project, cx| {
+        assert_eq!(
+            project.diagnostic_summaries(false, cx).collect::<Vec<_>>(),
+            &[(
+                ProjectPath {
+                    worktree_id,
+                    path: Arc::from(Path::new("a.rs")),
+                },
+                LanguageServerId(0),
+                DiagnosticSummary {
+                    error_count: 1,
+                    warning_count: 0,
+                    ..Default::default()
+                },
+            )]
+        )
+    });
+
+    // Join project as client C and observe the diagnostics.
+    let project_c = client_c.build_remote_project(project_id, cx_c).await;
+    executor.run_until_parked();
+    let project_c_diagnostic_summaries =
+        Rc::new(RefCell::new(project_c.read_with(cx_c, |project, cx| {
+            project.diagnostic_summaries(false, cx).collect::<Vec<_>>()
+        })));
+    project_c.update(cx_c, |_, cx| {
+        let summaries = project_c_diagnostic_summaries.clone();
+        cx.subscribe(&project_c, {
+            move |p, _, event, cx| {
+                if let project::Event::DiskBasedDiagnosticsFinished { .. } = event {
+                    *summaries.borrow_mut() = p.diagnostic_summaries(false, cx).collect();
+                }
+            }
+        })
+        .detach();
+    });
+
+    executor.run_until_parked();
+    assert_eq!(
+        project_c_diagnostic_summaries.borrow().as_slice(),
+        &[(
+            ProjectPath {
+                worktree_id,
+                path: Arc::from(Path::new("a.rs")),
+            },
+            LanguageServerId(0),
+            DiagnosticSummary {
+                error_count: 1,
+                warning_count: 0,
+                ..Default::default()
+            },
+        )]
+    );
+
+    // Simulate a language server reporting more errors for a file.
+    fake_language_server.notify::<lsp::notification::PublishDiagnostics>(
+        lsp::PublishDiagnosticsParams {
+            uri: lsp::Url::from_file_path("/a/a.rs").unwrap(),
+            version: None,
+            diagnostics: vec![
+                lsp::Diagnostic {
+                    severity: Some(lsp::DiagnosticSeverity::ERROR),
+                    range: lsp::Range::new(lsp::Position::new(0, 4), lsp::Position::new(0, 7)),
+                    message: "message 1".to_string(),
+                    ..Default::default()
+                },
+                lsp::Diagnostic {
+                    severity: Some(lsp::DiagnosticSeverity::WARNING),
+                    range: lsp::Range::new(lsp::Position::new(0, 10), lsp::Position::new(0, 13)),
+                    message: "message 2".to_string(),
+                    ..Default::default()
+                },
+            ],
+        },
+    );
+
+    // Clients B and C get the updated summaries
+    executor.run_until_parked();
+
+    project_b.read_with(cx_b, |project, cx| {
+        assert_eq!(
+            project.diagnostic_summaries(false, cx).collect::<Vec<_>>(),
+            [(
+                ProjectPath {
+                    worktree_id,
+                    path: Arc::from(Path::new("a.rs")),
+                },
+                LanguageServerId(0),
+                DiagnosticSummary {
+                    error_count: 1,
+                    warning_count: 1,
+                },
+            )]
+        );
+    });
+
+    project_c.read_with(cx_c, |project, cx| {
+        assert_eq!(
+            project.diagnostic_summaries(false, cx).collect::<Vec<_>>(),
+            [(
+                ProjectPath {
+                    worktree_id,
+                    path: Arc::from(Path::new("a.rs")),
+                },
+                LanguageServerId(0),
+                DiagnosticSummary {
+                    error_count: 1,
+                    warning_count: 1,
+                },
+            )]
+        );
+    });
+
+    // Open the file with the errors on client B. They should be present.
+    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx));
+    let buffer_b = cx_b.executor().spawn(open_buffer).await.unwrap();
+
+    buffer_b.read_with(cx_b, |buffer, _| {
+        assert_eq!(
+            buffer
+                .snapshot()
+                .diagnostics_in_range::<_, Point>(0..buffer.len(), false)
+                .collect::<Vec<_>>(),
+            &[
+                DiagnosticEntry {
+                    range: Point::new(0, 4)..Point::new(0, 7),
+                    diagnostic: Diagnostic {
+                        group_id: 2,
+                        message: "message 1".to_string(),
+                        severity: lsp::DiagnosticSeverity::ERROR,
+                        is_primary: true,
+                        ..Default::default()
+                    }
+                },
+                DiagnosticEntry {
+                    range: Point::new(0, 10)..Point::new(0, 13),
+                    diagnostic: Diagnostic {
+                        group_id: 3,
+                        severity: lsp::DiagnosticSeverity::WARNING,
+                        message: "message 2".to_string(),
+                        is_primary: true,
+                        ..Default::default()
+                    }
+                }
+            ]
+        );
+    });
+
+    // Simulate a language server reporting no errors for a file.
+    fake_language_server.notify::<lsp::notification::PublishDiagnostics>(
+        lsp::PublishDiagnosticsParams {
+            uri: lsp::Url::from_file_path("/a/a.rs").unwrap(),
+            version: None,
+            diagnostics: vec![],
+        },
+    );
+    executor.run_until_parked();
+
+    project_a.read_with(cx_a, |project, cx| {
+        assert_eq!(
+            project.diagnostic_summaries(false, cx).collect::<Vec<_>>(),
+            []
+        )
+    });
+
+    project_b.read_with(cx_b, |project, cx| {
+        assert_eq!(
+            project.diagnostic_summaries(false, cx).collect::<Vec<_>>(),
+            []
+        )
+    });
+
+    project_c.read_with(cx_c, |project, cx| {
+        assert_eq!(
+            project.diagnostic_summaries(false, cx).collect::<Vec<_>>(),
+            []
+        )
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_collaborating_with_lsp_progress_updates_and_diagnostics_ordering(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+
+    // Set up a fake language server.
+    let mut language = Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["rs".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    );
+    let mut fake_language_servers = language
+        .set_fake_lsp_adapter(Arc::new(FakeLspAdapter {
+            disk_based_diagnostics_progress_token: Some("the-disk-based-token".into()),
+            disk_based_diagnostics_sources: vec!["the-disk-based-diagnostics-source".into()],
+            ..Default::default()
+        }))
+        .await;
+    client_a.language_registry().add(Arc::new(language));
+
+    let file_names = &["one.rs", "two.rs", "three.rs", "four.rs", "five.rs"];
+    client_a
+        .fs()
+        .insert_tree(
+            "/test",
+            json!({
+                "one.rs": "const ONE: usize = 1;",
+                "two.rs": "const TWO: usize = 2;",
+                "three.rs": "const THREE: usize = 3;",
+                "four.rs": "const FOUR: usize = 3;",
+                "five.rs": "const FIVE: usize = 3;",
+            }),
+        )
+        .await;
+
+    let (project_a, worktree_id) = client_a.build_local_project("/test", cx_a).await;
+
+    // Share a project as client A
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+
+    // Join the project as client B and open all three files.
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    let guest_buffers = futures::future::try_join_all(file_names.iter().map(|file_name| {
+        project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, file_name), cx))
+    }))
+    .await
+    .unwrap();
+
+    // Simulate a language server reporting errors for a file.
+    let fake_language_server = fake_language_servers.next().await.unwrap();
+    fake_language_server
+        .request::<lsp::request::WorkDoneProgressCreate>(lsp::WorkDoneProgressCreateParams {
+            token: lsp::NumberOrString::String("the-disk-based-token".to_string()),
+        })
+        .await
+        .unwrap();
+    fake_language_server.notify::<lsp::notification::Progress>(lsp::ProgressParams {
+        token: lsp::NumberOrString::String("the-disk-based-token".to_string()),
+        value: lsp::ProgressParamsValue::WorkDone(lsp::WorkDoneProgress::Begin(
+            lsp::WorkDoneProgressBegin {
+                title: "Progress Began".into(),
+                ..Default::default()
+            },
+        )),
+    });
+    for file_name in file_names {
+        fake_language_server.notify::<lsp::notification::PublishDiagnostics>(
+            lsp::PublishDiagnosticsParams {
+                uri: lsp::Url::from_file_path(Path::new("/test").join(file_name)).unwrap(),
+                version: None,
+                diagnostics: vec![lsp::Diagnostic {
+                    severity: Some(lsp::DiagnosticSeverity::WARNING),
+                    source: Some("the-disk-based-diagnostics-source".into()),
+                    range: lsp::Range::new(lsp::Position::new(0, 0), lsp::Position::new(0, 0)),
+                    message: "message one".to_string(),
+                    ..Default::default()
+                }],
+            },
+        );
+    }
+    fake_language_server.notify::<lsp::notification::Progress>(lsp::ProgressParams {
+        token: lsp::NumberOrString::String("the-disk-based-token".to_string()),
+        value: lsp::ProgressParamsValue::WorkDone(lsp::WorkDoneProgress::End(
+            lsp::WorkDoneProgressEnd { message: None },
+        )),
+    });
+
+    // When the "disk base diagnostics finished" message is received, the buffers'
+    // diagnostics are expected to be present.
+    let disk_based_diagnostics_finished = Arc::new(AtomicBool::new(false));
+    project_b.update(cx_b, {
+        let project_b = project_b.clone();
+        let disk_based_diagnostics_finished = disk_based_diagnostics_finished.clone();
+        move |_, cx| {
+            cx.subscribe(&project_b, move |_, _, event, cx| {
+                if let project::Event::DiskBasedDiagnosticsFinished { .. } = event {
+                    disk_based_diagnostics_finished.store(true, SeqCst);
+                    for buffer in &guest_buffers {
+                        assert_eq!(
+                            buffer
+                                .read(cx)
+                                .snapshot()
+                                .diagnostics_in_range::<_, usize>(0..5, false)
+                                .count(),
+                            1,
+                            "expected a diagnostic for buffer {:?}",
+                            buffer.read(cx).file().unwrap().path(),
+                        );
+                    }
+                }
+            })
+            .detach();
+        }
+    });
+
+    executor.run_until_parked();
+    assert!(disk_based_diagnostics_finished.load(SeqCst));
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_reloading_buffer_manually(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree("/a", json!({ "a.rs": "let one = 1;" }))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+    let buffer_a = project_a
+        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx))
+        .await
+        .unwrap();
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx));
+    let buffer_b = cx_b.executor().spawn(open_buffer).await.unwrap();
+    buffer_b.update(cx_b, |buffer, cx| {
+        buffer.edit([(4..7, "six")], None, cx);
+        buffer.edit([(10..11, "6")], None, cx);
+        assert_eq!(buffer.text(), "let six = 6;");
+        assert!(buffer.is_dirty());
+        assert!(!buffer.has_conflict());
+    });
+    executor.run_until_parked();
+
+    buffer_a.read_with(cx_a, |buffer, _| assert_eq!(buffer.text(), "let six = 6;"));
+
+    client_a
+        .fs()
+        .save(
+            "/a/a.rs".as_ref(),
+            &Rope::from("let seven = 7;"),
+            LineEnding::Unix,
+        )
+        .await
+        .unwrap();
+    executor.run_until_parked();
+
+    buffer_a.read_with(cx_a, |buffer, _| assert!(buffer.has_conflict()));
+
+    buffer_b.read_with(cx_b, |buffer, _| assert!(buffer.has_conflict()));
+
+    project_b
+        .update(cx_b, |project, cx| {
+            project.reload_buffers(HashSet::from_iter([buffer_b.clone()]), true, cx)
+        })
+        .await
+        .unwrap();
+
+    buffer_a.read_with(cx_a, |buffer, _| {
+        assert_eq!(buffer.text(), "let seven = 7;");
+        assert!(!buffer.is_dirty());
+        assert!(!buffer.has_conflict());
+    });
+
+    buffer_b.read_with(cx_b, |buffer, _| {
+        assert_eq!(buffer.text(), "let seven = 7;");
+        assert!(!buffer.is_dirty());
+        assert!(!buffer.has_conflict());
+    });
+
+    buffer_a.update(cx_a, |buffer, cx| {
+        // Undoing on the host is a no-op when the reload was initiated by the guest.
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), "let seven = 7;");
+        assert!(!buffer.is_dirty());
+        assert!(!buffer.has_conflict());
+    });
+    buffer_b.update(cx_b, |buffer, cx| {
+        // Undoing on the guest rolls back the buffer to before it was reloaded but the conflict gets cleared.
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), "let six = 6;");
+        assert!(buffer.is_dirty());
+        assert!(!buffer.has_conflict());
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_formatting_buffer(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    executor.allow_parking();
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    // Set up a fake language server.
+    let mut language = Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["rs".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    );
+    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
+    client_a.language_registry().add(Arc::new(language));
+
+    // Here we insert a fake tree with a directory that exists on disk. This is needed
+    // because later we'll invoke a command, which requires passing a working directory
+    // that points to a valid location on disk.
+    let directory = env::current_dir().unwrap();
+    client_a
+        .fs()
+        .insert_tree(&directory, json!({ "a.rs": "let one = \"two\"" }))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project(&directory, cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx));
+    let buffer_b = cx_b.executor().spawn(open_buffer).await.unwrap();
+
+    let fake_language_server = fake_language_servers.next().await.unwrap();
+    fake_language_server.handle_request::<lsp::request::Formatting, _, _>(|_, _| async move {
+        Ok(Some(vec![
+            lsp::TextEdit {
+                range: lsp::Range::new(lsp::Position::new(0, 4), lsp::Position::new(0, 4)),
+                new_text: "h".to_string(),
+            },
+            lsp::TextEdit {
+                range: lsp::Range::new(lsp::Position::new(0, 7), lsp::Position::new(0, 7)),
+                new_text: "y".to_string(),
+            },
+        ]))
+    });
+
+    project_b
+        .update(cx_b, |project, cx| {
+            project.format(
+                HashSet::from_iter([buffer_b.clone()]),
+                true,
+                FormatTrigger::Save,
+                cx,
+            )
+        })
+        .await
+        .unwrap();
+
+    // The edits from the LSP are applied, and a final newline is added.
+    assert_eq!(
+        buffer_b.read_with(cx_b, |buffer, _| buffer.text()),
+        "let honey = \"two\"\n"
+    );
+
+    // Ensure buffer can be formatted using an external command. Notice how the
+    // host's configuration is honored as opposed to using the guest's settings.
+    cx_a.update(|cx| {
+        cx.update_global(|store: &mut SettingsStore, cx| {
+            store.update_user_settings::<AllLanguageSettings>(cx, |file| {
+                file.defaults.formatter = Some(Formatter::External {
+                    command: "awk".into(),
+                    arguments: vec!["{sub(/two/,\"{buffer_path}\")}1".to_string()].into(),
+                });
+            });
+        });
+    });
+    project_b
+        .update(cx_b, |project, cx| {
+            project.format(
+                HashSet::from_iter([buffer_b.clone()]),
+                true,
+                FormatTrigger::Save,
+                cx,
+            )
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        buffer_b.read_with(cx_b, |buffer, _| buffer.text()),
+        format!("let honey = \"{}/a.rs\"\n", directory.to_str().unwrap())
+    );
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_prettier_formatting_buffer(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    // Set up a fake language server.
+    let mut language = Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["rs".to_string()],
+                ..Default::default()
+            },
+            prettier_parser_name: Some("test_parser".to_string()),
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    );
+    let test_plugin = "test_plugin";
+    let mut fake_language_servers = language
+        .set_fake_lsp_adapter(Arc::new(FakeLspAdapter {
+            prettier_plugins: vec![test_plugin],
+            ..Default::default()
+        }))
+        .await;
+    let language = Arc::new(language);
+    client_a.language_registry().add(Arc::clone(&language));
+
+    // Here we insert a fake tree with a directory that exists on disk. This is needed
+    // because later we'll invoke a command, which requires passing a working directory
+    // that points to a valid location on disk.
+    let directory = env::current_dir().unwrap();
+    let buffer_text = "let one = \"two\"";
+    client_a
+        .fs()
+        .insert_tree(&directory, json!({ "a.rs": buffer_text }))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project(&directory, cx_a).await;
+    let prettier_format_suffix = project::TEST_PRETTIER_FORMAT_SUFFIX;
+    let open_buffer = project_a.update(cx_a, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx));
+    let buffer_a = cx_a.executor().spawn(open_buffer).await.unwrap();
+
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx));
+    let buffer_b = cx_b.executor().spawn(open_buffer).await.unwrap();
+
+    cx_a.update(|cx| {
+        cx.update_global(|store: &mut SettingsStore, cx| {
+            store.update_user_settings::<AllLanguageSettings>(cx, |file| {
+                file.defaults.formatter = Some(Formatter::Auto);
+            });
+        });
+    });
+    cx_b.update(|cx| {
+        cx.update_global(|store: &mut SettingsStore, cx| {
+            store.update_user_settings::<AllLanguageSettings>(cx, |file| {
+                file.defaults.formatter = Some(Formatter::LanguageServer);
+            });
+        });
+    });
+    let fake_language_server = fake_language_servers.next().await.unwrap();
+    fake_language_server.handle_request::<lsp::request::Formatting, _, _>(|_, _| async move {
+        panic!(
+            "Unexpected: prettier should be preferred since it's enabled and language supports it"
+        )
+    });
+
+    project_b
+        .update(cx_b, |project, cx| {
+            project.format(
+                HashSet::from_iter([buffer_b.clone()]),
+                true,
+                FormatTrigger::Save,
+                cx,
+            )
+        })
+        .await
+        .unwrap();
+
+    executor.run_until_parked();
+    assert_eq!(
+        buffer_b.read_with(cx_b, |buffer, _| buffer.text()),
+        buffer_text.to_string() + "\n" + prettier_format_suffix,
+        "Prettier formatting was not applied to client buffer after client's request"
+    );
+
+    project_a
+        .update(cx_a, |project, cx| {
+            project.format(
+                HashSet::from_iter([buffer_a.clone()]),
+                true,
+                FormatTrigger::Manual,
+                cx,
+            )
+        })
+        .await
+        .unwrap();
+
+    executor.run_until_parked();
+    assert_eq!(
+        buffer_b.read_with(cx_b, |buffer, _| buffer.text()),
+        buffer_text.to_string() + "\n" + prettier_format_suffix + "\n" + prettier_format_suffix,
+        "Prettier formatting was not applied to client buffer after host's request"
+    );
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_definition(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    // Set up a fake language server.
+    let mut language = Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["rs".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    );
+    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
+    client_a.language_registry().add(Arc::new(language));
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/root",
+            json!({
+                "dir-1": {
+                    "a.rs": "const ONE: usize = b::TWO + b::THREE;",
+                },
+                "dir-2": {
+                    "b.rs": "const TWO: c::T2 = 2;\nconst THREE: usize = 3;",
+                    "c.rs": "type T2 = usize;",
+                }
+            }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/root/dir-1", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    // Open the file on client B.
+    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx));
+    let buffer_b = cx_b.executor().spawn(open_buffer).await.unwrap();
+
+    // Request the definition of a symbol as the guest.
+    let fake_language_server = fake_language_servers.next().await.unwrap();
+    fake_language_server.handle_request::<lsp::request::GotoDefinition, _, _>(|_, _| async move {
+        Ok(Some(lsp::GotoDefinitionResponse::Scalar(
+            lsp::Location::new(
+                lsp::Url::from_file_path("/root/dir-2/b.rs").unwrap(),
+                lsp::Range::new(lsp::Position::new(0, 6), lsp::Position::new(0, 9)),
+            ),
+        )))
+    });
+
+    let definitions_1 = project_b
+        .update(cx_b, |p, cx| p.definition(&buffer_b, 23, cx))
+        .await
+        .unwrap();
+    cx_b.read(|cx| {
+        assert_eq!(definitions_1.len(), 1);
+        assert_eq!(project_b.read(cx).worktrees().count(), 2);
+        let target_buffer = definitions_1[0].target.buffer.read(cx);
+        assert_eq!(
+            target_buffer.text(),
+            "const TWO: c::T2 = 2;\nconst THREE: usize = 3;"
+        );
+        assert_eq!(
+            definitions_1[0].target.range.to_point(target_buffer),
+            Point::new(0, 6)..Point::new(0, 9)
+        );
+    });
+
+    // Try getting more definitions for the same buffer, ensuring the buffer gets reused from
+    // the previous call to `definition`.
+    fake_language_server.handle_request::<lsp::request::GotoDefinition, _, _>(|_, _| async move {
+        Ok(Some(lsp::GotoDefinitionResponse::Scalar(
+            lsp::Location::new(
+                lsp::Url::from_file_path("/root/dir-2/b.rs").unwrap(),
+                lsp::Range::new(lsp::Position::new(1, 6), lsp::Position::new(1, 11)),
+            ),
+        )))
+    });
+
+    let definitions_2 = project_b
+        .update(cx_b, |p, cx| p.definition(&buffer_b, 33, cx))
+        .await
+        .unwrap();
+    cx_b.read(|cx| {
+        assert_eq!(definitions_2.len(), 1);
+        assert_eq!(project_b.read(cx).worktrees().count(), 2);
+        let target_buffer = definitions_2[0].target.buffer.read(cx);
+        assert_eq!(
+            target_buffer.text(),
+            "const TWO: c::T2 = 2;\nconst THREE: usize = 3;"
+        );
+        assert_eq!(
+            definitions_2[0].target.range.to_point(target_buffer),
+            Point::new(1, 6)..Point::new(1, 11)
+        );
+    });
+    assert_eq!(
+        definitions_1[0].target.buffer,
+        definitions_2[0].target.buffer
+    );
+
+    fake_language_server.handle_request::<lsp::request::GotoTypeDefinition, _, _>(
+        |req, _| async move {
+            assert_eq!(
+                req.text_document_position_params.position,
+                lsp::Position::new(0, 7)
+            );
+            Ok(Some(lsp::GotoDefinitionResponse::Scalar(
+                lsp::Location::new(
+                    lsp::Url::from_file_path("/root/dir-2/c.rs").unwrap(),
+                    lsp::Range::new(lsp::Position::new(0, 5), lsp::Position::new(0, 7)),
+                ),
+            )))
+        },
+    );
+
+    let type_definitions = project_b
+        .update(cx_b, |p, cx| p.type_definition(&buffer_b, 7, cx))
+        .await
+        .unwrap();
+    cx_b.read(|cx| {
+        assert_eq!(type_definitions.len(), 1);
+        let target_buffer = type_definitions[0].target.buffer.read(cx);
+        assert_eq!(target_buffer.text(), "type T2 = usize;");
+        assert_eq!(
+            type_definitions[0].target.range.to_point(target_buffer),
+            Point::new(0, 5)..Point::new(0, 7)
+        );
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_references(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    // Set up a fake language server.
+    let mut language = Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["rs".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    );
+    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
+    client_a.language_registry().add(Arc::new(language));
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/root",
+            json!({
+                "dir-1": {
+                    "one.rs": "const ONE: usize = 1;",
+                    "two.rs": "const TWO: usize = one::ONE + one::ONE;",
+                },
+                "dir-2": {
+                    "three.rs": "const THREE: usize = two::TWO + one::ONE;",
+                }
+            }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/root/dir-1", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    // Open the file on client B.
+    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "one.rs"), cx));
+    let buffer_b = cx_b.executor().spawn(open_buffer).await.unwrap();
+
+    // Request references to a symbol as the guest.
+    let fake_language_server = fake_language_servers.next().await.unwrap();
+    fake_language_server.handle_request::<lsp::request::References, _, _>(|params, _| async move {
+        assert_eq!(
+            params.text_document_position.text_document.uri.as_str(),
+            "file:///root/dir-1/one.rs"
+        );
+        Ok(Some(vec![
+            lsp::Location {
+                uri: lsp::Url::from_file_path("/root/dir-1/two.rs").unwrap(),
+                range: lsp::Range::new(lsp::Position::new(0, 24), lsp::Position::new(0, 27)),
+            },
+            lsp::Location {
+                uri: lsp::Url::from_file_path("/root/dir-1/two.rs").unwrap(),
+                range: lsp::Range::new(lsp::Position::new(0, 35), lsp::Position::new(0, 38)),
+            },
+            lsp::Location {
+                uri: lsp::Url::from_file_path("/root/dir-2/three.rs").unwrap(),
+                range: lsp::Range::new(lsp::Position::new(0, 37), lsp::Position::new(0, 40)),
+            },
+        ]))
+    });
+
+    let references = project_b
+        .update(cx_b, |p, cx| p.references(&buffer_b, 7, cx))
+        .await
+        .unwrap();
+    cx_b.read(|cx| {
+        assert_eq!(references.len(), 3);
+        assert_eq!(project_b.read(cx).worktrees().count(), 2);
+
+        let two_buffer = references[0].buffer.read(cx);
+        let three_buffer = references[2].buffer.read(cx);
+        assert_eq!(
+            two_buffer.file().unwrap().path().as_ref(),
+            Path::new("two.rs")
+        );
+        assert_eq!(references[1].buffer, references[0].buffer);
+        assert_eq!(
+            three_buffer.file().unwrap().full_path(cx),
+            Path::new("/root/dir-2/three.rs")
+        );
+
+        assert_eq!(references[0].range.to_offset(two_buffer), 24..27);
+        assert_eq!(references[1].range.to_offset(two_buffer), 35..38);
+        assert_eq!(references[2].range.to_offset(three_buffer), 37..40);
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_project_search(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/root",
+            json!({
+                "dir-1": {
+                    "a": "hello world",
+                    "b": "goodnight moon",
+                    "c": "a world of goo",
+                    "d": "world champion of clown world",
+                },
+                "dir-2": {
+                    "e": "disney world is fun",
+                }
+            }),
+        )
+        .await;
+    let (project_a, _) = client_a.build_local_project("/root/dir-1", cx_a).await;
+    let (worktree_2, _) = project_a
+        .update(cx_a, |p, cx| {
+            p.find_or_create_local_worktree("/root/dir-2", true, cx)
+        })
+        .await
+        .unwrap();
+    worktree_2
+        .read_with(cx_a, |tree, _| tree.as_local().unwrap().scan_complete())
+        .await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    // Perform a search as the guest.
+    let mut results = HashMap::default();
+    let mut search_rx = project_b.update(cx_b, |project, cx| {
+        project.search(
+            SearchQuery::text("world", false, false, false, Vec::new(), Vec::new()).unwrap(),
+            cx,
+        )
+    });
+    while let Some((buffer, ranges)) = search_rx.next().await {
+        results.entry(buffer).or_insert(ranges);
+    }
+
+    let mut ranges_by_path = results
+        .into_iter()
+        .map(|(buffer, ranges)| {
+            buffer.read_with(cx_b, |buffer, cx| {
+                let path = buffer.file().unwrap().full_path(cx);
+                let offset_ranges = ranges
+                    .into_iter()
+                    .map(|range| range.to_offset(buffer))
+                    .collect::<Vec<_>>();
+                (path, offset_ranges)
+            })
+        })
+        .collect::<Vec<_>>();
+    ranges_by_path.sort_by_key(|(path, _)| path.clone());
+
+    assert_eq!(
+        ranges_by_path,
+        &[
+            (PathBuf::from("dir-1/a"), vec![6..11]),
+            (PathBuf::from("dir-1/c"), vec![2..7]),
+            (PathBuf::from("dir-1/d"), vec![0..5, 24..29]),
+            (PathBuf::from("dir-2/e"), vec![7..12]),
+        ]
+    );
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_document_highlights(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/root-1",
+            json!({
+                "main.rs": "fn double(number: i32) -> i32 { number + number }",
+            }),
+        )
+        .await;
+
+    // Set up a fake language server.
+    let mut language = Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["rs".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    );
+    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
+    client_a.language_registry().add(Arc::new(language));
+
+    let (project_a, worktree_id) = client_a.build_local_project("/root-1", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    // Open the file on client B.
+    let open_b = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "main.rs"), cx));
+    let buffer_b = cx_b.executor().spawn(open_b).await.unwrap();
+
+    // Request document highlights as the guest.
+    let fake_language_server = fake_language_servers.next().await.unwrap();
+    fake_language_server.handle_request::<lsp::request::DocumentHighlightRequest, _, _>(
+        |params, _| async move {
+            assert_eq!(
+                params
+                    .text_document_position_params
+                    .text_document
+                    .uri
+                    .as_str(),
+                "file:///root-1/main.rs"
+            );
+            assert_eq!(
+                params.text_document_position_params.position,
+                lsp::Position::new(0, 34)
+            );
+            Ok(Some(vec![
+                lsp::DocumentHighlight {
+                    kind: Some(lsp::DocumentHighlightKind::WRITE),
+                    range: lsp::Range::new(lsp::Position::new(0, 10), lsp::Position::new(0, 16)),
+                },
+                lsp::DocumentHighlight {
+                    kind: Some(lsp::DocumentHighlightKind::READ),
+                    range: lsp::Range::new(lsp::Position::new(0, 32), lsp::Position::new(0, 38)),
+                },
+                lsp::DocumentHighlight {
+                    kind: Some(lsp::DocumentHighlightKind::READ),
+                    range: lsp::Range::new(lsp::Position::new(0, 41), lsp::Position::new(0, 47)),
+                },
+            ]))
+        },
+    );
+
+    let highlights = project_b
+        .update(cx_b, |p, cx| p.document_highlights(&buffer_b, 34, cx))
+        .await
+        .unwrap();
+
+    buffer_b.read_with(cx_b, |buffer, _| {
+        let snapshot = buffer.snapshot();
+
+        let highlights = highlights
+            .into_iter()
+            .map(|highlight| (highlight.kind, highlight.range.to_offset(&snapshot)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            highlights,
+            &[
+                (lsp::DocumentHighlightKind::WRITE, 10..16),
+                (lsp::DocumentHighlightKind::READ, 32..38),
+                (lsp::DocumentHighlightKind::READ, 41..47)
+            ]
+        )
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_lsp_hover(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/root-1",
+            json!({
+                "main.rs": "use std::collections::HashMap;",
+            }),
+        )
+        .await;
+
+    // Set up a fake language server.
+    let mut language = Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["rs".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    );
+    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
+    client_a.language_registry().add(Arc::new(language));
+
+    let (project_a, worktree_id) = client_a.build_local_project("/root-1", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    // Open the file as the guest
+    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "main.rs"), cx));
+    let buffer_b = cx_b.executor().spawn(open_buffer).await.unwrap();
+
+    // Request hover information as the guest.
+    let fake_language_server = fake_language_servers.next().await.unwrap();
+    fake_language_server.handle_request::<lsp::request::HoverRequest, _, _>(
+        |params, _| async move {
+            assert_eq!(
+                params
+                    .text_document_position_params
+                    .text_document
+                    .uri
+                    .as_str(),
+                "file:///root-1/main.rs"
+            );
+            assert_eq!(
+                params.text_document_position_params.position,
+                lsp::Position::new(0, 22)
+            );
+            Ok(Some(lsp::Hover {
+                contents: lsp::HoverContents::Array(vec![
+                    lsp::MarkedString::String("Test hover content.".to_string()),
+                    lsp::MarkedString::LanguageString(lsp::LanguageString {
+                        language: "Rust".to_string(),
+                        value: "let foo = 42;".to_string(),
+                    }),
+                ]),
+                range: Some(lsp::Range::new(
+                    lsp::Position::new(0, 22),
+                    lsp::Position::new(0, 29),
+                )),
+            }))
+        },
+    );
+
+    let hover_info = project_b
+        .update(cx_b, |p, cx| p.hover(&buffer_b, 22, cx))
+        .await
+        .unwrap()
+        .unwrap();
+
+    buffer_b.read_with(cx_b, |buffer, _| {
+        let snapshot = buffer.snapshot();
+        assert_eq!(hover_info.range.unwrap().to_offset(&snapshot), 22..29);
+        assert_eq!(
+            hover_info.contents,
+            vec![
+                project::HoverBlock {
+                    text: "Test hover content.".to_string(),
+                    kind: HoverBlockKind::Markdown,
+                },
+                project::HoverBlock {
+                    text: "let foo = 42;".to_string(),
+                    kind: HoverBlockKind::Code {
+                        language: "Rust".to_string()
+                    },
+                }
+            ]
+        );
+    });
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_project_symbols(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    // Set up a fake language server.
+    let mut language = Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["rs".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    );
+    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
+    client_a.language_registry().add(Arc::new(language));
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/code",
+            json!({
+                "crate-1": {
+                    "one.rs": "const ONE: usize = 1;",
+                },
+                "crate-2": {
+                    "two.rs": "const TWO: usize = 2; const THREE: usize = 3;",
+                },
+                "private": {
+                    "passwords.txt": "the-password",
+                }
+            }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/code/crate-1", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    // Cause the language server to start.
+    let open_buffer_task =
+        project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "one.rs"), cx));
+    let _buffer = cx_b.executor().spawn(open_buffer_task).await.unwrap();
+
+    let fake_language_server = fake_language_servers.next().await.unwrap();
+    fake_language_server.handle_request::<lsp::WorkspaceSymbolRequest, _, _>(|_, _| async move {
+        Ok(Some(lsp::WorkspaceSymbolResponse::Flat(vec![
+            #[allow(deprecated)]
+            lsp::SymbolInformation {
+                name: "TWO".into(),
+                location: lsp::Location {
+                    uri: lsp::Url::from_file_path("/code/crate-2/two.rs").unwrap(),
+                    range: lsp::Range::new(lsp::Position::new(0, 6), lsp::Position::new(0, 9)),
+                },
+                kind: lsp::SymbolKind::CONSTANT,
+                tags: None,
+                container_name: None,
+                deprecated: None,
+            },
+        ])))
+    });
+
+    // Request the definition of a symbol as the guest.
+    let symbols = project_b
+        .update(cx_b, |p, cx| p.symbols("two", cx))
+        .await
+        .unwrap();
+    assert_eq!(symbols.len(), 1);
+    assert_eq!(symbols[0].name, "TWO");
+
+    // Open one of the returned symbols.
+    let buffer_b_2 = project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer_for_symbol(&symbols[0], cx)
+        })
+        .await
+        .unwrap();
+
+    buffer_b_2.read_with(cx_b, |buffer, cx| {
+        assert_eq!(
+            buffer.file().unwrap().full_path(cx),
+            Path::new("/code/crate-2/two.rs")
+        );
+    });
+
+    // Attempt to craft a symbol and violate host's privacy by opening an arbitrary file.
+    let mut fake_symbol = symbols[0].clone();
+    fake_symbol.path.path = Path::new("/code/secrets").into();
+    let error = project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer_for_symbol(&fake_symbol, cx)
+        })
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("invalid symbol signature"));
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_open_buffer_while_getting_definition_pointing_to_it(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    mut rng: StdRng,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    // Set up a fake language server.
+    let mut language = Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["rs".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    );
+    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
+    client_a.language_registry().add(Arc::new(language));
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/root",
+            json!({
+                "a.rs": "const ONE: usize = b::TWO;",
+                "b.rs": "const TWO: usize = 2",
+            }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/root", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    let open_buffer_task = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx));
+    let buffer_b1 = cx_b.executor().spawn(open_buffer_task).await.unwrap();
+
+    let fake_language_server = fake_language_servers.next().await.unwrap();
+    fake_language_server.handle_request::<lsp::request::GotoDefinition, _, _>(|_, _| async move {
+        Ok(Some(lsp::GotoDefinitionResponse::Scalar(
+            lsp::Location::new(
+                lsp::Url::from_file_path("/root/b.rs").unwrap(),
+                lsp::Range::new(lsp::Position::new(0, 6), lsp::Position::new(0, 9)),
+            ),
+        )))
+    });
+
+    let definitions;
+    let buffer_b2;
+    if rng.gen() {
+        definitions = project_b.update(cx_b, |p, cx| p.definition(&buffer_b1, 23, cx));
+        buffer_b2 = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "b.rs"), cx));
+    } else {
+        buffer_b2 = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "b.rs"), cx));
+        definitions = project_b.update(cx_b, |p, cx| p.definition(&buffer_b1, 23, cx));
+    }
+
+    let buffer_b2 = buffer_b2.await.unwrap();
+    let definitions = definitions.await.unwrap();
+    assert_eq!(definitions.len(), 1);
+    assert_eq!(definitions[0].target.buffer, buffer_b2);
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_contacts(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+    cx_d: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    let client_d = server.create_client(cx_d, "user_d").await;
+    server
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
+    let active_call_c = cx_c.read(ActiveCall::global);
+    let _active_call_d = cx_d.read(ActiveCall::global);
+
+    executor.run_until_parked();
+    assert_eq!(
+        contacts(&client_a, cx_a),
+        [
+            ("user_b".to_string(), "online", "free"),
+            ("user_c".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_b, cx_b),
+        [
+            ("user_a".to_string(), "online", "free"),
+            ("user_c".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_c, cx_c),
+        [
+            ("user_a".to_string(), "online", "free"),
+            ("user_b".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(contacts(&client_d, cx_d), []);
+
+    server.disconnect_client(client_c.peer_id().unwrap());
+    server.forbid_connections();
+    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
+    assert_eq!(
+        contacts(&client_a, cx_a),
+        [
+            ("user_b".to_string(), "online", "free"),
+            ("user_c".to_string(), "offline", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_b, cx_b),
+        [
+            ("user_a".to_string(), "online", "free"),
+            ("user_c".to_string(), "offline", "free")
+        ]
+    );
+    assert_eq!(contacts(&client_c, cx_c), []);
+    assert_eq!(contacts(&client_d, cx_d), []);
+
+    server.allow_connections();
+    client_c
+        .authenticate_and_connect(false, &cx_c.to_async())
+        .await
+        .unwrap();
+
+    executor.run_until_parked();
+    assert_eq!(
+        contacts(&client_a, cx_a),
+        [
+            ("user_b".to_string(), "online", "free"),
+            ("user_c".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_b, cx_b),
+        [
+            ("user_a".to_string(), "online", "free"),
+            ("user_c".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_c, cx_c),
+        [
+            ("user_a".to_string(), "online", "free"),
+            ("user_b".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(contacts(&client_d, cx_d), []);
+
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b.user_id().unwrap(), None, cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert_eq!(
+        contacts(&client_a, cx_a),
+        [
+            ("user_b".to_string(), "online", "busy"),
+            ("user_c".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_b, cx_b),
+        [
+            ("user_a".to_string(), "online", "busy"),
+            ("user_c".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_c, cx_c),
+        [
+            ("user_a".to_string(), "online", "busy"),
+            ("user_b".to_string(), "online", "busy")
+        ]
+    );
+    assert_eq!(contacts(&client_d, cx_d), []);
+
+    // Client B and client D become contacts while client B is being called.
+    server
+        .make_contacts(&mut [(&client_b, cx_b), (&client_d, cx_d)])
+        .await;
+    executor.run_until_parked();
+    assert_eq!(
+        contacts(&client_a, cx_a),
+        [
+            ("user_b".to_string(), "online", "busy"),
+            ("user_c".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_b, cx_b),
+        [
+            ("user_a".to_string(), "online", "busy"),
+            ("user_c".to_string(), "online", "free"),
+            ("user_d".to_string(), "online", "free"),
+        ]
+    );
+    assert_eq!(
+        contacts(&client_c, cx_c),
+        [
+            ("user_a".to_string(), "online", "busy"),
+            ("user_b".to_string(), "online", "busy")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_d, cx_d),
+        [("user_b".to_string(), "online", "busy")]
+    );
+
+    active_call_b.update(cx_b, |call, cx| call.decline_incoming(cx).unwrap());
+    executor.run_until_parked();
+    assert_eq!(
+        contacts(&client_a, cx_a),
+        [
+            ("user_b".to_string(), "online", "free"),
+            ("user_c".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_b, cx_b),
+        [
+            ("user_a".to_string(), "online", "free"),
+            ("user_c".to_string(), "online", "free"),
+            ("user_d".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_c, cx_c),
+        [
+            ("user_a".to_string(), "online", "free"),
+            ("user_b".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_d, cx_d),
+        [("user_b".to_string(), "online", "free")]
+    );
+
+    active_call_c
+        .update(cx_c, |call, cx| {
+            call.invite(client_a.user_id().unwrap(), None, cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert_eq!(
+        contacts(&client_a, cx_a),
+        [
+            ("user_b".to_string(), "online", "free"),
+            ("user_c".to_string(), "online", "busy")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_b, cx_b),
+        [
+            ("user_a".to_string(), "online", "busy"),
+            ("user_c".to_string(), "online", "busy"),
+            ("user_d".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_c, cx_c),
+        [
+            ("user_a".to_string(), "online", "busy"),
+            ("user_b".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_d, cx_d),
+        [("user_b".to_string(), "online", "free")]
+    );
+
+    active_call_a
+        .update(cx_a, |call, cx| call.accept_incoming(cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert_eq!(
+        contacts(&client_a, cx_a),
+        [
+            ("user_b".to_string(), "online", "free"),
+            ("user_c".to_string(), "online", "busy")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_b, cx_b),
+        [
+            ("user_a".to_string(), "online", "busy"),
+            ("user_c".to_string(), "online", "busy"),
+            ("user_d".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_c, cx_c),
+        [
+            ("user_a".to_string(), "online", "busy"),
+            ("user_b".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_d, cx_d),
+        [("user_b".to_string(), "online", "free")]
+    );
+
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b.user_id().unwrap(), None, cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert_eq!(
+        contacts(&client_a, cx_a),
+        [
+            ("user_b".to_string(), "online", "busy"),
+            ("user_c".to_string(), "online", "busy")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_b, cx_b),
+        [
+            ("user_a".to_string(), "online", "busy"),
+            ("user_c".to_string(), "online", "busy"),
+            ("user_d".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_c, cx_c),
+        [
+            ("user_a".to_string(), "online", "busy"),
+            ("user_b".to_string(), "online", "busy")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_d, cx_d),
+        [("user_b".to_string(), "online", "busy")]
+    );
+
+    active_call_a
+        .update(cx_a, |call, cx| call.hang_up(cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert_eq!(
+        contacts(&client_a, cx_a),
+        [
+            ("user_b".to_string(), "online", "free"),
+            ("user_c".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_b, cx_b),
+        [
+            ("user_a".to_string(), "online", "free"),
+            ("user_c".to_string(), "online", "free"),
+            ("user_d".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_c, cx_c),
+        [
+            ("user_a".to_string(), "online", "free"),
+            ("user_b".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_d, cx_d),
+        [("user_b".to_string(), "online", "free")]
+    );
+
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b.user_id().unwrap(), None, cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert_eq!(
+        contacts(&client_a, cx_a),
+        [
+            ("user_b".to_string(), "online", "busy"),
+            ("user_c".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_b, cx_b),
+        [
+            ("user_a".to_string(), "online", "busy"),
+            ("user_c".to_string(), "online", "free"),
+            ("user_d".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_c, cx_c),
+        [
+            ("user_a".to_string(), "online", "busy"),
+            ("user_b".to_string(), "online", "busy")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_d, cx_d),
+        [("user_b".to_string(), "online", "busy")]
+    );
+
+    server.forbid_connections();
+    server.disconnect_client(client_a.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
+    assert_eq!(contacts(&client_a, cx_a), []);
+    assert_eq!(
+        contacts(&client_b, cx_b),
+        [
+            ("user_a".to_string(), "offline", "free"),
+            ("user_c".to_string(), "online", "free"),
+            ("user_d".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_c, cx_c),
+        [
+            ("user_a".to_string(), "offline", "free"),
+            ("user_b".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_d, cx_d),
+        [("user_b".to_string(), "online", "free")]
+    );
+
+    // Test removing a contact
+    client_b
+        .user_store()
+        .update(cx_b, |store, cx| {
+            store.remove_contact(client_c.user_id().unwrap(), cx)
+        })
+        .await
+        .unwrap();
     executor.run_until_parked();
-    assert!(disk_based_diagnostics_finished.load(SeqCst));
+    assert_eq!(
+        contacts(&client_b, cx_b),
+        [
+            ("user_a".to_string(), "offline", "free"),
+            ("user_d".to_string(), "online", "free")
+        ]
+    );
+    assert_eq!(
+        contacts(&client_c, cx_c),
+        [("user_a".to_string(), "offline", "free"),]
+    );
+
+    fn contacts(
+        client: &TestClient,
+        cx: &TestAppContext,
+    ) -> Vec<(String, &'static str, &'static str)> {
+        client.user_store().read_with(cx, |store, _| {
+            store
+                .contacts()
+                .iter()
+                .map(|contact| {
+                    (
+                        contact.user.github_login.clone(),
+                        if contact.online { "online" } else { "offline" },
+                        if contact.busy { "busy" } else { "free" },
+                    )
+                })
+                .collect()
+        })
+    }
 }
 
 #[gpui::test(iterations = 10)]
-async fn test_reloading_buffer_manually(
+async fn test_contact_presence_across_devices(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
+    cx_a2: &mut TestAppContext,
     cx_b: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
+    let client_a2 = server.create_client(cx_a2, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
     server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
-        .await;
-    let active_call_a = cx_a.read(ActiveCall::global);
-
-    client_a
-        .fs()
-        .insert_tree("/a", json!({ "a.rs": "let one = 1;" }))
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
-    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
-    let buffer_a = project_a
-        .update(cx_a, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx))
-        .await
-        .unwrap();
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
-        .await
-        .unwrap();
-
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    let active_call_a2 = cx_a2.read(ActiveCall::global);
 
-    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx));
-    let buffer_b = cx_b.executor().spawn(open_buffer).await.unwrap();
-    buffer_b.update(cx_b, |buffer, cx| {
-        buffer.edit([(4..7, "six")], None, cx);
-        buffer.edit([(10..11, "6")], None, cx);
-        assert_eq!(buffer.text(), "let six = 6;");
-        assert!(buffer.is_dirty());
-        assert!(!buffer.has_conflict());
-    });
+    // User B sees both of user A's devices, neither of which is in a call.
     executor.run_until_parked();
+    assert_eq!(
+        contact_devices(&client_b, cx_b),
+        [(
+            "user_a".to_string(),
+            true,
+            false,
+            device_peer_ids(&[(&client_a, false), (&client_a2, false)])
+        )]
+    );
 
-    buffer_a.read_with(cx_a, |buffer, _| assert_eq!(buffer.text(), "let six = 6;"));
-
-    client_a
-        .fs()
-        .save(
-            "/a/a.rs".as_ref(),
-            &Rope::from("let seven = 7;"),
-            LineEnding::Unix,
-        )
+    // User A calls user B from their second device, so they're busy on that device only.
+    active_call_a2
+        .update(cx_a2, |call, cx| {
+            call.invite(client_b.user_id().unwrap(), None, cx)
+        })
         .await
         .unwrap();
     executor.run_until_parked();
+    assert_eq!(
+        contact_devices(&client_b, cx_b),
+        [(
+            "user_a".to_string(),
+            true,
+            true,
+            device_peer_ids(&[(&client_a, false), (&client_a2, true)])
+        )]
+    );
 
-    buffer_a.read_with(cx_a, |buffer, _| assert!(buffer.has_conflict()));
-
-    buffer_b.read_with(cx_b, |buffer, _| assert!(buffer.has_conflict()));
+    // User A's first device going offline leaves them online and busy.
+    server.forbid_connections();
+    server.disconnect_client(client_a.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
+    assert_eq!(
+        contact_devices(&client_b, cx_b),
+        [(
+            "user_a".to_string(),
+            true,
+            true,
+            device_peer_ids(&[(&client_a2, true)])
+        )]
+    );
 
-    project_b
-        .update(cx_b, |project, cx| {
-            project.reload_buffers(HashSet::from_iter([buffer_b.clone()]), true, cx)
-        })
+    // Once user A hangs up, they're free on every device.
+    active_call_a2
+        .update(cx_a2, |call, cx| call.hang_up(cx))
         .await
         .unwrap();
+    executor.run_until_parked();
+    assert_eq!(
+        contact_devices(&client_b, cx_b),
+        [(
+            "user_a".to_string(),
+            true,
+            false,
+            device_peer_ids(&[(&client_a2, false)])
+        )]
+    );
 
-    buffer_a.read_with(cx_a, |buffer, _| {
-        assert_eq!(buffer.text(), "let seven = 7;");
-        assert!(!buffer.is_dirty());
-        assert!(!buffer.has_conflict());
-    });
-
-    buffer_b.read_with(cx_b, |buffer, _| {
-        assert_eq!(buffer.text(), "let seven = 7;");
-        assert!(!buffer.is_dirty());
-        assert!(!buffer.has_conflict());
-    });
+    fn contact_devices(
+        client: &TestClient,
+        cx: &TestAppContext,
+    ) -> Vec<(String, bool, bool, Vec<(PeerId, bool)>)> {
+        client.user_store().read_with(cx, |store, _| {
+            store
+                .contacts()
+                .iter()
+                .map(|contact| {
+                    let mut devices = contact
+                        .devices
+                        .iter()
+                        .map(|device| (device.peer_id, device.busy))
+                        .collect::<Vec<_>>();
+                    devices.sort_by_key(|(peer_id, _)| (peer_id.owner_id, peer_id.id));
+                    (
+                        contact.user.github_login.clone(),
+                        contact.online,
+                        contact.busy,
+                        devices,
+                    )
+                })
+                .collect()
+        })
+    }
 
-    buffer_a.update(cx_a, |buffer, cx| {
-        // Undoing on the host is a no-op when the reload was initiated by the guest.
-        buffer.undo(cx);
-        assert_eq!(buffer.text(), "let seven = 7;");
-        assert!(!buffer.is_dirty());
-        assert!(!buffer.has_conflict());
-    });
-    buffer_b.update(cx_b, |buffer, cx| {
-        // Undoing on the guest rolls back the buffer to before it was reloaded but the conflict gets cleared.
-        buffer.undo(cx);
-        assert_eq!(buffer.text(), "let six = 6;");
-        assert!(buffer.is_dirty());
-        assert!(!buffer.has_conflict());
-    });
+    fn device_peer_ids(devices: &[(&TestClient, bool)]) -> Vec<(PeerId, bool)> {
+        let mut devices = devices
+            .iter()
+            .map(|(client, busy)| (client.peer_id().unwrap(), *busy))
+            .collect::<Vec<_>>();
+        devices.sort_by_key(|(peer_id, _)| (peer_id.owner_id, peer_id.id));
+        devices
+    }
 }
 
-#[gpui::test(iterations = 10)]
-async fn test_formatting_buffer(
+#[gpui::test]
+async fn test_connection_pool_introspection(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
+    cx_a2: &mut TestAppContext,
     cx_b: &mut TestAppContext,
 ) {
-    executor.allow_parking();
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
+    let client_a2 = server.create_client(cx_a2, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
+    let user_a = client_a.current_user_id(cx_a);
+    let user_b = client_b.current_user_id(cx_b);
+    assert_eq!(server.user_connection_count(user_a), 2);
+    assert_eq!(server.user_connection_count(user_b), 1);
+
     server
         .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
+    executor.run_until_parked();
     let active_call_a = cx_a.read(ActiveCall::global);
+    let room_id = RoomId::from_proto(
+        active_call_a.read_with(cx_a, |call, cx| call.room().unwrap().read(cx).id()),
+    );
 
-    // Set up a fake language server.
-    let mut language = Language::new(
-        LanguageConfig {
-            name: "Rust".into(),
-            matcher: LanguageMatcher {
-                path_suffixes: vec!["rs".to_string()],
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        Some(tree_sitter_rust::language()),
+    // Only the device that joined the room is reported as being in it, and nothing
+    // is left queued once the system has settled.
+    let mut stats_a = server.user_connection_stats(user_a).await;
+    stats_a.sort_by_key(|stats| stats.connection_id);
+    let mut expected_a = vec![
+        (client_a.peer_id().unwrap(), vec![room_id]),
+        (client_a2.peer_id().unwrap(), vec![]),
+    ];
+    expected_a.sort_by_key(|(peer_id, _)| (peer_id.owner_id, peer_id.id));
+    assert_eq!(
+        stats_a
+            .iter()
+            .map(|stats| (PeerId::from(stats.connection_id), stats.room_ids.clone()))
+            .collect::<Vec<_>>(),
+        expected_a
+    );
+    assert!(stats_a.iter().all(|stats| stats.queued_message_count == 0));
+
+    let stats_b = server.user_connection_stats(user_b).await;
+    assert_eq!(stats_b.len(), 1);
+    assert_eq!(stats_b[0].room_ids, [room_id]);
+
+    // A disconnected device is removed from the pool.
+    server.forbid_connections();
+    server.disconnect_client(client_a2.peer_id().unwrap());
+    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
+    assert_eq!(server.user_connection_count(user_a), 1);
+    assert_eq!(server.user_connection_stats(user_a).await.len(), 1);
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_contact_requests(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_a2: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_b2: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+    cx_c2: &mut TestAppContext,
+) {
+    // Connect to a server as 3 clients.
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_a2 = server.create_client(cx_a2, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_b2 = server.create_client(cx_b2, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    let client_c2 = server.create_client(cx_c2, "user_c").await;
+
+    assert_eq!(client_a.user_id().unwrap(), client_a2.user_id().unwrap());
+    assert_eq!(client_b.user_id().unwrap(), client_b2.user_id().unwrap());
+    assert_eq!(client_c.user_id().unwrap(), client_c2.user_id().unwrap());
+
+    // User A and User C request that user B become their contact.
+    client_a
+        .user_store()
+        .update(cx_a, |store, cx| {
+            store.request_contact(client_b.user_id().unwrap(), cx)
+        })
+        .await
+        .unwrap();
+    client_c
+        .user_store()
+        .update(cx_c, |store, cx| {
+            store.request_contact(client_b.user_id().unwrap(), cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+
+    // All users see the pending request appear in all their clients.
+    assert_eq!(
+        client_a.summarize_contacts(cx_a).outgoing_requests,
+        &["user_b"]
+    );
+    assert_eq!(
+        client_a2.summarize_contacts(cx_a2).outgoing_requests,
+        &["user_b"]
+    );
+    assert_eq!(
+        client_b.summarize_contacts(cx_b).incoming_requests,
+        &["user_a", "user_c"]
+    );
+    assert_eq!(
+        client_b2.summarize_contacts(cx_b2).incoming_requests,
+        &["user_a", "user_c"]
+    );
+    assert_eq!(
+        client_c.summarize_contacts(cx_c).outgoing_requests,
+        &["user_b"]
+    );
+    assert_eq!(
+        client_c2.summarize_contacts(cx_c2).outgoing_requests,
+        &["user_b"]
+    );
+
+    // Contact requests are present upon connecting (tested here via disconnect/reconnect)
+    disconnect_and_reconnect(&client_a, cx_a).await;
+    disconnect_and_reconnect(&client_b, cx_b).await;
+    disconnect_and_reconnect(&client_c, cx_c).await;
+    executor.run_until_parked();
+    assert_eq!(
+        client_a.summarize_contacts(cx_a).outgoing_requests,
+        &["user_b"]
+    );
+    assert_eq!(
+        client_b.summarize_contacts(cx_b).incoming_requests,
+        &["user_a", "user_c"]
+    );
+    assert_eq!(
+        client_c.summarize_contacts(cx_c).outgoing_requests,
+        &["user_b"]
     );
-    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
-    client_a.language_registry().add(Arc::new(language));
 
-    // Here we insert a fake tree with a directory that exists on disk. This is needed
-    // because later we'll invoke a command, which requires passing a working directory
-    // that points to a valid location on disk.
-    let directory = env::current_dir().unwrap();
-    client_a
-        .fs()
-        .insert_tree(&directory, json!({ "a.rs": "let one = \"two\"" }))
-        .await;
-    let (project_a, worktree_id) = client_a.build_local_project(&directory, cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+    // User B accepts the request from user A.
+    client_b
+        .user_store()
+        .update(cx_b, |store, cx| {
+            store.respond_to_contact_request(client_a.user_id().unwrap(), true, cx)
+        })
         .await
         .unwrap();
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
 
-    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx));
-    let buffer_b = cx_b.executor().spawn(open_buffer).await.unwrap();
+    executor.run_until_parked();
 
-    let fake_language_server = fake_language_servers.next().await.unwrap();
-    fake_language_server.handle_request::<lsp::request::Formatting, _, _>(|_, _| async move {
-        Ok(Some(vec![
-            lsp::TextEdit {
-                range: lsp::Range::new(lsp::Position::new(0, 4), lsp::Position::new(0, 4)),
-                new_text: "h".to_string(),
-            },
-            lsp::TextEdit {
-                range: lsp::Range::new(lsp::Position::new(0, 7), lsp::Position::new(0, 7)),
-                new_text: "y".to_string(),
-            },
-        ]))
-    });
+    // User B sees user A as their contact now in all client, and the incoming request from them is removed.
+    let contacts_b = client_b.summarize_contacts(cx_b);
+    assert_eq!(contacts_b.current, &["user_a"]);
+    assert_eq!(contacts_b.incoming_requests, &["user_c"]);
+    let contacts_b2 = client_b2.summarize_contacts(cx_b2);
+    assert_eq!(contacts_b2.current, &["user_a"]);
+    assert_eq!(contacts_b2.incoming_requests, &["user_c"]);
 
-    project_b
-        .update(cx_b, |project, cx| {
-            project.format(
-                HashSet::from_iter([buffer_b.clone()]),
-                true,
-                FormatTrigger::Save,
-                cx,
-            )
-        })
-        .await
-        .unwrap();
+    // User A sees user B as their contact now in all clients, and the outgoing request to them is removed.
+    let contacts_a = client_a.summarize_contacts(cx_a);
+    assert_eq!(contacts_a.current, &["user_b"]);
+    assert!(contacts_a.outgoing_requests.is_empty());
+    let contacts_a2 = client_a2.summarize_contacts(cx_a2);
+    assert_eq!(contacts_a2.current, &["user_b"]);
+    assert!(contacts_a2.outgoing_requests.is_empty());
 
-    // The edits from the LSP are applied, and a final newline is added.
+    // Contacts are present upon connecting (tested here via disconnect/reconnect)
+    disconnect_and_reconnect(&client_a, cx_a).await;
+    disconnect_and_reconnect(&client_b, cx_b).await;
+    disconnect_and_reconnect(&client_c, cx_c).await;
+    executor.run_until_parked();
+    assert_eq!(client_a.summarize_contacts(cx_a).current, &["user_b"]);
+    assert_eq!(client_b.summarize_contacts(cx_b).current, &["user_a"]);
     assert_eq!(
-        buffer_b.read_with(cx_b, |buffer, _| buffer.text()),
-        "let honey = \"two\"\n"
+        client_b.summarize_contacts(cx_b).incoming_requests,
+        &["user_c"]
+    );
+    assert!(client_c.summarize_contacts(cx_c).current.is_empty());
+    assert_eq!(
+        client_c.summarize_contacts(cx_c).outgoing_requests,
+        &["user_b"]
     );
 
-    // Ensure buffer can be formatted using an external command. Notice how the
-    // host's configuration is honored as opposed to using the guest's settings.
-    cx_a.update(|cx| {
-        cx.update_global(|store: &mut SettingsStore, cx| {
-            store.update_user_settings::<AllLanguageSettings>(cx, |file| {
-                file.defaults.formatter = Some(Formatter::External {
-                    command: "awk".into(),
-                    arguments: vec!["{sub(/two/,\"{buffer_path}\")}1".to_string()].into(),
-                });
-            });
-        });
-    });
-    project_b
-        .update(cx_b, |project, cx| {
-            project.format(
-                HashSet::from_iter([buffer_b.clone()]),
-                true,
-                FormatTrigger::Save,
-                cx,
-            )
+    // User B rejects the request from user C.
+    client_b
+        .user_store()
+        .update(cx_b, |store, cx| {
+            store.respond_to_contact_request(client_c.user_id().unwrap(), false, cx)
         })
         .await
         .unwrap();
-    assert_eq!(
-        buffer_b.read_with(cx_b, |buffer, _| buffer.text()),
-        format!("let honey = \"{}/a.rs\"\n", directory.to_str().unwrap())
-    );
+
+    executor.run_until_parked();
+
+    // User B doesn't see user C as their contact, and the incoming request from them is removed.
+    let contacts_b = client_b.summarize_contacts(cx_b);
+    assert_eq!(contacts_b.current, &["user_a"]);
+    assert!(contacts_b.incoming_requests.is_empty());
+    let contacts_b2 = client_b2.summarize_contacts(cx_b2);
+    assert_eq!(contacts_b2.current, &["user_a"]);
+    assert!(contacts_b2.incoming_requests.is_empty());
+
+    // User C doesn't see user B as their contact, and the outgoing request to them is removed.
+    let contacts_c = client_c.summarize_contacts(cx_c);
+    assert!(contacts_c.current.is_empty());
+    assert!(contacts_c.outgoing_requests.is_empty());
+    let contacts_c2 = client_c2.summarize_contacts(cx_c2);
+    assert!(contacts_c2.current.is_empty());
+    assert!(contacts_c2.outgoing_requests.is_empty());
+
+    // Incoming/outgoing requests are not present upon connecting (tested here via disconnect/reconnect)
+    disconnect_and_reconnect(&client_a, cx_a).await;
+    disconnect_and_reconnect(&client_b, cx_b).await;
+    disconnect_and_reconnect(&client_c, cx_c).await;
+    executor.run_until_parked();
+    assert_eq!(client_a.summarize_contacts(cx_a).current, &["user_b"]);
+    assert_eq!(client_b.summarize_contacts(cx_b).current, &["user_a"]);
+    assert!(client_b
+        .summarize_contacts(cx_b)
+        .incoming_requests
+        .is_empty());
+    assert!(client_c.summarize_contacts(cx_c).current.is_empty());
+    assert!(client_c
+        .summarize_contacts(cx_c)
+        .outgoing_requests
+        .is_empty());
+
+    async fn disconnect_and_reconnect(client: &TestClient, cx: &mut TestAppContext) {
+        client.disconnect(&cx.to_async());
+        client.clear_contacts(cx).await;
+        client
+            .authenticate_and_connect(false, &cx.to_async())
+            .await
+            .unwrap();
+    }
 }
 
 #[gpui::test(iterations = 10)]
-async fn test_prettier_formatting_buffer(
+async fn test_contact_notes(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
+    cx_a2: &mut TestAppContext,
     cx_b: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
+    let client_a2 = server.create_client(cx_a2, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
     server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
-    let active_call_a = cx_a.read(ActiveCall::global);
+    executor.run_until_parked();
 
-    // Set up a fake language server.
-    let mut language = Language::new(
-        LanguageConfig {
-            name: "Rust".into(),
-            matcher: LanguageMatcher {
-                path_suffixes: vec!["rs".to_string()],
-                ..Default::default()
-            },
-            prettier_parser_name: Some("test_parser".to_string()),
-            ..Default::default()
-        },
-        Some(tree_sitter_rust::language()),
-    );
-    let test_plugin = "test_plugin";
-    let mut fake_language_servers = language
-        .set_fake_lsp_adapter(Arc::new(FakeLspAdapter {
-            prettier_plugins: vec![test_plugin],
-            ..Default::default()
-        }))
-        .await;
-    let language = Arc::new(language);
-    client_a.language_registry().add(Arc::clone(&language));
+    let user_a_id = client_a.user_id().unwrap();
+    let user_b_id = client_b.user_id().unwrap();
 
-    // Here we insert a fake tree with a directory that exists on disk. This is needed
-    // because later we'll invoke a command, which requires passing a working directory
-    // that points to a valid location on disk.
-    let directory = env::current_dir().unwrap();
-    let buffer_text = "let one = \"two\"";
+    // User A gives user B a nickname and a note.
     client_a
-        .fs()
-        .insert_tree(&directory, json!({ "a.rs": buffer_text }))
-        .await;
-    let (project_a, worktree_id) = client_a.build_local_project(&directory, cx_a).await;
-    let prettier_format_suffix = project::TEST_PRETTIER_FORMAT_SUFFIX;
-    let open_buffer = project_a.update(cx_a, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx));
-    let buffer_a = cx_a.executor().spawn(open_buffer).await.unwrap();
-
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
-        .await
-        .unwrap();
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
-    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx));
-    let buffer_b = cx_b.executor().spawn(open_buffer).await.unwrap();
-
-    cx_a.update(|cx| {
-        cx.update_global(|store: &mut SettingsStore, cx| {
-            store.update_user_settings::<AllLanguageSettings>(cx, |file| {
-                file.defaults.formatter = Some(Formatter::Auto);
-            });
-        });
-    });
-    cx_b.update(|cx| {
-        cx.update_global(|store: &mut SettingsStore, cx| {
-            store.update_user_settings::<AllLanguageSettings>(cx, |file| {
-                file.defaults.formatter = Some(Formatter::LanguageServer);
-            });
-        });
-    });
-    let fake_language_server = fake_language_servers.next().await.unwrap();
-    fake_language_server.handle_request::<lsp::request::Formatting, _, _>(|_, _| async move {
-        panic!(
-            "Unexpected: prettier should be preferred since it's enabled and language supports it"
-        )
-    });
-
-    project_b
-        .update(cx_b, |project, cx| {
-            project.format(
-                HashSet::from_iter([buffer_b.clone()]),
-                true,
-                FormatTrigger::Save,
+        .user_store()
+        .update(cx_a, |store, cx| {
+            store.set_contact_note(
+                user_b_id,
+                Some("Bee".into()),
+                Some("Owns the parser".into()),
                 cx,
             )
         })
         .await
         .unwrap();
-
     executor.run_until_parked();
+
+    // The note is visible from all of user A's clients, but not to user B.
+    let expected_note = ContactNote {
+        nickname: Some("Bee".into()),
+        note: Some("Owns the parser".into()),
+    };
     assert_eq!(
-        buffer_b.read_with(cx_b, |buffer, _| buffer.text()),
-        buffer_text.to_string() + "\n" + prettier_format_suffix,
-        "Prettier formatting was not applied to client buffer after client's request"
+        contact_note(&client_a, user_b_id, cx_a),
+        Some(expected_note.clone())
+    );
+    assert_eq!(
+        contact_note(&client_a2, user_b_id, cx_a2),
+        Some(expected_note.clone())
     );
+    assert_eq!(contact_note(&client_b, user_a_id, cx_b), None);
+    assert_eq!(contact_display_name(&client_a, user_b_id, cx_a), "Bee");
 
-    project_a
-        .update(cx_a, |project, cx| {
-            project.format(
-                HashSet::from_iter([buffer_a.clone()]),
-                true,
-                FormatTrigger::Manual,
-                cx,
-            )
+    // Notes are present upon reconnecting.
+    client_a.disconnect(&cx_a.to_async());
+    client_a.clear_contacts(cx_a).await;
+    client_a
+        .authenticate_and_connect(false, &cx_a.to_async())
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert_eq!(
+        contact_note(&client_a, user_b_id, cx_a),
+        Some(expected_note.clone())
+    );
+
+    // Clearing the nickname and note removes them.
+    client_a2
+        .user_store()
+        .update(cx_a2, |store, cx| {
+            store.set_contact_note(user_b_id, None, Some("  ".into()), cx)
         })
         .await
         .unwrap();
-
     executor.run_until_parked();
-    assert_eq!(
-        buffer_b.read_with(cx_b, |buffer, _| buffer.text()),
-        buffer_text.to_string() + "\n" + prettier_format_suffix + "\n" + prettier_format_suffix,
-        "Prettier formatting was not applied to client buffer after host's request"
-    );
+    assert_eq!(contact_note(&client_a, user_b_id, cx_a), None);
+    assert_eq!(contact_display_name(&client_a, user_b_id, cx_a), "user_b");
+
+    // Users can't attach notes to people who aren't their contacts.
+    client_b
+        .user_store()
+        .update(cx_b, |store, cx| store.remove_contact(user_a_id, cx))
+        .await
+        .unwrap();
+    client_a
+        .user_store()
+        .update(cx_a, |store, cx| {
+            store.set_contact_note(user_b_id, Some("Bee".into()), None, cx)
+        })
+        .await
+        .unwrap_err();
+
+    fn contact_note(client: &TestClient, user_id: u64, cx: &TestAppContext) -> Option<ContactNote> {
+        client
+            .user_store()
+            .read_with(cx, |store, _| store.contact_note(user_id).cloned())
+    }
+
+    fn contact_display_name(client: &TestClient, user_id: u64, cx: &TestAppContext) -> String {
+        client.user_store().read_with(cx, |store, _| {
+            let user = store.get_cached_user(user_id).unwrap();
+            store.contact_display_name(&user).to_string()
+        })
+    }
 }
 
 #[gpui::test(iterations = 10)]
-async fn test_definition(
+async fn test_call_history(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
     server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
         .await;
-    let active_call_a = cx_a.read(ActiveCall::global);
 
-    // Set up a fake language server.
-    let mut language = Language::new(
-        LanguageConfig {
-            name: "Rust".into(),
-            matcher: LanguageMatcher {
-                path_suffixes: vec!["rs".to_string()],
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        Some(tree_sitter_rust::language()),
-    );
-    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
-    client_a.language_registry().add(Arc::new(language));
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
+    let active_call_c = cx_c.read(ActiveCall::global);
+    let user_a_id = client_a.user_id().unwrap();
+    let user_b_id = client_b.user_id().unwrap();
+    let user_c_id = client_c.user_id().unwrap();
 
-    client_a
-        .fs()
-        .insert_tree(
-            "/root",
-            json!({
-                "dir-1": {
-                    "a.rs": "const ONE: usize = b::TWO + b::THREE;",
-                },
-                "dir-2": {
-                    "b.rs": "const TWO: c::T2 = 2;\nconst THREE: usize = 3;",
-                    "c.rs": "type T2 = usize;",
-                }
-            }),
-        )
-        .await;
-    let (project_a, worktree_id) = client_a.build_local_project("/root/dir-1", cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+    // User A calls user B, who answers.
+    let mut incoming_call_b = active_call_b.read_with(cx_b, |call, _| call.incoming());
+    active_call_a
+        .update(cx_a, |call, cx| call.invite(user_b_id, None, cx))
         .await
         .unwrap();
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
-
-    // Open the file on client B.
-    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx));
-    let buffer_b = cx_b.executor().spawn(open_buffer).await.unwrap();
-
-    // Request the definition of a symbol as the guest.
-    let fake_language_server = fake_language_servers.next().await.unwrap();
-    fake_language_server.handle_request::<lsp::request::GotoDefinition, _, _>(|_, _| async move {
-        Ok(Some(lsp::GotoDefinitionResponse::Scalar(
-            lsp::Location::new(
-                lsp::Url::from_file_path("/root/dir-2/b.rs").unwrap(),
-                lsp::Range::new(lsp::Position::new(0, 6), lsp::Position::new(0, 9)),
-            ),
-        )))
-    });
+    incoming_call_b.next().await.unwrap().unwrap();
+    active_call_b
+        .update(cx_b, |call, cx| call.accept_incoming(cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
 
-    let definitions_1 = project_b
-        .update(cx_b, |p, cx| p.definition(&buffer_b, 23, cx))
+    // User A calls user C, who declines.
+    let mut incoming_call_c = active_call_c.read_with(cx_c, |call, _| call.incoming());
+    active_call_a
+        .update(cx_a, |call, cx| call.invite(user_c_id, None, cx))
         .await
         .unwrap();
-    cx_b.read(|cx| {
-        assert_eq!(definitions_1.len(), 1);
-        assert_eq!(project_b.read(cx).worktrees().count(), 2);
-        let target_buffer = definitions_1[0].target.buffer.read(cx);
-        assert_eq!(
-            target_buffer.text(),
-            "const TWO: c::T2 = 2;\nconst THREE: usize = 3;"
-        );
-        assert_eq!(
-            definitions_1[0].target.range.to_point(target_buffer),
-            Point::new(0, 6)..Point::new(0, 9)
-        );
-    });
+    incoming_call_c.next().await.unwrap().unwrap();
+    active_call_c.update(cx_c, |call, cx| call.decline_incoming(cx).unwrap());
+    executor.run_until_parked();
 
-    // Try getting more definitions for the same buffer, ensuring the buffer gets reused from
-    // the previous call to `definition`.
-    fake_language_server.handle_request::<lsp::request::GotoDefinition, _, _>(|_, _| async move {
-        Ok(Some(lsp::GotoDefinitionResponse::Scalar(
-            lsp::Location::new(
-                lsp::Url::from_file_path("/root/dir-2/b.rs").unwrap(),
-                lsp::Range::new(lsp::Position::new(1, 6), lsp::Position::new(1, 11)),
-            ),
-        )))
-    });
+    // User A calls user C again, but hangs up before they answer.
+    active_call_a
+        .update(cx_a, |call, cx| call.invite(user_c_id, None, cx))
+        .await
+        .unwrap();
+    incoming_call_c.next().await.unwrap().unwrap();
+    active_call_a
+        .update(cx_a, |call, cx| call.cancel_invite(user_c_id, cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
 
-    let definitions_2 = project_b
-        .update(cx_b, |p, cx| p.definition(&buffer_b, 33, cx))
+    let history_a = active_call_a
+        .update(cx_a, |call, cx| call.call_history(None, cx))
         .await
         .unwrap();
-    cx_b.read(|cx| {
-        assert_eq!(definitions_2.len(), 1);
-        assert_eq!(project_b.read(cx).worktrees().count(), 2);
-        let target_buffer = definitions_2[0].target.buffer.read(cx);
-        assert_eq!(
-            target_buffer.text(),
-            "const TWO: c::T2 = 2;\nconst THREE: usize = 3;"
-        );
-        assert_eq!(
-            definitions_2[0].target.range.to_point(target_buffer),
-            Point::new(1, 6)..Point::new(1, 11)
-        );
-    });
     assert_eq!(
-        definitions_1[0].target.buffer,
-        definitions_2[0].target.buffer
-    );
-
-    fake_language_server.handle_request::<lsp::request::GotoTypeDefinition, _, _>(
-        |req, _| async move {
-            assert_eq!(
-                req.text_document_position_params.position,
-                lsp::Position::new(0, 7)
-            );
-            Ok(Some(lsp::GotoDefinitionResponse::Scalar(
-                lsp::Location::new(
-                    lsp::Url::from_file_path("/root/dir-2/c.rs").unwrap(),
-                    lsp::Range::new(lsp::Position::new(0, 5), lsp::Position::new(0, 7)),
-                ),
-            )))
-        },
+        history_a
+            .iter()
+            .map(|entry| (
+                entry.caller.github_login.as_str(),
+                entry.callee.github_login.as_str(),
+                entry.status
+            ))
+            .collect::<Vec<_>>(),
+        &[
+            ("user_a", "user_b", CallStatus::Answered),
+            ("user_a", "user_c", CallStatus::Declined),
+            ("user_a", "user_c", CallStatus::Missed),
+        ]
     );
 
-    let type_definitions = project_b
-        .update(cx_b, |p, cx| p.type_definition(&buffer_b, 7, cx))
+    // User C only sees the calls they were part of.
+    let history_c = active_call_c
+        .update(cx_c, |call, cx| call.call_history(None, cx))
         .await
         .unwrap();
-    cx_b.read(|cx| {
-        assert_eq!(type_definitions.len(), 1);
-        let target_buffer = type_definitions[0].target.buffer.read(cx);
-        assert_eq!(target_buffer.text(), "type T2 = usize;");
-        assert_eq!(
-            type_definitions[0].target.range.to_point(target_buffer),
-            Point::new(0, 5)..Point::new(0, 7)
-        );
-    });
-}
-
-#[gpui::test(iterations = 10)]
-async fn test_references(
-    executor: BackgroundExecutor,
-    cx_a: &mut TestAppContext,
-    cx_b: &mut TestAppContext,
-) {
-    let mut server = TestServer::start(executor.clone()).await;
-    let client_a = server.create_client(cx_a, "user_a").await;
-    let client_b = server.create_client(cx_b, "user_b").await;
-    server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
-        .await;
-    let active_call_a = cx_a.read(ActiveCall::global);
-
-    // Set up a fake language server.
-    let mut language = Language::new(
-        LanguageConfig {
-            name: "Rust".into(),
-            matcher: LanguageMatcher {
-                path_suffixes: vec!["rs".to_string()],
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        Some(tree_sitter_rust::language()),
+    assert_eq!(
+        history_c
+            .iter()
+            .map(|entry| (entry.status, entry.is_missed_by(user_c_id)))
+            .collect::<Vec<_>>(),
+        &[(CallStatus::Declined, false), (CallStatus::Missed, true)]
     );
-    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
-    client_a.language_registry().add(Arc::new(language));
+    assert_eq!(history_c[1].other_user(user_c_id).id, user_a_id);
 
-    client_a
-        .fs()
-        .insert_tree(
-            "/root",
-            json!({
-                "dir-1": {
-                    "one.rs": "const ONE: usize = 1;",
-                    "two.rs": "const TWO: usize = one::ONE + one::ONE;",
-                },
-                "dir-2": {
-                    "three.rs": "const THREE: usize = two::TWO + one::ONE;",
-                }
-            }),
-        )
-        .await;
-    let (project_a, worktree_id) = client_a.build_local_project("/root/dir-1", cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+    // Paging returns older entries.
+    let older_history_a = active_call_a
+        .update(cx_a, |call, cx| {
+            call.call_history(Some(history_a[1].id), cx)
+        })
         .await
         .unwrap();
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
-
-    // Open the file on client B.
-    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "one.rs"), cx));
-    let buffer_b = cx_b.executor().spawn(open_buffer).await.unwrap();
-
-    // Request references to a symbol as the guest.
-    let fake_language_server = fake_language_servers.next().await.unwrap();
-    fake_language_server.handle_request::<lsp::request::References, _, _>(|params, _| async move {
-        assert_eq!(
-            params.text_document_position.text_document.uri.as_str(),
-            "file:///root/dir-1/one.rs"
-        );
-        Ok(Some(vec![
-            lsp::Location {
-                uri: lsp::Url::from_file_path("/root/dir-1/two.rs").unwrap(),
-                range: lsp::Range::new(lsp::Position::new(0, 24), lsp::Position::new(0, 27)),
-            },
-            lsp::Location {
-                uri: lsp::Url::from_file_path("/root/dir-1/two.rs").unwrap(),
-                range: lsp::Range::new(lsp::Position::new(0, 35), lsp::Position::new(0, 38)),
-            },
-            lsp::Location {
-                uri: lsp::Url::from_file_path("/root/dir-2/three.rs").unwrap(),
-                range: lsp::Range::new(lsp::Position::new(0, 37), lsp::Position::new(0, 40)),
-            },
-        ]))
-    });
+    assert_eq!(
+        older_history_a
+            .iter()
+            .map(|entry| entry.id)
+            .collect::<Vec<_>>(),
+        &[history_a[0].id]
+    );
 
-    let references = project_b
-        .update(cx_b, |p, cx| p.references(&buffer_b, 7, cx))
+    // User C redials user A from the missed call, once user A is off their call.
+    active_call_a
+        .update(cx_a, |call, cx| call.hang_up(cx))
         .await
         .unwrap();
-    cx_b.read(|cx| {
-        assert_eq!(references.len(), 3);
-        assert_eq!(project_b.read(cx).worktrees().count(), 2);
-
-        let two_buffer = references[0].buffer.read(cx);
-        let three_buffer = references[2].buffer.read(cx);
-        assert_eq!(
-            two_buffer.file().unwrap().path().as_ref(),
-            Path::new("two.rs")
-        );
-        assert_eq!(references[1].buffer, references[0].buffer);
-        assert_eq!(
-            three_buffer.file().unwrap().full_path(cx),
-            Path::new("/root/dir-2/three.rs")
-        );
+    executor.run_until_parked();
+    let mut incoming_call_a = active_call_a.read_with(cx_a, |call, _| call.incoming());
+    active_call_c
+        .update(cx_c, |call, cx| call.redial(&history_c[1], cx))
+        .await
+        .unwrap();
+    let call_a = incoming_call_a.next().await.unwrap().unwrap();
+    assert_eq!(call_a.calling_user.github_login, "user_c");
 
-        assert_eq!(references[0].range.to_offset(two_buffer), 24..27);
-        assert_eq!(references[1].range.to_offset(two_buffer), 35..38);
-        assert_eq!(references[2].range.to_offset(three_buffer), 37..40);
-    });
+    executor.run_until_parked();
+    let history_c = active_call_c
+        .update(cx_c, |call, cx| call.call_history(None, cx))
+        .await
+        .unwrap();
+    let last_call = history_c.last().unwrap();
+    assert_eq!(last_call.caller.id, user_c_id);
+    assert_eq!(last_call.callee.id, user_a_id);
+    assert_eq!(last_call.status, CallStatus::Ringing);
 }
 
 #[gpui::test(iterations = 10)]
-async fn test_project_search(
+async fn test_join_call_after_screen_was_shared(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
+
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
     server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
+
     let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
 
-    client_a
-        .fs()
-        .insert_tree(
-            "/root",
-            json!({
-                "dir-1": {
-                    "a": "hello world",
-                    "b": "goodnight moon",
-                    "c": "a world of goo",
-                    "d": "world champion of clown world",
-                },
-                "dir-2": {
-                    "e": "disney world is fun",
-                }
-            }),
-        )
-        .await;
-    let (project_a, _) = client_a.build_local_project("/root/dir-1", cx_a).await;
-    let (worktree_2, _) = project_a
-        .update(cx_a, |p, cx| {
-            p.find_or_create_local_worktree("/root/dir-2", true, cx)
+    // Call users B and C from client A.
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b.user_id().unwrap(), None, cx)
         })
         .await
         .unwrap();
-    worktree_2
-        .read_with(cx_a, |tree, _| tree.as_local().unwrap().scan_complete())
-        .await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
-        .await
-        .unwrap();
 
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+    executor.run_until_parked();
+    assert_eq!(
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: Default::default(),
+            pending: vec!["user_b".to_string()]
+        }
+    );
 
-    // Perform a search as the guest.
-    let mut results = HashMap::default();
-    let mut search_rx = project_b.update(cx_b, |project, cx| {
-        project.search(
-            SearchQuery::text("world", false, false, false, Vec::new(), Vec::new()).unwrap(),
-            cx,
-        )
-    });
-    while let Some((buffer, ranges)) = search_rx.next().await {
-        results.entry(buffer).or_insert(ranges);
-    }
+    // User B receives the call.
 
-    let mut ranges_by_path = results
-        .into_iter()
-        .map(|(buffer, ranges)| {
-            buffer.read_with(cx_b, |buffer, cx| {
-                let path = buffer.file().unwrap().full_path(cx);
-                let offset_ranges = ranges
-                    .into_iter()
-                    .map(|range| range.to_offset(buffer))
-                    .collect::<Vec<_>>();
-                (path, offset_ranges)
+    let mut incoming_call_b = active_call_b.read_with(cx_b, |call, _| call.incoming());
+    let call_b = incoming_call_b.next().await.unwrap().unwrap();
+    assert_eq!(call_b.calling_user.github_login, "user_a");
+
+    // User A shares their screen
+    let display = MacOSDisplay::new();
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.room().unwrap().update(cx, |room, cx| {
+                room.set_display_sources(vec![display.clone()]);
+                room.share_screen(cx)
             })
         })
-        .collect::<Vec<_>>();
-    ranges_by_path.sort_by_key(|(path, _)| path.clone());
+        .await
+        .unwrap();
 
+    client_b.user_store().update(cx_b, |user_store, _| {
+        user_store.clear_cache();
+    });
+
+    // User B joins the room
+    active_call_b
+        .update(cx_b, |call, cx| call.accept_incoming(cx))
+        .await
+        .unwrap();
+
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
+    assert!(incoming_call_b.next().await.unwrap().is_none());
+
+    executor.run_until_parked();
     assert_eq!(
-        ranges_by_path,
-        &[
-            (PathBuf::from("dir-1/a"), vec![6..11]),
-            (PathBuf::from("dir-1/c"), vec![2..7]),
-            (PathBuf::from("dir-1/d"), vec![0..5, 24..29]),
-            (PathBuf::from("dir-2/e"), vec![7..12]),
-        ]
+        room_participants(&room_a, cx_a),
+        RoomParticipants {
+            remote: vec!["user_b".to_string()],
+            pending: vec![],
+        }
+    );
+    assert_eq!(
+        room_participants(&room_b, cx_b),
+        RoomParticipants {
+            remote: vec!["user_a".to_string()],
+            pending: vec![],
+        }
     );
-}
 
-#[gpui::test(iterations = 10)]
-async fn test_document_highlights(
-    executor: BackgroundExecutor,
-    cx_a: &mut TestAppContext,
-    cx_b: &mut TestAppContext,
-) {
-    let mut server = TestServer::start(executor.clone()).await;
-    let client_a = server.create_client(cx_a, "user_a").await;
-    let client_b = server.create_client(cx_b, "user_b").await;
-    server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
-        .await;
-    let active_call_a = cx_a.read(ActiveCall::global);
+    // Ensure User B sees User A's screenshare.
 
-    client_a
-        .fs()
-        .insert_tree(
-            "/root-1",
-            json!({
-                "main.rs": "fn double(number: i32) -> i32 { number + number }",
-            }),
-        )
-        .await;
+    room_b.read_with(cx_b, |room, _| {
+        assert_eq!(
+            room.remote_participants()
+                .get(&client_a.user_id().unwrap())
+                .unwrap()
+                .video_tracks
+                .len(),
+            1
+        );
+    });
+}
 
-    // Set up a fake language server.
-    let mut language = Language::new(
-        LanguageConfig {
-            name: "Rust".into(),
-            matcher: LanguageMatcher {
-                path_suffixes: vec!["rs".to_string()],
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        Some(tree_sitter_rust::language()),
-    );
-    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
-    client_a.language_registry().add(Arc::new(language));
+#[gpui::test]
+async fn test_right_click_menu_behind_collab_panel(cx: &mut TestAppContext) {
+    let mut server = TestServer::start(cx.executor().clone()).await;
+    let client_a = server.create_client(cx, "user_a").await;
+    let (_workspace_a, cx) = client_a.build_test_workspace(cx).await;
 
-    let (project_a, worktree_id) = client_a.build_local_project("/root-1", cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
-        .await
-        .unwrap();
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    cx.simulate_resize(size(px(300.), px(300.)));
 
-    // Open the file on client B.
-    let open_b = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "main.rs"), cx));
-    let buffer_b = cx_b.executor().spawn(open_b).await.unwrap();
+    cx.simulate_keystrokes("cmd-n cmd-n cmd-n");
+    cx.update(|cx| cx.refresh());
 
-    // Request document highlights as the guest.
-    let fake_language_server = fake_language_servers.next().await.unwrap();
-    fake_language_server.handle_request::<lsp::request::DocumentHighlightRequest, _, _>(
-        |params, _| async move {
-            assert_eq!(
-                params
-                    .text_document_position_params
-                    .text_document
-                    .uri
-                    .as_str(),
-                "file:///root-1/main.rs"
-            );
-            assert_eq!(
-                params.text_document_position_params.position,
-                lsp::Position::new(0, 34)
-            );
-            Ok(Some(vec![
-                lsp::DocumentHighlight {
-                    kind: Some(lsp::DocumentHighlightKind::WRITE),
-                    range: lsp::Range::new(lsp::Position::new(0, 10), lsp::Position::new(0, 16)),
-                },
-                lsp::DocumentHighlight {
-                    kind: Some(lsp::DocumentHighlightKind::READ),
-                    range: lsp::Range::new(lsp::Position::new(0, 32), lsp::Position::new(0, 38)),
-                },
-                lsp::DocumentHighlight {
-                    kind: Some(lsp::DocumentHighlightKind::READ),
-                    range: lsp::Range::new(lsp::Position::new(0, 41), lsp::Position::new(0, 47)),
-                },
-            ]))
-        },
+    let tab_bounds = cx.debug_bounds("TAB-2").unwrap();
+    let new_tab_button_bounds = cx.debug_bounds("ICON-Plus").unwrap();
+
+    assert!(
+        tab_bounds.intersects(&new_tab_button_bounds),
+        "Tab should overlap with the new tab button, if this is failing check if there's been a redesign!"
     );
 
-    let highlights = project_b
-        .update(cx_b, |p, cx| p.document_highlights(&buffer_b, 34, cx))
-        .await
-        .unwrap();
+    cx.simulate_event(MouseDownEvent {
+        button: MouseButton::Right,
+        position: new_tab_button_bounds.center(),
+        modifiers: Modifiers::default(),
+        click_count: 1,
+    });
+
+    // regression test that the right click menu for tabs does not open.
+    assert!(cx.debug_bounds("MENU_ITEM-Close").is_none());
+
+    let tab_bounds = cx.debug_bounds("TAB-1").unwrap();
+    cx.simulate_event(MouseDownEvent {
+        button: MouseButton::Right,
+        position: tab_bounds.center(),
+        modifiers: Modifiers::default(),
+        click_count: 1,
+    });
+    assert!(cx.debug_bounds("MENU_ITEM-Close").is_some());
+}
 
-    buffer_b.read_with(cx_b, |buffer, _| {
-        let snapshot = buffer.snapshot();
+#[gpui::test]
+async fn test_cmd_k_left(cx: &mut TestAppContext) {
+    let client = TestServer::start1(cx).await;
+    let (workspace, cx) = client.build_test_workspace(cx).await;
 
-        let highlights = highlights
-            .into_iter()
-            .map(|highlight| (highlight.kind, highlight.range.to_offset(&snapshot)))
-            .collect::<Vec<_>>();
-        assert_eq!(
-            highlights,
-            &[
-                (lsp::DocumentHighlightKind::WRITE, 10..16),
-                (lsp::DocumentHighlightKind::READ, 32..38),
-                (lsp::DocumentHighlightKind::READ, 41..47)
-            ]
-        )
+    cx.simulate_keystrokes("cmd-n");
+    workspace.update(cx, |workspace, cx| {
+        assert!(workspace.items(cx).collect::<Vec<_>>().len() == 1);
+    });
+    cx.simulate_keystrokes("cmd-k left");
+    workspace.update(cx, |workspace, cx| {
+        assert!(workspace.items(cx).collect::<Vec<_>>().len() == 2);
+    });
+    cx.simulate_keystrokes("cmd-k");
+    // sleep for longer than the timeout in keyboard shortcut handling
+    // to verify that it doesn't fire in this case.
+    cx.executor().advance_clock(Duration::from_secs(2));
+    cx.simulate_keystrokes("left");
+    workspace.update(cx, |workspace, cx| {
+        assert!(workspace.items(cx).collect::<Vec<_>>().len() == 2);
     });
 }
 
-#[gpui::test(iterations = 10)]
-async fn test_lsp_hover(
+#[gpui::test]
+async fn test_scripted_edits_across_server_restart(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
 ) {
-    let mut server = TestServer::start(executor.clone()).await;
-    let client_a = server.create_client(cx_a, "user_a").await;
-    let client_b = server.create_client(cx_b, "user_b").await;
-    server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+    Scenario::new(["user_a", "user_b"])
+        .share_project("user_a", json!({ "main.rs": "fn main() {}" }))
+        .join_project("user_b", "user_a")
+        .edit("user_b", "user_a/main.rs", 0, "// ")
+        .assert_text("user_a/main.rs", "// fn main() {}")
+        .restart_server()
+        .edit("user_a", "user_a/main.rs", 3, "one ")
+        .edit("user_b", "user_a/main.rs", 7, "two ")
+        .assert_text("user_a/main.rs", "// one two fn main() {}")
+        .disconnect_client("user_b")
+        .save("user_b", "user_a/main.rs")
+        .assert_saved_on_disk("user_a/main.rs", "// one two fn main() {}")
+        .run(executor, &mut [cx_a, cx_b])
         .await;
-    let active_call_a = cx_a.read(ActiveCall::global);
+}
 
-    client_a
-        .fs()
-        .insert_tree(
-            "/root-1",
-            json!({
-                "main.rs": "use std::collections::HashMap;",
-            }),
-        )
+#[gpui::test]
+async fn test_scripted_edits_across_server_drain(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    Scenario::new(["user_a", "user_b"])
+        .share_project("user_a", json!({ "main.rs": "fn main() {}" }))
+        .join_project("user_b", "user_a")
+        .edit("user_b", "user_a/main.rs", 0, "// ")
+        .drain_server()
+        .edit("user_a", "user_a/main.rs", 3, "one ")
+        .edit("user_b", "user_a/main.rs", 7, "two ")
+        .assert_text("user_a/main.rs", "// one two fn main() {}")
+        .run(executor, &mut [cx_a, cx_b])
         .await;
+}
 
-    // Set up a fake language server.
-    let mut language = Language::new(
-        LanguageConfig {
-            name: "Rust".into(),
-            matcher: LanguageMatcher {
-                path_suffixes: vec!["rs".to_string()],
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        Some(tree_sitter_rust::language()),
-    );
-    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
-    client_a.language_registry().add(Arc::new(language));
+#[gpui::test]
+async fn test_scenario_from_json(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    Scenario::from_json(
+        r#"{
+            "users": ["user_a", "user_b"],
+            "steps": [
+                { "step": "share_project", "host": "user_a", "files": { "a.txt": "hello" } },
+                { "step": "join_project", "guest": "user_b", "host": "user_a" },
+                { "step": "edit", "user": "user_b", "path": "user_a/a.txt", "offset": 5, "text": "!" },
+                { "step": "assert_text", "path": "user_a/a.txt", "text": "hello!" }
+            ]
+        }"#,
+    )
+    .unwrap()
+    .run(executor, &mut [cx_a, cx_b])
+    .await;
+}
 
-    let (project_a, worktree_id) = client_a.build_local_project("/root-1", cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
-        .await
-        .unwrap();
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+#[gpui::test]
+async fn test_scripted_following(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    Scenario::new(["user_a", "user_b"])
+        .share_project(
+            "user_a",
+            json!({ "a.rs": "fn a() {}", "b.rs": "fn b() {}" }),
+        )
+        .join_project("user_b", "user_a")
+        .open_in_workspace("user_a", "user_a/a.rs")
+        .open_in_workspace("user_b", "user_a/a.rs")
+        .follow("user_b", "user_a")
+        .split_pane("user_a", "user_a")
+        .open_in_workspace("user_a", "user_a/b.rs")
+        .assert_active_path("user_b", "user_a/b.rs")
+        .run(executor, &mut [cx_a, cx_b])
+        .await;
+}
 
-    // Open the file as the guest
-    let open_buffer = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "main.rs"), cx));
-    let buffer_b = cx_b.executor().spawn(open_buffer).await.unwrap();
+#[gpui::test]
+async fn test_server_capabilities(executor: BackgroundExecutor, cx_a: &mut TestAppContext) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
 
-    // Request hover information as the guest.
-    let fake_language_server = fake_language_servers.next().await.unwrap();
-    fake_language_server.handle_request::<lsp::request::HoverRequest, _, _>(
-        |params, _| async move {
-            assert_eq!(
-                params
-                    .text_document_position_params
-                    .text_document
-                    .uri
-                    .as_str(),
-                "file:///root-1/main.rs"
-            );
-            assert_eq!(
-                params.text_document_position_params.position,
-                lsp::Position::new(0, 22)
-            );
-            Ok(Some(lsp::Hover {
-                contents: lsp::HoverContents::Array(vec![
-                    lsp::MarkedString::String("Test hover content.".to_string()),
-                    lsp::MarkedString::LanguageString(lsp::LanguageString {
-                        language: "Rust".to_string(),
-                        value: "let foo = 42;".to_string(),
-                    }),
-                ]),
-                range: Some(lsp::Range::new(
-                    lsp::Position::new(0, 22),
-                    lsp::Position::new(0, 29),
-                )),
-            }))
-        },
+    // The test server has a LiveKit server and isn't self-hosted, so it supports everything.
+    assert_eq!(
+        client_a.server_capabilities(),
+        proto::ServerCapabilities {
+            calls_audio: true,
+            channel_links: true,
+            relayed_audio: true,
+        }
     );
-
-    let hover_info = project_b
-        .update(cx_b, |p, cx| p.hover(&buffer_b, 22, cx))
-        .await
-        .unwrap()
-        .unwrap();
-
-    buffer_b.read_with(cx_b, |buffer, _| {
-        let snapshot = buffer.snapshot();
-        assert_eq!(hover_info.range.unwrap().to_offset(&snapshot), 22..29);
-        assert_eq!(
-            hover_info.contents,
-            vec![
-                project::HoverBlock {
-                    text: "Test hover content.".to_string(),
-                    kind: HoverBlockKind::Markdown,
-                },
-                project::HoverBlock {
-                    text: "let foo = 42;".to_string(),
-                    kind: HoverBlockKind::Code {
-                        language: "Rust".to_string()
-                    },
-                }
-            ]
-        );
-    });
 }
 
-#[gpui::test(iterations = 10)]
-async fn test_project_symbols(
+#[gpui::test]
+async fn test_room_ice_servers(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
@@ -5073,936 +10119,1339 @@ async fn test_project_symbols(
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
     server
-        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
-        .await;
-    let active_call_a = cx_a.read(ActiveCall::global);
-
-    // Set up a fake language server.
-    let mut language = Language::new(
-        LanguageConfig {
-            name: "Rust".into(),
-            matcher: LanguageMatcher {
-                path_suffixes: vec!["rs".to_string()],
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        Some(tree_sitter_rust::language()),
-    );
-    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
-    client_a.language_registry().add(Arc::new(language));
-
-    client_a
-        .fs()
-        .insert_tree(
-            "/code",
-            json!({
-                "crate-1": {
-                    "one.rs": "const ONE: usize = 1;",
-                },
-                "crate-2": {
-                    "two.rs": "const TWO: usize = 2; const THREE: usize = 3;",
-                },
-                "private": {
-                    "passwords.txt": "the-password",
-                }
-            }),
-        )
+        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
-    let (project_a, worktree_id) = client_a.build_local_project("/code/crate-1", cx_a).await;
-    let project_id = active_call_a
-        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
-        .await
-        .unwrap();
-    let project_b = client_b.build_remote_project(project_id, cx_b).await;
-
-    // Cause the language server to start.
-    let open_buffer_task =
-        project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "one.rs"), cx));
-    let _buffer = cx_b.executor().spawn(open_buffer_task).await.unwrap();
-
-    let fake_language_server = fake_language_servers.next().await.unwrap();
-    fake_language_server.handle_request::<lsp::WorkspaceSymbolRequest, _, _>(|_, _| async move {
-        Ok(Some(lsp::WorkspaceSymbolResponse::Flat(vec![
-            #[allow(deprecated)]
-            lsp::SymbolInformation {
-                name: "TWO".into(),
-                location: lsp::Location {
-                    uri: lsp::Url::from_file_path("/code/crate-2/two.rs").unwrap(),
-                    range: lsp::Range::new(lsp::Position::new(0, 6), lsp::Position::new(0, 9)),
-                },
-                kind: lsp::SymbolKind::CONSTANT,
-                tags: None,
-                container_name: None,
-                deprecated: None,
-            },
-        ])))
-    });
 
-    // Request the definition of a symbol as the guest.
-    let symbols = project_b
-        .update(cx_b, |p, cx| p.symbols("two", cx))
+    let active_call_a = cx_a.read(ActiveCall::global);
+    let active_call_b = cx_b.read(ActiveCall::global);
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b.user_id().unwrap(), None, cx)
+        })
         .await
         .unwrap();
-    assert_eq!(symbols.len(), 1);
-    assert_eq!(symbols[0].name, "TWO");
+    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+    executor.run_until_parked();
 
-    // Open one of the returned symbols.
-    let buffer_b_2 = project_b
-        .update(cx_b, |project, cx| {
-            project.open_buffer_for_symbol(&symbols[0], cx)
+    // Without any ICE servers configured, LiveKit's own are used.
+    room_a.read_with(cx_a, |room, _| assert_eq!(room.ice_configuration(), None));
+
+    // Participants who join after the room's ICE servers are overridden use them.
+    let room_id = RoomId::from_proto(room_a.read_with(cx_a, |room, _| room.id()));
+    let ice_servers = server
+        .app_state
+        .ice_servers_key
+        .as_ref()
+        .unwrap()
+        .seal(&IceConfig {
+            servers: vec![IceServer {
+                urls: vec!["turn:turn.example.com".into()],
+                username: Some("a".into()),
+                credential: Some("b".into()),
+                shared_secret: None,
+            }],
+            force_relay: true,
         })
+        .unwrap();
+    server
+        .app_state
+        .db
+        .set_room_ice_servers(room_id, Some(&ice_servers))
         .await
         .unwrap();
-
-    buffer_b_2.read_with(cx_b, |buffer, cx| {
+    active_call_b
+        .update(cx_b, |call, cx| call.accept_incoming(cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
+    room_b.read_with(cx_b, |room, _| {
         assert_eq!(
-            buffer.file().unwrap().full_path(cx),
-            Path::new("/code/crate-2/two.rs")
-        );
+            room.ice_configuration(),
+            Some(live_kit_client::IceConfiguration {
+                servers: vec![live_kit_client::IceServer {
+                    urls: vec!["turn:turn.example.com".into()],
+                    username: "a".into(),
+                    credential: "b".into(),
+                }],
+                force_relay: true,
+            })
+        )
     });
+}
 
-    // Attempt to craft a symbol and violate host's privacy by opening an arbitrary file.
-    let mut fake_symbol = symbols[0].clone();
-    fake_symbol.path.path = Path::new("/code/secrets").into();
-    let error = project_b
-        .update(cx_b, |project, cx| {
-            project.open_buffer_for_symbol(&fake_symbol, cx)
-        })
-        .await
-        .unwrap_err();
-    assert!(error.to_string().contains("invalid symbol signature"));
+#[gpui::test]
+async fn test_relayed_audio(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start_without_live_kit(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    assert!(client_a.server_capabilities().relayed_audio);
+    assert!(!client_a.server_capabilities().calls_audio);
+
+    let device_a = Arc::new(FakeAudioRelayDevice::default());
+    let device_b = Arc::new(FakeAudioRelayDevice::default());
+    let device_c = Arc::new(FakeAudioRelayDevice::default());
+    cx_a.update(|cx| set_audio_relay_device(device_a.clone(), cx));
+    cx_b.update(|cx| set_audio_relay_device(device_b.clone(), cx));
+    cx_c.update(|cx| set_audio_relay_device(device_c.clone(), cx));
+
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .await;
+    executor.run_until_parked();
+
+    let room_a = cx_a
+        .read(ActiveCall::global)
+        .update(cx_a, |call, _| call.room().unwrap().clone());
+    let room_b = cx_b
+        .read(ActiveCall::global)
+        .update(cx_b, |call, _| call.room().unwrap().clone());
+    room_a.read_with(cx_a, |room, _| {
+        assert!(room.has_audio());
+        assert!(!room.is_muted());
+    });
+    assert!(device_a.is_capturing());
+
+    // Frames captured by one participant are played by everyone else, once enough
+    // of them have been buffered.
+    let peer_id_a = client_a.peer_id().unwrap();
+    for frame in 0..3 {
+        device_a.capture_frame(vec![frame]);
+    }
+    executor.run_until_parked();
+    executor.advance_clock(RELAYED_AUDIO_FRAME_DURATION * 3);
+    let expected_frames = vec![
+        (peer_id_a, Some(vec![0])),
+        (peer_id_a, Some(vec![1])),
+        (peer_id_a, Some(vec![2])),
+    ];
+    assert_eq!(device_b.take_played_frames(), expected_frames);
+    assert_eq!(device_c.take_played_frames(), expected_frames);
+    assert_eq!(device_a.take_played_frames(), vec![]);
+
+    // Muted participants stop sending audio.
+    room_a.update(cx_a, |room, cx| room.toggle_mute(cx));
+    executor.run_until_parked();
+    assert!(!device_a.is_capturing());
+    room_a.read_with(cx_a, |room, _| assert!(room.is_muted()));
+
+    // Deafened participants stop playing audio.
+    room_a.update(cx_a, |room, cx| room.toggle_mute(cx));
+    room_b.update(cx_b, |room, cx| room.toggle_deafen(cx));
+    executor.run_until_parked();
+    assert!(device_a.is_capturing());
+    assert!(!device_b.is_capturing());
+    for frame in 3..6 {
+        device_a.capture_frame(vec![frame]);
+    }
+    executor.run_until_parked();
+    executor.advance_clock(RELAYED_AUDIO_FRAME_DURATION * 3);
+    assert_eq!(device_b.take_played_frames(), vec![]);
+    assert_eq!(device_c.take_played_frames().len(), 3);
 }
 
-#[gpui::test(iterations = 10)]
-async fn test_open_buffer_while_getting_definition_pointing_to_it(
+#[gpui::test]
+async fn test_call_summary(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
-    mut rng: StdRng,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
+    cx_a.update(|cx| {
+        cx.update_global(|store: &mut SettingsStore, cx| {
+            store.update_user_settings::<CallSettings>(cx, |settings| {
+                settings.summarize_calls = Some(true);
+            });
+        });
+    });
     server
         .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
     let active_call_a = cx_a.read(ActiveCall::global);
-
-    // Set up a fake language server.
-    let mut language = Language::new(
-        LanguageConfig {
-            name: "Rust".into(),
-            matcher: LanguageMatcher {
-                path_suffixes: vec!["rs".to_string()],
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        Some(tree_sitter_rust::language()),
-    );
-    let mut fake_language_servers = language.set_fake_lsp_adapter(Default::default()).await;
-    client_a.language_registry().add(Arc::new(language));
+    let active_call_b = cx_b.read(ActiveCall::global);
 
     client_a
         .fs()
-        .insert_tree(
-            "/root",
-            json!({
-                "a.rs": "const ONE: usize = b::TWO;",
-                "b.rs": "const TWO: usize = 2",
-            }),
-        )
+        .insert_tree("/a", json!({ "main.rs": "one\ntwo\nthree\n" }))
         .await;
-    let (project_a, worktree_id) = client_a.build_local_project("/root", cx_a).await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
     let project_id = active_call_a
         .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
         .await
         .unwrap();
     let project_b = client_b.build_remote_project(project_id, cx_b).await;
 
-    let open_buffer_task = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "a.rs"), cx));
-    let buffer_b1 = cx_b.executor().spawn(open_buffer_task).await.unwrap();
+    // Both the host's edits and the guest's count towards the summary.
+    let buffer_a = project_a
+        .update(cx_a, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
+        })
+        .await
+        .unwrap();
+    buffer_a.update(cx_a, |buffer, cx| buffer.edit([(0..0, "zero\n")], None, cx));
+    executor.run_until_parked();
+    let buffer_b = project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
+        })
+        .await
+        .unwrap();
+    buffer_b.update(cx_b, |buffer, cx| buffer.edit([(9..12, "TWO")], None, cx));
+    executor.run_until_parked();
 
-    let fake_language_server = fake_language_servers.next().await.unwrap();
-    fake_language_server.handle_request::<lsp::request::GotoDefinition, _, _>(|_, _| async move {
-        Ok(Some(lsp::GotoDefinitionResponse::Scalar(
-            lsp::Location::new(
-                lsp::Url::from_file_path("/root/b.rs").unwrap(),
-                lsp::Range::new(lsp::Position::new(0, 6), lsp::Position::new(0, 9)),
-            ),
-        )))
+    // Edits to a project that's closed before the call ends count too.
+    client_a
+        .fs()
+        .insert_tree("/b", json!({ "lib.rs": "" }))
+        .await;
+    let (project_a2, worktree_id2) = client_a.build_local_project("/b", cx_a).await;
+    active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a2.clone(), cx))
+        .await
+        .unwrap();
+    let buffer_a2 = project_a2
+        .update(cx_a, |project, cx| {
+            project.open_buffer((worktree_id2, "lib.rs"), cx)
+        })
+        .await
+        .unwrap();
+    buffer_a2.update(cx_a, |buffer, cx| {
+        buffer.edit([(0..0, "fn a() {}\n")], None, cx)
     });
+    drop(buffer_a2);
+    drop(project_a2);
+    executor.run_until_parked();
 
-    let definitions;
-    let buffer_b2;
-    if rng.gen() {
-        definitions = project_b.update(cx_b, |p, cx| p.definition(&buffer_b1, 23, cx));
-        buffer_b2 = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "b.rs"), cx));
-    } else {
-        buffer_b2 = project_b.update(cx_b, |p, cx| p.open_buffer((worktree_id, "b.rs"), cx));
-        definitions = project_b.update(cx_b, |p, cx| p.definition(&buffer_b1, 23, cx));
-    }
+    active_call_b
+        .update(cx_b, |call, cx| call.hang_up(cx))
+        .await
+        .unwrap();
+    active_call_a
+        .update(cx_a, |call, cx| call.hang_up(cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
 
-    let buffer_b2 = buffer_b2.await.unwrap();
-    let definitions = definitions.await.unwrap();
-    assert_eq!(definitions.len(), 1);
-    assert_eq!(definitions[0].target.buffer, buffer_b2);
+    // Only the participant who asked for a summary is sent one.
+    let call_summary_ids = |client: &TestClient, cx: &mut TestAppContext| {
+        client.notification_store().read_with(cx, |store, _| {
+            (0..store.notification_count())
+                .filter_map(|ix| match store.notification_at(ix)?.notification {
+                    Notification::CallSummaryReady { summary_id } => Some(summary_id),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        })
+    };
+    assert_eq!(call_summary_ids(&client_b, cx_b), []);
+    let summary_ids = call_summary_ids(&client_a, cx_a);
+    assert_eq!(summary_ids.len(), 1);
+    let summary_id = summary_ids[0];
+
+    let summary = active_call_a
+        .update(cx_a, |call, cx| call.call_summary(summary_id, cx))
+        .await
+        .unwrap();
+    assert_eq!(
+        summary
+            .participants
+            .iter()
+            .map(|user| user.github_login.as_str())
+            .collect::<Vec<_>>(),
+        ["user_a", "user_b"]
+    );
+    let mut edited_files = summary.edited_files.clone();
+    edited_files.sort_by(|a, b| a.path.cmp(&b.path));
+    assert_eq!(
+        edited_files,
+        [
+            proto::EditedFile {
+                path: "a/main.rs".into(),
+                lines_added: 2,
+                lines_removed: 1,
+            },
+            proto::EditedFile {
+                path: "b/lib.rs".into(),
+                lines_added: 1,
+                lines_removed: 0,
+            }
+        ]
+    );
+    assert_eq!(summary.statistics, None);
+    assert!(summary.to_markdown().contains("| `a/main.rs` | 2 | 1 |"));
+
+    // Other users can't see the summary.
+    active_call_b
+        .update(cx_b, |call, cx| call.call_summary(summary_id, cx))
+        .await
+        .unwrap_err();
 }
 
-#[gpui::test(iterations = 10)]
-async fn test_contacts(
+#[gpui::test]
+async fn test_call_summary_statistics(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
     cx_c: &mut TestAppContext,
-    cx_d: &mut TestAppContext,
 ) {
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
     let client_c = server.create_client(cx_c, "user_c").await;
-    let client_d = server.create_client(cx_d, "user_d").await;
+    for cx in [&mut *cx_a, &mut *cx_b, &mut *cx_c] {
+        cx.update(|cx| {
+            cx.update_global(|store: &mut SettingsStore, cx| {
+                store.update_user_settings::<CallSettings>(cx, |settings| {
+                    settings.summarize_calls = Some(true);
+                    settings.share_session_statistics = Some(true);
+                });
+            });
+        });
+    }
     server
-        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b), (&client_c, cx_c)])
         .await;
     let active_call_a = cx_a.read(ActiveCall::global);
     let active_call_b = cx_b.read(ActiveCall::global);
     let active_call_c = cx_c.read(ActiveCall::global);
-    let _active_call_d = cx_d.read(ActiveCall::global);
 
-    executor.run_until_parked();
-    assert_eq!(
-        contacts(&client_a, cx_a),
-        [
-            ("user_b".to_string(), "online", "free"),
-            ("user_c".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_b, cx_b),
-        [
-            ("user_a".to_string(), "online", "free"),
-            ("user_c".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_c, cx_c),
-        [
-            ("user_a".to_string(), "online", "free"),
-            ("user_b".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(contacts(&client_d, cx_d), []);
+    client_a
+        .fs()
+        .insert_tree("/a", json!({ "main.rs": "one\ntwo\nthree\n" }))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    active_call_a
+        .update(cx_a, |call, cx| call.set_location(Some(&project_a), cx))
+        .await
+        .unwrap();
+    project_a.update(cx_a, |project, cx| {
+        project.set_active_path(Some((worktree_id, "main.rs").into()), cx)
+    });
 
-    server.disconnect_client(client_c.peer_id().unwrap());
-    server.forbid_connections();
-    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
-    assert_eq!(
-        contacts(&client_a, cx_a),
-        [
-            ("user_b".to_string(), "online", "free"),
-            ("user_c".to_string(), "offline", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_b, cx_b),
-        [
-            ("user_a".to_string(), "online", "free"),
-            ("user_c".to_string(), "offline", "free")
-        ]
-    );
-    assert_eq!(contacts(&client_c, cx_c), []);
-    assert_eq!(contacts(&client_d, cx_d), []);
+    // Each participant's characters are counted in the buffers they wrote them.
+    let buffer_a = project_a
+        .update(cx_a, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
+        })
+        .await
+        .unwrap();
+    buffer_a.update(cx_a, |buffer, cx| buffer.edit([(0..0, "zero\n")], None, cx));
+    executor.run_until_parked();
+    let buffer_b = project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
+        })
+        .await
+        .unwrap();
+    buffer_b.update(cx_b, |buffer, cx| buffer.edit([(9..12, "TWO")], None, cx));
+    executor.run_until_parked();
 
-    server.allow_connections();
-    client_c
-        .authenticate_and_connect(false, &cx_c.to_async())
+    active_call_c
+        .update(cx_c, |call, cx| call.hang_up(cx))
+        .await
+        .unwrap();
+    active_call_b
+        .update(cx_b, |call, cx| call.hang_up(cx))
+        .await
+        .unwrap();
+    active_call_a
+        .update(cx_a, |call, cx| call.hang_up(cx))
         .await
         .unwrap();
-
     executor.run_until_parked();
-    assert_eq!(
-        contacts(&client_a, cx_a),
-        [
-            ("user_b".to_string(), "online", "free"),
-            ("user_c".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_b, cx_b),
-        [
-            ("user_a".to_string(), "online", "free"),
-            ("user_c".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_c, cx_c),
-        [
-            ("user_a".to_string(), "online", "free"),
-            ("user_b".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(contacts(&client_d, cx_d), []);
 
-    active_call_a
-        .update(cx_a, |call, cx| {
-            call.invite(client_b.user_id().unwrap(), None, cx)
-        })
+    let summary_id = client_a.notification_store().read_with(cx_a, |store, _| {
+        (0..store.notification_count())
+            .find_map(|ix| match store.notification_at(ix)?.notification {
+                Notification::CallSummaryReady { summary_id } => Some(summary_id),
+                _ => None,
+            })
+            .unwrap()
+    });
+    let summary = active_call_a
+        .update(cx_a, |call, cx| call.call_summary(summary_id, cx))
         .await
         .unwrap();
-    executor.run_until_parked();
-    assert_eq!(
-        contacts(&client_a, cx_a),
-        [
-            ("user_b".to_string(), "online", "busy"),
-            ("user_c".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_b, cx_b),
-        [
-            ("user_a".to_string(), "online", "busy"),
-            ("user_c".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_c, cx_c),
-        [
-            ("user_a".to_string(), "online", "busy"),
-            ("user_b".to_string(), "online", "busy")
-        ]
-    );
-    assert_eq!(contacts(&client_d, cx_d), []);
+    // Only totals are shared, so that nobody's part can be told apart, and the
+    // files that were viewed are counted without being named.
+    let statistics = summary.statistics.unwrap();
+    assert_eq!(statistics.contributor_count, 3);
+    assert_eq!(statistics.total_characters_written, 8);
+    assert_eq!(statistics.total_talk_time_ms, None);
+    assert_eq!(statistics.file_count, 1);
+}
 
-    // Client B and client D become contacts while client B is being called.
+#[gpui::test]
+async fn test_edit_attribution(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
     server
-        .make_contacts(&mut [(&client_b, cx_b), (&client_d, cx_d)])
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree("/attributed", json!({ "main.rs": "one\ntwo\n" }))
         .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/attributed", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+
+    let buffer_a = project_a
+        .update(cx_a, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
+        })
+        .await
+        .unwrap();
+    let buffer_b = project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
+        })
+        .await
+        .unwrap();
+    buffer_b.update(cx_b, |buffer, cx| {
+        buffer.edit([(0..0, "guest\n")], None, cx)
+    });
     executor.run_until_parked();
+    buffer_a.update(cx_a, |buffer, cx| {
+        buffer.edit([(14..14, "host\n")], None, cx)
+    });
+    executor.run_until_parked();
+
+    let attributed_offsets = |project: &Model<Project>,
+                              buffer: &Model<language::Buffer>,
+                              cx: &mut TestAppContext| {
+        let attribution = project.update(cx, |project, cx| project.edit_attribution(buffer, cx));
+        buffer.read_with(cx, |buffer, _| {
+            attribution
+                .into_iter()
+                .map(|attributed_range| {
+                    (
+                        attributed_range.range.to_offset(buffer),
+                        attributed_range.author.github_login,
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+    };
+
+    // The host sees who wrote what while the project is shared. Guests don't.
+    let expected_offsets = vec![(0..6, "user_b".to_string()), (14..19, "user_a".to_string())];
     assert_eq!(
-        contacts(&client_a, cx_a),
-        [
-            ("user_b".to_string(), "online", "busy"),
-            ("user_c".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_b, cx_b),
-        [
-            ("user_a".to_string(), "online", "busy"),
-            ("user_c".to_string(), "online", "free"),
-            ("user_d".to_string(), "online", "free"),
-        ]
-    );
-    assert_eq!(
-        contacts(&client_c, cx_c),
-        [
-            ("user_a".to_string(), "online", "busy"),
-            ("user_b".to_string(), "online", "busy")
-        ]
+        buffer_a.read_with(cx_a, |buffer, _| buffer.text()),
+        "guest\none\ntwo\nhost\n"
     );
     assert_eq!(
-        contacts(&client_d, cx_d),
-        [("user_b".to_string(), "online", "busy")]
+        attributed_offsets(&project_a, &buffer_a, cx_a),
+        expected_offsets
     );
+    assert_eq!(attributed_offsets(&project_b, &buffer_b, cx_b), []);
 
-    active_call_b.update(cx_b, |call, cx| call.decline_incoming(cx).unwrap());
+    // The attribution outlives the session, and the buffer too once it's saved.
+    project_a.update(cx_a, |project, cx| project.unshare(cx).unwrap());
     executor.run_until_parked();
     assert_eq!(
-        contacts(&client_a, cx_a),
-        [
-            ("user_b".to_string(), "online", "free"),
-            ("user_c".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_b, cx_b),
-        [
-            ("user_a".to_string(), "online", "free"),
-            ("user_c".to_string(), "online", "free"),
-            ("user_d".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_c, cx_c),
-        [
-            ("user_a".to_string(), "online", "free"),
-            ("user_b".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_d, cx_d),
-        [("user_b".to_string(), "online", "free")]
+        attributed_offsets(&project_a, &buffer_a, cx_a),
+        expected_offsets
     );
-
-    active_call_c
-        .update(cx_c, |call, cx| {
-            call.invite(client_a.user_id().unwrap(), None, cx)
+    project_a
+        .update(cx_a, |project, cx| {
+            project.save_buffer(buffer_a.clone(), cx)
+        })
+        .await
+        .unwrap();
+    drop(buffer_a);
+    executor.run_until_parked();
+    let buffer_a = project_a
+        .update(cx_a, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
         })
         .await
         .unwrap();
     executor.run_until_parked();
     assert_eq!(
-        contacts(&client_a, cx_a),
-        [
-            ("user_b".to_string(), "online", "free"),
-            ("user_c".to_string(), "online", "busy")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_b, cx_b),
-        [
-            ("user_a".to_string(), "online", "busy"),
-            ("user_c".to_string(), "online", "busy"),
-            ("user_d".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_c, cx_c),
-        [
-            ("user_a".to_string(), "online", "busy"),
-            ("user_b".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_d, cx_d),
-        [("user_b".to_string(), "online", "free")]
+        attributed_offsets(&project_a, &buffer_a, cx_a),
+        expected_offsets
     );
 
-    active_call_a
-        .update(cx_a, |call, cx| call.accept_incoming(cx))
+    // Nothing more is attributed once the host turns attribution off.
+    cx_a.update(|cx| {
+        cx.update_global(|store: &mut SettingsStore, cx| {
+            store.update_user_settings::<ProjectSettings>(cx, |settings| {
+                settings.attribute_session_edits = Some(false);
+            });
+        });
+    });
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    let buffer_b = project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
+        })
         .await
         .unwrap();
+    buffer_b.update(cx_b, |buffer, cx| buffer.edit([(0..0, "more\n")], None, cx));
     executor.run_until_parked();
     assert_eq!(
-        contacts(&client_a, cx_a),
-        [
-            ("user_b".to_string(), "online", "free"),
-            ("user_c".to_string(), "online", "busy")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_b, cx_b),
-        [
-            ("user_a".to_string(), "online", "busy"),
-            ("user_c".to_string(), "online", "busy"),
-            ("user_d".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_c, cx_c),
+        attributed_offsets(&project_a, &buffer_a, cx_a),
         [
-            ("user_a".to_string(), "online", "busy"),
-            ("user_b".to_string(), "online", "free")
+            (5..11, "user_b".to_string()),
+            (19..24, "user_a".to_string())
         ]
     );
+}
+
+#[gpui::test]
+async fn test_collab_policy(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/a",
+            json!({
+                ".zed": {
+                    "collab.toml": "deny = [\"secrets\"]\nread_only = true\n",
+                },
+                "main.rs": "fn main() {}",
+                "secrets": {
+                    "key.txt": "hunter2",
+                },
+            }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+
+    // The policy is loaded before the project is shared, even if it's shared as
+    // soon as it's opened.
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
     assert_eq!(
-        contacts(&client_d, cx_d),
-        [("user_b".to_string(), "online", "free")]
+        project_a.read_with(cx_a, |project, _| project.collab_policy()),
+        CollabPolicy {
+            deny: vec!["secrets".into()],
+            read_only: true,
+            allowed_roles: None,
+        }
     );
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    executor.run_until_parked();
+
+    // Denied paths are hidden from guests, and can't be opened by them.
+    project_b.read_with(cx_b, |project, cx| {
+        let worktree = project.worktree_for_id(worktree_id, cx).unwrap().read(cx);
+        assert!(worktree.entry_for_path("main.rs").is_some());
+        assert!(worktree.entry_for_path("secrets").is_none());
+        assert!(worktree.entry_for_path("secrets/key.txt").is_none());
+    });
+    project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer((worktree_id, "secrets/key.txt"), cx)
+        })
+        .await
+        .unwrap_err();
 
-    active_call_a
-        .update(cx_a, |call, cx| {
-            call.invite(client_b.user_id().unwrap(), None, cx)
+    // Guests join read-only.
+    assert!(project_b.read_with(cx_b, |project, _| project.is_read_only()));
+    let buffer_b = project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
         })
         .await
         .unwrap();
+    assert!(buffer_b.read_with(cx_b, |buffer, _| buffer.read_only()));
+
+    // The host can still edit their own project.
+    let buffer_a = project_a
+        .update(cx_a, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
+        })
+        .await
+        .unwrap();
+    buffer_a.update(cx_a, |buffer, cx| buffer.edit([(0..0, "// ")], None, cx));
     executor.run_until_parked();
     assert_eq!(
-        contacts(&client_a, cx_a),
-        [
-            ("user_b".to_string(), "online", "busy"),
-            ("user_c".to_string(), "online", "busy")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_b, cx_b),
-        [
-            ("user_a".to_string(), "online", "busy"),
-            ("user_c".to_string(), "online", "busy"),
-            ("user_d".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_c, cx_c),
-        [
-            ("user_a".to_string(), "online", "busy"),
-            ("user_b".to_string(), "online", "busy")
-        ]
+        buffer_b.read_with(cx_b, |buffer, _| buffer.text()),
+        "// fn main() {}"
     );
+
+    // Denying a file that a guest has open takes it back from them, and its
+    // edits are no longer sent to them.
+    client_a
+        .fs()
+        .insert_file(
+            "/a/.zed/collab.toml",
+            "deny = [\"secrets\", \"main.rs\"]\nread_only = true\n".into(),
+        )
+        .await;
+    executor.run_until_parked();
+    assert!(project_b.read_with(cx_b, |project, _| project.opened_buffers().is_empty()));
+    buffer_a.update(cx_a, |buffer, cx| buffer.edit([(0..0, "// ")], None, cx));
+    executor.run_until_parked();
     assert_eq!(
-        contacts(&client_d, cx_d),
-        [("user_b".to_string(), "online", "busy")]
+        buffer_b.read_with(cx_b, |buffer, _| buffer.text()),
+        "// fn main() {}"
     );
+    project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
+        })
+        .await
+        .unwrap_err();
+}
 
-    active_call_a
-        .update(cx_a, |call, cx| call.hang_up(cx))
+#[gpui::test]
+async fn test_guest_read_only_access(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree("/a", json!({ "main.rs": "fn main() {}" }))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
         .await
         .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
     executor.run_until_parked();
-    assert_eq!(
-        contacts(&client_a, cx_a),
-        [
-            ("user_b".to_string(), "online", "free"),
-            ("user_c".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_b, cx_b),
-        [
-            ("user_a".to_string(), "online", "free"),
-            ("user_c".to_string(), "online", "free"),
-            ("user_d".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_c, cx_c),
-        [
-            ("user_a".to_string(), "online", "free"),
-            ("user_b".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_d, cx_d),
-        [("user_b".to_string(), "online", "free")]
-    );
+    let peer_id_b = client_b.peer_id().unwrap();
 
-    active_call_a
-        .update(cx_a, |call, cx| {
-            call.invite(client_b.user_id().unwrap(), None, cx)
+    let buffer_a = project_a
+        .update(cx_a, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
         })
         .await
         .unwrap();
-    executor.run_until_parked();
-    assert_eq!(
-        contacts(&client_a, cx_a),
-        [
-            ("user_b".to_string(), "online", "busy"),
-            ("user_c".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_b, cx_b),
-        [
-            ("user_a".to_string(), "online", "busy"),
-            ("user_c".to_string(), "online", "free"),
-            ("user_d".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_c, cx_c),
-        [
-            ("user_a".to_string(), "online", "busy"),
-            ("user_b".to_string(), "online", "busy")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_d, cx_d),
-        [("user_b".to_string(), "online", "busy")]
-    );
+    let buffer_b = project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
+        })
+        .await
+        .unwrap();
+    assert!(!project_b.read_with(cx_b, |project, _| project.is_read_only()));
+    project_a.read_with(cx_a, |project, _| {
+        assert!(!project.collaborators()[&peer_id_b].read_only)
+    });
 
-    server.forbid_connections();
-    server.disconnect_client(client_a.peer_id().unwrap());
-    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
-    assert_eq!(contacts(&client_a, cx_a), []);
-    assert_eq!(
-        contacts(&client_b, cx_b),
-        [
-            ("user_a".to_string(), "offline", "free"),
-            ("user_c".to_string(), "online", "free"),
-            ("user_d".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_c, cx_c),
-        [
-            ("user_a".to_string(), "offline", "free"),
-            ("user_b".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_d, cx_d),
-        [("user_b".to_string(), "online", "free")]
-    );
+    // Guests can't change anyone's access.
+    let peer_id_a = client_a.peer_id().unwrap();
+    project_b
+        .update(cx_b, |project, cx| {
+            project.set_collaborator_read_only(peer_id_a, true, cx)
+        })
+        .await
+        .unwrap_err();
 
-    // Test removing a contact
+    // The host makes the project read-only for the guest.
+    project_a
+        .update(cx_a, |project, cx| {
+            project.set_collaborator_read_only(peer_id_b, true, cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    project_a.read_with(cx_a, |project, _| {
+        assert!(project.collaborators()[&peer_id_b].read_only)
+    });
+    assert!(project_b.read_with(cx_b, |project, _| project.is_read_only()));
+    assert!(buffer_b.read_with(cx_b, |buffer, _| buffer.read_only()));
+
+    // The server rejects edits from the guest, even if their client sends them.
+    let buffer_id = buffer_b.read_with(cx_b, |buffer, _| buffer.remote_id());
     client_b
-        .user_store()
-        .update(cx_b, |store, cx| {
-            store.remove_contact(client_c.user_id().unwrap(), cx)
+        .client()
+        .request(proto::UpdateBuffer {
+            project_id,
+            buffer_id: buffer_id.into(),
+            operations: vec![proto::Operation {
+                variant: Some(proto::operation::Variant::UpdateDiagnostics(
+                    Default::default(),
+                )),
+            }],
+        })
+        .await
+        .unwrap_err();
+
+    // The host gives the guest write access back.
+    project_a
+        .update(cx_a, |project, cx| {
+            project.set_collaborator_read_only(peer_id_b, false, cx)
         })
         .await
         .unwrap();
     executor.run_until_parked();
+    assert!(!project_b.read_with(cx_b, |project, _| project.is_read_only()));
+    assert!(!buffer_b.read_with(cx_b, |buffer, _| buffer.read_only()));
+    buffer_b.update(cx_b, |buffer, cx| buffer.edit([(0..0, "// ")], None, cx));
+    executor.run_until_parked();
     assert_eq!(
-        contacts(&client_b, cx_b),
-        [
-            ("user_a".to_string(), "offline", "free"),
-            ("user_d".to_string(), "online", "free")
-        ]
-    );
-    assert_eq!(
-        contacts(&client_c, cx_c),
-        [("user_a".to_string(), "offline", "free"),]
+        buffer_a.read_with(cx_a, |buffer, _| buffer.text()),
+        "// fn main() {}"
     );
-
-    fn contacts(
-        client: &TestClient,
-        cx: &TestAppContext,
-    ) -> Vec<(String, &'static str, &'static str)> {
-        client.user_store().read_with(cx, |store, _| {
-            store
-                .contacts()
-                .iter()
-                .map(|contact| {
-                    (
-                        contact.user.github_login.clone(),
-                        if contact.online { "online" } else { "offline" },
-                        if contact.busy { "busy" } else { "free" },
-                    )
-                })
-                .collect()
-        })
-    }
 }
 
-#[gpui::test(iterations = 10)]
-async fn test_contact_requests(
+#[gpui::test]
+async fn test_guest_edits_while_becoming_read_only(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
-    cx_a2: &mut TestAppContext,
     cx_b: &mut TestAppContext,
-    cx_b2: &mut TestAppContext,
-    cx_c: &mut TestAppContext,
-    cx_c2: &mut TestAppContext,
 ) {
-    // Connect to a server as 3 clients.
     let mut server = TestServer::start(executor.clone()).await;
     let client_a = server.create_client(cx_a, "user_a").await;
-    let client_a2 = server.create_client(cx_a2, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
-    let client_b2 = server.create_client(cx_b2, "user_b").await;
-    let client_c = server.create_client(cx_c, "user_c").await;
-    let client_c2 = server.create_client(cx_c2, "user_c").await;
-
-    assert_eq!(client_a.user_id().unwrap(), client_a2.user_id().unwrap());
-    assert_eq!(client_b.user_id().unwrap(), client_b2.user_id().unwrap());
-    assert_eq!(client_c.user_id().unwrap(), client_c2.user_id().unwrap());
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
 
-    // User A and User C request that user B become their contact.
     client_a
-        .user_store()
-        .update(cx_a, |store, cx| {
-            store.request_contact(client_b.user_id().unwrap(), cx)
+        .fs()
+        .insert_tree("/a", json!({ "main.rs": "fn main() {}" }))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    executor.run_until_parked();
+    let peer_id_a = client_a.peer_id().unwrap();
+    let peer_id_b = client_b.peer_id().unwrap();
+
+    let buffer_a = project_a
+        .update(cx_a, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
+        })
+        .await
+        .unwrap();
+    let buffer_b = project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
         })
         .await
         .unwrap();
-    client_c
-        .user_store()
-        .update(cx_c, |store, cx| {
-            store.request_contact(client_b.user_id().unwrap(), cx)
-        })
+
+    // The project becomes read-only for the guest while both of them are editing,
+    // before the guest learns of it, so the server refuses the guest's edit.
+    server
+        .app_state
+        .db
+        .set_project_collaborator_read_only(
+            ProjectId::from_proto(project_id),
+            peer_id_a.into(),
+            peer_id_b.into(),
+            true,
+        )
         .await
         .unwrap();
+    buffer_b.update(cx_b, |buffer, cx| buffer.edit([(0..0, "// ")], None, cx));
+    buffer_a.update(cx_a, |buffer, cx| {
+        buffer.edit([(buffer.len()..buffer.len(), " // host")], None, cx)
+    });
     executor.run_until_parked();
-
-    // All users see the pending request appear in all their clients.
-    assert_eq!(
-        client_a.summarize_contacts(cx_a).outgoing_requests,
-        &["user_b"]
-    );
-    assert_eq!(
-        client_a2.summarize_contacts(cx_a2).outgoing_requests,
-        &["user_b"]
-    );
-    assert_eq!(
-        client_b.summarize_contacts(cx_b).incoming_requests,
-        &["user_a", "user_c"]
-    );
     assert_eq!(
-        client_b2.summarize_contacts(cx_b2).incoming_requests,
-        &["user_a", "user_c"]
+        buffer_a.read_with(cx_a, |buffer, _| buffer.text()),
+        "fn main() {} // host"
     );
+
+    // Once the guest learns of it, their buffer is kept up to date with the host's,
+    // along with the edit that the host hasn't received.
+    project_a
+        .update(cx_a, |project, cx| {
+            project.set_collaborator_read_only(peer_id_b, true, cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert!(buffer_b.read_with(cx_b, |buffer, _| buffer.read_only()));
+    buffer_a.update(cx_a, |buffer, cx| buffer.edit([(0..0, "\n")], None, cx));
+    executor.run_until_parked();
     assert_eq!(
-        client_c.summarize_contacts(cx_c).outgoing_requests,
-        &["user_b"]
+        buffer_a.read_with(cx_a, |buffer, _| buffer.text()),
+        "\nfn main() {} // host"
     );
     assert_eq!(
-        client_c2.summarize_contacts(cx_c2).outgoing_requests,
-        &["user_b"]
+        buffer_b.read_with(cx_b, |buffer, _| buffer.text()),
+        "\n// fn main() {} // host"
     );
 
-    // Contact requests are present upon connecting (tested here via disconnect/reconnect)
-    disconnect_and_reconnect(&client_a, cx_a).await;
-    disconnect_and_reconnect(&client_b, cx_b).await;
-    disconnect_and_reconnect(&client_c, cx_c).await;
+    // When the guest can edit again, the host receives the edit they kept.
+    project_a
+        .update(cx_a, |project, cx| {
+            project.set_collaborator_read_only(peer_id_b, false, cx)
+        })
+        .await
+        .unwrap();
     executor.run_until_parked();
     assert_eq!(
-        client_a.summarize_contacts(cx_a).outgoing_requests,
-        &["user_b"]
-    );
-    assert_eq!(
-        client_b.summarize_contacts(cx_b).incoming_requests,
-        &["user_a", "user_c"]
+        buffer_a.read_with(cx_a, |buffer, _| buffer.text()),
+        "\n// fn main() {} // host"
     );
     assert_eq!(
-        client_c.summarize_contacts(cx_c).outgoing_requests,
-        &["user_b"]
+        buffer_b.read_with(cx_b, |buffer, _| buffer.text()),
+        "\n// fn main() {} // host"
     );
+}
 
-    // User B accepts the request from user A.
-    client_b
-        .user_store()
-        .update(cx_b, |store, cx| {
-            store.respond_to_contact_request(client_a.user_id().unwrap(), true, cx)
+#[gpui::test]
+async fn test_edit_suggestions(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/a",
+            json!({
+                ".zed": { "collab.toml": "read_only = true\n" },
+                "main.rs": "fn main() {}\n",
+            }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    executor.run_until_parked();
+    assert!(project_b.read_with(cx_b, |project, _| project.is_read_only()));
+
+    let buffer_a = project_a
+        .update(cx_a, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
+        })
+        .await
+        .unwrap();
+    let buffer_b = project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer((worktree_id, "main.rs"), cx)
         })
         .await
         .unwrap();
 
+    // The read-only guest suggests a couple of edits, which the host sees.
+    let (rename, comment) = buffer_b.read_with(cx_b, |buffer, _| {
+        (
+            vec![(
+                buffer.anchor_before(3)..buffer.anchor_after(7),
+                "start".to_string(),
+            )],
+            vec![(
+                buffer.anchor_before(0)..buffer.anchor_before(0),
+                "// guest\n".to_string(),
+            )],
+        )
+    });
+    let rename = project_b
+        .update(cx_b, |project, cx| {
+            project.suggest_edit(&buffer_b, rename, cx)
+        })
+        .await
+        .unwrap();
+    let comment = project_b
+        .update(cx_b, |project, cx| {
+            project.suggest_edit(&buffer_b, comment, cx)
+        })
+        .await
+        .unwrap();
     executor.run_until_parked();
+    assert_eq!(rename.author_user_id, client_b.user_id());
+    project_a.read_with(cx_a, |project, _| {
+        assert_eq!(
+            project.edit_suggestions(),
+            [rename.clone(), comment.clone()]
+        );
+    });
 
-    // User B sees user A as their contact now in all client, and the incoming request from them is removed.
-    let contacts_b = client_b.summarize_contacts(cx_b);
-    assert_eq!(contacts_b.current, &["user_a"]);
-    assert_eq!(contacts_b.incoming_requests, &["user_c"]);
-    let contacts_b2 = client_b2.summarize_contacts(cx_b2);
-    assert_eq!(contacts_b2.current, &["user_a"]);
-    assert_eq!(contacts_b2.incoming_requests, &["user_c"]);
+    // Suggestions that insert too much text are refused.
+    let oversized = buffer_b.read_with(cx_b, |buffer, _| {
+        vec![(
+            buffer.anchor_before(0)..buffer.anchor_before(0),
+            "a".repeat(MAX_SUGGESTED_TEXT_LEN + 1),
+        )]
+    });
+    project_b
+        .update(cx_b, |project, cx| {
+            project.suggest_edit(&buffer_b, oversized, cx)
+        })
+        .await
+        .unwrap_err();
 
-    // User A sees user B as their contact now in all clients, and the outgoing request to them is removed.
-    let contacts_a = client_a.summarize_contacts(cx_a);
-    assert_eq!(contacts_a.current, &["user_b"]);
-    assert!(contacts_a.outgoing_requests.is_empty());
-    let contacts_a2 = client_a2.summarize_contacts(cx_a2);
-    assert_eq!(contacts_a2.current, &["user_b"]);
-    assert!(contacts_a2.outgoing_requests.is_empty());
+    // Only the host can apply or reject suggestions.
+    project_b
+        .update(cx_b, |project, cx| {
+            project.apply_edit_suggestion(rename.id, cx)
+        })
+        .unwrap_err();
 
-    // Contacts are present upon connecting (tested here via disconnect/reconnect)
-    disconnect_and_reconnect(&client_a, cx_a).await;
-    disconnect_and_reconnect(&client_b, cx_b).await;
-    disconnect_and_reconnect(&client_c, cx_c).await;
+    // The host keeps editing, then applies one suggestion and rejects the other.
+    buffer_a.update(cx_a, |buffer, cx| {
+        buffer.edit([(13..13, "// host\n")], None, cx)
+    });
+    project_a
+        .update(cx_a, |project, cx| {
+            project.apply_edit_suggestion(rename.id, cx)
+        })
+        .unwrap();
+    project_a
+        .update(cx_a, |project, cx| {
+            project.reject_edit_suggestion(comment.id, cx)
+        })
+        .unwrap();
     executor.run_until_parked();
-    assert_eq!(client_a.summarize_contacts(cx_a).current, &["user_b"]);
-    assert_eq!(client_b.summarize_contacts(cx_b).current, &["user_a"]);
     assert_eq!(
-        client_b.summarize_contacts(cx_b).incoming_requests,
-        &["user_c"]
+        buffer_b.read_with(cx_b, |buffer, _| buffer.text()),
+        "fn start() {}\n// host\n"
     );
-    assert!(client_c.summarize_contacts(cx_c).current.is_empty());
+    project_b.read_with(cx_b, |project, _| {
+        assert!(project.edit_suggestions().is_empty());
+    });
+
+    // The applied text is credited to the guest who suggested it.
+    let attribution = project_a.update(cx_a, |project, cx| project.edit_attribution(&buffer_a, cx));
+    let attributed_offsets = buffer_a.read_with(cx_a, |buffer, _| {
+        attribution
+            .into_iter()
+            .map(|attributed_range| {
+                (
+                    attributed_range.range.to_offset(buffer),
+                    attributed_range.author.github_login,
+                )
+            })
+            .collect::<Vec<_>>()
+    });
     assert_eq!(
-        client_c.summarize_contacts(cx_c).outgoing_requests,
-        &["user_b"]
+        attributed_offsets,
+        [(3..8, "user_b".to_string()), (14..22, "user_a".to_string())]
     );
+}
 
-    // User B rejects the request from user C.
-    client_b
-        .user_store()
-        .update(cx_b, |store, cx| {
-            store.respond_to_contact_request(client_c.user_id().unwrap(), false, cx)
+#[gpui::test(iterations = 10)]
+async fn test_network_faults(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+    mut rng: StdRng,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    server.inject_network_faults(
+        NetworkFaults {
+            delay_probability: 0.3,
+            drop_probability: 0.,
+            max_delay: 10,
+        },
+        StdRng::seed_from_u64(rng.gen()),
+    );
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree("/a", json!({ "a.txt": "" }))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
+    let buffer_a = project_a
+        .update(cx_a, |project, cx| {
+            project.open_buffer((worktree_id, "a.txt"), cx)
+        })
+        .await
+        .unwrap();
+    let buffer_b = project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer((worktree_id, "a.txt"), cx)
         })
         .await
         .unwrap();
 
-    executor.run_until_parked();
-
-    // User B doesn't see user C as their contact, and the incoming request from them is removed.
-    let contacts_b = client_b.summarize_contacts(cx_b);
-    assert_eq!(contacts_b.current, &["user_a"]);
-    assert!(contacts_b.incoming_requests.is_empty());
-    let contacts_b2 = client_b2.summarize_contacts(cx_b2);
-    assert_eq!(contacts_b2.current, &["user_a"]);
-    assert!(contacts_b2.incoming_requests.is_empty());
-
-    // User C doesn't see user B as their contact, and the outgoing request to them is removed.
-    let contacts_c = client_c.summarize_contacts(cx_c);
-    assert!(contacts_c.current.is_empty());
-    assert!(contacts_c.outgoing_requests.is_empty());
-    let contacts_c2 = client_c2.summarize_contacts(cx_c2);
-    assert!(contacts_c2.current.is_empty());
-    assert!(contacts_c2.outgoing_requests.is_empty());
-
-    // Incoming/outgoing requests are not present upon connecting (tested here via disconnect/reconnect)
-    disconnect_and_reconnect(&client_a, cx_a).await;
-    disconnect_and_reconnect(&client_b, cx_b).await;
-    disconnect_and_reconnect(&client_c, cx_c).await;
-    executor.run_until_parked();
-    assert_eq!(client_a.summarize_contacts(cx_a).current, &["user_b"]);
-    assert_eq!(client_b.summarize_contacts(cx_b).current, &["user_a"]);
-    assert!(client_b
-        .summarize_contacts(cx_b)
-        .incoming_requests
-        .is_empty());
-    assert!(client_c.summarize_contacts(cx_c).current.is_empty());
-    assert!(client_c
-        .summarize_contacts(cx_c)
-        .outgoing_requests
-        .is_empty());
-
-    async fn disconnect_and_reconnect(client: &TestClient, cx: &mut TestAppContext) {
-        client.disconnect(&cx.to_async());
-        client.clear_contacts(cx).await;
-        client
-            .authenticate_and_connect(false, &cx.to_async())
-            .await
-            .unwrap();
+    // Edits converge even when their messages are delayed, so that the host's and
+    // the guest's edits cross each other.
+    for _ in 0..10 {
+        for (buffer, cx) in [(&buffer_a, &mut *cx_a), (&buffer_b, &mut *cx_b)] {
+            buffer.update(cx, |buffer, cx| {
+                let offset = rng.gen_range(0..=buffer.len());
+                buffer.edit([(offset..offset, "x")], None, cx);
+            });
+        }
+        if rng.gen_bool(0.3) {
+            executor.run_until_parked();
+        }
     }
+    executor.run_until_parked();
+    assert_eq!(buffer_a.read_with(cx_a, |buffer, _| buffer.len()), 20);
+    assert_eq!(
+        buffer_a.read_with(cx_a, |buffer, _| buffer.text()),
+        buffer_b.read_with(cx_b, |buffer, _| buffer.text())
+    );
+
+    // Losing a message severs the connection, and the guest recovers by reconnecting.
+    let peer_id_b = client_b.peer_id().unwrap();
+    server.set_network_faults(
+        peer_id_b,
+        NetworkFaults {
+            drop_probability: 1.,
+            ..Default::default()
+        },
+    );
+    buffer_b.update(cx_b, |buffer, cx| buffer.edit([(0..0, "y")], None, cx));
+    executor.run_until_parked();
+    assert_eq!(buffer_a.read_with(cx_a, |buffer, _| buffer.len()), 20);
+
+    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
+    executor.run_until_parked();
+    assert_ne!(client_b.peer_id().unwrap(), peer_id_b);
+    assert_eq!(buffer_a.read_with(cx_a, |buffer, _| buffer.len()), 21);
+    assert_eq!(
+        buffer_a.read_with(cx_a, |buffer, _| buffer.text()),
+        buffer_b.read_with(cx_b, |buffer, _| buffer.text())
+    );
 }
 
-#[gpui::test(iterations = 10)]
-async fn test_join_call_after_screen_was_shared(
+#[gpui::test]
+async fn test_lazily_loaded_remote_worktree(
     executor: BackgroundExecutor,
     cx_a: &mut TestAppContext,
     cx_b: &mut TestAppContext,
 ) {
-    let mut server = TestServer::start(executor.clone()).await;
-
+    let mut server = TestServer::start_with_config(executor.clone(), |config| {
+        config.lazy_worktree_entry_threshold = Some(0);
+    })
+    .await;
     let client_a = server.create_client(cx_a, "user_a").await;
     let client_b = server.create_client(cx_b, "user_b").await;
     server
-        .make_contacts(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
         .await;
-
     let active_call_a = cx_a.read(ActiveCall::global);
-    let active_call_b = cx_b.read(ActiveCall::global);
 
-    // Call users B and C from client A.
-    active_call_a
-        .update(cx_a, |call, cx| {
-            call.invite(client_b.user_id().unwrap(), None, cx)
-        })
+    client_a
+        .fs()
+        .insert_tree(
+            "/a",
+            json!({
+                "a.txt": "",
+                "dir1": {
+                    "b.txt": "",
+                    "dir2": { "c.txt": "" },
+                },
+                "dir3": { "d.txt": "" },
+            }),
+        )
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
         .await
         .unwrap();
-
-    let room_a = active_call_a.read_with(cx_a, |call, _| call.room().unwrap().clone());
+    let project_b = client_b.build_remote_project(project_id, cx_b).await;
     executor.run_until_parked();
-    assert_eq!(
-        room_participants(&room_a, cx_a),
-        RoomParticipants {
-            remote: Default::default(),
-            pending: vec!["user_b".to_string()]
-        }
-    );
 
-    // User B receives the call.
+    let worktree_b = project_b.read_with(cx_b, |project, cx| {
+        project.worktree_for_id(worktree_id, cx).unwrap()
+    });
+    let paths_b = |cx: &mut TestAppContext| {
+        worktree_b.read_with(cx, |worktree, _| {
+            worktree
+                .paths()
+                .map(|path| path.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        })
+    };
 
-    let mut incoming_call_b = active_call_b.read_with(cx_b, |call, _| call.incoming());
-    let call_b = incoming_call_b.next().await.unwrap().unwrap();
-    assert_eq!(call_b.calling_user.github_login, "user_a");
+    // The guest starts out with the root's children only.
+    assert_eq!(paths_b(cx_b), ["a.txt", "dir1", "dir3"]);
+    worktree_b.read_with(cx_b, |worktree, _| {
+        let dir1 = worktree.entry_for_path("dir1").unwrap();
+        assert!(dir1.kind.is_unloaded());
+    });
 
-    // User A shares their screen
-    let display = MacOSDisplay::new();
-    active_call_a
-        .update(cx_a, |call, cx| {
-            call.room().unwrap().update(cx, |room, cx| {
-                room.set_display_sources(vec![display.clone()]);
-                room.share_screen(cx)
-            })
-        })
+    // Changes inside a directory the guest hasn't loaded aren't replicated...
+    client_a.fs().insert_file("/a/dir3/e.txt", "".into()).await;
+    client_a
+        .fs()
+        .rename(
+            "/a/a.txt".as_ref(),
+            "/a/dir3/a.txt".as_ref(),
+            Default::default(),
+        )
         .await
         .unwrap();
+    executor.run_until_parked();
+    assert_eq!(paths_b(cx_b), ["dir1", "dir3"]);
 
-    client_b.user_store().update(cx_b, |user_store, _| {
-        user_store.clear_cache();
+    // ...but they're there once it loads the directory.
+    let dir3_id = worktree_b.read_with(cx_b, |worktree, _| {
+        worktree.entry_for_path("dir3").unwrap().id
     });
-
-    // User B joins the room
-    active_call_b
-        .update(cx_b, |call, cx| call.accept_incoming(cx))
+    project_b
+        .update(cx_b, |project, cx| {
+            project.expand_entry(worktree_id, dir3_id, cx).unwrap()
+        })
         .await
         .unwrap();
+    assert_eq!(
+        paths_b(cx_b),
+        ["dir1", "dir3", "dir3/a.txt", "dir3/d.txt", "dir3/e.txt"]
+    );
 
-    let room_b = active_call_b.read_with(cx_b, |call, _| call.room().unwrap().clone());
-    assert!(incoming_call_b.next().await.unwrap().is_none());
-
+    // Changes inside loaded directories are replicated as usual.
+    client_a.fs().insert_file("/a/dir3/f.txt", "".into()).await;
     executor.run_until_parked();
     assert_eq!(
-        room_participants(&room_a, cx_a),
-        RoomParticipants {
-            remote: vec!["user_b".to_string()],
-            pending: vec![],
-        }
+        paths_b(cx_b),
+        [
+            "dir1",
+            "dir3",
+            "dir3/a.txt",
+            "dir3/d.txt",
+            "dir3/e.txt",
+            "dir3/f.txt"
+        ]
     );
+
+    // Opening a buffer loads the directories that contain it.
+    project_b
+        .update(cx_b, |project, cx| {
+            project.open_buffer((worktree_id, "dir1/dir2/c.txt"), cx)
+        })
+        .await
+        .unwrap();
+    executor.run_until_parked();
     assert_eq!(
-        room_participants(&room_b, cx_b),
-        RoomParticipants {
-            remote: vec!["user_a".to_string()],
-            pending: vec![],
-        }
+        paths_b(cx_b),
+        [
+            "dir1",
+            "dir1/b.txt",
+            "dir1/dir2",
+            "dir1/dir2/c.txt",
+            "dir3",
+            "dir3/a.txt",
+            "dir3/d.txt",
+            "dir3/e.txt",
+            "dir3/f.txt"
+        ]
     );
 
-    // Ensure User B sees User A's screenshare.
+    // Wildcards in a directory's name only match themselves.
+    client_a
+        .fs()
+        .insert_tree("/a/dir_4", json!({ "g.txt": "", "dir5": { "h.txt": "" } }))
+        .await;
+    client_a
+        .fs()
+        .insert_tree("/a/dira4", json!({ "i.txt": "" }))
+        .await;
+    executor.run_until_parked();
+    let dir4_id = worktree_b.read_with(cx_b, |worktree, _| {
+        worktree.entry_for_path("dir_4").unwrap().id
+    });
+    project_b
+        .update(cx_b, |project, cx| {
+            project.expand_entry(worktree_id, dir4_id, cx).unwrap()
+        })
+        .await
+        .unwrap();
+    let paths = paths_b(cx_b);
+    assert!(paths.contains(&"dir_4/g.txt".to_string()));
+    assert!(paths.contains(&"dir_4/dir5".to_string()));
+    assert!(!paths.contains(&"dir_4/dir5/h.txt".to_string()));
+    assert!(!paths.contains(&"dira4/i.txt".to_string()));
 
-    room_b.read_with(cx_b, |room, _| {
-        assert_eq!(
-            room.remote_participants()
-                .get(&client_a.user_id().unwrap())
-                .unwrap()
-                .video_tracks
-                .len(),
-            1
-        );
+    // Searching the guest's paths loads all of them, and it receives every
+    // change from then on.
+    project_b
+        .update(cx_b, |project, cx| project.load_all_entries(cx))
+        .await
+        .unwrap();
+    client_a.fs().insert_file("/a/dira4/j.txt", "".into()).await;
+    executor.run_until_parked();
+    worktree_b.read_with(cx_b, |worktree, _| {
+        assert!(worktree.entries(true).all(|entry| !entry.is_unloaded()));
     });
+    let paths = paths_b(cx_b);
+    assert!(paths.contains(&"dir_4/dir5/h.txt".to_string()));
+    assert!(paths.contains(&"dira4/i.txt".to_string()));
+    assert!(paths.contains(&"dira4/j.txt".to_string()));
 }
 
 #[gpui::test]
-async fn test_right_click_menu_behind_collab_panel(cx: &mut TestAppContext) {
-    let mut server = TestServer::start(cx.executor().clone()).await;
-    let client_a = server.create_client(cx, "user_a").await;
-    let (_workspace_a, cx) = client_a.build_test_workspace(cx).await;
-
-    cx.simulate_resize(size(px(300.), px(300.)));
+async fn test_recovering_guest_operations(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b1: &mut TestAppContext,
+    cx_b2: &mut TestAppContext,
+    cx_c: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b1 = server.create_client(cx_b1, "user_b").await;
+    let client_c = server.create_client(cx_c, "user_c").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b1, cx_b1), (&client_c, cx_c)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
 
-    cx.simulate_keystrokes("cmd-n cmd-n cmd-n");
-    cx.update(|cx| cx.refresh());
+    client_a
+        .fs()
+        .insert_tree("/a", json!({ "a.txt": "one" }))
+        .await;
+    let (project_a, worktree_id) = client_a.build_local_project("/a", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let buffer_a = project_a
+        .update(cx_a, |project, cx| {
+            project.open_buffer((worktree_id, "a.txt"), cx)
+        })
+        .await
+        .unwrap();
+    let project_b1 = client_b1.build_remote_project(project_id, cx_b1).await;
+    let buffer_b1 = project_b1
+        .update(cx_b1, |project, cx| {
+            project.open_buffer((worktree_id, "a.txt"), cx)
+        })
+        .await
+        .unwrap();
+    let old_replica_id = project_b1.read_with(cx_b1, |project, _| project.replica_id());
 
-    let tab_bounds = cx.debug_bounds("TAB-2").unwrap();
-    let new_tab_button_bounds = cx.debug_bounds("ICON-Plus").unwrap();
+    // User B's edit never reaches the host, because they lose their connection for good.
+    server.forbid_connections();
+    server.disconnect_client(client_b1.peer_id().unwrap());
+    buffer_b1.update(cx_b1, |buffer, cx| buffer.edit([(3..3, " two")], None, cx));
+    executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
+    server.allow_connections();
+    executor.advance_clock(RECEIVE_TIMEOUT);
 
-    assert!(
-        tab_bounds.intersects(&new_tab_button_bounds),
-        "Tab should overlap with the new tab button, if this is failing check if there's been a redesign!"
+    // User C takes the replica that user B made the edit as.
+    let project_c = client_c.build_remote_project(project_id, cx_c).await;
+    assert_eq!(
+        project_c.read_with(cx_c, |project, _| project.replica_id()),
+        old_replica_id
     );
 
-    cx.simulate_event(MouseDownEvent {
-        button: MouseButton::Right,
-        position: new_tab_button_bounds.center(),
-        modifiers: Modifiers::default(),
-        click_count: 1,
+    // User B comes back on another connection, and finds the edit.
+    let client_b2 = server.create_client(cx_b2, "user_b").await;
+    let active_call_b2 = cx_b2.read(ActiveCall::global);
+    let mut incoming_call_b2 = active_call_b2.read_with(cx_b2, |call, _| call.incoming());
+    active_call_a
+        .update(cx_a, |call, cx| {
+            call.invite(client_b2.user_id().unwrap(), None, cx)
+        })
+        .await
+        .unwrap();
+    incoming_call_b2.next().await.unwrap().unwrap();
+    active_call_b2
+        .update(cx_b2, |call, cx| call.accept_incoming(cx))
+        .await
+        .unwrap();
+    let project_b2 = client_b2.build_remote_project(project_id, cx_b2).await;
+    executor.run_until_parked();
+    project_b2.read_with(cx_b2, |project, _| {
+        assert_ne!(project.replica_id(), old_replica_id);
+        assert!(project.has_recoverable_operations());
     });
 
-    // regression test that the right click menu for tabs does not open.
-    assert!(cx.debug_bounds("MENU_ITEM-Close").is_none());
-
-    let tab_bounds = cx.debug_bounds("TAB-1").unwrap();
-    cx.simulate_event(MouseDownEvent {
-        button: MouseButton::Right,
-        position: tab_bounds.center(),
-        modifiers: Modifiers::default(),
-        click_count: 1,
-    });
-    assert!(cx.debug_bounds("MENU_ITEM-Close").is_some());
-}
+    // The edit can't be replayed while user C is using its replica, but it's kept.
+    project_b2
+        .update(cx_b2, |project, cx| project.recover_operations(cx))
+        .await
+        .unwrap_err();
+    assert!(project_b2.read_with(cx_b2, |project, _| project.has_recoverable_operations()));
 
-#[gpui::test]
-async fn test_cmd_k_left(cx: &mut TestAppContext) {
-    let client = TestServer::start1(cx).await;
-    let (workspace, cx) = client.build_test_workspace(cx).await;
+    // Once user C leaves, the edit is replayed as the replica it was made as, and it
+    // reaches the host.
+    cx_c.update(|_| drop(project_c));
+    executor.run_until_parked();
+    project_b2
+        .update(cx_b2, |project, cx| project.recover_operations(cx))
+        .await
+        .unwrap();
+    executor.run_until_parked();
+    assert!(!project_b2.read_with(cx_b2, |project, _| project.has_recoverable_operations()));
+    assert_eq!(
+        buffer_a.read_with(cx_a, |buffer, _| buffer.text()),
+        "one two"
+    );
+    let buffer_b2 = project_b2
+        .update(cx_b2, |project, cx| {
+            project.open_buffer((worktree_id, "a.txt"), cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        buffer_b2.read_with(cx_b2, |buffer, _| buffer.text()),
+        "one two"
+    );
 
-    cx.simulate_keystrokes("cmd-n");
-    workspace.update(cx, |workspace, cx| {
-        assert!(workspace.items(cx).collect::<Vec<_>>().len() == 1);
-    });
-    cx.simulate_keystrokes("cmd-k left");
-    workspace.update(cx, |workspace, cx| {
-        assert!(workspace.items(cx).collect::<Vec<_>>().len() == 2);
-    });
-    cx.simulate_keystrokes("cmd-k");
-    // sleep for longer than the timeout in keyboard shortcut handling
-    // to verify that it doesn't fire in this case.
-    cx.executor().advance_clock(Duration::from_secs(2));
-    cx.simulate_keystrokes("left");
-    workspace.update(cx, |workspace, cx| {
-        assert!(workspace.items(cx).collect::<Vec<_>>().len() == 2);
-    });
+    // The edit is removed from disk once it's been replayed.
+    let project_c = client_c.build_remote_project(project_id, cx_c).await;
+    executor.run_until_parked();
+    assert!(!project_c.read_with(cx_c, |project, _| project.has_recoverable_operations()));
 }
@@ -0,0 +1,174 @@
+use anyhow::{anyhow, Result};
+use async_tungstenite::tungstenite::Message as WebSocketMessage;
+use futures::{channel::mpsc, Sink, Stream, StreamExt as _};
+use gpui::BackgroundExecutor;
+use parking_lot::Mutex;
+use rand::prelude::*;
+use rpc::Connection;
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+/// How often the messages sent over a test connection misbehave. Each message
+/// is subject to at most one fault, and the fault is chosen by the connection's
+/// own RNG, so that a failing seed reproduces the same races. Like a real stream,
+/// a connection always delivers its messages in order, but because each one is
+/// delayed independently, messages sent over different connections can overtake
+/// each other.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetworkFaults {
+    /// The probability that a message is held back, along with every message
+    /// sent after it, as happens on a slow link.
+    pub delay_probability: f64,
+    /// The probability that a message is lost. A socket can't lose a message and
+    /// keep going, so losing one also severs the connection.
+    pub drop_probability: f64,
+    /// The most times that a delayed message yields to other tasks
+    /// before it's delivered.
+    pub max_delay: usize,
+}
+
+enum Fault {
+    Delay(usize),
+    Drop,
+}
+
+impl NetworkFaults {
+    pub fn is_enabled(&self) -> bool {
+        self.delay_probability > 0. || self.drop_probability > 0.
+    }
+
+    fn choose(&self, rng: &mut StdRng) -> Option<Fault> {
+        if rng.gen_bool(self.drop_probability) {
+            Some(Fault::Drop)
+        } else if rng.gen_bool(self.delay_probability) {
+            Some(Fault::Delay(rng.gen_range(1..=self.max_delay.max(1))))
+        } else {
+            None
+        }
+    }
+}
+
+/// Like [`Connection::in_memory_with_suspension`], but every message is relayed
+/// through a task that injects the given faults. The faults can be changed while
+/// the connection is open.
+pub fn faulty_in_memory_connection(
+    executor: BackgroundExecutor,
+    faults: Arc<Mutex<NetworkFaults>>,
+    rng: StdRng,
+) -> (Connection, Connection, Arc<AtomicBool>, Arc<AtomicBool>) {
+    let killed = Arc::new(AtomicBool::new(false));
+    let suspended = Arc::new(AtomicBool::new(false));
+    let rng = Arc::new(Mutex::new(rng));
+
+    let (a_tx, a_rx) = mpsc::unbounded();
+    let (b_tx, b_rx) = mpsc::unbounded();
+    let a = FaultyLink {
+        tx: relay(a_tx, &faults, &rng, &killed, &executor),
+        rx: b_rx,
+        killed: killed.clone(),
+        suspended: suspended.clone(),
+    };
+    let b = FaultyLink {
+        tx: relay(b_tx, &faults, &rng, &killed, &executor),
+        rx: a_rx,
+        killed: killed.clone(),
+        suspended: suspended.clone(),
+    };
+
+    (Connection::new(a), Connection::new(b), killed, suspended)
+}
+
+/// Spawns a task that forwards messages to `delivered_tx`, injecting faults
+/// along the way, and returns the sender that feeds it.
+fn relay(
+    delivered_tx: mpsc::UnboundedSender<WebSocketMessage>,
+    faults: &Arc<Mutex<NetworkFaults>>,
+    rng: &Arc<Mutex<StdRng>>,
+    killed: &Arc<AtomicBool>,
+    executor: &BackgroundExecutor,
+) -> mpsc::UnboundedSender<WebSocketMessage> {
+    let (sent_tx, mut sent_rx) = mpsc::unbounded::<WebSocketMessage>();
+    let faults = faults.clone();
+    let rng = rng.clone();
+    let killed = killed.clone();
+    let executor = executor.clone();
+    executor
+        .clone()
+        .spawn(async move {
+            while let Some(message) = sent_rx.next().await {
+                executor.simulate_random_delay().await;
+
+                let fault = faults.lock().choose(&mut rng.lock());
+                match fault {
+                    None => {
+                        delivered_tx.unbounded_send(message).ok();
+                    }
+                    Some(Fault::Delay(yields)) => {
+                        for _ in 0..yields {
+                            executor.simulate_random_delay().await;
+                        }
+                        delivered_tx.unbounded_send(message).ok();
+                    }
+                    Some(Fault::Drop) => {
+                        log::info!("dropping message and severing connection");
+                        killed.store(true, SeqCst);
+                        break;
+                    }
+                }
+            }
+        })
+        .detach();
+    sent_tx
+}
+
+struct FaultyLink {
+    tx: mpsc::UnboundedSender<WebSocketMessage>,
+    rx: mpsc::UnboundedReceiver<WebSocketMessage>,
+    killed: Arc<AtomicBool>,
+    suspended: Arc<AtomicBool>,
+}
+
+impl Sink<WebSocketMessage> for FaultyLink {
+    type Error = anyhow::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, message: WebSocketMessage) -> Result<()> {
+        // Writes to a half-open TCP connection will error.
+        if self.killed.load(SeqCst) {
+            return Err(anyhow!("connection lost"));
+        }
+        self.tx
+            .unbounded_send(message)
+            .map_err(|error| anyhow!(error))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<()>> {
+        self.tx.close_channel();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Stream for FaultyLink {
+    type Item = Result<WebSocketMessage>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // Reads from a half-open or suspended TCP connection will hang.
+        if self.killed.load(SeqCst) || self.suspended.load(SeqCst) {
+            return Poll::Pending;
+        }
+        self.rx.poll_next_unpin(cx).map(|message| message.map(Ok))
+    }
+}
@@ -0,0 +1,150 @@
+use gpui::BackgroundExecutor;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{LineWriter, Write as _},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+/// A trace of the operations applied by a randomized test, written as one JSON
+/// object per line to the path in `TRACE_OPERATIONS`, and printed per client by
+/// the `collab_trace` binary.
+///
+/// Each run of a test writes its own trace, so tests that run in parallel don't
+/// interleave their operations. Each line is written as soon as its operation
+/// finishes, so the trace of a run that panics covers every operation up to the
+/// one that failed.
+pub struct OperationTrace {
+    path: PathBuf,
+    state: Mutex<TraceState>,
+}
+
+struct TraceState {
+    writer: LineWriter<File>,
+    /// Orders the starts and finishes of operations together, so that whether
+    /// one operation finished before another started can be read off the trace.
+    next_seq: usize,
+}
+
+/// The projects and buffers that an operation refers to, so that operations on
+/// the same entities can be lined up when reading a trace.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct OperationEntities {
+    pub project_ids: Vec<u64>,
+    pub buffer_ids: Vec<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceOutcome {
+    Applied,
+    Skipped,
+    Failed(String),
+}
+
+#[derive(Serialize)]
+struct TraceEvent {
+    started_seq: usize,
+    finished_seq: usize,
+    started_at_ms: u64,
+    finished_at_ms: u64,
+    peer: String,
+    client_ix: Option<usize>,
+    batch_id: Option<usize>,
+    #[serde(flatten)]
+    entities: OperationEntities,
+    operation: serde_json::Value,
+    outcome: TraceOutcome,
+}
+
+/// An operation that has started, which is written to the trace once it finishes.
+pub struct TracedOperation {
+    trace: Arc<OperationTrace>,
+    started_seq: usize,
+    started_at: Duration,
+    peer: String,
+    client_ix: Option<usize>,
+    batch_id: Option<usize>,
+    entities: OperationEntities,
+    operation: serde_json::Value,
+}
+
+impl OperationTrace {
+    /// Starts writing a new trace to the given path, replacing the trace of any
+    /// previous run. When tests run in parallel, each test's name is added to the
+    /// file name, so that their traces don't overwrite each other.
+    pub fn start(path: &Path) -> Arc<Self> {
+        let path = match thread::current().name() {
+            Some(test_name) if test_name != "main" => {
+                let test_name = test_name.rsplit("::").next().unwrap_or(test_name);
+                let mut file_name = path.file_stem().unwrap_or_default().to_os_string();
+                file_name.push(format!("-{test_name}"));
+                if let Some(extension) = path.extension() {
+                    file_name.push(".");
+                    file_name.push(extension);
+                }
+                path.with_file_name(file_name)
+            }
+            _ => path.to_path_buf(),
+        };
+        let file = File::create(&path).unwrap();
+        Arc::new(Self {
+            path,
+            state: Mutex::new(TraceState {
+                writer: LineWriter::new(file),
+                next_seq: 0,
+            }),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Records the start of an operation.
+    pub fn begin(
+        self: &Arc<Self>,
+        peer: &str,
+        client_ix: Option<usize>,
+        batch_id: Option<usize>,
+        operation: &impl Serialize,
+        entities: OperationEntities,
+        executor: &BackgroundExecutor,
+    ) -> TracedOperation {
+        let started_seq = util::post_inc(&mut self.state.lock().next_seq);
+        TracedOperation {
+            trace: self.clone(),
+            started_seq,
+            started_at: executor.simulated_time(),
+            peer: peer.to_string(),
+            client_ix,
+            batch_id,
+            entities,
+            operation: serde_json::to_value(operation).unwrap(),
+        }
+    }
+}
+
+impl TracedOperation {
+    pub fn finish(self, outcome: TraceOutcome, executor: &BackgroundExecutor) {
+        let mut state = self.trace.state.lock();
+        let event = TraceEvent {
+            started_seq: self.started_seq,
+            finished_seq: util::post_inc(&mut state.next_seq),
+            started_at_ms: self.started_at.as_millis() as u64,
+            finished_at_ms: executor.simulated_time().as_millis() as u64,
+            peer: self.peer,
+            client_ix: self.client_ix,
+            batch_id: self.batch_id,
+            entities: self.entities,
+            operation: self.operation,
+            outcome,
+        };
+        serde_json::to_writer(&mut state.writer, &event).unwrap();
+        state.writer.write_all(b"\n").unwrap();
+    }
+}
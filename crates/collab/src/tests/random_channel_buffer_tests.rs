@@ -1,6 +1,9 @@
 use crate::db::ChannelRole;
 
-use super::{run_randomized_test, RandomizedTest, TestClient, TestError, TestServer, UserTestPlan};
+use super::{
+    replay_randomized_test, run_randomized_test, RandomizedTest, TestClient, TestError, TestServer,
+    UserTestPlan,
+};
 use anyhow::Result;
 use async_trait::async_trait;
 use gpui::{BackgroundExecutor, SharedString, TestAppContext};
@@ -25,6 +28,19 @@ async fn test_random_channel_buffers(
     run_randomized_test::<RandomChannelBufferTest>(cx, executor, rng).await;
 }
 
+#[gpui::test]
+async fn test_channel_buffer_edits_after_reconnect(
+    cx: &mut TestAppContext,
+    executor: BackgroundExecutor,
+) {
+    replay_randomized_test::<RandomChannelBufferTest>(
+        cx,
+        executor,
+        include_bytes!("test_plans/channel_buffer_edits_after_reconnect.json"),
+    )
+    .await;
+}
+
 struct RandomChannelBufferTest;
 
 #[derive(Clone, Serialize, Deserialize)]
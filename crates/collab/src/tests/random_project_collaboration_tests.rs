@@ -1,4 +1,7 @@
-use super::{RandomizedTest, TestClient, TestError, TestServer, UserTestPlan};
+use super::{
+    ClosedRemoteBuffer, DeletedWorktreeEntry, OperationEntities, RandomizedTest, TestClient,
+    TestError, TestServer, UserTestPlan,
+};
 use crate::{db::UserId, tests::run_randomized_test};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
@@ -6,26 +9,30 @@ use call::ActiveCall;
 use collections::{BTreeMap, HashMap};
 use editor::Bias;
 use fs::{repository::GitFileStatus, FakeFs, Fs as _};
-use futures::StreamExt;
+use futures::{FutureExt as _, StreamExt};
 use gpui::{BackgroundExecutor, Model, TestAppContext};
 use language::{
-    range_to_lsp, FakeLspAdapter, Language, LanguageConfig, LanguageMatcher, PointUtf16,
+    range_to_lsp, FakeLspAdapter, File as _, Language, LanguageConfig, LanguageMatcher, PointUtf16,
 };
 use lsp::FakeLanguageServer;
 use pretty_assertions::assert_eq;
-use project::{search::SearchQuery, Project, ProjectPath};
+use project::{search::SearchQuery, Entry, Project, ProjectPath, RepositoryEntry, Snapshot};
 use rand::{
     distributions::{Alphanumeric, DistString},
     prelude::*,
 };
 use serde::{Deserialize, Serialize};
 use std::{
+    mem,
     ops::{Deref, Range},
     path::{Path, PathBuf},
     rc::Rc,
     sync::Arc,
 };
-use util::ResultExt;
+use util::{
+    paths::{normalize_unicode, paths_equal_normalized},
+    ResultExt,
+};
 
 #[gpui::test(
     iterations = 100,
@@ -52,19 +59,28 @@ enum ClientOperation {
     },
     OpenRemoteProject {
         host_id: UserId,
-        first_root_name: String,
+        host_project_id: u64,
     },
     AddWorktreeToProject {
         project_root_name: String,
         new_root_path: PathBuf,
     },
     CloseRemoteProject {
+        host_id: UserId,
+        host_project_id: u64,
+    },
+    RemoveWorktreeFromProject {
+        project_root_name: String,
+        worktree_root_name: String,
+    },
+    UnshareProject {
         project_root_name: String,
     },
     OpenBuffer {
         project_root_name: String,
         is_local: bool,
         full_path: PathBuf,
+        detach: bool,
     },
     SearchProject {
         project_root_name: String,
@@ -89,6 +105,24 @@ enum ClientOperation {
         full_path: PathBuf,
         detach: bool,
     },
+    WriteBufferFileOnDisk {
+        project_root_name: String,
+        full_path: PathBuf,
+        content: String,
+    },
+    RenameBufferFileOnDisk {
+        project_root_name: String,
+        full_path: PathBuf,
+        new_full_path: PathBuf,
+    },
+    KillLanguageServer {
+        project_root_name: String,
+        full_path: PathBuf,
+    },
+    RestartLanguageServer {
+        project_root_name: String,
+        full_path: PathBuf,
+    },
     RequestLspDataInBuffer {
         project_root_name: String,
         is_local: bool,
@@ -103,6 +137,23 @@ enum ClientOperation {
         full_path: PathBuf,
         is_dir: bool,
     },
+    RenameWorktreeEntry {
+        project_root_name: String,
+        is_local: bool,
+        full_path: PathBuf,
+        new_full_path: PathBuf,
+    },
+    MoveWorktreeEntry {
+        project_root_name: String,
+        is_local: bool,
+        full_path: PathBuf,
+        new_full_path: PathBuf,
+    },
+    DeleteWorktreeEntry {
+        project_root_name: String,
+        is_local: bool,
+        full_path: PathBuf,
+    },
     WriteFsEntry {
         path: PathBuf,
         is_dir: bool,
@@ -233,7 +284,7 @@ impl RandomizedTest for ProjectCollaborationTest {
                                             } else {
                                                 Some((
                                                     UserId::from_proto(participant.user.id),
-                                                    project.worktree_root_names[0].clone(),
+                                                    project.host_project_id,
                                                 ))
                                             }
                                         })
@@ -241,11 +292,11 @@ impl RandomizedTest for ProjectCollaborationTest {
                                     .collect::<Vec<_>>()
                             });
                             if !new_remote_projects.is_empty() {
-                                let (host_id, first_root_name) =
-                                    new_remote_projects.choose(rng).unwrap().clone();
+                                let (host_id, host_project_id) =
+                                    *new_remote_projects.choose(rng).unwrap();
                                 break ClientOperation::OpenRemoteProject {
                                     host_id,
-                                    first_root_name,
+                                    host_project_id,
                                 };
                             }
                         }
@@ -260,17 +311,23 @@ impl RandomizedTest for ProjectCollaborationTest {
                     71..=80 => {
                         if !client.remote_projects().is_empty() {
                             let project = client.remote_projects().choose(rng).unwrap().clone();
-                            let first_root_name = root_name_for_project(&project, cx);
-                            break ClientOperation::CloseRemoteProject {
-                                project_root_name: first_root_name,
-                            };
+                            let project_id =
+                                project.read_with(cx, |project, _| project.remote_id().unwrap());
+                            if let Some((host_id, host_project_id)) =
+                                host_project_id_for_remote_project(project_id, cx)
+                            {
+                                break ClientOperation::CloseRemoteProject {
+                                    host_id,
+                                    host_project_id,
+                                };
+                            }
                         }
                     }
 
                     // Mutate project worktrees
                     81.. => match rng.gen_range(0..100_u32) {
                         // Add a worktree to a local project
-                        0..=50 => {
+                        0..=40 => {
                             let Some(project) = client.local_projects().choose(rng).cloned() else {
                                 continue;
                             };
@@ -288,6 +345,146 @@ impl RandomizedTest for ProjectCollaborationTest {
                             };
                         }
 
+                        // Remove a worktree from a local project, possibly while guests
+                        // have requests in flight against it
+                        41..=50 => {
+                            let Some(project) = client.local_projects().choose(rng).cloned() else {
+                                continue;
+                            };
+                            let project_root_name = root_name_for_project(&project, cx);
+                            let worktree_root_name = project.read_with(cx, |project, cx| {
+                                project
+                                    .visible_worktrees(cx)
+                                    .skip(1)
+                                    .choose(rng)
+                                    .map(|worktree| worktree.read(cx).root_name().to_string())
+                            });
+                            let Some(worktree_root_name) = worktree_root_name else {
+                                continue;
+                            };
+                            break ClientOperation::RemoveWorktreeFromProject {
+                                project_root_name,
+                                worktree_root_name,
+                            };
+                        }
+
+                        // Rename a file in a worktree, changing its language
+                        51..=60 => {
+                            let Some(project) = choose_random_project(client, rng) else {
+                                continue;
+                            };
+                            let project_root_name = root_name_for_project(&project, cx);
+                            let is_local = project.read_with(cx, |project, _| project.is_local());
+                            let full_path = project.read_with(cx, |project, cx| {
+                                let worktree = project
+                                    .visible_worktrees(cx)
+                                    .filter(|worktree| {
+                                        worktree.read(cx).entries(false).any(|e| e.is_file())
+                                    })
+                                    .choose(rng)?;
+                                let worktree = worktree.read(cx);
+                                let entry = worktree
+                                    .entries(false)
+                                    .filter(|e| e.is_file())
+                                    .choose(rng)?;
+                                Some(Path::new(worktree.root_name()).join(&entry.path))
+                            });
+                            let Some(full_path) = full_path else { continue };
+                            let mut new_full_path = full_path.clone();
+                            if full_path.extension().map_or(false, |e| e == "rs") {
+                                new_full_path.set_extension("txt");
+                            } else {
+                                new_full_path.set_extension("rs");
+                            }
+                            break ClientOperation::RenameWorktreeEntry {
+                                project_root_name,
+                                is_local,
+                                full_path,
+                                new_full_path,
+                            };
+                        }
+
+                        // Move a file or directory into another directory of its worktree
+                        61..=65 => {
+                            let Some(project) = choose_random_project(client, rng) else {
+                                continue;
+                            };
+                            let project_root_name = root_name_for_project(&project, cx);
+                            let is_local = project.read_with(cx, |project, _| project.is_local());
+                            let paths = project.read_with(cx, |project, cx| {
+                                let worktree = project.visible_worktrees(cx).choose(rng)?;
+                                let worktree = worktree.read(cx);
+                                let entry = worktree
+                                    .entries(false)
+                                    .filter(|e| e.path.as_ref() != Path::new(""))
+                                    .choose(rng)?;
+                                let new_parent = worktree
+                                    .entries(false)
+                                    .filter(|e| {
+                                        e.is_dir()
+                                            && !e.path.starts_with(&entry.path)
+                                            && Some(e.path.as_ref()) != entry.path.parent()
+                                    })
+                                    .choose(rng)?;
+                                let root_path = Path::new(worktree.root_name());
+                                Some((
+                                    root_path.join(&entry.path),
+                                    root_path
+                                        .join(&new_parent.path)
+                                        .join(entry.path.file_name()?),
+                                ))
+                            });
+                            let Some((full_path, new_full_path)) = paths else {
+                                continue;
+                            };
+                            break ClientOperation::MoveWorktreeEntry {
+                                project_root_name,
+                                is_local,
+                                full_path,
+                                new_full_path,
+                            };
+                        }
+
+                        // Unshare a local project, possibly while guests have requests in
+                        // flight against it
+                        66..=70 => {
+                            let project = client
+                                .local_projects()
+                                .iter()
+                                .filter(|project| {
+                                    project.read_with(cx, |project, _| project.is_shared())
+                                })
+                                .choose(rng)
+                                .cloned();
+                            let Some(project) = project else { continue };
+                            let project_root_name = root_name_for_project(&project, cx);
+                            break ClientOperation::UnshareProject { project_root_name };
+                        }
+
+                        // Delete a file or directory from a worktree
+                        71..=78 => {
+                            let Some(project) = choose_random_project(client, rng) else {
+                                continue;
+                            };
+                            let project_root_name = root_name_for_project(&project, cx);
+                            let is_local = project.read_with(cx, |project, _| project.is_local());
+                            let full_path = project.read_with(cx, |project, cx| {
+                                let worktree = project.visible_worktrees(cx).choose(rng)?;
+                                let worktree = worktree.read(cx);
+                                let entry = worktree
+                                    .entries(false)
+                                    .filter(|e| e.path.as_ref() != Path::new(""))
+                                    .choose(rng)?;
+                                Some(Path::new(worktree.root_name()).join(&entry.path))
+                            });
+                            let Some(full_path) = full_path else { continue };
+                            break ClientOperation::DeleteWorktreeEntry {
+                                project_root_name,
+                                is_local,
+                                full_path,
+                            };
+                        }
+
                         // Add an entry to a worktree
                         _ => {
                             let Some(project) = choose_random_project(client, rng) else {
@@ -308,12 +505,35 @@ impl RandomizedTest for ProjectCollaborationTest {
                             });
                             let Some(worktree) = worktree else { continue };
                             let is_dir = rng.gen::<bool>();
-                            let mut full_path =
-                                worktree.read_with(cx, |w, _| PathBuf::from(w.root_name()));
-                            full_path.push(gen_file_name(rng));
-                            if !is_dir {
-                                full_path.set_extension("rs");
-                            }
+                            let full_path = worktree.read_with(cx, |worktree, _| {
+                                let mut full_path = PathBuf::from(worktree.root_name());
+
+                                // Sometimes collide with an existing entry of either kind.
+                                if rng.gen_bool(0.1) {
+                                    if let Some(entry) = worktree
+                                        .entries(false)
+                                        .filter(|e| e.path.as_ref() != Path::new(""))
+                                        .choose(rng)
+                                    {
+                                        full_path.push(&entry.path);
+                                        return full_path;
+                                    }
+                                }
+
+                                // Sometimes nest the new entry in directories that don't exist yet.
+                                let depth = if rng.gen_bool(0.2) {
+                                    rng.gen_range(2..=4)
+                                } else {
+                                    1
+                                };
+                                for _ in 0..depth {
+                                    full_path.push(gen_file_name(rng));
+                                }
+                                if !is_dir {
+                                    full_path.set_extension(gen_file_extension(rng));
+                                }
+                                full_path
+                            });
                             break ClientOperation::CreateWorktreeEntry {
                                 project_root_name,
                                 is_local,
@@ -366,10 +586,65 @@ impl RandomizedTest for ProjectCollaborationTest {
                                         detach,
                                     };
                                 }
+                                // Rename the buffer's file while it's open, either through the
+                                // project or on disk behind the host's back
+                                30..=34 => {
+                                    let mut new_full_path = full_path.clone();
+                                    new_full_path.set_file_name(gen_file_name(rng));
+                                    if let Some(extension) = full_path.extension() {
+                                        new_full_path.set_extension(extension);
+                                    }
+                                    if is_local && rng.gen() {
+                                        break ClientOperation::RenameBufferFileOnDisk {
+                                            project_root_name,
+                                            full_path,
+                                            new_full_path,
+                                        };
+                                    } else {
+                                        break ClientOperation::RenameWorktreeEntry {
+                                            project_root_name,
+                                            is_local,
+                                            full_path,
+                                            new_full_path,
+                                        };
+                                    }
+                                }
+                                // Change the buffer's file on disk behind the host's back
+                                35..=39 if is_local => {
+                                    let mut content = Alphanumeric.sample_string(rng, 16);
+                                    if rng.gen() {
+                                        content.insert(rng.gen_range(0..=content.len()), '\n');
+                                    }
+                                    break ClientOperation::WriteBufferFileOnDisk {
+                                        project_root_name,
+                                        full_path,
+                                        content,
+                                    };
+                                }
+                                // Kill or restart the host's language server for the buffer,
+                                // possibly while guests are waiting on it
+                                40..=41 if is_local => {
+                                    if rng.gen() {
+                                        break ClientOperation::KillLanguageServer {
+                                            project_root_name,
+                                            full_path,
+                                        };
+                                    } else {
+                                        break ClientOperation::RestartLanguageServer {
+                                            project_root_name,
+                                            full_path,
+                                        };
+                                    }
+                                }
                                 // Edit the buffer
-                                30..=69 => {
-                                    let edits = buffer
-                                        .read_with(cx, |buffer, _| buffer.get_random_edits(rng, 3));
+                                35..=69 => {
+                                    let edits = buffer.read_with(cx, |buffer, _| {
+                                        match rng.gen_range(0..100_u32) {
+                                            0..=1 => gen_large_paste(buffer, rng),
+                                            2..=16 => gen_multi_cursor_edits(buffer, rng),
+                                            _ => buffer.get_random_edits(rng, 3),
+                                        }
+                                    });
                                     break ClientOperation::EditBuffer {
                                         project_root_name,
                                         is_local,
@@ -440,10 +715,12 @@ impl RandomizedTest for ProjectCollaborationTest {
                                     Path::new(worktree.root_name()).join(&entry.path)
                                 }
                             });
+                            let detach = rng.gen_bool(0.2);
                             break ClientOperation::OpenBuffer {
                                 project_root_name,
                                 is_local,
                                 full_path,
+                                detach,
                             };
                         }
                     }
@@ -463,22 +740,43 @@ impl RandomizedTest for ProjectCollaborationTest {
                     let mut path;
                     let dir_paths = client.fs().directories(false);
 
+                    let file_paths = client.fs().files();
+
                     if is_dir {
                         content = String::new();
-                        path = dir_paths.choose(rng).unwrap().clone();
-                        path.push(gen_file_name(rng));
-                    } else {
-                        content = Alphanumeric.sample_string(rng, 16);
 
-                        // Create a new file or overwrite an existing file
-                        let file_paths = client.fs().files();
-                        if file_paths.is_empty() || rng.gen_bool(0.5) {
+                        // Create a new directory, or try to create one over an existing file
+                        if file_paths.is_empty() || rng.gen_bool(0.9) {
                             path = dir_paths.choose(rng).unwrap().clone();
                             path.push(gen_file_name(rng));
-                            path.set_extension("rs");
                         } else {
-                            path = file_paths.choose(rng).unwrap().clone()
-                        };
+                            path = file_paths.choose(rng).unwrap().clone();
+                        }
+                    } else {
+                        content = Alphanumeric.sample_string(rng, 16);
+
+                        // Create a new file, overwrite an existing file, or try to write a
+                        // file over an existing directory
+                        match rng.gen_range(0..20) {
+                            0..=9 => {
+                                path = dir_paths.choose(rng).unwrap().clone();
+                                path.push(gen_file_name(rng));
+                                path.set_extension(gen_file_extension(rng));
+                            }
+                            10..=18 if !file_paths.is_empty() => {
+                                path = file_paths.choose(rng).unwrap().clone();
+                            }
+                            _ => {
+                                let Some(dir_path) = dir_paths
+                                    .iter()
+                                    .filter(|path| path.parent().is_some())
+                                    .choose(rng)
+                                else {
+                                    continue;
+                                };
+                                path = dir_path.clone();
+                            }
+                        }
                     }
                     break ClientOperation::WriteFsEntry {
                         path,
@@ -490,6 +788,119 @@ impl RandomizedTest for ProjectCollaborationTest {
         }
     }
 
+    fn operation_entities(
+        client: &TestClient,
+        operation: &ClientOperation,
+        cx: &TestAppContext,
+    ) -> OperationEntities {
+        let (project_root_name, full_path) = match operation {
+            ClientOperation::OpenRemoteProject {
+                host_id,
+                host_project_id,
+            }
+            | ClientOperation::CloseRemoteProject {
+                host_id,
+                host_project_id,
+            } => {
+                return OperationEntities {
+                    project_ids: remote_project_id(*host_id, *host_project_id, cx)
+                        .into_iter()
+                        .collect(),
+                    buffer_ids: Vec::new(),
+                };
+            }
+            ClientOperation::AddWorktreeToProject {
+                project_root_name, ..
+            }
+            | ClientOperation::RemoveWorktreeFromProject {
+                project_root_name, ..
+            }
+            | ClientOperation::UnshareProject { project_root_name }
+            | ClientOperation::SearchProject {
+                project_root_name, ..
+            } => (project_root_name, None),
+            ClientOperation::OpenBuffer {
+                project_root_name,
+                full_path,
+                ..
+            }
+            | ClientOperation::EditBuffer {
+                project_root_name,
+                full_path,
+                ..
+            }
+            | ClientOperation::CloseBuffer {
+                project_root_name,
+                full_path,
+                ..
+            }
+            | ClientOperation::SaveBuffer {
+                project_root_name,
+                full_path,
+                ..
+            }
+            | ClientOperation::WriteBufferFileOnDisk {
+                project_root_name,
+                full_path,
+                ..
+            }
+            | ClientOperation::RenameBufferFileOnDisk {
+                project_root_name,
+                full_path,
+                ..
+            }
+            | ClientOperation::KillLanguageServer {
+                project_root_name,
+                full_path,
+            }
+            | ClientOperation::RestartLanguageServer {
+                project_root_name,
+                full_path,
+            }
+            | ClientOperation::RequestLspDataInBuffer {
+                project_root_name,
+                full_path,
+                ..
+            }
+            | ClientOperation::CreateWorktreeEntry {
+                project_root_name,
+                full_path,
+                ..
+            }
+            | ClientOperation::RenameWorktreeEntry {
+                project_root_name,
+                full_path,
+                ..
+            }
+            | ClientOperation::MoveWorktreeEntry {
+                project_root_name,
+                full_path,
+                ..
+            }
+            | ClientOperation::DeleteWorktreeEntry {
+                project_root_name,
+                full_path,
+                ..
+            } => (project_root_name, Some(full_path)),
+            _ => return OperationEntities::default(),
+        };
+
+        let Some(project) = project_for_root_name(client, project_root_name, cx) else {
+            return OperationEntities::default();
+        };
+        OperationEntities {
+            project_ids: project
+                .read_with(cx, |project, _| project.remote_id())
+                .into_iter()
+                .collect(),
+            buffer_ids: full_path
+                .and_then(|full_path| buffer_for_full_path(client, &project, full_path, cx))
+                .map(|buffer| buffer.read_with(cx, |buffer, _| buffer.remote_id().into()))
+                .into_iter()
+                .collect(),
+        }
+    }
+
     async fn apply_operation(
         client: &TestClient,
         operation: ClientOperation,
@@ -583,82 +994,254 @@ impl RandomizedTest for ProjectCollaborationTest {
                     .unwrap();
             }
 
-            ClientOperation::CloseRemoteProject { project_root_name } => {
+            ClientOperation::CloseRemoteProject {
+                host_id,
+                host_project_id,
+            } => {
+                let project_id = remote_project_id(host_id, host_project_id, cx)
+                    .ok_or(TestError::Inapplicable)?;
+                let ix = remote_project_ix_for_id(client, project_id, cx)
+                    .ok_or(TestError::Inapplicable)?;
+                let project = client.remote_projects()[ix].clone();
+
+                log::info!("{}: closing remote project {}", client.username, project_id);
+
+                // Once the server has acknowledged the guest's edits, the host must end up
+                // with all of them even though the guest leaves right away.
+                let flushed = project
+                    .read_with(cx, |project, _| project.flush())
+                    .await
+                    .is_ok();
+                if flushed {
+                    let closed_buffers = project.read_with(cx, |project, cx| {
+                        let replica_id = project.replica_id();
+                        project
+                            .opened_buffers()
+                            .into_iter()
+                            .filter_map(|buffer| {
+                                let buffer = buffer.read(cx);
+                                let last_edit = clock::Lamport {
+                                    replica_id,
+                                    value: buffer.version().get(replica_id),
+                                };
+                                (last_edit.value > 0).then(|| ClosedRemoteBuffer {
+                                    project_id,
+                                    buffer_id: buffer.remote_id(),
+                                    last_edit,
+                                })
+                            })
+                            .collect::<Vec<_>>()
+                    });
+                    client.closed_remote_buffers().extend(closed_buffers);
+                }
+
+                cx.update(|_| {
+                    client.remote_projects_mut().remove(ix);
+                    client.buffers().retain(|p, _| *p != project);
+                    drop(project);
+                });
+            }
+
+            ClientOperation::RemoveWorktreeFromProject {
+                project_root_name,
+                worktree_root_name,
+            } => {
+                let project = project_for_root_name(client, &project_root_name, cx)
+                    .ok_or(TestError::Inapplicable)?;
+                let worktree_id = project
+                    .read_with(cx, |project, cx| {
+                        project
+                            .visible_worktrees(cx)
+                            .skip(1)
+                            .find(|worktree| worktree.read(cx).root_name() == worktree_root_name)
+                            .map(|worktree| worktree.read(cx).id())
+                    })
+                    .ok_or(TestError::Inapplicable)?;
+
+                log::info!(
+                    "{}: removing worktree {} from local project {}",
+                    client.username,
+                    worktree_root_name,
+                    project_root_name,
+                );
+
+                ensure_project_shared(&project, client, cx).await;
+                project.update(cx, |project, cx| project.remove_worktree(worktree_id, cx));
+            }
+
+            ClientOperation::UnshareProject { project_root_name } => {
                 let project = project_for_root_name(client, &project_root_name, cx)
                     .ok_or(TestError::Inapplicable)?;
+                if !project.read_with(cx, |project, _| project.is_shared()) {
+                    return Err(TestError::Inapplicable);
+                }
 
                 log::info!(
-                    "{}: closing remote project with root path {}",
+                    "{}: unsharing local project {}",
                     client.username,
                     project_root_name,
                 );
 
-                let ix = client
-                    .remote_projects()
+                let active_call = cx.read(ActiveCall::global);
+                active_call.update(cx, |call, cx| call.unshare_project(project, cx))?;
+            }
+
+            ClientOperation::OpenRemoteProject {
+                host_id,
+                host_project_id,
+            } => {
+                let active_call = cx.read(ActiveCall::global);
+                let room = active_call
+                    .read_with(cx, |call, _| call.room().cloned())
+                    .ok_or(TestError::Inapplicable)?;
+                let project_id = room
+                    .read_with(cx, |room, _| {
+                        room.remote_project_id(host_id.to_proto(), host_project_id)
+                    })
+                    .ok_or(TestError::Inapplicable)?;
+                let shared_projects = room
+                    .update(cx, |room, cx| room.shared_project_metadata(cx))
+                    .await?;
+                let shared_project = shared_projects
                     .iter()
-                    .position(|p| p == &project)
-                    .unwrap();
-                cx.update(|_| {
-                    client.remote_projects_mut().remove(ix);
-                    client.buffers().retain(|p, _| *p != project);
-                    drop(project);
+                    .find(|project| {
+                        project.id == project_id
+                            && UserId::from_proto(project.host_user_id) == host_id
+                    })
+                    .ok_or(TestError::Inapplicable)?;
+
+                log::info!(
+                    "{}: joining remote project {} of user {}, root names {:?}",
+                    client.username,
+                    project_id,
+                    host_id,
+                    shared_project.worktree_root_names,
+                );
+
+                let project = room
+                    .update(cx, |room, cx| {
+                        room.join_project(
+                            project_id,
+                            client.language_registry().clone(),
+                            FakeFs::new(cx.background_executor().clone()),
+                            cx,
+                        )
+                    })
+                    .await?;
+                client.remote_projects_mut().push(project.clone());
+            }
+
+            ClientOperation::CreateWorktreeEntry {
+                project_root_name,
+                is_local,
+                full_path,
+                is_dir,
+            } => {
+                let project = project_for_root_name(client, &project_root_name, cx)
+                    .ok_or(TestError::Inapplicable)?;
+                let project_path = project_path_for_full_path(&project, &full_path, cx)
+                    .ok_or(TestError::Inapplicable)?;
+
+                log::info!(
+                    "{}: creating {} at path {:?} in {} project {}",
+                    client.username,
+                    if is_dir { "dir" } else { "file" },
+                    full_path,
+                    if is_local { "local" } else { "remote" },
+                    project_root_name,
+                );
+
+                ensure_project_shared(&project, client, cx).await;
+                let collides = project.read_with(cx, |project, cx| {
+                    project
+                        .entry_for_path(&project_path, cx)
+                        .map_or(false, |entry| !(is_dir && entry.is_dir()))
                 });
+                let result = project
+                    .update(cx, |p, cx| p.create_entry(project_path, is_dir, cx))
+                    .await;
+                if collides && is_local {
+                    // Creating an entry never replaces one that's already there.
+                    assert!(
+                        result.is_err(),
+                        "{}: creating {:?} replaced an existing entry",
+                        client.username,
+                        full_path
+                    );
+                } else if collides || !is_local {
+                    // A guest's view of the worktree can be behind the host's, so the
+                    // path may or may not be taken by the time the host creates it.
+                    result.map_err(|_| TestError::Inapplicable)?;
+                } else {
+                    result?;
+                }
             }
 
-            ClientOperation::OpenRemoteProject {
-                host_id,
-                first_root_name,
+            ClientOperation::RenameWorktreeEntry {
+                project_root_name,
+                is_local,
+                full_path,
+                new_full_path,
+            }
+            | ClientOperation::MoveWorktreeEntry {
+                project_root_name,
+                is_local,
+                full_path,
+                new_full_path,
             } => {
-                let active_call = cx.read(ActiveCall::global);
-                let project = active_call
-                    .update(cx, |call, cx| {
-                        let room = call.room().cloned()?;
-                        let participant = room
-                            .read(cx)
-                            .remote_participants()
-                            .get(&host_id.to_proto())?;
-                        let project_id = participant
-                            .projects
-                            .iter()
-                            .find(|project| project.worktree_root_names[0] == first_root_name)?
-                            .id;
-                        Some(room.update(cx, |room, cx| {
-                            room.join_project(
-                                project_id,
-                                client.language_registry().clone(),
-                                FakeFs::new(cx.background_executor().clone()),
-                                cx,
-                            )
-                        }))
-                    })
+                let project = project_for_root_name(client, &project_root_name, cx)
+                    .ok_or(TestError::Inapplicable)?;
+                let project_path = project_path_for_full_path(&project, &full_path, cx)
                     .ok_or(TestError::Inapplicable)?;
+                let new_project_path = project_path_for_full_path(&project, &new_full_path, cx)
+                    .ok_or(TestError::Inapplicable)?;
+                let entry_id = project
+                    .read_with(cx, |project, cx| project.entry_for_path(&project_path, cx))
+                    .ok_or(TestError::Inapplicable)?
+                    .id;
 
                 log::info!(
-                    "{}: joining remote project of user {}, root name {}",
+                    "{}: renaming {:?} to {:?} in {} project {}",
                     client.username,
-                    host_id,
-                    first_root_name,
+                    full_path,
+                    new_full_path,
+                    if is_local { "local" } else { "remote" },
+                    project_root_name,
                 );
 
-                let project = project.await?;
-                client.remote_projects_mut().push(project.clone());
+                ensure_project_shared(&project, client, cx).await;
+                project
+                    .update(cx, |p, cx| {
+                        p.rename_entry(entry_id, new_project_path.path, cx)
+                    })
+                    .await?;
             }
 
-            ClientOperation::CreateWorktreeEntry {
+            ClientOperation::DeleteWorktreeEntry {
                 project_root_name,
                 is_local,
                 full_path,
-                is_dir,
             } => {
                 let project = project_for_root_name(client, &project_root_name, cx)
                     .ok_or(TestError::Inapplicable)?;
                 let project_path = project_path_for_full_path(&project, &full_path, cx)
                     .ok_or(TestError::Inapplicable)?;
+                let (entry_id, deleted_entry_ids) = project
+                    .read_with(cx, |project, cx| {
+                        let worktree = project.worktree_for_id(project_path.worktree_id, cx)?;
+                        let worktree = worktree.read(cx);
+                        let entry_id = worktree.entry_for_path(&project_path.path)?.id;
+                        let deleted_entry_ids = worktree
+                            .descendent_entries(true, true, &project_path.path)
+                            .map(|entry| entry.id)
+                            .collect::<Vec<_>>();
+                        Some((entry_id, deleted_entry_ids))
+                    })
+                    .ok_or(TestError::Inapplicable)?;
 
                 log::info!(
-                    "{}: creating {} at path {:?} in {} project {}",
+                    "{}: deleting {:?} in {} project {}",
                     client.username,
-                    if is_dir { "dir" } else { "file" },
                     full_path,
                     if is_local { "local" } else { "remote" },
                     project_root_name,
@@ -666,14 +1249,27 @@ impl RandomizedTest for ProjectCollaborationTest {
 
                 ensure_project_shared(&project, client, cx).await;
                 project
-                    .update(cx, |p, cx| p.create_entry(project_path, is_dir, cx))
+                    .update(cx, |p, cx| p.delete_entry(entry_id, cx))
+                    .ok_or(TestError::Inapplicable)?
                     .await?;
+
+                if let Some(project_id) = project.read_with(cx, |project, _| project.remote_id()) {
+                    client
+                        .deleted_worktree_entries()
+                        .extend(deleted_entry_ids.into_iter().map(|entry_id| {
+                            DeletedWorktreeEntry {
+                                project_id,
+                                entry_id,
+                            }
+                        }));
+                }
             }
 
             ClientOperation::OpenBuffer {
                 project_root_name,
                 is_local,
                 full_path,
+                detach,
             } => {
                 let project = project_for_root_name(client, &project_root_name, cx)
                     .ok_or(TestError::Inapplicable)?;
@@ -681,18 +1277,28 @@ impl RandomizedTest for ProjectCollaborationTest {
                     .ok_or(TestError::Inapplicable)?;
 
                 log::info!(
-                    "{}: opening buffer {:?} in {} project {}",
+                    "{}: opening buffer {:?} in {} project {}, {}",
                     client.username,
                     full_path,
                     if is_local { "local" } else { "remote" },
                     project_root_name,
+                    if detach { "detaching" } else { "awaiting" }
                 );
 
                 ensure_project_shared(&project, client, cx).await;
-                let buffer = project
-                    .update(cx, |project, cx| project.open_buffer(project_path, cx))
-                    .await?;
-                client.buffers_for_project(&project).insert(buffer);
+                let open_buffer =
+                    project.update(cx, |project, cx| project.open_buffer(project_path, cx));
+                if detach {
+                    let request = cx
+                        .foreground_executor()
+                        .spawn(async move { open_buffer.await.map(drop) });
+                    client
+                        .detached_requests()
+                        .push((format!("open buffer {:?}", full_path), request));
+                } else {
+                    let buffer = open_buffer.await?;
+                    client.buffers_for_project(&project).insert(buffer);
+                }
             }
 
             ClientOperation::EditBuffer {
@@ -706,14 +1312,27 @@ impl RandomizedTest for ProjectCollaborationTest {
                 let buffer = buffer_for_full_path(client, &project, &full_path, cx)
                     .ok_or(TestError::Inapplicable)?;
 
-                log::info!(
-                    "{}: editing buffer {:?} in {} project {} with {:?}",
-                    client.username,
-                    full_path,
-                    if is_local { "local" } else { "remote" },
-                    project_root_name,
-                    edits
-                );
+                let inserted_len = edits.iter().map(|(_, text)| text.len()).sum::<usize>();
+                if inserted_len > 1024 {
+                    log::info!(
+                        "{}: editing buffer {:?} in {} project {} with {} edits inserting {} bytes",
+                        client.username,
+                        full_path,
+                        if is_local { "local" } else { "remote" },
+                        project_root_name,
+                        edits.len(),
+                        inserted_len,
+                    );
+                } else {
+                    log::info!(
+                        "{}: editing buffer {:?} in {} project {} with {:?}",
+                        client.username,
+                        full_path,
+                        if is_local { "local" } else { "remote" },
+                        project_root_name,
+                        edits
+                    );
+                }
 
                 ensure_project_shared(&project, client, cx).await;
                 buffer.update(cx, |buffer, cx| {
@@ -789,12 +1408,127 @@ impl RandomizedTest for ProjectCollaborationTest {
                     anyhow::Ok(())
                 });
                 if detach {
-                    cx.update(|cx| save.detach_and_log_err(cx));
+                    client
+                        .detached_requests()
+                        .push((format!("save buffer {:?}", full_path), save));
                 } else {
                     save.await?;
                 }
             }
 
+            ClientOperation::WriteBufferFileOnDisk {
+                project_root_name,
+                full_path,
+                content,
+            } => {
+                let project = project_for_root_name(client, &project_root_name, cx)
+                    .ok_or(TestError::Inapplicable)?;
+                let buffer = buffer_for_full_path(client, &project, &full_path, cx)
+                    .ok_or(TestError::Inapplicable)?;
+                let abs_path = buffer
+                    .read_with(cx, |buffer, cx| {
+                        let file = buffer.file()?.as_local()?;
+                        (!file.is_deleted()).then(|| file.abs_path(cx))
+                    })
+                    .ok_or(TestError::Inapplicable)?;
+
+                log::info!(
+                    "{}: writing {:?} on disk while buffer {:?} is open in project {}",
+                    client.username,
+                    content,
+                    full_path,
+                    project_root_name,
+                );
+
+                client
+                    .fs()
+                    .save(&abs_path, &content.as_str().into(), text::LineEnding::Unix)
+                    .await?;
+            }
+
+            ClientOperation::RenameBufferFileOnDisk {
+                project_root_name,
+                full_path,
+                new_full_path,
+            } => {
+                let project = project_for_root_name(client, &project_root_name, cx)
+                    .ok_or(TestError::Inapplicable)?;
+                let buffer = buffer_for_full_path(client, &project, &full_path, cx)
+                    .ok_or(TestError::Inapplicable)?;
+                let abs_path = buffer
+                    .read_with(cx, |buffer, cx| {
+                        let file = buffer.file()?.as_local()?;
+                        (!file.is_deleted()).then(|| file.abs_path(cx))
+                    })
+                    .ok_or(TestError::Inapplicable)?;
+                let new_abs_path = abs_path
+                    .with_file_name(new_full_path.file_name().ok_or(TestError::Inapplicable)?);
+
+                log::info!(
+                    "{}: renaming {:?} to {:?} on disk while its buffer is open in project {}",
+                    client.username,
+                    full_path,
+                    new_full_path,
+                    project_root_name,
+                );
+
+                client
+                    .fs()
+                    .rename(&abs_path, &new_abs_path, Default::default())
+                    .await?;
+            }
+
+            ClientOperation::KillLanguageServer {
+                project_root_name,
+                full_path,
+            } => {
+                let project = project_for_root_name(client, &project_root_name, cx)
+                    .ok_or(TestError::Inapplicable)?;
+                let buffer = buffer_for_full_path(client, &project, &full_path, cx)
+                    .ok_or(TestError::Inapplicable)?;
+                let servers = project.read_with(cx, |project, cx| {
+                    project
+                        .language_servers_for_buffer(buffer.read(cx), cx)
+                        .map(|(_, server)| server.clone())
+                        .collect::<Vec<_>>()
+                });
+                if servers.is_empty() {
+                    Err(TestError::Inapplicable)?;
+                }
+
+                log::info!(
+                    "{}: killing language server for buffer {:?} in project {}",
+                    client.username,
+                    full_path,
+                    project_root_name,
+                );
+
+                for server in servers {
+                    server.kill();
+                }
+            }
+
+            ClientOperation::RestartLanguageServer {
+                project_root_name,
+                full_path,
+            } => {
+                let project = project_for_root_name(client, &project_root_name, cx)
+                    .ok_or(TestError::Inapplicable)?;
+                let buffer = buffer_for_full_path(client, &project, &full_path, cx)
+                    .ok_or(TestError::Inapplicable)?;
+
+                log::info!(
+                    "{}: restarting language server for buffer {:?} in project {}",
+                    client.username,
+                    full_path,
+                    project_root_name,
+                );
+
+                project.update(cx, |project, cx| {
+                    project.restart_language_servers_for_buffers([buffer], cx);
+                });
+            }
+
             ClientOperation::RequestLspDataInBuffer {
                 project_root_name,
                 is_local,
@@ -819,6 +1553,7 @@ impl RandomizedTest for ProjectCollaborationTest {
                 );
 
                 use futures::{FutureExt as _, TryFutureExt as _};
+                let description = format!("LSP {:?} request for buffer {:?}", kind, full_path);
                 let offset = buffer.read_with(cx, |b, _| b.clip_offset(offset, Bias::Left));
 
                 let process_lsp_request = project.update(cx, |project, cx| match kind {
@@ -845,7 +1580,7 @@ impl RandomizedTest for ProjectCollaborationTest {
                 });
                 let request = cx.foreground_executor().spawn(process_lsp_request);
                 if detach {
-                    request.detach();
+                    client.detached_requests().push((description, request));
                 } else {
                     request.await?;
                 }
@@ -882,9 +1617,17 @@ impl RandomizedTest for ProjectCollaborationTest {
                     while let Some((buffer, ranges)) = search.next().await {
                         results.entry(buffer).or_insert(ranges);
                     }
-                    results
+                    anyhow::Ok(results.len())
                 });
-                search.await;
+                if detach {
+                    client.detached_requests().push((
+                        format!("search of project {}", project_root_name),
+                        cx.foreground_executor()
+                            .spawn(async move { search.await.map(drop) }),
+                    ));
+                } else {
+                    search.await?;
+                }
             }
 
             ClientOperation::WriteFsEntry {
@@ -900,9 +1643,11 @@ impl RandomizedTest for ProjectCollaborationTest {
                     return Err(TestError::Inapplicable);
                 }
 
+                // The path may collide with an existing entry of the other kind, in
+                // which case the file system refuses the write, like a real one would.
                 if is_dir {
                     log::info!("{}: creating dir at {:?}", client.username, path);
-                    client.fs().create_dir(&path).await.unwrap();
+                    client.fs().create_dir(&path).await?;
                 } else {
                     let exists = client.fs().metadata(&path).await?.is_some();
                     let verb = if exists { "updating" } else { "creating" };
@@ -911,8 +1656,7 @@ impl RandomizedTest for ProjectCollaborationTest {
                     client
                         .fs()
                         .save(&path, &content.as_str().into(), text::LineEnding::Unix)
-                        .await
-                        .unwrap();
+                        .await?;
                 }
             }
 
@@ -1138,6 +1882,21 @@ impl RandomizedTest for ProjectCollaborationTest {
     }
 
     async fn on_quiesce(_: &mut TestServer, clients: &mut [(Rc<TestClient>, TestAppContext)]) {
+        // Requests that were in flight when a host removed a worktree or unshared a project
+        // must resolve, either successfully or with an error, rather than hanging.
+        for (client, _) in clients.iter() {
+            let detached_requests = mem::take(&mut *client.detached_requests());
+            for (description, request) in detached_requests {
+                match request.now_or_never() {
+                    Some(Ok(())) => {}
+                    Some(Err(error)) => {
+                        log::info!("{}: {} failed: {:?}", client.username, description, error)
+                    }
+                    None => panic!("{}: {} never resolved", client.username, description),
+                }
+            }
+        }
+
         for (client, client_cx) in clients.iter() {
             for guest_project in client.remote_projects().iter() {
                 guest_project.read_with(client_cx, |guest_project, cx| {
@@ -1200,15 +1959,15 @@ impl RandomizedTest for ProjectCollaborationTest {
                                         guest_project.remote_id(),
                                     );
                                     assert_eq!(
-                                        guest_snapshot.entries(false).collect::<Vec<_>>(),
-                                        host_snapshot.entries(false).collect::<Vec<_>>(),
+                                        replicated_entries(guest_snapshot),
+                                        replicated_entries(host_snapshot),
                                         "{} has different snapshot than the host for worktree {:?} ({:?}) and project {:?}",
                                         client.username,
                                         host_snapshot.abs_path(),
                                         id,
                                         guest_project.remote_id(),
                                     );
-                                    assert_eq!(guest_snapshot.repositories().collect::<Vec<_>>(), host_snapshot.repositories().collect::<Vec<_>>(),
+                                    assert_eq!(replicated_repositories(guest_snapshot), replicated_repositories(host_snapshot),
                                         "{} has different repositories than the host for worktree {:?} and project {:?}",
                                         client.username,
                                         host_snapshot.abs_path(),
@@ -1221,6 +1980,27 @@ impl RandomizedTest for ProjectCollaborationTest {
                                         guest_project.remote_id(),
                                     );
                                 }
+
+                                // The host knows about every buffer the guest has open.
+                                let guest_peer_id = client.peer_id().unwrap();
+                                let host_registry =
+                                    host_project.read_with(host_cx, |host_project, _| {
+                                        host_project
+                                            .guest_open_buffers()
+                                            .get(&guest_peer_id)
+                                            .cloned()
+                                            .unwrap_or_default()
+                                    });
+                                for buffer in guest_project.opened_buffers() {
+                                    let buffer = buffer.read(cx);
+                                    assert!(
+                                        host_registry.contains(&buffer.remote_id()),
+                                        "{} has buffer {:?} open in project {:?}, but the host doesn't know about it",
+                                        client.username,
+                                        buffer.file().unwrap().full_path(cx),
+                                        guest_project.remote_id(),
+                                    );
+                                }
                             }
                         }
 
@@ -1238,6 +2018,68 @@ impl RandomizedTest for ProjectCollaborationTest {
                     });
             }
 
+            // Edits that the server acknowledged before a guest closed a project must have
+            // reached the host.
+            let closed_remote_buffers = mem::take(&mut *client.closed_remote_buffers());
+            for closed_buffer in closed_remote_buffers {
+                let host_version = clients.iter().find_map(|(host_client, host_cx)| {
+                    host_client
+                        .local_projects()
+                        .iter()
+                        .find_map(|host_project| {
+                            host_project.read_with(host_cx, |host_project, cx| {
+                                if host_project.remote_id() != Some(closed_buffer.project_id) {
+                                    return None;
+                                }
+                                let buffer = host_project.buffer_for_id(closed_buffer.buffer_id)?;
+                                Some(buffer.read(cx).version())
+                            })
+                        })
+                });
+                if let Some(host_version) = host_version {
+                    assert!(
+                        host_version.observed(closed_buffer.last_edit),
+                        "{}: host is missing edits to buffer {} made before closing project {}",
+                        client.username,
+                        closed_buffer.buffer_id,
+                        closed_buffer.project_id,
+                    );
+                }
+            }
+
+            // Buffers follow their files across renames: a buffer whose file exists refers
+            // to the worktree entry at the file's path, and a buffer whose file was deleted
+            // doesn't have a path that exists in its worktree.
+            for (project, buffers) in client.buffers().iter() {
+                project.read_with(client_cx, |project, cx| {
+                    if !project.is_local() {
+                        return;
+                    }
+                    for buffer in buffers {
+                        let Some(file) = project::File::from_dyn(buffer.read(cx).file()) else {
+                            continue;
+                        };
+                        let worktree = file.worktree.read(cx);
+                        if let Some(entry_id) = file.project_entry_id(cx) {
+                            assert_eq!(
+                                worktree.entry_for_id(entry_id).map(|entry| &entry.path),
+                                Some(&file.path),
+                                "{}: buffer for {:?} refers to the wrong worktree entry",
+                                client.username,
+                                file.path,
+                            );
+                        } else if file.is_deleted() {
+                            assert!(
+                                worktree.entry_for_path(&file.path).is_none(),
+                                "{}: buffer for {:?} is deleted, but its path exists",
+                                client.username,
+                                file.path,
+                            );
+                        }
+                    }
+                });
+            }
+
             let buffers = client.buffers().clone();
             for (guest_project, guest_buffers) in &buffers {
                 let project_id = if guest_project.read_with(client_cx, |project, _| {
@@ -1261,15 +2103,16 @@ impl RandomizedTest for ProjectCollaborationTest {
                             })
                         })?
                         .clone();
-                    Some((client.user_id().unwrap(), project, cx))
+                    Some((client, project, cx))
                 });
 
-                let (host_user_id, host_project, host_cx) =
-                    if let Some((host_user_id, host_project, host_cx)) = host_project {
-                        (host_user_id, host_project, host_cx)
+                let (host_client, host_project, host_cx) =
+                    if let Some((host_client, host_project, host_cx)) = host_project {
+                        (host_client, host_project, host_cx)
                     } else {
                         continue;
                     };
+                let host_user_id = host_client.user_id().unwrap();
 
                 for guest_buffer in guest_buffers {
                     let buffer_id =
@@ -1308,7 +2151,14 @@ impl RandomizedTest for ProjectCollaborationTest {
                     let guest_file = guest_buffer.read_with(client_cx, |b, _| b.file().cloned());
                     match (host_file, guest_file) {
                         (Some(host_file), Some(guest_file)) => {
-                            assert_eq!(guest_file.path(), host_file.path());
+                            assert!(
+                                paths_equal_normalized(guest_file.path(), host_file.path()),
+                                "guest {} path {:?} does not match host {} path {:?}",
+                                guest_user_id,
+                                guest_file.path(),
+                                host_user_id,
+                                host_file.path(),
+                            );
                             assert_eq!(guest_file.is_deleted(), host_file.is_deleted());
                             assert_eq!(
                                 guest_file.mtime(),
@@ -1378,10 +2228,102 @@ impl RandomizedTest for ProjectCollaborationTest {
                             "guest {} conflict status does not match host's for path {path:?} in project {project_id}",
                             client.username
                         );
+
+                    // A clean buffer must have converged on whatever is on disk, even if the file
+                    // was changed while saves and replicated edits were in flight.
+                    let host_clean_abs_path = host_buffer.read_with(host_cx, |b, cx| {
+                        let file = b.file()?.as_local()?;
+                        (!b.is_dirty() && !file.is_deleted()).then(|| file.abs_path(cx))
+                    });
+                    if let Some(abs_path) = host_clean_abs_path {
+                        let disk_text = host_client.fs().load(&abs_path).await.unwrap();
+                        assert_eq!(
+                            host_buffer.read_with(host_cx, |b, _| b.text()),
+                            disk_text.replace("\r\n", "\n"),
+                            "host's clean buffer does not match the disk for path {path:?} in project {project_id}",
+                        );
+                    }
+
+                    let host_language =
+                        host_buffer.read_with(host_cx, |b, _| b.language().map(|l| l.name()));
+                    let guest_language =
+                        guest_buffer.read_with(client_cx, |b, _| b.language().map(|l| l.name()));
+                    assert_eq!(guest_language, host_language,
+                            "guest {} language does not match host's for path {path:?} in project {project_id}",
+                            client.username
+                        );
+
+                    let expected_language = host_project.read_with(host_cx, |project, _| {
+                        project
+                            .languages()
+                            .language_for_file(&path, None)
+                            .now_or_never()
+                            .and_then(|language| language.ok())
+                            .map(|language| language.name())
+                    });
+                    assert_eq!(
+                        host_language, expected_language,
+                        "host language is stale for path {path:?} in project {project_id}",
+                    );
+
+                    let host_syntax_layer_count =
+                        host_buffer.read_with(host_cx, |b, _| b.snapshot().syntax_layers().count());
+                    let guest_syntax_layer_count = guest_buffer
+                        .read_with(client_cx, |b, _| b.snapshot().syntax_layers().count());
+                    assert_eq!(guest_syntax_layer_count, host_syntax_layer_count,
+                            "guest {} syntax layers do not match host's for path {path:?} in project {project_id}",
+                            client.username
+                        );
+
+                    let host_language_server_count =
+                        host_project.read_with(host_cx, |project, cx| {
+                            project
+                                .language_servers_for_buffer(host_buffer.read(cx), cx)
+                                .count()
+                        });
+                    assert_eq!(
+                        host_language_server_count,
+                        host_language.is_some() as usize,
+                        "host has {host_language_server_count} language servers for path {path:?} in project {project_id}",
+                    );
                 }
             }
         }
     }
+
+    async fn on_settled(_: &mut TestServer, clients: &mut [(Rc<TestClient>, TestAppContext)]) {
+        let deleted_worktree_entries = clients
+            .iter()
+            .flat_map(|(client, _)| client.deleted_worktree_entries().clone())
+            .collect::<Vec<_>>();
+
+        // Entries that were deleted from a project are gone on every guest.
+        for (client, client_cx) in clients.iter() {
+            for guest_project in client.remote_projects().iter() {
+                guest_project.read_with(client_cx, |guest_project, cx| {
+                    if guest_project.is_disconnected() {
+                        return;
+                    }
+                    for deleted_entry in &deleted_worktree_entries {
+                        if Some(deleted_entry.project_id) != guest_project.remote_id() {
+                            continue;
+                        }
+                        for worktree in guest_project.worktrees() {
+                            let worktree = worktree.read(cx);
+                            assert!(
+                                worktree.entry_for_id(deleted_entry.entry_id).is_none(),
+                                "{} still has deleted entry {:?} in worktree {:?} and project {:?}",
+                                client.username,
+                                deleted_entry.entry_id,
+                                worktree.abs_path(),
+                                guest_project.remote_id(),
+                            );
+                        }
+                    }
+                });
+            }
+        }
+    }
 }
 
 fn generate_git_operation(rng: &mut StdRng, client: &TestClient) -> GitOperation {
@@ -1505,6 +2447,44 @@ fn project_ix_for_root_name(
     })
 }
 
+/// The id under which the given host is sharing the project it gave the given id,
+/// which unlike the project's id stays the same across test runs.
+fn remote_project_id(host_id: UserId, host_project_id: u64, cx: &TestAppContext) -> Option<u64> {
+    let room = cx.read(|cx| ActiveCall::global(cx).read(cx).room().cloned())?;
+    room.read_with(cx, |room, _| {
+        room.remote_project_id(host_id.to_proto(), host_project_id)
+    })
+}
+
+fn host_project_id_for_remote_project(
+    project_id: u64,
+    cx: &TestAppContext,
+) -> Option<(UserId, u64)> {
+    let room = cx.read(|cx| ActiveCall::global(cx).read(cx).room().cloned())?;
+    room.read_with(cx, |room, _| {
+        room.remote_participants().values().find_map(|participant| {
+            let project = participant
+                .projects
+                .iter()
+                .find(|project| project.id == project_id)?;
+            Some((
+                UserId::from_proto(participant.user.id),
+                project.host_project_id,
+            ))
+        })
+    })
+}
+
+fn remote_project_ix_for_id(
+    client: &TestClient,
+    project_id: u64,
+    cx: &TestAppContext,
+) -> Option<usize> {
+    client.remote_projects().iter().position(|project| {
+        project.read_with(cx, |project, _| project.remote_id() == Some(project_id))
+    })
+}
+
 fn root_name_for_project(project: &Model<Project>, cx: &TestAppContext) -> String {
     project.read_with(cx, |project, cx| {
         project
@@ -1582,11 +2562,113 @@ fn choose_random_project(client: &TestClient, rng: &mut StdRng) -> Option<Model<
         .cloned()
 }
 
+/// Simulates pasting several megabytes of text at a single position.
+fn gen_large_paste(buffer: &language::Buffer, rng: &mut StdRng) -> Vec<(Range<usize>, Arc<str>)> {
+    const LINE_LEN: usize = 64;
+    const LINES_PER_MB: usize = 1024 * 1024 / LINE_LEN;
+
+    let offset = buffer.clip_offset(rng.gen_range(0..=buffer.len()), Bias::Left);
+    let line_count = rng.gen_range(1..=4) * LINES_PER_MB;
+    let mut text = String::with_capacity(line_count * LINE_LEN);
+    for _ in 0..line_count {
+        text.push_str(&Alphanumeric.sample_string(rng, LINE_LEN - 1));
+        text.push('\n');
+    }
+    vec![(offset..offset, text.into())]
+}
+
+/// Simulates typing the same text with many cursors, each of which may have a
+/// non-empty selection.
+fn gen_multi_cursor_edits(
+    buffer: &language::Buffer,
+    rng: &mut StdRng,
+) -> Vec<(Range<usize>, Arc<str>)> {
+    let cursor_count = rng.gen_range(2..=50);
+    let mut starts = (0..cursor_count)
+        .map(|_| buffer.clip_offset(rng.gen_range(0..=buffer.len()), Bias::Left))
+        .collect::<Vec<_>>();
+    starts.sort_unstable();
+    starts.dedup();
+
+    let text_len = rng.gen_range(0..5);
+    let text: Arc<str> = Alphanumeric.sample_string(rng, text_len).into();
+    let mut edits = Vec::with_capacity(starts.len());
+    for (ix, start) in starts.iter().copied().enumerate() {
+        let max_end = starts.get(ix + 1).copied().unwrap_or(buffer.len());
+        let end = buffer.clip_offset((start + rng.gen_range(0..4)).min(max_end), Bias::Right);
+        edits.push((start..end.min(max_end), text.clone()));
+    }
+    edits
+}
+
+fn gen_file_extension(rng: &mut StdRng) -> &'static str {
+    // Besides Rust files, generate plain text files and files with an extension that
+    // doesn't match any language, so that language detection gets exercised.
+    ["rs", "rs", "txt", "zzz"].choose(rng).unwrap()
+}
+
 fn gen_file_name(rng: &mut StdRng) -> String {
+    // Names that Windows reserves for devices, in any case and with any extension.
+    const RESERVED_NAMES: [&str; 6] = ["CON", "nul", "Aux", "PRN", "com1", "LPT9"];
+
+    match rng.gen_range(0..20) {
+        // A hidden file. The name is too long to ever be `.git`.
+        0 => format!(".{}", gen_name_chars(rng, 10)),
+        // Spaces inside the name, and at either end of it.
+        1 => format!("{} {}", gen_name_chars(rng, 4), gen_name_chars(rng, 5)),
+        2 => format!(" {} ", gen_name_chars(rng, 8)),
+        3 => RESERVED_NAMES.choose(rng).unwrap().to_string(),
+        // A long name, still short enough for every platform's limit of 255 bytes.
+        4 => gen_name_chars(rng, rng.gen_range(50..=80)),
+        _ => gen_name_chars(rng, 10),
+    }
+}
+
+fn gen_name_chars(rng: &mut StdRng, len: usize) -> String {
+    // Accented letters are spelled composed on most platforms and decomposed on
+    // macOS, so generate both spellings, along with letters that have no
+    // decomposition.
+    const ACCENTED_LETTERS: [(&str, &str); 3] = [
+        ("\u{e9}", "e\u{301}"),
+        ("\u{fc}", "u\u{308}"),
+        ("\u{f1}", "n\u{303}"),
+    ];
+    const OTHER_LETTERS: [char; 3] = ['\u{df}', '\u{436}', '\u{65e5}'];
+
     let mut name = String::new();
-    for _ in 0..10 {
-        let letter = rng.gen_range('a'..='z');
-        name.push(letter);
+    for _ in 0..len {
+        match rng.gen_range(0..20) {
+            0 => {
+                let (composed, decomposed) = ACCENTED_LETTERS.choose(rng).unwrap();
+                name.push_str(if rng.gen() { composed } else { decomposed });
+            }
+            1 => name.push(*OTHER_LETTERS.choose(rng).unwrap()),
+            _ => name.push(rng.gen_range('a'..='z')),
+        }
     }
     name
 }
+
+/// Returns the snapshot's entries with their paths spelled the way they're replicated,
+/// since a host's file names may be spelled differently than its guests receive them.
+fn replicated_entries(snapshot: &Snapshot) -> Vec<Entry> {
+    let mut entries = snapshot
+        .entries(false)
+        .map(|entry| {
+            let mut entry = entry.clone();
+            entry.path = normalize_unicode(&entry.path).into();
+            entry
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries
+}
+
+fn replicated_repositories(snapshot: &Snapshot) -> Vec<(Arc<Path>, RepositoryEntry)> {
+    let mut repositories = snapshot
+        .repositories()
+        .map(|(path, repository)| (normalize_unicode(path).into(), repository.clone()))
+        .collect::<Vec<(Arc<Path>, _)>>();
+    repositories.sort_by(|a, b| a.0.cmp(&b.0));
+    repositories
+}
@@ -1,14 +1,19 @@
 use crate::{
-    db::{self, NewUserParams, UserId},
-    rpc::{CLEANUP_TIMEOUT, RECONNECT_TIMEOUT},
-    tests::{TestClient, TestServer},
+    db::{self, DatabaseFaults, NewUserParams, UserId},
+    rpc::{RECONNECT_TIMEOUT, REJOIN_WINDOW},
+    tests::{
+        operation_trace::{OperationTrace, TraceOutcome},
+        room_participants, NetworkFaults, OperationEntities, RoomParticipants, TestClient,
+        TestServer,
+    },
 };
 use async_trait::async_trait;
+use call::ActiveCall;
 use futures::StreamExt;
 use gpui::{BackgroundExecutor, Task, TestAppContext};
 use parking_lot::Mutex;
 use rand::prelude::*;
-use rpc::RECEIVE_TIMEOUT;
+use rpc::{proto::PeerId, RECEIVE_TIMEOUT};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use settings::SettingsStore;
 use std::{
@@ -19,11 +24,13 @@ use std::{
         atomic::{AtomicBool, Ordering::SeqCst},
         Arc,
     },
+    time::Duration,
 };
 
 lazy_static::lazy_static! {
     static ref PLAN_LOAD_PATH: Option<PathBuf> = path_env_var("LOAD_PLAN");
     static ref PLAN_SAVE_PATH: Option<PathBuf> = path_env_var("SAVE_PLAN");
+    static ref TRACE_PATH: Option<PathBuf> = path_env_var("TRACE_OPERATIONS");
     static ref MAX_PEERS: usize = env::var("MAX_PEERS")
         .map(|i| i.parse().expect("invalid `MAX_PEERS` variable"))
         .unwrap_or(3);
@@ -33,6 +40,14 @@ lazy_static::lazy_static! {
 
 }
 
+/// How many tasks may run without the executor parking, or while a single operation
+/// is applied, before the test is considered hung. Override it with `TICK_BUDGET`.
+const DEFAULT_TICK_BUDGET: usize = 1_000_000;
+
+/// How many clients may be connected as the same user at once, when a test plan
+/// allows a user to have more than one.
+const MAX_CONNECTIONS_PER_USER: usize = 2;
+
 static LOADED_PLAN_JSON: Mutex<Option<Vec<u8>>> = Mutex::new(None);
 static LAST_PLAN: Mutex<Option<Box<dyn Send + FnOnce() -> Vec<u8>>>> = Mutex::new(None);
 
@@ -47,6 +62,9 @@ struct TestPlan<T: RandomizedTest> {
     allow_server_restarts: bool,
     allow_client_reconnection: bool,
     allow_client_disconnection: bool,
+    allow_multiple_connections: bool,
+    database_faults: DatabaseFaults,
+    trace: Option<Arc<OperationTrace>>,
 }
 
 pub struct UserTestPlan {
@@ -55,8 +73,17 @@ pub struct UserTestPlan {
     pub allow_client_reconnection: bool,
     pub allow_client_disconnection: bool,
     next_root_id: usize,
-    operation_ix: usize,
-    online: bool,
+    operation_ixs: Vec<usize>,
+    connected_client_ixs: Vec<usize>,
+}
+
+/// Identifies one of the clients connected as a user. A client keeps its index for
+/// as long as it's connected, and a new client takes the lowest free index, so that
+/// a replayed plan routes each operation to the same client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct ClientId {
+    user_id: UserId,
+    client_ix: usize,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -65,11 +92,32 @@ enum StoredOperation<T> {
     Server(ServerOperation),
     Client {
         user_id: UserId,
+        #[serde(default)]
+        client_ix: usize,
         batch_id: usize,
         operation: T,
     },
 }
 
+impl<T> StoredOperation<T> {
+    fn user_id(&self) -> Option<UserId> {
+        match self {
+            StoredOperation::Server(
+                ServerOperation::AddConnection { user_id }
+                | ServerOperation::RemoveConnection { user_id, .. }
+                | ServerOperation::BounceConnection { user_id, .. }
+                | ServerOperation::SleepClient { user_id, .. },
+            )
+            | StoredOperation::Client { user_id, .. } => Some(*user_id),
+            StoredOperation::Server(
+                ServerOperation::RestartServer
+                | ServerOperation::DrainServer
+                | ServerOperation::MutateClients { .. },
+            ) => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 enum ServerOperation {
     AddConnection {
@@ -77,16 +125,30 @@ enum ServerOperation {
     },
     RemoveConnection {
         user_id: UserId,
+        #[serde(default)]
+        client_ix: usize,
     },
     BounceConnection {
         user_id: UserId,
+        #[serde(default)]
+        client_ix: usize,
+    },
+    SleepClient {
+        user_id: UserId,
+        #[serde(default)]
+        client_ix: usize,
+        duration: Duration,
     },
+    /// The server restarts without warning, as when it crashes.
     RestartServer,
+    /// The server tells its clients that it's restarting before it does, handing
+    /// them resume tokens for rejoining their rooms.
+    DrainServer,
     MutateClients {
         batch_id: usize,
         #[serde(skip_serializing)]
         #[serde(skip_deserializing)]
-        user_ids: Vec<UserId>,
+        client_ids: Vec<ClientId>,
         quiesce: bool,
     },
 }
@@ -113,11 +175,30 @@ pub trait RandomizedTest: 'static + Sized {
         cx: &mut TestAppContext,
     ) -> Result<(), TestError>;
 
+    /// The projects and buffers that an operation refers to, for the operation
+    /// trace written when `TRACE_OPERATIONS` is set.
+    fn operation_entities(
+        _client: &TestClient,
+        _operation: &Self::Operation,
+        _cx: &TestAppContext,
+    ) -> OperationEntities {
+        OperationEntities::default()
+    }
+
     async fn initialize(server: &mut TestServer, users: &[UserTestPlan]);
 
     async fn on_client_added(_client: &Rc<TestClient>, _cx: &mut TestAppContext) {}
 
     async fn on_quiesce(server: &mut TestServer, client: &mut [(Rc<TestClient>, TestAppContext)]);
+
+    /// Called once every client has finished its operations, after the final
+    /// [`RandomizedTest::on_quiesce`]. Unlike between batches, no operation can
+    /// still be in flight, so invariants that race with them are checked here.
+    async fn on_settled(
+        _server: &mut TestServer,
+        _clients: &mut [(Rc<TestClient>, TestAppContext)],
+    ) {
+    }
 }
 
 pub async fn run_randomized_test<T: RandomizedTest>(
@@ -125,8 +206,45 @@ pub async fn run_randomized_test<T: RandomizedTest>(
     executor: BackgroundExecutor,
     rng: StdRng,
 ) {
+    let saved_plan = PLAN_LOAD_PATH.as_ref().map(|path| {
+        LOADED_PLAN_JSON
+            .lock()
+            .get_or_insert_with(|| {
+                eprintln!("loaded test plan from path {:?}", path);
+                std::fs::read(path).unwrap()
+            })
+            .clone()
+    });
+    run_test_plan::<T>(cx, executor, rng, saved_plan).await;
+}
+
+/// Replays a plan that was written with `SAVE_PLAN`, so that plans which once
+/// failed can be checked in as regression tests.
+pub async fn replay_randomized_test<T: RandomizedTest>(
+    cx: &mut TestAppContext,
+    executor: BackgroundExecutor,
+    plan_json: &[u8],
+) {
+    run_test_plan::<T>(
+        cx,
+        executor,
+        StdRng::seed_from_u64(0),
+        Some(plan_json.to_vec()),
+    )
+    .await;
+}
+
+async fn run_test_plan<T: RandomizedTest>(
+    cx: &mut TestAppContext,
+    executor: BackgroundExecutor,
+    rng: StdRng,
+    saved_plan: Option<Vec<u8>>,
+) {
+    if executor.tick_budget().is_none() {
+        executor.set_tick_budget(Some(DEFAULT_TICK_BUDGET));
+    }
     let mut server = TestServer::start(executor.clone()).await;
-    let plan = TestPlan::<T>::new(&mut server, rng).await;
+    let plan = TestPlan::<T>::new(&mut server, rng, saved_plan).await;
 
     LAST_PLAN.lock().replace({
         let plan = plan.clone();
@@ -134,24 +252,47 @@ pub async fn run_randomized_test<T: RandomizedTest>(
     });
 
     let mut clients = Vec::new();
+    let mut client_ids = Vec::new();
     let mut client_tasks = Vec::new();
     let mut operation_channels = Vec::new();
     loop {
-        let Some((next_operation, applied)) = plan.lock().next_server_operation(&clients) else {
+        let Some((next_operation, applied)) = plan.lock().next_server_operation(&client_ids) else {
             break;
         };
         applied.store(true, SeqCst);
+        let operation_id = executor.start_operation(format!("server: {next_operation:?}"));
+        let trace = plan.lock().trace.clone();
+        let traced_operation = trace.map(|trace| {
+            trace.begin(
+                "server",
+                None,
+                None,
+                &next_operation,
+                OperationEntities::default(),
+                &executor,
+            )
+        });
         let did_apply = TestPlan::apply_server_operation(
             plan.clone(),
             executor.clone(),
             &mut server,
             &mut clients,
+            &mut client_ids,
             &mut client_tasks,
             &mut operation_channels,
             next_operation,
             cx,
         )
         .await;
+        executor.finish_operation(operation_id);
+        if let Some(traced_operation) = traced_operation {
+            let outcome = if did_apply {
+                TraceOutcome::Applied
+            } else {
+                TraceOutcome::Skipped
+            };
+            traced_operation.finish(outcome, &executor);
+        }
         if !did_apply {
             applied.store(false, SeqCst);
         }
@@ -162,8 +303,11 @@ pub async fn run_randomized_test<T: RandomizedTest>(
     futures::future::join_all(client_tasks).await;
     executor.finish_waiting();
 
+    server.set_database_faults(DatabaseFaults::default());
     executor.run_until_parked();
     T::on_quiesce(&mut server, &mut clients).await;
+    T::on_settled(&mut server, &mut clients).await;
+    assert_clients_of_each_user_converged(&clients);
 
     for (client, cx) in clients {
         cx.update(|cx| {
@@ -179,6 +323,9 @@ pub async fn run_randomized_test<T: RandomizedTest>(
         eprintln!("saved test plan to path {:?}", path);
         std::fs::write(path, plan.lock().serialize()).unwrap();
     }
+    if let Some(trace) = plan.lock().trace.take() {
+        eprintln!("saved operation trace to path {:?}", trace.path());
+    }
 }
 
 pub fn save_randomized_test_plan() {
@@ -191,13 +338,48 @@ pub fn save_randomized_test_plan() {
 }
 
 impl<T: RandomizedTest> TestPlan<T> {
-    pub async fn new(server: &mut TestServer, mut rng: StdRng) -> Arc<Mutex<Self>> {
+    pub async fn new(
+        server: &mut TestServer,
+        mut rng: StdRng,
+        saved_plan: Option<Vec<u8>>,
+    ) -> Arc<Mutex<Self>> {
         let allow_server_restarts = rng.gen_bool(0.7);
         let allow_client_reconnection = rng.gen_bool(0.7);
         let allow_client_disconnection = rng.gen_bool(0.1);
+        let allow_multiple_connections = rng.gen_bool(0.3);
+
+        // Delay messages, so that operations sent over different connections race
+        // with each other the way they do over a real network. Messages are never
+        // dropped, because that severs the connection, which the operations that
+        // disconnect clients already cover.
+        if rng.gen_bool(0.5) {
+            server.inject_network_faults(
+                NetworkFaults {
+                    delay_probability: rng.gen_range(0.0..0.2),
+                    drop_probability: 0.,
+                    max_delay: 10,
+                },
+                StdRng::seed_from_u64(rng.gen()),
+            );
+        }
+
+        let saved_operations = saved_plan.map(|json| {
+            serde_json::from_slice::<Vec<StoredOperation<T::Operation>>>(&json).unwrap()
+        });
+
+        // Users are created in order, so a saved plan needs at least as many
+        // users as its highest user id.
+        let peer_count = saved_operations
+            .iter()
+            .flatten()
+            .filter_map(StoredOperation::user_id)
+            .map(|user_id| user_id.to_proto() as usize)
+            .max()
+            .unwrap_or(0)
+            .max(*MAX_PEERS);
 
         let mut users = Vec::new();
-        for ix in 0..*MAX_PEERS {
+        for ix in 0..peer_count {
             let username = format!("user-{}", ix + 1);
             let user_id = server
                 .app_state
@@ -216,9 +398,9 @@ impl<T: RandomizedTest> TestPlan<T> {
             users.push(UserTestPlan {
                 user_id,
                 username,
-                online: false,
                 next_root_id: 0,
-                operation_ix: 0,
+                operation_ixs: Vec::new(),
+                connected_client_ixs: Vec::new(),
                 allow_client_disconnection,
                 allow_client_reconnection,
             });
@@ -226,11 +408,32 @@ impl<T: RandomizedTest> TestPlan<T> {
 
         T::initialize(server, &users).await;
 
+        // Make some of the server's transactions fail, so that the server has to
+        // report the errors to clients and clean up after them. The faults are only
+        // injected while clients mutate and disconnect, because the harness's own
+        // queries expect the database to be healthy.
+        let database_faults = if rng.gen_bool(0.3) {
+            server.inject_database_faults(
+                DatabaseFaults::default(),
+                StdRng::seed_from_u64(rng.gen()),
+            );
+            DatabaseFaults {
+                error_probability: rng.gen_range(0.0..0.05),
+                timeout_probability: rng.gen_range(0.0..0.02),
+                max_timeout: 10,
+            }
+        } else {
+            DatabaseFaults::default()
+        };
+
         let plan = Arc::new(Mutex::new(Self {
             replay: false,
             allow_server_restarts,
             allow_client_reconnection,
             allow_client_disconnection,
+            allow_multiple_connections,
+            database_faults,
+            trace: TRACE_PATH.as_deref().map(OperationTrace::start),
             stored_operations: Vec::new(),
             operation_ix: 0,
             next_batch_id: 0,
@@ -239,23 +442,14 @@ impl<T: RandomizedTest> TestPlan<T> {
             rng,
         }));
 
-        if let Some(path) = &*PLAN_LOAD_PATH {
-            let json = LOADED_PLAN_JSON
-                .lock()
-                .get_or_insert_with(|| {
-                    eprintln!("loaded test plan from path {:?}", path);
-                    std::fs::read(path).unwrap()
-                })
-                .clone();
-            plan.lock().deserialize(json);
+        if let Some(stored_operations) = saved_operations {
+            plan.lock().replay(stored_operations);
         }
 
         plan
     }
 
-    fn deserialize(&mut self, json: Vec<u8>) {
-        let stored_operations: Vec<StoredOperation<T::Operation>> =
-            serde_json::from_slice(&json).unwrap();
+    fn replay(&mut self, stored_operations: Vec<StoredOperation<T::Operation>>) {
         self.replay = true;
         self.stored_operations = stored_operations
             .iter()
@@ -265,23 +459,29 @@ impl<T: RandomizedTest> TestPlan<T> {
                 let did_apply = Arc::new(AtomicBool::new(false));
                 if let StoredOperation::Server(ServerOperation::MutateClients {
                     batch_id: current_batch_id,
-                    user_ids,
+                    client_ids,
                     ..
                 }) = &mut operation
                 {
-                    assert!(user_ids.is_empty());
-                    user_ids.extend(stored_operations[i + 1..].iter().filter_map(|operation| {
+                    assert!(client_ids.is_empty());
+                    client_ids.extend(stored_operations[i + 1..].iter().filter_map(|operation| {
                         if let StoredOperation::Client {
-                            user_id, batch_id, ..
+                            user_id,
+                            client_ix,
+                            batch_id,
+                            ..
                         } = operation
                         {
                             if batch_id == current_batch_id {
-                                return Some(user_id);
+                                return Some(ClientId {
+                                    user_id: *user_id,
+                                    client_ix: *client_ix,
+                                });
                             }
                         }
                         None
                     }));
-                    user_ids.sort_unstable();
+                    client_ids.sort_unstable();
                 }
                 (operation, did_apply)
             })
@@ -308,7 +508,7 @@ impl<T: RandomizedTest> TestPlan<T> {
 
     fn next_server_operation(
         &mut self,
-        clients: &[(Rc<TestClient>, TestAppContext)],
+        client_ids: &[ClientId],
     ) -> Option<(ServerOperation, Arc<AtomicBool>)> {
         if self.replay {
             while let Some(stored_operation) = self.stored_operations.get(self.operation_ix) {
@@ -319,7 +519,7 @@ impl<T: RandomizedTest> TestPlan<T> {
             }
             None
         } else {
-            let operation = self.generate_server_operation(clients)?;
+            let operation = self.generate_server_operation(client_ids)?;
             let applied = Arc::new(AtomicBool::new(false));
             self.stored_operations
                 .push((StoredOperation::Server(operation.clone()), applied.clone()));
@@ -330,6 +530,7 @@ impl<T: RandomizedTest> TestPlan<T> {
     fn next_client_operation(
         &mut self,
         client: &TestClient,
+        current_client_ix: usize,
         current_batch_id: usize,
         cx: &TestAppContext,
     ) -> Option<(T::Operation, Arc<AtomicBool>)> {
@@ -340,18 +541,25 @@ impl<T: RandomizedTest> TestPlan<T> {
             .position(|user| user.user_id == current_user_id)
             .unwrap();
         let user_plan = &mut self.users[user_ix];
+        if user_plan.operation_ixs.len() <= current_client_ix {
+            user_plan.operation_ixs.resize(current_client_ix + 1, 0);
+        }
+        let operation_ix = &mut user_plan.operation_ixs[current_client_ix];
 
         if self.replay {
-            while let Some(stored_operation) = self.stored_operations.get(user_plan.operation_ix) {
-                user_plan.operation_ix += 1;
+            while let Some(stored_operation) = self.stored_operations.get(*operation_ix) {
+                *operation_ix += 1;
                 if let (
                     StoredOperation::Client {
-                        user_id, operation, ..
+                        user_id,
+                        client_ix,
+                        operation,
+                        ..
                     },
                     applied,
                 ) = stored_operation
                 {
-                    if user_id == &current_user_id {
+                    if user_id == &current_user_id && *client_ix == current_client_ix {
                         return Some((operation.clone(), applied.clone()));
                     }
                 }
@@ -375,6 +583,7 @@ impl<T: RandomizedTest> TestPlan<T> {
             self.stored_operations.push((
                 StoredOperation::Client {
                     user_id: current_user_id,
+                    client_ix: current_client_ix,
                     batch_id: current_batch_id,
                     operation: operation.clone(),
                 },
@@ -384,21 +593,29 @@ impl<T: RandomizedTest> TestPlan<T> {
         }
     }
 
-    fn generate_server_operation(
-        &mut self,
-        clients: &[(Rc<TestClient>, TestAppContext)],
-    ) -> Option<ServerOperation> {
+    fn generate_server_operation(&mut self, client_ids: &[ClientId]) -> Option<ServerOperation> {
         if self.operation_ix == self.max_operations {
             return None;
         }
 
+        let max_connections_per_user = if self.allow_multiple_connections {
+            MAX_CONNECTIONS_PER_USER
+        } else {
+            1
+        };
+
         Some(loop {
             break match self.rng.gen_range(0..100) {
-                0..=29 if clients.len() < self.users.len() => {
+                0..=29
+                    if self
+                        .users
+                        .iter()
+                        .any(|u| u.connected_client_ixs.len() < max_connections_per_user) =>
+                {
                     let user = self
                         .users
                         .iter()
-                        .filter(|u| !u.online)
+                        .filter(|u| u.connected_client_ixs.len() < max_connections_per_user)
                         .choose(&mut self.rng)
                         .unwrap();
                     self.operation_ix += 1;
@@ -406,38 +623,49 @@ impl<T: RandomizedTest> TestPlan<T> {
                         user_id: user.user_id,
                     }
                 }
-                30..=34 if clients.len() > 1 && self.allow_client_disconnection => {
-                    let (client, cx) = &clients[self.rng.gen_range(0..clients.len())];
-                    let user_id = client.current_user_id(cx);
+                30..=34 if client_ids.len() > 1 && self.allow_client_disconnection => {
+                    let ClientId { user_id, client_ix } =
+                        *client_ids.choose(&mut self.rng).unwrap();
                     self.operation_ix += 1;
-                    ServerOperation::RemoveConnection { user_id }
+                    ServerOperation::RemoveConnection { user_id, client_ix }
                 }
-                35..=39 if clients.len() > 1 && self.allow_client_reconnection => {
-                    let (client, cx) = &clients[self.rng.gen_range(0..clients.len())];
-                    let user_id = client.current_user_id(cx);
+                35..=39 if client_ids.len() > 1 && self.allow_client_reconnection => {
+                    let ClientId { user_id, client_ix } =
+                        *client_ids.choose(&mut self.rng).unwrap();
                     self.operation_ix += 1;
-                    ServerOperation::BounceConnection { user_id }
+                    ServerOperation::BounceConnection { user_id, client_ix }
                 }
-                40..=44 if self.allow_server_restarts && clients.len() > 1 => {
+                40..=44 if client_ids.len() > 1 && self.allow_client_reconnection => {
+                    let ClientId { user_id, client_ix } =
+                        *client_ids.choose(&mut self.rng).unwrap();
+                    let duration = Duration::from_secs(self.rng.gen_range(60..=600));
                     self.operation_ix += 1;
-                    ServerOperation::RestartServer
+                    ServerOperation::SleepClient {
+                        user_id,
+                        client_ix,
+                        duration,
+                    }
+                }
+                45..=49 if self.allow_server_restarts && client_ids.len() > 1 => {
+                    self.operation_ix += 1;
+                    if self.rng.gen() {
+                        ServerOperation::DrainServer
+                    } else {
+                        ServerOperation::RestartServer
+                    }
                 }
-                _ if !clients.is_empty() => {
+                _ if !client_ids.is_empty() => {
                     let count = self
                         .rng
                         .gen_range(1..10)
                         .min(self.max_operations - self.operation_ix);
                     let batch_id = util::post_inc(&mut self.next_batch_id);
-                    let mut user_ids = (0..count)
-                        .map(|_| {
-                            let ix = self.rng.gen_range(0..clients.len());
-                            let (client, cx) = &clients[ix];
-                            client.current_user_id(cx)
-                        })
+                    let mut client_ids = (0..count)
+                        .map(|_| *client_ids.choose(&mut self.rng).unwrap())
                         .collect::<Vec<_>>();
-                    user_ids.sort_unstable();
+                    client_ids.sort_unstable();
                     ServerOperation::MutateClients {
-                        user_ids,
+                        client_ids,
                         batch_id,
                         quiesce: self.rng.gen_bool(0.7),
                     }
@@ -452,24 +680,37 @@ impl<T: RandomizedTest> TestPlan<T> {
         deterministic: BackgroundExecutor,
         server: &mut TestServer,
         clients: &mut Vec<(Rc<TestClient>, TestAppContext)>,
+        client_ids: &mut Vec<ClientId>,
         client_tasks: &mut Vec<Task<()>>,
         operation_channels: &mut Vec<futures::channel::mpsc::UnboundedSender<usize>>,
         operation: ServerOperation,
         cx: &mut TestAppContext,
     ) -> bool {
+        // Operations that mutate clients leave the faults on, so that the clients'
+        // requests keep failing until the next operation that needs a healthy
+        // database.
+        let database_faults = plan.lock().database_faults;
+        if !matches!(operation, ServerOperation::MutateClients { .. }) {
+            server.set_database_faults(DatabaseFaults::default());
+        }
+
         match operation {
             ServerOperation::AddConnection { user_id } => {
                 let username;
+                let client_ix;
                 {
                     let mut plan = plan.lock();
                     let user = plan.user(user_id);
-                    if user.online {
+                    if user.connected_client_ixs.len() == MAX_CONNECTIONS_PER_USER {
                         return false;
                     }
-                    user.online = true;
+                    client_ix = (0..)
+                        .find(|ix| !user.connected_client_ixs.contains(ix))
+                        .unwrap();
+                    user.connected_client_ixs.push(client_ix);
                     username = user.username.clone();
                 };
-                log::info!("adding new connection for {}", username);
+                log::info!("adding new connection {} for {}", client_ix, username);
 
                 let mut client_cx = cx.new_app();
 
@@ -477,42 +718,50 @@ impl<T: RandomizedTest> TestPlan<T> {
                 let client = Rc::new(server.create_client(&mut client_cx, &username).await);
                 operation_channels.push(operation_tx);
                 clients.push((client.clone(), client_cx.clone()));
+                client_ids.push(ClientId { user_id, client_ix });
 
                 let foreground_executor = client_cx.foreground_executor().clone();
                 let simulate_client =
-                    Self::simulate_client(plan.clone(), client, operation_rx, client_cx);
+                    Self::simulate_client(plan.clone(), client, client_ix, operation_rx, client_cx);
                 client_tasks.push(foreground_executor.spawn(simulate_client));
 
-                log::info!("added connection for {}", username);
+                log::info!("added connection {} for {}", client_ix, username);
             }
 
             ServerOperation::RemoveConnection {
                 user_id: removed_user_id,
+                client_ix: removed_client_ix,
             } => {
-                log::info!("simulating full disconnection of user {}", removed_user_id);
-                let client_ix = clients
-                    .iter()
-                    .position(|(client, cx)| client.current_user_id(cx) == removed_user_id);
+                log::info!(
+                    "simulating full disconnection of user {}'s connection {}",
+                    removed_user_id,
+                    removed_client_ix
+                );
+                let client_ix = client_ids.iter().position(|id| {
+                    *id == ClientId {
+                        user_id: removed_user_id,
+                        client_ix: removed_client_ix,
+                    }
+                });
                 let Some(client_ix) = client_ix else {
                     return false;
                 };
-                let user_connection_ids = server
-                    .connection_pool
-                    .lock()
-                    .user_connection_ids(removed_user_id)
-                    .collect::<Vec<_>>();
-                assert_eq!(user_connection_ids.len(), 1);
-                let removed_peer_id = user_connection_ids[0].into();
+                let Some(removed_peer_id) = connected_peer_id(server, &clients[client_ix].0) else {
+                    return false;
+                };
                 let (client, client_cx) = clients.remove(client_ix);
+                client_ids.remove(client_ix);
                 let client_task = client_tasks.remove(client_ix);
                 operation_channels.remove(client_ix);
                 server.forbid_connections();
+                server.set_database_faults(database_faults);
                 server.disconnect_client(removed_peer_id);
                 deterministic.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
                 deterministic.start_waiting();
                 log::info!("waiting for user {} to exit...", removed_user_id);
                 client_task.await;
                 deterministic.finish_waiting();
+                server.set_database_faults(DatabaseFaults::default());
                 server.allow_connections();
 
                 for project in client.remote_projects().iter() {
@@ -525,7 +774,17 @@ impl<T: RandomizedTest> TestPlan<T> {
                     });
                 }
 
-                for (client, cx) in clients {
+                deterministic.run_until_parked();
+                server
+                    .assert_peer_purged(removed_peer_id, removed_user_id, clients)
+                    .await;
+
+                // The user only goes offline once its last client is gone.
+                let user_has_clients = client_ids.iter().any(|id| id.user_id == removed_user_id);
+                for (client, cx) in clients.iter() {
+                    if user_has_clients {
+                        break;
+                    }
                     let contacts = server
                         .app_state
                         .db
@@ -543,36 +802,125 @@ impl<T: RandomizedTest> TestPlan<T> {
                     }
                 }
 
-                log::info!("{} removed", client.username);
-                plan.lock().user(removed_user_id).online = false;
+                log::info!(
+                    "{}'s connection {} removed",
+                    client.username,
+                    removed_client_ix
+                );
+                plan.lock()
+                    .user(removed_user_id)
+                    .connected_client_ixs
+                    .retain(|ix| *ix != removed_client_ix);
                 client_cx.update(|cx| {
                     cx.clear_globals();
                     drop(client);
                 });
             }
 
-            ServerOperation::BounceConnection { user_id } => {
-                log::info!("simulating temporary disconnection of user {}", user_id);
-                let user_connection_ids = server
-                    .connection_pool
-                    .lock()
-                    .user_connection_ids(user_id)
-                    .collect::<Vec<_>>();
-                if user_connection_ids.is_empty() {
+            ServerOperation::BounceConnection { user_id, client_ix } => {
+                log::info!(
+                    "simulating temporary disconnection of user {}'s connection {}",
+                    user_id,
+                    client_ix
+                );
+                let client_ix = client_ids
+                    .iter()
+                    .position(|id| *id == ClientId { user_id, client_ix });
+                let Some(client_ix) = client_ix else {
                     return false;
-                }
-                assert_eq!(user_connection_ids.len(), 1);
-                let peer_id = user_connection_ids[0].into();
+                };
+                let Some(peer_id) = connected_peer_id(server, &clients[client_ix].0) else {
+                    return false;
+                };
                 server.disconnect_client(peer_id);
                 deterministic.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
             }
 
+            ServerOperation::SleepClient {
+                user_id,
+                client_ix,
+                duration,
+            } => {
+                log::info!(
+                    "simulating system sleep of user {}'s connection {} for {:?}",
+                    user_id,
+                    client_ix,
+                    duration
+                );
+                let client_ix = client_ids
+                    .iter()
+                    .position(|id| *id == ClientId { user_id, client_ix });
+                let Some(client_ix) = client_ix else {
+                    return false;
+                };
+                let Some(peer_id) = connected_peer_id(server, &clients[client_ix].0) else {
+                    return false;
+                };
+                server.simulate_system_sleep(peer_id, duration, deterministic.clone());
+
+                let (client, _) = &clients[client_ix];
+                let status = *client.status().borrow();
+                assert!(
+                    matches!(status, client::Status::Connected { .. }),
+                    "{} did not reconnect immediately after resuming: {:?}",
+                    client.username,
+                    status
+                );
+                assert_ne!(
+                    client.peer_id(),
+                    Some(peer_id),
+                    "{} is still using the connection it had before sleeping",
+                    client.username
+                );
+
+                // Let the server notice that the connection that died during sleep is gone.
+                deterministic.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
+            }
+
             ServerOperation::RestartServer => {
-                log::info!("simulating server restart");
+                log::info!("simulating server crash");
+                deterministic.run_until_parked();
+                let calls_before_restart = summarize_calls(clients);
                 server.reset().await;
                 deterministic.advance_clock(RECEIVE_TIMEOUT);
                 server.start().await.unwrap();
-                deterministic.advance_clock(CLEANUP_TIMEOUT);
+
+                // Even without warning, clients rejoin their rooms within the rejoin
+                // window, so every call carries on with the same participants.
+                deterministic.advance_clock(REJOIN_WINDOW);
+                deterministic.run_until_parked();
+                assert_eq!(
+                    summarize_calls(clients),
+                    calls_before_restart,
+                    "calls changed across the server crash"
+                );
+                let environment = &server.app_state.config.zed_environment;
+                let (stale_room_ids, _) = server
+                    .app_state
+                    .db
+                    .stale_server_resource_ids(environment, server.id())
+                    .await
+                    .unwrap();
+                assert_eq!(stale_room_ids, vec![]);
+            }
+
+            ServerOperation::DrainServer => {
+                log::info!("simulating server restart");
+                deterministic.run_until_parked();
+                let calls_before_restart = summarize_calls(clients);
+                server.restart(&deterministic).await;
+                deterministic.advance_clock(RECEIVE_TIMEOUT);
+                server.start().await.unwrap();
+
+                // Clients rejoin their rooms within the rejoin window, so every call
+                // carries on with the same participants once the window has closed.
+                deterministic.advance_clock(REJOIN_WINDOW);
+                deterministic.run_until_parked();
+                assert_eq!(
+                    summarize_calls(clients),
+                    calls_before_restart,
+                    "calls changed across the server restart"
+                );
                 let environment = &server.app_state.config.zed_environment;
                 let (stale_room_ids, _) = server
                     .app_state
@@ -584,25 +932,26 @@ impl<T: RandomizedTest> TestPlan<T> {
             }
 
             ServerOperation::MutateClients {
-                user_ids,
+                client_ids: mutated_client_ids,
                 batch_id,
                 quiesce,
             } => {
+                server.set_database_faults(database_faults);
                 let mut applied = false;
-                for user_id in user_ids {
-                    let client_ix = clients
-                        .iter()
-                        .position(|(client, cx)| client.current_user_id(cx) == user_id);
+                for client_id in mutated_client_ids {
+                    let client_ix = client_ids.iter().position(|id| *id == client_id);
                     let Some(client_ix) = client_ix else { continue };
                     applied = true;
                     if let Err(err) = operation_channels[client_ix].unbounded_send(batch_id) {
-                        log::error!("error signaling user {user_id}: {err}");
+                        log::error!("error signaling client {client_id:?}: {err}");
                     }
                 }
 
                 if quiesce && applied {
+                    server.set_database_faults(DatabaseFaults::default());
                     deterministic.run_until_parked();
                     T::on_quiesce(server, clients).await;
+                    assert_clients_of_each_user_converged(clients);
                 }
 
                 return applied;
@@ -614,27 +963,54 @@ impl<T: RandomizedTest> TestPlan<T> {
     async fn simulate_client(
         plan: Arc<Mutex<Self>>,
         client: Rc<TestClient>,
+        client_ix: usize,
         mut operation_rx: futures::channel::mpsc::UnboundedReceiver<usize>,
         mut cx: TestAppContext,
     ) {
         T::on_client_added(&client, &mut cx).await;
 
         while let Some(batch_id) = operation_rx.next().await {
-            let Some((operation, applied)) =
-                plan.lock().next_client_operation(&client, batch_id, &cx)
+            let Some((operation, applied)) = plan
+                .lock()
+                .next_client_operation(&client, client_ix, batch_id, &cx)
             else {
                 break;
             };
             applied.store(true, SeqCst);
-            match T::apply_operation(&client, operation, &mut cx).await {
-                Ok(()) => {}
+            let description = format!(
+                "{}: {}",
+                client.username,
+                util::truncate_and_trailoff(&serde_json::to_string(&operation).unwrap(), 200)
+            );
+            let executor = cx.executor();
+            let operation_id = executor.start_operation(description);
+            let trace = plan.lock().trace.clone();
+            let traced_operation = trace.map(|trace| {
+                trace.begin(
+                    &client.username,
+                    Some(client_ix),
+                    Some(batch_id),
+                    &operation,
+                    T::operation_entities(&client, &operation, &cx),
+                    &executor,
+                )
+            });
+            let result = T::apply_operation(&client, operation, &mut cx).await;
+            executor.finish_operation(operation_id);
+            let outcome = match result {
+                Ok(()) => TraceOutcome::Applied,
                 Err(TestError::Inapplicable) => {
                     applied.store(false, SeqCst);
                     log::info!("skipped operation");
+                    TraceOutcome::Skipped
                 }
                 Err(TestError::Other(error)) => {
                     log::error!("{} error: {}", client.username, error);
+                    TraceOutcome::Failed(error.to_string())
                 }
+            };
+            if let Some(traced_operation) = traced_operation {
+                traced_operation.finish(outcome, &executor);
             }
             cx.executor().simulate_random_delay().await;
         }
@@ -657,6 +1033,79 @@ impl UserTestPlan {
     }
 }
 
+/// Returns the peer id of the given client's connection, if the server knows about it.
+fn connected_peer_id(server: &TestServer, client: &TestClient) -> Option<PeerId> {
+    let peer_id = client.peer_id()?;
+    server
+        .connection_pool
+        .lock()
+        .user_connection_ids(UserId::from_proto(client.id()))
+        .any(|connection_id| PeerId::from(connection_id) == peer_id)
+        .then_some(peer_id)
+}
+
+/// The room each client is in, along with who it sees there.
+fn summarize_calls(
+    clients: &mut [(Rc<TestClient>, TestAppContext)],
+) -> Vec<Option<(u64, RoomParticipants)>> {
+    clients
+        .iter_mut()
+        .map(|(_, cx)| {
+            let room = cx
+                .read(ActiveCall::global)
+                .read_with(cx, |call, _| call.room().cloned());
+            room.map(|room| {
+                (
+                    room.read_with(cx, |room, _| room.id()),
+                    room_participants(&room, cx),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Asserts that the clients connected as the same user agree on the state that the
+/// server sends to every connection of a user.
+fn assert_clients_of_each_user_converged(clients: &[(Rc<TestClient>, TestAppContext)]) {
+    let connected_clients = clients
+        .iter()
+        .filter(|(client, _)| matches!(*client.status().borrow(), client::Status::Connected { .. }))
+        .collect::<Vec<_>>();
+    for (ix, (client_a, cx_a)) in connected_clients.iter().enumerate() {
+        let user_id = client_a.current_user_id(cx_a);
+        for (client_b, cx_b) in &connected_clients[ix + 1..] {
+            if client_b.current_user_id(cx_b) != user_id {
+                continue;
+            }
+
+            assert_eq!(
+                client_a.summarize_contacts(cx_a),
+                client_b.summarize_contacts(cx_b),
+                "{}'s clients disagree on their contacts",
+                client_a.username
+            );
+
+            let channels_a = client_a.channel_store().read_with(cx_a, |store, _| {
+                store
+                    .ordered_channels()
+                    .map(|(depth, channel)| (depth, channel.name.clone()))
+                    .collect::<Vec<_>>()
+            });
+            let channels_b = client_b.channel_store().read_with(cx_b, |store, _| {
+                store
+                    .ordered_channels()
+                    .map(|(depth, channel)| (depth, channel.name.clone()))
+                    .collect::<Vec<_>>()
+            });
+            assert_eq!(
+                channels_a, channels_b,
+                "{}'s clients disagree on their channels",
+                client_a.username
+            );
+        }
+    }
+}
+
 impl From<anyhow::Error> for TestError {
     fn from(value: anyhow::Error) -> Self {
         Self::Other(value)
@@ -0,0 +1,455 @@
+//! A small DSL for scripted collaboration tests.
+//!
+//! A [`Scenario`] is a list of steps ("a shares, b joins, the server restarts, b edits")
+//! that are applied in order against a [`TestServer`] and one [`TestClient`] per named
+//! user, running the executor until parked after every step. Each user shares at most
+//! one project, rooted at `/<user>`, so paths are written as `"<host>/<path>"`.
+//!
+//! Scenarios can be built in code or loaded from JSON via [`Scenario::from_json`].
+
+use super::{TestClient, TestServer};
+use crate::rpc::{RECONNECT_TIMEOUT, REJOIN_WINDOW};
+use call::ActiveCall;
+use collections::HashMap;
+use fs::Fs as _;
+use gpui::{BackgroundExecutor, Model, TestAppContext};
+use language::Buffer;
+use pretty_assertions::assert_eq;
+use project::{Project, ProjectPath};
+use rpc::RECEIVE_TIMEOUT;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use workspace::SplitDirection;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Scenario {
+    users: Vec<String>,
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum Step {
+    ShareProject {
+        host: String,
+        files: serde_json::Value,
+    },
+    JoinProject {
+        guest: String,
+        host: String,
+    },
+    Edit {
+        user: String,
+        path: String,
+        offset: usize,
+        text: String,
+    },
+    Save {
+        user: String,
+        path: String,
+    },
+    OpenInWorkspace {
+        user: String,
+        path: String,
+    },
+    SplitPane {
+        user: String,
+        host: String,
+    },
+    Follow {
+        follower: String,
+        leader: String,
+    },
+    DisconnectClient {
+        user: String,
+    },
+    RestartServer,
+    DrainServer,
+    AssertText {
+        path: String,
+        text: String,
+    },
+    AssertSavedOnDisk {
+        path: String,
+        text: String,
+    },
+    AssertActivePath {
+        user: String,
+        path: String,
+    },
+}
+
+struct ScenarioState {
+    server: TestServer,
+    clients: Vec<TestClient>,
+    projects: HashMap<(usize, String), Model<Project>>,
+    project_ids: HashMap<String, u64>,
+}
+
+impl Scenario {
+    /// Creates a scenario for the given users, who will all be placed in the same call.
+    pub fn new<'a>(users: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            users: users.into_iter().map(ToString::to_string).collect(),
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    pub fn share_project(mut self, host: &str, files: serde_json::Value) -> Self {
+        self.steps.push(Step::ShareProject {
+            host: host.into(),
+            files,
+        });
+        self
+    }
+
+    pub fn join_project(mut self, guest: &str, host: &str) -> Self {
+        self.steps.push(Step::JoinProject {
+            guest: guest.into(),
+            host: host.into(),
+        });
+        self
+    }
+
+    pub fn edit(mut self, user: &str, path: &str, offset: usize, text: &str) -> Self {
+        self.steps.push(Step::Edit {
+            user: user.into(),
+            path: path.into(),
+            offset,
+            text: text.into(),
+        });
+        self
+    }
+
+    pub fn save(mut self, user: &str, path: &str) -> Self {
+        self.steps.push(Step::Save {
+            user: user.into(),
+            path: path.into(),
+        });
+        self
+    }
+
+    /// Opens the path in the user's workspace for the project it belongs to.
+    pub fn open_in_workspace(mut self, user: &str, path: &str) -> Self {
+        self.steps.push(Step::OpenInWorkspace {
+            user: user.into(),
+            path: path.into(),
+        });
+        self
+    }
+
+    pub fn split_pane(mut self, user: &str, host: &str) -> Self {
+        self.steps.push(Step::SplitPane {
+            user: user.into(),
+            host: host.into(),
+        });
+        self
+    }
+
+    /// Makes the follower follow the leader in the first project they both have open.
+    pub fn follow(mut self, follower: &str, leader: &str) -> Self {
+        self.steps.push(Step::Follow {
+            follower: follower.into(),
+            leader: leader.into(),
+        });
+        self
+    }
+
+    pub fn disconnect_client(mut self, user: &str) -> Self {
+        self.steps
+            .push(Step::DisconnectClient { user: user.into() });
+        self
+    }
+
+    /// Restarts the server without warning its clients, as when it crashes.
+    pub fn restart_server(mut self) -> Self {
+        self.steps.push(Step::RestartServer);
+        self
+    }
+
+    /// Restarts the server after telling its clients, so they can resume their rooms.
+    pub fn drain_server(mut self) -> Self {
+        self.steps.push(Step::DrainServer);
+        self
+    }
+
+    /// Asserts that every user with the project open sees the given buffer text.
+    pub fn assert_text(mut self, path: &str, text: &str) -> Self {
+        self.steps.push(Step::AssertText {
+            path: path.into(),
+            text: text.into(),
+        });
+        self
+    }
+
+    /// Asserts that the host's file system contains the given text at the path.
+    pub fn assert_saved_on_disk(mut self, path: &str, text: &str) -> Self {
+        self.steps.push(Step::AssertSavedOnDisk {
+            path: path.into(),
+            text: text.into(),
+        });
+        self
+    }
+
+    /// Asserts that the active item in the user's workspace for the path's project is the path.
+    pub fn assert_active_path(mut self, user: &str, path: &str) -> Self {
+        self.steps.push(Step::AssertActivePath {
+            user: user.into(),
+            path: path.into(),
+        });
+        self
+    }
+
+    /// Runs the scenario, using one context per user, in the order the users were given.
+    pub async fn run(self, executor: BackgroundExecutor, cxs: &mut [&mut TestAppContext]) {
+        assert_eq!(
+            self.users.len(),
+            cxs.len(),
+            "scenario needs one context per user"
+        );
+
+        let mut server = TestServer::start(executor.clone()).await;
+        let mut clients = Vec::new();
+        for (user, cx) in self.users.iter().zip(cxs.iter_mut()) {
+            clients.push(server.create_client(cx, user).await);
+        }
+        server
+            .create_room(
+                &mut clients
+                    .iter()
+                    .zip(cxs.iter_mut())
+                    .map(|(client, cx)| (client, &mut **cx))
+                    .collect::<Vec<_>>(),
+            )
+            .await;
+
+        let mut state = ScenarioState {
+            server,
+            clients,
+            projects: HashMap::default(),
+            project_ids: HashMap::default(),
+        };
+        for step in self.steps {
+            log::info!("scenario step: {:?}", step);
+            state.apply(&self.users, step, &executor, cxs).await;
+            executor.run_until_parked();
+        }
+    }
+}
+
+impl ScenarioState {
+    async fn apply(
+        &mut self,
+        users: &[String],
+        step: Step,
+        executor: &BackgroundExecutor,
+        cxs: &mut [&mut TestAppContext],
+    ) {
+        let user_ix = |user: &str| {
+            users
+                .iter()
+                .position(|name| name == user)
+                .unwrap_or_else(|| panic!("unknown user {user}"))
+        };
+
+        match step {
+            Step::ShareProject { host, files } => {
+                let ix = user_ix(&host);
+                let cx = &mut *cxs[ix];
+                let client = &self.clients[ix];
+                let root = Path::new("/").join(&host);
+                client.fs().insert_tree(&root, files).await;
+                let (project, _) = client.build_local_project(&root, cx).await;
+                let project_id = cx
+                    .read(ActiveCall::global)
+                    .update(cx, |call, cx| call.share_project(project.clone(), cx))
+                    .await
+                    .unwrap();
+                self.project_ids.insert(host.clone(), project_id);
+                self.projects.insert((ix, host), project);
+            }
+            Step::JoinProject { guest, host } => {
+                let ix = user_ix(&guest);
+                let project_id = *self
+                    .project_ids
+                    .get(&host)
+                    .unwrap_or_else(|| panic!("{host} has not shared a project"));
+                let project = self.clients[ix]
+                    .build_remote_project(project_id, &mut *cxs[ix])
+                    .await;
+                self.projects.insert((ix, host), project);
+            }
+            Step::Edit {
+                user,
+                path,
+                offset,
+                text,
+            } => {
+                let ix = user_ix(&user);
+                let buffer = self.open_buffer(ix, &user, &path, &mut *cxs[ix]).await;
+                buffer.update(&mut *cxs[ix], |buffer, cx| {
+                    buffer.edit([(offset..offset, text)], None, cx)
+                });
+            }
+            Step::Save { user, path } => {
+                let ix = user_ix(&user);
+                let buffer = self.open_buffer(ix, &user, &path, &mut *cxs[ix]).await;
+                let project = self.project(ix, &user, &path);
+                project
+                    .update(&mut *cxs[ix], |project, cx| project.save_buffer(buffer, cx))
+                    .await
+                    .unwrap();
+            }
+            Step::OpenInWorkspace { user, path } => {
+                let ix = user_ix(&user);
+                let project = self.project(ix, &user, &path);
+                let project_path = self.project_path(&project, &path, &*cxs[ix]);
+                let client = &self.clients[ix];
+                let window = client.workspace_for_project(&project, &mut *cxs[ix]);
+                client
+                    .open_path_in_workspace(window, project_path, &mut *cxs[ix])
+                    .await
+                    .unwrap();
+            }
+            Step::SplitPane { user, host } => {
+                let ix = user_ix(&user);
+                let project = self.project(ix, &user, &format!("{host}/"));
+                let client = &self.clients[ix];
+                let window = client.workspace_for_project(&project, &mut *cxs[ix]);
+                client.split_active_pane(window, SplitDirection::Right, &mut *cxs[ix]);
+            }
+            Step::Follow { follower, leader } => {
+                let follower_ix = user_ix(&follower);
+                let leader_ix = user_ix(&leader);
+                let mut shared_hosts = self
+                    .projects
+                    .keys()
+                    .filter(|(ix, host)| {
+                        *ix == follower_ix && self.projects.contains_key(&(leader_ix, host.clone()))
+                    })
+                    .map(|(_, host)| host.clone())
+                    .collect::<Vec<_>>();
+                shared_hosts.sort();
+                let host = shared_hosts
+                    .first()
+                    .unwrap_or_else(|| panic!("{follower} and {leader} share no project"));
+                let project = self.projects[&(follower_ix, host.clone())].clone();
+                let leader_id = self.clients[leader_ix].peer_id().unwrap();
+                let client = &self.clients[follower_ix];
+                let window = client.workspace_for_project(&project, &mut *cxs[follower_ix]);
+                client.follow(window, leader_id, &mut *cxs[follower_ix]);
+            }
+            Step::DisconnectClient { user } => {
+                let peer_id = self.clients[user_ix(&user)].peer_id().unwrap();
+                self.server.disconnect_client(peer_id);
+                executor.advance_clock(RECEIVE_TIMEOUT + RECONNECT_TIMEOUT);
+            }
+            Step::RestartServer => {
+                self.server.reset().await;
+                executor.advance_clock(RECEIVE_TIMEOUT);
+                self.server.start().await.unwrap();
+                executor.advance_clock(REJOIN_WINDOW);
+            }
+            Step::DrainServer => {
+                self.server.restart(executor).await;
+                executor.advance_clock(RECEIVE_TIMEOUT);
+                self.server.start().await.unwrap();
+                executor.advance_clock(REJOIN_WINDOW);
+            }
+            Step::AssertText { path, text } => {
+                let (host, _) = split_path(&path);
+                let ixs = self
+                    .projects
+                    .keys()
+                    .filter(|(_, project_host)| project_host == host)
+                    .map(|(ix, _)| *ix)
+                    .collect::<Vec<_>>();
+                assert!(!ixs.is_empty(), "nobody has {host}'s project open");
+                for ix in ixs {
+                    let buffer = self.open_buffer(ix, &users[ix], &path, &mut *cxs[ix]).await;
+                    assert_eq!(
+                        buffer.read_with(&*cxs[ix], |buffer, _| buffer.text()),
+                        text,
+                        "{} has unexpected text for {path}",
+                        users[ix]
+                    );
+                }
+            }
+            Step::AssertSavedOnDisk { path, text } => {
+                let (host, _) = split_path(&path);
+                let client = &self.clients[user_ix(host)];
+                assert_eq!(
+                    client.fs().load(&Path::new("/").join(&path)).await.unwrap(),
+                    text,
+                    "{host}'s disk has unexpected text for {path}"
+                );
+            }
+            Step::AssertActivePath { user, path } => {
+                let ix = user_ix(&user);
+                let project = self.project(ix, &user, &path);
+                let project_path = self.project_path(&project, &path, &*cxs[ix]);
+                let client = &self.clients[ix];
+                let window = client.workspace_for_project(&project, &mut *cxs[ix]);
+                assert_eq!(
+                    client.active_project_path(window, &mut *cxs[ix]),
+                    Some(project_path),
+                    "{user} has an unexpected active item"
+                );
+            }
+        }
+    }
+
+    fn project_path(
+        &self,
+        project: &Model<Project>,
+        path: &str,
+        cx: &TestAppContext,
+    ) -> ProjectPath {
+        let (_, relative_path) = split_path(path);
+        let worktree_id = project.read_with(cx, |project, cx| {
+            project.worktrees().next().unwrap().read(cx).id()
+        });
+        ProjectPath {
+            worktree_id,
+            path: Path::new(relative_path).into(),
+        }
+    }
+
+    fn project(&self, ix: usize, user: &str, path: &str) -> Model<Project> {
+        let (host, _) = split_path(path);
+        self.projects
+            .get(&(ix, host.to_string()))
+            .unwrap_or_else(|| panic!("{user} does not have {host}'s project open"))
+            .clone()
+    }
+
+    async fn open_buffer(
+        &self,
+        ix: usize,
+        user: &str,
+        path: &str,
+        cx: &mut TestAppContext,
+    ) -> Model<Buffer> {
+        let project = self.project(ix, user, path);
+        let project_path = self.project_path(&project, path, cx);
+        let buffer = project
+            .update(cx, |project, cx| project.open_buffer(project_path, cx))
+            .await
+            .unwrap();
+        self.clients[ix]
+            .buffers_for_project(&project)
+            .insert(buffer.clone());
+        buffer
+    }
+}
+
+/// Splits a scenario path into the host whose project it belongs to and the path
+/// relative to that project's root.
+fn split_path(path: &str) -> (&str, &str) {
+    path.split_once('/')
+        .unwrap_or_else(|| panic!("path {path:?} must start with the host's name"))
+}
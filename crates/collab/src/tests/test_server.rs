@@ -1,7 +1,12 @@
 use crate::{
-    db::{tests::TestDb, NewUserParams, UserId},
+    db::{tests::TestDb, DatabaseFaults, NewUserParams, UserId},
     executor::Executor,
-    rpc::{Server, CLEANUP_TIMEOUT, RECONNECT_TIMEOUT},
+    ice,
+    message_bus::InMemoryMessageBus,
+    rpc::{ConnectionStats, Server, CLEANUP_TIMEOUT, RECONNECT_TIMEOUT},
+    runtime_config::{RuntimeConfig, SharedRuntimeConfig},
+    tests::network_faults::{faulty_in_memory_connection, NetworkFaults},
+    webhooks::{FakeWebhookClient, Webhooks},
     AppState, Config,
 };
 use anyhow::anyhow;
@@ -9,18 +14,22 @@ use call::ActiveCall;
 use channel::{ChannelBuffer, ChannelStore};
 use client::{
     self, proto::PeerId, Client, Connection, Credentials, EstablishConnectionError, UserStore,
+    RESTART_RECONNECT_SPREAD,
 };
 use collab_ui::channel_view::ChannelView;
 use collections::{HashMap, HashSet};
 use fs::FakeFs;
 use futures::{channel::oneshot, StreamExt as _};
-use gpui::{BackgroundExecutor, Context, Model, Task, TestAppContext, View, VisualTestContext};
+use gpui::{
+    BackgroundExecutor, Context, Model, Task, TestAppContext, View, VisualTestContext, WindowHandle,
+};
 use language::LanguageRegistry;
 use node_runtime::FakeNodeRuntime;
 
 use notifications::NotificationStore;
 use parking_lot::Mutex;
-use project::{Project, WorktreeId};
+use project::{Project, ProjectEntryId, ProjectPath, WorktreeId};
+use rand::prelude::*;
 use rpc::{
     proto::{self, ChannelRole},
     RECEIVE_TIMEOUT,
@@ -32,21 +41,31 @@ use std::{
     env,
     ops::{Deref, DerefMut},
     path::Path,
+    rc::Rc,
     sync::{
         atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst},
         Arc,
     },
+    time::{Duration, SystemTime},
 };
+use text::BufferId;
 use util::http::FakeHttpClient;
-use workspace::{Workspace, WorkspaceStore};
+use workspace::{item::ItemHandle, Pane, SplitDirection, Workspace, WorkspaceStore};
 
 pub struct TestServer {
     pub app_state: Arc<AppState>,
     pub test_live_kit_server: Arc<live_kit_client::TestServer>,
+    pub test_webhook_client: Arc<FakeWebhookClient>,
     server: Arc<Server>,
+    /// The server that clients connect to, which is replaced by [`TestServer::deploy`].
+    active_server: Arc<Mutex<Arc<Server>>>,
     next_github_user_id: i32,
     connection_killers: Arc<Mutex<HashMap<PeerId, Arc<AtomicBool>>>>,
+    connection_suspenders: Arc<Mutex<HashMap<PeerId, Arc<AtomicBool>>>>,
+    system_sleep_durations: Arc<Mutex<HashMap<PeerId, Arc<Mutex<Duration>>>>>,
     forbid_connections: Arc<AtomicBool>,
+    network_faults: Arc<Mutex<Option<(NetworkFaults, StdRng)>>>,
+    connection_faults: Arc<Mutex<HashMap<PeerId, Arc<Mutex<NetworkFaults>>>>>,
     _test_db: TestDb,
 }
 
@@ -64,8 +83,29 @@ struct TestClientState {
     remote_projects: Vec<Model<Project>>,
     buffers: HashMap<Model<Project>, HashSet<Model<language::Buffer>>>,
     channel_buffers: HashSet<Model<ChannelBuffer>>,
+    detached_requests: Vec<(String, Task<anyhow::Result<()>>)>,
+    closed_remote_buffers: Vec<ClosedRemoteBuffer>,
+    deleted_worktree_entries: Vec<DeletedWorktreeEntry>,
+    workspaces: HashMap<Model<Project>, WindowHandle<Workspace>>,
+}
+
+/// A buffer in a remote project that was closed after the server acknowledged all of
+/// the guest's edits to it, along with the timestamp of the guest's last edit.
+pub struct ClosedRemoteBuffer {
+    pub project_id: u64,
+    pub buffer_id: BufferId,
+    pub last_edit: clock::Lamport,
+}
+
+/// An entry that was successfully deleted from a shared project, and which no
+/// collaborator on that project should still have.
+#[derive(Clone, Copy)]
+pub struct DeletedWorktreeEntry {
+    pub project_id: u64,
+    pub entry_id: ProjectEntryId,
 }
 
+#[derive(Debug, PartialEq)]
 pub struct ContactsSummary {
     pub current: Vec<String>,
     pub outgoing_requests: Vec<String>,
@@ -74,6 +114,27 @@ pub struct ContactsSummary {
 
 impl TestServer {
     pub async fn start(deterministic: BackgroundExecutor) -> Self {
+        Self::start_internal(deterministic, true, |_| {}).await
+    }
+
+    /// Starts a server that has no LiveKit, like most self-hosted ones.
+    pub async fn start_without_live_kit(deterministic: BackgroundExecutor) -> Self {
+        Self::start_internal(deterministic, false, |_| {}).await
+    }
+
+    /// Starts a server whose configuration differs from the defaults used in tests.
+    pub async fn start_with_config(
+        deterministic: BackgroundExecutor,
+        configure: impl FnOnce(&mut Config),
+    ) -> Self {
+        Self::start_internal(deterministic, true, configure).await
+    }
+
+    async fn start_internal(
+        deterministic: BackgroundExecutor,
+        use_live_kit: bool,
+        configure: impl FnOnce(&mut Config),
+    ) -> Self {
         static NEXT_LIVE_KIT_SERVER_ID: AtomicUsize = AtomicUsize::new(0);
 
         let use_postgres = env::var("USE_POSTGRES").ok();
@@ -91,7 +152,16 @@ impl TestServer {
             deterministic.clone(),
         )
         .unwrap();
-        let app_state = Self::build_app_state(&test_db, &live_kit_server).await;
+        let mut config = Self::test_config();
+        configure(&mut config);
+        let webhook_client = Arc::new(FakeWebhookClient::default());
+        let app_state = Self::build_app_state(
+            &test_db,
+            use_live_kit.then_some(&*live_kit_server),
+            webhook_client.clone(),
+            deterministic.clone(),
+            config,
+        );
         let epoch = app_state
             .db
             .create_server(&app_state.config.zed_environment)
@@ -107,12 +177,18 @@ impl TestServer {
         deterministic.advance_clock(CLEANUP_TIMEOUT);
         Self {
             app_state,
+            active_server: Arc::new(Mutex::new(server.clone())),
             server,
             connection_killers: Default::default(),
+            connection_suspenders: Default::default(),
+            system_sleep_durations: Default::default(),
             forbid_connections: Default::default(),
+            network_faults: Default::default(),
+            connection_faults: Default::default(),
             next_github_user_id: 0,
             _test_db: test_db,
             test_live_kit_server: live_kit_server,
+            test_webhook_client: webhook_client,
         }
     }
 
@@ -152,6 +228,38 @@ impl TestServer {
         self.server.reset(epoch);
     }
 
+    /// Resets the server the way a restart does, after telling its clients that it's
+    /// restarting and giving them time to disconnect. The clients can't reconnect until
+    /// the server is started again, at which point they rejoin their rooms using the
+    /// resume tokens they were handed.
+    pub async fn restart(&self, executor: &BackgroundExecutor) {
+        self.forbid_connections();
+        self.server.send_restarting(None).await;
+        executor.advance_clock(RESTART_RECONNECT_SPREAD);
+        self.reset().await;
+        self.allow_connections();
+    }
+
+    /// Starts another server alongside the current ones, as happens during a deploy.
+    /// Clients connect to the new server from now on, while existing connections stay
+    /// on the server they were made to until they're lost.
+    pub async fn deploy(&self, executor: &BackgroundExecutor) -> Arc<Server> {
+        let epoch = self
+            .app_state
+            .db
+            .create_server(&self.app_state.config.zed_environment)
+            .await
+            .unwrap();
+        let server = Server::new(
+            epoch,
+            self.app_state.clone(),
+            Executor::Deterministic(executor.clone()),
+        );
+        server.start().await.unwrap();
+        *self.active_server.lock() = server.clone();
+        server
+    }
+
     pub async fn create_client(&mut self, cx: &mut TestAppContext, name: &str) -> TestClient {
         cx.update(|cx| {
             if cx.has_global::<SettingsStore>() {
@@ -186,14 +294,27 @@ impl TestServer {
         };
         let client_name = name.to_string();
         let mut client = cx.update(|cx| Client::new(http.clone(), cx));
-        let server = self.server.clone();
+        let active_server = self.active_server.clone();
         let db = self.app_state.db.clone();
         let connection_killers = self.connection_killers.clone();
+        let connection_suspenders = self.connection_suspenders.clone();
+        let system_sleep_durations = self.system_sleep_durations.clone();
+        let slept = Arc::new(Mutex::new(Duration::ZERO));
         let forbid_connections = self.forbid_connections.clone();
+        let network_faults = self.network_faults.clone();
+        let connection_faults = self.connection_faults.clone();
 
         Arc::get_mut(&mut client)
             .unwrap()
             .set_id(user_id.to_proto())
+            .override_system_clock({
+                let slept = slept.clone();
+                move || {
+                    // The simulated wall clock only moves while the machine is asleep, which is
+                    // exactly the amount by which it runs ahead of the client's frozen timers.
+                    SystemTime::UNIX_EPOCH + *slept.lock()
+                }
+            })
             .override_authenticate(move |cx| {
                 cx.spawn(|_| async move {
                     let access_token = "the-token".to_string();
@@ -207,19 +328,44 @@ impl TestServer {
                 assert_eq!(credentials.user_id, user_id.0 as u64);
                 assert_eq!(credentials.access_token, "the-token");
 
-                let server = server.clone();
+                let server = active_server.lock().clone();
                 let db = db.clone();
                 let connection_killers = connection_killers.clone();
+                let connection_suspenders = connection_suspenders.clone();
+                let system_sleep_durations = system_sleep_durations.clone();
+                let slept = slept.clone();
                 let forbid_connections = forbid_connections.clone();
+                let network_faults = network_faults.clone();
+                let connection_faults = connection_faults.clone();
                 let client_name = client_name.clone();
                 cx.spawn(move |cx| async move {
                     if forbid_connections.load(SeqCst) {
                         Err(EstablishConnectionError::other(anyhow!(
                             "server is forbidding connections"
                         )))
+                    } else if let Some(limit) = server
+                        .reached_connection_quota(user_id)
+                        .await
+                        .expect("counting connections failed")
+                    {
+                        Err(EstablishConnectionError::TooManyConnections { limit })
                     } else {
-                        let (client_conn, server_conn, killed) =
-                            Connection::in_memory(cx.background_executor().clone());
+                        let mut faults = None;
+                        let (client_conn, server_conn, killed, suspended) =
+                            match network_faults.lock().as_mut() {
+                                Some((default_faults, rng)) => {
+                                    let connection_faults = Arc::new(Mutex::new(*default_faults));
+                                    faults = Some(connection_faults.clone());
+                                    faulty_in_memory_connection(
+                                        cx.background_executor().clone(),
+                                        connection_faults,
+                                        StdRng::seed_from_u64(rng.gen()),
+                                    )
+                                }
+                                None => Connection::in_memory_with_suspension(
+                                    cx.background_executor().clone(),
+                                ),
+                            };
                         let (connection_id_tx, connection_id_rx) = oneshot::channel();
                         let user = db
                             .get_user_by_id(user_id)
@@ -240,6 +386,17 @@ impl TestServer {
                         connection_killers
                             .lock()
                             .insert(connection_id.into(), killed);
+                        connection_suspenders
+                            .lock()
+                            .insert(connection_id.into(), suspended);
+                        system_sleep_durations
+                            .lock()
+                            .insert(connection_id.into(), slept);
+                        if let Some(faults) = faults {
+                            connection_faults
+                                .lock()
+                                .insert(connection_id.into(), faults);
+                        }
                         Ok(client_conn)
                     }
                 })
@@ -292,7 +449,39 @@ impl TestServer {
         client
     }
 
+    /// Injects the given faults into every connection that's established from now on.
+    /// Each connection draws its faults from its own RNG, seeded from `rng`.
+    pub fn inject_network_faults(&self, faults: NetworkFaults, rng: StdRng) {
+        *self.network_faults.lock() = Some((faults, rng));
+    }
+
+    /// Changes the faults injected into an existing connection, which must have
+    /// been established after [`TestServer::inject_network_faults`] was called.
+    pub fn set_network_faults(&self, peer_id: PeerId, faults: NetworkFaults) {
+        *self
+            .connection_faults
+            .lock()
+            .get(&peer_id)
+            .expect("connection has no fault injection")
+            .lock() = faults;
+    }
+
+    /// Makes the server's database transactions fail with the given probabilities,
+    /// drawing the faults from `rng`.
+    pub fn inject_database_faults(&self, faults: DatabaseFaults, rng: StdRng) {
+        self.app_state.db.inject_faults(faults, rng);
+    }
+
+    /// Changes how often the server's database transactions fail, without
+    /// reseeding the RNG given to [`TestServer::inject_database_faults`].
+    pub fn set_database_faults(&self, faults: DatabaseFaults) {
+        self.app_state.db.set_faults(faults);
+    }
+
     pub fn disconnect_client(&self, peer_id: PeerId) {
+        self.connection_suspenders.lock().remove(&peer_id);
+        self.system_sleep_durations.lock().remove(&peer_id);
+        self.connection_faults.lock().remove(&peer_id);
         self.connection_killers
             .lock()
             .remove(&peer_id)
@@ -300,6 +489,38 @@ impl TestServer {
             .store(true, SeqCst);
     }
 
+    /// Simulates the machine running the given client going to sleep for `duration`.
+    ///
+    /// While asleep, the connection silently stops delivering messages and none of the
+    /// client's timers fire, so the rest of the system keeps running without advancing
+    /// the clock. On wake-up the wall clock has jumped forward and the socket is dead,
+    /// and the client is only given a single resume check to notice and reconnect.
+    pub fn simulate_system_sleep(
+        &self,
+        peer_id: PeerId,
+        duration: Duration,
+        deterministic: BackgroundExecutor,
+    ) {
+        self.connection_suspenders
+            .lock()
+            .get(&peer_id)
+            .unwrap()
+            .store(true, SeqCst);
+        deterministic.run_until_parked();
+
+        // Every connection made by the same client shares its clock, so the sleep is
+        // recorded against the client that owns this connection rather than its user.
+        *self
+            .system_sleep_durations
+            .lock()
+            .get(&peer_id)
+            .unwrap()
+            .lock() += duration;
+        self.disconnect_client(peer_id);
+        deterministic.advance_clock(client::SYSTEM_RESUME_CHECK_INTERVAL);
+        deterministic.run_until_parked();
+    }
+
     pub fn simulate_long_connection_interruption(
         &self,
         peer_id: PeerId,
@@ -313,6 +534,122 @@ impl TestServer {
         deterministic.run_until_parked();
     }
 
+    /// Asserts that a peer that has been fully disconnected left nothing behind: no
+    /// database records refer to its connection, and none of the remaining clients
+    /// still consider it a participant, collaborator, leader or follower. The removed
+    /// user may still take part through its other clients, if any remain.
+    pub async fn assert_peer_purged(
+        &self,
+        removed_peer_id: PeerId,
+        removed_user_id: UserId,
+        clients: &[(Rc<TestClient>, TestAppContext)],
+    ) {
+        assert!(
+            !self
+                .connection_pool
+                .lock()
+                .user_connection_ids(removed_user_id)
+                .any(|connection_id| PeerId::from(connection_id) == removed_peer_id),
+            "connection pool still contains removed peer {:?}",
+            removed_peer_id
+        );
+        let resources = self
+            .app_state
+            .db
+            .resources_for_connection(removed_peer_id.into())
+            .await
+            .unwrap();
+        assert_eq!(
+            resources,
+            Vec::<String>::new(),
+            "database still refers to removed peer {:?}",
+            removed_peer_id
+        );
+
+        let removed_user_has_clients = clients
+            .iter()
+            .any(|(client, cx)| client.current_user_id(cx) == removed_user_id);
+        for (client, cx) in clients {
+            let room = cx
+                .read(ActiveCall::global)
+                .read_with(cx, |call, _| call.room().cloned());
+            if let Some(room) = room {
+                room.read_with(cx, |room, _| {
+                    let leader_ids = room
+                        .remote_participants()
+                        .values()
+                        .map(|participant| participant.peer_id)
+                        .chain(client.peer_id())
+                        .chain([removed_peer_id])
+                        .collect::<Vec<_>>();
+                    let project_ids = room
+                        .remote_participants()
+                        .values()
+                        .flat_map(|participant| &participant.projects)
+                        .map(|project| project.id)
+                        .collect::<Vec<_>>();
+                    for participant in room.remote_participants().values() {
+                        assert_ne!(
+                            participant.peer_id, removed_peer_id,
+                            "{} still has removed peer as a participant",
+                            client.username
+                        );
+                        if !removed_user_has_clients {
+                            assert_ne!(
+                                participant.user.id,
+                                removed_user_id.to_proto(),
+                                "{} still has removed user as a participant",
+                                client.username
+                            );
+                        }
+                    }
+                    for leader_id in &leader_ids {
+                        for project_id in &project_ids {
+                            let followers = room.followers_for(*leader_id, *project_id);
+                            assert!(
+                                *leader_id != removed_peer_id || followers.is_empty(),
+                                "{} still has followers of removed peer",
+                                client.username
+                            );
+                            assert!(
+                                !followers.contains(&removed_peer_id),
+                                "{} still has removed peer as a follower",
+                                client.username
+                            );
+                        }
+                    }
+                });
+            }
+
+            for project in client
+                .local_projects()
+                .iter()
+                .chain(client.remote_projects().iter())
+            {
+                project.read_with(cx, |project, _| {
+                    assert!(
+                        !project.collaborators().contains_key(&removed_peer_id),
+                        "{} still has removed peer as a collaborator in project {:?}",
+                        client.username,
+                        project.remote_id()
+                    );
+                });
+            }
+
+            for channel_buffer in client.channel_buffers().iter() {
+                channel_buffer.read_with(cx, |channel_buffer, _| {
+                    assert!(
+                        !channel_buffer
+                            .collaborators()
+                            .contains_key(&removed_peer_id),
+                        "{} still has removed peer as a channel buffer collaborator",
+                        client.username
+                    );
+                });
+            }
+        }
+    }
+
     pub fn forbid_connections(&self) {
         self.forbid_connections.store(true, SeqCst);
     }
@@ -321,6 +658,16 @@ impl TestServer {
         self.forbid_connections.store(false, SeqCst);
     }
 
+    pub fn user_connection_count(&self, user_id: UserId) -> usize {
+        self.connection_pool.lock().user_connection_count(user_id)
+    }
+
+    pub async fn user_connection_stats(&self, user_id: UserId) -> Vec<ConnectionStats> {
+        let mut stats = self.connection_stats().await.unwrap();
+        stats.retain(|connection| connection.user_id == user_id);
+        stats
+    }
+
     pub async fn make_contacts(&self, clients: &mut [(&TestClient, &mut TestAppContext)]) {
         for ix in 1..clients.len() {
             let (left, right) = clients.split_at_mut(ix);
@@ -472,28 +819,80 @@ impl TestServer {
         }
     }
 
-    pub async fn build_app_state(
+    pub fn build_app_state(
         test_db: &TestDb,
-        fake_server: &live_kit_client::TestServer,
+        fake_server: Option<&live_kit_client::TestServer>,
+        webhook_client: Arc<FakeWebhookClient>,
+        deterministic: BackgroundExecutor,
+        config: Config,
     ) -> Arc<AppState> {
+        let webhooks = Webhooks::new(
+            &config,
+            webhook_client,
+            Executor::Deterministic(deterministic),
+        )
+        .unwrap();
         Arc::new(AppState {
             db: test_db.db().clone(),
-            live_kit_client: Some(Arc::new(fake_server.create_api_client())),
-            config: Config {
-                http_port: 0,
-                database_url: "".into(),
-                database_max_connections: 0,
-                api_token: "".into(),
-                invite_link_prefix: "".into(),
-                live_kit_server: None,
-                live_kit_key: None,
-                live_kit_secret: None,
-                rust_log: None,
-                log_json: None,
-                zed_environment: "test".into(),
-            },
+            live_kit_client: fake_server.map(|fake_server| {
+                Arc::new(fake_server.create_api_client()) as Arc<dyn live_kit_server::api::Client>
+            }),
+            ice_config: None,
+            ice_servers_key: ice::IceServersKey::from_config(&config)
+                .unwrap()
+                .map(Arc::new),
+            min_client_version: config.min_client_version().unwrap(),
+            runtime_config: SharedRuntimeConfig::new(RuntimeConfig::new(&config, &[]).unwrap()),
+            message_bus: Arc::new(InMemoryMessageBus::default()),
+            webhooks: Arc::new(webhooks),
+            config,
         })
     }
+
+    pub fn test_config() -> Config {
+        Config {
+            http_port: 0,
+            database_url: "".into(),
+            database_max_connections: 0,
+            api_token: "".into(),
+            invite_link_prefix: "".into(),
+            live_kit_server: None,
+            live_kit_key: None,
+            live_kit_secret: None,
+            rust_log: None,
+            log_json: None,
+            zed_environment: "test".into(),
+            min_client_version: None,
+            release_notes_url: None,
+            send_backlog_shed_threshold: None,
+            send_backlog_lagging_threshold: None,
+            send_backlog_disconnect_threshold: None,
+            ice_servers: None,
+            ice_force_relay: None,
+            turn_credential_ttl_secs: None,
+            ice_servers_key: Some(base64::encode([0; 32])),
+            lazy_worktree_entry_threshold: None,
+            avatar_url_prefix: Some("http://collab.test/avatars".into()),
+            rate_limits: None,
+            orphan_sweep_interval_secs: None,
+            reconnect_timeout_secs: None,
+            cleanup_timeout_secs: None,
+            feature_flags: None,
+            overload_event_loop_lag_ms: None,
+            overload_db_latency_ms: None,
+            reports_to_throttle_invites: None,
+            reports_to_block_public_joins: None,
+            contact_requests_per_hour: None,
+            contact_requests_to_strangers_per_hour: None,
+            max_connections_per_user: None,
+            max_shared_projects_per_room: None,
+            max_room_participants: None,
+            webhooks: None,
+            reconnect_hosts: None,
+            collation_locale: None,
+            trusted_proxies: None,
+        }
+    }
 }
 
 impl Deref for TestServer {
@@ -507,6 +906,7 @@ impl Deref for TestServer {
 impl Drop for TestServer {
     fn drop(&mut self) {
         self.server.teardown();
+        self.active_server.lock().teardown();
         self.test_live_kit_server.teardown().unwrap();
     }
 }
@@ -592,6 +992,32 @@ impl TestClient {
         })
     }
 
+    /// Requests that were started without being awaited, along with a description
+    /// of each, so tests can check that they eventually resolve.
+    pub fn detached_requests<'a>(
+        &'a self,
+    ) -> impl DerefMut<Target = Vec<(String, Task<anyhow::Result<()>>)>> + 'a {
+        RefMut::map(self.state.borrow_mut(), |state| {
+            &mut state.detached_requests
+        })
+    }
+
+    pub fn closed_remote_buffers<'a>(
+        &'a self,
+    ) -> impl DerefMut<Target = Vec<ClosedRemoteBuffer>> + 'a {
+        RefMut::map(self.state.borrow_mut(), |state| {
+            &mut state.closed_remote_buffers
+        })
+    }
+
+    pub fn deleted_worktree_entries<'a>(
+        &'a self,
+    ) -> impl DerefMut<Target = Vec<DeletedWorktreeEntry>> + 'a {
+        RefMut::map(self.state.borrow_mut(), |state| {
+            &mut state.deleted_worktree_entries
+        })
+    }
+
     pub fn buffers<'a>(
         &'a self,
     ) -> impl DerefMut<Target = HashMap<Model<Project>, HashSet<Model<language::Buffer>>>> + 'a
@@ -748,6 +1174,99 @@ impl TestClient {
         })
     }
 
+    /// Returns a headless workspace for the given project, building it the first time.
+    ///
+    /// Unlike [`Self::build_workspace`], the workspace isn't tied to a borrowed context, so
+    /// it can be retrieved again by randomized or scripted tests.
+    pub fn workspace_for_project(
+        &self,
+        project: &Model<Project>,
+        cx: &mut TestAppContext,
+    ) -> WindowHandle<Workspace> {
+        if let Some(window) = self.state.borrow().workspaces.get(project) {
+            return *window;
+        }
+
+        let window = cx.add_window(|cx| {
+            cx.activate_window();
+            Workspace::new(0, project.clone(), self.app_state.clone(), cx)
+        });
+        self.state
+            .borrow_mut()
+            .workspaces
+            .insert(project.clone(), window);
+        window
+    }
+
+    pub fn workspaces<'a>(
+        &'a self,
+    ) -> impl DerefMut<Target = HashMap<Model<Project>, WindowHandle<Workspace>>> + 'a {
+        RefMut::map(self.state.borrow_mut(), |state| &mut state.workspaces)
+    }
+
+    pub async fn open_path_in_workspace(
+        &self,
+        window: WindowHandle<Workspace>,
+        path: impl Into<ProjectPath>,
+        cx: &mut TestAppContext,
+    ) -> anyhow::Result<Box<dyn ItemHandle>> {
+        let path = path.into();
+        window
+            .update(cx, |workspace, cx| {
+                workspace.open_path(path, None, true, cx)
+            })?
+            .await
+    }
+
+    pub fn split_active_pane(
+        &self,
+        window: WindowHandle<Workspace>,
+        direction: SplitDirection,
+        cx: &mut TestAppContext,
+    ) -> View<Pane> {
+        window
+            .update(cx, |workspace, cx| {
+                let pane = workspace.active_pane().clone();
+                workspace.split_pane(pane, direction, cx)
+            })
+            .unwrap()
+    }
+
+    pub fn follow(
+        &self,
+        window: WindowHandle<Workspace>,
+        leader_id: PeerId,
+        cx: &mut TestAppContext,
+    ) {
+        window
+            .update(cx, |workspace, cx| workspace.follow(leader_id, cx))
+            .unwrap();
+    }
+
+    pub fn leader_for_active_pane(
+        &self,
+        window: WindowHandle<Workspace>,
+        cx: &mut TestAppContext,
+    ) -> Option<PeerId> {
+        window
+            .update(cx, |workspace, _| {
+                workspace.leader_for_pane(workspace.active_pane())
+            })
+            .unwrap()
+    }
+
+    pub fn active_project_path(
+        &self,
+        window: WindowHandle<Workspace>,
+        cx: &mut TestAppContext,
+    ) -> Option<ProjectPath> {
+        window
+            .update(cx, |workspace, cx| {
+                workspace.active_item(cx)?.project_path(cx)
+            })
+            .unwrap()
+    }
+
     pub fn active_workspace<'a>(
         &'a self,
         cx: &'a mut TestAppContext,
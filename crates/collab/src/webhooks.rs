@@ -0,0 +1,452 @@
+use crate::{
+    db::{ChannelId, RoomId, UserId},
+    executor::Executor,
+    Config, Result,
+};
+use anyhow::Context as _;
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Arc,
+    },
+    time::Duration,
+};
+use time::OffsetDateTime;
+
+/// How many times each event is posted to an endpoint before it's dropped.
+pub const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+
+/// How long to wait before posting an event to an endpoint again, which doubles with
+/// each attempt.
+pub const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// How many events can be waiting to be delivered to an endpoint, including the ones
+/// being retried, before new ones are dropped.
+pub const MAX_PENDING_DELIVERIES: usize = 1000;
+
+/// How long an endpoint has to respond before the attempt counts as failed.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The header holding the hex-encoded HMAC-SHA256 of the body, keyed with the
+/// endpoint's secret and prefixed with `sha256=`.
+pub const SIGNATURE_HEADER: &str = "x-zed-signature";
+
+/// The header holding the name of the event, which is also in the body.
+pub const EVENT_HEADER: &str = "x-zed-event";
+
+/// The header holding an id that's the same for every attempt to deliver an event,
+/// so that endpoints can tell retries apart from new events.
+pub const DELIVERY_HEADER: &str = "x-zed-delivery";
+
+/// A URL that's sent room lifecycle events, as configured in the `WEBHOOKS` JSON
+/// array.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    /// The key that payloads sent to the endpoint are signed with.
+    pub secret: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    RoomCreated {
+        room_id: RoomId,
+        channel_id: Option<ChannelId>,
+    },
+    RoomEnded {
+        room_id: RoomId,
+        channel_id: Option<ChannelId>,
+    },
+    UserJoined {
+        room_id: RoomId,
+        channel_id: Option<ChannelId>,
+        user_id: UserId,
+    },
+    UserLeft {
+        room_id: RoomId,
+        channel_id: Option<ChannelId>,
+        user_id: UserId,
+    },
+}
+
+impl WebhookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::RoomCreated { .. } => "room_created",
+            Self::RoomEnded { .. } => "room_ended",
+            Self::UserJoined { .. } => "user_joined",
+            Self::UserLeft { .. } => "user_left",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+    environment: &'a str,
+    /// When the event happened, in seconds since the Unix epoch. Events can arrive out
+    /// of order when some of them had to be retried.
+    timestamp: i64,
+}
+
+/// Sends the requests that deliver events to endpoints.
+#[async_trait]
+pub trait WebhookClient: Send + Sync {
+    /// POSTs the JSON body to the URL, failing unless the endpoint responds with a
+    /// success status.
+    async fn post(
+        &self,
+        url: &str,
+        headers: &[(&'static str, String)],
+        body: Vec<u8>,
+    ) -> Result<()>;
+}
+
+pub struct HttpWebhookClient(reqwest::Client);
+
+impl HttpWebhookClient {
+    pub fn new() -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .context("failed to build webhook client")?;
+        Ok(Self(client))
+    }
+}
+
+#[async_trait]
+impl WebhookClient for HttpWebhookClient {
+    async fn post(
+        &self,
+        url: &str,
+        headers: &[(&'static str, String)],
+        body: Vec<u8>,
+    ) -> Result<()> {
+        let mut request = self
+            .0
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body);
+        for (name, value) in headers {
+            request = request.header(*name, value);
+        }
+        request
+            .send()
+            .await
+            .context("failed to send webhook")?
+            .error_for_status()
+            .context("webhook endpoint responded with an error")?;
+        Ok(())
+    }
+}
+
+/// Posts room lifecycle events to the endpoints configured for the deployment, in the
+/// background. Each event is delivered on its own, retrying with exponential backoff
+/// when it fails, so an event that fails doesn't hold back the ones after it. Events
+/// are dropped while an endpoint already has [`MAX_PENDING_DELIVERIES`] of them
+/// pending, so one that's down can't make the server run out of memory.
+pub struct Webhooks {
+    endpoints: Vec<Arc<Endpoint>>,
+    client: Arc<dyn WebhookClient>,
+    executor: Executor,
+    environment: Arc<str>,
+}
+
+struct Endpoint {
+    url: String,
+    secret: String,
+    pending_deliveries: AtomicUsize,
+}
+
+struct Delivery {
+    id: String,
+    event_name: &'static str,
+    body: Vec<u8>,
+}
+
+impl Webhooks {
+    pub fn new(
+        config: &Config,
+        client: Arc<dyn WebhookClient>,
+        executor: Executor,
+    ) -> Result<Self> {
+        let endpoints: Vec<WebhookEndpoint> = match config.webhooks.as_deref() {
+            Some(endpoints) => serde_json::from_str(endpoints).context("invalid WEBHOOKS")?,
+            None => Vec::new(),
+        };
+        let endpoints = endpoints
+            .into_iter()
+            .map(|endpoint| {
+                Arc::new(Endpoint {
+                    url: endpoint.url,
+                    secret: endpoint.secret,
+                    pending_deliveries: AtomicUsize::new(0),
+                })
+            })
+            .collect();
+        Ok(Self {
+            endpoints,
+            client,
+            executor,
+            environment: config.zed_environment.clone(),
+        })
+    }
+
+    pub fn send(&self, event: WebhookEvent) {
+        if self.endpoints.is_empty() {
+            return;
+        }
+
+        let payload = WebhookPayload {
+            event: &event,
+            environment: &self.environment,
+            timestamp: OffsetDateTime::now_utc().unix_timestamp(),
+        };
+        let body = match serde_json::to_vec(&payload) {
+            Ok(body) => body,
+            Err(error) => {
+                tracing::error!(?error, "failed to serialize webhook");
+                return;
+            }
+        };
+        let id = nanoid::nanoid!();
+        for endpoint in &self.endpoints {
+            let pending_deliveries = endpoint.pending_deliveries.fetch_add(1, SeqCst);
+            if pending_deliveries >= MAX_PENDING_DELIVERIES {
+                endpoint.pending_deliveries.fetch_sub(1, SeqCst);
+                tracing::error!(
+                    url = %endpoint.url,
+                    event = event.name(),
+                    "too many webhooks pending, dropping"
+                );
+                continue;
+            }
+            self.executor.spawn_detached(deliver(
+                endpoint.clone(),
+                Delivery {
+                    id: id.clone(),
+                    event_name: event.name(),
+                    body: body.clone(),
+                },
+                self.client.clone(),
+                self.executor.clone(),
+            ));
+        }
+    }
+}
+
+async fn deliver(
+    endpoint: Arc<Endpoint>,
+    delivery: Delivery,
+    client: Arc<dyn WebhookClient>,
+    executor: Executor,
+) {
+    let headers = [
+        (EVENT_HEADER, delivery.event_name.to_string()),
+        (DELIVERY_HEADER, delivery.id),
+        (SIGNATURE_HEADER, sign(&endpoint.secret, &delivery.body)),
+    ];
+    let url = &endpoint.url;
+    let mut retry_delay = INITIAL_RETRY_DELAY;
+    for attempt in 1..=MAX_DELIVERY_ATTEMPTS {
+        match client.post(url, &headers, delivery.body.clone()).await {
+            Ok(()) => break,
+            Err(error) if attempt < MAX_DELIVERY_ATTEMPTS => {
+                tracing::warn!(%url, attempt, ?error, "failed to deliver webhook, retrying");
+                executor.sleep(retry_delay).await;
+                retry_delay *= 2;
+            }
+            Err(error) => {
+                tracing::error!(%url, attempt, ?error, "failed to deliver webhook, giving up");
+            }
+        }
+    }
+    endpoint.pending_deliveries.fetch_sub(1, SeqCst);
+}
+
+/// Signs the body the way endpoints can check it with nothing but their secret.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+/// Records the events posted to it, failing as many times as it's told to first.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeWebhookClient {
+    state: parking_lot::Mutex<FakeWebhookClientState>,
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct FakeWebhookClientState {
+    failures_remaining: usize,
+    attempt_count: usize,
+    deliveries: Vec<(String, serde_json::Value)>,
+}
+
+#[cfg(test)]
+impl FakeWebhookClient {
+    pub fn fail_next_attempts(&self, count: usize) {
+        self.state.lock().failures_remaining = count;
+    }
+
+    pub fn attempt_count(&self) -> usize {
+        self.state.lock().attempt_count
+    }
+
+    /// Takes the URLs and bodies of the events delivered so far.
+    pub fn take_deliveries(&self) -> Vec<(String, serde_json::Value)> {
+        std::mem::take(&mut self.state.lock().deliveries)
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl WebhookClient for FakeWebhookClient {
+    async fn post(
+        &self,
+        url: &str,
+        headers: &[(&'static str, String)],
+        body: Vec<u8>,
+    ) -> Result<()> {
+        let mut state = self.state.lock();
+        state.attempt_count += 1;
+        if state.failures_remaining > 0 {
+            state.failures_remaining -= 1;
+            Err(anyhow::anyhow!("endpoint unavailable"))?;
+        }
+        assert!(headers.iter().any(|(name, _)| *name == SIGNATURE_HEADER));
+        state
+            .deliveries
+            .push((url.to_string(), serde_json::from_slice(&body)?));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TestServer;
+    use gpui::BackgroundExecutor;
+
+    #[test]
+    fn test_sign() {
+        // The second test case of RFC 4231.
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_webhook_retries(executor: BackgroundExecutor) {
+        let mut config = TestServer::test_config();
+        config.webhooks = Some(r#"[{"url": "https://hooks.example.com", "secret": "s"}]"#.into());
+        let client = Arc::new(FakeWebhookClient::default());
+        let webhooks = Webhooks::new(
+            &config,
+            client.clone(),
+            Executor::Deterministic(executor.clone()),
+        )
+        .unwrap();
+
+        // Failed deliveries are retried after 1, 2 and 4 seconds.
+        client.fail_next_attempts(3);
+        webhooks.send(WebhookEvent::RoomCreated {
+            room_id: RoomId(1),
+            channel_id: None,
+        });
+        executor.run_until_parked();
+        assert_eq!(client.attempt_count(), 1);
+        executor.advance_clock(INITIAL_RETRY_DELAY);
+        assert_eq!(client.attempt_count(), 2);
+        executor.advance_clock(INITIAL_RETRY_DELAY * 2);
+        assert_eq!(client.attempt_count(), 3);
+        assert!(client.take_deliveries().is_empty());
+        executor.advance_clock(INITIAL_RETRY_DELAY * 4);
+        assert_eq!(client.attempt_count(), 4);
+        let deliveries = client.take_deliveries();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].0, "https://hooks.example.com");
+        assert_eq!(deliveries[0].1["event"], "room_created");
+        assert_eq!(deliveries[0].1["room_id"], 1);
+        assert_eq!(deliveries[0].1["environment"], "test");
+
+        // Events are dropped once every attempt has failed.
+        client.fail_next_attempts(MAX_DELIVERY_ATTEMPTS as usize);
+        webhooks.send(WebhookEvent::RoomEnded {
+            room_id: RoomId(1),
+            channel_id: None,
+        });
+        executor.advance_clock(INITIAL_RETRY_DELAY * 100);
+        assert_eq!(client.attempt_count(), 4 + MAX_DELIVERY_ATTEMPTS as usize);
+        assert!(client.take_deliveries().is_empty());
+    }
+
+    #[gpui::test]
+    async fn test_webhook_backlog(executor: BackgroundExecutor) {
+        let mut config = TestServer::test_config();
+        config.webhooks = Some(r#"[{"url": "https://hooks.example.com", "secret": "s"}]"#.into());
+        let client = Arc::new(FakeWebhookClient::default());
+        let webhooks = Webhooks::new(
+            &config,
+            client.clone(),
+            Executor::Deterministic(executor.clone()),
+        )
+        .unwrap();
+
+        // An event that's being retried doesn't hold back the ones after it.
+        client.fail_next_attempts(1);
+        webhooks.send(WebhookEvent::RoomCreated {
+            room_id: RoomId(1),
+            channel_id: None,
+        });
+        executor.run_until_parked();
+        webhooks.send(WebhookEvent::RoomEnded {
+            room_id: RoomId(1),
+            channel_id: None,
+        });
+        executor.run_until_parked();
+        let deliveries = client.take_deliveries();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].1["event"], "room_ended");
+        executor.advance_clock(INITIAL_RETRY_DELAY);
+        let deliveries = client.take_deliveries();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].1["event"], "room_created");
+
+        // Events are dropped while an endpoint that's down has too many pending.
+        client.fail_next_attempts(usize::MAX);
+        let attempt_count = client.attempt_count();
+        for room_id in 0..MAX_PENDING_DELIVERIES + 10 {
+            webhooks.send(WebhookEvent::RoomCreated {
+                room_id: RoomId(room_id as i32),
+                channel_id: None,
+            });
+        }
+        executor.run_until_parked();
+        assert_eq!(
+            client.attempt_count(),
+            attempt_count + MAX_PENDING_DELIVERIES
+        );
+
+        // Once they've been given up on, new events are delivered again.
+        executor.advance_clock(INITIAL_RETRY_DELAY * 100);
+        client.fail_next_attempts(0);
+        webhooks.send(WebhookEvent::RoomEnded {
+            room_id: RoomId(1),
+            channel_id: None,
+        });
+        executor.run_until_parked();
+        assert_eq!(client.take_deliveries().len(), 1);
+    }
+}
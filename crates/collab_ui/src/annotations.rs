@@ -0,0 +1,138 @@
+use crate::{review::selected_range, text_prompt::TextPrompt};
+use editor::{Editor, MultiBuffer};
+use gpui::{actions, AppContext, ViewContext};
+use project::Annotation;
+use workspace::{notifications::DetachAndPromptErr, Workspace};
+
+actions!(
+    annotations,
+    [AnnotateSelection, OpenAnnotations, RemoveAnnotation]
+);
+
+/// How many lines around each annotated span are shown when opening annotations.
+const CONTEXT_LINE_COUNT: u32 = 2;
+
+enum AnnotationHighlights {}
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|workspace: &mut Workspace, _| {
+        workspace
+            .register_action(annotate_selection)
+            .register_action(open_annotations)
+            .register_action(remove_annotation);
+    })
+    .detach();
+}
+
+/// Adds a private note to the selection, which the other participants never see.
+fn annotate_selection(
+    workspace: &mut Workspace,
+    _: &AnnotateSelection,
+    cx: &mut ViewContext<Workspace>,
+) {
+    let Some((buffer, range)) = selected_range(workspace, cx) else {
+        return;
+    };
+    let project = workspace.project().clone();
+    TextPrompt::show(
+        workspace,
+        "Annotate the selection",
+        "",
+        move |note, cx| {
+            project.update(cx, |project, cx| {
+                project.add_annotation(&buffer, range, note, cx);
+            });
+        },
+        cx,
+    );
+}
+
+/// Opens the annotated spans in a multibuffer, to come back to them.
+fn open_annotations(
+    workspace: &mut Workspace,
+    _: &OpenAnnotations,
+    cx: &mut ViewContext<Workspace>,
+) {
+    let project = workspace.project().clone();
+    let annotations = project.read(cx).annotations().to_vec();
+    if annotations.is_empty() {
+        return;
+    }
+    let open_annotations = annotations
+        .iter()
+        .map(|annotation| {
+            project.update(cx, |project, cx| project.open_annotation(annotation.id, cx))
+        })
+        .collect::<Vec<_>>();
+    cx.spawn(|workspace, mut cx| async move {
+        let mut locations = Vec::new();
+        for open_annotation in open_annotations {
+            locations.push(open_annotation.await?);
+        }
+
+        workspace.update(&mut cx, |workspace, cx| {
+            let project = workspace.project().read(cx);
+            let replica_id = project.replica_id();
+            let capability = project.capability();
+            let mut highlights = Vec::new();
+            let excerpt_buffer = cx.new_model(|cx| {
+                let mut multibuffer = MultiBuffer::new(replica_id, capability);
+                for location in locations {
+                    highlights.extend(multibuffer.push_excerpts_with_context_lines(
+                        location.buffer,
+                        vec![location.range],
+                        CONTEXT_LINE_COUNT,
+                        cx,
+                    ));
+                }
+                multibuffer.with_title("Annotations".into())
+            });
+            let editor = cx.new_view(|cx| {
+                Editor::for_multibuffer(excerpt_buffer, Some(workspace.project().clone()), cx)
+            });
+            editor.update(cx, |editor, cx| {
+                editor.highlight_background::<AnnotationHighlights>(
+                    highlights,
+                    |colors| colors.editor_document_highlight_write_background,
+                    cx,
+                );
+            });
+            workspace.add_item(Box::new(editor), cx);
+        })
+    })
+    .detach_and_prompt_err("Failed to open annotations", cx, |_, _| None);
+}
+
+fn remove_annotation(
+    workspace: &mut Workspace,
+    _: &RemoveAnnotation,
+    cx: &mut ViewContext<Workspace>,
+) {
+    let Some(annotation) = annotation_at_cursor(workspace, cx) else {
+        return;
+    };
+    workspace.project().update(cx, |project, cx| {
+        project.remove_annotation(annotation.id, cx)
+    });
+}
+
+/// The annotation whose span contains the newest cursor in the active editor.
+fn annotation_at_cursor(workspace: &Workspace, cx: &AppContext) -> Option<Annotation> {
+    let editor = workspace.active_item_as::<Editor>(cx)?;
+    let editor = editor.read(cx);
+    let head = editor.selections.newest_anchor().head();
+    let (buffer, position) = editor
+        .buffer()
+        .read(cx)
+        .text_anchor_for_position(head, cx)?;
+    let buffer = buffer.read(cx);
+    workspace
+        .project()
+        .read(cx)
+        .annotations_for_buffer(buffer.remote_id())
+        .find(|annotation| {
+            annotation.range.start.cmp(&position, buffer).is_le()
+                && annotation.range.end.cmp(&position, buffer).is_ge()
+        })
+        .cloned()
+}
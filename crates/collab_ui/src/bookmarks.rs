@@ -0,0 +1,184 @@
+use crate::{review::selected_range, text_prompt::TextPrompt};
+use anyhow::anyhow;
+use editor::{scroll::Autoscroll, Editor, MultiBuffer};
+use gpui::{actions, AppContext, Task, ViewContext};
+use language::Point;
+use project::Bookmark;
+use workspace::{notifications::DetachAndPromptErr, Workspace};
+
+actions!(
+    bookmarks,
+    [AddBookmark, OpenBookmarks, JumpToBookmark, RemoveBookmark]
+);
+
+/// How many lines around each bookmark are shown when opening bookmarks.
+const CONTEXT_LINE_COUNT: u32 = 2;
+
+enum BookmarkHighlights {}
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|workspace: &mut Workspace, _| {
+        workspace
+            .register_action(add_bookmark)
+            .register_action(open_bookmarks)
+            .register_action(jump_to_bookmark)
+            .register_action(remove_bookmark);
+    })
+    .detach();
+}
+
+/// Bookmarks the start of the selection under a name, for everyone in the project.
+fn add_bookmark(workspace: &mut Workspace, _: &AddBookmark, cx: &mut ViewContext<Workspace>) {
+    let Some((buffer, range)) = selected_range(workspace, cx) else {
+        return;
+    };
+    let project = workspace.project().clone();
+    TextPrompt::show(
+        workspace,
+        "Name the bookmark",
+        "",
+        move |name, cx| {
+            project
+                .update(cx, |project, cx| {
+                    project.add_bookmark(name, &buffer, range.start, cx)
+                })
+                .detach_and_prompt_err("Failed to add bookmark", cx, |_, _| None);
+        },
+        cx,
+    );
+}
+
+/// Opens the lines around every bookmark in a multibuffer.
+fn open_bookmarks(workspace: &mut Workspace, _: &OpenBookmarks, cx: &mut ViewContext<Workspace>) {
+    let project = workspace.project().clone();
+    let bookmarks = project.read(cx).bookmarks().to_vec();
+    if bookmarks.is_empty() {
+        return;
+    }
+    let open_bookmarks = bookmarks
+        .iter()
+        .map(|bookmark| project.update(cx, |project, cx| project.open_bookmark(bookmark.id, cx)))
+        .collect::<Vec<_>>();
+    cx.spawn(|workspace, mut cx| async move {
+        let mut locations = Vec::new();
+        for open_bookmark in open_bookmarks {
+            locations.push(open_bookmark.await?);
+        }
+
+        workspace.update(&mut cx, |workspace, cx| {
+            let project = workspace.project().read(cx);
+            let replica_id = project.replica_id();
+            let capability = project.capability();
+            let mut highlights = Vec::new();
+            let excerpt_buffer = cx.new_model(|cx| {
+                let mut multibuffer = MultiBuffer::new(replica_id, capability);
+                for location in locations {
+                    highlights.extend(multibuffer.push_excerpts_with_context_lines(
+                        location.buffer,
+                        vec![location.range],
+                        CONTEXT_LINE_COUNT,
+                        cx,
+                    ));
+                }
+                multibuffer.with_title("Bookmarks".into())
+            });
+            let editor = cx.new_view(|cx| {
+                Editor::for_multibuffer(excerpt_buffer, Some(workspace.project().clone()), cx)
+            });
+            editor.update(cx, |editor, cx| {
+                editor.highlight_background::<BookmarkHighlights>(
+                    highlights,
+                    |colors| colors.editor_document_highlight_read_background,
+                    cx,
+                );
+            });
+            workspace.add_item(Box::new(editor), cx);
+        })
+    })
+    .detach_and_prompt_err("Failed to open bookmarks", cx, |_, _| None);
+}
+
+/// Moves the cursor to a bookmark, given its name or its number.
+fn jump_to_bookmark(
+    workspace: &mut Workspace,
+    _: &JumpToBookmark,
+    cx: &mut ViewContext<Workspace>,
+) {
+    let workspace_handle = cx.view().downgrade();
+    TextPrompt::show(
+        workspace,
+        "Jump to the bookmark named or numbered",
+        "",
+        move |query, cx| {
+            workspace_handle
+                .update(cx, |workspace, cx| {
+                    open_bookmark(workspace, query.trim(), cx)
+                })
+                .ok();
+        },
+        cx,
+    );
+}
+
+fn open_bookmark(workspace: &mut Workspace, query: &str, cx: &mut ViewContext<Workspace>) {
+    let project = workspace.project().clone();
+    let bookmark_id = project
+        .read(cx)
+        .bookmarks()
+        .iter()
+        .find(|bookmark| bookmark.name == query || bookmark.id.to_string() == query)
+        .map(|bookmark| bookmark.id);
+    let open_bookmark = match bookmark_id {
+        Some(id) => project.update(cx, |project, cx| project.open_bookmark(id, cx)),
+        None => Task::ready(Err(anyhow!("There is no bookmark named {query:?}."))),
+    };
+    cx.spawn(|workspace, mut cx| async move {
+        let location = open_bookmark.await?;
+        workspace.update(&mut cx, |workspace, cx| {
+            let position = location
+                .buffer
+                .read(cx)
+                .summary_for_anchor::<Point>(&location.range.start);
+            let editor = workspace.open_project_item::<Editor>(location.buffer, cx);
+            editor.update(cx, |editor, cx| {
+                editor.change_selections(Some(Autoscroll::center()), cx, |s| {
+                    s.select_ranges([position..position])
+                });
+            });
+        })
+    })
+    .detach_and_prompt_err("Failed to open bookmark", cx, |_, _| None);
+}
+
+fn remove_bookmark(workspace: &mut Workspace, _: &RemoveBookmark, cx: &mut ViewContext<Workspace>) {
+    let Some(bookmark) = bookmark_at_cursor(workspace, cx) else {
+        return;
+    };
+    workspace
+        .project()
+        .update(cx, |project, cx| project.remove_bookmark(bookmark.id, cx))
+        .detach_and_prompt_err("Failed to remove bookmark", cx, |_, _| None);
+}
+
+/// A bookmark on the line of the newest cursor in the active editor.
+fn bookmark_at_cursor(workspace: &Workspace, cx: &AppContext) -> Option<Bookmark> {
+    let editor = workspace.active_item_as::<Editor>(cx)?;
+    let editor = editor.read(cx);
+    let head = editor.selections.newest_anchor().head();
+    let (buffer, position) = editor
+        .buffer()
+        .read(cx)
+        .text_anchor_for_position(head, cx)?;
+    let buffer = buffer.read(cx);
+    let row = buffer.summary_for_anchor::<Point>(&position).row;
+    workspace
+        .project()
+        .read(cx)
+        .bookmarks()
+        .iter()
+        .find(|bookmark| {
+            bookmark.buffer_id == buffer.remote_id()
+                && buffer.summary_for_anchor::<Point>(&bookmark.anchor).row == row
+        })
+        .cloned()
+}
@@ -29,7 +29,7 @@ use workspace::{
     ItemNavHistory, Pane, SaveIntent, Toast, ViewId, Workspace, WorkspaceId,
 };
 
-actions!(collab, [CopyLink]);
+actions!(collab, [CopyLink, ExportAsMarkdown]);
 
 pub fn init(cx: &mut AppContext) {
     register_followable_item::<ChannelView>(cx)
@@ -276,6 +276,16 @@ impl ChannelView {
             .ok();
     }
 
+    fn export_as_markdown(&mut self, _: &ExportAsMarkdown, cx: &mut ViewContext<Self>) {
+        let markdown = self.channel_buffer.read(cx).export_markdown(cx);
+        cx.write_to_clipboard(ClipboardItem::new(markdown));
+        self.workspace
+            .update(cx, |workspace, cx| {
+                workspace.show_toast(Toast::new(0, "Notes copied to clipboard as markdown"), cx);
+            })
+            .ok();
+    }
+
     pub fn channel(&self, cx: &AppContext) -> Option<Arc<Channel>> {
         self.channel_buffer.read(cx).channel(cx)
     }
@@ -339,6 +349,7 @@ impl Render for ChannelView {
         div()
             .size_full()
             .on_action(cx.listener(Self::copy_link))
+            .on_action(cx.listener(Self::export_as_markdown))
             .child(self.editor.clone())
     }
 }
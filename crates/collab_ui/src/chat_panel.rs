@@ -339,13 +339,13 @@ impl ChatPanel {
                             StyledText::new(format!(
                                 "{}{}",
                                 REPLY_TO_PREFIX,
-                                reply_to_message.sender.github_login.clone()
+                                reply_to_message.sender.display_name()
                             ))
                             .with_highlights(
                                 &cx.text_style(),
                                 vec![(
                                     (REPLY_TO_PREFIX.len() - 1)
-                                        ..(reply_to_message.sender.github_login.len()
+                                        ..(reply_to_message.sender.display_name().len()
                                             + REPLY_TO_PREFIX.len()),
                                     HighlightStyle {
                                         font_weight: Some(FontWeight::BOLD),
@@ -473,7 +473,7 @@ impl ChatPanel {
                                     .pl(cx.rem_size() + px(6.0))
                                     .pr(px(8.0))
                                     .font_weight(FontWeight::BOLD)
-                                    .child(Label::new(message.sender.github_login.clone())),
+                                    .child(Label::new(message.sender.display_name().to_string())),
                             )
                             .child(
                                 Label::new(format_timestamp(
@@ -979,6 +979,7 @@ mod tests {
                 github_login: "fgh".into(),
                 avatar_uri: "avatar_fgh".into(),
                 id: 103,
+                display_name: None,
             }),
             nonce: 5,
             mentions: vec![(ranges[0].clone(), 101), (ranges[1].clone(), 102)],
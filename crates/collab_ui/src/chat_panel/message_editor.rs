@@ -413,6 +413,7 @@ mod tests {
                             github_login: "a-b".into(),
                             id: 101,
                             avatar_uri: "avatar_a-b".into(),
+                            display_name: None,
                         }),
                         kind: proto::channel_member::Kind::Member,
                         role: proto::ChannelRole::Member,
@@ -422,6 +423,7 @@ mod tests {
                             github_login: "C_D".into(),
                             id: 102,
                             avatar_uri: "avatar_C_D".into(),
+                            display_name: None,
                         }),
                         kind: proto::channel_member::Kind::Member,
                         role: proto::ChannelRole::Member,
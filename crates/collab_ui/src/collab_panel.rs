@@ -3,10 +3,10 @@ mod contact_finder;
 
 use self::channel_modal::ChannelModal;
 use crate::{
-    channel_view::ChannelView, chat_panel::ChatPanel, face_pile::FacePile,
-    CollaborationPanelSettings,
+    channel_view::ChannelView, chat_panel::ChatPanel, face_pile::FacePile, text_prompt::TextPrompt,
+    time_boxed_calls::TIME_BOXED_CALL_DURATION, CollaborationPanelSettings,
 };
-use call::ActiveCall;
+use call::{ActiveCall, InviteContext};
 use channel::{Channel, ChannelEvent, ChannelId, ChannelStore};
 use client::{Client, Contact, User, UserStore};
 use contact_finder::ContactFinder;
@@ -39,6 +39,7 @@ use ui::{
 use util::{maybe, ResultExt, TryFutureExt};
 use workspace::{
     dock::{DockPosition, Panel, PanelEvent},
+    item::ItemHandle as _,
     notifications::{DetachAndPromptErr, NotifyResultExt, NotifyTaskExt},
     OpenChannelNotes, Workspace,
 };
@@ -438,8 +439,8 @@ impl CollabPanel {
                             .filter_map(|(_, participant)| {
                                 Some(StringMatchCandidate {
                                     id: participant.user.id as usize,
-                                    string: participant.user.github_login.clone(),
-                                    char_bag: participant.user.github_login.chars().collect(),
+                                    string: participant.user.display_name().to_string(),
+                                    char_bag: participant.user.display_name().chars().collect(),
                                 })
                             }),
                     );
@@ -451,6 +452,7 @@ impl CollabPanel {
                     &Default::default(),
                     executor.clone(),
                 ));
+                let collation = self.client.collation();
                 matches.sort_by(|a, b| {
                     let a_is_guest = room.role_for_user(a.candidate_id as u64)
                         == Some(proto::ChannelRole::Guest);
@@ -458,7 +460,7 @@ impl CollabPanel {
                         == Some(proto::ChannelRole::Guest);
                     a_is_guest
                         .cmp(&b_is_guest)
-                        .then_with(|| a.string.cmp(&b.string))
+                        .then_with(|| collation.compare(&a.string, &b.string))
                 });
                 for mat in matches {
                     let user_id = mat.candidate_id as u64;
@@ -493,8 +495,8 @@ impl CollabPanel {
                     .extend(room.pending_participants().iter().enumerate().map(
                         |(id, participant)| StringMatchCandidate {
                             id,
-                            string: participant.github_login.clone(),
-                            char_bag: participant.github_login.chars().collect(),
+                            string: participant.display_name().to_string(),
+                            char_bag: participant.display_name().chars().collect(),
                         },
                     ));
                 let matches = executor.block(match_strings(
@@ -834,7 +836,7 @@ impl CollabPanel {
         let user_id = user.id;
         let is_current_user =
             self.user_store.read(cx).current_user().map(|user| user.id) == Some(user_id);
-        let tooltip = format!("Follow {}", user.github_login);
+        let tooltip = format!("Follow {}", user.display_name());
 
         let is_call_admin = ActiveCall::global(cx).read(cx).room().is_some_and(|room| {
             room.read(cx).local_participant().role == proto::ChannelRole::Admin
@@ -842,7 +844,7 @@ impl CollabPanel {
 
         ListItem::new(SharedString::from(user.github_login.clone()))
             .start_slot(Avatar::new(user.avatar_uri.clone()))
-            .child(Label::new(user.github_login.clone()))
+            .child(Label::new(user.display_name().to_string()))
             .selected(is_selected)
             .end_slot(if is_pending {
                 Label::new("Calling").color(Color::Muted).into_any_element()
@@ -1129,14 +1131,19 @@ impl CollabPanel {
                     cx.handler_for(&this, move |this, cx| {
                         this.join_channel_chat(channel_id, cx)
                     }),
-                )
-                .entry(
+                );
+
+            // Channel links point at zed.dev, which doesn't know about channels on
+            // self-hosted servers.
+            if self.client.server_capabilities().channel_links {
+                context_menu = context_menu.entry(
                     "Copy Channel Link",
                     None,
                     cx.handler_for(&this, move |this, cx| {
                         this.copy_channel_link(channel_id, cx)
                     }),
                 );
+            }
 
             let mut has_destructive_actions = false;
             if self.channel_store.read(cx).is_channel_admin(channel_id) {
@@ -1250,6 +1257,10 @@ impl CollabPanel {
     ) {
         let this = cx.view().clone();
         let in_room = ActiveCall::global(cx).read(cx).room().is_some();
+        let shares_location = self
+            .user_store
+            .read(cx)
+            .shares_location_with(contact.user.id);
 
         let context_menu = ContextMenu::build(cx, |mut context_menu, _| {
             let user_id = contact.user.id;
@@ -1268,8 +1279,50 @@ impl CollabPanel {
                         });
                     }
                 });
+                let label = if in_room {
+                    format!("Invite {} with a Message…", contact.user.github_login)
+                } else {
+                    format!("Call {} with a Message…", contact.user.github_login)
+                };
+                context_menu = context_menu.entry(label, None, {
+                    let this = this.clone();
+                    move |cx| {
+                        this.update(cx, |this, cx| {
+                            this.call_with_message(user_id, cx);
+                        });
+                    }
+                });
+                if !in_room {
+                    let label = format!(
+                        "Call {} for {} Minutes",
+                        contact.user.github_login,
+                        TIME_BOXED_CALL_DURATION.as_secs() / 60
+                    );
+                    context_menu = context_menu.entry(label, None, {
+                        let this = this.clone();
+                        move |cx| {
+                            this.update(cx, |this, cx| {
+                                this.call_with_time_limit(user_id, cx);
+                            });
+                        }
+                    });
+                }
             }
 
+            let location_label = if shares_location {
+                "Stop Sharing My Project"
+            } else {
+                "Share My Project"
+            };
+            context_menu = context_menu.entry(location_label, None, {
+                let this = this.clone();
+                move |cx| {
+                    this.update(cx, |this, cx| {
+                        this.set_location_sharing(user_id, !shares_location, cx);
+                    });
+                }
+            });
+
             context_menu.entry("Remove Contact", None, {
                 let this = this.clone();
                 move |cx| {
@@ -1849,6 +1902,14 @@ impl CollabPanel {
         .detach_and_prompt_err("Failed to remove contact", cx, |_, _| None);
     }
 
+    fn set_location_sharing(&mut self, user_id: u64, enabled: bool, cx: &mut ViewContext<Self>) {
+        self.user_store
+            .update(cx, |store, cx| {
+                store.set_location_sharing(user_id, enabled, cx)
+            })
+            .detach_and_prompt_err("Failed to update project sharing", cx, |_, _| None);
+    }
+
     fn respond_to_contact_request(
         &mut self,
         user_id: u64,
@@ -1883,6 +1944,64 @@ impl CollabPanel {
             .detach_and_prompt_err("Call failed", cx, |_, _| None);
     }
 
+    /// Calls the user with a message, which points them to the cursor in the active
+    /// editor when it's editing one of the project's files.
+    fn call_with_message(&mut self, recipient_user_id: u64, cx: &mut ViewContext<Self>) {
+        let Some(workspace) = self.workspace.upgrade() else {
+            return;
+        };
+        let project = self.project.clone();
+        workspace.update(cx, |workspace, cx| {
+            let location = workspace.active_item_as::<Editor>(cx).and_then(|editor| {
+                let path = editor.project_path(cx)?;
+                project.read(cx).worktree_for_id(path.worktree_id, cx)?;
+                let row = editor
+                    .read(cx)
+                    .selections
+                    .newest::<language::Point>(cx)
+                    .head()
+                    .row;
+                Some((path, row))
+            });
+            let initial_text = location.as_ref().map_or(String::new(), |(path, row)| {
+                format!("Can you look at {}:{}?", path.path.display(), row + 1)
+            });
+            TextPrompt::show(
+                workspace,
+                "Send a message with the call",
+                &initial_text,
+                move |message, cx| {
+                    let (path, row) = location.unzip();
+                    let context = InviteContext { message, path, row };
+                    ActiveCall::global(cx)
+                        .update(cx, |call, cx| {
+                            call.invite_with_context(
+                                recipient_user_id,
+                                Some(project),
+                                Some(context),
+                                cx,
+                            )
+                        })
+                        .detach_and_prompt_err("Call failed", cx, |_, _| None);
+                },
+                cx,
+            );
+        });
+    }
+
+    fn call_with_time_limit(&mut self, recipient_user_id: u64, cx: &mut ViewContext<Self>) {
+        ActiveCall::global(cx)
+            .update(cx, |call, cx| {
+                call.invite_to_time_boxed_room(
+                    recipient_user_id,
+                    Some(self.project.clone()),
+                    TIME_BOXED_CALL_DURATION,
+                    cx,
+                )
+            })
+            .detach_and_prompt_err("Call failed", cx, |_, _| None);
+    }
+
     fn join_channel(&self, channel_id: u64, cx: &mut ViewContext<Self>) {
         let Some(workspace) = self.workspace.upgrade() else {
             return;
@@ -2189,6 +2308,7 @@ impl CollabPanel {
         let online = contact.online;
         let busy = contact.busy || calling;
         let github_login = SharedString::from(contact.user.github_login.clone());
+        let working_in = contact.working_in.clone().filter(|_| online);
         let item = ListItem::new(github_login.clone())
             .indent_level(1)
             .indent_step_size(px(20.))
@@ -2255,7 +2375,11 @@ impl CollabPanel {
                         format!("Call {}", &github_login)
                     }
                 };
-                Tooltip::text(text, cx)
+                if let Some(working_in) = &working_in {
+                    Tooltip::with_meta(text, None, format!("Working in {}", working_in), cx)
+                } else {
+                    Tooltip::text(text, cx)
+                }
             })
     }
 
@@ -2860,7 +2984,7 @@ impl Render for JoinChannelTooltip {
                     h_flex()
                         .gap_2()
                         .child(Avatar::new(participant.avatar_uri.clone()))
-                        .child(Label::new(participant.github_login.clone()))
+                        .child(Label::new(participant.display_name().to_string()))
                 }))
         })
     }
@@ -82,14 +82,13 @@ impl ChannelModal {
         let channel_id = self.channel_id;
         cx.spawn(|this, mut cx| async move {
             if mode == Mode::ManageMembers {
-                let mut members = channel_store
+                // The server lists the members in the order they're shown.
+                let members = channel_store
                     .update(&mut cx, |channel_store, cx| {
                         channel_store.get_channel_member_details(channel_id, cx)
                     })?
                     .await?;
 
-                members.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
-
                 this.update(&mut cx, |this, cx| {
                     this.picker
                         .update(cx, |picker, _| picker.delegate.members = members);
@@ -541,8 +540,11 @@ impl ChannelModalDelegate {
                     kind: proto::channel_member::Kind::Invitee,
                     role: ChannelRole::Member,
                 };
+                let collation = this.delegate.channel_store.read(cx).client().collation();
                 let members = &mut this.delegate.members;
-                match members.binary_search_by_key(&new_member.sort_key(), |k| k.sort_key()) {
+                match members.binary_search_by_key(&new_member.sort_key(collation), |k| {
+                    k.sort_key(collation)
+                }) {
                     Ok(ix) | Err(ix) => members.insert(ix, new_member),
                 }
 
@@ -163,9 +163,27 @@ impl Render for CollabTitlebarItem {
                                                 })
                                             })
                                             .tooltip({
-                                                let login = collaborator.user.github_login.clone();
+                                                let name =
+                                                    collaborator.user.display_name().to_string();
+                                                let limitations = collaborator
+                                                    .capabilities
+                                                    .as_ref()
+                                                    .map(|capabilities| {
+                                                        capabilities.limitations().join(", ")
+                                                    })
+                                                    .unwrap_or_default();
                                                 move |cx| {
-                                                    Tooltip::text(format!("Follow {login}"), cx)
+                                                    let title = format!("Follow {name}");
+                                                    if limitations.is_empty() {
+                                                        Tooltip::text(title, cx)
+                                                    } else {
+                                                        Tooltip::with_meta(
+                                                            title,
+                                                            None,
+                                                            limitations.clone(),
+                                                            cx,
+                                                        )
+                                                    }
                                                 }
                                             }),
                                     )
@@ -188,6 +206,8 @@ impl Render for CollabTitlebarItem {
                         let is_deafened = room.is_deafened().unwrap_or(false);
                         let is_screen_sharing = room.is_screen_sharing();
                         let read_only = room.read_only();
+                        let has_audio = room.has_audio();
+                        let can_share_screen = self.client.server_capabilities().calls_audio;
 
                         this.when(is_local && !read_only, |this| {
                             this.child(
@@ -235,7 +255,7 @@ impl Render for CollabTitlebarItem {
                                 )
                                 .pr_2(),
                         )
-                        .when(!read_only, |this| {
+                        .when(has_audio && !read_only, |this| {
                             this.child(
                                 IconButton::new(
                                     "mute-microphone",
@@ -262,34 +282,38 @@ impl Render for CollabTitlebarItem {
                                 .on_click(move |_, cx| crate::toggle_mute(&Default::default(), cx)),
                             )
                         })
-                        .child(
-                            IconButton::new(
-                                "mute-sound",
-                                if is_deafened {
-                                    ui::IconName::AudioOff
-                                } else {
-                                    ui::IconName::AudioOn
-                                },
+                        .when(has_audio, |this| {
+                            this.child(
+                                IconButton::new(
+                                    "mute-sound",
+                                    if is_deafened {
+                                        ui::IconName::AudioOff
+                                    } else {
+                                        ui::IconName::AudioOn
+                                    },
+                                )
+                                .style(ButtonStyle::Subtle)
+                                .selected_style(ButtonStyle::Tinted(TintColor::Negative))
+                                .icon_size(IconSize::Small)
+                                .selected(is_deafened)
+                                .tooltip(move |cx| {
+                                    if !read_only {
+                                        Tooltip::with_meta(
+                                            "Deafen Audio",
+                                            None,
+                                            "Mic will be muted",
+                                            cx,
+                                        )
+                                    } else {
+                                        Tooltip::text("Deafen Audio", cx)
+                                    }
+                                })
+                                .on_click(move |_, cx| {
+                                    crate::toggle_deafen(&Default::default(), cx)
+                                }),
                             )
-                            .style(ButtonStyle::Subtle)
-                            .selected_style(ButtonStyle::Tinted(TintColor::Negative))
-                            .icon_size(IconSize::Small)
-                            .selected(is_deafened)
-                            .tooltip(move |cx| {
-                                if !read_only {
-                                    Tooltip::with_meta(
-                                        "Deafen Audio",
-                                        None,
-                                        "Mic will be muted",
-                                        cx,
-                                    )
-                                } else {
-                                    Tooltip::text("Deafen Audio", cx)
-                                }
-                            })
-                            .on_click(move |_, cx| crate::toggle_deafen(&Default::default(), cx)),
-                        )
-                        .when(!read_only, |this| {
+                        })
+                        .when(can_share_screen && !read_only, |this| {
                             this.child(
                                 IconButton::new("screen-share", ui::IconName::Screen)
                                     .style(ButtonStyle::Subtle)
@@ -382,22 +406,20 @@ impl CollabTitlebarItem {
     pub fn render_project_host(&self, cx: &mut ViewContext<Self>) -> Option<impl IntoElement> {
         let host = self.project.read(cx).host()?;
         let host_user = self.user_store.read(cx).get_cached_user(host.user_id)?;
+        let host_name = host_user.display_name().to_string();
         let participant_index = self
             .user_store
             .read(cx)
             .participant_indices()
             .get(&host_user.id)?;
         Some(
-            Button::new("project_owner_trigger", host_user.github_login.clone())
+            Button::new("project_owner_trigger", host_name.clone())
                 .color(Color::Player(participant_index.0))
                 .style(ButtonStyle::Subtle)
                 .label_size(LabelSize::Small)
                 .tooltip(move |cx| {
                     Tooltip::text(
-                        format!(
-                            "{} is sharing this project. Click to follow.",
-                            host_user.github_login.clone()
-                        ),
+                        format!("{} is sharing this project. Click to follow.", host_name),
                         cx,
                     )
                 })
@@ -517,12 +539,9 @@ impl CollabTitlebarItem {
                                     avatar.indicator(
                                         AvatarAudioStatusIndicator::new(ui::AudioStatus::Muted)
                                             .tooltip({
-                                                let github_login = user.github_login.clone();
+                                                let name = user.display_name().to_string();
                                                 move |cx| {
-                                                    Tooltip::text(
-                                                        format!("{} is muted", github_login),
-                                                        cx,
-                                                    )
+                                                    Tooltip::text(format!("{} is muted", name), cx)
                                                 }
                                             }),
                                     )
@@ -632,13 +651,21 @@ impl CollabTitlebarItem {
             | client::Status::ConnectionLost
             | client::Status::Reauthenticating { .. }
             | client::Status::Reconnecting { .. }
-            | client::Status::ReconnectionError { .. } => Some(
-                div()
-                    .id("disconnected")
-                    .child(Icon::new(IconName::Disconnected).size(IconSize::Small))
-                    .tooltip(|cx| Tooltip::text("Disconnected", cx))
-                    .into_any_element(),
-            ),
+            | client::Status::ReconnectionError { .. } => {
+                let diagnosis = self.client.connection_diagnosis();
+                Some(
+                    div()
+                        .id("disconnected")
+                        .child(Icon::new(IconName::Disconnected).size(IconSize::Small))
+                        .tooltip(move |cx| match &diagnosis {
+                            Some(diagnosis) => {
+                                Tooltip::with_meta("Disconnected", None, diagnosis.guidance(), cx)
+                            }
+                            None => Tooltip::text("Disconnected", cx),
+                        })
+                        .into_any_element(),
+                )
+            }
             client::Status::UpgradeRequired => {
                 let auto_updater = auto_update::AutoUpdater::get(cx);
                 let label = match auto_updater.map(|auto_update| auto_update.read(cx).status()) {
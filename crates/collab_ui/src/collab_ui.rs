@@ -1,30 +1,46 @@
+pub mod annotations;
+pub mod bookmarks;
 pub mod channel_view;
 pub mod chat_panel;
 pub mod collab_panel;
 mod collab_titlebar_item;
+pub mod edit_suggestions;
 mod face_pile;
 pub mod notification_panel;
 pub mod notifications;
 mod panel_settings;
+pub mod review;
+pub mod session_export;
+mod text_prompt;
+pub mod time_boxed_calls;
 
-use std::{rc::Rc, sync::Arc};
+use std::{rc::Rc, sync::Arc, time::Duration};
 
 use call::{report_call_event_for_room, ActiveCall};
+use client::proto;
 pub use collab_panel::CollabPanel;
 pub use collab_titlebar_item::CollabTitlebarItem;
 use gpui::{
-    actions, point, AppContext, GlobalPixels, Pixels, PlatformDisplay, Size, Task, WindowBounds,
-    WindowContext, WindowKind, WindowOptions,
+    actions, point, AppContext, ClipboardItem, GlobalPixels, Pixels, PlatformDisplay, Size, Task,
+    ViewContext, WindowBounds, WindowContext, WindowKind, WindowOptions,
 };
 pub use panel_settings::{
     ChatPanelSettings, CollaborationPanelSettings, NotificationPanelSettings,
 };
 use settings::Settings;
-use workspace::{notifications::DetachAndPromptErr, AppState};
+use workspace::{notifications::DetachAndPromptErr, AppState, Toast, Workspace};
 
 actions!(
     collab,
-    [ToggleScreenSharing, ToggleMute, ToggleDeafen, LeaveCall]
+    [
+        ToggleScreenSharing,
+        ToggleMute,
+        ToggleDeafen,
+        LeaveCall,
+        ExportSession,
+        InviteGuest,
+        ExtendCall
+    ]
 );
 
 pub fn init(app_state: &Arc<AppState>, cx: &mut AppContext) {
@@ -33,12 +49,47 @@ pub fn init(app_state: &Arc<AppState>, cx: &mut AppContext) {
     NotificationPanelSettings::register(cx);
 
     vcs_menu::init(cx);
+    annotations::init(cx);
+    bookmarks::init(cx);
     collab_titlebar_item::init(cx);
     collab_panel::init(cx);
     channel_view::init(cx);
     chat_panel::init(cx);
+    edit_suggestions::init(cx);
     notification_panel::init(cx);
+    review::init(cx);
+    session_export::init(cx);
+    time_boxed_calls::init(cx);
     notifications::init(&app_state, cx);
+
+    cx.observe_new_views(|workspace: &mut Workspace, _| {
+        workspace.register_action(invite_guest);
+    })
+    .detach();
+}
+
+/// How long guests invited with [`InviteGuest`] can take part in the call.
+const GUEST_INVITATION_DURATION: Duration = Duration::from_secs(60 * 60);
+
+/// Invites a guest to view the current call, and copies the link they join it with.
+fn invite_guest(workspace: &mut Workspace, _: &InviteGuest, cx: &mut ViewContext<Workspace>) {
+    let Some(room) = ActiveCall::global(cx).read(cx).room().cloned() else {
+        return;
+    };
+    let invitation = room.update(cx, |room, cx| {
+        room.invite_guest(proto::ChannelRole::Guest, GUEST_INVITATION_DURATION, cx)
+    });
+    cx.spawn(|workspace, mut cx| async move {
+        let invitation = invitation.await?;
+        workspace.update(&mut cx, |workspace, cx| {
+            cx.write_to_clipboard(ClipboardItem::new(invitation.credentials.guest_link()));
+            workspace.show_toast(
+                Toast::new(0, "Guest invitation link copied to clipboard"),
+                cx,
+            );
+        })
+    })
+    .detach_and_prompt_err("Failed to invite guest", cx, |_, _| None);
 }
 
 pub fn toggle_screen_sharing(_: &ToggleScreenSharing, cx: &mut WindowContext) {
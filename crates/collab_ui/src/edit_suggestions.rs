@@ -0,0 +1,217 @@
+use crate::{review::selected_range, text_prompt::TextPrompt};
+use editor::{Editor, MultiBuffer};
+use gpui::{actions, Action as _, AppContext, ViewContext};
+use project::{EditSuggestion, Event};
+use workspace::{
+    notifications::{DetachAndPromptErr, NotifyResultExt},
+    Toast, Workspace,
+};
+
+actions!(
+    edit_suggestions,
+    [
+        SuggestEdit,
+        OpenEditSuggestions,
+        ApplyEditSuggestion,
+        RejectEditSuggestion
+    ]
+);
+
+const EDIT_SUGGESTIONS_TOAST_ID: usize = 0x3d1e5a77;
+
+/// How many lines around each suggested edit are shown when opening suggestions.
+const CONTEXT_LINE_COUNT: u32 = 2;
+
+enum EditSuggestionHighlights {}
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|workspace: &mut Workspace, cx| {
+        workspace
+            .register_action(suggest_edit)
+            .register_action(open_edit_suggestions)
+            .register_action(apply_edit_suggestion)
+            .register_action(reject_edit_suggestion);
+
+        // Let the host know when guests suggest edits.
+        let mut suggestion_count = 0;
+        cx.subscribe(workspace.project(), move |workspace, project, event, cx| {
+            if let Event::EditSuggestionsChanged = event {
+                let project = project.read(cx);
+                let count = project.edit_suggestions().len();
+                if project.is_local() && count > suggestion_count {
+                    workspace.show_toast(
+                        Toast::new(
+                            EDIT_SUGGESTIONS_TOAST_ID,
+                            format!("{count} suggested edits are waiting for you"),
+                        )
+                        .on_click("Open", |cx| {
+                            cx.dispatch_action(OpenEditSuggestions.boxed_clone())
+                        }),
+                        cx,
+                    );
+                }
+                suggestion_count = count;
+            }
+        })
+        .detach();
+    })
+    .detach();
+}
+
+/// Suggests replacing the selection with some other text, for the host to apply.
+fn suggest_edit(workspace: &mut Workspace, _: &SuggestEdit, cx: &mut ViewContext<Workspace>) {
+    let Some((buffer, range)) = selected_range(workspace, cx) else {
+        return;
+    };
+    let selected_text = buffer
+        .read(cx)
+        .text_for_range(range.clone())
+        .collect::<String>();
+    let project = workspace.project().clone();
+    TextPrompt::show(
+        workspace,
+        "Suggest replacing the selection with",
+        &selected_text,
+        move |new_text, cx| {
+            project
+                .update(cx, |project, cx| {
+                    project.suggest_edit(&buffer, vec![(range, new_text)], cx)
+                })
+                .detach_and_prompt_err("Failed to suggest edit", cx, |_, _| None);
+        },
+        cx,
+    );
+}
+
+/// Opens the spans that pending suggestions would replace in a multibuffer.
+fn open_edit_suggestions(
+    workspace: &mut Workspace,
+    _: &OpenEditSuggestions,
+    cx: &mut ViewContext<Workspace>,
+) {
+    let project = workspace.project().clone();
+    let suggestions = project.read(cx).edit_suggestions().to_vec();
+    if suggestions.is_empty() {
+        return;
+    }
+    let open_buffers = suggestions
+        .iter()
+        .map(|suggestion| {
+            project.update(cx, |project, cx| {
+                project.open_buffer_by_id(suggestion.buffer_id, cx)
+            })
+        })
+        .collect::<Vec<_>>();
+    cx.spawn(|workspace, mut cx| async move {
+        let mut buffers = Vec::new();
+        for (suggestion, open_buffer) in suggestions.iter().zip(open_buffers) {
+            let buffer = open_buffer.await?;
+            buffer
+                .update(&mut cx, |buffer, _| {
+                    buffer.wait_for_anchors(
+                        suggestion
+                            .edits
+                            .iter()
+                            .flat_map(|(range, _)| [range.start, range.end]),
+                    )
+                })?
+                .await?;
+            buffers.push(buffer);
+        }
+
+        workspace.update(&mut cx, |workspace, cx| {
+            let project = workspace.project().read(cx);
+            let replica_id = project.replica_id();
+            let capability = project.capability();
+            let mut highlights = Vec::new();
+            let excerpt_buffer = cx.new_model(|cx| {
+                let mut multibuffer = MultiBuffer::new(replica_id, capability);
+                for (suggestion, buffer) in suggestions.iter().zip(buffers) {
+                    highlights.extend(
+                        multibuffer.push_excerpts_with_context_lines(
+                            buffer,
+                            suggestion
+                                .edits
+                                .iter()
+                                .map(|(range, _)| range.clone())
+                                .collect(),
+                            CONTEXT_LINE_COUNT,
+                            cx,
+                        ),
+                    );
+                }
+                multibuffer.with_title("Suggested Edits".into())
+            });
+            let editor = cx.new_view(|cx| {
+                Editor::for_multibuffer(excerpt_buffer, Some(workspace.project().clone()), cx)
+            });
+            editor.update(cx, |editor, cx| {
+                editor.highlight_background::<EditSuggestionHighlights>(
+                    highlights,
+                    |colors| colors.editor_document_highlight_read_background,
+                    cx,
+                );
+            });
+            workspace.add_item(Box::new(editor), cx);
+        })
+    })
+    .detach_and_prompt_err("Failed to open suggested edits", cx, |_, _| None);
+}
+
+fn apply_edit_suggestion(
+    workspace: &mut Workspace,
+    _: &ApplyEditSuggestion,
+    cx: &mut ViewContext<Workspace>,
+) {
+    let Some(suggestion) = suggestion_at_cursor(workspace, cx) else {
+        return;
+    };
+    workspace
+        .project()
+        .update(cx, |project, cx| {
+            project.apply_edit_suggestion(suggestion.id, cx)
+        })
+        .notify_err(workspace, cx);
+}
+
+fn reject_edit_suggestion(
+    workspace: &mut Workspace,
+    _: &RejectEditSuggestion,
+    cx: &mut ViewContext<Workspace>,
+) {
+    let Some(suggestion) = suggestion_at_cursor(workspace, cx) else {
+        return;
+    };
+    workspace
+        .project()
+        .update(cx, |project, cx| {
+            project.reject_edit_suggestion(suggestion.id, cx)
+        })
+        .notify_err(workspace, cx);
+}
+
+/// The pending suggestion that would edit the text under the newest cursor in the
+/// active editor.
+fn suggestion_at_cursor(workspace: &Workspace, cx: &AppContext) -> Option<EditSuggestion> {
+    let editor = workspace.active_item_as::<Editor>(cx)?;
+    let editor = editor.read(cx);
+    let head = editor.selections.newest_anchor().head();
+    let (buffer, position) = editor
+        .buffer()
+        .read(cx)
+        .text_anchor_for_position(head, cx)?;
+    let buffer = buffer.read(cx);
+    workspace
+        .project()
+        .read(cx)
+        .edit_suggestions()
+        .iter()
+        .find(|suggestion| {
+            suggestion.buffer_id == buffer.remote_id()
+                && suggestion.edits.iter().any(|(range, _)| {
+                    range.start.cmp(&position, buffer).is_le()
+                        && range.end.cmp(&position, buffer).is_ge()
+                })
+        })
+        .cloned()
+}
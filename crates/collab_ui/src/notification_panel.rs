@@ -1,9 +1,11 @@
 use crate::{chat_panel::ChatPanel, NotificationPanelSettings};
 use anyhow::Result;
+use call::ActiveCall;
 use channel::ChannelStore;
 use client::{Client, Notification, User, UserStore};
 use collections::HashMap;
 use db::kvp::KEY_VALUE_STORE;
+use editor::{Editor, MultiBuffer};
 use futures::StreamExt;
 use gpui::{
     actions, div, img, list, px, AnyElement, AppContext, AsyncWindowContext, CursorStyle,
@@ -384,6 +386,13 @@ impl NotificationPanel {
                     can_navigate: true,
                 })
             }
+            Notification::CallSummaryReady { .. } => Some(NotificationPresenter {
+                icon: "icons/file.svg",
+                text: "A summary of your call is ready".into(),
+                needs_response: false,
+                actor: None,
+                can_navigate: true,
+            }),
         }
     }
 
@@ -395,6 +404,7 @@ impl NotificationPanel {
     ) {
         let should_mark_as_read = match notification {
             Notification::ContactRequestAccepted { .. } => true,
+            Notification::CallSummaryReady { .. } => false,
             Notification::ContactRequest { .. }
             | Notification::ChannelInvitation { .. }
             | Notification::ChannelMessageMention { .. } => false,
@@ -440,6 +450,52 @@ impl NotificationPanel {
                 });
             }
         }
+
+        if let Notification::CallSummaryReady { summary_id } = *notification {
+            self.open_call_summary(summary_id, cx);
+        }
+    }
+
+    /// Opens the summary of a call in a new markdown buffer, from which it can be
+    /// saved or copied elsewhere.
+    fn open_call_summary(&mut self, summary_id: u64, cx: &mut ViewContext<Self>) {
+        let summary =
+            ActiveCall::global(cx).update(cx, |call, cx| call.call_summary(summary_id, cx));
+        let workspace = self.workspace.clone();
+        cx.spawn(|_, mut cx| async move {
+            let markdown = summary.await?.to_markdown();
+            let language = workspace
+                .update(&mut cx, |workspace, _| {
+                    workspace
+                        .app_state()
+                        .languages
+                        .language_for_name("Markdown")
+                })?
+                .await
+                .log_err();
+            workspace
+                .update(&mut cx, |workspace, cx| {
+                    workspace.with_local_workspace(cx, |workspace, cx| {
+                        let project = workspace.project();
+                        let buffer = project.update(cx, |project, cx| {
+                            project
+                                .create_buffer(&markdown, language, cx)
+                                .expect("creating buffers on a local workspace always succeeds")
+                        });
+                        let buffer = cx.new_model(|cx| {
+                            MultiBuffer::singleton(buffer, cx).with_title("Call Summary".into())
+                        });
+                        workspace.add_item(
+                            Box::new(cx.new_view(|cx| {
+                                Editor::for_multibuffer(buffer, Some(project.clone()), cx)
+                            })),
+                            cx,
+                        );
+                    })
+                })?
+                .await
+        })
+        .detach_and_log_err(cx);
     }
 
     fn is_showing_notification(&self, notification: &Notification, cx: &ViewContext<Self>) -> bool {
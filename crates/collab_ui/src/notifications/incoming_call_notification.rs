@@ -1,14 +1,18 @@
 use crate::notification_window_options;
 use crate::notifications::collab_notification::CollabNotification;
+use anyhow::anyhow;
 use call::{ActiveCall, IncomingCall};
+use editor::{scroll::Autoscroll, Editor};
 use futures::StreamExt;
-use gpui::{prelude::*, AppContext, WindowHandle};
+use gpui::{prelude::*, AppContext, AsyncAppContext, WindowHandle};
+use language::Point;
+use project::ProjectPath;
 use settings::Settings;
 use std::sync::{Arc, Weak};
 use theme::ThemeSettings;
 use ui::{prelude::*, Button, Label};
 use util::ResultExt;
-use workspace::AppState;
+use workspace::{AppState, Workspace};
 
 pub fn init(app_state: &Arc<AppState>, cx: &mut AppContext) {
     let app_state = Arc::downgrade(app_state);
@@ -75,23 +79,25 @@ impl IncomingCallNotificationState {
             let join = active_call.update(cx, |active_call, cx| active_call.accept_incoming(cx));
             let caller_user_id = self.call.calling_user.id;
             let initial_project_id = self.call.initial_project.as_ref().map(|project| project.id);
+            let location = self.call.context.as_ref().and_then(|context| {
+                let path = context.path.clone()?;
+                Some((path, context.row))
+            });
             let app_state = self.app_state.clone();
             let cx: &mut AppContext = cx;
-            cx.spawn(|cx| async move {
+            cx.spawn(|mut cx| async move {
                 join.await?;
                 if let Some(project_id) = initial_project_id {
+                    let Some(app_state) = app_state.upgrade() else {
+                        return Ok(());
+                    };
                     cx.update(|cx| {
-                        if let Some(app_state) = app_state.upgrade() {
-                            workspace::join_remote_project(
-                                project_id,
-                                caller_user_id,
-                                app_state,
-                                cx,
-                            )
-                            .detach_and_log_err(cx);
-                        }
-                    })
-                    .log_err();
+                        workspace::join_remote_project(project_id, caller_user_id, app_state, cx)
+                    })?
+                    .await?;
+                    if let Some((path, row)) = location {
+                        open_invite_location(project_id, path, row, &mut cx).await?;
+                    }
                 }
                 anyhow::Ok(())
             })
@@ -104,6 +110,45 @@ impl IncomingCallNotificationState {
     }
 }
 
+/// Takes the callee to the location the caller pointed at in their invite, once
+/// they've joined the project it's in.
+async fn open_invite_location(
+    project_id: u64,
+    path: ProjectPath,
+    row: Option<u32>,
+    cx: &mut AsyncAppContext,
+) -> anyhow::Result<()> {
+    let workspace = cx
+        .update(|cx| {
+            cx.windows().into_iter().find_map(|window| {
+                let workspace = window.downcast::<Workspace>()?;
+                let remote_id = workspace.read(cx).ok()?.project().read(cx).remote_id();
+                (remote_id == Some(project_id)).then_some(workspace)
+            })
+        })?
+        .ok_or_else(|| anyhow!("no workspace for the invite's project"))?;
+
+    let item = workspace
+        .update(cx, |workspace, cx| {
+            // Stop following the caller, who'd otherwise pull the callee away.
+            let pane = workspace.active_pane().clone();
+            workspace.unfollow(&pane, cx);
+            workspace.open_path(path, None, true, cx)
+        })?
+        .await?;
+    if let Some((row, editor)) = row.zip(item.downcast::<Editor>()) {
+        workspace.update(cx, |_, cx| {
+            editor.update(cx, |editor, cx| {
+                let point = Point::new(row, 0);
+                editor.change_selections(Some(Autoscroll::center()), cx, |s| {
+                    s.select_ranges([point..point])
+                });
+            });
+        })?;
+    }
+    Ok(())
+}
+
 impl IncomingCallNotification {
     pub fn new(call: IncomingCall, app_state: Weak<AppState>) -> Self {
         Self {
@@ -125,10 +170,17 @@ impl Render for IncomingCallNotification {
 
         cx.set_rem_size(ui_font_size);
 
+        // Accepting a waiting call hangs up the call the user is already in.
+        let accept_label = if self.state.call.waiting {
+            "Hang Up & Accept"
+        } else {
+            "Accept"
+        };
+
         div().size_full().font(ui_font).child(
             CollabNotification::new(
                 self.state.call.calling_user.avatar_uri.clone(),
-                Button::new("accept", "Accept").on_click({
+                Button::new("accept", accept_label).on_click({
                     let state = self.state.clone();
                     move |_, cx| state.respond(true, cx)
                 }),
@@ -137,10 +189,29 @@ impl Render for IncomingCallNotification {
                     move |_, cx| state.respond(false, cx)
                 }),
             )
-            .child(v_flex().overflow_hidden().child(Label::new(format!(
-                "{} is sharing a project in Zed",
-                self.state.call.calling_user.github_login
-            )))),
+            .child(
+                v_flex()
+                    .overflow_hidden()
+                    .child(Label::new(format!(
+                        "{} is sharing a project in Zed",
+                        self.state.call.calling_user.display_name()
+                    )))
+                    .children(self.state.call.context.as_ref().map(|context| {
+                        let mut message = context.message.clone();
+                        if let Some(path) = &context.path {
+                            let location = match context.row {
+                                Some(row) => format!("{}:{}", path.path.display(), row + 1),
+                                None => path.path.display().to_string(),
+                            };
+                            if message.is_empty() {
+                                message = location;
+                            } else {
+                                message = format!("{message} ({location})");
+                            }
+                        }
+                        Label::new(message).color(Color::Muted)
+                    })),
+            ),
         )
     }
 }
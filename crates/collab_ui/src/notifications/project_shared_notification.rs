@@ -139,7 +139,7 @@ impl Render for ProjectSharedNotification {
                     this.dismiss(cx);
                 })),
             )
-            .child(Label::new(self.owner.github_login.clone()))
+            .child(Label::new(self.owner.display_name().to_string()))
             .child(Label::new(format!(
                 "is sharing a project in Zed{}",
                 if self.worktree_root_names.is_empty() {
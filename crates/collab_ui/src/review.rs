@@ -0,0 +1,179 @@
+use crate::text_prompt::TextPrompt;
+use editor::{actions::OpenReview, Editor};
+use gpui::{actions, AppContext, Model, ViewContext};
+use language::{Anchor, Buffer};
+use project::ReviewComment;
+use std::ops::Range;
+use workspace::{notifications::DetachAndPromptErr, Workspace};
+
+actions!(
+    review,
+    [
+        StartReview,
+        EndReview,
+        CommentOnSelection,
+        SuggestEditForSelection,
+        ResolveReviewComment,
+        ApplyReviewSuggestion
+    ]
+);
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|workspace: &mut Workspace, _| {
+        workspace
+            .register_action(start_review)
+            .register_action(end_review)
+            .register_action(comment_on_selection)
+            .register_action(suggest_edit_for_selection)
+            .register_action(resolve_review_comment)
+            .register_action(apply_review_suggestion);
+    })
+    .detach();
+}
+
+/// Starts a review of the changes in the buffers that are open in the project,
+/// and opens it.
+fn start_review(workspace: &mut Workspace, _: &StartReview, cx: &mut ViewContext<Workspace>) {
+    let project = workspace.project().clone();
+    let buffers = project.read(cx).opened_buffers();
+    let start_review = project.update(cx, |project, cx| project.start_review(buffers, cx));
+    cx.spawn(|workspace, mut cx| async move {
+        start_review.await?;
+        workspace.update(&mut cx, |workspace, cx| {
+            Editor::open_review(workspace, &OpenReview, cx)
+        })
+    })
+    .detach_and_prompt_err("Failed to start review", cx, |_, _| None);
+}
+
+fn end_review(workspace: &mut Workspace, _: &EndReview, cx: &mut ViewContext<Workspace>) {
+    workspace
+        .project()
+        .update(cx, |project, cx| project.end_review(cx));
+}
+
+fn comment_on_selection(
+    workspace: &mut Workspace,
+    _: &CommentOnSelection,
+    cx: &mut ViewContext<Workspace>,
+) {
+    let Some((buffer, range)) = selected_range(workspace, cx) else {
+        return;
+    };
+    let project = workspace.project().clone();
+    TextPrompt::show(
+        workspace,
+        "Comment on the selection",
+        "",
+        move |body, cx| {
+            project
+                .update(cx, |project, cx| {
+                    project.add_review_comment(&buffer, range, body, None, cx)
+                })
+                .detach_and_prompt_err("Failed to add review comment", cx, |_, _| None);
+        },
+        cx,
+    );
+}
+
+fn suggest_edit_for_selection(
+    workspace: &mut Workspace,
+    _: &SuggestEditForSelection,
+    cx: &mut ViewContext<Workspace>,
+) {
+    let Some((buffer, range)) = selected_range(workspace, cx) else {
+        return;
+    };
+    let selected_text = buffer
+        .read(cx)
+        .text_for_range(range.clone())
+        .collect::<String>();
+    let project = workspace.project().clone();
+    TextPrompt::show(
+        workspace,
+        "Suggest replacing the selection with",
+        &selected_text,
+        move |suggestion, cx| {
+            project
+                .update(cx, |project, cx| {
+                    project.add_review_comment(
+                        &buffer,
+                        range,
+                        "Suggested edit".into(),
+                        Some(suggestion),
+                        cx,
+                    )
+                })
+                .detach_and_prompt_err("Failed to suggest edit", cx, |_, _| None);
+        },
+        cx,
+    );
+}
+
+fn resolve_review_comment(
+    workspace: &mut Workspace,
+    _: &ResolveReviewComment,
+    cx: &mut ViewContext<Workspace>,
+) {
+    let Some(comment) = comment_at_cursor(workspace, cx) else {
+        return;
+    };
+    workspace
+        .project()
+        .update(cx, |project, cx| {
+            project.resolve_review_comment(comment.id, cx)
+        })
+        .detach_and_prompt_err("Failed to resolve review comment", cx, |_, _| None);
+}
+
+fn apply_review_suggestion(
+    workspace: &mut Workspace,
+    _: &ApplyReviewSuggestion,
+    cx: &mut ViewContext<Workspace>,
+) {
+    let Some(comment) = comment_at_cursor(workspace, cx) else {
+        return;
+    };
+    workspace
+        .project()
+        .update(cx, |project, cx| {
+            project.apply_review_suggestion(comment.id, cx)
+        })
+        .detach_and_prompt_err("Failed to apply suggested edit", cx, |_, _| None);
+}
+
+/// The newest selection in the active editor, if it lies within one buffer.
+pub(crate) fn selected_range(
+    workspace: &Workspace,
+    cx: &AppContext,
+) -> Option<(Model<Buffer>, Range<Anchor>)> {
+    let editor = workspace.active_item_as::<Editor>(cx)?;
+    let editor = editor.read(cx);
+    let selection = editor.selections.newest_anchor();
+    let multibuffer = editor.buffer().read(cx);
+    let (buffer, start) = multibuffer.text_anchor_for_position(selection.start, cx)?;
+    let (end_buffer, end) = multibuffer.text_anchor_for_position(selection.end, cx)?;
+    (buffer == end_buffer).then_some((buffer, start..end))
+}
+
+/// The review comment whose span contains the newest cursor in the active editor.
+fn comment_at_cursor(workspace: &Workspace, cx: &AppContext) -> Option<ReviewComment> {
+    let editor = workspace.active_item_as::<Editor>(cx)?;
+    let editor = editor.read(cx);
+    let head = editor.selections.newest_anchor().head();
+    let (buffer, position) = editor
+        .buffer()
+        .read(cx)
+        .text_anchor_for_position(head, cx)?;
+    let buffer = buffer.read(cx);
+    let review = workspace.project().read(cx).review()?;
+    review
+        .comments
+        .iter()
+        .find(|comment| {
+            comment.buffer_id == buffer.remote_id()
+                && comment.range.start.cmp(&position, buffer).is_le()
+                && comment.range.end.cmp(&position, buffer).is_ge()
+        })
+        .cloned()
+}
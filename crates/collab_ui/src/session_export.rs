@@ -0,0 +1,432 @@
+use crate::ExportSession;
+use anyhow::{bail, Result};
+use call::{call_settings::CallSettings, ActiveCall, Room};
+use channel::ChannelStore;
+use gpui::{AppContext, Model, Task, ViewContext};
+use language::ToPoint as _;
+use project::Project;
+use settings::Settings;
+use std::{fmt::Write as _, path::PathBuf, sync::Arc};
+use time::OffsetDateTime;
+use util::{
+    http::{HttpClient, ZedHttpClient},
+    ResultExt,
+};
+use workspace::{notifications::DetachAndPromptErr, Toast, Workspace};
+
+/// A record of a pairing session, gathered from the room's chat, the room's
+/// channel and the project being worked on, so that it can be archived.
+#[derive(Clone, Debug)]
+pub struct SessionExport {
+    pub title: String,
+    pub started_at: Option<OffsetDateTime>,
+    pub exported_at: OffsetDateTime,
+    pub room_messages: Vec<ExportedMessage>,
+    pub channel_messages: Vec<ExportedMessage>,
+    pub notes: Option<String>,
+    pub bookmarks: Vec<ExportedBookmark>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ExportedMessage {
+    pub sender: String,
+    pub timestamp: OffsetDateTime,
+    pub body: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct ExportedBookmark {
+    pub name: String,
+    pub path: String,
+    pub row: u32,
+}
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|workspace: &mut Workspace, _| {
+        workspace.register_action(export_session_to_file);
+    })
+    .detach();
+}
+
+/// Gathers the messages sent during the call, both in the room's chat and in
+/// its channel's chat, along with the channel's notes and the bookmarks of the
+/// given project. Calls outside of a channel have no channel chat or notes.
+pub fn export_session(
+    room: &Model<Room>,
+    project: Option<Model<Project>>,
+    cx: &mut AppContext,
+) -> Task<Result<SessionExport>> {
+    let open_room_chat = room.update(cx, |room, cx| room.open_chat(cx));
+    let room = room.read(cx);
+    let channel_id = room.channel_id();
+    let started_at = room.started_at().map(OffsetDateTime::from);
+    let channel_store = ChannelStore::global(cx);
+    let channel = channel_id
+        .and_then(|channel_id| channel_store.read(cx).channel_for_id(channel_id).cloned());
+    let title = channel.map_or_else(
+        || "Call".to_string(),
+        |channel| format!("#{}", channel.name),
+    );
+    let (open_chat, open_notes) = match channel_id {
+        Some(channel_id) => channel_store.update(cx, |channel_store, cx| {
+            (
+                Some(channel_store.open_channel_chat(channel_id, cx)),
+                Some(channel_store.open_channel_buffer(channel_id, cx)),
+            )
+        }),
+        None => (None, None),
+    };
+    let open_bookmarks = project.map_or(Vec::new(), |project| {
+        project.update(cx, |project, cx| {
+            project
+                .bookmarks()
+                .to_vec()
+                .into_iter()
+                .map(|bookmark| (bookmark.name, project.open_bookmark(bookmark.id, cx)))
+                .collect()
+        })
+    });
+
+    cx.spawn(|mut cx| async move {
+        let room_chat = open_room_chat.await?;
+        while let Some(load) = room_chat.update(&mut cx, |chat, cx| {
+            let first_timestamp = chat.messages().first()?.timestamp;
+            if sent_before(first_timestamp, started_at) {
+                None
+            } else {
+                chat.load_more_messages(cx)
+            }
+        })? {
+            load.await?;
+        }
+        let room_messages = room_chat.read_with(&cx, |chat, _| {
+            chat.messages()
+                .iter()
+                .filter(|message| {
+                    !message.is_pending() && !sent_before(message.timestamp, started_at)
+                })
+                .map(|message| ExportedMessage {
+                    sender: message.sender.github_login.clone(),
+                    timestamp: message.timestamp,
+                    body: message.body.clone(),
+                })
+                .collect()
+        })?;
+
+        let mut channel_messages = Vec::new();
+        if let Some(open_chat) = open_chat {
+            let chat = open_chat.await?;
+            while let Some(load) = chat.update(&mut cx, |chat, cx| {
+                let first_timestamp = chat.messages().first()?.timestamp;
+                if sent_before(first_timestamp, started_at) {
+                    None
+                } else {
+                    chat.load_more_messages(cx)
+                }
+            })? {
+                if load.await.is_none() {
+                    break;
+                }
+            }
+            channel_messages = chat.read_with(&cx, |chat, _| {
+                chat.messages()
+                    .iter()
+                    .filter(|message| {
+                        !message.is_pending() && !sent_before(message.timestamp, started_at)
+                    })
+                    .map(|message| ExportedMessage {
+                        sender: message.sender.github_login.clone(),
+                        timestamp: message.timestamp,
+                        body: message.body.clone(),
+                    })
+                    .collect()
+            })?;
+        }
+
+        let notes = match open_notes {
+            Some(open_notes) => {
+                let notes = open_notes.await?;
+                Some(notes.read_with(&cx, |notes, cx| notes.buffer().read(cx).text())?)
+            }
+            None => None,
+        };
+
+        let mut bookmarks = Vec::new();
+        for (name, open_bookmark) in open_bookmarks {
+            let Some(location) = open_bookmark.await.log_err() else {
+                continue;
+            };
+            bookmarks.push(location.buffer.read_with(&cx, |buffer, _| {
+                let path = buffer.file().map_or_else(
+                    || "untitled".to_string(),
+                    |file| file.path().to_string_lossy().to_string(),
+                );
+                ExportedBookmark {
+                    name,
+                    path,
+                    row: location.range.start.to_point(buffer).row,
+                }
+            })?);
+        }
+
+        Ok(SessionExport {
+            title,
+            started_at,
+            exported_at: OffsetDateTime::now_utc(),
+            room_messages,
+            channel_messages,
+            notes,
+            bookmarks,
+        })
+    })
+}
+
+/// Exports the current session to a markdown file chosen by the user, and posts
+/// it to the webhook in the `calls.session_export_webhook` setting, if any.
+fn export_session_to_file(
+    workspace: &mut Workspace,
+    _: &ExportSession,
+    cx: &mut ViewContext<Workspace>,
+) {
+    let call = ActiveCall::global(cx).read(cx);
+    let Some(room) = call.room().cloned() else {
+        return;
+    };
+    let http_client = call.client().http_client();
+    let webhook_url = CallSettings::get_global(cx).session_export_webhook.clone();
+    let fs = workspace.app_state().fs.clone();
+    let project = workspace.project().clone();
+    let start_abs_path = project
+        .read(cx)
+        .visible_worktrees(cx)
+        .next()
+        .and_then(|worktree| Some(worktree.read(cx).as_local()?.abs_path().to_path_buf()))
+        .unwrap_or_else(PathBuf::new);
+    let export = export_session(&room, Some(project), cx);
+    cx.spawn(|workspace, mut cx| async move {
+        let markdown = export.await?.to_markdown();
+        let Some(abs_path) = cx
+            .update(|cx| cx.prompt_for_new_path(&start_abs_path))?
+            .await
+            .ok()
+            .flatten()
+        else {
+            return Ok(());
+        };
+        fs.atomic_write(abs_path, markdown.clone()).await?;
+        if let Some(webhook_url) = webhook_url {
+            post_session_export(&http_client, &webhook_url, &markdown).await?;
+        }
+        workspace.update(&mut cx, |workspace, cx| {
+            workspace.show_toast(Toast::new(0, "Session exported"), cx);
+        })
+    })
+    .detach_and_prompt_err("Failed to export session", cx, |_, _| None);
+}
+
+/// Posts an exported session to a webhook, as a JSON object whose `text` field
+/// holds the session's markdown.
+pub async fn post_session_export(
+    http_client: &Arc<ZedHttpClient>,
+    url: &str,
+    markdown: &str,
+) -> Result<()> {
+    let body = serde_json::to_vec(&serde_json::json!({ "text": markdown }))?;
+    let response = http_client.post_json(url, body.into()).await?;
+    if !response.status().is_success() {
+        bail!("session export webhook failed with {}", response.status());
+    }
+    Ok(())
+}
+
+/// Whether a message was sent before the call started, and so belongs to an
+/// earlier session. Every message is kept if the call's start is unknown.
+fn sent_before(timestamp: OffsetDateTime, started_at: Option<OffsetDateTime>) -> bool {
+    started_at.map_or(false, |started_at| timestamp < started_at)
+}
+
+impl SessionExport {
+    /// Renders the session as a single markdown document.
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = String::new();
+        writeln!(markdown, "# Session: {}", escape_markdown(&self.title)).unwrap();
+        writeln!(markdown).unwrap();
+        if let Some(started_at) = self.started_at {
+            writeln!(markdown, "- **Started:** {}", format_timestamp(started_at)).unwrap();
+        }
+        writeln!(
+            markdown,
+            "- **Exported:** {}",
+            format_timestamp(self.exported_at)
+        )
+        .unwrap();
+
+        writeln!(markdown).unwrap();
+        writeln!(markdown, "## Call chat").unwrap();
+        writeln!(markdown).unwrap();
+        write_messages(&mut markdown, &self.room_messages);
+
+        writeln!(markdown).unwrap();
+        writeln!(markdown, "## Channel chat").unwrap();
+        writeln!(markdown).unwrap();
+        write_messages(&mut markdown, &self.channel_messages);
+
+        writeln!(markdown).unwrap();
+        writeln!(markdown, "## Notes").unwrap();
+        writeln!(markdown).unwrap();
+        match self.notes.as_deref().map(str::trim) {
+            Some(notes) if !notes.is_empty() => writeln!(markdown, "{}", notes).unwrap(),
+            _ => writeln!(markdown, "No notes were taken.").unwrap(),
+        }
+
+        writeln!(markdown).unwrap();
+        writeln!(markdown, "## Bookmarks").unwrap();
+        writeln!(markdown).unwrap();
+        if self.bookmarks.is_empty() {
+            writeln!(markdown, "No bookmarks were added.").unwrap();
+        } else {
+            for bookmark in &self.bookmarks {
+                writeln!(
+                    markdown,
+                    "- {}: {}",
+                    escape_markdown(&bookmark.name),
+                    code_span(&format!("{}:{}", bookmark.path, bookmark.row + 1))
+                )
+                .unwrap();
+            }
+        }
+        markdown
+    }
+}
+
+fn write_messages(markdown: &mut String, messages: &[ExportedMessage]) {
+    if messages.is_empty() {
+        writeln!(markdown, "No messages were sent.").unwrap();
+        return;
+    }
+    for message in messages {
+        writeln!(
+            markdown,
+            "- **@{}** ({}): {}",
+            escape_markdown(&message.sender),
+            format_timestamp(message.timestamp),
+            escape_markdown(&message.body).replace('\n', "\n  ")
+        )
+        .unwrap();
+    }
+}
+
+/// Escapes the characters that markdown would otherwise interpret, so that
+/// text typed by participants is rendered as it was written.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for char in text.chars() {
+        if "\\`*_{}[]()<>#+-!|~".contains(char) {
+            escaped.push('\\');
+        }
+        escaped.push(char);
+    }
+    escaped
+}
+
+/// Wraps text in a code span, using a fence longer than any run of backticks
+/// in the text.
+fn code_span(text: &str) -> String {
+    let longest_run = text
+        .split(|char| char != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest_run + 1);
+    if text.starts_with('`') || text.ends_with('`') {
+        format!("{fence} {text} {fence}")
+    } else {
+        format!("{fence}{text}{fence}")
+    }
+}
+
+fn format_timestamp(timestamp: OffsetDateTime) -> String {
+    let timestamp = timestamp.to_offset(time::UtcOffset::UTC);
+    format!(
+        "{} {:02}:{:02} UTC",
+        timestamp.date(),
+        timestamp.hour(),
+        timestamp.minute()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_export_to_markdown() {
+        let exported_at = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let export = SessionExport {
+            title: "#zed_dev".into(),
+            started_at: Some(exported_at - time::Duration::minutes(10)),
+            exported_at,
+            room_messages: vec![ExportedMessage {
+                sender: "user_c".into(),
+                timestamp: exported_at - time::Duration::minutes(6),
+                body: "can you share your screen?".into(),
+            }],
+            channel_messages: vec![
+                ExportedMessage {
+                    sender: "user_a".into(),
+                    timestamp: exported_at - time::Duration::minutes(5),
+                    body: "let's look at the *parser*".into(),
+                },
+                ExportedMessage {
+                    sender: "user_b".into(),
+                    timestamp: exported_at - time::Duration::minutes(3),
+                    body: "sure\n# opening it now".into(),
+                },
+            ],
+            notes: Some("\nTODO: add tests\n".into()),
+            bookmarks: vec![ExportedBookmark {
+                name: "[entry] point".into(),
+                path: "src/`main`.rs".into(),
+                row: 9,
+            }],
+        };
+
+        assert_eq!(
+            export.to_markdown(),
+            concat!(
+                "# Session: \\#zed\\_dev\n",
+                "\n",
+                "- **Started:** 2023-11-14 22:03 UTC\n",
+                "- **Exported:** 2023-11-14 22:13 UTC\n",
+                "\n",
+                "## Call chat\n",
+                "\n",
+                "- **@user\\_c** (2023-11-14 22:07 UTC): can you share your screen?\n",
+                "\n",
+                "## Channel chat\n",
+                "\n",
+                "- **@user\\_a** (2023-11-14 22:08 UTC): let's look at the \\*parser\\*\n",
+                "- **@user\\_b** (2023-11-14 22:10 UTC): sure\n",
+                "  \\# opening it now\n",
+                "\n",
+                "## Notes\n",
+                "\n",
+                "TODO: add tests\n",
+                "\n",
+                "## Bookmarks\n",
+                "\n",
+                "- \\[entry\\] point: ``src/`main`.rs:10``\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_sent_before() {
+        let started_at = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let second = time::Duration::seconds(1);
+        assert!(sent_before(started_at - second, Some(started_at)));
+        assert!(!sent_before(started_at, Some(started_at)));
+        assert!(!sent_before(started_at + second, Some(started_at)));
+        assert!(!sent_before(started_at - second, None));
+    }
+}
@@ -0,0 +1,85 @@
+use editor::Editor;
+use gpui::{
+    div, prelude::*, AppContext, DismissEvent, EventEmitter, FocusHandle, FocusableView, Render,
+    SharedString, View, ViewContext, WindowContext,
+};
+use ui::{h_flex, prelude::*, v_flex, Label};
+use workspace::{ModalView, Workspace};
+
+/// A modal that asks for a line of text, for the collaboration features that
+/// need one, such as commenting on a review.
+pub struct TextPrompt {
+    editor: View<Editor>,
+    prompt: SharedString,
+    on_confirm: Option<Box<dyn FnOnce(String, &mut WindowContext)>>,
+}
+
+impl TextPrompt {
+    /// Shows a prompt in the workspace, which calls `on_confirm` with the text that
+    /// was entered, unless it's dismissed or left empty.
+    pub fn show(
+        workspace: &mut Workspace,
+        prompt: impl Into<SharedString>,
+        initial_text: &str,
+        on_confirm: impl FnOnce(String, &mut WindowContext) + 'static,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let prompt = prompt.into();
+        let initial_text = initial_text.to_string();
+        workspace.toggle_modal(cx, move |cx| {
+            let editor = cx.new_view(|cx| {
+                let mut editor = Editor::single_line(cx);
+                editor.set_text(initial_text, cx);
+                editor.select_all(&Default::default(), cx);
+                editor
+            });
+            Self {
+                editor,
+                prompt,
+                on_confirm: Some(Box::new(on_confirm)),
+            }
+        });
+    }
+
+    fn cancel(&mut self, _: &menu::Cancel, cx: &mut ViewContext<Self>) {
+        cx.emit(DismissEvent);
+    }
+
+    fn confirm(&mut self, _: &menu::Confirm, cx: &mut ViewContext<Self>) {
+        let text = self.editor.read(cx).text(cx);
+        if !text.trim().is_empty() {
+            if let Some(on_confirm) = self.on_confirm.take() {
+                on_confirm(text, cx);
+            }
+        }
+        cx.emit(DismissEvent);
+    }
+}
+
+impl ModalView for TextPrompt {}
+
+impl EventEmitter<DismissEvent> for TextPrompt {}
+
+impl FocusableView for TextPrompt {
+    fn focus_handle(&self, cx: &AppContext) -> FocusHandle {
+        self.editor.focus_handle(cx)
+    }
+}
+
+impl Render for TextPrompt {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        v_flex()
+            .elevation_2(cx)
+            .key_context("TextPrompt")
+            .on_action(cx.listener(Self::cancel))
+            .on_action(cx.listener(Self::confirm))
+            .w_96()
+            .child(
+                h_flex()
+                    .px_2()
+                    .py_1()
+                    .child(Label::new(self.prompt.clone()).color(Color::Muted)),
+            )
+            .child(div().px_2().py_1().child(self.editor.clone()))
+    }
+}
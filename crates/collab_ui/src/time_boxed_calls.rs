@@ -0,0 +1,75 @@
+use crate::ExtendCall;
+use call::{room, ActiveCall};
+use gpui::{Action as _, AppContext, Model, ViewContext};
+use std::time::Duration;
+use workspace::{notifications::DetachAndPromptErr, Toast, Workspace};
+
+/// How long calls placed with a time limit last, unless they're extended.
+pub const TIME_BOXED_CALL_DURATION: Duration = Duration::from_secs(30 * 60);
+
+/// How much longer [`ExtendCall`] makes a call with a time limit last.
+const CALL_EXTENSION: Duration = Duration::from_secs(15 * 60);
+
+const TIME_BOXED_CALL_TOAST_ID: usize = 0x7b0c3e51;
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|workspace: &mut Workspace, cx| {
+        workspace.register_action(extend_call);
+        if let Some(call) = ActiveCall::try_global(cx) {
+            cx.subscribe(&call, on_call_event).detach();
+        }
+    })
+    .detach();
+}
+
+fn extend_call(_: &mut Workspace, _: &ExtendCall, cx: &mut ViewContext<Workspace>) {
+    let Some(room) = ActiveCall::global(cx).read(cx).room().cloned() else {
+        return;
+    };
+    room.update(cx, |room, cx| room.extend(CALL_EXTENSION, cx))
+        .detach_and_prompt_err("Failed to extend call", cx, |_, _| None);
+}
+
+/// Warns that a call with a time limit is about to end, offering to extend it to
+/// those who can, and says why the call ended once it has.
+fn on_call_event(
+    workspace: &mut Workspace,
+    call: Model<ActiveCall>,
+    event: &room::Event,
+    cx: &mut ViewContext<Workspace>,
+) {
+    match event {
+        room::Event::CountdownWarning { remaining } => {
+            let can_extend = call
+                .read(cx)
+                .room()
+                .map_or(false, |room| room.read(cx).local_participant_is_admin());
+            let mut toast = Toast::new(TIME_BOXED_CALL_TOAST_ID, countdown_message(*remaining));
+            if can_extend {
+                toast = toast.on_click("Extend", |cx| cx.dispatch_action(ExtendCall.boxed_clone()));
+            }
+            workspace.show_toast(toast, cx);
+        }
+        room::Event::Ended => {
+            workspace.show_toast(
+                Toast::new(
+                    TIME_BOXED_CALL_TOAST_ID,
+                    "The call ended because its time ran out",
+                ),
+                cx,
+            );
+        }
+        _ => {}
+    }
+}
+
+fn countdown_message(remaining: Duration) -> String {
+    let secs = remaining.as_secs();
+    if secs >= 120 {
+        format!("The call ends in {} minutes", secs / 60)
+    } else if secs >= 60 {
+        "The call ends in a minute".to_string()
+    } else {
+        format!("The call ends in {secs} seconds")
+    }
+}
@@ -195,6 +195,7 @@ gpui::actions!(
         NextScreen,
         OpenExcerpts,
         OpenPermalinkToLine,
+        OpenReview,
         Outdent,
         PageDown,
         PageUp,
@@ -233,6 +234,7 @@ gpui::actions!(
         SplitSelectionIntoLines,
         Tab,
         TabPrev,
+        ToggleEditAttribution,
         ToggleInlayHints,
         ToggleSoftWrap,
         Transpose,
@@ -88,7 +88,7 @@ pub use multi_buffer::{
 };
 use ordered_float::OrderedFloat;
 use parking_lot::{Mutex, RwLock};
-use project::{FormatTrigger, Location, Project, ProjectPath, ProjectTransaction};
+use project::{ChangedFile, FormatTrigger, Location, Project, ProjectPath, ProjectTransaction};
 use rand::prelude::*;
 use rpc::proto::*;
 use scroll::{Autoscroll, OngoingScroll, ScrollAnchor, ScrollManager, ScrollbarAutoHide};
@@ -133,6 +133,8 @@ pub(crate) const CURSORS_VISIBLE_FOR: Duration = Duration::from_millis(2000);
 pub const CODE_ACTIONS_DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(250);
 #[doc(hidden)]
 pub const DOCUMENT_HIGHLIGHTS_DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(75);
+#[doc(hidden)]
+pub const LINKED_EDITING_RANGES_DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(75);
 
 pub(crate) const FORMAT_TIMEOUT: Duration = Duration::from_secs(2);
 
@@ -216,7 +218,9 @@ impl InlayId {
 
 enum DocumentHighlightRead {}
 enum DocumentHighlightWrite {}
+enum EditAttribution {}
 enum InputComposition {}
+enum ReviewComments {}
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum Direction {
@@ -238,6 +242,7 @@ pub fn init(cx: &mut AppContext) {
         |workspace: &mut Workspace, _cx: &mut ViewContext<Workspace>| {
             workspace.register_action(Editor::new_file);
             workspace.register_action(Editor::new_file_in_direction);
+            workspace.register_action(Editor::open_review);
         },
     )
     .detach();
@@ -391,6 +396,8 @@ pub struct Editor {
     available_code_actions: Option<(Model<Buffer>, Arc<[CodeAction]>)>,
     code_actions_task: Option<Task<()>>,
     document_highlights_task: Option<Task<()>>,
+    linked_editing_ranges: Option<(Model<Buffer>, Vec<Range<text::Anchor>>)>,
+    linked_editing_ranges_task: Option<Task<Option<()>>>,
     pending_rename: Option<RenameState>,
     searchable: bool,
     cursor_shape: CursorShape,
@@ -409,6 +416,9 @@ pub struct Editor {
     copilot_state: CopilotState,
     inlay_hint_cache: InlayHintCache,
     next_inlay_id: usize,
+    /// The inlays naming who wrote the spans highlighted by
+    /// [`Editor::toggle_edit_attribution`], while they are shown.
+    edit_attribution_inlays: Option<Vec<InlayId>>,
     _subscriptions: Vec<Subscription>,
     pixel_position_of_newest_cursor: Option<gpui::Point<Pixels>>,
     gutter_width: Pixels,
@@ -1513,9 +1523,12 @@ impl Editor {
             next_completion_id: 0,
             completion_documentation_pre_resolve_debounce: DebouncedDelay::new(),
             next_inlay_id: 0,
+            edit_attribution_inlays: None,
             available_code_actions: Default::default(),
             code_actions_task: Default::default(),
             document_highlights_task: Default::default(),
+            linked_editing_ranges: Default::default(),
+            linked_editing_ranges_task: Default::default(),
             pending_rename: Default::default(),
             searchable: true,
             cursor_shape: Default::default(),
@@ -1934,6 +1947,7 @@ impl Editor {
             }
             self.refresh_code_actions(cx);
             self.refresh_document_highlights(cx);
+            self.refresh_linked_editing_ranges(cx);
             refresh_matching_bracket_highlights(self, cx);
             self.discard_copilot_suggestion(cx);
         }
@@ -2497,10 +2511,14 @@ impl Editor {
         }
 
         drop(snapshot);
+        let linked_edits = self.linked_edits(&edits, cx);
         self.transact(cx, |this, cx| {
             this.buffer.update(cx, |buffer, cx| {
                 buffer.edit(edits, this.autoindent_mode.clone(), cx);
             });
+            if let Some((buffer, linked_edits)) = linked_edits {
+                buffer.update(cx, |buffer, cx| buffer.edit(linked_edits, None, cx));
+            }
 
             let new_anchor_selections = new_selections.iter().map(|e| &e.0);
             let new_selection_deltas = new_selections.iter().map(|e| e.1);
@@ -2707,6 +2725,12 @@ impl Editor {
             this.change_selections(Some(Autoscroll::fit()), cx, |s| s.select(new_selections));
             this.refresh_copilot_suggestions(true, cx);
         });
+
+        if EditorSettings::get_global(cx).use_on_type_format {
+            if let Some(on_type_format_task) = self.trigger_on_type_formatting("\n".into(), cx) {
+                on_type_format_task.detach_and_log_err(cx);
+            }
+        }
     }
 
     pub fn newline_above(&mut self, _: &NewlineAbove, cx: &mut ViewContext<Self>) {
@@ -3007,6 +3031,70 @@ impl Editor {
         self.inlay_hint_cache.enabled
     }
 
+    /// Shows or hides who wrote which spans of the buffer while its project was
+    /// shared, highlighting the spans and naming their authors at the end of
+    /// the line each span ends on.
+    pub fn toggle_edit_attribution(
+        &mut self,
+        _: &ToggleEditAttribution,
+        cx: &mut ViewContext<Self>,
+    ) {
+        if let Some(inlay_ids) = self.edit_attribution_inlays.take() {
+            self.clear_background_highlights::<EditAttribution>(cx);
+            self.splice_inlay_hints(inlay_ids, Vec::new(), cx);
+            return;
+        }
+
+        let Some(project) = self.project.clone() else {
+            return;
+        };
+        let Some(buffer) = self.buffer.read(cx).as_singleton() else {
+            return;
+        };
+        let attribution = project.update(cx, |project, cx| project.edit_attribution(&buffer, cx));
+        let snapshot = self.buffer.read(cx).snapshot(cx);
+        let Some((&excerpt_id, _, buffer_snapshot)) = snapshot.as_singleton() else {
+            return;
+        };
+
+        let mut ranges = Vec::new();
+        let mut authors_by_row = BTreeMap::<u32, Vec<&str>>::new();
+        for attributed_range in &attribution {
+            let range = attributed_range.range.to_point(buffer_snapshot);
+            let last_row = if range.end.column == 0 && range.end.row > range.start.row {
+                range.end.row - 1
+            } else {
+                range.end.row
+            };
+            let authors = authors_by_row.entry(last_row).or_default();
+            if !authors.contains(&attributed_range.author.github_login.as_str()) {
+                authors.push(&attributed_range.author.github_login);
+            }
+            ranges.push(
+                snapshot.anchor_in_excerpt(excerpt_id, attributed_range.range.start)
+                    ..snapshot.anchor_in_excerpt(excerpt_id, attributed_range.range.end),
+            );
+        }
+        let inlays = authors_by_row
+            .into_iter()
+            .map(|(row, authors)| {
+                Inlay::suggestion(
+                    post_inc(&mut self.next_inlay_id),
+                    snapshot.anchor_after(Point::new(row, snapshot.line_len(row))),
+                    format!("  edited by {}", authors.join(", ")),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        self.edit_attribution_inlays = Some(inlays.iter().map(|inlay| inlay.id).collect());
+        self.highlight_background::<EditAttribution>(
+            ranges,
+            |colors| colors.editor_document_highlight_read_background,
+            cx,
+        );
+        self.splice_inlay_hints(Vec::new(), inlays, cx);
+    }
+
     fn refresh_inlay_hints(&mut self, reason: InlayHintRefreshReason, cx: &mut ViewContext<Self>) {
         if self.project.is_none() || self.mode != EditorMode::Full {
             return;
@@ -3527,16 +3615,25 @@ impl Editor {
         let buffer = actions_menu.buffer;
         let workspace = self.workspace()?;
 
-        let apply_code_actions = workspace
-            .read(cx)
-            .project()
-            .clone()
-            .update(cx, |project, cx| {
-                project.apply_code_action(buffer, action, true, cx)
-            });
+        let project = workspace.read(cx).project().clone();
+        let is_remote = project.read(cx).is_remote();
+        let apply_code_actions = project.update(cx, |project, cx| {
+            project.apply_code_action(buffer, action, true, cx)
+        });
         let workspace = workspace.downgrade();
-        Some(cx.spawn(|editor, cx| async move {
+        Some(cx.spawn(|editor, mut cx| async move {
             let project_transaction = apply_code_actions.await?;
+            // The host applied the action on the guest's behalf, so tell the guest
+            // which files it changed.
+            if is_remote {
+                let changed_files = cx.update(|cx| project_transaction.changed_files(cx))?;
+                if !changed_files.is_empty() {
+                    let message = changed_files_message(&title, &changed_files);
+                    workspace.update(&mut cx, |workspace, cx| {
+                        workspace.show_toast(Toast::new(0x2c0de5c7, message), cx)
+                    })?;
+                }
+            }
             Self::open_project_transaction(&editor, workspace, project_transaction, title, cx).await
         }))
     }
@@ -3666,6 +3763,109 @@ impl Editor {
         None
     }
 
+    fn refresh_linked_editing_ranges(&mut self, cx: &mut ViewContext<Self>) -> Option<()> {
+        let project = self.project.clone()?;
+        let cursor_position = self.selections.newest_anchor().head();
+        let Some((cursor_buffer, cursor_buffer_position)) = self
+            .buffer
+            .read(cx)
+            .text_anchor_for_position(cursor_position, cx)
+        else {
+            self.linked_editing_ranges.take();
+            return None;
+        };
+
+        // Keep using the current ranges while the cursor stays within them, so
+        // that typing doesn't discard them.
+        if let Some((buffer, ranges)) = &self.linked_editing_ranges {
+            if *buffer == cursor_buffer {
+                let buffer = buffer.read(cx);
+                if ranges.iter().any(|range| {
+                    range.start.cmp(&cursor_buffer_position, buffer).is_le()
+                        && range.end.cmp(&cursor_buffer_position, buffer).is_ge()
+                }) {
+                    return Some(());
+                }
+            }
+        }
+
+        self.linked_editing_ranges.take();
+        self.linked_editing_ranges_task = Some(cx.spawn(|this, mut cx| async move {
+            cx.background_executor()
+                .timer(LINKED_EDITING_RANGES_DEBOUNCE_TIMEOUT)
+                .await;
+
+            let ranges = project
+                .update(&mut cx, |project, cx| {
+                    project.linked_editing_ranges(&cursor_buffer, cursor_buffer_position, cx)
+                })
+                .log_err()?
+                .await
+                .log_err()?;
+
+            this.update(&mut cx, |this, cx| {
+                let cursor_position = this.selections.newest_anchor().head();
+                let cursor_moved_to_other_buffer = this
+                    .buffer
+                    .read(cx)
+                    .text_anchor_for_position(cursor_position, cx)
+                    .map_or(true, |(buffer, _)| buffer != cursor_buffer);
+                if ranges.len() > 1 && !cursor_moved_to_other_buffer {
+                    this.linked_editing_ranges = Some((cursor_buffer, ranges));
+                }
+            })
+            .ok()
+        }));
+        None
+    }
+
+    /// Returns the edits mirroring the given one into the other linked editing
+    /// ranges, if it is contained within one of them.
+    fn linked_edits(
+        &self,
+        edits: &[(Range<Point>, Arc<str>)],
+        cx: &AppContext,
+    ) -> Option<(Model<Buffer>, Vec<(Range<text::Anchor>, Arc<str>)>)> {
+        use language::ToOffset as _;
+
+        let (linked_buffer, ranges) = self.linked_editing_ranges.as_ref()?;
+        let [(edited_range, text)] = edits else {
+            return None;
+        };
+
+        let multibuffer = self.buffer.read(cx);
+        let (start_buffer, start) = multibuffer.text_anchor_for_position(edited_range.start, cx)?;
+        let (end_buffer, end) = multibuffer.text_anchor_for_position(edited_range.end, cx)?;
+        if start_buffer != *linked_buffer || end_buffer != *linked_buffer {
+            return None;
+        }
+
+        let buffer = linked_buffer.read(cx);
+        let start = start.to_offset(buffer);
+        let end = end.to_offset(buffer);
+        let ranges = ranges
+            .iter()
+            .map(|range| range.to_offset(buffer))
+            .collect::<Vec<_>>();
+        let edited_ix = ranges
+            .iter()
+            .position(|range| range.start <= start && end <= range.end)?;
+        let start_delta = start - ranges[edited_ix].start;
+        let end_delta = end - ranges[edited_ix].start;
+
+        let linked_edits = ranges
+            .iter()
+            .enumerate()
+            .filter(|(ix, range)| *ix != edited_ix && range.start + end_delta <= range.end)
+            .map(|(_, range)| {
+                let start = buffer.anchor_before(range.start + start_delta);
+                let end = buffer.anchor_after(range.start + end_delta);
+                (start..end, text.clone())
+            })
+            .collect();
+        Some((linked_buffer.clone(), linked_edits))
+    }
+
     fn refresh_document_highlights(&mut self, cx: &mut ViewContext<Self>) -> Option<()> {
         if self.pending_rename.is_some() {
             return None;
@@ -7640,6 +7840,76 @@ impl Editor {
         }
     }
 
+    /// Opens the changes under review in the project in a multibuffer, with the
+    /// spans that have been commented on highlighted.
+    pub fn open_review(workspace: &mut Workspace, _: &OpenReview, cx: &mut ViewContext<Workspace>) {
+        let project = workspace.project().clone();
+        let open_excerpts = project.update(cx, |project, cx| project.open_review_excerpts(cx));
+        cx.spawn(|workspace, mut cx| async move {
+            let locations = open_excerpts.await?;
+            workspace.update(&mut cx, |workspace, cx| {
+                let project = workspace.project().read(cx);
+                let replica_id = project.replica_id();
+                let capability = project.capability();
+                let comments = project
+                    .review()
+                    .map(|review| review.comments.clone())
+                    .unwrap_or_default();
+
+                let mut excerpts = Vec::new();
+                let excerpt_buffer = cx.new_model(|cx| {
+                    let mut multibuffer = MultiBuffer::new(replica_id, capability);
+                    for location in locations {
+                        let excerpt_ids = multibuffer.push_excerpts(
+                            location.buffer.clone(),
+                            [ExcerptRange {
+                                context: location.range.clone(),
+                                primary: None,
+                            }],
+                            cx,
+                        );
+                        excerpts.extend(
+                            excerpt_ids
+                                .into_iter()
+                                .map(|excerpt_id| (excerpt_id, location.clone())),
+                        );
+                    }
+                    multibuffer.with_title("Review".into())
+                });
+
+                let snapshot = excerpt_buffer.read(cx).snapshot(cx);
+                let mut comment_ranges = Vec::new();
+                for (excerpt_id, location) in &excerpts {
+                    let buffer = location.buffer.read(cx);
+                    for comment in &comments {
+                        if comment.buffer_id == buffer.remote_id()
+                            && comment.range.start.cmp(&location.range.end, buffer).is_le()
+                            && comment.range.end.cmp(&location.range.start, buffer).is_ge()
+                        {
+                            comment_ranges.push(
+                                snapshot.anchor_in_excerpt(*excerpt_id, comment.range.start)
+                                    ..snapshot.anchor_in_excerpt(*excerpt_id, comment.range.end),
+                            );
+                        }
+                    }
+                }
+
+                let editor = cx.new_view(|cx| {
+                    Editor::for_multibuffer(excerpt_buffer, Some(workspace.project().clone()), cx)
+                });
+                editor.update(cx, |editor, cx| {
+                    editor.highlight_background::<ReviewComments>(
+                        comment_ranges,
+                        |colors| colors.editor_document_highlight_read_background,
+                        cx,
+                    );
+                });
+                workspace.add_item(Box::new(editor), cx);
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
     pub fn rename(&mut self, _: &Rename, cx: &mut ViewContext<Self>) -> Option<Task<Result<()>>> {
         use language::ToOffset as _;
 
@@ -9465,6 +9735,32 @@ fn inlay_hint_settings(
         .inlay_hints
 }
 
+/// Describes the files a code action changed, such as
+/// `Inline into all callers changed main.rs (1 edit) and other.rs (2 edits)`.
+fn changed_files_message(title: &str, changed_files: &[ChangedFile]) -> String {
+    let mut files = changed_files
+        .iter()
+        .map(|file| {
+            let path = file.path.as_ref().map_or_else(
+                || "an untitled buffer".to_string(),
+                |path| path.path.to_string_lossy().into_owned(),
+            );
+            let edits = if file.edit_count == 1 {
+                "edit"
+            } else {
+                "edits"
+            };
+            format!("{path} ({} {edits})", file.edit_count)
+        })
+        .collect::<Vec<_>>();
+    let last = files.pop().unwrap_or_default();
+    if files.is_empty() {
+        format!("{title} changed {last}")
+    } else {
+        format!("{title} changed {} and {last}", files.join(", "))
+    }
+}
+
 fn consume_contiguous_rows(
     contiguous_row_selections: &mut Vec<Selection<Point>>,
     selection: &Selection<Point>,
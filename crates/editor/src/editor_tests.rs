@@ -7338,6 +7338,33 @@ fn test_split_words() {
     assert_eq!(split("helloworld"), &["helloworld"]);
 }
 
+#[test]
+fn test_changed_files_message() {
+    let changed_file = |path: Option<&str>, edit_count| ChangedFile {
+        path: path.map(|path| ProjectPath {
+            worktree_id: project::WorktreeId::from_usize(1),
+            path: Path::new(path).into(),
+        }),
+        edit_count,
+    };
+
+    assert_eq!(
+        changed_files_message("Fix", &[changed_file(Some("a.rs"), 1)]),
+        "Fix changed a.rs (1 edit)"
+    );
+    assert_eq!(
+        changed_files_message(
+            "Inline",
+            &[
+                changed_file(None, 1),
+                changed_file(Some("a.rs"), 2),
+                changed_file(Some("b.rs"), 3),
+            ]
+        ),
+        "Inline changed an untitled buffer (1 edit), a.rs (2 edits) and b.rs (3 edits)"
+    );
+}
+
 #[gpui::test]
 async fn test_move_to_enclosing_bracket(cx: &mut gpui::TestAppContext) {
     init_test(cx, |_| {});
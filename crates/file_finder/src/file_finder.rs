@@ -373,6 +373,12 @@ impl FileFinderDelegate {
         })
         .detach();
 
+        // Guests of large projects only have the entries of the directories they've
+        // opened, so fetch the rest, refreshing the matches as they arrive.
+        project
+            .update(cx, |project, cx| project.load_all_entries(cx))
+            .detach_and_log_err(cx);
+
         Self {
             file_finder,
             workspace,
@@ -33,6 +33,8 @@ use util::ResultExt;
 #[cfg(any(test, feature = "test-support"))]
 use collections::{btree_map, BTreeMap};
 #[cfg(any(test, feature = "test-support"))]
+use rand::{seq::SliceRandom, Rng};
+#[cfg(any(test, feature = "test-support"))]
 use repository::{FakeGitRepositoryState, GitFileStatus};
 #[cfg(any(test, feature = "test-support"))]
 use std::ffi::OsStr;
@@ -403,6 +405,7 @@ struct FakeFsState {
     event_txs: Vec<smol::channel::Sender<Vec<fsevent::Event>>>,
     events_paused: bool,
     buffered_events: Vec<fsevent::Event>,
+    event_delivery_rng: Option<rand::rngs::StdRng>,
     metadata_call_count: usize,
     read_dir_call_count: usize,
 }
@@ -517,17 +520,38 @@ impl FakeFsState {
             }));
 
         if !self.events_paused {
-            self.flush_events(self.buffered_events.len());
+            let mut count = self.buffered_events.len();
+            if let Some(rng) = self.event_delivery_rng.as_mut() {
+                // Hold back a random suffix of the events, simulating delayed delivery.
+                if rng.gen_bool(0.3) {
+                    count = rng.gen_range(0..=count);
+                }
+            }
+            self.flush_events(count);
         }
     }
 
     fn flush_events(&mut self, mut count: usize) {
         count = count.min(self.buffered_events.len());
-        let events = self.buffered_events.drain(0..count).collect::<Vec<_>>();
-        self.event_txs.retain(|tx| {
-            let _ = tx.try_send(events.clone());
-            !tx.is_closed()
-        });
+        let mut events = self.buffered_events.drain(0..count).collect::<Vec<_>>();
+        let batches = if let Some(rng) = self.event_delivery_rng.as_mut() {
+            events.shuffle(rng);
+            let mut batches = Vec::new();
+            while !events.is_empty() {
+                let len = rng.gen_range(1..=events.len());
+                batches.push(events.drain(0..len).collect::<Vec<_>>());
+            }
+            batches
+        } else {
+            vec![events]
+        };
+
+        for events in batches {
+            self.event_txs.retain(|tx| {
+                let _ = tx.try_send(events.clone());
+                !tx.is_closed()
+            });
+        }
     }
 }
 
@@ -553,6 +577,7 @@ impl FakeFs {
                 event_txs: Default::default(),
                 buffered_events: Vec::new(),
                 events_paused: false,
+                event_delivery_rng: None,
                 read_dir_call_count: 0,
                 metadata_call_count: 0,
             }),
@@ -621,6 +646,13 @@ impl FakeFs {
         self.state.lock().flush_events(count);
     }
 
+    /// Drive event delivery from the test RNG: flushed events are shuffled and
+    /// split into randomly-sized batches, and some events are held back until
+    /// the next write or an explicit call to [`Self::flush_events`].
+    pub fn randomize_event_delivery(&self) {
+        self.state.lock().event_delivery_rng = Some(self.executor.rng());
+    }
+
     #[must_use]
     pub fn insert_tree<'a>(
         &'a self,
@@ -866,15 +898,25 @@ impl Fs for FakeFs {
             state.next_mtime += Duration::from_nanos(1);
             state.next_inode += 1;
             state.write_path(&cur_path, |entry| {
-                entry.or_insert_with(|| {
-                    created_dirs.push(cur_path.clone());
-                    Arc::new(Mutex::new(FakeFsEntry::Dir {
-                        inode,
-                        mtime,
-                        entries: Default::default(),
-                        git_repo_state: None,
-                    }))
-                });
+                match entry {
+                    btree_map::Entry::Occupied(e) => {
+                        if e.get().lock().is_file() {
+                            return Err(anyhow!(
+                                "path already exists and is not a directory: {}",
+                                cur_path.display()
+                            ));
+                        }
+                    }
+                    btree_map::Entry::Vacant(e) => {
+                        created_dirs.push(cur_path.clone());
+                        e.insert(Arc::new(Mutex::new(FakeFsEntry::Dir {
+                            inode,
+                            mtime,
+                            entries: Default::default(),
+                            git_repo_state: None,
+                        })));
+                    }
+                }
                 Ok(())
             })?
         }
@@ -1091,6 +1133,11 @@ impl Fs for FakeFs {
         if let Some(path) = path.parent() {
             self.create_dir(path).await?;
         }
+        if let Some((entry, _)) = self.state.lock().try_read_path(&path, true) {
+            if let FakeFsEntry::Dir { .. } = &*entry.lock() {
+                return Err(anyhow!("is a directory: {}", path.display()));
+            }
+        }
         self.write_file_internal(path, content)?;
         Ok(())
     }
@@ -1383,4 +1430,40 @@ mod tests {
             "D",
         );
     }
+
+    #[gpui::test]
+    async fn test_fake_fs_entry_kind_collisions(executor: BackgroundExecutor) {
+        let fs = FakeFs::new(executor.clone());
+        fs.insert_tree(
+            "/root",
+            json!({
+                "dir": {},
+                "file": "F",
+            }),
+        )
+        .await;
+
+        // Like a real file system, a file can't be written over a directory, and a
+        // directory can't be created over a file or beneath one.
+        assert!(fs
+            .save("/root/dir".as_ref(), &"X".into(), LineEnding::Unix)
+            .await
+            .is_err());
+        assert!(fs.create_dir("/root/file".as_ref()).await.is_err());
+        assert!(fs.create_dir("/root/file/subdir".as_ref()).await.is_err());
+        assert!(fs
+            .save("/root/file/other".as_ref(), &"X".into(), LineEnding::Unix)
+            .await
+            .is_err());
+
+        assert_eq!(fs.directories(false).len(), 3);
+        assert_eq!(fs.load("/root/file".as_ref()).await.unwrap(), "F");
+
+        fs.create_dir("/root/dir/a/b".as_ref()).await.unwrap();
+        fs.save("/root/file".as_ref(), &"G".into(), LineEnding::Unix)
+            .await
+            .unwrap();
+        assert_eq!(fs.directories(false).len(), 5);
+        assert_eq!(fs.load("/root/file".as_ref()).await.unwrap(), "G");
+    }
 }
@@ -820,6 +820,12 @@ impl AppContext {
         &self.text_system
     }
 
+    /// Drops the image fetched from the given URI, so that it is fetched again
+    /// the next time it is rendered.
+    pub fn forget_image(&self, uri: impl Into<SharedUri>) {
+        self.image_cache.remove(uri.into());
+    }
+
     /// The current text style. Which is composed of all the style refinements provided to `with_text_style`.
     pub fn text_style(&self) -> TextStyle {
         let mut style = TextStyle::default();
@@ -354,6 +354,55 @@ impl BackgroundExecutor {
         self.dispatcher.as_test().unwrap().rng()
     }
 
+    /// in tests, returns how many tasks the dispatcher has run so far
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn tasks_run(&self) -> usize {
+        self.dispatcher.as_test().unwrap().tasks_run()
+    }
+
+    /// in tests, returns how much time has passed on the dispatcher's simulated clock
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn simulated_time(&self) -> Duration {
+        self.dispatcher.as_test().unwrap().simulated_time()
+    }
+
+    /// in tests, marks the described operation as being applied, so that it's named if the
+    /// executor hangs. Pass the returned id to `finish_operation` once it has been applied.
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn start_operation(&self, description: String) -> usize {
+        self.dispatcher
+            .as_test()
+            .unwrap()
+            .start_operation(description)
+    }
+
+    /// in tests, records how many tasks ran while applying an operation started with
+    /// `start_operation`, so that it shows up in the execution report (see `EXECUTION_REPORT`)
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn finish_operation(&self, id: usize) {
+        self.dispatcher.as_test().unwrap().finish_operation(id)
+    }
+
+    /// in tests, returns how many tasks may run without parking, or while applying a single
+    /// operation, before the executor panics (see `TICK_BUDGET`)
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn tick_budget(&self) -> Option<usize> {
+        self.dispatcher.as_test().unwrap().tick_budget()
+    }
+
+    /// in tests, makes the executor panic with a description of its pending work if it runs
+    /// more than `budget` tasks without parking, or while applying a single operation
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn set_tick_budget(&self, budget: Option<usize>) {
+        self.dispatcher.as_test().unwrap().set_tick_budget(budget)
+    }
+
+    /// in tests, summarizes the work the dispatcher has done so far
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn execution_report(&self) -> crate::ExecutionReport {
+        self.dispatcher.as_test().unwrap().execution_report()
+    }
+
     /// How many CPUs are available to the dispatcher
     pub fn num_cpus(&self) -> usize {
         num_cpus::get()
@@ -129,4 +129,8 @@ impl ImageCache {
             }
         }
     }
+
+    pub fn remove(&self, uri_or_path: impl Into<UriOrPath>) {
+        self.images.lock().remove(&uri_or_path.into());
+    }
 }
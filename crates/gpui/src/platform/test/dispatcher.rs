@@ -1,4 +1,4 @@
-use crate::{PlatformDispatcher, TaskLabel};
+use crate::{ExecutionReport, PlatformDispatcher, TaskLabel};
 use async_task::Runnable;
 use backtrace::Backtrace;
 use collections::{HashMap, HashSet, VecDeque};
@@ -39,6 +39,18 @@ struct TestDispatcherState {
     waiting_backtrace: Option<Backtrace>,
     deprioritized_task_labels: HashSet<TaskLabel>,
     block_on_ticks: RangeInclusive<usize>,
+    tasks_run: usize,
+    operations: Vec<(String, usize)>,
+    operations_in_progress: Vec<OperationInProgress>,
+    next_operation_id: usize,
+    tick_budget: Option<usize>,
+    ticks_since_parked: usize,
+}
+
+struct OperationInProgress {
+    id: usize,
+    description: String,
+    tasks_run_before: usize,
 }
 
 impl TestDispatcher {
@@ -57,6 +69,12 @@ impl TestDispatcher {
             waiting_backtrace: None,
             deprioritized_task_labels: Default::default(),
             block_on_ticks: 0..=1000,
+            tasks_run: 0,
+            operations: Vec::new(),
+            operations_in_progress: Vec::new(),
+            next_operation_id: 0,
+            tick_budget: None,
+            ticks_since_parked: 0,
         };
 
         TestDispatcher {
@@ -147,6 +165,64 @@ impl TestDispatcher {
         self.state.lock().random.clone()
     }
 
+    pub fn tasks_run(&self) -> usize {
+        self.state.lock().tasks_run
+    }
+
+    pub fn simulated_time(&self) -> Duration {
+        self.state.lock().time
+    }
+
+    /// Marks the described operation as being applied, so that it's named if the
+    /// executor hangs. Returns an id to pass to `finish_operation`.
+    pub fn start_operation(&self, description: String) -> usize {
+        let mut state = self.state.lock();
+        let id = post_inc(&mut state.next_operation_id);
+        let tasks_run_before = state.tasks_run;
+        state.operations_in_progress.push(OperationInProgress {
+            id,
+            description,
+            tasks_run_before,
+        });
+        id
+    }
+
+    /// Records how many tasks ran while the operation was applied, so that it shows
+    /// up in the execution report.
+    pub fn finish_operation(&self, id: usize) {
+        let mut state = self.state.lock();
+        if let Some(ix) = state
+            .operations_in_progress
+            .iter()
+            .position(|operation| operation.id == id)
+        {
+            let operation = state.operations_in_progress.remove(ix);
+            let steps = state.tasks_run - operation.tasks_run_before;
+            state.operations.push((operation.description, steps));
+        }
+    }
+
+    pub fn tick_budget(&self) -> Option<usize> {
+        self.state.lock().tick_budget
+    }
+
+    /// Makes the dispatcher panic if it runs more than `budget` tasks without
+    /// parking, or while a single operation is being applied.
+    pub fn set_tick_budget(&self, budget: Option<usize>) {
+        self.state.lock().tick_budget = budget;
+    }
+
+    pub fn execution_report(&self) -> ExecutionReport {
+        self.state.lock().execution_report()
+    }
+
+    /// Returns a function that reports on this dispatcher's execution, which remains
+    /// usable after the dispatcher has been moved into a test.
+    pub(crate) fn execution_reporter(&self) -> impl Fn() -> ExecutionReport {
+        let state = self.state.clone();
+        move || state.lock().execution_report()
+    }
+
     pub fn set_block_on_ticks(&self, range: std::ops::RangeInclusive<usize>) {
         self.state.lock().block_on_ticks = range;
     }
@@ -158,6 +234,57 @@ impl TestDispatcher {
     }
 }
 
+impl TestDispatcherState {
+    fn execution_report(&self) -> ExecutionReport {
+        let mut operations = self.operations.clone();
+        operations.sort_by(|(_, a), (_, b)| b.cmp(a));
+        ExecutionReport {
+            simulated_time: self.time,
+            tasks_run: self.tasks_run,
+            operations,
+        }
+    }
+
+    /// Describes why the dispatcher looks hung, if it has exceeded its tick budget.
+    fn detect_hang(&mut self) -> Option<String> {
+        let budget = self.tick_budget?;
+        let mut report = if self.ticks_since_parked > budget {
+            format!("ran {} tasks without parking", self.ticks_since_parked)
+        } else {
+            let operation = self
+                .operations_in_progress
+                .iter()
+                .find(|operation| self.tasks_run - operation.tasks_run_before > budget)?;
+            format!(
+                "ran more than {budget} tasks while applying operation: {}",
+                operation.description
+            )
+        };
+
+        report.push_str("\noperations in progress:");
+        for operation in &self.operations_in_progress {
+            let steps = self.tasks_run - operation.tasks_run_before;
+            report.push_str(&format!("\n  {steps:>8} tasks: {}", operation.description));
+        }
+        let foreground_len: usize = self.foreground.values().map(VecDeque::len).sum();
+        report.push_str(&format!(
+            "\npending tasks: {} foreground, {} background, {} deprioritized",
+            foreground_len,
+            self.background.len(),
+            self.deprioritized_background.len(),
+        ));
+        report.push_str(&format!("\npending timers at {:?}:", self.time));
+        for (due_time, _) in &self.delayed {
+            report.push_str(&format!("\n  due at {due_time:?}"));
+        }
+        if let Some(mut backtrace) = self.waiting_backtrace.take() {
+            backtrace.resolve();
+            report.push_str(&format!("\nbacktrace of waiting future:\n{backtrace:?}"));
+        }
+        Some(report)
+    }
+}
+
 impl Clone for TestDispatcher {
     fn clone(&self) -> Self {
         let id = post_inc(&mut self.state.lock().next_id.0);
@@ -235,6 +362,7 @@ impl PlatformDispatcher for TestDispatcher {
         if foreground_len == 0 && background_len == 0 {
             let deprioritized_background_len = state.deprioritized_background.len();
             if deprioritized_background_len == 0 {
+                state.ticks_since_parked = 0;
                 return false;
             }
             let ix = state.random.gen_range(0..deprioritized_background_len);
@@ -261,6 +389,12 @@ impl PlatformDispatcher for TestDispatcher {
             };
         };
 
+        state.tasks_run += 1;
+        state.ticks_since_parked += 1;
+        if let Some(report) = state.detect_hang() {
+            drop(state);
+            panic!("executor hung: {report}");
+        }
         let was_main_thread = state.is_main_thread;
         state.is_main_thread = main_thread;
         drop(state);
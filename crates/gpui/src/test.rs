@@ -30,8 +30,9 @@ use futures::StreamExt as _;
 use rand::prelude::*;
 use smol::channel;
 use std::{
-    env,
+    env, fmt,
     panic::{self, RefUnwindSafe},
+    time::Duration,
 };
 
 /// Run the given test function with the configured parameters.
@@ -47,6 +48,10 @@ pub fn run_test(
         .map(|seed| seed.parse().expect("invalid SEED variable"))
         .unwrap_or(0);
     let is_randomized = num_iterations > 1;
+    let report_execution = env::var("EXECUTION_REPORT").is_ok();
+    let tick_budget = env::var("TICK_BUDGET")
+        .map(|budget| budget.parse().expect("invalid TICK_BUDGET variable"))
+        .ok();
     if let Ok(iterations) = env::var("ITERATIONS") {
         num_iterations = iterations.parse().expect("invalid ITERATIONS variable");
     }
@@ -57,10 +62,15 @@ pub fn run_test(
             if is_randomized {
                 eprintln!("seed = {seed}");
             }
-            let result = panic::catch_unwind(|| {
-                let dispatcher = TestDispatcher::new(StdRng::seed_from_u64(seed));
-                test_fn(dispatcher, seed);
-            });
+            let dispatcher = TestDispatcher::new(StdRng::seed_from_u64(seed));
+            dispatcher.set_tick_budget(tick_budget);
+            let execution_report = dispatcher.execution_reporter();
+            // The dispatcher is created fresh for every attempt, so nothing observes it in a
+            // broken state after a panic, other than the execution report.
+            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| test_fn(dispatcher, seed)));
+            if report_execution {
+                eprintln!("seed {seed}: {}", execution_report());
+            }
 
             match result {
                 Ok(_) => break,
@@ -81,6 +91,36 @@ pub fn run_test(
     }
 }
 
+/// A summary of the work done by the deterministic executor during one test iteration,
+/// printed after each iteration when the `EXECUTION_REPORT` environment variable is set.
+#[derive(Clone, Debug, Default)]
+pub struct ExecutionReport {
+    /// How far the simulated clock was advanced.
+    pub simulated_time: Duration,
+    /// How many tasks the executor ran.
+    pub tasks_run: usize,
+    /// Operations recorded via `BackgroundExecutor::finish_operation`, along with how
+    /// many tasks ran while each was applied, most expensive first. Operations that are
+    /// applied concurrently are each charged for the tasks run in the meantime.
+    pub operations: Vec<(String, usize)>,
+}
+
+impl fmt::Display for ExecutionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MAX_REPORTED_OPERATIONS: usize = 10;
+
+        write!(
+            f,
+            "simulated {:?}, ran {} tasks",
+            self.simulated_time, self.tasks_run
+        )?;
+        for (description, steps) in self.operations.iter().take(MAX_REPORTED_OPERATIONS) {
+            write!(f, "\n  {steps:>8} tasks: {description}")?;
+        }
+        Ok(())
+    }
+}
+
 /// A test struct for converting an observation callback into a stream.
 pub struct Observation<T> {
     rx: channel::Receiver<T>,
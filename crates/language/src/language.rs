@@ -545,6 +545,7 @@ where
 pub struct FakeLspAdapter {
     pub name: &'static str,
     pub initialization_options: Option<Value>,
+    pub workspace_configuration: Option<Value>,
     pub capabilities: lsp::ServerCapabilities,
     pub initializer: Option<Box<dyn 'static + Send + Sync + Fn(&mut lsp::FakeLanguageServer)>>,
     pub disk_based_diagnostics_progress_token: Option<String>,
@@ -1374,6 +1375,7 @@ impl Default for FakeLspAdapter {
             initializer: None,
             disk_based_diagnostics_progress_token: None,
             initialization_options: None,
+            workspace_configuration: None,
             disk_based_diagnostics_sources: Vec::new(),
             prettier_plugins: Vec::new(),
         }
@@ -1433,6 +1435,12 @@ impl LspAdapter for Arc<FakeLspAdapter> {
         self.initialization_options.clone()
     }
 
+    fn workspace_configuration(&self, _: &Path, _: &mut AppContext) -> Value {
+        self.workspace_configuration
+            .clone()
+            .unwrap_or_else(|| serde_json::json!({}))
+    }
+
     fn prettier_plugins(&self) -> &[&'static str] {
         &self.prettier_plugins
     }
@@ -337,6 +337,34 @@ impl LanguageRegistry {
         })
     }
 
+    /// Returns the name of the language that a file at the given path is detected
+    /// as from its path alone, without loading that language.
+    pub fn language_name_for_path(&self, path: &Path) -> Option<Arc<str>> {
+        let filename = path.file_name().and_then(|name| name.to_str());
+        let extension = path.extension_or_hidden_file_name();
+        let path_suffixes = [extension, filename];
+        let path_matches = |matcher: &LanguageMatcher| {
+            matcher
+                .path_suffixes
+                .iter()
+                .any(|suffix| path_suffixes.contains(&Some(suffix.as_str())))
+        };
+
+        let state = self.state.read();
+        state
+            .languages
+            .iter()
+            .find(|language| path_matches(&language.config.matcher))
+            .map(|language| language.config.name.clone())
+            .or_else(|| {
+                state
+                    .available_languages
+                    .iter()
+                    .rfind(|language| !language.loaded && path_matches(&language.matcher))
+                    .map(|language| language.name.clone())
+            })
+    }
+
     fn get_or_load_language(
         self: &Arc<Self>,
         callback: impl Fn(&str, &LanguageMatcher) -> bool,
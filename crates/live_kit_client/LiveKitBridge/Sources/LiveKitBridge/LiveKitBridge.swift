@@ -157,11 +157,33 @@ public func LKRoomCreate(delegate: UnsafeRawPointer) -> UnsafeMutableRawPointer
     return Unmanaged.passRetained(Room(delegate: delegate)).toOpaque()
 }
 
+@_cdecl("LKRTCConfigurationCreate")
+public func LKRTCConfigurationCreate(forceRelay: Bool) -> UnsafeMutableRawPointer {
+    let configuration = RTCConfiguration.liveKitDefault()
+    if forceRelay {
+        configuration.iceTransportPolicy = .relay
+    }
+    return Unmanaged.passRetained(configuration).toOpaque()
+}
+
+@_cdecl("LKRTCConfigurationAddIceServer")
+public func LKRTCConfigurationAddIceServer(configuration: UnsafeRawPointer, urls: CFArray, username: CFString, credential: CFString) {
+    let configuration = Unmanaged<RTCConfiguration>.fromOpaque(configuration).takeUnretainedValue()
+    let server = RTCIceServer(urlStrings: urls as NSArray as! [String], username: username as String, credential: credential as String)
+    configuration.iceServers.append(server)
+}
+
 @_cdecl("LKRoomConnect")
-public func LKRoomConnect(room: UnsafeRawPointer, url: CFString, token: CFString, callback: @escaping @convention(c) (UnsafeRawPointer, CFString?) -> Void, callback_data: UnsafeRawPointer) {
+public func LKRoomConnect(room: UnsafeRawPointer, url: CFString, token: CFString, rtcConfiguration: UnsafeRawPointer?, callback: @escaping @convention(c) (UnsafeRawPointer, CFString?) -> Void, callback_data: UnsafeRawPointer) {
     let room = Unmanaged<Room>.fromOpaque(room).takeUnretainedValue()
 
-    room.connect(url as String, token as String).then { _ in
+    var connectOptions: ConnectOptions? = nil
+    if let rtcConfiguration = rtcConfiguration {
+        let rtcConfiguration = Unmanaged<RTCConfiguration>.fromOpaque(rtcConfiguration).takeRetainedValue()
+        connectOptions = ConnectOptions(rtcConfiguration: rtcConfiguration)
+    }
+
+    room.connect(url as String, token as String, connectOptions: connectOptions).then { _ in
         callback(callback_data, UnsafeRawPointer(nil) as! CFString?)
     }.catch { error in
         callback(callback_data, error.localizedDescription as CFString)
@@ -46,7 +46,10 @@ fn main() {
             )
             .unwrap();
             let room_a = Room::new();
-            room_a.connect(&live_kit_url, &user_a_token).await.unwrap();
+            room_a
+                .connect(&live_kit_url, &user_a_token, None)
+                .await
+                .unwrap();
 
             let user2_token = token::create(
                 &live_kit_key,
@@ -56,7 +59,10 @@ fn main() {
             )
             .unwrap();
             let room_b = Room::new();
-            room_b.connect(&live_kit_url, &user2_token).await.unwrap();
+            room_b
+                .connect(&live_kit_url, &user2_token, None)
+                .await
+                .unwrap();
 
             let mut room_updates = room_b.updates();
             let audio_track = LocalAudioTrack::create();
@@ -20,6 +20,21 @@ pub enum ConnectionState {
     Connected { url: String, token: String },
 }
 
+/// STUN and TURN servers that replace the ones the LiveKit server hands out.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct IceConfiguration {
+    pub servers: Vec<IceServer>,
+    /// Only let media flow through the TURN servers.
+    pub force_relay: bool,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct IceServer {
+    pub urls: Vec<String>,
+    pub username: String,
+    pub credential: String,
+}
+
 #[derive(Clone)]
 pub enum RoomUpdate {
     ActiveSpeakersChanged { speakers: Vec<Sid> },
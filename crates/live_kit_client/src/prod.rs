@@ -1,4 +1,4 @@
-use crate::{ConnectionState, RoomUpdate, Sid};
+use crate::{ConnectionState, IceConfiguration, RoomUpdate, Sid};
 use anyhow::{anyhow, Context, Result};
 use core_foundation::{
     array::{CFArray, CFArrayRef},
@@ -15,6 +15,7 @@ use parking_lot::Mutex;
 use postage::watch;
 use std::{
     ffi::c_void,
+    ptr,
     sync::{Arc, Weak},
 };
 
@@ -37,6 +38,7 @@ mod swift {
     pointer_type!(RemoteTrackPublication);
     pointer_type!(MacOSDisplay);
     pointer_type!(RoomDelegate);
+    pointer_type!(RtcConfiguration);
 }
 
 extern "C" {
@@ -88,10 +90,18 @@ extern "C" {
     ) -> swift::RoomDelegate;
 
     fn LKRoomCreate(delegate: swift::RoomDelegate) -> swift::Room;
+    fn LKRTCConfigurationCreate(force_relay: bool) -> swift::RtcConfiguration;
+    fn LKRTCConfigurationAddIceServer(
+        configuration: swift::RtcConfiguration,
+        urls: CFArrayRef,
+        username: CFStringRef,
+        credential: CFStringRef,
+    );
     fn LKRoomConnect(
         room: swift::Room,
         url: CFStringRef,
         token: CFStringRef,
+        rtc_configuration: swift::RtcConfiguration,
         callback: extern "C" fn(*mut c_void, CFStringRef),
         callback_data: *mut c_void,
     );
@@ -195,15 +205,44 @@ impl Room {
         self.connection.lock().1.clone()
     }
 
-    pub fn connect(self: &Arc<Self>, url: &str, token: &str) -> impl Future<Output = Result<()>> {
+    pub fn connect(
+        self: &Arc<Self>,
+        url: &str,
+        token: &str,
+        ice_configuration: Option<IceConfiguration>,
+    ) -> impl Future<Output = Result<()>> {
         let url = CFString::new(url);
         let token = CFString::new(token);
         let (did_connect, tx, rx) = Self::build_done_callback();
         unsafe {
+            // Ownership of the configuration passes to `LKRoomConnect`.
+            let rtc_configuration = match ice_configuration {
+                Some(ice_configuration) => {
+                    let rtc_configuration = LKRTCConfigurationCreate(ice_configuration.force_relay);
+                    for server in ice_configuration.servers {
+                        let urls = CFArray::from_CFTypes(
+                            &server
+                                .urls
+                                .iter()
+                                .map(|url| CFString::new(url))
+                                .collect::<Vec<_>>(),
+                        );
+                        LKRTCConfigurationAddIceServer(
+                            rtc_configuration,
+                            urls.as_concrete_TypeRef(),
+                            CFString::new(&server.username).as_concrete_TypeRef(),
+                            CFString::new(&server.credential).as_concrete_TypeRef(),
+                        );
+                    }
+                    rtc_configuration
+                }
+                None => swift::RtcConfiguration(ptr::null()),
+            };
             LKRoomConnect(
                 self.native_room,
                 url.as_concrete_TypeRef(),
                 token.as_concrete_TypeRef(),
+                rtc_configuration,
                 did_connect,
                 tx,
             )
@@ -1,4 +1,4 @@
-use crate::{ConnectionState, RoomUpdate, Sid};
+use crate::{ConnectionState, IceConfiguration, RoomUpdate, Sid};
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use collections::{BTreeMap, HashMap, HashSet};
@@ -216,6 +216,26 @@ impl TestServer {
         }
     }
 
+    /// Tells the participants of every room that the given ones are speaking, the
+    /// way LiveKit does as it detects their voices.
+    pub fn set_active_speakers(&self, speakers: &[String]) {
+        let server_rooms = self.rooms.lock();
+        for room in server_rooms.values() {
+            let speakers = speakers
+                .iter()
+                .filter(|speaker| room.client_rooms.contains_key(*speaker))
+                .cloned()
+                .collect::<Vec<_>>();
+            for client_room in room.client_rooms.values() {
+                let _ = client_room.0.lock().updates_tx.try_broadcast(
+                    RoomUpdate::ActiveSpeakersChanged {
+                        speakers: speakers.clone(),
+                    },
+                );
+            }
+        }
+    }
+
     async fn publish_video_track(
         &self,
         token: String,
@@ -510,6 +530,16 @@ impl live_kit_server::api::Client for TestApiClient {
             token::VideoGrant::for_guest(room),
         )
     }
+
+    fn observer_token(&self, room: &str, identity: &str) -> Result<String> {
+        let server = TestServer::get(&self.url)?;
+        token::create(
+            &server.api_key,
+            &server.secret_key,
+            Some(identity),
+            token::VideoGrant::for_observer(room),
+        )
+    }
 }
 
 struct RoomState {
@@ -518,6 +548,7 @@ struct RoomState {
         watch::Receiver<ConnectionState>,
     ),
     display_sources: Vec<MacOSDisplay>,
+    ice_configuration: Option<IceConfiguration>,
     paused_audio_tracks: HashSet<Sid>,
     updates_tx: async_broadcast::Sender<RoomUpdate>,
     updates_rx: async_broadcast::Receiver<RoomUpdate>,
@@ -531,6 +562,7 @@ impl Room {
         Arc::new(Self(Mutex::new(RoomState {
             connection: watch::channel_with(ConnectionState::Disconnected),
             display_sources: Default::default(),
+            ice_configuration: None,
             paused_audio_tracks: Default::default(),
             updates_tx,
             updates_rx,
@@ -541,10 +573,16 @@ impl Room {
         self.0.lock().connection.1.clone()
     }
 
-    pub fn connect(self: &Arc<Self>, url: &str, token: &str) -> impl Future<Output = Result<()>> {
+    pub fn connect(
+        self: &Arc<Self>,
+        url: &str,
+        token: &str,
+        ice_configuration: Option<IceConfiguration>,
+    ) -> impl Future<Output = Result<()>> {
         let this = self.clone();
         let url = url.to_string();
         let token = token.to_string();
+        this.0.lock().ice_configuration = ice_configuration;
         async move {
             let server = TestServer::get(&url)?;
             server
@@ -659,6 +697,10 @@ impl Room {
         self.0.lock().display_sources = sources;
     }
 
+    pub fn ice_configuration(&self) -> Option<IceConfiguration> {
+        self.0.lock().ice_configuration.clone()
+    }
+
     fn test_server(&self) -> Arc<TestServer> {
         match self.0.lock().connection.1.borrow().clone() {
             ConnectionState::Disconnected => panic!("must be connected to call this method"),
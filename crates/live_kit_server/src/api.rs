@@ -19,6 +19,7 @@ pub trait Client: Send + Sync {
     ) -> Result<()>;
     fn room_token(&self, room: &str, identity: &str) -> Result<String>;
     fn guest_token(&self, room: &str, identity: &str) -> Result<String>;
+    fn observer_token(&self, room: &str, identity: &str) -> Result<String>;
 }
 
 pub struct LiveKitParticipantUpdate {}
@@ -177,4 +178,13 @@ impl Client for LiveKitClient {
             token::VideoGrant::for_guest(room),
         )
     }
+
+    fn observer_token(&self, room: &str, identity: &str) -> Result<String> {
+        token::create(
+            &self.key,
+            &self.secret,
+            Some(identity),
+            token::VideoGrant::for_observer(room),
+        )
+    }
 }
@@ -67,6 +67,13 @@ impl<'a> VideoGrant<'a> {
             ..Default::default()
         }
     }
+
+    pub fn for_observer(room: &'a str) -> Self {
+        Self {
+            hidden: Some(true),
+            ..Self::for_guest(room)
+        }
+    }
 }
 
 pub fn create(
@@ -576,6 +576,9 @@ impl LanguageServer {
                     on_type_formatting: Some(DynamicRegistrationClientCapabilities {
                         dynamic_registration: None,
                     }),
+                    linked_editing_range: Some(LinkedEditingRangeClientCapabilities {
+                        dynamic_registration: None,
+                    }),
                     diagnostic: Some(DiagnosticClientCapabilities {
                         related_document_support: Some(true),
                         dynamic_registration: None,
@@ -1101,6 +1104,12 @@ impl LanguageServer {
             ..Default::default()
         }
     }
+
+    /// Simulates the language server's process exiting unexpectedly. Pending requests
+    /// fail, as do any requests made afterwards, until the server is restarted.
+    pub fn kill(&self) {
+        drop(self.io_tasks.lock().take());
+    }
 }
 
 #[cfg(any(test, feature = "test-support"))]
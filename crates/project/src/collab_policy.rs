@@ -0,0 +1,82 @@
+use anyhow::{Context as _, Result};
+use rpc::proto;
+use serde::Deserialize;
+
+/// The sharing rules that a repository declares for collaborators, in a
+/// `.zed/collab.toml` file at the root of its worktree.
+///
+/// ```toml
+/// deny = ["secrets/**", "**/*.pem"]
+/// read_only = true
+/// allowed_roles = ["admin", "member"]
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollabPolicy {
+    /// Globs of paths that are never exposed to guests, neither in the project
+    /// tree nor as buffers.
+    pub deny: Vec<String>,
+    /// Whether guests join the project read-only, unless they're channel admins.
+    pub read_only: bool,
+    /// The roles that may join the project. Every role may join when unset.
+    pub allowed_roles: Option<Vec<CollabRole>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollabRole {
+    Admin,
+    Member,
+    Guest,
+}
+
+impl CollabPolicy {
+    pub fn parse(content: &str) -> Result<Self> {
+        toml::from_str(content).context("invalid collaboration policy")
+    }
+
+    /// Combines the policies of a project's worktrees into the one that applies
+    /// to the project as a whole, keeping the most restrictive of each rule.
+    pub fn merge<'a>(policies: impl IntoIterator<Item = &'a Self>) -> Self {
+        let mut merged = Self::default();
+        for policy in policies {
+            merged.deny.extend(policy.deny.iter().cloned());
+            merged.read_only |= policy.read_only;
+            if let Some(allowed_roles) = &policy.allowed_roles {
+                merged.allowed_roles = Some(match merged.allowed_roles.take() {
+                    Some(roles) => roles
+                        .into_iter()
+                        .filter(|role| allowed_roles.contains(role))
+                        .collect(),
+                    None => allowed_roles.clone(),
+                });
+            }
+        }
+        merged
+    }
+
+    /// The parts of the policy that the server enforces. Denied paths are
+    /// enforced by the host, and are never sent to the server.
+    pub fn to_proto(&self) -> proto::CollabPolicy {
+        proto::CollabPolicy {
+            read_only: self.read_only,
+            restrict_roles: self.allowed_roles.is_some(),
+            allowed_roles: self
+                .allowed_roles
+                .iter()
+                .flatten()
+                .map(|role| role.to_proto() as i32)
+                .collect(),
+        }
+    }
+}
+
+impl CollabRole {
+    pub fn to_proto(self) -> proto::ChannelRole {
+        match self {
+            CollabRole::Admin => proto::ChannelRole::Admin,
+            CollabRole::Member => proto::ChannelRole::Member,
+            CollabRole::Guest => proto::ChannelRole::Guest,
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::Result;
+use clock::ReplicaId;
+use language::{proto::serialize_fingerprint, Anchor, Buffer, OffsetRangeExt};
+use serde::{Deserialize, Serialize};
+use std::{ops::Range, path::Path};
+
+/// A span of a buffer's text, and the collaborator who wrote it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttributedRange {
+    pub range: Range<Anchor>,
+    pub author: EditAuthor,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EditAuthor {
+    pub user_id: u64,
+    pub github_login: String,
+}
+
+/// Who wrote which spans of a buffer while its project was shared, worked out
+/// from the replica that inserted each fragment of the buffer's text.
+pub(crate) struct BufferEditAttribution {
+    ranges: Vec<AttributedRange>,
+    /// Text inserted by edits that this version observed has either been
+    /// attributed already, or was written outside of a collaboration session.
+    attributed_version: clock::Global,
+}
+
+/// The form in which attributions are persisted, once the buffer's text can no
+/// longer be anchored into.
+#[derive(Serialize, Deserialize)]
+struct SerializedEditAttribution {
+    /// The fingerprint of the text that the ranges' offsets refer to.
+    fingerprint: String,
+    ranges: Vec<SerializedAttributedRange>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializedAttributedRange {
+    start: usize,
+    end: usize,
+    author: EditAuthor,
+}
+
+impl BufferEditAttribution {
+    pub fn new(buffer: &Buffer) -> Self {
+        Self {
+            ranges: Vec::new(),
+            attributed_version: buffer.version(),
+        }
+    }
+
+    /// Restores a persisted attribution, if it was made for the buffer's current text.
+    pub fn deserialize(json: &str, buffer: &Buffer) -> Result<Option<Self>> {
+        let serialized: SerializedEditAttribution = serde_json::from_str(json)?;
+        if serialized.fingerprint != serialize_fingerprint(buffer.as_rope().fingerprint()) {
+            return Ok(None);
+        }
+
+        let len = buffer.len();
+        Ok(Some(Self {
+            ranges: serialized
+                .ranges
+                .into_iter()
+                .filter(|range| range.start < range.end && range.end <= len)
+                .map(|range| AttributedRange {
+                    range: buffer.anchor_after(range.start)..buffer.anchor_before(range.end),
+                    author: range.author,
+                })
+                .collect(),
+            attributed_version: buffer.version(),
+        }))
+    }
+
+    pub fn serialize(&self, buffer: &Buffer) -> Result<String> {
+        Ok(serde_json::to_string(&SerializedEditAttribution {
+            fingerprint: serialize_fingerprint(buffer.as_rope().fingerprint()),
+            ranges: self
+                .ranges(buffer)
+                .into_iter()
+                .map(|attributed_range| {
+                    let range = attributed_range.range.to_offset(buffer);
+                    SerializedAttributedRange {
+                        start: range.start,
+                        end: range.end,
+                        author: attributed_range.author,
+                    }
+                })
+                .collect(),
+        })?)
+    }
+
+    /// Returns the attributed spans that haven't since been deleted, in the order
+    /// they were attributed.
+    pub fn ranges(&self, buffer: &Buffer) -> Vec<AttributedRange> {
+        self.ranges
+            .iter()
+            .filter(|attributed_range| {
+                let range = attributed_range.range.to_offset(buffer);
+                range.start < range.end
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Prepends the spans attributed in earlier sessions.
+    pub fn merge_earlier(&mut self, earlier: Self) {
+        self.ranges.splice(0..0, earlier.ranges);
+    }
+
+    /// Leaves any text written since the last session unattributed.
+    pub fn start_session(&mut self, buffer: &Buffer) {
+        self.attributed_version = buffer.version();
+    }
+
+    /// Attributes the text that the given replica inserted since it was last
+    /// attributed to the given author. Replica ids are reused once collaborators
+    /// leave, so this must be called before a replica changes hands.
+    pub fn attribute(&mut self, buffer: &Buffer, replica_id: ReplicaId, author: &EditAuthor) {
+        for range in buffer.ranges_inserted_by(replica_id, &self.attributed_version) {
+            self.ranges.push(AttributedRange {
+                range: buffer.anchor_after(range.start)..buffer.anchor_before(range.end),
+                author: author.clone(),
+            });
+        }
+        self.attributed_version.observe(clock::Lamport {
+            replica_id,
+            value: buffer.version().get(replica_id),
+        });
+    }
+}
+
+/// The key under which the attribution of the file at the given path is persisted.
+pub(crate) fn persistence_key(abs_path: &Path) -> String {
+    format!("edit-attribution:{}", abs_path.display())
+}
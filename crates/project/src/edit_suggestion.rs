@@ -0,0 +1,92 @@
+use crate::review::deserialize_range;
+use anyhow::{anyhow, Result};
+use language::{proto::serialize_anchor, Anchor};
+use rpc::proto;
+use std::ops::Range;
+use text::BufferId;
+
+/// The most edits that one suggestion can make.
+pub(crate) const MAX_SUGGESTED_EDITS: usize = 256;
+/// The most text, in bytes, that one suggestion can insert.
+pub const MAX_SUGGESTED_TEXT_LEN: usize = 64 * 1024;
+/// The most suggestions that can be waiting for the host at once.
+pub(crate) const MAX_PENDING_EDIT_SUGGESTIONS: usize = 100;
+
+/// A set of edits to one of the host's buffers, proposed by a guest who can't
+/// edit the buffer themselves.
+///
+/// Like bookmarks, suggestions are owned by the host. Guests send their edits to
+/// the host, and receive every pending suggestion whenever the set changes. Only
+/// the host can apply or reject a suggestion, and applying it edits the buffer
+/// on the guest's behalf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditSuggestion {
+    pub id: u64,
+    pub buffer_id: BufferId,
+    pub edits: Vec<(Range<Anchor>, String)>,
+    pub author_user_id: Option<u64>,
+}
+
+impl EditSuggestion {
+    pub(crate) fn to_proto(&self) -> proto::EditSuggestion {
+        proto::EditSuggestion {
+            id: self.id,
+            buffer_id: self.buffer_id.into(),
+            edits: serialize_suggested_edits(&self.edits),
+            author_user_id: self.author_user_id,
+        }
+    }
+
+    pub(crate) fn from_proto(message: proto::EditSuggestion) -> Result<Self> {
+        Ok(Self {
+            id: message.id,
+            buffer_id: BufferId::new(message.buffer_id)?,
+            edits: deserialize_suggested_edits(message.edits)?,
+            author_user_id: message.author_user_id,
+        })
+    }
+}
+
+/// Checks that suggested edits are small enough for the host to take.
+pub(crate) fn check_suggested_edits(edits: &[(Range<Anchor>, String)]) -> Result<()> {
+    if edits.is_empty() {
+        return Err(anyhow!("an edit suggestion must make at least one edit"));
+    }
+    if edits.len() > MAX_SUGGESTED_EDITS {
+        return Err(anyhow!(
+            "an edit suggestion can make at most {MAX_SUGGESTED_EDITS} edits"
+        ));
+    }
+    let text_len = edits
+        .iter()
+        .map(|(_, new_text)| new_text.len())
+        .sum::<usize>();
+    if text_len > MAX_SUGGESTED_TEXT_LEN {
+        return Err(anyhow!(
+            "an edit suggestion can insert at most {MAX_SUGGESTED_TEXT_LEN} bytes"
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn serialize_suggested_edits(
+    edits: &[(Range<Anchor>, String)],
+) -> Vec<proto::SuggestedEdit> {
+    edits
+        .iter()
+        .map(|(range, new_text)| proto::SuggestedEdit {
+            start: Some(serialize_anchor(&range.start)),
+            end: Some(serialize_anchor(&range.end)),
+            new_text: new_text.clone(),
+        })
+        .collect()
+}
+
+pub(crate) fn deserialize_suggested_edits(
+    edits: Vec<proto::SuggestedEdit>,
+) -> Result<Vec<(Range<Anchor>, String)>> {
+    edits
+        .into_iter()
+        .map(|edit| Ok((deserialize_range(edit.start, edit.end)?, edit.new_text)))
+        .collect()
+}
@@ -0,0 +1,183 @@
+use crate::{
+    worktree::{Entry, Snapshot},
+    ProjectEntryId,
+};
+use language::LanguageRegistry;
+use rpc::proto;
+use std::{collections::BTreeMap, ops::Bound, sync::Arc};
+
+/// How many decorations are sent to guests in each message.
+#[cfg(any(test, feature = "test-support"))]
+pub(crate) const MAX_CHUNK_SIZE: usize = 2;
+#[cfg(not(any(test, feature = "test-support")))]
+pub(crate) const MAX_CHUNK_SIZE: usize = 256;
+
+/// What a worktree entry is decorated with, such as in the project panel, as
+/// detected by the host.
+///
+/// Guests are sent the host's decorations rather than detecting their own, as
+/// they may lack the extensions that provide an entry's language, and can't tell
+/// which files the host's settings make private.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryDecoration {
+    /// The name of the language that the file is detected as from its path.
+    pub language: Option<Arc<str>>,
+    /// Whether the file matches the host's `private_files` setting.
+    pub is_private: bool,
+}
+
+impl EntryDecoration {
+    pub(crate) fn for_entry(entry: &Entry, languages: &LanguageRegistry) -> Self {
+        if !entry.is_file() {
+            return Self::default();
+        }
+        Self {
+            language: languages.language_name_for_path(&entry.path),
+            is_private: entry.is_private,
+        }
+    }
+
+    fn to_proto(&self, entry_id: ProjectEntryId) -> proto::EntryDecoration {
+        proto::EntryDecoration {
+            entry_id: entry_id.to_proto(),
+            language: self.language.as_deref().map(str::to_string),
+            is_private: self.is_private,
+        }
+    }
+
+    fn from_proto(message: proto::EntryDecoration) -> (ProjectEntryId, Self) {
+        (
+            ProjectEntryId::from_proto(message.entry_id),
+            Self {
+                language: message.language.map(Arc::from),
+                is_private: message.is_private,
+            },
+        )
+    }
+}
+
+/// The decorations of a worktree's entries, leaving out the entries that have
+/// none. Hosts only keep them while the project is shared.
+#[derive(Debug, Default)]
+pub(crate) struct WorktreeEntryDecorations {
+    decorations: BTreeMap<ProjectEntryId, EntryDecoration>,
+}
+
+impl WorktreeEntryDecorations {
+    pub fn get(&self, entry_id: ProjectEntryId) -> Option<&EntryDecoration> {
+        self.decorations.get(&entry_id)
+    }
+
+    /// Detects the decorations of the given entries again, returning the ones
+    /// that changed, to send to guests.
+    pub fn detect(
+        &mut self,
+        snapshot: &Snapshot,
+        entry_ids: impl IntoIterator<Item = ProjectEntryId>,
+        languages: &LanguageRegistry,
+    ) -> Option<proto::WorktreeEntryDecorations> {
+        let mut update = proto::WorktreeEntryDecorations {
+            worktree_id: snapshot.id().to_proto(),
+            updated_decorations: Vec::new(),
+            removed_entry_ids: Vec::new(),
+        };
+        for entry_id in entry_ids {
+            let decoration = snapshot
+                .entry_for_id(entry_id)
+                .map(|entry| EntryDecoration::for_entry(entry, languages))
+                .unwrap_or_default();
+            if self.decorations.get(&entry_id) == Some(&decoration) {
+                continue;
+            }
+            if decoration == EntryDecoration::default() {
+                if self.decorations.remove(&entry_id).is_some() {
+                    update.removed_entry_ids.push(entry_id.to_proto());
+                }
+            } else {
+                update
+                    .updated_decorations
+                    .push(decoration.to_proto(entry_id));
+                self.decorations.insert(entry_id, decoration);
+            }
+        }
+
+        if update.updated_decorations.is_empty() && update.removed_entry_ids.is_empty() {
+            None
+        } else {
+            Some(update)
+        }
+    }
+
+    /// Detects the decorations of every entry again, such as after the host's
+    /// languages change.
+    pub fn detect_all(
+        &mut self,
+        snapshot: &Snapshot,
+        languages: &LanguageRegistry,
+    ) -> Option<proto::WorktreeEntryDecorations> {
+        let entry_ids = snapshot
+            .entries(true)
+            .map(|entry| entry.id)
+            .chain(self.decorations.keys().copied())
+            .collect::<Vec<_>>();
+        self.detect(snapshot, entry_ids, languages)
+    }
+
+    pub fn apply_proto(&mut self, message: proto::WorktreeEntryDecorations) {
+        for entry_id in message.removed_entry_ids {
+            self.decorations
+                .remove(&ProjectEntryId::from_proto(entry_id));
+        }
+        self.decorations.extend(
+            message
+                .updated_decorations
+                .into_iter()
+                .map(EntryDecoration::from_proto),
+        );
+    }
+
+    /// Returns up to `max_len` decorations, of the entries after the given one.
+    pub fn to_proto(
+        &self,
+        worktree_id: u64,
+        after_entry_id: Option<ProjectEntryId>,
+        max_len: usize,
+    ) -> proto::WorktreeEntryDecorations {
+        let start = after_entry_id.map_or(Bound::Unbounded, Bound::Excluded);
+        proto::WorktreeEntryDecorations {
+            worktree_id,
+            updated_decorations: self
+                .decorations
+                .range((start, Bound::Unbounded))
+                .take(max_len)
+                .map(|(entry_id, decoration)| decoration.to_proto(*entry_id))
+                .collect(),
+            removed_entry_ids: Vec::new(),
+        }
+    }
+}
+
+/// Splits an update into ones of up to [`MAX_CHUNK_SIZE`] decorations and removed
+/// entries each.
+pub(crate) fn split_update(
+    mut update: proto::WorktreeEntryDecorations,
+) -> impl Iterator<Item = proto::WorktreeEntryDecorations> {
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let updated_len = update.updated_decorations.len().min(MAX_CHUNK_SIZE);
+        let removed_len = update
+            .removed_entry_ids
+            .len()
+            .min(MAX_CHUNK_SIZE - updated_len);
+        let chunk = proto::WorktreeEntryDecorations {
+            worktree_id: update.worktree_id,
+            updated_decorations: update.updated_decorations.drain(..updated_len).collect(),
+            removed_entry_ids: update.removed_entry_ids.drain(..removed_len).collect(),
+        };
+        done = update.updated_decorations.is_empty() && update.removed_entry_ids.is_empty();
+        Some(chunk)
+    })
+}
@@ -113,6 +113,10 @@ pub(crate) struct GetDocumentHighlights {
     pub position: PointUtf16,
 }
 
+pub(crate) struct GetLinkedEditingRanges {
+    pub position: PointUtf16,
+}
+
 pub(crate) struct GetHover {
     pub position: PointUtf16,
 }
@@ -759,22 +763,25 @@ fn location_links_to_proto(
     peer_id: PeerId,
     cx: &mut AppContext,
 ) -> Vec<proto::LocationLink> {
+    // Links into buffers that the host may not hand to guests are left out.
     links
         .into_iter()
-        .map(|definition| {
-            let origin = definition.origin.map(|origin| {
+        .filter_map(|definition| {
+            let origin = definition.origin.and_then(|origin| {
                 let buffer_id = project
                     .create_buffer_for_peer(&origin.buffer, peer_id, cx)
+                    .ok()?
                     .into();
-                proto::Location {
+                Some(proto::Location {
                     start: Some(serialize_anchor(&origin.range.start)),
                     end: Some(serialize_anchor(&origin.range.end)),
                     buffer_id,
-                }
+                })
             });
 
             let buffer_id = project
                 .create_buffer_for_peer(&definition.target.buffer, peer_id, cx)
+                .ok()?
                 .into();
             let target = proto::Location {
                 start: Some(serialize_anchor(&definition.target.range.start)),
@@ -782,10 +789,10 @@ fn location_links_to_proto(
                 buffer_id,
             };
 
-            proto::LocationLink {
+            Some(proto::LocationLink {
                 origin,
                 target: Some(target),
-            }
+            })
         })
         .collect()
 }
@@ -902,13 +909,15 @@ impl LspCommand for GetReferences {
     ) -> proto::GetReferencesResponse {
         let locations = response
             .into_iter()
-            .map(|definition| {
-                let buffer_id = project.create_buffer_for_peer(&definition.buffer, peer_id, cx);
-                proto::Location {
+            .filter_map(|definition| {
+                let buffer_id = project
+                    .create_buffer_for_peer(&definition.buffer, peer_id, cx)
+                    .ok()?;
+                Some(proto::Location {
                     start: Some(serialize_anchor(&definition.range.start)),
                     end: Some(serialize_anchor(&definition.range.end)),
                     buffer_id: buffer_id.into(),
-                }
+                })
             })
             .collect();
         proto::GetReferencesResponse { locations }
@@ -1104,6 +1113,139 @@ impl LspCommand for GetDocumentHighlights {
     }
 }
 
+#[async_trait(?Send)]
+impl LspCommand for GetLinkedEditingRanges {
+    type Response = Vec<Range<Anchor>>;
+    type LspRequest = lsp::request::LinkedEditingRange;
+    type ProtoRequest = proto::GetLinkedEditingRanges;
+
+    fn check_capabilities(&self, capabilities: &ServerCapabilities) -> bool {
+        capabilities.linked_editing_range_provider.is_some()
+    }
+
+    fn to_lsp(
+        &self,
+        path: &Path,
+        _: &Buffer,
+        _: &Arc<LanguageServer>,
+        _: &AppContext,
+    ) -> lsp::LinkedEditingRangeParams {
+        lsp::LinkedEditingRangeParams {
+            text_document_position_params: lsp::TextDocumentPositionParams {
+                text_document: lsp::TextDocumentIdentifier {
+                    uri: lsp::Url::from_file_path(path).unwrap(),
+                },
+                position: point_to_lsp(self.position),
+            },
+            work_done_progress_params: Default::default(),
+        }
+    }
+
+    async fn response_from_lsp(
+        self,
+        message: Option<lsp::LinkedEditingRanges>,
+        _: Model<Project>,
+        buffer: Model<Buffer>,
+        _: LanguageServerId,
+        mut cx: AsyncAppContext,
+    ) -> Result<Vec<Range<Anchor>>> {
+        let Some(message) = message else {
+            return Ok(Vec::new());
+        };
+        buffer.update(&mut cx, |buffer, _| {
+            message
+                .ranges
+                .into_iter()
+                .map(|range| {
+                    let start = buffer.clip_point_utf16(point_from_lsp(range.start), Bias::Left);
+                    let end = buffer.clip_point_utf16(point_from_lsp(range.end), Bias::Left);
+                    // Bias the ranges outward, so that text typed at their edges
+                    // is considered part of them.
+                    buffer.anchor_before(start)..buffer.anchor_after(end)
+                })
+                .collect()
+        })
+    }
+
+    fn to_proto(&self, project_id: u64, buffer: &Buffer) -> proto::GetLinkedEditingRanges {
+        proto::GetLinkedEditingRanges {
+            project_id,
+            buffer_id: buffer.remote_id().into(),
+            position: Some(language::proto::serialize_anchor(
+                &buffer.anchor_before(self.position),
+            )),
+            version: serialize_version(&buffer.version()),
+        }
+    }
+
+    async fn from_proto(
+        message: proto::GetLinkedEditingRanges,
+        _: Model<Project>,
+        buffer: Model<Buffer>,
+        mut cx: AsyncAppContext,
+    ) -> Result<Self> {
+        let position = message
+            .position
+            .and_then(deserialize_anchor)
+            .ok_or_else(|| anyhow!("invalid position"))?;
+        buffer
+            .update(&mut cx, |buffer, _| {
+                buffer.wait_for_version(deserialize_version(&message.version))
+            })?
+            .await?;
+        Ok(Self {
+            position: buffer.update(&mut cx, |buffer, _| position.to_point_utf16(buffer))?,
+        })
+    }
+
+    fn response_to_proto(
+        response: Vec<Range<Anchor>>,
+        _: &mut Project,
+        _: PeerId,
+        _: &clock::Global,
+        _: &mut AppContext,
+    ) -> proto::GetLinkedEditingRangesResponse {
+        proto::GetLinkedEditingRangesResponse {
+            ranges: response
+                .into_iter()
+                .map(|range| proto::AnchorRange {
+                    start: Some(serialize_anchor(&range.start)),
+                    end: Some(serialize_anchor(&range.end)),
+                })
+                .collect(),
+        }
+    }
+
+    async fn response_from_proto(
+        self,
+        message: proto::GetLinkedEditingRangesResponse,
+        _: Model<Project>,
+        buffer: Model<Buffer>,
+        mut cx: AsyncAppContext,
+    ) -> Result<Vec<Range<Anchor>>> {
+        let mut ranges = Vec::new();
+        for range in message.ranges {
+            let start = range
+                .start
+                .and_then(deserialize_anchor)
+                .ok_or_else(|| anyhow!("missing range start"))?;
+            let end = range
+                .end
+                .and_then(deserialize_anchor)
+                .ok_or_else(|| anyhow!("missing range end"))?;
+            buffer
+                .update(&mut cx, |buffer, _| buffer.wait_for_anchors([start, end]))?
+                .await?;
+            ranges.push(start..end);
+        }
+        Ok(ranges)
+    }
+
+    fn buffer_id_from_proto(message: &proto::GetLinkedEditingRanges) -> Result<BufferId> {
+        BufferId::new(message.buffer_id)
+    }
+}
+
 #[async_trait(?Send)]
 impl LspCommand for GetHover {
     type Response = Option<Hover>;
@@ -1829,13 +1971,21 @@ impl LspCommand for OnTypeFormatting {
         self,
         message: proto::OnTypeFormattingResponse,
         _: Model<Project>,
-        _: Model<Buffer>,
-        _: AsyncAppContext,
+        buffer: Model<Buffer>,
+        mut cx: AsyncAppContext,
     ) -> Result<Option<Transaction>> {
         let Some(transaction) = message.transaction else {
             return Ok(None);
         };
-        Ok(Some(language::proto::deserialize_transaction(transaction)?))
+        let transaction = language::proto::deserialize_transaction(transaction)?;
+        // Wait for the host's formatting edits to be replicated, so that the
+        // transaction can be undone as soon as it is returned.
+        buffer
+            .update(&mut cx, |buffer, _| {
+                buffer.wait_for_edits(transaction.edit_ids.iter().copied())
+            })?
+            .await?;
+        Ok(Some(transaction))
     }
 
     fn buffer_id_from_proto(message: &proto::OnTypeFormatting) -> Result<BufferId> {
@@ -0,0 +1,290 @@
+use anyhow::{Context as _, Result};
+use clock::ReplicaId;
+use collections::{HashMap, HashSet};
+use db::{define_connection, query, sqlez_macros::sql};
+use prost::Message as _;
+use rpc::proto;
+use std::mem;
+use text::BufferId;
+
+define_connection!(
+    // Current schema shape using pseudo-rust syntax:
+    // journaled_buffer_operations(
+    //   project_id: u64,
+    //   session_id: i64,
+    //   replica_id: u32,
+    //   buffer_id: u64,
+    //   sequence: u64,
+    //   operation: Vec<u8>,
+    // )
+    pub static ref DB: OperationJournalDb<()> =
+        &[sql!(
+            CREATE TABLE journaled_buffer_operations(
+                project_id INTEGER NOT NULL,
+                session_id INTEGER NOT NULL,
+                replica_id INTEGER NOT NULL,
+                buffer_id INTEGER NOT NULL,
+                sequence INTEGER NOT NULL,
+                operation BLOB NOT NULL,
+                PRIMARY KEY(project_id, session_id, buffer_id, sequence)
+            ) STRICT;
+        )];
+);
+
+impl OperationJournalDb {
+    // Returns the operations of the most recent session other than the given one
+    // that left any behind, ordered by buffer and then by when they were made.
+    query! {
+        fn journaled_operations(project_id: u64, session_id: i64) -> Result<Vec<(i64, u32, u64, Vec<u8>)>> {
+            SELECT session_id, replica_id, buffer_id, operation
+            FROM journaled_buffer_operations
+            WHERE project_id = ?1 AND session_id = (
+                SELECT session_id FROM journaled_buffer_operations
+                WHERE project_id = ?1 AND session_id != ?2
+                ORDER BY rowid DESC
+                LIMIT 1
+            )
+            ORDER BY buffer_id, sequence
+        }
+    }
+
+    query! {
+        async fn delete_session(project_id: u64, session_id: i64) -> Result<()> {
+            DELETE FROM journaled_buffer_operations
+            WHERE project_id = ? AND session_id = ?
+        }
+    }
+}
+
+/// Buffer operations that a guest made, but that the server hasn't acknowledged yet.
+///
+/// They're kept on disk until they're acknowledged, so that if Zed quits before they
+/// reach the host, they can be recovered the next time the guest joins the project.
+pub(crate) struct OperationJournal {
+    project_id: u64,
+    /// Tells this journal's operations apart from those left by earlier sessions.
+    session_id: i64,
+    replica_id: ReplicaId,
+    /// Operations that haven't been written to disk yet, along with their position
+    /// among the operations on their buffer.
+    unwritten_operations: Vec<(BufferId, u64, proto::Operation)>,
+    /// The position of the next operation on each buffer.
+    next_sequence_by_buffer_id: HashMap<BufferId, u64>,
+    /// How many of the operations on each buffer have been acknowledged.
+    acknowledged_by_buffer_id: HashMap<BufferId, u64>,
+    /// Buffers whose acknowledged operations may still be on disk.
+    newly_acknowledged_buffer_ids: HashSet<BufferId>,
+    is_cleared: bool,
+}
+
+/// Operations that were journaled in an earlier session, and can be replayed into the
+/// buffers they were made on.
+pub(crate) struct RecoveredOperations {
+    pub project_id: u64,
+    pub session_id: i64,
+    /// The replica that made the operations, which they can only be applied as while
+    /// no other collaborator is using it.
+    pub replica_id: ReplicaId,
+    pub operations_by_buffer_id: HashMap<BufferId, Vec<proto::Operation>>,
+}
+
+impl OperationJournal {
+    pub fn new(project_id: u64, replica_id: ReplicaId) -> Self {
+        Self {
+            project_id,
+            session_id: rand::random(),
+            replica_id,
+            unwritten_operations: Default::default(),
+            next_sequence_by_buffer_id: Default::default(),
+            acknowledged_by_buffer_id: Default::default(),
+            newly_acknowledged_buffer_ids: Default::default(),
+            is_cleared: false,
+        }
+    }
+
+    pub fn session_id(&self) -> i64 {
+        self.session_id
+    }
+
+    pub fn push(&mut self, buffer_id: BufferId, operation: proto::Operation) {
+        let next_sequence = self
+            .next_sequence_by_buffer_id
+            .entry(buffer_id)
+            .or_default();
+        self.unwritten_operations
+            .push((buffer_id, *next_sequence, operation));
+        *next_sequence += 1;
+    }
+
+    /// Forgets the oldest operations on the buffer, once the server has acknowledged them.
+    pub fn acknowledge(&mut self, buffer_id: BufferId, count: usize) {
+        let next_sequence = self
+            .next_sequence_by_buffer_id
+            .get(&buffer_id)
+            .copied()
+            .unwrap_or_default();
+        let acknowledged = self.acknowledged_by_buffer_id.entry(buffer_id).or_default();
+        *acknowledged = (*acknowledged + count as u64).min(next_sequence);
+        self.newly_acknowledged_buffer_ids.insert(buffer_id);
+    }
+
+    /// Forgets every operation, once the buffers have been resynchronized with the host.
+    pub fn clear(&mut self) {
+        self.unwritten_operations.clear();
+        self.newly_acknowledged_buffer_ids.clear();
+        self.acknowledged_by_buffer_id = self.next_sequence_by_buffer_id.clone();
+        self.is_cleared = true;
+    }
+
+    /// Writes the operations made since the journal was last persisted to disk, and
+    /// removes the ones that have been acknowledged since then.
+    pub async fn persist(&mut self) -> Result<()> {
+        let acknowledged_by_buffer_id = &self.acknowledged_by_buffer_id;
+        let unwritten_operations = mem::take(&mut self.unwritten_operations)
+            .into_iter()
+            .filter(|(buffer_id, sequence, _)| {
+                *sequence
+                    >= acknowledged_by_buffer_id
+                        .get(buffer_id)
+                        .copied()
+                        .unwrap_or(0)
+            })
+            .map(|(buffer_id, sequence, operation)| {
+                (u64::from(buffer_id), sequence, operation.encode_to_vec())
+            })
+            .collect::<Vec<_>>();
+        let acknowledged = mem::take(&mut self.newly_acknowledged_buffer_ids)
+            .into_iter()
+            .map(|buffer_id| (u64::from(buffer_id), acknowledged_by_buffer_id[&buffer_id]))
+            .collect::<Vec<_>>();
+        let is_cleared = mem::take(&mut self.is_cleared);
+        if unwritten_operations.is_empty() && acknowledged.is_empty() && !is_cleared {
+            return Ok(());
+        }
+
+        let project_id = self.project_id;
+        let session_id = self.session_id;
+        let replica_id = self.replica_id as u32;
+        DB.write(move |conn| {
+            conn.with_savepoint("persist_operation_journal", || {
+                if is_cleared {
+                    conn.exec_bound(sql!(
+                        DELETE FROM journaled_buffer_operations
+                        WHERE project_id = ? AND session_id = ?
+                    ))?((project_id, session_id))?;
+                }
+                for (buffer_id, acknowledged) in acknowledged {
+                    conn.exec_bound(sql!(
+                        DELETE FROM journaled_buffer_operations
+                        WHERE project_id = ? AND session_id = ? AND buffer_id = ? AND sequence < ?
+                    ))?((project_id, session_id, buffer_id, acknowledged))?;
+                }
+                for (buffer_id, sequence, operation) in unwritten_operations {
+                    conn.exec_bound(sql!(
+                        INSERT INTO journaled_buffer_operations
+                            (project_id, session_id, replica_id, buffer_id, sequence, operation)
+                        VALUES
+                            (?, ?, ?, ?, ?, ?)
+                    ))?((
+                        project_id, session_id, replica_id, buffer_id, sequence, operation,
+                    ))?;
+                }
+                Ok(())
+            })
+        })
+        .await
+    }
+
+    /// Reads the operations that were left in the project's journal when Zed last quit,
+    /// which stay there until they're [discarded](Self::discard).
+    pub async fn recover(project_id: u64, session_id: i64) -> Result<Option<RecoveredOperations>> {
+        let rows = DB.journaled_operations(project_id, session_id)?;
+        let Some((session_id, replica_id, _, _)) = rows.first() else {
+            return Ok(None);
+        };
+        let session_id = *session_id;
+        let replica_id = ReplicaId::try_from(*replica_id)?;
+
+        let mut operations_by_buffer_id = HashMap::<BufferId, Vec<proto::Operation>>::default();
+        for (_, _, buffer_id, operation) in &rows {
+            let operation =
+                proto::Operation::decode(operation.as_slice()).context("invalid operation")?;
+            operations_by_buffer_id
+                .entry(BufferId::new(*buffer_id)?)
+                .or_default()
+                .push(operation);
+        }
+        Ok(Some(RecoveredOperations {
+            project_id,
+            session_id,
+            replica_id,
+            operations_by_buffer_id,
+        }))
+    }
+
+    /// Removes recovered operations from disk, once they've been replayed or declined.
+    pub async fn discard(recovered: &RecoveredOperations) -> Result<()> {
+        DB.delete_session(recovered.project_id, recovered.session_id)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[gpui::test]
+    async fn test_operation_journal() {
+        let project_id = 1;
+        let buffer_1 = BufferId::new(1).unwrap();
+        let buffer_2 = BufferId::new(2).unwrap();
+        let edit = |lamport_timestamp| proto::Operation {
+            variant: Some(proto::operation::Variant::Edit(proto::operation::Edit {
+                replica_id: 1,
+                lamport_timestamp,
+                new_text: vec!["a".into()],
+                ..Default::default()
+            })),
+        };
+
+        let mut journal = OperationJournal::new(project_id, 1);
+        let recover = || OperationJournal::recover(project_id, 0);
+        assert!(recover().await.unwrap().is_none());
+
+        journal.push(buffer_1, edit(1));
+        journal.push(buffer_1, edit(2));
+        journal.push(buffer_2, edit(3));
+        journal.persist().await.unwrap();
+        journal.acknowledge(buffer_1, 1);
+        journal.persist().await.unwrap();
+        journal.push(buffer_2, edit(4));
+        journal.persist().await.unwrap();
+
+        // Only the operations that weren't acknowledged are recovered, and they stay
+        // on disk until they're discarded.
+        let recovered = recover().await.unwrap().unwrap();
+        assert_eq!(recovered.replica_id, 1);
+        assert_eq!(recovered.operations_by_buffer_id[&buffer_1], [edit(2)]);
+        assert_eq!(
+            recovered.operations_by_buffer_id[&buffer_2],
+            [edit(3), edit(4)]
+        );
+        assert!(recover().await.unwrap().is_some());
+
+        // A journal doesn't recover its own operations.
+        assert!(OperationJournal::recover(project_id, journal.session_id())
+            .await
+            .unwrap()
+            .is_none());
+
+        OperationJournal::discard(&recovered).await.unwrap();
+        assert!(recover().await.unwrap().is_none());
+
+        // Nothing is left to recover once every operation has been acknowledged.
+        journal.push(buffer_1, edit(5));
+        journal.persist().await.unwrap();
+        journal.clear();
+        journal.persist().await.unwrap();
+        assert!(recover().await.unwrap().is_none());
+    }
+}
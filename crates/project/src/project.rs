@@ -1,9 +1,15 @@
+mod collab_policy;
 pub mod debounced_delay;
+mod edit_attribution;
+mod edit_suggestion;
+mod entry_decoration;
 mod ignore;
 pub mod lsp_command;
 pub mod lsp_ext_command;
+mod operation_journal;
 mod prettier_support;
 pub mod project_settings;
+mod review;
 pub mod search;
 pub mod terminals;
 pub mod worktree;
@@ -18,9 +24,15 @@ use client::{proto, Client, Collaborator, TypedEnvelope, UserStore};
 use clock::ReplicaId;
 use collections::{hash_map, BTreeMap, HashMap, HashSet, VecDeque};
 use copilot::Copilot;
+use db::kvp::KEY_VALUE_STORE;
 use debounced_delay::DebouncedDelay;
+use edit_attribution::BufferEditAttribution;
+use entry_decoration::WorktreeEntryDecorations;
 use futures::{
-    channel::mpsc::{self, UnboundedReceiver},
+    channel::{
+        mpsc::{self, UnboundedReceiver},
+        oneshot,
+    },
     future::{try_join_all, Shared},
     stream::FuturesUnordered,
     AsyncWriteExt, Future, FutureExt, StreamExt, TryFutureExt,
@@ -52,6 +64,7 @@ use lsp::{
 };
 use lsp_command::*;
 use node_runtime::NodeRuntime;
+use operation_journal::{OperationJournal, RecoveredOperations};
 use parking_lot::{Mutex, RwLock};
 use postage::watch;
 use prettier_support::{DefaultPrettier, PrettierInstance};
@@ -84,16 +97,30 @@ use std::{
 use terminals::Terminals;
 use text::{Anchor, BufferId};
 use util::{
-    debug_panic, defer, http::HttpClient, merge_json_value_into,
-    paths::LOCAL_SETTINGS_RELATIVE_PATH, post_inc, ResultExt, TryFutureExt as _,
+    debug_panic, defer,
+    http::HttpClient,
+    merge_json_value_into,
+    paths::{
+        paths_equal_normalized, LOCAL_COLLAB_POLICY_RELATIVE_PATH, LOCAL_SETTINGS_RELATIVE_PATH,
+        PROJECT_TEMPLATES_DIR,
+    },
+    post_inc, ResultExt, TryFutureExt as _,
 };
 
+pub use collab_policy::{CollabPolicy, CollabRole};
+pub use edit_attribution::{AttributedRange, EditAuthor};
+pub use edit_suggestion::{EditSuggestion, MAX_SUGGESTED_TEXT_LEN};
+pub use entry_decoration::EntryDecoration;
 pub use fs::*;
 #[cfg(any(test, feature = "test-support"))]
 pub use prettier::FORMAT_SUFFIX as TEST_PRETTIER_FORMAT_SUFFIX;
+pub use review::{Review, ReviewComment, ReviewExcerpt, REVIEW_CONTEXT_LINES};
 pub use worktree::*;
 
 const MAX_SERVER_REINSTALL_ATTEMPT_COUNT: u64 = 4;
+/// How long the languages have to stop changing before the decorations of shared
+/// worktrees' entries are detected again.
+const ENTRY_DECORATIONS_DEBOUNCE: Duration = Duration::from_millis(250);
 
 pub trait Item {
     fn entry_id(&self, cx: &AppContext) -> Option<ProjectEntryId>;
@@ -138,6 +165,9 @@ pub struct Project {
     /// A mapping from a buffer ID to None means that we've started waiting for an ID but haven't finished loading it.
     /// Used for re-issuing buffer requests when peers temporarily disconnect
     incomplete_remote_buffers: HashMap<BufferId, Option<Model<Buffer>>>,
+    /// Buffers that this guest closed, along with the request telling the host so. They're
+    /// forgotten once the host sends them again.
+    closed_remote_buffers: HashMap<BufferId, Shared<Task<()>>>,
     buffer_snapshots: HashMap<BufferId, HashMap<LanguageServerId, Vec<LspBufferSnapshot>>>, // buffer_id -> server_id -> vec of snapshots
     buffers_being_formatted: HashSet<BufferId>,
     buffers_needing_diff: HashSet<WeakModel<Buffer>>,
@@ -153,6 +183,26 @@ pub struct Project {
     default_prettier: DefaultPrettier,
     prettiers_per_worktree: HashMap<WorktreeId, HashSet<Option<PathBuf>>>,
     prettier_instances: HashMap<PathBuf, PrettierInstance>,
+    bookmarks: Vec<Bookmark>,
+    /// The buffers the host's bookmarks point into, kept open for as long as
+    /// they're bookmarked so that their anchors can always be resolved.
+    bookmarked_buffers: HashMap<BufferId, Model<Buffer>>,
+    next_bookmark_id: u64,
+    annotations: Vec<Annotation>,
+    next_annotation_id: u64,
+    review: Option<Review>,
+    next_review_comment_id: u64,
+    edit_suggestions: Vec<EditSuggestion>,
+    next_edit_suggestion_id: u64,
+    /// Who wrote what in the host's buffers while the project was shared.
+    edit_attributions: HashMap<BufferId, BufferEditAttribution>,
+    /// The sharing rules declared by each local worktree's `.zed/collab.toml`.
+    collab_policies: HashMap<WorktreeId, CollabPolicy>,
+    /// The host's decorations of each worktree's entries, which guests are sent.
+    entry_decorations: HashMap<WorktreeId, WorktreeEntryDecorations>,
+    entry_decorations_debouncer: DebouncedDelay,
+    /// Edits made as a guest that hadn't reached the host when Zed last quit.
+    recoverable_operations: Option<RecoveredOperations>,
 }
 
 pub enum LanguageServerToQuery {
@@ -176,6 +226,11 @@ enum BufferOrderedMessage {
         message: proto::update_language_server::Variant,
     },
     Resync,
+    /// Resolves once every message before it has been sent, reporting whether all of the
+    /// buffer operations among them were acknowledged by the server.
+    Flush(oneshot::Sender<bool>),
+    /// Leaves a remote project once every message before it has been sent.
+    LeaveProject,
 }
 
 enum LocalProjectUpdate {
@@ -209,6 +264,11 @@ enum ProjectClientState {
     Remote {
         sharing_has_stopped: bool,
         capability: Capability,
+        role: proto::ChannelRole,
+        /// Whether the host has made the project read-only for this guest, through
+        /// their collaboration policy or by changing the guest's access, regardless
+        /// of their role.
+        read_only_by_host: bool,
         remote_id: u64,
         replica_id: ReplicaId,
     },
@@ -270,8 +330,14 @@ pub enum Event {
     },
     CollaboratorJoined(proto::PeerId),
     CollaboratorLeft(proto::PeerId),
+    CollaboratorReadOnlyChanged(proto::PeerId),
     RefreshInlayHints,
     RevealInProjectPanel(ProjectEntryId),
+    BookmarksChanged,
+    AnnotationsChanged,
+    ReviewChanged,
+    EditSuggestionsChanged,
+    RecoverableOperationsFound,
 }
 
 pub enum LanguageServerState {
@@ -291,6 +357,8 @@ pub struct LanguageServerStatus {
     pub name: String,
     pub pending_work: BTreeMap<String, LanguageServerProgress>,
     pub has_pending_diagnostic_updates: bool,
+    /// The workspace configuration the server runs with, with secrets redacted.
+    pub configuration: Option<serde_json::Value>,
     progress_tokens: HashSet<String>,
 }
 
@@ -378,6 +446,59 @@ pub struct MarkupContent {
     pub value: String,
 }
 
+/// A named position in a buffer, shared with everyone collaborating on the project.
+///
+/// Bookmarks are owned by the host. Guests ask the host to add or remove them,
+/// and receive the full list whenever it changes. The host keeps a bookmarked
+/// buffer open until its last bookmark is removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bookmark {
+    pub id: u64,
+    pub name: String,
+    pub buffer_id: BufferId,
+    pub anchor: language::Anchor,
+    pub author_user_id: Option<u64>,
+}
+
+impl Bookmark {
+    fn to_proto(&self) -> proto::Bookmark {
+        proto::Bookmark {
+            id: self.id,
+            name: self.name.clone(),
+            buffer_id: self.buffer_id.into(),
+            anchor: Some(serialize_anchor(&self.anchor)),
+            author_user_id: self.author_user_id,
+        }
+    }
+
+    fn from_proto(message: proto::Bookmark) -> Result<Self> {
+        Ok(Self {
+            id: message.id,
+            name: message.name,
+            buffer_id: BufferId::new(message.buffer_id)?,
+            anchor: message
+                .anchor
+                .and_then(deserialize_anchor)
+                .ok_or_else(|| anyhow!("invalid bookmark anchor"))?,
+            author_user_id: message.author_user_id,
+        })
+    }
+}
+
+/// A private note on a span of a buffer, such as a place a reviewer wants to
+/// come back to.
+///
+/// Unlike bookmarks, annotations are never sent to the other participants.
+/// They only live as long as this peer's project, and stay anchored to their
+/// text as the buffer is edited and resynchronized after reconnecting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    pub id: u64,
+    pub buffer_id: BufferId,
+    pub range: Range<language::Anchor>,
+    pub note: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct LocationLink {
     pub origin: Option<Location>,
@@ -431,6 +552,35 @@ impl Hover {
 #[derive(Default)]
 pub struct ProjectTransaction(pub HashMap<Model<Buffer>, language::Transaction>);
 
+/// A file that was modified by a [`ProjectTransaction`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangedFile {
+    pub path: Option<ProjectPath>,
+    /// The number of disjoint ranges that were edited in the file.
+    pub edit_count: usize,
+}
+
+impl ProjectTransaction {
+    /// Summarizes the files touched by this transaction, ordered by path.
+    pub fn changed_files(&self, cx: &AppContext) -> Vec<ChangedFile> {
+        let mut changed_files = self
+            .0
+            .iter()
+            .map(|(buffer, transaction)| {
+                let buffer = buffer.read(cx);
+                ChangedFile {
+                    path: buffer.project_path(cx),
+                    edit_count: buffer
+                        .edited_ranges_for_transaction::<usize>(transaction)
+                        .count(),
+                }
+            })
+            .collect::<Vec<_>>();
+        changed_files.sort_by(|a, b| a.path.cmp(&b.path));
+        changed_files
+    }
+}
+
 impl DiagnosticSummary {
     fn new<'a, T: 'a>(diagnostics: impl IntoIterator<Item = &'a DiagnosticEntry<T>>) -> Self {
         let mut this = Self {
@@ -461,7 +611,7 @@ impl DiagnosticSummary {
         path: &Path,
     ) -> proto::DiagnosticSummary {
         proto::DiagnosticSummary {
-            path: path.to_string_lossy().to_string(),
+            path: proto::path_to_proto(path),
             language_server_id: language_server_id.0 as u64,
             error_count: self.error_count as u32,
             warning_count: self.warning_count as u32,
@@ -554,6 +704,7 @@ impl Project {
 
         client.add_model_message_handler(Self::handle_add_collaborator);
         client.add_model_message_handler(Self::handle_update_project_collaborator);
+        client.add_model_message_handler(Self::handle_update_collaborator_read_only);
         client.add_model_message_handler(Self::handle_remove_collaborator);
         client.add_model_message_handler(Self::handle_buffer_reloaded);
         client.add_model_message_handler(Self::handle_buffer_saved);
@@ -562,6 +713,8 @@ impl Project {
         client.add_model_message_handler(Self::handle_update_project);
         client.add_model_message_handler(Self::handle_unshare_project);
         client.add_model_message_handler(Self::handle_create_buffer_for_peer);
+        client.add_model_message_handler(Self::handle_unshare_buffer_for_peer);
+        client.add_model_request_handler(Self::handle_close_buffer);
         client.add_model_message_handler(Self::handle_update_buffer_file);
         client.add_model_request_handler(Self::handle_update_buffer);
         client.add_model_message_handler(Self::handle_update_diagnostic_summary);
@@ -588,6 +741,7 @@ impl Project {
         client.add_model_request_handler(Self::handle_lsp_command::<GetDefinition>);
         client.add_model_request_handler(Self::handle_lsp_command::<GetTypeDefinition>);
         client.add_model_request_handler(Self::handle_lsp_command::<GetDocumentHighlights>);
+        client.add_model_request_handler(Self::handle_lsp_command::<GetLinkedEditingRanges>);
         client.add_model_request_handler(Self::handle_lsp_command::<GetReferences>);
         client.add_model_request_handler(Self::handle_lsp_command::<PrepareRename>);
         client.add_model_request_handler(Self::handle_lsp_command::<PerformRename>);
@@ -598,6 +752,20 @@ impl Project {
         client.add_model_request_handler(Self::handle_open_buffer_by_path);
         client.add_model_request_handler(Self::handle_save_buffer);
         client.add_model_message_handler(Self::handle_update_diff_base);
+        client.add_model_request_handler(Self::handle_add_bookmark);
+        client.add_model_request_handler(Self::handle_remove_bookmark);
+        client.add_model_request_handler(Self::handle_get_bookmarks);
+        client.add_model_request_handler(Self::handle_get_language_server_configurations);
+        client.add_model_message_handler(Self::handle_update_bookmarks);
+        client.add_model_request_handler(Self::handle_get_entry_decorations);
+        client.add_model_message_handler(Self::handle_update_entry_decorations);
+        client.add_model_request_handler(Self::handle_add_review_comment);
+        client.add_model_request_handler(Self::handle_resolve_review_comment);
+        client.add_model_request_handler(Self::handle_get_review);
+        client.add_model_message_handler(Self::handle_update_review);
+        client.add_model_request_handler(Self::handle_suggest_edit);
+        client.add_model_request_handler(Self::handle_get_edit_suggestions);
+        client.add_model_message_handler(Self::handle_update_edit_suggestions);
         client.add_model_request_handler(Self::handle_lsp_command::<lsp_ext_command::ExpandMacro>);
     }
 
@@ -611,8 +779,11 @@ impl Project {
     ) -> Model<Self> {
         cx.new_model(|cx: &mut ModelContext<Self>| {
             let (tx, rx) = mpsc::unbounded();
-            cx.spawn(move |this, cx| Self::send_buffer_ordered_messages(this, rx, cx))
-                .detach();
+            cx.spawn({
+                let client = client.clone();
+                move |this, cx| Self::send_buffer_ordered_messages(this, client, true, None, rx, cx)
+            })
+            .detach();
             let copilot_lsp_subscription =
                 Copilot::global(cx).map(|copilot| subscribe_for_copilot_events(&copilot, cx));
             Self {
@@ -623,6 +794,7 @@ impl Project {
                 opened_buffers: Default::default(),
                 shared_buffers: Default::default(),
                 incomplete_remote_buffers: Default::default(),
+                closed_remote_buffers: Default::default(),
                 loading_buffers_by_path: Default::default(),
                 loading_local_worktrees: Default::default(),
                 local_buffer_ids_by_path: Default::default(),
@@ -665,6 +837,20 @@ impl Project {
                 default_prettier: DefaultPrettier::default(),
                 prettiers_per_worktree: HashMap::default(),
                 prettier_instances: HashMap::default(),
+                bookmarks: Vec::new(),
+                bookmarked_buffers: HashMap::default(),
+                next_bookmark_id: 1,
+                annotations: Vec::new(),
+                next_annotation_id: 1,
+                review: None,
+                next_review_comment_id: 1,
+                edit_suggestions: Vec::new(),
+                next_edit_suggestion_id: 1,
+                edit_attributions: Default::default(),
+                collab_policies: Default::default(),
+                entry_decorations: Default::default(),
+                entry_decorations_debouncer: DebouncedDelay::new(),
+                recoverable_operations: None,
             }
         })
     }
@@ -684,6 +870,7 @@ impl Project {
         let response = client
             .request_envelope(proto::JoinProject {
                 project_id: remote_id,
+                load_entries_lazily: true,
             })
             .await?;
         let this = cx.new_model(|cx| {
@@ -697,8 +884,13 @@ impl Project {
             }
 
             let (tx, rx) = mpsc::unbounded();
-            cx.spawn(move |this, cx| Self::send_buffer_ordered_messages(this, rx, cx))
-                .detach();
+            cx.spawn({
+                let client = client.clone();
+                move |this, cx| {
+                    Self::send_buffer_ordered_messages(this, client, false, Some(remote_id), rx, cx)
+                }
+            })
+            .detach();
             let copilot_lsp_subscription =
                 Copilot::global(cx).map(|copilot| subscribe_for_copilot_events(&copilot, cx));
             let mut this = Self {
@@ -709,6 +901,7 @@ impl Project {
                 opened_buffer: watch::channel(),
                 shared_buffers: Default::default(),
                 incomplete_remote_buffers: Default::default(),
+                closed_remote_buffers: Default::default(),
                 loading_local_worktrees: Default::default(),
                 local_buffer_ids_by_path: Default::default(),
                 local_buffer_ids_by_entry_id: Default::default(),
@@ -731,6 +924,8 @@ impl Project {
                 client_state: ProjectClientState::Remote {
                     sharing_has_stopped: false,
                     capability: Capability::ReadWrite,
+                    role,
+                    read_only_by_host: response.payload.read_only,
                     remote_id,
                     replica_id,
                 },
@@ -748,6 +943,7 @@ impl Project {
                                 name: server.name,
                                 pending_work: Default::default(),
                                 has_pending_diagnostic_updates: false,
+                                configuration: None,
                                 progress_tokens: Default::default(),
                             },
                         )
@@ -770,6 +966,20 @@ impl Project {
                 default_prettier: DefaultPrettier::default(),
                 prettiers_per_worktree: HashMap::default(),
                 prettier_instances: HashMap::default(),
+                bookmarks: Vec::new(),
+                bookmarked_buffers: HashMap::default(),
+                next_bookmark_id: 1,
+                annotations: Vec::new(),
+                next_annotation_id: 1,
+                review: None,
+                next_review_comment_id: 1,
+                edit_suggestions: Vec::new(),
+                next_edit_suggestion_id: 1,
+                edit_attributions: Default::default(),
+                collab_policies: Default::default(),
+                entry_decorations: Default::default(),
+                entry_decorations_debouncer: DebouncedDelay::new(),
+                recoverable_operations: None,
             };
             this.set_role(role, cx);
             for worktree in worktrees {
@@ -792,6 +1002,11 @@ impl Project {
         this.update(&mut cx, |this, cx| {
             this.set_collaborators_from_proto(response.payload.collaborators, cx)?;
             this.client_subscriptions.push(subscription);
+            this.refresh_bookmarks(cx);
+            this.refresh_entry_decorations(cx);
+            this.refresh_review(cx);
+            this.refresh_edit_suggestions(cx);
+            this.refresh_language_server_configurations(cx);
             anyhow::Ok(())
         })??;
 
@@ -804,10 +1019,12 @@ impl Project {
             ProjectClientState::Shared { .. } => {
                 let _ = self.unshare_internal(cx);
             }
-            ProjectClientState::Remote { remote_id, .. } => {
-                let _ = self.client.send(proto::LeaveProject {
-                    project_id: *remote_id,
-                });
+            ProjectClientState::Remote { .. } => {
+                // Leave once any operations that are still queued have been sent, so that
+                // the host receives every edit made before the project was closed.
+                let _ = self
+                    .buffer_ordered_messages_tx
+                    .unbounded_send(BufferOrderedMessage::LeaveProject);
                 self.disconnected_from_host_internal(cx);
             }
         }
@@ -1012,7 +1229,9 @@ impl Project {
             self.opened_buffers.iter().any(|(_, buffer)| {
                 if let Some(buffer) = buffer.upgrade() {
                     if let Some(file) = File::from_dyn(buffer.read(cx).file()) {
-                        if file.worktree == worktree && file.path() == &path.path {
+                        if file.worktree == worktree
+                            && paths_equal_normalized(file.path(), &path.path)
+                        {
                             return true;
                         }
                     }
@@ -1060,6 +1279,98 @@ impl Project {
         self.collaborators.values().find(|c| c.replica_id == 0)
     }
 
+    /// Makes the shared project read-only or read-write for one of its guests.
+    ///
+    /// Only the host can change a guest's access, and guests whose role in the
+    /// room doesn't allow them to edit stay read-only either way.
+    pub fn set_collaborator_read_only(
+        &mut self,
+        peer_id: proto::PeerId,
+        read_only: bool,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        let project_id = match self.client_state {
+            ProjectClientState::Shared { remote_id, .. } => remote_id,
+            _ => {
+                return Task::ready(Err(anyhow!(
+                    "only the host of a shared project can change a guest's access"
+                )))
+            }
+        };
+        if !self.collaborators.contains_key(&peer_id) {
+            return Task::ready(Err(anyhow!("unknown peer {:?}", peer_id)));
+        }
+
+        let request = self.client.request(proto::SetCollaboratorReadOnly {
+            project_id,
+            peer_id: Some(peer_id),
+            read_only,
+        });
+        cx.spawn(move |this, mut cx| async move {
+            request.await?;
+            this.update(&mut cx, |this, cx| {
+                this.collaborator_read_only_changed(peer_id, read_only, cx)
+            })
+        })
+    }
+
+    fn collaborator_read_only_changed(
+        &mut self,
+        peer_id: proto::PeerId,
+        read_only: bool,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if Some(peer_id) == self.client.peer_id() {
+            if let ProjectClientState::Remote {
+                read_only_by_host, ..
+            } = &mut self.client_state
+            {
+                *read_only_by_host = read_only;
+            }
+            self.update_capability(cx);
+        } else if let Some(collaborator) = self.collaborators.get_mut(&peer_id) {
+            collaborator.read_only = read_only;
+        } else {
+            return;
+        }
+        cx.emit(Event::CollaboratorReadOnlyChanged(peer_id));
+        cx.notify();
+    }
+
+    /// The buffers that each guest has open in this project, keyed by the guest's peer id.
+    ///
+    /// This is only maintained on the host, which records every buffer it replicates to a
+    /// guest, forgets it when the guest closes it, and rebuilds the record from the guest's
+    /// own state when the guest reconnects.
+    pub fn guest_open_buffers(&self) -> &HashMap<proto::PeerId, HashSet<BufferId>> {
+        &self.shared_buffers
+    }
+
+    /// Returns the guests that have the file at `path` open, or any file within it when
+    /// `path` is a directory.
+    pub fn guests_with_open_buffers_in(&self, path: &ProjectPath) -> Vec<&Collaborator> {
+        let buffer_ids = self
+            .local_buffer_ids_by_path
+            .iter()
+            .filter(|(buffer_path, _)| {
+                buffer_path.worktree_id == path.worktree_id
+                    && buffer_path.path.starts_with(&path.path)
+            })
+            .map(|(_, buffer_id)| *buffer_id)
+            .collect::<HashSet<_>>();
+        let mut guests = self
+            .collaborators
+            .values()
+            .filter(|collaborator| {
+                self.shared_buffers
+                    .get(&collaborator.peer_id)
+                    .map_or(false, |open_buffers| !open_buffers.is_disjoint(&buffer_ids))
+            })
+            .collect::<Vec<_>>();
+        guests.sort_by_key(|collaborator| collaborator.replica_id);
+        guests
+    }
+
     /// Collect all worktrees, including ones that don't appear in the project panel
     pub fn worktrees<'a>(&'a self) -> impl 'a + DoubleEndedIterator<Item = Model<Worktree>> {
         self.worktrees
@@ -1150,7 +1461,7 @@ impl Project {
                     .request(proto::CreateProjectEntry {
                         worktree_id: project_path.worktree_id.to_proto(),
                         project_id,
-                        path: project_path.path.to_string_lossy().into(),
+                        path: proto::path_to_proto(&project_path.path),
                         is_directory,
                     })
                     .await?;
@@ -1197,7 +1508,7 @@ impl Project {
                     .request(proto::CopyProjectEntry {
                         project_id,
                         entry_id: entry_id.to_proto(),
-                        new_path: new_path.to_string_lossy().into(),
+                        new_path: proto::path_to_proto(&new_path),
                     })
                     .await?;
                 match response.entry {
@@ -1243,7 +1554,7 @@ impl Project {
                     .request(proto::RenameProjectEntry {
                         project_id,
                         entry_id: entry_id.to_proto(),
-                        new_path: new_path.to_string_lossy().into(),
+                        new_path: proto::path_to_proto(&new_path),
                     })
                     .await?;
                 match response.entry {
@@ -1299,6 +1610,24 @@ impl Project {
         }
     }
 
+    /// Loads every entry of the visible worktrees whose entries are loaded lazily
+    /// from the host, so that all of their paths can be searched.
+    pub fn load_all_entries(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        let worktrees = self.visible_worktrees(cx).collect::<Vec<_>>();
+        let tasks = worktrees
+            .into_iter()
+            .filter_map(|worktree| {
+                worktree.update(cx, |worktree, cx| {
+                    Some(worktree.as_remote_mut()?.load_all_entries(cx))
+                })
+            })
+            .collect::<Vec<_>>();
+        cx.background_executor().spawn(async move {
+            try_join_all(tasks).await?;
+            Ok(())
+        })
+    }
+
     pub fn expand_entry(
         &mut self,
         worktree_id: WorktreeId,
@@ -1311,12 +1640,19 @@ impl Project {
                 worktree.as_local_mut().unwrap().expand_entry(entry_id, cx)
             })
         } else {
+            // Guests of large worktrees only have the entries of the directories
+            // they've loaded.
+            let path = worktree.read(cx).entry_for_id(entry_id)?.path.clone();
+            let load_directory = worktree.update(cx, |worktree, cx| {
+                worktree.as_remote_mut().unwrap().load_directory(path, cx)
+            });
             let worktree = worktree.downgrade();
             let request = self.client.request(proto::ExpandProjectEntry {
                 project_id: self.remote_id().unwrap(),
                 entry_id: entry_id.to_proto(),
             });
             Some(cx.spawn(move |_, mut cx| async move {
+                load_directory.await?;
                 let response = request.await?;
                 if let Some(worktree) = worktree.upgrade() {
                     worktree
@@ -1355,6 +1691,16 @@ impl Project {
             }
         }
 
+        if Self::records_edit_attribution(cx) {
+            for buffer in self.opened_buffers() {
+                let buffer = buffer.read(cx);
+                self.edit_attributions
+                    .entry(buffer.remote_id())
+                    .or_insert_with(|| BufferEditAttribution::new(buffer))
+                    .start_session(buffer);
+            }
+        }
+
         for worktree_handle in self.worktrees.iter_mut() {
             match worktree_handle {
                 WorktreeHandle::Strong(_) => {}
@@ -1366,32 +1712,11 @@ impl Project {
             }
         }
 
-        for (server_id, status) in &self.language_server_statuses {
-            self.client
-                .send(proto::StartLanguageServer {
-                    project_id,
-                    server: Some(proto::LanguageServer {
-                        id: server_id.0 as u64,
-                        name: status.name.clone(),
-                    }),
-                })
-                .log_err();
-        }
+        self.send_language_servers_and_settings(project_id, cx);
 
-        let store = cx.global::<SettingsStore>();
-        for worktree in self.worktrees() {
-            let worktree_id = worktree.read(cx).id().to_proto();
-            for (path, content) in store.local_settings(worktree.entity_id().as_u64() as usize) {
-                self.client
-                    .send(proto::UpdateWorktreeSettings {
-                        project_id,
-                        worktree_id,
-                        path: path.to_string_lossy().into(),
-                        content: Some(content),
-                    })
-                    .log_err();
-            }
-        }
+        // Guests fetch the decorations when they join, so there's nobody to send
+        // them to yet.
+        self.detect_all_entry_decorations(cx);
 
         let (updates_tx, mut updates_rx) = mpsc::unbounded();
         let client = self.client.clone();
@@ -1423,19 +1748,22 @@ impl Project {
                             }
                         }
                         LocalProjectUpdate::CreateBufferForPeer { peer_id, buffer_id } => {
-                            let buffer = this.update(&mut cx, |this, _| {
-                                let buffer = this.opened_buffers.get(&buffer_id).unwrap();
-                                let shared_buffers =
-                                    this.shared_buffers.entry(peer_id).or_default();
-                                if shared_buffers.insert(buffer_id) {
-                                    if let OpenBuffer::Strong(buffer) = buffer {
-                                        Some(buffer.clone())
-                                    } else {
-                                        None
-                                    }
-                                } else {
-                                    None
+                            let buffer = this.update(&mut cx, |this, cx| {
+                                let OpenBuffer::Strong(buffer) =
+                                    this.opened_buffers.get(&buffer_id)?
+                                else {
+                                    return None;
+                                };
+                                let buffer = buffer.clone();
+                                // The policy may have changed since the buffer was queued.
+                                if !this.is_buffer_shareable(&buffer, cx) {
+                                    return None;
                                 }
+                                this.shared_buffers
+                                    .entry(peer_id)
+                                    .or_default()
+                                    .insert(buffer_id)
+                                    .then_some(buffer)
                             })?;
 
                             let Some(buffer) = buffer else { continue };
@@ -1488,6 +1816,37 @@ impl Project {
         Ok(())
     }
 
+    /// Tells the server which language servers are running and what the worktrees'
+    /// local settings are, for it to pass on to guests.
+    fn send_language_servers_and_settings(&self, project_id: u64, cx: &AppContext) {
+        for (server_id, status) in &self.language_server_statuses {
+            self.client
+                .send(proto::StartLanguageServer {
+                    project_id,
+                    server: Some(proto::LanguageServer {
+                        id: server_id.0 as u64,
+                        name: status.name.clone(),
+                    }),
+                })
+                .log_err();
+        }
+
+        let store = cx.global::<SettingsStore>();
+        for worktree in self.worktrees() {
+            let worktree_id = worktree.read(cx).id().to_proto();
+            for (path, content) in store.local_settings(worktree.entity_id().as_u64() as usize) {
+                self.client
+                    .send(proto::UpdateWorktreeSettings {
+                        project_id,
+                        worktree_id,
+                        path: proto::path_to_proto(&path),
+                        content: Some(content),
+                    })
+                    .log_err();
+            }
+        }
+    }
+
     pub fn reshared(
         &mut self,
         message: proto::ResharedProject,
@@ -1496,6 +1855,16 @@ impl Project {
         self.shared_buffers.clear();
         self.set_collaborators_from_proto(message.collaborators, cx)?;
         self.metadata_changed(cx);
+
+        // The server disconnects hosts whose messages it failed to store, so send
+        // everything that isn't resent by the worktrees' update streams again.
+        let project_id = message.id;
+        self.send_language_servers_and_settings(project_id, cx);
+        for worktree in self.worktrees() {
+            if let Some(worktree) = worktree.read(cx).as_local() {
+                worktree.send_diagnostic_summaries(project_id).log_err();
+            }
+        }
         Ok(())
     }
 
@@ -1526,6 +1895,7 @@ impl Project {
                         name: server.name,
                         pending_work: Default::default(),
                         has_pending_diagnostic_updates: false,
+                        configuration: None,
                         progress_tokens: Default::default(),
                     },
                 )
@@ -1534,6 +1904,11 @@ impl Project {
         self.buffer_ordered_messages_tx
             .unbounded_send(BufferOrderedMessage::Resync)
             .unwrap();
+        self.refresh_bookmarks(cx);
+        self.refresh_entry_decorations(cx);
+        self.refresh_review(cx);
+        self.refresh_edit_suggestions(cx);
+        self.refresh_language_server_configurations(cx);
         cx.notify();
         Ok(())
     }
@@ -1551,10 +1926,18 @@ impl Project {
         }
 
         if let ProjectClientState::Shared { remote_id, .. } = self.client_state {
+            self.attribute_session_edits(cx);
+            self.persist_edit_attributions(self.edit_attributions.keys().copied(), cx);
+
             self.client_state = ProjectClientState::Local;
             self.collaborators.clear();
             self.shared_buffers.clear();
             self.client_subscriptions.clear();
+            // Suggestions can only be credited to their authors while the
+            // project is shared, so they don't outlive the session.
+            self.edit_suggestions.clear();
+            self.entry_decorations.clear();
+            self.entry_decorations_debouncer = DebouncedDelay::new();
 
             for worktree_handle in self.worktrees.iter_mut() {
                 if let WorktreeHandle::Strong(worktree) = worktree_handle {
@@ -1596,13 +1979,26 @@ impl Project {
     }
 
     pub fn set_role(&mut self, role: proto::ChannelRole, cx: &mut ModelContext<Self>) {
-        let new_capability =
-            if role == proto::ChannelRole::Member || role == proto::ChannelRole::Admin {
+        if let ProjectClientState::Remote { role: old_role, .. } = &mut self.client_state {
+            *old_role = role;
+            self.update_capability(cx);
+        }
+    }
+
+    fn update_capability(&mut self, cx: &mut ModelContext<Self>) {
+        if let ProjectClientState::Remote {
+            capability,
+            role,
+            read_only_by_host,
+            ..
+        } = &mut self.client_state
+        {
+            let can_edit = matches!(role, proto::ChannelRole::Admin | proto::ChannelRole::Member);
+            let new_capability = if can_edit && !*read_only_by_host {
                 Capability::ReadWrite
             } else {
                 Capability::ReadOnly
             };
-        if let ProjectClientState::Remote { capability, .. } = &mut self.client_state {
             if *capability == new_capability {
                 return;
             }
@@ -1611,6 +2007,14 @@ impl Project {
             for buffer in self.opened_buffers() {
                 buffer.update(cx, |buffer, cx| buffer.set_capability(new_capability, cx));
             }
+
+            // Edits made before the guest learned that they can no longer edit are
+            // refused by the server, and are kept until the guest can edit again. Until
+            // then, the guest's buffers are brought up to date with the host's, and once
+            // they can edit again, the edits they kept are sent to the host.
+            self.buffer_ordered_messages_tx
+                .unbounded_send(BufferOrderedMessage::Resync)
+                .ok();
         }
     }
 
@@ -1644,6 +2048,7 @@ impl Project {
                     *open_buffer = OpenBuffer::Weak(buffer.downgrade());
                 }
             }
+            self.closed_remote_buffers.clear();
 
             // Wake up all futures currently waiting on a buffer to get opened,
             // to give them a chance to fail now that we've disconnected.
@@ -1819,7 +2224,7 @@ impl Project {
         let project_id = self.remote_id().unwrap();
         let remote_worktree_id = worktree.read(cx).id();
         let path = path.clone();
-        let path_string = path.to_string_lossy().to_string();
+        let path_string = proto::path_to_proto(&path);
         cx.spawn(move |this, mut cx| async move {
             let response = rpc
                 .request(proto::OpenBufferByPath {
@@ -1903,72 +2308,1019 @@ impl Project {
         }
     }
 
-    pub fn save_buffers(
-        &self,
-        buffers: HashSet<Model<Buffer>>,
-        cx: &mut ModelContext<Self>,
-    ) -> Task<Result<()>> {
-        cx.spawn(move |this, mut cx| async move {
-            let save_tasks = buffers.into_iter().filter_map(|buffer| {
-                this.update(&mut cx, |this, cx| this.save_buffer(buffer, cx))
-                    .ok()
-            });
-            try_join_all(save_tasks).await?;
-            Ok(())
-        })
+    pub fn bookmarks(&self) -> &[Bookmark] {
+        &self.bookmarks
     }
 
-    pub fn save_buffer(
-        &self,
-        buffer: Model<Buffer>,
-        cx: &mut ModelContext<Self>,
-    ) -> Task<Result<()>> {
-        let Some(file) = File::from_dyn(buffer.read(cx).file()) else {
-            return Task::ready(Err(anyhow!("buffer doesn't have a file")));
-        };
-        let worktree = file.worktree.clone();
-        let path = file.path.clone();
-        worktree.update(cx, |worktree, cx| match worktree {
-            Worktree::Local(worktree) => worktree.save_buffer(buffer, path, false, cx),
-            Worktree::Remote(worktree) => worktree.save_buffer(buffer, cx),
-        })
+    pub fn bookmark(&self, id: u64) -> Option<&Bookmark> {
+        self.bookmarks.iter().find(|bookmark| bookmark.id == id)
     }
 
-    pub fn save_buffer_as(
+    pub fn add_bookmark(
         &mut self,
-        buffer: Model<Buffer>,
-        abs_path: PathBuf,
+        name: String,
+        buffer: &Model<Buffer>,
+        anchor: language::Anchor,
         cx: &mut ModelContext<Self>,
-    ) -> Task<Result<()>> {
-        let worktree_task = self.find_or_create_local_worktree(&abs_path, true, cx);
-        let old_file = File::from_dyn(buffer.read(cx).file())
-            .filter(|f| f.is_local())
-            .cloned();
-        cx.spawn(move |this, mut cx| async move {
-            if let Some(old_file) = &old_file {
+    ) -> Task<Result<Bookmark>> {
+        let buffer_id = buffer.read(cx).remote_id();
+        if self.is_local() {
+            let author_user_id = self.user_store.read(cx).current_user().map(|user| user.id);
+            Task::ready(Ok(self.insert_bookmark(
+                name,
+                buffer.clone(),
+                anchor,
+                author_user_id,
+                cx,
+            )))
+        } else if let Some(project_id) = self.remote_id() {
+            let request = self.client.request(proto::AddBookmark {
+                project_id,
+                name,
+                buffer_id: buffer_id.into(),
+                anchor: Some(serialize_anchor(&anchor)),
+            });
+            cx.spawn(move |this, mut cx| async move {
+                let bookmark = request
+                    .await?
+                    .bookmark
+                    .ok_or_else(|| anyhow!("missing bookmark"))?;
+                let bookmark = Bookmark::from_proto(bookmark)?;
                 this.update(&mut cx, |this, cx| {
-                    this.unregister_buffer_from_language_servers(&buffer, old_file, cx);
+                    if this.bookmark(bookmark.id).is_none() {
+                        this.bookmarks.push(bookmark.clone());
+                        cx.emit(Event::BookmarksChanged);
+                        cx.notify();
+                    }
                 })?;
-            }
-            let (worktree, path) = worktree_task.await?;
-            worktree
-                .update(&mut cx, |worktree, cx| match worktree {
-                    Worktree::Local(worktree) => {
-                        worktree.save_buffer(buffer.clone(), path.into(), true, cx)
+                Ok(bookmark)
+            })
+        } else {
+            Task::ready(Err(anyhow!("cannot add bookmark while disconnected")))
+        }
+    }
+
+    pub fn remove_bookmark(&mut self, id: u64, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        if self.is_local() {
+            self.remove_local_bookmark(id, cx);
+            Task::ready(Ok(()))
+        } else if let Some(project_id) = self.remote_id() {
+            let request = self.client.request(proto::RemoveBookmark {
+                project_id,
+                bookmark_id: id,
+            });
+            cx.spawn(move |this, mut cx| async move {
+                request.await?;
+                this.update(&mut cx, |this, cx| {
+                    let len = this.bookmarks.len();
+                    this.bookmarks.retain(|bookmark| bookmark.id != id);
+                    if this.bookmarks.len() != len {
+                        cx.emit(Event::BookmarksChanged);
+                        cx.notify();
                     }
-                    Worktree::Remote(_) => panic!("cannot remote buffers as new files"),
+                })
+            })
+        } else {
+            Task::ready(Err(anyhow!("cannot remove bookmark while disconnected")))
+        }
+    }
+
+    /// Opens the buffer containing the given bookmark, resolving to its location
+    /// once the bookmarked position has been replicated to this peer.
+    pub fn open_bookmark(
+        &mut self,
+        id: u64,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Location>> {
+        let Some(bookmark) = self.bookmark(id).cloned() else {
+            return Task::ready(Err(anyhow!("unknown bookmark {}", id)));
+        };
+        let open_buffer = self.open_buffer_by_id(bookmark.buffer_id, cx);
+        cx.spawn(move |_, mut cx| async move {
+            let buffer = open_buffer.await?;
+            buffer
+                .update(&mut cx, |buffer, _| {
+                    buffer.wait_for_anchors([bookmark.anchor])
                 })?
                 .await?;
-
-            this.update(&mut cx, |this, cx| {
-                this.detect_language_for_buffer(&buffer, cx);
-                this.register_buffer_with_language_servers(&buffer, cx);
-            })?;
-            Ok(())
+            Ok(Location {
+                buffer,
+                range: bookmark.anchor..bookmark.anchor,
+            })
         })
     }
 
-    pub fn get_open_buffer(
+    fn insert_bookmark(
+        &mut self,
+        name: String,
+        buffer: Model<Buffer>,
+        anchor: language::Anchor,
+        author_user_id: Option<u64>,
+        cx: &mut ModelContext<Self>,
+    ) -> Bookmark {
+        let buffer_id = buffer.read(cx).remote_id();
+        self.bookmarked_buffers.insert(buffer_id, buffer);
+        let id = post_inc(&mut self.next_bookmark_id);
+        let name = name.trim();
+        let bookmark = Bookmark {
+            id,
+            name: if name.is_empty() {
+                id.to_string()
+            } else {
+                name.to_string()
+            },
+            buffer_id,
+            anchor,
+            author_user_id,
+        };
+        self.bookmarks.push(bookmark.clone());
+        self.bookmarks_changed(cx);
+        bookmark
+    }
+
+    fn remove_local_bookmark(&mut self, id: u64, cx: &mut ModelContext<Self>) {
+        let len = self.bookmarks.len();
+        self.bookmarks.retain(|bookmark| bookmark.id != id);
+        if self.bookmarks.len() != len {
+            let bookmarks = &self.bookmarks;
+            self.bookmarked_buffers.retain(|buffer_id, _| {
+                bookmarks
+                    .iter()
+                    .any(|bookmark| bookmark.buffer_id == *buffer_id)
+            });
+            self.bookmarks_changed(cx);
+        }
+    }
+
+    fn bookmarks_changed(&mut self, cx: &mut ModelContext<Self>) {
+        if let ProjectClientState::Shared { remote_id, .. } = &self.client_state {
+            self.client
+                .send(proto::UpdateBookmarks {
+                    project_id: *remote_id,
+                    bookmarks: self.bookmarks.iter().map(Bookmark::to_proto).collect(),
+                })
+                .log_err();
+        }
+        cx.emit(Event::BookmarksChanged);
+        cx.notify();
+    }
+
+    fn set_bookmarks_from_proto(
+        &mut self,
+        bookmarks: Vec<proto::Bookmark>,
+        cx: &mut ModelContext<Self>,
+    ) -> Result<()> {
+        self.bookmarks = bookmarks
+            .into_iter()
+            .map(Bookmark::from_proto)
+            .collect::<Result<_>>()?;
+        cx.emit(Event::BookmarksChanged);
+        cx.notify();
+        Ok(())
+    }
+
+    fn refresh_bookmarks(&self, cx: &mut ModelContext<Self>) {
+        if self.is_local() {
+            return;
+        }
+        let Some(project_id) = self.remote_id() else {
+            return;
+        };
+        let request = self.client.request(proto::GetBookmarks { project_id });
+        cx.spawn(move |this, mut cx| async move {
+            let response = request.await?;
+            this.update(&mut cx, |this, cx| {
+                this.set_bookmarks_from_proto(response.bookmarks, cx)
+            })?
+        })
+        .detach_and_log_err(cx);
+    }
+
+    pub fn review(&self) -> Option<&Review> {
+        self.review.as_ref()
+    }
+
+    /// Starts a review of the changes in the given buffers, replacing the review
+    /// that's in progress, if any. Each buffer's diff is recomputed first, so
+    /// that the review covers its latest changes.
+    pub fn start_review(
+        &mut self,
+        buffers: Vec<Model<Buffer>>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        if !self.is_local() {
+            return Task::ready(Err(anyhow!("only the host can start a review")));
+        }
+
+        let diff_recalculations = buffers
+            .iter()
+            .filter_map(|buffer| buffer.update(cx, |buffer, cx| buffer.git_diff_recalc(cx)))
+            .collect::<Vec<_>>();
+        cx.spawn(move |this, mut cx| async move {
+            futures::future::join_all(diff_recalculations).await;
+            this.update(&mut cx, |this, cx| {
+                let excerpts = buffers
+                    .iter()
+                    .flat_map(|buffer| {
+                        let snapshot = buffer.read(cx).snapshot();
+                        let buffer_id = snapshot.remote_id();
+                        review::excerpt_ranges_for_changes(&snapshot)
+                            .into_iter()
+                            .map(move |range| ReviewExcerpt { buffer_id, range })
+                    })
+                    .collect();
+                this.review = Some(Review {
+                    excerpts,
+                    comments: Vec::new(),
+                });
+                this.review_changed(cx);
+            })
+        })
+    }
+
+    pub fn end_review(&mut self, cx: &mut ModelContext<Self>) {
+        if self.is_local() && self.review.take().is_some() {
+            self.review_changed(cx);
+        }
+    }
+
+    pub fn add_review_comment(
+        &mut self,
+        buffer: &Model<Buffer>,
+        range: Range<language::Anchor>,
+        body: String,
+        suggestion: Option<String>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<ReviewComment>> {
+        let buffer_id = buffer.read(cx).remote_id();
+        if self.is_local() {
+            let author_user_id = self.user_store.read(cx).current_user().map(|user| user.id);
+            Task::ready(self.insert_review_comment(
+                buffer_id,
+                range,
+                body,
+                suggestion,
+                author_user_id,
+                cx,
+            ))
+        } else if let Some(project_id) = self.remote_id() {
+            let request = self.client.request(proto::AddReviewComment {
+                project_id,
+                buffer_id: buffer_id.into(),
+                start: Some(serialize_anchor(&range.start)),
+                end: Some(serialize_anchor(&range.end)),
+                body,
+                suggestion,
+            });
+            cx.spawn(move |this, mut cx| async move {
+                let comment = request
+                    .await?
+                    .comment
+                    .ok_or_else(|| anyhow!("missing review comment"))?;
+                let comment = ReviewComment::from_proto(comment)?;
+                this.update(&mut cx, |this, cx| {
+                    if let Some(review) = this.review.as_mut() {
+                        if review.comment(comment.id).is_none() {
+                            review.comments.push(comment.clone());
+                            cx.emit(Event::ReviewChanged);
+                            cx.notify();
+                        }
+                    }
+                })?;
+                Ok(comment)
+            })
+        } else {
+            Task::ready(Err(anyhow!("cannot add review comment while disconnected")))
+        }
+    }
+
+    pub fn resolve_review_comment(
+        &mut self,
+        id: u64,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        if self.is_local() {
+            self.remove_local_review_comment(id, cx);
+            Task::ready(Ok(()))
+        } else if let Some(project_id) = self.remote_id() {
+            let request = self.client.request(proto::ResolveReviewComment {
+                project_id,
+                comment_id: id,
+            });
+            cx.spawn(move |this, mut cx| async move {
+                request.await?;
+                this.update(&mut cx, |this, cx| {
+                    if let Some(review) = this.review.as_mut() {
+                        let len = review.comments.len();
+                        review.comments.retain(|comment| comment.id != id);
+                        if review.comments.len() != len {
+                            cx.emit(Event::ReviewChanged);
+                            cx.notify();
+                        }
+                    }
+                })
+            })
+        } else {
+            Task::ready(Err(anyhow!(
+                "cannot resolve review comment while disconnected"
+            )))
+        }
+    }
+
+    /// Replaces the span that a review comment is attached to with the text the
+    /// comment suggests, and resolves the comment. The edit is an ordinary
+    /// buffer edit, so it reaches everyone else in the project like any other.
+    pub fn apply_review_suggestion(
+        &mut self,
+        id: u64,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        let Some(comment) = self
+            .review
+            .as_ref()
+            .and_then(|review| review.comment(id))
+            .cloned()
+        else {
+            return Task::ready(Err(anyhow!("unknown review comment {}", id)));
+        };
+        let Some(suggestion) = comment.suggestion else {
+            return Task::ready(Err(anyhow!("review comment {} suggests no edit", id)));
+        };
+        let open_buffer = self.open_buffer_by_id(comment.buffer_id, cx);
+        cx.spawn(move |this, mut cx| async move {
+            let buffer = open_buffer.await?;
+            let range = comment.range;
+            buffer
+                .update(&mut cx, |buffer, _| {
+                    buffer.wait_for_anchors([range.start, range.end])
+                })?
+                .await?;
+            buffer.update(&mut cx, |buffer, cx| {
+                if buffer.read_only() {
+                    return Err(anyhow!("cannot edit a read-only buffer"));
+                }
+                buffer.edit([(range, suggestion)], None, cx);
+                Ok(())
+            })??;
+            this.update(&mut cx, |this, cx| this.resolve_review_comment(id, cx))?
+                .await
+        })
+    }
+
+    /// Opens the buffers of the review's excerpts, resolving to their locations
+    /// once the excerpts' text has been replicated to this peer.
+    pub fn open_review_excerpts(
+        &mut self,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<Location>>> {
+        let Some(review) = self.review.clone() else {
+            return Task::ready(Err(anyhow!("no review in progress")));
+        };
+        cx.spawn(move |this, mut cx| async move {
+            let mut locations = Vec::new();
+            for excerpt in review.excerpts {
+                let buffer = this
+                    .update(&mut cx, |this, cx| {
+                        this.open_buffer_by_id(excerpt.buffer_id, cx)
+                    })?
+                    .await?;
+                buffer
+                    .update(&mut cx, |buffer, _| {
+                        buffer.wait_for_anchors([excerpt.range.start, excerpt.range.end])
+                    })?
+                    .await?;
+                locations.push(Location {
+                    buffer,
+                    range: excerpt.range,
+                });
+            }
+            Ok(locations)
+        })
+    }
+
+    fn insert_review_comment(
+        &mut self,
+        buffer_id: BufferId,
+        range: Range<language::Anchor>,
+        body: String,
+        suggestion: Option<String>,
+        author_user_id: Option<u64>,
+        cx: &mut ModelContext<Self>,
+    ) -> Result<ReviewComment> {
+        let review = self
+            .review
+            .as_mut()
+            .ok_or_else(|| anyhow!("no review in progress"))?;
+        let comment = ReviewComment {
+            id: post_inc(&mut self.next_review_comment_id),
+            buffer_id,
+            range,
+            body,
+            suggestion,
+            author_user_id,
+        };
+        review.comments.push(comment.clone());
+        self.review_changed(cx);
+        Ok(comment)
+    }
+
+    fn remove_local_review_comment(&mut self, id: u64, cx: &mut ModelContext<Self>) {
+        let Some(review) = self.review.as_mut() else {
+            return;
+        };
+        let len = review.comments.len();
+        review.comments.retain(|comment| comment.id != id);
+        if review.comments.len() != len {
+            self.review_changed(cx);
+        }
+    }
+
+    fn review_changed(&mut self, cx: &mut ModelContext<Self>) {
+        if let ProjectClientState::Shared { remote_id, .. } = &self.client_state {
+            self.client
+                .send(proto::UpdateReview {
+                    project_id: *remote_id,
+                    review: self.review.as_ref().map(Review::to_proto),
+                })
+                .log_err();
+        }
+        cx.emit(Event::ReviewChanged);
+        cx.notify();
+    }
+
+    fn set_review_from_proto(
+        &mut self,
+        review: Option<proto::Review>,
+        cx: &mut ModelContext<Self>,
+    ) -> Result<()> {
+        self.review = review.map(Review::from_proto).transpose()?;
+        cx.emit(Event::ReviewChanged);
+        cx.notify();
+        Ok(())
+    }
+
+    fn refresh_review(&self, cx: &mut ModelContext<Self>) {
+        if self.is_local() {
+            return;
+        }
+        let Some(project_id) = self.remote_id() else {
+            return;
+        };
+        let request = self.client.request(proto::GetReview { project_id });
+        cx.spawn(move |this, mut cx| async move {
+            let response = request.await?;
+            this.update(&mut cx, |this, cx| {
+                this.set_review_from_proto(response.review, cx)
+            })?
+        })
+        .detach_and_log_err(cx);
+    }
+
+    pub fn edit_suggestions(&self) -> &[EditSuggestion] {
+        &self.edit_suggestions
+    }
+
+    pub fn edit_suggestion(&self, id: u64) -> Option<&EditSuggestion> {
+        self.edit_suggestions
+            .iter()
+            .find(|suggestion| suggestion.id == id)
+    }
+
+    /// Proposes edits to a buffer for the host to apply or reject, as guests
+    /// who can't edit the project do instead of editing it.
+    pub fn suggest_edit(
+        &mut self,
+        buffer: &Model<Buffer>,
+        edits: Vec<(Range<language::Anchor>, String)>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<EditSuggestion>> {
+        if let Err(error) = edit_suggestion::check_suggested_edits(&edits) {
+            return Task::ready(Err(error));
+        }
+        let buffer_id = buffer.read(cx).remote_id();
+        if self.is_local() {
+            let author_user_id = self.user_store.read(cx).current_user().map(|user| user.id);
+            Task::ready(self.insert_edit_suggestion(buffer_id, edits, author_user_id, cx))
+        } else if let Some(project_id) = self.remote_id() {
+            let request = self.client.request(proto::SuggestEdit {
+                project_id,
+                buffer_id: buffer_id.into(),
+                edits: edit_suggestion::serialize_suggested_edits(&edits),
+            });
+            cx.spawn(move |this, mut cx| async move {
+                let suggestion = request
+                    .await?
+                    .suggestion
+                    .ok_or_else(|| anyhow!("missing edit suggestion"))?;
+                let suggestion = EditSuggestion::from_proto(suggestion)?;
+                this.update(&mut cx, |this, cx| {
+                    if this.edit_suggestion(suggestion.id).is_none() {
+                        this.edit_suggestions.push(suggestion.clone());
+                        cx.emit(Event::EditSuggestionsChanged);
+                        cx.notify();
+                    }
+                })?;
+                Ok(suggestion)
+            })
+        } else {
+            Task::ready(Err(anyhow!("cannot suggest an edit while disconnected")))
+        }
+    }
+
+    /// Applies a suggestion's edits and removes the suggestion. The edits reach
+    /// everyone else in the project like any other, and the text they insert is
+    /// credited to the guest who suggested them.
+    pub fn apply_edit_suggestion(&mut self, id: u64, cx: &mut ModelContext<Self>) -> Result<()> {
+        if !self.is_local() {
+            return Err(anyhow!("only the host can apply edit suggestions"));
+        }
+        let suggestion = self
+            .edit_suggestion(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("unknown edit suggestion {}", id))?;
+        let buffer = self
+            .opened_buffers
+            .get(&suggestion.buffer_id)
+            .and_then(|buffer| buffer.upgrade())
+            .ok_or_else(|| anyhow!("unknown buffer id {}", suggestion.buffer_id))?;
+        if buffer.read(cx).read_only() {
+            return Err(anyhow!("cannot edit a read-only buffer"));
+        }
+
+        // The suggestion is written by the host's replica, so credit everything
+        // that replica wrote before it to the host, leaving only the suggested
+        // text to credit to its author.
+        let is_shared = self.is_shared();
+        if is_shared {
+            self.attribute_session_edits(cx);
+        }
+        buffer.update(cx, |buffer, cx| buffer.edit(suggestion.edits, None, cx));
+        if let Some(author_user_id) = suggestion.author_user_id.filter(|_| is_shared) {
+            self.attribute_edits(self.replica_id(), author_user_id, cx);
+        }
+        self.remove_local_edit_suggestion(id, cx);
+        Ok(())
+    }
+
+    pub fn reject_edit_suggestion(&mut self, id: u64, cx: &mut ModelContext<Self>) -> Result<()> {
+        if !self.is_local() {
+            return Err(anyhow!("only the host can reject edit suggestions"));
+        }
+        if self.edit_suggestion(id).is_none() {
+            return Err(anyhow!("unknown edit suggestion {}", id));
+        }
+        self.remove_local_edit_suggestion(id, cx);
+        Ok(())
+    }
+
+    fn insert_edit_suggestion(
+        &mut self,
+        buffer_id: BufferId,
+        edits: Vec<(Range<language::Anchor>, String)>,
+        author_user_id: Option<u64>,
+        cx: &mut ModelContext<Self>,
+    ) -> Result<EditSuggestion> {
+        if self.edit_suggestions.len() >= edit_suggestion::MAX_PENDING_EDIT_SUGGESTIONS {
+            return Err(anyhow!(
+                "too many edit suggestions are waiting for the host"
+            ));
+        }
+        let suggestion = EditSuggestion {
+            id: post_inc(&mut self.next_edit_suggestion_id),
+            buffer_id,
+            edits,
+            author_user_id,
+        };
+        self.edit_suggestions.push(suggestion.clone());
+        self.edit_suggestions_changed(cx);
+        Ok(suggestion)
+    }
+
+    fn remove_local_edit_suggestion(&mut self, id: u64, cx: &mut ModelContext<Self>) {
+        let len = self.edit_suggestions.len();
+        self.edit_suggestions
+            .retain(|suggestion| suggestion.id != id);
+        if self.edit_suggestions.len() != len {
+            self.edit_suggestions_changed(cx);
+        }
+    }
+
+    fn edit_suggestions_changed(&mut self, cx: &mut ModelContext<Self>) {
+        if let ProjectClientState::Shared { remote_id, .. } = &self.client_state {
+            self.client
+                .send(proto::UpdateEditSuggestions {
+                    project_id: *remote_id,
+                    suggestions: self
+                        .edit_suggestions
+                        .iter()
+                        .map(EditSuggestion::to_proto)
+                        .collect(),
+                })
+                .log_err();
+        }
+        cx.emit(Event::EditSuggestionsChanged);
+        cx.notify();
+    }
+
+    fn set_edit_suggestions_from_proto(
+        &mut self,
+        suggestions: Vec<proto::EditSuggestion>,
+        cx: &mut ModelContext<Self>,
+    ) -> Result<()> {
+        self.edit_suggestions = suggestions
+            .into_iter()
+            .map(EditSuggestion::from_proto)
+            .collect::<Result<_>>()?;
+        cx.emit(Event::EditSuggestionsChanged);
+        cx.notify();
+        Ok(())
+    }
+
+    fn refresh_edit_suggestions(&self, cx: &mut ModelContext<Self>) {
+        if self.is_local() {
+            return;
+        }
+        let Some(project_id) = self.remote_id() else {
+            return;
+        };
+        let request = self
+            .client
+            .request(proto::GetEditSuggestions { project_id });
+        cx.spawn(move |this, mut cx| async move {
+            let response = request.await?;
+            this.update(&mut cx, |this, cx| {
+                this.set_edit_suggestions_from_proto(response.suggestions, cx)
+            })?
+        })
+        .detach_and_log_err(cx);
+    }
+
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+
+    pub fn annotation(&self, id: u64) -> Option<&Annotation> {
+        self.annotations
+            .iter()
+            .find(|annotation| annotation.id == id)
+    }
+
+    pub fn annotations_for_buffer(
+        &self,
+        buffer_id: BufferId,
+    ) -> impl Iterator<Item = &Annotation> + '_ {
+        self.annotations
+            .iter()
+            .filter(move |annotation| annotation.buffer_id == buffer_id)
+    }
+
+    pub fn add_annotation(
+        &mut self,
+        buffer: &Model<Buffer>,
+        range: Range<language::Anchor>,
+        note: String,
+        cx: &mut ModelContext<Self>,
+    ) -> Annotation {
+        let annotation = Annotation {
+            id: post_inc(&mut self.next_annotation_id),
+            buffer_id: buffer.read(cx).remote_id(),
+            range,
+            note,
+        };
+        self.annotations.push(annotation.clone());
+        cx.emit(Event::AnnotationsChanged);
+        cx.notify();
+        annotation
+    }
+
+    pub fn remove_annotation(&mut self, id: u64, cx: &mut ModelContext<Self>) {
+        let len = self.annotations.len();
+        self.annotations.retain(|annotation| annotation.id != id);
+        if self.annotations.len() != len {
+            cx.emit(Event::AnnotationsChanged);
+            cx.notify();
+        }
+    }
+
+    /// Opens the buffer containing the given annotation, resolving to its
+    /// location once the annotated text is present in the buffer, which may have
+    /// been reopened since the annotation was added.
+    pub fn open_annotation(
+        &mut self,
+        id: u64,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Location>> {
+        let Some(annotation) = self.annotation(id).cloned() else {
+            return Task::ready(Err(anyhow!("unknown annotation {}", id)));
+        };
+        let open_buffer = self.open_buffer_by_id(annotation.buffer_id, cx);
+        cx.spawn(move |_, mut cx| async move {
+            let buffer = open_buffer.await?;
+            buffer
+                .update(&mut cx, |buffer, _| {
+                    buffer.wait_for_anchors([annotation.range.start, annotation.range.end])
+                })?
+                .await?;
+            Ok(Location {
+                buffer,
+                range: annotation.range,
+            })
+        })
+    }
+
+    /// Returns who wrote which spans of the given buffer while the project was
+    /// shared, in the current session as well as in earlier ones. Only the host
+    /// keeps track of this.
+    pub fn edit_attribution(
+        &mut self,
+        buffer: &Model<Buffer>,
+        cx: &mut ModelContext<Self>,
+    ) -> Vec<AttributedRange> {
+        if self.is_shared() {
+            self.attribute_session_edits(cx);
+        }
+        let buffer = buffer.read(cx);
+        self.edit_attributions
+            .get(&buffer.remote_id())
+            .map_or(Vec::new(), |attribution| attribution.ranges(buffer))
+    }
+
+    /// Whether the user wants to be able to see who wrote what after a session.
+    fn records_edit_attribution(cx: &AppContext) -> bool {
+        ProjectSettings::get_global(cx)
+            .attribute_session_edits
+            .unwrap_or(true)
+    }
+
+    /// Attributes the text that the given replica wrote in the open buffers to
+    /// the given user.
+    fn attribute_edits(&mut self, replica_id: ReplicaId, user_id: u64, cx: &AppContext) {
+        if !Self::records_edit_attribution(cx) {
+            return;
+        }
+        let github_login = self
+            .user_store
+            .read(cx)
+            .get_cached_user(user_id)
+            .map_or_else(|| user_id.to_string(), |user| user.github_login.clone());
+        let author = EditAuthor {
+            user_id,
+            github_login,
+        };
+        for (buffer_id, attribution) in &mut self.edit_attributions {
+            if let Some(buffer) = self
+                .opened_buffers
+                .get(buffer_id)
+                .and_then(|buffer| buffer.upgrade())
+            {
+                attribution.attribute(buffer.read(cx), replica_id, &author);
+            }
+        }
+    }
+
+    /// Attributes the text that the host and every current collaborator wrote.
+    fn attribute_session_edits(&mut self, cx: &AppContext) {
+        let mut authors = self
+            .collaborators
+            .values()
+            .map(|collaborator| (collaborator.replica_id, collaborator.user_id))
+            .collect::<Vec<_>>();
+        if let Some(user) = self.user_store.read(cx).current_user() {
+            authors.push((self.replica_id(), user.id));
+        }
+        for (replica_id, user_id) in authors {
+            self.attribute_edits(replica_id, user_id, cx);
+        }
+    }
+
+    /// Saves the attributions of the given buffers, so that they outlive the
+    /// buffers as long as their files aren't changed elsewhere.
+    fn persist_edit_attributions(
+        &self,
+        buffer_ids: impl IntoIterator<Item = BufferId>,
+        cx: &AppContext,
+    ) {
+        if !Self::records_edit_attribution(cx) {
+            return;
+        }
+        for buffer_id in buffer_ids {
+            let Some(attribution) = self.edit_attributions.get(&buffer_id) else {
+                continue;
+            };
+            if let Some(buffer) = self
+                .opened_buffers
+                .get(&buffer_id)
+                .and_then(|buffer| buffer.upgrade())
+            {
+                Self::persist_edit_attribution(attribution, buffer.read(cx), cx);
+            }
+        }
+    }
+
+    fn persist_edit_attribution(
+        attribution: &BufferEditAttribution,
+        buffer: &Buffer,
+        cx: &AppContext,
+    ) {
+        if !Self::records_edit_attribution(cx) || attribution.is_empty() {
+            return;
+        }
+        let Some(file) = File::from_dyn(buffer.file()).and_then(|file| file.as_local()) else {
+            return;
+        };
+
+        let key = edit_attribution::persistence_key(&file.abs_path(cx));
+        if let Some(json) = attribution.serialize(buffer).log_err() {
+            cx.background_executor()
+                .spawn(KEY_VALUE_STORE.write_kvp(key, json))
+                .detach_and_log_err(cx);
+        }
+    }
+
+    fn load_edit_attribution(&mut self, buffer: &Model<Buffer>, cx: &mut ModelContext<Self>) {
+        let Some(file) = File::from_dyn(buffer.read(cx).file()).and_then(|file| file.as_local())
+        else {
+            return;
+        };
+        let key = edit_attribution::persistence_key(&file.abs_path(cx));
+        let version = buffer.read(cx).version();
+        let buffer = buffer.downgrade();
+        let load = cx
+            .background_executor()
+            .spawn(async move { KEY_VALUE_STORE.read_kvp(&key) });
+        cx.spawn(move |this, mut cx| async move {
+            let Some(json) = load.await? else {
+                return Ok(());
+            };
+            let Some(buffer) = buffer.upgrade() else {
+                return Ok(());
+            };
+            this.update(&mut cx, |this, cx| {
+                let buffer = buffer.read(cx);
+                // The persisted offsets only apply to the text as it was loaded.
+                if buffer.version() != version {
+                    return Ok(());
+                }
+                if let Some(earlier) = BufferEditAttribution::deserialize(&json, buffer)? {
+                    match this.edit_attributions.entry(buffer.remote_id()) {
+                        hash_map::Entry::Occupied(mut entry) => {
+                            entry.get_mut().merge_earlier(earlier)
+                        }
+                        hash_map::Entry::Vacant(entry) => {
+                            entry.insert(earlier);
+                        }
+                    }
+                }
+                anyhow::Ok(())
+            })?
+        })
+        .detach_and_log_err(cx);
+    }
+
+    pub fn language_server_configuration(
+        &self,
+        server_id: LanguageServerId,
+    ) -> Option<&serde_json::Value> {
+        self.language_server_statuses
+            .get(&server_id)?
+            .configuration
+            .as_ref()
+    }
+
+    fn language_server_configuration_changed(
+        &mut self,
+        server_id: LanguageServerId,
+        mut configuration: serde_json::Value,
+        cx: &mut ModelContext<Self>,
+    ) {
+        redact_lsp_configuration(&mut configuration);
+        if self.language_server_configuration(server_id) == Some(&configuration) {
+            return;
+        }
+
+        if let Some(project_id) = self.remote_id() {
+            self.client
+                .send(proto::UpdateLanguageServer {
+                    project_id,
+                    language_server_id: server_id.0 as u64,
+                    variant: Some(
+                        proto::update_language_server::Variant::ConfigurationUpdated(
+                            proto::LspConfigurationUpdated {
+                                configuration: configuration.to_string(),
+                            },
+                        ),
+                    ),
+                })
+                .log_err();
+        }
+        self.set_language_server_configuration(server_id, configuration, cx);
+    }
+
+    fn set_language_server_configuration(
+        &mut self,
+        server_id: LanguageServerId,
+        configuration: serde_json::Value,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if let Some(status) = self.language_server_statuses.get_mut(&server_id) {
+            status.configuration = Some(configuration);
+            cx.notify();
+        }
+    }
+
+    fn refresh_language_server_configurations(&self, cx: &mut ModelContext<Self>) {
+        if self.is_local() {
+            return;
+        }
+        let Some(project_id) = self.remote_id() else {
+            return;
+        };
+        let request = self
+            .client
+            .request(proto::GetLanguageServerConfigurations { project_id });
+        cx.spawn(move |this, mut cx| async move {
+            let response = request.await?;
+            this.update(&mut cx, |this, cx| {
+                for server in response.configurations {
+                    let configuration = serde_json::from_str(&server.configuration)?;
+                    this.set_language_server_configuration(
+                        LanguageServerId(server.language_server_id as usize),
+                        configuration,
+                        cx,
+                    );
+                }
+                anyhow::Ok(())
+            })?
+        })
+        .detach_and_log_err(cx);
+    }
+
+    pub fn save_buffers(
+        &self,
+        buffers: HashSet<Model<Buffer>>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        cx.spawn(move |this, mut cx| async move {
+            let save_tasks = buffers.into_iter().filter_map(|buffer| {
+                this.update(&mut cx, |this, cx| this.save_buffer(buffer, cx))
+                    .ok()
+            });
+            try_join_all(save_tasks).await?;
+            Ok(())
+        })
+    }
+
+    pub fn save_buffer(
+        &self,
+        buffer: Model<Buffer>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        let Some(file) = File::from_dyn(buffer.read(cx).file()) else {
+            return Task::ready(Err(anyhow!("buffer doesn't have a file")));
+        };
+        let worktree = file.worktree.clone();
+        let path = file.path.clone();
+        worktree.update(cx, |worktree, cx| match worktree {
+            Worktree::Local(worktree) => worktree.save_buffer(buffer, path, false, cx),
+            Worktree::Remote(worktree) => worktree.save_buffer(buffer, cx),
+        })
+    }
+
+    pub fn save_buffer_as(
+        &mut self,
+        buffer: Model<Buffer>,
+        abs_path: PathBuf,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        let worktree_task = self.find_or_create_local_worktree(&abs_path, true, cx);
+        let old_file = File::from_dyn(buffer.read(cx).file())
+            .filter(|f| f.is_local())
+            .cloned();
+        cx.spawn(move |this, mut cx| async move {
+            if let Some(old_file) = &old_file {
+                this.update(&mut cx, |this, cx| {
+                    this.unregister_buffer_from_language_servers(&buffer, old_file, cx);
+                })?;
+            }
+            let (worktree, path) = worktree_task.await?;
+            worktree
+                .update(&mut cx, |worktree, cx| match worktree {
+                    Worktree::Local(worktree) => {
+                        worktree.save_buffer(buffer.clone(), path.into(), true, cx)
+                    }
+                    Worktree::Remote(_) => panic!("cannot remote buffers as new files"),
+                })?
+                .await?;
+
+            this.update(&mut cx, |this, cx| {
+                this.detect_language_for_buffer(&buffer, cx);
+                this.register_buffer_with_language_servers(&buffer, cx);
+            })?;
+            Ok(())
+        })
+    }
+
+    pub fn get_open_buffer(
         &mut self,
         path: &ProjectPath,
         cx: &mut ModelContext<Self>,
@@ -1977,7 +3329,7 @@ impl Project {
         self.opened_buffers.values().find_map(|buffer| {
             let buffer = buffer.upgrade()?;
             let file = File::from_dyn(buffer.read(cx).file())?;
-            if file.worktree == worktree && file.path() == &path.path {
+            if file.worktree == worktree && paths_equal_normalized(file.path(), &path.path) {
                 Some(buffer)
             } else {
                 None
@@ -2021,6 +3373,7 @@ impl Project {
                 entry.insert(open_buffer);
             }
         }
+        self.closed_remote_buffers.remove(&remote_id);
         cx.subscribe(buffer, |this, buffer, event, cx| {
             this.on_buffer_event(buffer, event, cx);
         })
@@ -2043,6 +3396,14 @@ impl Project {
             }
         }
 
+        if self.is_local() && Self::records_edit_attribution(cx) {
+            if self.is_shared() {
+                self.edit_attributions
+                    .insert(remote_id, BufferEditAttribution::new(buffer.read(cx)));
+            }
+            self.load_edit_attribution(buffer, cx);
+        }
+
         self.detect_language_for_buffer(buffer, cx);
         self.register_buffer_with_language_servers(buffer, cx);
         self.register_buffer_with_copilot(buffer, cx);
@@ -2062,6 +3423,14 @@ impl Project {
                     }
                 }
             }
+            if this.is_remote() {
+                this.close_remote_buffer(buffer.remote_id(), cx);
+            }
+            // The attribution is saved when the buffer closes, and loaded again if it's
+            // reopened.
+            if let Some(attribution) = this.edit_attributions.remove(&buffer.remote_id()) {
+                Self::persist_edit_attribution(&attribution, buffer, cx);
+            }
         })
         .detach();
 
@@ -2069,6 +3438,35 @@ impl Project {
         Ok(())
     }
 
+    /// Tells the host that this guest no longer has a buffer open, so that the host sends
+    /// the buffer again if it's reopened.
+    fn close_remote_buffer(&mut self, buffer_id: BufferId, cx: &mut ModelContext<Self>) {
+        if self.is_disconnected() {
+            return;
+        }
+        let Some(project_id) = self.remote_id() else {
+            return;
+        };
+        // The buffer may have been opened again since it was released.
+        if let Some(buffer) = self.opened_buffers.get(&buffer_id) {
+            if buffer.upgrade().is_some() {
+                return;
+            }
+        }
+        self.opened_buffers.remove(&buffer_id);
+        let request = self.client.request(proto::CloseBuffer {
+            project_id,
+            buffer_id: buffer_id.into(),
+        });
+        let closing = cx
+            .background_executor()
+            .spawn(async move {
+                request.await.log_err();
+            })
+            .shared();
+        self.closed_remote_buffers.insert(buffer_id, closing);
+    }
+
     fn register_buffer_with_language_servers(
         &mut self,
         buffer_handle: &Model<Buffer>,
@@ -2200,6 +3598,9 @@ impl Project {
 
     async fn send_buffer_ordered_messages(
         this: WeakModel<Self>,
+        client: Arc<Client>,
+        is_local: bool,
+        mut project_id: Option<u64>,
         rx: UnboundedReceiver<BufferOrderedMessage>,
         mut cx: AsyncAppContext,
     ) -> Result<()> {
@@ -2207,36 +3608,83 @@ impl Project {
 
         let mut operations_by_buffer_id = HashMap::default();
         async fn flush_operations(
-            this: &WeakModel<Project>,
+            client: &Client,
+            executor: &BackgroundExecutor,
+            project_id: Option<u64>,
             operations_by_buffer_id: &mut HashMap<BufferId, Vec<proto::Operation>>,
+            journal: &mut Option<OperationJournal>,
             needs_resync_with_host: &mut bool,
             is_local: bool,
-            cx: &mut AsyncAppContext,
-        ) -> Result<()> {
+        ) {
+            if let Some(journal) = journal.as_mut() {
+                journal.persist().await.log_err();
+            }
+            let Some(project_id) = project_id else {
+                operations_by_buffer_id.clear();
+                return;
+            };
             for (buffer_id, operations) in operations_by_buffer_id.drain() {
-                let request = this.update(cx, |this, _| {
-                    let project_id = this.remote_id()?;
-                    Some(this.client.request(proto::UpdateBuffer {
-                        buffer_id: buffer_id.into(),
-                        project_id,
-                        operations,
-                    }))
-                })?;
-                if let Some(request) = request {
-                    if request.await.is_err() && !is_local {
-                        *needs_resync_with_host = true;
-                        break;
+                // Operations that the server turns away for being sent too quickly are
+                // sent again once it allows, since they can't be recovered otherwise.
+                let result = loop {
+                    let result = client
+                        .request(proto::UpdateBuffer {
+                            buffer_id: buffer_id.into(),
+                            project_id,
+                            operations: operations.clone(),
+                        })
+                        .await;
+                    match result.as_ref().err().and_then(rpc::retry_after) {
+                        Some(retry_after) => executor.timer(retry_after).await,
+                        None => break result,
+                    }
+                };
+                if result.is_ok() {
+                    if let Some(journal) = journal.as_mut() {
+                        journal.acknowledge(buffer_id, operations.len());
                     }
+                } else if !is_local {
+                    *needs_resync_with_host = true;
+                    break;
                 }
             }
-            Ok(())
+            if let Some(journal) = journal.as_mut() {
+                journal.persist().await.log_err();
+            }
         }
 
+        let executor = cx.background_executor().clone();
         let mut needs_resync_with_host = false;
+
+        // Guests keep the operations they make on disk until they're acknowledged, first
+        // taking any that were left there when they were last in the project.
+        let mut journal = None;
+        if let Some(project_id) = project_id.filter(|_| !is_local) {
+            let replica_id = this.update(&mut cx, |this, _| this.replica_id())?;
+            let new_journal = OperationJournal::new(project_id, replica_id);
+            let recovered = executor
+                .spawn(OperationJournal::recover(
+                    project_id,
+                    new_journal.session_id(),
+                ))
+                .await;
+            if let Some(recovered) = recovered.log_err().flatten() {
+                this.update(&mut cx, |this, cx| {
+                    this.recoverable_operations = Some(recovered);
+                    cx.emit(Event::RecoverableOperationsFound);
+                })?;
+            }
+            journal = Some(new_journal);
+        }
+
         let mut changes = rx.ready_chunks(MAX_BATCH_SIZE);
 
         while let Some(changes) = changes.next().await {
-            let is_local = this.update(&mut cx, |this, _| this.is_local())?;
+            // Messages may still be queued when the project is released, in which case
+            // they're sent on behalf of the project as it was last seen.
+            if let Ok(remote_id) = this.update(&mut cx, |this, _| this.remote_id()) {
+                project_id = remote_id;
+            }
 
             for change in changes {
                 match change {
@@ -2244,6 +3692,9 @@ impl Project {
                         buffer_id,
                         operation,
                     } => {
+                        if let Some(journal) = journal.as_mut() {
+                            journal.push(buffer_id, operation.clone());
+                        }
                         if needs_resync_with_host {
                             continue;
                         }
@@ -2256,12 +3707,16 @@ impl Project {
 
                     BufferOrderedMessage::Resync => {
                         operations_by_buffer_id.clear();
-                        if this
-                            .update(&mut cx, |this, cx| this.synchronize_remote_buffers(cx))?
-                            .await
-                            .is_ok()
-                        {
+                        let Ok(synchronize) =
+                            this.update(&mut cx, |this, cx| this.synchronize_remote_buffers(cx))
+                        else {
+                            continue;
+                        };
+                        if synchronize.await.is_ok() {
                             needs_resync_with_host = false;
+                            if let Some(journal) = journal.as_mut() {
+                                journal.clear();
+                            }
                         }
                     }
 
@@ -2270,40 +3725,163 @@ impl Project {
                         message,
                     } => {
                         flush_operations(
-                            &this,
+                            &client,
+                            &executor,
+                            project_id,
                             &mut operations_by_buffer_id,
+                            &mut journal,
                             &mut needs_resync_with_host,
                             is_local,
-                            &mut cx,
                         )
-                        .await?;
+                        .await;
 
-                        this.update(&mut cx, |this, _| {
-                            if let Some(project_id) = this.remote_id() {
-                                this.client
-                                    .send(proto::UpdateLanguageServer {
-                                        project_id,
-                                        language_server_id: language_server_id.0 as u64,
-                                        variant: Some(message),
-                                    })
-                                    .log_err();
-                            }
-                        })?;
+                        if let Some(project_id) = project_id {
+                            client
+                                .send(proto::UpdateLanguageServer {
+                                    project_id,
+                                    language_server_id: language_server_id.0 as u64,
+                                    variant: Some(message),
+                                })
+                                .log_err();
+                        }
+                    }
+
+                    BufferOrderedMessage::Flush(done) => {
+                        flush_operations(
+                            &client,
+                            &executor,
+                            project_id,
+                            &mut operations_by_buffer_id,
+                            &mut journal,
+                            &mut needs_resync_with_host,
+                            is_local,
+                        )
+                        .await;
+                        done.send(!needs_resync_with_host).ok();
+                    }
+
+                    BufferOrderedMessage::LeaveProject => {
+                        flush_operations(
+                            &client,
+                            &executor,
+                            project_id,
+                            &mut operations_by_buffer_id,
+                            &mut journal,
+                            &mut needs_resync_with_host,
+                            is_local,
+                        )
+                        .await;
+                        if let Some(project_id) = project_id.take() {
+                            client.send(proto::LeaveProject { project_id }).log_err();
+                        }
                     }
                 }
             }
 
-            flush_operations(
-                &this,
-                &mut operations_by_buffer_id,
-                &mut needs_resync_with_host,
-                is_local,
-                &mut cx,
-            )
-            .await?;
+            flush_operations(
+                &client,
+                &executor,
+                project_id,
+                &mut operations_by_buffer_id,
+                &mut journal,
+                &mut needs_resync_with_host,
+                is_local,
+            )
+            .await;
+        }
+
+        Ok(())
+    }
+
+    /// Resolves once every buffer operation made locally in this project so far has been
+    /// acknowledged by the server, or fails if any of them couldn't be delivered.
+    ///
+    /// By the time the server acknowledges an operation it has forwarded it to every other
+    /// collaborator, and if it was made by a guest, the host has applied it, or the server
+    /// is keeping it for the host to receive when it reconnects. Guests wait for this
+    /// before closing a project, so that edits they've made reach the host before they
+    /// leave.
+    pub fn flush(&self) -> impl Future<Output = Result<()>> {
+        let (tx, rx) = oneshot::channel();
+        let sent = self
+            .buffer_ordered_messages_tx
+            .unbounded_send(BufferOrderedMessage::Flush(tx));
+        async move {
+            sent.map_err(|_| anyhow!("project was dropped"))?;
+            if rx.await? {
+                Ok(())
+            } else {
+                Err(anyhow!("failed to send buffer operations to the host"))
+            }
+        }
+    }
+
+    /// Whether there are edits that were made as a guest before Zed last quit, but never
+    /// reached the host, which can be replayed with [`Self::recover_operations`].
+    pub fn has_recoverable_operations(&self) -> bool {
+        self.recoverable_operations
+            .as_ref()
+            .map_or(false, |recovered| {
+                !recovered.operations_by_buffer_id.is_empty()
+            })
+    }
+
+    /// Forgets the edits that didn't reach the host before Zed last quit, so that
+    /// they aren't offered again.
+    pub fn discard_recoverable_operations(&mut self, cx: &mut ModelContext<Self>) {
+        if let Some(recovered) = self.recoverable_operations.take() {
+            cx.background_executor()
+                .spawn(async move { OperationJournal::discard(&recovered).await })
+                .detach_and_log_err(cx);
+        }
+    }
+
+    /// Replays the edits that didn't reach the host before Zed last quit into the
+    /// buffers they were made in, then resynchronizes the buffers so that the host
+    /// receives whichever of them it's missing. The edits are kept on disk until
+    /// they've been replayed.
+    pub fn recover_operations(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        let Some(mut recovered) = self.recoverable_operations.take() else {
+            return Task::ready(Ok(()));
+        };
+        if self
+            .collaborators
+            .values()
+            .any(|collaborator| collaborator.replica_id == recovered.replica_id)
+        {
+            self.recoverable_operations = Some(recovered);
+            return Task::ready(Err(anyhow!(
+                "edits can't be recovered while another collaborator is using their replica"
+            )));
         }
 
-        Ok(())
+        cx.spawn(move |this, mut cx| async move {
+            for (buffer_id, operations) in mem::take(&mut recovered.operations_by_buffer_id) {
+                let buffer = this
+                    .update(&mut cx, |this, cx| this.open_buffer_by_id(buffer_id, cx))?
+                    .await?;
+                let operations = operations
+                    .into_iter()
+                    .map(language::proto::deserialize_operation)
+                    .collect::<Result<Vec<_>>>()?;
+                buffer.update(&mut cx, |buffer, cx| {
+                    buffer.apply_ops(operations, cx)?;
+                    if buffer.deferred_ops_len() > 0 {
+                        Err(anyhow!(
+                            "recovered edits to buffer {buffer_id} depend on edits the host lacks"
+                        ))
+                    } else {
+                        Ok(())
+                    }
+                })??;
+            }
+            this.update(&mut cx, |this, _| {
+                this.buffer_ordered_messages_tx
+                    .unbounded_send(BufferOrderedMessage::Resync)
+                    .ok();
+            })?;
+            OperationJournal::discard(&recovered).await
+        })
     }
 
     fn on_buffer_event(
@@ -2318,12 +3896,34 @@ impl Project {
         ) {
             self.request_buffer_diff_recalculation(&buffer, cx);
         }
+        if matches!(event, BufferEvent::Saved) {
+            // Keep the persisted attribution in step with the text on disk.
+            self.persist_edit_attributions([buffer.read(cx).remote_id()], cx);
+        }
 
         match event {
             BufferEvent::Operation(operation) => {
+                // Guests are sent the whole buffer if it's later shared with them.
+                if self.is_local() && !self.is_buffer_shareable(&buffer, cx) {
+                    return None;
+                }
+                let buffer_id = buffer.read(cx).remote_id();
+                if let Some(recovered) = &mut self.recoverable_operations {
+                    // Edits made since rejoining could have been given the same timestamps
+                    // as the recovered ones, if they were made as the same replica.
+                    if matches!(operation, Operation::Buffer(_))
+                        && recovered.replica_id == self.replica_id()
+                        && recovered
+                            .operations_by_buffer_id
+                            .remove(&buffer_id)
+                            .is_some()
+                    {
+                        log::warn!("discarding recovered edits to edited buffer {buffer_id}");
+                    }
+                }
                 self.buffer_ordered_messages_tx
                     .unbounded_send(BufferOrderedMessage::Operation {
-                        buffer_id: buffer.read(cx).remote_id(),
+                        buffer_id,
                         operation: language::proto::serialize_operation(operation),
                     })
                     .ok();
@@ -2669,6 +4269,10 @@ impl Project {
                             for buffer in buffers_with_unknown_injections {
                                 buffer.update(cx, |buffer, cx| buffer.reparse(cx));
                             }
+
+                            if project.is_shared() {
+                                project.schedule_entry_decorations_detection(cx);
+                            }
                         })
                         .ok();
                 }
@@ -2702,6 +4306,13 @@ impl Project {
                     let settings =
                         cx.update(|cx| adapter.workspace_configuration(server.root_path(), cx))?;
 
+                    this.update(&mut cx, |this, cx| {
+                        this.language_server_configuration_changed(
+                            server.server_id(),
+                            settings.clone(),
+                            cx,
+                        )
+                    })?;
                     server
                         .notify::<lsp::notification::DidChangeConfiguration>(
                             lsp::DidChangeConfigurationParams { settings },
@@ -2733,6 +4344,35 @@ impl Project {
         None
     }
 
+    /// Like [`Self::detect_language_for_buffer`], but clears the buffer's language when
+    /// its new path no longer matches any language, so that a file renamed from `.rs`
+    /// to `.txt` stops being treated as Rust.
+    fn detect_language_for_renamed_buffer(
+        &mut self,
+        buffer_handle: &Model<Buffer>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let buffer = buffer_handle.read(cx);
+        let Some(file) = buffer.file() else {
+            return;
+        };
+        let full_path = file.full_path(cx);
+        let content = buffer.as_rope();
+        match self
+            .languages
+            .language_for_file(&full_path, Some(content))
+            .now_or_never()
+        {
+            Some(Ok(new_language)) => self.set_language_for_buffer(buffer_handle, new_language, cx),
+            Some(Err(_)) => buffer_handle.update(cx, |buffer, cx| {
+                if buffer.language().is_some() {
+                    buffer.set_language(None, cx);
+                }
+            }),
+            None => {}
+        }
+    }
+
     pub fn set_language_for_buffer(
         &mut self,
         buffer: &Model<Buffer>,
@@ -3247,6 +4887,7 @@ impl Project {
                 name: language_server.name().to_string(),
                 pending_work: Default::default(),
                 has_pending_diagnostic_updates: false,
+                configuration: None,
                 progress_tokens: Default::default(),
             },
         );
@@ -3263,6 +4904,9 @@ impl Project {
             })?;
         }
 
+        let configuration = adapter.workspace_configuration(language_server.root_path(), cx);
+        self.language_server_configuration_changed(server_id, configuration, cx);
+
         // Tell the language server about every open buffer in the worktree that matches the language.
         for buffer in self.opened_buffers.values() {
             if let Some(buffer_handle) = buffer.upgrade() {
@@ -4572,6 +6216,23 @@ impl Project {
         self.document_highlights_impl(buffer, position, cx)
     }
 
+    /// Returns the ranges that should be edited together with the one at the
+    /// given position, such as the names of matching opening and closing tags.
+    pub fn linked_editing_ranges<T: ToPointUtf16>(
+        &self,
+        buffer: &Model<Buffer>,
+        position: T,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<Range<Anchor>>>> {
+        let position = position.to_point_utf16(buffer.read(cx));
+        self.request_lsp(
+            buffer.clone(),
+            LanguageServerToQuery::Primary,
+            GetLinkedEditingRanges { position },
+            cx,
+        )
+    }
+
     pub fn symbols(&self, query: &str, cx: &mut ModelContext<Self>) -> Task<Result<Vec<Symbol>>> {
         if self.is_local() {
             let mut requests = Vec::new();
@@ -4636,6 +6297,7 @@ impl Project {
                 );
             }
 
+            let collation = self.client.collation();
             cx.spawn(move |this, mut cx| async move {
                 let responses = futures::future::join_all(requests).await;
                 let this = match this.upgrade() {
@@ -4703,7 +6365,17 @@ impl Project {
                     symbols
                 })?;
 
-                Ok(futures::future::join_all(symbols).await)
+                // Language servers respond in no particular order, so sort their
+                // symbols the way the server orders names, to list them the same way
+                // for the host and its guests.
+                let mut symbols = futures::future::join_all(symbols).await;
+                symbols.sort_by(|a, b| {
+                    collation
+                        .compare(&a.name, &b.name)
+                        .then_with(|| a.path.path.cmp(&b.path.path))
+                        .then_with(|| a.range.start.cmp(&b.range.start))
+                });
+                Ok(symbols)
             })
         } else if let Some(project_id) = self.remote_id() {
             let request = self.client.request(proto::GetProjectSymbols {
@@ -5335,13 +7007,17 @@ impl Project {
                 trigger,
                 version: serialize_version(&buffer.read(cx).version()),
             };
-            cx.spawn(move |_, _| async move {
-                client
-                    .request(request)
-                    .await?
-                    .transaction
-                    .map(language::proto::deserialize_transaction)
-                    .transpose()
+            cx.spawn(move |_, mut cx| async move {
+                let Some(transaction) = client.request(request).await?.transaction else {
+                    return Ok(None);
+                };
+                let transaction = language::proto::deserialize_transaction(transaction)?;
+                buffer
+                    .update(&mut cx, |buffer, _| {
+                        buffer.wait_for_edits(transaction.edit_ids.iter().copied())
+                    })?
+                    .await?;
+                Ok(Some(transaction))
             })
         } else {
             Task::ready(Err(anyhow!("project does not have a remote id")))
@@ -5780,7 +7456,10 @@ impl Project {
             let request = self.client.request(query.to_proto(project_id));
             cx.spawn(move |this, mut cx| async move {
                 let response = request.await?;
-                let mut result = HashMap::default();
+                // Report the matches in the order the host found them, which groups
+                // them by buffer and sorts the buffers by path.
+                let mut result = Vec::<(Model<Buffer>, Vec<Range<Anchor>>)>::new();
+                let mut result_ixs = HashMap::<BufferId, usize>::default();
                 for location in response.locations {
                     let buffer_id = BufferId::new(location.buffer_id)?;
                     let target_buffer = this
@@ -5796,10 +7475,13 @@ impl Project {
                         .end
                         .and_then(deserialize_anchor)
                         .ok_or_else(|| anyhow!("missing target end"))?;
-                    result
-                        .entry(target_buffer)
-                        .or_insert(Vec::new())
-                        .push(start..end)
+                    match result_ixs.entry(buffer_id) {
+                        hash_map::Entry::Occupied(entry) => result[*entry.get()].1.push(start..end),
+                        hash_map::Entry::Vacant(entry) => {
+                            entry.insert(result.len());
+                            result.push((target_buffer, vec![start..end]));
+                        }
+                    }
                 }
                 for (buffer, ranges) in result {
                     let _ = tx.send((buffer, ranges)).await;
@@ -5877,14 +7559,17 @@ impl Project {
             .iter()
             .filter_map(|(_, b)| {
                 let buffer = b.upgrade()?;
-                let (is_ignored, snapshot) = buffer.update(cx, |buffer, cx| {
-                    let is_ignored = buffer
+                let (entry, snapshot) = buffer.update(cx, |buffer, cx| {
+                    let entry = buffer
                         .project_path(cx)
-                        .and_then(|path| self.entry_for_path(&path, cx))
-                        .map_or(false, |entry| entry.is_ignored);
-                    (is_ignored, buffer.snapshot())
+                        .and_then(|path| self.entry_for_path(&path, cx));
+                    (entry, buffer.snapshot())
                 });
-                if is_ignored && !query.include_ignored() {
+                let is_ignored = entry.as_ref().map_or(false, |entry| entry.is_ignored);
+                // Like the rest of an external symlink's contents, its open buffers are
+                // only searched when the symlink is followed.
+                let is_external = entry.as_ref().map_or(false, |entry| entry.is_external);
+                if (is_ignored && !query.include_ignored()) || is_external {
                     return None;
                 } else if let Some(path) = snapshot.file().map(|file| file.path()) {
                     Some((path.clone(), (buffer, snapshot)))
@@ -6091,6 +7776,12 @@ impl Project {
                                     if unnamed_buffers.contains_key(&entry.path) {
                                         continue;
                                     }
+                                    // Lazily-loaded symlinks outside of the worktree are only
+                                    // searched when `external_symlinks` is set to follow them,
+                                    // regardless of whether they were expanded.
+                                    if entry.is_external {
+                                        continue;
+                                    }
                                     let matches = if query.file_matches(Some(&entry.path)) {
                                         abs_path.clear();
                                         abs_path.push(&snapshot.abs_path());
@@ -6127,7 +7818,7 @@ impl Project {
                     for snapshot in snapshots {
                         for ignored_entry in snapshot
                             .entries(query.include_ignored())
-                            .filter(|e| e.is_ignored)
+                            .filter(|e| e.is_ignored && !e.is_external)
                         {
                             let limiter = Arc::clone(&max_concurrent_workers);
                             scope.spawn(async move {
@@ -6391,6 +8082,59 @@ impl Project {
         }
     }
 
+    /// Returns the names of the templates in the user's project templates directory.
+    pub fn project_templates(&self, cx: &mut ModelContext<Self>) -> Task<Result<Vec<String>>> {
+        let fs = self.fs.clone();
+        cx.background_executor().spawn(async move {
+            let mut names = Vec::new();
+            if !is_dir(fs.as_ref(), &PROJECT_TEMPLATES_DIR).await? {
+                return Ok(names);
+            }
+            let mut entries = fs.read_dir(&PROJECT_TEMPLATES_DIR).await?;
+            while let Some(path) = entries.next().await {
+                let path = path?;
+                if is_dir(fs.as_ref(), &path).await? {
+                    if let Some(name) = path.file_name() {
+                        names.push(name.to_string_lossy().into_owned());
+                    }
+                }
+            }
+            names.sort();
+            Ok(names)
+        })
+    }
+
+    /// Copies the named template to a new directory at `abs_path`, and adds that
+    /// directory to the project as a visible worktree.
+    pub fn create_worktree_from_template(
+        &mut self,
+        template: &str,
+        abs_path: impl Into<PathBuf>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Model<Worktree>>> {
+        if !self.is_local() {
+            return Task::ready(Err(anyhow!("can't create worktrees in remote projects")));
+        }
+        let mut template_components = Path::new(template).components();
+        let template_path = match (template_components.next(), template_components.next()) {
+            (Some(Component::Normal(name)), None) => PROJECT_TEMPLATES_DIR.join(name),
+            _ => return Task::ready(Err(anyhow!("invalid template name {template:?}"))),
+        };
+
+        let fs = self.fs.clone();
+        let abs_path = abs_path.into();
+        cx.spawn(move |this, mut cx| async move {
+            if !is_dir(fs.as_ref(), &template_path).await? {
+                return Err(anyhow!("template {template_path:?} does not exist"));
+            }
+            copy_recursive(fs.as_ref(), &template_path, &abs_path, Default::default()).await?;
+            this.update(&mut cx, |this, cx| {
+                this.create_local_worktree(&abs_path, true, cx)
+            })?
+            .await
+        })
+    }
+
     pub fn find_local_worktree(
         &self,
         abs_path: &Path,
@@ -6486,6 +8230,9 @@ impl Project {
             cx.emit(Event::LanguageServerRemoved(server_id_to_remove));
         }
 
+        self.collab_policies.remove(&id_to_remove);
+        self.entry_decorations.remove(&id_to_remove);
+
         let mut prettier_instances_to_clean = FuturesUnordered::new();
         if let Some(prettier_paths) = self.prettiers_per_worktree.remove(&id_to_remove) {
             for path in prettier_paths.iter().flatten() {
@@ -6539,6 +8286,8 @@ impl Project {
                     this.update_local_worktree_buffers(&worktree, changes, cx);
                     this.update_local_worktree_language_servers(&worktree, changes, cx);
                     this.update_local_worktree_settings(&worktree, changes, cx);
+                    this.update_local_worktree_collab_policy(&worktree, changes, cx);
+                    this.update_local_worktree_entry_decorations(&worktree, changes, cx);
                     this.update_prettier_settings(&worktree, changes, cx);
                     cx.emit(Event::WorktreeUpdatedEntries(
                         worktree.read(cx).id(),
@@ -6550,6 +8299,18 @@ impl Project {
                 }
             })
             .detach();
+        } else {
+            cx.subscribe(worktree, |_, worktree, event, cx| {
+                if let worktree::Event::UpdatedEntries(changes) = event {
+                    if !changes.is_empty() {
+                        cx.emit(Event::WorktreeUpdatedEntries(
+                            worktree.read(cx).id(),
+                            changes.clone(),
+                        ));
+                    }
+                }
+            })
+            .detach();
         }
 
         let push_strong_handle = {
@@ -6659,11 +8420,16 @@ impl Project {
                     let old_path = old_file.abs_path(cx);
                     if new_file.abs_path(cx) != old_path {
                         renamed_buffers.push((cx.handle(), old_file.clone()));
-                        self.local_buffer_ids_by_path.remove(&project_path);
+                        // Key the buffer by the path it was renamed to, which isn't
+                        // necessarily the path of the change that's being processed.
+                        self.local_buffer_ids_by_path.remove(&ProjectPath {
+                            worktree_id,
+                            path: old_file.path.clone(),
+                        });
                         self.local_buffer_ids_by_path.insert(
                             ProjectPath {
                                 worktree_id,
-                                path: path.clone(),
+                                path: new_file.path.clone(),
                             },
                             buffer_id,
                         );
@@ -6696,7 +8462,7 @@ impl Project {
 
         for (buffer, old_file) in renamed_buffers {
             self.unregister_buffer_from_language_servers(&buffer, &old_file, cx);
-            self.detect_language_for_buffer(&buffer, cx);
+            self.detect_language_for_renamed_buffer(&buffer, cx);
             self.register_buffer_with_language_servers(&buffer, cx);
         }
     }
@@ -6926,7 +8692,7 @@ impl Project {
                                 .send(proto::UpdateWorktreeSettings {
                                     project_id: remote_id,
                                     worktree_id: remote_worktree_id.to_proto(),
-                                    path: directory.to_string_lossy().into_owned(),
+                                    path: proto::path_to_proto(&directory),
                                     content: file_content,
                                 })
                                 .log_err();
@@ -6939,6 +8705,270 @@ impl Project {
         .detach();
     }
 
+    fn update_local_worktree_collab_policy(
+        &mut self,
+        worktree: &Model<Worktree>,
+        changes: &UpdatedEntriesSet,
+        cx: &mut ModelContext<Self>,
+    ) {
+        // Only the policy at the root of the worktree applies.
+        if changes
+            .iter()
+            .any(|(path, _, _)| path.as_ref() == *LOCAL_COLLAB_POLICY_RELATIVE_PATH)
+        {
+            self.reload_local_worktree_collab_policy(worktree, cx)
+                .detach_and_log_err(cx);
+        }
+    }
+
+    /// Loads the policies of every local worktree once they've been scanned, so
+    /// that none of them are missing when the project is shared.
+    pub fn load_collab_policy(
+        &mut self,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<CollabPolicy>> {
+        let worktrees = self
+            .worktrees()
+            .filter(|worktree| worktree.read(cx).is_local())
+            .collect::<Vec<_>>();
+        cx.spawn(move |this, mut cx| async move {
+            for worktree in worktrees {
+                let scan_complete = worktree.update(&mut cx, |worktree, _| {
+                    worktree.as_local().map(|worktree| worktree.scan_complete())
+                })?;
+                if let Some(scan_complete) = scan_complete {
+                    scan_complete.await;
+                }
+                this.update(&mut cx, |this, cx| {
+                    this.reload_local_worktree_collab_policy(&worktree, cx)
+                })?
+                .await?;
+            }
+            this.update(&mut cx, |this, _| this.collab_policy())
+        })
+    }
+
+    fn reload_local_worktree_collab_policy(
+        &mut self,
+        worktree: &Model<Worktree>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        let worktree_id = worktree.read(cx).id();
+        let Some(local_worktree) = worktree.read(cx).as_local() else {
+            return Task::ready(Ok(()));
+        };
+        let abs_path = local_worktree
+            .entry_for_path(*LOCAL_COLLAB_POLICY_RELATIVE_PATH)
+            .map(|_| local_worktree.absolutize(*LOCAL_COLLAB_POLICY_RELATIVE_PATH));
+        let fs = self.fs.clone();
+        let worktree = worktree.downgrade();
+        cx.spawn(move |this, mut cx| async move {
+            let policy = match abs_path {
+                Some(abs_path) => CollabPolicy::parse(&fs.load(&abs_path?).await?)?,
+                None => CollabPolicy::default(),
+            };
+            worktree.update(&mut cx, |worktree, _| {
+                if let Some(worktree) = worktree.as_local_mut() {
+                    worktree.set_denied_to_guests(&policy.deny);
+                }
+            })?;
+            this.update(&mut cx, |this, cx| {
+                this.collab_policies.insert(worktree_id, policy);
+                this.unshare_denied_buffers(cx);
+            })
+        })
+    }
+
+    /// Takes back the buffers that guests may no longer see under the current
+    /// policy, which closes them for those guests.
+    fn unshare_denied_buffers(&mut self, cx: &mut ModelContext<Self>) {
+        let ProjectClientState::Shared { remote_id, .. } = self.client_state else {
+            return;
+        };
+        let mut denied_buffer_ids = HashSet::default();
+        for (buffer_id, buffer) in &self.opened_buffers {
+            if let Some(buffer) = buffer.upgrade() {
+                if !self.is_buffer_shareable(&buffer, cx) {
+                    denied_buffer_ids.insert(*buffer_id);
+                }
+            }
+        }
+        for (peer_id, buffer_ids) in &mut self.shared_buffers {
+            buffer_ids.retain(|buffer_id| {
+                if !denied_buffer_ids.contains(buffer_id) {
+                    return true;
+                }
+                self.client
+                    .send(proto::UnshareBufferForPeer {
+                        project_id: remote_id,
+                        peer_id: Some(*peer_id),
+                        buffer_id: (*buffer_id).into(),
+                    })
+                    .log_err();
+                false
+            });
+        }
+    }
+
+    fn update_local_worktree_entry_decorations(
+        &mut self,
+        worktree: &Model<Worktree>,
+        changes: &UpdatedEntriesSet,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if !self.is_shared() {
+            return;
+        }
+        let snapshot = worktree.read(cx).snapshot();
+        let update = self
+            .entry_decorations
+            .entry(snapshot.id())
+            .or_default()
+            .detect(
+                &snapshot,
+                changes.iter().map(|(_, entry_id, _)| *entry_id),
+                &self.languages,
+            );
+        self.entry_decorations_changed(update.into_iter().collect(), cx);
+    }
+
+    /// Detects the decorations of every local worktree's entries again once the
+    /// languages they're detected as stop changing, such as while extensions load.
+    fn schedule_entry_decorations_detection(&mut self, cx: &mut ModelContext<Self>) {
+        self.entry_decorations_debouncer
+            .fire_new(ENTRY_DECORATIONS_DEBOUNCE, cx, |this, cx| {
+                let updates = this.detect_all_entry_decorations(cx);
+                this.entry_decorations_changed(updates, cx);
+                Task::ready(())
+            });
+    }
+
+    /// Detects the decorations of every local worktree's entries again, returning
+    /// the ones that changed.
+    fn detect_all_entry_decorations(
+        &mut self,
+        cx: &mut ModelContext<Self>,
+    ) -> Vec<proto::WorktreeEntryDecorations> {
+        let mut updates = Vec::new();
+        for worktree in self.worktrees().collect::<Vec<_>>() {
+            let snapshot = worktree.read(cx).snapshot();
+            updates.extend(
+                self.entry_decorations
+                    .entry(snapshot.id())
+                    .or_default()
+                    .detect_all(&snapshot, &self.languages),
+            );
+        }
+        updates
+    }
+
+    fn entry_decorations_changed(
+        &mut self,
+        updates: Vec<proto::WorktreeEntryDecorations>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if updates.is_empty() {
+            return;
+        }
+        if let ProjectClientState::Shared { remote_id, .. } = &self.client_state {
+            for update in updates.into_iter().flat_map(entry_decoration::split_update) {
+                self.client
+                    .send(proto::UpdateEntryDecorations {
+                        project_id: *remote_id,
+                        worktrees: vec![update],
+                    })
+                    .log_err();
+            }
+        }
+        cx.notify();
+    }
+
+    fn refresh_entry_decorations(&self, cx: &mut ModelContext<Self>) {
+        if self.is_local() {
+            return;
+        }
+        let Some(project_id) = self.remote_id() else {
+            return;
+        };
+        let client = self.client.clone();
+        cx.spawn(move |this, mut cx| async move {
+            // The host sends the decorations a chunk at a time.
+            let mut cursor = None;
+            loop {
+                let is_first_chunk = cursor.is_none();
+                let response = client
+                    .request(proto::GetEntryDecorations { project_id, cursor })
+                    .await?;
+                this.update(&mut cx, |this, cx| {
+                    if is_first_chunk {
+                        this.entry_decorations.clear();
+                    }
+                    this.apply_entry_decorations(response.worktrees, cx);
+                })?;
+                cursor = response.next;
+                if cursor.is_none() {
+                    return anyhow::Ok(());
+                }
+            }
+        })
+        .detach_and_log_err(cx);
+    }
+
+    fn apply_entry_decorations(
+        &mut self,
+        worktrees: Vec<proto::WorktreeEntryDecorations>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        for worktree in worktrees {
+            self.entry_decorations
+                .entry(WorktreeId::from_proto(worktree.worktree_id))
+                .or_default()
+                .apply_proto(worktree);
+        }
+        cx.notify();
+    }
+
+    /// How the host decorates the given entry, such as with the icon of its
+    /// language, which may not be one that's available to guests.
+    pub fn entry_decoration(
+        &self,
+        worktree_id: WorktreeId,
+        entry_id: ProjectEntryId,
+        cx: &AppContext,
+    ) -> Option<EntryDecoration> {
+        if self.is_local() {
+            let worktree = self.worktree_for_id(worktree_id, cx)?;
+            let entry = worktree.read(cx).entry_for_id(entry_id)?;
+            Some(EntryDecoration::for_entry(entry, &self.languages))
+                .filter(|decoration| *decoration != EntryDecoration::default())
+        } else {
+            self.entry_decorations
+                .get(&worktree_id)?
+                .get(entry_id)
+                .cloned()
+        }
+    }
+
+    /// The sharing rules declared by the project's worktrees, which apply when
+    /// the project is shared.
+    pub fn collab_policy(&self) -> CollabPolicy {
+        CollabPolicy::merge(self.collab_policies.values())
+    }
+
+    /// Whether the host may hand this buffer to guests, given the project's
+    /// private files and collaboration policy.
+    fn is_buffer_shareable(&self, buffer: &Model<Buffer>, cx: &AppContext) -> bool {
+        let Some(file) = File::from_dyn(buffer.read(cx).file()) else {
+            return true;
+        };
+        if file.is_private() {
+            return false;
+        }
+        file.worktree.read(cx).as_local().map_or(true, |worktree| {
+            !worktree.is_path_denied_to_guests(&file.path)
+        })
+    }
+
     pub fn set_active_path(&mut self, entry: Option<ProjectPath>, cx: &mut ModelContext<Self>) {
         let new_active_entry = entry.and_then(|project_path| {
             let worktree = self.worktree_for_id(project_path.worktree_id, cx)?;
@@ -7142,6 +9172,21 @@ impl Project {
         })?
     }
 
+    async fn handle_update_collaborator_read_only(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::UpdateCollaboratorReadOnly>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        let peer_id = envelope
+            .payload
+            .peer_id
+            .ok_or_else(|| anyhow!("invalid peer id"))?;
+        this.update(&mut cx, |this, cx| {
+            this.collaborator_read_only_changed(peer_id, envelope.payload.read_only, cx)
+        })
+    }
+
     async fn handle_remove_collaborator(
         this: Model<Self>,
         envelope: TypedEnvelope<proto::RemoveProjectCollaborator>,
@@ -7153,11 +9198,15 @@ impl Project {
                 .payload
                 .peer_id
                 .ok_or_else(|| anyhow!("invalid peer id"))?;
-            let replica_id = this
+            let collaborator = this
                 .collaborators
                 .remove(&peer_id)
-                .ok_or_else(|| anyhow!("unknown peer {:?}", peer_id))?
-                .replica_id;
+                .ok_or_else(|| anyhow!("unknown peer {:?}", peer_id))?;
+            let replica_id = collaborator.replica_id;
+            if this.is_local() {
+                this.attribute_edits(replica_id, collaborator.user_id, cx);
+                this.persist_edit_attributions(this.edit_attributions.keys().copied(), cx);
+            }
             for buffer in this.opened_buffers.values() {
                 if let Some(buffer) = buffer.upgrade() {
                     buffer.update(cx, |buffer, cx| buffer.remove_peer(replica_id, cx));
@@ -7217,7 +9266,7 @@ impl Project {
                     store
                         .set_local_settings(
                             worktree.entity_id().as_u64() as usize,
-                            PathBuf::from(&envelope.payload.path).into(),
+                            proto::path_from_proto(envelope.payload.path.clone()).into(),
                             envelope.payload.content.as_ref().map(String::as_str),
                             cx,
                         )
@@ -7243,7 +9292,8 @@ impl Project {
         let entry = worktree
             .update(&mut cx, |worktree, cx| {
                 let worktree = worktree.as_local_mut().unwrap();
-                let path = PathBuf::from(envelope.payload.path);
+                let path = proto::path_from_proto(envelope.payload.path);
+                let path = worktree.resolve_path_spelling(&path);
                 worktree.create_entry(path, envelope.payload.is_directory, cx)
             })?
             .await?;
@@ -7267,11 +9317,10 @@ impl Project {
         let worktree_scan_id = worktree.update(&mut cx, |worktree, _| worktree.scan_id())?;
         let entry = worktree
             .update(&mut cx, |worktree, cx| {
-                let new_path = PathBuf::from(envelope.payload.new_path);
-                worktree
-                    .as_local_mut()
-                    .unwrap()
-                    .rename_entry(entry_id, new_path, cx)
+                let worktree = worktree.as_local_mut().unwrap();
+                let new_path = proto::path_from_proto(envelope.payload.new_path);
+                let new_path = worktree.resolve_path_spelling(&new_path);
+                worktree.rename_entry(entry_id, new_path, cx)
             })?
             .await?;
         Ok(proto::ProjectEntryResponse {
@@ -7294,11 +9343,10 @@ impl Project {
         let worktree_scan_id = worktree.update(&mut cx, |worktree, _| worktree.scan_id())?;
         let entry = worktree
             .update(&mut cx, |worktree, cx| {
-                let new_path = PathBuf::from(envelope.payload.new_path);
-                worktree
-                    .as_local_mut()
-                    .unwrap()
-                    .copy_entry(entry_id, new_path, cx)
+                let worktree = worktree.as_local_mut().unwrap();
+                let new_path = proto::path_from_proto(envelope.payload.new_path);
+                let new_path = worktree.resolve_path_spelling(&new_path);
+                worktree.copy_entry(entry_id, new_path, cx)
             })?
             .await?;
         Ok(proto::ProjectEntryResponse {
@@ -7372,7 +9420,7 @@ impl Project {
                 if let Some(summary) = envelope.payload.summary {
                     let project_path = ProjectPath {
                         worktree_id,
-                        path: Path::new(&summary.path).into(),
+                        path: proto::path_from_proto(summary.path.clone()).into(),
                     };
                     worktree.update(cx, |worktree, _| {
                         worktree
@@ -7407,6 +9455,7 @@ impl Project {
                     name: server.name,
                     pending_work: Default::default(),
                     has_pending_diagnostic_updates: false,
+                    configuration: None,
                     progress_tokens: Default::default(),
                 },
             );
@@ -7466,6 +9515,11 @@ impl Project {
                 proto::update_language_server::Variant::DiskBasedDiagnosticsUpdated(_) => {
                     this.disk_based_diagnostics_finished(language_server_id, cx)
                 }
+
+                proto::update_language_server::Variant::ConfigurationUpdated(payload) => {
+                    let configuration = serde_json::from_str(&payload.configuration)?;
+                    this.set_language_server_configuration(language_server_id, configuration, cx);
+                }
             }
 
             Ok(())
@@ -7493,7 +9547,11 @@ impl Project {
                         buffer.update(cx, |buffer, cx| buffer.apply_ops(ops, cx))?;
                     }
                     OpenBuffer::Operations(operations) => operations.extend_from_slice(&ops),
-                    OpenBuffer::Weak(_) => {}
+                    OpenBuffer::Weak(buffer) => {
+                        if let Some(buffer) = buffer.upgrade() {
+                            buffer.update(cx, |buffer, cx| buffer.apply_ops(ops, cx))?;
+                        }
+                    }
                 },
                 hash_map::Entry::Vacant(e) => {
                     assert!(
@@ -7527,8 +9585,21 @@ impl Project {
                         let worktree = this.worktree_for_id(worktree_id, cx).ok_or_else(|| {
                             anyhow!("no worktree found for id {}", file.worktree_id)
                         })?;
-                        buffer_file = Some(Arc::new(File::from_proto(file, worktree.clone(), cx)?)
-                            as Arc<dyn language::File>);
+                        let file = File::from_proto(file, worktree.clone(), cx)?;
+
+                        // Load the directory containing the buffer, so that it can
+                        // be revealed in the project panel, for example.
+                        if let Some(parent) = file.path.parent() {
+                            worktree.update(cx, |worktree, cx| {
+                                if let Some(worktree) = worktree.as_remote_mut() {
+                                    worktree
+                                        .load_directory(parent.into(), cx)
+                                        .detach_and_log_err(cx);
+                                }
+                            });
+                        }
+
+                        buffer_file = Some(Arc::new(file) as Arc<dyn language::File>);
                     }
 
                     let buffer_id = BufferId::new(state.id)?;
@@ -7570,6 +9641,331 @@ impl Project {
         })?
     }
 
+    async fn handle_unshare_buffer_for_peer(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::UnshareBufferForPeer>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        this.update(&mut cx, |this, cx| {
+            let buffer_id = BufferId::new(envelope.payload.buffer_id)?;
+            this.incomplete_remote_buffers.remove(&buffer_id);
+            let Some(buffer) = this
+                .opened_buffers
+                .remove(&buffer_id)
+                .and_then(|buffer| buffer.upgrade())
+            else {
+                return Ok(());
+            };
+            // The buffer is no longer kept in sync with the host, so its editors
+            // are closed the way they would be if its file had been deleted.
+            buffer.update(cx, |buffer, cx| {
+                buffer.set_capability(Capability::ReadOnly, cx)
+            });
+            if let Some(entry_id) =
+                File::from_dyn(buffer.read(cx).file()).and_then(|file| file.project_entry_id(cx))
+            {
+                cx.emit(Event::DeletedEntry(entry_id));
+            }
+            Ok(())
+        })?
+    }
+
+    async fn handle_close_buffer(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::CloseBuffer>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<proto::Ack> {
+        let guest_id = envelope.original_sender_id()?;
+        let buffer_id = BufferId::new(envelope.payload.buffer_id)?;
+        this.update(&mut cx, |this, _| {
+            if let Some(buffer_ids) = this.shared_buffers.get_mut(&guest_id) {
+                buffer_ids.remove(&buffer_id);
+            }
+        })?;
+        Ok(proto::Ack {})
+    }
+
+    async fn handle_add_bookmark(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::AddBookmark>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<proto::AddBookmarkResponse> {
+        let sender_id = envelope.original_sender_id()?;
+        let buffer_id = BufferId::new(envelope.payload.buffer_id)?;
+        let anchor = envelope
+            .payload
+            .anchor
+            .and_then(deserialize_anchor)
+            .ok_or_else(|| anyhow!("invalid anchor"))?;
+        let (buffer, wait_for_anchor) = this.update(&mut cx, |this, cx| {
+            let buffer = this
+                .opened_buffers
+                .get(&buffer_id)
+                .and_then(|buffer| buffer.upgrade())
+                .ok_or_else(|| anyhow!("unknown buffer id {}", buffer_id))?;
+            let wait_for_anchor = buffer.update(cx, |buffer, _| buffer.wait_for_anchors([anchor]));
+            Ok::<_, anyhow::Error>((buffer, wait_for_anchor))
+        })??;
+        wait_for_anchor.await?;
+
+        let bookmark = this.update(&mut cx, |this, cx| {
+            let author_user_id = this
+                .collaborators
+                .get(&sender_id)
+                .map(|collaborator| collaborator.user_id);
+            this.insert_bookmark(envelope.payload.name, buffer, anchor, author_user_id, cx)
+        })?;
+        Ok(proto::AddBookmarkResponse {
+            bookmark: Some(bookmark.to_proto()),
+        })
+    }
+
+    async fn handle_remove_bookmark(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::RemoveBookmark>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<proto::Ack> {
+        this.update(&mut cx, |this, cx| {
+            this.remove_local_bookmark(envelope.payload.bookmark_id, cx)
+        })?;
+        Ok(proto::Ack {})
+    }
+
+    async fn handle_get_bookmarks(
+        this: Model<Self>,
+        _: TypedEnvelope<proto::GetBookmarks>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<proto::GetBookmarksResponse> {
+        this.update(&mut cx, |this, _| proto::GetBookmarksResponse {
+            bookmarks: this.bookmarks.iter().map(Bookmark::to_proto).collect(),
+        })
+    }
+
+    async fn handle_get_entry_decorations(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::GetEntryDecorations>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<proto::GetEntryDecorationsResponse> {
+        let cursor = envelope.payload.cursor;
+        this.update(&mut cx, |this, _| {
+            let mut worktrees = this.entry_decorations.iter().collect::<Vec<_>>();
+            worktrees.sort_unstable_by_key(|(worktree_id, _)| **worktree_id);
+
+            let mut response = proto::GetEntryDecorationsResponse::default();
+            let mut remaining = entry_decoration::MAX_CHUNK_SIZE;
+            for (worktree_id, decorations) in worktrees {
+                let worktree_id = worktree_id.to_proto();
+                let after_entry_id = match &cursor {
+                    Some(cursor) if worktree_id < cursor.worktree_id => continue,
+                    Some(cursor) if worktree_id == cursor.worktree_id => {
+                        Some(ProjectEntryId::from_proto(cursor.entry_id))
+                    }
+                    _ => None,
+                };
+                let chunk = decorations.to_proto(worktree_id, after_entry_id, remaining);
+                remaining -= chunk.updated_decorations.len();
+                if remaining == 0 {
+                    response.next = chunk.updated_decorations.last().map(|decoration| {
+                        proto::EntryDecorationsCursor {
+                            worktree_id,
+                            entry_id: decoration.entry_id,
+                        }
+                    });
+                    response.worktrees.push(chunk);
+                    break;
+                }
+                response.worktrees.push(chunk);
+            }
+            response
+        })
+    }
+
+    async fn handle_get_language_server_configurations(
+        this: Model<Self>,
+        _: TypedEnvelope<proto::GetLanguageServerConfigurations>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<proto::GetLanguageServerConfigurationsResponse> {
+        this.update(&mut cx, |this, _| {
+            let configurations = this
+                .language_server_statuses
+                .iter()
+                .filter_map(|(server_id, status)| {
+                    Some(proto::LanguageServerConfiguration {
+                        language_server_id: server_id.0 as u64,
+                        configuration: status.configuration.as_ref()?.to_string(),
+                    })
+                })
+                .collect();
+            proto::GetLanguageServerConfigurationsResponse { configurations }
+        })
+    }
+
+    async fn handle_update_bookmarks(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::UpdateBookmarks>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        this.update(&mut cx, |this, cx| {
+            this.set_bookmarks_from_proto(envelope.payload.bookmarks, cx)
+        })?
+    }
+
+    async fn handle_update_entry_decorations(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::UpdateEntryDecorations>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        this.update(&mut cx, |this, cx| {
+            this.apply_entry_decorations(envelope.payload.worktrees, cx)
+        })
+    }
+
+    async fn handle_add_review_comment(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::AddReviewComment>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<proto::AddReviewCommentResponse> {
+        let sender_id = envelope.original_sender_id()?;
+        let payload = envelope.payload;
+        let buffer_id = BufferId::new(payload.buffer_id)?;
+        let range = review::deserialize_range(payload.start, payload.end)?;
+        let wait_for_anchors = this.update(&mut cx, |this, cx| {
+            let buffer = this
+                .opened_buffers
+                .get(&buffer_id)
+                .and_then(|buffer| buffer.upgrade())
+                .ok_or_else(|| anyhow!("unknown buffer id {}", buffer_id))?;
+            Ok::<_, anyhow::Error>(buffer.update(cx, |buffer, _| {
+                buffer.wait_for_anchors([range.start, range.end])
+            }))
+        })??;
+        wait_for_anchors.await?;
+
+        let comment = this.update(&mut cx, |this, cx| {
+            let author_user_id = this
+                .collaborators
+                .get(&sender_id)
+                .map(|collaborator| collaborator.user_id);
+            this.insert_review_comment(
+                buffer_id,
+                range,
+                payload.body,
+                payload.suggestion,
+                author_user_id,
+                cx,
+            )
+        })??;
+        Ok(proto::AddReviewCommentResponse {
+            comment: Some(comment.to_proto()),
+        })
+    }
+
+    async fn handle_resolve_review_comment(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::ResolveReviewComment>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<proto::Ack> {
+        this.update(&mut cx, |this, cx| {
+            this.remove_local_review_comment(envelope.payload.comment_id, cx)
+        })?;
+        Ok(proto::Ack {})
+    }
+
+    async fn handle_get_review(
+        this: Model<Self>,
+        _: TypedEnvelope<proto::GetReview>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<proto::GetReviewResponse> {
+        this.update(&mut cx, |this, _| proto::GetReviewResponse {
+            review: this.review.as_ref().map(Review::to_proto),
+        })
+    }
+
+    async fn handle_update_review(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::UpdateReview>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        this.update(&mut cx, |this, cx| {
+            this.set_review_from_proto(envelope.payload.review, cx)
+        })?
+    }
+
+    async fn handle_suggest_edit(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::SuggestEdit>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<proto::SuggestEditResponse> {
+        let sender_id = envelope.original_sender_id()?;
+        let payload = envelope.payload;
+        let buffer_id = BufferId::new(payload.buffer_id)?;
+        let edits = edit_suggestion::deserialize_suggested_edits(payload.edits)?;
+        edit_suggestion::check_suggested_edits(&edits)?;
+        let wait_for_anchors = this.update(&mut cx, |this, cx| {
+            let buffer = this
+                .opened_buffers
+                .get(&buffer_id)
+                .and_then(|buffer| buffer.upgrade())
+                .ok_or_else(|| anyhow!("unknown buffer id {}", buffer_id))?;
+            Ok::<_, anyhow::Error>(buffer.update(cx, |buffer, _| {
+                buffer
+                    .wait_for_anchors(edits.iter().flat_map(|(range, _)| [range.start, range.end]))
+            }))
+        })??;
+        wait_for_anchors.await?;
+
+        let suggestion = this.update(&mut cx, |this, cx| {
+            let author_user_id = this
+                .collaborators
+                .get(&sender_id)
+                .map(|collaborator| collaborator.user_id);
+            this.insert_edit_suggestion(buffer_id, edits, author_user_id, cx)
+        })??;
+        Ok(proto::SuggestEditResponse {
+            suggestion: Some(suggestion.to_proto()),
+        })
+    }
+
+    async fn handle_get_edit_suggestions(
+        this: Model<Self>,
+        _: TypedEnvelope<proto::GetEditSuggestions>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<proto::GetEditSuggestionsResponse> {
+        this.update(&mut cx, |this, _| proto::GetEditSuggestionsResponse {
+            suggestions: this
+                .edit_suggestions
+                .iter()
+                .map(EditSuggestion::to_proto)
+                .collect(),
+        })
+    }
+
+    async fn handle_update_edit_suggestions(
+        this: Model<Self>,
+        envelope: TypedEnvelope<proto::UpdateEditSuggestions>,
+        _: Arc<Client>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        this.update(&mut cx, |this, cx| {
+            this.set_edit_suggestions_from_proto(envelope.payload.suggestions, cx)
+        })?
+    }
+
     async fn handle_update_diff_base(
         this: Model<Self>,
         envelope: TypedEnvelope<proto::UpdateDiffBase>,
@@ -7624,10 +10020,16 @@ impl Project {
                     .worktree_for_id(WorktreeId::from_proto(file.worktree_id), cx)
                     .ok_or_else(|| anyhow!("no such worktree"))?;
                 let file = File::from_proto(file, worktree, cx)?;
+                let old_path = buffer.read(cx).file().map(|file| file.path().clone());
+                let renamed = old_path.as_ref() != Some(&file.path);
                 buffer.update(cx, |buffer, cx| {
                     buffer.file_updated(Arc::new(file), cx);
                 });
-                this.detect_language_for_buffer(&buffer, cx);
+                if renamed {
+                    this.detect_language_for_renamed_buffer(&buffer, cx);
+                } else {
+                    this.detect_language_for_buffer(&buffer, cx);
+                }
             }
             Ok(())
         })?
@@ -8129,12 +10531,16 @@ impl Project {
         cx.spawn(move |mut cx| async move {
             let mut locations = Vec::new();
             while let Some((buffer, ranges)) = result.next().await {
+                let Ok(buffer_id) = this.update(&mut cx, |this, cx| {
+                    this.create_buffer_for_peer(&buffer, peer_id, cx)
+                })?
+                else {
+                    continue;
+                };
                 for range in ranges {
                     let start = serialize_anchor(&range.start);
                     let end = serialize_anchor(&range.end);
-                    let buffer_id = this.update(&mut cx, |this, cx| {
-                        this.create_buffer_for_peer(&buffer, peer_id, cx).into()
-                    })?;
+                    let buffer_id = buffer_id.into();
                     locations.push(proto::Location {
                         buffer_id,
                         start: Some(start),
@@ -8174,18 +10580,9 @@ impl Project {
             .await?;
 
         this.update(&mut cx, |this, cx| {
-            let is_private = buffer
-                .read(cx)
-                .file()
-                .map(|f| f.is_private())
-                .unwrap_or_default();
-            if is_private {
-                Err(anyhow!(ErrorCode::UnsharedItem))
-            } else {
-                Ok(proto::OpenBufferForSymbolResponse {
-                    buffer_id: this.create_buffer_for_peer(&buffer, peer_id, cx).into(),
-                })
-            }
+            Ok(proto::OpenBufferForSymbolResponse {
+                buffer_id: this.create_buffer_for_peer(&buffer, peer_id, cx)?.into(),
+            })
         })?
     }
 
@@ -8223,7 +10620,7 @@ impl Project {
             this.open_buffer(
                 ProjectPath {
                     worktree_id,
-                    path: PathBuf::from(envelope.payload.path).into(),
+                    path: proto::path_from_proto(envelope.payload.path).into(),
                 },
                 cx,
             )
@@ -8240,21 +10637,14 @@ impl Project {
         cx: &mut AsyncAppContext,
     ) -> Result<proto::OpenBufferResponse> {
         this.update(cx, |this, cx| {
-            let is_private = buffer
-                .read(cx)
-                .file()
-                .map(|f| f.is_private())
-                .unwrap_or_default();
-            if is_private {
-                Err(anyhow!(ErrorCode::UnsharedItem))
-            } else {
-                Ok(proto::OpenBufferResponse {
-                    buffer_id: this.create_buffer_for_peer(&buffer, peer_id, cx).into(),
-                })
-            }
+            Ok(proto::OpenBufferResponse {
+                buffer_id: this.create_buffer_for_peer(&buffer, peer_id, cx)?.into(),
+            })
         })?
     }
 
+    /// Serializes a transaction for a guest, leaving out the edits to buffers that
+    /// the host may not hand to guests. Those edits are still made on the host.
     fn serialize_project_transaction_for_peer(
         &mut self,
         project_transaction: ProjectTransaction,
@@ -8266,9 +10656,10 @@ impl Project {
             transactions: Default::default(),
         };
         for (buffer, transaction) in project_transaction.0 {
-            serialized_transaction
-                .buffer_ids
-                .push(self.create_buffer_for_peer(&buffer, peer_id, cx).into());
+            let Ok(buffer_id) = self.create_buffer_for_peer(&buffer, peer_id, cx) else {
+                continue;
+            };
+            serialized_transaction.buffer_ids.push(buffer_id.into());
             serialized_transaction
                 .transactions
                 .push(language::proto::serialize_transaction(&transaction));
@@ -8314,19 +10705,24 @@ impl Project {
         })
     }
 
+    /// Sends the buffer to the given guest, unless it's one that the host may not
+    /// hand to guests, in which case an [`ErrorCode::UnsharedItem`] is returned.
     fn create_buffer_for_peer(
         &mut self,
         buffer: &Model<Buffer>,
         peer_id: proto::PeerId,
         cx: &mut AppContext,
-    ) -> BufferId {
+    ) -> Result<BufferId> {
+        if !self.is_buffer_shareable(buffer, cx) {
+            return Err(anyhow!(ErrorCode::UnsharedItem));
+        }
         let buffer_id = buffer.read(cx).remote_id();
         if let ProjectClientState::Shared { updates_tx, .. } = &self.client_state {
             updates_tx
                 .unbounded_send(LocalProjectUpdate::CreateBufferForPeer { peer_id, buffer_id })
                 .ok();
         }
-        buffer_id
+        Ok(buffer_id)
     }
 
     fn wait_for_remote_buffer(
@@ -8337,6 +10733,7 @@ impl Project {
         let mut opened_buffer_rx = self.opened_buffer.1.clone();
 
         cx.spawn(move |this, mut cx| async move {
+            let mut requested_again = false;
             let buffer = loop {
                 let Some(this) = this.upgrade() else {
                     return Err(anyhow!("project dropped"));
@@ -8349,15 +10746,43 @@ impl Project {
                 })?;
 
                 if let Some(buffer) = buffer {
+                    // Once it's been handed out, the buffer is only kept open for as long as
+                    // something uses it, after which the host is told it was closed.
+                    this.update(&mut cx, |this, _| {
+                        if let Some(open_buffer @ OpenBuffer::Strong(_)) =
+                            this.opened_buffers.get_mut(&id)
+                        {
+                            *open_buffer = OpenBuffer::Weak(buffer.downgrade());
+                        }
+                    })?;
                     break buffer;
                 } else if this.update(&mut cx, |this, _| this.is_disconnected())? {
                     return Err(anyhow!("disconnected before buffer {} could be opened", id));
                 }
 
-                this.update(&mut cx, |this, _| {
+                // The host doesn't send a buffer that it thinks we still have open. If we
+                // closed this one just before asking for it, ask again once the host knows.
+                let request_again = this.update(&mut cx, |this, _| {
                     this.incomplete_remote_buffers.entry(id).or_default();
+                    let closing = this.closed_remote_buffers.get(&id)?.clone();
+                    let project_id = this.remote_id()?;
+                    let client = this.client.clone();
+                    Some(async move {
+                        closing.await;
+                        client
+                            .request(proto::OpenBufferById {
+                                project_id,
+                                id: id.into(),
+                            })
+                            .await
+                            .log_err();
+                    })
                 })?;
                 drop(this);
+                if let Some(request_again) = request_again.filter(|_| !requested_again) {
+                    requested_again = true;
+                    cx.background_executor().spawn(request_again).detach();
+                }
 
                 opened_buffer_rx
                     .next()
@@ -8438,7 +10863,11 @@ impl Project {
                                 buffer.read(cx).serialize_ops(Some(remote_version), cx);
                             cx.background_executor().spawn(async move {
                                 let operations = operations.await;
-                                for chunk in split_operations(operations) {
+                                // Guests who can't edit can still be brought up to date, as
+                                // long as they don't send the host any operations.
+                                for chunk in split_operations(operations)
+                                    .filter(|operations| !operations.is_empty())
+                                {
                                     client
                                         .request(proto::UpdateBuffer {
                                             project_id,
@@ -8561,7 +10990,7 @@ impl Project {
             let kind = unsafe { mem::transmute(serialized_symbol.kind) };
             let path = ProjectPath {
                 worktree_id,
-                path: PathBuf::from(serialized_symbol.path).into(),
+                path: proto::path_from_proto(serialized_symbol.path).into(),
             };
             let language = languages
                 .language_for_file(&path.path, None)
@@ -9067,7 +11496,7 @@ fn serialize_symbol(symbol: &Symbol) -> proto::Symbol {
         language_server_name: symbol.language_server_name.0.to_string(),
         source_worktree_id: symbol.source_worktree_id.to_proto(),
         worktree_id: symbol.path.worktree_id.to_proto(),
-        path: symbol.path.path.to_string_lossy().to_string(),
+        path: proto::path_to_proto(&symbol.path.path),
         name: symbol.name.clone(),
         kind: unsafe { mem::transmute(symbol.kind) },
         start: Some(proto::PointUtf16 {
@@ -9138,6 +11567,13 @@ impl Item for Buffer {
     }
 }
 
+async fn is_dir(fs: &dyn Fs, path: &Path) -> Result<bool> {
+    Ok(fs
+        .metadata(path)
+        .await?
+        .map_or(false, |metadata| metadata.is_dir))
+}
+
 async fn wait_for_loading_buffer(
     mut receiver: postage::watch::Receiver<Option<Result<Model<Buffer>, Arc<anyhow::Error>>>>,
 ) -> Result<Model<Buffer>, Arc<anyhow::Error>> {
@@ -9167,3 +11603,53 @@ fn include_text(server: &lsp::LanguageServer) -> bool {
         })
         .unwrap_or(false)
 }
+
+/// The settings in a language server's configuration whose values can be shared
+/// with collaborators, compared case-insensitively. They toggle or tune features,
+/// and unlike paths, commands or environment variables, can't hold secrets.
+const SHARED_LSP_CONFIGURATION_KEYS: &[&str] = &[
+    "enable",
+    "enabled",
+    "features",
+    "allfeatures",
+    "nodefaultfeatures",
+    "checkonsave",
+    "diagnostics",
+    "inlayhints",
+    "lens",
+    "hover",
+    "completion",
+    "format",
+    "lint",
+    "trace",
+    "loglevel",
+    "edition",
+];
+
+/// Replaces the values that shouldn't be shared with collaborators, which are all
+/// those that aren't in [`SHARED_LSP_CONFIGURATION_KEYS`]. Objects and arrays are
+/// kept, so that guests can still see how the configuration is laid out.
+fn redact_lsp_configuration(value: &mut serde_json::Value) {
+    redact_lsp_configuration_value(value, false);
+}
+
+fn redact_lsp_configuration_value(value: &mut serde_json::Value, shared: bool) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let shared = SHARED_LSP_CONFIGURATION_KEYS.contains(&key.to_lowercase().as_str());
+                redact_lsp_configuration_value(value, shared);
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                redact_lsp_configuration_value(value, shared);
+            }
+        }
+        _ => {
+            if !shared {
+                *value = serde_json::Value::String("<redacted>".into());
+            }
+        }
+    }
+}
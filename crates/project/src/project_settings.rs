@@ -39,6 +39,33 @@ pub struct ProjectSettings {
     /// Treat the files matching these globs as `.env` files.
     /// Default: [ "**/.env*" ]
     pub private_files: Option<Vec<String>>,
+
+    /// How to treat symlinks to directories outside of the worktree, such as
+    /// other repositories linked into this one.
+    ///
+    /// Default: lazy
+    #[serde(default)]
+    pub external_symlinks: ExternalSymlinks,
+
+    /// Whether to remember who wrote which spans of a file while its project was
+    /// shared, so that it can be shown after the session. This is only stored on
+    /// the host's machine.
+    ///
+    /// Default: true
+    pub attribute_session_edits: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExternalSymlinks {
+    /// List the linked directory, but only load its contents once it is expanded.
+    /// Its contents aren't searched.
+    #[default]
+    Lazy,
+    /// Scan and search the linked directory as part of the worktree.
+    Follow,
+    /// Leave the linked directory out of the worktree.
+    Ignore,
 }
 
 #[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
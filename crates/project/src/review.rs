@@ -0,0 +1,151 @@
+use anyhow::{anyhow, Result};
+use language::{
+    proto::{deserialize_anchor, serialize_anchor},
+    Anchor, BufferSnapshot, Point,
+};
+use rpc::proto;
+use std::ops::Range;
+use text::BufferId;
+
+/// How many unchanged lines are shown above and below each changed hunk.
+pub const REVIEW_CONTEXT_LINES: u32 = 3;
+
+/// A set of changed hunks across the host's buffers, shown to everyone in the
+/// project as a single multibuffer, along with the comments left on them.
+///
+/// Like bookmarks, the review is owned by the host. Guests ask the host to add
+/// or resolve comments, and receive the whole review whenever it changes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Review {
+    pub excerpts: Vec<ReviewExcerpt>,
+    pub comments: Vec<ReviewComment>,
+}
+
+/// A changed hunk and the lines around it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReviewExcerpt {
+    pub buffer_id: BufferId,
+    pub range: Range<Anchor>,
+}
+
+/// A comment on a span of a reviewed buffer, optionally suggesting the text
+/// that should replace the span.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReviewComment {
+    pub id: u64,
+    pub buffer_id: BufferId,
+    pub range: Range<Anchor>,
+    pub body: String,
+    pub suggestion: Option<String>,
+    pub author_user_id: Option<u64>,
+}
+
+impl Review {
+    pub fn comment(&self, id: u64) -> Option<&ReviewComment> {
+        self.comments.iter().find(|comment| comment.id == id)
+    }
+
+    pub(crate) fn to_proto(&self) -> proto::Review {
+        proto::Review {
+            excerpts: self
+                .excerpts
+                .iter()
+                .map(|excerpt| proto::ReviewExcerpt {
+                    buffer_id: excerpt.buffer_id.into(),
+                    start: Some(serialize_anchor(&excerpt.range.start)),
+                    end: Some(serialize_anchor(&excerpt.range.end)),
+                })
+                .collect(),
+            comments: self.comments.iter().map(ReviewComment::to_proto).collect(),
+        }
+    }
+
+    pub(crate) fn from_proto(message: proto::Review) -> Result<Self> {
+        Ok(Self {
+            excerpts: message
+                .excerpts
+                .into_iter()
+                .map(|excerpt| {
+                    Ok(ReviewExcerpt {
+                        buffer_id: BufferId::new(excerpt.buffer_id)?,
+                        range: deserialize_range(excerpt.start, excerpt.end)?,
+                    })
+                })
+                .collect::<Result<_>>()?,
+            comments: message
+                .comments
+                .into_iter()
+                .map(ReviewComment::from_proto)
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl ReviewComment {
+    pub(crate) fn to_proto(&self) -> proto::ReviewComment {
+        proto::ReviewComment {
+            id: self.id,
+            buffer_id: self.buffer_id.into(),
+            start: Some(serialize_anchor(&self.range.start)),
+            end: Some(serialize_anchor(&self.range.end)),
+            body: self.body.clone(),
+            suggestion: self.suggestion.clone(),
+            author_user_id: self.author_user_id,
+        }
+    }
+
+    pub(crate) fn from_proto(message: proto::ReviewComment) -> Result<Self> {
+        Ok(Self {
+            id: message.id,
+            buffer_id: BufferId::new(message.buffer_id)?,
+            range: deserialize_range(message.start, message.end)?,
+            body: message.body,
+            suggestion: message.suggestion,
+            author_user_id: message.author_user_id,
+        })
+    }
+}
+
+pub(crate) fn deserialize_range(
+    start: Option<proto::Anchor>,
+    end: Option<proto::Anchor>,
+) -> Result<Range<Anchor>> {
+    let start = start
+        .and_then(deserialize_anchor)
+        .ok_or_else(|| anyhow!("invalid review range start"))?;
+    let end = end
+        .and_then(deserialize_anchor)
+        .ok_or_else(|| anyhow!("invalid review range end"))?;
+    Ok(start..end)
+}
+
+/// Returns the ranges of whole lines covering the buffer's changed hunks and
+/// [`REVIEW_CONTEXT_LINES`] lines around each of them, merging ranges that
+/// overlap or touch.
+pub(crate) fn excerpt_ranges_for_changes(snapshot: &BufferSnapshot) -> Vec<Range<Anchor>> {
+    let max_row = snapshot.max_point().row;
+    let mut row_ranges = Vec::<Range<u32>>::new();
+    for hunk in snapshot.git_diff_hunks_in_row_range(0..max_row + 1) {
+        let rows = hunk.buffer_range;
+        let last_changed_row = if rows.is_empty() {
+            rows.start
+        } else {
+            rows.end - 1
+        };
+        let start = rows.start.min(max_row).saturating_sub(REVIEW_CONTEXT_LINES);
+        let end = (last_changed_row + REVIEW_CONTEXT_LINES).min(max_row);
+        match row_ranges.last_mut() {
+            Some(last) if start <= last.end + 1 => last.end = last.end.max(end),
+            _ => row_ranges.push(start..end),
+        }
+    }
+
+    row_ranges
+        .into_iter()
+        .map(|rows| {
+            let start = Point::new(rows.start, 0);
+            let end = Point::new(rows.end, snapshot.line_len(rows.end));
+            snapshot.anchor_before(start)..snapshot.anchor_after(end)
+        })
+        .collect()
+}
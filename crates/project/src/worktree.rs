@@ -1,6 +1,8 @@
 use crate::{
-    copy_recursive, ignore::IgnoreStack, project_settings::ProjectSettings, DiagnosticSummary,
-    ProjectEntryId, RemoveOptions,
+    copy_recursive,
+    ignore::IgnoreStack,
+    project_settings::{ExternalSymlinks, ProjectSettings},
+    DiagnosticSummary, ProjectEntryId, RemoveOptions,
 };
 use ::ignore::gitignore::{Gitignore, GitignoreBuilder};
 use anyhow::{anyhow, Context as _, Result};
@@ -9,7 +11,7 @@ use clock::ReplicaId;
 use collections::{HashMap, HashSet, VecDeque};
 use fs::{
     repository::{GitFileStatus, GitRepository, RepoPath},
-    Fs,
+    CreateOptions, Fs,
 };
 use futures::{
     channel::{
@@ -46,7 +48,8 @@ use settings::{Settings, SettingsStore};
 use smol::channel::{self, Sender};
 use std::{
     any::Any,
-    cmp::{self, Ordering},
+    cmp::Ordering,
+    collections::BTreeMap,
     convert::TryFrom,
     ffi::OsStr,
     fmt,
@@ -64,7 +67,7 @@ use std::{
 use sum_tree::{Bias, Edit, SeekTarget, SumTree, TreeMap, TreeSet};
 use text::BufferId;
 use util::{
-    paths::{PathMatcher, HOME},
+    paths::{paths_equal_normalized, PathMatcher, HOME},
     ResultExt,
 };
 
@@ -103,11 +106,14 @@ struct ScanRequest {
 
 pub struct RemoteWorktree {
     snapshot: Snapshot,
-    background_snapshot: Arc<Mutex<Snapshot>>,
+    background_snapshot: Arc<Mutex<(Snapshot, RemoteChanges)>>,
     project_id: u64,
     client: Arc<Client>,
     updates_tx: Option<UnboundedSender<proto::UpdateWorktree>>,
     snapshot_subscriptions: VecDeque<(usize, oneshot::Sender<()>)>,
+    /// Callers waiting for a directory to be loaded, or for every directory to
+    /// be loaded when the path is `None`.
+    directory_subscriptions: Vec<(Option<Arc<Path>>, oneshot::Sender<()>)>,
     replica_id: ReplicaId,
     diagnostic_summaries: HashMap<Arc<Path>, HashMap<LanguageServerId, DiagnosticSummary>>,
     visible: bool,
@@ -135,6 +141,10 @@ pub struct Snapshot {
     /// greater than the `completed_scan_id` if operations are performed
     /// on the worktree while it is processing a file-system event.
     completed_scan_id: usize,
+
+    /// The directories whose children have been replicated, for remote worktrees
+    /// whose entries are loaded lazily. Every directory is loaded when `None`.
+    loaded_directories: Option<Arc<HashSet<Arc<Path>>>>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -231,22 +241,48 @@ pub struct LocalSnapshot {
     git_repositories: TreeMap<ProjectEntryId, LocalRepositoryEntry>,
     file_scan_exclusions: Vec<PathMatcher>,
     private_files: Vec<PathMatcher>,
+    external_symlinks: ExternalSymlinks,
+    /// Paths that the project's collaboration policy keeps from guests.
+    denied_to_guests: Vec<PathMatcher>,
 }
 
+/// How many scans a removed entry's id is kept for, in case its inode turns up
+/// at another path.
+const REMOVED_ENTRY_ID_RETENTION_SCANS: usize = 4;
+
 struct BackgroundScannerState {
     snapshot: LocalSnapshot,
     scanned_dirs: HashSet<ProjectEntryId>,
     path_prefixes_to_scan: HashSet<Arc<Path>>,
     paths_to_scan: HashSet<Arc<Path>>,
-    /// The ids of all of the entries that were removed from the snapshot
-    /// as part of the current update. These entry ids may be re-used
-    /// if the same inode is discovered at a new path, or if the given
-    /// path is re-created after being deleted.
-    removed_entry_ids: HashMap<u64, ProjectEntryId>,
+    /// The ids of the entries that were removed from the snapshot, along with
+    /// the scan that removed them. These entry ids may be re-used if the same
+    /// inode is discovered at a new path, or if the given path is re-created
+    /// after being deleted. They're kept for a few scans after the one that
+    /// removed them, because the two halves of a rename can be reported in
+    /// separate batches of file system events.
+    removed_entry_ids: HashMap<u64, RemovedEntry>,
     changed_paths: Vec<Arc<Path>>,
     prev_snapshot: Snapshot,
 }
 
+struct RemovedEntry {
+    id: ProjectEntryId,
+    scan_id: usize,
+    mtime: SystemTime,
+    is_dir: bool,
+}
+
+impl RemovedEntry {
+    /// Whether the given entry is the one that was removed, now found at another
+    /// path. The file system recycles the inodes of deleted files, so once the scan
+    /// that removed the entry is over, the inode alone isn't enough to tell; the
+    /// entry must also have been left unmodified, as it is by a rename.
+    fn was_moved_to(&self, entry: &Entry, scan_id: usize) -> bool {
+        self.is_dir == entry.is_dir() && (self.scan_id == scan_id || self.mtime == entry.mtime)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LocalRepositoryEntry {
     pub(crate) git_dir_scan_id: usize,
@@ -330,12 +366,15 @@ impl Worktree {
                         ProjectSettings::get(Some((cx.handle().entity_id().as_u64() as usize, &Path::new(""))), cx).private_files.as_deref(),
                         "private_files",
                     );
+                    let new_external_symlinks = ProjectSettings::get(Some((cx.handle().entity_id().as_u64() as usize, &Path::new(""))), cx).external_symlinks;
 
                     if new_file_scan_exclusions != this.snapshot.file_scan_exclusions
                         || new_private_files != this.snapshot.private_files
+                        || new_external_symlinks != this.snapshot.external_symlinks
                     {
                         this.snapshot.file_scan_exclusions = new_file_scan_exclusions;
                         this.snapshot.private_files = new_private_files;
+                        this.snapshot.external_symlinks = new_external_symlinks;
 
                         log::info!(
                             "Re-scanning directories, new scan exclude files: {:?}, new dotenv files: {:?}",
@@ -386,6 +425,8 @@ impl Worktree {
                     ProjectSettings::get(Some((cx.handle().entity_id().as_u64() as usize, &Path::new(""))), cx).private_files.as_deref(),
                     "private_files",
                 ),
+                external_symlinks: ProjectSettings::get(Some((cx.handle().entity_id().as_u64() as usize, &Path::new(""))), cx).external_symlinks,
+                denied_to_guests: Vec::new(),
                 ignores_by_parent_abs_path: Default::default(),
                 git_repositories: Default::default(),
                 snapshot: Snapshot {
@@ -398,6 +439,7 @@ impl Worktree {
                     repository_entries: Default::default(),
                     scan_id: 1,
                     completed_scan_id: 0,
+                    loaded_directories: None,
                 },
             };
 
@@ -462,10 +504,12 @@ impl Worktree {
                 repository_entries: Default::default(),
                 scan_id: 1,
                 completed_scan_id: 0,
+                loaded_directories: None,
             };
 
             let (updates_tx, mut updates_rx) = mpsc::unbounded();
-            let background_snapshot = Arc::new(Mutex::new(snapshot.clone()));
+            let background_snapshot =
+                Arc::new(Mutex::new((snapshot.clone(), RemoteChanges::default())));
             let (mut snapshot_updated_tx, mut snapshot_updated_rx) = watch::channel();
 
             cx.background_executor()
@@ -473,10 +517,16 @@ impl Worktree {
                     let background_snapshot = background_snapshot.clone();
                     async move {
                         while let Some(update) = updates_rx.next().await {
-                            if let Err(error) =
-                                background_snapshot.lock().apply_remote_update(update)
                             {
-                                log::error!("error applying worktree update: {}", error);
+                                let (snapshot, changes) = &mut *background_snapshot.lock();
+                                match snapshot.apply_remote_update(update) {
+                                    Ok((entry_changes, repo_changes)) => {
+                                        changes.record(entry_changes, repo_changes)
+                                    }
+                                    Err(error) => {
+                                        log::error!("error applying worktree update: {}", error)
+                                    }
+                                }
                             }
                             snapshot_updated_tx.send(()).await.ok();
                         }
@@ -488,8 +538,15 @@ impl Worktree {
                 while (snapshot_updated_rx.recv().await).is_some() {
                     this.update(&mut cx, |this, cx| {
                         let this = this.as_remote_mut().unwrap();
-                        this.snapshot = this.background_snapshot.lock().clone();
-                        cx.emit(Event::UpdatedEntries(Arc::from([])));
+                        let (entry_changes, repo_changes) = {
+                            let (snapshot, changes) = &mut *this.background_snapshot.lock();
+                            this.snapshot = snapshot.clone();
+                            changes.take()
+                        };
+                        cx.emit(Event::UpdatedEntries(entry_changes));
+                        if !repo_changes.is_empty() {
+                            cx.emit(Event::UpdatedGitRepositories(repo_changes));
+                        }
                         cx.notify();
                         while let Some((scan_id, _)) = this.snapshot_subscriptions.front() {
                             if this.observed_snapshot(*scan_id) {
@@ -499,6 +556,17 @@ impl Worktree {
                                 break;
                             }
                         }
+                        for (path, tx) in mem::take(&mut this.directory_subscriptions) {
+                            let is_loaded = match &path {
+                                Some(path) => this.snapshot.is_directory_loaded(path),
+                                None => this.snapshot.loaded_directories.is_none(),
+                            };
+                            if is_loaded {
+                                let _ = tx.send(());
+                            } else {
+                                this.directory_subscriptions.push((path, tx));
+                            }
+                        }
                     })?;
                 }
                 anyhow::Ok(())
@@ -512,6 +580,7 @@ impl Worktree {
                 background_snapshot,
                 updates_tx: Some(updates_tx),
                 snapshot_subscriptions: Default::default(),
+                directory_subscriptions: Default::default(),
                 client: client.clone(),
                 diagnostic_summaries: Default::default(),
                 visible: worktree.visible,
@@ -715,7 +784,11 @@ impl LocalWorktree {
         path: &Path,
         cx: &mut ModelContext<Worktree>,
     ) -> Task<Result<Model<Buffer>>> {
-        let path = Arc::from(path);
+        // Collaborators may spell the path's file names differently from the file
+        // system, so open the file by the path of its entry.
+        let path = self
+            .entry_for_path(path)
+            .map_or_else(|| Arc::from(path), |entry| entry.path.clone());
         cx.spawn(move |this, mut cx| async move {
             let (file, contents, diff_base) = this
                 .update(&mut cx, |t, cx| t.as_local().unwrap().load(&path, cx))?
@@ -760,7 +833,7 @@ impl LocalWorktree {
                                 project_id: share.project_id,
                                 worktree_id,
                                 summary: Some(proto::DiagnosticSummary {
-                                    path: path.to_string_lossy().to_string(),
+                                    path: proto::path_to_proto(path),
                                     language_server_id: server_id.0 as u64,
                                     error_count: 0,
                                     warning_count: 0,
@@ -831,7 +904,7 @@ impl LocalWorktree {
                         project_id: share.project_id,
                         worktree_id: self.id().to_proto(),
                         summary: Some(proto::DiagnosticSummary {
-                            path: worktree_path.to_string_lossy().to_string(),
+                            path: proto::path_to_proto(&worktree_path),
                             language_server_id: server_id.0 as u64,
                             error_count: new_summary.error_count as u32,
                             warning_count: new_summary.warning_count as u32,
@@ -852,7 +925,10 @@ impl LocalWorktree {
     ) {
         let repo_changes = self.changed_repos(&self.snapshot, &new_snapshot);
 
+        // The background scanner doesn't know about the collaboration policy.
+        let denied_to_guests = mem::take(&mut self.snapshot.denied_to_guests);
         self.snapshot = new_snapshot;
+        self.snapshot.denied_to_guests = denied_to_guests;
 
         if let Some(share) = self.share.as_mut() {
             share
@@ -1075,6 +1151,29 @@ impl LocalWorktree {
         })
     }
 
+    /// Loads all of the worktree's entries from the host, if they are being loaded
+    /// lazily, so that they can all be searched.
+    pub fn load_all_entries(&mut self, cx: &mut ModelContext<Worktree>) -> Task<Result<()>> {
+        if self.snapshot.loaded_directories.is_none() {
+            return Task::ready(Ok(()));
+        }
+        if self.disconnected {
+            return Task::ready(Err(anyhow!("worktree was disconnected from host")));
+        }
+
+        let (tx, rx) = oneshot::channel();
+        self.directory_subscriptions.push((None, tx));
+        let request = self.client.request(proto::LoadWorktreeEntries {
+            project_id: self.project_id,
+            worktree_id: self.snapshot.id.to_proto(),
+        });
+        cx.background_executor().spawn(async move {
+            request.await?;
+            rx.await?;
+            Ok(())
+        })
+    }
+
     pub fn save_buffer(
         &self,
         buffer_handle: Model<Buffer>,
@@ -1188,11 +1287,16 @@ impl LocalWorktree {
         let abs_path = self.absolutize(&path);
         let fs = self.fs.clone();
         let write = cx.background_executor().spawn(async move {
+            let abs_path = abs_path?;
             if is_dir {
-                fs.create_dir(&abs_path?).await
+                fs.create_dir(&abs_path).await
             } else {
-                fs.save(&abs_path?, &Default::default(), Default::default())
-                    .await
+                // Never truncate a file that's already there, such as one created since
+                // the worktree was last scanned.
+                if let Some(parent) = abs_path.parent() {
+                    fs.create_dir(parent).await?;
+                }
+                fs.create_file(&abs_path, CreateOptions::default()).await
             }
         });
 
@@ -1477,19 +1581,18 @@ impl LocalWorktree {
         share_rx
     }
 
+    /// Keeps the paths matching the given globs from guests. The deny list is
+    /// applied to the entries sent to guests from the next time the worktree is
+    /// shared.
+    pub fn set_denied_to_guests(&mut self, globs: &[String]) {
+        self.snapshot.denied_to_guests = path_matchers(Some(globs), "deny");
+    }
+
     pub fn share(&mut self, project_id: u64, cx: &mut ModelContext<Worktree>) -> Task<Result<()>> {
         let client = self.client.clone();
 
-        for (path, summaries) in &self.diagnostic_summaries {
-            for (&server_id, summary) in summaries {
-                if let Err(e) = self.client.send(proto::UpdateDiagnosticSummary {
-                    project_id,
-                    worktree_id: cx.entity_id().as_u64(),
-                    summary: Some(summary.to_proto(server_id, path)),
-                }) {
-                    return Task::ready(Err(e));
-                }
-            }
+        if let Err(e) = self.send_diagnostic_summaries(project_id) {
+            return Task::ready(Err(e));
         }
 
         let rx = self.observe_updates(project_id, cx, move |update| {
@@ -1499,6 +1602,20 @@ impl LocalWorktree {
             .spawn(async move { rx.await.map_err(|_| anyhow!("share ended")) })
     }
 
+    /// Sends the server the summaries of the diagnostics in every path that has any.
+    pub fn send_diagnostic_summaries(&self, project_id: u64) -> Result<()> {
+        for (path, summaries) in &self.diagnostic_summaries {
+            for (&server_id, summary) in summaries {
+                self.client.send(proto::UpdateDiagnosticSummary {
+                    project_id,
+                    worktree_id: self.id().to_proto(),
+                    summary: Some(summary.to_proto(server_id, path)),
+                })?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn unshare(&mut self) {
         self.share.take();
     }
@@ -1516,9 +1633,40 @@ impl RemoteWorktree {
     pub fn disconnected_from_host(&mut self) {
         self.updates_tx.take();
         self.snapshot_subscriptions.clear();
+        self.directory_subscriptions.clear();
         self.disconnected = true;
     }
 
+    /// Loads the entries of the given directory and its ancestors from the host,
+    /// if the worktree's entries are being loaded lazily.
+    pub fn load_directory(
+        &mut self,
+        path: Arc<Path>,
+        cx: &mut ModelContext<Worktree>,
+    ) -> Task<Result<()>> {
+        if self.snapshot.is_directory_loaded(&path) {
+            return Task::ready(Ok(()));
+        }
+        if self.disconnected {
+            return Task::ready(Err(anyhow!("worktree was disconnected from host")));
+        }
+
+        // The server sends the directory's entries before responding, but they are
+        // applied asynchronously, so wait until they appear in the snapshot.
+        let (tx, rx) = oneshot::channel();
+        self.directory_subscriptions.push((Some(path.clone()), tx));
+        let request = self.client.request(proto::LoadWorktreeDirectory {
+            project_id: self.project_id,
+            worktree_id: self.snapshot.id.to_proto(),
+            path: proto::path_to_proto(&path),
+        });
+        cx.background_executor().spawn(async move {
+            request.await?;
+            rx.await?;
+            Ok(())
+        })
+    }
+
     pub fn save_buffer(
         &self,
         buffer_handle: Model<Buffer>,
@@ -1620,12 +1768,38 @@ impl RemoteWorktree {
         let wait_for_snapshot = self.wait_for_snapshot(scan_id);
         cx.spawn(|this, mut cx| async move {
             wait_for_snapshot.await?;
-            this.update(&mut cx, |worktree, _| {
+            this.update(&mut cx, |worktree, cx| {
                 let worktree = worktree.as_remote_mut().unwrap();
-                let mut snapshot = worktree.background_snapshot.lock();
-                let entry = snapshot.insert_entry(entry);
-                worktree.snapshot = snapshot.clone();
-                entry
+                let (entry, changes) = {
+                    let (snapshot, _) = &mut *worktree.background_snapshot.lock();
+                    let old_entry = snapshot
+                        .entry_for_id(ProjectEntryId::from_proto(entry.id))
+                        .cloned();
+                    let entry = snapshot.insert_entry(entry)?;
+                    worktree.snapshot = snapshot.clone();
+
+                    // The host's update for this entry won't change anything, so
+                    // report the change now, as the host's own worktree does.
+                    let mut changes = Vec::new();
+                    match old_entry {
+                        Some(old_entry) if old_entry.path == entry.path => {
+                            if old_entry != entry {
+                                changes.push((entry.path.clone(), entry.id, PathChange::Updated));
+                            }
+                        }
+                        old_entry => {
+                            if let Some(old_entry) = old_entry {
+                                changes.push((old_entry.path, old_entry.id, PathChange::Removed));
+                            }
+                            changes.push((entry.path.clone(), entry.id, PathChange::Added));
+                        }
+                    }
+                    (entry, changes)
+                };
+                if !changes.is_empty() {
+                    cx.emit(Event::UpdatedEntries(changes.into()));
+                }
+                Ok(entry)
             })?
         })
     }
@@ -1639,11 +1813,22 @@ impl RemoteWorktree {
         let wait_for_snapshot = self.wait_for_snapshot(scan_id);
         cx.spawn(move |this, mut cx| async move {
             wait_for_snapshot.await?;
-            this.update(&mut cx, |worktree, _| {
+            this.update(&mut cx, |worktree, cx| {
                 let worktree = worktree.as_remote_mut().unwrap();
-                let mut snapshot = worktree.background_snapshot.lock();
-                snapshot.delete_entry(id);
-                worktree.snapshot = snapshot.clone();
+                let removed_entries = {
+                    let (snapshot, _) = &mut *worktree.background_snapshot.lock();
+                    let removed_entries = snapshot.delete_entry(id);
+                    worktree.snapshot = snapshot.clone();
+                    removed_entries
+                };
+                if !removed_entries.is_empty() {
+                    cx.emit(Event::UpdatedEntries(
+                        removed_entries
+                            .into_iter()
+                            .map(|(path, id)| (path, id, PathChange::Removed))
+                            .collect(),
+                    ));
+                }
             })?;
             Ok(())
         })
@@ -1695,8 +1880,13 @@ impl Snapshot {
         Ok(entry)
     }
 
-    fn delete_entry(&mut self, entry_id: ProjectEntryId) -> Option<Arc<Path>> {
-        let removed_entry = self.entries_by_id.remove(&entry_id, &())?;
+    /// Deletes an entry along with its descendants, returning the paths and ids of
+    /// the entries that were deleted.
+    fn delete_entry(&mut self, entry_id: ProjectEntryId) -> Vec<(Arc<Path>, ProjectEntryId)> {
+        let Some(removed_entry) = self.entries_by_id.remove(&entry_id, &()) else {
+            return Vec::new();
+        };
+        let mut removed_entries = Vec::new();
         self.entries_by_path = {
             let mut cursor = self.entries_by_path.cursor::<TraversalProgress>();
             let mut new_entries_by_path =
@@ -1704,6 +1894,7 @@ impl Snapshot {
             while let Some(entry) = cursor.item() {
                 if entry.path.starts_with(&removed_entry.path) {
                     self.entries_by_id.remove(&entry.id, &());
+                    removed_entries.push((entry.path.clone(), entry.id));
                     cursor.next(&());
                 } else {
                     break;
@@ -1713,7 +1904,7 @@ impl Snapshot {
             new_entries_by_path
         };
 
-        Some(removed_entry.path)
+        removed_entries
     }
 
     #[cfg(any(test, feature = "test-support"))]
@@ -1724,26 +1915,84 @@ impl Snapshot {
             .and_then(|entry| entry.git_status)
     }
 
-    pub(crate) fn apply_remote_update(&mut self, mut update: proto::UpdateWorktree) -> Result<()> {
+    /// Applies an update from the host, returning the paths and repositories
+    /// that it changed.
+    pub(crate) fn apply_remote_update(
+        &mut self,
+        mut update: proto::UpdateWorktree,
+    ) -> Result<(
+        Vec<(Arc<Path>, ProjectEntryId, PathChange)>,
+        Vec<(Arc<Path>, GitRepositoryChange)>,
+    )> {
         let mut entries_by_path_edits = Vec::new();
         let mut entries_by_id_edits = Vec::new();
+        let mut entry_changes = Vec::new();
+        let mut repo_changes = Vec::new();
+        let is_initial_update = self.completed_scan_id == 0;
+
+        let newly_loaded_directories = mem::take(&mut update.loaded_directories)
+            .into_iter()
+            .map(|path| Arc::from(proto::path_from_proto(path)))
+            .collect::<Vec<Arc<Path>>>();
+        if !newly_loaded_directories.is_empty() {
+            let loaded_directories =
+                Arc::make_mut(self.loaded_directories.get_or_insert_with(Default::default));
+            loaded_directories.extend(newly_loaded_directories.iter().cloned());
+        }
+        if update.all_directories_loaded {
+            self.loaded_directories = None;
+        }
 
         for entry_id in update.removed_entries {
             let entry_id = ProjectEntryId::from_proto(entry_id);
             entries_by_id_edits.push(Edit::Remove(entry_id));
             if let Some(entry) = self.entry_for_id(entry_id) {
                 entries_by_path_edits.push(Edit::Remove(PathKey(entry.path.clone())));
+                entry_changes.push((entry.path.clone(), entry_id, PathChange::Removed));
             }
         }
 
         for entry in update.updated_entries {
-            let entry = Entry::try_from((&self.root_char_bag, entry))?;
+            let mut entry = Entry::try_from((&self.root_char_bag, entry))?;
+            if entry.kind == EntryKind::Dir && !self.is_directory_loaded(&entry.path) {
+                entry.kind = EntryKind::UnloadedDir;
+            }
+            match self.entry_for_id(entry.id) {
+                Some(old_entry) if old_entry.path == entry.path => {
+                    if *old_entry != entry {
+                        entry_changes.push((entry.path.clone(), entry.id, PathChange::Updated));
+                    }
+                }
+                old_entry => {
+                    if let Some(old_entry) = old_entry {
+                        entry_changes.push((
+                            old_entry.path.clone(),
+                            old_entry.id,
+                            PathChange::Removed,
+                        ));
+                    }
+                    let is_loading = is_initial_update
+                        || update.all_directories_loaded
+                        || entry.path.parent().map_or(false, |parent| {
+                            newly_loaded_directories
+                                .iter()
+                                .any(|directory| directory.as_ref() == parent)
+                        });
+                    let change = if is_loading {
+                        PathChange::Loaded
+                    } else {
+                        PathChange::Added
+                    };
+                    entry_changes.push((entry.path.clone(), entry.id, change));
+                }
+            }
             if let Some(PathEntry { path, .. }) = self.entries_by_id.get(&entry.id, &()) {
                 entries_by_path_edits.push(Edit::Remove(PathKey(path.clone())));
             }
             if let Some(old_entry) = self.entries_by_path.get(&PathKey(entry.path.clone()), &()) {
                 if old_entry.id != entry.id {
                     entries_by_id_edits.push(Edit::Remove(old_entry.id));
+                    entry_changes.push((old_entry.path.clone(), old_entry.id, PathChange::Removed));
                 }
             }
             entries_by_id_edits.push(Edit::Insert(PathEntry {
@@ -1758,12 +2007,34 @@ impl Snapshot {
         self.entries_by_path.edit(entries_by_path_edits, &());
         self.entries_by_id.edit(entries_by_id_edits, &());
 
+        // Directories that were replicated before their children were loaded.
+        let newly_loaded_entries = newly_loaded_directories
+            .iter()
+            .filter_map(|path| {
+                let entry = self.entry_for_path(path)?;
+                entry.is_unloaded().then(|| {
+                    entry_changes.push((entry.path.clone(), entry.id, PathChange::Updated));
+                    Edit::Insert(Entry {
+                        kind: EntryKind::Dir,
+                        ..entry.clone()
+                    })
+                })
+            })
+            .collect();
+        self.entries_by_path.edit(newly_loaded_entries, &());
+
         update.removed_repositories.sort_unstable();
-        self.repository_entries.retain(|_, entry| {
+        self.repository_entries.retain(|work_directory, entry| {
             if let Ok(_) = update
                 .removed_repositories
                 .binary_search(&entry.work_directory.to_proto())
             {
+                repo_changes.push((
+                    work_directory.0.clone(),
+                    GitRepositoryChange {
+                        old_repository: Some(entry.clone()),
+                    },
+                ));
                 false
             } else {
                 true
@@ -1776,16 +2047,26 @@ impl Snapshot {
 
             if let Some(entry) = self.entry_for_id(*work_directory_entry) {
                 let work_directory = RepositoryWorkDirectory(entry.path.clone());
-                if self.repository_entries.get(&work_directory).is_some() {
+                let old_repository = self.repository_entries.get(&work_directory).cloned();
+                let branch = repository.branch.map(Into::into);
+                if old_repository.as_ref().map(|repo| &repo.branch) != Some(&branch) {
+                    repo_changes.push((
+                        work_directory.0.clone(),
+                        GitRepositoryChange {
+                            old_repository: old_repository.clone(),
+                        },
+                    ));
+                }
+                if old_repository.is_some() {
                     self.repository_entries.update(&work_directory, |repo| {
-                        repo.branch = repository.branch.map(Into::into);
+                        repo.branch = branch;
                     });
                 } else {
                     self.repository_entries.insert(
                         work_directory,
                         RepositoryEntry {
                             work_directory: work_directory_entry,
-                            branch: repository.branch.map(Into::into),
+                            branch,
                         },
                     )
                 }
@@ -1799,7 +2080,7 @@ impl Snapshot {
             self.completed_scan_id = update.scan_id as usize;
         }
 
-        Ok(())
+        Ok((entry_changes, repo_changes))
     }
 
     pub fn file_count(&self) -> usize {
@@ -1980,6 +2261,22 @@ impl Snapshot {
             .map(|entry| &entry.path)
     }
 
+    /// Whether the children of the given directory have been replicated, which is
+    /// always the case unless this is a remote worktree whose entries are loaded lazily.
+    pub fn is_directory_loaded(&self, path: &Path) -> bool {
+        self.loaded_directories
+            .as_ref()
+            .map_or(true, |directories| directories.contains(path))
+    }
+
+    /// The directories whose children have been replicated, if the worktree's
+    /// entries are loaded lazily.
+    pub fn loaded_directories(&self) -> impl Iterator<Item = &Arc<Path>> {
+        self.loaded_directories
+            .iter()
+            .flat_map(|directories| directories.iter())
+    }
+
     fn child_entries<'a>(&'a self, parent_path: &'a Path) -> ChildEntriesIter<'a> {
         let mut cursor = self.entries_by_path.cursor();
         cursor.seek(&TraversalTarget::Path(parent_path), Bias::Right, &());
@@ -2051,6 +2348,45 @@ impl Snapshot {
                     None
                 }
             })
+            .or_else(|| self.entry_for_unnormalized_path(path))
+    }
+
+    /// Finds the entry whose file names only differ from the path's in their Unicode
+    /// normalization form, as when a path that's stored decomposed on the host is
+    /// received composed from a collaborator.
+    fn entry_for_unnormalized_path(&self, path: &Path) -> Option<&Entry> {
+        if path.to_str().map_or(true, str::is_ascii) {
+            return None;
+        }
+
+        let mut entry = self.root_entry()?;
+        for component in path.components() {
+            let name = Path::new(component.as_os_str());
+            entry = self.child_entries(&entry.path).find(|child| {
+                child.path.file_name().map_or(false, |child_name| {
+                    paths_equal_normalized(Path::new(child_name), name)
+                })
+            })?;
+        }
+        Some(entry)
+    }
+
+    /// Spells the path the way the longest of its ancestors that has an entry is
+    /// spelled in the file system, so that a path received from a collaborator
+    /// refers to the directories that already exist, rather than creating others
+    /// whose names only differ in their Unicode normalization form.
+    pub fn resolve_path_spelling(&self, path: &Path) -> Arc<Path> {
+        for ancestor in path.ancestors() {
+            if let Some(entry) = self.entry_for_path(ancestor) {
+                let suffix = path.strip_prefix(ancestor).unwrap();
+                if suffix.as_os_str().is_empty() {
+                    return entry.path.clone();
+                } else {
+                    return entry.path.join(suffix).into();
+                }
+            }
+        }
+        path.into()
     }
 
     pub fn entry_for_id(&self, id: ProjectEntryId) -> Option<&Entry> {
@@ -2088,9 +2424,11 @@ impl LocalSnapshot {
         let mut updated_repositories = Vec::new();
         let mut removed_repositories = Vec::new();
 
-        for (_, entry_id, path_change) in entry_changes.iter() {
+        for (path, entry_id, path_change) in entry_changes.iter() {
             if let PathChange::Removed = path_change {
                 removed_entries.push(entry_id.0 as u64);
+            } else if self.is_path_denied_to_guests(path) {
+                continue;
             } else if let Some(entry) = self.entry_for_id(*entry_id) {
                 updated_entries.push(proto::Entry::from(entry));
             }
@@ -2133,6 +2471,8 @@ impl LocalSnapshot {
             is_last_update: self.completed_scan_id == self.scan_id,
             updated_repositories,
             removed_repositories,
+            loaded_directories: Vec::new(),
+            all_directories_loaded: false,
         }
     }
 
@@ -2140,6 +2480,7 @@ impl LocalSnapshot {
         let mut updated_entries = self
             .entries_by_path
             .iter()
+            .filter(|entry| !self.is_path_denied_to_guests(&entry.path))
             .map(proto::Entry::from)
             .collect::<Vec<_>>();
         updated_entries.sort_unstable_by_key(|e| e.id);
@@ -2162,6 +2503,8 @@ impl LocalSnapshot {
             is_last_update: self.completed_scan_id == self.scan_id,
             updated_repositories,
             removed_repositories: Vec::new(),
+            loaded_directories: Vec::new(),
+            all_directories_loaded: false,
         }
     }
 
@@ -2346,6 +2689,14 @@ impl LocalSnapshot {
         })
     }
 
+    pub fn is_path_denied_to_guests(&self, path: &Path) -> bool {
+        path.ancestors().any(|ancestor| {
+            self.denied_to_guests
+                .iter()
+                .any(|deny_matcher| deny_matcher.is_match(&ancestor))
+        })
+    }
+
     pub fn is_path_excluded(&self, mut path: PathBuf) -> bool {
         loop {
             if self
@@ -2410,8 +2761,12 @@ impl BackgroundScannerState {
     }
 
     fn reuse_entry_id(&mut self, entry: &mut Entry) {
-        if let Some(removed_entry_id) = self.removed_entry_ids.remove(&entry.inode) {
-            entry.id = removed_entry_id;
+        let removed_entry = self
+            .removed_entry_ids
+            .remove(&entry.inode)
+            .filter(|removed_entry| removed_entry.was_moved_to(entry, self.snapshot.scan_id));
+        if let Some(removed_entry) = removed_entry {
+            entry.id = removed_entry.id;
         } else if let Some(existing_entry) = self.snapshot.entry_for_path(&entry.path) {
             entry.id = existing_entry.id;
         }
@@ -2503,12 +2858,23 @@ impl BackgroundScannerState {
         self.snapshot.entries_by_path = new_entries;
 
         let mut entries_by_id_edits = Vec::new();
+        let scan_id = self.snapshot.scan_id;
         for entry in removed_entries.cursor::<()>() {
-            let removed_entry_id = self
+            let removed_entry = self
                 .removed_entry_ids
                 .entry(entry.inode)
-                .or_insert(entry.id);
-            *removed_entry_id = cmp::max(*removed_entry_id, entry.id);
+                .or_insert(RemovedEntry {
+                    id: entry.id,
+                    scan_id,
+                    mtime: entry.mtime,
+                    is_dir: entry.is_dir(),
+                });
+            if entry.id >= removed_entry.id {
+                removed_entry.id = entry.id;
+                removed_entry.mtime = entry.mtime;
+                removed_entry.is_dir = entry.is_dir();
+            }
+            removed_entry.scan_id = scan_id;
             entries_by_id_edits.push(Edit::Remove(entry.id));
         }
         self.snapshot.entries_by_id.edit(entries_by_id_edits, &());
@@ -2866,7 +3232,7 @@ impl language::File for File {
         rpc::proto::File {
             worktree_id: self.worktree.entity_id().as_u64(),
             entry_id: self.entry_id.map(|id| id.to_proto()),
-            path: self.path.to_string_lossy().into(),
+            path: proto::path_to_proto(&self.path),
             mtime: Some(self.mtime.into()),
             is_deleted: self.is_deleted,
         }
@@ -2951,7 +3317,7 @@ impl File {
 
         Ok(Self {
             worktree,
-            path: Path::new(&proto.path).into(),
+            path: proto::path_from_proto(proto.path).into(),
             mtime: proto.mtime.ok_or_else(|| anyhow!("no timestamp"))?.into(),
             entry_id: proto.entry_id.map(ProjectEntryId::from_proto),
             is_local: false,
@@ -3037,6 +3403,51 @@ pub struct GitRepositoryChange {
 pub type UpdatedEntriesSet = Arc<[(Arc<Path>, ProjectEntryId, PathChange)]>;
 pub type UpdatedGitRepositoriesSet = Arc<[(Arc<Path>, GitRepositoryChange)]>;
 
+/// The changes that updates from the host have made to a remote worktree's
+/// background snapshot since they were last reported to observers. Several
+/// updates can arrive in between, so the changes to each path are combined.
+#[derive(Default)]
+struct RemoteChanges {
+    entries: BTreeMap<Arc<Path>, (ProjectEntryId, PathChange)>,
+    repositories: BTreeMap<Arc<Path>, GitRepositoryChange>,
+}
+
+impl RemoteChanges {
+    fn record(
+        &mut self,
+        entry_changes: Vec<(Arc<Path>, ProjectEntryId, PathChange)>,
+        repo_changes: Vec<(Arc<Path>, GitRepositoryChange)>,
+    ) {
+        for (path, entry_id, change) in entry_changes {
+            let change = match (self.entries.get(&path).map(|(_, change)| *change), change) {
+                (Some(PathChange::Added | PathChange::Loaded), PathChange::Removed) => {
+                    self.entries.remove(&path);
+                    continue;
+                }
+                (Some(previous @ (PathChange::Added | PathChange::Loaded)), _) => previous,
+                (Some(PathChange::Removed), PathChange::Removed) => PathChange::Removed,
+                (Some(PathChange::Removed), _) => PathChange::Updated,
+                (_, change) => change,
+            };
+            self.entries.insert(path, (entry_id, change));
+        }
+
+        // Observers care about the state of each repository before the first change.
+        for (path, change) in repo_changes {
+            self.repositories.entry(path).or_insert(change);
+        }
+    }
+
+    fn take(&mut self) -> (UpdatedEntriesSet, UpdatedGitRepositoriesSet) {
+        let entries = mem::take(&mut self.entries)
+            .into_iter()
+            .map(|(path, (entry_id, change))| (path, entry_id, change))
+            .collect();
+        let repositories = mem::take(&mut self.repositories).into_iter().collect();
+        (entries, repositories)
+    }
+}
+
 impl Entry {
     fn new(
         path: Arc<Path>,
@@ -3519,9 +3930,16 @@ impl BackgroundScanner {
                 state.reload_repositories(&dot_git_paths_to_reload, self.fs.as_ref());
             }
             state.snapshot.completed_scan_id = state.snapshot.scan_id;
-            for (_, entry_id) in mem::take(&mut state.removed_entry_ids) {
-                state.scanned_dirs.remove(&entry_id);
-            }
+            let BackgroundScannerState {
+                snapshot,
+                scanned_dirs,
+                removed_entry_ids,
+                ..
+            } = &mut *state;
+            removed_entry_ids.retain(|_, removed_entry| {
+                scanned_dirs.remove(&removed_entry.id);
+                snapshot.scan_id - removed_entry.scan_id < REMOVED_ENTRY_ID_RETENTION_SCANS
+            });
         }
 
         self.send_status_update(false, None);
@@ -3653,10 +4071,12 @@ impl BackgroundScanner {
         let mut new_ignore;
         let root_char_bag;
         let next_entry_id;
+        let external_symlinks;
         {
             let state = self.state.lock();
             let snapshot = &state.snapshot;
             root_abs_path = snapshot.abs_path().clone();
+            external_symlinks = snapshot.external_symlinks;
             if snapshot.is_path_excluded(job.path.to_path_buf()) {
                 log::error!("skipping excluded directory {:?}", job.path);
                 return Ok(());
@@ -3783,7 +4203,15 @@ impl BackgroundScanner {
                 };
 
                 if !canonical_path.starts_with(root_canonical_path) {
-                    child_entry.is_external = true;
+                    match external_symlinks {
+                        ExternalSymlinks::Lazy => child_entry.is_external = true,
+                        ExternalSymlinks::Follow => {}
+                        ExternalSymlinks::Ignore => {
+                            log::debug!("skipping external symlink {child_path:?}");
+                            self.state.lock().remove_path(&child_path);
+                            continue;
+                        }
+                    }
                 }
             }
 
@@ -3954,7 +4382,16 @@ impl BackgroundScanner {
                     );
                     let is_dir = fs_entry.is_dir();
                     fs_entry.is_ignored = ignore_stack.is_abs_path_ignored(&abs_path, is_dir);
-                    fs_entry.is_external = !canonical_path.starts_with(&root_canonical_path);
+                    if !canonical_path.starts_with(&root_canonical_path) {
+                        match state.snapshot.external_symlinks {
+                            ExternalSymlinks::Lazy => fs_entry.is_external = true,
+                            ExternalSymlinks::Follow => {}
+                            ExternalSymlinks::Ignore => {
+                                state.remove_path(path);
+                                continue;
+                            }
+                        }
+                    }
                     fs_entry.is_private = state.snapshot.is_path_private(path);
 
                     if !is_dir && !fs_entry.is_ignored && !fs_entry.is_external {
@@ -4604,7 +5041,7 @@ impl<'a> From<&'a Entry> for proto::Entry {
         Self {
             id: entry.id.to_proto(),
             is_dir: entry.is_dir(),
-            path: entry.path.to_string_lossy().into(),
+            path: proto::path_to_proto(&entry.path),
             inode: entry.inode,
             mtime: Some(entry.mtime.into()),
             is_symlink: entry.is_symlink,
@@ -4627,7 +5064,7 @@ impl<'a> TryFrom<(&'a CharBag, proto::Entry)> for Entry {
                 char_bag.extend(entry.path.chars().map(|c| c.to_ascii_lowercase()));
                 EntryKind::File(char_bag)
             };
-            let path: Arc<Path> = PathBuf::from(entry.path).into();
+            let path: Arc<Path> = proto::path_from_proto(entry.path).into();
             Ok(Entry {
                 id: ProjectEntryId::from_proto(entry.id),
                 kind,
@@ -1,5 +1,5 @@
 use crate::{
-    project_settings::ProjectSettings,
+    project_settings::{ExternalSymlinks, ProjectSettings},
     worktree::{Event, Snapshot, WorktreeModelHandle},
     Entry, EntryKind, PathChange, Project, Worktree,
 };
@@ -12,6 +12,7 @@ use parking_lot::Mutex;
 use postage::stream::Stream;
 use pretty_assertions::assert_eq;
 use rand::prelude::*;
+use rpc::proto;
 use serde_json::json;
 use settings::SettingsStore;
 use std::{
@@ -444,6 +445,117 @@ async fn test_symlinks_pointing_outside(cx: &mut TestAppContext) {
     );
 }
 
+#[gpui::test]
+async fn test_following_and_ignoring_external_symlinks(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.update(|cx| {
+        cx.update_global::<SettingsStore, _>(|store, cx| {
+            store.update_user_settings::<ProjectSettings>(cx, |project_settings| {
+                project_settings.external_symlinks = ExternalSymlinks::Follow;
+            });
+        });
+    });
+    let fs = FakeFs::new(cx.background_executor.clone());
+    fs.insert_tree(
+        "/root",
+        json!({
+            "app": {
+                "vendor": {
+                    // symlinks here
+                },
+                "main.rs": "",
+            },
+            "lib": {
+                ".git": {},
+                "src": {
+                    "lib.rs": "",
+                },
+            },
+        }),
+    )
+    .await;
+
+    // This symlink points to another repository, outside of the worktree's root.
+    fs.insert_symlink("/root/app/vendor/lib", "../../lib".into())
+        .await;
+
+    let tree = Worktree::local(
+        build_client(cx),
+        Path::new("/root/app"),
+        true,
+        fs.clone(),
+        Default::default(),
+        &mut cx.to_async(),
+    )
+    .await
+    .unwrap();
+
+    let remote_snapshot = tree.update(cx, |tree, cx| {
+        let tree = tree.as_local_mut().unwrap();
+        let snapshot = Arc::new(Mutex::new(tree.snapshot()));
+        let _ = tree.observe_updates(0, cx, {
+            let snapshot = snapshot.clone();
+            move |update| {
+                snapshot.lock().apply_remote_update(update).unwrap();
+                async { true }
+            }
+        });
+        snapshot
+    });
+
+    cx.read(|cx| tree.read(cx).as_local().unwrap().scan_complete())
+        .await;
+    cx.executor().run_until_parked();
+
+    // The symlinked repository is scanned as part of the worktree.
+    tree.read_with(cx, |tree, _| {
+        assert_eq!(
+            tree.entries(true)
+                .map(|entry| (entry.path.as_ref(), entry.is_external))
+                .collect::<Vec<_>>(),
+            vec![
+                (Path::new(""), false),
+                (Path::new("main.rs"), false),
+                (Path::new("vendor"), false),
+                (Path::new("vendor/lib"), false),
+                (Path::new("vendor/lib/src"), false),
+                (Path::new("vendor/lib/src/lib.rs"), false),
+            ]
+        );
+        assert_eq!(tree.visible_file_count(), 2);
+        assert_eq!(
+            remote_snapshot.lock().entries(true).collect::<Vec<_>>(),
+            tree.entries(true).collect::<Vec<_>>()
+        );
+    });
+
+    cx.update(|cx| {
+        cx.update_global::<SettingsStore, _>(|store, cx| {
+            store.update_user_settings::<ProjectSettings>(cx, |project_settings| {
+                project_settings.external_symlinks = ExternalSymlinks::Ignore;
+            });
+        });
+    });
+    tree.flush_fs_events(cx).await;
+    cx.read(|cx| tree.read(cx).as_local().unwrap().scan_complete())
+        .await;
+    cx.executor().run_until_parked();
+
+    // The symlinked repository is left out of the worktree, for guests too.
+    tree.read_with(cx, |tree, _| {
+        assert_eq!(
+            tree.entries(true)
+                .map(|entry| entry.path.as_ref())
+                .collect::<Vec<_>>(),
+            vec![Path::new(""), Path::new("main.rs"), Path::new("vendor")]
+        );
+        assert_eq!(
+            remote_snapshot.lock().entries(true).collect::<Vec<_>>(),
+            tree.entries(true).collect::<Vec<_>>()
+        );
+    });
+}
+
 #[cfg(target_os = "macos")]
 #[gpui::test]
 async fn test_renaming_case_only(cx: &mut TestAppContext) {
@@ -1378,6 +1490,380 @@ async fn test_create_dir_all_on_create_entry(cx: &mut TestAppContext) {
     });
 }
 
+#[gpui::test]
+async fn test_create_entry_never_truncates_files(cx: &mut TestAppContext) {
+    init_test(cx);
+    let client = cx.update(|cx| Client::new(FakeHttpClient::with_404_response(), cx));
+    let fs = FakeFs::new(cx.background_executor.clone());
+    fs.insert_tree("/root", json!({ "a.txt": "contents" }))
+        .await;
+
+    let tree = Worktree::local(
+        client,
+        "/root".as_ref(),
+        true,
+        fs.clone(),
+        Default::default(),
+        &mut cx.to_async(),
+    )
+    .await
+    .unwrap();
+    cx.executor().run_until_parked();
+
+    tree.update(cx, |tree, cx| {
+        tree.as_local_mut()
+            .unwrap()
+            .create_entry("a.txt".as_ref(), false, cx)
+    })
+    .await
+    .unwrap_err();
+    assert_eq!(
+        fs.load("/root/a.txt".as_ref()).await.unwrap(),
+        "contents".to_string()
+    );
+}
+
+#[gpui::test]
+async fn test_replicated_paths_use_proto_separator(cx: &mut TestAppContext) {
+    init_test(cx);
+    let fs = FakeFs::new(cx.background_executor.clone());
+    fs.insert_tree(
+        "/root",
+        json!({
+            "Src": {
+                "Module": {
+                    "lib.rs": "",
+                },
+                "main.rs": "",
+            },
+        }),
+    )
+    .await;
+
+    let tree = Worktree::local(
+        build_client(cx),
+        Path::new("/root"),
+        true,
+        fs.clone(),
+        Default::default(),
+        &mut cx.to_async(),
+    )
+    .await
+    .unwrap();
+    cx.read(|cx| tree.read(cx).as_local().unwrap().scan_complete())
+        .await;
+
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    let metadata = tree.update(cx, |tree, cx| {
+        let tree = tree.as_local_mut().unwrap();
+        let _ = tree.observe_updates(0, cx, {
+            let updates = updates.clone();
+            move |update| {
+                updates.lock().push(update);
+                async { true }
+            }
+        });
+        tree.metadata_proto()
+    });
+    let remote = cx.update(|cx| Worktree::remote(1, 1, metadata, build_client(cx), cx));
+    cx.executor().run_until_parked();
+
+    // Paths are sent with the same separator and case on every platform...
+    let updates = mem::take(&mut *updates.lock());
+    let mut proto_paths = updates
+        .iter()
+        .flat_map(|update| {
+            update
+                .updated_entries
+                .iter()
+                .map(|entry| entry.path.clone())
+        })
+        .collect::<Vec<_>>();
+    proto_paths.sort();
+    assert_eq!(
+        proto_paths,
+        ["", "Src", "Src/Module", "Src/Module/lib.rs", "Src/main.rs"]
+    );
+
+    // ...and converted back to native paths when they're received.
+    remote.update(cx, |remote, _| {
+        for update in updates {
+            remote.as_remote_mut().unwrap().update_from_remote(update);
+        }
+    });
+    cx.executor().run_until_parked();
+    assert_eq!(
+        remote.read_with(cx, |remote, _| remote.paths().cloned().collect::<Vec<_>>()),
+        [
+            PathBuf::from("Src"),
+            Path::new("Src").join("Module"),
+            Path::new("Src").join("Module").join("lib.rs"),
+            Path::new("Src").join("main.rs"),
+        ]
+        .into_iter()
+        .map(Arc::from)
+        .collect::<Vec<Arc<Path>>>()
+    );
+}
+
+#[gpui::test]
+async fn test_replicated_paths_use_composed_unicode(cx: &mut TestAppContext) {
+    init_test(cx);
+    let fs = FakeFs::new(cx.background_executor.clone());
+    // The file names are decomposed, the way macOS stores them.
+    fs.insert_tree(
+        "/root",
+        json!({
+            "cafe\u{301}": {
+                "nai\u{308}ve.txt": "",
+            },
+        }),
+    )
+    .await;
+
+    let tree = Worktree::local(
+        build_client(cx),
+        Path::new("/root"),
+        true,
+        fs.clone(),
+        Default::default(),
+        &mut cx.to_async(),
+    )
+    .await
+    .unwrap();
+    cx.read(|cx| tree.read(cx).as_local().unwrap().scan_complete())
+        .await;
+
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    let metadata = tree.update(cx, |tree, cx| {
+        let tree = tree.as_local_mut().unwrap();
+        let _ = tree.observe_updates(0, cx, {
+            let updates = updates.clone();
+            move |update| {
+                updates.lock().push(update);
+                async { true }
+            }
+        });
+        tree.metadata_proto()
+    });
+    let remote = cx.update(|cx| Worktree::remote(1, 1, metadata, build_client(cx), cx));
+    cx.executor().run_until_parked();
+    remote.update(cx, |remote, _| {
+        for update in mem::take(&mut *updates.lock()) {
+            remote.as_remote_mut().unwrap().update_from_remote(update);
+        }
+    });
+    cx.executor().run_until_parked();
+
+    // Collaborators receive the names composed...
+    let composed_path = Path::new("caf\u{e9}").join("na\u{ef}ve.txt");
+    assert_eq!(
+        remote.read_with(cx, |remote, _| remote.paths().cloned().collect::<Vec<_>>()),
+        [PathBuf::from("caf\u{e9}"), composed_path.clone()]
+            .into_iter()
+            .map(Arc::from)
+            .collect::<Vec<Arc<Path>>>()
+    );
+
+    // ...and the host finds its entries by the names that collaborators send.
+    tree.read_with(cx, |tree, _| {
+        let entry = tree.entry_for_path(&composed_path).unwrap();
+        assert_eq!(
+            entry.path.as_ref(),
+            Path::new("cafe\u{301}").join("nai\u{308}ve.txt")
+        );
+        assert!(tree.entry_for_path("caf\u{e9}/other.txt").is_none());
+    });
+
+    // Entries that collaborators create are created in the directories that exist.
+    let entry = tree
+        .update(cx, |tree, cx| {
+            let tree = tree.as_local_mut().unwrap();
+            let path = tree.resolve_path_spelling(Path::new("caf\u{e9}/other.txt"));
+            tree.create_entry(path, false, cx)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        entry.path.as_ref(),
+        Path::new("cafe\u{301}").join("other.txt")
+    );
+    assert_eq!(
+        fs.directories(false),
+        [
+            PathBuf::from("/"),
+            PathBuf::from("/root"),
+            PathBuf::from("/root/cafe\u{301}")
+        ]
+    );
+}
+
+#[gpui::test]
+async fn test_remote_worktree_change_events(cx: &mut TestAppContext) {
+    init_test(cx);
+    let fs = FakeFs::new(cx.background_executor.clone());
+    fs.insert_tree(
+        "/root",
+        json!({
+            "a": {
+                "file1": "",
+                "file2": "",
+            },
+            "b": {
+                "file3": "",
+            },
+        }),
+    )
+    .await;
+
+    let tree = Worktree::local(
+        build_client(cx),
+        Path::new("/root"),
+        true,
+        fs.clone(),
+        Default::default(),
+        &mut cx.to_async(),
+    )
+    .await
+    .unwrap();
+    cx.read(|cx| tree.read(cx).as_local().unwrap().scan_complete())
+        .await;
+
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    let metadata = tree.update(cx, |tree, cx| {
+        let tree = tree.as_local_mut().unwrap();
+        let _ = tree.observe_updates(0, cx, {
+            let updates = updates.clone();
+            move |update| {
+                updates.lock().push(update);
+                async { true }
+            }
+        });
+        tree.metadata_proto()
+    });
+    let remote = cx.update(|cx| Worktree::remote(1, 1, metadata, build_client(cx), cx));
+
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    remote.update(cx, |remote, cx| {
+        check_worktree_change_events(remote, cx);
+        cx.subscribe(&cx.handle(), {
+            let changes = changes.clone();
+            move |_, _, event, _| {
+                if let Event::UpdatedEntries(event_changes) = event {
+                    changes.lock().extend(event_changes.iter().cloned());
+                }
+            }
+        })
+        .detach();
+    });
+    let sync_remote = |cx: &mut TestAppContext| {
+        cx.executor().run_until_parked();
+        remote.update(cx, |remote, _| {
+            for update in updates.lock().drain(..) {
+                remote.as_remote_mut().unwrap().update_from_remote(update);
+            }
+        });
+        cx.executor().run_until_parked();
+        assert_eq!(
+            remote.read_with(cx, |remote, _| remote.paths().cloned().collect::<Vec<_>>()),
+            tree.read_with(cx, |tree, _| tree.paths().cloned().collect::<Vec<_>>())
+        );
+        let mut changes = mem::take(&mut *changes.lock());
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        changes
+            .into_iter()
+            .map(|(path, _, change)| (path.to_str().unwrap().to_string(), change))
+            .collect::<Vec<_>>()
+    };
+
+    // The initial replication reports every entry as loaded.
+    assert_eq!(
+        sync_remote(cx),
+        [
+            ("".to_string(), PathChange::Loaded),
+            ("a".to_string(), PathChange::Loaded),
+            ("a/file1".to_string(), PathChange::Loaded),
+            ("a/file2".to_string(), PathChange::Loaded),
+            ("b".to_string(), PathChange::Loaded),
+            ("b/file3".to_string(), PathChange::Loaded),
+        ]
+    );
+
+    // Later updates report the entries that the host added, removed and moved.
+    fs.rename(
+        "/root/a/file1".as_ref(),
+        "/root/b/file1".as_ref(),
+        Default::default(),
+    )
+    .await
+    .unwrap();
+    fs.remove_file("/root/b/file3".as_ref(), Default::default())
+        .await
+        .unwrap();
+    fs.create_dir("/root/c".as_ref()).await.unwrap();
+    fs.insert_file("/root/c/file4", "".into()).await;
+    let mut changes = sync_remote(cx);
+    // The mtimes of the directories containing the changes may have been updated too.
+    changes.retain(|(_, change)| *change != PathChange::Updated);
+    assert_eq!(
+        changes,
+        [
+            ("a/file1".to_string(), PathChange::Removed),
+            ("b/file1".to_string(), PathChange::Added),
+            ("b/file3".to_string(), PathChange::Removed),
+            ("c".to_string(), PathChange::Added),
+            ("c/file4".to_string(), PathChange::Added),
+        ]
+    );
+
+    // Entries that the guest creates or deletes itself are reported when it applies
+    // them, even if the host's update arrives afterwards.
+    let entry = tree
+        .update(cx, |tree, cx| {
+            tree.as_local_mut()
+                .unwrap()
+                .create_entry(Path::new("c/file5"), false, cx)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let scan_id = remote.read_with(cx, |remote, _| remote.completed_scan_id());
+    remote
+        .update(cx, |remote, cx| {
+            remote
+                .as_remote_mut()
+                .unwrap()
+                .insert_entry(proto::Entry::from(&entry), scan_id, cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        sync_remote(cx),
+        [("c/file5".to_string(), PathChange::Added)]
+    );
+
+    tree.update(cx, |tree, cx| {
+        tree.as_local_mut().unwrap().delete_entry(entry.id, cx)
+    })
+    .unwrap()
+    .await
+    .unwrap();
+    let scan_id = remote.read_with(cx, |remote, _| remote.completed_scan_id());
+    remote
+        .update(cx, |remote, cx| {
+            remote
+                .as_remote_mut()
+                .unwrap()
+                .delete_entry(entry.id, scan_id, cx)
+        })
+        .await
+        .unwrap();
+    let mut changes = sync_remote(cx);
+    changes.retain(|(_, change)| *change != PathChange::Updated);
+    assert_eq!(changes, [("c/file5".to_string(), PathChange::Removed)]);
+}
+
 #[gpui::test(iterations = 100)]
 async fn test_random_worktree_operations_during_initial_scan(
     cx: &mut TestAppContext,
@@ -1484,6 +1970,7 @@ async fn test_random_worktree_changes(cx: &mut TestAppContext, mut rng: StdRng)
     let root_dir = Path::new("/test");
     let fs = FakeFs::new(cx.background_executor.clone()) as Arc<dyn Fs>;
     fs.as_fake().insert_tree(root_dir, json!({})).await;
+    fs.as_fake().randomize_event_delivery();
     for _ in 0..initial_entries {
         randomly_mutate_fs(&fs, root_dir, 1.0, &mut rng).await;
     }
@@ -14,6 +14,10 @@ struct TypeConfig {
 #[derive(Deserialize, Debug)]
 pub struct FileAssociations {
     suffixes: HashMap<String, String>,
+    /// The types of files whose suffixes aren't associated with one, by the name of
+    /// the language they're detected as.
+    #[serde(default)]
+    languages: HashMap<String, String>,
     types: HashMap<String, TypeConfig>,
 }
 
@@ -39,15 +43,18 @@ impl FileAssociations {
             })
             .unwrap_or_else(|_| FileAssociations {
                 suffixes: HashMap::default(),
+                languages: HashMap::default(),
                 types: HashMap::default(),
             })
     }
 
-    pub fn get_icon(path: &Path, cx: &AppContext) -> Option<Arc<str>> {
+    /// The icon of a file, from its suffix or else the language it's detected as,
+    /// which guests are told by the host.
+    pub fn get_icon(path: &Path, language: Option<&str>, cx: &AppContext) -> Option<Arc<str>> {
         let this = cx.try_global::<Self>()?;
 
-        // FIXME: Associate a type with the languages and have the file's language
-        //        override these associations
+        // Suffixes are checked first, as they can tell apart files of the same
+        // language, such as ESLint configs.
         maybe!({
             let suffix = path.icon_suffix()?;
 
@@ -56,6 +63,12 @@ impl FileAssociations {
                 .and_then(|type_str| this.types.get(type_str))
                 .map(|type_config| type_config.icon.clone())
         })
+        .or_else(|| {
+            this.languages
+                .get(language?)
+                .and_then(|type_str| this.types.get(type_str))
+                .map(|type_config| type_config.icon.clone())
+        })
         .or_else(|| this.types.get("default").map(|config| config.icon.clone()))
     }
 
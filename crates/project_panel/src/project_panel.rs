@@ -97,7 +97,7 @@ pub struct EntryDetails {
     is_processing: bool,
     is_cut: bool,
     git_status: Option<GitFileStatus>,
-    is_dotenv: bool,
+    is_private: bool,
 }
 
 actions!(
@@ -791,13 +791,30 @@ impl ProjectPanel {
     fn delete(&mut self, _: &Delete, cx: &mut ViewContext<Self>) {
         maybe!({
             let Selection { entry_id, .. } = self.selection?;
-            let path = self.project.read(cx).path_for_entry(entry_id, cx)?.path;
-            let file_name = path.file_name()?;
+            let project = self.project.read(cx);
+            let project_path = project.path_for_entry(entry_id, cx)?;
+            let file_name = project_path.path.file_name()?;
+
+            // Warn the host before deleting files that guests are still editing.
+            let user_store = project.user_store().read(cx);
+            let guest_names = project
+                .guests_with_open_buffers_in(&project_path)
+                .into_iter()
+                .filter_map(|guest| {
+                    let user = user_store.get_cached_user(guest.user_id)?;
+                    Some(format!("@{}", user.github_login))
+                })
+                .collect::<Vec<_>>();
+            let detail = match guest_names.as_slice() {
+                [] => None,
+                [name] => Some(format!("{name} still has it open.")),
+                names => Some(format!("{} still have it open.", names.join(", "))),
+            };
 
             let answer = cx.prompt(
                 PromptLevel::Info,
                 &format!("Delete {file_name:?}?"),
-                None,
+                detail.as_deref(),
                 &["Delete", "Cancel"],
             );
 
@@ -1277,10 +1294,21 @@ impl ProjectPanel {
                 for entry in visible_worktree_entries[entry_range].iter() {
                     let status = git_status_setting.then(|| entry.git_status).flatten();
                     let is_expanded = expanded_entry_ids.binary_search(&entry.id).is_ok();
+                    // Guests are told how the host decorates its entries, as they
+                    // may not know the languages or private files of the host.
+                    let decoration = self
+                        .project
+                        .read(cx)
+                        .entry_decoration(snapshot.id(), entry.id, cx)
+                        .unwrap_or_default();
                     let icon = match entry.kind {
                         EntryKind::File(_) => {
                             if show_file_icons {
-                                FileAssociations::get_icon(&entry.path, cx)
+                                FileAssociations::get_icon(
+                                    &entry.path,
+                                    decoration.language.as_deref(),
+                                    cx,
+                                )
                             } else {
                                 None
                             }
@@ -1316,7 +1344,7 @@ impl ProjectPanel {
                             .clipboard_entry
                             .map_or(false, |e| e.is_cut() && e.entry_id() == entry.id),
                         git_status: status,
-                        is_dotenv: entry.is_private,
+                        is_private: entry.is_private || decoration.is_private,
                     };
 
                     if let Some(edit_state) = &self.edit_state {
@@ -1379,6 +1407,7 @@ impl ProjectPanel {
 
         let file_name = details.filename.clone();
         let icon = details.icon.clone();
+        let is_private = details.is_private;
         let depth = details.depth;
         div()
             .id(entry_id.to_proto() as usize)
@@ -1413,6 +1442,15 @@ impl ProjectPanel {
                         }
                         .ml_1(),
                     )
+                    .when(is_private && !show_editor, |this| {
+                        this.child(
+                            div().ml_1().child(
+                                Icon::new(IconName::FileLock)
+                                    .size(IconSize::XSmall)
+                                    .color(Color::Muted),
+                            ),
+                        )
+                    })
                     .on_click(cx.listener(move |this, event: &gpui::ClickEvent, cx| {
                         if event.down.button == MouseButton::Right {
                             return;
@@ -7,7 +7,7 @@ use gpui::{
 use ordered_float::OrderedFloat;
 use picker::{Picker, PickerDelegate};
 use project::{Project, Symbol};
-use std::{borrow::Cow, cmp::Reverse, sync::Arc};
+use std::{borrow::Cow, sync::Arc};
 use theme::ActiveTheme;
 use util::ResultExt;
 use workspace::{
@@ -78,16 +78,26 @@ impl ProjectSymbolsDelegate {
             &Default::default(),
             cx.background_executor().clone(),
         ));
-        let sort_key_for_match = |mat: &StringMatch| {
-            let symbol = &self.symbols[mat.candidate_id];
-            (
-                Reverse(OrderedFloat(mat.score)),
-                &symbol.label.text[symbol.label.filter_range.clone()],
-            )
+        // Break ties between equally good matches the same way on every machine,
+        // so that symbols with the same name are always listed in the same order.
+        let collation = self.project.read(cx).client().collation();
+        let compare_matches = |a: &StringMatch, b: &StringMatch| {
+            let a_symbol = &self.symbols[a.candidate_id];
+            let b_symbol = &self.symbols[b.candidate_id];
+            OrderedFloat(b.score)
+                .cmp(&OrderedFloat(a.score))
+                .then_with(|| {
+                    collation.compare(
+                        &a_symbol.label.text[a_symbol.label.filter_range.clone()],
+                        &b_symbol.label.text[b_symbol.label.filter_range.clone()],
+                    )
+                })
+                .then_with(|| a_symbol.path.path.cmp(&b_symbol.path.path))
+                .then_with(|| a_symbol.range.start.cmp(&b_symbol.range.start))
         };
 
-        visible_matches.sort_unstable_by_key(sort_key_for_match);
-        external_matches.sort_unstable_by_key(sort_key_for_match);
+        visible_matches.sort_unstable_by(compare_matches);
+        external_matches.sort_unstable_by(compare_matches);
         let mut matches = visible_matches;
         matches.append(&mut external_matches);
 
@@ -0,0 +1,150 @@
+use crate::proto::{self, envelope::Payload};
+use prost::Message as _;
+use std::{
+    array,
+    sync::atomic::{AtomicU64, Ordering::SeqCst},
+};
+
+/// A coarse grouping of messages, used to attribute bandwidth to features.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MessageCategory {
+    BufferOperations,
+    WorktreeSync,
+    Search,
+    MediaSignaling,
+    Other,
+}
+
+impl MessageCategory {
+    pub const ALL: [Self; 5] = [
+        Self::BufferOperations,
+        Self::WorktreeSync,
+        Self::Search,
+        Self::MediaSignaling,
+        Self::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::BufferOperations => "buffer_operations",
+            Self::WorktreeSync => "worktree_sync",
+            Self::Search => "search",
+            Self::MediaSignaling => "media_signaling",
+            Self::Other => "other",
+        }
+    }
+
+    pub fn of(envelope: &proto::Envelope) -> Self {
+        match &envelope.payload {
+            Some(
+                Payload::UpdateBuffer(_)
+                | Payload::UpdateBufferFile(_)
+                | Payload::CreateBufferForPeer(_)
+                | Payload::OpenBufferById(_)
+                | Payload::OpenBufferByPath(_)
+                | Payload::OpenBufferResponse(_)
+                | Payload::SynchronizeBuffers(_)
+                | Payload::SynchronizeBuffersResponse(_)
+                | Payload::BufferSaved(_)
+                | Payload::BufferReloaded(_)
+                | Payload::UpdateDiffBase(_)
+                | Payload::JoinChannelBuffer(_)
+                | Payload::JoinChannelBufferResponse(_)
+                | Payload::UpdateChannelBuffer(_)
+                | Payload::AckBufferOperation(_),
+            ) => Self::BufferOperations,
+            Some(
+                Payload::ShareProject(_)
+                | Payload::JoinProject(_)
+                | Payload::JoinProjectResponse(_)
+                | Payload::UpdateProject(_)
+                | Payload::UpdateWorktree(_)
+                | Payload::UpdateWorktreeSettings(_)
+                | Payload::UpdateDiagnosticSummary(_)
+                | Payload::StartLanguageServer(_)
+                | Payload::UpdateLanguageServer(_),
+            ) => Self::WorktreeSync,
+            Some(
+                Payload::SearchProject(_)
+                | Payload::SearchProjectResponse(_)
+                | Payload::GetProjectSymbols(_)
+                | Payload::GetProjectSymbolsResponse(_),
+            ) => Self::Search,
+            Some(
+                Payload::CreateRoom(_)
+                | Payload::CreateRoomResponse(_)
+                | Payload::JoinRoom(_)
+                | Payload::JoinRoomResponse(_)
+                | Payload::RejoinRoom(_)
+                | Payload::RejoinRoomResponse(_)
+                | Payload::RoomUpdated(_)
+                | Payload::Call(_)
+                | Payload::IncomingCall(_)
+                | Payload::CallCanceled(_)
+                | Payload::UpdateParticipantLocation(_)
+                | Payload::UpdateScreenAnnotations(_)
+                | Payload::RelayedAudioFrame(_),
+            ) => Self::MediaSignaling,
+            _ => Self::Other,
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// The number of bytes sent and received, broken down by [`MessageCategory`].
+///
+/// Sizes are those of the encoded messages, before compression.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BandwidthUsage {
+    sent: [u64; MessageCategory::ALL.len()],
+    received: [u64; MessageCategory::ALL.len()],
+}
+
+impl BandwidthUsage {
+    pub fn bytes_sent(&self, category: MessageCategory) -> u64 {
+        self.sent[category.index()]
+    }
+
+    pub fn bytes_received(&self, category: MessageCategory) -> u64 {
+        self.received[category.index()]
+    }
+
+    pub fn total_bytes_sent(&self) -> u64 {
+        self.sent.iter().sum()
+    }
+
+    pub fn total_bytes_received(&self) -> u64 {
+        self.received.iter().sum()
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct BandwidthCounters {
+    sent: [AtomicU64; MessageCategory::ALL.len()],
+    received: [AtomicU64; MessageCategory::ALL.len()],
+}
+
+impl BandwidthCounters {
+    pub fn record_sent(&self, category: MessageCategory, len: usize) {
+        self.sent[category.index()].fetch_add(len as u64, SeqCst);
+    }
+
+    pub fn record_received(&self, category: MessageCategory, len: usize) {
+        self.received[category.index()].fetch_add(len as u64, SeqCst);
+    }
+
+    pub fn usage(&self) -> BandwidthUsage {
+        BandwidthUsage {
+            sent: array::from_fn(|ix| self.sent[ix].load(SeqCst)),
+            received: array::from_fn(|ix| self.received[ix].load(SeqCst)),
+        }
+    }
+}
+
+/// Returns the category and encoded size of an envelope.
+pub(crate) fn measure(envelope: &proto::Envelope) -> (MessageCategory, usize) {
+    (MessageCategory::of(envelope), envelope.encoded_len())
+}
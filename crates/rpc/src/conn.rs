@@ -1,5 +1,5 @@
 use async_tungstenite::tungstenite::Message as WebSocketMessage;
-use futures::{SinkExt as _, StreamExt as _};
+use futures::{channel::mpsc, SinkExt as _, StreamExt as _};
 
 pub struct Connection {
     pub(crate) tx:
@@ -10,6 +10,7 @@ pub struct Connection {
             + Unpin
             + futures::Stream<Item = Result<WebSocketMessage, anyhow::Error>>,
     >,
+    pub(crate) protocol_version: u32,
 }
 
 impl Connection {
@@ -25,34 +26,99 @@ impl Connection {
         Self {
             tx: Box::new(tx),
             rx: Box::new(rx),
+            protocol_version: crate::PROTOCOL_VERSION,
         }
     }
 
+    /// Records the protocol version spoken by the peer on the other end of this
+    /// connection, so that messages can be converted to and from the forms it understands.
+    pub fn with_protocol_version(mut self, protocol_version: u32) -> Self {
+        self.protocol_version = protocol_version;
+        self
+    }
+
     pub async fn send(&mut self, message: WebSocketMessage) -> Result<(), anyhow::Error> {
         self.tx.send(message).await
     }
 
+    /// Creates a connection whose messages are carried over another connection, such as
+    /// one that a client brokers for other instances of Zed signed in as the same user.
+    /// Returns the connection, the sender that the carrier writes the messages it
+    /// receives into, and the receiver that it reads the messages to send from.
+    pub fn tunneled() -> (
+        Self,
+        mpsc::UnboundedSender<WebSocketMessage>,
+        mpsc::UnboundedReceiver<WebSocketMessage>,
+    ) {
+        let (incoming_tx, incoming_rx) = mpsc::unbounded();
+        let (outgoing_tx, outgoing_rx) = mpsc::unbounded();
+        // Pings are answered like they are on a WebSocket, so that the other side can
+        // measure the round trip.
+        let pong_tx = outgoing_tx.clone();
+        let connection = Self {
+            tx: Box::new(outgoing_tx.sink_map_err(anyhow::Error::from)),
+            rx: Box::new(incoming_rx.map(move |message| {
+                if let WebSocketMessage::Ping(payload) = &message {
+                    pong_tx
+                        .unbounded_send(WebSocketMessage::Pong(payload.clone()))
+                        .ok();
+                }
+                Ok(message)
+            })),
+            protocol_version: crate::PROTOCOL_VERSION,
+        };
+        (connection, incoming_tx, outgoing_rx)
+    }
+
     #[cfg(any(test, feature = "test-support"))]
     pub fn in_memory(
         executor: gpui::BackgroundExecutor,
     ) -> (Self, Self, std::sync::Arc<std::sync::atomic::AtomicBool>) {
+        let (a, b, killed, _) = Self::in_memory_with_suspension(executor);
+        (a, b, killed)
+    }
+
+    /// Like [`Connection::in_memory`], but also returns a flag that silently suspends
+    /// the connection: writes keep succeeding, but nothing is ever read on either side,
+    /// as happens to a socket while the machine on one end of it is asleep.
+    #[cfg(any(test, feature = "test-support"))]
+    #[allow(clippy::type_complexity)]
+    pub fn in_memory_with_suspension(
+        executor: gpui::BackgroundExecutor,
+    ) -> (
+        Self,
+        Self,
+        std::sync::Arc<std::sync::atomic::AtomicBool>,
+        std::sync::Arc<std::sync::atomic::AtomicBool>,
+    ) {
         use std::sync::{
             atomic::{AtomicBool, Ordering::SeqCst},
             Arc,
         };
 
         let killed = Arc::new(AtomicBool::new(false));
-        let (a_tx, a_rx) = channel(killed.clone(), executor.clone());
-        let (b_tx, b_rx) = channel(killed.clone(), executor);
+        let suspended = Arc::new(AtomicBool::new(false));
+        let (a_tx, a_rx) = channel(killed.clone(), suspended.clone(), executor.clone());
+        let (b_tx, b_rx) = channel(killed.clone(), suspended.clone(), executor);
         return (
-            Self { tx: a_tx, rx: b_rx },
-            Self { tx: b_tx, rx: a_rx },
+            Self {
+                tx: a_tx,
+                rx: b_rx,
+                protocol_version: crate::PROTOCOL_VERSION,
+            },
+            Self {
+                tx: b_tx,
+                rx: a_rx,
+                protocol_version: crate::PROTOCOL_VERSION,
+            },
             killed,
+            suspended,
         );
 
         #[allow(clippy::type_complexity)]
         fn channel(
             killed: Arc<AtomicBool>,
+            suspended: Arc<AtomicBool>,
             executor: gpui::BackgroundExecutor,
         ) -> (
             Box<dyn Send + Unpin + futures::Sink<WebSocketMessage, Error = anyhow::Error>>,
@@ -88,12 +154,13 @@ impl Connection {
                 let executor = executor.clone();
                 move |msg| {
                     let killed = killed.clone();
+                    let suspended = suspended.clone();
                     let executor = executor.clone();
                     Box::pin(async move {
                         executor.simulate_random_delay().await;
 
-                        // Reads from a half-open TCP connection will hang.
-                        if killed.load(SeqCst) {
+                        // Reads from a half-open or suspended TCP connection will hang.
+                        if killed.load(SeqCst) || suspended.load(SeqCst) {
                             futures::future::pending::<()>().await;
                         }
 
@@ -0,0 +1,312 @@
+use crate::proto;
+use collections::HashMap;
+use parking_lot::Mutex;
+use std::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst},
+    time::Duration,
+};
+
+/// A connection degrades once this many messages are waiting to be written to it...
+const DEGRADED_QUEUE_DEPTH: usize = 256;
+/// ...and recovers once its queue has drained below this depth.
+const RECOVERED_QUEUE_DEPTH: usize = 32;
+/// A connection also degrades when pings take longer than this to be answered...
+pub(crate) const DEGRADED_ROUND_TRIP_TIME: Duration = Duration::from_millis(1500);
+/// ...and recovers once they are answered faster than this.
+const RECOVERED_ROUND_TRIP_TIME: Duration = Duration::from_millis(500);
+
+/// How many messages may wait to be written to a connection before the peer stops
+/// buffering them and starts shedding load instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BacklogPolicy {
+    /// Messages sent with [`crate::Peer::forward_send_lossy`] are dropped at this depth.
+    pub shed_lossy_messages: usize,
+    /// The receiver is told that it's lagging at this depth.
+    pub notify_lagging: usize,
+    /// The connection is closed with [`crate::ErrorCode::SendBacklogExceeded`] at this depth.
+    pub disconnect: usize,
+}
+
+impl BacklogPolicy {
+    /// Never sheds or disconnects, however many messages are waiting.
+    pub const UNLIMITED: Self = Self {
+        shed_lossy_messages: usize::MAX,
+        notify_lagging: usize::MAX,
+        disconnect: usize::MAX,
+    };
+}
+
+impl Default for BacklogPolicy {
+    fn default() -> Self {
+        Self {
+            shed_lossy_messages: 1024,
+            notify_lagging: 2048,
+            disconnect: 8192,
+        }
+    }
+}
+
+/// What should happen to the next message sent over a connection, given its backlog.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BacklogAction {
+    Enqueue,
+    EnqueueAndNotifyLagging,
+    Disconnect,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionQuality {
+    Good,
+    Degraded,
+}
+
+/// Tracks how well a connection keeps up with the messages sent over it, so that
+/// non-essential updates can be sent less often while it struggles.
+#[derive(Default)]
+pub(crate) struct ConnectionHealth {
+    queued_messages: AtomicUsize,
+    round_trip_time: Mutex<Option<Duration>>,
+    degraded: AtomicBool,
+    coalesced_messages: Mutex<HashMap<u64, proto::Envelope>>,
+    backlog_policy: Mutex<BacklogPolicy>,
+    notified_lagging: AtomicBool,
+    evicted: AtomicBool,
+    notices: Mutex<Vec<proto::Envelope>>,
+}
+
+impl ConnectionHealth {
+    pub fn new(backlog_policy: BacklogPolicy) -> Self {
+        Self {
+            backlog_policy: Mutex::new(backlog_policy),
+            ..Default::default()
+        }
+    }
+
+    pub fn quality(&self) -> ConnectionQuality {
+        if self.degraded.load(SeqCst) {
+            ConnectionQuality::Degraded
+        } else {
+            ConnectionQuality::Good
+        }
+    }
+
+    pub fn queued_messages(&self) -> usize {
+        self.queued_messages.load(SeqCst)
+    }
+
+    pub fn round_trip_time(&self) -> Option<Duration> {
+        *self.round_trip_time.lock()
+    }
+
+    pub fn message_enqueued(&self) {
+        self.queued_messages.fetch_add(1, SeqCst);
+        self.update_quality();
+    }
+
+    pub fn message_dequeued(&self) {
+        self.queued_messages.fetch_sub(1, SeqCst);
+        self.update_quality();
+    }
+
+    pub fn set_round_trip_time(&self, round_trip_time: Duration) {
+        *self.round_trip_time.lock() = Some(round_trip_time);
+        self.update_quality();
+    }
+
+    /// Stores a message to be sent once the connection recovers, replacing any
+    /// message previously stored under the same key.
+    pub fn coalesce(&self, key: u64, envelope: proto::Envelope) {
+        self.coalesced_messages.lock().insert(key, envelope);
+    }
+
+    /// Returns the messages that were held back while the connection was
+    /// degraded, in the order they were originally sent.
+    pub fn take_coalesced_messages(&self) -> Vec<proto::Envelope> {
+        let mut coalesced_messages = self.coalesced_messages.lock();
+        if coalesced_messages.is_empty() {
+            return Vec::new();
+        }
+        let mut envelopes = coalesced_messages
+            .drain()
+            .map(|(_, envelope)| envelope)
+            .collect::<Vec<_>>();
+        envelopes.sort_by_key(|envelope| envelope.id);
+        envelopes
+    }
+
+    pub fn set_backlog_policy(&self, backlog_policy: BacklogPolicy) {
+        *self.backlog_policy.lock() = backlog_policy;
+    }
+
+    pub fn should_shed_lossy_message(&self) -> bool {
+        self.queued_messages() >= self.backlog_policy.lock().shed_lossy_messages
+    }
+
+    /// Decides what to do with a message that's about to be enqueued. The receiver is
+    /// only told it's lagging once each time its backlog grows past the threshold.
+    pub fn backlog_action(&self) -> BacklogAction {
+        let queued_messages = self.queued_messages();
+        let backlog_policy = *self.backlog_policy.lock();
+        if self.evicted.load(SeqCst) || queued_messages >= backlog_policy.disconnect {
+            BacklogAction::Disconnect
+        } else if queued_messages.saturating_add(1) >= backlog_policy.notify_lagging {
+            if self.notified_lagging.swap(true, SeqCst) {
+                BacklogAction::Enqueue
+            } else {
+                BacklogAction::EnqueueAndNotifyLagging
+            }
+        } else {
+            if queued_messages < backlog_policy.shed_lossy_messages {
+                self.notified_lagging.store(false, SeqCst);
+            }
+            BacklogAction::Enqueue
+        }
+    }
+
+    /// Stores a message to be written ahead of everything that's already queued.
+    pub fn push_notice(&self, envelope: proto::Envelope) {
+        self.notices.lock().push(envelope);
+    }
+
+    pub fn take_notices(&self) -> Vec<proto::Envelope> {
+        std::mem::take(&mut *self.notices.lock())
+    }
+
+    pub fn evict(&self) {
+        self.evicted.store(true, SeqCst);
+    }
+
+    pub fn is_evicted(&self) -> bool {
+        self.evicted.load(SeqCst)
+    }
+
+    fn update_quality(&self) {
+        let queued_messages = self.queued_messages.load(SeqCst);
+        let round_trip_time = *self.round_trip_time.lock();
+        if self.degraded.load(SeqCst) {
+            if queued_messages <= RECOVERED_QUEUE_DEPTH
+                && round_trip_time.map_or(true, |rtt| rtt <= RECOVERED_ROUND_TRIP_TIME)
+            {
+                self.degraded.store(false, SeqCst);
+                tracing::info!("connection recovered");
+            }
+        } else if queued_messages >= DEGRADED_QUEUE_DEPTH
+            || round_trip_time.map_or(false, |rtt| rtt >= DEGRADED_ROUND_TRIP_TIME)
+        {
+            self.degraded.store(true, SeqCst);
+            tracing::info!(queued_messages, ?round_trip_time, "connection degraded");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_quality_hysteresis() {
+        let health = ConnectionHealth::default();
+        assert_eq!(health.quality(), ConnectionQuality::Good);
+
+        for _ in 0..DEGRADED_QUEUE_DEPTH - 1 {
+            health.message_enqueued();
+        }
+        assert_eq!(health.quality(), ConnectionQuality::Good);
+        health.message_enqueued();
+        assert_eq!(health.quality(), ConnectionQuality::Degraded);
+
+        // Draining part of the queue isn't enough to recover.
+        while health.queued_messages() > RECOVERED_QUEUE_DEPTH + 1 {
+            health.message_dequeued();
+        }
+        assert_eq!(health.quality(), ConnectionQuality::Degraded);
+        health.message_dequeued();
+        assert_eq!(health.quality(), ConnectionQuality::Good);
+
+        // A slow round trip degrades the connection until a fast one is measured.
+        health.set_round_trip_time(DEGRADED_ROUND_TRIP_TIME);
+        assert_eq!(health.quality(), ConnectionQuality::Degraded);
+        health.set_round_trip_time(RECOVERED_ROUND_TRIP_TIME * 2);
+        assert_eq!(health.quality(), ConnectionQuality::Degraded);
+        health.set_round_trip_time(RECOVERED_ROUND_TRIP_TIME);
+        assert_eq!(health.quality(), ConnectionQuality::Good);
+    }
+
+    #[test]
+    fn test_backlog_actions() {
+        let health = ConnectionHealth::new(BacklogPolicy {
+            shed_lossy_messages: 2,
+            notify_lagging: 4,
+            disconnect: 6,
+        });
+        let mut actions = Vec::new();
+        for _ in 0..6 {
+            actions.push((
+                health.queued_messages(),
+                health.should_shed_lossy_message(),
+                health.backlog_action(),
+            ));
+            health.message_enqueued();
+        }
+        actions.push((
+            health.queued_messages(),
+            health.should_shed_lossy_message(),
+            health.backlog_action(),
+        ));
+        assert_eq!(
+            actions,
+            [
+                (0, false, BacklogAction::Enqueue),
+                (1, false, BacklogAction::Enqueue),
+                (2, true, BacklogAction::Enqueue),
+                (3, true, BacklogAction::EnqueueAndNotifyLagging),
+                (4, true, BacklogAction::Enqueue),
+                (5, true, BacklogAction::Enqueue),
+                (6, true, BacklogAction::Disconnect),
+            ]
+        );
+
+        // The receiver is notified again only after it has caught up in between.
+        for _ in 0..3 {
+            health.message_dequeued();
+        }
+        assert_eq!(health.backlog_action(), BacklogAction::Enqueue);
+        while health.queued_messages() > 1 {
+            health.message_dequeued();
+        }
+        assert_eq!(health.backlog_action(), BacklogAction::Enqueue);
+        for _ in 0..2 {
+            health.message_enqueued();
+        }
+        assert_eq!(
+            health.backlog_action(),
+            BacklogAction::EnqueueAndNotifyLagging
+        );
+
+        // Once evicted, a connection stays that way.
+        health.evict();
+        while health.queued_messages() > 0 {
+            health.message_dequeued();
+        }
+        assert_eq!(health.backlog_action(), BacklogAction::Disconnect);
+    }
+
+    #[test]
+    fn test_coalesced_messages() {
+        let health = ConnectionHealth::default();
+        let envelope = |id| proto::Envelope {
+            id,
+            ..Default::default()
+        };
+        health.coalesce(1, envelope(3));
+        health.coalesce(2, envelope(4));
+        health.coalesce(1, envelope(5));
+        let ids = health
+            .take_coalesced_messages()
+            .into_iter()
+            .map(|envelope| envelope.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [4, 5]);
+        assert!(health.take_coalesced_messages().is_empty());
+    }
+}
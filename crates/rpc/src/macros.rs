@@ -18,6 +18,20 @@ macro_rules! messages {
             }
         }
 
+        pub fn envelope_payload_mut(envelope: &mut Envelope) -> Option<&mut dyn std::any::Any> {
+            match &mut envelope.payload {
+                $(Some(envelope::Payload::$name(payload)) => Some(payload),)*
+                _ => None
+            }
+        }
+
+        pub fn envelope_payload_name(envelope: &Envelope) -> Option<&'static str> {
+            match &envelope.payload {
+                $(Some(envelope::Payload::$name(_)) => Some(std::stringify!($name)),)*
+                _ => None
+            }
+        }
+
         $(
             impl EnvelopedMessage for $name {
                 const NAME: &'static str = std::stringify!($name);
@@ -52,6 +66,13 @@ macro_rules! messages {
 #[macro_export]
 macro_rules! request_messages {
     ($(($request_name:ident, $response_name:ident)),* $(,)?) => {
+        pub fn is_request(envelope: &Envelope) -> bool {
+            matches!(
+                envelope.payload,
+                $(Some(envelope::Payload::$request_name(_)))|*
+            )
+        }
+
         $(impl RequestMessage for $request_name {
             type Response = $response_name;
         })*
@@ -0,0 +1,118 @@
+use crate::proto::EnvelopedMessage;
+use std::any::{Any, TypeId};
+
+/// Converts one message type between its current form and the form understood by
+/// peers speaking a protocol version older than `introduced_in`.
+struct Migration {
+    message_type: TypeId,
+    introduced_in: u32,
+    upgrade: Box<dyn Send + Sync + Fn(&mut dyn Any)>,
+    downgrade: Box<dyn Send + Sync + Fn(&mut dyn Any)>,
+}
+
+/// The set of message changes that are still in their transition window.
+///
+/// When the meaning of an existing message changes, both forms are supported until
+/// every client speaks a protocol version that understands the new one: messages
+/// received from older peers are upgraded to the new form before being handled, and
+/// messages sent to them are downgraded to the old form, so the rest of the code only
+/// ever deals with the current form. Once [`crate::MIN_PROTOCOL_VERSION`] has caught up
+/// with a migration's version, the migration can be deleted along with the old form.
+#[derive(Default)]
+pub struct Migrations {
+    migrations: Vec<Migration>,
+}
+
+impl Migrations {
+    /// The migrations applied to connections with peers speaking older protocol versions.
+    pub fn current() -> Self {
+        Self::default()
+    }
+
+    /// Registers a change to `T` made in protocol version `introduced_in`. `upgrade`
+    /// rewrites a message in the old form into the new one, and `downgrade` does the
+    /// reverse.
+    pub fn add<T: EnvelopedMessage>(
+        mut self,
+        introduced_in: u32,
+        upgrade: fn(&mut T),
+        downgrade: fn(&mut T),
+    ) -> Self {
+        self.migrations.push(Migration {
+            message_type: TypeId::of::<T>(),
+            introduced_in,
+            upgrade: Box::new(move |message: &mut dyn Any| {
+                upgrade(message.downcast_mut::<T>().unwrap())
+            }),
+            downgrade: Box::new(move |message: &mut dyn Any| {
+                downgrade(message.downcast_mut::<T>().unwrap())
+            }),
+        });
+        self.migrations
+            .sort_by_key(|migration| migration.introduced_in);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.migrations.is_empty()
+    }
+
+    /// Rewrites a message received from a peer speaking `peer_version` into its current form.
+    pub fn upgrade(&self, message: &mut dyn Any, peer_version: u32) {
+        let message_type = (*message).type_id();
+        for migration in &self.migrations {
+            if migration.message_type == message_type && peer_version < migration.introduced_in {
+                (migration.upgrade)(message);
+            }
+        }
+    }
+
+    /// Rewrites a message in its current form into the form understood by a peer speaking
+    /// `peer_version`.
+    pub fn downgrade(&self, message: &mut dyn Any, peer_version: u32) {
+        let message_type = (*message).type_id();
+        for migration in self.migrations.iter().rev() {
+            if migration.message_type == message_type && peer_version < migration.introduced_in {
+                (migration.downgrade)(message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto;
+
+    #[test]
+    fn test_migrations() {
+        // Pretend that version 70 started counting ids from 1 rather than 0, and
+        // version 71 started doubling them.
+        let migrations = Migrations::default()
+            .add::<proto::Test>(71, |message| message.id *= 2, |message| message.id /= 2)
+            .add::<proto::Test>(70, |message| message.id += 1, |message| message.id -= 1);
+
+        let mut message = proto::Test { id: 3 };
+        migrations.upgrade(&mut message, 69);
+        assert_eq!(message.id, 8);
+        migrations.downgrade(&mut message, 69);
+        assert_eq!(message.id, 3);
+
+        let mut message = proto::Test { id: 3 };
+        migrations.upgrade(&mut message, 70);
+        assert_eq!(message.id, 6);
+        migrations.downgrade(&mut message, 70);
+        assert_eq!(message.id, 3);
+
+        let mut message = proto::Test { id: 3 };
+        migrations.upgrade(&mut message, 71);
+        assert_eq!(message.id, 3);
+        migrations.downgrade(&mut message, 71);
+        assert_eq!(message.id, 3);
+
+        // Other message types are left alone.
+        let mut message = proto::Ping {};
+        migrations.upgrade(&mut message, 69);
+        migrations.downgrade(&mut message, 69);
+    }
+}
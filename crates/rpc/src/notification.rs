@@ -38,6 +38,10 @@ pub enum Notification {
         sender_id: u64,
         channel_id: u64,
     },
+    CallSummaryReady {
+        #[serde(rename = "entity_id")]
+        summary_id: u64,
+    },
 }
 
 impl Notification {
@@ -96,6 +100,7 @@ mod tests {
                 channel_id: 30,
                 message_id: 1,
             },
+            Notification::CallSummaryReady { summary_id: 7 },
         ] {
             let message = notification.to_proto();
             let deserialized = Notification::from_proto(&message).unwrap();
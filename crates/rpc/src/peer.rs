@@ -1,31 +1,39 @@
 use crate::{ErrorCode, ErrorCodeExt, ErrorExt, RpcError};
 
 use super::{
+    bandwidth::{self, BandwidthCounters, BandwidthUsage},
+    connection_health::{BacklogAction, BacklogPolicy, ConnectionHealth, ConnectionQuality},
+    migration::Migrations,
     proto::{self, AnyTypedEnvelope, EnvelopedMessage, MessageStream, PeerId, RequestMessage},
+    rate_limit::{self, RateLimitAction, RateLimitPolicy, RateLimitStats, RateLimiter},
     Connection,
 };
 use anyhow::{anyhow, Context, Result};
 use collections::HashMap;
 use futures::{
     channel::{mpsc, oneshot},
+    future::{self, BoxFuture},
     stream::BoxStream,
     FutureExt, SinkExt, StreamExt, TryFutureExt,
 };
 use parking_lot::{Mutex, RwLock};
-use serde::{ser::SerializeStruct, Serialize};
-use std::{fmt, sync::atomic::Ordering::SeqCst};
+use serde::{ser::SerializeStruct, Deserialize, Serialize};
 use std::{
+    any::TypeId,
+    collections::hash_map::DefaultHasher,
     future::Future,
+    hash::{Hash, Hasher},
     marker::PhantomData,
     sync::{
         atomic::{self, AtomicU32},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
+use std::{fmt, sync::atomic::Ordering::SeqCst};
 use tracing::instrument;
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
 pub struct ConnectionId {
     pub owner_id: u32,
     pub id: u32,
@@ -98,10 +106,45 @@ impl<T: RequestMessage> TypedEnvelope<T> {
     }
 }
 
+/// Delivers messages addressed to connections that belong to other peers, such as
+/// the other servers of a deployment.
+pub type RemoteRouter = Arc<dyn Send + Sync + Fn(ConnectionId, RoutedEnvelope) -> Result<()>>;
+
+/// An envelope that a [`RemoteRouter`] carries between peers.
+#[derive(Clone, Debug)]
+pub enum RoutedEnvelope {
+    /// A one-way message for the receiving connection.
+    Message(proto::Envelope),
+    /// A request for the receiving connection, whose response is routed back to the
+    /// peer with the given epoch.
+    Request {
+        requester_epoch: u32,
+        request_id: u32,
+        envelope: proto::Envelope,
+    },
+    /// The response to a request that the receiving peer routed to another one. Its
+    /// receiver's id is unused, as it's addressed to the peer itself.
+    Response {
+        request_id: u32,
+        envelope: proto::Envelope,
+    },
+}
+
 pub struct Peer {
     epoch: AtomicU32,
     pub connections: RwLock<HashMap<ConnectionId, ConnectionState>>,
     next_connection_id: AtomicU32,
+    bandwidth: Arc<BandwidthCounters>,
+    migrations: Arc<Migrations>,
+    backlog_policy: Mutex<BacklogPolicy>,
+    rate_limit_policy: Mutex<RateLimitPolicy>,
+    remote_router: RwLock<Option<RemoteRouter>>,
+    /// The requests routed to other peers that haven't been answered, along with
+    /// the epoch of the peer that each one was routed to.
+    #[allow(clippy::type_complexity)]
+    remote_requests:
+        Mutex<HashMap<u32, (u32, oneshot::Sender<(proto::Envelope, oneshot::Sender<()>)>)>>,
+    next_remote_request_id: AtomicU32,
 }
 
 #[derive(Clone, Serialize)]
@@ -113,6 +156,65 @@ pub struct ConnectionState {
     #[serde(skip)]
     response_channels:
         Arc<Mutex<Option<HashMap<u32, oneshot::Sender<(proto::Envelope, oneshot::Sender<()>)>>>>>,
+    #[serde(skip)]
+    health: Arc<ConnectionHealth>,
+    #[serde(skip)]
+    bandwidth: Arc<BandwidthCounters>,
+    #[serde(skip)]
+    rate_limiter: Arc<RateLimiter>,
+    protocol_version: u32,
+    /// Which outgoing envelopes are compressed, which depends on the protocol version.
+    #[serde(skip)]
+    compression: Arc<Mutex<proto::Compression>>,
+}
+
+impl ConnectionState {
+    fn enqueue(&self, message: proto::Message) -> Result<()> {
+        // Messages held back for coalescing were sent before this one, so they're
+        // queued first. This also ends the window in which they can be replaced, so
+        // a stale update can't be delivered after, say, a project is unshared.
+        for envelope in self.health.take_coalesced_messages() {
+            self.enqueue_in_order(proto::Message::Envelope(envelope))?;
+        }
+        self.enqueue_in_order(message)
+    }
+
+    fn enqueue_in_order(&self, message: proto::Message) -> Result<()> {
+        match self.health.backlog_action() {
+            BacklogAction::Enqueue => {}
+            BacklogAction::EnqueueAndNotifyLagging => {
+                let queued_message_count = self.health.queued_messages();
+                tracing::warn!(queued_message_count, "connection is lagging");
+                self.push_notice(proto::ConnectionLagging {
+                    queued_message_count: queued_message_count as u32,
+                });
+            }
+            BacklogAction::Disconnect => {
+                if !self.health.is_evicted() {
+                    let queued_message_count = self.health.queued_messages();
+                    tracing::warn!(queued_message_count, "send backlog exceeded, disconnecting");
+                    self.push_notice(ErrorCode::SendBacklogExceeded.anyhow().to_proto());
+                    self.health.evict();
+                }
+                return Err(ErrorCode::SendBacklogExceeded.anyhow());
+            }
+        }
+
+        self.health.message_enqueued();
+        if self.outgoing_tx.unbounded_send(message).is_err() {
+            self.health.message_dequeued();
+            return Err(anyhow!("connection was closed"));
+        }
+        Ok(())
+    }
+
+    /// Queues a message to be written before any of the messages that are already
+    /// waiting, so that it reaches a lagging receiver promptly.
+    fn push_notice<T: EnvelopedMessage>(&self, message: T) {
+        let message_id = self.next_message_id.fetch_add(1, SeqCst);
+        self.health
+            .push_notice(message.into_envelope(message_id, None, None));
+    }
 }
 
 const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
@@ -121,10 +223,23 @@ pub const RECEIVE_TIMEOUT: Duration = Duration::from_secs(10);
 
 impl Peer {
     pub fn new(epoch: u32) -> Arc<Self> {
+        Self::with_migrations(epoch, Migrations::current())
+    }
+
+    pub fn with_migrations(epoch: u32, migrations: Migrations) -> Arc<Self> {
         Arc::new(Self {
             epoch: AtomicU32::new(epoch),
             connections: Default::default(),
             next_connection_id: Default::default(),
+            bandwidth: Default::default(),
+            migrations: Arc::new(migrations),
+            // Only servers shed load, by setting a policy. Clients keep everything
+            // they send, since dropping it would lose their edits.
+            backlog_policy: Mutex::new(BacklogPolicy::UNLIMITED),
+            rate_limit_policy: Default::default(),
+            remote_router: Default::default(),
+            remote_requests: Default::default(),
+            next_remote_request_id: Default::default(),
         })
     }
 
@@ -132,6 +247,31 @@ impl Peer {
         self.epoch.load(SeqCst)
     }
 
+    /// Sets the thresholds applied to the send queues of all connections, including
+    /// existing ones.
+    pub fn set_backlog_policy(&self, policy: BacklogPolicy) {
+        *self.backlog_policy.lock() = policy;
+        for connection in self.connections.read().values() {
+            connection.health.set_backlog_policy(policy);
+        }
+    }
+
+    /// Sends messages and requests addressed to connections whose ids were issued by
+    /// peers in other epochs through the given router, rather than failing to find
+    /// them.
+    pub fn set_remote_router(&self, router: RemoteRouter) {
+        *self.remote_router.write() = Some(router);
+    }
+
+    /// Sets the limits on the messages sent by all connections, including existing
+    /// ones, whose allowances start over if their limits changed.
+    pub fn set_rate_limit_policy(&self, policy: RateLimitPolicy) {
+        for connection in self.connections.read().values() {
+            connection.rate_limiter.set_policy(policy.clone());
+        }
+        *self.rate_limit_policy.lock() = policy;
+    }
+
     #[instrument(skip_all)]
     pub fn add_connection<F, Fut, Out>(
         self: &Arc<Self>,
@@ -166,12 +306,24 @@ impl Peer {
             outgoing_tx,
             next_message_id: Default::default(),
             response_channels: Arc::new(Mutex::new(Some(Default::default()))),
+            health: Arc::new(ConnectionHealth::new(*self.backlog_policy.lock())),
+            bandwidth: Default::default(),
+            rate_limiter: Arc::new(RateLimiter::new(self.rate_limit_policy.lock().clone())),
+            protocol_version: connection.protocol_version,
+            compression: Arc::new(Mutex::new(proto::Compression::for_protocol_version(
+                connection.protocol_version,
+            ))),
         };
         let mut writer = MessageStream::new(connection.tx);
         let mut reader = MessageStream::new(connection.rx);
 
         let this = self.clone();
         let response_channels = connection_state.response_channels.clone();
+        let health = connection_state.health.clone();
+        let connection_bandwidth = connection_state.bandwidth.clone();
+        let rate_limiter = connection_state.rate_limiter.clone();
+        let next_message_id = connection_state.next_message_id.clone();
+        let compression = connection_state.compression.clone();
         let handle_io = async move {
             tracing::trace!(%connection_id, "handle io future: start");
 
@@ -189,21 +341,79 @@ impl Peer {
             let receive_timeout = create_timer(RECEIVE_TIMEOUT).fuse();
             futures::pin_mut!(receive_timeout);
 
+            // Used to measure the round trip time of keepalive pings.
+            let mut ping_sent_at = None;
+
+            // A message the rate limiter held back. Reading stops until it's handled,
+            // so the sender is slowed down by backpressure, but writes and pings go on.
+            let mut delayed_incoming = None;
+            let resume_reading = future::Fuse::terminated();
+            futures::pin_mut!(resume_reading);
+
+            let record_sent = |envelope: &proto::Envelope| {
+                let (category, len) = bandwidth::measure(envelope);
+                connection_bandwidth.record_sent(category, len);
+                this.bandwidth.record_sent(category, len);
+            };
+
             loop {
                 tracing::trace!(%connection_id, "outer loop iteration start");
-                let read_message = reader.read().fuse();
+                let read_message = if delayed_incoming.is_some() {
+                    future::Either::Left(future::pending())
+                } else {
+                    future::Either::Right(reader.read())
+                }
+                .fuse();
                 futures::pin_mut!(read_message);
 
                 loop {
                     tracing::trace!(%connection_id, "inner loop iteration start");
+                    writer.set_compression(*compression.lock());
+                    // Read this first, because the disconnection notice is queued before
+                    // the connection is marked as evicted.
+                    let evicted = health.is_evicted();
+                    let mut envelopes = health.take_notices();
+                    // Held-back messages are newer than everything in the queue, since
+                    // queueing a message flushes them first, so they can only be written
+                    // directly once the queue is empty.
+                    if !evicted
+                        && health.quality() == ConnectionQuality::Good
+                        && health.queued_messages() == 0
+                    {
+                        envelopes.extend(health.take_coalesced_messages());
+                    }
+                    for envelope in envelopes {
+                        tracing::trace!(%connection_id, "priority rpc message: writing");
+                        record_sent(&envelope);
+                        futures::select_biased! {
+                            result = writer.write(proto::Message::Envelope(envelope)).fuse() => {
+                                result.context("failed to write RPC message")?;
+                            }
+                            _ = create_timer(WRITE_TIMEOUT).fuse() => {
+                                tracing::trace!(%connection_id, "priority rpc message: writing timed out");
+                                Err(anyhow!("timed out writing message"))?;
+                            }
+                        }
+                    }
+                    if evicted {
+                        tracing::trace!(%connection_id, "send backlog exceeded");
+                        Err(ErrorCode::SendBacklogExceeded.anyhow())?;
+                    }
+
                     futures::select_biased! {
                         outgoing = outgoing_rx.next().fuse() => match outgoing {
                             Some(outgoing) => {
                                 tracing::trace!(%connection_id, "outgoing rpc message: writing");
+                                // The protocol version may have changed while waiting.
+                                writer.set_compression(*compression.lock());
+                                if let proto::Message::Envelope(envelope) = &outgoing {
+                                    record_sent(envelope);
+                                }
                                 futures::select_biased! {
                                     result = writer.write(outgoing).fuse() => {
                                         tracing::trace!(%connection_id, "outgoing rpc message: done writing");
                                         result.context("failed to write RPC message")?;
+                                        health.message_dequeued();
                                         tracing::trace!(%connection_id, "keepalive interval: resetting after sending message");
                                         keepalive_timer.set(create_timer(KEEPALIVE_INTERVAL).fuse());
                                     }
@@ -224,6 +434,7 @@ impl Peer {
                                 result = writer.write(proto::Message::Ping).fuse() => {
                                     tracing::trace!(%connection_id, "keepalive interval: done pinging");
                                     result.context("failed to send keepalive")?;
+                                    ping_sent_at = Some(Instant::now());
                                     tracing::trace!(%connection_id, "keepalive interval: resetting after pinging");
                                     keepalive_timer.set(create_timer(KEEPALIVE_INTERVAL).fuse());
                                 }
@@ -238,26 +449,56 @@ impl Peer {
                             tracing::trace!(%connection_id, "incoming rpc message: received");
                             tracing::trace!(%connection_id, "receive timeout: resetting");
                             receive_timeout.set(create_timer(RECEIVE_TIMEOUT).fuse());
+                            if let proto::Message::Pong = incoming {
+                                if let Some(ping_sent_at) = ping_sent_at.take() {
+                                    health.set_round_trip_time(ping_sent_at.elapsed());
+                                }
+                            }
                             if let proto::Message::Envelope(incoming) = incoming {
                                 tracing::trace!(%connection_id, "incoming rpc message: processing");
-                                futures::select_biased! {
-                                    result = incoming_tx.send(incoming).fuse() => match result {
-                                        Ok(_) => {
-                                            tracing::trace!(%connection_id, "incoming rpc message: processed");
-                                        }
-                                        Err(_) => {
-                                            tracing::trace!(%connection_id, "incoming rpc message: channel closed");
-                                            return Ok(())
+                                let (category, len) = bandwidth::measure(&incoming);
+                                connection_bandwidth.record_received(category, len);
+                                this.bandwidth.record_received(category, len);
+                                match rate_limiter.check(&incoming, Instant::now()) {
+                                    RateLimitAction::Handle => {}
+                                    RateLimitAction::Reject { retry_after } => {
+                                        tracing::trace!(%connection_id, ?retry_after, "incoming rpc message: rate limited");
+                                        let message_id = next_message_id.fetch_add(1, SeqCst);
+                                        health.push_notice(
+                                            rate_limit::rate_limited_error(retry_after)
+                                                .into_envelope(message_id, Some(incoming.id), None),
+                                        );
+                                        break;
+                                    }
+                                    RateLimitAction::Delay(delay) => {
+                                        if delay > RECEIVE_TIMEOUT {
+                                            Err(anyhow!("sending messages too quickly"))?;
                                         }
-                                    },
-                                    _ = create_timer(WRITE_TIMEOUT).fuse() => {
-                                        tracing::trace!(%connection_id, "incoming rpc message: processing timed out");
-                                        Err(anyhow!("timed out processing incoming message"))?
+                                        tracing::trace!(%connection_id, ?delay, "incoming rpc message: delaying");
+                                        delayed_incoming = Some(incoming);
+                                        resume_reading.set(create_timer(delay).fuse());
+                                        // Nothing is read while the message is held back.
+                                        receive_timeout.set(create_timer(delay + RECEIVE_TIMEOUT).fuse());
+                                        break;
                                     }
                                 }
+                                let timeout = create_timer(WRITE_TIMEOUT);
+                                if !forward_incoming(&mut incoming_tx, incoming, timeout, connection_id).await? {
+                                    return Ok(());
+                                }
                             }
                             break;
                         },
+                        _ = resume_reading => {
+                            tracing::trace!(%connection_id, "incoming rpc message: done delaying");
+                            if let Some(incoming) = delayed_incoming.take() {
+                                let timeout = create_timer(WRITE_TIMEOUT);
+                                if !forward_incoming(&mut incoming_tx, incoming, timeout, connection_id).await? {
+                                    return Ok(());
+                                }
+                            }
+                            break;
+                        }
                         _ = receive_timeout => {
                             tracing::trace!(%connection_id, "receive timeout: delay between messages too long");
                             Err(anyhow!("delay between messages too long"))?
@@ -268,12 +509,15 @@ impl Peer {
         };
 
         let response_channels = connection_state.response_channels.clone();
+        let initial_protocol_version = connection_state.protocol_version;
         self.connections
             .write()
             .insert(connection_id, connection_state);
 
+        let this = self.clone();
         let incoming_rx = incoming_rx.filter_map(move |incoming| {
             let response_channels = response_channels.clone();
+            let this = this.clone();
             async move {
                 let message_id = incoming.id;
                 tracing::trace!(?incoming, "incoming message future: start");
@@ -326,14 +570,28 @@ impl Peer {
                     None
                 } else {
                     tracing::trace!(%connection_id, message_id, "incoming message: received");
-                    proto::build_typed_envelope(connection_id, incoming).or_else(|| {
-                        tracing::error!(
+                    let Some(mut envelope) = proto::build_typed_envelope(connection_id, incoming)
+                    else {
+                        // The peer speaks a newer protocol version with a message this one
+                        // doesn't know. Replying means that a request fails right away,
+                        // rather than once the peer gives up waiting for a response.
+                        tracing::warn!(
                             %connection_id,
                             message_id,
-                            "unable to construct a typed envelope"
+                            "incoming message: unsupported type"
                         );
-                        None
-                    })
+                        this.respond_with_unsupported_message(connection_id, message_id)
+                            .ok();
+                        return None;
+                    };
+                    let protocol_version = this
+                        .connection_state(connection_id)
+                        .map_or(initial_protocol_version, |connection| {
+                            connection.protocol_version
+                        });
+                    this.migrations
+                        .upgrade(envelope.payload_mut(), protocol_version);
+                    Some(envelope)
                 }
             }
         });
@@ -354,6 +612,22 @@ impl Peer {
         self.add_connection(connection, move |duration| executor.timer(duration))
     }
 
+    /// Records the protocol version that the peer on the other end of a connection
+    /// announced once connected, replacing the one the connection was added with.
+    pub fn set_protocol_version(
+        &self,
+        connection_id: ConnectionId,
+        protocol_version: u32,
+    ) -> Result<()> {
+        let mut connections = self.connections.write();
+        let connection = connections
+            .get_mut(&connection_id)
+            .ok_or_else(|| anyhow!("no such connection: {}", connection_id))?;
+        connection.protocol_version = protocol_version;
+        *connection.compression.lock() = proto::Compression::for_protocol_version(protocol_version);
+        Ok(())
+    }
+
     pub fn disconnect(&self, connection_id: ConnectionId) {
         self.connections.write().remove(&connection_id);
     }
@@ -366,6 +640,15 @@ impl Peer {
 
     pub fn teardown(&self) {
         self.connections.write().clear();
+        self.remote_requests.lock().clear();
+    }
+
+    /// Fails the requests that were routed to the peer with the given epoch, which
+    /// won't be answered because that peer has stopped.
+    pub fn fail_remote_requests(&self, epoch: u32) {
+        self.remote_requests
+            .lock()
+            .retain(|_, (receiver_epoch, _)| *receiver_epoch != epoch);
     }
 
     pub fn request<T: RequestMessage>(
@@ -402,52 +685,96 @@ impl Peer {
         request: T,
     ) -> impl Future<Output = Result<TypedEnvelope<T::Response>>> {
         let (tx, rx) = oneshot::channel();
-        let send = self.connection_state(receiver_id).and_then(|connection| {
-            let message_id = connection.next_message_id.fetch_add(1, SeqCst);
-            connection
-                .response_channels
+        let send = if let Some(router) = self.remote_router(receiver_id) {
+            // Responses are upgraded by the peer that routes them back.
+            let request_id = self.next_remote_request_id.fetch_add(1, SeqCst);
+            self.remote_requests
                 .lock()
-                .as_mut()
-                .ok_or_else(|| anyhow!("connection was closed"))?
-                .insert(message_id, tx);
-            connection
-                .outgoing_tx
-                .unbounded_send(proto::Message::Envelope(request.into_envelope(
-                    message_id,
-                    None,
-                    original_sender_id.map(Into::into),
-                )))
-                .map_err(|_| anyhow!("connection was closed"))?;
-            Ok(())
-        });
+                .insert(request_id, (receiver_id.owner_id, tx));
+            let envelope = request.into_envelope(0, None, original_sender_id.map(Into::into));
+            router(
+                receiver_id,
+                RoutedEnvelope::Request {
+                    requester_epoch: self.epoch(),
+                    request_id,
+                    envelope,
+                },
+            )
+            .map(|_| crate::PROTOCOL_VERSION)
+            .map_err(|error| {
+                self.remote_requests.lock().remove(&request_id);
+                error
+            })
+        } else {
+            self.send_request(original_sender_id, receiver_id, request, tx)
+        };
+        let migrations = self.migrations.clone();
         async move {
-            send?;
+            let protocol_version = send?;
             let (response, _barrier) = rx.await.map_err(|_| anyhow!("connection was closed"))?;
 
             if let Some(proto::envelope::Payload::Error(error)) = &response.payload {
                 Err(RpcError::from_proto(&error, T::NAME))
             } else {
+                let message_id = response.id;
+                let original_sender_id = response.original_sender_id;
+                let mut payload = T::Response::from_envelope(response)
+                    .ok_or_else(|| anyhow!("received response of the wrong type"))?;
+                migrations.upgrade(&mut payload, protocol_version);
                 Ok(TypedEnvelope {
-                    message_id: response.id,
+                    message_id,
                     sender_id: receiver_id,
-                    original_sender_id: response.original_sender_id,
-                    payload: T::Response::from_envelope(response)
-                        .ok_or_else(|| anyhow!("received response of the wrong type"))?,
+                    original_sender_id,
+                    payload,
                 })
             }
         }
     }
 
+    /// Queues a request on one of this peer's connections, returning the protocol
+    /// version of the peer on the other end.
+    fn send_request<T: RequestMessage>(
+        &self,
+        original_sender_id: Option<ConnectionId>,
+        receiver_id: ConnectionId,
+        request: T,
+        tx: oneshot::Sender<(proto::Envelope, oneshot::Sender<()>)>,
+    ) -> Result<u32> {
+        self.connection_state(receiver_id).and_then(|connection| {
+            let message_id = connection.next_message_id.fetch_add(1, SeqCst);
+            connection
+                .response_channels
+                .lock()
+                .as_mut()
+                .ok_or_else(|| anyhow!("connection was closed"))?
+                .insert(message_id, tx);
+            connection.enqueue(proto::Message::Envelope(self.envelope(
+                &connection,
+                request,
+                message_id,
+                None,
+                original_sender_id.map(Into::into),
+            )))?;
+            Ok(connection.protocol_version)
+        })
+    }
+
     pub fn send<T: EnvelopedMessage>(&self, receiver_id: ConnectionId, message: T) -> Result<()> {
+        if let Some(router) = self.remote_router(receiver_id) {
+            let envelope = message.into_envelope(0, None, None);
+            return router(receiver_id, RoutedEnvelope::Message(envelope));
+        }
         let connection = self.connection_state(receiver_id)?;
         let message_id = connection
             .next_message_id
             .fetch_add(1, atomic::Ordering::SeqCst);
-        connection
-            .outgoing_tx
-            .unbounded_send(proto::Message::Envelope(
-                message.into_envelope(message_id, None, None),
-            ))?;
+        connection.enqueue(proto::Message::Envelope(self.envelope(
+            &connection,
+            message,
+            message_id,
+            None,
+            None,
+        )))?;
         Ok(())
     }
 
@@ -457,20 +784,222 @@ impl Peer {
         receiver_id: ConnectionId,
         message: T,
     ) -> Result<()> {
+        if let Some(router) = self.remote_router(receiver_id) {
+            let envelope = message.into_envelope(0, None, Some(sender_id.into()));
+            return router(receiver_id, RoutedEnvelope::Message(envelope));
+        }
         let connection = self.connection_state(receiver_id)?;
         let message_id = connection
             .next_message_id
             .fetch_add(1, atomic::Ordering::SeqCst);
-        connection
-            .outgoing_tx
-            .unbounded_send(proto::Message::Envelope(message.into_envelope(
-                message_id,
-                None,
-                Some(sender_id.into()),
-            )))?;
+        connection.enqueue(proto::Message::Envelope(self.envelope(
+            &connection,
+            message,
+            message_id,
+            None,
+            Some(sender_id.into()),
+        )))?;
         Ok(())
     }
 
+    /// Like [`Peer::forward_send`], but for messages that only describe the latest
+    /// state of something, such as a progress indicator. While the receiving
+    /// connection is degraded, only the most recent message sent with a given key
+    /// is kept, and it is delivered once the connection recovers or another message
+    /// is sent over it, whichever comes first.
+    pub fn forward_send_coalesced<T: EnvelopedMessage>(
+        &self,
+        sender_id: ConnectionId,
+        receiver_id: ConnectionId,
+        key: impl Hash,
+        message: T,
+    ) -> Result<()> {
+        if self.remote_router(receiver_id).is_some() {
+            return self.forward_send(sender_id, receiver_id, message);
+        }
+        let connection = self.connection_state(receiver_id)?;
+        let message_id = connection
+            .next_message_id
+            .fetch_add(1, atomic::Ordering::SeqCst);
+        let envelope = self.envelope(
+            &connection,
+            message,
+            message_id,
+            None,
+            Some(sender_id.into()),
+        );
+        if connection.health.quality() == ConnectionQuality::Degraded {
+            let mut hasher = DefaultHasher::new();
+            TypeId::of::<T>().hash(&mut hasher);
+            key.hash(&mut hasher);
+            connection.health.coalesce(hasher.finish(), envelope);
+            Ok(())
+        } else {
+            connection.enqueue(proto::Message::Envelope(envelope))
+        }
+    }
+
+    /// Like [`Peer::forward_send`], but for messages the receiver can afford to miss,
+    /// such as transient presence indicators. These are dropped rather than queued
+    /// once the receiving connection has fallen behind.
+    pub fn forward_send_lossy<T: EnvelopedMessage>(
+        &self,
+        sender_id: ConnectionId,
+        receiver_id: ConnectionId,
+        message: T,
+    ) -> Result<()> {
+        if self.remote_router(receiver_id).is_some() {
+            return self.forward_send(sender_id, receiver_id, message);
+        }
+        let connection = self.connection_state(receiver_id)?;
+        if connection.health.should_shed_lossy_message() {
+            tracing::trace!(%receiver_id, message = T::NAME, "dropping lossy message");
+            return Ok(());
+        }
+        self.forward_send(sender_id, receiver_id, message)
+    }
+
+    /// Delivers an envelope that another peer's [`RemoteRouter`] addressed to this
+    /// peer. Requests are answered by routing their response back to the peer that
+    /// sent them, once it arrives, which is what the returned future waits for.
+    pub fn deliver_routed(
+        self: &Arc<Self>,
+        receiver_id: ConnectionId,
+        routed: RoutedEnvelope,
+    ) -> BoxFuture<'static, Result<()>> {
+        match routed {
+            RoutedEnvelope::Message(envelope) => {
+                future::ready(self.deliver(receiver_id, envelope)).boxed()
+            }
+            RoutedEnvelope::Response {
+                request_id,
+                envelope,
+            } => {
+                let request = self.remote_requests.lock().remove(&request_id);
+                if let Some((_, tx)) = request {
+                    tx.send((envelope, oneshot::channel().0)).ok();
+                }
+                future::ready(Ok(())).boxed()
+            }
+            RoutedEnvelope::Request {
+                requester_epoch,
+                request_id,
+                envelope,
+            } => {
+                let this = self.clone();
+                let response = self.deliver_request(receiver_id, envelope);
+                async move {
+                    let envelope = response.await.unwrap_or_else(|error| {
+                        ErrorCode::Internal
+                            .message(error.to_string())
+                            .to_proto()
+                            .into_envelope(0, None, None)
+                    });
+                    let router = this
+                        .remote_router
+                        .read()
+                        .clone()
+                        .ok_or_else(|| anyhow!("no remote router"))?;
+                    router(
+                        ConnectionId {
+                            owner_id: requester_epoch,
+                            id: 0,
+                        },
+                        RoutedEnvelope::Response {
+                            request_id,
+                            envelope,
+                        },
+                    )
+                }
+                .boxed()
+            }
+        }
+    }
+
+    /// Sends a request that was routed from another peer to one of this peer's
+    /// connections, returning the response in the current protocol version.
+    fn deliver_request(
+        &self,
+        receiver_id: ConnectionId,
+        mut envelope: proto::Envelope,
+    ) -> impl Future<Output = Result<proto::Envelope>> {
+        let (tx, rx) = oneshot::channel();
+        let send = self.connection_state(receiver_id).and_then(|connection| {
+            if let Some(payload) = proto::envelope_payload_mut(&mut envelope) {
+                self.migrations
+                    .downgrade(payload, connection.protocol_version);
+            }
+            envelope.id = connection.next_message_id.fetch_add(1, SeqCst);
+            envelope.responding_to = None;
+            connection
+                .response_channels
+                .lock()
+                .as_mut()
+                .ok_or_else(|| anyhow!("connection was closed"))?
+                .insert(envelope.id, tx);
+            connection.enqueue(proto::Message::Envelope(envelope))?;
+            Ok(connection.protocol_version)
+        });
+        let migrations = self.migrations.clone();
+        async move {
+            let protocol_version = send?;
+            let (mut response, _barrier) =
+                rx.await.map_err(|_| anyhow!("connection was closed"))?;
+            if let Some(payload) = proto::envelope_payload_mut(&mut response) {
+                migrations.upgrade(payload, protocol_version);
+            }
+            Ok(response)
+        }
+    }
+
+    /// Sends a message that another peer's [`RemoteRouter`] addressed to one of this
+    /// peer's connections.
+    fn deliver(&self, receiver_id: ConnectionId, mut envelope: proto::Envelope) -> Result<()> {
+        let connection = self.connection_state(receiver_id)?;
+        if let Some(payload) = proto::envelope_payload_mut(&mut envelope) {
+            self.migrations
+                .downgrade(payload, connection.protocol_version);
+        }
+        envelope.id = connection
+            .next_message_id
+            .fetch_add(1, atomic::Ordering::SeqCst);
+        envelope.responding_to = None;
+        connection.enqueue(proto::Message::Envelope(envelope))
+    }
+
+    pub fn rate_limit_stats(&self, connection_id: ConnectionId) -> Result<RateLimitStats> {
+        Ok(self.connection_state(connection_id)?.rate_limiter.stats())
+    }
+
+    pub fn bandwidth_usage(&self, connection_id: ConnectionId) -> Result<BandwidthUsage> {
+        Ok(self.connection_state(connection_id)?.bandwidth.usage())
+    }
+
+    /// Bandwidth used by all connections, including the ones that have been closed.
+    pub fn total_bandwidth_usage(&self) -> BandwidthUsage {
+        self.bandwidth.usage()
+    }
+
+    pub fn connection_quality(&self, connection_id: ConnectionId) -> Result<ConnectionQuality> {
+        Ok(self.connection_state(connection_id)?.health.quality())
+    }
+
+    /// The number of messages waiting to be written to the given connection.
+    pub fn queued_message_count(&self, connection_id: ConnectionId) -> Result<usize> {
+        Ok(self
+            .connection_state(connection_id)?
+            .health
+            .queued_messages())
+    }
+
+    /// The most recently measured round trip time of a keepalive ping.
+    pub fn round_trip_time(&self, connection_id: ConnectionId) -> Result<Option<Duration>> {
+        Ok(self
+            .connection_state(connection_id)?
+            .health
+            .round_trip_time())
+    }
+
     pub fn respond<T: RequestMessage>(
         &self,
         receipt: Receipt<T>,
@@ -480,13 +1009,13 @@ impl Peer {
         let message_id = connection
             .next_message_id
             .fetch_add(1, atomic::Ordering::SeqCst);
-        connection
-            .outgoing_tx
-            .unbounded_send(proto::Message::Envelope(response.into_envelope(
-                message_id,
-                Some(receipt.message_id),
-                None,
-            )))?;
+        connection.enqueue(proto::Message::Envelope(self.envelope(
+            &connection,
+            response,
+            message_id,
+            Some(receipt.message_id),
+            None,
+        )))?;
         Ok(())
     }
 
@@ -499,13 +1028,13 @@ impl Peer {
         let message_id = connection
             .next_message_id
             .fetch_add(1, atomic::Ordering::SeqCst);
-        connection
-            .outgoing_tx
-            .unbounded_send(proto::Message::Envelope(response.into_envelope(
-                message_id,
-                Some(receipt.message_id),
-                None,
-            )))?;
+        connection.enqueue(proto::Message::Envelope(self.envelope(
+            &connection,
+            response,
+            message_id,
+            Some(receipt.message_id),
+            None,
+        )))?;
         Ok(())
     }
 
@@ -523,16 +1052,58 @@ impl Peer {
         let message_id = connection
             .next_message_id
             .fetch_add(1, atomic::Ordering::SeqCst);
-        connection
-            .outgoing_tx
-            .unbounded_send(proto::Message::Envelope(response.into_envelope(
-                message_id,
-                Some(envelope.message_id()),
-                None,
-            )))?;
+        connection.enqueue(proto::Message::Envelope(response.into_envelope(
+            message_id,
+            Some(envelope.message_id()),
+            None,
+        )))?;
         Ok(())
     }
 
+    fn respond_with_unsupported_message(
+        &self,
+        connection_id: ConnectionId,
+        message_id: u32,
+    ) -> Result<()> {
+        let connection = self.connection_state(connection_id)?;
+        let response = ErrorCode::UnsupportedMessage
+            .message("message is not supported by this protocol version".into())
+            .with_tag("protocol_version", &connection.protocol_version.to_string())
+            .to_proto();
+        let response_id = connection
+            .next_message_id
+            .fetch_add(1, atomic::Ordering::SeqCst);
+        connection.enqueue(proto::Message::Envelope(response.into_envelope(
+            response_id,
+            Some(message_id),
+            None,
+        )))?;
+        Ok(())
+    }
+
+    /// Wraps a message in an envelope, converting it to the form understood by the
+    /// peer on the other end of the given connection.
+    fn envelope<T: EnvelopedMessage>(
+        &self,
+        connection: &ConnectionState,
+        mut message: T,
+        message_id: u32,
+        responding_to: Option<u32>,
+        original_sender_id: Option<PeerId>,
+    ) -> proto::Envelope {
+        self.migrations
+            .downgrade(&mut message, connection.protocol_version);
+        message.into_envelope(message_id, responding_to, original_sender_id)
+    }
+
+    fn remote_router(&self, receiver_id: ConnectionId) -> Option<RemoteRouter> {
+        if receiver_id.owner_id == self.epoch() {
+            None
+        } else {
+            self.remote_router.read().clone()
+        }
+    }
+
     fn connection_state(&self, connection_id: ConnectionId) -> Result<ConnectionState> {
         let connections = self.connections.read();
         let connection = connections
@@ -542,6 +1113,32 @@ impl Peer {
     }
 }
 
+/// Passes a message read from a connection on to be handled, returning false if
+/// the connection's messages are no longer being handled.
+async fn forward_incoming(
+    incoming_tx: &mut mpsc::Sender<proto::Envelope>,
+    incoming: proto::Envelope,
+    timeout: impl Future,
+    connection_id: ConnectionId,
+) -> Result<bool> {
+    futures::select_biased! {
+        result = incoming_tx.send(incoming).fuse() => match result {
+            Ok(_) => {
+                tracing::trace!(%connection_id, "incoming rpc message: processed");
+                Ok(true)
+            }
+            Err(_) => {
+                tracing::trace!(%connection_id, "incoming rpc message: channel closed");
+                Ok(false)
+            }
+        },
+        _ = timeout.fuse() => {
+            tracing::trace!(%connection_id, "incoming rpc message: processing timed out");
+            Err(anyhow!("timed out processing incoming message"))
+        }
+    }
+}
+
 impl Serialize for Peer {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -556,7 +1153,7 @@ impl Serialize for Peer {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::TypedEnvelope;
+    use crate::{connection_health::DEGRADED_ROUND_TRIP_TIME, TypedEnvelope};
     use async_tungstenite::tungstenite::Message as WebSocketMessage;
     use gpui::TestAppContext;
 
@@ -664,6 +1261,87 @@ mod tests {
         }
     }
 
+    #[gpui::test(iterations = 10)]
+    async fn test_migrating_messages_for_older_peers(cx: &mut TestAppContext) {
+        init_logger();
+
+        let executor = cx.executor();
+
+        // Pretend the next protocol version starts offsetting test ids by 100.
+        let server = Peer::with_migrations(
+            0,
+            Migrations::default().add::<proto::Test>(
+                crate::PROTOCOL_VERSION + 1,
+                |message| message.id += 100,
+                |message| message.id -= 100,
+            ),
+        );
+        let old_client = Peer::new(0);
+        let new_client = Peer::new(0);
+
+        let (old_client_conn, server_to_old_client_conn, _kill) =
+            Connection::in_memory(cx.executor());
+        let (old_client_conn_id, io_task1, old_client_incoming) =
+            old_client.add_test_connection(old_client_conn, cx.executor());
+        let (_, io_task2, server_incoming1) =
+            server.add_test_connection(server_to_old_client_conn, cx.executor());
+
+        let (new_client_conn, server_to_new_client_conn, _kill) =
+            Connection::in_memory(cx.executor());
+        let (new_client_conn_id, io_task3, new_client_incoming) =
+            new_client.add_test_connection(new_client_conn, cx.executor());
+        let (_, io_task4, server_incoming2) = server.add_test_connection(
+            server_to_new_client_conn.with_protocol_version(crate::PROTOCOL_VERSION + 1),
+            cx.executor(),
+        );
+
+        let (received_tx, mut received_rx) = futures::channel::mpsc::unbounded();
+        executor.spawn(io_task1).detach();
+        executor.spawn(io_task2).detach();
+        executor.spawn(io_task3).detach();
+        executor.spawn(io_task4).detach();
+        executor.spawn(old_client_incoming.count()).detach();
+        executor.spawn(new_client_incoming.count()).detach();
+        for incoming in [server_incoming1, server_incoming2] {
+            let server = server.clone();
+            let received_tx = received_tx.clone();
+            executor
+                .spawn(incoming.for_each(move |envelope| {
+                    let envelope = envelope
+                        .into_any()
+                        .downcast::<TypedEnvelope<proto::Test>>()
+                        .unwrap();
+                    received_tx.unbounded_send(envelope.payload.id).unwrap();
+                    server
+                        .respond(envelope.receipt(), envelope.payload.clone())
+                        .unwrap();
+                    futures::future::ready(())
+                }))
+                .detach();
+        }
+
+        // Messages from the older peer are upgraded on the way in and downgraded on the
+        // way out, so that the server only deals with the new form.
+        assert_eq!(
+            old_client
+                .request(old_client_conn_id, proto::Test { id: 1 })
+                .await
+                .unwrap(),
+            proto::Test { id: 1 }
+        );
+        assert_eq!(received_rx.next().await, Some(101));
+
+        // Messages from peers that already speak the new form are left alone.
+        assert_eq!(
+            new_client
+                .request(new_client_conn_id, proto::Test { id: 101 })
+                .await
+                .unwrap(),
+            proto::Test { id: 101 }
+        );
+        assert_eq!(received_rx.next().await, Some(101));
+    }
+
     #[gpui::test(iterations = 50)]
     async fn test_order_of_response_and_incoming(cx: &mut TestAppContext) {
         let executor = cx.executor();
@@ -910,6 +1588,371 @@ mod tests {
             .is_err());
     }
 
+    #[gpui::test(iterations = 10)]
+    async fn test_send_backlog_eviction(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+        let (server_conn, client_conn, _kill) = Connection::in_memory(executor.clone());
+
+        let server = Peer::new(0);
+        server.set_backlog_policy(BacklogPolicy {
+            shed_lossy_messages: 2,
+            notify_lagging: 3,
+            disconnect: 4,
+        });
+        let (connection_id, server_io, _server_incoming) =
+            server.add_test_connection(server_conn, executor.clone());
+        let client = Peer::new(0);
+        let (_, client_io, client_incoming) =
+            client.add_test_connection(client_conn, executor.clone());
+
+        // Nothing is written until the server's I/O starts, so messages pile up.
+        server.send(connection_id, proto::Test { id: 1 }).unwrap();
+        server.send(connection_id, proto::Test { id: 2 }).unwrap();
+        server
+            .forward_send_lossy(connection_id, connection_id, proto::Test { id: 3 })
+            .unwrap();
+        assert_eq!(server.queued_message_count(connection_id).unwrap(), 2);
+        server.send(connection_id, proto::Test { id: 4 }).unwrap();
+        server.send(connection_id, proto::Test { id: 5 }).unwrap();
+        assert_eq!(
+            server
+                .send(connection_id, proto::Test { id: 6 })
+                .unwrap_err()
+                .error_code(),
+            ErrorCode::SendBacklogExceeded
+        );
+        assert!(server.send(connection_id, proto::Test { id: 7 }).is_err());
+
+        // The lagging notice and the error jump the queue, after which the server
+        // hangs up instead of writing the backlog.
+        executor.spawn(client_io).detach();
+        let server_io = executor.spawn(server_io);
+        let received = client_incoming
+            .map(|envelope| {
+                let envelope = envelope.into_any();
+                if let Some(envelope) =
+                    envelope.downcast_ref::<TypedEnvelope<proto::ConnectionLagging>>()
+                {
+                    format!("lagging {}", envelope.payload.queued_message_count)
+                } else if let Some(envelope) =
+                    envelope.downcast_ref::<TypedEnvelope<proto::Error>>()
+                {
+                    format!("error {:?}", envelope.payload.code())
+                } else {
+                    panic!("unexpected message");
+                }
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            received,
+            [
+                "lagging 2".to_string(),
+                "error SendBacklogExceeded".to_string()
+            ]
+        );
+        assert_eq!(
+            server_io.await.unwrap_err().error_code(),
+            ErrorCode::SendBacklogExceeded
+        );
+    }
+
+    #[gpui::test]
+    async fn test_coalesced_messages_stay_ordered(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+        let (server_conn, client_conn, _kill) = Connection::in_memory(executor.clone());
+
+        let server = Peer::new(0);
+        let (connection_id, server_io, _server_incoming) =
+            server.add_test_connection(server_conn, executor.clone());
+        let client = Peer::new(0);
+        let (_, client_io, client_incoming) =
+            client.add_test_connection(client_conn, executor.clone());
+        executor.spawn(server_io).detach();
+        executor.spawn(client_io).detach();
+
+        server
+            .connection_state(connection_id)
+            .unwrap()
+            .health
+            .set_round_trip_time(DEGRADED_ROUND_TRIP_TIME);
+        server
+            .forward_send_coalesced(connection_id, connection_id, 1, proto::Test { id: 1 })
+            .unwrap();
+        server
+            .forward_send_coalesced(connection_id, connection_id, 1, proto::Test { id: 2 })
+            .unwrap();
+        // Sending any other message flushes the latest coalesced one ahead of it.
+        server.send(connection_id, proto::Test { id: 3 }).unwrap();
+        server
+            .forward_send_coalesced(connection_id, connection_id, 1, proto::Test { id: 4 })
+            .unwrap();
+
+        let received = client_incoming
+            .take(2)
+            .map(|envelope| {
+                envelope
+                    .into_any()
+                    .downcast_ref::<TypedEnvelope<proto::Test>>()
+                    .unwrap()
+                    .payload
+                    .id
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(received, [2, 3]);
+    }
+
+    #[gpui::test]
+    async fn test_default_policy_never_sheds(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+        let (client_conn, _server_conn, _kill) = Connection::in_memory(executor.clone());
+
+        let client = Peer::new(0);
+        let (connection_id, _client_io, _client_incoming) =
+            client.add_test_connection(client_conn, executor.clone());
+        for id in 0..1000 {
+            client
+                .forward_send_lossy(connection_id, connection_id, proto::Test { id })
+                .unwrap();
+        }
+        assert_eq!(client.queued_message_count(connection_id).unwrap(), 1000);
+    }
+
+    #[gpui::test]
+    async fn test_rate_limited_requests(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+        let (server_conn, client_conn, _kill) = Connection::in_memory(executor.clone());
+
+        let server = Peer::new(0);
+        server.set_rate_limit_policy(RateLimitPolicy {
+            default: None,
+            messages: [(
+                "Test".to_string(),
+                crate::RateLimit {
+                    per_second: 1,
+                    burst: 1,
+                },
+            )]
+            .into_iter()
+            .collect(),
+        });
+        let (server_conn_id, server_io, mut server_incoming) =
+            server.add_test_connection(server_conn, executor.clone());
+        let client = Peer::new(0);
+        let (client_conn_id, client_io, _client_incoming) =
+            client.add_test_connection(client_conn, executor.clone());
+        executor.spawn(server_io).detach();
+        executor.spawn(client_io).detach();
+        executor
+            .spawn({
+                let server = server.clone();
+                async move {
+                    while let Some(envelope) = server_incoming.next().await {
+                        let envelope = envelope.into_any();
+                        if let Some(envelope) =
+                            envelope.downcast_ref::<TypedEnvelope<proto::Test>>()
+                        {
+                            server
+                                .respond(envelope.receipt(), envelope.payload.clone())
+                                .unwrap();
+                        } else {
+                            panic!("unexpected message");
+                        }
+                    }
+                }
+            })
+            .detach();
+
+        assert_eq!(
+            client
+                .request(client_conn_id, proto::Test { id: 1 })
+                .await
+                .unwrap(),
+            proto::Test { id: 1 }
+        );
+
+        // Once the burst is used up, requests are rejected without reaching the
+        // handler, and the error says when to try again.
+        let error = client
+            .request(client_conn_id, proto::Test { id: 2 })
+            .await
+            .unwrap_err();
+        assert_eq!(error.error_code(), ErrorCode::RateLimited);
+        let retry_after = crate::retry_after(&error).unwrap();
+        assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(1));
+        assert_eq!(
+            server.rate_limit_stats(server_conn_id).unwrap(),
+            RateLimitStats {
+                rejected_requests: 1,
+                delayed_messages: 0,
+            }
+        );
+
+        // Changing the policy applies to existing connections.
+        server.set_rate_limit_policy(RateLimitPolicy::default());
+        assert_eq!(
+            client
+                .request(client_conn_id, proto::Test { id: 3 })
+                .await
+                .unwrap(),
+            proto::Test { id: 3 }
+        );
+
+        // Peers without a policy, like the client, don't limit anything.
+        assert_eq!(
+            client.rate_limit_stats(client_conn_id).unwrap(),
+            RateLimitStats::default()
+        );
+    }
+
+    #[gpui::test]
+    async fn test_delayed_messages(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+        let (server_conn, client_conn, _kill) = Connection::in_memory(executor.clone());
+
+        let server = Peer::new(0);
+        server.set_rate_limit_policy(RateLimitPolicy {
+            default: None,
+            messages: [(
+                "Test".to_string(),
+                crate::RateLimit {
+                    per_second: 1,
+                    burst: 1,
+                },
+            )]
+            .into_iter()
+            .collect(),
+        });
+        let (server_conn_id, server_io, server_incoming) =
+            server.add_test_connection(server_conn, executor.clone());
+        let client = Peer::new(0);
+        let (client_conn_id, client_io, client_incoming) =
+            client.add_test_connection(client_conn, executor.clone());
+        executor.spawn(server_io).detach();
+        executor.spawn(client_io).detach();
+
+        let received_ids = |incoming: BoxStream<'static, Box<dyn AnyTypedEnvelope>>| {
+            let ids = Arc::new(Mutex::new(Vec::new()));
+            executor
+                .spawn({
+                    let ids = ids.clone();
+                    incoming.for_each(move |envelope| {
+                        let envelope = envelope.into_any();
+                        let envelope = envelope.downcast_ref::<TypedEnvelope<proto::Test>>();
+                        ids.lock().push(envelope.unwrap().payload.id);
+                        future::ready(())
+                    })
+                })
+                .detach();
+            ids
+        };
+        let server_received = received_ids(server_incoming);
+        let client_received = received_ids(client_incoming);
+
+        for id in 1..=3 {
+            client.send(client_conn_id, proto::Test { id }).unwrap();
+        }
+        executor.run_until_parked();
+        assert_eq!(*server_received.lock(), [1]);
+
+        // The server keeps writing while it holds back what the client sent.
+        server.send(server_conn_id, proto::Test { id: 4 }).unwrap();
+        executor.run_until_parked();
+        assert_eq!(*client_received.lock(), [4]);
+
+        // Held-back messages are handled in the order they were sent.
+        executor.advance_clock(Duration::from_secs(1));
+        executor.run_until_parked();
+        assert_eq!(*server_received.lock(), [1, 2]);
+        executor.advance_clock(Duration::from_secs(2));
+        executor.run_until_parked();
+        assert_eq!(*server_received.lock(), [1, 2, 3]);
+        assert_eq!(
+            server.rate_limit_stats(server_conn_id).unwrap(),
+            RateLimitStats {
+                rejected_requests: 0,
+                delayed_messages: 2,
+            }
+        );
+    }
+
+    #[gpui::test]
+    async fn test_remote_routing(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+        let (server_conn, client_conn, _kill) = Connection::in_memory(executor.clone());
+
+        // The client is connected to the second server, and the first server routes
+        // the messages addressed to it through the second one.
+        let server_1 = Peer::new(1);
+        let server_2 = Peer::new(2);
+        for (server, other_server) in [(&server_1, &server_2), (&server_2, &server_1)] {
+            let other_server = Arc::downgrade(other_server);
+            let executor = executor.clone();
+            server.set_remote_router(Arc::new(move |receiver_id, routed| {
+                let other_server = other_server.upgrade().unwrap();
+                executor
+                    .spawn(other_server.deliver_routed(receiver_id, routed))
+                    .detach();
+                Ok(())
+            }));
+        }
+        let (connection_id, server_io, _server_incoming) =
+            server_2.add_test_connection(server_conn, executor.clone());
+        let client = Peer::new(0);
+        let (_, client_io, mut client_incoming) =
+            client.add_test_connection(client_conn, executor.clone());
+        executor.spawn(server_io).detach();
+        executor.spawn(client_io).detach();
+
+        server_1.send(connection_id, proto::Test { id: 1 }).unwrap();
+        let sender_id = ConnectionId { owner_id: 1, id: 5 };
+        server_1
+            .forward_send(sender_id, connection_id, proto::Test { id: 2 })
+            .unwrap();
+        for expected_id in [1, 2] {
+            let envelope = client_incoming.next().await.unwrap();
+            let envelope = envelope
+                .into_any()
+                .downcast::<TypedEnvelope<proto::Test>>()
+                .unwrap();
+            assert_eq!(envelope.payload, proto::Test { id: expected_id });
+            if expected_id == 2 {
+                assert_eq!(envelope.original_sender_id, Some(sender_id.into()));
+            }
+        }
+
+        // Requests are routed too, and so are their responses.
+        let response = executor.spawn(server_1.request(connection_id, proto::Test { id: 3 }));
+        let request = client_incoming
+            .next()
+            .await
+            .unwrap()
+            .into_any()
+            .downcast::<TypedEnvelope<proto::Test>>()
+            .unwrap();
+        assert_eq!(request.payload, proto::Test { id: 3 });
+        client
+            .respond(request.receipt(), proto::Test { id: 4 })
+            .unwrap();
+        assert_eq!(response.await.unwrap(), proto::Test { id: 4 });
+
+        // Requests fail once the peer they were routed to stops.
+        let response = executor.spawn(server_1.request(connection_id, proto::Test { id: 5 }));
+        client_incoming.next().await.unwrap();
+        server_1.fail_remote_requests(2);
+        assert_eq!(
+            response.await.unwrap_err().to_string(),
+            "connection was closed"
+        );
+
+        // Connections of the routing peer's own epoch are never routed.
+        let own_connection_id = ConnectionId { owner_id: 1, id: 0 };
+        assert!(server_1
+            .send(own_connection_id, proto::Test { id: 3 })
+            .is_err());
+    }
+
     #[gpui::test(iterations = 50)]
     async fn test_io_error(cx: &mut TestAppContext) {
         let executor = cx.executor();
@@ -932,4 +1975,41 @@ mod tests {
             "connection was closed"
         );
     }
+
+    #[gpui::test(iterations = 10)]
+    async fn test_unsupported_message(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+        let (client_conn, server_conn, _kill) = Connection::in_memory(executor.clone());
+
+        let client = Peer::new(0);
+        let (_, io_handler, mut incoming) =
+            client.add_test_connection(client_conn, executor.clone());
+        executor.spawn(io_handler).detach();
+        executor
+            .spawn(async move { incoming.next().await })
+            .detach();
+
+        // Pretend a peer speaking a newer protocol version sent a message of a type
+        // that this one doesn't know, which decodes without a payload.
+        let mut writer = proto::MessageStream::new(server_conn.tx);
+        let mut reader = proto::MessageStream::new(server_conn.rx);
+        writer
+            .write(proto::Message::Envelope(proto::Envelope {
+                id: 7,
+                ..Default::default()
+            }))
+            .await
+            .unwrap();
+
+        let response = loop {
+            if let proto::Message::Envelope(envelope) = reader.read().await.unwrap() {
+                break envelope;
+            }
+        };
+        assert_eq!(response.responding_to, Some(7));
+        let Some(proto::envelope::Payload::Error(error)) = response.payload else {
+            panic!("expected an error response, got {:?}", response.payload);
+        };
+        assert_eq!(error.code(), ErrorCode::UnsupportedMessage);
+    }
 }
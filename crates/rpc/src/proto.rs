@@ -12,9 +12,11 @@ use std::{
     cmp,
     fmt::Debug,
     io, iter,
+    path::{Path, PathBuf, MAIN_SEPARATOR},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use std::{fmt, mem};
+use util::paths::normalize_unicode;
 
 include!(concat!(env!("OUT_DIR"), "/zed.messages.rs"));
 
@@ -43,6 +45,7 @@ pub trait AnyTypedEnvelope: 'static + Send + Sync {
     fn payload_type_id(&self) -> TypeId;
     fn payload_type_name(&self) -> &'static str;
     fn as_any(&self) -> &dyn Any;
+    fn payload_mut(&mut self) -> &mut dyn Any;
     fn into_any(self: Box<Self>) -> Box<dyn Any + Send + Sync>;
     fn is_background(&self) -> bool;
     fn original_sender_id(&self) -> Option<PeerId>;
@@ -68,6 +71,10 @@ impl<T: EnvelopedMessage> AnyTypedEnvelope for TypedEnvelope<T> {
         self
     }
 
+    fn payload_mut(&mut self) -> &mut dyn Any {
+        &mut self.payload
+    }
+
     fn into_any(self: Box<Self>) -> Box<dyn Any + Send + Sync> {
         self
     }
@@ -133,25 +140,36 @@ impl fmt::Display for PeerId {
 }
 
 messages!(
+    (AcceptWaitingCall, Foreground),
     (Ack, Foreground),
     (AckBufferOperation, Background),
     (AckChannelMessage, Background),
     (AddNotification, Foreground),
+    (AddBookmark, Foreground),
+    (AddBookmarkResponse, Foreground),
     (AddProjectCollaborator, Foreground),
+    (AddReviewComment, Foreground),
+    (AddReviewCommentResponse, Foreground),
     (ApplyCodeAction, Background),
     (ApplyCodeActionResponse, Background),
     (ApplyCompletionAdditionalEdits, Background),
     (ApplyCompletionAdditionalEditsResponse, Background),
+    (BrokeredConnectionMessage, Foreground),
     (BufferReloaded, Foreground),
     (BufferSaved, Foreground),
     (Call, Foreground),
     (CallCanceled, Foreground),
     (CancelCall, Foreground),
     (ChannelMessageSent, Foreground),
+    (CloseBrokeredConnection, Foreground),
+    (CloseBuffer, Foreground),
+    (ConnectionLagging, Foreground),
     (CopyProjectEntry, Foreground),
     (CreateBufferForPeer, Foreground),
     (CreateChannel, Foreground),
     (CreateChannelResponse, Foreground),
+    (CreateGuest, Foreground),
+    (CreateGuestResponse, Foreground),
     (CreateProjectEntry, Foreground),
     (CreateRoom, Foreground),
     (CreateRoomResponse, Foreground),
@@ -162,11 +180,26 @@ messages!(
     (Error, Foreground),
     (ExpandProjectEntry, Foreground),
     (ExpandProjectEntryResponse, Foreground),
+    (ExtendRoom, Foreground),
     (Follow, Foreground),
     (FollowResponse, Foreground),
     (FormatBuffers, Foreground),
     (FormatBuffersResponse, Foreground),
     (FuzzySearchUsers, Foreground),
+    (GetBookmarks, Foreground),
+    (GetBookmarksResponse, Foreground),
+    (GetEditSuggestions, Foreground),
+    (GetEditSuggestionsResponse, Foreground),
+    (GetEntryDecorations, Foreground),
+    (GetEntryDecorationsResponse, Foreground),
+    (GetReview, Foreground),
+    (GetReviewResponse, Foreground),
+    (GetRoomMessages, Foreground),
+    (GetRoomMessagesResponse, Foreground),
+    (GetCallHistory, Foreground),
+    (GetCallHistoryResponse, Foreground),
+    (GetCallSummary, Foreground),
+    (GetCallSummaryResponse, Foreground),
     (GetChannelMembers, Foreground),
     (GetChannelMembersResponse, Foreground),
     (GetChannelMessages, Background),
@@ -182,6 +215,10 @@ messages!(
     (GetDocumentHighlightsResponse, Background),
     (GetHover, Background),
     (GetHoverResponse, Background),
+    (GetLanguageServerConfigurations, Background),
+    (GetLanguageServerConfigurationsResponse, Background),
+    (GetLinkedEditingRanges, Background),
+    (GetLinkedEditingRangesResponse, Background),
     (GetNotifications, Foreground),
     (GetNotificationsResponse, Foreground),
     (GetPrivateUserInfo, Foreground),
@@ -190,6 +227,8 @@ messages!(
     (GetProjectSymbolsResponse, Background),
     (GetReferences, Background),
     (GetReferencesResponse, Background),
+    (GetRoomProjects, Foreground),
+    (GetRoomProjectsResponse, Foreground),
     (GetTypeDefinition, Background),
     (GetTypeDefinitionResponse, Background),
     (GetUsers, Foreground),
@@ -211,10 +250,13 @@ messages!(
     (LeaveChannelChat, Foreground),
     (LeaveProject, Foreground),
     (LeaveRoom, Foreground),
+    (LoadWorktreeDirectory, Foreground),
+    (LoadWorktreeEntries, Foreground),
     (MarkNotificationRead, Foreground),
     (MoveChannel, Foreground),
     (OnTypeFormatting, Background),
     (OnTypeFormattingResponse, Background),
+    (OpenBrokeredConnection, Foreground),
     (OpenBufferById, Background),
     (OpenBufferByPath, Background),
     (OpenBufferForSymbol, Background),
@@ -231,30 +273,47 @@ messages!(
     (RejoinChannelBuffersResponse, Foreground),
     (RejoinRoom, Foreground),
     (RejoinRoomResponse, Foreground),
+    (RelayedAudioFrame, Background),
+    (RemoveBookmark, Foreground),
     (ReloadBuffers, Foreground),
     (ReloadBuffersResponse, Foreground),
+    (ReportRoomActivity, Foreground),
     (RemoveChannelMember, Foreground),
     (RemoveChannelMessage, Foreground),
     (RemoveContact, Foreground),
     (RemoveProjectCollaborator, Foreground),
+    (RoomPreflight, Foreground),
+    (RoomPreflightResponse, Foreground),
     (RenameChannel, Foreground),
     (RenameChannelResponse, Foreground),
     (RenameProjectEntry, Foreground),
+    (ReportUser, Foreground),
     (RequestContact, Foreground),
     (ResolveCompletionDocumentation, Background),
     (ResolveCompletionDocumentationResponse, Background),
     (ResolveInlayHint, Background),
     (ResolveInlayHintResponse, Background),
+    (ResolveReviewComment, Foreground),
+    (SuggestEdit, Foreground),
+    (SuggestEditResponse, Foreground),
     (RespondToChannelInvite, Foreground),
     (RespondToContactRequest, Foreground),
+    (RoomMessageReceived, Foreground),
+    (RoomCountdown, Foreground),
+    (RoomEnded, Foreground),
     (RoomUpdated, Foreground),
     (SaveBuffer, Foreground),
     (SetChannelMemberRole, Foreground),
+    (SetCollaboratorReadOnly, Foreground),
+    (SetDisplayName, Foreground),
     (SetChannelVisibility, Foreground),
     (SearchProject, Background),
     (SearchProjectResponse, Background),
     (SendChannelMessage, Background),
     (SendChannelMessageResponse, Background),
+    (SendRoomMessage, Foreground),
+    (SendRoomMessageResponse, Foreground),
+    (ServerRestarting, Foreground),
     (ShareProject, Foreground),
     (ShareProjectResponse, Foreground),
     (ShowContacts, Foreground),
@@ -263,24 +322,38 @@ messages!(
     (SynchronizeBuffersResponse, Foreground),
     (Test, Foreground),
     (Unfollow, Foreground),
+    (UnshareBufferForPeer, Foreground),
     (UnshareProject, Foreground),
+    (UpdateBookmarks, Foreground),
     (UpdateBuffer, Foreground),
     (UpdateBufferFile, Foreground),
     (UpdateChannelBuffer, Foreground),
     (UpdateChannelBufferCollaborators, Foreground),
+    (UpdateCollaboratorReadOnly, Foreground),
     (UpdateChannels, Foreground),
     (UpdateUserChannels, Foreground),
+    (UpdateUser, Foreground),
+    (UpdateContactNote, Foreground),
+    (SetLocationSharing, Foreground),
+    (SetUserBlocked, Foreground),
+    (UpdateWorkingLocation, Foreground),
     (UpdateContacts, Foreground),
     (UpdateDiagnosticSummary, Foreground),
     (UpdateDiffBase, Foreground),
+    (UpdateEditSuggestions, Foreground),
+    (UpdateEntryDecorations, Foreground),
     (UpdateFollowers, Foreground),
     (UpdateInviteInfo, Foreground),
     (UpdateLanguageServer, Foreground),
     (UpdateParticipantLocation, Foreground),
     (UpdateProject, Foreground),
     (UpdateProjectCollaborator, Foreground),
+    (UpdateReview, Foreground),
+    (UpdateScreenAnnotations, Foreground),
     (UpdateWorktree, Foreground),
     (UpdateWorktreeSettings, Foreground),
+    (UploadAvatar, Foreground),
+    (UploadAvatarResponse, Foreground),
     (UsersResponse, Foreground),
     (LspExtExpandMacro, Background),
     (LspExtExpandMacroResponse, Background),
@@ -288,6 +361,9 @@ messages!(
 );
 
 request_messages!(
+    (AcceptWaitingCall, JoinRoomResponse),
+    (AddBookmark, AddBookmarkResponse),
+    (AddReviewComment, AddReviewCommentResponse),
     (ApplyCodeAction, ApplyCodeActionResponse),
     (
         ApplyCompletionAdditionalEdits,
@@ -295,17 +371,23 @@ request_messages!(
     ),
     (Call, Ack),
     (CancelCall, Ack),
+    (CloseBuffer, Ack),
     (CopyProjectEntry, ProjectEntryResponse),
     (CreateChannel, CreateChannelResponse),
+    (CreateGuest, CreateGuestResponse),
     (CreateProjectEntry, ProjectEntryResponse),
     (CreateRoom, CreateRoomResponse),
     (DeclineCall, Ack),
     (DeleteChannel, Ack),
     (DeleteProjectEntry, ProjectEntryResponse),
     (ExpandProjectEntry, ExpandProjectEntryResponse),
+    (ExtendRoom, Ack),
     (Follow, FollowResponse),
     (FormatBuffers, FormatBuffersResponse),
     (FuzzySearchUsers, UsersResponse),
+    (GetBookmarks, GetBookmarksResponse),
+    (GetCallHistory, GetCallHistoryResponse),
+    (GetCallSummary, GetCallSummaryResponse),
     (GetChannelMembers, GetChannelMembersResponse),
     (GetChannelMessages, GetChannelMessagesResponse),
     (GetChannelMessagesById, GetChannelMessagesResponse),
@@ -313,11 +395,21 @@ request_messages!(
     (GetCompletions, GetCompletionsResponse),
     (GetDefinition, GetDefinitionResponse),
     (GetDocumentHighlights, GetDocumentHighlightsResponse),
+    (GetEditSuggestions, GetEditSuggestionsResponse),
+    (GetEntryDecorations, GetEntryDecorationsResponse),
     (GetHover, GetHoverResponse),
+    (
+        GetLanguageServerConfigurations,
+        GetLanguageServerConfigurationsResponse
+    ),
+    (GetLinkedEditingRanges, GetLinkedEditingRangesResponse),
     (GetNotifications, GetNotificationsResponse),
     (GetPrivateUserInfo, GetPrivateUserInfoResponse),
     (GetProjectSymbols, GetProjectSymbolsResponse),
     (GetReferences, GetReferencesResponse),
+    (GetReview, GetReviewResponse),
+    (GetRoomMessages, GetRoomMessagesResponse),
+    (GetRoomProjects, GetRoomProjectsResponse),
     (GetTypeDefinition, GetTypeDefinitionResponse),
     (GetUsers, UsersResponse),
     (IncomingCall, Ack),
@@ -330,9 +422,12 @@ request_messages!(
     (JoinRoom, JoinRoomResponse),
     (LeaveChannelBuffer, Ack),
     (LeaveRoom, Ack),
+    (LoadWorktreeDirectory, Ack),
+    (LoadWorktreeEntries, Ack),
     (MarkNotificationRead, Ack),
     (MoveChannel, Ack),
     (OnTypeFormatting, OnTypeFormattingResponse),
+    (OpenBrokeredConnection, Ack),
     (OpenBufferById, OpenBufferResponse),
     (OpenBufferByPath, OpenBufferResponse),
     (OpenBufferForSymbol, OpenBufferForSymbolResponse),
@@ -343,55 +438,77 @@ request_messages!(
     (RejoinChannelBuffers, RejoinChannelBuffersResponse),
     (RejoinRoom, RejoinRoomResponse),
     (ReloadBuffers, ReloadBuffersResponse),
+    (RemoveBookmark, Ack),
+    (ReportRoomActivity, Ack),
     (RemoveChannelMember, Ack),
     (RemoveChannelMessage, Ack),
     (RemoveContact, Ack),
     (RenameChannel, RenameChannelResponse),
+    (RoomPreflight, RoomPreflightResponse),
     (RenameProjectEntry, ProjectEntryResponse),
+    (ReportUser, Ack),
     (RequestContact, Ack),
     (
         ResolveCompletionDocumentation,
         ResolveCompletionDocumentationResponse
     ),
     (ResolveInlayHint, ResolveInlayHintResponse),
+    (ResolveReviewComment, Ack),
     (RespondToChannelInvite, Ack),
     (RespondToContactRequest, Ack),
     (SaveBuffer, BufferSaved),
     (SearchProject, SearchProjectResponse),
     (SendChannelMessage, SendChannelMessageResponse),
+    (SendRoomMessage, SendRoomMessageResponse),
     (SetChannelMemberRole, Ack),
     (SetChannelVisibility, Ack),
     (ShareProject, ShareProjectResponse),
+    (SuggestEdit, SuggestEditResponse),
     (SynchronizeBuffers, SynchronizeBuffersResponse),
     (Test, Test),
     (UpdateBuffer, Ack),
+    (UpdateContactNote, Ack),
+    (SetCollaboratorReadOnly, Ack),
+    (SetDisplayName, Ack),
+    (SetLocationSharing, Ack),
+    (SetUserBlocked, Ack),
     (UpdateParticipantLocation, Ack),
     (UpdateProject, Ack),
     (UpdateWorktree, Ack),
+    (UploadAvatar, UploadAvatarResponse),
     (LspExtExpandMacro, LspExtExpandMacroResponse),
     (SetRoomParticipantRole, Ack),
 );
 
 entity_messages!(
     {project_id, ShareProject},
+    AddBookmark,
     AddProjectCollaborator,
+    AddReviewComment,
     ApplyCodeAction,
     ApplyCompletionAdditionalEdits,
     BufferReloaded,
     BufferSaved,
+    CloseBuffer,
     CopyProjectEntry,
     CreateBufferForPeer,
     CreateProjectEntry,
     DeleteProjectEntry,
     ExpandProjectEntry,
     FormatBuffers,
+    GetBookmarks,
     GetCodeActions,
     GetCompletions,
     GetDefinition,
     GetDocumentHighlights,
+    GetEditSuggestions,
+    GetEntryDecorations,
     GetHover,
+    GetLanguageServerConfigurations,
+    GetLinkedEditingRanges,
     GetProjectSymbols,
     GetReferences,
+    GetReview,
     GetTypeDefinition,
     InlayHints,
     JoinProject,
@@ -404,22 +521,32 @@ entity_messages!(
     PrepareRename,
     RefreshInlayHints,
     ReloadBuffers,
+    RemoveBookmark,
     RemoveProjectCollaborator,
     RenameProjectEntry,
     ResolveCompletionDocumentation,
     ResolveInlayHint,
+    ResolveReviewComment,
     SaveBuffer,
     SearchProject,
+    SetCollaboratorReadOnly,
     StartLanguageServer,
+    SuggestEdit,
     SynchronizeBuffers,
+    UnshareBufferForPeer,
     UnshareProject,
+    UpdateBookmarks,
     UpdateBuffer,
     UpdateBufferFile,
+    UpdateCollaboratorReadOnly,
     UpdateDiagnosticSummary,
     UpdateDiffBase,
+    UpdateEditSuggestions,
+    UpdateEntryDecorations,
     UpdateLanguageServer,
     UpdateProject,
     UpdateProjectCollaborator,
+    UpdateReview,
     UpdateWorktree,
     UpdateWorktreeSettings,
     LspExtExpandMacro,
@@ -433,14 +560,57 @@ entity_messages!(
     UpdateChannelBufferCollaborators,
 );
 
+entity_messages!({room_id, Room}, RoomMessageReceived);
+
 const KIB: usize = 1024;
 const MIB: usize = KIB * 1024;
 const MAX_BUFFER_LEN: usize = MIB;
 
+/// The first protocol version whose peers can read envelopes that weren't compressed.
+pub const UNCOMPRESSED_ENVELOPES_PROTOCOL_VERSION: u32 = 69;
+
+/// Envelopes that encode to fewer bytes than this aren't worth compressing for peers
+/// that don't require it.
+pub const COMPRESSION_THRESHOLD: usize = 4 * KIB;
+
+/// Every zstd frame starts with these bytes. An encoded envelope never does, as its
+/// first byte would have to tag field 5 as a varint, and that field is a message.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Which envelopes are compressed before they're written to a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// Every envelope is compressed, as peers before
+    /// [`UNCOMPRESSED_ENVELOPES_PROTOCOL_VERSION`] can't read any other kind.
+    Always,
+    /// Only envelopes that encode to at least this many bytes are compressed.
+    AboveThreshold(usize),
+}
+
+impl Compression {
+    /// The compression to use when writing to a peer that speaks the given protocol
+    /// version.
+    pub fn for_protocol_version(protocol_version: u32) -> Self {
+        if protocol_version >= UNCOMPRESSED_ENVELOPES_PROTOCOL_VERSION {
+            Self::AboveThreshold(COMPRESSION_THRESHOLD)
+        } else {
+            Self::Always
+        }
+    }
+
+    fn applies_to(&self, encoded_len: usize) -> bool {
+        match self {
+            Self::Always => true,
+            Self::AboveThreshold(threshold) => encoded_len >= *threshold,
+        }
+    }
+}
+
 /// A stream of protobuf messages.
 pub struct MessageStream<S> {
     stream: S,
     encoding_buffer: Vec<u8>,
+    compression: Compression,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -456,12 +626,19 @@ impl<S> MessageStream<S> {
         Self {
             stream,
             encoding_buffer: Vec::new(),
+            compression: Compression::Always,
         }
     }
 
     pub fn inner_mut(&mut self) -> &mut S {
         &mut self.stream
     }
+
+    /// Changes which of the envelopes written from now on are compressed. Envelopes
+    /// are read whether they were compressed or not.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
 }
 
 impl<S> MessageStream<S>
@@ -481,9 +658,12 @@ where
                 message
                     .encode(&mut self.encoding_buffer)
                     .map_err(io::Error::from)?;
-                let buffer =
+                let buffer = if self.compression.applies_to(self.encoding_buffer.len()) {
                     zstd::stream::encode_all(self.encoding_buffer.as_slice(), COMPRESSION_LEVEL)
-                        .unwrap();
+                        .unwrap()
+                } else {
+                    self.encoding_buffer.clone()
+                };
 
                 self.encoding_buffer.clear();
                 self.encoding_buffer.shrink_to(MAX_BUFFER_LEN);
@@ -513,9 +693,14 @@ where
         while let Some(bytes) = self.stream.next().await {
             match bytes? {
                 WebSocketMessage::Binary(bytes) => {
-                    zstd::stream::copy_decode(bytes.as_slice(), &mut self.encoding_buffer).unwrap();
-                    let envelope = Envelope::decode(self.encoding_buffer.as_slice())
-                        .map_err(io::Error::from)?;
+                    let encoded = if bytes.starts_with(&ZSTD_MAGIC) {
+                        zstd::stream::copy_decode(bytes.as_slice(), &mut self.encoding_buffer)
+                            .unwrap();
+                        self.encoding_buffer.as_slice()
+                    } else {
+                        bytes.as_slice()
+                    };
+                    let envelope = Envelope::decode(encoded).map_err(io::Error::from)?;
 
                     self.encoding_buffer.clear();
                     self.encoding_buffer.shrink_to(MAX_BUFFER_LEN);
@@ -568,6 +753,97 @@ impl From<Nonce> for u128 {
     }
 }
 
+impl BrokeredConnectionMessage {
+    /// Wraps a message sent on a brokered connection, for the broker to relay. Close
+    /// frames aren't relayed, as brokered connections are closed with a
+    /// [`CloseBrokeredConnection`] message instead.
+    pub fn from_websocket_message(
+        brokered_connection_id: u64,
+        message: WebSocketMessage,
+    ) -> Option<Self> {
+        use brokered_connection_message::Kind;
+
+        let (kind, payload) = match message {
+            WebSocketMessage::Binary(payload) => (Kind::Binary, payload),
+            WebSocketMessage::Ping(payload) => (Kind::Ping, payload),
+            WebSocketMessage::Pong(payload) => (Kind::Pong, payload),
+            _ => return None,
+        };
+        Some(Self {
+            brokered_connection_id,
+            kind: kind as i32,
+            payload,
+        })
+    }
+
+    pub fn into_websocket_message(self) -> WebSocketMessage {
+        use brokered_connection_message::Kind;
+
+        match self.kind() {
+            Kind::Binary => WebSocketMessage::Binary(self.payload),
+            Kind::Ping => WebSocketMessage::Ping(self.payload),
+            Kind::Pong => WebSocketMessage::Pong(self.payload),
+        }
+    }
+}
+
+/// The separator of worktree-relative paths in messages, which collaborators
+/// on every platform convert their paths to and from.
+pub const PATH_SEPARATOR: char = '/';
+
+/// The character that stands in for a backslash in the file names of
+/// collaborators on Windows, where it separates paths. Unix allows backslashes
+/// in file names, and sends them as they are. This is the private-use
+/// character that WSL maps them to as well.
+const ESCAPED_BACKSLASH: char = '\u{f05c}';
+
+/// Converts a worktree-relative path to the form in which it's sent to
+/// collaborators, whose platform may separate paths differently.
+///
+/// Separators are converted, and file names are normalized to the composed
+/// Unicode form that most platforms use, so that a name spelled decomposed on
+/// macOS matches the same name elsewhere. Paths keep the case they have on the
+/// host, whose file system decides whether paths that differ only in case are
+/// the same.
+pub fn path_to_proto(path: &Path) -> String {
+    path_to_proto_with_separator(&normalize_unicode(path).to_string_lossy(), MAIN_SEPARATOR)
+}
+
+/// Converts a worktree-relative path received from a collaborator to a
+/// native path, with its file names in the composed Unicode form.
+pub fn path_from_proto(path: String) -> PathBuf {
+    let path = PathBuf::from(path_from_proto_with_separator(path, MAIN_SEPARATOR));
+    normalize_unicode(&path).into_owned()
+}
+
+fn path_to_proto_with_separator(path: &str, separator: char) -> String {
+    if separator == PATH_SEPARATOR {
+        path.to_string()
+    } else {
+        path.chars()
+            .map(|char| match char {
+                char if char == separator => PATH_SEPARATOR,
+                ESCAPED_BACKSLASH => '\\',
+                char => char,
+            })
+            .collect()
+    }
+}
+
+fn path_from_proto_with_separator(path: String, separator: char) -> String {
+    if separator == PATH_SEPARATOR {
+        path
+    } else {
+        path.chars()
+            .map(|char| match char {
+                PATH_SEPARATOR => separator,
+                '\\' => ESCAPED_BACKSLASH,
+                char => char,
+            })
+            .collect()
+    }
+}
+
 pub fn split_worktree_update(
     mut message: UpdateWorktree,
     max_chunk_size: usize,
@@ -615,6 +891,13 @@ pub fn split_worktree_update(
             Default::default()
         };
 
+        // Directories only count as loaded once all of their children have been sent.
+        let loaded_directories = if done_files {
+            mem::take(&mut message.loaded_directories)
+        } else {
+            Default::default()
+        };
+
         if done_files {
             updated_repositories.extend(mem::take(&mut repository_map).into_values());
         }
@@ -630,6 +913,8 @@ pub fn split_worktree_update(
             is_last_update: done_files && message.is_last_update,
             updated_repositories,
             removed_repositories,
+            loaded_directories,
+            all_directories_loaded: done_files && message.all_directories_loaded,
         })
     })
 }
@@ -670,6 +955,65 @@ mod tests {
         assert!(stream.encoding_buffer.capacity() <= MAX_BUFFER_LEN);
     }
 
+    #[gpui::test]
+    async fn test_compression() {
+        let small = Envelope {
+            id: 1,
+            payload: Some(envelope::Payload::Ping(Ping {})),
+            ..Default::default()
+        };
+        let large = Envelope {
+            id: 2,
+            payload: Some(envelope::Payload::UpdateWorktree(UpdateWorktree {
+                root_name: "abcdefg".repeat(1000),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Compression::for_protocol_version(UNCOMPRESSED_ENVELOPES_PROTOCOL_VERSION - 1),
+            Compression::Always
+        );
+
+        let (tx, mut rx) = futures::channel::mpsc::unbounded();
+        let mut sink = MessageStream::new(tx.sink_map_err(|_| anyhow!("")));
+        for compression in [
+            Compression::Always,
+            Compression::for_protocol_version(UNCOMPRESSED_ENVELOPES_PROTOCOL_VERSION),
+        ] {
+            sink.set_compression(compression);
+            sink.write(Message::Envelope(small.clone())).await.unwrap();
+            sink.write(Message::Envelope(large.clone())).await.unwrap();
+        }
+        drop(sink);
+
+        // Only small envelopes are left uncompressed, and only for peers that can
+        // read them.
+        let mut frames = Vec::new();
+        while let Some(WebSocketMessage::Binary(bytes)) = rx.next().await {
+            frames.push(bytes);
+        }
+        let compressed = frames
+            .iter()
+            .map(|bytes| bytes.starts_with(&ZSTD_MAGIC))
+            .collect::<Vec<_>>();
+        assert_eq!(compressed, [true, true, false, true]);
+        assert!(frames[3].len() < large.encoded_len());
+
+        let mut stream = MessageStream::new(futures::stream::iter(
+            frames
+                .into_iter()
+                .map(|bytes| anyhow::Ok(WebSocketMessage::Binary(bytes))),
+        ));
+        for expected in [&small, &large, &small, &large] {
+            match stream.read().await.unwrap() {
+                Message::Envelope(envelope) => assert_eq!(&envelope, expected),
+                message => panic!("unexpected message {message:?}"),
+            }
+        }
+    }
+
     #[gpui::test]
     fn test_converting_peer_id_from_and_to_u64() {
         let peer_id = PeerId {
@@ -693,4 +1037,58 @@ mod tests {
         };
         assert_eq!(PeerId::from_u64(peer_id.as_u64()), peer_id);
     }
+
+    #[test]
+    fn test_converting_paths_between_platforms() {
+        // A Windows host sends paths that a Unix guest can use, and vice versa.
+        let proto_path = path_to_proto_with_separator("src\\module\\lib.rs", '\\');
+        assert_eq!(proto_path, "src/module/lib.rs");
+        assert_eq!(
+            path_from_proto_with_separator(proto_path.clone(), '/'),
+            "src/module/lib.rs"
+        );
+        assert_eq!(
+            path_from_proto_with_separator(proto_path, '\\'),
+            "src\\module\\lib.rs"
+        );
+
+        // Windows also accepts forward slashes, so paths can mix both styles.
+        assert_eq!(
+            path_to_proto_with_separator("src/module\\Lib.rs", '\\'),
+            "src/module/Lib.rs"
+        );
+
+        // Backslashes in Unix file names don't become separators on Windows.
+        let proto_path = path_to_proto_with_separator("src/a\\b.rs", '/');
+        assert_eq!(proto_path, "src/a\\b.rs");
+        assert_eq!(
+            path_from_proto_with_separator(proto_path.clone(), '\\'),
+            "src\\a\u{f05c}b.rs"
+        );
+        assert_eq!(
+            path_to_proto_with_separator("src\\a\u{f05c}b.rs", '\\'),
+            proto_path
+        );
+
+        // Case is preserved in both directions.
+        assert_eq!(
+            path_from_proto_with_separator("Src/README.md".into(), '\\'),
+            "Src\\README.md"
+        );
+
+        // Paths round-trip on the current platform.
+        let path = Path::new("a").join("b").join("c.txt");
+        assert_eq!(path_to_proto(&path), "a/b/c.txt");
+        assert_eq!(path_from_proto(path_to_proto(&path)), path);
+
+        // File names are sent composed, whichever way they're spelled locally.
+        let decomposed = Path::new("re\u{301}sume\u{301}").join("c\u{327}a.txt");
+        let composed = Path::new("r\u{e9}sum\u{e9}").join("\u{e7}a.txt");
+        assert_eq!(path_to_proto(&decomposed), "r\u{e9}sum\u{e9}/\u{e7}a.txt");
+        assert_eq!(path_from_proto(path_to_proto(&decomposed)), composed);
+        assert_eq!(
+            path_from_proto("re\u{301}sume\u{301}/c\u{327}a.txt".into()),
+            composed
+        );
+    }
 }
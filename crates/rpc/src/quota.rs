@@ -0,0 +1,39 @@
+use crate::{ErrorCode, ErrorCodeExt, ErrorExt};
+
+/// The tag on [`ErrorCode::RoomFull`] and [`ErrorCode::TooManySharedProjects`] errors
+/// that says what the quota that was reached is.
+const QUOTA_TAG: &str = "limit";
+
+/// The header on the response to a connection rejected because the user already has
+/// as many connections as they can, whose value is how many that is.
+pub const CONNECTION_LIMIT_HEADER: &str = "x-zed-connection-limit";
+
+/// The error that requests are rejected with when they would go over a quota.
+pub fn quota_exceeded_error(code: ErrorCode, quota: usize) -> anyhow::Error {
+    code.message(format!("{code:?}: the limit is {quota}"))
+        .with_tag(QUOTA_TAG, &quota.to_string())
+        .anyhow()
+}
+
+/// The quota that a request went over, or `None` if the error is of some other kind.
+pub fn exceeded_quota(error: &anyhow::Error) -> Option<usize> {
+    match error.error_code() {
+        ErrorCode::RoomFull | ErrorCode::TooManySharedProjects => {
+            error.error_tag(QUOTA_TAG)?.parse().ok()
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_exceeded_error() {
+        let error = quota_exceeded_error(ErrorCode::RoomFull, 50);
+        assert_eq!(error.error_code(), ErrorCode::RoomFull);
+        assert_eq!(exceeded_quota(&error), Some(50));
+        assert_eq!(exceeded_quota(&ErrorCode::Forbidden.anyhow()), None);
+    }
+}
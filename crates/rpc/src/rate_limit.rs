@@ -0,0 +1,285 @@
+use crate::{proto, ErrorCode, ErrorCodeExt, ErrorExt, RpcError};
+use collections::HashMap;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::atomic::{AtomicU64, Ordering::SeqCst},
+    time::{Duration, Instant},
+};
+
+/// The tag on [`ErrorCode::RateLimited`] errors that says how many milliseconds
+/// to wait before sending the request again.
+const RETRY_AFTER_TAG: &str = "retry_after_ms";
+
+/// How many messages a connection may send in a burst, and how quickly that
+/// allowance is replenished.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub per_second: u32,
+    pub burst: u32,
+}
+
+/// The rate limits applied to the messages each connection sends.
+///
+/// Messages with a limit of their own are counted separately from the rest,
+/// which share the default limit. Nothing is limited by default.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimitPolicy {
+    #[serde(default)]
+    pub default: Option<RateLimit>,
+    /// Limits for individual message types, keyed by name, such as `UpdateWorktree`.
+    #[serde(default)]
+    pub messages: HashMap<String, RateLimit>,
+}
+
+/// How many of a connection's messages were rate limited.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RateLimitStats {
+    /// Requests that were rejected with [`ErrorCode::RateLimited`].
+    pub rejected_requests: u64,
+    /// Other messages, which can't be rejected, that were held back instead.
+    pub delayed_messages: u64,
+}
+
+/// What should happen to a message that a connection sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RateLimitAction {
+    Handle,
+    /// Respond to the request with [`rate_limited_error`].
+    Reject {
+        retry_after: Duration,
+    },
+    /// Stop reading from the connection for this long before handling the message,
+    /// so that the sender is slowed down by backpressure.
+    Delay(Duration),
+}
+
+/// Tracks the messages a single connection sends against a [`RateLimitPolicy`].
+pub(crate) struct RateLimiter {
+    policy: Mutex<RateLimitPolicy>,
+    buckets: Mutex<HashMap<Option<&'static str>, TokenBucket>>,
+    rejected_requests: AtomicU64,
+    delayed_messages: AtomicU64,
+}
+
+impl RateLimiter {
+    pub fn new(policy: RateLimitPolicy) -> Self {
+        Self {
+            policy: Mutex::new(policy),
+            buckets: Default::default(),
+            rejected_requests: Default::default(),
+            delayed_messages: Default::default(),
+        }
+    }
+
+    /// Counts a message that the connection sent. Requests are rejected once the
+    /// limit is reached, while other messages go into debt and are held back until
+    /// it's paid off. Responses are never limited.
+    pub fn check(&self, envelope: &proto::Envelope, now: Instant) -> RateLimitAction {
+        if envelope.responding_to.is_some() {
+            return RateLimitAction::Handle;
+        }
+        let Some(name) = proto::envelope_payload_name(envelope) else {
+            return RateLimitAction::Handle;
+        };
+        let policy = self.policy.lock();
+        let (key, limit) = match policy.messages.get(name) {
+            Some(limit) => (Some(name), *limit),
+            None => match policy.default {
+                Some(limit) => (None, limit),
+                None => return RateLimitAction::Handle,
+            },
+        };
+        drop(policy);
+
+        let mut buckets = self.buckets.lock();
+        let bucket = buckets
+            .entry(key)
+            .or_insert_with(|| TokenBucket::new(limit, now));
+        if proto::is_request(envelope) {
+            match bucket.try_acquire(now) {
+                Ok(()) => RateLimitAction::Handle,
+                Err(retry_after) => {
+                    self.rejected_requests.fetch_add(1, SeqCst);
+                    RateLimitAction::Reject { retry_after }
+                }
+            }
+        } else {
+            let delay = bucket.acquire(now);
+            if delay.is_zero() {
+                RateLimitAction::Handle
+            } else {
+                self.delayed_messages.fetch_add(1, SeqCst);
+                RateLimitAction::Delay(delay)
+            }
+        }
+    }
+
+    /// Replaces the policy, giving every message type a full allowance under its
+    /// new limit.
+    pub fn set_policy(&self, policy: RateLimitPolicy) {
+        let mut current_policy = self.policy.lock();
+        if *current_policy != policy {
+            *current_policy = policy;
+            self.buckets.lock().clear();
+        }
+    }
+
+    pub fn stats(&self) -> RateLimitStats {
+        RateLimitStats {
+            rejected_requests: self.rejected_requests.load(SeqCst),
+            delayed_messages: self.delayed_messages.load(SeqCst),
+        }
+    }
+}
+
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            updated_at: now,
+        }
+    }
+
+    /// Takes a token if one is available, or returns how long until one will be.
+    fn try_acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1. {
+            self.tokens -= 1.;
+            Ok(())
+        } else {
+            Err(self.time_to_refill(1. - self.tokens))
+        }
+    }
+
+    /// Takes a token even if none is available, returning how long until the
+    /// bucket is no longer in debt.
+    fn acquire(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= 1.;
+        if self.tokens >= 0. {
+            Duration::ZERO
+        } else {
+            self.time_to_refill(-self.tokens)
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.limit.per_second as f64)
+            .min(self.limit.burst as f64);
+        self.updated_at = now;
+    }
+
+    fn time_to_refill(&self, tokens: f64) -> Duration {
+        Duration::try_from_secs_f64(tokens / self.limit.per_second as f64).unwrap_or(Duration::MAX)
+    }
+}
+
+/// The error that rate limited requests are rejected with, which says how long to
+/// wait before retrying.
+pub fn rate_limited_error(retry_after: Duration) -> proto::Error {
+    ErrorCode::RateLimited
+        .message("too many requests".to_string())
+        .with_tag(RETRY_AFTER_TAG, &retry_after.as_millis().to_string())
+        .to_proto()
+}
+
+/// How long to wait before retrying a request that failed because it was rate
+/// limited, or `None` if the error is of some other kind.
+pub fn retry_after(error: &anyhow::Error) -> Option<Duration> {
+    let error = error.downcast_ref::<RpcError>()?;
+    if error.error_code() != ErrorCode::RateLimited {
+        return None;
+    }
+    let retry_after_ms = error.error_tag(RETRY_AFTER_TAG)?.parse().ok()?;
+    Some(Duration::from_millis(retry_after_ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::EnvelopedMessage as _;
+
+    #[test]
+    fn test_rate_limits() {
+        let limiter = RateLimiter::new(RateLimitPolicy {
+            default: Some(RateLimit {
+                per_second: 10,
+                burst: 2,
+            }),
+            messages: [(
+                "UpdateDiagnosticSummary".to_string(),
+                RateLimit {
+                    per_second: 1,
+                    burst: 1,
+                },
+            )]
+            .into_iter()
+            .collect(),
+        });
+        let request = proto::Test { id: 1 }.into_envelope(0, None, None);
+        let update_diagnostics =
+            proto::UpdateDiagnosticSummary::default().into_envelope(1, None, None);
+        let response = proto::Ack {}.into_envelope(2, Some(0), None);
+        let start = Instant::now();
+
+        // Requests are rejected once the burst is used up, until tokens refill.
+        assert_eq!(limiter.check(&request, start), RateLimitAction::Handle);
+        assert_eq!(limiter.check(&request, start), RateLimitAction::Handle);
+        assert_eq!(
+            limiter.check(&request, start),
+            RateLimitAction::Reject {
+                retry_after: Duration::from_millis(100)
+            }
+        );
+        assert_eq!(
+            limiter.check(&request, start + Duration::from_millis(100)),
+            RateLimitAction::Handle
+        );
+
+        // Messages with their own limit don't count against the default one, and
+        // are held back rather than rejected.
+        assert_eq!(
+            limiter.check(&update_diagnostics, start),
+            RateLimitAction::Handle
+        );
+        assert_eq!(
+            limiter.check(&update_diagnostics, start),
+            RateLimitAction::Delay(Duration::from_secs(1))
+        );
+        assert_eq!(
+            limiter.check(&update_diagnostics, start),
+            RateLimitAction::Delay(Duration::from_secs(2))
+        );
+
+        // Messages that aren't limited at all are always handled.
+        let unlimited = RateLimiter::new(RateLimitPolicy::default());
+        for _ in 0..100 {
+            assert_eq!(unlimited.check(&request, start), RateLimitAction::Handle);
+        }
+        assert_eq!(limiter.check(&response, start), RateLimitAction::Handle);
+
+        assert_eq!(
+            limiter.stats(),
+            RateLimitStats {
+                rejected_requests: 1,
+                delayed_messages: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let error = RpcError::from_proto(&rate_limited_error(Duration::from_millis(250)), "Test");
+        assert_eq!(retry_after(&error), Some(Duration::from_millis(250)));
+        assert_eq!(retry_after(&ErrorCode::Forbidden.anyhow()), None);
+    }
+}
@@ -1,14 +1,28 @@
 pub mod auth;
+mod bandwidth;
 mod conn;
+mod connection_health;
 mod error;
+mod migration;
 mod notification;
 mod peer;
 pub mod proto;
+mod quota;
+mod rate_limit;
+mod upgrade;
 
+pub use bandwidth::{BandwidthUsage, MessageCategory};
 pub use conn::Connection;
+pub use connection_health::{BacklogPolicy, ConnectionQuality};
 pub use error::*;
+pub use migration::Migrations;
 pub use notification::*;
 pub use peer::*;
+pub use quota::{exceeded_quota, quota_exceeded_error, CONNECTION_LIMIT_HEADER};
+pub use rate_limit::{rate_limited_error, retry_after, RateLimit, RateLimitPolicy, RateLimitStats};
+pub use upgrade::*;
 mod macros;
 
-pub const PROTOCOL_VERSION: u32 = 68;
+pub const PROTOCOL_VERSION: u32 = 69;
+/// The oldest protocol version the server still accepts connections from.
+pub const MIN_PROTOCOL_VERSION: u32 = 68;
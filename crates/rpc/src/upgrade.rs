@@ -0,0 +1,119 @@
+use util::SemanticVersion;
+
+pub const MIN_PROTOCOL_VERSION_HEADER: &str = "x-zed-minimum-protocol-version";
+pub const MIN_APP_VERSION_HEADER: &str = "x-zed-minimum-app-version";
+pub const RELEASE_NOTES_URL_HEADER: &str = "x-zed-release-notes-url";
+
+/// Sent by the server alongside an "upgrade required" response to tell the client
+/// which versions it still accepts. Servers that predate these headers leave every
+/// field empty.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UpgradeRequired {
+    pub min_protocol_version: Option<u32>,
+    pub min_app_version: Option<SemanticVersion>,
+    pub release_notes_url: Option<String>,
+}
+
+impl UpgradeRequired {
+    pub fn to_headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = Vec::new();
+        if let Some(version) = self.min_protocol_version {
+            headers.push((MIN_PROTOCOL_VERSION_HEADER, version.to_string()));
+        }
+        if let Some(version) = self.min_app_version {
+            headers.push((MIN_APP_VERSION_HEADER, version.to_string()));
+        }
+        if let Some(url) = &self.release_notes_url {
+            headers.push((RELEASE_NOTES_URL_HEADER, url.clone()));
+        }
+        headers
+    }
+
+    pub fn from_headers<'a>(header: impl Fn(&str) -> Option<&'a str>) -> Self {
+        Self {
+            min_protocol_version: header(MIN_PROTOCOL_VERSION_HEADER)
+                .and_then(|version| version.parse().ok()),
+            min_app_version: header(MIN_APP_VERSION_HEADER)
+                .and_then(|version| version.parse().ok()),
+            release_notes_url: header(RELEASE_NOTES_URL_HEADER).map(str::to_string),
+        }
+    }
+}
+
+/// The protocol version spoken by servers from before they announced theirs.
+const UNANNOUNCED_PROTOCOL_VERSION: u32 = 68;
+
+/// Settles on the protocol version to speak with a server that announced it speaks
+/// `server_version` and accepts nothing older than `server_min_version`. Messages are
+/// converted to and from the older of the two versions, unless this client is too old
+/// for the server, in which case it needs to be upgraded. Servers that announce no
+/// version are older ones, which speak version 68.
+pub fn negotiate_protocol_version(
+    server_version: u32,
+    server_min_version: u32,
+) -> Result<u32, UpgradeRequired> {
+    if server_version == 0 {
+        return Ok(UNANNOUNCED_PROTOCOL_VERSION);
+    }
+    if crate::PROTOCOL_VERSION < server_min_version {
+        return Err(UpgradeRequired {
+            min_protocol_version: Some(server_min_version),
+            ..Default::default()
+        });
+    }
+    Ok(server_version.min(crate::PROTOCOL_VERSION))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::HashMap;
+
+    #[test]
+    fn test_headers_round_trip() {
+        let upgrade_required = UpgradeRequired {
+            min_protocol_version: Some(68),
+            min_app_version: Some("0.125.1".parse().unwrap()),
+            release_notes_url: Some("https://zed.dev/releases/stable/0.125.1".into()),
+        };
+        let headers = upgrade_required
+            .to_headers()
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        assert_eq!(
+            UpgradeRequired::from_headers(|name| headers.get(name).map(String::as_str)),
+            upgrade_required
+        );
+
+        let headers = HashMap::from_iter([(MIN_APP_VERSION_HEADER, "not-a-version".to_string())]);
+        assert_eq!(
+            UpgradeRequired::from_headers(|name| headers.get(name).map(String::as_str)),
+            UpgradeRequired::default()
+        );
+    }
+
+    #[test]
+    fn test_negotiate_protocol_version() {
+        let version = crate::PROTOCOL_VERSION;
+        assert_eq!(negotiate_protocol_version(version, version), Ok(version));
+        assert_eq!(
+            negotiate_protocol_version(version - 1, version - 1),
+            Ok(version - 1)
+        );
+        assert_eq!(
+            negotiate_protocol_version(version + 1, version),
+            Ok(version)
+        );
+        assert_eq!(
+            negotiate_protocol_version(0, 0),
+            Ok(UNANNOUNCED_PROTOCOL_VERSION)
+        );
+        assert_eq!(
+            negotiate_protocol_version(version + 2, version + 1),
+            Err(UpgradeRequired {
+                min_protocol_version: Some(version + 1),
+                ..Default::default()
+            })
+        );
+    }
+}
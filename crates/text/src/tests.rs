@@ -691,6 +691,29 @@ fn test_concurrent_edits() {
     assert_eq!(buffer3.text(), "a12c34e56");
 }
 
+#[test]
+fn test_ranges_inserted_by() {
+    let mut buffer1 = Buffer::new(1, BufferId::new(1).unwrap(), "abcdef".into());
+    let mut buffer2 = Buffer::new(2, BufferId::new(1).unwrap(), "abcdef".into());
+    let base_version = buffer1.version();
+
+    buffer1.apply_op(buffer2.edit([(1..2, "12")])).unwrap();
+    buffer1.edit([(5..5, "xy")]);
+    buffer1.apply_op(buffer2.edit([(3..3, "34")])).unwrap();
+    assert_eq!(buffer1.text(), "a1234cdxyef");
+    assert_eq!(buffer1.ranges_inserted_by(2, &base_version), [1..5]);
+    assert_eq!(buffer1.ranges_inserted_by(1, &base_version), [7..9]);
+
+    // Deleted text and text inserted before the given version are excluded.
+    let version = buffer1.version();
+    buffer1.edit([(2..3, ""), (10..10, "z")]);
+    buffer1.apply_op(buffer2.edit([(0..0, "5")])).unwrap();
+    assert_eq!(buffer1.text(), "5a134cdxyezf");
+    assert_eq!(buffer1.ranges_inserted_by(2, &base_version), [0..1, 2..5]);
+    assert_eq!(buffer1.ranges_inserted_by(2, &version), [0..1]);
+    assert_eq!(buffer1.ranges_inserted_by(1, &version), [10..11]);
+}
+
 #[gpui::test(iterations = 100)]
 fn test_random_concurrent_edits(mut rng: StdRng) {
     let peers = env::var("PEERS")
@@ -2038,6 +2038,32 @@ impl BufferSnapshot {
         self.edits_since_in_range(since, Anchor::MIN..Anchor::MAX)
     }
 
+    /// Returns the ranges of visible text that the given replica inserted in edits
+    /// that the given version hasn't observed, merging ranges that touch.
+    pub fn ranges_inserted_by(
+        &self,
+        replica_id: ReplicaId,
+        since: &clock::Global,
+    ) -> Vec<Range<usize>> {
+        let mut ranges = Vec::<Range<usize>>::new();
+        let mut offset = 0;
+        for fragment in self.fragments.iter().filter(|fragment| fragment.visible) {
+            let fragment_range = offset..offset + fragment.len;
+            offset = fragment_range.end;
+            if fragment.timestamp.replica_id != replica_id || since.observed(fragment.timestamp) {
+                continue;
+            }
+
+            match ranges.last_mut() {
+                Some(last_range) if last_range.end == fragment_range.start => {
+                    last_range.end = fragment_range.end;
+                }
+                _ => ranges.push(fragment_range),
+            }
+        }
+        ranges
+    }
+
     pub fn anchored_edits_since<'a, D>(
         &'a self,
         since: &'a clock::Global,
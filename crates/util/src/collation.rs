@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, iter};
+use unicode_normalization::UnicodeNormalization;
+
+/// How to order strings that are shown to people, such as user names and symbols.
+///
+/// Every collation is a total order that only considers two strings equal if
+/// they're identical, so lists sorted on different machines come out the same.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Collation {
+    /// Orders strings the way most locales order names: ignoring case and
+    /// accents at first, then putting unaccented letters before accented ones,
+    /// and lowercase letters before uppercase ones.
+    #[default]
+    Natural,
+    /// Orders strings by their Unicode code points, like [`str::cmp`].
+    Codepoint,
+    /// Orders strings like [`Collation::Natural`], except for the letters that the
+    /// locale's alphabet treats as letters of their own, rather than accented ones.
+    Locale(Locale),
+}
+
+/// A locale whose alphabet has letters that [`Collation::Natural`] would order as
+/// accented forms of other letters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Locale {
+    /// Danish and Norwegian, which put æ, ø and å after z.
+    Danish,
+    /// Swedish and Finnish, which put å, ä and ö after z.
+    Swedish,
+    /// Spanish, which puts ñ after n.
+    Spanish,
+}
+
+impl Collation {
+    /// The collation for a locale, given as a language tag such as `sv-SE` or
+    /// `nb_NO.UTF-8`. The `C` and `POSIX` locales order strings by code point, and
+    /// locales without letters of their own order them naturally.
+    pub fn for_locale(tag: &str) -> Self {
+        let tag = tag.trim();
+        if tag.eq_ignore_ascii_case("c") || tag.eq_ignore_ascii_case("posix") {
+            return Collation::Codepoint;
+        }
+        let language = tag
+            .split(|c| matches!(c, '-' | '_' | '.' | '@'))
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match language.as_str() {
+            "da" | "nb" | "nn" | "no" => Collation::Locale(Locale::Danish),
+            "sv" | "fi" => Collation::Locale(Locale::Swedish),
+            "es" => Collation::Locale(Locale::Spanish),
+            _ => Collation::Natural,
+        }
+    }
+
+    pub fn compare(self, a: &str, b: &str) -> Ordering {
+        let locale = match self {
+            Collation::Natural => None,
+            Collation::Codepoint => return a.cmp(b),
+            Collation::Locale(locale) => Some(locale),
+        };
+        primary_key(a, locale)
+            .cmp(primary_key(b, locale))
+            .then_with(|| secondary_key(a).cmp(secondary_key(b)))
+            .then_with(|| tertiary_key(a).cmp(tertiary_key(b)))
+            .then_with(|| a.cmp(b))
+    }
+}
+
+impl Locale {
+    /// Where the letter comes in the locale's alphabet, as the letter it comes
+    /// after and its position among the letters that come after that one, if the
+    /// alphabet treats it as a letter of its own.
+    fn letter(self, c: char) -> Option<(char, u8)> {
+        let c = c.to_lowercase().next()?;
+        match (self, c) {
+            (Locale::Danish, 'æ' | 'ä') => Some(('z', 1)),
+            (Locale::Danish, 'ø' | 'ö') => Some(('z', 2)),
+            (Locale::Danish, 'å') => Some(('z', 3)),
+            (Locale::Swedish, 'å') => Some(('z', 1)),
+            (Locale::Swedish, 'ä' | 'æ') => Some(('z', 2)),
+            (Locale::Swedish, 'ö' | 'ø') => Some(('z', 3)),
+            (Locale::Spanish, 'ñ') => Some(('n', 1)),
+            _ => None,
+        }
+    }
+}
+
+/// Compares two strings with the [default collation](Collation::Natural).
+pub fn collate(a: &str, b: &str) -> Ordering {
+    Collation::default().compare(a, b)
+}
+
+/// The string's letters, without their accents or case, except for the letters
+/// that the locale orders on their own.
+fn primary_key(text: &str, locale: Option<Locale>) -> impl Iterator<Item = (char, u8)> + '_ {
+    text.nfc().flat_map(move |c| {
+        let letter = locale.and_then(|locale| locale.letter(c));
+        let base_letters = letter.is_none().then(|| {
+            iter::once(c)
+                .nfd()
+                .filter(|c| !is_combining_mark(*c))
+                .flat_map(char::to_lowercase)
+                .map(|c| (c, 0))
+        });
+        letter.into_iter().chain(base_letters.into_iter().flatten())
+    })
+}
+
+/// The string's letters and accents, without their case.
+fn secondary_key(text: &str) -> impl Iterator<Item = char> + '_ {
+    text.nfd().flat_map(char::to_lowercase)
+}
+
+/// The string's letters with their accents and case, where a lowercase letter
+/// comes before its uppercase form.
+fn tertiary_key(text: &str) -> impl Iterator<Item = (char, bool)> + '_ {
+    text.nfd()
+        .flat_map(|c| c.to_lowercase().map(move |lower| (lower, lower != c)))
+}
+
+fn is_combining_mark(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036F}'
+            | '\u{1AB0}'..='\u{1AFF}'
+            | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{20D0}'..='\u{20FF}'
+            | '\u{FE20}'..='\u{FE2F}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_natural_collation() {
+        let mut names = vec![
+            "zed", "Émile", "bob", "Alice", "emile", "alice", "Bob", "éa",
+        ];
+        names.sort_by(|a, b| collate(a, b));
+        assert_eq!(
+            names,
+            ["alice", "Alice", "bob", "Bob", "éa", "emile", "Émile", "zed"]
+        );
+
+        // Composed and decomposed spellings are ordered next to each other, but
+        // aren't considered equal.
+        assert_eq!(collate("caf\u{e9}", "cafe\u{301}"), Ordering::Greater);
+        assert_eq!(collate("caf\u{e9}", "caf\u{e9}"), Ordering::Equal);
+
+        let mut names = vec!["zed", "Alice", "bob"];
+        names.sort_by(|a, b| Collation::for_locale("C").compare(a, b));
+        assert_eq!(names, ["Alice", "bob", "zed"]);
+    }
+
+    #[test]
+    fn test_locale_collation() {
+        assert_eq!(
+            Collation::for_locale("sv-SE"),
+            Collation::Locale(Locale::Swedish)
+        );
+        assert_eq!(
+            Collation::for_locale("nb_NO.UTF-8"),
+            Collation::Locale(Locale::Danish)
+        );
+        assert_eq!(Collation::for_locale("POSIX"), Collation::Codepoint);
+        assert_eq!(Collation::for_locale("en-US"), Collation::Natural);
+
+        let mut names = vec!["Östen", "zoe", "Åsa", "anna", "Ärla", "oskar"];
+        names.sort_by(|a, b| Collation::for_locale("sv").compare(a, b));
+        assert_eq!(names, ["anna", "oskar", "zoe", "Åsa", "Ärla", "Östen"]);
+        names.sort_by(|a, b| Collation::for_locale("da").compare(a, b));
+        assert_eq!(names, ["anna", "oskar", "zoe", "Ärla", "Östen", "Åsa"]);
+        names.sort_by(|a, b| collate(a, b));
+        assert_eq!(names, ["anna", "Ärla", "Åsa", "oskar", "Östen", "zoe"]);
+
+        // Decomposed spellings of a locale's letters are ordered like composed ones.
+        let mut names = vec!["ñu", "oro", "n\u{303}a", "nube"];
+        names.sort_by(|a, b| Collation::for_locale("es").compare(a, b));
+        assert_eq!(names, ["nube", "n\u{303}a", "ñu", "oro"]);
+    }
+}
@@ -3,6 +3,7 @@ pub use anyhow::{anyhow, Result};
 use futures::future::BoxFuture;
 use isahc::config::{Configurable, RedirectPolicy};
 pub use isahc::{
+    error::ErrorKind,
     http::{Method, StatusCode, Uri},
     Error,
 };
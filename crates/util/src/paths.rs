@@ -1,10 +1,12 @@
 use std::{
+    borrow::Cow,
     ffi::OsStr,
     path::{Path, PathBuf},
 };
 
 use globset::{Glob, GlobMatcher};
 use serde::{Deserialize, Serialize};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
 
 lazy_static::lazy_static! {
     pub static ref HOME: PathBuf = dirs::home_dir().expect("failed to determine home directory");
@@ -12,6 +14,7 @@ lazy_static::lazy_static! {
     pub static ref CONVERSATIONS_DIR: PathBuf = CONFIG_DIR.join("conversations");
     pub static ref EMBEDDINGS_DIR: PathBuf = CONFIG_DIR.join("embeddings");
     pub static ref THEMES_DIR: PathBuf = CONFIG_DIR.join("themes");
+    pub static ref PROJECT_TEMPLATES_DIR: PathBuf = CONFIG_DIR.join("templates");
     pub static ref LOGS_DIR: PathBuf = if cfg!(target_os = "macos") {
         HOME.join("Library/Logs/Zed")
     } else {
@@ -27,6 +30,7 @@ lazy_static::lazy_static! {
     pub static ref COPILOT_DIR: PathBuf = SUPPORT_DIR.join("copilot");
     pub static ref DEFAULT_PRETTIER_DIR: PathBuf = SUPPORT_DIR.join("prettier");
     pub static ref DB_DIR: PathBuf = SUPPORT_DIR.join("db");
+    pub static ref SCRATCH_PROJECTS_DIR: PathBuf = SUPPORT_DIR.join("scratch");
     pub static ref CRASHES_DIR: PathBuf = if cfg!(target_os = "macos") {
         HOME.join("Library/Logs/DiagnosticReports")
     } else {
@@ -43,6 +47,7 @@ lazy_static::lazy_static! {
     pub static ref LOG: PathBuf = LOGS_DIR.join("Zed.log");
     pub static ref OLD_LOG: PathBuf = LOGS_DIR.join("Zed.log.old");
     pub static ref LOCAL_SETTINGS_RELATIVE_PATH: &'static Path = Path::new(".zed/settings.json");
+    pub static ref LOCAL_COLLAB_POLICY_RELATIVE_PATH: &'static Path = Path::new(".zed/collab.toml");
 }
 
 pub trait PathExt {
@@ -122,6 +127,24 @@ impl<T: AsRef<Path>> PathExt for T {
     }
 }
 
+/// Spells the path's file names in Unicode normalization form C. macOS stores file
+/// names decomposed, while other platforms usually store them composed, so the same
+/// name can be spelled differently on two machines.
+pub fn normalize_unicode(path: &Path) -> Cow<'_, Path> {
+    match path.to_str() {
+        Some(path_str) if !is_nfc(path_str) => {
+            Cow::Owned(PathBuf::from(path_str.nfc().collect::<String>()))
+        }
+        _ => Cow::Borrowed(path),
+    }
+}
+
+/// Returns whether two paths name the same file, even if their file names are
+/// spelled in different Unicode normalization forms.
+pub fn paths_equal_normalized(a: &Path, b: &Path) -> bool {
+    a == b || normalize_unicode(a) == normalize_unicode(b)
+}
+
 /// A delimiter to use in `path_query:row_number:column_number` strings parsing.
 pub const FILE_ROW_COLUMN_DELIMITER: char = ':';
 
@@ -466,4 +489,17 @@ mod tests {
             "Path matcher {path_matcher} should match {path:?}"
         );
     }
+
+    #[test]
+    fn test_normalize_unicode() {
+        let composed = Path::new("caf\u{e9}/na\u{ef}ve.txt");
+        let decomposed = Path::new("cafe\u{301}/nai\u{308}ve.txt");
+        assert_eq!(normalize_unicode(decomposed), composed);
+        assert!(matches!(normalize_unicode(composed), Cow::Borrowed(_)));
+        assert!(paths_equal_normalized(composed, decomposed));
+        assert!(!paths_equal_normalized(
+            composed,
+            Path::new("cafe/naive.txt")
+        ));
+    }
 }
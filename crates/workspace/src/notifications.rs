@@ -1,4 +1,5 @@
 use crate::{Toast, Workspace};
+use client::{proto::ErrorCode, ErrorExt as _};
 use collections::HashMap;
 use gpui::{
     AnyView, AppContext, AsyncWindowContext, DismissEvent, Entity, EntityId, EventEmitter, Global,
@@ -6,6 +7,26 @@ use gpui::{
 };
 use std::{any::TypeId, ops::DerefMut};
 
+/// What to tell users when the server turned their request away because it's overloaded.
+pub const OVERLOADED_MESSAGE: &str =
+    "Zed's servers are busy right now. Please try again in a few minutes.";
+
+/// What to tell users when the server turned their request away because it would
+/// go over one of its quotas, if that's why it failed.
+pub fn quota_exceeded_message(error: &anyhow::Error) -> Option<String> {
+    let limit = client::exceeded_quota(error)?;
+    match error.error_code() {
+        ErrorCode::RoomFull => Some(format!(
+            "This call already has {limit} participants, which is as many as it can have."
+        )),
+        ErrorCode::TooManySharedProjects => Some(format!(
+            "{limit} projects are already shared in this call, which is as many as it can \
+            have. Unshare one, then try again."
+        )),
+        _ => None,
+    }
+}
+
 pub fn init(cx: &mut AppContext) {
     cx.set_global(NotificationTracker::new());
 }
@@ -335,7 +356,11 @@ where
                 log::error!("{err:?}");
                 if let Ok(prompt) = cx.update(|cx| {
                     let detail = f(&err, cx)
-                        .unwrap_or_else(|| format!("{err:?}. Please try again.", err = err));
+                        .or_else(|| quota_exceeded_message(&err))
+                        .unwrap_or_else(|| match err.error_code() {
+                            ErrorCode::Overloaded => OVERLOADED_MESSAGE.to_string(),
+                            _ => format!("{err:?}. Please try again.", err = err),
+                        });
                     cx.prompt(PromptLevel::Critical, &msg, Some(&detail), &["Ok"])
                 }) {
                     prompt.await.ok();
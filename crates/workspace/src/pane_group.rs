@@ -209,7 +209,7 @@ impl Member {
                                 if is_in_unshared_view {
                                     Some(Label::new(format!(
                                         "{} is in an unshared pane",
-                                        leader.user.github_login
+                                        leader.user.display_name()
                                     )))
                                 } else {
                                     None
@@ -218,17 +218,17 @@ impl Member {
                                 leader_join_data = Some((leader_project_id, leader.user.id));
                                 Some(Label::new(format!(
                                     "Follow {} to their active project",
-                                    leader.user.github_login,
+                                    leader.user.display_name(),
                                 )))
                             }
                         }
                         ParticipantLocation::UnsharedProject => Some(Label::new(format!(
                             "{} is viewing an unshared Zed project",
-                            leader.user.github_login
+                            leader.user.display_name()
                         ))),
                         ParticipantLocation::External => Some(Label::new(format!(
                             "{} is viewing a window outside of Zed",
-                            leader.user.github_login
+                            leader.user.display_name()
                         ))),
                     };
                 }
@@ -83,7 +83,7 @@ impl Item for SharedScreen {
     type Event = Event;
 
     fn tab_tooltip_text(&self, _: &AppContext) -> Option<SharedString> {
-        Some(format!("{}'s screen", self.user.github_login).into())
+        Some(format!("{}'s screen", self.user.display_name()).into())
     }
 
     fn deactivated(&mut self, cx: &mut ViewContext<Self>) {
@@ -102,7 +102,7 @@ impl Item for SharedScreen {
             .gap_1()
             .child(Icon::new(IconName::Screen))
             .child(
-                Label::new(format!("{}'s screen", self.user.github_login)).color(if selected {
+                Label::new(format!("{}'s screen", self.user.display_name())).color(if selected {
                     Color::Default
                 } else {
                     Color::Muted
@@ -88,6 +88,10 @@ lazy_static! {
         .and_then(parse_pixel_position_env_var);
 }
 
+/// How long someone has to keep speaking before a pane that follows the speaker
+/// switches to them.
+pub const SPEAKER_FOLLOW_DELAY: Duration = Duration::from_millis(1500);
+
 #[derive(Clone, PartialEq)]
 pub struct RemoveWorktreeFromProject(pub WorktreeId);
 
@@ -106,6 +110,9 @@ actions!(
         ActivatePreviousPane,
         ActivateNextPane,
         FollowNextCollaborator,
+        FollowPreviousCollaborator,
+        FollowMostRecentlyActiveCollaborator,
+        ToggleFollowSpeaker,
         NewTerminal,
         NewCenterTerminal,
         ToggleTerminalFocus,
@@ -494,6 +501,12 @@ pub struct Workspace {
     project: Model<Project>,
     follower_states: HashMap<View<Pane>, FollowerState>,
     last_leaders_by_pane: HashMap<WeakView<Pane>, PeerId>,
+    /// Whether the active pane follows whoever is speaking in the call. It stops
+    /// doing so once the user follows or unfollows someone themselves.
+    following_speaker: bool,
+    /// Follows the latest speaker once they've kept speaking for
+    /// [`SPEAKER_FOLLOW_DELAY`].
+    _follow_speaker: Option<Task<()>>,
     window_edited: bool,
     active_call: Option<(Model<ActiveCall>, Vec<Subscription>)>,
     leader_updates_tx: mpsc::UnboundedSender<(PeerId, proto::UpdateFollowers)>,
@@ -571,6 +584,10 @@ impl Workspace {
                     cx.new_view(|_| MessageNotification::new(message.clone()))
                 }),
 
+                project::Event::RecoverableOperationsFound => {
+                    this.show_recoverable_operations_notification(cx)
+                }
+
                 project::Event::LanguageServerPrompt(request) => {
                     let request = request.clone();
 
@@ -598,6 +615,13 @@ impl Workspace {
         })
         .detach();
 
+        // The project may have found edits to recover before this workspace subscribed.
+        cx.defer(|this, cx| {
+            if this.project.read(cx).has_recoverable_operations() {
+                this.show_recoverable_operations_notification(cx);
+            }
+        });
+
         cx.on_focus_lost(|this, cx| {
             let focus_handle = this.focus_handle(cx);
             cx.focus(&focus_handle);
@@ -754,6 +778,8 @@ impl Workspace {
             project: project.clone(),
             follower_states: Default::default(),
             last_leaders_by_pane: Default::default(),
+            following_speaker: false,
+            _follow_speaker: None,
             window_edited: false,
             active_call,
             database_id: workspace_id,
@@ -940,6 +966,23 @@ impl Workspace {
         &self.project
     }
 
+    fn show_recoverable_operations_notification(&mut self, cx: &mut ViewContext<Self>) {
+        let project = self.project.clone();
+        self.show_notification(0, cx, |cx| {
+            cx.new_view(|_| {
+                MessageNotification::new(
+                    "Some of your edits hadn't reached the host when Zed quit.",
+                )
+                .with_click_message("Recover them")
+                .on_click(move |cx| {
+                    project
+                        .update(cx, |project, cx| project.recover_operations(cx))
+                        .detach_and_log_err(cx);
+                })
+            })
+        })
+    }
+
     pub fn recent_navigation_history(
         &self,
         limit: Option<usize>,
@@ -1205,8 +1248,16 @@ impl Workspace {
     ) -> Task<Result<bool>> {
         let active_call = self.active_call().cloned();
         let window = cx.window_handle();
+        let project = self.project.read(cx);
+        let flush = project.is_remote().then(|| project.flush());
 
         cx.spawn(|this, mut cx| async move {
+            // Make sure the host has received every edit made in a remote project before
+            // leaving it.
+            if let Some(flush) = flush {
+                flush.await.log_err();
+            }
+
             let workspace_count = (*cx).update(|cx| {
                 cx.windows()
                     .iter()
@@ -2526,40 +2577,123 @@ impl Workspace {
         _: &FollowNextCollaborator,
         cx: &mut ViewContext<Self>,
     ) {
-        let collaborators = self.project.read(cx).collaborators();
+        self.follow_adjacent_collaborator(false, cx);
+    }
+
+    pub fn follow_previous_collaborator(
+        &mut self,
+        _: &FollowPreviousCollaborator,
+        cx: &mut ViewContext<Self>,
+    ) {
+        self.follow_adjacent_collaborator(true, cx);
+    }
+
+    /// Follows the participant after the one the active pane follows, or before it
+    /// if `reverse` is true, in the order they're shown in. A pane that isn't
+    /// following anyone picks its last leader back up. Following the same
+    /// participant again, because they're the only one, stops following them.
+    pub fn follow_adjacent_collaborator(&mut self, reverse: bool, cx: &mut ViewContext<Self>) {
+        let Some(room) = self.active_call().and_then(|call| call.read(cx).room()) else {
+            return;
+        };
+        let room = room.read(cx);
         let next_leader_id = if let Some(leader_id) = self.leader_for_pane(&self.active_pane) {
-            let mut collaborators = collaborators.keys().copied();
-            for peer_id in collaborators.by_ref() {
-                if peer_id == leader_id {
-                    break;
-                }
-            }
-            collaborators.next()
-        } else if let Some(last_leader_id) =
-            self.last_leaders_by_pane.get(&self.active_pane.downgrade())
-        {
-            if collaborators.contains_key(last_leader_id) {
-                Some(*last_leader_id)
-            } else {
-                None
-            }
+            room.adjacent_participant(Some(leader_id), reverse)
         } else {
-            None
+            self.last_leaders_by_pane
+                .get(&self.active_pane.downgrade())
+                .copied()
+                .filter(|leader_id| room.remote_participant_for_peer_id(*leader_id).is_some())
+                .or_else(|| room.adjacent_participant(None, reverse))
         };
 
         let pane = self.active_pane.clone();
-        let Some(leader_id) = next_leader_id.or_else(|| collaborators.keys().copied().next())
-        else {
+        let Some(leader_id) = next_leader_id else {
             return;
         };
         if Some(leader_id) == self.unfollow(&pane, cx) {
             return;
         }
-        self.start_following(leader_id, cx)
-            .map(|task| task.detach_and_log_err(cx));
+        self.follow(leader_id, cx);
+    }
+
+    /// Follows the participant who most recently joined the call, moved to another
+    /// project, started speaking or shared their screen.
+    pub fn follow_most_recently_active_collaborator(
+        &mut self,
+        _: &FollowMostRecentlyActiveCollaborator,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let Some(room) = self.active_call().and_then(|call| call.read(cx).room()) else {
+            return;
+        };
+        if let Some(leader_id) = room.read(cx).most_recently_active_participant() {
+            self.follow(leader_id, cx);
+        }
+    }
+
+    /// Makes the active pane follow whoever is speaking in the call, as they start
+    /// speaking, or stops doing so. Only calls that know who is speaking can be
+    /// followed this way.
+    pub fn toggle_follow_speaker(&mut self, _: &ToggleFollowSpeaker, cx: &mut ViewContext<Self>) {
+        if self.following_speaker {
+            self.following_speaker = false;
+            let pane = self.active_pane.clone();
+            self.unfollow(&pane, cx);
+            cx.notify();
+            return;
+        }
+
+        let Some(room) = self.active_call().and_then(|call| call.read(cx).room()) else {
+            return;
+        };
+        let room = room.read(cx);
+        if !room.knows_voice_activity() {
+            return;
+        }
+        let speaker_id = room.active_speaker();
+        self.following_speaker = true;
+        if let Some(speaker_id) = speaker_id {
+            self.follow_speaker(speaker_id, cx);
+        }
+        cx.notify();
+    }
+
+    /// Follows the given speaker in the active pane, unless they're in another
+    /// project, as joining every project that someone speaks from would open
+    /// windows as the conversation goes back and forth.
+    fn follow_speaker(&mut self, speaker_id: PeerId, cx: &mut ViewContext<Self>) {
+        let Some(room) = self.active_call().and_then(|call| call.read(cx).room()) else {
+            return;
+        };
+        let Some(speaker) = room.read(cx).remote_participant_for_peer_id(speaker_id) else {
+            return;
+        };
+        let in_this_project = match speaker.location {
+            call::ParticipantLocation::SharedProject { project_id } => {
+                Some(project_id) == self.project.read(cx).remote_id()
+            }
+            call::ParticipantLocation::UnsharedProject | call::ParticipantLocation::External => {
+                false
+            }
+        };
+        if !in_this_project || self.leader_for_pane(&self.active_pane) == Some(speaker_id) {
+            return;
+        }
+        if let Some(task) = self.start_following(speaker_id, cx) {
+            task.detach_and_log_err(cx);
+        }
+        // Following someone new unfollows the previous speaker, which would
+        // otherwise stop following speakers altogether.
+        self.following_speaker = true;
+    }
+
+    pub fn is_following_speaker(&self) -> bool {
+        self.following_speaker
     }
 
     pub fn follow(&mut self, leader_id: PeerId, cx: &mut ViewContext<Self>) {
+        self.following_speaker = false;
         let Some(room) = ActiveCall::global(cx).read(cx).room() else {
             return;
         };
@@ -2604,6 +2738,7 @@ impl Workspace {
 
     pub fn unfollow(&mut self, pane: &View<Pane>, cx: &mut ViewContext<Self>) -> Option<PeerId> {
         let state = self.follower_states.remove(pane)?;
+        self.following_speaker = false;
         let leader_id = state.leader_id;
         for (_, item) in state.items_by_leader_view_id {
             item.set_leader_peer_id(None, cx);
@@ -3050,9 +3185,26 @@ impl Workspace {
         Some(cx.new_view(|cx| SharedScreen::new(&track, peer_id, user.clone(), cx)))
     }
 
+    /// Reports the name of this workspace's project as the one the current user is
+    /// working in, for the contacts they've chosen to share it with.
+    fn update_working_location(&self, cx: &mut ViewContext<Self>) {
+        let user_store = self.app_state.user_store.read(cx);
+        if user_store.current_user().is_none() {
+            return;
+        }
+        let name = self
+            .project
+            .read(cx)
+            .visible_worktrees(cx)
+            .next()
+            .map(|worktree| worktree.read(cx).root_name().to_string());
+        user_store.update_working_location(name).log_err();
+    }
+
     pub fn on_window_activation_changed(&mut self, cx: &mut ViewContext<Self>) {
         if cx.is_window_active() {
             self.update_active_view_for_followers(cx);
+            self.update_working_location(cx);
             cx.background_executor()
                 .spawn(persistence::DB.update_timestamp(self.database_id()))
                 .detach();
@@ -3091,6 +3243,34 @@ impl Workspace {
             | call::room::Event::RemoteVideoTracksChanged { participant_id } => {
                 self.leader_updated(*participant_id, cx);
             }
+            call::room::Event::ActiveSpeakerChanged { participant_id }
+                if self.following_speaker =>
+            {
+                // Speakers are only followed once they've kept speaking for a
+                // moment, so that brief interjections don't take over the pane.
+                let speaker_id = *participant_id;
+                self._follow_speaker = speaker_id.map(|speaker_id| {
+                    cx.spawn(|this, mut cx| async move {
+                        cx.background_executor().timer(SPEAKER_FOLLOW_DELAY).await;
+                        this.update(&mut cx, |this, cx| {
+                            let still_speaking = this
+                                .active_call()
+                                .and_then(|call| call.read(cx).room())
+                                .map_or(false, |room| {
+                                    room.read(cx).active_speaker() == Some(speaker_id)
+                                });
+                            if this.following_speaker && still_speaking {
+                                this.follow_speaker(speaker_id, cx);
+                            }
+                        })
+                        .ok();
+                    })
+                });
+            }
+            call::room::Event::Left { .. } => {
+                self.following_speaker = false;
+                self._follow_speaker = None;
+            }
             _ => {}
         }
     }
@@ -3363,6 +3543,9 @@ impl Workspace {
             .on_action(cx.listener(Self::save_all))
             .on_action(cx.listener(Self::add_folder_to_project))
             .on_action(cx.listener(Self::follow_next_collaborator))
+            .on_action(cx.listener(Self::follow_previous_collaborator))
+            .on_action(cx.listener(Self::follow_most_recently_active_collaborator))
+            .on_action(cx.listener(Self::toggle_follow_speaker))
             .on_action(cx.listener(|workspace, _: &Unfollow, cx| {
                 let pane = workspace.active_pane().clone();
                 workspace.unfollow(&pane, cx);
@@ -4201,6 +4384,10 @@ pub fn join_channel(
                                 "This channel is private, and you do not have access. Please ask someone to add you and try again.".into()
                             },
                             ErrorCode::Disconnected => "Please check your internet connection and try again.".into(),
+                            ErrorCode::Overloaded => notifications::OVERLOADED_MESSAGE.into(),
+                            ErrorCode::RoomFull => notifications::quota_exceeded_message(&err)
+                                .unwrap_or_else(|| err.to_string())
+                                .into(),
                             _ => format!("{}\n\nPlease try again.", err).into(),
                         };
                         cx.prompt(
@@ -255,6 +255,10 @@ fn main() {
         theme_selector::init(cx);
         language_tools::init(cx);
         call::init(app_state.client.clone(), app_state.user_store.clone(), cx);
+        call::audio_relay::set_audio_relay_device(
+            Arc::new(call::audio_relay::SystemAudioRelayDevice::default()),
+            cx,
+        );
         notifications::init(app_state.client.clone(), app_state.user_store.clone(), cx);
         collab_ui::init(&app_state, cx);
         feedback::init(cx);
@@ -348,6 +352,17 @@ fn main() {
                 })
                 .detach_and_log_err(cx);
             }
+            Ok(Some(OpenRequest::SignInAsGuest { credentials })) => {
+                triggered_authentication = true;
+                let app_state = app_state.clone();
+                let client = client.clone();
+                cx.spawn(|cx| async move {
+                    client.sign_in_as_guest(credentials, &cx).await?;
+                    restore_or_create_workspace(&app_state, cx).await;
+                    anyhow::Ok(())
+                })
+                .detach_and_log_err(cx);
+            }
             Ok(None) | Err(_) => cx
                 .spawn({
                     let app_state = app_state.clone();
@@ -403,6 +418,14 @@ fn main() {
                         cx.update(|cx| open_notes_task.detach_and_log_err(cx))
                             .log_err();
                     }
+                    OpenRequest::SignInAsGuest { credentials } => {
+                        let client = app_state.client.clone();
+                        let sign_in_task = cx.spawn(|cx| async move {
+                            client.sign_in_as_guest(credentials, &cx).await
+                        });
+                        cx.update(|cx| sign_in_task.detach_and_log_err(cx))
+                            .log_err();
+                    }
                 }
             }
         })
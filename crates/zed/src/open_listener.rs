@@ -1,6 +1,7 @@
 use anyhow::{anyhow, Context, Result};
 use cli::{ipc, IpcHandshake};
 use cli::{ipc::IpcSender, CliRequest, CliResponse};
+use client::Credentials;
 use collections::HashMap;
 use editor::scroll::Autoscroll;
 use editor::Editor;
@@ -35,6 +36,9 @@ pub enum OpenRequest {
         channel_id: u64,
         heading: Option<String>,
     },
+    SignInAsGuest {
+        credentials: Credentials,
+    },
 }
 
 pub struct OpenListener {
@@ -96,7 +100,23 @@ impl OpenListener {
 
     fn handle_zed_url_scheme(&self, request_path: &str) -> Option<OpenRequest> {
         let mut parts = request_path.split("/");
-        if parts.next() == Some("channel") {
+        let kind = parts.next();
+        if kind == Some("guest") {
+            if let (Some(user_id), Some(access_token), None) =
+                (parts.next(), parts.next(), parts.next())
+            {
+                if let (Ok(user_id), Ok(access_token)) =
+                    (user_id.parse::<u64>(), urlencoding::decode(access_token))
+                {
+                    return Some(OpenRequest::SignInAsGuest {
+                        credentials: Credentials {
+                            user_id,
+                            access_token: access_token.into_owned(),
+                        },
+                    });
+                }
+            }
+        } else if kind == Some("channel") {
             if let Some(slug) = parts.next() {
                 if let Some(id_str) = slug.split("-").last() {
                     if let Ok(channel_id) = id_str.parse::<u64>() {